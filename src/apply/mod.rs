@@ -0,0 +1,301 @@
+use crate::diff::binary::{self, BinaryHunk};
+use crate::diff::lines;
+use crate::object::mode::Mode;
+
+/// The changes a patch makes to one file.
+///
+/// Paths are `None` on the side where the file does not exist, so a new file
+/// has no `old_path` and a deleted file has no `new_path`.
+#[derive(Debug, Default)]
+pub struct FilePatch {
+  pub old_path: Option<String>,
+  pub new_path: Option<String>,
+  pub old_mode: Option<Mode>,
+  pub new_mode: Option<Mode>,
+
+  /// The (usually abbreviated) blob hashes from the `index` line, if any.
+  pub old_hash: Option<String>,
+  pub new_hash: Option<String>,
+
+  pub hunks: Vec<PatchHunk>,
+
+  /// Whether this is a binary patch, which cannot be applied line by line.
+  pub binary: bool,
+
+  /// For a `GIT binary patch`, the hunk that makes the new version (the one
+  /// that goes back to the old version is not kept). A binary patch without
+  /// one only says that the files differ.
+  pub binary_hunk: Option<BinaryHunk>,
+}
+
+impl FilePatch {
+  /// The path the patch is reported under: the new path unless the file is
+  /// deleted.
+  pub fn path(&self) -> &str {
+    self
+      .new_path
+      .as_deref()
+      .or(self.old_path.as_deref())
+      .unwrap_or("")
+  }
+}
+
+/// One hunk of a unified diff.
+#[derive(Debug, Default)]
+pub struct PatchHunk {
+  /// Where the hunk starts in the old file (one-based, or zero when the old
+  /// side is empty).
+  pub old_start: usize,
+
+  /// The lines the hunk expects to find: context and removed lines.
+  pub before: Vec<Vec<u8>>,
+
+  /// The lines the hunk leaves behind: context and added lines.
+  pub after: Vec<Vec<u8>>,
+}
+
+/// Parses the file patches out of a `diff --git` style patch.
+///
+/// Anything before the first file header and after the last hunk (such as a
+/// commit message or an email signature) is ignored.
+pub fn parse(text: &[u8]) -> Result<Vec<FilePatch>, String> {
+  let lines = lines(text);
+  let mut patches: Vec<FilePatch> = Vec::new();
+  let mut i = 0;
+  while i < lines.len() {
+    let line = String::from_utf8_lossy(lines[i]);
+    let line = line.trim_end_matches('\n');
+    i += 1;
+
+    if let Some(names) = line.strip_prefix("diff --git ") {
+      let (old, new) = split_names(names);
+      patches.push(FilePatch {
+        old_path: Some(old),
+        new_path: Some(new),
+        ..Default::default()
+      });
+      continue;
+    }
+    // a plain unified diff without a git header starts at its `---` line
+    let next_is_new = i < lines.len() && lines[i].starts_with(b"+++ ");
+    if line.starts_with("--- ") && next_is_new && patches.last().is_none_or(|p| !p.hunks.is_empty())
+    {
+      patches.push(FilePatch::default());
+    }
+    let patch = match patches.last_mut() {
+      Some(patch) => patch,
+      None => continue,
+    };
+
+    if let Some(mode) = line.strip_prefix("new file mode ") {
+      patch.old_path = None;
+      patch.new_mode = Some(parse_mode(mode)?);
+    } else if let Some(mode) = line.strip_prefix("deleted file mode ") {
+      patch.new_path = None;
+      patch.old_mode = Some(parse_mode(mode)?);
+    } else if let Some(mode) = line.strip_prefix("old mode ") {
+      patch.old_mode = Some(parse_mode(mode)?);
+    } else if let Some(mode) = line.strip_prefix("new mode ") {
+      patch.new_mode = Some(parse_mode(mode)?);
+    } else if let Some(path) = line.strip_prefix("rename from ") {
+      patch.old_path = Some(path.to_string());
+    } else if let Some(path) = line.strip_prefix("rename to ") {
+      patch.new_path = Some(path.to_string());
+    } else if let Some(index) = line.strip_prefix("index ") {
+      let (hashes, mode) = match index.split_once(' ') {
+        Some((hashes, mode)) => (hashes, Some(parse_mode(mode)?)),
+        None => (index, None),
+      };
+      if let Some((old, new)) = hashes.split_once("..") {
+        patch.old_hash = Some(old.to_string());
+        patch.new_hash = Some(new.to_string());
+      }
+      if mode.is_some() {
+        patch.old_mode = mode;
+        patch.new_mode = mode;
+      }
+    } else if line.starts_with("Binary files ") {
+      patch.binary = true;
+    } else if line == "GIT binary patch" {
+      patch.binary = true;
+      let (hunk, used) = binary::parse_hunk(&lines[i..])?
+        .ok_or_else(|| format!("unrecognized binary patch at line {}", i + 1))?;
+      patch.binary_hunk = Some(hunk);
+      i += used;
+      // the reverse hunk is optional
+      if let Some((_, used)) = binary::parse_hunk(&lines[i..])? {
+        i += used;
+      }
+    } else if let Some(path) = line.strip_prefix("--- ") {
+      patch.old_path = strip_label(path, "a/");
+    } else if let Some(path) = line.strip_prefix("+++ ") {
+      patch.new_path = strip_label(path, "b/");
+    } else if line.starts_with("@@ ") {
+      let (hunk, len) = parse_hunk(line, &lines[i..])?;
+      patch.hunks.push(hunk);
+      i += len;
+    }
+  }
+  Ok(patches)
+}
+
+/// Splits the `a/old b/new` names of a `diff --git` line.
+fn split_names(names: &str) -> (String, String) {
+  // when the names are the same, the split is right in the middle
+  let half = names.len() / 2;
+  if names.is_char_boundary(half) && names.as_bytes().get(half) == Some(&b' ') {
+    let (old, new) = (&names[..half], &names[half + 1..]);
+    if let (Some(old), Some(new)) = (old.strip_prefix("a/"), new.strip_prefix("b/")) {
+      if old == new {
+        return (old.to_string(), new.to_string());
+      }
+    }
+  }
+  match names.find(" b/") {
+    Some(split) => (
+      names[..split].trim_start_matches("a/").to_string(),
+      names[split + 3..].to_string(),
+    ),
+    None => (names.to_string(), names.to_string()),
+  }
+}
+
+/// Turns a `---`/`+++` label into a path, or `None` for `/dev/null`.
+fn strip_label(label: &str, prefix: &str) -> Option<String> {
+  let label = label.split('\t').next().unwrap_or(label).trim_end();
+  match label {
+    "/dev/null" => None,
+    _ => Some(label.strip_prefix(prefix).unwrap_or(label).to_string()),
+  }
+}
+
+fn parse_mode(mode: &str) -> Result<Mode, String> {
+  Mode::from_octal(mode.trim())
+}
+
+/// Parses a hunk from its `@@ -a,b +c,d @@` header and the lines after it,
+/// returning the hunk and the number of lines it took up.
+fn parse_hunk(header: &str, lines: &[&[u8]]) -> Result<(PatchHunk, usize), String> {
+  let corrupt = || format!("corrupt patch at '{}'", header);
+  let ranges: Vec<&str> = header.split(' ').skip(1).take(2).collect();
+  let range = |text: Option<&&str>, sign: char| -> Result<(usize, usize), String> {
+    let text = text
+      .and_then(|t| t.strip_prefix(sign))
+      .ok_or_else(corrupt)?;
+    let (start, len) = text.split_once(',').unwrap_or((text, "1"));
+    Ok((
+      start.parse().map_err(|_| corrupt())?,
+      len.parse().map_err(|_| corrupt())?,
+    ))
+  };
+  let (old_start, mut old_left) = range(ranges.first(), '-')?;
+  let (_, mut new_left) = range(ranges.get(1), '+')?;
+
+  let mut hunk = PatchHunk {
+    old_start,
+    ..Default::default()
+  };
+  let mut used = 0;
+  // the side(s) the last line went to, for `\ No newline at end of file`
+  let mut last = (false, false);
+  while used < lines.len() && (old_left > 0 || new_left > 0 || lines[used].starts_with(b"\\")) {
+    let line = lines[used];
+    let text = if line.len() > 1 {
+      line[1..].to_vec()
+    } else {
+      b"\n".to_vec()
+    };
+    match line.first() {
+      Some(b' ') | Some(b'\n') if old_left > 0 && new_left > 0 => {
+        hunk.before.push(text.clone());
+        hunk.after.push(text);
+        (old_left, new_left) = (old_left - 1, new_left - 1);
+        last = (true, true);
+      }
+      Some(b'-') if old_left > 0 => {
+        hunk.before.push(text);
+        old_left -= 1;
+        last = (true, false);
+      }
+      Some(b'+') if new_left > 0 => {
+        hunk.after.push(text);
+        new_left -= 1;
+        last = (false, true);
+      }
+      Some(b'\\') => {
+        if last.0 {
+          strip_newline(hunk.before.last_mut());
+        }
+        if last.1 {
+          strip_newline(hunk.after.last_mut());
+        }
+      }
+      _ => return Err(corrupt()),
+    }
+    used += 1;
+  }
+  if old_left > 0 || new_left > 0 {
+    return Err(corrupt());
+  }
+  Ok((hunk, used))
+}
+
+fn strip_newline(line: Option<&mut Vec<u8>>) {
+  if let Some(line) = line {
+    if line.ends_with(b"\n") {
+      line.pop();
+    }
+  }
+}
+
+/// Applies hunks to the contents of a file.
+///
+/// Each hunk is looked for where its header says it starts (shifted by how
+/// far earlier hunks moved), then further and further away from there. Its
+/// context must match exactly. Returns the new contents, or the index of the
+/// first hunk that could not be placed.
+pub fn apply_hunks(content: &[u8], hunks: &[PatchHunk]) -> Result<Vec<u8>, usize> {
+  let lines = lines(content);
+  let mut out: Vec<u8> = Vec::new();
+  let mut done = 0;
+  let mut offset: isize = 0;
+  for (n, hunk) in hunks.iter().enumerate() {
+    let expected = if hunk.before.is_empty() {
+      hunk.old_start
+    } else {
+      hunk.old_start.saturating_sub(1)
+    };
+    let expected = (expected as isize + offset).clamp(done as isize, lines.len() as isize) as usize;
+    let fits = |at: usize| {
+      at >= done
+        && at + hunk.before.len() <= lines.len()
+        && hunk
+          .before
+          .iter()
+          .zip(&lines[at..])
+          .all(|(want, have)| want.as_slice() == *have)
+    };
+    let found = (0..=lines.len()).find_map(|distance| {
+      [expected.checked_sub(distance), Some(expected + distance)]
+        .into_iter()
+        .flatten()
+        .find(|at| fits(*at))
+    });
+    let at = match found {
+      Some(at) => at,
+      None => return Err(n),
+    };
+    for line in &lines[done..at] {
+      out.extend_from_slice(line);
+    }
+    for line in &hunk.after {
+      out.extend_from_slice(line);
+    }
+    done = at + hunk.before.len();
+    offset = at as isize - hunk.old_start.saturating_sub(1) as isize;
+  }
+  for line in &lines[done..] {
+    out.extend_from_slice(line);
+  }
+  Ok(out)
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use bstr::{BString, ByteSlice};
+
+use crate::ignore::wildmatch;
+use crate::repo::Repo;
+
+/// The attributes given to paths by `.gitattributes` files.
+///
+/// Each line of those files is a pattern followed by the attributes it sets
+/// on the paths it matches: `text` sets one, `-text` unsets it, `!text` makes
+/// it unspecified again and `eol=crlf` gives it a value. Patterns are matched
+/// as in `.gitignore`, except that they cannot be negated and a pattern
+/// ending in `/` matches no file.
+///
+/// Rules come from the file named by `core.attributesFile`, the
+/// `.gitattributes` of every directory and `.git/info/attributes`, each
+/// winning over the ones before it, with deeper directories winning over
+/// shallower ones. Within a file the last matching line wins.
+///
+/// A macro names a set of attributes: `binary` is `-diff -merge -text`, and
+/// more can be defined at the top level with `[attr]name attributes...`.
+///
+/// ### Example
+/// ```text
+/// *.sh   text eol=lf
+/// *.bat  text eol=crlf
+/// *.png  binary
+/// ```
+pub struct Attributes {
+  work_tree: PathBuf,
+  global: Vec<Rule>,
+  info: Vec<Rule>,
+
+  /// The rules of each directory's `.gitattributes` read so far.
+  dirs: HashMap<BString, Vec<Rule>>,
+  macros: HashMap<String, Vec<(String, State)>>,
+}
+
+/// The state of an attribute for a path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum State {
+  Set,
+  Unset,
+  Value(String),
+  Unspecified,
+}
+
+/// A single line of an attributes file.
+struct Rule {
+  /// The directory (relative to the root of the working tree, without a
+  /// trailing `/`) whose `.gitattributes` the rule came from.
+  base: BString,
+  pattern: BString,
+
+  /// Whether the pattern is matched against the full path (it contains a
+  /// `/`) rather than just the file name.
+  anchored: bool,
+  attrs: Vec<(String, State)>,
+}
+
+impl Attributes {
+  /// Loads the repository-wide rules and the root `.gitattributes`. The
+  /// files of subdirectories are read as paths inside them are looked up.
+  pub fn new(repo: &Repo) -> Self {
+    let mut attributes = Self {
+      work_tree: repo.work_tree.clone(),
+      global: Vec::new(),
+      info: Vec::new(),
+      dirs: HashMap::new(),
+      macros: HashMap::from([(
+        "binary".to_string(),
+        ["diff", "merge", "text"]
+          .map(|name| (name.to_string(), State::Unset))
+          .to_vec(),
+      )]),
+    };
+    if let Some(path) = attributes_file(repo) {
+      attributes.global = attributes.load_file(&path, b"", true);
+    }
+    attributes.info = attributes.load_file(&repo.git_dir.join("info/attributes"), b"", true);
+    attributes.load_dir(b"");
+    attributes
+  }
+
+  /// The state of an attribute for a path relative to the root of the
+  /// working tree.
+  pub fn get(&mut self, path: impl AsRef<[u8]>, name: &str) -> State {
+    let path = path.as_ref();
+    let mut dirs: Vec<&[u8]> = path.rfind_iter(b"/").map(|slash| &path[..slash]).collect();
+    dirs.push(b"");
+    for dir in &dirs {
+      self.load_dir(dir);
+    }
+
+    let sources = std::iter::once(&self.info)
+      .chain(dirs.iter().map(|dir| &self.dirs[*dir]))
+      .chain(std::iter::once(&self.global));
+    for rules in sources {
+      for rule in rules.iter().rev() {
+        if !rule.matches(path) {
+          continue;
+        }
+        if let Some((_, state)) = rule.attrs.iter().rev().find(|(attr, _)| attr == name) {
+          return state.clone();
+        }
+      }
+    }
+    State::Unspecified
+  }
+
+  fn load_dir(&mut self, dir: &[u8]) {
+    if self.dirs.contains_key(dir) {
+      return;
+    }
+    let path = self
+      .work_tree
+      .join(OsStr::from_bytes(dir))
+      .join(".gitattributes");
+    let rules = self.load_file(&path, dir, dir.is_empty());
+    self.dirs.insert(dir.into(), rules);
+  }
+
+  /// Reads the rules of a file. Macros may only be defined at the top level.
+  fn load_file(&mut self, path: &Path, base: &[u8], top: bool) -> Vec<Rule> {
+    let data = match fs::read(path) {
+      Ok(data) => data,
+      Err(_) => return Vec::new(),
+    };
+    let mut rules = Vec::new();
+    for line in data.lines() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with(b"#") {
+        continue;
+      }
+      let (pattern, attrs) = match line.find_byteset(b" \t") {
+        Some(space) => line.split_at(space),
+        None => (line, &b""[..]),
+      };
+      let attrs = self.parse_attrs(attrs);
+      if let Some(name) = pattern.strip_prefix(b"[attr]") {
+        if top {
+          self.macros.insert(name.to_str_lossy().into_owned(), attrs);
+        }
+        continue;
+      }
+      // negated and directory patterns match no file
+      if pattern.starts_with(b"!") || pattern.ends_with(b"/") {
+        continue;
+      }
+      rules.push(Rule {
+        base: base.into(),
+        anchored: pattern.contains(&b'/'),
+        pattern: pattern.strip_prefix(b"/").unwrap_or(pattern).into(),
+        attrs,
+      });
+    }
+    rules
+  }
+
+  /// Parses the attributes of a line, expanding macros into the attributes
+  /// they set after the macro itself.
+  fn parse_attrs(&self, attrs: &[u8]) -> Vec<(String, State)> {
+    let mut parsed = Vec::new();
+    for attr in attrs.fields().map(|attr| attr.to_str_lossy()) {
+      let (name, state) = match (attr.strip_prefix('-'), attr.strip_prefix('!')) {
+        (Some(name), _) => (name, State::Unset),
+        (_, Some(name)) => (name, State::Unspecified),
+        _ => match attr.split_once('=') {
+          Some((name, value)) => (name, State::Value(value.to_string())),
+          None => (&*attr, State::Set),
+        },
+      };
+      let expansion = match state {
+        State::Set => self.macros.get(name),
+        _ => None,
+      };
+      parsed.push((name.to_string(), state));
+      parsed.extend(expansion.into_iter().flatten().cloned());
+    }
+    parsed
+  }
+}
+
+impl Rule {
+  fn matches(&self, path: &[u8]) -> bool {
+    let relative = if self.base.is_empty() {
+      path
+    } else {
+      match path
+        .strip_prefix(self.base.as_slice())
+        .and_then(|p| p.strip_prefix(b"/"))
+      {
+        Some(relative) => relative,
+        None => return false,
+      }
+    };
+    let subject = match self.anchored {
+      true => relative,
+      false => relative.rsplit_str("/").next().unwrap_or(relative),
+    };
+    wildmatch(&self.pattern, subject)
+  }
+}
+
+/// The attributes file named by `core.attributesFile`.
+fn attributes_file(repo: &Repo) -> Option<PathBuf> {
+  let core = repo.config.as_ref()?.section(Some("core"))?;
+  let path = core
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case("attributesFile"))
+    .map(|(_, value)| value)?;
+  Some(match path.strip_prefix("~/") {
+    Some(rest) => Path::new(&std::env::var("HOME").unwrap_or_default()).join(rest),
+    None => PathBuf::from(path),
+  })
+}
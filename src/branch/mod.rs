@@ -0,0 +1,161 @@
+use crate::{
+  object::refs,
+  remote::{head, Remote},
+  repo::Repo,
+  rev::walk::graph_ahead_behind,
+};
+
+/// The branch a branch tracks, from its `branch.<name>.remote` and
+/// `branch.<name>.merge` config.
+pub struct Upstream {
+  /// The short name, eg. `origin/main`.
+  pub name: String,
+
+  /// How many commits the branch is ahead of and behind its upstream, or
+  /// `None` if the upstream ref is gone.
+  pub ahead_behind: Option<(usize, usize)>,
+}
+
+impl Upstream {
+  /// The lines the long format of `status` shows about the upstream.
+  pub fn long(&self) -> String {
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    match self.ahead_behind {
+      None => format!(
+        "Your branch is based on '{}', but the upstream is gone.\n  \
+         (use \"git branch --unset-upstream\" to fixup)\n",
+        self.name
+      ),
+      Some((0, 0)) => format!("Your branch is up to date with '{}'.\n", self.name),
+      Some((ahead, 0)) => format!(
+        "Your branch is ahead of '{}' by {} commit{}.\n  \
+         (use \"git push\" to publish your local commits)\n",
+        self.name,
+        ahead,
+        plural(ahead)
+      ),
+      Some((0, behind)) => format!(
+        "Your branch is behind '{}' by {} commit{}, and can be fast-forwarded.\n  \
+         (use \"git pull\" to update your local branch)\n",
+        self.name,
+        behind,
+        plural(behind)
+      ),
+      Some((ahead, behind)) => format!(
+        "Your branch and '{}' have diverged,\nand have {} and {} different commits each, \
+         respectively.\n  (use \"git pull\" to merge the remote branch into yours)\n",
+        self.name, ahead, behind
+      ),
+    }
+  }
+
+  /// How far apart the branch and its upstream are, the way `branch -v` and
+  /// `status --porcelain --branch` show it: `ahead 1, behind 2`, `gone`, or
+  /// nothing when they are the same.
+  pub fn short(&self) -> String {
+    match self.ahead_behind {
+      None => "gone".to_string(),
+      Some((0, 0)) => String::new(),
+      Some((ahead, 0)) => format!("ahead {}", ahead),
+      Some((0, behind)) => format!("behind {}", behind),
+      Some((ahead, behind)) => format!("ahead {}, behind {}", ahead, behind),
+    }
+  }
+}
+
+/// Finds the ref a branch tracks, eg. `refs/remotes/origin/main` for `main`.
+///
+/// The upstream is `branch.<name>.merge` on the remote named by
+/// `branch.<name>.remote`, mapped to a remote-tracking ref by the remote's
+/// `fetch` refspec (or the local branch itself, for the remote `.`).
+pub fn tracking_ref(repo: &Repo, branch: &str) -> Option<String> {
+  let config = repo.config.as_ref()?;
+  let section = config.section(Some(format!("branch \"{}\"", branch)))?;
+  let merge = section.get("merge")?;
+  match section.get("remote")? {
+    "." => Some(merge.to_string()),
+    remote => {
+      let refspec = config.get_from(Some(format!("remote \"{}\"", remote)), "fetch")?;
+      map_refspec(refspec, merge)
+    }
+  }
+}
+
+/// Finds the remote-tracking branch that a branch of this name would be
+/// made from, as `switch <branch>` does when there is no such branch: the
+/// one remote that has a branch of that name, or if more than one does,
+/// the one `checkout.defaultRemote` names. Returns the remote and its
+/// remote-tracking branch, eg. `origin` and `refs/remotes/origin/topic`.
+pub fn remote_branch(repo: &Repo, branch: &str) -> Result<Option<(String, String)>, String> {
+  let merge = format!("refs/heads/{}", branch);
+  let mut found: Vec<(String, String)> = Vec::new();
+  for name in crate::remote::names(repo) {
+    let remote = Remote::get(repo, &name)?;
+    if let Some(tracking) = head::tracking_ref(&remote, &merge) {
+      if refs::exists(repo, &tracking) {
+        found.push((name, tracking));
+      }
+    }
+  }
+  if found.len() > 1 {
+    let default = repo
+      .config
+      .as_ref()
+      .and_then(|config| config.section(Some("checkout")))
+      .and_then(|section| {
+        section
+          .iter()
+          .find(|(key, _)| key.eq_ignore_ascii_case("defaultRemote"))
+      })
+      .map(|(_, value)| value);
+    found.retain(|(name, _)| Some(name.as_str()) == default);
+  }
+  Ok(match found.len() {
+    1 => found.pop(),
+    _ => None,
+  })
+}
+
+/// Sets a branch up to track a branch of a remote (`branch.<name>.remote`
+/// and `branch.<name>.merge`).
+pub fn set_upstream(
+  repo: &mut Repo,
+  branch: &str,
+  remote: &str,
+  merge: &str,
+) -> Result<(), String> {
+  let section = format!("branch \"{}\"", branch);
+  repo.set_config(&section, "remote", remote)?;
+  repo.set_config(&section, "merge", merge)
+}
+
+/// Looks up the upstream of a branch and counts how far apart it is from
+/// `head`, the commit the branch points at.
+pub fn upstream(repo: &Repo, branch: &str, head: &str) -> Result<Option<Upstream>, String> {
+  let tracking = match tracking_ref(repo, branch) {
+    Some(tracking) => tracking,
+    None => return Ok(None),
+  };
+  let name = ["refs/heads/", "refs/remotes/"]
+    .iter()
+    .find_map(|prefix| tracking.strip_prefix(prefix))
+    .unwrap_or(&tracking)
+    .to_string();
+  let ahead_behind = match refs::resolve(repo, tracking.as_ref()) {
+    Ok(upstream) => Some(graph_ahead_behind(repo, head, &upstream)?),
+    Err(_) => None,
+  };
+  Ok(Some(Upstream { name, ahead_behind }))
+}
+
+/// Maps a ref through a refspec such as `+refs/heads/*:refs/remotes/origin/*`.
+fn map_refspec(refspec: &str, name: &str) -> Option<String> {
+  let (source, destination) = refspec.trim_start_matches('+').split_once(':')?;
+  match source.split_once('*') {
+    Some((prefix, suffix)) => {
+      let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+      Some(destination.replacen('*', matched, 1))
+    }
+    None => (source == name).then(|| destination.to_string()),
+  }
+}
@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Asks a long operation to stop early.
+///
+/// Operations that take a token check it as they go (between commits of a
+/// walk, files of a checkout, objects of a pack being written or indexed,
+/// packets of a fetch) and give up with an error once it is cancelled,
+/// undoing what they had done where they can. Clones share the
+/// same flag, so a token can be handed to an operation running on another
+/// thread and cancelled from this one.
+///
+/// # Example
+/// ```text
+/// let cancel = Cancel::new();
+/// let mut walk = RevWalk::new(&repo);
+/// walk.cancel(&cancel);
+/// // ...and from another thread:
+/// cancel.cancel();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Cancel {
+  flag: Arc<AtomicBool>,
+}
+
+impl Cancel {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Asks every operation holding this token to stop.
+  pub fn cancel(&self) {
+    self.flag.store(true, Ordering::SeqCst);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.flag.load(Ordering::SeqCst)
+  }
+
+  /// Fails once the token is cancelled, for use with `?` in a loop.
+  pub fn check(&self) -> Result<(), String> {
+    match self.is_cancelled() {
+      true => Err("operation cancelled".to_string()),
+      false => Ok(()),
+    }
+  }
+}
+
+/// A token that is cancelled when the user presses Ctrl-C, for commands that
+/// want to stop cleanly rather than being killed half way through.
+///
+/// A second Ctrl-C exits straight away, in case the command is stuck
+/// somewhere that does not check the token. Only one command should ask for
+/// this, as the handler can only be installed once.
+pub fn on_interrupt() -> Cancel {
+  let cancel = Cancel::new();
+  let token = cancel.clone();
+  let _ = ctrlc::set_handler(move || {
+    if token.is_cancelled() {
+      std::process::exit(130);
+    }
+    token.cancel();
+  });
+  cancel
+}
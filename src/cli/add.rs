@@ -1,10 +1,249 @@
+use bstr::{BString, ByteSlice};
 use clap::Args;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::process;
+use std::slice;
 
+use git_rs_core::{
+  ignore::Ignore,
+  index::{self, Index, IndexEntry, ASSUME_VALID, SKIP_WORKTREE},
+  object::mode::Mode,
+  parallel, pathspec,
+  repo::Repo,
+  sparse::Sparse,
+  worktree,
+};
+
+/// Add file contents to the index.
+///
+/// Every file matching the pathspecs is brought into the index as it is in
+/// the working tree: new files are added, changed files updated and files
+/// that are gone removed. Ignored files are left out unless `--force` is
+/// given, and naming one is an error. With `--update` only tracked files are
+/// looked at, and with `--all` (or `--update`) and no pathspecs the whole
+/// working tree is.
+///
+/// In a sparse checkout, files outside of it are left alone unless
+/// `--sparse` is given.
+///
+/// # Example
+/// ```bash
+/// $ git add src/main.rs docs
+/// $ git add -u
+/// $ git add -A
+/// ```
 #[derive(Args, Debug)]
 pub struct Add {
-  pub name: Option<String>,
+  /// Add, update and remove files across the whole working tree when no
+  /// pathspecs are given.
+  #[clap(short = 'A', long, conflicts_with = "update")]
+  pub all: bool,
+
+  /// Only update and remove files that are already tracked.
+  #[clap(short, long)]
+  pub update: bool,
+
+  /// Add ignored files as well.
+  #[clap(short, long)]
+  pub force: bool,
+
+  /// Update files outside of the sparse checkout as well.
+  #[clap(long)]
+  pub sparse: bool,
+
+  /// Only show what would be added and removed.
+  #[clap(short = 'n', long)]
+  pub dry_run: bool,
+
+  /// Report what is being added and removed.
+  #[clap(short, long)]
+  pub verbose: bool,
+
+  /// The files to add.
+  pub paths: Vec<String>,
+}
+
+pub fn cmd_add(opts: &Add) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  repo.require_work_tree()?;
+  if opts.paths.is_empty() && !opts.all && !opts.update {
+    eprintln!("Nothing specified, nothing added.");
+    eprintln!("hint: Maybe you wanted to say 'git add .'?");
+    return Ok(());
+  }
+  let specs = pathspec::resolve(&repo, &opts.paths)?;
+  let mut index = Index::read(&repo)?;
+  let sparse = Sparse::read(&repo).filter(|_| !opts.sparse);
+
+  // a pathspec has to match a tracked or an existing file
+  let mut outside: Vec<BString> = Vec::new();
+  for (spec, arg) in specs.iter().zip(&opts.paths) {
+    let tracked: Vec<&IndexEntry> = index
+      .entries()
+      .iter()
+      .filter(|entry| pathspec::matches(&entry.path, slice::from_ref(spec)))
+      .collect();
+    let exists = fs::symlink_metadata(repo.work_tree_path(spec)).is_ok();
+    if tracked.is_empty() && !exists {
+      return Err(format!("pathspec '{}' did not match any files", arg));
+    }
+    // files left out of a sparse checkout are marked skip-worktree
+    if sparse.is_some()
+      && !exists
+      && tracked
+        .iter()
+        .all(|entry| entry.extended_flags & SKIP_WORKTREE != 0)
+    {
+      outside.push(spec.clone());
+    }
+  }
+
+  // the tracked files that changed, including conflicted ones, which adding
+  // resolves; `true` marks the ones to remove
+  let mut changes: Vec<(BString, bool)> = Vec::new();
+  for (kind, path) in worktree::unstaged_changes(&repo, &index)? {
+    let is_gitlink = index.get(&path).map(|entry| entry.mode) == Some(Mode::Gitlink.bits());
+    if pathspec::matches(&path, &specs) && !is_gitlink {
+      changes.push((path, kind == 'D'));
+    }
+  }
+  for entry in index.entries().iter().filter(|entry| entry.stage() != 0) {
+    if pathspec::matches(&entry.path, &specs) {
+      let exists = fs::symlink_metadata(repo.work_tree_path(&entry.path)).is_ok();
+      changes.push((entry.path.clone(), !exists));
+    }
+  }
+
+  // and the untracked ones, unless only tracked files are updated
+  let mut ignored: Vec<BString> = Vec::new();
+  if !opts.update {
+    let mut ignore = Ignore::new(&repo);
+    for untracked in worktree::untracked(&repo, &index, &mut ignore, true)? {
+      let path = untracked.path.trim_end_with(|c| c == '/');
+      let named = specs
+        .iter()
+        .any(|spec| spec == path || pathspec::matches(spec, &[BString::from(path)]));
+      if untracked.ignored && !opts.force {
+        if named {
+          ignored.push(path.into());
+        }
+        continue;
+      }
+      let files = match untracked.is_dir {
+        true => files_in(&repo, path)?,
+        false => vec![path.into()],
+      };
+      for file in files {
+        if pathspec::matches(&file, &specs) {
+          match &sparse {
+            Some(sparse) if !sparse.includes(&file) => outside.push(file),
+            _ => changes.push((file, false)),
+          }
+        }
+      }
+    }
+  }
+  changes.sort();
+  changes.dedup_by(|a, b| a.0 == b.0);
+
+  let failed = !ignored.is_empty() || !outside.is_empty();
+  if !ignored.is_empty() {
+    eprintln!("The following paths are ignored by one of your .gitignore files:");
+    for path in &ignored {
+      eprintln!("{}", path);
+    }
+    eprintln!("hint: Use -f if you really want to add them.");
+  }
+  if !outside.is_empty() {
+    eprintln!(
+      "The following paths and/or pathspecs matched paths that exist\n\
+       outside of your sparse-checkout definition, so will not be\n\
+       updated in the index:"
+    );
+    for path in &outside {
+      eprintln!("{}", path);
+    }
+    eprintln!("hint: If you intend to update such entries, try one of the following:");
+    eprintln!("hint: * Use the --sparse option.");
+    eprintln!("hint: * Disable or modify the sparsity rules.");
+  }
+
+  // the files are hashed up front, on `index.threads` threads when there
+  // are a lot of them
+  let workers = match changes.len() >= parallel::THRESHOLD {
+    true => parallel::workers(&repo, "index", "threads"),
+    false => 1,
+  };
+  let hashes = parallel::map(
+    &changes,
+    workers,
+    |(path, remove)| {
+      (!remove).then(|| {
+        let indexed = index.get(path).map(|entry| entry.hash.as_str());
+        index::hash_file(&repo, path, indexed, !opts.dry_run)
+      })
+    },
+    |_, _| (),
+  );
+
+  for ((path, _), hash) in changes.iter().zip(hashes) {
+    match hash {
+      Some(hash) => {
+        let hash = hash?;
+        let metadata = fs::symlink_metadata(repo.work_tree_path(path))
+          .map_err(|e| format!("unable to stat '{}': {}", path, e))?;
+        let mut entry = IndexEntry::from_metadata(path, &hash, &metadata);
+        if let Some(old) = index.get(path) {
+          entry.flags |= old.flags & ASSUME_VALID;
+          entry.extended_flags = old.extended_flags;
+        }
+        index.add(entry);
+        if opts.verbose || opts.dry_run {
+          println!("add '{}'", path);
+        }
+      }
+      None => {
+        index.remove(path);
+        if opts.verbose || opts.dry_run {
+          println!("remove '{}'", path);
+        }
+      }
+    }
+  }
+  if !opts.dry_run {
+    index.write(&repo)?;
+  }
+  if failed {
+    process::exit(1);
+  }
+  Ok(())
 }
 
-pub fn cmd_add() -> Result<(), String> {
-  todo!("cmd_add");
+/// The files inside an untracked directory, sorted. Repositories nested in
+/// it are left out.
+fn files_in(repo: &Repo, dir: &[u8]) -> Result<Vec<BString>, String> {
+  let full_path = repo.work_tree_path(dir);
+  let entries = fs::read_dir(&full_path).map_err(|e| format!("{}: {}", full_path.display(), e))?;
+  let mut names: Vec<(BString, bool)> = entries
+    .flatten()
+    .map(|e| {
+      let is_dir = e.file_type().is_ok_and(|t| t.is_dir());
+      (BString::from(e.file_name().as_bytes()), is_dir)
+    })
+    .collect();
+  if names.iter().any(|(name, is_dir)| name == ".git" && *is_dir) {
+    return Ok(Vec::new());
+  }
+  names.sort();
+
+  let mut files = Vec::new();
+  for (name, is_dir) in names {
+    let path = BString::from([dir, b"/", &name].concat());
+    match is_dir {
+      true => files.extend(files_in(repo, &path)?),
+      false => files.push(path),
+    }
+  }
+  Ok(files)
 }
@@ -0,0 +1,754 @@
+use clap::Args;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use git_rs_core::{
+  apply::{self, FilePatch},
+  cancel::Cancel,
+  diff::blob_data,
+  identity::{Role, Signature},
+  index::{self, unpack::Files, Index, IndexEntry},
+  mail::{self, Mail},
+  merge,
+  object::{
+    self, blob::Blob, commit::CommitBuilder, find_by_prefix, find_object, mode::Mode, refs, tree,
+  },
+  progress::NoProgress,
+  repo::Repo,
+  rerere, rev, worktree,
+};
+
+/// Apply a series of patches from a mailbox.
+///
+/// Reads patches in mbox format (as written by `git format-patch`) from the
+/// given files, or standard input, and commits each of them in turn. The
+/// author, date and message of each commit come from the email's `From`,
+/// `Date` and `Subject` headers and its body.
+///
+/// With `-3`, a patch that does not apply cleanly is merged in instead: the
+/// blob ids on its `index` lines are used to rebuild the files it was made
+/// against, and the change is merged into the current files from there,
+/// leaving conflict markers where the two disagree.
+///
+/// When a patch fails, `am` stops with its state kept in `.git/rebase-apply`.
+/// Fix things up and `git add` the results, then carry on with `--continue`,
+/// or use `--skip` to drop the patch or `--abort` to go back to where things
+/// were before `am` started.
+///
+/// # Example
+/// ```bash
+/// $ git am 0001-add-parser.patch 0002-use-parser.patch
+/// Applying: add parser
+/// Applying: use parser
+/// $ git am -3 < topic.mbox
+/// ```
+#[derive(Args, Debug)]
+pub struct Am {
+  /// The mailbox files to read (standard input if none are given).
+  pub mbox: Vec<String>,
+
+  /// Fall back to a three-way merge if a patch does not apply cleanly.
+  #[clap(short = '3', long = "3way")]
+  pub three_way: bool,
+
+  /// Commit the resolved index and carry on applying the patches.
+  #[clap(long = "continue", conflicts_with_all = &["skip", "abort"])]
+  pub resume: bool,
+
+  /// Drop the patch that failed and carry on with the next.
+  #[clap(long, conflicts_with = "abort")]
+  pub skip: bool,
+
+  /// Stop applying patches and restore the original branch.
+  #[clap(long)]
+  pub abort: bool,
+
+  /// Do not print the subject of each patch as it is applied.
+  #[clap(short, long)]
+  pub quiet: bool,
+}
+
+/// The state of an `am` session, kept in `.git/rebase-apply` between runs.
+///
+/// Each message is stored in a numbered file (`0001`, `0002`, ...) alongside
+/// the number of the `next` one to apply and the `last` one.
+struct Session {
+  dir: PathBuf,
+  next: usize,
+  last: usize,
+  three_way: bool,
+  quiet: bool,
+}
+
+/// What happened when applying a patch.
+enum Outcome {
+  Applied,
+  Failed,
+  Conflicted,
+}
+
+pub fn cmd_am(opts: &Am) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let dir = repo.git_dir.join("rebase-apply");
+  let resuming = opts.resume || opts.skip || opts.abort;
+  if resuming && !dir.is_dir() {
+    return Err("Resolve operation not in progress, we are not resuming.".to_string());
+  }
+  if !resuming && dir.is_dir() {
+    return Err(
+      "previous rebase directory .git/rebase-apply still exists but mbox given.".to_string(),
+    );
+  }
+
+  let mut session = if resuming {
+    Session::load(dir)?
+  } else {
+    start(&repo, dir, opts)?
+  };
+  if opts.abort {
+    rerere::clear(&repo)?;
+    return abort(&repo, &session);
+  }
+  if opts.skip {
+    rerere::clear(&repo)?;
+    reset_to_head(&repo)?;
+    session.advance()?;
+  }
+  if opts.resume {
+    let mail = session.current()?;
+    let index = Index::read(&repo)?;
+    if index.entries().iter().any(|e| e.stage() != 0) {
+      println!("You still have unmerged paths in your index.");
+      println!("You should 'git add' each file with resolved conflicts to mark them as such.");
+      println!("You might run `git rm` on a file to accept \"deleted by them\" for it.");
+      resolve_hints();
+      return Ok(());
+    }
+    if Some(index.files()) == head_files(&repo)? {
+      println!("No changes - did you forget to use 'git add'?");
+      println!("If there is nothing left to stage, chances are that something else");
+      println!("already introduced the same changes; you might want to skip this patch.");
+      resolve_hints();
+      return Ok(());
+    }
+    rerere_messages(&repo)?;
+    commit(&repo, &index, &mail)?;
+    session.advance()?;
+  }
+
+  while session.next <= session.last {
+    let mail = session.current()?;
+    let subject = subject(&mail);
+    if !session.quiet {
+      println!("Applying: {}", subject);
+    }
+    match apply_mail(&repo, &mail, &subject, session.three_way)? {
+      Outcome::Applied => {
+        commit(&repo, &Index::read(&repo)?, &mail)?;
+        session.advance()?;
+      }
+      Outcome::Conflicted => {
+        rerere_messages(&repo)?;
+        println!("error: Failed to merge in the changes.");
+        session.stop(&subject);
+        return Ok(());
+      }
+      Outcome::Failed => {
+        session.stop(&subject);
+        return Ok(());
+      }
+    }
+  }
+  fs::remove_dir_all(&session.dir).map_err(|e| format!("could not remove .git/rebase-apply: {}", e))
+}
+
+/// Splits the mailboxes into a new session, after making sure the index has
+/// no changes of its own that the patches would get mixed up with.
+fn start(repo: &Repo, dir: PathBuf, opts: &Am) -> Result<Session, String> {
+  let index = Index::read(repo)?;
+  let head = head_files(repo)?.unwrap_or_default();
+  let staged = index.files();
+  let mut dirty: Vec<String> = staged
+    .iter()
+    .filter(|(path, entry)| head.get(*path) != Some(*entry))
+    .map(|(path, _)| path.to_string())
+    .chain(
+      head
+        .keys()
+        .filter(|path| !staged.contains_key(*path))
+        .map(|p| p.to_string()),
+    )
+    .collect();
+  dirty.sort_unstable();
+  dirty.dedup();
+  if !dirty.is_empty() {
+    return Err(format!(
+      "Dirty index: cannot apply patches (dirty: {})",
+      dirty.join(" ")
+    ));
+  }
+
+  let mut text = String::new();
+  if opts.mbox.is_empty() {
+    io::stdin()
+      .read_to_string(&mut text)
+      .map_err(|e| format!("could not read from standard input ({})", e))?;
+  }
+  for path in &opts.mbox {
+    text.push_str(
+      &fs::read_to_string(path)
+        .map_err(|e| format!("could not open '{}' for reading: {}", path, e))?,
+    );
+  }
+  let messages = mail::split_mbox(&text);
+  if messages.is_empty() {
+    return Err("empty mbox: nothing to apply".to_string());
+  }
+
+  fs::create_dir_all(&dir).map_err(|e| format!("could not create .git/rebase-apply: {}", e))?;
+  for (n, message) in messages.iter().enumerate() {
+    write_state(&dir, &format!("{:04}", n + 1), message)?;
+  }
+  if let Ok(head) = rev::parse(repo, "HEAD") {
+    refs::update(repo, "ORIG_HEAD", &head)?;
+    write_state(&dir, "orig-head", &head)?;
+  }
+  if opts.three_way {
+    write_state(&dir, "threeway", "")?;
+  }
+  if opts.quiet {
+    write_state(&dir, "quiet", "")?;
+  }
+  let session = Session {
+    dir,
+    next: 1,
+    last: messages.len(),
+    three_way: opts.three_way,
+    quiet: opts.quiet,
+  };
+  session.save()?;
+  Ok(session)
+}
+
+impl Session {
+  fn load(dir: PathBuf) -> Result<Self, String> {
+    let number = |name: &str| -> Result<usize, String> {
+      read_state(&dir, name)?
+        .trim()
+        .parse()
+        .map_err(|_| format!("corrupt .git/rebase-apply/{}", name))
+    };
+    Ok(Session {
+      next: number("next")?,
+      last: number("last")?,
+      three_way: dir.join("threeway").exists(),
+      quiet: dir.join("quiet").exists(),
+      dir,
+    })
+  }
+
+  fn save(&self) -> Result<(), String> {
+    write_state(&self.dir, "next", &format!("{}\n", self.next))?;
+    write_state(&self.dir, "last", &format!("{}\n", self.last))
+  }
+
+  /// The message of the patch being applied.
+  fn current(&self) -> Result<Mail, String> {
+    Ok(Mail::parse(&read_state(
+      &self.dir,
+      &format!("{:04}", self.next),
+    )?))
+  }
+
+  fn advance(&mut self) -> Result<(), String> {
+    self.next += 1;
+    self.save()
+  }
+
+  /// Explains how to carry on after a patch failed.
+  fn stop(&self, subject: &str) {
+    println!("Patch failed at {:04} {}", self.next, subject);
+    println!("hint: Use 'git am --show-current-patch=diff' to see the failed patch");
+    resolve_hints();
+  }
+}
+
+fn resolve_hints() {
+  println!("When you have resolved this problem, run \"git am --continue\".");
+  println!("If you prefer to skip this patch, run \"git am --skip\" instead.");
+  println!("To restore the original branch and stop patching, run \"git am --abort\".");
+}
+
+fn read_state(dir: &Path, name: &str) -> Result<String, String> {
+  fs::read_to_string(dir.join(name))
+    .map_err(|e| format!("could not read .git/rebase-apply/{}: {}", name, e))
+}
+
+fn write_state(dir: &Path, name: &str, data: &str) -> Result<(), String> {
+  fs::write(dir.join(name), data)
+    .map_err(|e| format!("could not write .git/rebase-apply/{}: {}", name, e))
+}
+
+/// Goes back to the commit `am` started from, dropping any patches applied
+/// since, and ends the session.
+fn abort(repo: &Repo, session: &Session) -> Result<(), String> {
+  if let Ok(head) = read_state(&session.dir, "orig-head") {
+    let head = head.trim();
+    let tree = find_object(repo, head, Some("tree"), true)?;
+    let index = Index::read(repo)?;
+    worktree::switch_files(
+      repo,
+      &index,
+      &tree::flatten(repo, &tree)?,
+      true,
+      &mut NoProgress,
+      &Cancel::new(),
+    )?
+    .write(repo)?;
+    refs::update(repo, "HEAD", head)?;
+  }
+  fs::remove_dir_all(&session.dir).map_err(|e| format!("could not remove .git/rebase-apply: {}", e))
+}
+
+/// Records or replays the resolutions of the conflicts in the index.
+fn rerere_messages(repo: &Repo) -> Result<(), String> {
+  rerere::run(repo)?
+    .iter()
+    .for_each(|message| println!("{}", message));
+  Ok(())
+}
+
+/// Throws away the changes of a failed patch.
+fn reset_to_head(repo: &Repo) -> Result<(), String> {
+  let index = Index::read(repo)?;
+  let files = head_files(repo)?.unwrap_or_default();
+  worktree::switch_files(repo, &index, &files, true, &mut NoProgress, &Cancel::new())?.write(repo)
+}
+
+/// The files of the commit HEAD points at, or `None` on an unborn branch.
+fn head_files(repo: &Repo) -> Result<Option<Files>, String> {
+  match rev::parse(repo, "HEAD") {
+    Ok(_) => {
+      let tree = find_object(repo, "HEAD", Some("tree"), true)?;
+      Ok(Some(tree::flatten(repo, &tree)?))
+    }
+    Err(_) => Ok(None),
+  }
+}
+
+/// The commit title for a message: its subject without `[PATCH]` and such.
+fn subject(mail: &Mail) -> String {
+  mail::clean_subject(mail.header("Subject").unwrap_or(""))
+}
+
+/// The part of the body that belongs in the commit message: everything up to
+/// the `---` line that separates it from the patch.
+fn message(mail: &Mail) -> String {
+  let mut body = String::new();
+  for line in mail.body.lines() {
+    if line == "---" || line.starts_with("diff --git ") {
+      break;
+    }
+    body.push_str(line);
+    body.push('\n');
+  }
+  let body = body.trim_matches('\n');
+  match body.is_empty() {
+    true => format!("{}\n", subject(mail)),
+    false => format!("{}\n\n{}\n", subject(mail), body),
+  }
+}
+
+/// Commits the index on top of HEAD, with the author, date and message taken
+/// from the email.
+fn commit(repo: &Repo, index: &Index, mail: &Mail) -> Result<(), String> {
+  let (name, email) = match mail.header("From") {
+    Some(from) => mail::parse_address(from),
+    None => return Err("Patch does not have a valid e-mail address.".to_string()),
+  };
+  let committer = Signature::current(repo, Role::Committer)?;
+  // without a date, fall back to now, as the committer is stamped
+  let (time, timezone) = match mail.header("Date").and_then(mail::parse_date) {
+    Some(date) => date,
+    None => (committer.time, committer.timezone.clone()),
+  };
+  let author = Signature {
+    name,
+    email,
+    time,
+    timezone,
+  };
+
+  let mut builder = CommitBuilder::new().tree(&tree::build(repo, &index.files())?);
+  if let Ok(head) = rev::parse(repo, "HEAD") {
+    builder = builder.parent(&head);
+  }
+  let hash = builder
+    .author(author)
+    .committer(committer)
+    .message(&message(mail))
+    .write(repo)?;
+  refs::update(repo, "HEAD", &hash)
+}
+
+/// The new state of a file after a patch: its path and, unless it was
+/// deleted, its mode and contents.
+struct Patched {
+  old_path: Option<String>,
+  path: String,
+  new: Option<(Mode, Vec<u8>)>,
+}
+
+/// Applies the patch in an email to the index and working tree, falling back
+/// to a three-way merge if asked to.
+fn apply_mail(repo: &Repo, mail: &Mail, subject: &str, three_way: bool) -> Result<Outcome, String> {
+  let patches = apply::parse(mail.body.as_bytes())?;
+  if patches.is_empty() {
+    println!("Patch is empty.");
+    return Ok(Outcome::Failed);
+  }
+  let mut index = Index::read(repo)?;
+  let mut results: Vec<Patched> = Vec::new();
+  let mut errors: Vec<String> = Vec::new();
+  for patch in &patches {
+    match apply_file(repo, &index, patch)? {
+      Ok(patched) => results.push(patched),
+      Err(error) => errors.push(error),
+    }
+  }
+  if errors.is_empty() {
+    for patched in &results {
+      update(repo, &mut index, patched)?;
+    }
+    index.write(repo)?;
+    return Ok(Outcome::Applied);
+  }
+
+  if !three_way {
+    for error in &errors {
+      println!("{}", error);
+    }
+    return Ok(Outcome::Failed);
+  }
+  println!("Using index info to reconstruct a base tree...");
+  let mut bases: Vec<Option<(Mode, String)>> = Vec::new();
+  for patch in &patches {
+    let base = match (&patch.old_path, &patch.old_hash) {
+      (None, _) => None,
+      (Some(_), Some(hash)) => match find_by_prefix(repo, hash).as_slice() {
+        [hash] => Some((patch.old_mode.unwrap_or(Mode::Normal), hash.clone())),
+        _ => None,
+      },
+      (Some(_), None) => None,
+    };
+    if base.is_none() && patch.old_path.is_some() {
+      println!("Repository lacks necessary blobs to fall back on 3-way merge.");
+      return Ok(Outcome::Failed);
+    }
+    bases.push(base);
+  }
+  // show how the files have changed since the patch was made
+  for (patch, base) in patches.iter().zip(&bases) {
+    if let (Some(path), Some((_, hash))) = (&patch.old_path, base) {
+      match index.get(path) {
+        Some(entry) if entry.hash == *hash => (),
+        Some(_) => println!("M\t{}", path),
+        None => println!("D\t{}", path),
+      }
+    }
+  }
+  println!("Falling back to patching base and 3-way merge...");
+
+  let mut conflicted = false;
+  for (patch, base) in patches.iter().zip(&bases) {
+    let base_data = blob_data(repo, base.as_ref())?;
+    let theirs = match &patch.new_path {
+      Some(_) if patch.binary => match apply_binary(repo, patch, &base_data)? {
+        Ok(data) => Some(data),
+        Err(error) => {
+          println!("{}", error);
+          return Ok(Outcome::Failed);
+        }
+      },
+      Some(_) => match apply::apply_hunks(&base_data, &patch.hunks) {
+        Ok(data) => Some(data),
+        Err(_) => {
+          println!("error: Did you hand edit your patch?");
+          println!("It does not apply to blobs recorded in its index.");
+          return Ok(Outcome::Failed);
+        }
+      },
+      None => None,
+    };
+    conflicted |= merge_file(repo, &mut index, patch, base.as_ref(), theirs, subject)?;
+  }
+  index.write(repo)?;
+  Ok(match conflicted {
+    true => Outcome::Conflicted,
+    false => Outcome::Applied,
+  })
+}
+
+/// Works out what a file patch does to the current index, without changing
+/// anything yet. The inner error is the message to show when the patch does
+/// not apply.
+fn apply_file(
+  repo: &Repo,
+  index: &Index,
+  patch: &FilePatch,
+) -> Result<Result<Patched, String>, String> {
+  let current = match &patch.old_path {
+    Some(path) => {
+      let entry = match index.get(path) {
+        Some(entry) => entry,
+        None => return Ok(Err(format!("error: {}: does not exist in index", path))),
+      };
+      if index::is_modified(repo, entry)? {
+        return Ok(Err(format!("error: {}: does not match index", path)));
+      }
+      let mode = entry.tree_mode().unwrap_or(Mode::Normal);
+      Some((mode, blob_data(repo, Some(&(mode, entry.hash.clone())))?))
+    }
+    None => {
+      let path = patch.path();
+      if index.get(path).is_some() {
+        return Ok(Err(format!("error: {}: already exists in index", path)));
+      }
+      if fs::symlink_metadata(repo.work_tree.join(path)).is_ok() {
+        return Ok(Err(format!(
+          "error: {}: already exists in working directory",
+          path
+        )));
+      }
+      None
+    }
+  };
+
+  let new = match &patch.new_path {
+    None => None,
+    Some(path) => {
+      let mode = patch
+        .new_mode
+        .or_else(|| current.as_ref().map(|(mode, _)| *mode))
+        .unwrap_or(Mode::Normal);
+      let data = current
+        .as_ref()
+        .map(|(_, data)| data.as_slice())
+        .unwrap_or(b"");
+      let data = match patch.binary {
+        true => match apply_binary(repo, patch, data)? {
+          Ok(data) => data,
+          Err(error) => {
+            return Ok(Err(format!(
+              "{}\nerror: {}: patch does not apply",
+              error, path
+            )))
+          }
+        },
+        false => match apply::apply_hunks(data, &patch.hunks) {
+          Ok(data) => data,
+          Err(n) => {
+            return Ok(Err(format!(
+              "error: patch failed: {}:{}\nerror: {}: patch does not apply",
+              patch.old_path.as_deref().unwrap_or(path),
+              patch.hunks[n].old_start,
+              path
+            )))
+          }
+        },
+      };
+      Some((mode, data))
+    }
+  };
+  Ok(Ok(Patched {
+    old_path: patch.old_path.clone(),
+    path: patch.path().to_string(),
+    new,
+  }))
+}
+
+/// Works out the new contents of a file from a binary patch and its current
+/// contents. The hunk of a `GIT binary patch` is applied after checking the
+/// full hash on the patch's `index` line against what it is applied to (a
+/// delta only makes sense on the exact file it was made from), and what it
+/// makes is checked too. A patch that only says the files differ can still
+/// be applied if the new blob is already in the repository. The inner error
+/// says why the patch doesn't apply.
+fn apply_binary(
+  repo: &Repo,
+  patch: &FilePatch,
+  data: &[u8],
+) -> Result<Result<Vec<u8>, String>, String> {
+  let hunk = match (&patch.binary_hunk, &patch.new_hash) {
+    (Some(hunk), _) => hunk,
+    (None, Some(hash)) if object::exists(repo, hash) => {
+      return Ok(Ok(blob_data(repo, Some(&(Mode::Normal, hash.clone())))?))
+    }
+    (None, _) => {
+      return Ok(Err(format!(
+        "error: cannot apply binary patch to '{}' without full index line",
+        patch.path()
+      )))
+    }
+  };
+  let path = patch.path();
+  let full = |hash: &Option<String>| hash.clone().filter(|hash| hash.len() == 40);
+  if let (Some(_), Some(expected)) = (&patch.old_path, full(&patch.old_hash)) {
+    let hash = object::write(repo, &Blob::new(data), true)?;
+    if hash != expected {
+      return Ok(Err(format!(
+        "error: the patch applies to '{}' ({}), which does not match the current contents.",
+        path, hash
+      )));
+    }
+  }
+  let result = match hunk.apply(data) {
+    Ok(result) => result,
+    Err(e) => {
+      return Ok(Err(format!(
+        "error: binary patch does not apply to '{}' ({})",
+        path, e
+      )))
+    }
+  };
+  if let Some(expected) = full(&patch.new_hash) {
+    let hash = object::write(repo, &Blob::new(&result), true)?;
+    if hash != expected {
+      return Ok(Err(format!(
+        "error: binary patch to '{}' creates incorrect result (expecting {}, got {})",
+        path, expected, hash
+      )));
+    }
+  }
+  Ok(Ok(result))
+}
+
+/// Writes a patched file to the index and working tree.
+fn update(repo: &Repo, index: &mut Index, patched: &Patched) -> Result<(), String> {
+  if let Some(old_path) = &patched.old_path {
+    if *old_path != patched.path || patched.new.is_none() {
+      index.remove(old_path);
+      worktree::remove_file(repo, old_path)?;
+    }
+  }
+  if let Some((mode, data)) = &patched.new {
+    let hash = object::write(repo, &Blob::new(data), false)?;
+    index.add(worktree::checkout_file(repo, &patched.path, *mode, &hash)?);
+  }
+  Ok(())
+}
+
+/// Merges a file patch's changes into the current version of the file,
+/// starting from the version it was made against. Returns whether the merge
+/// left a conflict.
+fn merge_file(
+  repo: &Repo,
+  index: &mut Index,
+  patch: &FilePatch,
+  base: Option<&(Mode, String)>,
+  theirs: Option<Vec<u8>>,
+  subject: &str,
+) -> Result<bool, String> {
+  let path = patch.path().to_string();
+  let ours_entry = patch
+    .old_path
+    .as_deref()
+    .and_then(|old| index.get(old))
+    .or_else(|| index.get(&path))
+    .cloned();
+  let ours = match &ours_entry {
+    Some(entry) => {
+      let mode = entry.tree_mode().unwrap_or(Mode::Normal);
+      Some((mode, blob_data(repo, Some(&(mode, entry.hash.clone())))?))
+    }
+    None => None,
+  };
+  let base_data = match base {
+    Some(base) => Some(blob_data(repo, Some(base))?),
+    None => None,
+  };
+  let mode = patch
+    .new_mode
+    .or(ours.as_ref().map(|(mode, _)| *mode))
+    .unwrap_or(Mode::Normal);
+  let patched = |new: Option<Vec<u8>>| Patched {
+    old_path: patch.old_path.clone(),
+    path: path.clone(),
+    new: new.map(|data| (mode, data)),
+  };
+
+  let ours_data = ours.as_ref().map(|(_, data)| data.clone());
+  if ours_data == base_data {
+    return update(repo, index, &patched(theirs)).map(|_| false);
+  }
+  if ours_data == theirs {
+    return Ok(false);
+  }
+  let (ours_data, theirs) = match (ours_data, theirs) {
+    (Some(ours), Some(theirs)) => (ours, theirs),
+    (ours, theirs) => {
+      // one side deleted the file while the other changed it
+      let (deleted, modified) = match ours.is_none() {
+        true => ("HEAD", subject),
+        false => (subject, "HEAD"),
+      };
+      println!(
+        "CONFLICT (modify/delete): {} deleted in {} and modified in {}.  Version {} of {} left in tree.",
+        path, deleted, modified, modified, path
+      );
+      if let Some(theirs) = &theirs {
+        let hash = object::write(repo, &Blob::new(theirs), false)?;
+        worktree::checkout_file(repo, &path, mode, &hash)?;
+      }
+      stage_sides(repo, index, &path, mode, [base_data, ours, theirs])?;
+      return Ok(true);
+    }
+  };
+
+  println!("Auto-merging {}", path);
+  let base_bytes = base_data.clone().unwrap_or_default();
+  let merged = merge::merge_file(&base_bytes, &ours_data, &theirs, "HEAD", subject);
+  if merged.conflicts == 0 {
+    return update(repo, index, &patched(Some(merged.data))).map(|_| false);
+  }
+  let kind = if base_data.is_none() {
+    "add/add"
+  } else {
+    "content"
+  };
+  println!("CONFLICT ({}): Merge conflict in {}", kind, path);
+  if let Some(old_path) = patch.old_path.as_ref().filter(|old| **old != path) {
+    index.remove(old_path);
+    worktree::remove_file(repo, old_path)?;
+  }
+  let hash = object::write(repo, &Blob::new(&merged.data), false)?;
+  worktree::checkout_file(repo, &path, mode, &hash)?;
+  stage_sides(
+    repo,
+    index,
+    &path,
+    mode,
+    [base_data, Some(ours_data), Some(theirs)],
+  )?;
+  Ok(true)
+}
+
+/// Records the base, our and their versions of a conflicted file in stages
+/// 1, 2 and 3 of the index.
+fn stage_sides(
+  repo: &Repo,
+  index: &mut Index,
+  path: &str,
+  mode: Mode,
+  sides: [Option<Vec<u8>>; 3],
+) -> Result<(), String> {
+  index.remove(path);
+  for (stage, data) in (1u16..).zip(sides) {
+    if let Some(data) = data {
+      let hash = object::write(repo, &Blob::new(&data), false)?;
+      index.add(IndexEntry::new(path, mode, &hash).with_stage(stage));
+    }
+  }
+  Ok(())
+}
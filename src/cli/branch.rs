@@ -0,0 +1,264 @@
+use std::path::Path;
+
+use clap::Args;
+use serde::Serialize;
+
+use git_rs_core::{
+  branch::{self, Upstream},
+  color::Colors,
+  object::{commit::Commit, find_object, read, refs, serializable::Unbox},
+  repo::Repo,
+  rev::walk,
+};
+
+/// List or create branches.
+///
+/// Without a name, lists the local branches with a `*` next to the current
+/// one. With `-v`, each branch is followed by the commit it points at and how
+/// far it is ahead of or behind its upstream; `-vv` names the upstream too.
+///
+/// # Example
+/// ```bash
+/// $ git branch -vv
+/// * main  33663e6 [origin/main: ahead 1] Add the frobnicator
+///   topic 8f2c1d0 Start on the widget
+/// $ git branch feature HEAD~2
+/// $ git branch --contains 8f2c1d0
+///   topic
+/// ```
+#[derive(Args, Debug)]
+pub struct Branch {
+  /// The name of the branch to create.
+  pub name: Option<String>,
+
+  /// The commit the new branch will point to.
+  #[clap(default_value_t = String::from("HEAD"))]
+  pub start_point: String,
+
+  /// Show the commit and upstream of each branch. Given twice, also show
+  /// the name of the upstream.
+  #[clap(short, long, parse(from_occurrences))]
+  pub verbose: usize,
+
+  /// Color the branches: `always`, `never` or `auto` (when the output goes
+  /// to a terminal). Without a value, `always`.
+  #[clap(
+    long,
+    value_name = "WHEN",
+    min_values = 0,
+    require_equals = true,
+    default_missing_value = "always",
+    possible_values = &["always", "never", "auto"]
+  )]
+  pub color: Option<String>,
+
+  /// Don't color the branches.
+  #[clap(long, overrides_with = "color")]
+  pub no_color: bool,
+
+  /// Only list the branches that contain this commit (HEAD if not given).
+  #[clap(
+    long,
+    value_name = "COMMIT",
+    min_values = 0,
+    max_values = 1,
+    default_missing_value = "HEAD",
+    conflicts_with = "name"
+  )]
+  pub contains: Option<String>,
+
+  /// Print the branches as a JSON array of [`BranchItem`] objects.
+  #[clap(long, conflicts_with = "name")]
+  pub json: bool,
+}
+
+/// A branch as `branch --json` prints it.
+#[derive(Serialize)]
+pub struct BranchItem {
+  /// The name of the branch without `refs/heads/`, or `None` for a detached
+  /// HEAD.
+  pub name: Option<String>,
+  pub hash: String,
+
+  /// Whether HEAD is on this branch (or is the detached HEAD).
+  pub current: bool,
+
+  /// The branch this one tracks, if it has one.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub upstream: Option<UpstreamItem>,
+}
+
+/// An upstream as `branch --json` and `status --json` print it.
+#[derive(Serialize)]
+pub struct UpstreamItem {
+  /// The short name of the remote-tracking branch, eg. `origin/main`.
+  pub name: String,
+
+  /// How many commits the branch is ahead of and behind its upstream, or
+  /// `None` when the upstream is gone.
+  pub ahead: Option<usize>,
+  pub behind: Option<usize>,
+}
+
+impl From<&Upstream> for UpstreamItem {
+  fn from(upstream: &Upstream) -> UpstreamItem {
+    UpstreamItem {
+      name: upstream.name.clone(),
+      ahead: upstream.ahead_behind.map(|(ahead, _)| ahead),
+      behind: upstream.ahead_behind.map(|(_, behind)| behind),
+    }
+  }
+}
+
+pub fn cmd_branch(opts: &Branch) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  match &opts.name {
+    None => {
+      let color = opts.no_color.then_some("never").or(opts.color.as_deref());
+      let colors = Colors::new(&repo, "branch", color)?;
+      let contains = match &opts.contains {
+        Some(name) => Some(
+          find_object(&repo, name, Some("commit"), true)
+            .map_err(|_| format!("malformed object name {}", name))?,
+        ),
+        None => None,
+      };
+      list_branches(&repo, opts, contains.as_deref(), &colors)
+    }
+    Some(name) => create_branch(&repo, name, &opts.start_point),
+  }
+}
+
+/// Points a new branch at the start point.
+fn create_branch(repo: &Repo, name: &str, start_point: &str) -> Result<(), String> {
+  let refname = format!("refs/heads/{}", name);
+  if refs::exists(repo, &refname) {
+    return Err(format!("a branch named '{}' already exists", name));
+  }
+  let hash = find_object(repo, start_point, Some("commit"), true)
+    .map_err(|_| format!("not a valid object name: '{}'", start_point))?;
+  refs::update(repo, &refname, &hash)
+}
+
+/// Prints the local branches, the current one (or a detached HEAD) marked
+/// with a `*` and in the `branch.current` color. With a commit to contain,
+/// only the branches it can be reached from are printed. With `--json`, the
+/// branches are printed as [`BranchItem`]s instead.
+fn list_branches(
+  repo: &Repo,
+  opts: &Branch,
+  contains: Option<&str>,
+  colors: &Colors,
+) -> Result<(), String> {
+  let current = refs::read_symbolic(repo, "HEAD");
+  let mut branches: Vec<(String, String, bool)> = Vec::new();
+  if current.is_none() {
+    if let Ok(head) = refs::resolve(repo, "HEAD".as_ref()) {
+      let label = match refs::detached_from(repo) {
+        Some((name, hash)) if hash != head => format!("(HEAD detached from {})", name),
+        Some((name, _)) => format!("(HEAD detached at {})", name),
+        None => format!("(HEAD detached at {})", &head[..7]),
+      };
+      branches.push((label, head, true));
+    }
+  }
+  for (refname, hash) in refs::collect(repo, Some(Path::new("refs/heads"))) {
+    let is_current = current.as_ref() == Some(&refname);
+    let name = refname.strip_prefix("refs/heads/").unwrap_or(&refname);
+    branches.push((name.to_string(), hash, is_current));
+  }
+  if let Some(commit) = contains {
+    let tips: Vec<String> = branches.iter().map(|(_, hash, _)| hash.clone()).collect();
+    let mut found = walk::contains(repo, commit, &tips)?.into_iter();
+    branches.retain(|_| found.next().unwrap_or(false));
+  }
+  if opts.json {
+    let mut items = Vec::new();
+    for (name, hash, current) in branches {
+      let (name, upstream) = match name.starts_with('(') {
+        true => (None, None),
+        false => {
+          let upstream = branch::upstream(repo, &name, &hash)?;
+          (Some(name), upstream.as_ref().map(UpstreamItem::from))
+        }
+      };
+      items.push(BranchItem {
+        name,
+        hash,
+        current,
+        upstream,
+      });
+    }
+    println!(
+      "{}",
+      serde_json::to_string(&items).map_err(|e| e.to_string())?
+    );
+    return Ok(());
+  }
+
+  let width = branches
+    .iter()
+    .map(|(name, ..)| name.len())
+    .max()
+    .unwrap_or(0);
+  for (name, hash, is_current) in &branches {
+    let (marker, slot) = match is_current {
+      true => ('*', "branch.current"),
+      false => (' ', "branch.local"),
+    };
+    if opts.verbose == 0 {
+      println!(
+        "{} {}{}{}",
+        marker,
+        colors.color(slot),
+        name,
+        colors.reset()
+      );
+      continue;
+    }
+    let object = read(repo, hash, Some("commit"))?;
+    let commit = object.unbox::<Commit>()?;
+    let converted = commit.to_utf8();
+    let commit = converted.as_ref().unwrap_or(commit);
+    let subject = commit.message().lines().next().unwrap_or("");
+    let tracking = match name.starts_with('(') {
+      true => String::new(),
+      false => tracking_info(repo, name, hash, opts.verbose > 1, colors)?,
+    };
+    let name = format!("{:<width$}", name, width = width);
+    println!(
+      "{} {}{}{} {} {}{}",
+      marker,
+      colors.color(slot),
+      name,
+      colors.reset(),
+      &hash[..7],
+      tracking,
+      subject
+    );
+  }
+  Ok(())
+}
+
+/// The `[origin/main: ahead 1] ` part of a verbose listing, with the name of
+/// the upstream (in the `branch.upstream` color) only when `names` is set.
+fn tracking_info(
+  repo: &Repo,
+  name: &str,
+  hash: &str,
+  names: bool,
+  colors: &Colors,
+) -> Result<String, String> {
+  let upstream = match branch::upstream(repo, name, hash)? {
+    Some(upstream) => upstream,
+    None => return Ok(String::new()),
+  };
+  let short = upstream.short();
+  let name = colors.paint("branch.upstream", &upstream.name);
+  Ok(match (names, short.is_empty()) {
+    (true, true) => format!("[{}] ", name),
+    (true, false) => format!("[{}: {}] ", name, short),
+    (false, true) => String::new(),
+    (false, false) => format!("[{}] ", short),
+  })
+}
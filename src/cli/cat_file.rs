@@ -1,34 +1,133 @@
-use std::path::PathBuf;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process;
 
 use clap::Args;
 
-use crate::{object::read, repo::Repo};
+use git_rs_core::{
+  object::{find_object, header, read, serializable::Unbox, tree::Tree},
+  repo::Repo,
+};
 
 #[derive(Args, Debug)]
 pub struct CatFile {
-  /// Specify the type.
-  #[clap(name = "TYPE")]
-  pub typename: String,
+  /// Specify the type, or the object when one of `-p`, `-t`, `-s` or `-e`
+  /// is given instead.
+  #[clap(name = "TYPE", required_unless_present = "batch-check")]
+  pub typename: Option<String>,
 
   /// The object to display.
-  pub object: String,
+  pub object: Option<String>,
+
+  /// Pretty-print the object: trees as `ls-tree` lists them, and anything
+  /// else as it is.
+  #[clap(short, conflicts_with_all = &["t", "s", "e"])]
+  pub p: bool,
+
+  /// Show the type of the object.
+  #[clap(short, conflicts_with_all = &["s", "e"])]
+  pub t: bool,
+
+  /// Show the size of the object.
+  #[clap(short, conflicts_with = "e")]
+  pub s: bool,
+
+  /// Show nothing, only exit with 0 if the object exists and is valid.
+  #[clap(short)]
+  pub e: bool,
+
+  /// Show the hash, type and size of each object named on standard input.
+  #[clap(long, conflicts_with_all = &["TYPE", "p", "t", "s", "e"])]
+  pub batch_check: bool,
 }
 
 /// Prints a compressed object file.
 ///
-/// Looks in the git directory for an object file with the given object hash. If
-/// found, try to uncompress it and parse the payload data.
+/// Looks in the git directory for the object the revision names (which may
+/// be a path in a commit's tree, like `HEAD:src/main.rs`). If found, try to
+/// uncompress it and parse the payload data. Given a type, the object is
+/// peeled to an object of that type, as `v1.0` names the commit it tags.
+///
+/// `-t` and `-s`, and `--batch-check` for each object named on a line of
+/// standard input, only read the header of the object, so are quick even
+/// for big blobs. `--batch-check` prints `<hash> <type> <size>`, or
+/// `<name> missing` for a name that names nothing.
 ///
 /// # Example
 /// ```bash
 /// $ git cat-file blob 00a534409c6fe1acb2cf24f17d101a4d0016c3f5
+/// $ git cat-file -p HEAD:Cargo.toml
+/// $ git rev-list --objects --no-object-names HEAD | git cat-file --batch-check
 /// ```
 pub fn cmd_cat_file(opts: &CatFile) -> Result<(), String> {
-  if let Some(repo) = Repo::find_repo(&PathBuf::from("."), true)? {
-    let gob = read(repo, &opts.object, Some(&opts.typename))?;
-    print!("{}", String::from_utf8_lossy(gob.serialize()));
-    Ok(())
-  } else {
-    Err("repository not found".to_string())
+  let repo = Repo::discover(Path::new("."))?;
+  if opts.batch_check {
+    return batch_check(&repo);
+  }
+  let typename = opts.typename.as_deref().unwrap_or_default();
+  let flagged = opts.p || opts.t || opts.s || opts.e;
+  let (typename, name) = match (&opts.object, flagged) {
+    (None, true) => (None, typename),
+    (Some(object), false) => (Some(typename), object.as_str()),
+    (None, false) => return Err("cat-file needs an object to show".to_string()),
+    (Some(_), true) => {
+      return Err("only one object may be named with -p, -t, -s or -e".to_string())
+    }
+  };
+  let hash = match find_object(&repo, name, typename, true) {
+    Ok(hash) => hash,
+    Err(_) if opts.e => process::exit(1),
+    Err(_) => return Err(format!("Not a valid object name {}", name)),
+  };
+  if opts.t || opts.s {
+    let (kind, size) = header(&repo, &hash)?;
+    match opts.t {
+      true => println!("{}", kind),
+      false => println!("{}", size),
+    }
+    return Ok(());
+  }
+  let gob = match read(&repo, &hash, typename) {
+    Ok(gob) => gob,
+    Err(_) if opts.e => process::exit(1),
+    Err(err) => return Err(err),
+  };
+
+  if opts.e {
+    return Ok(());
+  }
+  if opts.p && gob.format() == "tree" {
+    for item in gob.unbox::<Tree>()?.entries() {
+      println!(
+        "{} {} {}\t{}",
+        item.mode,
+        item.mode.object_type(),
+        item.hash,
+        item.path
+      );
+    }
+    return Ok(());
+  }
+  // the bytes as they are, whatever encoding they are in
+  io::stdout()
+    .write_all(gob.serialize())
+    .map_err(|e| format!("unable to write the object ({})", e))
+}
+
+/// Prints the hash, type and size of each object named on standard input.
+fn batch_check(repo: &Repo) -> Result<(), String> {
+  let error = |e: io::Error| format!("unable to write the object ({})", e);
+  let mut out = io::stdout().lock();
+  for line in io::stdin().lock().lines() {
+    let name = line.map_err(|e| e.to_string())?;
+    let found = find_object(repo, &name, None, false)
+      .and_then(|hash| header(repo, &hash).map(|header| (hash, header)));
+    match found {
+      Ok((hash, (kind, size))) => writeln!(out, "{} {} {}", hash, kind, size),
+      Err(_) => writeln!(out, "{} missing", name),
+    }
+    .and_then(|()| out.flush())
+    .map_err(error)?;
   }
+  Ok(())
 }
@@ -0,0 +1,59 @@
+use std::io::{self, BufRead, Write};
+
+use clap::Args;
+
+use git_rs_core::identity::mailmap::{self, Mailmap};
+use git_rs_core::repo::Repo;
+
+/// Show canonical names and email addresses of contacts.
+///
+/// Each contact, given as `Name <email>` or `<email>`, is looked up in the
+/// mailmap (see [`Mailmap`]) and printed as it should be shown, or as it is
+/// if the mailmap says nothing about it.
+///
+/// # Example
+/// ```bash
+/// $ git check-mailmap "Jane <jane@old.example.com>"
+/// Jane Doe <jane@example.com>
+/// ```
+#[derive(Args, Debug)]
+pub struct CheckMailmap {
+  /// Read more contacts from standard input, one per line, after those
+  /// given.
+  #[clap(long)]
+  pub stdin: bool,
+
+  /// The contacts to look up.
+  pub contacts: Vec<String>,
+}
+
+pub fn cmd_check_mailmap(opts: &CheckMailmap) -> Result<(), String> {
+  if opts.contacts.is_empty() && !opts.stdin {
+    return Err("no contacts specified".to_string());
+  }
+  let repo: Repo = Repo::default();
+  let mailmap = Mailmap::read(&repo);
+  for contact in &opts.contacts {
+    check(&mailmap, contact)?;
+  }
+  if opts.stdin {
+    for line in io::stdin().lock().lines() {
+      let line = line.map_err(|e| format!("could not read from standard input ({})", e))?;
+      check(&mailmap, &line)?;
+      // whoever is feeding us may be waiting for the answer
+      let _ = io::stdout().flush();
+    }
+  }
+  Ok(())
+}
+
+fn check(mailmap: &Mailmap, contact: &str) -> Result<(), String> {
+  let (name, email, _) = mailmap::split_contact(contact)
+    .ok_or_else(|| format!("unable to parse contact: {}", contact))?;
+  let (name, email) = mailmap.map(name.unwrap_or(""), email);
+  match name.is_empty() {
+    true => println!("<{}>", email),
+    false => println!("{} <{}>", name, email),
+  }
+  Ok(())
+}
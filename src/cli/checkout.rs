@@ -1,32 +1,74 @@
+use bstr::{BString, ByteSlice};
 use clap::Args;
-use std::fs::write;
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, OpenOptionsExt};
 use std::path::Path;
 
-use crate::{
-  object::{blob::Blob, commit::Commit, read, serializable::Unbox, tree::Tree},
+use git_rs_core::{
+  attr::Attributes,
+  diff::blob_data,
+  index::{Index, IndexEntry},
+  merge::{driver::Driver, MergeOptions},
+  object::{self, blob::Blob, commit::Commit, mode::Mode, read, serializable::Unbox, tree::Tree},
+  pathspec,
   repo::Repo,
+  worktree,
 };
 
+/// Checks out a commit or tree into an empty directory, or checks out
+/// unmerged paths from the index.
+///
+/// With `--ours` or `--theirs`, each unmerged path is written out as it is
+/// on one side of the conflict (stage 2 or 3 of the index), and with `-m`
+/// the conflicted merge of the two is made again, markers and all. The
+/// index is left alone, so the paths are still unmerged afterwards.
+///
+/// # Example
+/// ```bash
+/// $ git checkout HEAD~2 /tmp/old
+/// $ git checkout --theirs src/main.rs
+/// $ git checkout -m src/main.rs
+/// ```
 #[derive(Args, Debug)]
 pub struct Checkout {
-  /// The commit or tree to checkout.
-  pub object: String,
+  /// The commit or tree to checkout and the EMPTY directory to checkout on,
+  /// or with `--ours`, `--theirs` or `-m`, the paths to check out.
+  #[clap(required = true)]
+  pub args: Vec<String>,
 
-  /// The EMPTY directory to checkout on.
-  pub path: String,
+  /// Check out our version (stage 2) of unmerged paths.
+  #[clap(long, conflicts_with_all = &["theirs", "merge"])]
+  pub ours: bool,
+
+  /// Check out their version (stage 3) of unmerged paths.
+  #[clap(long, conflicts_with = "merge")]
+  pub theirs: bool,
+
+  /// Make the conflicted merge of unmerged paths again.
+  #[clap(short, long)]
+  pub merge: bool,
 }
 
 pub fn cmd_checkout(opts: &Checkout) -> Result<(), String> {
   let repo: Repo = Repo::default();
+  if opts.ours || opts.theirs || opts.merge {
+    return checkout_paths(&repo, opts);
+  }
+  let (object, path) = match opts.args.as_slice() {
+    [object, path] => (object, path),
+    _ => return Err("checkout needs a commit or tree and a directory".to_string()),
+  };
 
   // Parse the commit into a commit object.
-  let mut object = read(repo.clone(), &opts.object, None)?;
+  let mut object = read(&repo, object, None)?;
 
   // Parse the commit object into a tree.
   if object.format().eq("commit") {
     let commit = object.unbox::<Commit>()?;
-    let tree_hash = commit.map.get("tree").unwrap();
-    object = read(repo.clone(), tree_hash, Some("tree"))?;
+    object = read(&repo, commit.tree(), Some("tree"))?;
   }
 
   let tree = object.unbox::<Tree>()?;
@@ -34,36 +76,151 @@ pub fn cmd_checkout(opts: &Checkout) -> Result<(), String> {
   // Verify the path is an empty directory. If it's not a directory, fail with
   // an Err. if it's not empty, fail with an Err. If some of the paths don't
   // exist yet, create them.
-  let path = Path::new(&opts.path);
+  let dir = Path::new(path);
   // create the directory entries along the path if they do not exist
-  if let Err(msg) = std::fs::create_dir_all(path) {
-    return Err(format!("failed to create path {} ({})", &opts.path, msg));
+  if let Err(msg) = std::fs::create_dir_all(dir) {
+    return Err(format!("failed to create path {} ({})", path, msg));
   }
-  if !path.is_dir() {
-    return Err(format!("{} is not a directory", opts.path));
+  if !dir.is_dir() {
+    return Err(format!("{} is not a directory", path));
   }
-  if path.read_dir().unwrap().next().is_some() {
-    return Err(format!("{} is not empty", opts.path));
+  if dir.read_dir().unwrap().next().is_some() {
+    return Err(format!("{} is not empty", path));
   }
 
-  tree_checkout(&repo, tree, path)?;
+  tree_checkout(&repo, tree, dir)?;
   Ok(())
 }
 
+/// Writes out the paths from the index: a side of each conflict, or the
+/// conflicted merge of both, and paths that are not unmerged as they are.
+fn checkout_paths(repo: &Repo, opts: &Checkout) -> Result<(), String> {
+  let specs = pathspec::resolve(repo, &opts.args)?;
+  let index = Index::read(repo)?;
+  let mut paths: Vec<&BString> = index
+    .entries()
+    .iter()
+    .map(|entry| &entry.path)
+    .filter(|path| pathspec::matches(path, &specs))
+    .collect();
+  paths.dedup();
+  for (spec, arg) in specs.iter().zip(&opts.args) {
+    if !paths
+      .iter()
+      .any(|path| pathspec::matches(path, std::slice::from_ref(spec)))
+    {
+      return Err(format!(
+        "pathspec '{}' did not match any file(s) known to git",
+        arg
+      ));
+    }
+  }
+
+  // check every path can be checked out before writing any of them
+  let mut files: Vec<(&BString, Mode, String)> = Vec::new();
+  for path in paths {
+    if let Some(entry) = index.get(path) {
+      files.push((path, entry_mode(entry)?, entry.hash.clone()));
+      continue;
+    }
+    let [base, ours, theirs] = index.stages(path);
+    let side = match (opts.ours, opts.theirs) {
+      (true, _) => Some((ours, "our")),
+      (_, true) => Some((theirs, "their")),
+      _ => None,
+    };
+    let file = match side {
+      Some((Some(entry), _)) => (path, entry_mode(entry)?, entry.hash.clone()),
+      Some((None, whose)) => {
+        return Err(format!("path '{}' does not have {} version", path, whose))
+      }
+      None => match (ours, theirs) {
+        (Some(ours), Some(theirs)) => (path, entry_mode(ours)?, remerge(repo, base, ours, theirs)?),
+        _ => return Err(format!("path '{}' does not have necessary versions", path)),
+      },
+    };
+    files.push(file);
+  }
+  for (path, mode, hash) in files {
+    worktree::checkout_file(repo, path, mode, &hash)?;
+  }
+  Ok(())
+}
+
+/// Merges the two sides of a conflict again, from the versions in the
+/// index, and returns the hash of the result.
+fn remerge(
+  repo: &Repo,
+  base: Option<&IndexEntry>,
+  ours: &IndexEntry,
+  theirs: &IndexEntry,
+) -> Result<String, String> {
+  let data = |entry: &IndexEntry| -> Result<Vec<u8>, String> {
+    blob_data(repo, Some(&(entry_mode(entry)?, entry.hash.clone())))
+  };
+  let base = match base {
+    Some(base) => data(base)?,
+    None => Vec::new(),
+  };
+  let options = MergeOptions {
+    ours_label: "ours",
+    theirs_label: "theirs",
+    ..Default::default()
+  };
+  let path = ours.path.to_str_lossy();
+  let driver = Driver::for_path(repo, &mut Attributes::new(repo), ours.path.as_slice());
+  let merged = driver.merge(
+    repo,
+    &path,
+    [&base, &data(ours)?, &data(theirs)?],
+    &options,
+    0,
+  )?;
+  object::write(repo, &Blob::new(&merged.data), false)
+}
+
+fn entry_mode(entry: &IndexEntry) -> Result<Mode, String> {
+  entry
+    .tree_mode()
+    .ok_or_else(|| format!("invalid mode for {}", entry.path.as_bstr()))
+}
+
+/// Writes out the entries of a tree under `path`, each with the permissions
+/// its mode gives it: executables as executable, and symbolic links as
+/// links to the path the blob holds.
 fn tree_checkout(repo: &Repo, tree: &Tree, path: &Path) -> Result<(), String> {
   for item in tree.entries() {
-    let obj = read(repo.clone(), &item.hash, None)?;
-    let dest = path.join(item.path.as_str());
-
-    if obj.format().eq("tree") {
-      if let Err(msg) = std::fs::create_dir_all(&dest) {
-        return Err(format!("failed to create path {:?} ({})", &dest, msg));
+    let dest = path.join(OsStr::from_bytes(&item.path));
+    let created = |result: io::Result<()>| {
+      result.map_err(|msg| format!("failed to create path {:?} ({})", &dest, msg))
+    };
+    let written = |result: io::Result<()>| {
+      result.map_err(|msg| format!("failed to write file {:?} ({})", &dest, msg))
+    };
+    match item.mode {
+      // submodules are left as empty directories
+      Mode::Gitlink => created(fs::create_dir_all(&dest))?,
+      Mode::Directory => {
+        created(fs::create_dir_all(&dest))?;
+        let object = read(repo, &item.hash, Some("tree"))?;
+        tree_checkout(repo, object.unbox::<Tree>()?, &dest)?;
+      }
+      Mode::Symbolic => {
+        let object = read(repo, &item.hash, Some("blob"))?;
+        let target = OsStr::from_bytes(object.unbox::<Blob>()?.data());
+        written(symlink(target, &dest))?;
       }
-      let tree = obj.unbox::<Tree>()?;
-      tree_checkout(repo, tree, &dest)?;
-    } else if obj.format().eq("blob") {
-      if let Err(msg) = write(&dest, &obj.unbox::<Blob>()?.data()) {
-        return Err(format!("failed to write file {:?} ({})", &dest, msg));
+      mode => {
+        let object = read(repo, &item.hash, Some("blob"))?;
+        let data = object.unbox::<Blob>()?.data();
+        written(
+          OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode.permissions().unwrap_or(0o666))
+            .open(&dest)
+            .and_then(|mut file| file.write_all(data)),
+        )?;
       }
     }
   }
@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+
+use clap::Args;
+
+use git_rs_core::{
+  branch,
+  diff::patch_id::commit_patch_id,
+  object::{commit::Commit, find_object, read, refs, serializable::Unbox},
+  repo::Repo,
+  rev::walk::RevWalk,
+};
+
+/// Find commits yet to be applied to upstream.
+///
+/// Lists the commits of `<head>` that are not in `<upstream>`, oldest first,
+/// marking each with `-` if a commit with the same change (by patch id) is
+/// already in `<upstream>` and `+` if not. Commits in `<limit>` are left out.
+/// Without `<upstream>`, the branch the current branch tracks is used.
+///
+/// # Example
+/// ```bash
+/// $ git cherry -v origin/main
+/// - 5e4d3c2b1a0f9e8d7c6b5a4f3e2d1c0b9a8f7e6d Fix the parser
+/// + 1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b Add the frobnicator
+/// ```
+#[derive(Args, Debug)]
+pub struct Cherry {
+  /// Show the subjects of the commits too.
+  #[clap(short, long)]
+  pub verbose: bool,
+
+  /// The branch to look for the commits in.
+  pub upstream: Option<String>,
+
+  /// The branch the commits are on (`HEAD` if not given).
+  pub head: Option<String>,
+
+  /// Leave out the commits up to and including this one.
+  pub limit: Option<String>,
+}
+
+pub fn cmd_cherry(opts: &Cherry) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let upstream = match &opts.upstream {
+    Some(upstream) => upstream.clone(),
+    None => refs::read_symbolic(&repo, "HEAD")
+      .and_then(|head| branch::tracking_ref(&repo, head.strip_prefix("refs/heads/")?))
+      .ok_or("Could not find a tracked remote branch, please specify <upstream> manually.")?,
+  };
+  let resolve = |name: &str| {
+    find_object(&repo, name, Some("commit"), true).map_err(|_| format!("unknown commit {}", name))
+  };
+  let upstream = resolve(&upstream)?;
+  let head = resolve(opts.head.as_deref().unwrap_or("HEAD"))?;
+  if upstream == head {
+    return Ok(());
+  }
+
+  // the patch ids of the commits only in upstream
+  let mut walk = RevWalk::new(&repo);
+  walk.push(&upstream);
+  walk.hide(&head);
+  let mut upstream_ids = HashSet::new();
+  for hash in walk.run()? {
+    if let Some(id) = commit_patch_id(&repo, &hash, false)? {
+      upstream_ids.insert(id);
+    }
+  }
+
+  let mut walk = RevWalk::new(&repo);
+  walk.push(&head);
+  walk.hide(&upstream);
+  if let Some(limit) = &opts.limit {
+    walk.hide(&resolve(limit)?);
+  }
+  let mut commits = walk.run()?;
+  commits.reverse();
+  for hash in commits {
+    let id = match commit_patch_id(&repo, &hash, false)? {
+      Some(id) => id,
+      None => continue,
+    };
+    let sign = if upstream_ids.contains(&id) { '-' } else { '+' };
+    if opts.verbose {
+      let object = read(&repo, &hash, Some("commit"))?;
+      let commit = object.unbox::<Commit>()?;
+      let converted = commit.to_utf8();
+      let commit = converted.as_ref().unwrap_or(commit);
+      let subject = commit.message().lines().next().unwrap_or("");
+      println!("{} {} {}", sign, hash, subject);
+    } else {
+      println!("{} {}", sign, hash);
+    }
+  }
+  Ok(())
+}
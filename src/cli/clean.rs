@@ -0,0 +1,121 @@
+use clap::Args;
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use git_rs_core::{ignore::Ignore, index::Index, pathspec, repo::Repo, worktree};
+
+/// Remove untracked files from the working tree.
+///
+/// Deletes the files that git does not track, leaving ignored files alone
+/// unless `-x` (everything) or `-X` (only ignored files) is given. Untracked
+/// directories are only removed with `-d`. Since this cannot be undone, `-f`
+/// is required unless doing a dry run (`-n`) or choosing interactively (`-i`).
+///
+/// # Example
+/// ```bash
+/// $ git clean -n -d
+/// Would remove build/
+/// Would remove notes.txt
+/// $ git clean -fdx
+/// ```
+#[derive(Args, Debug)]
+pub struct Clean {
+  /// Remove untracked directories too.
+  #[clap(short)]
+  pub d: bool,
+
+  /// Actually remove the files.
+  #[clap(short, long)]
+  pub force: bool,
+
+  /// Show what would be removed without removing anything.
+  #[clap(short = 'n', long)]
+  pub dry_run: bool,
+
+  /// Ask before removing each file.
+  #[clap(short, long)]
+  pub interactive: bool,
+
+  /// Do not list the removed files.
+  #[clap(short, long)]
+  pub quiet: bool,
+
+  /// Remove ignored files as well.
+  #[clap(short = 'x', conflicts_with = "only-ignored")]
+  pub all: bool,
+
+  /// Remove only ignored files.
+  #[clap(short = 'X')]
+  pub only_ignored: bool,
+
+  /// Only clean these paths.
+  pub paths: Vec<String>,
+}
+
+pub fn cmd_clean(opts: &Clean) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  if !opts.force && !opts.dry_run && !opts.interactive {
+    return Err(
+      "clean.requireForce defaults to true and neither -i, -n, nor -f given; refusing to clean"
+        .to_string(),
+    );
+  }
+  let paths = pathspec::resolve(&repo, &opts.paths)?;
+  let index = Index::read(&repo)?;
+  let mut ignore = Ignore::new(&repo);
+
+  let mut candidates: Vec<worktree::Untracked> =
+    worktree::untracked(&repo, &index, &mut ignore, !opts.all)?
+      .into_iter()
+      .filter(|u| match (opts.all, opts.only_ignored) {
+        (true, _) => true,
+        (_, true) => u.ignored,
+        _ => !u.ignored,
+      })
+      .filter(|u| opts.d || !u.is_dir)
+      .filter(|u| pathspec::matches(u.path.strip_suffix(b"/").unwrap_or(&u.path), &paths))
+      .collect();
+
+  if opts.interactive && !opts.dry_run {
+    candidates = choose(candidates)?;
+  }
+
+  for candidate in candidates {
+    if opts.dry_run {
+      println!("Would remove {}", candidate.path);
+      continue;
+    }
+    if !opts.quiet {
+      println!("Removing {}", candidate.path);
+    }
+    let full_path = repo.work_tree_path(&candidate.path);
+    let removed = if candidate.is_dir {
+      fs::remove_dir_all(&full_path)
+    } else {
+      fs::remove_file(&full_path)
+    };
+    if let Err(e) = removed {
+      println!("warning: failed to remove {}: {}", candidate.path, e);
+    }
+  }
+  Ok(())
+}
+
+/// Asks about each file on the terminal and keeps the ones confirmed.
+fn choose(candidates: Vec<worktree::Untracked>) -> Result<Vec<worktree::Untracked>, String> {
+  let stdin = io::stdin();
+  let mut lines = stdin.lock().lines();
+  let mut chosen = Vec::new();
+  for candidate in candidates {
+    print!("Remove {} [y/N]? ", candidate.path);
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let answer = match lines.next() {
+      Some(line) => line.map_err(|e| e.to_string())?,
+      None => break,
+    };
+    if answer.trim().eq_ignore_ascii_case("y") || answer.trim().eq_ignore_ascii_case("yes") {
+      chosen.push(candidate);
+    }
+  }
+  Ok(chosen)
+}
@@ -1,10 +1,383 @@
 use clap::Args;
+use std::fs;
+use std::io::{self, Read};
 
+use git_rs_core::{
+  color::Colors,
+  diff::{patch, TreeChange},
+  editor,
+  identity::{
+    date::{self, approxidate},
+    Role, Signature,
+  },
+  index::Index,
+  merge::state::MergeState,
+  object::{
+    commit::{Commit as CommitObject, CommitBuilder},
+    read, refs,
+    serializable::Unbox,
+  },
+  repo::Repo,
+  rerere, rev,
+  status::Status,
+  trailer,
+};
+
+/// Record changes to the repository.
+///
+/// Creates a new commit from the contents of the index, on top of HEAD, and
+/// moves the current branch to it. Without `-m` or `-F` the message is written
+/// in an editor (`GIT_EDITOR`, `core.editor`, `VISUAL` or `EDITOR`), starting
+/// from a template that summarizes what is about to be committed.
+///
+/// With `--amend` the last commit is replaced instead: the new commit takes
+/// its parents, author and (unless another one is given) message, and its
+/// tree from the index.
+///
+/// # Example
+/// ```bash
+/// $ git commit -m "Fix the frobnicator"
+/// [main 9f3c2a1] Fix the frobnicator
+///  1 file changed, 2 insertions(+), 1 deletion(-)
+/// $ git commit --amend --no-edit
+/// ```
 #[derive(Args, Debug)]
 pub struct Commit {
-  pub name: Option<String>,
+  /// Use the given message. Given more than once, each is its own paragraph.
+  #[clap(short, long, multiple_occurrences = true)]
+  pub message: Vec<String>,
+
+  /// Take the message from a file (`-` for standard input).
+  #[clap(short = 'F', long, value_name = "FILE", conflicts_with = "message")]
+  pub file: Option<String>,
+
+  /// Edit the message even though it was given with `-m`, `-F` or `--amend`.
+  #[clap(short, long)]
+  pub edit: bool,
+
+  /// Use the message of the amended commit as is.
+  #[clap(long, conflicts_with = "edit")]
+  pub no_edit: bool,
+
+  /// Replace the last commit instead of adding a new one.
+  #[clap(long)]
+  pub amend: bool,
+
+  /// Override the author date. Any format git understands is accepted,
+  /// including relative dates such as `2 days ago`.
+  #[clap(long)]
+  pub date: Option<String>,
+
+  /// Add a `Signed-off-by` trailer for the committer to the message, unless
+  /// it already ends with one.
+  #[clap(short, long)]
+  pub signoff: bool,
+
+  /// Commit even if the tree is the same as its parent's.
+  #[clap(long)]
+  pub allow_empty: bool,
+
+  /// Do not print the summary of the new commit.
+  #[clap(short, long)]
+  pub quiet: bool,
+}
+
+pub fn cmd_commit(opts: &Commit) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let mut index = Index::read(&repo)?;
+  if index.entries().iter().any(|e| e.stage() != 0) {
+    return Err("Committing is not possible because you have unmerged files.".to_string());
+  }
+  rerere::run(&repo)?
+    .iter()
+    .for_each(|message| println!("{}", message));
+
+  let head = rev::parse(&repo, "HEAD").ok();
+  let merging = MergeState::read(&repo)?;
+  if merging.is_some() && opts.amend {
+    return Err("You are in the middle of a merge -- cannot amend.".to_string());
+  }
+  let object = match (&head, opts.amend) {
+    (Some(head), true) => Some(read(&repo, head, Some("commit"))?),
+    (None, true) => return Err("You have nothing to amend.".to_string()),
+    (_, false) => None,
+  };
+  let amended: Option<&CommitObject> = match &object {
+    Some(object) => Some(object.unbox::<CommitObject>()?),
+    None => None,
+  };
+  let parents: Vec<String> = match amended {
+    Some(commit) => commit.parents().to_vec(),
+    None => head
+      .iter()
+      .chain(merging.iter().flat_map(|m| &m.heads))
+      .cloned()
+      .collect(),
+  };
+
+  let status = Status::collect(&repo, &mut index, parents.first().map(|p| p.as_str()))?;
+  if status.staged.is_empty() && amended.is_none() && merging.is_none() && !opts.allow_empty {
+    print!("{}", status.long(&Colors::new(&repo, "status", None)?));
+    return Ok(());
+  }
+
+  let mut author = match amended.and_then(|commit| commit.get("author")) {
+    Some(line) => Signature::parse(line).ok_or_else(|| "corrupt author".to_string())?,
+    None => Signature::current(&repo, Role::Author)?,
+  };
+  if let Some(text) = &opts.date {
+    (author.time, author.timezone) =
+      approxidate(text, date::now()).ok_or_else(|| format!("invalid date format: {}", text))?;
+  }
+  let committer = Signature::current(&repo, Role::Committer)?;
+  let builder = CommitBuilder::new()
+    .author(author.clone())
+    .committer(committer.clone());
+  let (author, committer) = (author.to_string(), committer.to_string());
+  let message = match message(
+    &repo,
+    opts,
+    amended,
+    merging.as_ref(),
+    &status,
+    &author,
+    &committer,
+  )? {
+    Some(message) => message,
+    None => {
+      println!("Aborting commit due to empty commit message.");
+      return Ok(());
+    }
+  };
+
+  let hash = builder
+    .tree(&index.write_tree(&repo)?)
+    .parents(&parents)
+    .message(&message)
+    .write(&repo)?;
+
+  let title = message.lines().next().unwrap_or("");
+  let reason = match (&amended, status.initial) {
+    (Some(_), _) => format!("commit (amend): {}", title),
+    (None, true) => format!("commit (initial): {}", title),
+    (None, false) if merging.is_some() => format!("commit (merge): {}", title),
+    (None, false) => format!("commit: {}", title),
+  };
+  update_head(&repo, head.as_deref(), &hash, &committer, &reason)?;
+  if merging.is_some() {
+    MergeState::clear(&repo)?;
+  }
+  if index.is_changed() {
+    index.write(&repo)?;
+  }
+
+  if !opts.quiet {
+    let mut line = format!(
+      "[{}{} {}] {}\n",
+      status.branch.as_deref().unwrap_or("detached HEAD"),
+      if status.initial { " (root-commit)" } else { "" },
+      &hash[..7],
+      subject(&message)
+    );
+    if person(&author) != person(&committer) {
+      line.push_str(&format!(" Author: {}\n", person(&author)));
+    }
+    if amended.is_some() || opts.date.is_some() {
+      line.push_str(&format!(" Date: {}\n", date(&author)));
+    }
+    line.push_str(&summary(&repo, &status.staged)?);
+    print!("{}", line);
+  }
+  Ok(())
+}
+
+/// Works out the message of the new commit, launching the editor if needed,
+/// and cleans it up. Without one given, it starts from the amended commit's
+/// message, or that of the merge being concluded. Returns `None` if the
+/// message ends up empty.
+fn message(
+  repo: &Repo,
+  opts: &Commit,
+  amended: Option<&CommitObject>,
+  merging: Option<&MergeState>,
+  status: &Status,
+  author: &str,
+  committer: &str,
+) -> Result<Option<String>, String> {
+  let given = !opts.message.is_empty() || opts.file.is_some();
+  let text = if !opts.message.is_empty() {
+    opts.message.join("\n\n")
+  } else if let Some(path) = &opts.file {
+    let mut text = String::new();
+    match path.as_str() {
+      "-" => io::stdin()
+        .read_to_string(&mut text)
+        .map(|_| ())
+        .map_err(|e| format!("could not read log from standard input ({})", e))?,
+      _ => {
+        text =
+          fs::read_to_string(path).map_err(|_| format!("could not read log file '{}'", path))?
+      }
+    }
+    text
+  } else {
+    match (amended, merging) {
+      (Some(commit), _) => commit.message().to_owned(),
+      (None, Some(merging)) => merging.message.clone(),
+      (None, None) => String::new(),
+    }
+  };
+  let text = match opts.signoff {
+    true => trailer::sign_off(&text, &person(committer)),
+    false => text,
+  };
+
+  let path = repo.git_dir.join("COMMIT_EDITMSG");
+  let write = |data: &str| {
+    fs::write(&path, data).map_err(|e| format!("could not write COMMIT_EDITMSG ({})", e))
+  };
+  let edit = opts.edit || (!given && !opts.no_edit);
+  if !edit {
+    write(&text)?;
+    return Ok(Some(cleanup(&text, false)).filter(|m| !m.is_empty()));
+  }
+
+  let mut template = text;
+  if !template.ends_with('\n') {
+    template.push('\n');
+  }
+  template.push('\n');
+  template.push_str(&template_summary(
+    status,
+    author,
+    committer,
+    amended.is_some() || opts.date.is_some(),
+  ));
+  write(&template)?;
+  editor::launch(repo, &path.to_string_lossy())?;
+  let edited =
+    fs::read_to_string(&path).map_err(|e| format!("could not read COMMIT_EDITMSG ({})", e))?;
+  Ok(Some(cleanup(&edited, true)).filter(|m| !m.is_empty()))
+}
+
+/// Tidies up a commit message: strips trailing whitespace, squeezes runs of
+/// blank lines into one and drops leading and trailing blank lines. With
+/// `strip_comments`, lines starting with `#` are removed too.
+fn cleanup(text: &str, strip_comments: bool) -> String {
+  let mut out = String::new();
+  let mut blank = false;
+  for line in text.lines() {
+    if strip_comments && line.starts_with('#') {
+      continue;
+    }
+    let line = line.trim_end();
+    if line.is_empty() {
+      blank = !out.is_empty();
+      continue;
+    }
+    if blank {
+      out.push('\n');
+      blank = false;
+    }
+    out.push_str(line);
+    out.push('\n');
+  }
+  out
+}
+
+/// Moves HEAD (and the branch it is on) to the new commit and records the
+/// move in their reflogs.
+fn update_head(
+  repo: &Repo,
+  old: Option<&str>,
+  new: &str,
+  committer: &str,
+  reason: &str,
+) -> Result<(), String> {
+  refs::update(repo, "HEAD", new)?;
+  refs::append_log(repo, "HEAD", old, new, committer, reason)?;
+  if let Some(branch) = refs::read_symbolic(repo, "HEAD") {
+    refs::append_log(repo, &branch, old, new, committer, reason)?;
+  }
+  Ok(())
+}
+
+/// The title of a message: its first paragraph on one line.
+fn subject(message: &str) -> String {
+  message
+    .lines()
+    .take_while(|line| !line.trim().is_empty())
+    .collect::<Vec<&str>>()
+    .join(" ")
+}
+
+/// The `Name <email>` part of an author or committer line.
+fn person(line: &str) -> String {
+  Signature::parse(line)
+    .map(|signature| signature.person())
+    .unwrap_or_default()
+}
+
+/// The date of an author or committer line, formatted for people.
+fn date(line: &str) -> String {
+  Signature::parse(line)
+    .map(|signature| signature.date())
+    .unwrap_or_default()
+}
+
+/// The stat line and created/deleted file lines printed after committing.
+fn summary(repo: &Repo, changes: &[TreeChange]) -> Result<String, String> {
+  if changes.is_empty() {
+    return Ok(String::new());
+  }
+  let (files, insertions, deletions) = patch::totals(repo, changes)?;
+  Ok(format!(
+    "{}\n{}",
+    patch::shortstat(files, insertions, deletions),
+    patch::summary(repo, changes)?
+  ))
 }
 
-pub fn cmd_commit() -> Result<(), String> {
-  todo!("cmd_commit");
+/// The commented summary appended to the message in the editor.
+fn template_summary(status: &Status, author: &str, committer: &str, show_date: bool) -> String {
+  let mut out = String::from(
+    "# Please enter the commit message for your changes. Lines starting\n\
+     # with '#' will be ignored, and an empty message aborts the commit.\n#\n",
+  );
+  let mut extra = false;
+  if person(author) != person(committer) {
+    out.push_str(&format!("# Author:    {}\n", person(author)));
+    extra = true;
+  }
+  if show_date {
+    out.push_str(&format!("# Date:      {}\n", date(author)));
+    extra = true;
+  }
+  if extra {
+    out.push_str("#\n");
+  }
+  out.push_str(&format!("# {}\n", status.position()));
+  if status.initial {
+    out.push_str("#\n# Initial commit\n#\n");
+  }
+  let sections = [
+    ("Changes to be committed:", status.staged_lines()),
+    ("Changes not staged for commit:", status.unstaged_lines()),
+    (
+      "Untracked files:",
+      status
+        .untracked
+        .iter()
+        .map(|path| path.to_string())
+        .collect(),
+    ),
+  ];
+  for (title, lines) in sections.iter().filter(|(_, lines)| !lines.is_empty()) {
+    out.push_str(&format!("# {}\n", title));
+    for line in lines {
+      out.push_str(&format!("#\t{}\n", line));
+    }
+    out.push_str("#\n");
+  }
+  out
 }
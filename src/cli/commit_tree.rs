@@ -0,0 +1,99 @@
+use clap::Args;
+use std::io::{self, Read};
+
+use git_rs_core::{
+  identity::{Role, Signature},
+  object::{commit::CommitBuilder, find_object},
+  repo::Repo,
+};
+
+/// Create a new commit object.
+///
+/// Writes a commit for the given tree with the given parents and prints its
+/// hash. The message comes from `-m` or `-F`, or standard input if neither is
+/// given. The author and committer are read from the `GIT_AUTHOR_NAME`,
+/// `GIT_AUTHOR_EMAIL` and `GIT_AUTHOR_DATE` environment variables (and their
+/// `GIT_COMMITTER_*` counterparts), falling back to `user.name` and
+/// `user.email` from the repository's config or `~/.gitconfig`, and the
+/// current time. Dates may be given as `<seconds> <timezone>`, in ISO 8601 or
+/// in RFC 2822 format.
+///
+/// # Example
+/// ```bash
+/// $ tree=$(git write-tree)
+/// $ git commit-tree $tree -p HEAD -m "update readme"
+/// ```
+#[derive(Args, Debug)]
+pub struct CommitTree {
+  /// The tree the commit records.
+  pub tree: String,
+
+  /// A parent commit (may be given more than once).
+  #[clap(short, value_name = "PARENT", multiple_occurrences = true)]
+  pub p: Vec<String>,
+
+  /// A paragraph of the commit message (may be given more than once).
+  #[clap(short, value_name = "MESSAGE", multiple_occurrences = true)]
+  pub m: Vec<String>,
+
+  /// Read the commit message from a file (`-` for standard input).
+  #[clap(short = 'F', value_name = "FILE", multiple_occurrences = true)]
+  pub file: Vec<String>,
+}
+
+pub fn cmd_commit_tree(opts: &CommitTree) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let tree = find_object(&repo, &opts.tree, Some("tree"), true)?;
+
+  let mut parents: Vec<String> = Vec::new();
+  for parent in &opts.p {
+    let parent = find_object(&repo, parent, Some("commit"), true)?;
+    if !parents.contains(&parent) {
+      parents.push(parent);
+    }
+  }
+
+  let hash = CommitBuilder::new()
+    .tree(&tree)
+    .parents(&parents)
+    .author(Signature::current(&repo, Role::Author)?)
+    .committer(Signature::current(&repo, Role::Committer)?)
+    .message(&message(opts)?)
+    .write(&repo)?;
+  println!("{}", hash);
+  Ok(())
+}
+
+/// Builds the message from `-m` paragraphs and `-F` files, or standard input.
+fn message(opts: &CommitTree) -> Result<String, String> {
+  let mut paragraphs: Vec<String> = opts.m.clone();
+  for file in &opts.file {
+    let text = if file == "-" {
+      read_stdin()?
+    } else {
+      std::fs::read_to_string(file)
+        .map_err(|e| format!("could not read log file '{}': {}", file, e))?
+    };
+    paragraphs.push(text);
+  }
+  if opts.m.is_empty() && opts.file.is_empty() {
+    paragraphs.push(read_stdin()?);
+  }
+  let paragraphs: Vec<&str> = paragraphs
+    .iter()
+    .map(|p| p.trim_end_matches('\n'))
+    .collect();
+  let mut message = paragraphs.join("\n\n");
+  if !message.is_empty() {
+    message.push('\n');
+  }
+  Ok(message)
+}
+
+fn read_stdin() -> Result<String, String> {
+  let mut text = String::new();
+  io::stdin()
+    .read_to_string(&mut text)
+    .map_err(|e| format!("could not read from standard input ({})", e))?;
+  Ok(text)
+}
@@ -0,0 +1,190 @@
+use std::path::Path;
+
+use clap::{Args, CommandFactory};
+use clap_complete::{generate, Shell};
+
+use crate::cli::Arguments;
+use git_rs_core::object::refs;
+use git_rs_core::repo::Repo;
+
+/// Print a script that completes commands in a shell.
+///
+/// The script is generated from the commands and options `git-rs` takes,
+/// for `bash`, `zsh`, `fish` or `powershell`. Where a command takes a
+/// branch or other ref, the script also asks `git-rs completions --refs`
+/// for the refs of the repository it is completing in.
+///
+/// # Example
+/// ```bash
+/// $ git-rs completions bash > /etc/bash_completion.d/git-rs
+/// $ git-rs completions zsh > ~/.zfunc/_git-rs
+/// ```
+#[derive(Args, Debug)]
+pub struct Completions {
+  /// The shell to complete in.
+  #[clap(
+    possible_values = &["bash", "zsh", "fish", "powershell"],
+    required_unless_present = "refs"
+  )]
+  pub shell: Option<String>,
+
+  /// List the branches, tags and remote branches of the repository, for the
+  /// scripts to complete refs with.
+  #[clap(long, hide = true)]
+  pub refs: bool,
+}
+
+/// The commands whose arguments are refs.
+const REF_COMMANDS: [&str; 17] = [
+  "branch",
+  "checkout",
+  "cherry",
+  "diff-tree",
+  "format-patch",
+  "log",
+  "merge",
+  "name-rev",
+  "range-diff",
+  "rebase",
+  "reset",
+  "rev-list",
+  "rev-parse",
+  "show",
+  "show-ref",
+  "switch",
+  "tag",
+];
+
+pub fn cmd_completions(opts: &Completions) -> Result<(), String> {
+  if opts.refs {
+    // completing outside a repository just finds no refs
+    if let Ok(repo) = Repo::discover(Path::new(".")) {
+      list_refs(&repo);
+    }
+    return Ok(());
+  }
+  let shell = match opts.shell.as_deref() {
+    Some("bash") => Shell::Bash,
+    Some("zsh") => Shell::Zsh,
+    Some("fish") => Shell::Fish,
+    _ => Shell::PowerShell,
+  };
+  let mut command = Arguments::command();
+  let name = command.get_name().to_string();
+  let mut script = Vec::new();
+  generate(shell, &mut command, &name, &mut script);
+  let script = String::from_utf8(script).map_err(|e| e.to_string())?;
+  print!("{}", hook_refs(shell, &name, &script));
+  Ok(())
+}
+
+/// Adds the completion of refs to a generated script: after what it offers
+/// for a command in [`REF_COMMANDS`], the refs are offered too, unless an
+/// option is being completed.
+fn hook_refs(shell: Shell, name: &str, script: &str) -> String {
+  let function = format!("_{}", name);
+  match shell {
+    Shell::Bash => {
+      let hook = format!(
+        r#"
+{function}_refs() {{
+    {function} "$@"
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" word
+    [[ "${{cur}}" == -* ]] && return 0
+    for word in "${{COMP_WORDS[@]:1:COMP_CWORD-1}}"; do
+        case "${{word}}" in
+            {cases})
+                COMPREPLY+=( $(compgen -W "$("$1" completions --refs 2>/dev/null)" -- "${{cur}}") )
+                return 0
+                ;;
+        esac
+    done
+}}
+
+complete -F {function}_refs -o bashdefault -o default {name}
+"#,
+        function = function,
+        name = name,
+        cases = REF_COMMANDS.join("|"),
+      );
+      let complete = format!("complete -F {} ", function);
+      let script: String = script
+        .lines()
+        .filter(|line| !line.starts_with(&complete))
+        .map(|line| format!("{}\n", line))
+        .collect();
+      script.trim_end().to_string() + "\n" + &hook
+    }
+    Shell::Zsh => {
+      // the generated function is wrapped by one of the same name, which
+      // is what the script calls at the end
+      let hook = format!(
+        r#"{function}() {{
+    {function}_generated "$@"
+    local ret=$?
+    if [[ ${{PREFIX}} != -* ]] && (( ${{words[(I)({cases})]}} )); then
+        local -a refs
+        refs=(${{(f)"$(${{words[1]}} completions --refs 2>/dev/null)"}})
+        compadd -a refs && ret=0
+    fi
+    return ret
+}}
+
+{function} "$@"
+"#,
+        function = function,
+        cases = REF_COMMANDS.join("|"),
+      );
+      let script = script.replacen(
+        &format!("{}() {{", function),
+        &format!("{}_generated() {{", function),
+        1,
+      );
+      let call = format!("{} \"$@\"", function);
+      let end = script.rfind(&call).unwrap_or(script.len());
+      script[..end].to_string() + &hook
+    }
+    Shell::Fish => format!(
+      "{}complete -c {} -n \"__fish_seen_subcommand_from {}\" -a \"({} completions --refs 2>/dev/null)\"\n",
+      script,
+      name,
+      REF_COMMANDS.join(" "),
+      name
+    ),
+    _ => {
+      let commands: Vec<String> = REF_COMMANDS
+        .iter()
+        .map(|command| format!("'{}'", command))
+        .collect();
+      let hook = format!(
+        r#"    $refCommands = @({commands})
+    $words = @($commandElements | ForEach-Object {{ $_.ToString() }})
+    if (-not $wordToComplete.StartsWith('-') -and @($words | Where-Object {{ $refCommands -contains $_ }}).Count -gt 0) {{
+        & $words[0] completions --refs 2>$null | ForEach-Object {{
+            $completions += [CompletionResult]::new($_, $_, [CompletionResultType]::ParameterValue, $_)
+        }}
+    }}
+
+"#,
+        commands = commands.join(", "),
+      );
+      let filter = "    $completions.Where{";
+      match script.rfind(filter) {
+        Some(at) => format!("{}{}{}", &script[..at], hook, &script[at..]),
+        None => script.to_string(),
+      }
+    }
+  }
+}
+
+/// Prints the short names of the refs completions offer: `HEAD`, then the
+/// branches, tags and remote branches.
+fn list_refs(repo: &Repo) {
+  let all = refs::collect(repo, None);
+  println!("HEAD");
+  for prefix in ["refs/heads/", "refs/tags/", "refs/remotes/"] {
+    for name in all.keys().filter_map(|name| name.strip_prefix(prefix)) {
+      println!("{}", name);
+    }
+  }
+}
@@ -0,0 +1,91 @@
+use clap::Args;
+
+use git_rs_core::repo::Repo;
+
+/// Count unpacked objects and their disk consumption.
+///
+/// With `-v`, the packs and any garbage in the object database are counted
+/// too, which is what tells whether a repack is due.
+///
+/// # Example
+/// ```bash
+/// $ git count-objects -v
+/// count: 12
+/// size: 48
+/// in-pack: 3050
+/// packs: 1
+/// size-pack: 1021
+/// prune-packable: 0
+/// garbage: 0
+/// size-garbage: 0
+/// ```
+#[derive(Args, Debug)]
+pub struct CountObjects {
+  /// Report the packs and garbage as well.
+  #[clap(short, long)]
+  pub verbose: bool,
+
+  /// Print sizes in human readable units.
+  #[clap(short = 'H', long)]
+  pub human_readable: bool,
+}
+
+pub fn cmd_count_objects(opts: &CountObjects) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let stats = repo.objects.disk_stats();
+  let size = |bytes: u64| match opts.human_readable {
+    true => humanise(bytes),
+    false => (bytes / 1024).to_string(),
+  };
+
+  if !opts.verbose {
+    match opts.human_readable {
+      true => println!("{} objects, {}", stats.loose, humanise(stats.loose_size)),
+      false => println!(
+        "{} objects, {} kilobytes",
+        stats.loose,
+        stats.loose_size / 1024
+      ),
+    }
+    return Ok(());
+  }
+  let cwd = std::env::current_dir().unwrap_or_default();
+  for (path, why) in &stats.garbage {
+    let path = path.strip_prefix(&cwd).unwrap_or(path);
+    eprintln!("warning: {}: {}", why, path.display());
+  }
+  println!("count: {}", stats.loose);
+  println!("size: {}", size(stats.loose_size));
+  println!("in-pack: {}", stats.in_pack);
+  println!("packs: {}", stats.packs);
+  println!("size-pack: {}", size(stats.pack_size));
+  println!("prune-packable: {}", stats.prune_packable);
+  println!("garbage: {}", stats.garbage.len());
+  println!("size-garbage: {}", size(stats.garbage_size));
+  for alternate in repo.objects.alternates() {
+    println!("alternate: {}", alternate.dir().display());
+  }
+  Ok(())
+}
+
+/// A size in the largest binary unit there is more than one of, to two
+/// places, rounded the way git rounds it.
+fn humanise(bytes: u64) -> String {
+  if bytes > 1 << 30 {
+    format!(
+      "{}.{:02} GiB",
+      bytes >> 30,
+      (bytes & ((1 << 30) - 1)) / 10737419
+    )
+  } else if bytes > 1 << 20 {
+    let x = bytes + 5243;
+    format!("{}.{:02} MiB", x >> 20, ((x & ((1 << 20) - 1)) * 100) >> 20)
+  } else if bytes > 1 << 10 {
+    let x = bytes + 5;
+    format!("{}.{:02} KiB", x >> 10, ((x & ((1 << 10) - 1)) * 100) >> 10)
+  } else if bytes == 1 {
+    "1 byte".to_string()
+  } else {
+    format!("{} bytes", bytes)
+  }
+}
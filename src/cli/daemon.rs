@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use git_rs_core::{
+  daemon::{Daemon as Server, Options},
+  transport::git::DEFAULT_PORT,
+};
+
+/// A really simple server for git repositories.
+///
+/// Listens for `git://` connections and lets clients fetch from the
+/// repositories it finds at the paths they ask for. A repository is only
+/// served if it has a `git-daemon-export-ok` file in its git directory, or
+/// with `--export-all`, and if directories are given, only if it is in one
+/// of them. Nothing can be pushed unless `--enable=receive-pack` is given,
+/// as anyone who can connect could then push. The daemon runs until it is
+/// stopped.
+///
+/// # Example
+/// ```bash
+/// $ git daemon --base-path=/srv/git --export-all --enable=receive-pack /srv/git
+/// Serving git://0.0.0.0:9418/
+/// $ git fetch git://example.com/project.git
+/// $ git push git://example.com/project.git main
+/// ```
+#[derive(Args, Debug)]
+pub struct Daemon {
+  /// The directories repositories may be served from.
+  pub directory: Vec<PathBuf>,
+
+  /// The port to listen on.
+  #[clap(long, default_value_t = DEFAULT_PORT)]
+  pub port: u16,
+
+  /// The address to listen on.
+  #[clap(long, default_value = "0.0.0.0")]
+  pub listen: String,
+
+  /// Serve repositories without a `git-daemon-export-ok` file too.
+  #[clap(long)]
+  pub export_all: bool,
+
+  /// Look up the paths clients ask for under this directory.
+  #[clap(long)]
+  pub base_path: Option<PathBuf>,
+
+  /// Serve a service that is off by default: `receive-pack`, which lets
+  /// clients push (may be given more than once).
+  #[clap(
+    long,
+    value_name = "SERVICE",
+    multiple_occurrences = true,
+    number_of_values = 1
+  )]
+  pub enable: Vec<String>,
+
+  /// Log connections and requests to standard error.
+  #[clap(long)]
+  pub verbose: bool,
+}
+
+pub fn cmd_daemon(opts: &Daemon) -> Result<(), String> {
+  let mut receive_pack = false;
+  for service in &opts.enable {
+    match service.as_str() {
+      "upload-pack" => (),
+      "receive-pack" => receive_pack = true,
+      _ => return Err(format!("unknown service {}", service)),
+    }
+  }
+  let options = Options {
+    export_all: opts.export_all,
+    base_path: opts.base_path.clone(),
+    whitelist: opts.directory.clone(),
+    receive_pack,
+    verbose: opts.verbose,
+  };
+  let server = Server::bind(&format!("{}:{}", opts.listen, opts.port), options)?;
+  println!("Serving git://{}/", server.local_addr()?);
+  server.run()
+}
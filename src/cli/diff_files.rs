@@ -0,0 +1,89 @@
+use bstr::BString;
+use clap::Args;
+use std::fs;
+use std::io::{self, Write};
+
+use git_rs_core::{
+  diff::{
+    compare,
+    raw::{self, Printer, NULL_HASH},
+    TreeChange,
+  },
+  index::{self, file_mode, Index},
+  pathspec,
+  repo::Repo,
+};
+
+/// Compares files in the working tree and the index.
+///
+/// Prints one raw record per tracked file whose working tree copy differs
+/// from the index. Changed files are shown with the null hash, since they are
+/// not hashed. An unmerged path is shown with a `U` record, followed by how
+/// the working tree differs from our side of the conflict.
+///
+/// # Example
+/// ```bash
+/// $ git diff-files --name-status
+/// M src/main.rs
+/// D old.txt
+/// ```
+#[derive(Args, Debug)]
+pub struct DiffFiles {
+  /// Show only the names of the changed files.
+  #[clap(long, conflicts_with = "name-status")]
+  pub name_only: bool,
+
+  /// Show only the names and status of the changed files.
+  #[clap(long)]
+  pub name_status: bool,
+
+  /// Separate records with NUL bytes instead of newlines.
+  #[clap(short)]
+  pub z: bool,
+
+  /// Only compare these paths.
+  pub paths: Vec<String>,
+}
+
+pub fn cmd_diff_files(opts: &DiffFiles) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let printer = Printer::new(opts.name_only, opts.name_status, opts.z);
+  let paths = pathspec::resolve(&repo, &opts.paths)?;
+  let index = Index::read(&repo)?;
+
+  let mut records: Vec<(BString, BString)> =
+    compare(&index.files(), &raw::worktree_files(&repo, &index)?)
+      .into_iter()
+      .map(|change| (change.path.clone(), printer.change(&change)))
+      .collect();
+
+  let mut last: Option<&BString> = None;
+  for entry in index.entries().iter().filter(|e| e.stage() != 0) {
+    let metadata = fs::symlink_metadata(repo.work_tree_path(&entry.path)).ok();
+    if last != Some(&entry.path) {
+      let mode = metadata.as_ref().map(file_mode);
+      records.push((entry.path.clone(), printer.unmerged(&entry.path, mode)));
+      last = Some(&entry.path);
+    }
+    if entry.stage() == 2 && index::is_modified(&repo, entry)? {
+      let change = TreeChange {
+        path: entry.path.clone(),
+        old: entry.tree_mode().map(|mode| (mode, entry.hash.clone())),
+        new: metadata.map(|metadata| (file_mode(&metadata), NULL_HASH.to_string())),
+        renamed_from: None,
+      };
+      records.push((entry.path.clone(), printer.change(&change)));
+    }
+  }
+  records.sort_by(|a, b| a.0.cmp(&b.0));
+
+  let mut out = BString::default();
+  for (path, record) in records {
+    if pathspec::matches(&path, &paths) {
+      out.extend_from_slice(&record);
+    }
+  }
+  io::stdout()
+    .write_all(&out)
+    .map_err(|e| format!("unable to write the changes ({})", e))
+}
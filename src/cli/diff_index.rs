@@ -0,0 +1,104 @@
+use bstr::BString;
+use clap::Args;
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+use git_rs_core::{
+  diff::{
+    compare,
+    raw::{self, Printer},
+  },
+  index::{file_mode, Index},
+  object::{self, find_object, refs, tree},
+  pathspec,
+  repo::Repo,
+};
+
+/// Compares a tree to the working tree or the index.
+///
+/// Prints one raw record per path that differs between the tree and the
+/// working tree, or the index with `--cached`. Files changed in the working
+/// tree are shown with the null hash, since they are not hashed.
+///
+/// # Example
+/// ```bash
+/// $ git diff-index --cached HEAD
+/// :000000 100644 0000000... 8ba3a16... A new.txt
+/// ```
+#[derive(Args, Debug)]
+pub struct DiffIndex {
+  /// The tree to compare with.
+  pub tree: String,
+
+  /// Compare with the index instead of the working tree.
+  #[clap(long)]
+  pub cached: bool,
+
+  /// Show only the names of the changed files.
+  #[clap(long, conflicts_with = "name-status")]
+  pub name_only: bool,
+
+  /// Show only the names and status of the changed files.
+  #[clap(long)]
+  pub name_status: bool,
+
+  /// Separate records with NUL bytes instead of newlines.
+  #[clap(short)]
+  pub z: bool,
+
+  /// Only compare these paths.
+  #[clap(last = true)]
+  pub paths: Vec<String>,
+}
+
+pub fn cmd_diff_index(opts: &DiffIndex) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let printer = Printer::new(opts.name_only, opts.name_status, opts.z);
+  let paths = pathspec::resolve(&repo, &opts.paths)?;
+  // on a branch with no commits yet, HEAD is compared as the empty tree
+  let tree = match opts.tree == "HEAD" && refs::unborn(&repo).is_some() {
+    true => object::head_tree(&repo)?,
+    false => find_object(&repo, &opts.tree, Some("tree"), true)?,
+  };
+  let index = Index::read(&repo)?;
+
+  let old = tree::flatten(&repo, &tree)?;
+  let new = if opts.cached {
+    index.files()
+  } else {
+    raw::worktree_files(&repo, &index)?
+  };
+  let unmerged: BTreeSet<&BString> = index
+    .entries()
+    .iter()
+    .filter(|e| e.stage() != 0)
+    .map(|e| &e.path)
+    .collect();
+
+  // unmerged paths get a single record of their own, in path order
+  let mut records: Vec<(BString, BString)> = compare(&old, &new)
+    .into_iter()
+    .filter(|change| !unmerged.contains(&change.path))
+    .map(|change| (change.path.clone(), printer.change(&change)))
+    .collect();
+  for path in unmerged {
+    let mode = match opts.cached {
+      true => None,
+      false => std::fs::symlink_metadata(repo.work_tree_path(path))
+        .ok()
+        .map(|metadata| file_mode(&metadata)),
+    };
+    records.push((path.clone(), printer.unmerged(path, mode)));
+  }
+  records.sort_by(|a, b| a.0.cmp(&b.0));
+
+  let mut out = BString::default();
+  for (path, record) in records {
+    if pathspec::matches(&path, &paths) {
+      out.extend_from_slice(&record);
+    }
+  }
+  io::stdout()
+    .write_all(&out)
+    .map_err(|e| format!("unable to write the changes ({})", e))
+}
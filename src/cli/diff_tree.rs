@@ -0,0 +1,197 @@
+use std::io::{self, Write};
+
+use clap::Args;
+use serde::Serialize;
+
+use git_rs_core::{
+  diff::{
+    compare,
+    patch::{line_counts, Stats},
+    raw::Printer,
+    TreeChange,
+  },
+  object::{commit::Commit, find_object, read, serializable::Unbox, tree},
+  pager, pathspec,
+  repo::Repo,
+};
+
+/// Compares the content and mode of blobs found via two tree objects.
+///
+/// Prints one raw record per changed path. Only the top level of the trees is
+/// compared unless `-r` is given. With a single commit, it is compared with
+/// its parent and the record list is preceded by the commit's hash (root
+/// commits are only shown with `--root`, and merges are skipped).
+///
+/// # Example
+/// ```bash
+/// $ git diff-tree -r HEAD~ HEAD
+/// :100644 100644 7898192... c1827f0... M a.txt
+/// $ git diff-tree -r --name-only --no-commit-id HEAD
+/// a.txt
+/// $ git diff-tree --numstat --no-commit-id HEAD
+/// 1       1       a.txt
+/// ```
+#[derive(Args, Debug)]
+pub struct DiffTree {
+  /// The trees (or commits) to compare.
+  #[clap(required = true, max_values = 2)]
+  pub trees: Vec<String>,
+
+  /// Recurse into subtrees.
+  #[clap(short)]
+  pub r: bool,
+
+  /// Show the changes of a root commit against the empty tree.
+  #[clap(long)]
+  pub root: bool,
+
+  /// Do not print the commit hash before its changes.
+  #[clap(long)]
+  pub no_commit_id: bool,
+
+  /// Show only the names of the changed files.
+  #[clap(long, conflicts_with = "name-status")]
+  pub name_only: bool,
+
+  /// Show only the names and status of the changed files.
+  #[clap(long)]
+  pub name_status: bool,
+
+  /// Show how many lines each file changed, with a graph of the lines
+  /// added and removed, as wide as the terminal or `WIDTH` columns.
+  #[clap(long, value_name = "WIDTH", min_values = 0, require_equals = true)]
+  pub stat: Option<Option<usize>>,
+
+  /// Show the number of lines each file adds and removes, and its name,
+  /// separated by tabs (`-` for binary files).
+  #[clap(long)]
+  pub numstat: bool,
+
+  /// Show only the total of files changed and lines added and removed.
+  #[clap(long)]
+  pub shortstat: bool,
+
+  /// Print the `--stat` as a JSON array of [`StatItem`] objects, without the
+  /// commit hash.
+  #[clap(long, requires = "stat")]
+  pub json: bool,
+
+  /// Separate records with NUL bytes instead of newlines.
+  #[clap(short)]
+  pub z: bool,
+
+  /// Only compare these paths.
+  #[clap(last = true)]
+  pub paths: Vec<String>,
+}
+
+/// A changed file as `diff-tree --stat --json` prints it.
+#[derive(Serialize)]
+pub struct StatItem {
+  /// The path, with bytes that are not UTF-8 replaced.
+  pub path: String,
+
+  /// The path the file was renamed from, if it was.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub renamed_from: Option<String>,
+
+  /// The number of lines added and deleted, or `None` for a binary file.
+  pub added: Option<usize>,
+  pub deleted: Option<usize>,
+}
+
+pub fn cmd_diff_tree(opts: &DiffTree) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let printer = Printer::new(opts.name_only, opts.name_status, opts.z);
+  let paths = pathspec::resolve(&repo, &opts.paths)?;
+
+  let (commit, old, new) = match opts.trees.as_slice() {
+    [one, two] => (
+      None,
+      Some(find_object(&repo, one, Some("tree"), true)?),
+      find_object(&repo, two, Some("tree"), true)?,
+    ),
+    [one] => {
+      let hash = find_object(&repo, one, Some("commit"), true)?;
+      let object = read(&repo, &hash, Some("commit"))?;
+      let commit = object.unbox::<Commit>()?;
+      let old = match commit.parents() {
+        [] if opts.root => None,
+        [parent] => Some(find_object(&repo, parent, Some("tree"), true)?),
+        _ => return Ok(()),
+      };
+      (Some(hash), old, commit.tree().to_owned())
+    }
+    _ => unreachable!(),
+  };
+
+  let stats = Stats {
+    numstat: opts.numstat,
+    stat: opts.stat.map(|width| width.unwrap_or_else(pager::columns)),
+    shortstat: opts.shortstat,
+  };
+  // the summaries always count the files in subtrees
+  let changes: Vec<TreeChange> = if opts.r || stats.any() {
+    let old = match &old {
+      Some(old) => tree::flatten(&repo, old)?,
+      None => Default::default(),
+    };
+    compare(&old, &tree::flatten(&repo, &new)?)
+      .into_iter()
+      .filter(|change| pathspec::matches(&change.path, &paths))
+      .collect()
+  } else {
+    let old = match &old {
+      Some(old) => tree::list(&repo, old)?,
+      None => Default::default(),
+    };
+    // a directory is shown if anything under it is asked for
+    compare(&old, &tree::list(&repo, &new)?)
+      .into_iter()
+      .filter(|change| {
+        let dir = [change.path.as_slice(), b"/"].concat();
+        pathspec::matches(&change.path, &paths) || paths.iter().any(|p| p.starts_with(&dir))
+      })
+      .collect()
+  };
+
+  if opts.json {
+    let items = changes
+      .iter()
+      .map(|change| stat_item(&repo, change))
+      .collect::<Result<Vec<StatItem>, String>>()?;
+    println!(
+      "{}",
+      serde_json::to_string(&items).map_err(|e| e.to_string())?
+    );
+    return Ok(());
+  }
+  if changes.is_empty() {
+    return Ok(());
+  }
+  if let Some(commit) = commit.filter(|_| !opts.no_commit_id) {
+    print!("{}{}", commit, if opts.z { '\0' } else { '\n' });
+  }
+  if stats.any() {
+    print!("{}", stats.render(&repo, &changes, opts.z)?);
+    return Ok(());
+  }
+  let out: Vec<u8> = changes
+    .iter()
+    .flat_map(|change| Vec::from(printer.change(change)))
+    .collect();
+  io::stdout()
+    .write_all(&out)
+    .map_err(|e| format!("unable to write the changes ({})", e))
+}
+
+/// Counts the lines a change adds and deletes into a [`StatItem`].
+fn stat_item(repo: &Repo, change: &TreeChange) -> Result<StatItem, String> {
+  let counts = line_counts(repo, change)?;
+  Ok(StatItem {
+    path: change.path.to_string(),
+    renamed_from: change.renamed_from.as_ref().map(|from| from.to_string()),
+    added: counts.map(|(added, _)| added),
+    deleted: counts.map(|(_, deleted)| deleted),
+  })
+}
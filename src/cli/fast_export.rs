@@ -0,0 +1,321 @@
+use clap::Args;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+
+use git_rs_core::{
+  diff::{self, blob_data, raw::NULL_HASH, TreeChange},
+  object::{commit::Commit, mode::Mode, peel, read, refs, serializable::Unbox, tag::Tag},
+  repo::Repo,
+  rev::{
+    self,
+    walk::{RevWalk, Sort},
+  },
+};
+
+/// Export history as a fast-import stream.
+///
+/// Writes the commits reachable from the given refs (or from every ref with
+/// `--all`), oldest first, along with the blobs they add and the annotated
+/// tags pointing at them, in the format read by `git fast-import` and many
+/// other version control tools. Each blob and commit gets a mark (`:1`,
+/// `:2`, ...) that later commands refer back to. Commits are only given the
+/// files that changed since their first parent.
+///
+/// # Example
+/// ```bash
+/// $ git fast-export --all | (cd ../copy && git fast-import)
+/// $ git fast-export main~3..main > recent.stream
+/// ```
+#[derive(Args, Debug)]
+pub struct FastExport {
+  /// The refs (or ranges) to export.
+  #[clap(required_unless_present = "all")]
+  pub revisions: Vec<String>,
+
+  /// Export every ref.
+  #[clap(long)]
+  pub all: bool,
+
+  /// Write the marks of the exported objects to this file when done.
+  #[clap(long, value_name = "FILE")]
+  pub export_marks: Option<String>,
+}
+
+/// The state of an export: the marks handed out so far and the stream.
+struct Exporter<'a> {
+  repo: &'a Repo,
+  marks: HashMap<String, usize>,
+  out: Vec<u8>,
+}
+
+pub fn cmd_fast_export(opts: &FastExport) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+
+  // the refs to export, in order, with the objects they point at
+  let mut walk = RevWalk::new(&repo);
+  let mut tips: Vec<(String, String)> = Vec::new();
+  if opts.all {
+    // symbolic refs such as `refs/remotes/origin/HEAD` are left to the refs
+    // they point at
+    let all = refs::collect(&repo, None).into_iter();
+    tips.extend(all.filter(|(name, _)| refs::read_symbolic(&repo, name).is_none()));
+  }
+  for (_, hash) in &tips {
+    if let Ok(commit) = peel(&repo, hash, Some("commit")) {
+      walk.push(&commit);
+    }
+  }
+  for spec in &opts.revisions {
+    walk.push_spec(spec)?;
+    let included: Vec<&str> = match (spec.split_once("..."), spec.split_once("..")) {
+      (Some((left, right)), _) => vec![left, right],
+      (None, Some((_, right))) => vec![right],
+      (None, None) if spec.starts_with('^') => vec![],
+      (None, None) => vec![spec],
+    };
+    for name in included {
+      let name = if name.is_empty() { "HEAD" } else { name };
+      tips.push((full_name(&repo, name), rev::parse(&repo, name)?));
+    }
+  }
+
+  // Each commit is exported under the name of the first ref to reach it,
+  // visiting commits newest first.
+  let mut names: HashMap<String, String> = HashMap::new();
+  for (name, hash) in &tips {
+    if let Ok(commit) = peel(&repo, hash, Some("commit")) {
+      names.entry(commit).or_insert_with(|| name.clone());
+    }
+  }
+  for commit in walk.run()? {
+    if let Some(name) = names.get(&commit).cloned() {
+      for parent in walk.parents(&commit) {
+        names.entry(parent).or_insert_with(|| name.clone());
+      }
+    }
+  }
+
+  walk.sort(Sort::Topo);
+  let mut order = walk.run()?;
+  order.reverse();
+  let mut exporter = Exporter {
+    repo: &repo,
+    marks: HashMap::new(),
+    out: Vec::new(),
+  };
+  for commit in &order {
+    let name = names.get(commit).cloned().unwrap_or_default();
+    exporter.commit(commit, &name)?;
+  }
+
+  // refs that were not used to name a commit still need to be set
+  let used: Vec<&String> = order
+    .iter()
+    .filter_map(|commit| names.get(commit))
+    .collect();
+  let mut tags: Vec<(&String, &String)> = Vec::new();
+  let mut resets: Vec<(&String, String)> = Vec::new();
+  for (name, hash) in &tips {
+    let target = match peel(&repo, hash, Some("commit")) {
+      Ok(target) => target,
+      Err(_) => continue,
+    };
+    if target != *hash {
+      tags.push((name, hash));
+    } else if !used.contains(&name) && !resets.iter().any(|(n, _)| *n == name) {
+      resets.push((name, target));
+    }
+  }
+  for (name, target) in resets.iter().rev() {
+    // a ref to a commit that was left out is deleted
+    let from = match exporter.marks.get(target) {
+      Some(mark) => format!(":{}", mark),
+      None => NULL_HASH.to_owned(),
+    };
+    exporter.write(format!("reset {}\nfrom {}\n\n", name, from).as_bytes());
+  }
+  for (name, hash) in tags.iter().rev() {
+    exporter.tag(name, hash)?;
+  }
+
+  io::stdout()
+    .write_all(&exporter.out)
+    .map_err(|e| format!("could not write the stream: {}", e))?;
+  if let Some(path) = &opts.export_marks {
+    // like git, only the marks of commits are worth keeping
+    let text: String = order
+      .iter()
+      .map(|commit| format!(":{} {}\n", exporter.marks[commit], commit))
+      .collect();
+    fs::write(path, text).map_err(|e| format!("could not write '{}': {}", path, e))?;
+  }
+  Ok(())
+}
+
+impl Exporter<'_> {
+  fn write(&mut self, data: &[u8]) {
+    self.out.extend_from_slice(data);
+  }
+
+  /// Hands out the next mark for an object.
+  fn mark(&mut self, hash: &str) -> usize {
+    let mark = self.marks.len() + 1;
+    self.marks.insert(hash.to_owned(), mark);
+    mark
+  }
+
+  /// Writes a commit, preceded by any blobs it adds that were not written yet.
+  fn commit(&mut self, hash: &str, name: &str) -> Result<(), String> {
+    let object = read(self.repo, hash, Some("commit"))?;
+    let commit: &Commit = object.unbox::<Commit>()?;
+    let parents: Vec<usize> = commit
+      .parents()
+      .iter()
+      .filter_map(|parent| self.marks.get(parent).copied())
+      .collect();
+
+    // diff against the first parent, or against nothing if it is not exported
+    let base = match commit
+      .parents()
+      .first()
+      .filter(|parent| self.marks.contains_key(*parent))
+    {
+      Some(parent) => Some(peel(self.repo, parent, Some("tree"))?),
+      None => None,
+    };
+    let mut changes = diff::diff_trees(self.repo, base.as_deref(), Some(commit.tree()))?;
+    for (mode, hash) in changes.iter().filter_map(|change| change.new.as_ref()) {
+      if mode.is_blob() && !self.marks.contains_key(hash) {
+        let data = blob_data(self.repo, Some(&(*mode, hash.clone())))?;
+        let mark = self.mark(hash);
+        self.write(format!("blob\nmark :{}\ndata {}\n", mark, data.len()).as_bytes());
+        self.write(&data);
+        self.write(b"\n");
+      }
+    }
+
+    if commit.parents().is_empty() {
+      self.write(format!("reset {}\n", name).as_bytes());
+    }
+    let mark = self.mark(hash);
+    let mut text = format!("commit {}\nmark :{}\n", name, mark);
+    if let Some(author) = commit.get("author") {
+      text.push_str(&format!("author {}\n", author));
+    }
+    if let Some(committer) = commit.get("committer") {
+      text.push_str(&format!("committer {}\n", committer));
+    }
+    let message = commit.message();
+    text.push_str(&format!("data {}\n{}", message.len(), message));
+    for (n, parent) in parents.iter().enumerate() {
+      let kind = if n == 0 { "from" } else { "merge" };
+      text.push_str(&format!("{} :{}\n", kind, parent));
+    }
+    changes.sort_by(depth_first);
+    for change in &changes {
+      match &change.new {
+        // submodules are written by commit, as there is no blob to mark
+        Some((Mode::Gitlink, hash)) => text.push_str(&format!(
+          "M {} {} {}\n",
+          Mode::Gitlink,
+          hash,
+          quote_path(&change.path)
+        )),
+        Some((mode, hash)) => text.push_str(&format!(
+          "M {} :{} {}\n",
+          mode,
+          self.marks[hash],
+          quote_path(&change.path)
+        )),
+        None => text.push_str(&format!("D {}\n", quote_path(&change.path))),
+      }
+    }
+    text.push('\n');
+    self.write(text.as_bytes());
+    Ok(())
+  }
+
+  /// Writes an annotated tag, as long as the commit it points at was exported.
+  fn tag(&mut self, name: &str, hash: &str) -> Result<(), String> {
+    let object = read(self.repo, hash, Some("tag"))?;
+    let tag: &Tag = object.unbox::<Tag>()?;
+    let target = peel(self.repo, hash, Some("commit"))?;
+    let mark = match self.marks.get(&target) {
+      Some(mark) => *mark,
+      None => return Ok(()),
+    };
+    let mut text = format!(
+      "tag {}\nfrom :{}\n",
+      name.strip_prefix("refs/tags/").unwrap_or(name),
+      mark
+    );
+    if let Some(tagger) = tag.get("tagger") {
+      text.push_str(&format!("tagger {}\n", tagger));
+    }
+    let message = tag.message();
+    text.push_str(&format!("data {}\n{}\n", message.len(), message));
+    self.write(text.as_bytes());
+    Ok(())
+  }
+}
+
+/// Orders file changes by path, except that a path comes after the paths
+/// beneath it and a deletion comes before other changes to the same path, so
+/// that a file can replace a directory (and vice versa) on import.
+fn depth_first(a: &TreeChange, b: &TreeChange) -> Ordering {
+  let (x, y) = (&a.path, &b.path);
+  let len = x.len().min(y.len());
+  x[..len]
+    .cmp(&y[..len])
+    .then(y.len().cmp(&x.len()))
+    .then(a.new.is_some().cmp(&b.new.is_some()))
+}
+
+/// Expands a revision to the full name of the ref it names (eg. `main` to
+/// `refs/heads/main`), or leaves it as is if it is not a ref.
+fn full_name(repo: &Repo, name: &str) -> String {
+  let mut name = name.to_owned();
+  while let Some(target) = refs::read_symbolic(repo, &name) {
+    name = target;
+  }
+  [
+    name.clone(),
+    format!("refs/{}", name),
+    format!("refs/tags/{}", name),
+    format!("refs/heads/{}", name),
+    format!("refs/remotes/{}", name),
+  ]
+  .into_iter()
+  .find(|candidate| candidate.starts_with("refs/") && refs::exists(repo, candidate))
+  .unwrap_or(name)
+}
+
+/// Quotes a path the way fast-import expects: C-style if it has special
+/// characters, or just wrapped in quotes if it has spaces.
+fn quote_path(path: &[u8]) -> String {
+  let special = |b: u8| !(0x20..0x7f).contains(&b) || b == b'"' || b == b'\\';
+  if !path.iter().copied().any(special) {
+    // only printable ASCII is left
+    let path = String::from_utf8_lossy(path);
+    return match path.contains(' ') {
+      true => format!("\"{}\"", path),
+      false => path.into_owned(),
+    };
+  }
+  let mut quoted = String::from("\"");
+  for b in path.iter().copied() {
+    match b {
+      b'"' => quoted.push_str("\\\""),
+      b'\\' => quoted.push_str("\\\\"),
+      b'\t' => quoted.push_str("\\t"),
+      b'\n' => quoted.push_str("\\n"),
+      b'\r' => quoted.push_str("\\r"),
+      b if special(b) => quoted.push_str(&format!("\\{:03o}", b)),
+      b => quoted.push(b as char),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
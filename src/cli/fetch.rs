@@ -0,0 +1,267 @@
+use std::process;
+
+use clap::Args;
+
+use git_rs_core::{
+  cancel,
+  progress::Meter,
+  remote::{
+    fetch::{self, display_url, RefUpdate, Status, Tags},
+    refspec::RefSpec,
+    Remote,
+  },
+  repo::Repo,
+};
+
+/// Download objects and refs from another repository.
+///
+/// Fetches the refs the refspecs name from a remote (or a URL), with the
+/// objects they reach, and updates the local refs the refspecs map them to.
+/// Without refspecs, the remote's `remote.<name>.fetch` refspecs are used,
+/// and without those its `HEAD` is fetched. Everything fetched is recorded
+/// in `FETCH_HEAD`. A refspec starting with `^` leaves out the refs it
+/// matches.
+///
+/// Tags that point at what is fetched (or at what the repository already
+/// has) are fetched too, when refs are fetched into local refs. With
+/// `--tags` every tag is fetched, and with `--no-tags` none but those the
+/// refspecs fetch; `remote.<name>.tagOpt` can say either.
+///
+/// With `--prefetch`, the refs are fetched into `refs/prefetch/` instead,
+/// for a later fetch to find the objects already there.
+///
+/// The packs that come in are kept (with a `.keep` file) until the refs are
+/// updated, so that a repack at the same time doesn't delete them first;
+/// with `--keep` they stay kept.
+///
+/// With `--prune` (or `fetch.prune`), the refs that the refspecs keep refs
+/// the remote no longer has in are deleted, and with `--prune-tags` too the
+/// local tags it no longer has.
+///
+/// Remotes are fetched from over `git://`, as `daemon` serves them, or over
+/// HTTP through the dumb protocol: the server needs only to serve the files
+/// of the repository, once `update-server-info` has written the lists of
+/// its refs and packs. A server that asks for a username and password gets
+/// those in the URL, or else those the `credential.helper`s give.
+///
+/// HTTP requests go through the proxy `http.proxy` names, or else the one
+/// the `http_proxy`, `https_proxy` or `all_proxy` environment variables
+/// do, except for the hosts in `NO_PROXY`. Servers are trusted on the
+/// authorities in `http.sslCAInfo` if it is set, and `http.extraHeader`
+/// adds headers to every request.
+///
+/// Over `git://`, the remote is told which commits the repository has, so
+/// it can leave out what they reach. `fetch.negotiationAlgorithm` picks
+/// them: `consecutive` (the default) sends the newest, `skipping` sends
+/// fewer and fewer going back in history, and `noop` sends none.
+///
+/// # Example
+/// ```bash
+/// $ git fetch --prune origin
+/// From http://example.com/project
+///  - [deleted]         (none)     -> origin/old
+///  * [new branch]      main       -> origin/main
+///    3e2ab91..7c1d0f4  topic      -> origin/topic
+///  + 1a2b3c4...5d6e7f8 wip        -> origin/wip  (forced update)
+/// $ git fetch --tags origin
+/// $ git fetch http://example.com/project main:refs/remotes/mirror/main
+/// ```
+#[derive(Args, Debug)]
+pub struct Fetch {
+  /// The remote to fetch from, or a URL.
+  #[clap(default_value = "origin")]
+  pub repository: String,
+
+  /// Which refs to fetch, and where to keep them.
+  pub refspecs: Vec<String>,
+
+  /// Say nothing.
+  #[clap(short, long)]
+  pub quiet: bool,
+
+  /// Report refs that were already up to date too.
+  #[clap(short, long)]
+  pub verbose: bool,
+
+  /// Delete the refs that the refspecs keep refs the remote no longer has
+  /// in.
+  #[clap(short, long, conflicts_with = "no-prune")]
+  pub prune: bool,
+
+  /// Don't prune, whatever the config says.
+  #[clap(long)]
+  pub no_prune: bool,
+
+  /// With --prune, also delete the local tags the remote no longer has.
+  #[clap(short = 'P', long, conflicts_with = "no-prune-tags")]
+  pub prune_tags: bool,
+
+  /// Don't prune tags, whatever the config says.
+  #[clap(long)]
+  pub no_prune_tags: bool,
+
+  /// Fetch every tag of the remote as well.
+  #[clap(short, long, conflicts_with = "no-tags")]
+  pub tags: bool,
+
+  /// Fetch no tags but those the refspecs do.
+  #[clap(short, long)]
+  pub no_tags: bool,
+
+  /// Fetch into `refs/prefetch/` instead of where the refspecs say, and
+  /// leave `FETCH_HEAD` and tags alone.
+  #[clap(long)]
+  pub prefetch: bool,
+
+  /// Keep the packs that are downloaded, for repacks to leave alone.
+  #[clap(short, long)]
+  pub keep: bool,
+
+  /// Show progress, even if standard error is not a terminal.
+  #[clap(long, conflicts_with = "no-progress")]
+  pub progress: bool,
+
+  /// Never show progress.
+  #[clap(long)]
+  pub no_progress: bool,
+
+  /// Only tell the remote about the commits this commit (or the refs this
+  /// glob matches) reaches, instead of those all the refs do (may be given
+  /// more than once).
+  #[clap(
+    long = "negotiation-tip",
+    value_name = "COMMIT|GLOB",
+    multiple_occurrences = true,
+    number_of_values = 1
+  )]
+  pub negotiation_tips: Vec<String>,
+}
+
+pub fn cmd_fetch(opts: &Fetch) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let remote = Remote::get(&repo, &opts.repository)?;
+  let mut refspecs = Vec::new();
+  let mut specs = opts.refspecs.iter();
+  while let Some(spec) = specs.next() {
+    // `tag <name>` is short for `refs/tags/<name>:refs/tags/<name>`
+    let spec = match spec.as_str() {
+      "tag" => {
+        let name = specs.next().ok_or("you need to specify a tag name")?;
+        format!("refs/tags/{}:refs/tags/{}", name, name)
+      }
+      _ => spec.clone(),
+    };
+    refspecs.push(RefSpec::parse(&spec)?);
+  }
+
+  let show_progress = match (opts.quiet, opts.progress, opts.no_progress) {
+    (_, true, _) => Some(true),
+    (true, _, _) | (_, _, true) => Some(false),
+    _ => None,
+  };
+  let flag = |on: bool, off: bool| match (on, off) {
+    (true, _) => Some(true),
+    (_, true) => Some(false),
+    _ => None,
+  };
+  let options = fetch::Options {
+    negotiation_tips: opts.negotiation_tips.clone(),
+    prune: flag(opts.prune, opts.no_prune),
+    prune_tags: flag(opts.prune_tags, opts.no_prune_tags),
+    tags: match (opts.tags, opts.no_tags) {
+      (true, _) => Some(Tags::All),
+      (_, true) => Some(Tags::None),
+      _ => None,
+    },
+    prefetch: opts.prefetch,
+    keep: opts.keep,
+    cancel: cancel::on_interrupt(),
+  };
+  let updates = fetch::fetch(
+    &repo,
+    &remote,
+    &refspecs,
+    &options,
+    Meter::boxed(show_progress).as_mut(),
+  )?;
+
+  let shown: Vec<&RefUpdate> = updates
+    .iter()
+    .filter(|update| opts.verbose || update.status != Status::UpToDate)
+    .collect();
+  if !opts.quiet && !shown.is_empty() {
+    eprintln!("From {}", display_url(&remote.url));
+    let width = shown
+      .iter()
+      .map(|update| remote_name(update).len())
+      .fold(10, usize::max);
+    for update in &shown {
+      eprintln!("{}", report(update, width));
+    }
+  }
+  if updates
+    .iter()
+    .any(|update| matches!(update.status, Status::Rejected(_)))
+  {
+    process::exit(1);
+  }
+  Ok(())
+}
+
+/// A line of the report of what was fetched, eg.
+/// ` * [new branch]      main       -> origin/main`.
+fn report(update: &RefUpdate, width: usize) -> String {
+  let kind = match update.remote.split('/').nth(1) {
+    Some("heads") => Some("branch"),
+    Some("tags") => Some("tag"),
+    _ => None,
+  };
+  let range = |dots: &str| match &update.old {
+    Some(old) => format!("{}{}{}", &old[..7], dots, &update.new[..7]),
+    None => String::new(),
+  };
+  let (code, summary, note) = match update.status {
+    Status::FetchHead => ('*', kind.unwrap_or("branch").to_string(), String::new()),
+    Status::New => (
+      '*',
+      format!("[new {}]", kind.unwrap_or("ref")),
+      String::new(),
+    ),
+    Status::UpToDate => ('=', "[up to date]".to_string(), String::new()),
+    Status::FastForward => (' ', range(".."), String::new()),
+    Status::Forced => ('+', range("..."), "  (forced update)".to_string()),
+    Status::Rejected(why) => ('!', "[rejected]".to_string(), format!("  ({})", why)),
+    Status::Pruned => ('-', "[deleted]".to_string(), String::new()),
+  };
+  let local = match &update.local {
+    Some(local) => pretty(local),
+    None => "FETCH_HEAD",
+  };
+  format!(
+    " {} {:<17} {:<width$} -> {}{}",
+    code,
+    summary,
+    remote_name(update),
+    local,
+    note,
+    width = width
+  )
+}
+
+/// The remote ref a line of the report is for, or `(none)` for a ref that
+/// was pruned, as the remote has no such ref.
+fn remote_name(update: &RefUpdate) -> &str {
+  match update.status {
+    Status::Pruned => "(none)",
+    _ => pretty(&update.remote),
+  }
+}
+
+/// A ref's name without the prefix that says what kind of ref it is, eg.
+/// `origin/main` for `refs/remotes/origin/main`.
+fn pretty(name: &str) -> &str {
+  ["refs/heads/", "refs/tags/", "refs/remotes/"]
+    .iter()
+    .find_map(|prefix| name.strip_prefix(prefix))
+    .unwrap_or(name)
+}
@@ -0,0 +1,642 @@
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use clap::Args;
+
+use git_rs_core::{
+  branch,
+  identity::{date, Signature},
+  ignore::wildmatch,
+  object::{self, commit::Commit, find_object, mail_map::MailMap, read, refs, serializable::Unbox},
+  repo::Repo,
+  rev::walk::RevWalk,
+};
+
+/// Output information on each ref.
+///
+/// Lists the refs that match the patterns (all of them when none are given),
+/// each as the format says, sorted by refname or the `--sort` keys. A
+/// pattern matches the refs it is a prefix of, up to a `/`, or that it
+/// matches as a glob.
+///
+/// The format is text with `%(atom)`s standing for what is known about the
+/// ref: `refname`, `objectname`, `objecttype`, `objectsize`, `HEAD`,
+/// `symref`, `upstream`, the `author`, `committer`, `tagger` and `creator`
+/// of the object (with `name`, `email` or `date` after them for just that
+/// part) and its `subject`, `body` and `contents`. Headers of the object,
+/// like `tree`, `parent`, `object`, `type` and `tag`, can be named too. An
+/// atom starting with `*` is about the object a tag points at. `%%` is a
+/// `%`, and `%xx` the character with that hex code.
+///
+/// Some atoms take a modifier after a `:`, such as `refname:short`,
+/// `refname:lstrip=2`, `objectname:short`, `upstream:track`,
+/// `upstream:trackshort` and `creatordate:iso` (or any other date format).
+///
+/// # Example
+/// ```bash
+/// $ git for-each-ref --sort=-creatordate --count=2 \
+///     --format='%(refname:short) %(creatordate:short)' refs/tags
+/// v1.1 2023-11-14
+/// v1.0 2023-10-02
+/// $ git for-each-ref --format='%(refname:short) %(upstream:track)' refs/heads
+/// main [ahead 1]
+/// topic
+/// ```
+#[derive(Args, Debug)]
+pub struct ForEachRef {
+  /// Only show the refs that match one of these patterns.
+  pub patterns: Vec<String>,
+
+  /// How to show each ref.
+  #[clap(
+    long,
+    value_name = "FORMAT",
+    default_value = "%(objectname) %(objecttype)\t%(refname)"
+  )]
+  pub format: String,
+
+  /// Sort by this atom, in reverse with a leading `-`, and comparing the
+  /// numbers in it as numbers with `version:` (or `v:`) before it. Given
+  /// more than once, the last key is sorted by first.
+  #[clap(long, value_name = "KEY", multiple_occurrences = true)]
+  pub sort: Vec<String>,
+
+  /// Stop after showing this many refs.
+  #[clap(long, value_name = "N")]
+  pub count: Option<usize>,
+
+  /// Only show the refs that point at the object, or at a tag of it.
+  #[clap(long, value_name = "OBJECT")]
+  pub points_at: Option<String>,
+
+  /// Only show the refs whose commits are reachable from this commit (HEAD
+  /// if not given).
+  #[clap(
+    long,
+    value_name = "COMMIT",
+    min_values = 0,
+    max_values = 1,
+    default_missing_value = "HEAD"
+  )]
+  pub merged: Option<String>,
+
+  /// Only show the refs whose commits are not reachable from this commit
+  /// (HEAD if not given).
+  #[clap(
+    long,
+    value_name = "COMMIT",
+    min_values = 0,
+    max_values = 1,
+    default_missing_value = "HEAD"
+  )]
+  pub no_merged: Option<String>,
+}
+
+pub fn cmd_for_each_ref(opts: &ForEachRef) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let format = parse_format(&opts.format)?;
+  let mut keys = Vec::new();
+  for key in opts.sort.iter().rev() {
+    keys.push(parse_key(key)?);
+  }
+  if keys.is_empty() {
+    keys.push(parse_key("refname")?);
+  }
+
+  let points_at = match &opts.points_at {
+    Some(name) => Some(find_object(&repo, name, None, false)?),
+    None => None,
+  };
+  let merged = match &opts.merged {
+    Some(name) => Some(reachable(&repo, name)?),
+    None => None,
+  };
+  let no_merged = match &opts.no_merged {
+    Some(name) => Some(reachable(&repo, name)?),
+    None => None,
+  };
+
+  let mut items = Vec::new();
+  for (name, hash) in refs::collect(&repo, None) {
+    if !opts.patterns.is_empty() && !opts.patterns.iter().any(|p| matches(p, &name)) {
+      continue;
+    }
+    let item = Item::new(&repo, name, hash);
+    if let Some(points_at) = &points_at {
+      let tagged = match item.object()?.kind.as_str() {
+        "tag" => item.object()?.fields.get("object").cloned(),
+        _ => None,
+      };
+      if &item.hash != points_at && tagged.as_ref() != Some(points_at) {
+        continue;
+      }
+    }
+    if merged.is_some() || no_merged.is_some() {
+      let commit = match object::peel(&repo, &item.hash, Some("commit")) {
+        Ok(commit) => commit,
+        Err(_) => continue,
+      };
+      if merged.as_ref().is_some_and(|set| !set.contains(&commit))
+        || no_merged.as_ref().is_some_and(|set| set.contains(&commit))
+      {
+        continue;
+      }
+    }
+    items.push(item);
+  }
+
+  // the values of the keys of each ref are worked out once, before sorting
+  let mut sorted = Vec::new();
+  for item in items {
+    let mut values = Vec::new();
+    for key in &keys {
+      values.push(item.value(&key.atom)?);
+    }
+    sorted.push((values, item));
+  }
+  sorted.sort_by(|(a, one), (b, two)| {
+    keys
+      .iter()
+      .zip(a.iter().zip(b))
+      .map(|(key, (a, b))| key.compare(a, b))
+      .find(|ordering| ordering.is_ne())
+      .unwrap_or_else(|| one.name.cmp(&two.name))
+  });
+
+  for (_, item) in sorted.iter().take(opts.count.unwrap_or(usize::MAX)) {
+    let mut line = String::new();
+    for part in &format {
+      match part {
+        Part::Text(text) => line.push_str(text),
+        Part::Atom(atom) => line.push_str(&item.value(atom)?.text),
+      }
+    }
+    println!("{}", line);
+  }
+  Ok(())
+}
+
+/// Every commit reachable from the named one.
+fn reachable(repo: &Repo, name: &str) -> Result<HashSet<String>, String> {
+  let commit = find_object(repo, name, Some("commit"), true)
+    .map_err(|_| format!("malformed object name {}", name))?;
+  let mut walk = RevWalk::new(repo);
+  walk.push(&commit);
+  Ok(walk.run()?.into_iter().collect())
+}
+
+/// Whether a pattern matches a ref: it is the whole name, or the name up to
+/// a `/`, or it matches the name as a glob.
+fn matches(pattern: &str, name: &str) -> bool {
+  match name.strip_prefix(pattern) {
+    Some(rest) if rest.is_empty() || pattern.ends_with('/') || rest.starts_with('/') => true,
+    _ => wildmatch(pattern.as_bytes(), name.as_bytes()),
+  }
+}
+
+/// Part of a format: text as it is, or an atom to fill in.
+enum Part {
+  Text(String),
+  Atom(Atom),
+}
+
+/// Something known about a ref, like `refname:short` or `*objectname`.
+struct Atom {
+  /// Whether it is about the object a tag points at.
+  deref: bool,
+  name: String,
+  modifier: Option<String>,
+}
+
+/// The atoms that can be used, other than those about people.
+const ATOMS: &[&str] = &[
+  "refname",
+  "objectname",
+  "objecttype",
+  "objectsize",
+  "HEAD",
+  "symref",
+  "upstream",
+  "subject",
+  "body",
+  "contents",
+  "tree",
+  "parent",
+  "object",
+  "type",
+  "tag",
+];
+
+/// The people an object can name, each with an atom of their own.
+const PEOPLE: &[&str] = &["author", "committer", "tagger", "creator"];
+
+/// Parses an atom, the part of a format inside `%(...)`.
+fn parse_atom(text: &str) -> Result<Atom, String> {
+  let (deref, rest) = match text.strip_prefix('*') {
+    Some(rest) => (true, rest),
+    None => (false, text),
+  };
+  let (name, modifier) = match rest.split_once(':') {
+    Some((name, modifier)) => (name, Some(modifier.to_owned())),
+    None => (rest, None),
+  };
+  let person = PEOPLE.iter().any(|person| {
+    name
+      .strip_prefix(person)
+      .is_some_and(|part| ["", "name", "email", "date"].contains(&part))
+  });
+  if !person && !ATOMS.contains(&name) {
+    return Err(format!("unknown field name: {}", text));
+  }
+  Ok(Atom {
+    deref,
+    name: name.to_owned(),
+    modifier,
+  })
+}
+
+/// Splits a format into text and atoms, with `%%` and `%xx` escapes made
+/// into the characters they stand for.
+fn parse_format(format: &str) -> Result<Vec<Part>, String> {
+  let mut parts = Vec::new();
+  let mut text = String::new();
+  let mut rest = format;
+  while let Some(percent) = rest.find('%') {
+    text.push_str(&rest[..percent]);
+    rest = &rest[percent + 1..];
+    if let Some(inner) = rest.strip_prefix('(') {
+      let end = inner
+        .find(')')
+        .ok_or_else(|| format!("malformed format string {}", format))?;
+      if !text.is_empty() {
+        parts.push(Part::Text(std::mem::take(&mut text)));
+      }
+      parts.push(Part::Atom(parse_atom(&inner[..end])?));
+      rest = &inner[end + 1..];
+    } else if let Some(after) = rest.strip_prefix('%') {
+      text.push('%');
+      rest = after;
+    } else if let Some(byte) = rest
+      .get(..2)
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+    {
+      text.push(byte as char);
+      rest = &rest[2..];
+    } else {
+      text.push('%');
+    }
+  }
+  text.push_str(rest);
+  if !text.is_empty() {
+    parts.push(Part::Text(text));
+  }
+  Ok(parts)
+}
+
+/// A key to sort by.
+struct Key {
+  atom: Atom,
+  reverse: bool,
+  version: bool,
+}
+
+impl Key {
+  fn compare(&self, a: &Value, b: &Value) -> Ordering {
+    let ordering = match (a.number, b.number) {
+      (Some(a), Some(b)) => a.cmp(&b),
+      _ if self.version => compare_versions(&a.text, &b.text),
+      _ => a.text.cmp(&b.text),
+    };
+    match self.reverse {
+      true => ordering.reverse(),
+      false => ordering,
+    }
+  }
+}
+
+fn parse_key(text: &str) -> Result<Key, String> {
+  let (reverse, text) = match text.strip_prefix('-') {
+    Some(text) => (true, text),
+    None => (false, text),
+  };
+  let (version, text) = match text
+    .strip_prefix("version:")
+    .or_else(|| text.strip_prefix("v:"))
+  {
+    Some(text) => (true, text),
+    None => (false, text),
+  };
+  Ok(Key {
+    atom: parse_atom(text)?,
+    reverse,
+    version,
+  })
+}
+
+/// Compares two strings with the runs of digits in them compared as
+/// numbers, so that `v1.10` comes after `v1.9`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+  let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+  loop {
+    let digits = |text: &[u8]| text.iter().take_while(|c| c.is_ascii_digit()).count();
+    let ordering = match (a.first(), b.first()) {
+      (None, None) => return Ordering::Equal,
+      (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+        let (one, two) = (digits(a), digits(b));
+        let trim = |run: &[u8]| run.iter().skip_while(|c| **c == b'0').count();
+        let (x, y) = (
+          &a[one - trim(&a[..one])..one],
+          &b[two - trim(&b[..two])..two],
+        );
+        let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+        a = &a[one..];
+        b = &b[two..];
+        ordering
+      }
+      (x, y) => {
+        let ordering = x.cmp(&y);
+        a = a.get(1..).unwrap_or_default();
+        b = b.get(1..).unwrap_or_default();
+        ordering
+      }
+    };
+    if ordering.is_ne() {
+      return ordering;
+    }
+  }
+}
+
+/// What an atom stands for, with a number to sort it by, if it is one
+/// (sizes, and the times of dates).
+struct Value {
+  text: String,
+  number: Option<i64>,
+}
+
+impl From<String> for Value {
+  fn from(text: String) -> Self {
+    Self { text, number: None }
+  }
+}
+
+/// What is needed of an object to fill in the atoms about it.
+struct Object {
+  kind: String,
+  size: usize,
+
+  /// The headers and message of a commit or tag (in UTF-8), or nothing for
+  /// other objects.
+  fields: MailMap,
+}
+
+impl Object {
+  fn read(repo: &Repo, hash: &str) -> Result<Self, String> {
+    let object = read(repo, hash, None)?;
+    let mut fields = MailMap::new();
+    match object.format().as_str() {
+      "commit" => {
+        let commit = object.unbox::<Commit>()?;
+        let converted = commit.to_utf8();
+        fields.parse_bytes(converted.as_ref().unwrap_or(commit).to_bytes(), 0);
+      }
+      "tag" => fields.parse_bytes(object.serialize(), 0),
+      _ => (),
+    }
+    Ok(Self {
+      kind: object.format().to_owned(),
+      size: object.serialize().len(),
+      fields,
+    })
+  }
+
+  /// The person named by a header, and the line they are named in.
+  fn person(&self, role: &str) -> Option<(&String, Signature)> {
+    let line = match role {
+      "creator" => match self.kind.as_str() {
+        "tag" => self.fields.get("tagger"),
+        _ => self.fields.get("committer"),
+      },
+      role => self.fields.get(role),
+    }?;
+    Some((line, Signature::parse(line)?))
+  }
+}
+
+/// A ref to show, and what has been read of the object it points at.
+struct Item<'r> {
+  repo: &'r Repo,
+  name: String,
+  hash: String,
+  object: OnceCell<Object>,
+
+  /// The object a tag points at, or `None` if it is not a tag.
+  target: OnceCell<Option<Object>>,
+}
+
+impl<'r> Item<'r> {
+  fn new(repo: &'r Repo, name: String, hash: String) -> Self {
+    Self {
+      repo,
+      name,
+      hash,
+      object: OnceCell::new(),
+      target: OnceCell::new(),
+    }
+  }
+
+  fn object(&self) -> Result<&Object, String> {
+    if self.object.get().is_none() {
+      let _ = self.object.set(Object::read(self.repo, &self.hash)?);
+    }
+    Ok(self.object.get().unwrap())
+  }
+
+  fn target(&self) -> Result<Option<&Object>, String> {
+    if self.target.get().is_none() {
+      let object = self.object()?;
+      let target = match object.fields.get("object") {
+        Some(hash) if object.kind == "tag" => Some(Object::read(self.repo, hash)?),
+        _ => None,
+      };
+      let _ = self.target.set(target);
+    }
+    Ok(self.target.get().unwrap().as_ref())
+  }
+
+  /// Fills in an atom for this ref.
+  fn value(&self, atom: &Atom) -> Result<Value, String> {
+    let modifier = atom.modifier.as_deref();
+    let unknown = || {
+      let modifier = modifier.unwrap_or("");
+      format!(
+        "unrecognized %({}:{}) argument: {}",
+        atom.name, modifier, modifier
+      )
+    };
+    match atom.name.as_str() {
+      "refname" => return Ok(self.refname(modifier).ok_or_else(unknown)?.into()),
+      "HEAD" => {
+        let head = refs::read_symbolic(self.repo, "HEAD");
+        let text = if head.as_ref() == Some(&self.name) {
+          "*"
+        } else {
+          " "
+        };
+        return Ok(text.to_string().into());
+      }
+      "symref" => {
+        let target = match refs::read_symbolic(self.repo, &self.name) {
+          Some(target) if modifier == Some("short") => refs::shorten(self.repo, &target),
+          Some(target) => target,
+          None => String::new(),
+        };
+        return Ok(target.into());
+      }
+      "upstream" => return Ok(self.upstream(modifier).ok_or_else(unknown)??.into()),
+      _ => (),
+    }
+
+    let (hash, object) = match atom.deref {
+      false => (self.hash.clone(), self.object()?),
+      true => match self.target()? {
+        Some(target) => (
+          self.object()?.fields.get("object").cloned().unwrap(),
+          target,
+        ),
+        None => return Ok(String::new().into()),
+      },
+    };
+    let message = object.fields.message();
+    let (subject, body) = split_message(message);
+    Ok(match (atom.name.as_str(), modifier) {
+      ("objectname", None) => hash.into(),
+      ("objectname", Some("short")) => hash[..7].to_string().into(),
+      ("objectname", Some(length)) => {
+        let length = length
+          .strip_prefix("short=")
+          .and_then(|length| length.parse::<usize>().ok())
+          .ok_or_else(unknown)?;
+        hash[..length.clamp(4, hash.len())].to_string().into()
+      }
+      ("objecttype", _) => object.kind.clone().into(),
+      ("objectsize", _) => Value {
+        text: object.size.to_string(),
+        number: Some(object.size as i64),
+      },
+      ("subject", _) | ("contents", Some("subject")) => subject.into(),
+      ("body", _) | ("contents", Some("body")) => body.into(),
+      ("contents", _) => message.to_string().into(),
+      ("parent", _) => object.fields.get_all("parent").join(" ").into(),
+      ("tree" | "object" | "type" | "tag", _) => object
+        .fields
+        .get(&atom.name)
+        .cloned()
+        .unwrap_or_default()
+        .into(),
+      (name, modifier) => {
+        let (role, part) = PEOPLE
+          .iter()
+          .find_map(|role| Some((*role, name.strip_prefix(role)?)))
+          .unwrap();
+        let (line, signature) = match object.person(role) {
+          Some(person) => person,
+          None => return Ok(String::new().into()),
+        };
+        match part {
+          "name" => signature.name.into(),
+          "email" if modifier == Some("trim") => signature.email.into(),
+          "email" => format!("<{}>", signature.email).into(),
+          "date" => Value {
+            text: date::format(
+              signature.time,
+              &signature.timezone,
+              modifier.unwrap_or("default"),
+            )
+            .ok_or_else(|| format!("unknown date format {}", modifier.unwrap_or("")))?,
+            number: Some(signature.time),
+          },
+          _ => line.clone().into(),
+        }
+      }
+    })
+  }
+
+  /// The name of the ref: in full, `short`, or with components taken off
+  /// the start (`lstrip=<n>`) or end (`rstrip=<n>`). A negative count says
+  /// how many to keep instead.
+  fn refname(&self, modifier: Option<&str>) -> Option<String> {
+    let modifier = match modifier {
+      None => return Some(self.name.clone()),
+      Some("short") => return Some(refs::shorten(self.repo, &self.name)),
+      Some(modifier) => modifier,
+    };
+    let (left, count) = match modifier.split_once('=')? {
+      ("lstrip" | "strip", count) => (true, count.parse::<i64>().ok()?),
+      ("rstrip", count) => (false, count.parse::<i64>().ok()?),
+      _ => return None,
+    };
+    let parts: Vec<&str> = self.name.split('/').collect();
+    let len = parts.len() as i64;
+    let strip = match count < 0 {
+      true => (len + count).max(0),
+      false => count.min(len),
+    } as usize;
+    Some(match left {
+      true => parts[strip..].join("/"),
+      false => parts[..parts.len() - strip].join("/"),
+    })
+  }
+
+  /// The branch this ref tracks, if it is a branch that tracks one: its
+  /// name in full or `short`, or how far apart they are (`track` says
+  /// `[ahead 1, behind 2]`, `trackshort` says `<>`), without the brackets
+  /// with `nobracket`.
+  fn upstream(&self, modifier: Option<&str>) -> Option<Result<String, String>> {
+    let branch = match self.name.strip_prefix("refs/heads/") {
+      Some(branch) => branch,
+      None => return Some(Ok(String::new())),
+    };
+    let tracking = match branch::tracking_ref(self.repo, branch) {
+      Some(tracking) => tracking,
+      None => return Some(Ok(String::new())),
+    };
+    let (track, brackets) = match modifier {
+      None => return Some(Ok(tracking)),
+      Some("short") => return Some(Ok(refs::shorten(self.repo, &tracking))),
+      Some("track") => (true, true),
+      Some("track,nobracket") => (true, false),
+      Some("trackshort") => (false, false),
+      Some(_) => return None,
+    };
+    let upstream = match branch::upstream(self.repo, branch, &self.hash) {
+      Ok(Some(upstream)) => upstream,
+      Ok(None) => return Some(Ok(String::new())),
+      Err(e) => return Some(Err(e)),
+    };
+    let text = match (track, upstream.ahead_behind) {
+      (true, _) => upstream.short(),
+      (false, None) => String::new(),
+      (false, Some((0, 0))) => "=".to_string(),
+      (false, Some((_, 0))) => ">".to_string(),
+      (false, Some((0, _))) => "<".to_string(),
+      (false, Some(_)) => "<>".to_string(),
+    };
+    Some(Ok(match brackets && !text.is_empty() {
+      true => format!("[{}]", text),
+      false => text,
+    }))
+  }
+}
+
+/// The subject of a message, its first paragraph with the lines joined,
+/// and the body, which is everything after it.
+fn split_message(message: &str) -> (String, String) {
+  let message = message.trim_start_matches('\n');
+  let (subject, body) = match message.find("\n\n") {
+    Some(end) => (&message[..end], message[end..].trim_start_matches('\n')),
+    None => (message, ""),
+  };
+  let subject: Vec<&str> = subject.lines().map(str::trim).collect();
+  (subject.join(" "), body.to_string())
+}
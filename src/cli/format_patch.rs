@@ -0,0 +1,217 @@
+use clap::Args;
+use std::fs;
+use std::path::PathBuf;
+
+use git_rs_core::{
+  diff::{self, patch},
+  mail,
+  object::{commit::Commit, find_object, read, serializable::Unbox},
+  repo::Repo,
+  rev::walk::RevWalk,
+};
+
+/// Prepare patches for e-mail submission.
+///
+/// Writes each commit in the given range as a patch in mbox format, ready to
+/// be sent by e-mail and applied with `git am`. A single revision `<since>`
+/// means the commits from `<since>` up to HEAD, while `-<n>` takes the `n`
+/// most recent commits (from HEAD, or the given revision). Each patch goes to
+/// a file named after its subject (eg. `0001-fix-typo.patch`) whose name is
+/// printed, or to standard output with `--stdout`.
+///
+/// Changes to binary files are included as binary patches that `git am` can
+/// apply, unless `--no-binary` is given.
+///
+/// # Example
+/// ```bash
+/// $ git format-patch -2
+/// 0001-add-parser.patch
+/// 0002-use-parser.patch
+/// $ git format-patch --stdout main..topic > topic.mbox
+/// ```
+#[derive(Args, Debug)]
+#[clap(allow_negative_numbers = true)]
+pub struct FormatPatch {
+  /// The commits to format: `<since>`, `<since>..<until>` or `-<n>`.
+  pub revisions: Vec<String>,
+
+  /// Write the patches to this directory.
+  #[clap(short, long, value_name = "DIR")]
+  pub output_directory: Option<String>,
+
+  /// Print all the patches to standard output instead of writing files.
+  #[clap(long, conflicts_with = "output-directory")]
+  pub stdout: bool,
+
+  /// Number the patches in the subject even if there is only one.
+  #[clap(short, long, conflicts_with = "no-numbered")]
+  pub numbered: bool,
+
+  /// Never number the patches in the subject.
+  #[clap(short = 'N', long)]
+  pub no_numbered: bool,
+
+  /// Use this instead of `PATCH` in the subject prefix.
+  #[clap(long, default_value = "PATCH")]
+  pub subject_prefix: String,
+
+  /// Include binary patches for changes to binary files (the default).
+  #[clap(long, overrides_with = "no-binary")]
+  pub binary: bool,
+
+  /// Only note that binary files differ, without their contents.
+  #[clap(long)]
+  pub no_binary: bool,
+}
+
+/// Patch files are named after their subject, cut down to this many bytes
+/// (including the number but not `.patch`).
+const NAME_MAX: usize = 57;
+
+pub fn cmd_format_patch(opts: &FormatPatch) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let mut walk = RevWalk::new(&repo);
+  let mut max_count: Option<usize> = None;
+  let mut revisions: Vec<&String> = Vec::new();
+  for arg in &opts.revisions {
+    match arg.strip_prefix('-').map(|n| n.parse::<usize>()) {
+      Some(Ok(n)) => max_count = Some(n),
+      Some(Err(_)) => return Err(format!("unrecognized argument: {}", arg)),
+      None => revisions.push(arg),
+    }
+  }
+  match (revisions.as_slice(), max_count) {
+    ([], Some(_)) => walk.push_spec("HEAD")?,
+    ([rev], Some(_)) => walk.push_spec(rev)?,
+    ([rev], None) if !rev.contains("..") => walk.push_spec(&format!("{}..HEAD", rev))?,
+    ([_, ..], _) => {
+      for rev in &revisions {
+        walk.push_spec(rev)?;
+      }
+    }
+    ([], None) => return Err("no revision given".to_string()),
+  }
+  walk.max_count(max_count);
+
+  let mut commits = Vec::new();
+  for hash in walk.run()? {
+    // merges have no single diff to send
+    if walk.parents(&hash).len() <= 1 {
+      commits.push(hash);
+    }
+  }
+  commits.reverse();
+
+  let numbered = opts.numbered || (commits.len() > 1 && !opts.no_numbered);
+  if let Some(dir) = &opts.output_directory {
+    fs::create_dir_all(dir).map_err(|e| format!("could not create directory '{}': {}", dir, e))?;
+  }
+  for (n, hash) in commits.iter().enumerate() {
+    let prefix = match numbered {
+      true => format!("[{} {}/{}] ", opts.subject_prefix, n + 1, commits.len()),
+      false => format!("[{}] ", opts.subject_prefix),
+    };
+    let (email, title) = format_commit(&repo, hash, &prefix, !opts.no_binary)?;
+    if opts.stdout {
+      // patches after the first are set apart by a blank line
+      if n > 0 {
+        println!();
+      }
+      print!("{}", email);
+      continue;
+    }
+    let name = patch_name(n + 1, &title);
+    let path = match &opts.output_directory {
+      Some(dir) => PathBuf::from(dir).join(&name),
+      None => PathBuf::from(&name),
+    };
+    fs::write(&path, email).map_err(|e| format!("could not write '{}': {}", path.display(), e))?;
+    println!("{}", path.display());
+  }
+  Ok(())
+}
+
+/// Formats a commit as an email, returning it along with the commit's title.
+fn format_commit(
+  repo: &Repo,
+  hash: &str,
+  prefix: &str,
+  binary: bool,
+) -> Result<(String, String), String> {
+  let object = read(repo, hash, Some("commit"))?;
+  let commit: &Commit = object.unbox::<Commit>()?;
+  let converted = commit.to_utf8();
+  let commit = converted.as_ref().unwrap_or(commit);
+  let author = commit.get("author").cloned().unwrap_or_default();
+  let (name, email) = mail::parse_address(&author);
+  let mut date = author.rsplit(' ');
+  let timezone = date.next().unwrap_or("+0000").to_string();
+  let seconds: i64 = date.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+
+  let message = commit.message();
+  let (title, body) = mail::split_message(message);
+  let mut out = format!("From {} {}\n", hash, mail::MBOX_FROM_DATE);
+  out.push_str(&mail::from_header(&name, &email));
+  out.push_str(&format!(
+    "\nDate: {}\n",
+    mail::format_date(seconds, &timezone)
+  ));
+  out.push_str(&mail::header(&format!("Subject: {}", prefix), &title));
+  out.push('\n');
+  if !message.is_ascii() {
+    out.push_str("MIME-Version: 1.0\nContent-Type: text/plain; charset=UTF-8\nContent-Transfer-Encoding: 8bit\n");
+  }
+  out.push('\n');
+  if !body.is_empty() {
+    out.push_str(&body);
+    out.push('\n');
+  }
+
+  let parent = commit.parents().first().cloned();
+  let parent_tree = match &parent {
+    Some(parent) => Some(find_object(repo, parent, Some("tree"), true)?),
+    None => None,
+  };
+  let changes = diff::detect_renames(
+    repo,
+    diff::diff_trees(repo, parent_tree.as_deref(), Some(commit.tree()))?,
+  )?;
+  out.push_str("---\n");
+  out.push_str(&patch::stat(repo, &changes, 72)?);
+  out.push_str(&patch::summary(repo, &changes)?);
+  out.push('\n');
+  for change in &changes {
+    out.push_str(&patch::file_patch_with(repo, change, None, binary)?);
+  }
+  out.push_str(&format!("-- \n{}\n\n", env!("CARGO_PKG_VERSION")));
+  Ok((out, title))
+}
+
+/// Names the file for the `n`th patch after its title, keeping only letters,
+/// digits, `.` and `_` and joining the words in between with dashes.
+fn patch_name(n: usize, title: &str) -> String {
+  let mut name = format!("{:04}-", n);
+  let start = name.len();
+  let mut gap = false;
+  let mut last = '\0';
+  for c in title.chars() {
+    if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+      if gap && name.len() > start {
+        name.push('-');
+      }
+      gap = false;
+      if !(c == '.' && last == '.') {
+        name.push(c);
+      }
+      last = c;
+    } else {
+      gap = true;
+      last = c;
+    }
+  }
+  while name.len() > start && (name.ends_with('.') || name.ends_with('-')) {
+    name.pop();
+  }
+  name.truncate(NAME_MAX);
+  format!("{}.patch", name)
+}
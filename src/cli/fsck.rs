@@ -0,0 +1,65 @@
+use clap::Args;
+use std::process;
+
+use git_rs_core::connected;
+use git_rs_core::index::Index;
+use git_rs_core::object::{self, refs};
+use git_rs_core::repo::Repo;
+
+/// Verifies the connectivity of the objects in the database.
+///
+/// Everything reachable from `HEAD`, the refs and the index is checked to be
+/// in the repository. Objects that are missing are listed, along with the
+/// object that refers to them, and the command fails.
+///
+/// Only connectivity is checked, as with `git fsck --connectivity-only`: the
+/// objects themselves are not validated, and unreachable ones are not
+/// reported.
+///
+/// # Example
+/// ```bash
+/// $ git fsck
+/// broken link from    tree c9b801068840df6bd9a0cd4efec5d00fafdbe36a
+///               to    tree 6be660545b31f61a82a87d2b1915f0b88bb9f16f
+/// missing tree 6be660545b31f61a82a87d2b1915f0b88bb9f16f
+/// ```
+#[derive(Args, Debug)]
+pub struct Fsck {
+  /// Check only that reachable objects exist (the only check there is).
+  #[clap(long)]
+  pub connectivity_only: bool,
+}
+
+pub fn cmd_fsck(_opts: &Fsck) -> Result<(), String> {
+  object::ignore_replacements();
+  let repo: Repo = Repo::default();
+  let mut tips: Vec<String> = refs::lookup(&repo, "HEAD").into_iter().collect();
+  tips.extend(refs::collect(&repo, None).into_values());
+  let mut missing = connected::missing(&repo, &tips, &[])?;
+  for entry in Index::read(&repo)?.entries() {
+    // submodule commits live in another repository
+    if entry.mode != 0o160000 && !repo.objects.exists(&entry.hash) {
+      missing.push(connected::Missing {
+        kind: "blob",
+        hash: entry.hash.clone(),
+        from: None,
+      });
+    }
+  }
+  if missing.is_empty() {
+    return Ok(());
+  }
+
+  for object in &missing {
+    if let Some((kind, from)) = &object.from {
+      println!("broken link from {:>7} {}", kind, from);
+      println!("              to {:>7} {}", object.kind, object.hash);
+    }
+  }
+  missing.sort_by(|a, b| a.hash.cmp(&b.hash));
+  missing.dedup_by(|a, b| a.hash == b.hash);
+  for object in &missing {
+    println!("missing {} {}", object.kind, object.hash);
+  }
+  process::exit(2);
+}
@@ -2,13 +2,14 @@ use std::{fs, path::PathBuf, str::FromStr};
 
 use clap::Args;
 
-use crate::object::blob::Blob;
-use crate::object::commit::Commit;
-use crate::object::serializable::Serializable;
-use crate::object::tag::Tag;
-use crate::object::tree::Tree;
-use crate::object::write;
-use crate::repo::Repo;
+use git_rs_core::convert;
+use git_rs_core::object::blob::Blob;
+use git_rs_core::object::commit::Commit;
+use git_rs_core::object::serializable::Serializable;
+use git_rs_core::object::tag::Tag;
+use git_rs_core::object::tree::Tree;
+use git_rs_core::object::write;
+use git_rs_core::repo::Repo;
 
 /// Hashes a file into a loose object.
 ///
@@ -56,6 +57,11 @@ pub struct HashObject {
   #[clap(short, long)]
   pub write: bool,
 
+  /// Hash the contents as they are, rather than as they would be stored for
+  /// the file in the working tree.
+  #[clap(long)]
+  pub no_filters: bool,
+
   /// The object type.
   #[clap(name = "TYPE", default_value_t = String::from("blob"))]
   pub typename: String,
@@ -66,18 +72,27 @@ pub struct HashObject {
 /// If the `-w` flag is passed, writes the object to the git directory at the
 /// path corresponding to its hash and prints its hash. If not write flag is
 /// given, only prints the hash.
+///
+/// A blob inside the working tree is converted as it would be when adding
+/// it, by its filter driver and line ending settings, unless `--no-filters`
+/// is given.
 pub fn cmd_hash_object(opts: &HashObject) -> Result<(), String> {
   let repo: Repo = Repo::default();
   let path: PathBuf = PathBuf::from_str(&opts.file).unwrap();
-  if let Ok(file) = fs::read(path) {
+  if let Ok(mut file) = fs::read(path) {
+    if opts.typename == "blob" && !opts.no_filters && !repo.bare {
+      if let Ok(path) = repo.worktree_path(&opts.file) {
+        file = convert::to_git(&repo, &path, file, None, opts.write)?;
+      }
+    }
     let obj: Box<dyn Serializable> = match opts.typename.as_str() {
-      "blob" => Box::new(Blob::new(repo, &file)),
-      "commit" => Box::new(Commit::new(repo, &file)),
-      "tag" => Box::new(Tag::new(repo, &file)),
-      "tree" => Box::new(Tree::new(repo, &file)),
+      "blob" => Box::new(Blob::new(&file)),
+      "commit" => Box::new(Commit::new(&file)),
+      "tag" => Box::new(Tag::new(&file)),
+      "tree" => Box::new(Tree::new(&file)?),
       _ => return Err(format!("unsupported type \"{}\"", opts.typename)),
     };
-    println!("{}", write(&*obj, !opts.write)?);
+    println!("{}", write(&repo, &*obj, !opts.write)?);
     Ok(())
   } else {
     Err("object not found".to_string())
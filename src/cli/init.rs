@@ -1,18 +1,60 @@
 use std::path::PathBuf;
 
-use crate::repo::Repo;
 use clap::Args;
+use git_rs_core::repo::{InitOptions, Repo};
 
 #[derive(Args, Debug)]
 pub struct Init {
   /// Where to create the repository.
   #[clap(default_value_t = String::from("."))]
   pub path: String,
+
+  /// Create a repository without a working tree.
+  #[clap(long)]
+  pub bare: bool,
+
+  /// The name of the branch to start out on.
+  #[clap(short = 'b', long, value_name = "name")]
+  pub initial_branch: Option<String>,
+
+  /// The directory to copy the files of the git directory from.
+  #[clap(long, value_name = "template-directory")]
+  pub template: Option<PathBuf>,
+
+  /// Put the git directory here, linked to from a `.git` file in the working
+  /// tree.
+  #[clap(long, value_name = "git-dir")]
+  pub separate_git_dir: Option<PathBuf>,
+
+  /// The hash function objects are named by.
+  #[clap(long, value_name = "format", possible_values = ["sha1", "sha256"])]
+  pub object_format: Option<String>,
+
+  /// How refs are kept: as files, or in reftables, which suit repositories
+  /// with very many refs.
+  #[clap(long, value_name = "format", possible_values = ["files", "reftable"])]
+  pub ref_format: Option<String>,
 }
 
+/// Creates an empty repository.
+///
+/// Only SHA-1 object names are supported, so `--object-format=sha256` is
+/// refused.
 pub fn cmd_init(opts: &Init) -> Result<(), String> {
-  let repo: Repo = Repo::new(&PathBuf::from(&opts.path))?;
-  let path: PathBuf = repo.work_tree.canonicalize().unwrap();
-  println!("Initialized empty Git repository in {}", path.display());
+  if opts.object_format.as_deref() == Some("sha256") {
+    return Err("the sha256 object format is not supported".to_string());
+  }
+  let options = InitOptions {
+    bare: opts.bare,
+    initial_branch: opts.initial_branch.clone(),
+    template: opts.template.clone(),
+    separate_git_dir: opts.separate_git_dir.clone(),
+    ref_format: opts.ref_format.clone(),
+  };
+  let repo: Repo = Repo::create(&PathBuf::from(&opts.path), &options)?;
+  println!(
+    "Initialized empty Git repository in {}/",
+    repo.git_dir.display()
+  );
   Ok(())
 }
@@ -0,0 +1,142 @@
+use std::fs;
+use std::io::{self, Read};
+
+use clap::Args;
+
+use git_rs_core::trailer::{AddOptions, IfExists, IfMissing, Line, Message, Trailer, Where};
+
+/// Add or parse structured information in commit messages.
+///
+/// Reads each file (or standard input) as a commit message and prints it with
+/// the trailers given with `--trailer` added to its trailer block, the last
+/// paragraph of `Token: value` lines. Trailers already in the message are
+/// printed normalized, with one space after the colon.
+///
+/// Unlike git, `--where`, `--if-exists` and `--if-missing` apply to every
+/// `--trailer`, wherever they are on the command line.
+///
+/// # Example
+/// ```bash
+/// $ printf 'Fix the frobnicator\n' | git interpret-trailers --trailer 'Reviewed-by=A U Thor <author@example.com>'
+/// Fix the frobnicator
+///
+/// Reviewed-by: A U Thor <author@example.com>
+/// ```
+#[derive(Args, Debug)]
+pub struct InterpretTrailers {
+  /// The messages to read. Standard input is read if there are none.
+  pub files: Vec<String>,
+
+  /// A trailer to add, as `token=value` or `token: value`.
+  #[clap(long, multiple_occurrences = true, value_name = "TRAILER")]
+  pub trailer: Vec<String>,
+
+  /// Where to add the trailers: `end`, `start`, `after` or `before`.
+  #[clap(long = "where", value_name = "PLACEMENT")]
+  pub position: Option<Where>,
+
+  /// What to do when a trailer with the same token is already there:
+  /// `addIfDifferentNeighbor`, `addIfDifferent`, `add`, `replace` or
+  /// `doNothing`.
+  #[clap(long, value_name = "ACTION")]
+  pub if_exists: Option<IfExists>,
+
+  /// What to do when there is no trailer with the same token: `add` or
+  /// `doNothing`.
+  #[clap(long, value_name = "ACTION")]
+  pub if_missing: Option<IfMissing>,
+
+  /// Edit the files rather than printing the result.
+  #[clap(long)]
+  pub in_place: bool,
+
+  /// Leave out trailers with empty values.
+  #[clap(long)]
+  pub trim_empty: bool,
+
+  /// Print only the trailers.
+  #[clap(long)]
+  pub only_trailers: bool,
+
+  /// Do not add the trailers given with `--trailer`.
+  #[clap(long)]
+  pub only_input: bool,
+
+  /// Join continuation lines onto the trailer they continue.
+  #[clap(long)]
+  pub unfold: bool,
+
+  /// Do not treat a `---` line as the start of a patch.
+  #[clap(long)]
+  pub no_divider: bool,
+
+  /// Print the trailers already in the message, one per line (the same as
+  /// `--only-trailers --only-input --unfold`).
+  #[clap(long)]
+  pub parse: bool,
+}
+
+pub fn cmd_interpret_trailers(opts: &InterpretTrailers) -> Result<(), String> {
+  let trailers = opts
+    .trailer
+    .iter()
+    .map(|text| Trailer::parse_arg(text))
+    .collect::<Result<Vec<Trailer>, String>>()?;
+  if opts.files.is_empty() {
+    if opts.in_place {
+      return Err("no input file given for in-place editing".to_string());
+    }
+    let mut text = String::new();
+    io::stdin()
+      .read_to_string(&mut text)
+      .map_err(|e| format!("could not read from standard input ({})", e))?;
+    print!("{}", process(opts, &text, &trailers));
+    return Ok(());
+  }
+  for path in &opts.files {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read '{}': {}", path, e))?;
+    let output = process(opts, &text, &trailers);
+    match opts.in_place {
+      true => fs::write(path, output).map_err(|e| format!("could not write '{}': {}", path, e))?,
+      false => print!("{}", output),
+    }
+  }
+  Ok(())
+}
+
+/// Adds the trailers to a message and formats it as the options ask.
+fn process(opts: &InterpretTrailers, text: &str, trailers: &[Trailer]) -> String {
+  let (only_trailers, only_input, unfold) = match opts.parse {
+    true => (true, true, true),
+    false => (opts.only_trailers, opts.only_input, opts.unfold),
+  };
+  let mut message = Message::parse(text, !opts.no_divider);
+  if unfold {
+    for line in &mut message.lines {
+      if let Line::Trailer(trailer) = line {
+        trailer.value = trailer.unfolded_value();
+      }
+    }
+  }
+  if !only_input {
+    let options = AddOptions {
+      position: opts.position.unwrap_or_default(),
+      if_exists: opts.if_exists.unwrap_or_default(),
+      if_missing: opts.if_missing.unwrap_or_default(),
+    };
+    for trailer in trailers {
+      message.add(trailer.clone(), &options);
+    }
+  }
+  message.lines.retain(|line| match line {
+    Line::Trailer(trailer) => !opts.trim_empty || !trailer.value.is_empty(),
+    Line::Other(_) => !only_trailers,
+  });
+  match only_trailers {
+    true => message
+      .trailers()
+      .map(|trailer| format!("{}\n", trailer))
+      .collect(),
+    false => message.to_string(),
+  }
+}
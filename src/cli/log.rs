@@ -1,71 +1,294 @@
-use std::collections::HashSet;
+use std::io::{self, Write};
 
 use clap::Args;
-use colored::Colorize;
-use indexmap::IndexMap;
+use encoding_rs::{Encoding, UTF_8};
+use regex::bytes::Regex;
+use serde::Serialize;
 
-use crate::{
-  object::{commit::Commit, find_object, read, serializable::Unbox},
+use git_rs_core::{
+  color::Colors,
+  diff::pickaxe::Pickaxe,
+  gpg::{self, Verification},
+  identity::{date::parse_limit, Signature},
+  object::{commit::Commit, read, refs, serializable::Unbox},
+  pathspec,
   repo::Repo,
+  rev::{
+    graph::Graph,
+    walk::{RevWalk, Sort},
+  },
 };
 
 /// Display history of a given commit.
 ///
 /// Starting at the given commit (or HEAD by default) this command will show
-/// the history of changes for a particular commit.
+/// the history of changes for a particular commit. When paths are given, only
+/// commits that change those paths are shown.
 ///
 /// # Example
 /// ```bash
 /// $ git log
+/// $ git log --follow -- src/main.rs
+/// $ git log --json
+/// [{"hash":"9f3c2a1...","parents":["5e8b0c4..."],"author":{"name":"A U Thor","email":"author@example.com","time":1700000000,"timezone":"-0700"},...}]
 /// ```
 #[derive(Args, Debug)]
 pub struct Log {
   /// The commit to start at.
   #[clap(default_value_t = String::from("HEAD"))]
   pub commit: String,
+
+  /// Continue listing the history of a file beyond renames.
+  #[clap(long)]
+  pub follow: bool,
+
+  /// Draw a text-based graph of the commit history alongside the log.
+  #[clap(long)]
+  pub graph: bool,
+
+  /// Only show commits that change the number of occurrences of a string.
+  #[clap(short = 'S', value_name = "STRING")]
+  pub pickaxe_string: Option<String>,
+
+  /// Only show commits whose diff adds or removes a line matching a regex.
+  #[clap(short = 'G', value_name = "REGEX", conflicts_with = "pickaxe-string")]
+  pub pickaxe_regex: Option<String>,
+
+  /// Only show commits more recent than a date, such as `2 weeks ago`.
+  #[clap(long, visible_alias = "after", value_name = "DATE")]
+  pub since: Option<String>,
+
+  /// Only show commits older than a date.
+  #[clap(long, visible_alias = "before", value_name = "DATE")]
+  pub until: Option<String>,
+
+  /// Check the signatures of signed commits, and show what was found.
+  #[clap(long)]
+  pub show_signature: bool,
+
+  /// Color the output: `always`, `never` or `auto` (when it goes to a
+  /// terminal). Without a value, `always`.
+  #[clap(
+    long,
+    value_name = "WHEN",
+    min_values = 0,
+    require_equals = true,
+    default_missing_value = "always",
+    possible_values = &["always", "never", "auto"]
+  )]
+  pub color: Option<String>,
+
+  /// Don't color the output.
+  #[clap(long, overrides_with = "color")]
+  pub no_color: bool,
+
+  /// Print the commits as a JSON array of [`LogEntry`] objects.
+  #[clap(long, conflicts_with = "graph")]
+  pub json: bool,
+
+  /// Only show commits that change these paths.
+  #[clap(last = true)]
+  pub paths: Vec<String>,
 }
 
-pub fn cmd_log(opts: &Log) -> Result<(), String> {
-  let repo: Repo = Repo::default();
-  let mut seen: HashSet<String> = HashSet::default();
-  print_commit(
-    repo.clone(),
-    find_object(repo, &opts.commit, None, false).to_string(),
-    &mut seen,
-  )?;
-  Ok(())
+/// A commit as `log --json` prints it.
+#[derive(Serialize)]
+pub struct LogEntry {
+  pub hash: String,
+
+  /// The parents the commit is shown with, which skip commits left out by
+  /// the paths given.
+  pub parents: Vec<String>,
+  pub author: Option<Signature>,
+  pub committer: Option<Signature>,
+
+  /// The whole message, including its trailing newline.
+  pub message: String,
+
+  /// What checking the commit's signature found, with `--show-signature`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub signature: Option<Verification>,
 }
 
-/// Walks through the commit tree and prints out each commit.
-fn print_commit(repo: Repo, hash: String, seen: &mut HashSet<String>) -> Result<(), String> {
-  if seen.contains(&hash) {
-    // already added commit, nothing to do!
-    return Ok(());
+pub fn cmd_log(opts: &Log) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let mut walk = RevWalk::new(&repo);
+  if opts.commit == "HEAD" {
+    if let Some(branch) = refs::unborn(&repo) {
+      return Err(format!(
+        "your current branch '{}' does not have any commits yet",
+        branch.strip_prefix("refs/heads/").unwrap_or(&branch)
+      ));
+    }
+  }
+  walk.push_spec(&opts.commit)?;
+  walk.paths(&pathspec::resolve(&repo, &opts.paths)?);
+  walk.since(opts.since.as_deref().map(parse_limit).transpose()?);
+  walk.until(opts.until.as_deref().map(parse_limit).transpose()?);
+  if opts.follow {
+    if opts.paths.len() != 1 {
+      return Err("--follow requires exactly one pathspec".to_string());
+    }
+    walk.follow(true);
+  }
+  if opts.graph {
+    walk.sort(Sort::Topo);
+  }
+  if let Some(needle) = &opts.pickaxe_string {
+    walk.pickaxe(Some(Pickaxe::Occurrences(needle.as_bytes().to_vec())));
+  } else if let Some(pattern) = &opts.pickaxe_regex {
+    let regex = Regex::new(pattern).map_err(|e| format!("invalid regex: {}", e))?;
+    walk.pickaxe(Some(Pickaxe::Grep(regex)));
   }
-  seen.insert(hash.to_owned());
-
-  let commit_object = read(repo.clone(), &hash, Some("commit"))?;
-  assert!(commit_object.format().eq("commit"));
-  let commit: &Commit = commit_object.unbox::<Commit>()?;
 
-  let map: &IndexMap<String, String> = &commit.map;
-  if !map.contains_key("parent") {
-    // base case - the initial commit has no parent
+  let hashes = walk.run()?;
+  if opts.json {
+    let entries = hashes
+      .iter()
+      .map(|hash| log_entry(&repo, hash, walk.parents(hash), opts.show_signature))
+      .collect::<Result<Vec<LogEntry>, String>>()?;
+    println!(
+      "{}",
+      serde_json::to_string(&entries).map_err(|e| e.to_string())?
+    );
     return Ok(());
   }
 
-  let parents: Vec<&str> = map.get("parent").unwrap().split(' ').collect();
-  for parent in parents {
-    for key in map.keys() {
-      match key.as_str() {
-        "tree" => println!("commit {}", map.get("tree").unwrap().yellow()),
-        "author" => println!("Author: {}", map.get("author").unwrap()),
-        "" => println!("\n    {}", map.get("").unwrap()),
-        _ => continue,
+  let encoding = output_encoding(&repo);
+  let color = opts.no_color.then_some("never").or(opts.color.as_deref());
+  let colors = Colors::new(&repo, "diff", color)?;
+  let mut graph = Graph::new();
+  for (i, hash) in hashes.iter().enumerate() {
+    let parents = walk.parents(hash);
+    let mut lines = format_commit(&repo, hash, &parents, opts.show_signature, &colors)?;
+    if i > 0 {
+      lines.insert(0, String::new());
+    }
+    if !opts.graph {
+      for line in lines {
+        print(encoding, &line);
       }
+      continue;
     }
-    print_commit(repo.clone(), parent.to_string(), seen)?; // do without clone?
-  }
 
+    // The separating blank line is drawn before the commit takes its place.
+    if i > 0 {
+      print(encoding, &graph.padding());
+      lines.remove(0);
+    }
+    let rows = graph.update(hash, &parents);
+    // Rows that join columns are drawn alongside the commit's own lines.
+    let padding = match rows.collapse.is_empty() {
+      true => rows.padding,
+      false => graph.padding(),
+    };
+    let mut prefixes = vec![rows.commit];
+    prefixes.extend(rows.expand);
+    prefixes.extend(rows.collapse);
+    let width = prefixes.iter().map(|p| p.len()).max().unwrap_or(0);
+    let width = width.max(padding.len());
+    for (n, line) in lines.iter().enumerate() {
+      let prefix = prefixes.get(n).unwrap_or(&padding);
+      let line = format!("{:<width$} {}", prefix, line, width = width);
+      print(encoding, line.trim_end());
+    }
+    for row in prefixes.iter().skip(lines.len()) {
+      print(encoding, row);
+    }
+  }
   Ok(())
 }
+
+/// Formats a single commit as the lines `log` prints for it. What checking
+/// its signature found follows the first line, if asked for.
+fn format_commit(
+  repo: &Repo,
+  hash: &str,
+  parents: &[String],
+  show_signature: bool,
+  colors: &Colors,
+) -> Result<Vec<String>, String> {
+  let commit_object = read(repo, hash, Some("commit"))?;
+  let original: &Commit = commit_object.unbox::<Commit>()?;
+  let converted = original.to_utf8();
+  let commit = converted.as_ref().unwrap_or(original);
+
+  let mut lines = vec![colors.paint("diff.commit", &format!("commit {}", hash))];
+  if show_signature {
+    let verification = gpg::verify_object(repo, hash)?;
+    lines.extend(verification.output.lines().map(str::to_string));
+  }
+  if parents.len() > 1 {
+    let short: Vec<&str> = parents.iter().map(|p| &p[..7]).collect();
+    lines.push(format!("Merge: {}", short.join(" ")));
+  }
+  if let Some(author) = commit.get("author") {
+    // drop the timestamp and timezone that follow the email address
+    let end = author.rfind('>').map_or(author.len(), |i| i + 1);
+    lines.push(format!("Author: {}", &author[..end]));
+  }
+  lines.push(String::new());
+  for line in commit.message().lines() {
+    lines.push(format!("    {}", line));
+  }
+  Ok(lines)
+}
+
+fn log_entry(
+  repo: &Repo,
+  hash: &str,
+  parents: Vec<String>,
+  show_signature: bool,
+) -> Result<LogEntry, String> {
+  let commit_object = read(repo, hash, Some("commit"))?;
+  let original: &Commit = commit_object.unbox::<Commit>()?;
+  let converted = original.to_utf8();
+  let commit = converted.as_ref().unwrap_or(original);
+  Ok(LogEntry {
+    hash: hash.to_string(),
+    parents,
+    author: commit.get("author").and_then(|line| Signature::parse(line)),
+    committer: commit
+      .get("committer")
+      .and_then(|line| Signature::parse(line)),
+    message: commit.message().to_string(),
+    signature: match show_signature {
+      true => Some(gpg::verify_object(repo, hash)?),
+      false => None,
+    },
+  })
+}
+
+/// The encoding commits are shown in: `i18n.logOutputEncoding`, or the
+/// `i18n.commitEncoding` they are written in, or UTF-8.
+fn output_encoding(repo: &Repo) -> &'static Encoding {
+  let section = repo
+    .config
+    .as_ref()
+    .and_then(|config| config.section(Some("i18n")));
+  let value = |key: &str| {
+    section.and_then(|section| {
+      section
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value)
+    })
+  };
+  value("logOutputEncoding")
+    .or_else(|| value("commitEncoding"))
+    .and_then(|label| Encoding::for_label(label.trim().as_bytes()))
+    .unwrap_or(UTF_8)
+}
+
+/// Prints a line in the output encoding.
+fn print(encoding: &'static Encoding, line: &str) {
+  if encoding == UTF_8 {
+    println!("{}", line);
+    return;
+  }
+  let (bytes, _, _) = encoding.encode(line);
+  let mut stdout = io::stdout().lock();
+  let _ = stdout
+    .write_all(&bytes)
+    .and_then(|_| stdout.write_all(b"\n"));
+}
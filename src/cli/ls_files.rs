@@ -0,0 +1,76 @@
+use bstr::{BString, ByteVec};
+use clap::Args;
+use std::io::{self, Write};
+
+use git_rs_core::{index::Index, pathspec, repo::Repo, status::quote};
+
+/// Show information about files in the index.
+///
+/// Lists the paths in the index, once each. With `--stage` each entry is
+/// shown with its mode, hash and merge stage, so that the base, our and
+/// their versions of a conflicted path (stages 1 to 3) are told apart;
+/// `--unmerged` shows only those.
+///
+/// # Example
+/// ```bash
+/// $ git ls-files --unmerged
+/// 100644 d00491fd7e5bb6fa28c517a0bb32b8b506539d4d 1	a.txt
+/// 100644 9e35b3a47b0d0adfcb79b9d9fa6ce8b55ed7b2d3 2	a.txt
+/// 100644 3ab0f5c8d2e1d2c5b3d6e4c7f7b2a0c0b1d9e8f7 3	a.txt
+/// ```
+// the example is tab separated, as the output is
+#[allow(clippy::tabs_in_doc_comments)]
+#[derive(Args, Debug)]
+pub struct LsFiles {
+  /// Show the mode, hash and stage of each entry.
+  #[clap(short, long)]
+  pub stage: bool,
+
+  /// Show only the entries of unmerged paths (implies `--stage`).
+  #[clap(short, long)]
+  pub unmerged: bool,
+
+  /// End each line with a NUL byte, and don't quote paths.
+  #[clap(short)]
+  pub z: bool,
+
+  /// Only list these paths.
+  pub paths: Vec<String>,
+}
+
+pub fn cmd_ls_files(opts: &LsFiles) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let paths = pathspec::resolve(&repo, &opts.paths)?;
+  let index = Index::read(&repo)?;
+  let eol = if opts.z { "\0" } else { "\n" };
+
+  let mut out = BString::default();
+  let mut last: Option<&BString> = None;
+  for entry in index.entries() {
+    if !pathspec::matches(&entry.path, &paths) || (opts.unmerged && entry.stage() == 0) {
+      continue;
+    }
+    // with -z the bytes are written as they are, whatever their encoding
+    let path = match opts.z {
+      true => entry.path.clone(),
+      false => quote(&entry.path).into(),
+    };
+    if opts.stage || opts.unmerged {
+      out.push_str(format!(
+        "{:06o} {} {}\t",
+        entry.mode,
+        entry.hash,
+        entry.stage()
+      ));
+      out.push_str(path);
+      out.push_str(eol);
+    } else if last != Some(&entry.path) {
+      out.push_str(path);
+      out.push_str(eol);
+    }
+    last = Some(&entry.path);
+  }
+  io::stdout()
+    .write_all(&out)
+    .map_err(|e| format!("unable to write the list ({})", e))
+}
@@ -0,0 +1,114 @@
+use clap::Args;
+use std::process;
+
+use git_rs_core::{
+  maintenance::{self, Task},
+  object,
+  progress::Meter,
+  repo::Repo,
+};
+
+/// Run tasks to optimize Git repository data.
+///
+/// `run` runs the tasks named with `--task`, in that order, or else those
+/// `maintenance.<task>.enabled` turns on, which are all but `prefetch` by
+/// default. Each tidies up a little at a time, so that a big repository
+/// stays quick to work with without a long `gc`:
+///
+/// - `prefetch` fetches from every remote into `refs/prefetch/`, so the
+///   objects are there by the time they are fetched, leaving the
+///   remote-tracking branches alone;
+/// - `loose-objects` deletes the loose objects that are in a pack (see
+///   `prune-packed`), and packs those that aren't in a pack yet;
+/// - `incremental-repack` packs the objects of every pack but the biggest
+///   into one, unless the pack is to be kept (it has a `.keep` file);
+/// - `commit-graph` writes a commit-graph of the commits the refs reach;
+/// - `pack-refs` packs the refs.
+///
+/// Before the tasks, the temporary files and lock files that processes
+/// which stopped halfway left behind are deleted, once they are older than
+/// `gc.pruneExpire` (two weeks by default, or `never`).
+///
+/// With `--auto`, a task only runs if there is enough for it to do, which
+/// `maintenance.<task>.auto` says as `gc.auto` does: how many commits
+/// missing from the commit-graph (100 by default), loose objects (100),
+/// packs (10) or loose refs (100) it takes. 0 turns the task off, and a
+/// negative number has it always run. `maintenance.auto = false` turns
+/// `--auto` off altogether.
+///
+/// # Example
+/// ```bash
+/// $ git maintenance run --task=commit-graph --task=loose-objects
+/// $ git -c maintenance.loose-objects.auto=1000 maintenance run --auto
+/// ```
+#[derive(Args, Debug)]
+pub struct Maintenance {
+  /// What to do.
+  #[clap(possible_values = &["run"])]
+  pub command: String,
+
+  /// A task to run (may be given more than once).
+  #[clap(
+    long = "task",
+    value_name = "TASK",
+    multiple_occurrences = true,
+    number_of_values = 1
+  )]
+  pub tasks: Vec<String>,
+
+  /// Only run the tasks that have enough to do.
+  #[clap(long)]
+  pub auto: bool,
+
+  /// Do not show progress.
+  #[clap(short, long)]
+  pub quiet: bool,
+}
+
+pub fn cmd_maintenance(opts: &Maintenance) -> Result<(), String> {
+  // the commit-graph and the packs hold the objects as they are, not as
+  // they have been replaced
+  object::ignore_replacements();
+  let repo: Repo = Repo::default();
+  if opts.auto && !maintenance::auto_enabled(&repo) {
+    return Ok(());
+  }
+  let mut tasks = Vec::new();
+  for name in &opts.tasks {
+    let task = Task::parse(name).ok_or_else(|| format!("'{}' is not a valid task", name))?;
+    if tasks.contains(&task) {
+      return Err(format!("task '{}' cannot be selected multiple times", name));
+    }
+    tasks.push(task);
+  }
+  if tasks.is_empty() {
+    tasks = maintenance::enabled(&repo);
+  }
+
+  let lock = match maintenance::lock(&repo) {
+    Ok(lock) => lock,
+    // another run is already at it
+    Err(_) if opts.auto => return Ok(()),
+    Err(e) => return Err(e),
+  };
+  if let Some(before) = maintenance::stale_before(&repo)? {
+    maintenance::remove_stale_files(&repo, before);
+  }
+  let mut progress = Meter::boxed(if opts.quiet { Some(false) } else { None });
+  let mut failed = false;
+  for task in tasks {
+    if opts.auto && !task.due(&repo)? {
+      continue;
+    }
+    if let Err(e) = task.run(&repo, progress.as_mut()) {
+      eprintln!("error: {}", e);
+      eprintln!("error: task '{}' failed", task.name());
+      failed = true;
+    }
+  }
+  if failed {
+    drop(lock);
+    process::exit(1);
+  }
+  Ok(())
+}
@@ -1,10 +1,314 @@
+use bstr::{BString, ByteSlice};
 use clap::Args;
+use std::collections::BTreeMap;
+use std::fs;
+use std::process;
 
+use crate::cli::commit::{cmd_commit, Commit};
+use git_rs_core::{
+  cancel::{self, Cancel},
+  diff::{self, patch},
+  identity::{Role, Signature},
+  index::{Index, IndexEntry},
+  merge::{state::MergeState, tree::merge_commits},
+  object::{self, commit::CommitBuilder, find_object, mode::Mode, refs, tree},
+  pager,
+  progress::{Meter, NoProgress},
+  repo::Repo,
+  rerere,
+  rev::{self, walk::merge_bases},
+  worktree,
+};
+
+type Files = BTreeMap<BString, (Mode, String)>;
+
+/// Join two or more development histories together.
+///
+/// Brings the changes made on another branch since it split off into the
+/// current one. When the current branch is behind, it is simply moved up
+/// (a fast-forward, unless `--no-ff` is given); otherwise the two are merged
+/// into a new commit with both as parents.
+///
+/// When files conflict, the merge stops with the conflicts left in the index
+/// and the working tree, and what was being merged kept in `MERGE_HEAD`,
+/// `MERGE_MSG` and `MERGE_MODE`. Resolve them and `git commit` (or `git
+/// merge --continue`) to conclude the merge, or go back to where things were
+/// with `--abort`.
+///
+/// # Example
+/// ```bash
+/// $ git merge topic
+/// Merge made by the 'ort' strategy.
+/// $ git merge --abort
+/// ```
 #[derive(Args, Debug)]
 pub struct Merge {
-  pub name: Option<String>,
+  /// The commit to merge into the current branch.
+  #[clap(required_unless_present_any = &["resume", "abort"])]
+  pub commit: Option<String>,
+
+  /// Use this message for the merge commit.
+  #[clap(short, long)]
+  pub message: Option<String>,
+
+  /// Make a merge commit even when the branch could be fast-forwarded.
+  #[clap(long, conflicts_with = "ff-only")]
+  pub no_ff: bool,
+
+  /// Refuse to merge unless the branch can be fast-forwarded.
+  #[clap(long)]
+  pub ff_only: bool,
+
+  /// Merge commits that have no common ancestor.
+  #[clap(long)]
+  pub allow_unrelated_histories: bool,
+
+  /// Conclude a merge that stopped for conflicts, once they are resolved.
+  #[clap(long = "continue", conflicts_with_all = &["commit", "abort"])]
+  pub resume: bool,
+
+  /// Give up on a merge that stopped for conflicts, putting the branch,
+  /// index and working tree back the way they were before it.
+  #[clap(long, conflicts_with = "commit")]
+  pub abort: bool,
+}
+
+pub fn cmd_merge(opts: &Merge) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  if opts.abort {
+    return abort(&repo);
+  }
+  if opts.resume {
+    if MergeState::read(&repo)?.is_none() {
+      return Err("There is no merge in progress (MERGE_HEAD missing).".to_string());
+    }
+    return cmd_commit(&Commit {
+      message: Vec::new(),
+      file: None,
+      edit: false,
+      no_edit: false,
+      amend: false,
+      date: None,
+      signoff: false,
+      allow_empty: false,
+      quiet: false,
+    });
+  }
+  if MergeState::read(&repo)?.is_some() {
+    return Err(
+      "You have not concluded your merge (MERGE_HEAD exists).\nPlease, commit your changes before you merge."
+        .to_string(),
+    );
+  }
+
+  let name = opts.commit.as_deref().unwrap_or_default();
+  let theirs = find_object(&repo, name, Some("commit"), true)
+    .map_err(|_| format!("{} - not something we can merge", name))?;
+  let head = find_object(&repo, "HEAD", Some("commit"), true)?;
+  let bases = merge_bases(&repo, &head, &theirs)?;
+  if bases.contains(&theirs) {
+    println!("Already up to date.");
+    return Ok(());
+  }
+  let fast_forward = bases.contains(&head) && !opts.no_ff;
+  if opts.ff_only && !fast_forward {
+    return Err("Not possible to fast-forward, aborting.".to_string());
+  }
+
+  let index = Index::read(&repo)?;
+  if index.entries().iter().any(|e| e.stage() != 0) {
+    return Err("Merging is not possible because you have unmerged files.".to_string());
+  }
+  let head_files = tree::flatten(&repo, &object::head_tree(&repo)?)?;
+  let staged: Vec<BString> = diff::compare(&head_files, &index.files())
+    .into_iter()
+    .map(|change| change.path)
+    .collect();
+  if !staged.is_empty() {
+    return Err(overwritten(&staged));
+  }
+
+  if fast_forward {
+    println!("Updating {}..{}", &head[..7], &theirs[..7]);
+    println!("Fast-forward");
+    let tree = find_object(&repo, &theirs, Some("tree"), true)?;
+    switch(&repo, &index, &head_files, &tree::flatten(&repo, &tree)?)?.write(&repo)?;
+    let reason = format!("merge {}: Fast-forward", name);
+    move_head(&repo, &head, &theirs, &reason)?;
+    return print_stat(&repo, &head, &theirs);
+  }
+
+  let merge = merge_commits(
+    &repo,
+    &head,
+    &theirs,
+    ("HEAD", name),
+    opts.allow_unrelated_histories,
+  )?;
+  let mut new_index = switch(
+    &repo,
+    &index,
+    &head_files,
+    &tree::flatten(&repo, &merge.tree)?,
+  )?;
+  for (path, stage, mode, hash) in &merge.conflicts {
+    new_index.add(IndexEntry::new(path, *mode, hash).with_stage(*stage));
+  }
+  new_index.write(&repo)?;
+  merge
+    .messages
+    .iter()
+    .for_each(|message| println!("{}", message));
+
+  let mut message = match &opts.message {
+    Some(message) => format!("{}\n", message.trim_end()),
+    None => default_message(&repo, name),
+  };
+  if !merge.conflicts.is_empty() {
+    let mut paths: Vec<&BString> = merge.conflicts.iter().map(|c| &c.0).collect();
+    paths.dedup();
+    message.push_str("\n# Conflicts:\n");
+    for path in paths {
+      message.push_str(&format!("#\t{}\n", path));
+    }
+    refs::update(&repo, "ORIG_HEAD", &head)?;
+    MergeState {
+      heads: vec![theirs],
+      message,
+      no_ff: opts.no_ff,
+    }
+    .write(&repo)?;
+    rerere::run(&repo)?
+      .iter()
+      .for_each(|message| println!("{}", message));
+    println!("Automatic merge failed; fix conflicts and then commit the result.");
+    process::exit(1);
+  }
+
+  let hash = CommitBuilder::new()
+    .author(Signature::current(&repo, Role::Author)?)
+    .committer(Signature::current(&repo, Role::Committer)?)
+    .tree(&merge.tree)
+    .parents(&[head.clone(), theirs])
+    .message(&message)
+    .write(&repo)?;
+  let reason = format!("merge {}: Merge made by the 'ort' strategy.", name);
+  move_head(&repo, &head, &hash, &reason)?;
+  println!("Merge made by the 'ort' strategy.");
+  print_stat(&repo, &head, &hash)
+}
+
+/// Puts the branch, index and working tree back the way they were before
+/// the merge in progress, keeping local changes to the files it didn't
+/// touch.
+fn abort(repo: &Repo) -> Result<(), String> {
+  if MergeState::read(repo)?.is_none() {
+    return Err("There is no merge to abort (MERGE_HEAD missing).".to_string());
+  }
+  let orig = rev::parse(repo, "ORIG_HEAD").or_else(|_| rev::parse(repo, "HEAD"))?;
+  let tree = find_object(repo, &orig, Some("tree"), true)?;
+  let index = Index::read(repo)?;
+  worktree::switch_files(
+    repo,
+    &index,
+    &tree::flatten(repo, &tree)?,
+    false,
+    &mut NoProgress,
+    &Cancel::new(),
+  )?
+  .write(repo)?;
+  if rev::parse(repo, "HEAD").ok().as_deref() != Some(orig.as_str()) {
+    refs::update(repo, "HEAD", &orig)?;
+  }
+  rerere::clear(repo)?;
+  MergeState::clear(repo)
+}
+
+/// Moves the index and working tree from `HEAD`'s files to the merged ones,
+/// refusing if that would overwrite local changes or untracked files.
+fn switch(repo: &Repo, index: &Index, head_files: &Files, target: &Files) -> Result<Index, String> {
+  let changed: Vec<BString> = worktree::unstaged_changes(repo, index)?
+    .into_iter()
+    .map(|(_, path)| path)
+    .filter(|path| head_files.get(path) != target.get(path))
+    .collect();
+  if !changed.is_empty() {
+    return Err(overwritten(&changed));
+  }
+  let untracked: Vec<&BString> = target
+    .keys()
+    .filter(|path| index.get(path).is_none() && !head_files.contains_key(*path))
+    .filter(|path| fs::symlink_metadata(repo.work_tree_path(path)).is_ok())
+    .collect();
+  if !untracked.is_empty() {
+    return Err(format!(
+      "The following untracked working tree files would be overwritten by merge:\n\t{}\nPlease move or remove them before you merge.",
+      bstr::join("\n\t", &untracked).as_bstr()
+    ));
+  }
+  let mut progress = Meter::boxed(None);
+  worktree::switch_files(
+    repo,
+    index,
+    target,
+    false,
+    progress.as_mut(),
+    &cancel::on_interrupt(),
+  )
+}
+
+/// The error for local changes a merge would overwrite.
+fn overwritten(paths: &[BString]) -> String {
+  format!(
+    "Your local changes to the following files would be overwritten by merge:\n\t{}\nPlease commit your changes or stash them before you merge.",
+    bstr::join("\n\t", paths).as_bstr()
+  )
+}
+
+/// The message of a merge commit, after the kind of ref that was merged,
+/// eg. `Merge branch 'topic'`, followed by the branch merged into unless
+/// that is `master` or `main`.
+fn default_message(repo: &Repo, name: &str) -> String {
+  let kind = [
+    ("refs/heads/", "branch"),
+    ("refs/tags/", "tag"),
+    ("refs/remotes/", "remote-tracking branch"),
+  ]
+  .iter()
+  .find(|(prefix, _)| refs::exists(repo, &format!("{}{}", prefix, name)))
+  .map_or("commit", |(_, kind)| kind);
+  let mut message = format!("Merge {} '{}'", kind, name);
+  if let Some(branch) = refs::read_symbolic(repo, "HEAD") {
+    let branch = branch.trim_start_matches("refs/heads/");
+    if branch != "master" && branch != "main" {
+      message.push_str(&format!(" into {}", branch));
+    }
+  }
+  message.push('\n');
+  message
+}
+
+/// Moves HEAD (and the branch it is on) to the merge, remembering where it
+/// was in `ORIG_HEAD`, and records the move in their reflogs.
+fn move_head(repo: &Repo, old: &str, new: &str, reason: &str) -> Result<(), String> {
+  refs::update(repo, "ORIG_HEAD", old)?;
+  refs::update(repo, "HEAD", new)?;
+  let identity = Signature::current(repo, Role::Committer)?.to_string();
+  refs::append_log(repo, "HEAD", Some(old), new, &identity, reason)?;
+  if let Some(branch) = refs::read_symbolic(repo, "HEAD") {
+    refs::append_log(repo, &branch, Some(old), new, &identity, reason)?;
+  }
+  Ok(())
 }
 
-pub fn cmd_merge() -> Result<(), String> {
-  todo!("cmd_merge");
+/// Prints what the merge changed on the current branch, as `--stat` would.
+fn print_stat(repo: &Repo, old: &str, new: &str) -> Result<(), String> {
+  let (old, new) = (
+    find_object(repo, old, Some("tree"), true)?,
+    find_object(repo, new, Some("tree"), true)?,
+  );
+  let changes = diff::detect_renames(repo, diff::diff_trees(repo, Some(&old), Some(&new))?)?;
+  print!("{}", patch::stat(repo, &changes, pager::columns())?);
+  print!("{}", patch::summary(repo, &changes)?);
+  Ok(())
 }
@@ -0,0 +1,129 @@
+use std::{
+  fs,
+  io::{self, Write},
+  process,
+};
+
+use clap::Args;
+
+use git_rs_core::{
+  diff::patch::is_binary,
+  merge::{self, Favor, MergeOptions},
+};
+
+/// Run a three-way file merge.
+///
+/// Merges the changes from `<base-file>` to `<other-file>` into
+/// `<current-file>`, writing the result back to `<current-file>` (or to
+/// standard output with `-p`). Regions changed differently on the two sides
+/// are written with conflict markers, unless one of `--ours`, `--theirs` or
+/// `--union` says how to resolve them.
+///
+/// The exit code is the number of conflicts (up to 127), so that the command
+/// can be used as a merge driver.
+///
+/// # Example
+/// ```bash
+/// $ git merge-file -p -L mine -L base -L yours a.txt base.txt b.txt
+/// one
+/// <<<<<<< mine
+/// two
+/// =======
+/// 2
+/// >>>>>>> yours
+/// three
+/// ```
+#[derive(Args, Debug)]
+pub struct MergeFile {
+  /// The file to merge into.
+  pub current: String,
+
+  /// The common ancestor of the two files.
+  pub base: String,
+
+  /// The file to merge from.
+  pub other: String,
+
+  /// The names to use for the current, base and other files in conflict
+  /// markers, in that order, instead of their paths.
+  #[clap(
+    short = 'L',
+    multiple_occurrences = true,
+    max_occurrences = 3,
+    value_name = "NAME"
+  )]
+  pub labels: Vec<String>,
+
+  /// Resolve conflicts by taking the current side.
+  #[clap(long, conflicts_with_all = &["theirs", "union"])]
+  pub ours: bool,
+
+  /// Resolve conflicts by taking the other side.
+  #[clap(long, conflicts_with = "union")]
+  pub theirs: bool,
+
+  /// Resolve conflicts by taking both sides.
+  #[clap(long)]
+  pub union: bool,
+
+  /// Show the base of each conflict as well.
+  #[clap(long)]
+  pub diff3: bool,
+
+  /// The length of the conflict markers.
+  #[clap(long, default_value = "7", value_name = "N")]
+  pub marker_size: usize,
+
+  /// Write the result to standard output instead of the current file.
+  #[clap(short = 'p', long = "stdout")]
+  pub stdout: bool,
+
+  /// Accepted for compatibility: conflicts are only reported by the exit
+  /// code.
+  #[clap(short, long)]
+  pub quiet: bool,
+}
+
+pub fn cmd_merge_file(opts: &MergeFile) -> Result<(), String> {
+  let paths = [&opts.current, &opts.base, &opts.other];
+  let mut files = Vec::new();
+  for path in paths {
+    let data = fs::read(path).map_err(|e| format!("could not read '{}' ({})", path, e))?;
+    if is_binary(&data) {
+      return Err(format!("Cannot merge binary files: {}", path));
+    }
+    files.push(data);
+  }
+
+  let label = |n: usize| opts.labels.get(n).unwrap_or(paths[n]).as_str();
+  let options = MergeOptions {
+    ours_label: label(0),
+    base_label: label(1),
+    theirs_label: label(2),
+    favor: match (opts.ours, opts.theirs, opts.union) {
+      (true, _, _) => Some(Favor::Ours),
+      (_, true, _) => Some(Favor::Theirs),
+      (_, _, true) => Some(Favor::Union),
+      _ => None,
+    },
+    marker_size: opts.marker_size,
+    diff3: opts.diff3,
+    join_non_alnum: true,
+  };
+  let merged = merge::merge_file_with(&files[1], &files[0], &files[2], &options);
+  if opts.stdout {
+    let mut stdout = io::stdout();
+    stdout
+      .write_all(&merged.data)
+      .and_then(|_| stdout.flush())
+      .map_err(|e| format!("could not write the result ({})", e))?;
+  } else {
+    fs::write(&opts.current, &merged.data)
+      .map_err(|e| format!("could not write '{}' ({})", opts.current, e))?;
+  }
+
+  if merged.conflicts > 0 {
+    process::exit(merged.conflicts.min(127) as i32);
+  }
+  Ok(())
+}
@@ -0,0 +1,85 @@
+use std::process;
+
+use clap::Args;
+
+use git_rs_core::{merge::tree::merge_commits, object::find_object, repo::Repo};
+
+/// Perform a merge without touching the index or working tree.
+///
+/// Merges `<branch2>` into `<branch1>` entirely in the object database and
+/// prints the hash of the resulting tree. Files with conflicting changes are
+/// written into the tree with conflict markers. When there are conflicts, the
+/// tree is followed by the versions of each conflicted file (mode, hash and
+/// stage, as `ls-files -u` would show them), a blank line, and messages
+/// describing the conflicts, and the exit code is 1.
+///
+/// # Example
+/// ```bash
+/// $ git merge-tree --write-tree main topic
+/// 0c684c8a71af7a7722dbba59b67b3fe15fa40e6d
+/// 100644 de980441c3ab03a8c07dda1ad27b8a11f39deb1e 1    f
+/// 100644 59362d46b7561aabadf32af73adf0ab712221968 2    f
+/// 100644 7be73ce3c1b1cdaea86e8168dfee8575175953bf 3    f
+///
+/// Auto-merging f
+/// CONFLICT (content): Merge conflict in f
+/// ```
+#[derive(Args, Debug)]
+pub struct MergeTree {
+  /// Accepted for compatibility: the result is always written as a tree.
+  #[clap(long)]
+  pub write_tree: bool,
+
+  /// Only list the names of the conflicted files.
+  #[clap(long)]
+  pub name_only: bool,
+
+  /// Leave out the messages describing the conflicts.
+  #[clap(long)]
+  pub no_messages: bool,
+
+  /// Merge commits that have no common ancestor.
+  #[clap(long)]
+  pub allow_unrelated_histories: bool,
+
+  /// The branch to merge into.
+  pub branch1: String,
+
+  /// The branch to merge.
+  pub branch2: String,
+}
+
+pub fn cmd_merge_tree(opts: &MergeTree) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let ours = find_object(&repo, &opts.branch1, Some("commit"), true)?;
+  let theirs = find_object(&repo, &opts.branch2, Some("commit"), true)?;
+  let merge = merge_commits(
+    &repo,
+    &ours,
+    &theirs,
+    (&opts.branch1, &opts.branch2),
+    opts.allow_unrelated_histories,
+  )?;
+
+  println!("{}", merge.tree);
+  if merge.conflicts.is_empty() {
+    return Ok(());
+  }
+  let mut last = None;
+  for (path, stage, mode, hash) in &merge.conflicts {
+    if !opts.name_only {
+      println!("{} {} {}\t{}", mode, hash, stage, path);
+    } else if last != Some(path) {
+      println!("{}", path);
+    }
+    last = Some(path);
+  }
+  if !opts.no_messages {
+    println!();
+    merge
+      .messages
+      .iter()
+      .for_each(|message| println!("{}", message));
+  }
+  process::exit(1);
+}
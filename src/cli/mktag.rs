@@ -0,0 +1,56 @@
+use std::io::{self, Read};
+
+use clap::Args;
+
+use git_rs_core::{
+  object::{self, read, tag},
+  repo::Repo,
+};
+
+/// Create a tag object with extra validation.
+///
+/// Reads a tag object from standard input, checks that it is well formed and
+/// that the object it points at exists and has the type it claims to have,
+/// then writes it and prints its hash.
+///
+/// # Example
+/// ```bash
+/// $ git mktag <<EOF
+/// object 598d5c24fcb38f2f5d8043888eea02f0a2b9f72f
+/// type commit
+/// tag v1.0
+/// tagger A U Thor <author@example.com> 1700000000 -0700
+///
+/// The first release.
+/// EOF
+/// 8765289e2e8fa02e6b079561a869eb0ff684b922
+/// ```
+#[derive(Args, Debug)]
+pub struct Mktag {}
+
+pub fn cmd_mktag() -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let mut data = Vec::new();
+  io::stdin()
+    .read_to_end(&mut data)
+    .map_err(|e| format!("could not read from standard input ({})", e))?;
+  let (hash, kind) = match tag::check(&data) {
+    Ok(tagged) => tagged,
+    Err(err) => {
+      println!("error: tag input does not pass fsck: {}", err);
+      return Err("tag on stdin did not pass our strict fsck check".to_string());
+    }
+  };
+  let object =
+    read(&repo, &hash, None).map_err(|_| format!("could not read tagged object '{}'", hash))?;
+  if *object.format() != kind {
+    return Err(format!(
+      "object '{}' tagged as '{}', but is a '{}' type",
+      hash,
+      kind,
+      object.format()
+    ));
+  }
+  println!("{}", object::write(&repo, &tag::Tag::new(&data), false)?);
+  Ok(())
+}
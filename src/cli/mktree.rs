@@ -0,0 +1,165 @@
+use std::io::{self, Read};
+
+use bstr::{BString, ByteSlice};
+use clap::Args;
+
+use git_rs_core::{
+  object::{mode::Mode, read, tree::TreeBuilder},
+  repo::Repo,
+};
+
+/// Build a tree object from ls-tree formatted text.
+///
+/// Reads lines of the form `<mode> SP <type> SP <hash> TAB <name>` (as
+/// written by `ls-tree`) from standard input, writes a tree holding those
+/// entries and prints its hash. The entries may come in any order: they are
+/// sorted the way git requires. Each entry must have a valid mode, a name
+/// without slashes that is not used twice, and (unless `--missing` is given,
+/// or it is a submodule) point at an existing object of the type its mode
+/// calls for.
+///
+/// # Example
+/// ```bash
+/// $ git ls-tree HEAD | git mktree
+/// 4177ad2279c3a933b765410ce67f7aad9227b785
+/// ```
+#[derive(Args, Debug)]
+pub struct Mktree {
+  /// Read NUL-terminated lines, with names that are not quoted.
+  #[clap(short = 'z')]
+  pub nul_terminated: bool,
+
+  /// Allow entries to point at objects that do not exist.
+  #[clap(long)]
+  pub missing: bool,
+
+  /// Build more than one tree, each ended by a blank line.
+  #[clap(long)]
+  pub batch: bool,
+}
+
+pub fn cmd_mktree(opts: &Mktree) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let mut text = Vec::new();
+  io::stdin()
+    .read_to_end(&mut text)
+    .map_err(|e| format!("could not read from standard input ({})", e))?;
+  let terminator = if opts.nul_terminated { b'\0' } else { b'\n' };
+  let mut lines: Vec<&[u8]> = text.split(|b| *b == terminator).collect();
+  if lines.last() == Some(&&b""[..]) {
+    lines.pop();
+  }
+
+  let mut entries = Vec::new();
+  for (i, line) in lines.iter().enumerate() {
+    if line.is_empty() {
+      if !opts.batch {
+        return Err("input format error: (blank line only valid in batch mode)".to_string());
+      }
+      println!("{}", write(&repo, &mut entries)?);
+      continue;
+    }
+    entries.push(parse_line(&repo, line, opts)?);
+    if i == lines.len() - 1 {
+      println!("{}", write(&repo, &mut entries)?);
+    }
+  }
+  if lines.is_empty() {
+    println!("{}", write(&repo, &mut entries)?);
+  }
+  Ok(())
+}
+
+/// Reads and checks one entry.
+fn parse_line(repo: &Repo, line: &[u8], opts: &Mktree) -> Result<(BString, Mode, String), String> {
+  let format_error = || format!("input format error: {}", line.as_bstr());
+  let (info, name) = line.split_once_str("\t").ok_or_else(format_error)?;
+  let info = info.to_str().map_err(|_| format_error())?;
+  let mut fields = info.split(' ');
+  let (mode, kind, hash) = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+    (Some(mode), Some(kind), Some(hash), None) => (mode, kind, hash),
+    _ => return Err(format_error()),
+  };
+  let mode = Mode::from_octal(mode).map_err(|_| format_error())?;
+  if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+    return Err(format_error());
+  }
+  let name = match opts.nul_terminated {
+    true => name.into(),
+    false => unquote(name).ok_or_else(format_error)?,
+  };
+  if name.contains(&b'/') {
+    return Err(format!("path {} contains slash", name));
+  }
+  if !["blob", "tree", "commit", "tag"].contains(&kind) {
+    return Err(format!("invalid object type \"{}\"", kind));
+  }
+
+  let mode_kind = mode.object_type();
+  if kind != mode_kind {
+    return Err(format!(
+      "entry '{}' object type ({}) doesn't match mode type ({})",
+      name, kind, mode_kind
+    ));
+  }
+  // submodule commits are in another repository, so are never looked for
+  if !opts.missing && mode != Mode::Gitlink {
+    let object = read(repo, hash, None)
+      .map_err(|_| format!("entry '{}' object {} is unavailable", name, hash))?;
+    if *object.format() != kind {
+      return Err(format!(
+        "entry '{}' object {} is a {} but specified type was ({})",
+        name,
+        hash,
+        object.format(),
+        kind
+      ));
+    }
+  }
+  Ok((name, mode, hash.to_owned()))
+}
+
+/// Writes the entries read so far as a tree, leaving the list empty.
+fn write(repo: &Repo, entries: &mut Vec<(BString, Mode, String)>) -> Result<String, String> {
+  let mut builder = TreeBuilder::new();
+  for (name, mode, hash) in entries.drain(..) {
+    if builder.insert(name.clone(), mode, &hash)?.is_some() {
+      return Err(format!("duplicate entry '{}'", name));
+    }
+  }
+  builder.write(repo)
+}
+
+/// Undoes the C-style quoting `ls-tree` puts around names with unusual
+/// characters in them. Names without quotes are returned as they are.
+pub(crate) fn unquote(name: &[u8]) -> Option<BString> {
+  let inner = match name.strip_prefix(b"\"") {
+    Some(inner) => inner.strip_suffix(b"\"")?,
+    None => return Some(name.into()),
+  };
+  let mut bytes = Vec::new();
+  let mut chars = inner.iter().copied();
+  while let Some(c) = chars.next() {
+    if c != b'\\' {
+      bytes.push(c);
+      continue;
+    }
+    bytes.push(match chars.next()? {
+      b'a' => 7,
+      b'b' => 8,
+      b't' => b'\t',
+      b'n' => b'\n',
+      b'v' => 11,
+      b'f' => 12,
+      b'r' => b'\r',
+      c @ (b'"' | b'\\') => c,
+      c @ b'0'..=b'3' => {
+        let digits = [c, chars.next()?, chars.next()?];
+        let digits = std::str::from_utf8(&digits).ok()?;
+        u8::from_str_radix(digits, 8).ok()?
+      }
+      _ => return None,
+    });
+  }
+  Some(bytes.into())
+}
@@ -1,34 +1,136 @@
 pub(crate) mod add;
+pub(crate) mod am;
+pub(crate) mod branch;
 pub(crate) mod cat_file;
+pub(crate) mod check_mailmap;
 pub(crate) mod checkout;
+pub(crate) mod cherry;
+pub(crate) mod clean;
 pub(crate) mod commit;
+pub(crate) mod commit_tree;
+pub(crate) mod completions;
+pub(crate) mod count_objects;
+pub(crate) mod daemon;
+pub(crate) mod diff_files;
+pub(crate) mod diff_index;
+pub(crate) mod diff_tree;
+pub(crate) mod fast_export;
+pub(crate) mod fetch;
+pub(crate) mod for_each_ref;
+pub(crate) mod format_patch;
+pub(crate) mod fsck;
 pub(crate) mod hash_object;
 pub(crate) mod init;
+pub(crate) mod interpret_trailers;
 pub(crate) mod log;
+pub(crate) mod ls_files;
+pub(crate) mod maintenance;
 pub(crate) mod merge;
+pub(crate) mod merge_file;
+pub(crate) mod merge_tree;
+pub(crate) mod mktag;
+pub(crate) mod mktree;
+pub(crate) mod mv;
+pub(crate) mod name_rev;
+pub(crate) mod pack_refs;
+pub(crate) mod patch_id;
+pub(crate) mod prune_packed;
+pub(crate) mod push;
+pub(crate) mod range_diff;
+pub(crate) mod read_tree;
 pub(crate) mod rebase;
+pub(crate) mod remote;
+pub(crate) mod repack;
+pub(crate) mod replace;
+pub(crate) mod rerere;
+pub(crate) mod reset;
+pub(crate) mod restore;
+pub(crate) mod rev_list;
 pub(crate) mod rev_parse;
 pub(crate) mod rm;
+#[cfg(feature = "serve")]
+pub(crate) mod serve;
+pub(crate) mod show;
 pub(crate) mod show_ref;
 pub(crate) mod show_tree;
+pub(crate) mod sparse_checkout;
+pub(crate) mod status;
+pub(crate) mod switch;
+pub(crate) mod symbolic_ref;
 pub(crate) mod tag;
+pub(crate) mod update_index;
+pub(crate) mod update_ref;
+pub(crate) mod var;
+pub(crate) mod write_tree;
 
 use add::Add;
+use am::Am;
+use branch::Branch;
 use cat_file::CatFile;
+use check_mailmap::CheckMailmap;
 use checkout::Checkout;
+use cherry::Cherry;
 use clap::{Parser, Subcommand};
+use clean::Clean;
 use commit::Commit;
+use commit_tree::CommitTree;
+use completions::Completions;
+use count_objects::CountObjects;
+use daemon::Daemon;
+use diff_files::DiffFiles;
+use diff_index::DiffIndex;
+use diff_tree::DiffTree;
+use fast_export::FastExport;
+use fetch::Fetch;
+use for_each_ref::ForEachRef;
+use format_patch::FormatPatch;
+use fsck::Fsck;
 use hash_object::HashObject;
 use init::Init;
+use interpret_trailers::InterpretTrailers;
 use log::Log;
+use ls_files::LsFiles;
+use maintenance::Maintenance;
 use merge::Merge;
+use merge_file::MergeFile;
+use merge_tree::MergeTree;
+use mktag::Mktag;
+use mktree::Mktree;
+use mv::Mv;
+use name_rev::NameRev;
+use pack_refs::PackRefs;
+use patch_id::PatchId;
+use prune_packed::PrunePacked;
+use push::Push;
+use range_diff::RangeDiff;
+use read_tree::ReadTree;
 use rebase::Rebase;
+use remote::Remote;
+use repack::Repack;
+use replace::Replace;
+use rerere::Rerere;
+use reset::Reset;
+use restore::Restore;
+use rev_list::RevList;
 use rev_parse::RevParse;
 use rm::Rm;
+#[cfg(feature = "serve")]
+use serve::Serve;
+use show::Show;
+use show_ref::ShowRef;
 use show_tree::ShowTree;
+use sparse_checkout::SparseCheckout;
+use status::Status;
+use switch::Switch;
+use symbolic_ref::SymbolicRef;
 use tag::Tag;
+use update_index::UpdateIndex;
+use update_ref::UpdateRef;
+use var::Var;
+use write_tree::WriteTree;
 
-use self::show_ref::ShowRef;
+use git_rs_core::repo::add_config_parameter;
+use std::{env, path::PathBuf};
 
 /// the rusty content tracker
 ///
@@ -39,51 +141,270 @@ use self::show_ref::ShowRef;
 #[derive(Debug, Parser)]
 #[clap(author, version, about, long_about = None, propagate_version = true)]
 pub struct Arguments {
+  /// Run as if started in this directory. When given more than once, each
+  /// path is taken relative to the one before it.
+  #[clap(short = 'C', multiple_occurrences = true, value_name = "PATH")]
+  pub directory: Vec<PathBuf>,
+
+  /// Use this git directory rather than looking for one.
+  #[clap(long, value_name = "PATH")]
+  pub git_dir: Option<PathBuf>,
+
+  /// Use this working tree rather than the one the git directory belongs to.
+  #[clap(long, value_name = "PATH")]
+  pub work_tree: Option<PathBuf>,
+
+  /// Set a config value for this command only, overriding the config file.
+  #[clap(short = 'c', multiple_occurrences = true, value_name = "NAME=VALUE")]
+  pub config: Vec<String>,
+
+  /// Read objects as they are, rather than as `refs/replace` replaces them.
+  #[clap(long)]
+  pub no_replace_objects: bool,
+
+  /// Send the output to the pager, if it is going to a terminal.
+  #[clap(short = 'p', long)]
+  pub paginate: bool,
+
+  /// Don't send the output to the pager.
+  #[clap(short = 'P', long, overrides_with = "paginate")]
+  pub no_pager: bool,
+
   #[clap(subcommand)]
   pub command: Command,
 }
 
+impl Arguments {
+  /// Applies the options that come before the command, which change where
+  /// the repository is looked for and how it is configured.
+  ///
+  /// These are passed on through the environment (`GIT_DIR`, `GIT_WORK_TREE`,
+  /// `GIT_CONFIG_PARAMETERS` and `GIT_NO_REPLACE_OBJECTS`), the way git
+  /// passes them on to the commands it runs.
+  pub fn apply(&self) -> Result<(), String> {
+    for dir in self
+      .directory
+      .iter()
+      .filter(|dir| !dir.as_os_str().is_empty())
+    {
+      env::set_current_dir(dir)
+        .map_err(|e| format!("cannot change to '{}': {}", dir.display(), e))?;
+    }
+    if let Some(git_dir) = &self.git_dir {
+      env::set_var("GIT_DIR", git_dir);
+    }
+    if let Some(work_tree) = &self.work_tree {
+      env::set_var("GIT_WORK_TREE", work_tree);
+    }
+    if self.no_replace_objects {
+      env::set_var("GIT_NO_REPLACE_OBJECTS", "1");
+    }
+    for setting in &self.config {
+      add_config_parameter(setting)?;
+    }
+    Ok(())
+  }
+
+  /// Whether to send the output to the pager, as `-p` and `--no-pager` say.
+  /// The last one given wins.
+  pub fn paginate(&self) -> Option<bool> {
+    match (self.paginate, self.no_pager) {
+      (_, true) => Some(false),
+      (true, false) => Some(true),
+      (false, false) => None,
+    }
+  }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Command {
   /// Add file contents to the index.
   Add(Add),
 
+  /// Apply a series of patches from a mailbox.
+  Am(Am),
+
+  /// List or create branches.
+  Branch(Branch),
+
   /// Provide content or type and size information for repository objects.
   CatFile(CatFile),
 
+  /// Show canonical names and email addresses of contacts.
+  CheckMailmap(CheckMailmap),
+
   /// Switch branches or restore working tree files.
   Checkout(Checkout),
 
+  /// Find commits yet to be applied to upstream.
+  Cherry(Cherry),
+
+  /// Remove untracked files from the working tree.
+  Clean(Clean),
+
   /// Record changes to the repository.
   Commit(Commit),
 
+  /// Create a new commit object.
+  CommitTree(CommitTree),
+
+  /// Print a script that completes commands in a shell.
+  Completions(Completions),
+
+  /// Count unpacked objects and their disk consumption.
+  CountObjects(CountObjects),
+
+  /// A really simple server for git repositories.
+  Daemon(Daemon),
+
+  /// Compares files in the working tree and the index.
+  DiffFiles(DiffFiles),
+
+  /// Compares a tree to the working tree or the index.
+  DiffIndex(DiffIndex),
+
+  /// Compares the content and mode of blobs found via two tree objects.
+  DiffTree(DiffTree),
+
+  /// Export history as a fast-import stream.
+  FastExport(FastExport),
+
+  /// Download objects and refs from another repository.
+  Fetch(Fetch),
+
+  /// Output information on each ref.
+  ForEachRef(ForEachRef),
+
+  /// Prepare patches for e-mail submission.
+  FormatPatch(FormatPatch),
+
+  /// Verifies the connectivity of the objects in the database.
+  Fsck(Fsck),
+
   /// Compute object ID and optionally creates a blob from a file.
   HashObject(HashObject),
 
   /// Create an empty Git repository or reinitialize an existing one.
   Init(Init),
 
+  /// Add or parse structured information in commit messages.
+  InterpretTrailers(InterpretTrailers),
+
   /// Show commit logs.
   Log(Log),
 
+  /// Show information about files in the index.
+  LsFiles(LsFiles),
+
   /// List the contents of a tree object.
   LsTree(ShowTree),
 
+  /// Run tasks to optimize Git repository data.
+  Maintenance(Maintenance),
+
   /// Join two or more development histories together.
   Merge(Merge),
 
+  /// Run a three-way file merge.
+  MergeFile(MergeFile),
+
+  /// Perform a merge without touching the index or working tree.
+  MergeTree(MergeTree),
+
+  /// Create a tag object with extra validation.
+  Mktag(Mktag),
+
+  /// Build a tree object from ls-tree formatted text.
+  Mktree(Mktree),
+
+  /// Move or rename a file, a directory, or a symlink.
+  Mv(Mv),
+
+  /// Find symbolic names for given revs.
+  NameRev(NameRev),
+
+  /// Pack heads and tags for efficient repository access.
+  PackRefs(PackRefs),
+
+  /// Compute unique IDs for patches.
+  PatchId(PatchId),
+
+  /// Remove extra objects that are already in pack files.
+  PrunePacked(PrunePacked),
+
+  /// Update remote refs along with associated objects.
+  Push(Push),
+
+  /// Compare two versions of a series of commits.
+  RangeDiff(RangeDiff),
+
+  /// Reads tree information into the index.
+  ReadTree(ReadTree),
+
   /// Reapply commits on top of another base tip.
   Rebase(Rebase),
 
+  /// Manage the set of repositories whose branches are tracked.
+  Remote(Remote),
+
+  /// Pack unpacked objects in a repository.
+  Repack(Repack),
+
+  /// Create, list or delete refs to replace objects.
+  Replace(Replace),
+
+  /// Reuse recorded resolutions of conflicted merges.
+  Rerere(Rerere),
+
+  /// Reset current HEAD to the specified state.
+  Reset(Reset),
+
+  /// Restore working tree files.
+  Restore(Restore),
+
+  /// Lists commit objects in reverse chronological order.
+  RevList(RevList),
+
   /// Pick out and massage parameters.
   RevParse(RevParse),
 
   /// Remove files from the working tree and from the index.
   Rm(Rm),
 
+  /// Browse the repository in a web browser.
+  #[cfg(feature = "serve")]
+  Serve(Serve),
+
+  /// Show various types of objects.
+  Show(Show),
+
   /// List references in a local repository.
   ShowRef(ShowRef),
 
+  /// Reduce your working tree to a subset of tracked files.
+  SparseCheckout(SparseCheckout),
+
+  /// Show the working tree status.
+  Status(Status),
+
+  /// Switch branches.
+  Switch(Switch),
+
+  /// Read, modify and delete symbolic refs.
+  SymbolicRef(SymbolicRef),
+
   /// Create, list, delete or verify a tag object signed with GPG.
   Tag(Tag),
+
+  /// Register file contents in the working tree to the index.
+  UpdateIndex(UpdateIndex),
+
+  /// Update the object name stored in a ref safely.
+  UpdateRef(UpdateRef),
+
+  /// Show a Git logical variable.
+  Var(Var),
+
+  /// Create a tree object from the current index.
+  WriteTree(WriteTree),
 }
@@ -0,0 +1,118 @@
+use bstr::{BString, ByteSlice};
+use clap::Args;
+use std::fs;
+
+use git_rs_core::{
+  index::{self, Index},
+  repo::Repo,
+};
+
+/// Move or rename a file, a directory, or a symlink.
+///
+/// Renames the files in the working tree and updates their index entries in
+/// one step. When the last argument is an existing directory, every source is
+/// moved into it; otherwise there must be exactly one source, which is renamed
+/// to the destination.
+///
+/// # Example
+/// ```bash
+/// $ git mv old.txt new.txt
+/// $ git mv a.txt b.txt src/
+/// ```
+#[derive(Args, Debug)]
+pub struct Mv {
+  /// The files or directories to move, followed by the destination.
+  #[clap(required = true, min_values = 2)]
+  pub paths: Vec<String>,
+
+  /// Move even if the destination exists.
+  #[clap(short, long)]
+  pub force: bool,
+
+  /// Only show what would be moved.
+  #[clap(short = 'n', long)]
+  pub dry_run: bool,
+
+  /// Report the names of files as they are moved.
+  #[clap(short, long)]
+  pub verbose: bool,
+}
+
+pub fn cmd_mv(opts: &Mv) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let (sources, destination) = opts.paths.split_at(opts.paths.len() - 1);
+  let destination = repo.worktree_path(&destination[0])?;
+  let into_dir = repo.work_tree_path(&destination).is_dir();
+  if sources.len() > 1 && !into_dir {
+    return Err(format!("destination '{}' is not a directory", destination));
+  }
+  let mut index = Index::read(&repo)?;
+
+  // Work out every move up front so nothing happens if one of them is bad.
+  let mut moves: Vec<(BString, BString)> = Vec::new();
+  for arg in sources {
+    let source = repo.worktree_path(arg)?;
+    let target = match (into_dir, source.rsplit_str("/").next()) {
+      (true, Some(name)) if destination.is_empty() => name.into(),
+      (true, Some(name)) => BString::from([destination.as_slice(), b"/", name].concat()),
+      _ => destination.clone(),
+    };
+    let prefix = [source.as_slice(), b"/"].concat();
+    let bad = |reason: &str| format!("{}, source={}, destination={}", reason, source, target);
+
+    let tracked =
+      index.get(&source).is_some() || index.entries().iter().any(|e| e.path.starts_with(&prefix));
+    if fs::symlink_metadata(repo.work_tree_path(&source)).is_err() {
+      return Err(bad("bad source"));
+    } else if !tracked {
+      return Err(bad("not under version control"));
+    } else if source == target || target.starts_with(&prefix) {
+      return Err(bad("can not move directory into itself"));
+    } else if repo.work_tree_path(&target).exists() {
+      if !opts.force || repo.work_tree_path(&target).is_dir() {
+        return Err(bad("destination exists"));
+      }
+    } else if moves.iter().any(|(_, other)| *other == target) {
+      return Err(bad("multiple sources for the same target"));
+    }
+    moves.push((source, target));
+  }
+
+  for (source, target) in moves {
+    if opts.verbose || opts.dry_run {
+      println!("Renaming {} to {}", source, target);
+    }
+    if opts.dry_run {
+      continue;
+    }
+    // Note which entries are clean beforehand, since only those can safely
+    // have their stat data refreshed after the rename changes it.
+    let prefix = [source.as_slice(), b"/"].concat();
+    let mut entries = Vec::new();
+    for entry in index.entries() {
+      if entry.path == source || entry.path.starts_with(&prefix) {
+        entries.push((entry.clone(), !index::is_modified(&repo, entry)?));
+      }
+    }
+
+    let (from, to) = (repo.work_tree_path(&source), repo.work_tree_path(&target));
+    fs::rename(&from, &to).map_err(|e| format!("renaming '{}' failed: {}", source, e))?;
+
+    for (mut entry, clean) in entries {
+      index.remove(&entry.path);
+      entry.path = [target.as_slice(), &entry.path[source.len()..]]
+        .concat()
+        .into();
+      if clean {
+        if let Ok(metadata) = fs::symlink_metadata(repo.work_tree_path(&entry.path)) {
+          entry.refresh(&metadata);
+        }
+      }
+      index.add(entry);
+    }
+  }
+  if !opts.dry_run {
+    index.write(&repo)?;
+  }
+  Ok(())
+}
@@ -0,0 +1,145 @@
+use clap::Args;
+
+use git_rs_core::{
+  ignore::wildmatch,
+  object::{self, commit::Commit, find_object, refs, serializable::Unbox},
+  repo::Repo,
+  rev::{name::Names, walk::CUTOFF_SLOP},
+};
+
+/// Find symbolic names for given revs.
+///
+/// Names each commit after a ref it can be reached from, the way a person
+/// would find it: `tags/v1.2~3` is three first parents back from the commit
+/// tagged `v1.2`, and `master~2^2` is the second parent of `master~2`. Names
+/// from tags are preferred, then those from the nearest ref. A commit no ref
+/// reaches is `undefined`.
+///
+/// # Example
+/// ```bash
+/// $ git name-rev 33663e6
+/// 33663e6 tags/v1.2~3
+/// $ git name-rev --tags --name-only HEAD~1
+/// v1.2~1
+/// ```
+#[derive(Args, Debug)]
+pub struct NameRev {
+  /// The commits to name.
+  pub commits: Vec<String>,
+
+  /// Only name commits after tags.
+  #[clap(long)]
+  pub tags: bool,
+
+  /// Only name commits after the refs that match one of these globs, on
+  /// their whole name or a part of it after a `/`.
+  #[clap(long = "refs", value_name = "PATTERN", multiple_occurrences = true)]
+  pub patterns: Vec<String>,
+
+  /// Don't name commits after the refs that match one of these globs.
+  #[clap(long, value_name = "PATTERN", multiple_occurrences = true)]
+  pub exclude: Vec<String>,
+
+  /// Name every commit that can be reached from a ref.
+  #[clap(long, conflicts_with = "commits")]
+  pub all: bool,
+
+  /// Print only the names, not the commits they name.
+  #[clap(long)]
+  pub name_only: bool,
+
+  /// Fail, rather than print `undefined`, when a commit has no name.
+  #[clap(long)]
+  pub no_undefined: bool,
+
+  /// Show the abbreviated hash of a commit that has no name.
+  #[clap(long)]
+  pub always: bool,
+}
+
+pub fn cmd_name_rev(opts: &NameRev) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let mut commits = Vec::new();
+  for spec in &opts.commits {
+    let hash = find_object(&repo, spec, Some("commit"), true)
+      .map_err(|_| format!("not a valid object name: '{}'", spec))?;
+    commits.push((spec, hash));
+  }
+  if commits.is_empty() && !opts.all {
+    return Err("no commits given to name".to_string());
+  }
+
+  // only commits a day older than the oldest to be named are walked into
+  let mut cutoff = None;
+  for (_, hash) in &commits {
+    let object = object::read(&repo, hash, Some("commit"))?;
+    let time = object.unbox::<Commit>()?.commit_time() - CUTOFF_SLOP;
+    cutoff = Some(cutoff.map_or(time, |cutoff: i64| cutoff.min(time)));
+  }
+  let mut names = Names::new(&repo, cutoff.filter(|_| !opts.all));
+  for (refname, hash) in refs::collect(&repo, None) {
+    if opts.tags && !refname.starts_with("refs/tags/") {
+      continue;
+    }
+    if !opts.patterns.is_empty() && !opts.patterns.iter().any(|p| matches(p, &refname)) {
+      continue;
+    }
+    if opts.exclude.iter().any(|p| matches(p, &refname)) {
+      continue;
+    }
+    let tip = tip_name(&repo, &refname, opts.tags && opts.name_only);
+    names.add(&tip, &hash, refname.starts_with("refs/tags/"))?;
+  }
+
+  if opts.all {
+    for (hash, name) in names.all() {
+      match opts.name_only {
+        true => println!("{}", name),
+        false => println!("{} {}", hash, name),
+      }
+    }
+    return Ok(());
+  }
+  for (spec, hash) in commits {
+    let name = match names.name(&hash) {
+      Some(name) => name,
+      None if opts.always => hash[..7].to_string(),
+      None if opts.no_undefined => return Err(format!("cannot describe '{}'", hash)),
+      None => "undefined".to_string(),
+    };
+    match opts.name_only {
+      true => println!("{}", name),
+      false => println!("{} {}", spec, name),
+    }
+  }
+  Ok(())
+}
+
+/// The name a ref is shown by: as short as it can be when only tags name
+/// commits and only the names are shown, or else without its `refs/heads/`
+/// or `refs/`.
+fn tip_name(repo: &Repo, refname: &str, abbreviate: bool) -> String {
+  if abbreviate {
+    return refs::shorten(repo, refname);
+  }
+  refname
+    .strip_prefix("refs/heads/")
+    .or_else(|| refname.strip_prefix("refs/"))
+    .unwrap_or(refname)
+    .to_string()
+}
+
+/// Whether a glob matches a ref, on its whole name or on what comes after
+/// one of its `/`s.
+fn matches(pattern: &str, refname: &str) -> bool {
+  let mut rest = refname;
+  loop {
+    if wildmatch(pattern.as_bytes(), rest.as_bytes()) {
+      return true;
+    }
+    match rest.split_once('/') {
+      Some((_, tail)) => rest = tail,
+      None => return false,
+    }
+  }
+}
@@ -0,0 +1,41 @@
+use clap::Args;
+
+use git_rs_core::{object::refs, repo::Repo};
+
+/// Pack heads and tags for efficient repository access.
+///
+/// Moves the refs that have files of their own into `packed-refs`, one file
+/// for all of them, which is far quicker to read than thousands of files
+/// once there are thousands of tags. Annotated tags are packed with what
+/// they peel to, so that is known without reading them. A ref that is
+/// changed again gets a file of its own again, which wins over the packed
+/// one.
+///
+/// Only the tags, and the refs that were packed before, are packed unless
+/// `--all` is given, as branches move too often to be worth it. The files
+/// of the refs packed are deleted, unless `--no-prune` is given. Refs kept
+/// in a reftable are compacted into one table instead.
+///
+/// # Example
+/// ```bash
+/// $ git pack-refs --all --prune
+/// ```
+#[derive(Args, Debug)]
+pub struct PackRefs {
+  /// Pack every ref, branches included.
+  #[clap(long)]
+  pub all: bool,
+
+  /// Delete the files of the refs packed (the default).
+  #[clap(long, conflicts_with = "no-prune")]
+  pub prune: bool,
+
+  /// Keep the files of the refs packed.
+  #[clap(long)]
+  pub no_prune: bool,
+}
+
+pub fn cmd_pack_refs(opts: &PackRefs) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  refs::pack(&repo, opts.all, !opts.no_prune)
+}
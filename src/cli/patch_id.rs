@@ -0,0 +1,45 @@
+use std::io::{self, Read};
+
+use clap::Args;
+
+use git_rs_core::diff::patch_id::parse_patch_ids;
+
+/// Compute unique IDs for patches.
+///
+/// Reads patches (such as the output of `git log -p` or `git format-patch`)
+/// from standard input and prints a patch id for each, followed by the commit
+/// it was found under. The patch id is a hash of the patch with whitespace and
+/// line numbers left out, so the same change made on top of a different base
+/// has the same id.
+///
+/// # Example
+/// ```bash
+/// $ git format-patch --stdout -1 | git patch-id --stable
+/// 2a4c6e0f2b0e7c3bd1f3b9e63c1d8d3a9c5e7f10 9c8d7e6a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0e
+/// ```
+#[derive(Args, Debug)]
+pub struct PatchId {
+  /// Hash each file separately and add the hashes up, so that the id does not
+  /// depend on the order of the files in the patch.
+  #[clap(long, conflicts_with = "unstable")]
+  pub stable: bool,
+
+  /// Hash the patch as a whole (the default).
+  #[clap(long)]
+  pub unstable: bool,
+
+  /// Keep whitespace when hashing (implies `--stable`).
+  #[clap(long)]
+  pub verbatim: bool,
+}
+
+pub fn cmd_patch_id(opts: &PatchId) -> Result<(), String> {
+  let mut text = String::new();
+  io::stdin()
+    .read_to_string(&mut text)
+    .map_err(|e| format!("could not read from standard input ({})", e))?;
+  for (id, commit) in parse_patch_ids(&text, opts.stable, opts.verbatim) {
+    println!("{} {}", id, commit);
+  }
+  Ok(())
+}
@@ -0,0 +1,42 @@
+use clap::Args;
+
+use git_rs_core::repo::Repo;
+
+/// Remove extra objects that are already in pack files.
+///
+/// Deletes the loose objects that are in a pack as well, which take up
+/// space for nothing, and the directories of them that are left empty.
+/// `maintenance run` does this as part of its `loose-objects` task.
+///
+/// # Example
+/// ```bash
+/// $ git prune-packed -n
+/// rm -f .git/objects/3b/18e512dba79e4c8300dd08aeb37f8e728b8dad
+/// ```
+#[derive(Args, Debug)]
+pub struct PrunePacked {
+  /// Only say what would be deleted.
+  #[clap(short = 'n', long)]
+  pub dry_run: bool,
+
+  /// Say nothing.
+  #[clap(short, long)]
+  pub quiet: bool,
+}
+
+pub fn cmd_prune_packed(opts: &PrunePacked) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let pruned = repo.objects.prune_packed(opts.dry_run)?;
+  if opts.dry_run {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    for path in &pruned {
+      let path = path.strip_prefix(&cwd).unwrap_or(path);
+      println!("rm -f {}", path.display());
+    }
+    return Ok(());
+  }
+  if !opts.quiet && !pruned.is_empty() {
+    eprintln!("Removed {} duplicate objects", pruned.len());
+  }
+  Ok(())
+}
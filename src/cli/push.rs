@@ -0,0 +1,222 @@
+use std::process;
+
+use clap::Args;
+
+use git_rs_core::{
+  cancel,
+  progress::Meter,
+  remote::{
+    fetch::display_url,
+    push::{self, RefUpdate, Status},
+    refspec::RefSpec,
+    Remote,
+  },
+  repo::Repo,
+  transport::send_pack::Signed,
+};
+
+/// Update remote refs along with associated objects.
+///
+/// Pushes the refs the refspecs name to a remote (or a URL), with the
+/// objects they reach that the remote doesn't have, and updates the remote
+/// refs the refspecs map them to. Without refspecs, the remote's
+/// `remote.<name>.push` refspecs are used, and without those the current
+/// branch is pushed to the branch of the same name. A refspec with no
+/// source (`:<ref>`) deletes the remote ref. A remote ref is only moved to
+/// a descendant of where it was, unless its refspec starts with `+` or
+/// `--force` is given.
+///
+/// Only `git://` remotes can be pushed to, when `daemon` serves them with
+/// `--enable=receive-pack`.
+///
+/// Push options (`-o`, or `push.pushOption`) are handed to the hooks of the
+/// remote. With `--signed` (or `push.gpgSign`), the push sends a
+/// certificate of what it updates, signed with `user.signingKey` (or the
+/// committer's key), which the remote checks and keeps; `--signed=if-asked`
+/// only signs if the remote takes signed pushes.
+///
+/// An atomic push (`--atomic`, or `push.atomic`) updates every remote ref
+/// or none of them: if one is rejected, here or by the remote, none are
+/// updated.
+///
+/// # Example
+/// ```bash
+/// $ git push -o ci.skip origin main
+/// To git://example.com/project.git
+///    3e2ab91..7c1d0f4  main -> main
+/// $ git push --signed origin main:refs/heads/release :refs/heads/old
+/// $ git push --atomic origin main next
+/// ```
+#[derive(Args, Debug)]
+pub struct Push {
+  /// The remote to push to, or a URL.
+  #[clap(default_value = "origin")]
+  pub repository: String,
+
+  /// Which refs to push, and where to.
+  pub refspecs: Vec<String>,
+
+  /// Update remote refs even when that loses commits.
+  #[clap(short, long)]
+  pub force: bool,
+
+  /// Hand a string to the hooks of the remote (may be given more than once).
+  #[clap(
+    short = 'o',
+    long = "push-option",
+    value_name = "OPTION",
+    multiple_occurrences = true,
+    number_of_values = 1
+  )]
+  pub push_options: Vec<String>,
+
+  /// Sign the push: `true`, `false` or `if-asked`.
+  #[clap(
+    long,
+    value_name = "true|false|if-asked",
+    min_values = 0,
+    require_equals = true,
+    default_missing_value = "true",
+    conflicts_with = "no-signed"
+  )]
+  pub signed: Option<String>,
+
+  /// Don't sign the push, whatever the config says.
+  #[clap(long)]
+  pub no_signed: bool,
+
+  /// Update every remote ref or none of them.
+  #[clap(long, conflicts_with = "no-atomic")]
+  pub atomic: bool,
+
+  /// Update the remote refs that can be, whatever the config says.
+  #[clap(long)]
+  pub no_atomic: bool,
+
+  /// Say nothing, unless something goes wrong.
+  #[clap(short, long)]
+  pub quiet: bool,
+
+  /// Report refs that were already up to date too.
+  #[clap(short, long)]
+  pub verbose: bool,
+
+  /// Show progress, even if standard error is not a terminal.
+  #[clap(long, conflicts_with = "no-progress")]
+  pub progress: bool,
+
+  /// Never show progress.
+  #[clap(long)]
+  pub no_progress: bool,
+}
+
+pub fn cmd_push(opts: &Push) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let remote = Remote::get(&repo, &opts.repository)?;
+  let refspecs = opts
+    .refspecs
+    .iter()
+    .map(|spec| RefSpec::parse(spec))
+    .collect::<Result<Vec<_>, _>>()?;
+  let signed = match (&opts.signed, opts.no_signed) {
+    (_, true) => Some(Signed::No),
+    (Some(value), _) => {
+      Some(Signed::parse(value).ok_or_else(|| format!("bad value for --signed: {}", value))?)
+    }
+    (None, _) => None,
+  };
+  let show_progress = match (opts.quiet, opts.progress, opts.no_progress) {
+    (_, true, _) => Some(true),
+    (true, _, _) | (_, _, true) => Some(false),
+    _ => None,
+  };
+  let options = push::Options {
+    force: opts.force,
+    push_options: opts.push_options.clone(),
+    signed,
+    atomic: match (opts.atomic, opts.no_atomic) {
+      (true, _) => Some(true),
+      (_, true) => Some(false),
+      _ => None,
+    },
+    cancel: cancel::on_interrupt(),
+  };
+  let updates = push::push(
+    &repo,
+    &remote,
+    &refspecs,
+    &options,
+    Meter::boxed(show_progress).as_mut(),
+  )?;
+
+  let failed = updates.iter().any(|update| {
+    matches!(
+      update.status,
+      Status::Rejected(_) | Status::RemoteRejected(_)
+    )
+  });
+  let shown: Vec<&RefUpdate> = updates
+    .iter()
+    .filter(|update| opts.verbose || update.status != Status::UpToDate)
+    .collect();
+  if !shown.is_empty() && (!opts.quiet || failed) {
+    eprintln!("To {}", remote.url);
+    for update in &shown {
+      eprintln!("{}", report(update));
+    }
+  } else if !opts.quiet {
+    eprintln!("Everything up-to-date");
+  }
+  if failed {
+    eprintln!(
+      "error: failed to push some refs to '{}'",
+      display_url(&remote.url)
+    );
+    process::exit(1);
+  }
+  Ok(())
+}
+
+/// A line of the report of what was pushed, eg.
+/// ` * [new branch]      main -> main`.
+fn report(update: &RefUpdate) -> String {
+  let kind = match update.remote.split('/').nth(1) {
+    Some("heads") => "branch",
+    Some("tags") => "tag",
+    _ => "ref",
+  };
+  let range = |dots: &str| match &update.old {
+    Some(old) => format!("{}{}{}", &old[..7], dots, &update.new[..7]),
+    None => String::new(),
+  };
+  let (code, summary, note) = match &update.status {
+    Status::New => ('*', format!("[new {}]", kind), String::new()),
+    Status::UpToDate => ('=', "[up to date]".to_string(), String::new()),
+    Status::FastForward => (' ', range(".."), String::new()),
+    Status::Forced => ('+', range("..."), " (forced update)".to_string()),
+    Status::Deleted => ('-', "[deleted]".to_string(), String::new()),
+    Status::Rejected(why) => ('!', "[rejected]".to_string(), format!(" ({})", why)),
+    Status::RemoteRejected(why) => ('!', "[remote rejected]".to_string(), format!(" ({})", why)),
+  };
+  let remote = pretty(&update.remote);
+  match &update.local {
+    Some(local) => format!(
+      " {} {:<17} {} -> {}{}",
+      code,
+      summary,
+      pretty(local),
+      remote,
+      note
+    ),
+    None => format!(" {} {:<17} {}{}", code, summary, remote, note),
+  }
+}
+
+/// A ref's name without the prefix that says what kind of ref it is, eg.
+/// `main` for `refs/heads/main`.
+fn pretty(name: &str) -> &str {
+  ["refs/heads/", "refs/tags/", "refs/remotes/"]
+    .iter()
+    .find_map(|prefix| name.strip_prefix(prefix))
+    .unwrap_or(name)
+}
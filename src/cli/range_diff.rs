@@ -0,0 +1,204 @@
+use clap::Args;
+
+use git_rs_core::{
+  color::Colors,
+  range_diff::{self, Patch},
+  repo::Repo,
+  rev::walk::RevWalk,
+};
+
+/// Compare two versions of a series of commits.
+///
+/// Takes two ranges of commits (eg. a topic branch before and after a
+/// rebase), pairs up the commits that correspond to each other and shows how
+/// each pair differs. Commits with the same diff are paired first, then the
+/// rest are paired to keep the differences between them as small as possible.
+/// Each line of output names a commit from each range (or `-` when one has no
+/// counterpart) and how they compare: `=` for the same, `!` for different
+/// (followed by a diff of the two), `<` for only in the first range and `>`
+/// for only in the second.
+///
+/// The ranges can be given as `<range1> <range2>`, as `<rev1>...<rev2>`
+/// (meaning `<rev2>..<rev1> <rev1>..<rev2>`), or as `<base> <rev1> <rev2>`
+/// (meaning `<base>..<rev1> <base>..<rev2>`).
+///
+/// # Example
+/// ```bash
+/// $ git range-diff main topic@{1} topic
+/// 1:  3f2a1b0 = 1:  9c8d7e6 Add the parser
+/// 2:  5e4d3c2 ! 2:  1a2b3c4 Use the parser
+///     @@ Commit message
+///     ...
+/// ```
+#[derive(Args, Debug)]
+pub struct RangeDiff {
+  /// The ranges to compare.
+  #[clap(required = true, max_values = 3)]
+  pub ranges: Vec<String>,
+
+  /// How much bigger than its own diff the diff between two commits may be
+  /// for them to still be paired up, in percent.
+  #[clap(long, default_value = "60", value_name = "FACTOR")]
+  pub creation_factor: i64,
+
+  /// Only show the commits of the first range.
+  #[clap(long, conflicts_with = "right-only")]
+  pub left_only: bool,
+
+  /// Only show the commits of the second range.
+  #[clap(long)]
+  pub right_only: bool,
+
+  /// Only list the pairs, without the diffs between them.
+  #[clap(short = 's', long)]
+  pub no_patch: bool,
+
+  /// Accepted for compatibility: the diffs between commits are always
+  /// colored as plain diffs.
+  #[clap(long)]
+  pub no_dual_color: bool,
+
+  /// Color the output: `always`, `never` or `auto` (when it goes to a
+  /// terminal). Without a value, `always`.
+  #[clap(
+    long,
+    value_name = "WHEN",
+    min_values = 0,
+    require_equals = true,
+    default_missing_value = "always",
+    possible_values = &["always", "never", "auto"]
+  )]
+  pub color: Option<String>,
+
+  /// Don't color the output.
+  #[clap(long, overrides_with = "color")]
+  pub no_color: bool,
+}
+
+pub fn cmd_range_diff(opts: &RangeDiff) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let (one, two) = match opts.ranges.as_slice() {
+    [base, one, two] => (format!("{}..{}", base, one), format!("{}..{}", base, two)),
+    [one, two] if !one.contains("..") || !two.contains("..") => {
+      return Err("need two commit ranges".to_string())
+    }
+    [one, two] => (one.clone(), two.clone()),
+    [range] => match range.split_once("...") {
+      Some((one, two)) => {
+        let (one, two) = (
+          if one.is_empty() { "HEAD" } else { one },
+          if two.is_empty() { "HEAD" } else { two },
+        );
+        (format!("{}..{}", two, one), format!("{}..{}", one, two))
+      }
+      None => return Err("single arg format must be symmetric range".to_string()),
+    },
+    _ => return Err("need two commit ranges".to_string()),
+  };
+  let color = opts.no_color.then_some("never").or(opts.color.as_deref());
+  let colors = Colors::new(&repo, "diff", color)?;
+  let a = read_range(&repo, &one)?;
+  let b = read_range(&repo, &two)?;
+  let a2b = range_diff::correspondences(&a, &b, opts.creation_factor);
+  let mut b2a: Vec<Option<usize>> = vec![None; b.len()];
+  for (i, j) in a2b.iter().enumerate() {
+    if let Some(j) = j {
+      b2a[*j] = Some(i);
+    }
+  }
+
+  // Follow the order of the second range, showing the commits of the first
+  // range that have no counterpart once everything before them was shown.
+  let width = (1 + a.len().max(b.len())).to_string().len();
+  let header = |i: Option<usize>, j: Option<usize>| {
+    let side = |n: Option<usize>, patches: &[Patch]| match n {
+      Some(n) => format!(
+        "{:>width$}:  {}",
+        n + 1,
+        &patches[n].hash[..7],
+        width = width
+      ),
+      None => format!("{:>width$}:  -------", "-", width = width),
+    };
+    let subject = match i {
+      Some(i) => &a[i].subject,
+      None => &b[j.unwrap()].subject,
+    };
+    let line = |status: char| format!("{} {} {} {}", side(i, &a), status, side(j, &b), subject);
+    // a changed pair shows which side is which
+    let line = match (i, j) {
+      (Some(_), None) => colors.paint("diff.old", &line('<')),
+      (None, Some(_)) => colors.paint("diff.new", &line('>')),
+      (Some(i), Some(j)) if a[i].text != b[j].text => [
+        colors.paint("diff.old", &format!("{} ", side(Some(i), &a))),
+        colors.paint("diff.commit", "!"),
+        colors.paint("diff.new", &format!(" {}", side(Some(j), &b))),
+        colors.paint("diff.commit", &format!(" {}", subject)),
+      ]
+      .concat(),
+      _ => colors.paint("diff.commit", &line('=')),
+    };
+    println!("{}", line);
+  };
+  let mut shown = vec![false; a.len()];
+  let (mut i, mut j) = (0, 0);
+  while i < a.len() || j < b.len() {
+    while i < a.len() && shown[i] {
+      i += 1;
+    }
+    if i < a.len() && a2b[i].is_none() {
+      if !opts.right_only {
+        header(Some(i), None);
+      }
+      i += 1;
+      continue;
+    }
+    while j < b.len() && b2a[j].is_none() {
+      if !opts.left_only {
+        header(None, Some(j));
+      }
+      j += 1;
+    }
+    if j < b.len() {
+      let paired = b2a[j].unwrap();
+      header(Some(paired), Some(j));
+      if !opts.no_patch {
+        for line in range_diff::interdiff(&a[paired], &b[j]).lines() {
+          println!("{}", paint_interdiff(&colors, line));
+        }
+      }
+      shown[paired] = true;
+      j += 1;
+    }
+  }
+  Ok(())
+}
+
+/// Colors a line of the diff between two commits, which is indented by four
+/// spaces, the way a line of a diff is colored.
+fn paint_interdiff(colors: &Colors, line: &str) -> String {
+  let (indent, rest) = line.split_at(line.len().min(4));
+  match rest.chars().next() {
+    Some('+') => format!("{}{}", indent, colors.paint("diff.new", rest)),
+    Some('-') => format!("{}{}", indent, colors.paint("diff.old", rest)),
+    Some('@') => match rest.strip_prefix("@@") {
+      Some(section) => format!("{}{}{}", indent, colors.paint("diff.frag", "@@"), section),
+      None => line.to_string(),
+    },
+    _ => line.to_string(),
+  }
+}
+
+/// Renders the non-merge commits of a range, oldest first.
+fn read_range(repo: &Repo, range: &str) -> Result<Vec<Patch>, String> {
+  let mut walk = RevWalk::new(repo);
+  walk.push_spec(range)?;
+  let mut patches = Vec::new();
+  for hash in walk.run()? {
+    if walk.parents(&hash).len() <= 1 {
+      patches.push(Patch::read(repo, &hash)?);
+    }
+  }
+  patches.reverse();
+  Ok(patches)
+}
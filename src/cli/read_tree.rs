@@ -0,0 +1,165 @@
+use bstr::BString;
+use clap::Args;
+use std::fs;
+
+use git_rs_core::{
+  index::{
+    self,
+    unpack::{self, Files},
+    Index, IndexEntry,
+  },
+  object::{find_object, tree},
+  repo::Repo,
+  worktree,
+};
+
+/// Reads tree information into the index.
+///
+/// With one tree, the index is replaced by the tree (or, with `--prefix`, the
+/// tree is added under a subdirectory). With `-m` and two trees the index is
+/// moved from the first tree to the second, keeping local changes, and with
+/// three trees the last two are merged using the first as their common
+/// ancestor, leaving conflicts in the index as stages 1 to 3. `-u` brings the
+/// working tree along too.
+///
+/// # Example
+/// ```bash
+/// $ git read-tree HEAD
+/// $ git read-tree -m -u HEAD topic
+/// $ git read-tree -m $(git merge-base HEAD topic) HEAD topic
+/// $ git read-tree --prefix=vendor/lib/ lib-tree
+/// ```
+#[derive(Args, Debug)]
+pub struct ReadTree {
+  /// The trees to read (one to three of them).
+  #[clap(max_values = 3, required_unless_present = "empty")]
+  pub trees: Vec<String>,
+
+  /// Merge the trees rather than just reading them.
+  #[clap(short, conflicts_with = "reset")]
+  pub m: bool,
+
+  /// Like -m, but throw away unmerged entries and local changes.
+  #[clap(long)]
+  pub reset: bool,
+
+  /// Update the working tree to match the merged index.
+  #[clap(short)]
+  pub u: bool,
+
+  /// Also resolve paths deleted on one side and unchanged on the other.
+  #[clap(long)]
+  pub aggressive: bool,
+
+  /// Read the tree into the index under this directory.
+  #[clap(long, value_name = "PREFIX/", conflicts_with_all = &["m", "reset"])]
+  pub prefix: Option<String>,
+
+  /// Empty the index instead of reading a tree.
+  #[clap(long, conflicts_with = "trees")]
+  pub empty: bool,
+}
+
+pub fn cmd_read_tree(opts: &ReadTree) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let index = Index::read(&repo)?;
+  let mut trees: Vec<Files> = Vec::new();
+  for name in &opts.trees {
+    let hash = find_object(&repo, name, Some("tree"), true)?;
+    trees.push(tree::flatten(&repo, &hash)?);
+  }
+  if opts.u && !opts.m && !opts.reset {
+    return Err("-u is meaningless without -m or --reset".to_string());
+  }
+
+  let merged = if opts.empty {
+    Index::new()
+  } else if let Some(prefix) = &opts.prefix {
+    if trees.len() != 1 {
+      return Err("--prefix takes exactly one tree".to_string());
+    }
+    read_prefixed(&index, &trees[0], prefix)?
+  } else {
+    match (trees.as_slice(), opts.m || opts.reset) {
+      ([tree], _) => unpack::one_way(&index, tree),
+      (_, false) => return Err("multiple trees can only be read with -m".to_string()),
+      ([_, merge], true) if opts.reset => unpack::one_way(&index, merge),
+      ([head, merge], true) => unpack::two_way(&index, head, merge)?,
+      ([base, ours, theirs], true) => {
+        unpack::three_way(&index, base, ours, theirs, opts.aggressive)?
+      }
+      _ => return Err("read-tree needs between one and three trees".to_string()),
+    }
+  };
+
+  let merged = if opts.u {
+    update_worktree(&repo, &index, merged, opts.reset)?
+  } else {
+    merged
+  };
+  merged.write(&repo)
+}
+
+/// Adds a tree to the index under a directory that must not exist yet.
+fn read_prefixed(index: &Index, tree: &Files, prefix: &str) -> Result<Index, String> {
+  let dir = prefix.trim_end_matches('/');
+  let clash = index
+    .entries()
+    .iter()
+    .any(|e| e.path == dir || e.path.starts_with(format!("{}/", dir).as_bytes()));
+  if clash {
+    return Err(format!("subdirectory '{}' already exists.", dir));
+  }
+  let mut result = Index::new();
+  for entry in index.entries() {
+    result.add(entry.clone());
+  }
+  for (path, (mode, hash)) in tree {
+    let path = [dir.as_bytes(), b"/", path].concat();
+    result.add(IndexEntry::new(path, *mode, hash));
+  }
+  Ok(result)
+}
+
+/// Writes out the files whose stage 0 entries changed and deletes the ones
+/// that went away, refusing to touch files with local changes unless `force`
+/// is set.
+fn update_worktree(repo: &Repo, old: &Index, new: Index, force: bool) -> Result<Index, String> {
+  let (before, after) = (old.files(), new.files());
+  let mut changed: Vec<&BString> = before
+    .keys()
+    .chain(after.keys())
+    .filter(|path| before.get(*path) != after.get(*path))
+    .collect();
+  changed.sort();
+  changed.dedup();
+
+  if !force {
+    for path in &changed {
+      let exists = fs::symlink_metadata(repo.work_tree_path(path)).is_ok();
+      match old.get(path) {
+        Some(entry) if exists && index::is_modified(repo, entry)? => {
+          return Err(format!("Entry '{}' not uptodate. Cannot merge.", path))
+        }
+        None if exists => {
+          return Err(format!(
+            "Untracked working tree file '{}' would be overwritten by merge.",
+            path
+          ))
+        }
+        _ => (),
+      }
+    }
+  }
+
+  let mut result = new;
+  for path in changed {
+    match after.get(path) {
+      Some((mode, hash)) => result.add(worktree::checkout_file(repo, path, *mode, hash)?),
+      // a conflicted path keeps the working tree copy of our side
+      None if result.entries().iter().any(|e| e.path == *path) => (),
+      None => worktree::remove_file(repo, path)?,
+    }
+  }
+  Ok(result)
+}
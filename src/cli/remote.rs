@@ -0,0 +1,63 @@
+use clap::Args;
+
+use git_rs_core::{remote::head, repo::Repo};
+
+/// Manage the set of repositories whose branches are tracked.
+///
+/// `set-head` sets or deletes the default branch of a remote, the
+/// symbolic ref `refs/remotes/<name>/HEAD`, which lets the remote's name
+/// stand for that branch (`origin` for `origin/main`). With `--auto`, the
+/// remote is asked which branch its `HEAD` is on; with a branch, it is
+/// pointed at the remote-tracking branch of that name, which must exist;
+/// and with `--delete` it is deleted. Fetching from a remote with its own
+/// refspecs sets it as `--auto` would if it isn't set yet.
+///
+/// # Example
+/// ```bash
+/// $ git remote set-head origin --auto
+/// origin/HEAD set to main
+/// $ git remote set-head origin next
+/// $ git remote set-head origin --delete
+/// ```
+#[derive(Args, Debug)]
+pub struct Remote {
+  /// What to do.
+  #[clap(possible_values = &["set-head"])]
+  pub command: String,
+
+  /// The remote.
+  pub name: String,
+
+  /// The remote-tracking branch to make the default, without
+  /// `refs/remotes/<name>/`.
+  pub branch: Option<String>,
+
+  /// Ask the remote which branch is its default.
+  #[clap(short, long, conflicts_with_all = &["branch", "delete"])]
+  pub auto: bool,
+
+  /// Delete the default branch of the remote.
+  #[clap(short, long, conflicts_with = "branch")]
+  pub delete: bool,
+}
+
+pub fn cmd_remote(opts: &Remote) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let name = &opts.name;
+  match (&opts.branch, opts.auto, opts.delete) {
+    (_, true, _) => {
+      let remote = git_rs_core::remote::Remote::get(&repo, name)?;
+      if remote.name.is_none() {
+        return Err(format!("No such remote '{}'", name));
+      }
+      let branch = head::set_auto(&repo, &remote)?;
+      println!("{}/HEAD set to {}", name, branch);
+      Ok(())
+    }
+    (_, _, true) => head::delete(&repo, name),
+    (Some(branch), _, _) => head::set(&repo, name, branch),
+    (None, _, _) => {
+      Err("usage: git remote set-head <name> (-a | --auto | -d | --delete | <branch>)".to_string())
+    }
+  }
+}
@@ -0,0 +1,147 @@
+use clap::Args;
+use std::collections::HashSet;
+use std::fs;
+
+use git_rs_core::{
+  cancel, object,
+  progress::Meter,
+  repack::{self, Options},
+  repo::{parse_size, Repo},
+};
+
+/// Pack unpacked objects in a repository.
+///
+/// Packs the objects reachable from the refs, `HEAD` and the index that are
+/// not in a pack yet into a new pack, storing objects as deltas against
+/// similar ones where that saves space. With `-a`, every reachable object is
+/// packed, packed or not, and `-d` then deletes the packs that were there
+/// before. `-d` also deletes the loose objects that are now in a pack.
+///
+/// Packs with a `.keep` file next to them are to be kept: `-d` never
+/// deletes them, and `-a` leaves their objects out of the new pack unless
+/// `--pack-kept-objects` (or `repack.packKeptObjects`) says otherwise.
+///
+/// The search for deltas runs on `pack.threads` threads. How many objects
+/// each one is compared against, and how long a chain of deltas can get, are
+/// set with `--window` and `--depth` (or `pack.window` and `pack.depth`).
+///
+/// # Example
+/// ```bash
+/// $ git repack -a -d --window=50 --depth=100
+/// ```
+#[derive(Args, Debug)]
+pub struct Repack {
+  /// Pack everything reachable into a single pack.
+  #[clap(short = 'a')]
+  pub all: bool,
+
+  /// Delete the loose objects that are packed and, with -a, the packs that
+  /// were there before.
+  #[clap(short = 'd')]
+  pub delete: bool,
+
+  /// With -a, pack the objects in kept packs too.
+  #[clap(long)]
+  pub pack_kept_objects: bool,
+
+  /// How many objects to try as the delta base of each object.
+  #[clap(long, value_name = "N")]
+  pub window: Option<usize>,
+
+  /// The longest chain of deltas to allow.
+  #[clap(long, value_name = "N")]
+  pub depth: Option<usize>,
+
+  /// The number of threads to search for deltas on.
+  #[clap(long, value_name = "N")]
+  pub threads: Option<usize>,
+
+  /// The most memory each thread may hold objects in, such as `256m`.
+  #[clap(long, value_name = "SIZE")]
+  pub window_memory: Option<String>,
+
+  /// Do not show progress.
+  #[clap(short, long)]
+  pub quiet: bool,
+}
+
+pub fn cmd_repack(opts: &Repack) -> Result<(), String> {
+  // the objects are packed as they are, not as they have been replaced
+  object::ignore_replacements();
+  let repo: Repo = Repo::default();
+  let mut options = Options::new(&repo)?;
+  options.window = opts.window.unwrap_or(options.window);
+  options.depth = opts.depth.unwrap_or(options.depth);
+  options.threads = match opts.threads {
+    Some(0) | None => options.threads,
+    Some(threads) => threads,
+  };
+  if let Some(size) = &opts.window_memory {
+    options.window_memory = parse_size(size).ok_or(format!("invalid size: {}", size))?;
+  }
+
+  let mut objects = repack::reachable(&repo)?;
+  if !opts.all {
+    objects.retain(|(hash, _)| !repo.objects.is_packed(hash));
+  } else if !opts.pack_kept_objects && !pack_kept_objects(&repo) {
+    let mut kept = HashSet::new();
+    for pack in repo.objects.packs().iter() {
+      if repack::is_kept(pack.path()) {
+        kept.extend(pack.hashes()?);
+      }
+    }
+    objects.retain(|(hash, _)| !kept.contains(hash));
+  }
+  if objects.is_empty() {
+    println!("Nothing new to pack.");
+    return Ok(());
+  }
+
+  let dir = repo.objects.dir().join("pack");
+  let old: Vec<_> = fs::read_dir(&dir)
+    .into_iter()
+    .flatten()
+    .flatten()
+    .map(|entry| entry.path())
+    .filter(|path| path.extension().is_some_and(|ext| ext == "pack"))
+    .filter(|path| !repack::is_kept(path))
+    .collect();
+  let mut progress = Meter::boxed(if opts.quiet { Some(false) } else { None });
+  let cancel = cancel::on_interrupt();
+  let name = repack::write(&repo, &objects, &options, progress.as_mut(), &cancel)?;
+
+  if opts.all && opts.delete {
+    let new = dir.join(format!("pack-{}.pack", name));
+    for pack in old.iter().filter(|pack| **pack != new) {
+      // the index goes first, so the pack is never listed without its data
+      let index = pack.with_extension("idx");
+      fs::remove_file(&index).map_err(|e| format!("{}: {}", index.display(), e))?;
+      fs::remove_file(pack).map_err(|e| format!("{}: {}", pack.display(), e))?;
+    }
+  }
+  if opts.delete {
+    repo.objects.reprepare_packs();
+    repo.objects.prune_packed(false)?;
+  }
+  Ok(())
+}
+
+/// Whether `repack.packKeptObjects` says to pack the objects in kept packs.
+fn pack_kept_objects(repo: &Repo) -> bool {
+  repo
+    .config
+    .as_ref()
+    .and_then(|config| config.section(Some("repack")))
+    .and_then(|section| {
+      section
+        .iter()
+        .rev()
+        .find(|(key, _)| key.eq_ignore_ascii_case("packKeptObjects"))
+    })
+    .is_some_and(|(_, value)| {
+      matches!(
+        value.to_ascii_lowercase().as_str(),
+        "true" | "yes" | "on" | "1" | ""
+      )
+    })
+}
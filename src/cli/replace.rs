@@ -0,0 +1,129 @@
+use std::path::Path;
+
+use clap::Args;
+
+use git_rs_core::env;
+use git_rs_core::ignore::wildmatch;
+use git_rs_core::object::{self, refs};
+use git_rs_core::repo::Repo;
+use git_rs_core::rev;
+
+/// Create, list or delete refs to replace objects.
+///
+/// `git replace <object> <replacement>` makes every command that reads
+/// `<object>` see `<replacement>` in its place, by writing the ref
+/// `refs/replace/<object>`. Both must be of the same type unless `-f` is
+/// given, which also allows an existing replacement to be overwritten.
+///
+/// With no arguments, or with `-l`, the replaced objects are listed,
+/// optionally only those matching a pattern: `--format=medium` adds their
+/// replacements and `--format=long` the types of both.
+///
+/// # Example
+/// ```bash
+/// $ git replace 3f8a2c1 9d0e417
+/// $ git replace --format=medium
+/// 3f8a2c1e53c26f2d3b9a4ba25c7a1a6e2ba7b3f1 -> 9d0e41795a6b2da0a7f5c8bfc8c6e1ed6a3ad7a2
+/// ```
+#[derive(Args, Debug)]
+pub struct Replace {
+  /// Overwrite an existing replacement, and allow one of another type.
+  #[clap(short, long)]
+  pub force: bool,
+
+  /// Delete the replacements of the given objects.
+  #[clap(short, long, conflicts_with_all = &["force", "list"])]
+  pub delete: bool,
+
+  /// List the replaced objects that match a pattern.
+  #[clap(short, long)]
+  pub list: bool,
+
+  /// How to list them: `short`, `medium` or `long`.
+  #[clap(long, possible_values = ["short", "medium", "long"], value_name = "format")]
+  pub format: Option<String>,
+
+  /// The objects (or the object and its replacement, or a pattern).
+  pub args: Vec<String>,
+}
+
+pub fn cmd_replace(opts: &Replace) -> Result<(), String> {
+  // the objects themselves are what is being dealt with here
+  object::ignore_replacements();
+  let repo: Repo = Repo::default();
+  let base = env::replace_ref_base();
+  let base = base.to_string_lossy();
+  let base = base.trim_end_matches('/');
+
+  if opts.delete {
+    for name in &opts.args {
+      let hash = rev::parse(&repo, name)?;
+      let name = format!("{}/{}", base, hash);
+      match refs::exists(&repo, &name) {
+        true => {
+          refs::delete(&repo, &name)?;
+          println!("Deleted replace ref '{}'", hash);
+        }
+        false => eprintln!("error: replace ref '{}' not found", hash),
+      }
+    }
+    return Ok(());
+  }
+
+  if opts.list || opts.args.is_empty() {
+    if opts.args.len() > 1 {
+      return Err("only one pattern can be given with -l".to_string());
+    }
+    let pattern = opts.args.first().map_or("*", String::as_str);
+    let format = opts.format.as_deref().unwrap_or("short");
+    let kind = |hash: &str| object::read(&repo, hash, None).map(|object| object.format().clone());
+    for (name, replacement) in refs::collect(&repo, Some(Path::new(base))) {
+      let hash = name.rsplit('/').next().unwrap_or(&name);
+      if !wildmatch(pattern.as_bytes(), hash.as_bytes()) {
+        continue;
+      }
+      match format {
+        "short" => println!("{}", hash),
+        "medium" => println!("{} -> {}", hash, replacement),
+        _ => println!(
+          "{} ({}) -> {} ({})",
+          hash,
+          kind(hash)?,
+          replacement,
+          kind(&replacement)?
+        ),
+      }
+    }
+    return Ok(());
+  }
+
+  if opts.format.is_some() {
+    return Err("--format cannot be used when not listing".to_string());
+  }
+  if opts.args.len() != 2 {
+    return Err("bad number of arguments".to_string());
+  }
+  let (original, replacement) = (&opts.args[0], &opts.args[1]);
+  let hash = rev::parse(&repo, original)
+    .map_err(|_| format!("failed to resolve '{}' as a valid ref", original))?;
+  let with = rev::parse(&repo, replacement)
+    .map_err(|_| format!("failed to resolve '{}' as a valid ref", replacement))?;
+  let name = format!("{}/{}", base, hash);
+  if hash == with {
+    return Err(format!("new object is the same as the old one: '{}'", hash));
+  }
+  let (kind, with_kind) = (
+    object::read(&repo, &hash, None)?.format().clone(),
+    object::read(&repo, &with, None)?.format().clone(),
+  );
+  if !opts.force && kind != with_kind {
+    return Err(format!(
+      "Objects must be of the same type.\n'{}' points to a replaced object of type '{}'\nwhile '{}' points to a replacement object of type '{}'.",
+      original, kind, replacement, with_kind
+    ));
+  }
+  if !opts.force && refs::exists(&repo, &name) {
+    return Err(format!("replace ref '{}' already exists", name));
+  }
+  refs::update(&repo, &name, &with)
+}
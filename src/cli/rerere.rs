@@ -0,0 +1,80 @@
+use bstr::BString;
+use clap::Args;
+use std::fs;
+
+use git_rs_core::{diff::patch, pathspec, repo::Repo, rerere};
+
+/// Reuse recorded resolutions of conflicted merges.
+///
+/// When enabled (with `rerere.enabled`, or by creating `.git/rr-cache`), the
+/// conflicts left by a merge are recorded, and so is the way they get
+/// resolved once the result is committed. The next time the same conflict
+/// comes up, the recorded resolution is applied to the file automatically.
+/// This happens on its own in the commands that can leave conflicts; running
+/// `git rerere` does it by hand.
+///
+/// The other subcommands look at or tidy up what has been recorded: `status`
+/// and `remaining` list the conflicted paths, `diff` shows how each file has
+/// changed from its conflict, `forget` drops a bad resolution, `clear` drops
+/// the conflicts of the current merge and `gc` drops old entries.
+///
+/// # Example
+/// ```bash
+/// $ git config rerere.enabled true
+/// $ git am -3 topic.mbox
+/// Recorded preimage for 'src/lib.rs'
+/// $ git rerere forget src/lib.rs
+/// ```
+#[derive(Args, Debug)]
+pub struct Rerere {
+  /// What to do (by default, record and replay resolutions).
+  #[clap(possible_values = &["clear", "forget", "diff", "status", "remaining", "gc"])]
+  pub command: Option<String>,
+
+  /// The paths to forget the resolutions of.
+  pub paths: Vec<String>,
+}
+
+pub fn cmd_rerere(opts: &Rerere) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  match opts.command.as_deref() {
+    None => rerere::run(&repo)?
+      .iter()
+      .for_each(|message| println!("{}", message)),
+    Some("clear") => rerere::clear(&repo)?,
+    Some("forget") => {
+      if opts.paths.is_empty() {
+        return Err("'git rerere forget' without paths is deprecated".to_string());
+      }
+      let specs = pathspec::resolve(&repo, &opts.paths)?;
+      let paths: Vec<BString> = rerere::remaining(&repo)?
+        .into_iter()
+        .filter(|path| pathspec::matches(path, &specs))
+        .collect();
+      for path in paths {
+        rerere::forget(&repo, &path)?
+          .iter()
+          .for_each(|message| println!("{}", message));
+      }
+    }
+    Some("status") => rerere::pending(&repo)
+      .iter()
+      .for_each(|(_, path)| println!("{}", path)),
+    Some("remaining") => rerere::remaining(&repo)?
+      .iter()
+      .for_each(|path| println!("{}", path)),
+    Some("diff") => {
+      for (id, path) in rerere::pending(&repo) {
+        let before = rerere::preimage(&repo, &id)?;
+        let after = fs::read(repo.work_tree_path(&path)).unwrap_or_default();
+        let body = patch::unified(&before, &after);
+        if !body.is_empty() {
+          print!("--- a/{}\n+++ b/{}\n{}", path, path, body);
+        }
+      }
+    }
+    Some("gc") => rerere::gc(&repo)?,
+    Some(command) => return Err(format!("unknown rerere subcommand '{}'", command)),
+  }
+  Ok(())
+}
@@ -0,0 +1,176 @@
+use bstr::{BString, ByteSlice};
+use clap::Args;
+
+use git_rs_core::{
+  cancel, diff,
+  index::{self, Index},
+  object::{self, find_object, refs, tree},
+  pathspec,
+  progress::Meter,
+  repo::Repo,
+  rev, worktree,
+};
+
+/// Reset current HEAD to the specified state.
+///
+/// Moves the current branch to the given commit (HEAD by default). With
+/// `--soft` only the branch moves, with `--mixed` (the default) the index is
+/// also reset to the commit's tree, and with `--hard` the working tree is
+/// overwritten too. A hard reset refuses to throw away uncommitted changes
+/// unless `--force` is given.
+///
+/// When paths are given, the branch is left alone and only the index entries
+/// for those paths are reset, which unstages them.
+///
+/// # Example
+/// ```bash
+/// $ git reset --hard HEAD~1
+/// $ git reset -- src/main.rs
+/// ```
+#[derive(Args, Debug)]
+pub struct Reset {
+  /// The commit to reset to.
+  pub commit: Option<String>,
+
+  /// Only move the branch, leaving the index and working tree alone.
+  #[clap(long, conflicts_with_all = &["mixed", "hard"])]
+  pub soft: bool,
+
+  /// Reset the index but not the working tree (the default).
+  #[clap(long, conflicts_with = "hard")]
+  pub mixed: bool,
+
+  /// Reset the index and the working tree.
+  #[clap(long)]
+  pub hard: bool,
+
+  /// Discard uncommitted changes during a hard reset.
+  #[clap(short, long)]
+  pub force: bool,
+
+  /// Only reset the index entries of these paths.
+  #[clap(last = true)]
+  pub paths: Vec<String>,
+}
+
+pub fn cmd_reset(opts: &Reset) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+
+  // Like git, a lone argument that is not a revision is taken as a path.
+  let mut paths = opts.paths.clone();
+  let mut commit = opts.commit.clone().unwrap_or_else(|| String::from("HEAD"));
+  if paths.is_empty() && opts.commit.is_some() && rev::parse(&repo, &commit).is_err() {
+    paths.push(commit);
+    commit = String::from("HEAD");
+  }
+  let target = find_object(&repo, &commit, Some("commit"), true)?;
+
+  if !paths.is_empty() {
+    if opts.soft || opts.hard {
+      let mode = if opts.soft { "soft" } else { "hard" };
+      return Err(format!("Cannot do {} reset with paths.", mode));
+    }
+    return reset_paths(&repo, &target, &pathspec::resolve(&repo, &paths)?);
+  }
+
+  let tree = find_object(&repo, &target, Some("tree"), true)?;
+  let files = tree::flatten(&repo, &tree)?;
+  let index = Index::read(&repo)?;
+  if opts.hard {
+    if !opts.force {
+      let lost = uncommitted_changes(&repo, &index)?;
+      if !lost.is_empty() {
+        return Err(format!(
+          "Your local changes to the following files would be lost:\n\t{}\nUse --force to discard them.",
+          bstr::join("\n\t", lost).as_bstr()
+        ));
+      }
+    }
+    let mut progress = Meter::boxed(None);
+    let cancel = cancel::on_interrupt();
+    worktree::switch_files(&repo, &index, &files, true, progress.as_mut(), &cancel)?
+      .write(&repo)?;
+  } else if !opts.soft {
+    // Keep the stat data of entries that are not changing so the working
+    // tree does not look modified afterwards.
+    let mut new_index = Index::new();
+    for (path, (mode, hash)) in &files {
+      match index.get(path) {
+        Some(entry) if entry.hash == *hash && entry.mode == index::mode_bits(*mode) => {
+          new_index.add(entry.clone())
+        }
+        _ => new_index.add(index::IndexEntry::new(path, *mode, hash)),
+      }
+    }
+    new_index.write(&repo)?;
+    print_unstaged(&repo, &new_index)?;
+  }
+
+  if let Ok(head) = rev::parse(&repo, "HEAD") {
+    refs::update(&repo, "ORIG_HEAD", &head)?;
+  }
+  refs::update(&repo, "HEAD", &target)?;
+  if opts.hard {
+    println!("HEAD is now at {}", &target[..7]);
+  }
+  Ok(())
+}
+
+/// Resets the index entries for the given paths to their state in a commit.
+fn reset_paths(repo: &Repo, commit: &str, paths: &[BString]) -> Result<(), String> {
+  let tree = find_object(repo, commit, Some("tree"), true)?;
+  let files = tree::flatten(repo, &tree)?;
+  let mut index = Index::read(repo)?;
+
+  let staged: Vec<BString> = index
+    .entries()
+    .iter()
+    .map(|e| e.path.clone())
+    .filter(|path| pathspec::matches(path, paths))
+    .collect();
+  for path in staged {
+    if !files.contains_key(&path) {
+      index.remove(&path);
+    }
+  }
+  for (path, (mode, hash)) in files
+    .iter()
+    .filter(|(path, _)| pathspec::matches(path, paths))
+  {
+    let unchanged = index
+      .get(path)
+      .is_some_and(|e| e.hash == *hash && e.mode == index::mode_bits(*mode));
+    if !unchanged {
+      index.add(index::IndexEntry::new(path, *mode, hash));
+    }
+  }
+  index.write(repo)?;
+  print_unstaged(repo, &index)
+}
+
+/// Lists the files with changes that are now unstaged, like git does after a
+/// mixed reset.
+fn print_unstaged(repo: &Repo, index: &Index) -> Result<(), String> {
+  let changes = worktree::unstaged_changes(repo, index)?;
+  if !changes.is_empty() {
+    println!("Unstaged changes after reset:");
+    for (status, path) in changes {
+      println!("{}\t{}", status, path);
+    }
+  }
+  Ok(())
+}
+
+/// Lists the files with staged or unstaged changes relative to HEAD.
+fn uncommitted_changes(repo: &Repo, index: &Index) -> Result<Vec<BString>, String> {
+  let head_files = tree::flatten(repo, &object::head_tree(repo)?)?;
+  let staged = diff::compare(&head_files, &index.files());
+  let mut paths: Vec<BString> = staged.into_iter().map(|change| change.path).collect();
+  for (_, path) in worktree::unstaged_changes(repo, index)? {
+    if !paths.contains(&path) {
+      paths.push(path);
+    }
+  }
+  paths.sort();
+  Ok(paths)
+}
@@ -0,0 +1,133 @@
+use bstr::BString;
+use clap::Args;
+
+use git_rs_core::{
+  index::{Index, IndexEntry},
+  object::{find_object, mode::Mode, tree},
+  pathspec,
+  repo::Repo,
+  worktree,
+};
+
+/// Restore working tree files.
+///
+/// Copies the given paths from the index into the working tree, throwing away
+/// unstaged changes. With `--staged` the index is restored from HEAD instead,
+/// which unstages changes. `--source` picks a different commit or tree to
+/// restore from.
+///
+/// # Example
+/// ```bash
+/// $ git restore src/main.rs
+/// $ git restore --staged src/main.rs
+/// $ git restore --source HEAD~2 --staged --worktree src/
+/// ```
+#[derive(Args, Debug)]
+pub struct Restore {
+  /// The commit or tree to restore from.
+  #[clap(short, long)]
+  pub source: Option<String>,
+
+  /// Restore the index.
+  #[clap(short = 'S', long)]
+  pub staged: bool,
+
+  /// Restore the working tree (the default).
+  #[clap(short = 'W', long)]
+  pub worktree: bool,
+
+  /// The paths to restore.
+  #[clap(required = true)]
+  pub paths: Vec<String>,
+}
+
+pub fn cmd_restore(opts: &Restore) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let paths = pathspec::resolve(&repo, &opts.paths)?;
+  let restore_worktree = opts.worktree || !opts.staged;
+  let mut index = Index::read(&repo)?;
+
+  // The working tree alone is restored from the index, anything else from a
+  // commit (HEAD unless another source is given).
+  let source = match &opts.source {
+    None if !opts.staged => index.files(),
+    source => {
+      let name = source.as_deref().unwrap_or("HEAD");
+      let tree = find_object(&repo, name, Some("tree"), true)?;
+      tree::flatten(&repo, &tree)?
+    }
+  };
+
+  // Check that every path names something before touching anything.
+  let known: Vec<&BString> = source
+    .keys()
+    .chain(index.entries().iter().map(|e| &e.path))
+    .collect();
+  for (spec, arg) in paths.iter().zip(&opts.paths) {
+    if !known
+      .iter()
+      .any(|path| pathspec::matches(path, std::slice::from_ref(spec)))
+    {
+      return Err(format!(
+        "pathspec '{}' did not match any file(s) known to git",
+        arg
+      ));
+    }
+  }
+
+  let mut matched: Vec<BString> = known
+    .into_iter()
+    .filter(|path| pathspec::matches(path, &paths))
+    .cloned()
+    .collect();
+  matched.sort();
+  matched.dedup();
+
+  for path in &matched {
+    let entry = source.get(path);
+    if restore_worktree {
+      restore_file(&repo, &mut index, path, entry, opts.staged)?;
+    } else {
+      match entry {
+        Some((mode, hash)) => {
+          let unchanged = index.get(path).is_some_and(|e| e.hash == *hash);
+          if !unchanged {
+            index.add(IndexEntry::new(path, *mode, hash));
+          }
+        }
+        None => {
+          index.remove(path);
+        }
+      }
+    }
+  }
+  index.write(&repo)
+}
+
+/// Restores a single file in the working tree, and in the index too when
+/// `staged` is set. A file missing from the source is deleted.
+fn restore_file(
+  repo: &Repo,
+  index: &mut Index,
+  path: &[u8],
+  entry: Option<&(Mode, String)>,
+  staged: bool,
+) -> Result<(), String> {
+  match entry {
+    Some((mode, hash)) => {
+      let written = worktree::checkout_file(repo, path, *mode, hash)?;
+      // keep the index's stat data in step with the file we just wrote
+      let in_index = index.get(path).is_some_and(|e| e.hash == *hash);
+      if staged || in_index {
+        index.add(written);
+      }
+    }
+    None => {
+      worktree::remove_file(repo, path)?;
+      if staged {
+        index.remove(path);
+      }
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,86 @@
+use clap::Args;
+
+use git_rs_core::cancel;
+use git_rs_core::identity::date::parse_limit;
+use git_rs_core::pathspec;
+use git_rs_core::repo::Repo;
+use git_rs_core::rev::walk::{RevWalk, Sort};
+
+/// Lists commit objects in reverse chronological order.
+///
+/// Starting from the given commits, lists every reachable commit that is not
+/// also reachable from an excluded commit (prefixed with `^`). Ranges may be
+/// given as `A..B` (reachable from B but not A) or `A...B` (reachable from
+/// either but not both).
+///
+/// # Example
+/// ```bash
+/// $ git rev-list --count main..topic
+/// 3
+/// ```
+#[derive(Args, Debug)]
+pub struct RevList {
+  /// The commits (or ranges) to start from.
+  #[clap(required = true)]
+  pub revisions: Vec<String>,
+
+  /// Print the number of commits instead of listing them.
+  #[clap(long)]
+  pub count: bool,
+
+  /// Limit the number of commits to output.
+  #[clap(short = 'n', long)]
+  pub max_count: Option<usize>,
+
+  /// Also list the trees and blobs referenced by the listed commits.
+  #[clap(long)]
+  pub objects: bool,
+
+  /// Show no parents before all of their children are shown.
+  #[clap(long)]
+  pub topo_order: bool,
+
+  /// Only show commits more recent than a date, such as `2 weeks ago`.
+  #[clap(long, visible_alias = "after", value_name = "DATE")]
+  pub since: Option<String>,
+
+  /// Only show commits older than a date.
+  #[clap(long, visible_alias = "before", value_name = "DATE")]
+  pub until: Option<String>,
+
+  /// Only list commits that change these paths.
+  #[clap(last = true)]
+  pub paths: Vec<String>,
+}
+
+pub fn cmd_rev_list(opts: &RevList) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let mut walk = RevWalk::new(&repo);
+  walk.cancel(&cancel::on_interrupt());
+  for spec in &opts.revisions {
+    walk.push_spec(spec)?;
+  }
+  if opts.topo_order {
+    walk.sort(Sort::Topo);
+  }
+  walk.max_count(opts.max_count);
+  walk.paths(&pathspec::resolve(&repo, &opts.paths)?);
+  walk.since(opts.since.as_deref().map(parse_limit).transpose()?);
+  walk.until(opts.until.as_deref().map(parse_limit).transpose()?);
+
+  let commits = walk.run()?;
+  if opts.count {
+    println!("{}", commits.len());
+    return Ok(());
+  }
+
+  for commit in &commits {
+    println!("{}", commit);
+  }
+  if opts.objects {
+    for (hash, path) in walk.objects(&commits)? {
+      println!("{} {}", hash, path);
+    }
+  }
+  Ok(())
+}
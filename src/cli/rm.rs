@@ -1,10 +1,154 @@
+use bstr::{BString, ByteSlice};
 use clap::Args;
 
+use git_rs_core::{
+  index::{self, Index},
+  object::{self, tree},
+  pathspec,
+  repo::Repo,
+  worktree,
+};
+
+/// Remove files from the working tree and from the index.
+///
+/// The files must be tracked and, to avoid losing work, must match both the
+/// index and HEAD unless `--force` is given. With `--cached` the files are
+/// only removed from the index, which is safe as long as the working tree
+/// still holds either the staged or the committed version.
+///
+/// # Example
+/// ```bash
+/// $ git rm old.txt
+/// $ git rm -r --cached build/
+/// ```
 #[derive(Args, Debug)]
 pub struct Rm {
-  pub name: Option<String>,
+  /// The files to remove.
+  #[clap(required = true)]
+  pub paths: Vec<String>,
+
+  /// Only remove the files from the index.
+  #[clap(long)]
+  pub cached: bool,
+
+  /// Override the up-to-date check.
+  #[clap(short, long)]
+  pub force: bool,
+
+  /// Allow recursive removal when a leading directory name is given.
+  #[clap(short)]
+  pub r: bool,
+
+  /// Only show what would be removed.
+  #[clap(short = 'n', long)]
+  pub dry_run: bool,
+
+  /// Do not list the removed files.
+  #[clap(short, long)]
+  pub quiet: bool,
 }
 
-pub fn cmd_rm() -> Result<(), String> {
-  todo!("cmd_rm");
+pub fn cmd_rm(opts: &Rm) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let specs = pathspec::resolve(&repo, &opts.paths)?;
+  let mut index = Index::read(&repo)?;
+
+  let mut matched: Vec<BString> = Vec::new();
+  for (spec, arg) in specs.iter().zip(&opts.paths) {
+    let files: Vec<BString> = index
+      .entries()
+      .iter()
+      .map(|e| e.path.clone())
+      .filter(|path| pathspec::matches(path, std::slice::from_ref(spec)))
+      .collect();
+    if files.is_empty() {
+      return Err(format!("pathspec '{}' did not match any files", arg));
+    }
+    if !opts.r && files.iter().any(|path| path != spec) {
+      return Err(format!("not removing '{}' recursively without -r", arg));
+    }
+    matched.extend(files);
+  }
+  matched.sort();
+  matched.dedup();
+
+  if !opts.force {
+    check_up_to_date(&repo, &index, &matched, opts.cached)?;
+  }
+
+  for path in &matched {
+    if !opts.quiet {
+      println!("rm '{}'", path);
+    }
+    if opts.dry_run {
+      continue;
+    }
+    index.remove(path);
+    if !opts.cached {
+      worktree::remove_file(&repo, path)?;
+    }
+  }
+  if !opts.dry_run {
+    index.write(&repo)?;
+  }
+  Ok(())
+}
+
+/// Refuses to remove files whose changes would be lost, listing them the way
+/// git does.
+fn check_up_to_date(
+  repo: &Repo,
+  index: &Index,
+  paths: &[BString],
+  cached: bool,
+) -> Result<(), String> {
+  let head = tree::flatten(repo, &object::head_tree(repo)?)?;
+
+  let mut both = Vec::new();
+  let mut staged = Vec::new();
+  let mut local = Vec::new();
+  for path in paths {
+    let entry = match index.get(path) {
+      Some(entry) => entry,
+      None => continue,
+    };
+    let staged_change = head
+      .get(path)
+      .is_none_or(|(mode, hash)| *hash != entry.hash || index::mode_bits(*mode) != entry.mode);
+    // a file that is already gone from the working tree has nothing to lose
+    let exists = std::fs::symlink_metadata(repo.work_tree_path(path)).is_ok();
+    let local_change = exists && index::is_modified(repo, entry)?;
+    if staged_change && local_change {
+      both.push(path.as_bstr());
+    } else if !cached && staged_change && exists {
+      staged.push(path.as_bstr());
+    } else if !cached && local_change {
+      local.push(path.as_bstr());
+    }
+  }
+
+  let mut errors = Vec::new();
+  if !both.is_empty() {
+    errors.push(format!(
+      "the following file(s) have staged content different from both the\nfile and the HEAD:\n    {}\n(use -f to force removal)",
+      bstr::join("\n    ", both).as_bstr()
+    ));
+  }
+  if !staged.is_empty() {
+    errors.push(format!(
+      "the following file(s) have changes staged in the index:\n    {}\n(use --cached to keep the file, or -f to force removal)",
+      bstr::join("\n    ", staged).as_bstr()
+    ));
+  }
+  if !local.is_empty() {
+    errors.push(format!(
+      "the following file(s) have local modifications:\n    {}\n(use --cached to keep the file, or -f to force removal)",
+      bstr::join("\n    ", local).as_bstr()
+    ));
+  }
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(errors.join("\n"))
+  }
 }
@@ -0,0 +1,43 @@
+use clap::Args;
+
+use git_rs_core::{repo::Repo, serve::Server};
+
+/// Browse the repository in a web browser.
+///
+/// Starts a small web server showing the branches, tags, history, trees and
+/// files of the repository, and the diff each commit makes, for looking
+/// around it (or letting others on the network look around it) without
+/// setting up anything else. Nothing can be changed through it. The server
+/// runs until it is stopped.
+///
+/// # Example
+/// ```bash
+/// $ git serve --local
+/// Serving /home/me/project at http://127.0.0.1:1234/
+/// ```
+#[derive(Args, Debug)]
+pub struct Serve {
+  /// The port to listen on.
+  #[clap(short, long, default_value_t = 1234)]
+  pub port: u16,
+
+  /// Only accept connections from this machine.
+  #[clap(short, long)]
+  pub local: bool,
+}
+
+pub fn cmd_serve(opts: &Serve) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let host = match opts.local {
+    true => "127.0.0.1",
+    false => "0.0.0.0",
+  };
+  let server = Server::bind(&repo, &format!("{}:{}", host, opts.port))?;
+  let addr = server.local_addr()?;
+  let dir = match repo.bare {
+    true => &repo.git_dir,
+    false => &repo.work_tree,
+  };
+  println!("Serving {} at http://{}/", dir.display(), addr);
+  server.run()
+}
@@ -0,0 +1,303 @@
+use std::io::{self, Write};
+
+use clap::Args;
+use regex::bytes::Regex;
+
+use git_rs_core::{
+  color::Colors,
+  diff::{
+    self,
+    patch::{self, Stats},
+    words::{Style, WordDiff},
+  },
+  identity::Signature,
+  object::{commit::Commit, find_object, read, serializable::Unbox, tag::Tag, tree::Tree},
+  pager,
+  repo::Repo,
+};
+
+/// Show various types of objects.
+///
+/// Shows each object the way it is best read: a commit with its author,
+/// date and message followed by the diff it makes to its first parent (or
+/// to nothing, for a root commit; merges are shown without one), an
+/// annotated tag with its tagger and message followed by the object it
+/// tags, a tree as `ls-tree` lists it, and a blob as it is.
+///
+/// # Example
+/// ```bash
+/// $ git show v1.0
+/// tag v1.0
+/// Tagger: T Agger <tagger@example.com>
+/// Date:   Tue Nov 14 15:13:20 2023 -0700
+///
+/// The first release
+///
+/// commit 33663e6a3b6b1b1f5dc2d2b2b7ae9b3ffbbd3e10
+/// ...
+/// $ git show HEAD~2 --no-patch
+/// $ git show --word-diff
+/// ...
+/// @@ -1 +1 @@
+/// Hello [-world-]{+there+}
+/// ```
+#[derive(Args, Debug)]
+pub struct Show {
+  /// The objects to show.
+  #[clap(default_value = "HEAD")]
+  pub objects: Vec<String>,
+
+  /// Don't show the diffs of commits.
+  #[clap(short = 's', long)]
+  pub no_patch: bool,
+
+  /// Show how many lines each file changed, with a graph of the lines
+  /// added and removed, in place of the diff. The output is as wide as the
+  /// terminal, or `WIDTH` columns.
+  #[clap(long, value_name = "WIDTH", min_values = 0, require_equals = true)]
+  pub stat: Option<Option<usize>>,
+
+  /// Show the number of lines each file adds and removes, and its name,
+  /// separated by tabs (`-` for binary files), in place of the diff.
+  #[clap(long)]
+  pub numstat: bool,
+
+  /// Show only the total of files changed and lines added and removed, in
+  /// place of the diff.
+  #[clap(long)]
+  pub shortstat: bool,
+
+  /// Show the diffs of files whose diff driver has a command with that
+  /// command.
+  #[clap(long)]
+  pub ext_diff: bool,
+
+  /// How to show changes to submodules: as the commits they move between
+  /// (`short`), or with the subjects of the commits in between (`log`).
+  /// Without a value, `log`; without the option, `diff.submodule`.
+  #[clap(
+    long,
+    value_name = "FORMAT",
+    min_values = 0,
+    require_equals = true,
+    default_missing_value = "log",
+    possible_values = &["short", "log"]
+  )]
+  pub submodule: Option<String>,
+
+  /// Show the words that changed rather than the lines: marked as
+  /// `[-removed-]` and `{+added+}` (`plain`), in color (`color`), or each
+  /// on a line of its own for scripts (`porcelain`). Without a value,
+  /// `plain`.
+  #[clap(
+    long,
+    value_name = "MODE",
+    min_values = 0,
+    require_equals = true,
+    default_missing_value = "plain",
+    possible_values = &["plain", "color", "porcelain", "none"]
+  )]
+  pub word_diff: Option<String>,
+
+  /// What counts as a word for `--word-diff` (which it implies), in place of
+  /// runs of anything but whitespace; `.` diffs characters.
+  #[clap(long, value_name = "REGEX")]
+  pub word_diff_regex: Option<String>,
+
+  /// Color the output: `always`, `never` or `auto` (when it goes to a
+  /// terminal). Without a value, `always`.
+  #[clap(
+    long,
+    value_name = "WHEN",
+    min_values = 0,
+    require_equals = true,
+    default_missing_value = "always",
+    possible_values = &["always", "never", "auto"]
+  )]
+  pub color: Option<String>,
+
+  /// Don't color the output.
+  #[clap(long, overrides_with = "color")]
+  pub no_color: bool,
+}
+
+pub fn cmd_show(opts: &Show) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let color = match opts.word_diff.as_deref() {
+    Some("color") => Some("always"),
+    _ => opts.no_color.then_some("never").or(opts.color.as_deref()),
+  };
+  let colors = Colors::new(&repo, "diff", color)?;
+  let mut out = io::stdout().lock();
+  for (i, name) in opts.objects.iter().enumerate() {
+    let hash = find_object(&repo, name, None, false)?;
+    if i > 0 {
+      write(&mut out, b"\n")?;
+    }
+    show(&repo, &hash, opts, &colors, &mut out)?;
+  }
+  Ok(())
+}
+
+/// Shows one object, and what it tags if it is a tag.
+fn show(
+  repo: &Repo,
+  hash: &str,
+  opts: &Show,
+  colors: &Colors,
+  out: &mut impl Write,
+) -> Result<(), String> {
+  let object = read(repo, hash, None)?;
+  match object.format().as_str() {
+    "commit" => {
+      let original = object.unbox::<Commit>()?;
+      let converted = original.to_utf8();
+      let commit = converted.as_ref().unwrap_or(original);
+      let text = format_commit(repo, hash, commit, opts, colors)?;
+      write(out, text.as_bytes())
+    }
+    "tag" => {
+      let tag = object.unbox::<Tag>()?;
+      let mut text = colors.paint(
+        "diff.commit",
+        &format!("tag {}", tag.get("tag").map_or("", |t| t.as_str())),
+      );
+      text.push('\n');
+      if let Some(tagger) = tag.get("tagger").and_then(|line| Signature::parse(line)) {
+        text.push_str(&format!(
+          "Tagger: {}\nDate:   {}\n",
+          tagger.person(),
+          tagger.date()
+        ));
+      }
+      text.push('\n');
+      text.push_str(tag.message());
+      text.push('\n');
+      write(out, text.as_bytes())?;
+      let target = match tag.get("object") {
+        Some(target) => target.clone(),
+        None => return Err(format!("malformed tag {}", hash)),
+      };
+      show(repo, &target, opts, colors, out)
+    }
+    "tree" => {
+      let tree = object.unbox::<Tree>()?;
+      let mut text = String::new();
+      for item in tree.entries() {
+        text.push_str(&format!(
+          "{} {} {}\t{}\n",
+          item.mode,
+          item.mode.object_type(),
+          item.hash,
+          item.path
+        ));
+      }
+      write(out, text.as_bytes())
+    }
+    _ => write(out, object.serialize()),
+  }
+}
+
+/// Formats a commit the way `log` does, with its date, followed by the
+/// patch it makes to its first parent unless `--no-patch` is given. Merges
+/// have no single diff, so they are shown without one.
+fn format_commit(
+  repo: &Repo,
+  hash: &str,
+  commit: &Commit,
+  opts: &Show,
+  colors: &Colors,
+) -> Result<String, String> {
+  let mut out = colors.paint("diff.commit", &format!("commit {}", hash));
+  out.push('\n');
+  let parents = commit.parents();
+  if parents.len() > 1 {
+    let short: Vec<&str> = parents.iter().map(|p| &p[..7]).collect();
+    out.push_str(&format!("Merge: {}\n", short.join(" ")));
+  }
+  if let Some(author) = commit.get("author").and_then(|line| Signature::parse(line)) {
+    out.push_str(&format!(
+      "Author: {}\nDate:   {}\n",
+      author.person(),
+      author.date()
+    ));
+  }
+  out.push('\n');
+  for line in commit.message().lines() {
+    out.push_str(&format!("    {}\n", line));
+  }
+  if opts.no_patch || parents.len() > 1 {
+    return Ok(out);
+  }
+
+  let parent_tree = match parents.first() {
+    Some(parent) => Some(find_object(repo, parent, Some("tree"), true)?),
+    None => None,
+  };
+  let changes = diff::detect_renames(
+    repo,
+    diff::diff_trees(repo, parent_tree.as_deref(), Some(commit.tree()))?,
+  )?;
+  let regex = match &opts.word_diff_regex {
+    Some(regex) => {
+      Some(Regex::new(regex).map_err(|_| format!("invalid regular expression: {}", regex))?)
+    }
+    None => None,
+  };
+  let style = match opts.word_diff.as_deref() {
+    Some("none") => None,
+    Some("color") => Some(Style::Color(colors)),
+    Some("porcelain") => Some(Style::Porcelain),
+    Some(_) => Some(Style::Plain),
+    None => regex.is_some().then_some(Style::Plain),
+  };
+  let words = style.map(|style| WordDiff {
+    style,
+    regex: regex.as_ref(),
+  });
+  if !changes.is_empty() {
+    out.push('\n');
+  }
+  let stats = Stats {
+    numstat: opts.numstat,
+    stat: opts.stat.map(|width| width.unwrap_or_else(pager::columns)),
+    shortstat: opts.shortstat,
+  };
+  if stats.any() {
+    out.push_str(&stats.render(repo, &changes, false)?);
+    return Ok(out);
+  }
+  let submodule_log = match &opts.submodule {
+    Some(format) => format == "log",
+    None => config(repo, "diff", "submodule").as_deref() == Some("log"),
+  };
+  for change in &changes {
+    let external = match opts.ext_diff {
+      true => patch::external(repo, change)?,
+      false => None,
+    };
+    if let Some(output) = external {
+      out.push_str(&String::from_utf8_lossy(&output));
+    } else if let (true, Some(log)) = (submodule_log, patch::submodule_log(repo, change)?) {
+      out.push_str(&log);
+    } else {
+      out.push_str(&patch::file_patch_with(repo, change, words, false)?);
+    }
+  }
+  Ok(out)
+}
+
+/// Writes out part of what is shown, as bytes, since blobs needn't be text.
+fn write(out: &mut impl Write, bytes: &[u8]) -> Result<(), String> {
+  out
+    .write_all(bytes)
+    .map_err(|e| format!("unable to write the object ({})", e))
+}
+
+fn config(repo: &Repo, section: &str, key: &str) -> Option<String> {
+  let section = repo.config.as_ref()?.section(Some(section))?;
+  section
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_owned())
+}
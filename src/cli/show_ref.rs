@@ -1,16 +1,154 @@
+use std::path::Path;
+use std::process;
+
 use clap::Args;
 
-use crate::{object::refs, repo::Repo};
+use git_rs_core::{
+  object::{self, refs},
+  repo::Repo,
+};
 
+/// List the refs in a local repository.
+///
+/// Prints the hash and name of each ref, or of those that match one of the
+/// patterns. A pattern matches a ref whose name ends with it, after a `/`,
+/// so `main` matches `refs/heads/main` and `refs/remotes/origin/main`.
+///
+/// Exits with 1 when no ref matches, which makes `show-ref -q` a check that a
+/// ref exists. With `--verify`, the refs must be named in full, and a
+/// missing one is fatal.
+///
+/// # Example
+/// ```bash
+/// $ git show-ref --tags -d
+/// 33663e6a3b6b1b1f5dc2d2b2b7ae9b3ffbbd3e10 refs/tags/v1.0
+/// 8f2c1d0e0f2d3c2b1a0f9e8d7c6b5a4f3e2d1c0b refs/tags/v1.0^{}
+/// $ git show-ref --verify -q refs/heads/main && echo yes
+/// yes
+/// ```
 #[derive(Args, Debug)]
-pub struct ShowRef;
+pub struct ShowRef {
+  /// Only show the refs that match one of these, or with `--verify`, the
+  /// refs named by them.
+  pub patterns: Vec<String>,
+
+  /// Only show tags (along with the branches, if `--heads` is given too).
+  #[clap(long)]
+  pub tags: bool,
+
+  /// Only show branches (along with the tags, if `--tags` is given too).
+  #[clap(long)]
+  pub heads: bool,
+
+  /// Show the refs named in full (`refs/heads/main` or `HEAD`), and fail if
+  /// one of them does not exist.
+  #[clap(long)]
+  pub verify: bool,
+
+  /// Show `HEAD` too, even if it doesn't match.
+  #[clap(long)]
+  pub head: bool,
+
+  /// Show what each annotated tag points at too, as `<name>^{}`.
+  #[clap(short, long)]
+  pub dereference: bool,
 
-/// Print out a list of hash, path pairs of all the refs in this repository.
-pub fn cmd_show_ref() -> Result<(), String> {
+  /// Only show the hashes, shortened to this many digits if given.
+  #[clap(
+    short = 's',
+    long = "hash",
+    value_name = "N",
+    min_values = 0,
+    require_equals = true
+  )]
+  pub hash: Option<Option<usize>>,
+
+  /// Shorten the hashes to this many digits (7 if not given).
+  #[clap(long, value_name = "N", min_values = 0, require_equals = true)]
+  pub abbrev: Option<Option<usize>>,
+
+  /// Show nothing, only exit with whether the refs exist.
+  #[clap(short, long)]
+  pub quiet: bool,
+}
+
+pub fn cmd_show_ref(opts: &ShowRef) -> Result<(), String> {
   let repo: Repo = Repo::default();
-  let refs = refs::collect(&repo, None);
-  for (k, v) in refs.iter() {
-    println!("{} {}", v, k)
+  let mut found = false;
+  if opts.verify {
+    if opts.patterns.is_empty() {
+      die("--verify requires a reference");
+    }
+    for name in &opts.patterns {
+      let hash = match name.starts_with("refs/") || name == "HEAD" {
+        true => refs::resolve(&repo, Path::new(name)).ok(),
+        false => None,
+      };
+      match hash {
+        Some(hash) => show(&repo, opts, name, &hash)?,
+        None if opts.quiet => process::exit(1),
+        None => die(&format!("'{}' - not a valid ref", name)),
+      }
+    }
+    return Ok(());
+  }
+
+  if opts.head {
+    if let Ok(hash) = refs::resolve(&repo, Path::new("HEAD")) {
+      show(&repo, opts, "HEAD", &hash)?;
+      found = true;
+    }
+  }
+  for (name, hash) in refs::collect(&repo, None) {
+    let kind_matches = match (opts.heads, opts.tags) {
+      (false, false) => true,
+      (heads, tags) => {
+        heads && name.starts_with("refs/heads/") || tags && name.starts_with("refs/tags/")
+      }
+    };
+    let name_matches = opts.patterns.is_empty()
+      || opts
+        .patterns
+        .iter()
+        .any(|pattern| name == *pattern || name.ends_with(&format!("/{}", pattern)));
+    if kind_matches && name_matches {
+      show(&repo, opts, &name, &hash)?;
+      found = true;
+    }
+  }
+  if !found {
+    process::exit(1);
   }
   Ok(())
 }
+
+/// Prints a ref as the options say, with the object its tag points at after
+/// it if asked to.
+fn show(repo: &Repo, opts: &ShowRef, name: &str, hash: &str) -> Result<(), String> {
+  if opts.quiet {
+    return Ok(());
+  }
+  let length = match (opts.hash, opts.abbrev) {
+    (Some(Some(length)), _) | (_, Some(Some(length))) => length.clamp(4, hash.len()),
+    (_, Some(None)) => 7,
+    _ => hash.len(),
+  };
+  match opts.hash {
+    Some(_) => println!("{}", &hash[..length]),
+    None => println!("{} {}", &hash[..length], name),
+  }
+  // as in git, the name of a peeled tag is shown even with --hash
+  if opts.dereference {
+    let peeled = object::peel(repo, hash, None)?;
+    if peeled != hash {
+      println!("{} {}^{{}}", &peeled[..length], name);
+    }
+  }
+  Ok(())
+}
+
+/// Says what went wrong and exits, the way git does when it can't go on.
+fn die(message: &str) -> ! {
+  println!("fatal: {}", message);
+  process::exit(128)
+}
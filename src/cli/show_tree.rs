@@ -1,27 +1,65 @@
 use clap::Args;
+use serde::Serialize;
 
-use crate::object::serializable::Unbox;
-use crate::object::tree::Tree;
-use crate::{object::read, repo::Repo};
+use git_rs_core::object::serializable::Unbox;
+use git_rs_core::object::tree::Tree;
+use git_rs_core::{object::read, repo::Repo};
 
 /// Print the contents of a tree object.
 #[derive(Args, Debug)]
 pub struct ShowTree {
   /// The object to show.
   pub object: String,
+
+  /// Print the entries as a JSON array of [`TreeItem`] objects.
+  #[clap(long)]
+  pub json: bool,
+}
+
+/// A tree entry as `ls-tree --json` prints it.
+#[derive(Serialize)]
+pub struct TreeItem {
+  /// The mode in octal, padded to six digits, eg. `100644`.
+  pub mode: String,
+
+  /// `blob`, `tree` or `commit` (for a submodule).
+  #[serde(rename = "type")]
+  pub object_type: String,
+  pub hash: String,
+
+  /// The name, with bytes that are not UTF-8 replaced.
+  pub path: String,
 }
 
 pub fn cmd_show_tree(opts: &ShowTree) -> Result<(), String> {
   let repo: Repo = Repo::default();
-  let tree_object = read(repo.clone(), &opts.object, Some("tree"))?;
+  let tree_object = read(&repo, &opts.object, Some("tree"))?;
   assert!(tree_object.format().eq("tree"));
   let tree: &Tree = tree_object.unbox::<Tree>()?;
 
+  if opts.json {
+    let items: Vec<TreeItem> = tree
+      .entries()
+      .iter()
+      .map(|item| TreeItem {
+        mode: item.mode.to_string(),
+        object_type: item.mode.object_type().to_string(),
+        hash: item.hash.clone(),
+        path: item.path.to_string(),
+      })
+      .collect();
+    println!(
+      "{}",
+      serde_json::to_string(&items).map_err(|e| e.to_string())?
+    );
+    return Ok(());
+  }
+
   for item in tree.entries() {
     println!(
       "{} {} {}\t{}",
       item.mode,
-      read(repo.clone(), &item.hash, None).unwrap().format(),
+      item.mode.object_type(),
       item.hash,
       item.path
     );
@@ -0,0 +1,67 @@
+use clap::Args;
+use std::fs;
+
+use git_rs_core::{index::Index, repo::Repo, sparse::Sparse};
+
+/// Reduce your working tree to a subset of tracked files.
+///
+/// `init` turns on a sparse checkout of just the files at the top of the
+/// working tree (or keeps the directories already chosen), `set` chooses the
+/// directories to have in the working tree and `list` shows them. Only cone
+/// mode is supported: everything inside a chosen directory is included,
+/// along with the files directly inside the directories leading to it.
+///
+/// The files left out are deleted from the working tree and marked
+/// skip-worktree in the index, so that `status` and `checkout` leave them
+/// alone.
+///
+/// # Example
+/// ```bash
+/// $ git sparse-checkout set src/lib docs
+/// $ git sparse-checkout list
+/// docs
+/// src/lib
+/// ```
+#[derive(Args, Debug)]
+pub struct SparseCheckout {
+  /// What to do.
+  #[clap(possible_values = &["init", "set", "list"])]
+  pub command: String,
+
+  /// The directories to include, relative to the top of the working tree.
+  pub dirs: Vec<String>,
+}
+
+pub fn cmd_sparse_checkout(opts: &SparseCheckout) -> Result<(), String> {
+  let mut repo: Repo = Repo::default();
+  repo.require_work_tree()?;
+  let sparse = match opts.command.as_str() {
+    "list" => {
+      match Sparse::read(&repo) {
+        Some(sparse) => sparse.dirs().for_each(|dir| println!("{}", dir)),
+        None => {
+          eprintln!("warning: this worktree is not sparse (sparse-checkout file may not exist)")
+        }
+      }
+      return Ok(());
+    }
+    "init" => Sparse::read(&repo).unwrap_or_else(|| Sparse::new([])),
+    _ => Sparse::new(opts.dirs.iter().map(|dir| dir.as_bytes())),
+  };
+
+  let info = repo.git_dir.join("info");
+  fs::create_dir_all(&info).map_err(|e| format!("{}: {}", info.display(), e))?;
+  let path = info.join("sparse-checkout");
+  fs::write(&path, sparse.to_bytes()).map_err(|e| format!("{}: {}", path.display(), e))?;
+  repo.set_worktree_config("core", "sparseCheckout", "true")?;
+  repo.set_worktree_config("core", "sparseCheckoutCone", "true")?;
+
+  let mut index = Index::read(&repo)?;
+  sparse.apply(&repo, &mut index)?;
+  // a sparse index can only collapse the directories left out once their
+  // trees are known
+  if index.entries().iter().all(|entry| entry.stage() == 0) {
+    index.write_tree(&repo)?;
+  }
+  index.write(&repo)
+}
@@ -0,0 +1,170 @@
+use std::io::{self, Write};
+
+use clap::Args;
+use serde::Serialize;
+
+use git_rs_core::{color::Colors, index::Index, repo::Repo, rev, status};
+
+use crate::cli::branch::UpstreamItem;
+
+/// Show the working tree status.
+///
+/// Lists the changes staged in the index, the changes in the working tree
+/// that are not, the paths with conflicts and the untracked files. By default
+/// the output is meant for people; `--porcelain` gives a stable format for
+/// scripts.
+///
+/// # Example
+/// ```bash
+/// $ git status --porcelain=v2 --branch
+/// # branch.oid 33663e62baecc8820390bed267126f631c5bb5dd
+/// # branch.head main
+/// 1 .M N... 100644 100644 100644 6178079822... 6178079822... src/main.rs
+/// ? notes.txt
+/// ```
+#[derive(Args, Debug)]
+pub struct Status {
+  /// Print the status in a stable format for scripts: `v1` (the default)
+  /// or `v2`.
+  #[clap(
+    long,
+    value_name = "VERSION",
+    min_values = 0,
+    require_equals = true,
+    default_missing_value = "v1",
+    possible_values = &["v1", "v2"]
+  )]
+  pub porcelain: Option<String>,
+
+  /// Show the branch and its upstream in the porcelain formats.
+  #[clap(short, long)]
+  pub branch: bool,
+
+  /// End entries with NUL rather than a newline and do not quote paths.
+  /// Implies `--porcelain` if no other format is given.
+  #[clap(short)]
+  pub z: bool,
+
+  /// Color the output: `always`, `never` or `auto` (when it goes to a
+  /// terminal). Without a value, `always`.
+  #[clap(
+    long,
+    value_name = "WHEN",
+    min_values = 0,
+    require_equals = true,
+    default_missing_value = "always",
+    possible_values = &["always", "never", "auto"]
+  )]
+  pub color: Option<String>,
+
+  /// Don't color the output.
+  #[clap(long, overrides_with = "color")]
+  pub no_color: bool,
+
+  /// Print the status as a [`StatusReport`] JSON object.
+  #[clap(long, conflicts_with_all = &["porcelain", "z"])]
+  pub json: bool,
+}
+
+/// The status as `status --json` prints it.
+#[derive(Serialize)]
+pub struct StatusReport {
+  /// The branch HEAD points at, without `refs/heads/`, or `None` when HEAD
+  /// is detached.
+  pub branch: Option<String>,
+
+  /// The commit HEAD points at, or `None` on a branch with no commits yet.
+  pub head: Option<String>,
+
+  /// The branch the current one tracks, if it has one.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub upstream: Option<UpstreamItem>,
+
+  /// The changed, conflicted and untracked paths, in the order
+  /// `--porcelain` lists them.
+  pub entries: Vec<StatusEntry>,
+}
+
+/// A path as `status --json` prints it.
+#[derive(Serialize)]
+pub struct StatusEntry {
+  /// The path, with bytes that are not UTF-8 replaced.
+  pub path: String,
+
+  /// How the index and the working tree changed, as the two letters
+  /// `--porcelain` shows but with `.` for no change: eg. `.M`, `UU` for a
+  /// conflict or `??` for an untracked file.
+  pub code: String,
+
+  /// The path a staged rename came from.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub renamed_from: Option<String>,
+}
+
+pub fn cmd_status(opts: &Status) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  repo.require_work_tree()?;
+  let mut index = Index::read(&repo)?;
+  let head = rev::parse(&repo, "HEAD").ok();
+  let status = status::Status::collect(&repo, &mut index, head.as_deref())?;
+  // saving the refreshed entries and untracked cache is only an
+  // optimization, so a failure to is no reason to fail
+  if index.is_changed() {
+    let _ = index.write(&repo);
+  }
+  if opts.json {
+    let report = status_report(&status);
+    println!(
+      "{}",
+      serde_json::to_string(&report).map_err(|e| e.to_string())?
+    );
+    return Ok(());
+  }
+  let format = match (&opts.porcelain, opts.z) {
+    (Some(format), _) => format.as_str(),
+    (None, true) => "v1",
+    (None, false) => "",
+  };
+  let out = match format {
+    "v1" => status.porcelain_v1(opts.branch, opts.z),
+    "v2" => status.porcelain_v2(&repo, opts.branch, opts.z)?,
+    _ => {
+      let color = opts.no_color.then_some("never").or(opts.color.as_deref());
+      status.long(&Colors::new(&repo, "status", color)?).into()
+    }
+  };
+  // with -z, paths are written as they are, whatever their encoding
+  io::stdout()
+    .write_all(&out)
+    .map_err(|e| format!("unable to write the status ({})", e))
+}
+
+/// Gathers the branch, upstream and paths of a status into a [`StatusReport`].
+fn status_report(status: &status::Status) -> StatusReport {
+  let mut entries: Vec<StatusEntry> = status
+    .entries()
+    .into_iter()
+    .map(|entry| StatusEntry {
+      path: entry.path.to_string(),
+      code: format!("{}{}", entry.staged, entry.unstaged),
+      renamed_from: entry.renamed_from.map(|from| from.to_string()),
+    })
+    .collect();
+  entries.extend(status.unmerged.iter().map(|unmerged| StatusEntry {
+    path: unmerged.path.to_string(),
+    code: unmerged.code().to_string(),
+    renamed_from: None,
+  }));
+  entries.sort_by(|a, b| a.path.cmp(&b.path));
+  entries.extend(status.untracked.iter().map(|path| StatusEntry {
+    path: path.to_string(),
+    code: "??".to_string(),
+    renamed_from: None,
+  }));
+  StatusReport {
+    branch: status.branch.clone(),
+    head: status.head.clone(),
+    upstream: status.upstream.as_ref().map(UpstreamItem::from),
+    entries,
+  }
+}
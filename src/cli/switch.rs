@@ -0,0 +1,298 @@
+use bstr::{BString, ByteSlice};
+use clap::Args;
+use std::fs;
+
+use git_rs_core::{
+  branch, cancel, diff,
+  identity::{Role, Signature},
+  index::Index,
+  object::{self, commit::Commit, find_object, refs, serializable::Unbox, tree},
+  progress::Meter,
+  repo::Repo,
+  rev::{self, walk::RevWalk},
+  worktree,
+};
+
+/// How many of the commits a detached HEAD leaves behind are listed.
+const MAX_ORPHANS_SHOWN: usize = 4;
+
+/// Switch branches.
+///
+/// Updates the index and working tree to match the branch and points HEAD at
+/// it. Local changes are carried over to the new branch when the files they
+/// touch are the same on both branches; otherwise the switch is refused unless
+/// `--discard-changes` is given. Switching away from a detached HEAD warns
+/// about the commits made on it that no branch reaches.
+///
+/// Switching to a branch that doesn't exist, when one remote has a branch
+/// of that name (or `checkout.defaultRemote` names one of those that do),
+/// creates it from the remote-tracking branch and sets it up to track it,
+/// unless `--no-guess` is given.
+///
+/// # Example
+/// ```bash
+/// $ git switch main
+/// $ git switch -c topic HEAD~3
+/// $ git switch --detach v1.0
+/// $ git switch topic
+/// branch 'topic' set up to track 'origin/topic'.
+/// Switched to a new branch 'topic'
+/// ```
+#[derive(Args, Debug)]
+pub struct Switch {
+  /// The branch to switch to (or the start point of a new branch).
+  pub branch: Option<String>,
+
+  /// Create a new branch with this name and switch to it.
+  #[clap(short, long, value_name = "NEW_BRANCH")]
+  pub create: Option<String>,
+
+  /// Switch to a commit without being on any branch.
+  #[clap(long, conflicts_with = "create")]
+  pub detach: bool,
+
+  /// Don't make a branch that doesn't exist from a remote's branch of the
+  /// same name.
+  #[clap(long)]
+  pub no_guess: bool,
+
+  /// Throw away local changes instead of refusing to switch.
+  #[clap(short = 'f', long, alias = "force")]
+  pub discard_changes: bool,
+
+  /// Show progress while updating files, even if standard error is not a
+  /// terminal.
+  #[clap(long, conflicts_with = "no-progress")]
+  pub progress: bool,
+
+  /// Never show progress.
+  #[clap(long)]
+  pub no_progress: bool,
+}
+
+pub fn cmd_switch(opts: &Switch) -> Result<(), String> {
+  let mut repo: Repo = Repo::default();
+
+  // the remote-tracking branch a branch that doesn't exist is made from
+  let mut guessed: Option<(String, String)> = None;
+
+  // Work out the commit to move to and the branch (if any) to put HEAD on.
+  let (target, branch) = match (&opts.create, &opts.branch) {
+    (Some(name), start) => {
+      let refname = format!("refs/heads/{}", name);
+      if refs::exists(&repo, &refname) {
+        return Err(format!("a branch named '{}' already exists", name));
+      }
+      let start = start.as_deref().unwrap_or("HEAD");
+      (
+        find_object(&repo, start, Some("commit"), true)?,
+        Some(refname),
+      )
+    }
+    (None, Some(name)) if opts.detach => (find_object(&repo, name, Some("commit"), true)?, None),
+    (None, Some(name)) => {
+      let refname = format!("refs/heads/{}", name);
+      if refs::lookup(&repo, &refname).is_none() && !opts.no_guess {
+        guessed = branch::remote_branch(&repo, name)?;
+      }
+      let tracking = guessed.as_ref().map(|(_, tracking)| tracking.as_str());
+      match refs::lookup(&repo, tracking.unwrap_or(&refname)) {
+        Some(hash) => (hash, Some(refname)),
+        None if rev::parse(&repo, name).is_ok() => {
+          return Err(format!(
+            "a branch is expected, got '{}'\nhint: use --detach to switch to a commit",
+            name
+          ))
+        }
+        None => return Err(format!("invalid reference: {}", name)),
+      }
+    }
+    (None, None) => return Err("missing branch or commit argument".to_string()),
+  };
+
+  let current = refs::read_symbolic(&repo, "HEAD");
+  let old_head = rev::parse(&repo, "HEAD").ok();
+  if branch.is_some() && branch == current && opts.create.is_none() {
+    println!(
+      "Already on '{}'",
+      opts.branch.as_deref().unwrap_or_default()
+    );
+    return Ok(());
+  }
+
+  let head_files = tree::flatten(&repo, &object::head_tree(&repo)?)?;
+  let mut target_files = tree::flatten(&repo, &find_object(&repo, &target, Some("tree"), true)?)?;
+  let index = Index::read(&repo)?;
+
+  if !opts.discard_changes {
+    // Local changes survive the switch when the file is the same on both
+    // sides; changes to files that differ would be overwritten.
+    let staged = index.files();
+    let mut changed: Vec<BString> = diff::compare(&head_files, &staged)
+      .into_iter()
+      .map(|change| change.path)
+      .collect();
+    changed.extend(
+      worktree::unstaged_changes(&repo, &index)?
+        .into_iter()
+        .map(|c| c.1),
+    );
+    changed.sort();
+    changed.dedup();
+
+    let conflicts: Vec<&BString> = changed
+      .iter()
+      .filter(|path| head_files.get(*path) != target_files.get(*path))
+      .collect();
+    if !conflicts.is_empty() {
+      return Err(format!(
+        "Your local changes to the following files would be overwritten by checkout:\n\t{}\nPlease commit your changes or stash them before you switch branches.",
+        bstr::join("\n\t", &conflicts).as_bstr()
+      ));
+    }
+    let untracked: Vec<&BString> = target_files
+      .keys()
+      .filter(|path| index.get(path).is_none() && !head_files.contains_key(*path))
+      .filter(|path| fs::symlink_metadata(repo.work_tree_path(path)).is_ok())
+      .collect();
+    if !untracked.is_empty() {
+      return Err(format!(
+        "The following untracked working tree files would be overwritten by checkout:\n\t{}\nPlease move or remove them before you switch branches.",
+        bstr::join("\n\t", &untracked).as_bstr()
+      ));
+    }
+
+    // Carry the staged versions of changed files over to the new branch.
+    for path in &changed {
+      match staged.get(path) {
+        Some(entry) => target_files.insert(path.clone(), entry.clone()),
+        None => target_files.remove(path),
+      };
+    }
+  }
+
+  let show_progress = match (opts.progress, opts.no_progress) {
+    (true, _) => Some(true),
+    (_, true) => Some(false),
+    _ => None,
+  };
+  let mut progress = Meter::boxed(show_progress);
+  worktree::switch_files(
+    &repo,
+    &index,
+    &target_files,
+    opts.discard_changes,
+    progress.as_mut(),
+    &cancel::on_interrupt(),
+  )?
+  .write(&repo)?;
+  let create = opts.create.is_some() || guessed.is_some();
+  match &branch {
+    Some(refname) => {
+      if create {
+        refs::update(&repo, refname, &target)?;
+      }
+      let name = refname.trim_start_matches("refs/heads/");
+      if let Some((remote, tracking)) = &guessed {
+        branch::set_upstream(&mut repo, name, remote, &format!("refs/heads/{}", name))?;
+        println!(
+          "branch '{}' set up to track '{}'.",
+          name,
+          tracking.trim_start_matches("refs/remotes/")
+        );
+      }
+      refs::update_symbolic(&repo, "HEAD", refname)?;
+      if create {
+        println!("Switched to a new branch '{}'", name);
+      } else {
+        println!("Switched to branch '{}'", name);
+      }
+    }
+    None => {
+      refs::detach_head(&repo, &target)?;
+      println!(
+        "HEAD is now at {} {}",
+        &target[..7],
+        subject(&repo, &target)?
+      );
+    }
+  }
+
+  // without an identity to stamp it with, the move goes unrecorded
+  if let Ok(identity) = Signature::current(&repo, Role::Committer) {
+    let from = match (&current, &old_head) {
+      (Some(branch), _) => branch.trim_start_matches("refs/heads/").to_owned(),
+      (None, head) => head.clone().unwrap_or_default(),
+    };
+    let to = opts
+      .create
+      .as_deref()
+      .or(opts.branch.as_deref())
+      .unwrap_or_default();
+    let reason = format!("checkout: moving from {} to {}", from, to);
+    refs::append_log(
+      &repo,
+      "HEAD",
+      old_head.as_deref(),
+      &target,
+      &identity.to_string(),
+      &reason,
+    )?;
+  }
+  match (&current, &old_head) {
+    (None, Some(old)) if *old != target => warn_orphaned(&repo, old, &target)?,
+    _ => (),
+  }
+  Ok(())
+}
+
+/// Warns about the commits a detached HEAD is leaving behind, that no ref
+/// can reach any more, since nothing else would remember them.
+fn warn_orphaned(repo: &Repo, old: &str, new: &str) -> Result<(), String> {
+  let mut walk = RevWalk::new(repo);
+  walk.push(old);
+  walk.hide(new);
+  for hash in refs::collect(repo, None).values() {
+    if let Ok(commit) = object::peel(repo, hash, Some("commit")) {
+      walk.hide(&commit);
+    }
+  }
+  let lost = walk.run()?;
+  if lost.is_empty() {
+    return Ok(());
+  }
+
+  let mut lines = String::new();
+  for hash in lost.iter().take(MAX_ORPHANS_SHOWN) {
+    lines.push_str(&format!("  {} {}\n", &hash[..7], subject(repo, hash)?));
+  }
+  if lost.len() > MAX_ORPHANS_SHOWN {
+    lines.push_str(&format!(
+      " ... and {} more.\n",
+      lost.len() - MAX_ORPHANS_SHOWN
+    ));
+  }
+  let (commits, them) = match lost.len() {
+    1 => ("commit", "it"),
+    _ => ("commits", "them"),
+  };
+  eprint!(
+    "Warning: you are leaving {} {} behind, not connected to\n\
+     any of your branches:\n\n{}\n\
+     If you want to keep {} by creating a new branch, this may be a good time\n\
+     to do so with:\n\n git branch <new-branch-name> {}\n\n",
+    lost.len(),
+    commits,
+    lines,
+    them,
+    &old[..7]
+  );
+  Ok(())
+}
+
+/// The first line of a commit's message.
+fn subject(repo: &Repo, hash: &str) -> Result<String, String> {
+  let commit = object::read(repo, hash, Some("commit"))?;
+  let message = commit.unbox::<Commit>()?.message();
+  Ok(message.lines().next().unwrap_or_default().to_owned())
+}
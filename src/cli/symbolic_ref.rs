@@ -0,0 +1,123 @@
+use std::path::Path;
+use std::process;
+
+use clap::Args;
+
+use git_rs_core::{
+  identity::{Role, Signature},
+  object::refs,
+  repo::Repo,
+};
+
+/// Read, modify and delete symbolic refs.
+///
+/// Given only a name, prints the ref that symbolic ref points at, following
+/// symbolic refs to symbolic refs to the last of them. Given a ref too,
+/// points the symbolic ref at it. `HEAD` may only point at refs under
+/// `refs/`.
+///
+/// # Example
+/// ```bash
+/// $ git symbolic-ref --short HEAD
+/// main
+/// $ git symbolic-ref HEAD refs/heads/topic
+/// ```
+#[derive(Args, Debug)]
+pub struct SymbolicRef {
+  /// The symbolic ref, eg. `HEAD`.
+  pub name: String,
+
+  /// The ref to point it at.
+  #[clap(conflicts_with_all = &["delete", "short", "no-recurse"])]
+  pub target: Option<String>,
+
+  /// The reason for the change, recorded in the reflog of the symbolic ref.
+  #[clap(short = 'm', value_name = "REASON", requires = "target")]
+  pub message: Option<String>,
+
+  /// Delete the symbolic ref.
+  #[clap(short, long)]
+  pub delete: bool,
+
+  /// Print the ref shortened, eg. `main` for `refs/heads/main`.
+  #[clap(long)]
+  pub short: bool,
+
+  /// Print what the symbolic ref itself points at, even if that is another
+  /// symbolic ref.
+  #[clap(long)]
+  pub no_recurse: bool,
+
+  /// Don't say so when the ref is not a symbolic ref (such as a detached
+  /// `HEAD`), only exit with 1.
+  #[clap(short, long)]
+  pub quiet: bool,
+}
+
+pub fn cmd_symbolic_ref(opts: &SymbolicRef) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let name = &opts.name;
+  if opts.delete {
+    if name == "HEAD" {
+      die(&format!("deleting '{}' is not allowed", name));
+    }
+    if refs::read_symbolic(&repo, name).is_none() {
+      die(&format!("Cannot delete {}, not a symbolic ref", name));
+    }
+    return refs::delete(&repo, name);
+  }
+
+  let target = match &opts.target {
+    Some(target) => target,
+    None => {
+      let mut target = match refs::read_symbolic(&repo, name) {
+        Some(target) => target,
+        None if opts.quiet => process::exit(1),
+        None => die(&format!("ref {} is not a symbolic ref", name)),
+      };
+      if !opts.no_recurse {
+        let mut depth = 0;
+        while let Some(next) = refs::read_symbolic(&repo, &target) {
+          depth += 1;
+          if depth > 5 {
+            die(&format!("No such ref: {}", name));
+          }
+          target = next;
+        }
+      }
+      match opts.short {
+        true => println!("{}", refs::shorten(&repo, &target)),
+        false => println!("{}", target),
+      }
+      return Ok(());
+    }
+  };
+
+  if name == "HEAD" && !target.starts_with("refs/") {
+    die("Refusing to point HEAD outside of refs/");
+  }
+  if !refs::check_name(target) {
+    die(&format!(
+      "Refusing to set '{}' to invalid ref '{}'",
+      name, target
+    ));
+  }
+  let old = refs::resolve(&repo, Path::new(name)).ok();
+  // without an identity to stamp it with, the move goes unrecorded
+  let committer = Signature::current(&repo, Role::Committer).ok();
+  refs::update_symbolic(&repo, name, target)?;
+  if let (Some(message), Some(committer)) = (&opts.message, committer) {
+    // a branch yet to be born has no commit to record
+    if let Ok(new) = refs::resolve(&repo, Path::new(name)) {
+      let committer = committer.to_string();
+      refs::append_log(&repo, name, old.as_deref(), &new, &committer, message)?;
+    }
+  }
+  Ok(())
+}
+
+/// Says what went wrong and exits, the way git does when it can't go on.
+fn die(message: &str) -> ! {
+  println!("fatal: {}", message);
+  process::exit(128)
+}
@@ -1,16 +1,13 @@
-use std::{fs::File, io::Write, path::Path};
+use std::path::Path;
 
 use clap::Args;
 
-use crate::{
+use git_rs_core::{
+  identity::{Role, Signature},
   object::refs,
-  object::{
-    self,
-    mail_map::{self, MailMap},
-    serializable::Serializable,
-    tag::Tag as TagObject,
-  },
-  repo::{repo_dir, Repo},
+  object::{self, tag::TagBuilder},
+  repo::Repo,
+  rev::walk,
 };
 
 /// List and create tags.
@@ -26,56 +23,81 @@ pub struct Tag {
   /// Creates an annotated tag.
   #[clap(short, long)]
   pub annotated: bool,
+
+  /// Only list the tags that contain this commit (HEAD if not given).
+  #[clap(
+    long,
+    value_name = "COMMIT",
+    min_values = 0,
+    max_values = 1,
+    default_missing_value = "HEAD",
+    conflicts_with = "name"
+  )]
+  pub contains: Option<String>,
 }
 
 pub fn cmd_tag(opts: &Tag) -> Result<(), String> {
   let repo: Repo = Repo::default();
-  if opts.name.is_none() {
-    list_all_tags(&repo);
-  } else if opts.annotated {
-    let tag_name = &opts.name.as_ref().unwrap();
-    let hash = create_annotated_tag(&repo, tag_name, &opts.object)?;
-    create_simple_tag(&repo, tag_name, &hash);
-  } else {
-    create_simple_tag(&repo, opts.name.as_ref().unwrap(), &opts.object);
+  match &opts.name {
+    None => {
+      let contains = match &opts.contains {
+        Some(name) => Some(
+          object::find_object(&repo, name, Some("commit"), true)
+            .map_err(|_| format!("malformed object name {}", name))?,
+        ),
+        None => None,
+      };
+      list_all_tags(&repo, contains.as_deref())?
+    }
+    Some(tag_name) if opts.annotated => {
+      let hash = create_annotated_tag(&repo, tag_name, &opts.object)?;
+      create_simple_tag(&repo, tag_name, &hash)?;
+    }
+    Some(tag_name) => create_simple_tag(&repo, tag_name, &opts.object)?,
   }
   Ok(())
 }
 
-/// Lists all tags in the given repository.
-fn list_all_tags(repo: &Repo) {
-  let path_buf = repo_dir(&repo.git_dir, &["refs", "tags"], true).unwrap();
-  let refs = refs::collect(repo, Some(path_buf.as_path()));
+/// Lists all tags in the given repository, or only those that contain the
+/// commit, if one is given. Tags of things other than commits contain no
+/// commits.
+fn list_all_tags(repo: &Repo, contains: Option<&str>) -> Result<(), String> {
+  let mut refs: Vec<(String, String)> = refs::collect(repo, Some(Path::new("refs/tags")))
+    .into_iter()
+    .collect();
+  if let Some(commit) = contains {
+    refs.retain_mut(|(_, hash)| match object::peel(repo, hash, Some("commit")) {
+      Ok(peeled) => {
+        *hash = peeled;
+        true
+      }
+      Err(_) => false,
+    });
+    let tips: Vec<String> = refs.iter().map(|(_, hash)| hash.clone()).collect();
+    let mut found = walk::contains(repo, commit, &tips)?.into_iter();
+    refs.retain(|_| found.next().unwrap_or(false));
+  }
   let prefix = Path::new("refs/tags/");
-  for k in refs.keys() {
+  for (k, _) in &refs {
     let tag_name = Path::new(k).strip_prefix(prefix).expect("strip prefix");
     println!("{}", tag_name.to_string_lossy())
   }
+  Ok(())
 }
 
-fn create_simple_tag(repo: &Repo, name: &String, object: &String) {
-  let path = repo_dir(&repo.git_dir, &["refs", "tags"], true).unwrap();
-  let mut file = File::create(path.join(name)).expect("create failed");
-  if object.eq("HEAD") {
-    let mut payload = refs::resolve(repo, &repo.git_dir.join("HEAD")).expect("resolve");
-    payload.push('\n');
-    file.write_all(payload.as_bytes()).expect("write failed");
-  } else {
-    let mut payload: String = String::from(object);
-    payload.push('\n');
-    file.write_all(payload.as_bytes()).expect("write failed");
+fn create_simple_tag(repo: &Repo, name: &str, object: &str) -> Result<(), String> {
+  let hash = match object {
+    "HEAD" => refs::resolve(repo, Path::new("HEAD"))?,
+    object => object.to_owned(),
   };
+  refs::update(repo, &format!("refs/tags/{}", name), &hash)
 }
 
-fn create_annotated_tag(repo: &Repo, name: &String, object: &String) -> Result<String, String> {
-  // TODO: Create git objects with signature, name, email, and message (editor?)
-  let mut mail_map: MailMap = MailMap::new();
-  mail_map.map.insert("commit".to_owned(), object.to_owned());
-  mail_map.map.insert("tag".to_owned(), name.to_owned());
-  mail_map
-    .map
-    .insert("".to_owned(), "\n".to_owned());
-  let payload = mail_map::map_to_bytes(&mail_map.map);
-  let new_tag: Box<dyn Serializable> = Box::new(TagObject::new(repo.clone(), &payload));
-  object::write(&*new_tag, false)
+fn create_annotated_tag(repo: &Repo, name: &str, object: &str) -> Result<String, String> {
+  // TODO: Ask for a message (editor?)
+  TagBuilder::new()
+    .object(&object::find_object(repo, object, None, false)?)
+    .name(name)
+    .tagger(Signature::current(repo, Role::Committer)?)
+    .write(repo)
 }
@@ -0,0 +1,328 @@
+use bstr::BString;
+use clap::Args;
+use std::fs;
+use std::io::{self, BufRead};
+
+use git_rs_core::{
+  index::{self, untracked::UntrackedCache, Index, IndexEntry, ASSUME_VALID, SKIP_WORKTREE},
+  object::mode::Mode,
+  parallel,
+  repo::Repo,
+};
+
+/// Register file contents in the working tree to the index.
+///
+/// Each path given is re-hashed from the working tree and its index entry
+/// updated. New files are only added with `--add` and missing files are only
+/// dropped with `--remove`. Entries can also be written directly with
+/// `--cacheinfo` or, one per line, from standard input with `--index-info`,
+/// using the same `<mode> <hash>\t<path>` lines that `ls-files -s` prints.
+///
+/// # Example
+/// ```bash
+/// $ git update-index --add new.txt
+/// $ git update-index --add --cacheinfo 100644,e69de29bb2d1d6434b8b29ae775ad8c2e48c5391,empty.txt
+/// $ git update-index --skip-worktree config.local
+/// $ git update-index --untracked-cache
+/// ```
+#[derive(Args, Debug)]
+pub struct UpdateIndex {
+  /// Add files that are not in the index yet.
+  #[clap(long)]
+  pub add: bool,
+
+  /// Remove files that are in the index but missing from the working tree.
+  #[clap(long)]
+  pub remove: bool,
+
+  /// Remove the files from the index even if they still exist.
+  #[clap(long)]
+  pub force_remove: bool,
+
+  /// Insert an entry directly, given as `<mode>,<hash>,<path>`.
+  #[clap(long, value_name = "MODE,HASH,PATH", multiple_occurrences = true)]
+  pub cacheinfo: Vec<String>,
+
+  /// Read entries to insert from standard input.
+  #[clap(long)]
+  pub index_info: bool,
+
+  /// Mark the files so their working tree copies are assumed unchanged.
+  #[clap(long, conflicts_with = "no-assume-unchanged")]
+  pub assume_unchanged: bool,
+
+  /// Clear the assume-unchanged mark.
+  #[clap(long)]
+  pub no_assume_unchanged: bool,
+
+  /// Mark the files so their working tree copies are left alone.
+  #[clap(long, conflicts_with = "no-skip-worktree")]
+  pub skip_worktree: bool,
+
+  /// Clear the skip-worktree mark.
+  #[clap(long)]
+  pub no_skip_worktree: bool,
+
+  /// Add the untracked cache to the index, so that `status` need not read
+  /// the directories that have not changed.
+  #[clap(long, conflicts_with = "no-untracked-cache")]
+  pub untracked_cache: bool,
+
+  /// Drop the untracked cache from the index.
+  #[clap(long)]
+  pub no_untracked_cache: bool,
+
+  /// Write the index as a split index, holding only what changed since a
+  /// shared index that holds the rest.
+  #[clap(long, conflicts_with = "no-split-index")]
+  pub split_index: bool,
+
+  /// Write the index as a single file again.
+  #[clap(long)]
+  pub no_split_index: bool,
+
+  /// Report what is being added and removed.
+  #[clap(long)]
+  pub verbose: bool,
+
+  /// The files to update.
+  pub paths: Vec<String>,
+}
+
+pub fn cmd_update_index(opts: &UpdateIndex) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let mut index = Index::read(&repo)?;
+
+  if opts.untracked_cache && index.untracked.is_none() {
+    index.untracked = Some(UntrackedCache::new(&repo));
+    index.mark_changed();
+    if opts.verbose {
+      println!("Untracked cache enabled for '{}'", repo.work_tree.display());
+    }
+  } else if opts.no_untracked_cache && index.untracked.is_some() {
+    index.untracked = None;
+    index.mark_changed();
+    if opts.verbose {
+      println!("Untracked cache disabled");
+    }
+  }
+
+  if opts.split_index || opts.no_split_index {
+    index.split = opts.split_index;
+    index.mark_changed();
+  }
+
+  for info in &opts.cacheinfo {
+    let fields: Vec<&str> = info.splitn(3, ',').collect();
+    if fields.len() != 3 {
+      return Err(format!(
+        "option 'cacheinfo' expects <mode>,<sha1>,<path>, got '{}'",
+        info
+      ));
+    }
+    if !opts.add && !index.contains(fields[2]) {
+      return Err(format!(
+        "{}: cannot add to the index - missing --add option?\ngit update-index: --cacheinfo cannot add {}",
+        fields[2], fields[2]
+      ));
+    }
+    add_cacheinfo(&mut index, fields[0], fields[1], fields[2], 0)?;
+    if opts.verbose {
+      println!("add '{}'", fields[2]);
+    }
+  }
+
+  if opts.index_info {
+    for line in io::stdin().lock().lines() {
+      let line = line.map_err(|e| e.to_string())?;
+      index_info(&mut index, &line)?;
+    }
+  }
+
+  let marking = opts.assume_unchanged
+    || opts.no_assume_unchanged
+    || opts.skip_worktree
+    || opts.no_skip_worktree;
+  let paths = opts
+    .paths
+    .iter()
+    .map(|arg| repo.worktree_path(arg))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  // the files that will be added are hashed up front, on `index.threads`
+  // threads when there are a lot of them
+  let workers = match paths.len() >= parallel::THRESHOLD {
+    true => parallel::workers(&repo, "index", "threads"),
+    false => 1,
+  };
+  let mut hashes = match marking || opts.force_remove {
+    true => Vec::new(),
+    false => parallel::map(
+      &paths,
+      workers,
+      |path| {
+        let metadata = fs::symlink_metadata(repo.work_tree_path(path));
+        let is_file = metadata.is_ok_and(|metadata| !metadata.is_dir());
+        let added = opts.add || index.get(path).is_some();
+        (is_file && added).then(|| {
+          let indexed = index.get(path).map(|entry| entry.hash.as_str());
+          index::hash_file(&repo, path, indexed, true)
+        })
+      },
+      |_, _| (),
+    ),
+  }
+  .into_iter();
+
+  for path in &paths {
+    if marking {
+      mark(&mut index, path, opts)?;
+    } else {
+      update_path(&repo, &mut index, path, hashes.next().flatten(), opts)?;
+    }
+  }
+  index.write(&repo)
+}
+
+/// Re-hashes a file from the working tree into the index, or removes it.
+///
+/// `hash` is the result of hashing the file already, if it was.
+fn update_path(
+  repo: &Repo,
+  index: &mut Index,
+  path: &BString,
+  hash: Option<Result<String, String>>,
+  opts: &UpdateIndex,
+) -> Result<(), String> {
+  if opts.force_remove {
+    if index.remove(path) && opts.verbose {
+      println!("remove '{}'", path);
+    }
+    return Ok(());
+  }
+
+  let metadata = match fs::symlink_metadata(repo.work_tree_path(path)) {
+    Ok(metadata) if !metadata.is_dir() => metadata,
+    Ok(_) => {
+      return Err(format!(
+        "{}: is a directory - add files inside instead",
+        path
+      ))
+    }
+    Err(_) if opts.remove => {
+      if index.remove(path) && opts.verbose {
+        println!("remove '{}'", path);
+      }
+      return Ok(());
+    }
+    Err(_) => {
+      return Err(format!(
+        "{}: does not exist and --remove not passed\nUnable to process path {}",
+        path, path
+      ))
+    }
+  };
+  // an unmerged path is in the index, at its conflict stages
+  let tracked = index.entries().iter().any(|entry| entry.path == *path);
+  if !tracked && !opts.add {
+    return Err(format!(
+      "{}: cannot add to the index - missing --add option?\nUnable to process path {}",
+      path, path
+    ));
+  }
+
+  let hash = match hash {
+    Some(hash) => hash?,
+    None => {
+      let indexed = index.get(path).map(|entry| entry.hash.as_str());
+      index::hash_file(repo, path, indexed, true)?
+    }
+  };
+  let mut entry = IndexEntry::from_metadata(path, &hash, &metadata);
+  if let Some(old) = index.get(path) {
+    entry.flags |= old.flags & ASSUME_VALID;
+    entry.extended_flags = old.extended_flags;
+  }
+  index.add(entry);
+  if opts.verbose {
+    println!("add '{}'", path);
+  }
+  Ok(())
+}
+
+/// Sets or clears the assume-unchanged and skip-worktree bits of an entry.
+fn mark(index: &mut Index, path: &BString, opts: &UpdateIndex) -> Result<(), String> {
+  let mut entry = match index.get(path) {
+    Some(entry) => entry.clone(),
+    None => return Err(format!("Unable to mark file {}", path)),
+  };
+  if opts.assume_unchanged {
+    entry.flags |= ASSUME_VALID;
+  } else if opts.no_assume_unchanged {
+    entry.flags &= !ASSUME_VALID;
+  }
+  if opts.skip_worktree {
+    entry.extended_flags |= SKIP_WORKTREE;
+  } else if opts.no_skip_worktree {
+    entry.extended_flags &= !SKIP_WORKTREE;
+  }
+  index.add(entry);
+  Ok(())
+}
+
+/// Applies one line of `--index-info` input.
+///
+/// Three forms are accepted: `<mode> <hash>\t<path>` as printed by
+/// `ls-files -s` without a stage, `<mode> <hash> <stage>\t<path>`, and
+/// `<mode> <type> <hash>\t<path>` as printed by `ls-tree`. A mode of `0`
+/// removes the path instead.
+fn index_info(index: &mut Index, line: &str) -> Result<(), String> {
+  let (meta, path) = match line.split_once('\t') {
+    Some(parts) => parts,
+    None => return Err(format!("malformed index info {}", line)),
+  };
+  let fields: Vec<&str> = meta.split(' ').collect();
+  let (mode, hash, stage) = match fields.as_slice() {
+    [mode, hash] => (*mode, *hash, 0),
+    [mode, hash, stage] if stage.len() == 1 => {
+      let stage = stage
+        .parse::<u16>()
+        .map_err(|_| format!("malformed index info {}", line))?;
+      (*mode, *hash, stage)
+    }
+    [mode, _, hash] => (*mode, *hash, 0),
+    _ => return Err(format!("malformed index info {}", line)),
+  };
+  if mode == "0" {
+    index.remove(path);
+    return Ok(());
+  }
+  add_cacheinfo(index, mode, hash, path, stage)
+}
+
+/// Adds an entry with no stat data from its mode, hash and path.
+fn add_cacheinfo(
+  index: &mut Index,
+  mode: &str,
+  hash: &str,
+  path: &str,
+  stage: u16,
+) -> Result<(), String> {
+  let mode = Mode::from_octal(mode)
+    .ok()
+    .filter(|mode| *mode != Mode::Directory)
+    .ok_or_else(|| format!("git update-index: invalid mode {}", mode))?;
+  if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+    return Err(format!("git update-index: invalid object id {}", hash));
+  }
+  if path.is_empty()
+    || path.starts_with('/')
+    || path
+      .split('/')
+      .any(|c| c == ".." || c == "." || c == ".git")
+  {
+    return Err(format!("Invalid path '{}'", path));
+  }
+  index.add(IndexEntry::new(path, mode, hash).with_stage(stage));
+  Ok(())
+}
@@ -0,0 +1,468 @@
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process;
+
+use clap::Args;
+
+use git_rs_core::{
+  identity::{Role, Signature},
+  object::{
+    find_object,
+    refs::{self, LogEntry, Transaction, Update, NULL_HASH},
+  },
+  repo::Repo,
+};
+
+use crate::cli::mktree::unquote;
+
+/// Update the object name stored in a ref safely.
+///
+/// Points a ref at an object, checking first that it points at `<old>`, if
+/// given (or that it does not exist, if `<old>` is 40 zeros). With `-d`,
+/// deletes the ref instead. Symbolic refs are followed, so the branch that
+/// `HEAD` is on is what moves, unless `--no-deref` is given.
+///
+/// With `--stdin`, reads changes to make all at once, or not at all, one per
+/// line:
+///
+/// ```text
+/// update SP <ref> SP <new> [SP <old>] LF
+/// create SP <ref> SP <new> LF
+/// delete SP <ref> [SP <old>] LF
+/// verify SP <ref> [SP <old>] LF
+/// option SP no-deref LF
+/// ```
+///
+/// These go into a transaction which is made when the input ends. `start`
+/// begins one explicitly, `prepare` locks its refs and checks them, and
+/// `commit` or `abort` ends it, each saying `<command>: ok` when done, so
+/// that another program can take part in the transaction. With `-z`, each
+/// command, ref and value ends with a NUL instead, and an empty `<old>` is
+/// one that is not given.
+///
+/// # Example
+/// ```bash
+/// $ git update-ref refs/heads/main HEAD~1 HEAD
+/// $ printf 'start\ncreate refs/tags/v1 HEAD\nprepare\ncommit\n' | git update-ref --stdin
+/// start: ok
+/// prepare: ok
+/// commit: ok
+/// ```
+#[derive(Args, Debug)]
+pub struct UpdateRef {
+  /// The ref to update.
+  #[clap(required_unless_present = "stdin")]
+  pub name: Option<String>,
+
+  /// What to point the ref at, then what it must point at now. With `-d`,
+  /// only the second.
+  #[clap(max_values = 2)]
+  pub values: Vec<String>,
+
+  /// The reason for the update, recorded in the reflog.
+  #[clap(short = 'm', value_name = "REASON")]
+  pub message: Option<String>,
+
+  /// Delete the ref.
+  #[clap(short = 'd')]
+  pub delete: bool,
+
+  /// Update a symbolic ref itself, rather than the ref it points at.
+  #[clap(long)]
+  pub no_deref: bool,
+
+  /// Read updates from standard input.
+  #[clap(long, conflicts_with_all = &["name", "delete"])]
+  pub stdin: bool,
+
+  /// Read NUL-terminated commands and values.
+  #[clap(short = 'z', requires = "stdin")]
+  pub nul_terminated: bool,
+
+  /// Keep a reflog of the ref, even if it is not one that gets one anyway.
+  #[clap(long)]
+  pub create_reflog: bool,
+}
+
+pub fn cmd_update_ref(opts: &UpdateRef) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let result = match opts.stdin {
+    true => update_from_stdin(&repo, opts),
+    false => update(&repo, opts),
+  };
+  if let Err(err) = result {
+    println!("fatal: {}", err);
+    process::exit(128);
+  }
+  Ok(())
+}
+
+/// Makes the one update given on the command line.
+fn update(repo: &Repo, opts: &UpdateRef) -> Result<(), String> {
+  let name = opts.name.as_deref().unwrap_or_default();
+  let value = |text: &str| value(repo, text).map_err(|_| format!("{}: not a valid SHA1", text));
+  let (new, old) = match (opts.delete, opts.values.as_slice()) {
+    (true, []) => (NULL_HASH.to_string(), None),
+    (true, [old]) => (NULL_HASH.to_string(), Some(value(old)?)),
+    (false, [new]) => (value(new)?, None),
+    (false, [new, old]) => (value(new)?, Some(value(old)?)),
+    _ => {
+      eprintln!("usage: git update-ref [<options>] -d <refname> [<old-val>]");
+      eprintln!("   or: git update-ref [<options>]    <refname> <new-val> [<old-val>]");
+      eprintln!("   or: git update-ref [<options>] --stdin [-z]");
+      process::exit(129);
+    }
+  };
+  let request = Request {
+    update: Update {
+      name: name.to_owned(),
+      new: Some(new),
+      old,
+    },
+    no_deref: opts.no_deref,
+  };
+  let result = prepare(repo, opts, &[request]).and_then(commit);
+  match result {
+    Err(err) if opts.delete => {
+      println!("error: {}", err);
+      process::exit(1);
+    }
+    Err(err) => Err(format!("update_ref failed for ref '{}': {}", name, err)),
+    Ok(()) => Ok(()),
+  }
+}
+
+/// Where a transaction read from standard input is at.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+  /// Updates are being added, to a transaction that was not started
+  /// explicitly.
+  Open,
+  Started,
+  Prepared,
+
+  /// The transaction was committed or aborted, and only `start` can come
+  /// next.
+  Closed,
+}
+
+/// Reads commands from standard input and carries them out.
+fn update_from_stdin(repo: &Repo, opts: &UpdateRef) -> Result<(), String> {
+  let stdin = io::stdin();
+  let mut input = Input {
+    reader: stdin.lock(),
+    nul_terminated: opts.nul_terminated,
+  };
+  let mut state = State::Open;
+  let mut requests = Vec::new();
+  let mut prepared = None;
+  let mut no_deref = opts.no_deref;
+  while let Some((verb, rest)) = input.command()? {
+    let next = match verb.as_str() {
+      "start" => State::Started,
+      "prepare" => State::Prepared,
+      "commit" | "abort" => State::Closed,
+      _ => State::Open,
+    };
+    match state {
+      State::Started if next == State::Started => {
+        return Err("cannot restart ongoing transaction".to_string())
+      }
+      State::Prepared if next != State::Closed => {
+        return Err("prepared transactions can only be closed".to_string())
+      }
+      State::Closed if next != State::Started => return Err("transaction is closed".to_string()),
+      _ => (),
+    }
+
+    match verb.as_str() {
+      "start" => (),
+      "prepare" => prepared = Some(prepare(repo, opts, &std::mem::take(&mut requests))?),
+      "commit" => {
+        let prepared = match prepared.take() {
+          Some(prepared) => prepared,
+          None => prepare(repo, opts, &std::mem::take(&mut requests))?,
+        };
+        commit(prepared)?;
+      }
+      "abort" => {
+        prepared = None;
+        requests.clear();
+      }
+      "option" => match rest.as_str() {
+        "no-deref" => no_deref = true,
+        option => return Err(format!("option unknown: {}", option)),
+      },
+      "update" | "create" | "delete" | "verify" => {
+        let update = input.update(repo, &verb, &rest)?;
+        requests.push(Request {
+          update,
+          no_deref: std::mem::replace(&mut no_deref, opts.no_deref),
+        });
+      }
+      _ if rest.is_empty() => return Err(format!("unknown command: {}", verb)),
+      _ => return Err(format!("unknown command: {} {}", verb, rest)),
+    }
+    if next != State::Open || state == State::Closed {
+      println!("{}: ok", verb);
+    }
+    state = match next {
+      State::Open => state,
+      next => next,
+    };
+  }
+
+  // what is left is committed, as if the input had ended with `commit`
+  match (state, prepared) {
+    (State::Closed, _) => Ok(()),
+    (_, Some(prepared)) => commit(prepared),
+    (_, None) => commit(prepare(repo, opts, &requests)?),
+  }
+}
+
+/// Standard input, read a command at a time.
+struct Input<R: BufRead> {
+  reader: R,
+  nul_terminated: bool,
+}
+
+impl<R: BufRead> Input<R> {
+  /// Reads up to the next LF (or NUL), or `None` at the end of the input.
+  fn read(&mut self) -> Result<Option<String>, String> {
+    let terminator = if self.nul_terminated { b'\0' } else { b'\n' };
+    let mut field = Vec::new();
+    let read = self
+      .reader
+      .read_until(terminator, &mut field)
+      .map_err(|e| format!("could not read from standard input ({})", e))?;
+    if read == 0 {
+      return Ok(None);
+    }
+    if field.last() == Some(&terminator) {
+      field.pop();
+    }
+    String::from_utf8(field)
+      .map(Some)
+      .map_err(|_| "input is not valid UTF-8".to_string())
+  }
+
+  /// Reads the next command, split into its name and the rest of it.
+  fn command(&mut self) -> Result<Option<(String, String)>, String> {
+    Ok(self.read()?.map(|line| match line.split_once(' ') {
+      Some((verb, rest)) => (verb.to_owned(), rest.to_owned()),
+      None => (line, String::new()),
+    }))
+  }
+
+  /// Parses the arguments of an `update`, `create`, `delete` or `verify`
+  /// command into the update it asks for, reading them from further fields
+  /// of the input with `-z`.
+  fn update(&mut self, repo: &Repo, verb: &str, rest: &str) -> Result<Update, String> {
+    let kinds: &[&str] = match verb {
+      "update" => &["newvalue", "oldvalue"],
+      "create" => &["newvalue"],
+      _ => &["oldvalue"],
+    };
+    let (name, values) = match self.nul_terminated {
+      true => {
+        let mut values = Vec::new();
+        for kind in kinds {
+          let value = self.read()?.ok_or_else(|| {
+            format!(
+              "{} {}: unexpected end of input when reading <{}>",
+              verb, rest, kind
+            )
+          })?;
+          values.push(Some(value).filter(|value| !value.is_empty()));
+        }
+        (rest.to_owned(), values)
+      }
+      false => {
+        let mut fields = split_fields(rest)
+          .ok_or_else(|| format!("{}: badly quoted argument: {}", verb, rest))?
+          .into_iter();
+        let name = fields.next().unwrap_or_default();
+        let mut values: Vec<Option<String>> = fields.by_ref().take(kinds.len()).map(Some).collect();
+        if let Some(extra) = fields.next() {
+          return Err(format!("{} {}: extra input: {}", verb, name, extra));
+        }
+        values.resize(kinds.len(), None);
+        (name, values)
+      }
+    };
+    if name.is_empty() {
+      return Err(format!("{}: missing <ref>", verb));
+    }
+    if !refs::check_name(&name) {
+      return Err(format!("invalid ref format: {}", name));
+    }
+
+    let mut hashes = Vec::new();
+    for (kind, text) in kinds.iter().zip(values) {
+      let hash = match text {
+        Some(text) => Some(
+          value(repo, &text)
+            .map_err(|_| format!("{} {}: invalid <{}>: {}", verb, name, kind, text))?,
+        ),
+        None => None,
+      };
+      hashes.push(hash);
+    }
+    let zero = |kind: &str| Err(format!("{} {}: zero <{}>", verb, name, kind));
+    let (new, old) = match (verb, hashes.as_slice()) {
+      ("update", [None, _]) if !self.nul_terminated => {
+        return Err(format!("{} {}: missing <newvalue>", verb, name))
+      }
+      ("update", [None, old]) => {
+        eprintln!(
+          "warning: {} {}: missing <newvalue>, treating as zero",
+          verb, name
+        );
+        (NULL_HASH.to_string(), old.clone())
+      }
+      ("update", [Some(new), old]) => (new.clone(), old.clone()),
+      ("create", [None]) => return Err(format!("{} {}: missing <newvalue>", verb, name)),
+      ("create", [Some(new)]) if new == NULL_HASH => return zero("newvalue"),
+      ("create", [Some(new)]) => (new.clone(), Some(NULL_HASH.to_string())),
+      ("delete", [Some(old)]) if old == NULL_HASH => return zero("oldvalue"),
+      ("delete", [old]) => (NULL_HASH.to_string(), old.clone()),
+      (_, [old]) => {
+        let old = old.clone().unwrap_or_else(|| NULL_HASH.to_string());
+        return Ok(Update {
+          name,
+          new: None,
+          old: Some(old),
+        });
+      }
+      _ => unreachable!(),
+    };
+    Ok(Update {
+      name,
+      new: Some(new),
+      old,
+    })
+  }
+}
+
+/// Splits the arguments of a command at spaces, undoing the quotes around
+/// any that are quoted. `None` if the quotes are not closed.
+fn split_fields(text: &str) -> Option<Vec<String>> {
+  let mut fields = Vec::new();
+  let mut rest = text;
+  while !rest.is_empty() {
+    let end = match rest.starts_with('"') {
+      true => {
+        let bytes = rest.as_bytes();
+        let mut end = 1;
+        while end < bytes.len() && bytes[end] != b'"' {
+          end += if bytes[end] == b'\\' { 2 } else { 1 };
+        }
+        end + 1
+      }
+      false => rest.find(' ').unwrap_or(rest.len()),
+    };
+    let field = unquote(rest.get(..end)?.as_bytes())?;
+    fields.push(String::from_utf8(field.into()).ok()?);
+    rest = rest.get(end..)?;
+    rest = rest.strip_prefix(' ').unwrap_or(rest);
+  }
+  Some(fields)
+}
+
+/// The hash a value names, which is any revision, or 40 zeros for none.
+fn value(repo: &Repo, text: &str) -> Result<String, String> {
+  match text {
+    NULL_HASH => Ok(NULL_HASH.to_string()),
+    text => find_object(repo, text, None, false),
+  }
+}
+
+/// An update as it was asked for, before symbolic refs are followed.
+struct Request {
+  update: Update,
+  no_deref: bool,
+}
+
+/// A transaction ready to commit, and the entries to add to reflogs once
+/// it is.
+struct Prepared<'r> {
+  repo: &'r Repo,
+  transaction: Box<dyn Transaction + 'r>,
+  logs: Vec<(String, LogEntry)>,
+}
+
+/// Prepares a transaction of the requested updates, following symbolic refs
+/// to the refs they point at unless asked not to.
+fn prepare<'r>(
+  repo: &'r Repo,
+  opts: &UpdateRef,
+  requests: &[Request],
+) -> Result<Prepared<'r>, String> {
+  let mut updates = Vec::new();
+  let mut names = Vec::new();
+  for request in requests {
+    let mut update = request.update.clone();
+    let mut chain = vec![update.name.clone()];
+    while !request.no_deref && chain.len() <= 5 {
+      match refs::read_symbolic(repo, &update.name) {
+        Some(target) => update.name = target,
+        None => break,
+      }
+      chain.push(update.name.clone());
+    }
+    updates.push(update);
+    names.push(chain);
+  }
+  let transaction = repo.refs.prepare(&updates)?;
+
+  // the refs are locked, so what they point at now is what they are
+  // changed from
+  let mut logs = Vec::new();
+  // without an identity to stamp them with, the changes go unrecorded
+  if let Ok(identity) = Signature::current(repo, Role::Committer) {
+    let identity = identity.to_string();
+    for (update, chain) in updates.iter().zip(names) {
+      let new = match update.new.as_deref() {
+        None | Some(NULL_HASH) => continue,
+        Some(new) => new,
+      };
+      let old =
+        refs::resolve(repo, Path::new(&update.name)).unwrap_or_else(|_| NULL_HASH.to_string());
+      for name in chain {
+        if opts.create_reflog || keeps_log(repo, &name) {
+          let entry = LogEntry {
+            old: old.clone(),
+            new: new.to_owned(),
+            identity: identity.clone(),
+            message: opts.message.clone().unwrap_or_default(),
+          };
+          logs.push((name, entry));
+        }
+      }
+    }
+  }
+  Ok(Prepared {
+    repo,
+    transaction,
+    logs,
+  })
+}
+
+/// Commits a prepared transaction, then records the changes in the reflogs.
+fn commit(prepared: Prepared) -> Result<(), String> {
+  prepared.transaction.commit()?;
+  for (name, entry) in &prepared.logs {
+    prepared.repo.refs.append_log(name, entry)?;
+  }
+  Ok(())
+}
+
+/// Whether a ref keeps a reflog: `HEAD`, branches, remote-tracking branches
+/// and notes do, as do refs that have one already.
+fn keeps_log(repo: &Repo, name: &str) -> bool {
+  name == "HEAD"
+    || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+      .iter()
+      .any(|prefix| name.starts_with(prefix))
+    || matches!(repo.refs.read_log(name), Ok(log) if !log.is_empty())
+}
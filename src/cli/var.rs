@@ -0,0 +1,105 @@
+use std::process;
+
+use clap::Args;
+use ini::Ini;
+
+use git_rs_core::editor;
+use git_rs_core::env;
+use git_rs_core::identity::{Role, Signature};
+use git_rs_core::pager;
+use git_rs_core::repo::Repo;
+
+/// Show a git logical variable.
+///
+/// The variables are what git would use: `GIT_AUTHOR_IDENT` and
+/// `GIT_COMMITTER_IDENT` (who new commits are by, and when), `GIT_EDITOR`
+/// and `GIT_PAGER` (the programs messages are edited and output is paged
+/// with), and `GIT_DEFAULT_BRANCH` (the branch new repositories start on).
+/// With `-l`, the config is listed before them.
+///
+/// # Example
+/// ```bash
+/// $ git var GIT_AUTHOR_IDENT
+/// A U Thor <author@example.com> 1700000000 +0000
+/// ```
+#[derive(Args, Debug)]
+pub struct Var {
+  /// List the config and every variable.
+  #[clap(short)]
+  pub l: bool,
+
+  /// The variable to show.
+  pub variable: Option<String>,
+}
+
+const VARIABLES: [&str; 5] = [
+  "GIT_COMMITTER_IDENT",
+  "GIT_AUTHOR_IDENT",
+  "GIT_EDITOR",
+  "GIT_PAGER",
+  "GIT_DEFAULT_BRANCH",
+];
+
+pub fn cmd_var(opts: &Var) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let global = env::global_config().and_then(|path| Ini::load_from_file(path).ok());
+  match (&opts.variable, opts.l) {
+    (None, true) => {
+      for config in [global.as_ref(), repo.config.as_ref()]
+        .into_iter()
+        .flatten()
+      {
+        list_config(config);
+      }
+      for name in VARIABLES {
+        // as git does, leaving out what can't be worked out
+        if let Ok(value) = value(&repo, global.as_ref(), name) {
+          println!("{}={}", name, value);
+        }
+      }
+    }
+    (Some(name), false) if VARIABLES.contains(&name.as_str()) => {
+      println!("{}", value(&repo, global.as_ref(), name)?)
+    }
+    _ => {
+      eprintln!("usage: git var (-l | <variable>)");
+      process::exit(129);
+    }
+  }
+  Ok(())
+}
+
+fn value(repo: &Repo, global: Option<&Ini>, name: &str) -> Result<String, String> {
+  match name {
+    "GIT_COMMITTER_IDENT" => Ok(Signature::current(repo, Role::Committer)?.to_string()),
+    "GIT_AUTHOR_IDENT" => Ok(Signature::current(repo, Role::Author)?.to_string()),
+    "GIT_EDITOR" => editor::editor(repo),
+    "GIT_PAGER" => Ok(pager::pager(repo).unwrap_or_else(|| "cat".to_string())),
+    _ => Ok(
+      [repo.config.as_ref(), global]
+        .into_iter()
+        .flatten()
+        .find_map(|config| config.get_from(Some("init"), "defaultBranch"))
+        .unwrap_or("master")
+        .to_string(),
+    ),
+  }
+}
+
+/// Prints the settings in a config file as `section.subsection.key=value`.
+fn list_config(config: &Ini) {
+  let sections = config
+    .iter()
+    .filter_map(|(section, properties)| Some((section?, properties)));
+  for (section, properties) in sections {
+    let section = match section.split_once(' ') {
+      Some((name, subsection)) => {
+        format!("{}.{}", name.to_lowercase(), subsection.trim_matches('"'))
+      }
+      None => section.to_lowercase(),
+    };
+    for (key, value) in properties.iter() {
+      println!("{}.{}={}", section, key.to_lowercase(), value);
+    }
+  }
+}
@@ -0,0 +1,77 @@
+use clap::Args;
+
+use git_rs_core::{
+  index::{Index, IndexEntry},
+  object::{exists, mode::Mode},
+  repo::Repo,
+};
+
+/// Create a tree object from the current index.
+///
+/// Writes a tree for every directory in the index and prints the hash of the
+/// root tree. Directories unchanged since the trees were last written are
+/// not written again, as the index remembers their trees. Every object the index refers to must already exist, unless
+/// `--missing-ok` is given, and the index must not have unresolved conflicts.
+///
+/// # Example
+/// ```bash
+/// $ git write-tree
+/// 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+/// ```
+#[derive(Args, Debug)]
+pub struct WriteTree {
+  /// Skip checking that the objects in the index exist.
+  #[clap(long)]
+  pub missing_ok: bool,
+
+  /// Write the tree of this subdirectory instead of the root.
+  #[clap(long, value_name = "PREFIX/")]
+  pub prefix: Option<String>,
+}
+
+pub fn cmd_write_tree(opts: &WriteTree) -> Result<(), String> {
+  let repo: Repo = Repo::default();
+  let mut index = Index::read(&repo)?;
+  if let Some(entry) = index.entries().iter().find(|e| e.stage() != 0) {
+    return Err(format!(
+      "{}: unmerged ({})\nwrite-tree: error building trees",
+      entry.path, entry.hash
+    ));
+  }
+  if !opts.missing_ok {
+    // submodule commits are in another repository
+    let missing = |e: &&IndexEntry| e.tree_mode() != Some(Mode::Gitlink) && !exists(&repo, &e.hash);
+    if let Some(entry) = index.entries().iter().find(missing) {
+      return Err(format!(
+        "invalid object {} {} for '{}'\nwrite-tree: error building trees",
+        entry.tree_mode().map(|m| m.to_string()).unwrap_or_default(),
+        entry.hash,
+        entry.path
+      ));
+    }
+  }
+
+  let hash = index.write_tree(&repo)?;
+  if index.is_changed() {
+    index.write(&repo)?;
+  }
+  match &opts.prefix {
+    Some(prefix) => {
+      let tree = index
+        .cache_tree
+        .as_ref()
+        .and_then(|tree| tree.find(prefix.as_bytes()));
+      match tree.and_then(|tree| tree.tree.as_ref()) {
+        Some((_, hash)) => println!("{}", hash),
+        None => {
+          return Err(format!(
+            "prefix {}/ not found",
+            prefix.trim_end_matches('/')
+          ))
+        }
+      }
+    }
+    None => println!("{}", hash),
+  }
+  Ok(())
+}
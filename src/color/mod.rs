@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, IsTerminal};
+
+use crate::repo::Repo;
+
+/// What resets the terminal after something colored.
+pub const RESET: &str = "\x1b[m";
+
+/// The color of each slot until `color.<command>.<slot>` says otherwise.
+const DEFAULTS: [(&str, &str); 23] = [
+  ("diff.context", "normal"),
+  ("diff.meta", "bold"),
+  ("diff.frag", "cyan"),
+  ("diff.func", "normal"),
+  ("diff.old", "red"),
+  ("diff.new", "green"),
+  ("diff.commit", "yellow"),
+  ("diff.whitespace", "red reverse"),
+  ("status.header", "normal"),
+  ("status.added", "green"),
+  ("status.changed", "red"),
+  ("status.untracked", "red"),
+  ("status.branch", "green"),
+  ("status.nobranch", "red"),
+  ("status.localbranch", "green"),
+  ("status.remotebranch", "red"),
+  ("status.unmerged", "red"),
+  ("branch.plain", "normal"),
+  ("branch.current", "green"),
+  ("branch.local", "normal"),
+  ("branch.remote", "red"),
+  ("branch.upstream", "blue"),
+  ("branch.worktree", "cyan"),
+];
+
+/// The colors a command paints its output in, if it is colored at all.
+///
+/// Whether it is comes from `--color` (`always`, `never` or `auto`), then
+/// `color.<command>`, then `color.ui`, which defaults to `auto`: colored
+/// when the output goes to a terminal (or to the pager), unless `TERM` is
+/// `dumb`. Each slot, like `diff.new` or `status.untracked`, has a color
+/// that `color.<command>.<slot>` can change, given the way git takes them:
+/// up to two colors (foreground then background) by name (`red`,
+/// `brightred`), number (0 to 255) or `#rrggbb`, and attributes like `bold`,
+/// `ul` or `reverse` (and `no-bold` to turn one off).
+///
+/// # Example
+/// ```text
+/// let colors = Colors::new(&repo, "status", opts.color.as_deref())?;
+/// println!("\t{}", colors.paint("status.untracked", "notes.txt"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Colors {
+  enabled: bool,
+  slots: HashMap<String, String>,
+}
+
+impl Colors {
+  /// The colors for a command, as `--color` (if given) and the config say.
+  /// `command` is also the config section its slots are set in, eg. `diff`
+  /// for `color.diff.new`.
+  pub fn new(repo: &Repo, command: &str, flag: Option<&str>) -> Result<Colors, String> {
+    let setting = match flag {
+      Some(flag) => Some(flag.to_string()),
+      None => config(repo, "color", command).or_else(|| config(repo, "color", "ui")),
+    };
+    let enabled = match setting.as_deref().map(str::to_ascii_lowercase).as_deref() {
+      Some("always") => true,
+      Some("never" | "false" | "no" | "off" | "0") => false,
+      Some("auto" | "true" | "yes" | "on" | "1") | None => auto(repo),
+      Some(value) => return Err(format!("invalid color value: {}", value)),
+    };
+    let mut slots = HashMap::new();
+    for (slot, color) in DEFAULTS {
+      let (section, name) = slot.split_once('.').unwrap();
+      let configured = config(repo, &format!("color \"{}\"", section), name);
+      // `updated` is the old name of `status.added`
+      let configured = configured.or_else(|| match slot {
+        "status.added" => config(repo, "color \"status\"", "updated"),
+        _ => None,
+      });
+      let color = configured.as_deref().unwrap_or(color);
+      slots.insert(slot.to_string(), parse(color)?);
+    }
+    Ok(Colors { enabled, slots })
+  }
+
+  /// Wraps some text in the color of a slot, followed by a reset, if the
+  /// output is colored and the slot has a color. Otherwise the text is left
+  /// as it is.
+  pub fn paint(&self, slot: &str, text: &str) -> String {
+    match self.color(slot) {
+      "" => text.to_string(),
+      color => format!("{}{}{}", color, text, RESET),
+    }
+  }
+
+  /// The escape sequence that starts the color of a slot, or nothing if the
+  /// output is not colored.
+  pub fn color(&self, slot: &str) -> &str {
+    match self.enabled {
+      true => self.slots.get(slot).map_or("", String::as_str),
+      false => "",
+    }
+  }
+
+  /// The escape sequence that resets the terminal, or nothing if the output
+  /// is not colored.
+  pub fn reset(&self) -> &str {
+    match self.enabled {
+      true => RESET,
+      false => "",
+    }
+  }
+}
+
+/// Whether `auto` means color: when stdout is a terminal, or the pager is
+/// showing the output and `color.pager` doesn't say not to color it, and
+/// the terminal is not a dumb one.
+fn auto(repo: &Repo) -> bool {
+  let pager = env::var("GIT_PAGER_IN_USE").is_ok_and(|value| value == "true")
+    && !matches!(
+      config(repo, "color", "pager")
+        .map(|value| value.to_ascii_lowercase())
+        .as_deref(),
+      Some("false" | "no" | "off" | "0")
+    );
+  let terminal = env::var("TERM").is_ok_and(|term| term != "dumb");
+  (io::stdout().is_terminal() || pager) && terminal
+}
+
+/// Turns a color as git's config gives it, like `bold red` or
+/// `#ff0000 ul`, into the escape sequence that starts it. `normal` (or
+/// nothing at all) gives an empty sequence.
+pub fn parse(value: &str) -> Result<String, String> {
+  let invalid = || format!("invalid color value: {}", value);
+  let mut attributes: Vec<u32> = Vec::new();
+  let mut colors: Vec<String> = Vec::new();
+  for word in value.split_whitespace() {
+    let word = word.to_ascii_lowercase();
+    if word == "reset" {
+      return Ok(RESET.to_string());
+    }
+    if let Some(attribute) = attribute(&word) {
+      if !attributes.contains(&attribute) {
+        attributes.push(attribute);
+      }
+      continue;
+    }
+    // the first color is the foreground, the second the background
+    let background = colors.len() as u32;
+    if background > 1 {
+      return Err(invalid());
+    }
+    colors.push(color(&word, background).ok_or_else(invalid)?);
+  }
+  attributes.sort_unstable();
+  let codes: Vec<String> = attributes
+    .iter()
+    .map(u32::to_string)
+    .chain(colors.into_iter().filter(|color| !color.is_empty()))
+    .collect();
+  match codes.is_empty() {
+    true => Ok(String::new()),
+    false => Ok(format!("\x1b[{}m", codes.join(";"))),
+  }
+}
+
+/// The code of an attribute, or of turning it off.
+fn attribute(word: &str) -> Option<u32> {
+  let (off, name) = match word.strip_prefix("no") {
+    Some(name) => (true, name.strip_prefix('-').unwrap_or(name)),
+    None => (false, word),
+  };
+  let (on, off_code) = match name {
+    "bold" => (1, 22),
+    "dim" => (2, 22),
+    "italic" => (3, 23),
+    "ul" => (4, 24),
+    "blink" => (5, 25),
+    "reverse" => (7, 27),
+    "strike" => (9, 29),
+    _ => return None,
+  };
+  Some(if off { off_code } else { on })
+}
+
+/// The code of a foreground (`background` is 0) or background color. A
+/// `normal` color has no code.
+fn color(word: &str, background: u32) -> Option<String> {
+  const NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+  ];
+  let base = 30 + background * 10;
+  if word == "normal" {
+    return Some(String::new());
+  }
+  if word == "default" {
+    return Some((base + 9).to_string());
+  }
+  if let Some(i) = NAMES.iter().position(|name| *name == word) {
+    return Some((base + i as u32).to_string());
+  }
+  if let Some(name) = word.strip_prefix("bright") {
+    let i = NAMES.iter().position(|n| *n == name)?;
+    return Some((base + 60 + i as u32).to_string());
+  }
+  if let Some(hex) = word.strip_prefix('#') {
+    if hex.len() != 6 {
+      return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    return Some(format!(
+      "{};2;{};{};{}",
+      base + 8,
+      rgb >> 16,
+      (rgb >> 8) & 0xff,
+      rgb & 0xff
+    ));
+  }
+  match word.parse::<i32>().ok()? {
+    -1 => Some(String::new()),
+    n @ 0..=7 => Some((base + n as u32).to_string()),
+    n @ 8..=255 => Some(format!("{};5;{}", base + 8, n)),
+    _ => None,
+  }
+}
+
+fn config(repo: &Repo, section: &str, key: &str) -> Option<String> {
+  let section = repo.config.as_ref()?.section(Some(section))?;
+  section
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_string())
+}
@@ -0,0 +1,111 @@
+use std::collections::HashSet;
+
+use crate::object::{self, commit::Commit, mode::Mode, serializable::Unbox, tag::Tag, tree::Tree};
+use crate::repo::Repo;
+use crate::rev::graft::Grafts;
+
+/// An object that something reachable refers to but the repository does not
+/// have.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Missing {
+  /// What the object was expected to be: `commit`, `tree`, `blob`, or
+  /// `object` when nothing says.
+  pub kind: &'static str,
+  pub hash: String,
+
+  /// The kind and hash of the object that refers to it, unless it was one of
+  /// the starting points.
+  pub from: Option<(&'static str, String)>,
+}
+
+/// Finds the objects reachable from `tips` that are not in the repository,
+/// which would leave it corrupt if a ref were pointed at one of the tips. A
+/// fetch or push that brings in a pack must not update its refs unless this
+/// comes back empty.
+///
+/// The walk stops at the commits in `complete`, usually the ones the refs
+/// already point at, which are taken to have all of their history.
+///
+/// Two kinds of holes are allowed. Commits are followed to their grafted
+/// parents, so the parents of the commits listed in `.git/shallow` are not
+/// needed, as the history was cut off there on purpose. And anything that an object in a promisor pack (one with a
+/// `.promisor` file) refers to may be missing, as the remote it came from
+/// has promised to hand it over when asked.
+pub fn missing(repo: &Repo, tips: &[String], complete: &[String]) -> Result<Vec<Missing>, String> {
+  let grafts = Grafts::read(repo);
+  let mut seen: HashSet<String> = complete.iter().cloned().collect();
+  // the objects still to be looked at, which are missing until found
+  let mut stack: Vec<Missing> = tips
+    .iter()
+    .rev()
+    .map(|tip| Missing {
+      kind: "object",
+      hash: tip.clone(),
+      from: None,
+    })
+    .collect();
+  let mut missing = Vec::new();
+
+  while let Some(Missing { kind, hash, from }) = stack.pop() {
+    if !seen.insert(hash.clone()) {
+      continue;
+    }
+    if !repo.objects.exists(&hash) {
+      let promised = from
+        .as_ref()
+        .is_some_and(|(_, from)| repo.objects.is_promisor(from));
+      if !promised {
+        missing.push(Missing { kind, hash, from });
+      }
+      continue;
+    }
+    // blobs refer to nothing, so need not be read, and what an object that
+    // could be anything is can be told from its header
+    if kind == "blob" || (kind == "object" && object::header(repo, &hash)?.0 == "blob") {
+      continue;
+    }
+
+    let object = object::read(repo, &hash, None)?;
+    let mut refer = |kind: &'static str, target: &str, referrer: &'static str| {
+      stack.push(Missing {
+        kind,
+        hash: target.to_string(),
+        from: Some((referrer, hash.clone())),
+      });
+    };
+    match object.format().as_str() {
+      "commit" => {
+        let commit = object.unbox::<Commit>()?;
+        for parent in grafts.parents(&hash, commit.parents()).iter().rev() {
+          refer("commit", parent, "commit");
+        }
+        refer("tree", commit.tree(), "commit");
+      }
+      "tree" => {
+        let tree = object.unbox::<Tree>()?;
+        for entry in tree.entries().iter().rev() {
+          match entry.mode {
+            Mode::Directory => refer("tree", &entry.hash, "tree"),
+            // submodule commits live in another repository
+            Mode::Gitlink => (),
+            _ => refer("blob", &entry.hash, "tree"),
+          }
+        }
+      }
+      "tag" => {
+        let tag = object.unbox::<Tag>()?;
+        let kind = match tag.get("type").map(String::as_str) {
+          Some("commit") => "commit",
+          Some("tree") => "tree",
+          Some("blob") => "blob",
+          _ => "object",
+        };
+        if let Some(target) = tag.get("object") {
+          refer(kind, target, "tag");
+        }
+      }
+      _ => (),
+    }
+  }
+  Ok(missing)
+}
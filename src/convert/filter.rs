@@ -0,0 +1,344 @@
+use std::collections::BTreeMap;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+
+use bstr::ByteSlice;
+
+use crate::repo::Repo;
+use crate::trace;
+use crate::transport::pkt_line;
+
+/// A filter driver, named by the `filter` attribute of a path and set up in
+/// the `filter.<name>` section of the config.
+///
+/// `clean` is a command that turns the contents of a file in the working tree
+/// into what is stored in the repository, and `smudge` one that turns them
+/// back; each reads the contents on its standard input and prints the
+/// result, with `%f` in the command replaced by the path of the file.
+///
+/// `process` is instead a command started once and kept running, which
+/// handles every file for the driver over the long-running filter protocol
+/// and takes precedence over the others.
+///
+/// A driver that fails is ignored and the contents are used as they are,
+/// unless `required` is set, in which case the file cannot be added or
+/// checked out.
+///
+/// ### Example
+/// ```text
+/// [filter "lfs"]
+///   clean = git-lfs clean -- %f
+///   smudge = git-lfs smudge -- %f
+///   process = git-lfs filter-process
+///   required = true
+/// ```
+pub struct Filter {
+  name: String,
+  clean: Option<String>,
+  smudge: Option<String>,
+  process: Option<String>,
+  required: bool,
+}
+
+/// Which way the contents of a file are going.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+  Clean,
+  Smudge,
+}
+
+/// A long-running filter process, kept for the life of the program.
+struct Process {
+  child: Child,
+  traced: trace::event::Child,
+  stdin: ChildStdin,
+  stdout: BufReader<ChildStdout>,
+  capabilities: Vec<String>,
+}
+
+/// The running filter processes by command. A command that could not be
+/// started, or that failed the handshake, has none and is not retried.
+static PROCESSES: Mutex<BTreeMap<String, Option<Process>>> = Mutex::new(BTreeMap::new());
+
+impl Filter {
+  /// Reads the driver with a name from the config. A driver that is not
+  /// configured has no commands, and leaves contents alone.
+  pub fn new(repo: &Repo, name: &str) -> Self {
+    let section = repo
+      .config
+      .as_ref()
+      .and_then(|config| config.section(Some(format!("filter \"{}\"", name))));
+    let get = |key: &str| {
+      section.and_then(|section| {
+        section
+          .iter()
+          .find(|(name, _)| name.eq_ignore_ascii_case(key))
+          .map(|(_, value)| value.to_owned())
+      })
+    };
+    let required = get("required").map(|value| value.to_ascii_lowercase());
+    Self {
+      name: name.to_string(),
+      clean: get("clean"),
+      smudge: get("smudge"),
+      process: get("process"),
+      required: matches!(required.as_deref(), Some("true" | "yes" | "on" | "1" | "")),
+    }
+  }
+
+  /// Whether the driver has any commands set up.
+  pub fn is_configured(&self) -> bool {
+    self.clean.is_some() || self.smudge.is_some() || self.process.is_some()
+  }
+
+  /// Runs the contents of the file at `path` through the driver.
+  pub fn apply(
+    &self,
+    repo: &Repo,
+    path: &[u8],
+    data: Vec<u8>,
+    direction: Direction,
+  ) -> Result<Vec<u8>, String> {
+    let result = match (&self.process, direction) {
+      (Some(process), _) => run_process(repo, process, path, &data, direction),
+      (None, Direction::Clean) => self.clean.as_ref().map(|cmd| run(repo, cmd, path, &data)),
+      (None, Direction::Smudge) => self.smudge.as_ref().map(|cmd| run(repo, cmd, path, &data)),
+    };
+    match result {
+      Some(Ok(filtered)) => Ok(filtered),
+      _ if self.required => Err(match direction {
+        Direction::Clean => format!("{}: clean filter '{}' failed", path.as_bstr(), self.name),
+        Direction::Smudge => format!("{}: smudge filter {} failed", path.as_bstr(), self.name),
+      }),
+      Some(Err(e)) => {
+        eprintln!("error: {}", e);
+        Ok(data)
+      }
+      None => Ok(data),
+    }
+  }
+}
+
+/// Runs a `clean` or `smudge` command through the shell, from the top of the
+/// working tree, feeding it the contents.
+fn run(repo: &Repo, cmd: &str, path: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+  let quoted = format!("'{}'", path.to_str_lossy().replace('\'', "'\\''"));
+  let cmd = cmd.replace("%f", &quoted);
+  let error = || format!("external filter '{}' failed", cmd);
+  let mut command = Command::new("sh");
+  command
+    .arg("-c")
+    .arg(&cmd)
+    .current_dir(&repo.work_tree)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped());
+  let traced = trace::command("filter", &command);
+  let mut child = command
+    .spawn()
+    .map_err(|e| format!("cannot fork to run external filter '{}' ({})", cmd, e))?;
+  let pid = child.id();
+
+  // feed it from another thread, so that neither side blocks on a full pipe
+  let mut stdin = child.stdin.take().unwrap();
+  let input = data.to_vec();
+  let feeder = std::thread::spawn(move || stdin.write_all(&input));
+  let output = child.wait_with_output().map_err(|_| error())?;
+  traced.exit(pid, &output.status);
+  let fed = feeder.join().map_err(|_| error())?;
+  // a filter may well not read everything, if it does not need to
+  if let Err(e) = fed {
+    if e.kind() != std::io::ErrorKind::BrokenPipe {
+      return Err(error());
+    }
+  }
+  match output.status.success() {
+    true => Ok(output.stdout),
+    false => Err(error()),
+  }
+}
+
+/// Hands a file to a long-running filter process, starting it first if it
+/// is not running yet.
+///
+/// A file is sent as a list of `key=value` packets naming the command and
+/// the path, then its contents, each followed by a flush packet. The process
+/// answers with its status, then the filtered contents and a status once
+/// more, which may change its mind after all. `error` fails just the one
+/// file, and `abort` every file after it too.
+fn run_process(
+  repo: &Repo,
+  cmd: &str,
+  path: &[u8],
+  data: &[u8],
+  direction: Direction,
+) -> Option<Result<Vec<u8>, String>> {
+  let capability = match direction {
+    Direction::Clean => "clean",
+    Direction::Smudge => "smudge",
+  };
+  let mut processes = PROCESSES.lock().unwrap_or_else(|e| e.into_inner());
+  let process = processes
+    .entry(cmd.to_string())
+    .or_insert_with(|| {
+      Process::start(repo, cmd)
+        .map_err(|e| eprintln!("error: {}", e))
+        .ok()
+    })
+    .as_mut()?;
+  if !process.capabilities.iter().any(|c| c == capability) {
+    return None;
+  }
+
+  let error = || format!("external filter '{}' failed", cmd);
+  match process.filter(capability, path, data) {
+    Ok((status, filtered)) => match status.as_str() {
+      "success" => Some(Ok(filtered)),
+      // the process is done with this kind of file, but may do others
+      "abort" => {
+        process.capabilities.retain(|c| c != capability);
+        Some(Err(error()))
+      }
+      _ => Some(Err(error())),
+    },
+    // it is no longer speaking the protocol, so it is stopped
+    Err(_) => {
+      if let Some(process) = processes.get_mut(cmd).and_then(Option::take) {
+        process.stop();
+      }
+      Some(Err(error()))
+    }
+  }
+}
+
+impl Process {
+  /// Starts a filter process through the shell and shakes hands with it.
+  fn start(repo: &Repo, cmd: &str) -> Result<Self, String> {
+    let error = || format!("initialization for subprocess '{}' failed", cmd);
+    let mut command = Command::new("sh");
+    command
+      .arg("-c")
+      .arg(cmd)
+      .current_dir(&repo.work_tree)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped());
+    let traced = trace::command("subprocess", &command);
+    let mut child = command
+      .spawn()
+      .map_err(|e| format!("cannot fork to run subprocess '{}' ({})", cmd, e))?;
+    let mut process = Self {
+      stdin: child.stdin.take().unwrap(),
+      stdout: BufReader::new(child.stdout.take().unwrap()),
+      child,
+      traced,
+      capabilities: Vec::new(),
+    };
+
+    match process.handshake() {
+      Ok(()) => Ok(process),
+      Err(_) => {
+        process.stop();
+        Err(error())
+      }
+    }
+  }
+
+  /// Stops the process, which is no longer speaking the protocol.
+  fn stop(mut self) {
+    let _ = self.child.kill();
+    if let Ok(status) = self.child.wait() {
+      self.traced.exit(self.child.id(), &status);
+    }
+  }
+
+  /// Greets the process, agreeing on version 2 of the protocol, and then
+  /// finds out which of the capabilities offered it has.
+  fn handshake(&mut self) -> Result<(), String> {
+    self.write_list(&["git-filter-client", "version=2"])?;
+    let greeting = self.read_list()?;
+    if greeting.first().map(String::as_str) != Some("git-filter-server")
+      || !greeting.iter().any(|line| line == "version=2")
+    {
+      return Err("bad greeting from filter process".to_string());
+    }
+    self.write_list(&["capability=clean", "capability=smudge"])?;
+    self.capabilities = self
+      .read_list()?
+      .into_iter()
+      .filter_map(|line| line.strip_prefix("capability=").map(String::from))
+      .collect();
+    Ok(())
+  }
+
+  /// Sends a file through the process, returning its final status and the
+  /// filtered contents.
+  fn filter(
+    &mut self,
+    command: &str,
+    path: &[u8],
+    data: &[u8],
+  ) -> Result<(String, Vec<u8>), String> {
+    let command = format!("command={}", command);
+    let pathname = format!("pathname={}", path.to_str_lossy());
+    self.write_list(&[&command, &pathname])?;
+    for chunk in data.chunks(pkt_line::MAX_DATA) {
+      write_packet(&mut self.stdin, Some(chunk))?;
+    }
+    write_packet(&mut self.stdin, None)?;
+    self.stdin.flush().map_err(|e| e.to_string())?;
+
+    let before = status(self.read_list()?, "success");
+    if before != "success" {
+      return Ok((before, Vec::new()));
+    }
+    let mut filtered = Vec::new();
+    while let Some(packet) = read_packet(&mut self.stdout)? {
+      filtered.extend(packet);
+    }
+    Ok((status(self.read_list()?, &before), filtered))
+  }
+
+  /// Writes text packets, each ending in a newline, and then a flush packet.
+  fn write_list(&mut self, lines: &[&str]) -> Result<(), String> {
+    for line in lines {
+      write_packet(&mut self.stdin, Some(format!("{}\n", line).as_bytes()))?;
+    }
+    write_packet(&mut self.stdin, None)?;
+    self.stdin.flush().map_err(|e| e.to_string())
+  }
+
+  /// Reads text packets up to a flush packet, without their newlines.
+  fn read_list(&mut self) -> Result<Vec<String>, String> {
+    let mut lines = Vec::new();
+    while let Some(packet) = read_packet(&mut self.stdout)? {
+      let line = packet.strip_suffix(b"\n").unwrap_or(&packet);
+      lines.push(line.to_str_lossy().into_owned());
+    }
+    Ok(lines)
+  }
+}
+
+/// The last status in a list of `key=value` lines, or the one before if it
+/// has none.
+fn status(lines: Vec<String>, previous: &str) -> String {
+  lines
+    .iter()
+    .rev()
+    .find_map(|line| line.strip_prefix("status="))
+    .unwrap_or(previous)
+    .to_string()
+}
+
+/// Writes a pkt-line to the filter process.
+fn write_packet(out: &mut impl Write, data: Option<&[u8]>) -> Result<(), String> {
+  pkt_line::write(out, data).map_err(|e| format!("could not write to filter process ({})", e))
+}
+
+/// Reads a pkt-line from the filter process, which is `None` for a flush
+/// packet.
+fn read_packet(input: &mut impl Read) -> Result<Option<Vec<u8>>, String> {
+  pkt_line::read(input).map_err(|e| match e.kind() {
+    ErrorKind::InvalidData => "bad packet length from filter process".to_string(),
+    _ => format!("could not read from filter process ({})", e),
+  })
+}
@@ -0,0 +1,339 @@
+pub mod filter;
+
+use bstr::ByteSlice;
+
+use crate::attr::{Attributes, State};
+use crate::diff::blob_data;
+use crate::lfs;
+use crate::object::mode::Mode;
+use crate::repo::Repo;
+
+use filter::{Direction, Filter};
+
+/// How the line endings of a file are converted between the repository and
+/// the working tree.
+///
+/// Text files are kept with LF line endings in the repository, and may be
+/// given CRLF line endings in the working tree. Whether a file is text comes
+/// from its `text` attribute (or the older `crlf`): set, it is text; unset,
+/// it is left alone; `auto`, it is text unless its contents look binary. The
+/// `eol` attribute picks the line endings of a text file in the working
+/// tree, and implies `text` when that is not given.
+///
+/// Files with neither attribute are left alone unless `core.autocrlf` is on,
+/// in which case they are treated as `text=auto`, with CRLF line endings in
+/// the working tree (`true`) or converted only on the way in (`input`).
+/// Otherwise text files get the line endings named by `core.eol`, LF unless
+/// it is `crlf`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Action {
+  Binary,
+  Text,
+  TextInput,
+  TextCrlf,
+  Auto,
+  AutoInput,
+  AutoCrlf,
+}
+
+/// Counts of the kinds of characters in a file, to tell text from binary.
+#[derive(Clone, Copy, Default)]
+struct Stats {
+  nul: usize,
+  lone_cr: usize,
+  lone_lf: usize,
+  crlf: usize,
+  printable: usize,
+  nonprintable: usize,
+}
+
+/// Converts the contents of a file in the working tree into what is stored
+/// in the repository for it: they are run through the clean command of the
+/// file's filter driver, if it has one, and then have their line endings
+/// converted. Files in the care of Git LFS are understood even when it is
+/// not set up, as far as matching them up with their pointers.
+///
+/// `indexed` is the blob the index has for the file, if any: a file that is
+/// only text by guessing is left alone if that blob has CRs in it, as it was
+/// evidently added that way. When `warn` is given, conversions that would not
+/// survive a round trip through the working tree are warned about, or
+/// refused if `core.safecrlf` is `true`.
+pub fn to_git(
+  repo: &Repo,
+  path: &[u8],
+  data: Vec<u8>,
+  indexed: Option<&str>,
+  warn: bool,
+) -> Result<Vec<u8>, String> {
+  let mut attributes = Attributes::new(repo);
+  let data = match attributes.get(path, "filter") {
+    State::Value(name) => {
+      let filter = Filter::new(repo, &name);
+      match name == "lfs" && !filter.is_configured() {
+        true => lfs::clean(repo, data, indexed),
+        false => filter.apply(repo, path, data, Direction::Clean)?,
+      }
+    }
+    _ => data,
+  };
+  let action = action(repo, &mut attributes, path);
+  crlf_to_git(repo, action, path, data, indexed, warn)
+}
+
+/// Converts the contents of a file to be written to the working tree from
+/// what is stored in the repository: the reverse of [`to_git`].
+pub fn to_worktree(repo: &Repo, path: &[u8], data: Vec<u8>) -> Result<Vec<u8>, String> {
+  let mut attributes = Attributes::new(repo);
+  let action = action(repo, &mut attributes, path);
+  let data = crlf_to_worktree(repo, action, data);
+  match attributes.get(path, "filter") {
+    State::Value(name) => {
+      let filter = Filter::new(repo, &name);
+      match name == "lfs" && !filter.is_configured() {
+        true => Ok(lfs::smudge(repo, data)),
+        false => filter.apply(repo, path, data, Direction::Smudge),
+      }
+    }
+    _ => Ok(data),
+  }
+}
+
+fn crlf_to_git(
+  repo: &Repo,
+  action: Action,
+  path: &[u8],
+  data: Vec<u8>,
+  indexed: Option<&str>,
+  warn: bool,
+) -> Result<Vec<u8>, String> {
+  if action == Action::Binary || data.is_empty() {
+    return Ok(data);
+  }
+  let stats = Stats::gather(&data);
+  let mut convert = stats.crlf > 0;
+  if matches!(action, Action::Auto | Action::AutoInput | Action::AutoCrlf) {
+    if stats.is_binary() {
+      return Ok(data);
+    }
+    if convert && has_cr(repo, indexed) {
+      convert = false;
+    }
+  }
+
+  if warn {
+    let mut after = stats;
+    if convert {
+      after.lone_lf += after.crlf;
+      after.crlf = 0;
+    }
+    if will_convert_lf_to_crlf(repo, &after, action) {
+      after.crlf += after.lone_lf;
+      after.lone_lf = 0;
+    }
+    if stats.crlf > 0 && after.crlf == 0 {
+      check_safe(repo, path, "CRLF", "LF")?;
+    }
+    if stats.lone_lf > 0 && after.lone_lf == 0 {
+      check_safe(repo, path, "LF", "CRLF")?;
+    }
+  }
+
+  if !convert {
+    return Ok(data);
+  }
+  let mut converted = Vec::with_capacity(data.len() - stats.crlf);
+  let mut bytes = data.iter().peekable();
+  while let Some(&c) = bytes.next() {
+    if c != b'\r' || bytes.peek() != Some(&&b'\n') {
+      converted.push(c);
+    }
+  }
+  Ok(converted)
+}
+
+fn crlf_to_worktree(repo: &Repo, action: Action, data: Vec<u8>) -> Vec<u8> {
+  if action == Action::Binary || data.is_empty() {
+    return data;
+  }
+  let stats = Stats::gather(&data);
+  if !will_convert_lf_to_crlf(repo, &stats, action) {
+    return data;
+  }
+  let mut converted = Vec::with_capacity(data.len() + stats.lone_lf);
+  let mut previous = 0;
+  for &c in &data {
+    if c == b'\n' && previous != b'\r' {
+      converted.push(b'\r');
+    }
+    converted.push(c);
+    previous = c;
+  }
+  converted
+}
+
+/// Works out how a path is converted from its attributes and the config.
+fn action(repo: &Repo, attributes: &mut Attributes, path: &[u8]) -> Action {
+  let text = |state| match state {
+    State::Set => Some(Action::Text),
+    State::Unset => Some(Action::Binary),
+    State::Value(value) if value == "input" => Some(Action::TextInput),
+    State::Value(value) if value == "auto" => Some(Action::Auto),
+    _ => None,
+  };
+  let mut action =
+    text(attributes.get(path, "text")).or_else(|| text(attributes.get(path, "crlf")));
+
+  if action != Some(Action::Binary) {
+    let eol = attributes.get(path, "eol");
+    let auto = action == Some(Action::Auto);
+    match eol {
+      State::Value(eol) if eol == "lf" => {
+        action = Some(if auto {
+          Action::AutoInput
+        } else {
+          Action::TextInput
+        })
+      }
+      State::Value(eol) if eol == "crlf" => {
+        action = Some(if auto {
+          Action::AutoCrlf
+        } else {
+          Action::TextCrlf
+        })
+      }
+      _ => (),
+    }
+  }
+
+  match action {
+    Some(Action::Text) if eol_is_crlf(repo) => Action::TextCrlf,
+    Some(Action::Text) => Action::TextInput,
+    Some(action) => action,
+    None => match autocrlf(repo).as_deref() {
+      Some("true") => Action::AutoCrlf,
+      Some("input") => Action::AutoInput,
+      _ => Action::Binary,
+    },
+  }
+}
+
+/// Whether text files get CRLF line endings in the working tree, unless
+/// their attributes say otherwise.
+fn eol_is_crlf(repo: &Repo) -> bool {
+  match autocrlf(repo).as_deref() {
+    Some("true") => true,
+    Some("input") => false,
+    _ => {
+      config(repo, "eol")
+        .map(|eol| eol.to_ascii_lowercase())
+        .as_deref()
+        == Some("crlf")
+    }
+  }
+}
+
+/// Whether the LFs of a file would be turned into CRLFs on checkout.
+fn will_convert_lf_to_crlf(repo: &Repo, stats: &Stats, action: Action) -> bool {
+  let crlf = match action {
+    Action::Binary | Action::TextInput | Action::AutoInput => false,
+    Action::TextCrlf | Action::AutoCrlf => true,
+    Action::Text | Action::Auto => eol_is_crlf(repo),
+  };
+  if !crlf || stats.lone_lf == 0 {
+    return false;
+  }
+  match action {
+    // a file guessed to be text is left alone if it already has CRs
+    Action::Auto | Action::AutoInput | Action::AutoCrlf => {
+      stats.lone_cr == 0 && stats.crlf == 0 && !stats.is_binary()
+    }
+    _ => true,
+  }
+}
+
+/// Warns that a conversion will not survive a round trip, or refuses it if
+/// `core.safecrlf` is `true`.
+fn check_safe(repo: &Repo, path: &[u8], from: &str, to: &str) -> Result<(), String> {
+  let safe = config(repo, "safecrlf").map(|value| value.to_ascii_lowercase());
+  match safe.as_deref() {
+    Some("true" | "yes" | "on" | "1") => Err(format!(
+      "{} would be replaced by {} in {}",
+      from,
+      to,
+      path.as_bstr()
+    )),
+    Some("false" | "no" | "off" | "0") => Ok(()),
+    _ => {
+      eprintln!(
+        "warning: in the working copy of '{}', {} will be replaced by {} the next time Git touches it",
+        path.as_bstr(),
+        from,
+        to
+      );
+      Ok(())
+    }
+  }
+}
+
+/// Whether the blob the index has for a file has CRs in it.
+fn has_cr(repo: &Repo, indexed: Option<&str>) -> bool {
+  indexed.is_some_and(|hash| {
+    blob_data(repo, Some(&(Mode::Normal, hash.to_string()))).is_ok_and(|data| data.contains(&b'\r'))
+  })
+}
+
+impl Stats {
+  fn gather(data: &[u8]) -> Self {
+    let mut stats = Self::default();
+    let mut i = 0;
+    while i < data.len() {
+      match data[i] {
+        b'\r' if data.get(i + 1) == Some(&b'\n') => {
+          stats.crlf += 1;
+          i += 1;
+        }
+        b'\r' => stats.lone_cr += 1,
+        b'\n' => stats.lone_lf += 1,
+        127 => stats.nonprintable += 1,
+        // backspace, tab, escape and form feed are common in text
+        8 | 9 | 27 | 12 => stats.printable += 1,
+        0 => {
+          stats.nul += 1;
+          stats.nonprintable += 1;
+        }
+        c if c < 32 => stats.nonprintable += 1,
+        _ => stats.printable += 1,
+      }
+      i += 1;
+    }
+    // a DOS end of file marker at the very end is not held against it
+    if data.last() == Some(&0x1a) {
+      stats.nonprintable -= 1;
+    }
+    stats
+  }
+
+  /// Whether the file looks binary: it has NULs or lone CRs, or more than
+  /// one in 128 of its characters are not printable.
+  fn is_binary(&self) -> bool {
+    self.lone_cr > 0 || self.nul > 0 || (self.printable >> 7) < self.nonprintable
+  }
+}
+
+/// The value of `core.autocrlf`, lowercased. `true` may be given as any of
+/// git's boolean spellings.
+fn autocrlf(repo: &Repo) -> Option<String> {
+  let value = config(repo, "autocrlf")?.to_ascii_lowercase();
+  Some(match value.as_str() {
+    "yes" | "on" | "1" | "" => "true".to_string(),
+    _ => value,
+  })
+}
+
+fn config(repo: &Repo, key: &str) -> Option<String> {
+  let core = repo.config.as_ref()?.section(Some("core"))?;
+  core
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_owned())
+}
@@ -31,3 +31,89 @@ pub fn sha_1(data: &[u8]) -> String {
   let result = hasher.finalize();
   hex::encode(result)
 }
+
+/// Computes the HMAC-SHA1 of the given data with a key, which is how a
+/// server signs the nonces it hands out for signed pushes.
+pub fn hmac_sha_1(key: &[u8], data: &[u8]) -> String {
+  let mut key = match key.len() > 64 {
+    true => Sha1::digest(key).to_vec(),
+    false => key.to_vec(),
+  };
+  key.resize(64, 0);
+  let pad = |byte: u8| key.iter().map(|k| k ^ byte).collect::<Vec<u8>>();
+  let inner = Sha1::digest([&pad(0x36)[..], data].concat());
+  hex::encode(Sha1::digest([&pad(0x5c)[..], &inner[..]].concat()))
+}
+
+/// The round constants of SHA-256: the fractional parts of the cube roots of
+/// the first 64 primes.
+const SHA_256_ROUNDS: [u32; 64] = [
+  0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+  0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+  0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+  0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+  0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+  0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+  0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+  0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Computes the SHA-256 hash of the given data, which Git LFS names its
+/// objects by.
+pub fn sha_256(data: &[u8]) -> String {
+  let mut state: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+  ];
+  let blocks = data.chunks_exact(64);
+  // the data is padded with a 1 bit, then 0 bits, then its length in bits
+  let mut tail = blocks.remainder().to_vec();
+  tail.push(0x80);
+  while tail.len() % 64 != 56 {
+    tail.push(0);
+  }
+  tail.extend(((data.len() as u64) * 8).to_be_bytes());
+  for block in blocks.chain(tail.chunks(64)) {
+    sha_256_block(&mut state, block);
+  }
+  state.iter().map(|word| format!("{:08x}", word)).collect()
+}
+
+fn sha_256_block(state: &mut [u32; 8], block: &[u8]) {
+  let mut w = [0u32; 64];
+  for (i, word) in block.chunks(4).enumerate() {
+    w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+  }
+  for i in 16..64 {
+    let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+    let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+    w[i] = w[i - 16]
+      .wrapping_add(s0)
+      .wrapping_add(w[i - 7])
+      .wrapping_add(s1);
+  }
+
+  let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+  for i in 0..64 {
+    let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+    let choice = (e & f) ^ (!e & g);
+    let t1 = h
+      .wrapping_add(s1)
+      .wrapping_add(choice)
+      .wrapping_add(SHA_256_ROUNDS[i])
+      .wrapping_add(w[i]);
+    let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+    let majority = (a & b) ^ (a & c) ^ (b & c);
+    let t2 = s0.wrapping_add(majority);
+    h = g;
+    g = f;
+    f = e;
+    e = d.wrapping_add(t1);
+    d = c;
+    c = b;
+    b = a;
+    a = t1.wrapping_add(t2);
+  }
+  for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+    *word = word.wrapping_add(add);
+  }
+}
@@ -0,0 +1,182 @@
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use crate::repo::{is_git_dir, Repo};
+use crate::transport::{git, pkt_line, receive_pack, upload_pack};
+
+/// The file that marks a repository as one the daemon may serve.
+pub const EXPORT_OK: &str = "git-daemon-export-ok";
+
+/// Which repositories a daemon serves.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+  /// Serve every repository that is found, not only those with a
+  /// `git-daemon-export-ok` file in their git directory.
+  pub export_all: bool,
+
+  /// The directory the paths clients ask for are under, instead of `/`.
+  pub base_path: Option<PathBuf>,
+
+  /// The directories repositories must be in to be served, or any directory
+  /// if there are none.
+  pub whitelist: Vec<PathBuf>,
+
+  /// Let clients push to the repositories served, which they can't by
+  /// default, as anyone may connect.
+  pub receive_pack: bool,
+
+  /// Say on standard error who connected and what they asked for.
+  pub verbose: bool,
+}
+
+/// A server for the `git://` protocol, which lets anyone fetch from the
+/// repositories it exports, as `git daemon` does.
+///
+/// Each connection asks for a service and a repository by path. Only
+/// `git-upload-pack` (fetching) is served, and `git-receive-pack` (pushing)
+/// if it is enabled; the repository is looked for at
+/// the path, then with `.git` or `/.git` added, and must be in one of the
+/// whitelisted directories and be exported. A request that is refused gets
+/// the same answer whether or not the repository is there, so that clients
+/// can't find out what is on the machine.
+pub struct Daemon {
+  options: Arc<Options>,
+  listener: TcpListener,
+}
+
+impl Daemon {
+  /// Starts listening on `addr` (eg. `0.0.0.0:9418`).
+  pub fn bind(addr: &str, options: Options) -> Result<Daemon, String> {
+    let listener =
+      TcpListener::bind(addr).map_err(|e| format!("unable to listen on {} ({})", addr, e))?;
+    Ok(Daemon {
+      options: Arc::new(options),
+      listener,
+    })
+  }
+
+  /// The address being listened on, with the port picked if `0` was asked
+  /// for.
+  pub fn local_addr(&self) -> Result<SocketAddr, String> {
+    self
+      .listener
+      .local_addr()
+      .map_err(|e| format!("unable to get the address listened on ({})", e))
+  }
+
+  /// Serves connections until the process is stopped, each on a thread of
+  /// its own. A connection that fails is dropped without stopping the
+  /// others.
+  pub fn run(&self) -> Result<(), String> {
+    for stream in self.listener.incoming() {
+      let stream = match stream {
+        Ok(stream) => stream,
+        Err(_) => continue,
+      };
+      let options = self.options.clone();
+      thread::spawn(move || {
+        if let Err(err) = handle(&options, stream) {
+          if options.verbose {
+            eprintln!("{}", err);
+          }
+        }
+      });
+    }
+    Ok(())
+  }
+}
+
+/// Reads the request from a connection, and serves it if it is allowed.
+fn handle(options: &Options, stream: TcpStream) -> Result<(), String> {
+  let peer = stream
+    .peer_addr()
+    .map_or_else(|_| "unknown".to_string(), |addr| addr.to_string());
+  if options.verbose {
+    eprintln!("Connection from {}", peer);
+  }
+  let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+  let mut writer = BufWriter::new(stream);
+  let request = pkt_line::read(&mut reader)
+    .map_err(|e| format!("{}: bad request ({})", peer, e))?
+    .unwrap_or_default();
+  // `<service> <path>\0host=<host>\0`, where the host is optional
+  let request = String::from_utf8_lossy(&request);
+  let line = request.split('\0').next().unwrap_or_default().trim_end();
+  let (service, path) = line
+    .split_once(' ')
+    .ok_or_else(|| format!("{}: bad request '{}'", peer, line))?;
+  if options.verbose {
+    eprintln!("Request {} for '{}'", service, path);
+  }
+
+  let refuse = |writer: &mut BufWriter<TcpStream>, message: String| {
+    let _ = pkt_line::write_line(writer, &format!("ERR {}", message));
+    let _ = writer.flush();
+    Err(format!("{}: {}", peer, message))
+  };
+  let enabled = match service {
+    git::UPLOAD_PACK => true,
+    git::RECEIVE_PACK => options.receive_pack,
+    _ => false,
+  };
+  if !enabled {
+    return refuse(&mut writer, format!("service not enabled: '{}'", path));
+  }
+  let git_dir = match find(options, path) {
+    Some(git_dir) => git_dir,
+    None => {
+      let message = format!("access denied or repository not exported: {}", path);
+      return refuse(&mut writer, message);
+    }
+  };
+  let repo = Repo::open(&git_dir, &git_dir, true)?;
+  match service {
+    git::RECEIVE_PACK => receive_pack::serve(&repo, &mut reader, &mut writer),
+    _ => upload_pack::serve(&repo, &mut reader, &mut writer),
+  }
+}
+
+/// The git directory of the repository a client asked for by `path`, if it
+/// is there and may be served.
+pub fn find(options: &Options, path: &str) -> Option<PathBuf> {
+  let relative = path.strip_prefix('/')?;
+  if Path::new(relative)
+    .components()
+    .any(|part| !matches!(part, Component::Normal(_)))
+  {
+    return None;
+  }
+  let path = match &options.base_path {
+    Some(base) => base.join(relative),
+    None => Path::new("/").join(relative),
+  };
+  let with = |ending: &str| {
+    let mut with = path.clone().into_os_string();
+    with.push(ending);
+    PathBuf::from(with)
+  };
+  let git_dir = [
+    with(".git/.git"),
+    path.join(".git"),
+    with(".git"),
+    path.clone(),
+  ]
+  .into_iter()
+  .find(|dir| is_git_dir(dir))?
+  .canonicalize()
+  .ok()?;
+
+  let whitelisted = options.whitelist.is_empty()
+    || options
+      .whitelist
+      .iter()
+      .any(|dir| dir.canonicalize().is_ok_and(|dir| git_dir.starts_with(dir)));
+  let exported = options.export_all || git_dir.join(EXPORT_OK).exists();
+  match whitelisted && exported {
+    true => Some(git_dir),
+    false => None,
+  }
+}
@@ -0,0 +1,170 @@
+use crate::crypto;
+use crate::object::pack::apply_delta;
+use crate::repack::delta;
+
+/// The characters of git's base85 encoding, in order of their value.
+const BASE85: &[u8; 85] =
+  b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// The most bytes encoded on one line of a binary hunk.
+const LINE_BYTES: usize = 52;
+
+/// One side of a `GIT binary patch`: how to get from one version of a file
+/// to the other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinaryHunk {
+  /// The whole of the result.
+  Literal(Vec<u8>),
+
+  /// A delta against the version the hunk is applied to, in the format used
+  /// by packs.
+  Delta(Vec<u8>),
+}
+
+impl BinaryHunk {
+  /// Works out the version of the file the hunk leads to from `base`.
+  pub fn apply(&self, base: &[u8]) -> Result<Vec<u8>, String> {
+    match self {
+      BinaryHunk::Literal(data) => Ok(data.clone()),
+      BinaryHunk::Delta(delta) => apply_delta(base, delta),
+    }
+  }
+}
+
+/// Renders the body of a binary patch from `old` to `new`: a hunk that turns
+/// the old version into the new one, then one that turns it back.
+///
+/// Each hunk is either the new version (`literal`) or a delta against the
+/// old one (`delta`), whichever is smaller once deflated, followed by its
+/// size before deflating. The deflated data is written in base85, up to 52
+/// bytes to a line, each line starting with a letter for how many bytes it
+/// holds (`A` to `Z` for 1 to 26, then `a` to `z`), and a blank line ends
+/// the hunk.
+pub fn patch(old: &[u8], new: &[u8]) -> Result<String, String> {
+  Ok(format!(
+    "GIT binary patch\n{}{}",
+    hunk(old, new)?,
+    hunk(new, old)?
+  ))
+}
+
+/// Renders the hunk that turns `from` into `to`.
+fn hunk(from: &[u8], to: &[u8]) -> Result<String, String> {
+  let literal = crypto::compress(to)?;
+  let delta = match from.is_empty() || to.is_empty() {
+    true => None,
+    false => delta::create(from, to, literal.len()),
+  };
+  let delta = match delta {
+    Some(delta) => Some((delta.len(), crypto::compress(&delta)?)),
+    None => None,
+  };
+  let (mut out, data) = match delta {
+    Some((size, deflated)) if deflated.len() < literal.len() => {
+      (format!("delta {}\n", size), deflated)
+    }
+    _ => (format!("literal {}\n", to.len()), literal),
+  };
+  for chunk in data.chunks(LINE_BYTES) {
+    out.push(match chunk.len() {
+      len @ 1..=26 => (b'A' + len as u8 - 1) as char,
+      len => (b'a' + len as u8 - 27) as char,
+    });
+    out.push_str(&encode_85(chunk));
+    out.push('\n');
+  }
+  out.push('\n');
+  Ok(out)
+}
+
+/// Parses a binary hunk from its `literal` or `delta` line to the blank line
+/// that ends it, returning it along with the number of lines it took up.
+/// Returns `None` if the first line doesn't start a hunk.
+pub fn parse_hunk(lines: &[&[u8]]) -> Result<Option<(BinaryHunk, usize)>, String> {
+  let header = match lines.first() {
+    Some(header) => String::from_utf8_lossy(header),
+    None => return Ok(None),
+  };
+  let header = header.trim_end();
+  let (literal, size) = match (
+    header.strip_prefix("literal "),
+    header.strip_prefix("delta "),
+  ) {
+    (Some(size), _) => (true, size),
+    (None, Some(size)) => (false, size),
+    (None, None) => return Ok(None),
+  };
+  let size: usize = size
+    .parse()
+    .map_err(|_| format!("corrupt binary patch: {}", header))?;
+
+  let mut deflated = Vec::new();
+  let mut used = 1;
+  for line in &lines[1..] {
+    used += 1;
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let (len, encoded) = match line.split_first() {
+      None => break,
+      Some((c @ b'A'..=b'Z', rest)) => ((c - b'A' + 1) as usize, rest),
+      Some((c @ b'a'..=b'z', rest)) => ((c - b'a' + 27) as usize, rest),
+      Some(_) => return Err(format!("corrupt binary patch at line {}", used)),
+    };
+    match decode_85(encoded, len) {
+      Some(bytes) => deflated.extend(bytes),
+      None => return Err(format!("corrupt binary patch at line {}", used)),
+    }
+  }
+  let data = crypto::decompress(&deflated)?;
+  if data.len() != size {
+    return Err(format!(
+      "corrupt binary patch: expected {} bytes, got {}",
+      size,
+      data.len()
+    ));
+  }
+  let hunk = match literal {
+    true => BinaryHunk::Literal(data),
+    false => BinaryHunk::Delta(data),
+  };
+  Ok(Some((hunk, used)))
+}
+
+/// Encodes data in base85, each 4 bytes (the last padded with zeros) as 5
+/// characters, most significant first.
+fn encode_85(data: &[u8]) -> String {
+  let mut out = String::new();
+  for group in data.chunks(4) {
+    let mut value = group
+      .iter()
+      .chain([0; 4].iter())
+      .take(4)
+      .fold(0u32, |value, byte| value << 8 | *byte as u32);
+    let mut chars = [0u8; 5];
+    for c in chars.iter_mut().rev() {
+      *c = BASE85[(value % 85) as usize];
+      value /= 85;
+    }
+    out.push_str(std::str::from_utf8(&chars).unwrap());
+  }
+  out
+}
+
+/// Decodes `len` bytes of base85 text, or `None` if it isn't valid or isn't
+/// as long as `len` bytes take.
+fn decode_85(text: &[u8], len: usize) -> Option<Vec<u8>> {
+  if text.len() != len.div_ceil(4) * 5 {
+    return None;
+  }
+  let mut out = Vec::new();
+  for group in text.chunks(5) {
+    let mut value = 0u32;
+    for c in group {
+      let digit = BASE85.iter().position(|b| b == c)? as u32;
+      value = value.checked_mul(85)?.checked_add(digit)?;
+    }
+    out.extend(value.to_be_bytes());
+  }
+  out.truncate(len);
+  Some(out)
+}
@@ -0,0 +1,293 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+
+use regex::bytes::Regex;
+
+use crate::attr::{Attributes, State};
+use crate::object::mode::Mode;
+use crate::repo::Repo;
+use crate::trace;
+
+/// A diff driver, named by the `diff` attribute of a path and set up in the
+/// `diff.<name>` section of the config.
+///
+/// `xfuncname` is a list of extended regular expressions, one to a line,
+/// picking the lines that head hunks in place of git's guess at where
+/// functions start: the nearest line above a hunk that one of them matches
+/// heads it, with the text of the first group if there is one (or else of
+/// the whole match). A pattern starting with `!` instead rules out the
+/// lines it matches. `funcname` is the same with basic regular expressions.
+///
+/// `command` is a program that shows the diff of the files in place of the
+/// usual patch, when external diffs are asked for. It is run with the path,
+/// then the file, hash and mode of each side, as `GIT_EXTERNAL_DIFF` is.
+///
+/// `wordRegex` says what counts as a word when diffing words rather than
+/// lines, as `diff.wordRegex` does for every path.
+///
+/// `binary = true` makes the files binary, which `-diff` does too, while
+/// setting `diff` makes them text whatever they look like.
+///
+/// A few languages have hunk headers built in, used when a path's driver is
+/// named after one and doesn't have its own.
+///
+/// ### Example
+/// ```text
+/// [diff "ini"]
+///   xfuncname = "^\\[.*\\]$"
+///   command = ini-diff
+/// ```
+#[derive(Default)]
+pub struct Driver {
+  /// The patterns for the lines heading hunks, if not git's guess.
+  pub funcname: Option<FuncName>,
+
+  /// The command showing the diff in place of the patch.
+  pub command: Option<String>,
+
+  /// Whether the files are binary (`Some(true)`) or text (`Some(false)`),
+  /// or `None` to guess from what is in them.
+  pub binary: Option<bool>,
+
+  /// What counts as a word when diffing words, if not runs of anything but
+  /// whitespace.
+  pub word_regex: Option<Regex>,
+}
+
+/// The patterns picking the lines that head hunks.
+pub struct FuncName {
+  /// Each pattern, and whether it rules lines out rather than in.
+  patterns: Vec<(Regex, bool)>,
+}
+
+/// The hunk headers built in for some languages, by driver name.
+const BUILTIN: [(&str, &str); 4] = [
+  (
+    "golang",
+    "^[ \t]*(func[ \t]*.*(\\{[ \t]*)?)\n^[ \t]*(type[ \t].*(struct|interface)[ \t]*(\\{[ \t]*)?)",
+  ),
+  ("markdown", "^ {0,3}#{1,6}[ \t].*"),
+  ("python", "^[ \t]*((class|(async[ \t]+)?def)[ \t].*)$"),
+  (
+    "rust",
+    "^[\t ]*((pub(\\([^\\)]+\\))?[\t ]+)?((async|const|unsafe|extern([\t ]+\"[^\"]+\"))[\t ]+)?(struct|enum|union|mod|trait|fn|impl|macro_rules!)[< \t]+[^;]*)$",
+  ),
+];
+
+impl Driver {
+  /// The driver for a path, from its `diff` attribute.
+  pub fn for_path(repo: &Repo, attributes: &mut Attributes, path: &[u8]) -> Result<Driver, String> {
+    let words = config(repo, "diff", "wordRegex");
+    let (name, binary) = match attributes.get(path, "diff") {
+      State::Set => (None, Some(false)),
+      State::Unset => (None, Some(true)),
+      State::Value(name) => (Some(name), None),
+      State::Unspecified => (None, None),
+    };
+    let name = match name {
+      Some(name) => name,
+      None => {
+        return Ok(Driver {
+          binary,
+          word_regex: word_regex(words)?,
+          ..Default::default()
+        })
+      }
+    };
+
+    let section = format!("diff \"{}\"", name);
+    let get = |key: &str| config(repo, &section, key);
+    let funcname = match (get("xfuncname"), get("funcname")) {
+      (Some(patterns), _) => Some(FuncName::new(&patterns, true)?),
+      (None, Some(patterns)) => Some(FuncName::new(&patterns, false)?),
+      (None, None) => match BUILTIN.iter().find(|(builtin, _)| *builtin == name) {
+        Some((_, patterns)) => Some(FuncName::new(patterns, true)?),
+        None => None,
+      },
+    };
+    let binary = get("binary").map(|value| {
+      let value = value.to_ascii_lowercase();
+      matches!(value.as_str(), "true" | "yes" | "on" | "1" | "")
+    });
+    Ok(Driver {
+      funcname,
+      command: get("command"),
+      binary,
+      word_regex: word_regex(get("wordRegex").or(words))?,
+    })
+  }
+
+  /// Runs the driver's command on the two sides of a change to `path`,
+  /// returning what it prints. A side that is missing is passed as
+  /// `/dev/null`, with `.` for its hash and mode. A renamed file also gets
+  /// its new path and the header lines describing the rename.
+  pub fn external(
+    &self,
+    repo: &Repo,
+    path: &str,
+    old: Option<(&Mode, &str, &[u8])>,
+    new: Option<(&Mode, &str, &[u8])>,
+    rename: Option<(&str, &str)>,
+  ) -> Result<Option<Vec<u8>>, String> {
+    let command = match &self.command {
+      Some(command) => command,
+      None => return Ok(None),
+    };
+    let mut files = Vec::new();
+    let mut args = vec![path.to_string()];
+    for (side, version) in [("old", old), ("new", new)] {
+      match version {
+        Some((mode, hash, data)) => {
+          let file = temp_file(side, path);
+          fs::write(&file, data)
+            .map_err(|e| format!("unable to write temporary file {} ({})", file.display(), e))?;
+          args.extend([
+            file.display().to_string(),
+            hash.to_string(),
+            mode.to_string(),
+          ]);
+          files.push(file);
+        }
+        None => args.extend(["/dev/null", ".", "."].map(String::from)),
+      }
+    }
+    if let Some((new_path, header)) = rename {
+      args.extend([new_path.to_string(), header.to_string()]);
+    }
+
+    let mut child = Command::new("sh");
+    child
+      .arg("-c")
+      .arg(format!("{} \"$@\"", command))
+      .arg(command)
+      .args(&args)
+      .current_dir(&repo.work_tree)
+      .stdout(Stdio::piped());
+    let traced = trace::command("diff", &child);
+    let output = child.spawn().and_then(|child| {
+      let pid = child.id();
+      let output = child.wait_with_output()?;
+      traced.exit(pid, &output.status);
+      Ok(output)
+    });
+    files.iter().for_each(|file| drop(fs::remove_file(file)));
+    let output = output.map_err(|e| format!("cannot run external diff '{}' ({})", command, e))?;
+    match output.status.success() {
+      true => Ok(Some(output.stdout)),
+      false => Err(format!("external diff died, stopping at {}", path)),
+    }
+  }
+}
+
+impl FuncName {
+  /// Compiles patterns given one to a line, as extended regular
+  /// expressions or else basic ones.
+  pub fn new(patterns: &str, extended: bool) -> Result<FuncName, String> {
+    let patterns = patterns
+      .lines()
+      .map(|line| {
+        let (pattern, negate) = match line.strip_prefix('!') {
+          Some(pattern) => (pattern, true),
+          None => (line, false),
+        };
+        let pattern = match extended {
+          true => pattern.to_string(),
+          false => to_extended(pattern),
+        };
+        Regex::new(&pattern)
+          .map(|regex| (regex, negate))
+          .map_err(|_| format!("Invalid regexp to look for hunk header: {}", line))
+      })
+      .collect::<Result<_, _>>()?;
+    Ok(FuncName { patterns })
+  }
+
+  /// The text a line heads a hunk with, or `None` if it can't head one.
+  pub fn find(&self, line: &[u8]) -> Option<String> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    for (regex, negate) in &self.patterns {
+      if let Some(captures) = regex.captures(line) {
+        if *negate {
+          return None;
+        }
+        let found = captures.get(1).or_else(|| captures.get(0))?.as_bytes();
+        let found = &found[..found.len().min(80)];
+        return Some(String::from_utf8_lossy(found).trim_end().to_string());
+      }
+    }
+    None
+  }
+}
+
+/// Rewrites a basic regular expression as an extended one, where `(`, `)`,
+/// `{`, `}`, `|`, `+` and `?` are special unless escaped, rather than the
+/// other way around.
+fn to_extended(pattern: &str) -> String {
+  const SWAPPED: &str = "(){}|+?";
+  let mut out = String::new();
+  let mut chars = pattern.chars();
+  let mut bracket = false;
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' if !bracket => match chars.next() {
+        Some(next) if SWAPPED.contains(next) => out.push(next),
+        Some(next) => {
+          out.push('\\');
+          out.push(next);
+        }
+        None => out.push_str("\\\\"),
+      },
+      '[' if !bracket => {
+        bracket = true;
+        out.push(c);
+        // a `]` straight after the `[` (or `[^`) is part of the set
+        let rest = chars.as_str();
+        let skip = match rest.strip_prefix('^') {
+          Some(after) if after.starts_with(']') => 2,
+          _ if rest.starts_with(']') => 1,
+          _ => 0,
+        };
+        for _ in 0..skip {
+          out.push(chars.next().unwrap());
+        }
+      }
+      ']' if bracket => {
+        bracket = false;
+        out.push(c);
+      }
+      c if !bracket && SWAPPED.contains(c) => {
+        out.push('\\');
+        out.push(c);
+      }
+      c => out.push(c),
+    }
+  }
+  out
+}
+
+/// Compiles the pattern for words, if there is one.
+fn word_regex(pattern: Option<String>) -> Result<Option<Regex>, String> {
+  pattern
+    .map(|pattern| {
+      Regex::new(&pattern).map_err(|_| format!("invalid regular expression: {}", pattern))
+    })
+    .transpose()
+}
+
+fn config(repo: &Repo, section: &str, key: &str) -> Option<String> {
+  let section = repo.config.as_ref()?.section(Some(section))?;
+  section
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_owned())
+}
+
+/// A temporary file for one side of an external diff, named after the file
+/// so that the command can tell what kind of file it is.
+fn temp_file(side: &str, path: &str) -> PathBuf {
+  let name = path.rsplit('/').next().unwrap_or(path);
+  env::temp_dir().join(format!("git_rs_{}_{}_{}", process::id(), side, name))
+}
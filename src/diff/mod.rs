@@ -0,0 +1,402 @@
+pub mod binary;
+pub mod driver;
+pub mod patch;
+pub mod patch_id;
+pub mod pickaxe;
+pub mod raw;
+pub mod words;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use bstr::BString;
+
+use crate::object::{self, blob::Blob, mode::Mode, serializable::Unbox, tree};
+use crate::repo::Repo;
+
+/// What happened to an element of the old sequence to produce the new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+  Equal,
+  Delete,
+  Insert,
+}
+
+/// A single step of an edit script.
+///
+/// `old` and `new` are the positions of the element in the old and new
+/// sequences. Only `old` is meaningful for a deletion and only `new` is
+/// meaningful for an insertion.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edit {
+  pub op: Op,
+  pub old: usize,
+  pub new: usize,
+}
+
+/// Computes a shortest edit script between two sequences.
+///
+/// This is Eugene Myers' O(ND) algorithm ("An O(ND) Difference Algorithm and
+/// Its Variations", 1986), the same one git uses by default. It works on any
+/// sequence of comparable items, so lines, words and characters can all be
+/// diffed with it. Common prefixes and suffixes are stripped first since they
+/// are cheap to find and usually make up most of a file.
+pub fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+  let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+  let suffix = a[prefix..]
+    .iter()
+    .rev()
+    .zip(b[prefix..].iter().rev())
+    .take_while(|(x, y)| x == y)
+    .count();
+  let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+  let mut edits: Vec<Edit> = (0..prefix)
+    .map(|i| Edit {
+      op: Op::Equal,
+      old: i,
+      new: i,
+    })
+    .collect();
+  for edit in shortest_edit(a_mid, b_mid) {
+    edits.push(Edit {
+      op: edit.op,
+      old: edit.old + prefix,
+      new: edit.new + prefix,
+    });
+  }
+  edits.extend((0..suffix).map(|i| Edit {
+    op: Op::Equal,
+    old: a.len() - suffix + i,
+    new: b.len() - suffix + i,
+  }));
+  edits
+}
+
+/// The core of Myers' algorithm: a greedy search for the furthest reaching
+/// path on each diagonal, followed by a backtrack through the saved states.
+fn shortest_edit<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+  let (n, m) = (a.len() as isize, b.len() as isize);
+  let max = (n + m) as usize;
+  let offset = max as isize + 1;
+  let mut v: Vec<isize> = vec![0; 2 * max + 3];
+  let mut trace: Vec<Vec<isize>> = Vec::new();
+
+  'search: for d in 0..=max as isize {
+    trace.push(v.clone());
+    let mut k = -d;
+    while k <= d {
+      let idx = (k + offset) as usize;
+      let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+        v[idx + 1]
+      } else {
+        v[idx - 1] + 1
+      };
+      let mut y = x - k;
+      while x < n && y < m && a[x as usize] == b[y as usize] {
+        x += 1;
+        y += 1;
+      }
+      v[idx] = x;
+      if x >= n && y >= m {
+        break 'search;
+      }
+      k += 2;
+    }
+  }
+
+  // Walk backwards through the trace to recover the path.
+  let mut edits = Vec::new();
+  let (mut x, mut y) = (n, m);
+  for (d, v) in trace.iter().enumerate().rev() {
+    let d = d as isize;
+    let k = x - y;
+    let idx = (k + offset) as usize;
+    let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+      k + 1
+    } else {
+      k - 1
+    };
+    let prev_x = v[(prev_k + offset) as usize];
+    let prev_y = prev_x - prev_k;
+    while x > prev_x && y > prev_y {
+      x -= 1;
+      y -= 1;
+      edits.push(Edit {
+        op: Op::Equal,
+        old: x as usize,
+        new: y as usize,
+      });
+    }
+    if d > 0 {
+      if x == prev_x {
+        edits.push(Edit {
+          op: Op::Insert,
+          old: x as usize,
+          new: prev_y as usize,
+        });
+      } else {
+        edits.push(Edit {
+          op: Op::Delete,
+          old: prev_x as usize,
+          new: y as usize,
+        });
+      }
+    }
+    x = prev_x;
+    y = prev_y;
+  }
+  edits.reverse();
+  edits
+}
+
+/// Splits data into lines, keeping the trailing newline on each line.
+pub fn lines(data: &[u8]) -> Vec<&[u8]> {
+  data.split_inclusive(|b| *b == b'\n').collect()
+}
+
+/// A group of nearby changes along with some unchanged context lines.
+#[derive(Debug)]
+pub struct Hunk {
+  /// The first line of the hunk in the old file (one-based).
+  pub old_start: usize,
+  pub old_len: usize,
+
+  /// The first line of the hunk in the new file (one-based).
+  pub new_start: usize,
+  pub new_len: usize,
+
+  /// The edits making up the hunk, context included.
+  pub edits: Vec<Edit>,
+}
+
+impl Hunk {
+  /// Formats the `@@ -a,b +c,d @@` line that starts a hunk.
+  pub fn header(&self) -> String {
+    format!(
+      "@@ -{} +{} @@",
+      range(self.old_start, self.old_len),
+      range(self.new_start, self.new_len)
+    )
+  }
+}
+
+/// Formats one side of a hunk header the way `diff -u` does.
+fn range(start: usize, len: usize) -> String {
+  match len {
+    1 => format!("{}", start),
+    0 => format!("{},0", start - 1),
+    _ => format!("{},{}", start, len),
+  }
+}
+
+/// Groups an edit script into hunks with the given number of context lines.
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+  let changes: Vec<usize> = (0..edits.len())
+    .filter(|i| edits[*i].op != Op::Equal)
+    .collect();
+  let mut hunks = Vec::new();
+  let mut i = 0;
+  while i < changes.len() {
+    // Extend the hunk while the next change is close enough to share context.
+    let start = changes[i].saturating_sub(context);
+    let mut end = changes[i];
+    while i + 1 < changes.len() && changes[i + 1] - end <= 2 * context + 1 {
+      i += 1;
+      end = changes[i];
+    }
+    let end = (end + context + 1).min(edits.len());
+    let slice = &edits[start..end];
+
+    let old_len = slice.iter().filter(|e| e.op != Op::Insert).count();
+    let new_len = slice.iter().filter(|e| e.op != Op::Delete).count();
+    let old_start = match slice.iter().find(|e| e.op != Op::Insert) {
+      Some(edit) => edit.old + 1,
+      None => slice[0].old + 1,
+    };
+    let new_start = match slice.iter().find(|e| e.op != Op::Delete) {
+      Some(edit) => edit.new + 1,
+      None => slice[0].new + 1,
+    };
+    hunks.push(Hunk {
+      old_start: if old_len == 0 {
+        old_start
+      } else {
+        old_start.max(1)
+      },
+      old_len,
+      new_start,
+      new_len,
+      edits: slice.to_vec(),
+    });
+    i += 1;
+  }
+  hunks
+}
+
+/// A file that differs between two trees.
+#[derive(Debug)]
+pub struct TreeChange {
+  pub path: BString,
+
+  /// The mode and blob hash before the change (`None` if it was added).
+  pub old: Option<(Mode, String)>,
+
+  /// The mode and blob hash after the change (`None` if it was deleted).
+  pub new: Option<(Mode, String)>,
+
+  /// The path the file was renamed from, if rename detection paired it up.
+  pub renamed_from: Option<BString>,
+}
+
+/// Lists the files that differ between two trees, sorted by path.
+///
+/// Either tree may be `None`, which stands for the empty tree (eg. when
+/// diffing a root commit).
+pub fn diff_trees(
+  repo: &Repo,
+  old: Option<&str>,
+  new: Option<&str>,
+) -> Result<Vec<TreeChange>, String> {
+  let old = match old {
+    Some(hash) => tree::flatten(repo, hash)?,
+    None => Default::default(),
+  };
+  let new = match new {
+    Some(hash) => tree::flatten(repo, hash)?,
+    None => Default::default(),
+  };
+  Ok(compare(&old, &new))
+}
+
+/// Lists the files that differ between two path to (mode, hash) maps, as
+/// returned by [`tree::flatten`] or [`Index::files`](crate::index::Index::files).
+pub fn compare(
+  old: &BTreeMap<BString, (Mode, String)>,
+  new: &BTreeMap<BString, (Mode, String)>,
+) -> Vec<TreeChange> {
+  let paths: BTreeSet<&BString> = old.keys().chain(new.keys()).collect();
+  let mut changes = Vec::new();
+  for path in paths {
+    let (before, after) = (old.get(path), new.get(path));
+    if before != after {
+      changes.push(TreeChange {
+        path: path.clone(),
+        old: before.cloned(),
+        new: after.cloned(),
+        renamed_from: None,
+      });
+    }
+  }
+  changes
+}
+
+/// Pairs up deleted and added files that are really renames.
+///
+/// A deletion and an addition of the same blob are an exact rename. The rest
+/// are paired by content similarity, best match first, as long as the files
+/// are at least 50% similar. Each pair is replaced by a single change at the
+/// new path whose `renamed_from` names the old path.
+pub fn detect_renames(repo: &Repo, changes: Vec<TreeChange>) -> Result<Vec<TreeChange>, String> {
+  let deleted: Vec<usize> = (0..changes.len())
+    .filter(|i| changes[*i].new.is_none())
+    .collect();
+  let added: Vec<usize> = (0..changes.len())
+    .filter(|i| changes[*i].old.is_none())
+    .collect();
+  let mut pairs: Vec<(usize, usize)> = Vec::new();
+
+  // Exact renames first: the same blob was deleted and added.
+  for a in &added {
+    let hash = &changes[*a].new.as_ref().unwrap().1;
+    let source = deleted
+      .iter()
+      .find(|d| changes[**d].old.as_ref().unwrap().1 == *hash && !pairs.iter().any(|p| p.0 == **d));
+    if let Some(d) = source {
+      pairs.push((*d, *a));
+    }
+  }
+
+  // Then inexact renames, scored by how much content the files share.
+  let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+  let remaining_deleted: Vec<usize> = deleted
+    .iter()
+    .filter(|d| !pairs.iter().any(|p| p.0 == **d))
+    .cloned()
+    .collect();
+  let remaining_added: Vec<usize> = added
+    .iter()
+    .filter(|a| !pairs.iter().any(|p| p.1 == **a))
+    .cloned()
+    .collect();
+  if remaining_deleted.len() * remaining_added.len() <= RENAME_LIMIT * RENAME_LIMIT {
+    for d in &remaining_deleted {
+      let before = blob_data(repo, changes[*d].old.as_ref())?;
+      for a in &remaining_added {
+        let after = blob_data(repo, changes[*a].new.as_ref())?;
+        let score = similarity(&before, &after);
+        if score >= 0.5 {
+          candidates.push((score, *d, *a));
+        }
+      }
+    }
+  }
+  candidates.sort_by(|x, y| y.0.partial_cmp(&x.0).unwrap());
+  for (_, d, a) in candidates {
+    if !pairs.iter().any(|p| p.0 == d || p.1 == a) {
+      pairs.push((d, a));
+    }
+  }
+
+  // Fold each deletion into the addition it was paired with.
+  let mut slots: Vec<Option<TreeChange>> = changes.into_iter().map(Some).collect();
+  for (d, a) in pairs {
+    let source = slots[d].take().unwrap();
+    let target = slots[a].as_mut().unwrap();
+    target.renamed_from = Some(source.path);
+    target.old = source.old;
+  }
+  Ok(slots.into_iter().flatten().collect())
+}
+
+/// The most deletions (or additions) compared pairwise when looking for
+/// inexact renames, like git's `diff.renameLimit`.
+const RENAME_LIMIT: usize = 1000;
+
+/// Scores how similar two files are, from 0 (nothing in common) to 1.
+///
+/// The score is the number of bytes in lines shared by both files divided by
+/// the size of the larger file, which is a rough take on git's own rename
+/// similarity index.
+pub fn similarity(one: &[u8], two: &[u8]) -> f64 {
+  let largest = one.len().max(two.len());
+  if largest == 0 {
+    return 1.0;
+  }
+  let mut lines: HashMap<&[u8], usize> = HashMap::new();
+  for line in one.split_inclusive(|b| *b == b'\n') {
+    *lines.entry(line).or_insert(0) += 1;
+  }
+  let mut common = 0;
+  for line in two.split_inclusive(|b| *b == b'\n') {
+    if let Some(count) = lines.get_mut(line).filter(|count| **count > 0) {
+      *count -= 1;
+      common += line.len();
+    }
+  }
+  common as f64 / largest as f64
+}
+
+/// Reads the contents of a blob, treating a missing side as empty.
+///
+/// A submodule has no blob, so it reads as the commit it is at, the way git
+/// shows submodules in diffs.
+pub fn blob_data(repo: &Repo, entry: Option<&(Mode, String)>) -> Result<Vec<u8>, String> {
+  match entry {
+    Some((Mode::Directory, _)) | None => Ok(Vec::new()),
+    Some((Mode::Gitlink, hash)) => Ok(format!("Subproject commit {}\n", hash).into_bytes()),
+    Some((_, hash)) => {
+      let object = object::read(repo, hash, Some("blob"))?;
+      Ok(object.unbox::<Blob>()?.data().to_owned())
+    }
+  }
+}
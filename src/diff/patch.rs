@@ -0,0 +1,576 @@
+use crate::attr::Attributes;
+use crate::object::mode::Mode;
+use crate::repo::Repo;
+use crate::submodule;
+
+use super::binary;
+use super::driver::{Driver, FuncName};
+use super::raw::NULL_HASH;
+use super::words::{self, WordDiff};
+use super::{blob_data, hunks, lines, myers, similarity, Op, TreeChange};
+
+/// The number of context lines shown around each change.
+pub const CONTEXT: usize = 3;
+
+/// Renders a change in the `diff --git` format that `git apply` and `git am`
+/// read back.
+///
+/// The header names both sides of the change and notes created and deleted
+/// files, mode changes and renames, followed by an `index` line with the
+/// abbreviated blob hashes. The hunks that follow are in unified format, each
+/// headed by the nearest line above it that looks like the start of a
+/// function, or that the path's diff driver picks. Binary files are only
+/// reported as differing.
+pub fn file_patch(repo: &Repo, change: &TreeChange) -> Result<String, String> {
+  file_patch_with(repo, change, None, false)
+}
+
+/// Renders a change like [`file_patch`], with the changed words shown in
+/// place of the changed lines when `words` is given.
+///
+/// With `binary`, changes to binary files are written out as a `GIT binary
+/// patch` (see [`binary::patch`]) that can be applied, with the full blob
+/// hashes on their `index` line so that what they apply to can be checked.
+pub fn file_patch_with(
+  repo: &Repo,
+  change: &TreeChange,
+  words: Option<WordDiff>,
+  binary: bool,
+) -> Result<String, String> {
+  let old_path = change.renamed_from.as_ref().unwrap_or(&change.path);
+  let mut out = format!("diff --git a/{} b/{}\n", old_path, change.path);
+  match (&change.old, &change.new) {
+    (None, Some((mode, _))) => out.push_str(&format!("new file mode {}\n", mode)),
+    (Some((mode, _)), None) => out.push_str(&format!("deleted file mode {}\n", mode)),
+    (Some((old, _)), Some((new, _))) if old != new => {
+      out.push_str(&format!("old mode {}\nnew mode {}\n", old, new));
+    }
+    _ => (),
+  }
+
+  let before = blob_data(repo, change.old.as_ref())?;
+  let after = blob_data(repo, change.new.as_ref())?;
+  if change.renamed_from.is_some() {
+    out.push_str(&format!(
+      "similarity index {}%\nrename from {}\nrename to {}\n",
+      score(change, &before, &after),
+      old_path,
+      change.path
+    ));
+  }
+  let driver = Driver::for_path(repo, &mut Attributes::new(repo), &change.path)?;
+  let binary_file = driver
+    .binary
+    .unwrap_or_else(|| is_binary(&before) || is_binary(&after));
+  let (old_hash, new_hash) = (hash(change.old.as_ref()), hash(change.new.as_ref()));
+  if old_hash != new_hash {
+    match binary_file && binary {
+      true => out.push_str(&format!("index {}..{}", old_hash, new_hash)),
+      false => out.push_str(&format!("index {}..{}", &old_hash[..7], &new_hash[..7])),
+    }
+    match (&change.old, &change.new) {
+      (Some((old, _)), Some((new, _))) if old == new => out.push_str(&format!(" {}", old)),
+      _ => (),
+    }
+    out.push('\n');
+  }
+
+  let old_label = match change.old {
+    Some(_) => format!("a/{}", old_path),
+    None => "/dev/null".to_string(),
+  };
+  let new_label = match change.new {
+    Some(_) => format!("b/{}", change.path),
+    None => "/dev/null".to_string(),
+  };
+  if binary_file {
+    if old_hash != new_hash {
+      match binary {
+        true => out.push_str(&binary::patch(&before, &after)?),
+        false => out.push_str(&format!(
+          "Binary files {} and {} differ\n",
+          old_label, new_label
+        )),
+      }
+    }
+    return Ok(out);
+  }
+  let words = words.map(|words| WordDiff {
+    regex: words.regex.or(driver.word_regex.as_ref()),
+    ..words
+  });
+  let body = unified_with(&before, &after, driver.funcname.as_ref(), words);
+  if !body.is_empty() {
+    out.push_str(&format!("--- {}\n+++ {}\n{}", old_label, new_label, body));
+  }
+  Ok(out)
+}
+
+/// Runs the external diff command of a changed path's diff driver, if it
+/// has one, returning what it prints in place of the patch.
+pub fn external(repo: &Repo, change: &TreeChange) -> Result<Option<Vec<u8>>, String> {
+  let driver = Driver::for_path(repo, &mut Attributes::new(repo), &change.path)?;
+  if driver.command.is_none() {
+    return Ok(None);
+  }
+  let before = blob_data(repo, change.old.as_ref())?;
+  let after = blob_data(repo, change.new.as_ref())?;
+  let old_path = change
+    .renamed_from
+    .as_ref()
+    .unwrap_or(&change.path)
+    .to_string();
+  let header = format!(
+    "similarity index {}%\nrename from {}\nrename to {}\n",
+    score(change, &before, &after),
+    old_path,
+    change.path
+  );
+  let new_path = change.path.to_string();
+  driver.external(
+    repo,
+    &old_path,
+    change
+      .old
+      .as_ref()
+      .map(|(mode, hash)| (mode, hash.as_str(), &before[..])),
+    change
+      .new
+      .as_ref()
+      .map(|(mode, hash)| (mode, hash.as_str(), &after[..])),
+    change
+      .renamed_from
+      .is_some()
+      .then_some((&new_path, &header)),
+  )
+}
+
+/// Describes a change to a submodule by the commits it moves between, as
+/// [`submodule::log`] does, or returns `None` if the change isn't to a
+/// submodule (or turns a file into one, or the other way around).
+pub fn submodule_log(repo: &Repo, change: &TreeChange) -> Result<Option<String>, String> {
+  let (old, new) = match (&change.old, &change.new) {
+    (Some((Mode::Gitlink, old)), Some((Mode::Gitlink, new))) => (Some(old), Some(new)),
+    (Some((Mode::Gitlink, old)), None) => (Some(old), None),
+    (None, Some((Mode::Gitlink, new))) => (None, Some(new)),
+    _ => return Ok(None),
+  };
+  let path = change.path.to_string();
+  submodule::log(
+    repo,
+    &path,
+    old.map(String::as_str),
+    new.map(String::as_str),
+  )
+  .map(Some)
+}
+
+/// Renders the hunks of a line diff in unified format (without file headers).
+pub fn unified(old: &[u8], new: &[u8]) -> String {
+  unified_with(old, new, None, None)
+}
+
+/// Renders the hunks of a line diff in unified format, headed by the lines
+/// that `funcname` picks (or, without it, git's guess at where functions
+/// start), and with the words that changed in place of the lines when
+/// `words` is given.
+pub fn unified_with(
+  old: &[u8],
+  new: &[u8],
+  funcname: Option<&FuncName>,
+  words: Option<WordDiff>,
+) -> String {
+  let (a, b) = (lines(old), lines(new));
+  let mut out = String::new();
+  for hunk in hunks(&myers(&a, &b), CONTEXT) {
+    out.push_str(&hunk.header());
+    let before = hunk.old_start.saturating_sub(1);
+    let function = match funcname {
+      Some(funcname) => a[..before.min(a.len())]
+        .iter()
+        .rev()
+        .find_map(|line| funcname.find(line)),
+      None => function_line(&a, before),
+    };
+    if let Some(function) = function {
+      out.push(' ');
+      out.push_str(&function);
+    }
+    out.push('\n');
+    if let Some(words) = words {
+      out.push_str(&words::hunk(&a, &b, &hunk, words.regex, words.style));
+      continue;
+    }
+    for edit in &hunk.edits {
+      let (sign, line) = match edit.op {
+        Op::Equal => (' ', a[edit.old]),
+        Op::Delete => ('-', a[edit.old]),
+        Op::Insert => ('+', b[edit.new]),
+      };
+      out.push(sign);
+      out.push_str(&String::from_utf8_lossy(line));
+      if !line.ends_with(b"\n") {
+        out.push_str("\n\\ No newline at end of file\n");
+      }
+    }
+  }
+  out
+}
+
+/// Finds the closest line above `before` (exclusive, zero-based) that starts
+/// with an identifier, which is git's default guess at a function header.
+fn function_line(lines: &[&[u8]], before: usize) -> Option<String> {
+  lines[..before.min(lines.len())]
+    .iter()
+    .rev()
+    .find(
+      |line| matches!(line.first(), Some(c) if c.is_ascii_alphabetic() || *c == b'_' || *c == b'$'),
+    )
+    .map(|line| {
+      let line = &line[..line.len().min(80)];
+      String::from_utf8_lossy(line).trim_end().to_string()
+    })
+}
+
+/// The number of lines a change adds and removes, or `None` for binary files.
+pub fn line_counts(repo: &Repo, change: &TreeChange) -> Result<Option<(usize, usize)>, String> {
+  let before = blob_data(repo, change.old.as_ref())?;
+  let after = blob_data(repo, change.new.as_ref())?;
+  let driver = Driver::for_path(repo, &mut Attributes::new(repo), &change.path)?;
+  if driver
+    .binary
+    .unwrap_or_else(|| is_binary(&before) || is_binary(&after))
+  {
+    return Ok(None);
+  }
+  let edits = myers(&lines(&before), &lines(&after));
+  let added = edits.iter().filter(|e| e.op == Op::Insert).count();
+  let deleted = edits.iter().filter(|e| e.op == Op::Delete).count();
+  Ok(Some((added, deleted)))
+}
+
+/// A file's line in a stat: its name, the lines it adds and deletes (or `None`
+/// when it is binary) and its old and new sizes in bytes.
+type StatRow = (String, Option<(usize, usize)>, (usize, usize));
+
+/// Renders the `--stat` summary of a set of changes, `width` columns wide.
+///
+/// Each file gets a line with its name, the number of changed lines and a
+/// bar of `+` and `-` signs, scaled down when the largest change does not fit
+/// into the space left over by the longest name. A total line follows.
+pub fn stat(repo: &Repo, changes: &[TreeChange], width: usize) -> Result<String, String> {
+  let mut rows: Vec<StatRow> = Vec::new();
+  for change in changes {
+    let sizes = (
+      blob_data(repo, change.old.as_ref())?.len(),
+      blob_data(repo, change.new.as_ref())?.len(),
+    );
+    rows.push((display_name(change), line_counts(repo, change)?, sizes));
+  }
+
+  let max_len = rows.iter().map(|r| r.0.chars().count()).max().unwrap_or(0);
+  let max_change = rows
+    .iter()
+    .filter_map(|r| r.1.map(|(a, d)| a + d))
+    .max()
+    .unwrap_or(0);
+  let bin_width = rows
+    .iter()
+    .filter(|r| r.1.is_none())
+    .map(|r| 14 + decimal_width(r.2 .0) + decimal_width(r.2 .1))
+    .max()
+    .unwrap_or(0);
+  let mut number_width = decimal_width(max_change);
+  if bin_width > 0 {
+    number_width = number_width.max(3);
+  }
+
+  // split the width between the names and the graph the same way git does
+  let width = width.max(16 + 6 + number_width);
+  let mut graph_width = if max_change + 4 > bin_width {
+    max_change
+  } else {
+    bin_width - 4
+  };
+  let mut name_width = max_len;
+  if name_width + number_width + 6 + graph_width > width {
+    if graph_width + number_width + 6 > width * 3 / 8 {
+      graph_width = (width * 3 / 8).saturating_sub(number_width + 6).max(6);
+    }
+    if name_width + number_width + 6 + graph_width > width {
+      name_width = width - number_width - 6 - graph_width;
+    } else {
+      graph_width = width - number_width - 6 - name_width;
+    }
+  }
+
+  let mut out = String::new();
+  let (mut insertions, mut deletions) = (0, 0);
+  for (name, counts, (old_size, new_size)) in &rows {
+    let name = truncate_name(name, name_width);
+    let padding = name_width.saturating_sub(name.chars().count());
+    let (added, deleted) = match counts {
+      Some(counts) => *counts,
+      None => {
+        out.push_str(&format!(
+          " {}{} | {:>w$} {} -> {} bytes\n",
+          name,
+          " ".repeat(padding),
+          "Bin",
+          old_size,
+          new_size,
+          w = number_width
+        ));
+        continue;
+      }
+    };
+    insertions += added;
+    deletions += deleted;
+    let (mut plus, mut minus) = (added, deleted);
+    if graph_width <= max_change {
+      let mut total = scale(added + deleted, graph_width, max_change);
+      if total < 2 && added > 0 && deleted > 0 {
+        total = 2;
+      }
+      if added < deleted {
+        plus = scale(added, graph_width, max_change);
+        minus = total - plus;
+      } else {
+        minus = scale(deleted, graph_width, max_change);
+        plus = total - minus;
+      }
+    }
+    out.push_str(&format!(
+      " {}{} | {:>w$}{}{}{}\n",
+      name,
+      " ".repeat(padding),
+      added + deleted,
+      if added + deleted > 0 { " " } else { "" },
+      "+".repeat(plus),
+      "-".repeat(minus),
+      w = number_width
+    ));
+  }
+  out.push_str(&shortstat(rows.len(), insertions, deletions));
+  out.push('\n');
+  Ok(out)
+}
+
+/// The summaries of a set of changes to show in place of their patches, as
+/// `--numstat`, `--stat` and `--shortstat` ask for them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+  pub numstat: bool,
+
+  /// The width of the `--stat` to show, if any.
+  pub stat: Option<usize>,
+
+  pub shortstat: bool,
+}
+
+impl Stats {
+  /// Whether any summary is asked for.
+  pub fn any(&self) -> bool {
+    self.numstat || self.stat.is_some() || self.shortstat
+  }
+
+  /// Renders the summaries asked for, in the order git shows them. The
+  /// `--shortstat` line already ends a `--stat`, so isn't shown twice.
+  pub fn render(&self, repo: &Repo, changes: &[TreeChange], nul: bool) -> Result<String, String> {
+    let mut out = String::new();
+    if self.numstat {
+      out.push_str(&numstat(repo, changes, nul)?);
+    }
+    if let Some(width) = self.stat {
+      out.push_str(&stat(repo, changes, width)?);
+    } else if self.shortstat && !changes.is_empty() {
+      let (files, insertions, deletions) = totals(repo, changes)?;
+      out.push_str(&shortstat(files, insertions, deletions));
+      out.push('\n');
+    }
+    Ok(out)
+  }
+}
+
+/// Renders the `--numstat` lines of a set of changes: the number of lines
+/// each adds and deletes (`-` for binary files) and its name, separated by
+/// tabs. With `nul` each ends with a NUL rather than a newline, and a
+/// rename has its old and new names in place of the one, each followed by a
+/// NUL.
+pub fn numstat(repo: &Repo, changes: &[TreeChange], nul: bool) -> Result<String, String> {
+  let mut out = String::new();
+  for change in changes {
+    match line_counts(repo, change)? {
+      Some((added, deleted)) => out.push_str(&format!("{}\t{}\t", added, deleted)),
+      None => out.push_str("-\t-\t"),
+    }
+    match (&change.renamed_from, nul) {
+      (Some(from), true) => out.push_str(&format!("\0{}\0{}\0", from, change.path)),
+      (None, true) => out.push_str(&format!("{}\0", change.path)),
+      (_, false) => out.push_str(&format!("{}\n", display_name(change))),
+    }
+  }
+  Ok(out)
+}
+
+/// The number of files a set of changes touches, and the lines they add and
+/// delete in all, for [`shortstat`]. Binary files count as files, but add
+/// and delete no lines.
+pub fn totals(repo: &Repo, changes: &[TreeChange]) -> Result<(usize, usize, usize), String> {
+  let (mut insertions, mut deletions) = (0, 0);
+  for change in changes {
+    if let Some((added, deleted)) = line_counts(repo, change)? {
+      insertions += added;
+      deletions += deleted;
+    }
+  }
+  Ok((changes.len(), insertions, deletions))
+}
+
+/// Formats the `N files changed, N insertions(+), N deletions(-)` line.
+pub fn shortstat(files: usize, insertions: usize, deletions: usize) -> String {
+  let plural =
+    |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+  let mut out = format!(" {} changed", plural(files, "file", "files"));
+  if insertions > 0 || deletions == 0 {
+    out.push_str(&format!(
+      ", {}",
+      plural(insertions, "insertion(+)", "insertions(+)")
+    ));
+  }
+  if deletions > 0 || insertions == 0 {
+    out.push_str(&format!(
+      ", {}",
+      plural(deletions, "deletion(-)", "deletions(-)")
+    ));
+  }
+  out
+}
+
+/// Renders the `--summary` lines: created and deleted files, renames and mode
+/// changes.
+pub fn summary(repo: &Repo, changes: &[TreeChange]) -> Result<String, String> {
+  let mut out = String::new();
+  for change in changes {
+    match (&change.old, &change.new) {
+      (None, Some((mode, _))) => out.push_str(&format!(" create mode {} {}\n", mode, change.path)),
+      (Some((mode, _)), None) => out.push_str(&format!(" delete mode {} {}\n", mode, change.path)),
+      (Some((old, _)), Some((new, _))) => {
+        if change.renamed_from.is_some() {
+          let before = blob_data(repo, change.old.as_ref())?;
+          let after = blob_data(repo, change.new.as_ref())?;
+          out.push_str(&format!(
+            " rename {} ({}%)\n",
+            display_name(change),
+            score(change, &before, &after)
+          ));
+        }
+        if old != new {
+          let name = match change.renamed_from {
+            Some(_) => display_name(change),
+            None => change.path.to_string(),
+          };
+          out.push_str(&format!(" mode change {} => {} {}\n", old, new, name));
+        }
+      }
+      (None, None) => (),
+    }
+  }
+  Ok(out)
+}
+
+/// Names a change for `--stat` and `--summary`, showing renames with the part
+/// of the path that changed in braces, eg. `src/{old.rs => new.rs}`.
+pub fn display_name(change: &TreeChange) -> String {
+  let from = match &change.renamed_from {
+    Some(from) => from.as_slice(),
+    None => return change.path.to_string(),
+  };
+  let to = change.path.as_slice();
+
+  // the common prefix ends with a slash
+  let mut prefix = 0;
+  for (i, (x, y)) in from.iter().zip(to.iter()).enumerate() {
+    if x != y {
+      break;
+    }
+    if *x == b'/' {
+      prefix = i + 1;
+    }
+  }
+  // and the common suffix starts with one, without overlapping the prefix
+  let mut suffix = 0;
+  let limit = prefix.saturating_sub(1);
+  let (mut i, mut j) = (from.len(), to.len());
+  while i > limit && j > limit && from[i - 1] == to[j - 1] {
+    i -= 1;
+    j -= 1;
+    if from[i] == b'/' {
+      suffix = from.len() - i;
+    }
+  }
+
+  if prefix + suffix == 0 {
+    return format!("{} => {}", String::from_utf8_lossy(from), change.path);
+  }
+  let middle = |name: &[u8]| {
+    let end = name.len().saturating_sub(suffix).max(prefix);
+    String::from_utf8_lossy(&name[prefix..end]).to_string()
+  };
+  format!(
+    "{}{{{} => {}}}{}",
+    String::from_utf8_lossy(&from[..prefix]),
+    middle(from),
+    middle(to),
+    String::from_utf8_lossy(&from[from.len() - suffix..])
+  )
+}
+
+/// Shortens a name to `width` columns by replacing its start with `...`,
+/// cutting at a directory boundary when there is one.
+fn truncate_name(name: &str, width: usize) -> String {
+  let chars: Vec<char> = name.chars().collect();
+  if chars.len() <= width {
+    return name.to_string();
+  }
+  let keep = width.saturating_sub(3);
+  let tail: String = chars[chars.len() - keep..].iter().collect();
+  match tail.find('/') {
+    Some(slash) => format!("...{}", &tail[slash..]),
+    None => format!("...{}", tail),
+  }
+}
+
+/// Scales a change count to fit in a graph `width` columns wide.
+fn scale(count: usize, width: usize, max_change: usize) -> usize {
+  if count == 0 {
+    return 0;
+  }
+  1 + count * (width - 1) / max_change
+}
+
+fn decimal_width(n: usize) -> usize {
+  n.to_string().len()
+}
+
+/// The similarity of a renamed file as a whole percentage.
+fn score(change: &TreeChange, before: &[u8], after: &[u8]) -> usize {
+  match (&change.old, &change.new) {
+    (Some((_, old)), Some((_, new))) if old == new => 100,
+    _ => (similarity(before, after) * 100.0) as usize,
+  }
+}
+
+/// The blob hash of one side of a change, or all zeros if there is none.
+fn hash(entry: Option<&(Mode, String)>) -> String {
+  match entry {
+    Some((_, hash)) => hash.clone(),
+    None => NULL_HASH.to_string(),
+  }
+}
+
+/// Guesses whether data is binary the way git does: by looking for a NUL byte
+/// in the first 8000 bytes.
+pub fn is_binary(data: &[u8]) -> bool {
+  data[..data.len().min(8000)].contains(&0)
+}
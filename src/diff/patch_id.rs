@@ -0,0 +1,265 @@
+use std::iter::Peekable;
+use std::str::SplitInclusive;
+
+use crate::crypto::sha_1;
+use crate::object::{commit::Commit, peel, read, serializable::Unbox};
+use crate::repo::Repo;
+
+use super::raw::NULL_HASH;
+use super::{blob_data, diff_trees, hunks, lines, myers, patch, Op, TreeChange};
+
+/// Computes the patch id of a commit: a hash of its diff against its parent
+/// with whitespace and line numbers left out, so that the same change
+/// applied on top of a different base gets the same id. Merges have none.
+pub fn commit_patch_id(repo: &Repo, hash: &str, stable: bool) -> Result<Option<String>, String> {
+  let object = read(repo, hash, Some("commit"))?;
+  let commit = object.unbox::<Commit>()?;
+  let base = match commit.parents() {
+    [] => None,
+    [parent] => Some(peel(repo, parent, Some("tree"))?),
+    _ => return Ok(None),
+  };
+  let changes = diff_trees(repo, base.as_deref(), Some(commit.tree()))?;
+  diff_patch_id(repo, &changes, stable).map(Some)
+}
+
+/// Computes the patch id of a list of changes.
+///
+/// Each file contributes its header (paths and modes) and the lines of its
+/// hunks, with all whitespace removed. Binary files contribute their blob ids
+/// instead. A stable id hashes each file separately and adds the hashes up,
+/// so that it does not depend on the order of the files.
+pub fn diff_patch_id(repo: &Repo, changes: &[TreeChange], stable: bool) -> Result<String, String> {
+  let mut id = PatchId::new(stable);
+  for change in changes {
+    let old_path = change.renamed_from.as_ref().unwrap_or(&change.path);
+    id.add(format!("diff--gita/{}b/{}", old_path, change.path).as_bytes());
+    match (&change.old, &change.new) {
+      (None, Some((mode, _))) => id.add(format!("newfilemode{}", mode).as_bytes()),
+      (Some((mode, _)), None) => id.add(format!("deletedfilemode{}", mode).as_bytes()),
+      (Some((old, _)), Some((new, _))) if old != new => {
+        id.add(format!("oldmode{}newmode{}", old, new).as_bytes())
+      }
+      _ => (),
+    }
+
+    let before = blob_data(repo, change.old.as_ref())?;
+    let after = blob_data(repo, change.new.as_ref())?;
+    if patch::is_binary(&before) || patch::is_binary(&after) {
+      for side in [&change.old, &change.new] {
+        id.add(
+          side
+            .as_ref()
+            .map(|(_, hash)| hash.as_str())
+            .unwrap_or(NULL_HASH)
+            .as_bytes(),
+        );
+      }
+    } else {
+      match (&change.old, &change.new) {
+        (None, _) => id.add(format!("---/dev/null+++b/{}", change.path).as_bytes()),
+        (_, None) => id.add(format!("---a/{}+++/dev/null", old_path).as_bytes()),
+        _ => id.add(format!("---a/{}+++b/{}", old_path, change.path).as_bytes()),
+      }
+      let (a, b) = (lines(&before), lines(&after));
+      for hunk in hunks(&myers(&a, &b), patch::CONTEXT) {
+        for edit in &hunk.edits {
+          // the space before a context line goes along with the rest
+          let (sign, line) = match edit.op {
+            Op::Equal => (b' ', a[edit.old]),
+            Op::Delete => (b'-', a[edit.old]),
+            Op::Insert => (b'+', b[edit.new]),
+          };
+          id.add(&remove_space(&[&[sign], line].concat()));
+        }
+      }
+    }
+    id.end_file();
+  }
+  Ok(id.finish())
+}
+
+/// Reads the patch ids of the patches in some text, such as the output of
+/// `git format-patch` or `git log -p`, pairing each with the commit named
+/// before it (or the null hash if there was none).
+///
+/// The text is hashed the way it is written: diff headers are taken as they
+/// come, hunk headers are dropped and, unless `verbatim` is set, whitespace is
+/// removed from every line.
+pub fn parse_patch_ids(text: &str, stable: bool, verbatim: bool) -> Vec<(String, String)> {
+  let mut lines = text.split_inclusive('\n').peekable();
+  let mut commit = NULL_HASH.to_owned();
+  let mut ids = Vec::new();
+  while lines.peek().is_some() {
+    let (id, next) = one_patch_id(&mut lines, stable, verbatim);
+    if let Some(id) = id {
+      ids.push((id, commit));
+    }
+    commit = next.unwrap_or_else(|| NULL_HASH.to_owned());
+  }
+  ids
+}
+
+/// Reads lines up to the end of a patch, returning its patch id (if it had
+/// any diff) and the commit named on the line that ended it, if any.
+fn one_patch_id(
+  lines: &mut Peekable<SplitInclusive<char>>,
+  stable: bool,
+  verbatim: bool,
+) -> (Option<String>, Option<String>) {
+  let mut id = PatchId::new(stable || verbatim);
+  let (mut before, mut after): (i64, i64) = (-1, -1);
+  let mut binary = false;
+  let (mut pre, mut post) = (String::new(), String::new());
+  let mut length = 0;
+  let mut next = None;
+  for line in lines.by_ref() {
+    // a commit line (as written by `log` or `format-patch`) starts the next
+    let named = line
+      .strip_prefix("commit ")
+      .or_else(|| line.strip_prefix("From "));
+    if named.is_none() && line.starts_with("\\ ") && line.len() > 12 {
+      continue;
+    }
+    let rest = named.unwrap_or(line);
+    if rest.len() >= 40 && rest[..40].bytes().all(|b| b.is_ascii_hexdigit()) {
+      next = Some(rest[..40].to_owned());
+      break;
+    }
+    if length == 0 && !line.starts_with("diff ") {
+      continue;
+    }
+
+    if before == -1 {
+      if line.starts_with("GIT binary patch") || line.starts_with("Binary files") {
+        binary = true;
+        before = 0;
+        id.add(pre.as_bytes());
+        id.add(post.as_bytes());
+        id.end_file();
+        continue;
+      } else if let Some(hashes) = line.strip_prefix("index ") {
+        if let Some((old, new)) = hashes.trim_end().split_once("..") {
+          pre = old.to_owned();
+          post = new.split(' ').next().unwrap_or("").to_owned();
+        }
+        continue;
+      } else if line.starts_with("--- ") {
+        (before, after) = (1, 1);
+      } else if !line.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        break;
+      }
+    }
+    if binary {
+      if line.starts_with("diff ") {
+        binary = false;
+        before = -1;
+      }
+      continue;
+    }
+
+    if before == 0 && after == 0 {
+      if line.starts_with("@@ -") {
+        (before, after) = hunk_lengths(line);
+        continue;
+      }
+      if !line.starts_with("diff ") {
+        break;
+      }
+      id.end_file();
+      (before, after) = (-1, -1);
+    }
+    if line.starts_with(['-', ' ']) {
+      before -= 1;
+    }
+    if line.starts_with(['+', ' ']) {
+      after -= 1;
+    }
+    let data = match verbatim {
+      true => line.as_bytes().to_vec(),
+      false => remove_space(line.as_bytes()),
+    };
+    length += data.len();
+    id.add(&data);
+  }
+  if id.stable {
+    // the last file is added even if it was binary and so already is
+    id.flush();
+  }
+  let id = id.finish();
+  (Some(id).filter(|_| length > 0), next)
+}
+
+/// Reads the line counts out of a `@@ -a,b +c,d @@` hunk header.
+fn hunk_lengths(line: &str) -> (i64, i64) {
+  let mut parts = line.split(' ').skip(1);
+  let mut length = |prefix: char| {
+    parts
+      .next()
+      .and_then(|range| range.strip_prefix(prefix))
+      .map(|range| match range.split_once(',') {
+        Some((_, length)) => length.parse().unwrap_or(1),
+        None => 1,
+      })
+      .unwrap_or(1)
+  };
+  let before = length('-');
+  (before, length('+'))
+}
+
+/// Drops the whitespace from a line (as C's `isspace` sees it).
+fn remove_space(line: &[u8]) -> Vec<u8> {
+  line
+    .iter()
+    .filter(|b| !matches!(b, b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c))
+    .copied()
+    .collect()
+}
+
+/// Accumulates a patch id, either as one hash over everything or, when
+/// stable, as the sum of a hash per file.
+struct PatchId {
+  stable: bool,
+  data: Vec<u8>,
+  sum: [u8; 20],
+}
+
+impl PatchId {
+  fn new(stable: bool) -> Self {
+    PatchId {
+      stable,
+      data: Vec::new(),
+      sum: [0; 20],
+    }
+  }
+
+  fn add(&mut self, data: &[u8]) {
+    self.data.extend_from_slice(data);
+  }
+
+  /// Adds the hash of the file so far to the sum, for a stable id.
+  fn end_file(&mut self) {
+    if self.stable {
+      self.flush();
+    }
+  }
+
+  fn flush(&mut self) {
+    let hash = sha_1(&self.data);
+    self.data.clear();
+    let mut carry = 0u16;
+    for (i, byte) in self.sum.iter_mut().enumerate() {
+      let digit = u16::from_str_radix(&hash[2 * i..2 * i + 2], 16).unwrap_or(0);
+      carry += *byte as u16 + digit;
+      *byte = carry as u8;
+      carry >>= 8;
+    }
+  }
+
+  fn finish(mut self) -> String {
+    if !self.stable {
+      self.flush();
+    }
+    self.sum.iter().map(|b| format!("{:02x}", b)).collect()
+  }
+}
@@ -0,0 +1,75 @@
+use bstr::BString;
+use regex::bytes::Regex;
+
+use crate::pathspec;
+use crate::repo::Repo;
+
+use super::{blob_data, detect_renames, diff_trees, lines, myers, Op};
+
+/// Content-based history search ("pickaxe").
+///
+/// `-S<string>` looks for commits where the number of occurrences of a string
+/// changes, which finds the commit that introduced or removed it (but not one
+/// that merely moved it around). `-G<regex>` looks for commits whose diff adds
+/// or removes a line matching a regular expression.
+pub enum Pickaxe {
+  /// `-S`: the occurrence count of the string differs.
+  Occurrences(Vec<u8>),
+
+  /// `-G`: an added or removed line matches the pattern.
+  Grep(Regex),
+}
+
+impl Pickaxe {
+  /// Checks whether the change between two trees matches, looking only at the
+  /// files matched by the pathspecs (all files if there are none).
+  pub fn matches(
+    &self,
+    repo: &Repo,
+    old: Option<&str>,
+    new: Option<&str>,
+    paths: &[BString],
+  ) -> Result<bool, String> {
+    let changes = detect_renames(repo, diff_trees(repo, old, new)?)?;
+    for change in changes {
+      if !pathspec::matches(&change.path, paths) {
+        continue;
+      }
+      let before = blob_data(repo, change.old.as_ref())?;
+      let after = blob_data(repo, change.new.as_ref())?;
+      let found = match self {
+        Pickaxe::Occurrences(needle) => count(&before, needle) != count(&after, needle),
+        Pickaxe::Grep(pattern) => {
+          let (a, b) = (lines(&before), lines(&after));
+          myers(&a, &b).iter().any(|edit| match edit.op {
+            Op::Equal => false,
+            Op::Delete => pattern.is_match(a[edit.old]),
+            Op::Insert => pattern.is_match(b[edit.new]),
+          })
+        }
+      };
+      if found {
+        return Ok(true);
+      }
+    }
+    Ok(false)
+  }
+}
+
+/// Counts the non-overlapping occurrences of a needle in a haystack.
+fn count(haystack: &[u8], needle: &[u8]) -> usize {
+  if needle.is_empty() {
+    return 0;
+  }
+  let mut total = 0;
+  let mut i = 0;
+  while i + needle.len() <= haystack.len() {
+    if &haystack[i..i + needle.len()] == needle {
+      total += 1;
+      i += needle.len();
+    } else {
+      i += 1;
+    }
+  }
+  total
+}
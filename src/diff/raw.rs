@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use bstr::{BString, ByteVec};
+
+use crate::index::{self, Index};
+use crate::object::mode::Mode;
+use crate::repo::Repo;
+use crate::status::quote;
+
+use super::TreeChange;
+
+/// The hash shown for a side of a change that has no object (yet).
+pub const NULL_HASH: &str = "0000000000000000000000000000000000000000";
+
+/// How much of each change the raw diff commands print.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+  /// `:<old mode> <new mode> <old hash> <new hash> <status>\t<path>`
+  Raw,
+
+  /// `<path>`
+  NameOnly,
+
+  /// `<status>\t<path>`
+  NameStatus,
+}
+
+/// Writes changes as the machine-readable records of `diff-tree`,
+/// `diff-index` and `diff-files`.
+///
+/// The status letter is `A`, `D` or `M` for an added, deleted or modified
+/// path, `T` when the type of the file changed (eg. a file became a symlink)
+/// and `U` for an unmerged path. With `nul` set (`-z`), the tab and newline
+/// separators become NUL bytes and paths are written as they are; otherwise
+/// paths are quoted as they are by `status`.
+pub struct Printer {
+  pub format: Format,
+  pub nul: bool,
+}
+
+impl Printer {
+  pub fn new(name_only: bool, name_status: bool, nul: bool) -> Self {
+    let format = match (name_only, name_status) {
+      (true, _) => Format::NameOnly,
+      (_, true) => Format::NameStatus,
+      _ => Format::Raw,
+    };
+    Self { format, nul }
+  }
+
+  /// Formats a change between two files.
+  pub fn change(&self, change: &TreeChange) -> BString {
+    let status = match (&change.old, &change.new) {
+      (None, _) => 'A',
+      (_, None) => 'D',
+      (Some((old, _)), Some((new, _))) if kind(*old) != kind(*new) => 'T',
+      _ => 'M',
+    };
+    self.record(
+      change.old.as_ref(),
+      change.new.as_ref(),
+      status,
+      &change.path,
+    )
+  }
+
+  /// Formats the record for an unmerged path, given the mode of the file in
+  /// the working tree (if any).
+  pub fn unmerged(&self, path: impl AsRef<[u8]>, mode: Option<Mode>) -> BString {
+    let new = mode.map(|mode| (mode, NULL_HASH.to_string()));
+    self.record(None, new.as_ref(), 'U', path.as_ref())
+  }
+
+  fn record(
+    &self,
+    old: Option<&(Mode, String)>,
+    new: Option<&(Mode, String)>,
+    status: char,
+    path: &[u8],
+  ) -> BString {
+    let (tab, end) = if self.nul { ('\0', '\0') } else { ('\t', '\n') };
+    let mut record = match self.format {
+      Format::NameOnly => BString::default(),
+      Format::NameStatus => format!("{}{}", status, tab).into(),
+      Format::Raw => {
+        let (old_mode, old_hash) = side(old);
+        let (new_mode, new_hash) = side(new);
+        format!(
+          ":{} {} {} {} {}{}",
+          old_mode, new_mode, old_hash, new_hash, status, tab
+        )
+        .into()
+      }
+    };
+    match self.nul {
+      true => record.push_str(path),
+      false => record.push_str(quote(path)),
+    }
+    record.push_char(end);
+    record
+  }
+}
+
+/// The mode and hash shown for one side of a change.
+fn side(entry: Option<&(Mode, String)>) -> (String, &str) {
+  match entry {
+    Some((mode, hash)) => (mode.to_string(), hash),
+    None => ("000000".to_string(), NULL_HASH),
+  }
+}
+
+/// Files and symlinks (and trees) are different kinds of entry, while the
+/// executable bit is only a change of mode.
+fn kind(mode: Mode) -> u8 {
+  match mode {
+    Mode::Normal | Mode::Executable => 0,
+    Mode::Symbolic => 1,
+    Mode::Directory => 2,
+    Mode::Gitlink => 3,
+  }
+}
+
+/// Lists the stage 0 files of the index as they are in the working tree.
+///
+/// Files that match their index entry keep its mode and hash. Files that were
+/// changed get their mode from the working tree and the null hash, since
+/// their contents are not hashed, and files that are gone are left out.
+pub fn worktree_files(
+  repo: &Repo,
+  index: &Index,
+) -> Result<BTreeMap<BString, (Mode, String)>, String> {
+  let mut files = BTreeMap::new();
+  for (path, (mode, hash)) in index.files() {
+    let entry = index.get(&path).unwrap();
+    if !index::is_modified(repo, entry)? {
+      files.insert(path, (mode, hash));
+    } else if let Ok(metadata) = fs::symlink_metadata(repo.work_tree_path(&path)) {
+      if !metadata.is_dir() {
+        files.insert(path, (index::file_mode(&metadata), NULL_HASH.to_string()));
+      }
+    }
+  }
+  Ok(files)
+}
@@ -0,0 +1,170 @@
+use std::ops::Range;
+
+use regex::bytes::Regex;
+
+use crate::color::Colors;
+
+use super::{myers, Hunk, Op};
+
+/// How a word diff shows the words removed and added.
+#[derive(Clone, Copy)]
+pub enum Style<'a> {
+  /// In line with the rest, as `[-removed-]` and `{+added+}`.
+  Plain,
+
+  /// In line with the rest, in the colors of `diff.old` and `diff.new`.
+  Color(&'a Colors),
+
+  /// For scripts: each run of removed, added or unchanged text on a line of
+  /// its own, starting with `-`, `+` or a space, and each line of the files
+  /// ending with a line of `~`.
+  Porcelain,
+}
+
+/// A word diff asked for in place of a line diff.
+#[derive(Clone, Copy)]
+pub struct WordDiff<'a> {
+  pub style: Style<'a>,
+
+  /// What counts as a word, if not what the path's diff driver says.
+  pub regex: Option<&'a Regex>,
+}
+
+/// What a stretch of text in a word diff is.
+#[derive(Clone, Copy)]
+enum Kind {
+  Context,
+  Removed,
+  Added,
+}
+
+/// Renders the edits of a hunk of a line diff as a word diff.
+///
+/// Lines that are the same on both sides are shown as they are. Each run of
+/// removed and added lines is broken into words, the matches of `regex`
+/// (or, without one, runs of anything but whitespace), which are diffed
+/// with each other, so that only the words that changed are marked. What
+/// lies between words is taken from the new side, so changes to whitespace
+/// alone don't show. A regex matching any single character (`.`) diffs
+/// characters instead.
+pub fn hunk(a: &[&[u8]], b: &[&[u8]], hunk: &Hunk, regex: Option<&Regex>, style: Style) -> String {
+  let default = Regex::new(r"(?-u)\S+").unwrap();
+  let regex = regex.unwrap_or(&default);
+  let mut out = Vec::new();
+  let (mut removed, mut added) = (Vec::new(), Vec::new());
+  for edit in &hunk.edits {
+    match edit.op {
+      Op::Delete => removed.extend_from_slice(a[edit.old]),
+      Op::Insert => added.extend_from_slice(b[edit.new]),
+      Op::Equal => {
+        changes(&mut out, &removed, &added, regex, style);
+        removed.clear();
+        added.clear();
+        write(&mut out, Kind::Context, a[edit.old], style);
+      }
+    }
+  }
+  changes(&mut out, &removed, &added, regex, style);
+  if !out.ends_with(b"\n") {
+    out.push(b'\n');
+  }
+  String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Writes a run of removed and added lines, marking the words that differ.
+fn changes(out: &mut Vec<u8>, old: &[u8], new: &[u8], regex: &Regex, style: Style) {
+  if new.is_empty() {
+    write(out, Kind::Removed, old, style);
+    return;
+  }
+  let (old_words, new_words) = (words(old, regex), words(new, regex));
+  let edits = myers(
+    &old_words
+      .iter()
+      .map(|w| &old[w.clone()])
+      .collect::<Vec<_>>(),
+    &new_words
+      .iter()
+      .map(|w| &new[w.clone()])
+      .collect::<Vec<_>>(),
+  );
+
+  // the new side is written up to `written`, and read up to `read`; what
+  // lies between is unchanged, and written along with the next change
+  let (mut written, mut read) = (0, 0);
+  let mut i = 0;
+  while i < edits.len() {
+    if edits[i].op == Op::Equal {
+      read = new_words[edits[i].new].end;
+      i += 1;
+      continue;
+    }
+    let (mut removed, mut inserted): (Vec<usize>, Vec<usize>) = (Vec::new(), Vec::new());
+    while i < edits.len() && edits[i].op != Op::Equal {
+      match edits[i].op {
+        Op::Delete => removed.push(edits[i].old),
+        _ => inserted.push(edits[i].new),
+      }
+      i += 1;
+    }
+    let start = inserted
+      .first()
+      .map_or(read, |first| new_words[*first].start);
+    write(out, Kind::Context, &new[written..start], style);
+    if let (Some(first), Some(last)) = (removed.first(), removed.last()) {
+      let range = old_words[*first].start..old_words[*last].end;
+      write(out, Kind::Removed, &old[range], style);
+    }
+    written = start;
+    if let (Some(first), Some(last)) = (inserted.first(), inserted.last()) {
+      let range = new_words[*first].start..new_words[*last].end;
+      write(out, Kind::Added, &new[range.clone()], style);
+      written = range.end;
+    }
+    read = written;
+  }
+  write(out, Kind::Context, &new[written..], style);
+}
+
+/// The words of some lines, as the ranges the regex matches within each
+/// line (leaving out the line ends).
+fn words(text: &[u8], regex: &Regex) -> Vec<Range<usize>> {
+  let mut words = Vec::new();
+  let mut offset = 0;
+  for line in text.split_inclusive(|b| *b == b'\n') {
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    words.extend(
+      regex
+        .find_iter(content)
+        .filter(|m| !m.range().is_empty())
+        .map(|m| offset + m.start()..offset + m.end()),
+    );
+    offset += line.len();
+  }
+  words
+}
+
+/// Writes some text in a style, marking each of its lines separately.
+fn write(out: &mut Vec<u8>, kind: Kind, text: &[u8], style: Style) {
+  let (start, end, newline): (&str, &str, &str) = match (style, kind) {
+    (Style::Plain, Kind::Context) => ("", "", "\n"),
+    (Style::Plain, Kind::Removed) => ("[-", "-]", "\n"),
+    (Style::Plain, Kind::Added) => ("{+", "+}", "\n"),
+    (Style::Color(_), Kind::Context) => ("", "", "\n"),
+    (Style::Color(colors), Kind::Removed) => (colors.color("diff.old"), colors.reset(), "\n"),
+    (Style::Color(colors), Kind::Added) => (colors.color("diff.new"), colors.reset(), "\n"),
+    (Style::Porcelain, Kind::Context) => (" ", "\n", "~\n"),
+    (Style::Porcelain, Kind::Removed) => ("-", "\n", "~\n"),
+    (Style::Porcelain, Kind::Added) => ("+", "\n", "~\n"),
+  };
+  for (i, part) in text.split(|b| *b == b'\n').enumerate() {
+    if i > 0 {
+      out.extend_from_slice(newline.as_bytes());
+    }
+    if !part.is_empty() {
+      out.extend_from_slice(start.as_bytes());
+      out.extend_from_slice(part);
+      out.extend_from_slice(end.as_bytes());
+    }
+  }
+}
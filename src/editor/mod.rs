@@ -0,0 +1,57 @@
+use std::env;
+use std::process::Command;
+
+use crate::repo::Repo;
+use crate::trace;
+
+/// The editor to edit messages with: `GIT_EDITOR`, `core.editor`, `VISUAL`
+/// or `EDITOR`, and otherwise `vi`.
+///
+/// `VISUAL` is passed over on a dumb terminal, and then there is no falling
+/// back to `vi` either, as it could not be used.
+pub fn editor(repo: &Repo) -> Result<String, String> {
+  let configured = repo
+    .config
+    .as_ref()
+    .and_then(|config| config.get_from(Some("core"), "editor"))
+    .map(|editor| editor.to_owned());
+  let dumb = env::var("TERM").map_or(true, |term| term == "dumb");
+  let editor = env::var("GIT_EDITOR")
+    .ok()
+    .or(configured)
+    .or_else(|| env::var("VISUAL").ok().filter(|_| !dumb))
+    .or_else(|| env::var("EDITOR").ok());
+  match editor {
+    Some(editor) => Ok(editor),
+    None if dumb => Err("Terminal is dumb, but EDITOR unset".to_string()),
+    None => Ok("vi".to_string()),
+  }
+}
+
+/// Opens a file in the user's editor and waits for it to be closed.
+pub fn launch(repo: &Repo, path: &str) -> Result<(), String> {
+  let editor = editor(repo)?;
+  if editor == ":" {
+    return Ok(());
+  }
+  // like git, let the shell split the editor's arguments
+  let mut command = Command::new("sh");
+  command
+    .arg("-c")
+    .arg(format!("{} \"$@\"", editor))
+    .arg(&editor)
+    .arg(path);
+  let traced = trace::command("editor", &command);
+  let status = command
+    .spawn()
+    .and_then(|mut child| {
+      let status = child.wait()?;
+      traced.exit(child.id(), &status);
+      Ok(status)
+    })
+    .map_err(|e| format!("unable to start editor '{}' ({})", editor, e))?;
+  match status.success() {
+    true => Ok(()),
+    false => Err(format!("There was a problem with the editor '{}'.", editor)),
+  }
+}
@@ -0,0 +1,137 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The value of an environment variable, unless it is unset or empty, which
+/// git takes to mean the same thing for most of its variables.
+pub fn var(name: &str) -> Option<OsString> {
+  env::var_os(name).filter(|value| !value.is_empty())
+}
+
+/// The git directory named by `GIT_DIR`, which stops git from looking for
+/// one.
+pub fn git_dir() -> Option<PathBuf> {
+  var("GIT_DIR").map(PathBuf::from)
+}
+
+/// The working tree named by `GIT_WORK_TREE`, which wins over
+/// `core.worktree`.
+pub fn work_tree() -> Option<PathBuf> {
+  var("GIT_WORK_TREE").map(PathBuf::from)
+}
+
+/// The directories named by `GIT_CEILING_DIRECTORIES`, which the search for
+/// a git directory does not go into. Relative paths are ignored.
+pub fn ceiling_directories() -> Vec<PathBuf> {
+  var("GIT_CEILING_DIRECTORIES")
+    .map(|dirs| env::split_paths(&dirs).collect::<Vec<_>>())
+    .unwrap_or_default()
+    .into_iter()
+    .filter(|dir| dir.is_absolute())
+    .filter_map(|dir| dir.canonicalize().ok())
+    .collect()
+}
+
+/// The index file: `GIT_INDEX_FILE`, or `index` in the git directory.
+pub fn index_file(git_dir: &Path) -> PathBuf {
+  var("GIT_INDEX_FILE")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| git_dir.join("index"))
+}
+
+/// The object database: `GIT_OBJECT_DIRECTORY`, or `objects` in the git
+/// directory.
+pub fn object_dir(git_dir: &Path) -> PathBuf {
+  var("GIT_OBJECT_DIRECTORY")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| git_dir.join("objects"))
+}
+
+/// The object databases named by `GIT_ALTERNATE_OBJECT_DIRECTORIES`, a
+/// colon separated list, which objects are also read from.
+pub fn alternate_object_dirs() -> Vec<PathBuf> {
+  var("GIT_ALTERNATE_OBJECT_DIRECTORIES")
+    .map(|dirs| env::split_paths(&dirs).collect())
+    .unwrap_or_default()
+}
+
+/// The graft file: `GIT_GRAFT_FILE`, or `info/grafts` in the git directory.
+pub fn graft_file(git_dir: &Path) -> PathBuf {
+  var("GIT_GRAFT_FILE")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| git_dir.join("info").join("grafts"))
+}
+
+/// Where replace refs are kept: `GIT_REPLACE_REF_BASE`, or `refs/replace/`.
+pub fn replace_ref_base() -> PathBuf {
+  var("GIT_REPLACE_REF_BASE")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| PathBuf::from("refs/replace/"))
+}
+
+/// The user's own config file: `GIT_CONFIG_GLOBAL`, or `~/.gitconfig`.
+/// Setting `GIT_CONFIG_GLOBAL` to `/dev/null` leaves it out.
+pub fn global_config() -> Option<PathBuf> {
+  match var("GIT_CONFIG_GLOBAL") {
+    Some(path) if path == "/dev/null" => None,
+    Some(path) => Some(PathBuf::from(path)),
+    None => var("HOME").map(|home| Path::new(&home).join(".gitconfig")),
+  }
+}
+
+/// The settings passed down in the environment, which override the config
+/// files, in the order they apply.
+///
+/// `GIT_CONFIG_COUNT` gives a number of settings, each a
+/// `GIT_CONFIG_KEY_<n>` and `GIT_CONFIG_VALUE_<n>` counting from 0. They are
+/// followed by the `'key'='value'` pairs in `GIT_CONFIG_PARAMETERS`, which
+/// are quoted the way a shell would and come from `git -c`.
+pub fn config_parameters() -> Result<Vec<(String, String)>, String> {
+  let mut parameters = Vec::new();
+  if let Some(count) = var("GIT_CONFIG_COUNT") {
+    let count: usize = count
+      .to_str()
+      .and_then(|count| count.parse().ok())
+      .ok_or("bogus count in GIT_CONFIG_COUNT")?;
+    for i in 0..count {
+      let key = var(&format!("GIT_CONFIG_KEY_{}", i))
+        .ok_or(format!("missing config key GIT_CONFIG_KEY_{}", i))?;
+      let value = env::var_os(format!("GIT_CONFIG_VALUE_{}", i))
+        .ok_or(format!("missing config value GIT_CONFIG_VALUE_{}", i))?;
+      parameters.push((
+        key.to_string_lossy().into_owned(),
+        value.to_string_lossy().into_owned(),
+      ));
+    }
+  }
+
+  let text = env::var("GIT_CONFIG_PARAMETERS").unwrap_or_default();
+  let invalid = || "unable to parse command-line config".to_string();
+  let mut words: Vec<String> = vec![String::new()];
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '\'' => loop {
+        match chars.next().ok_or_else(invalid)? {
+          '\'' => break,
+          c => words.last_mut().unwrap().push(c),
+        }
+      },
+      '\\' => words
+        .last_mut()
+        .unwrap()
+        .push(chars.next().ok_or_else(invalid)?),
+      '=' => words.last_mut().unwrap().push('\0'),
+      ' ' if !words.last().unwrap().is_empty() => words.push(String::new()),
+      ' ' => (),
+      _ => return Err(invalid()),
+    }
+  }
+  for word in words.into_iter().filter(|word| !word.is_empty()) {
+    parameters.push(match word.split_once('\0') {
+      Some((key, value)) => (key.to_owned(), value.to_owned()),
+      None => (word, "true".to_owned()),
+    });
+  }
+  Ok(parameters)
+}
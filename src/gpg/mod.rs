@@ -0,0 +1,317 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{self, Command, Stdio};
+
+use serde::Serialize;
+
+use crate::identity::{Role, Signature};
+use crate::object;
+use crate::repo::Repo;
+use crate::trace;
+
+/// How a signature checked out. Each comes with the letter git's `%G?`
+/// shows for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+  /// A good signature from a key that is trusted (`G`).
+  Good,
+
+  /// A good signature from a key that nothing says can be trusted (`U`).
+  Untrusted,
+
+  /// The signature does not match what was signed (`B`).
+  Bad,
+
+  /// A good signature that has expired (`X`).
+  ExpiredSignature,
+
+  /// A good signature made by a key that has since expired (`Y`).
+  ExpiredKey,
+
+  /// A good signature made by a key that has been revoked (`R`).
+  RevokedKey,
+
+  /// The signature could not be checked, usually because the key is not in
+  /// the keyring (`E`).
+  Unverified,
+
+  /// There is no signature at all (`N`).
+  Missing,
+}
+
+impl Status {
+  /// The letter `%G?` shows for the status.
+  pub fn letter(self) -> char {
+    match self {
+      Status::Good => 'G',
+      Status::Untrusted => 'U',
+      Status::Bad => 'B',
+      Status::ExpiredSignature => 'X',
+      Status::ExpiredKey => 'Y',
+      Status::RevokedKey => 'R',
+      Status::Unverified => 'E',
+      Status::Missing => 'N',
+    }
+  }
+}
+
+/// What checking the signature of a commit or tag found.
+#[derive(Clone, Debug, Serialize)]
+pub struct Verification {
+  pub status: Status,
+
+  /// Who the key belongs to, eg. `A U Thor <author@example.com>`.
+  pub signer: Option<String>,
+
+  /// The long id of the key that made the signature.
+  pub key: Option<String>,
+
+  /// The fingerprint of the key that made the signature, and of its primary
+  /// key, which differ when a subkey made it.
+  pub fingerprint: Option<String>,
+  pub primary_fingerprint: Option<String>,
+
+  /// How far the key is trusted: `undefined`, `never`, `marginal`, `fully`
+  /// or `ultimate`.
+  pub trust: Option<String>,
+
+  /// What the signing program said about it, for a person to read.
+  #[serde(skip)]
+  pub output: String,
+}
+
+impl Verification {
+  fn missing() -> Self {
+    Self {
+      status: Status::Missing,
+      signer: None,
+      key: None,
+      fingerprint: None,
+      primary_fingerprint: None,
+      trust: None,
+      output: String::new(),
+    }
+  }
+}
+
+/// The lines a signature starts with, and the config section naming the
+/// program that checks it.
+const FORMATS: [(&str, &str); 4] = [
+  ("-----BEGIN PGP SIGNATURE-----", "openpgp"),
+  ("-----BEGIN PGP MESSAGE-----", "openpgp"),
+  ("-----BEGIN SIGNED MESSAGE-----", "x509"),
+  ("-----BEGIN SSH SIGNATURE-----", "ssh"),
+];
+
+/// Splits the data of a commit into what was signed and the signature, the
+/// value of its `gpgsig` header. Returns `None` if it is not signed.
+pub fn split_commit(data: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+  let mut payload = Vec::new();
+  let mut signature = Vec::new();
+  let mut in_signature = false;
+  let mut lines = data.split_inclusive(|c| *c == b'\n');
+  for line in lines.by_ref() {
+    if line == b"\n" {
+      payload.extend_from_slice(line);
+      break;
+    }
+    if let Some(value) = line.strip_prefix(b"gpgsig ") {
+      in_signature = true;
+      signature.extend_from_slice(value);
+    } else if let Some(value) = line.strip_prefix(b" ").filter(|_| in_signature) {
+      signature.extend_from_slice(value);
+    } else {
+      in_signature = false;
+      payload.extend_from_slice(line);
+    }
+  }
+  payload.extend(lines.flatten());
+  match signature.is_empty() {
+    true => None,
+    false => Some((payload, signature)),
+  }
+}
+
+/// Splits the data of a tag into what was signed and the signature, which
+/// is at the end of its message. Returns `None` if it is not signed.
+pub fn split_tag(data: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+  let mut start = 0;
+  for line in data.split_inclusive(|c| *c == b'\n') {
+    if FORMATS
+      .iter()
+      .any(|(begin, _)| line.starts_with(begin.as_bytes()))
+    {
+      return Some((data[..start].to_vec(), data[start..].to_vec()));
+    }
+    start += line.len();
+  }
+  None
+}
+
+/// Checks the signature of a commit or tag.
+pub fn verify_object(repo: &Repo, hash: &str) -> Result<Verification, String> {
+  let object = object::read(repo, hash, None)?;
+  let data = object.serialize();
+  let split = match object.format().as_str() {
+    "commit" => split_commit(data),
+    "tag" => split_tag(data),
+    _ => None,
+  };
+  match split {
+    Some((payload, signature)) => verify(repo, &payload, &signature),
+    None => Ok(Verification::missing()),
+  }
+}
+
+/// Checks a signature of some data with the program for its format: `gpg`
+/// for OpenPGP, `gpgsm` for X.509, unless `gpg.program` (or
+/// `gpg.<format>.program`) says otherwise. SSH signatures can't be checked.
+pub fn verify(repo: &Repo, payload: &[u8], signature: &[u8]) -> Result<Verification, String> {
+  let format = FORMATS
+    .iter()
+    .find(|(begin, _)| signature.starts_with(begin.as_bytes()))
+    .map_or("openpgp", |(_, format)| format);
+  let mut verification = Verification {
+    status: Status::Unverified,
+    ..Verification::missing()
+  };
+  if format == "ssh" {
+    verification.output = "error: ssh signatures can't be checked\n".to_string();
+    return Ok(verification);
+  }
+  let program = config(repo, &format!("gpg \"{}\"", format), "program")
+    .or_else(|| config(repo, "gpg", "program").filter(|_| format == "openpgp"))
+    .unwrap_or_else(|| match format {
+      "x509" => "gpgsm".to_string(),
+      _ => "gpg".to_string(),
+    });
+
+  // the signature goes in a file, the payload down stdin
+  let path = env::temp_dir().join(format!(".git_vtag_tmp{}", process::id()));
+  fs::write(&path, signature).map_err(|e| format!("could not create temporary file: {}", e))?;
+  let mut command = Command::new(&program);
+  command
+    .args(["--keyid-format=long", "--status-fd=1", "--verify"])
+    .arg(&path)
+    .arg("-")
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+  let traced = trace::command("gpg", &command);
+  let child = command.spawn();
+  let output = child.and_then(|mut child| {
+    // gpg does not answer until it has read everything, so the pipes won't
+    // fill up; if it gives up early, what it says is what counts
+    let _ = child.stdin.take().unwrap().write_all(payload);
+    let pid = child.id();
+    let output = child.wait_with_output()?;
+    traced.exit(pid, &output.status);
+    Ok(output)
+  });
+  let _ = fs::remove_file(&path);
+  let output = output.map_err(|e| format!("could not run {}: {}", program, e))?;
+  verification.output = String::from_utf8_lossy(&output.stderr).into_owned();
+
+  let status = String::from_utf8_lossy(&output.stdout);
+  for line in status
+    .lines()
+    .filter_map(|line| line.strip_prefix("[GNUPG:] "))
+  {
+    let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let mut fields = rest.split(' ');
+    let result = match keyword {
+      "GOODSIG" => Status::Good,
+      "BADSIG" => Status::Bad,
+      "EXPSIG" => Status::ExpiredSignature,
+      "EXPKEYSIG" => Status::ExpiredKey,
+      "REVKEYSIG" => Status::RevokedKey,
+      "ERRSIG" => Status::Unverified,
+      "VALIDSIG" => {
+        verification.fingerprint = fields.next().map(str::to_string);
+        verification.primary_fingerprint = fields.nth(8).map(str::to_string);
+        continue;
+      }
+      _ => {
+        if let Some(trust) = keyword.strip_prefix("TRUST_") {
+          verification.trust = Some(trust.to_lowercase());
+        }
+        continue;
+      }
+    };
+    verification.status = result;
+    verification.key = fields.next().map(str::to_string);
+    if result != Status::Unverified {
+      verification.signer = Some(fields.collect::<Vec<_>>().join(" "));
+    }
+  }
+  // a good signature counts for less if nothing vouches for the key
+  if verification.status == Status::Good
+    && matches!(
+      verification.trust.as_deref(),
+      None | Some("undefined" | "never")
+    )
+  {
+    verification.status = Status::Untrusted;
+  }
+  Ok(verification)
+}
+
+/// The key to sign with: `user.signingKey`, or else the committer, whose
+/// key `gpg` finds by their name and email.
+pub fn signing_key(repo: &Repo) -> Result<String, String> {
+  match config(repo, "user", "signingKey") {
+    Some(key) => Ok(key),
+    None => Ok(Signature::current(repo, Role::Committer)?.person()),
+  }
+}
+
+/// Makes a detached, armored signature of some data with the signing key,
+/// with the program for `gpg.format` (`openpgp` unless it says otherwise),
+/// as [`verify`] picks it.
+pub fn sign(repo: &Repo, payload: &[u8]) -> Result<String, String> {
+  let format = config(repo, "gpg", "format").unwrap_or_else(|| "openpgp".to_string());
+  let program = match format.as_str() {
+    "openpgp" | "x509" => config(repo, &format!("gpg \"{}\"", format), "program")
+      .or_else(|| config(repo, "gpg", "program").filter(|_| format == "openpgp"))
+      .unwrap_or_else(|| match format.as_str() {
+        "x509" => "gpgsm".to_string(),
+        _ => "gpg".to_string(),
+      }),
+    _ => return Err(format!("unsupported value for gpg.format: {}", format)),
+  };
+  let key = signing_key(repo)?;
+  let mut command = Command::new(&program);
+  command
+    .args(["--status-fd=2", "-bsau"])
+    .arg(&key)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+  let traced = trace::command("gpg", &command);
+  let output = command
+    .spawn()
+    .and_then(|mut child| {
+      let _ = child.stdin.take().unwrap().write_all(payload);
+      let pid = child.id();
+      let output = child.wait_with_output()?;
+      traced.exit(pid, &output.status);
+      Ok(output)
+    })
+    .map_err(|e| format!("could not run {}: {}", program, e))?;
+  // gpg may exit cleanly without having signed anything
+  let status = String::from_utf8_lossy(&output.stderr);
+  if !output.status.success() || !status.contains("[GNUPG:] SIG_CREATED ") {
+    return Err("gpg failed to sign the data".to_string());
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn config(repo: &Repo, section: &str, key: &str) -> Option<String> {
+  let section = repo.config.as_ref()?.section(Some(section))?;
+  section
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_string())
+}
@@ -0,0 +1,242 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::mail;
+
+pub const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+pub const MONTHS: [&str; 12] = [
+  "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// The current time in seconds since the epoch.
+pub fn now() -> i64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|now| now.as_secs() as i64)
+    .unwrap_or(0)
+}
+
+/// Formats a timestamp and timezone the way git shows dates by default, eg.
+/// `Tue Nov 14 15:13:20 2023 -0700`.
+pub fn format_default(seconds: i64, timezone: &str) -> String {
+  let local = seconds + tz_offset(timezone) * 60;
+  let days = local.div_euclid(86400);
+  let time = local.rem_euclid(86400);
+  let (year, month, day) = civil_from_days(days);
+  format!(
+    "{} {} {} {:02}:{:02}:{:02} {} {}",
+    DAYS[(days + 4).rem_euclid(7) as usize],
+    MONTHS[month as usize - 1],
+    day,
+    time / 3600,
+    time / 60 % 60,
+    time % 60,
+    year,
+    timezone
+  )
+}
+
+/// Formats a timestamp and timezone in one of the styles git's `--date`
+/// takes: `default`, `iso` (`2023-11-14 15:13:20 -0700`), `iso-strict`
+/// (`2023-11-14T15:13:20-07:00`), `rfc`, `short` (`2023-11-14`), `raw`
+/// (`1700000000 -0700`) or `unix`. Returns `None` for any other style.
+pub fn format(seconds: i64, timezone: &str, style: &str) -> Option<String> {
+  let local = seconds + tz_offset(timezone) * 60;
+  let (year, month, day) = civil_from_days(local.div_euclid(86400));
+  let time = local.rem_euclid(86400);
+  let (hour, minute, second) = (time / 3600, time / 60 % 60, time % 60);
+  Some(match style {
+    "default" => format_default(seconds, timezone),
+    "iso" | "iso8601" => format!(
+      "{}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+      year, month, day, hour, minute, second, timezone
+    ),
+    "iso-strict" | "iso8601-strict" => {
+      let zone = match is_timezone(timezone) {
+        true => format!("{}:{}", &timezone[..3], &timezone[3..]),
+        false => timezone.to_string(),
+      };
+      format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+        year, month, day, hour, minute, second, zone
+      )
+    }
+    "rfc" | "rfc2822" => mail::format_date(seconds, timezone),
+    "short" => format!("{}-{:02}-{:02}", year, month, day),
+    "raw" => format!("{} {}", seconds, timezone),
+    "unix" => seconds.to_string(),
+    _ => return None,
+  })
+}
+
+/// Parses an exact date into a timestamp and timezone.
+///
+/// Understands git's own format (`<seconds> <timezone>`, optionally with a
+/// leading `@`), ISO 8601 (`2005-04-07T22:13:13 +0200`, where the `T` may be
+/// a space and the time and timezone may be left out) and RFC 2822
+/// (`Thu, 07 Apr 2005 22:13:13 +0200`). Dates without a timezone are taken to
+/// be in UTC.
+pub fn parse(text: &str) -> Option<(i64, String)> {
+  let text = text.trim();
+  parse_raw(text)
+    .or_else(|| parse_iso(text))
+    .or_else(|| mail::parse_date(text))
+}
+
+/// Parses a date the way git does for `--since` and `commit --date`: either
+/// an exact date that [`parse`] understands, or one relative to `now`, such as
+/// `now`, `yesterday`, `2 weeks ago` or `3.days.1.hour.ago`.
+///
+/// Relative dates are in UTC.
+pub fn approxidate(text: &str, now: i64) -> Option<(i64, String)> {
+  if let Some(date) = parse(text) {
+    return Some(date);
+  }
+  let lowercase = text.trim().to_ascii_lowercase();
+  let words: Vec<&str> = lowercase
+    .split(|c: char| c.is_whitespace() || c == '.' || c == ',')
+    .filter(|word| !word.is_empty())
+    .collect();
+  if words.is_empty() {
+    return None;
+  }
+
+  let mut time = now;
+  let mut words = words.iter();
+  while let Some(word) = words.next() {
+    match *word {
+      "now" | "today" | "ago" => (),
+      "yesterday" => time -= 86400,
+      number => {
+        let count: i64 = number.parse().ok()?;
+        let unit = words.next()?;
+        time = go_back(time, count, unit.strip_suffix('s').unwrap_or(unit))?;
+      }
+    }
+  }
+  Some((time, "+0000".to_string()))
+}
+
+/// Reads the date given to an option such as `--since` into a timestamp.
+pub fn parse_limit(text: &str) -> Result<i64, String> {
+  approxidate(text, now())
+    .map(|(time, _)| time)
+    .ok_or_else(|| format!("invalid date format: {}", text))
+}
+
+/// Moves a time back by a number of units (`second`, `day`, `month`...).
+fn go_back(time: i64, count: i64, unit: &str) -> Option<i64> {
+  let seconds = match unit {
+    "second" | "sec" => 1,
+    "minute" | "min" => 60,
+    "hour" => 3600,
+    "day" => 86400,
+    "week" => 7 * 86400,
+    "fortnight" => 14 * 86400,
+    "month" | "year" => {
+      // months and years go by the calendar, so that a month before the
+      // 15th of March is the 15th of February
+      let months = if unit == "year" { count * 12 } else { count };
+      let (year, month, day) = civil_from_days(time.div_euclid(86400));
+      let total = year * 12 + month - 1 - months;
+      let days = days_from_civil(total.div_euclid(12), total.rem_euclid(12) + 1, day);
+      return Some(days * 86400 + time.rem_euclid(86400));
+    }
+    _ => return None,
+  };
+  Some(time - count * seconds)
+}
+
+/// Parses `<seconds> <timezone>`, optionally with a leading `@` and without
+/// the timezone.
+fn parse_raw(text: &str) -> Option<(i64, String)> {
+  let mut parts = text.trim_start_matches('@').split(' ');
+  let seconds: i64 = parts.next()?.parse().ok()?;
+  match (parts.next(), parts.next()) {
+    (None, _) => Some((seconds, "+0000".to_string())),
+    (Some(timezone), None) if is_timezone(timezone) => Some((seconds, timezone.to_string())),
+    _ => None,
+  }
+}
+
+/// Parses an ISO 8601 date such as `2005-04-07T22:13:13+02:00`.
+fn parse_iso(text: &str) -> Option<(i64, String)> {
+  let (date, rest) = match text.find(['T', ' ']) {
+    Some(i) => (&text[..i], text[i + 1..].trim_start()),
+    None => (text, ""),
+  };
+  let fields: Vec<i64> = date
+    .split('-')
+    .map(|field| field.parse().ok())
+    .collect::<Option<_>>()?;
+  let (year, month, day) = match fields.as_slice() {
+    [year, month, day] if (1..=12).contains(month) && (1..=31).contains(day) => {
+      (*year, *month, *day)
+    }
+    _ => return None,
+  };
+
+  // the time runs up to the timezone, if there is one
+  let zone_start = rest.find(['+', '-', 'Z', ' ']).unwrap_or(rest.len());
+  let (time, zone) = (&rest[..zone_start], rest[zone_start..].trim());
+  let hms: Vec<i64> = match time {
+    "" => vec![0, 0, 0],
+    _ => time
+      .split(':')
+      .map(|field| field.parse().ok())
+      .collect::<Option<_>>()?,
+  };
+  let (hours, minutes, seconds) = match hms.as_slice() {
+    [h, m] => (*h, *m, 0),
+    [h, m, s] => (*h, *m, *s),
+    _ => return None,
+  };
+  let timezone = match zone.replace(':', "").as_str() {
+    "" | "Z" => "+0000".to_string(),
+    zone if is_timezone(zone) => zone.to_string(),
+    _ => return None,
+  };
+
+  let local = days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds;
+  Some((local - tz_offset(&timezone) * 60, timezone))
+}
+
+/// Whether text is a `+hhmm` or `-hhmm` timezone.
+fn is_timezone(text: &str) -> bool {
+  text.len() == 5 && text.starts_with(['+', '-']) && text[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// The offset of a `+hhmm` timezone from UTC in minutes.
+pub fn tz_offset(timezone: &str) -> i64 {
+  let value: i64 = timezone.get(1..).and_then(|v| v.parse().ok()).unwrap_or(0);
+  let minutes = value / 100 * 60 + value % 100;
+  match timezone.starts_with('-') {
+    true => -minutes,
+    false => minutes,
+  }
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) date, using Howard
+/// Hinnant's algorithm for the proleptic Gregorian calendar.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + i64::from(month <= 2);
+  (year, month, day)
+}
+
+/// The inverse of [`civil_from_days`].
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let yoe = year.rem_euclid(400);
+  let mp = (month + 9) % 12;
+  let doy = (153 * mp + 2) / 5 + day - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  era * 146097 + doe - 719468
+}
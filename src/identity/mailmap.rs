@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::object::{self, blob::Blob, serializable::Unbox, tree};
+use crate::repo::Repo;
+use crate::rev;
+
+/// What a name and email are mapped to. Either may be left as it is.
+#[derive(Clone, Debug, Default)]
+struct Mapping {
+  name: Option<String>,
+  email: Option<String>,
+}
+
+/// The mappings for one email address: the one for any name, and those for
+/// particular names, which win over it. Names are kept lowercase.
+#[derive(Clone, Debug, Default)]
+struct Entry {
+  any: Mapping,
+  names: HashMap<String, Mapping>,
+}
+
+/// The canonical names and emails of the people in a history, from
+/// `.mailmap` files.
+///
+/// Each line maps the identities with an email address, or with a name and
+/// email address, to a proper name, a proper email or both:
+/// ```text
+/// Proper Name <commit@email.xx>
+/// <proper@email.xx> <commit@email.xx>
+/// Proper Name <proper@email.xx> <commit@email.xx>
+/// Proper Name <proper@email.xx> Commit Name <commit@email.xx>
+/// ```
+/// Names and emails are matched regardless of case, and lines starting with
+/// `#` are comments.
+#[derive(Clone, Debug, Default)]
+pub struct Mailmap {
+  entries: HashMap<String, Entry>,
+}
+
+impl Mailmap {
+  /// Reads the mailmap of a repository: `.mailmap` at the top of the working
+  /// tree, then the file named by `mailmap.file` and the blob named by
+  /// `mailmap.blob` (`HEAD:.mailmap` in a bare repository), with later
+  /// mappings winning over earlier ones.
+  pub fn read(repo: &Repo) -> Self {
+    let mut mailmap = Self::default();
+    if !repo.bare {
+      mailmap.parse(&fs::read_to_string(repo.work_tree.join(".mailmap")).unwrap_or_default());
+    }
+    if let Some(file) = config(repo, "file") {
+      mailmap.parse(&fs::read_to_string(file).unwrap_or_default());
+    }
+    let blob = config(repo, "blob").or_else(|| repo.bare.then(|| "HEAD:.mailmap".to_string()));
+    if let Some(text) = blob.and_then(|blob| read_blob(repo, &blob)) {
+      mailmap.parse(&text);
+    }
+    mailmap
+  }
+
+  /// Adds the mappings in the text of a mailmap file.
+  pub fn parse(&mut self, text: &str) {
+    let lines = text.lines().filter(|line| !line.starts_with('#'));
+    for (name, email, rest) in lines.filter_map(split_contact) {
+      match split_contact(rest) {
+        Some((old_name, old_email, _)) => self.add(name, Some(email), old_name, old_email),
+        None => self.add(name, None, None, email),
+      }
+    }
+  }
+
+  fn add(
+    &mut self,
+    name: Option<&str>,
+    email: Option<&str>,
+    old_name: Option<&str>,
+    old_email: &str,
+  ) {
+    let entry = self.entries.entry(old_email.to_lowercase()).or_default();
+    let mapping = match old_name {
+      Some(old_name) => entry.names.entry(old_name.to_lowercase()).or_default(),
+      None => &mut entry.any,
+    };
+    if let Some(name) = name {
+      mapping.name = Some(name.to_string());
+    }
+    if let Some(email) = email {
+      mapping.email = Some(email.to_string());
+    }
+  }
+
+  /// The canonical name and email for a name and email.
+  pub fn map(&self, name: &str, email: &str) -> (String, String) {
+    let mapping = self
+      .entries
+      .get(&email.to_lowercase())
+      .map(|entry| entry.names.get(&name.to_lowercase()).unwrap_or(&entry.any));
+    match mapping {
+      Some(mapping) => (
+        mapping.name.clone().unwrap_or_else(|| name.to_string()),
+        mapping.email.clone().unwrap_or_else(|| email.to_string()),
+      ),
+      None => (name.to_string(), email.to_string()),
+    }
+  }
+}
+
+/// Splits `Name <email>` off the start of some text, returning the name (if
+/// there is one), the email and the text after it.
+pub fn split_contact(text: &str) -> Option<(Option<&str>, &str, &str)> {
+  let start = text.find('<')?;
+  let end = start + text[start..].find('>')?;
+  let name = text[..start].trim();
+  let name = (!name.is_empty()).then_some(name);
+  Some((name, &text[start + 1..end], &text[end + 1..]))
+}
+
+/// Reads a blob named like `HEAD:.mailmap`.
+fn read_blob(repo: &Repo, spec: &str) -> Option<String> {
+  let (revision, path) = spec.split_once(':')?;
+  let tree = object::peel(repo, &rev::parse(repo, revision).ok()?, Some("tree")).ok()?;
+  let (_, hash) = tree::lookup(repo, &tree, path).ok()??;
+  let object = object::read(repo, &hash, Some("blob")).ok()?;
+  let blob = object.unbox::<Blob>().ok()?;
+  Some(String::from_utf8_lossy(blob.data()).into_owned())
+}
+
+fn config(repo: &Repo, key: &str) -> Option<String> {
+  let section = repo.config.as_ref()?.section(Some("mailmap"))?;
+  section
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_string())
+}
@@ -0,0 +1,131 @@
+pub mod date;
+pub mod mailmap;
+
+use std::fmt::Display;
+
+use ini::Ini;
+use serde::Serialize;
+
+use crate::env;
+use crate::repo::Repo;
+
+/// Whose identity is being looked up, which picks the environment variables
+/// that are read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+  /// The person who wrote a change (`GIT_AUTHOR_*`).
+  Author,
+
+  /// The person who recorded it (`GIT_COMMITTER_*`).
+  Committer,
+}
+
+impl Role {
+  fn variable(self, field: &str) -> String {
+    match self {
+      Role::Author => format!("GIT_AUTHOR_{}", field),
+      Role::Committer => format!("GIT_COMMITTER_{}", field),
+    }
+  }
+}
+
+/// The author or committer line of a commit (or the tagger of a tag):
+/// `Name <email> seconds timezone`.
+///
+/// ## Example
+/// ```text
+/// A U Thor <author@example.com> 1112911993 -0700
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Signature {
+  pub name: String,
+  pub email: String,
+
+  /// The time in seconds since the epoch.
+  pub time: i64,
+
+  /// The timezone the time was recorded in, as `+hhmm` or `-hhmm`.
+  pub timezone: String,
+}
+
+impl Signature {
+  /// Parses a signature line. A missing or unreadable date is taken to be
+  /// the epoch in UTC, as git does.
+  pub fn parse(line: &str) -> Option<Self> {
+    let start = line.find('<')?;
+    let end = start + line[start..].find('>')?;
+    let mut date = line[end + 1..].split_whitespace();
+    let time = date.next().and_then(|time| time.parse().ok()).unwrap_or(0);
+    let timezone = date.next().unwrap_or("+0000");
+    Some(Self {
+      name: line[..start].trim().to_string(),
+      email: line[start + 1..end].to_string(),
+      time,
+      timezone: timezone.to_string(),
+    })
+  }
+
+  /// Works out who the current user is, for stamping new commits and tags.
+  ///
+  /// The name, email and date come from the `GIT_AUTHOR_*` or
+  /// `GIT_COMMITTER_*` environment variables, falling back to `user.name` and
+  /// `user.email` from the repository's config or `~/.gitconfig` (or the
+  /// file named by `GIT_CONFIG_GLOBAL`), and the current time. Dates may be
+  /// in any format [`date::parse`] understands.
+  pub fn current(repo: &Repo, role: Role) -> Result<Self, String> {
+    let global = env::global_config().and_then(|path| Ini::load_from_file(path).ok());
+    let config = |key: &str| {
+      [repo.config.as_ref(), global.as_ref()]
+        .into_iter()
+        .flatten()
+        .find_map(|config| config.get_from(Some("user"), key))
+        .map(|value| value.to_owned())
+    };
+    let name = std::env::var(role.variable("NAME"))
+      .ok()
+      .or_else(|| config("name"));
+    let email = std::env::var(role.variable("EMAIL"))
+      .ok()
+      .or_else(|| config("email"));
+    let (name, email) = match (name, email) {
+      (Some(name), Some(email)) if !name.is_empty() => (name, email),
+      _ => {
+        return Err(format!(
+          "{} identity unknown\n\n*** Please tell me who you are.\n\nRun\n\n  git config user.email \"you@example.com\"\n  git config user.name \"Your Name\"\n\nto set your account's default identity.",
+          if role == Role::Author { "Author" } else { "Committer" }
+        ))
+      }
+    };
+
+    let (time, timezone) = match std::env::var(role.variable("DATE")) {
+      Ok(text) => date::parse(&text).ok_or_else(|| format!("invalid date format: {}", text))?,
+      Err(_) => (date::now(), "+0000".to_string()),
+    };
+    Ok(Self {
+      name,
+      email,
+      time,
+      timezone,
+    })
+  }
+
+  /// The `Name <email>` part of the signature.
+  pub fn person(&self) -> String {
+    format!("{} <{}>", self.name, self.email)
+  }
+
+  /// The date formatted for people, eg. `Tue Nov 14 15:13:20 2023 -0700`.
+  pub fn date(&self) -> String {
+    date::format_default(self.time, &self.timezone)
+  }
+}
+
+impl Display for Signature {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{} <{}> {} {}",
+      self.name, self.email, self.time, self.timezone
+    )
+  }
+}
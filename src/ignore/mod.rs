@@ -0,0 +1,250 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use bstr::{BString, ByteSlice};
+
+use crate::repo::Repo;
+
+/// The rules deciding which untracked files git should pretend not to see.
+///
+/// Rules come from `.git/info/exclude`, the file named by `core.excludesFile`
+/// and the `.gitignore` file of every directory. Each line of those files is a
+/// glob pattern, optionally negated with a leading `!`. A pattern ending in `/`
+/// only matches directories, and a pattern containing a `/` anywhere else is
+/// matched against the whole path relative to its `.gitignore`, whereas one
+/// without is matched against the file name at any depth.
+///
+/// The last matching rule wins, and rules from deeper `.gitignore` files win
+/// over shallower ones. Callers walking the working tree should not descend
+/// into ignored directories, since everything inside them is ignored too.
+///
+/// ### Example
+/// ```text
+/// # build output, but keep the docs
+/// target/
+/// *.log
+/// !important.log
+/// /TODO
+/// doc/**/*.pdf
+/// ```
+pub struct Ignore {
+  rules: Vec<Rule>,
+  work_tree: PathBuf,
+}
+
+/// A single line of an ignore file.
+struct Rule {
+  /// The directory (relative to the root of the working tree, without a
+  /// trailing `/`) whose `.gitignore` the rule came from.
+  base: BString,
+  pattern: BString,
+  negated: bool,
+  dir_only: bool,
+
+  /// Whether the pattern is matched against the full path (it contains a
+  /// `/`) rather than just the file name.
+  anchored: bool,
+}
+
+impl Ignore {
+  /// Loads the repository-wide rules and the root `.gitignore`. The
+  /// `.gitignore` files of subdirectories are added with [`Ignore::load_dir`]
+  /// as the working tree is walked.
+  pub fn new(repo: &Repo) -> Self {
+    let mut ignore = Self {
+      rules: Vec::new(),
+      work_tree: repo.work_tree.clone(),
+    };
+    if let Some(path) = excludes_file(repo) {
+      ignore.load_file(&path, b"");
+    }
+    ignore.load_file(&repo.git_dir.join("info").join("exclude"), b"");
+    ignore.load_dir(b"");
+    ignore
+  }
+
+  /// Adds the rules of the `.gitignore` in a directory of the working tree.
+  pub fn load_dir(&mut self, dir: impl AsRef<[u8]>) {
+    let dir = dir.as_ref();
+    let path = self
+      .work_tree
+      .join(OsStr::from_bytes(dir))
+      .join(".gitignore");
+    self.load_file(&path, dir);
+  }
+
+  fn load_file(&mut self, path: &Path, base: &[u8]) {
+    let data = match fs::read(path) {
+      Ok(data) => data,
+      Err(_) => return,
+    };
+    for line in data.lines() {
+      if let Some(rule) = Rule::parse(line, base) {
+        self.rules.push(rule);
+      }
+    }
+  }
+
+  /// Applies the rules to a single path, without looking at its parents.
+  pub fn matches(&self, path: impl AsRef<[u8]>, is_dir: bool) -> bool {
+    let path = path.as_ref();
+    for rule in self.rules.iter().rev() {
+      if rule.dir_only && !is_dir {
+        continue;
+      }
+      let relative = if rule.base.is_empty() {
+        path
+      } else {
+        match path
+          .strip_prefix(rule.base.as_slice())
+          .and_then(|p| p.strip_prefix(b"/"))
+        {
+          Some(relative) => relative,
+          None => continue,
+        }
+      };
+      let subject = if rule.anchored {
+        relative
+      } else {
+        relative.rsplit_str("/").next().unwrap_or(relative)
+      };
+      if wildmatch(&rule.pattern, subject) {
+        return !rule.negated;
+      }
+    }
+    false
+  }
+}
+
+/// The file named by `core.excludesFile`, if it is set.
+pub fn excludes_file(repo: &Repo) -> Option<PathBuf> {
+  let core = repo.config.as_ref()?.section(Some("core"))?;
+  let path = core
+    .get("excludesFile")
+    .or_else(|| core.get("excludesfile"))?;
+  Some(match path.strip_prefix("~/") {
+    Some(rest) => Path::new(&std::env::var("HOME").unwrap_or_default()).join(rest),
+    None => PathBuf::from(path),
+  })
+}
+
+impl Rule {
+  fn parse(line: &[u8], base: &[u8]) -> Option<Rule> {
+    // trailing spaces are dropped unless escaped with a backslash
+    let mut line = line.trim_end_with(|c| c == '\r' || c == '\n');
+    while line.ends_with(b" ") && !line.ends_with(b"\\ ") {
+      line = &line[..line.len() - 1];
+    }
+    if line.is_empty() || line.starts_with(b"#") {
+      return None;
+    }
+    let (negated, line) = match line.strip_prefix(b"!") {
+      Some(rest) => (true, rest),
+      None => (false, line),
+    };
+    let (dir_only, line) = match line.strip_suffix(b"/") {
+      Some(rest) => (true, rest),
+      None => (false, line),
+    };
+    let anchored = line.contains(&b'/');
+    let pattern = line.strip_prefix(b"/").unwrap_or(line);
+    if pattern.is_empty() {
+      return None;
+    }
+    Some(Rule {
+      base: base.into(),
+      pattern: pattern.into(),
+      negated,
+      dir_only,
+      anchored,
+    })
+  }
+}
+
+/// Matches a path against a glob pattern the way git's `wildmatch` does.
+///
+/// `?` matches any one character and `*` any run of characters, neither of
+/// them crossing a `/`. `**` crosses directories: `**/` matches zero or more
+/// leading directories and a trailing `/**` matches everything inside. `[...]`
+/// matches a character class (negated with `!` or `^`) and `\` escapes the
+/// next character.
+pub fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+  match pattern.first() {
+    None => text.is_empty(),
+    Some(b'*') if pattern.get(1) == Some(&b'*') => {
+      let rest = &pattern[2..];
+      match rest.first() {
+        Some(b'/') => {
+          let rest = &rest[1..];
+          wildmatch(rest, text)
+            || (0..text.len()).any(|i| text[i] == b'/' && wildmatch(rest, &text[i + 1..]))
+        }
+        _ => (0..=text.len()).any(|i| wildmatch(rest, &text[i..])),
+      }
+    }
+    Some(b'*') => {
+      let rest = &pattern[1..];
+      for i in 0..=text.len() {
+        if wildmatch(rest, &text[i..]) {
+          return true;
+        }
+        if i < text.len() && text[i] == b'/' {
+          break;
+        }
+      }
+      false
+    }
+    Some(b'?') => !text.is_empty() && text[0] != b'/' && wildmatch(&pattern[1..], &text[1..]),
+    Some(b'[') => match (
+      text.first(),
+      match_class(&pattern[1..], text.first().copied()),
+    ) {
+      (Some(_), Some((true, len))) => wildmatch(&pattern[1 + len..], &text[1..]),
+      (_, Some((false, _))) | (None, _) => false,
+      // an unterminated class is just a literal `[`
+      (Some(ch), None) => *ch == b'[' && wildmatch(&pattern[1..], &text[1..]),
+    },
+    Some(b'\\') if pattern.len() > 1 => {
+      text.first() == Some(&pattern[1]) && wildmatch(&pattern[2..], &text[1..])
+    }
+    Some(ch) => text.first() == Some(ch) && wildmatch(&pattern[1..], &text[1..]),
+  }
+}
+
+/// Matches a character against the class starting just after a `[`.
+///
+/// Returns whether it matched and the length of the class including the
+/// closing `]`, or `None` if the class is never closed.
+fn match_class(class: &[u8], ch: Option<u8>) -> Option<(bool, usize)> {
+  let mut i = 0;
+  let negated = matches!(class.first(), Some(b'!') | Some(b'^'));
+  if negated {
+    i += 1;
+  }
+  let mut matched = false;
+  let mut first = true;
+  while i < class.len() {
+    let c = class[i];
+    if c == b']' && !first {
+      let matched = ch.is_some_and(|ch| ch != b'/') && matched != negated;
+      return Some((matched, i + 1));
+    }
+    first = false;
+    let (low, mut next) = match c {
+      b'\\' if i + 1 < class.len() => (class[i + 1], i + 2),
+      _ => (c, i + 1),
+    };
+    let mut high = low;
+    if next + 1 < class.len() && class[next] == b'-' && class[next + 1] != b']' {
+      high = class[next + 1];
+      next += 2;
+    }
+    if ch.is_some_and(|ch| low <= ch && ch <= high) {
+      matched = true;
+    }
+    i = next;
+  }
+  None
+}
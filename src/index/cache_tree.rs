@@ -0,0 +1,207 @@
+use bstr::{BString, ByteSlice};
+
+use crate::object::{self, mode::Mode, tree::TreeBuilder};
+use crate::repo::Repo;
+
+use super::IndexEntry;
+
+/// The cache tree extension (`TREE`), which remembers the tree each
+/// directory of the index was last written as, so that only the directories
+/// that changed since need to be written again.
+///
+/// Each directory is stored as its name (empty for the root), a NUL, the
+/// number of index entries it covers and its number of subtrees in ASCII,
+/// separated by a space and ending in a newline, and the hash of its tree.
+/// An entry count of -1 marks a directory changed since, which has no hash.
+/// The subtrees follow their parent, depth first.
+#[derive(Debug, Default)]
+pub struct CacheTree {
+  pub name: BString,
+
+  /// The number of index entries the tree covers and its hash, unless the
+  /// directory has changed since it was written.
+  pub tree: Option<(usize, String)>,
+
+  /// The subdirectories, sorted as git keeps them: by the length of their
+  /// names, then by name.
+  pub subtrees: Vec<CacheTree>,
+}
+
+impl CacheTree {
+  /// Parses the data of a `TREE` extension.
+  pub fn parse(data: &[u8]) -> Result<Self, String> {
+    let mut offset = 0;
+    let tree = Self::parse_dir(data, &mut offset)?;
+    match offset == data.len() {
+      true => Ok(tree),
+      false => Err(error()),
+    }
+  }
+
+  fn parse_dir(data: &[u8], offset: &mut usize) -> Result<Self, String> {
+    let rest = data.get(*offset..).ok_or_else(error)?;
+    let name_end = rest.find_byte(0).ok_or_else(error)?;
+    let line_end = name_end + rest[name_end..].find_byte(b'\n').ok_or_else(error)?;
+    let counts = rest.get(name_end + 1..line_end).ok_or_else(error)?;
+    let (entries, subtrees) = counts
+      .to_str()
+      .ok()
+      .and_then(|counts| counts.split_once(' '))
+      .ok_or_else(error)?;
+    let entries: isize = entries.parse().map_err(|_| error())?;
+    let subtrees: usize = subtrees.parse().map_err(|_| error())?;
+    *offset += line_end + 1;
+
+    let tree = match usize::try_from(entries) {
+      Ok(entries) => {
+        let hash = data.get(*offset..*offset + 20).ok_or_else(error)?;
+        *offset += 20;
+        Some((entries, hex::encode(hash)))
+      }
+      Err(_) => None,
+    };
+    let mut dir = CacheTree {
+      name: BString::from(&rest[..name_end]),
+      tree,
+      subtrees: Vec::new(),
+    };
+    for _ in 0..subtrees {
+      dir.subtrees.push(Self::parse_dir(data, offset)?);
+    }
+    dir.subtrees.sort_by(|a, b| order(&a.name, &b.name));
+    Ok(dir)
+  }
+
+  /// Serializes the extension.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut data = Vec::new();
+    self.write_dir(&mut data);
+    data
+  }
+
+  fn write_dir(&self, data: &mut Vec<u8>) {
+    data.extend_from_slice(&self.name);
+    data.push(0);
+    let entries = match &self.tree {
+      Some((entries, _)) => entries.to_string(),
+      None => "-1".to_string(),
+    };
+    data.extend(format!("{} {}\n", entries, self.subtrees.len()).as_bytes());
+    if let Some((_, hash)) = &self.tree {
+      data.extend(hex::decode(hash).unwrap_or_else(|_| vec![0; 20]));
+    }
+    for subtree in &self.subtrees {
+      subtree.write_dir(data);
+    }
+  }
+
+  /// Writes the tree of the directory, given the index entries inside it and
+  /// the length of its path, and returns its hash. The trees of unchanged
+  /// directories are reused, and the others are written and cached.
+  pub fn update(
+    &mut self,
+    repo: &Repo,
+    entries: &[IndexEntry],
+    prefix_len: usize,
+  ) -> Result<String, String> {
+    if let Some((count, hash)) = &self.tree {
+      if *count == entries.len() && object::exists(repo, hash) {
+        return Ok(hash.clone());
+      }
+    }
+    let mut builder = TreeBuilder::new();
+    let mut names = Vec::new();
+    let mut i = 0;
+    while i < entries.len() {
+      let entry = &entries[i];
+      let path = &entry.path[prefix_len..];
+      match path.find_byte(b'/') {
+        None => {
+          if let Some(mode) = entry.tree_mode() {
+            builder.insert(path, mode, &entry.hash)?;
+          }
+          i += 1;
+        }
+        Some(slash) => {
+          let prefix = &path[..slash + 1];
+          let len = entries[i..].partition_point(|e| e.path[prefix_len..].starts_with(prefix));
+          let name = &path[..slash];
+          let hash =
+            self
+              .subtree(name)
+              .update(repo, &entries[i..i + len], prefix_len + prefix.len())?;
+          builder.insert(name, Mode::Directory, &hash)?;
+          names.push(name);
+          i += len;
+        }
+      }
+    }
+    self
+      .subtrees
+      .retain(|subtree| names.contains(&subtree.name.as_slice()));
+    let hash = builder.write(repo)?;
+    self.tree = Some((entries.len(), hash.clone()));
+    Ok(hash)
+  }
+
+  /// Invalidates the directories leading to a path that was added to or
+  /// removed from the index. A directory at the path itself is dropped, as
+  /// it may have been replaced by a file.
+  pub fn invalidate(&mut self, path: &[u8]) {
+    self.tree = None;
+    match path.split_once_str("/") {
+      Some((name, rest)) => {
+        if let Some(subtree) = self.subtree_mut(name) {
+          subtree.invalidate(rest);
+        }
+      }
+      None => self.subtrees.retain(|subtree| subtree.name != path),
+    }
+  }
+
+  /// Finds the cached tree of a directory, by its path from this one.
+  pub fn find(&self, path: &[u8]) -> Option<&CacheTree> {
+    path
+      .split_str("/")
+      .filter(|name| !name.is_empty())
+      .try_fold(self, |dir, name| {
+        dir.subtrees.iter().find(|subtree| subtree.name == name)
+      })
+  }
+
+  /// Finds the subtree with a name, or adds an invalid one.
+  fn subtree(&mut self, name: &[u8]) -> &mut CacheTree {
+    let i = match self
+      .subtrees
+      .binary_search_by(|subtree| order(&subtree.name, name))
+    {
+      Ok(i) => i,
+      Err(i) => {
+        let subtree = CacheTree {
+          name: name.into(),
+          ..Default::default()
+        };
+        self.subtrees.insert(i, subtree);
+        i
+      }
+    };
+    &mut self.subtrees[i]
+  }
+
+  fn subtree_mut(&mut self, name: &[u8]) -> Option<&mut CacheTree> {
+    self
+      .subtrees
+      .binary_search_by(|subtree| order(&subtree.name, name))
+      .ok()
+      .map(|i| &mut self.subtrees[i])
+  }
+}
+
+/// The order git keeps subtrees in.
+fn order(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+  a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn error() -> String {
+  "index file is corrupt (bad cache tree extension)".to_string()
+}
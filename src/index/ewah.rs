@@ -0,0 +1,68 @@
+/// Parses one of the EWAH compressed bitmaps that some index extensions use
+/// to mark entries or directories, returning the bits and the number of
+/// bytes it took up.
+///
+/// A bitmap is stored as its length in bits, the number of 64-bit words that
+/// follow, the words, and the position of the last run-length word, all
+/// big-endian. Each run-length word holds a bit (bit 0), how many words of
+/// that bit come next (bits 1-32) and how many literal words follow it (bits
+/// 33-63).
+pub fn read(data: &[u8]) -> Result<(Vec<bool>, usize), String> {
+  let error = || "corrupt ewah bitmap".to_string();
+  let be32 = |offset: usize| -> Result<u32, String> {
+    let bytes = data.get(offset..offset + 4).ok_or_else(error)?;
+    Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+  };
+  let size = be32(0)? as usize;
+  let count = be32(4)? as usize;
+  let words = data.get(8..8 + count * 8).ok_or_else(error)?;
+  let words: Vec<u64> = words
+    .chunks(8)
+    .map(|word| u64::from_be_bytes(word.try_into().unwrap()))
+    .collect();
+  let len = 8 + count * 8 + 4;
+  if data.len() < len {
+    return Err(error());
+  }
+
+  let mut bits = Vec::with_capacity(size);
+  let mut i = 0;
+  while i < words.len() && bits.len() < size {
+    let marker = words[i];
+    let run = ((marker >> 1) & 0xffff_ffff) as usize;
+    let literals = (marker >> 33) as usize;
+    let len = bits.len() + (run * 64).min(size - bits.len());
+    bits.resize(len, marker & 1 == 1);
+    for word in words.get(i + 1..i + 1 + literals).ok_or_else(error)? {
+      bits.extend((0..64).map(|bit| word >> bit & 1 == 1));
+    }
+    i += 1 + literals;
+  }
+  bits.resize(size, false);
+  Ok((bits, len))
+}
+
+/// Serializes a bitmap, as a single run-length word followed by every word
+/// as a literal. As in git, the bitmap ends at the last bit set.
+pub fn write(bits: &[bool]) -> Vec<u8> {
+  let bits = &bits[..bits.iter().rposition(|bit| *bit).map_or(0, |i| i + 1)];
+  let words: Vec<u64> = bits
+    .chunks(64)
+    .map(|chunk| {
+      chunk
+        .iter()
+        .enumerate()
+        .filter(|(_, bit)| **bit)
+        .fold(0, |word, (i, _)| word | 1 << i)
+    })
+    .collect();
+  let mut data = Vec::with_capacity(16 + words.len() * 8);
+  data.extend((bits.len() as u32).to_be_bytes());
+  data.extend((words.len() as u32 + 1).to_be_bytes());
+  data.extend(((words.len() as u64) << 33).to_be_bytes());
+  for word in words {
+    data.extend(word.to_be_bytes());
+  }
+  data.extend(0u32.to_be_bytes());
+  data
+}
@@ -0,0 +1,171 @@
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bstr::{BString, ByteSlice};
+
+use crate::index::{ewah, IndexEntry};
+use crate::repo::Repo;
+use crate::trace;
+
+/// The fsmonitor extension (`FSMN`), which lets a filesystem watcher vouch
+/// for entries so that they need not be looked at.
+///
+/// It holds the token the watcher gave when it was last asked what changed,
+/// to ask from next time, and a bitmap of the entries it has not vouched
+/// for since. The other entries are marked `fsmonitor_valid` while the index
+/// is in memory.
+pub struct FsMonitor {
+  pub token: String,
+
+  /// The entries not vouched for, as read.
+  dirty: Vec<bool>,
+}
+
+impl FsMonitor {
+  pub fn new(token: String) -> Self {
+    Self {
+      token,
+      dirty: Vec::new(),
+    }
+  }
+
+  /// Parses the data of an `FSMN` extension. Version 1 holds a timestamp in
+  /// nanoseconds where version 2 holds an opaque token; both are kept as a
+  /// string.
+  pub fn parse(data: &[u8]) -> Result<Self, String> {
+    let error = || "index file is corrupt (bad fsmonitor extension)".to_string();
+    let version = data
+      .get(0..4)
+      .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+      .ok_or_else(error)?;
+    let (token, offset) = match version {
+      1 => {
+        let bytes = data.get(4..12).ok_or_else(error)?;
+        (
+          u64::from_be_bytes(bytes.try_into().unwrap()).to_string(),
+          12,
+        )
+      }
+      2 => {
+        let len = data[4..].iter().position(|b| *b == 0).ok_or_else(error)?;
+        let token = String::from_utf8_lossy(&data[4..4 + len]).into_owned();
+        (token, 4 + len + 1)
+      }
+      _ => return Err(format!("bad fsmonitor version {}", version)),
+    };
+    let size = data
+      .get(offset..offset + 4)
+      .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()) as usize)
+      .ok_or_else(error)?;
+    let (dirty, len) = ewah::read(data.get(offset + 4..).ok_or_else(error)?)?;
+    if len != size {
+      return Err(error());
+    }
+    Ok(Self { token, dirty })
+  }
+
+  /// Marks the entries the extension vouches for, once they are all read.
+  pub fn apply(&self, entries: &mut [IndexEntry]) -> Result<(), String> {
+    if self.dirty.len() > entries.len() {
+      return Err("index file is corrupt (bad fsmonitor extension)".to_string());
+    }
+    for (i, entry) in entries.iter_mut().enumerate() {
+      entry.fsmonitor_valid = !self.dirty.get(i).copied().unwrap_or(false);
+    }
+    Ok(())
+  }
+
+  /// Serializes the extension, as version 2.
+  pub fn to_bytes(&self, entries: &[IndexEntry]) -> Vec<u8> {
+    let dirty: Vec<bool> = entries.iter().map(|entry| !entry.fsmonitor_valid).collect();
+    let bitmap = ewah::write(&dirty);
+
+    let mut data = Vec::new();
+    data.extend(2u32.to_be_bytes());
+    data.extend_from_slice(self.token.as_bytes());
+    data.push(0);
+    data.extend((bitmap.len() as u32).to_be_bytes());
+    data.extend(bitmap);
+    data
+  }
+}
+
+/// What a filesystem watcher reported.
+pub enum Changes {
+  /// The paths that may have changed. A path ending in `/` is a directory,
+  /// anything in which may have changed.
+  Paths(Vec<BString>),
+
+  /// Anything may have changed, as the watcher was asked about a time it
+  /// knows nothing about, or could not be asked at all.
+  All,
+}
+
+/// Asks the hook named by `core.fsmonitor` what has changed since `token`,
+/// returning the token to ask from next time and the changes.
+///
+/// The hook is run with the version of the protocol and the token. With
+/// version 2 it prints a new token and then the paths, each ending in NUL;
+/// with version 1 the token is a time in nanoseconds and it prints just the
+/// paths. A lone `/` for a path means everything. Version 2 is tried first
+/// unless `version` says otherwise, falling back to version 1 if it fails.
+pub fn query(repo: &Repo, hook: &str, version: Option<u32>, token: &str) -> (String, Changes) {
+  let now = now().to_string();
+  if version != Some(1) {
+    if let Some(output) = run(repo, hook, 2, token) {
+      let mut fields = output.split_str("\0").filter(|field| !field.is_empty());
+      match fields.next().map(|token| token.to_str_lossy().into_owned()) {
+        Some(token) => return (token, changes(fields)),
+        None => return (now, Changes::All),
+      }
+    }
+    if version == Some(2) {
+      return (now, Changes::All);
+    }
+  }
+  match run(repo, hook, 1, token) {
+    Some(output) => (
+      now,
+      changes(output.split_str("\0").filter(|field| !field.is_empty())),
+    ),
+    None => (now, Changes::All),
+  }
+}
+
+/// Runs the hook through the shell, from the top of the working tree,
+/// returning what it printed if it succeeded.
+fn run(repo: &Repo, hook: &str, version: u32, token: &str) -> Option<Vec<u8>> {
+  let mut command = Command::new("sh");
+  command
+    .arg("-c")
+    .arg(format!("{} \"$@\"", hook))
+    .arg(hook)
+    .arg(version.to_string())
+    .arg(token)
+    .current_dir(&repo.work_tree)
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::inherit());
+  let traced = trace::command("hook", &command);
+  let child = command.spawn().ok()?;
+  let pid = child.id();
+  let output = child.wait_with_output().ok()?;
+  traced.exit(pid, &output.status);
+  output.status.success().then_some(output.stdout)
+}
+
+fn changes<'a>(paths: impl Iterator<Item = &'a [u8]>) -> Changes {
+  let paths: Vec<BString> = paths.map(BString::from).collect();
+  match paths.first().map(|path| path.as_slice()) {
+    Some(b"/") => Changes::All,
+    _ => Changes::Paths(paths),
+  }
+}
+
+/// The current time in nanoseconds, which a version 1 hook takes as the
+/// token.
+pub fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |time| time.as_nanos() as u64)
+}
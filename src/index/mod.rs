@@ -0,0 +1,961 @@
+pub mod cache_tree;
+pub mod ewah;
+pub mod fsmonitor;
+pub mod split;
+pub mod unpack;
+pub mod untracked;
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::{self, Metadata};
+use std::io::Write;
+use std::mem;
+use std::os::unix::{ffi::OsStringExt, fs::MetadataExt};
+use std::path::PathBuf;
+
+use bstr::{BString, ByteSlice};
+
+use crate::convert;
+use crate::crypto;
+use crate::env;
+use crate::object::{self, blob::Blob, mode::Mode, refs::create_lock, tree};
+use crate::repo::Repo;
+use crate::trace::{self, event};
+use cache_tree::CacheTree;
+use fsmonitor::{Changes, FsMonitor};
+use split::Link;
+use untracked::UntrackedCache;
+
+/// The git index (aka. the staging area or cache).
+///
+/// The index lives in `.git/index` and records the state of the working tree
+/// that will go into the next commit. It is a flat, sorted list of entries,
+/// one per tracked file, each holding the blob hash and mode of the file along
+/// with the `stat` information of the file when it was last staged. The stat
+/// information lets git tell that a file is unchanged without re-hashing it.
+///
+/// The file starts with a 12 byte header: the signature `DIRC`, a version
+/// number and the number of entries, all big-endian. Then come the entries,
+/// then any extensions, then a SHA-1 checksum of everything before it.
+///
+/// Each extension is a four letter signature, a 32-bit size and its data.
+/// The cache tree (`TREE`), untracked cache (`UNTR`) and fsmonitor (`FSMN`)
+/// extensions are understood. Other optional extensions are kept as they
+/// are, except those describing where the entries are in the file.
+///
+/// A split index holds only the entries that changed since a shared index,
+/// which holds the rest, was written (see [`Link`]). A sparse index holds a
+/// directory outside the sparse checkout as a single entry for its tree.
+///
+/// An entry changed in the same instant the index was written (or later) is
+/// "racily clean": the file may have been changed again after it was staged
+/// without its stat data showing it. Such entries are smudged when the index
+/// is read, so that their contents are checked.
+pub struct Index {
+  pub version: u32,
+  entries: Vec<IndexEntry>,
+
+  /// The modification time of the index file when it was read.
+  timestamp: Option<(u32, u32)>,
+  pub cache_tree: Option<CacheTree>,
+  pub untracked: Option<UntrackedCache>,
+  pub fsmonitor: Option<FsMonitor>,
+
+  /// The optional extensions that are not understood, by signature.
+  extensions: Vec<([u8; 4], Vec<u8>)>,
+
+  /// Whether the index is written as a split index, holding only what has
+  /// changed since a shared index that holds the rest.
+  pub split: bool,
+
+  /// The hash of the shared index the index was read on top of.
+  shared_index: Option<String>,
+
+  /// Whether the index is written as a sparse index.
+  sparse: bool,
+
+  /// Whether anything has changed since the index was read.
+  changed: bool,
+}
+
+/// A single file in the index.
+#[derive(Clone, Debug, Default)]
+pub struct IndexEntry {
+  pub ctime: (u32, u32),
+  pub mtime: (u32, u32),
+  pub dev: u32,
+  pub ino: u32,
+  pub mode: u32,
+  pub uid: u32,
+  pub gid: u32,
+  pub size: u32,
+  pub hash: String,
+  pub flags: u16,
+
+  /// The second set of flags that version 3 entries may carry, which holds
+  /// the skip-worktree and intent-to-add bits.
+  pub extended_flags: u16,
+
+  /// The path of the file from the root of the working tree, which may be
+  /// any bytes but NUL.
+  pub path: BString,
+
+  /// Whether a filesystem watcher has said the file is unchanged since the
+  /// entry was last checked. This is only kept in memory; on disk it is
+  /// part of the fsmonitor extension.
+  pub fsmonitor_valid: bool,
+}
+
+/// Flag bit telling git to assume the file is unchanged without checking.
+pub const ASSUME_VALID: u16 = 0x8000;
+
+/// Flag bit marking an entry that carries extended flags.
+const EXTENDED: u16 = 0x4000;
+
+/// Extended flag bit telling git to leave the file in the working tree alone.
+pub const SKIP_WORKTREE: u16 = 0x4000;
+
+/// The size of the fixed-width part of an entry, up to and including flags.
+const ENTRY_HEADER_LEN: usize = 62;
+
+impl Index {
+  pub fn new() -> Self {
+    Self {
+      version: 2,
+      entries: Vec::new(),
+      timestamp: None,
+      cache_tree: None,
+      untracked: None,
+      fsmonitor: None,
+      extensions: Vec::new(),
+      split: false,
+      shared_index: None,
+      sparse: false,
+      changed: false,
+    }
+  }
+
+  /// Reads the index of a repository, `.git/index` unless `GIT_INDEX_FILE`
+  /// names another. A missing index is an empty one.
+  ///
+  /// A split index is merged with its shared index, and the directories of
+  /// a sparse index are expanded into their files. Whether the index is
+  /// written back split or sparse depends on `core.splitIndex` (unset keeps
+  /// it as it is) and `index.sparse`, which needs a cone mode sparse
+  /// checkout.
+  ///
+  /// The untracked cache is added or dropped as `core.untrackedCache` says
+  /// (`keep`, the default, leaves it as it is). If `core.fsmonitor` names a
+  /// hook, it is asked what has changed since the index was last written;
+  /// otherwise the fsmonitor extension is dropped.
+  pub fn read(repo: &Repo) -> Result<Index, String> {
+    let path = env::index_file(&repo.git_dir);
+    let _region = trace::region(&format!("read cache {}", path.display()));
+    let _event = event::region("index", "do_read_index", path.to_str());
+    let mut index = match fs::metadata(&path) {
+      Ok(metadata) => {
+        let data = fs::read(&path).map_err(|e| format!("unable to read index ({})", e))?;
+        let (mut index, link) = Index::parse(&data)?;
+        if let Some(link) = link {
+          let shared = Index::read_shared(repo, &link.shared)?;
+          index.entries = link.merge(shared, mem::take(&mut index.entries))?;
+          index.shared_index = Some(link.shared);
+          index.split = true;
+        }
+        if let Some(fsmonitor) = &index.fsmonitor {
+          fsmonitor.apply(&mut index.entries)?;
+        }
+        index.timestamp = Some((metadata.mtime() as u32, metadata.mtime_nsec() as u32));
+        index.smudge_racy_entries();
+        index.expand(repo)?;
+        event::data("index", "read/version", index.version);
+        event::data("index", "read/cache_nr", index.entries.len());
+        index
+      }
+      Err(_) => Index::new(),
+    };
+
+    match config_bool(repo, "core", "untrackedCache") {
+      Some(true) if index.untracked.is_none() => {
+        index.untracked = Some(UntrackedCache::new(repo));
+        index.changed = true;
+      }
+      Some(false) if index.untracked.is_some() => {
+        index.untracked = None;
+        index.changed = true;
+      }
+      _ => (),
+    }
+    if let Some(split) = config_bool(repo, "core", "splitIndex") {
+      index.changed |= index.split != split;
+      index.split = split;
+    }
+    index.sparse = config_bool(repo, "core", "sparseCheckout") == Some(true)
+      && config_bool(repo, "core", "sparseCheckoutCone") == Some(true)
+      && config_bool(repo, "index", "sparse") == Some(true)
+      && !index.split;
+    match fsmonitor_hook(repo) {
+      Some(hook) if !repo.bare => index.query_fsmonitor(repo, &hook)?,
+      _ => index.fsmonitor = None,
+    }
+    Ok(index)
+  }
+
+  /// Reads the entries of a shared index.
+  fn read_shared(repo: &Repo, hash: &str) -> Result<Vec<IndexEntry>, String> {
+    let path = repo.git_dir.join(format!("sharedindex.{}", hash));
+    let data =
+      fs::read(&path).map_err(|e| format!("{}: index file open failed: {}", path.display(), e))?;
+    if hex::encode(&data[data.len().saturating_sub(20)..]) != hash {
+      return Err(format!(
+        "broken index, expect {} in {}",
+        hash,
+        path.display()
+      ));
+    }
+    match Index::parse(&data)? {
+      (index, None) => Ok(index.entries),
+      (_, Some(_)) => Err(format!("{}: shared index is itself split", path.display())),
+    }
+  }
+
+  /// Parses the raw bytes of an index file, along with the link to its
+  /// shared index if it is a split index.
+  fn parse(data: &[u8]) -> Result<(Index, Option<Link>), String> {
+    if data.len() < 32 || &data[0..4] != b"DIRC" {
+      return Err("index file is corrupt (bad signature)".to_string());
+    }
+    let body = &data[..data.len() - 20];
+    if crypto::sha_1(body) != hex::encode(&data[data.len() - 20..]) {
+      return Err("index file is corrupt (bad checksum)".to_string());
+    }
+    let version = be32(data, 4);
+    if version != 2 && version != 3 {
+      return Err(format!("index version {} is not supported", version));
+    }
+    let count = be32(data, 8) as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut offset = 12;
+    for _ in 0..count {
+      if offset + ENTRY_HEADER_LEN > body.len() {
+        return Err("index file is corrupt (truncated entry)".to_string());
+      }
+      let flags = u16::from_be_bytes([data[offset + 60], data[offset + 61]]);
+      let mut path_start = offset + ENTRY_HEADER_LEN;
+      let mut extended_flags = 0;
+      if flags & EXTENDED != 0 {
+        extended_flags = u16::from_be_bytes([data[path_start], data[path_start + 1]]);
+        path_start += 2;
+      }
+      let path_end = match body[path_start..].iter().position(|b| *b == 0) {
+        Some(len) => path_start + len,
+        None => return Err("index file is corrupt (unterminated path)".to_string()),
+      };
+      entries.push(IndexEntry {
+        fsmonitor_valid: false,
+        ctime: (be32(data, offset), be32(data, offset + 4)),
+        mtime: (be32(data, offset + 8), be32(data, offset + 12)),
+        dev: be32(data, offset + 16),
+        ino: be32(data, offset + 20),
+        mode: be32(data, offset + 24),
+        uid: be32(data, offset + 28),
+        gid: be32(data, offset + 32),
+        size: be32(data, offset + 36),
+        hash: hex::encode(&data[offset + 40..offset + 60]),
+        flags: flags & !EXTENDED,
+        extended_flags,
+        path: BString::from(&data[path_start..path_end]),
+      });
+      // entries are NUL-padded to a multiple of eight bytes
+      let len = path_end - offset;
+      offset += (len + 8) & !7;
+    }
+
+    let mut index = Index {
+      version,
+      entries,
+      ..Index::new()
+    };
+    let mut link = None;
+    while offset + 8 <= body.len() {
+      let signature = &body[offset..offset + 4];
+      let size = be32(body, offset + 4) as usize;
+      let data = body
+        .get(offset + 8..offset + 8 + size)
+        .ok_or("index file is corrupt (truncated extension)")?;
+      match signature {
+        b"TREE" => index.cache_tree = Some(CacheTree::parse(data)?),
+        b"UNTR" => index.untracked = Some(UntrackedCache::parse(data)?),
+        b"FSMN" => index.fsmonitor = Some(FsMonitor::parse(data)?),
+        b"link" => link = Some(Link::parse(data)?),
+        // a sparse index, whose directory entries are expanded on reading
+        b"sdir" => (),
+        // the end of index entries and index entry offset table extensions
+        // would be wrong once the entries change
+        b"EOIE" | b"IEOT" => (),
+        // an extension starting with a capital letter is optional
+        [b'A'..=b'Z', ..] => index
+          .extensions
+          .push((signature.try_into().unwrap(), data.to_vec())),
+        _ => {
+          return Err(format!(
+            "index uses {} extension, which we do not understand",
+            signature.as_bstr()
+          ))
+        }
+      }
+      offset += 8 + size;
+    }
+    Ok((index, link))
+  }
+
+  /// Expands the directory entries of a sparse index into the files of
+  /// their trees, all marked skip-worktree.
+  fn expand(&mut self, repo: &Repo) -> Result<(), String> {
+    let directory = Mode::Directory.bits();
+    if !self.entries.iter().any(|e| e.mode == directory) {
+      return Ok(());
+    }
+    let mut entries = Vec::with_capacity(self.entries.len());
+    for entry in mem::take(&mut self.entries) {
+      if entry.mode != directory {
+        entries.push(entry);
+        continue;
+      }
+      for (path, (mode, hash)) in tree::flatten(repo, &entry.hash)? {
+        let mut file = IndexEntry::new([entry.path.as_slice(), &path].concat(), mode, &hash);
+        file.extended_flags = SKIP_WORKTREE;
+        entries.push(file);
+      }
+    }
+    self.entries = entries;
+    Ok(())
+  }
+
+  /// The entries as they are written: with every directory whose entries
+  /// are all skip-worktree collapsed into one entry for its tree, if the
+  /// index is sparse. Only directories in the cache tree can be collapsed.
+  fn sparse_entries(&self) -> Cow<'_, [IndexEntry]> {
+    match (&self.cache_tree, self.sparse) {
+      (Some(cache_tree), true) => {
+        let mut entries = Vec::new();
+        collapse(cache_tree, &self.entries, 0, &mut entries);
+        Cow::Owned(entries)
+      }
+      _ => Cow::Borrowed(&self.entries),
+    }
+  }
+
+  /// Smudges the entries changed at or after the index was written, by
+  /// zeroing their recorded size, so that their stat data no longer matches
+  /// and their contents are compared instead.
+  fn smudge_racy_entries(&mut self) {
+    for entry in &mut self.entries {
+      if entry.mode != Mode::Gitlink.bits() && self.timestamp.is_some_and(|t| entry.mtime >= t) {
+        entry.size = 0;
+      }
+    }
+  }
+
+  /// Asks a filesystem watcher hook what has changed since it was last
+  /// asked, so that the entries and untracked cache directories it does not
+  /// mention can be trusted without looking at the working tree.
+  ///
+  /// The first time, or if the hook fails, nothing is trusted; entries are
+  /// vouched for again as they are found to be unchanged.
+  fn query_fsmonitor(&mut self, repo: &Repo, hook: &str) -> Result<(), String> {
+    let version = match config(repo, "core", "fsmonitorHookVersion") {
+      Some(version) => match version.trim() {
+        "1" => Some(1),
+        "2" => Some(2),
+        _ => return Err(format!("bad core.fsmonitorHookVersion '{}'", version)),
+      },
+      None => None,
+    };
+    let token = match &self.fsmonitor {
+      Some(fsmonitor) => fsmonitor.token.clone(),
+      None => fsmonitor::now().to_string(),
+    };
+    let fresh = self.fsmonitor.is_none();
+    let (token, changes) = fsmonitor::query(repo, hook, version, &token);
+    match changes {
+      Changes::Paths(paths) if !fresh => {
+        for path in paths {
+          let path = path.strip_suffix(b"/").unwrap_or(&path);
+          let prefix = [path, b"/"].concat();
+          let start = self.entries.partition_point(|e| e.path.as_slice() < path);
+          for entry in &mut self.entries[start..] {
+            if entry.path != path && !entry.path.starts_with(&prefix) {
+              break;
+            }
+            entry.fsmonitor_valid = false;
+          }
+          if let Some(untracked) = &mut self.untracked {
+            untracked.invalidate(&prefix);
+          }
+        }
+        if let Some(untracked) = &mut self.untracked {
+          untracked.fsmonitor = true;
+        }
+      }
+      _ => {
+        for entry in &mut self.entries {
+          entry.fsmonitor_valid = false;
+        }
+        if let Some(untracked) = &mut self.untracked {
+          untracked.fsmonitor = false;
+        }
+      }
+    }
+    self.fsmonitor = Some(FsMonitor::new(token));
+    self.changed = true;
+    Ok(())
+  }
+
+  /// When the index file was last written, as it was read.
+  pub fn timestamp(&self) -> Option<(u32, u32)> {
+    self.timestamp
+  }
+
+  /// Whether the index has changed since it was read, by adding, removing
+  /// or refreshing entries or by updating its extensions.
+  pub fn is_changed(&self) -> bool {
+    self.changed
+  }
+
+  /// Marks the index as changed, when one of its extensions was updated.
+  pub fn mark_changed(&mut self) {
+    self.changed = true;
+  }
+
+  /// Writes the index to `.git/index`, or the file named by `GIT_INDEX_FILE`.
+  ///
+  /// A split index is written on top of the shared index it was read on
+  /// top of, unless too many entries (more than `splitIndex.maxPercentChange`,
+  /// 20% by default) have changed since, in which case a new shared index is
+  /// written.
+  ///
+  /// Like git, the new index is written to `index.lock` and renamed into
+  /// place, so another process that holds the lock makes this fail rather
+  /// than one write overwrite the other.
+  pub fn write(&self, repo: &Repo) -> Result<(), String> {
+    let path = env::index_file(&repo.git_dir);
+    let _region = trace::region("write index");
+    let _event = event::region("index", "do_write_index", path.to_str());
+    let mut lock_path = path.clone().into_os_string();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+    let mut lock = create_lock(&lock_path)?;
+    let written = match self.split {
+      true => self.split_bytes(repo),
+      false => Ok(self.to_bytes()),
+    }
+    .and_then(|data| {
+      lock
+        .write_all(&data)
+        .and_then(|()| fs::rename(&lock_path, &path))
+        .map_err(|e| format!("unable to write index ({})", e))
+    });
+    if written.is_err() {
+      let _ = fs::remove_file(&lock_path);
+    }
+    written
+  }
+
+  /// Serializes the index, including the trailing checksum.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let entries = self.sparse_entries();
+    let mut extensions = self.extensions(&entries);
+    if self.sparse {
+      extensions.push((b"sdir", Vec::new()));
+    }
+    serialize(self.version, &entries, extensions)
+  }
+
+  /// Serializes the index as a split index, writing a new shared index if
+  /// need be.
+  fn split_bytes(&self, repo: &Repo) -> Result<Vec<u8>, String> {
+    let max_percent = config(repo, "splitIndex", "maxPercentChange")
+      .and_then(|value| value.parse::<usize>().ok())
+      .filter(|percent| *percent <= 100)
+      .unwrap_or(20);
+    let shared = self
+      .shared_index
+      .as_ref()
+      .and_then(|hash| Some((hash, Index::read_shared(repo, hash).ok()?)));
+    let split = shared.map(|(hash, shared)| Link::split(hash, &shared, &self.entries));
+    let (link, entries) = match split {
+      Some((link, entries)) if entries.len() * 100 <= max_percent * self.entries.len() => {
+        (link, entries)
+      }
+      _ => {
+        let shared = serialize(self.version, &self.entries, Vec::new());
+        let hash = hex::encode(&shared[shared.len() - 20..]);
+        let path = repo.git_dir.join(format!("sharedindex.{}", hash));
+        if !path.exists() {
+          fs::write(&path, &shared).map_err(|e| format!("unable to write shared index ({})", e))?;
+        }
+        (Link::new(&hash), Vec::new())
+      }
+    };
+    let mut extensions = vec![(b"link".as_slice(), link.to_bytes())];
+    extensions.extend(self.extensions(&self.entries));
+    Ok(serialize(self.version, &entries, extensions))
+  }
+
+  /// The extensions to write, given the entries of the whole index as they
+  /// are written.
+  fn extensions(&self, entries: &[IndexEntry]) -> Vec<(&[u8], Vec<u8>)> {
+    let mut extensions: Vec<(&[u8], Vec<u8>)> = Vec::new();
+    if let Some(cache_tree) = &self.cache_tree {
+      extensions.push((b"TREE", cache_tree.to_bytes()));
+    }
+    for (signature, extension) in &self.extensions {
+      extensions.push((signature, extension.clone()));
+    }
+    if let Some(untracked) = &self.untracked {
+      extensions.push((b"UNTR", untracked.to_bytes()));
+    }
+    if let Some(fsmonitor) = &self.fsmonitor {
+      extensions.push((b"FSMN", fsmonitor.to_bytes(entries)));
+    }
+    extensions
+  }
+
+  /// The entries, sorted by path and then by stage.
+  pub fn entries(&self) -> &[IndexEntry] {
+    &self.entries
+  }
+
+  /// Finds the stage 0 entry for a path.
+  pub fn get(&self, path: impl AsRef<[u8]>) -> Option<&IndexEntry> {
+    let path = path.as_ref();
+    self
+      .entries
+      .iter()
+      .find(|e| e.path == path && e.stage() == 0)
+  }
+
+  /// Finds the conflict stages of a path: the base, our and their versions
+  /// (stages 1 to 3), any of which may be missing. All are `None` unless
+  /// the path is unmerged.
+  pub fn stages(&self, path: impl AsRef<[u8]>) -> [Option<&IndexEntry>; 3] {
+    let path = path.as_ref();
+    let mut stages = [None; 3];
+    for entry in self
+      .entries
+      .iter()
+      .filter(|e| e.path == path && e.stage() != 0)
+    {
+      stages[entry.stage() as usize - 1] = Some(entry);
+    }
+    stages
+  }
+
+  /// Adds an entry, replacing the entries it supersedes: a normal (stage 0)
+  /// entry replaces everything at its path, while a conflict stage replaces
+  /// the normal entry and any entry for the same stage.
+  pub fn add(&mut self, entry: IndexEntry) {
+    if let Some(cache_tree) = &mut self.cache_tree {
+      cache_tree.invalidate(&entry.path);
+    }
+    if let Some(untracked) = &mut self.untracked {
+      untracked.invalidate(&entry.path);
+    }
+    self.changed = true;
+    let stage = entry.stage();
+    self
+      .entries
+      .retain(|e| e.path != entry.path || (stage != 0 && e.stage() != 0 && e.stage() != stage));
+    let position = self
+      .entries
+      .partition_point(|e| (&e.path, e.stage()) < (&entry.path, entry.stage()));
+    self.entries.insert(position, entry);
+  }
+
+  /// Removes every entry at a path, returning whether there were any.
+  pub fn remove(&mut self, path: impl AsRef<[u8]>) -> bool {
+    let path = path.as_ref();
+    let before = self.entries.len();
+    self.entries.retain(|e| e.path != path);
+    if self.entries.len() == before {
+      return false;
+    }
+    if let Some(cache_tree) = &mut self.cache_tree {
+      cache_tree.invalidate(path);
+    }
+    if let Some(untracked) = &mut self.untracked {
+      untracked.invalidate(path);
+    }
+    self.changed = true;
+    true
+  }
+
+  /// Writes the trees of the index and returns the hash of the root tree.
+  /// The index must have no conflicts.
+  ///
+  /// Only the directories changed since the cache tree was last updated are
+  /// written; the cache tree is created if need be and brought up to date.
+  pub fn write_tree(&mut self, repo: &Repo) -> Result<String, String> {
+    let mut cache_tree = self.cache_tree.take().unwrap_or_default();
+    let before = cache_tree.tree.clone();
+    let hash = cache_tree.update(repo, &self.entries, 0);
+    if cache_tree.tree != before {
+      self.changed = true;
+    }
+    self.cache_tree = Some(cache_tree);
+    hash
+  }
+
+  /// Whether there is an entry at a path, in any stage.
+  pub fn contains(&self, path: impl AsRef<[u8]>) -> bool {
+    let path = path.as_ref();
+    let i = self.entries.partition_point(|e| e.path.as_slice() < path);
+    self.entries.get(i).is_some_and(|e| e.path == path)
+  }
+
+  /// Whether there are entries inside a directory.
+  pub fn contains_dir(&self, dir: impl AsRef<[u8]>) -> bool {
+    let prefix = [dir.as_ref(), b"/"].concat();
+    let i = self
+      .entries
+      .partition_point(|e| e.path.as_slice() < prefix.as_slice());
+    self
+      .entries
+      .get(i)
+      .is_some_and(|e| e.path.starts_with(&prefix))
+  }
+
+  /// Updates the stat data of the entries whose files have been touched
+  /// without being changed, so that they need not be hashed again.
+  ///
+  /// With a filesystem watcher, the entries found to be unchanged are also
+  /// marked as vouched for, and entries already vouched for are skipped.
+  pub fn refresh(&mut self, repo: &Repo) -> Result<(), String> {
+    let _event = event::region("index", "refresh", None);
+    let fsmonitor = self.fsmonitor.is_some();
+    for i in 0..self.entries.len() {
+      let entry = &self.entries[i];
+      if entry.stage() != 0
+        || entry.assumed_unchanged()
+        || entry.fsmonitor_valid
+        || entry.mode == Mode::Gitlink.bits()
+      {
+        continue;
+      }
+      let metadata = match fs::symlink_metadata(repo.work_tree_path(&entry.path)) {
+        Ok(metadata) if !metadata.is_dir() => metadata,
+        _ => continue,
+      };
+      let unchanged = match entry.stat_matches(&metadata) {
+        true => true,
+        false if entry.mode != mode_bits(file_mode(&metadata)) => false,
+        false if hash_file(repo, &entry.path, Some(&entry.hash), false)? == entry.hash => {
+          self.entries[i].refresh(&metadata);
+          self.changed = true;
+          true
+        }
+        false => false,
+      };
+      if unchanged && fsmonitor {
+        self.entries[i].fsmonitor_valid = true;
+        self.changed = true;
+      }
+    }
+    Ok(())
+  }
+
+  /// The tracked files as a path to (mode, hash) map, like
+  /// [`crate::object::tree::flatten`].
+  pub fn files(&self) -> BTreeMap<BString, (Mode, String)> {
+    self
+      .entries
+      .iter()
+      .filter(|e| e.stage() == 0)
+      .filter_map(|e| {
+        e.tree_mode()
+          .map(|mode| (e.path.clone(), (mode, e.hash.clone())))
+      })
+      .collect()
+  }
+}
+
+impl Default for Index {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl IndexEntry {
+  /// Creates an entry with no stat information, which is always considered
+  /// stale and so is re-checked against the working tree.
+  pub fn new(path: impl AsRef<[u8]>, mode: Mode, hash: &str) -> Self {
+    Self {
+      mode: mode_bits(mode),
+      hash: hash.to_owned(),
+      path: path.as_ref().into(),
+      ..Default::default()
+    }
+  }
+
+  /// Creates an entry for a file in the working tree.
+  pub fn from_metadata(path: impl AsRef<[u8]>, hash: &str, metadata: &Metadata) -> Self {
+    let mut entry = IndexEntry::new(path, file_mode(metadata), hash);
+    entry.refresh(metadata);
+    entry
+  }
+
+  /// Records the stat information of the file in the working tree.
+  pub fn refresh(&mut self, metadata: &Metadata) {
+    self.ctime = (metadata.ctime() as u32, metadata.ctime_nsec() as u32);
+    self.mtime = (metadata.mtime() as u32, metadata.mtime_nsec() as u32);
+    self.dev = metadata.dev() as u32;
+    self.ino = metadata.ino() as u32;
+    self.uid = metadata.uid();
+    self.gid = metadata.gid();
+    self.size = metadata.size() as u32;
+  }
+
+  /// Checks whether the stat information still matches the file, in which
+  /// case the file is assumed to be unchanged.
+  pub fn stat_matches(&self, metadata: &Metadata) -> bool {
+    self.mtime == (metadata.mtime() as u32, metadata.mtime_nsec() as u32)
+      && self.ctime == (metadata.ctime() as u32, metadata.ctime_nsec() as u32)
+      && self.ino == metadata.ino() as u32
+      && self.size == metadata.size() as u32
+      && self.mode == mode_bits(file_mode(metadata))
+      // a smudged entry has a size of zero
+      && (self.size != 0 || self.hash == object::EMPTY_BLOB)
+  }
+
+  /// Whether the entry is marked assume-unchanged or skip-worktree, so that
+  /// the working tree copy should not be looked at.
+  pub fn assumed_unchanged(&self) -> bool {
+    self.flags & ASSUME_VALID != 0 || self.extended_flags & SKIP_WORKTREE != 0
+  }
+
+  /// The merge stage: 0 normally, or 1-3 for the base, ours and theirs
+  /// versions of a conflicted file.
+  pub fn stage(&self) -> u16 {
+    (self.flags >> 12) & 0x3
+  }
+
+  /// The entry at another merge stage, eg. at stage 2 to record our version
+  /// of a conflicted file.
+  pub fn with_stage(mut self, stage: u16) -> Self {
+    self.flags = (self.flags & !(0x3 << 12)) | ((stage & 0x3) << 12);
+    self
+  }
+
+  /// The mode of the entry as it would be written to a tree.
+  pub fn tree_mode(&self) -> Option<Mode> {
+    Mode::try_from(self.mode).ok()
+  }
+}
+
+/// Converts a tree mode into the bits stored in the index.
+pub fn mode_bits(mode: Mode) -> u32 {
+  mode.bits()
+}
+
+/// Works out the tree mode of a file from its metadata.
+pub fn file_mode(metadata: &Metadata) -> Mode {
+  if metadata.file_type().is_symlink() {
+    Mode::Symbolic
+  } else if metadata.mode() & 0o111 != 0 {
+    Mode::Executable
+  } else {
+    Mode::Normal
+  }
+}
+
+/// Hashes the contents of a file in the working tree as a blob, optionally
+/// writing it to the object database.
+///
+/// The contents are converted as they would be stored, given the blob the
+/// index has for the file. Conversions that will not survive a round trip
+/// are only warned about when writing.
+pub fn hash_file(
+  repo: &Repo,
+  path: impl AsRef<[u8]>,
+  indexed: Option<&str>,
+  write: bool,
+) -> Result<String, String> {
+  let path = path.as_ref();
+  let full_path = repo.work_tree_path(path);
+  let error = |e: std::io::Error| format!("{}: {}", path.as_bstr(), e);
+  let metadata = fs::symlink_metadata(&full_path).map_err(error)?;
+  let data = if metadata.file_type().is_symlink() {
+    let target = fs::read_link(&full_path).map_err(error)?;
+    target.into_os_string().into_vec()
+  } else {
+    let data = fs::read(&full_path).map_err(error)?;
+    convert::to_git(repo, path, data, indexed, write)?
+  };
+  object::write(repo, &Blob::new(&data), !write)
+}
+
+/// Checks whether the file in the working tree differs from its index entry.
+///
+/// Entries marked assume-unchanged or skip-worktree, or vouched for by a
+/// filesystem watcher, are never modified. A
+/// missing file counts as modified. When the stat information matches the
+/// file is assumed unchanged, otherwise its contents are hashed and compared.
+pub fn is_modified(repo: &Repo, entry: &IndexEntry) -> Result<bool, String> {
+  if entry.assumed_unchanged() || entry.fsmonitor_valid {
+    return Ok(false);
+  }
+  let metadata = match fs::symlink_metadata(repo.work_tree_path(&entry.path)) {
+    Ok(metadata) => metadata,
+    Err(_) => return Ok(true),
+  };
+  // submodules are not looked inside of: one is unchanged as long as its
+  // directory is there
+  if entry.mode == Mode::Gitlink.bits() {
+    return Ok(!metadata.is_dir());
+  }
+  if metadata.is_dir() {
+    return Ok(true);
+  }
+  if entry.stat_matches(&metadata) {
+    return Ok(false);
+  }
+  if entry.mode != mode_bits(file_mode(&metadata)) {
+    return Ok(true);
+  }
+  Ok(hash_file(repo, &entry.path, Some(&entry.hash), false)? != entry.hash)
+}
+
+/// The filesystem watcher hook named by `core.fsmonitor`. It may also be a
+/// boolean, asking for git's own watcher, which is not supported.
+fn fsmonitor_hook(repo: &Repo) -> Option<String> {
+  let hook = config(repo, "core", "fsmonitor")?;
+  match hook.to_ascii_lowercase().as_str() {
+    "" | "false" | "no" | "off" | "0" | "true" | "yes" | "on" | "1" => None,
+    _ => Some(hook),
+  }
+}
+
+/// Looks up a setting, ignoring the case of its name.
+fn config(repo: &Repo, section: &str, key: &str) -> Option<String> {
+  let section = repo.config.as_ref()?.section(Some(section))?;
+  section
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_owned())
+}
+
+/// Serializes entries and extensions as an index file, including the
+/// trailing checksum.
+fn serialize(version: u32, entries: &[IndexEntry], extensions: Vec<(&[u8], Vec<u8>)>) -> Vec<u8> {
+  // extended flags need at least version 3
+  let extended = entries.iter().any(|e| e.extended_flags != 0);
+  let version = if extended { version.max(3) } else { version };
+  let mut data = Vec::new();
+  data.extend_from_slice(b"DIRC");
+  data.extend_from_slice(&version.to_be_bytes());
+  data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+  for entry in entries {
+    let start = data.len();
+    for field in [
+      entry.ctime.0,
+      entry.ctime.1,
+      entry.mtime.0,
+      entry.mtime.1,
+      entry.dev,
+      entry.ino,
+      entry.mode,
+      entry.uid,
+      entry.gid,
+      entry.size,
+    ] {
+      data.extend_from_slice(&field.to_be_bytes());
+    }
+    data.extend_from_slice(&hex::decode(&entry.hash).unwrap_or_else(|_| vec![0; 20]));
+    let name_len = entry.path.len().min(0xfff) as u16;
+    let mut flags = (entry.flags & !(EXTENDED | 0xfff)) | name_len;
+    if entry.extended_flags != 0 {
+      flags |= EXTENDED;
+    }
+    data.extend_from_slice(&flags.to_be_bytes());
+    if entry.extended_flags != 0 {
+      data.extend_from_slice(&entry.extended_flags.to_be_bytes());
+    }
+    data.extend_from_slice(&entry.path);
+    let len = data.len() - start;
+    data.resize(start + ((len + 8) & !7), 0);
+  }
+
+  for (signature, extension) in extensions {
+    data.extend_from_slice(signature);
+    data.extend((extension.len() as u32).to_be_bytes());
+    data.extend(extension);
+  }
+  let checksum = hex::decode(crypto::sha_1(&data)).unwrap();
+  data.extend_from_slice(&checksum);
+  data
+}
+
+/// Collects the entries of a directory as a sparse index holds them, given
+/// its cached tree, the entries inside it and the length of its path.
+fn collapse(
+  cache_tree: &CacheTree,
+  entries: &[IndexEntry],
+  prefix_len: usize,
+  result: &mut Vec<IndexEntry>,
+) {
+  let sparse = entries
+    .iter()
+    .all(|e| e.stage() == 0 && e.extended_flags & SKIP_WORKTREE != 0);
+  if let (true, true, Some((count, hash))) = (prefix_len > 0, sparse, &cache_tree.tree) {
+    if *count == entries.len() {
+      let mut entry = IndexEntry::new(&entries[0].path[..prefix_len], Mode::Directory, hash);
+      entry.extended_flags = SKIP_WORKTREE;
+      result.push(entry);
+      return;
+    }
+  }
+  let mut i = 0;
+  while i < entries.len() {
+    let path = &entries[i].path[prefix_len..];
+    let slash = match path.find_byte(b'/') {
+      Some(slash) => slash,
+      None => {
+        result.push(entries[i].clone());
+        i += 1;
+        continue;
+      }
+    };
+    let prefix = &path[..slash + 1];
+    let len = entries[i..].partition_point(|e| e.path[prefix_len..].starts_with(prefix));
+    match cache_tree.find(&path[..slash]) {
+      Some(subtree) => collapse(
+        subtree,
+        &entries[i..i + len],
+        prefix_len + slash + 1,
+        result,
+      ),
+      None => result.extend_from_slice(&entries[i..i + len]),
+    }
+    i += len;
+  }
+}
+
+/// Reads a boolean setting, as git does.
+fn config_bool(repo: &Repo, section: &str, key: &str) -> Option<bool> {
+  match config(repo, section, key)?.to_ascii_lowercase().as_str() {
+    "true" | "yes" | "on" | "1" | "" => Some(true),
+    "false" | "no" | "off" | "0" => Some(false),
+    _ => None,
+  }
+}
+
+fn be32(data: &[u8], offset: usize) -> u32 {
+  u32::from_be_bytes([
+    data[offset],
+    data[offset + 1],
+    data[offset + 2],
+    data[offset + 3],
+  ])
+}
@@ -0,0 +1,166 @@
+use std::cmp::Ordering;
+use std::mem;
+
+use super::{ewah, IndexEntry};
+
+/// The link extension (`link`) of a split index, which holds only the
+/// entries that changed since a shared index was written, to save writing
+/// every entry of a large index each time.
+///
+/// It holds the hash of the shared index, which lives in
+/// `.git/sharedindex.<hash>`, then two bitmaps over its entries, which may
+/// be left out when empty: those deleted since, and those replaced by an
+/// entry of the split index.
+/// The replacements come first in the split index, with empty paths, and
+/// the entries added since follow.
+pub struct Link {
+  pub shared: String,
+  delete: Vec<bool>,
+  replace: Vec<bool>,
+}
+
+impl Link {
+  /// A link to a shared index that the split index adds nothing to.
+  pub fn new(shared: &str) -> Self {
+    Self {
+      shared: shared.to_string(),
+      delete: Vec::new(),
+      replace: Vec::new(),
+    }
+  }
+
+  /// Parses the data of a `link` extension.
+  pub fn parse(data: &[u8]) -> Result<Self, String> {
+    let error = || "index file is corrupt (bad link extension)".to_string();
+    let shared = hex::encode(data.get(0..20).ok_or_else(error)?);
+    let (delete, replace) = match data.len() {
+      20 => (Vec::new(), Vec::new()),
+      _ => {
+        let (delete, len) = ewah::read(&data[20..])?;
+        let (replace, rest) = ewah::read(&data[20 + len..])?;
+        if 20 + len + rest != data.len() {
+          return Err(error());
+        }
+        (delete, replace)
+      }
+    };
+    Ok(Self {
+      shared,
+      delete,
+      replace,
+    })
+  }
+
+  /// Serializes the extension. The bitmaps are always written, even empty,
+  /// as git does not cope without them.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut data = hex::decode(&self.shared).unwrap_or_else(|_| vec![0; 20]);
+    data.extend(ewah::write(&self.delete));
+    data.extend(ewah::write(&self.replace));
+    data
+  }
+
+  /// Applies the entries of a split index to those of its shared index,
+  /// returning the entries of the whole index.
+  pub fn merge(
+    &self,
+    shared: Vec<IndexEntry>,
+    split: Vec<IndexEntry>,
+  ) -> Result<Vec<IndexEntry>, String> {
+    let error = || "index file is corrupt (bad link extension)".to_string();
+    if self.delete.len() > shared.len() || self.replace.len() > shared.len() {
+      return Err(error());
+    }
+    let replacements = self.replace.iter().filter(|bit| **bit).count();
+    if split.len() < replacements || split[..replacements].iter().any(|e| !e.path.is_empty()) {
+      return Err(error());
+    }
+    let mut split = split.into_iter();
+
+    let mut entries = Vec::with_capacity(shared.len() + split.len());
+    for (i, entry) in shared.into_iter().enumerate() {
+      if self.delete.get(i) == Some(&true) {
+        continue;
+      }
+      match self.replace.get(i) == Some(&true) {
+        true => {
+          let mut replacement = split.next().ok_or_else(error)?;
+          replacement.path = entry.path;
+          entries.push(replacement);
+        }
+        false => entries.push(entry),
+      }
+    }
+
+    // added entries replace any the shared index has at the same path
+    entries.extend(split);
+    entries.sort_by(|a, b| key(a).cmp(&key(b)));
+    entries.dedup_by(|later, earlier| {
+      let same = key(later) == key(earlier);
+      if same {
+        mem::swap(later, earlier);
+      }
+      same
+    });
+    Ok(entries)
+  }
+
+  /// Works out what a split index on top of a shared index holds for a set
+  /// of entries: the link, and the replaced and added entries.
+  pub fn split(
+    shared_hash: &str,
+    shared: &[IndexEntry],
+    entries: &[IndexEntry],
+  ) -> (Self, Vec<IndexEntry>) {
+    let mut link = Link::new(shared_hash);
+    link.delete = vec![false; shared.len()];
+    link.replace = vec![false; shared.len()];
+    let mut replaced = Vec::new();
+    let mut added = Vec::new();
+
+    let (mut i, mut j) = (0, 0);
+    while i < shared.len() || j < entries.len() {
+      let order = match (shared.get(i), entries.get(j)) {
+        (Some(old), Some(new)) => key(old).cmp(&key(new)),
+        (Some(_), None) => Ordering::Less,
+        _ => Ordering::Greater,
+      };
+      match order {
+        Ordering::Less => {
+          link.delete[i] = true;
+          i += 1;
+        }
+        Ordering::Greater => {
+          added.push(entries[j].clone());
+          j += 1;
+        }
+        Ordering::Equal => {
+          if !same(&shared[i], &entries[j]) {
+            link.replace[i] = true;
+            replaced.push(IndexEntry {
+              path: Default::default(),
+              ..entries[j].clone()
+            });
+          }
+          i += 1;
+          j += 1;
+        }
+      }
+    }
+    replaced.extend(added);
+    (link, replaced)
+  }
+}
+
+fn key(entry: &IndexEntry) -> (&[u8], u16) {
+  (&entry.path, entry.stage())
+}
+
+/// Whether two entries for the same path are stored the same.
+fn same(a: &IndexEntry, b: &IndexEntry) -> bool {
+  (a.ctime, a.mtime, a.dev, a.ino, a.mode, a.uid, a.gid, a.size)
+    == (b.ctime, b.mtime, b.dev, b.ino, b.mode, b.uid, b.gid, b.size)
+    && a.hash == b.hash
+    && a.flags & !0xfff == b.flags & !0xfff
+    && a.extended_flags == b.extended_flags
+}
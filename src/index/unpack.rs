@@ -0,0 +1,119 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bstr::BString;
+
+use crate::object::mode::Mode;
+
+use super::{mode_bits, Index, IndexEntry};
+
+/// The files of a tree, keyed by full path (see `tree::flatten`).
+pub type Files = BTreeMap<BString, (Mode, String)>;
+
+/// Reads a tree into the index, replacing what was there.
+///
+/// Entries whose mode and hash do not change keep their stat data, so they
+/// are not needlessly re-hashed afterwards.
+pub fn one_way(index: &Index, tree: &Files) -> Index {
+  let mut result = Index::new();
+  for (path, (mode, hash)) in tree {
+    result.add(keep_or_new(index, path, *mode, hash));
+  }
+  result
+}
+
+/// Moves the index from tree `head` to tree `merge`, keeping local changes.
+///
+/// A path that is the same in both trees keeps whatever the index has for
+/// it. Otherwise the index must either still match `head` (in which case it
+/// takes the version in `merge`) or already match `merge`; anything else
+/// would throw away a staged change and fails.
+pub fn two_way(index: &Index, head: &Files, merge: &Files) -> Result<Index, String> {
+  let mut result = Index::new();
+  let staged = index.files();
+  for path in all_paths(&[&staged, head, merge]) {
+    let (current, before, after) = (staged.get(path), head.get(path), merge.get(path));
+    let take = if before == after || current == after {
+      current
+    } else if current == before {
+      after
+    } else {
+      return Err(format!(
+        "Entry '{}' would be overwritten by merge. Cannot merge.",
+        path
+      ));
+    };
+    if let Some((mode, hash)) = take {
+      result.add(keep_or_new(index, path, *mode, hash));
+    }
+  }
+  Ok(result)
+}
+
+/// Merges tree `theirs` into tree `ours` using `base` as the common ancestor.
+///
+/// Paths are resolved with the trivial rules: if both sides agree, or only
+/// one side changed the path since `base`, that version is taken. Everything
+/// else is left as a conflict, with the base, ours and theirs versions stored
+/// in stages 1, 2 and 3. A side deleting a path the other left alone is only
+/// resolved (as a deletion) when `aggressive` is set.
+///
+/// The index must match `ours` for every path the merge changes.
+pub fn three_way(
+  index: &Index,
+  base: &Files,
+  ours: &Files,
+  theirs: &Files,
+  aggressive: bool,
+) -> Result<Index, String> {
+  let staged = index.files();
+  let mut result = Index::new();
+  for path in all_paths(&[&staged, base, ours, theirs]) {
+    let (o, a, b) = (base.get(path), ours.get(path), theirs.get(path));
+    let resolved = if a == b {
+      Some(a)
+    } else if o == a && (b.is_some() || aggressive) {
+      Some(b)
+    } else if o == b && (a.is_some() || aggressive) {
+      Some(a)
+    } else {
+      None
+    };
+
+    // the index may only differ from ours where the merge leaves ours alone
+    let current = staged.get(path);
+    if !index.entries().is_empty() && current != a && resolved != Some(a) {
+      return Err(format!(
+        "Entry '{}' would be overwritten by merge. Cannot merge.",
+        path
+      ));
+    }
+
+    match resolved {
+      Some(Some((mode, hash))) if Some(&(*mode, hash.clone())) == current => {
+        result.add(keep_or_new(index, path, *mode, hash));
+      }
+      Some(Some((mode, hash))) => result.add(IndexEntry::new(path, *mode, hash)),
+      Some(None) => (),
+      None => {
+        for (stage, side) in [(1, o), (2, a), (3, b)] {
+          if let Some((mode, hash)) = side {
+            result.add(IndexEntry::new(path, *mode, hash).with_stage(stage));
+          }
+        }
+      }
+    }
+  }
+  Ok(result)
+}
+
+/// Reuses the index entry for a path if it already holds this mode and hash.
+fn keep_or_new(index: &Index, path: &[u8], mode: Mode, hash: &str) -> IndexEntry {
+  match index.get(path) {
+    Some(entry) if entry.hash == hash && entry.mode == mode_bits(mode) => entry.clone(),
+    _ => IndexEntry::new(path, mode, hash),
+  }
+}
+
+fn all_paths<'a>(maps: &[&'a Files]) -> BTreeSet<&'a BString> {
+  maps.iter().flat_map(|map| map.keys()).collect()
+}
@@ -0,0 +1,486 @@
+use std::fs::{self, Metadata};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use bstr::{BString, ByteSlice};
+
+use crate::ignore;
+use crate::index::ewah;
+use crate::object::{self, blob::Blob};
+use crate::repo::Repo;
+
+/// The flags `status` walks the working tree with, as git numbers them:
+/// untracked directories are shown as a single entry, and not at all when
+/// there is nothing to show in them. A cache made with other flags is no use.
+const DIR_FLAGS: u32 = 0x2 | 0x4;
+
+/// The file in each directory that ignore rules are read from.
+const EXCLUDE_PER_DIR: &str = ".gitignore";
+
+/// The untracked cache (the `UNTR` index extension), which saves `status`
+/// from listing the directories of the working tree that have not changed.
+///
+/// For each directory it looked into, the cache keeps the stat data of the
+/// directory, the hash of its `.gitignore` and the untracked files and
+/// directories that were found there. As long as the directory's stat data
+/// and `.gitignore` are the same, nothing has been added to it or removed,
+/// so what was found can be used again without reading the directory.
+/// Changes to the index invalidate the directories of the paths changed, and
+/// a change to `info/exclude` or `core.excludesFile` throws everything away.
+///
+/// The format is git's, so that either can use a cache the other wrote.
+pub struct UntrackedCache {
+  /// Where the cache was made, as `Location <work tree>, system <OS>`; a
+  /// cache copied somewhere else cannot be trusted.
+  ident: BString,
+  info_exclude: OidStat,
+  excludes_file: OidStat,
+  dir_flags: u32,
+  exclude_per_dir: BString,
+  root: Option<Dir>,
+
+  /// Whether a filesystem watcher has invalidated the directories that
+  /// changed, so that the valid ones can be trusted without a look. This
+  /// is only kept in memory.
+  pub fsmonitor: bool,
+}
+
+/// The stat data and hash of a file of ignore rules. A missing file has no
+/// hash.
+#[derive(Clone, Default, PartialEq)]
+struct OidStat {
+  stat: StatData,
+  hash: Option<String>,
+}
+
+/// What the untracked cache knows about a directory of the working tree.
+#[derive(Default)]
+pub struct Dir {
+  pub name: BString,
+
+  /// The untracked files in the directory that are not ignored, and the
+  /// untracked directories with something in them, with a trailing `/`.
+  pub untracked: Vec<BString>,
+
+  /// The subdirectories that were looked into, sorted by name.
+  pub dirs: Vec<Dir>,
+
+  /// Whether `untracked` is still what is in the directory, unless its stat
+  /// data says otherwise.
+  pub valid: bool,
+
+  /// Whether the directory is untracked, and was only looked into to see if
+  /// it has anything to show.
+  pub check_only: bool,
+  pub stat: StatData,
+
+  /// The hash of the directory's `.gitignore`, if it has one.
+  pub exclude: Option<String>,
+}
+
+/// The parts of a file's stat information that tell when it has changed, as
+/// stored in the index.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StatData {
+  pub ctime: (u32, u32),
+  pub mtime: (u32, u32),
+  pub dev: u32,
+  pub ino: u32,
+  pub uid: u32,
+  pub gid: u32,
+  pub size: u32,
+}
+
+/// The size of stat data on disk.
+const STAT_DATA_LEN: usize = 36;
+
+impl StatData {
+  pub fn from_metadata(metadata: &Metadata) -> Self {
+    Self {
+      ctime: (metadata.ctime() as u32, metadata.ctime_nsec() as u32),
+      mtime: (metadata.mtime() as u32, metadata.mtime_nsec() as u32),
+      dev: metadata.dev() as u32,
+      ino: metadata.ino() as u32,
+      uid: metadata.uid(),
+      gid: metadata.gid(),
+      size: metadata.size() as u32,
+    }
+  }
+
+  /// Whether the file still looks the same.
+  pub fn matches(&self, metadata: &Metadata) -> bool {
+    let other = Self::from_metadata(metadata);
+    self.mtime == other.mtime
+      && self.ctime == other.ctime
+      && self.ino == other.ino
+      && self.size == other.size
+  }
+
+  fn parse(data: &[u8]) -> Self {
+    let field = |i: usize| u32::from_be_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+    Self {
+      ctime: (field(0), field(1)),
+      mtime: (field(2), field(3)),
+      dev: field(4),
+      ino: field(5),
+      uid: field(6),
+      gid: field(7),
+      size: field(8),
+    }
+  }
+
+  fn to_bytes(self) -> Vec<u8> {
+    [
+      self.ctime.0,
+      self.ctime.1,
+      self.mtime.0,
+      self.mtime.1,
+      self.dev,
+      self.ino,
+      self.uid,
+      self.gid,
+      self.size,
+    ]
+    .iter()
+    .flat_map(|field| field.to_be_bytes())
+    .collect()
+  }
+}
+
+impl OidStat {
+  /// Reads the current state of a file.
+  fn of(repo: &Repo, path: Option<&Path>) -> Self {
+    let path = match path {
+      Some(path) => path,
+      None => return Self::default(),
+    };
+    Self {
+      stat: fs::metadata(path)
+        .map(|metadata| StatData::from_metadata(&metadata))
+        .unwrap_or_default(),
+      hash: fs::read(path).ok().map(|data| rules_hash(repo, &data)),
+    }
+  }
+}
+
+impl UntrackedCache {
+  /// Creates an empty cache for the working tree of a repository.
+  pub fn new(repo: &Repo) -> Self {
+    let mut ident = BString::from(ident(repo));
+    ident.push(0);
+    Self {
+      ident,
+      info_exclude: OidStat::of(repo, Some(&repo.git_dir.join("info").join("exclude"))),
+      excludes_file: OidStat::of(repo, ignore::excludes_file(repo).as_deref()),
+      dir_flags: DIR_FLAGS,
+      exclude_per_dir: EXCLUDE_PER_DIR.into(),
+      root: None,
+      fsmonitor: false,
+    }
+  }
+
+  /// Parses the data of an `UNTR` extension.
+  pub fn parse(data: &[u8]) -> Result<Self, String> {
+    let error = || "index file is corrupt (bad untracked cache)".to_string();
+    if data.len() < 2 || data.last() != Some(&0) {
+      return Err(error());
+    }
+    let mut offset = 0;
+    let ident_len = varint(data, &mut offset).ok_or_else(error)?;
+    let ident = data.get(offset..offset + ident_len).ok_or_else(error)?;
+    offset += ident_len;
+    let header = data
+      .get(offset..offset + 2 * STAT_DATA_LEN + 4 + 40)
+      .ok_or_else(error)?;
+    let hash = |at: usize| match &header[at..at + 20] {
+      [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0] => None,
+      hash => Some(hex::encode(hash)),
+    };
+    let mut cache = Self {
+      ident: ident.into(),
+      info_exclude: OidStat {
+        stat: StatData::parse(header),
+        hash: hash(2 * STAT_DATA_LEN + 4),
+      },
+      excludes_file: OidStat {
+        stat: StatData::parse(&header[STAT_DATA_LEN..]),
+        hash: hash(2 * STAT_DATA_LEN + 24),
+      },
+      dir_flags: u32::from_be_bytes(header[2 * STAT_DATA_LEN..][..4].try_into().unwrap()),
+      exclude_per_dir: BString::default(),
+      root: None,
+      fsmonitor: false,
+    };
+    offset += header.len();
+    let name_len = data[offset..].find_byte(0).ok_or_else(error)?;
+    cache.exclude_per_dir = data[offset..offset + name_len].into();
+    offset += name_len + 1;
+
+    let count = varint(data, &mut offset).ok_or_else(error)?;
+    if count == 0 {
+      return Ok(cache);
+    }
+    // the directories come depth first, each with the number of
+    // subdirectories that follow it
+    let mut dirs: Vec<(Dir, usize)> = Vec::with_capacity(count.min(data.len()));
+    while dirs.len() < count {
+      let untracked = varint(data, &mut offset).ok_or_else(error)?;
+      let children = varint(data, &mut offset).ok_or_else(error)?;
+      let mut strings = Vec::new();
+      for _ in 0..=untracked {
+        let len = data
+          .get(offset..)
+          .and_then(|rest| rest.find_byte(0))
+          .ok_or_else(error)?;
+        strings.push(BString::from(&data[offset..offset + len]));
+        offset += len + 1;
+      }
+      let name = strings.remove(0);
+      let dir = Dir {
+        name,
+        untracked: strings,
+        ..Default::default()
+      };
+      dirs.push((dir, children));
+    }
+
+    let mut bitmaps = Vec::new();
+    for _ in 0..3 {
+      let (bits, len) = ewah::read(data.get(offset..).ok_or_else(error)?)?;
+      bitmaps.push(bits);
+      offset += len;
+    }
+    let bit = |bitmap: usize, i: usize| bitmaps[bitmap].get(i).copied().unwrap_or(false);
+    for (i, (dir, _)) in dirs.iter_mut().enumerate() {
+      dir.check_only = bit(1, i);
+      if bit(0, i) {
+        let stat = data.get(offset..offset + STAT_DATA_LEN).ok_or_else(error)?;
+        dir.valid = true;
+        dir.stat = StatData::parse(stat);
+        offset += STAT_DATA_LEN;
+      }
+    }
+    for (i, (dir, _)) in dirs.iter_mut().enumerate() {
+      if bit(2, i) {
+        let hash = data.get(offset..offset + 20).ok_or_else(error)?;
+        dir.exclude = Some(hex::encode(hash));
+        offset += 20;
+      }
+    }
+
+    let mut dirs = dirs.into_iter();
+    cache.root = build(&mut dirs);
+    Ok(cache)
+  }
+
+  /// Serializes the cache as the data of an `UNTR` extension.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut data = Vec::new();
+    push_varint(&mut data, self.ident.len());
+    data.extend_from_slice(&self.ident);
+    data.extend(self.info_exclude.stat.to_bytes());
+    data.extend(self.excludes_file.stat.to_bytes());
+    data.extend(self.dir_flags.to_be_bytes());
+    for hash in [&self.info_exclude.hash, &self.excludes_file.hash] {
+      match hash {
+        Some(hash) => data.extend(hex::decode(hash).unwrap()),
+        None => data.extend([0; 20]),
+      }
+    }
+    data.extend_from_slice(&self.exclude_per_dir);
+    data.push(0);
+    let root = match &self.root {
+      Some(root) => root,
+      None => {
+        push_varint(&mut data, 0);
+        return data;
+      }
+    };
+
+    let mut dirs = Vec::new();
+    flatten(root, &mut dirs);
+    push_varint(&mut data, dirs.len());
+    for dir in &dirs {
+      let untracked: &[BString] = match dir.valid {
+        true => &dir.untracked,
+        false => &[],
+      };
+      push_varint(&mut data, untracked.len());
+      push_varint(&mut data, dir.dirs.len());
+      for name in std::iter::once(&dir.name).chain(untracked) {
+        data.extend_from_slice(name);
+        data.push(0);
+      }
+    }
+    let valid: Vec<bool> = dirs.iter().map(|dir| dir.valid).collect();
+    let check_only: Vec<bool> = dirs.iter().map(|dir| dir.valid && dir.check_only).collect();
+    let hashed: Vec<bool> = dirs.iter().map(|dir| dir.exclude.is_some()).collect();
+    data.extend(ewah::write(&valid));
+    data.extend(ewah::write(&check_only));
+    data.extend(ewah::write(&hashed));
+    for dir in dirs.iter().filter(|dir| dir.valid) {
+      data.extend(dir.stat.to_bytes());
+    }
+    for hash in dirs.iter().filter_map(|dir| dir.exclude.as_ref()) {
+      data.extend(hex::decode(hash).unwrap());
+    }
+    data.push(0);
+    data
+  }
+
+  /// Checks that the cache was made for this working tree with the same
+  /// repository-wide ignore rules, emptying it if not. Returns whether it
+  /// had to be emptied.
+  pub fn validate(&mut self, repo: &Repo) -> bool {
+    let fresh = Self::new(repo);
+    let ident = self.ident.split_str("\0").next().unwrap_or_default();
+    let same = ident == fresh.ident.split_str("\0").next().unwrap_or_default()
+      && self.dir_flags == fresh.dir_flags
+      && self.exclude_per_dir == fresh.exclude_per_dir
+      && self.info_exclude.hash == fresh.info_exclude.hash
+      && self.excludes_file.hash == fresh.excludes_file.hash;
+    if !same {
+      *self = fresh;
+    }
+    !same
+  }
+
+  /// The top directory of the working tree.
+  pub fn root(&mut self) -> &mut Dir {
+    self.root.get_or_insert_with(Dir::default)
+  }
+
+  /// Invalidates the directories leading to a path, when it is added to or
+  /// removed from the index. Whether each of them has anything untracked in
+  /// it may have changed.
+  pub fn invalidate(&mut self, path: &[u8]) {
+    let mut dir = match &mut self.root {
+      Some(root) => root,
+      None => return,
+    };
+    let mut names = path.split_str("/").peekable();
+    while let Some(name) = names.next() {
+      dir.valid = false;
+      if names.peek().is_none() {
+        break;
+      }
+      dir = match dir.dirs.iter_mut().find(|dir| dir.name == name) {
+        Some(child) => child,
+        None => return,
+      };
+    }
+  }
+}
+
+impl Dir {
+  /// Whether what the cache has for the directory can be used, given the
+  /// current metadata and `.gitignore` hash of the directory.
+  ///
+  /// A directory changed at or after `timestamp`, when the index was last
+  /// written, may have been changed again since it was read without that
+  /// showing in its stat data, so it is not trusted.
+  pub fn is_valid(
+    &self,
+    metadata: &Metadata,
+    exclude: &Option<String>,
+    timestamp: Option<(u32, u32)>,
+  ) -> bool {
+    self.valid
+      && self.stat.matches(metadata)
+      && self.exclude == *exclude
+      && timestamp.is_some_and(|timestamp| self.stat.mtime < timestamp)
+  }
+
+  /// An empty directory, which is never valid.
+  pub fn new(name: &[u8]) -> Self {
+    Self {
+      name: name.into(),
+      ..Default::default()
+    }
+  }
+
+  /// Takes the subdirectory with the given name out of the cache, leaving
+  /// an empty one with no name in its place.
+  ///
+  /// The subdirectories must be taken in order of their names, which keeps
+  /// the ones not taken yet sorted for searching.
+  pub fn take_dir(&mut self, name: &[u8]) -> Option<Dir> {
+    let i = self
+      .dirs
+      .binary_search_by(|dir| dir.name.as_slice().cmp(name))
+      .ok()?;
+    Some(std::mem::take(&mut self.dirs[i]))
+  }
+}
+
+/// The hash of the `.gitignore` in a directory of the working tree, if it
+/// has one.
+pub fn exclude_hash(repo: &Repo, dir: &[u8]) -> Option<String> {
+  let path = repo.work_tree_path(dir).join(EXCLUDE_PER_DIR);
+  fs::read(path).ok().map(|data| rules_hash(repo, &data))
+}
+
+/// Hashes a file of ignore rules the way git does, which is as a blob with a
+/// newline added to the end, unless it is empty.
+fn rules_hash(repo: &Repo, data: &[u8]) -> String {
+  let data = match data.is_empty() {
+    true => data.to_vec(),
+    false => [data, b"\n"].concat(),
+  };
+  object::write(repo, &Blob::new(&data), true).unwrap_or_default()
+}
+
+/// What the cache is tagged with, so that it is only used where it was
+/// made.
+fn ident(repo: &Repo) -> String {
+  let system = match std::env::consts::OS {
+    "linux" => "Linux",
+    "macos" => "Darwin",
+    "freebsd" => "FreeBSD",
+    os => os,
+  };
+  format!("Location {}, system {}", repo.work_tree.display(), system)
+}
+
+/// Rebuilds a directory and its subdirectories from the depth first list
+/// they were stored in.
+fn build(dirs: &mut impl Iterator<Item = (Dir, usize)>) -> Option<Dir> {
+  let (mut dir, children) = dirs.next()?;
+  for _ in 0..children {
+    dir.dirs.extend(build(dirs));
+  }
+  Some(dir)
+}
+
+/// Lists a directory and its subdirectories, depth first.
+fn flatten<'a>(dir: &'a Dir, dirs: &mut Vec<&'a Dir>) {
+  dirs.push(dir);
+  for child in &dir.dirs {
+    flatten(child, dirs);
+  }
+}
+
+/// Reads a number in git's variable width encoding, where each byte holds
+/// seven bits, most significant first, and the top bit says whether another
+/// follows.
+fn varint(data: &[u8], offset: &mut usize) -> Option<usize> {
+  let mut byte = *data.get(*offset)?;
+  *offset += 1;
+  let mut value = (byte & 0x7f) as usize;
+  while byte & 0x80 != 0 {
+    byte = *data.get(*offset)?;
+    *offset += 1;
+    value = (value + 1).checked_mul(0x80)? | (byte & 0x7f) as usize;
+  }
+  Some(value)
+}
+
+fn push_varint(data: &mut Vec<u8>, mut value: usize) {
+  let mut bytes = vec![(value & 0x7f) as u8];
+  while value >= 0x80 {
+    value = (value >> 7) - 1;
+    bytes.push(0x80 | (value & 0x7f) as u8);
+  }
+  bytes.reverse();
+  data.extend(bytes);
+}
@@ -0,0 +1,112 @@
+use std::fs;
+use std::path::PathBuf;
+
+use bstr::ByteSlice;
+
+use crate::crypto;
+use crate::diff::blob_data;
+use crate::object::mode::Mode;
+use crate::repo::Repo;
+
+/// A Git LFS pointer, which is stored in the repository in place of a large
+/// file whose contents are kept elsewhere.
+///
+/// It names the contents by their SHA-256 hash and gives their size, after
+/// the version of the format. Any other keys sort between the version and
+/// the hash. A fetched file is kept in `.git/lfs/objects`, under the first
+/// two pairs of digits of its hash.
+///
+/// ### Example
+/// ```text
+/// version https://git-lfs.github.com/spec/v1
+/// oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393
+/// size 12345
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct Pointer {
+  pub oid: String,
+  pub size: u64,
+}
+
+/// The version line every pointer starts with.
+const VERSION: &str = "https://git-lfs.github.com/spec/v1";
+
+/// Pointers are small: anything larger is a file of its own.
+const MAX_POINTER_SIZE: usize = 1024;
+
+impl Pointer {
+  /// Parses a pointer, or returns `None` if the data is not one.
+  pub fn parse(data: &[u8]) -> Option<Self> {
+    if data.len() > MAX_POINTER_SIZE || !data.ends_with(b"\n") {
+      return None;
+    }
+    let mut lines = data.lines().map(|line| line.split_once_str(" "));
+    match lines.next()? {
+      Some((b"version", version)) if version == VERSION.as_bytes() => (),
+      _ => return None,
+    }
+    let (mut oid, mut size) = (None, None);
+    for line in lines {
+      match line? {
+        (b"oid", value) => {
+          let hash = value.strip_prefix(b"sha256:")?;
+          if hash.len() != 64 || !hash.iter().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f')) {
+            return None;
+          }
+          oid = Some(hash.to_str().ok()?.to_string());
+        }
+        (b"size", value) => size = Some(value.to_str().ok()?.parse().ok()?),
+        (key, _)
+          if oid.is_none() && key.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'-') => {}
+        _ => return None,
+      }
+    }
+    Some(Self {
+      oid: oid?,
+      size: size?,
+    })
+  }
+
+  /// Where the contents are kept once fetched.
+  pub fn path(&self, repo: &Repo) -> PathBuf {
+    repo
+      .git_dir
+      .join("lfs/objects")
+      .join(&self.oid[0..2])
+      .join(&self.oid[2..4])
+      .join(&self.oid)
+  }
+
+  /// Reads the contents from `.git/lfs/objects`, if they have been fetched.
+  pub fn resolve(&self, repo: &Repo) -> Option<Vec<u8>> {
+    let data = fs::read(self.path(repo)).ok()?;
+    (data.len() as u64 == self.size).then_some(data)
+  }
+
+  /// Whether some contents are the ones pointed to.
+  pub fn matches(&self, data: &[u8]) -> bool {
+    data.len() as u64 == self.size && crypto::sha_256(data) == self.oid
+  }
+}
+
+/// Stands in for the clean command of an LFS filter that is not set up:
+/// contents that are the ones the index points to are taken as that
+/// pointer, so that a fetched file does not look changed. Anything else is
+/// stored as it is.
+pub fn clean(repo: &Repo, data: Vec<u8>, indexed: Option<&str>) -> Vec<u8> {
+  let stored =
+    indexed.and_then(|hash| blob_data(repo, Some(&(Mode::Normal, hash.to_string()))).ok());
+  match stored {
+    Some(stored) if Pointer::parse(&stored).is_some_and(|pointer| pointer.matches(&data)) => stored,
+    _ => data,
+  }
+}
+
+/// Stands in for the smudge command of an LFS filter that is not set up: a
+/// pointer is replaced by the contents it points to, if they have been
+/// fetched.
+pub fn smudge(repo: &Repo, data: Vec<u8>) -> Vec<u8> {
+  Pointer::parse(&data)
+    .and_then(|pointer| pointer.resolve(repo))
+    .unwrap_or(data)
+}
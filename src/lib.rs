@@ -0,0 +1,89 @@
+/// Applying patches to the index and the working tree, as `am` does.
+pub mod apply;
+/// The attributes `.gitattributes` files give paths.
+pub mod attr;
+/// The branches branches track.
+pub mod branch;
+/// Stopping long operations early.
+pub mod cancel;
+/// Coloring output the way `color.*` says to.
+pub mod color;
+/// Finding the objects a history needs but the repository does not have.
+pub mod connected;
+/// Converting files between the repository and the working tree: line
+/// endings, encodings and filters.
+pub mod convert;
+/// Usernames and passwords for remotes, and the helpers that keep them.
+pub mod credential;
+/// Hashing and compressing objects.
+pub mod crypto;
+/// Serving repositories over the `git://` protocol.
+pub mod daemon;
+/// Diffing sequences, blobs and trees, and rendering the diffs.
+pub mod diff;
+/// Editing messages in the user's editor.
+pub mod editor;
+/// The environment variables git is run with.
+pub mod env;
+/// Checking signatures with gpg.
+pub mod gpg;
+/// Authors, committers and their dates, and the mailmap that maps them.
+pub mod identity;
+/// The untracked files git pretends not to see.
+pub mod ignore;
+/// The index, which stages the next commit, and its extensions.
+pub mod index;
+/// Git LFS pointers.
+pub mod lfs;
+/// Patches as mail, as `format-patch` writes them and `am` reads them.
+pub mod mail;
+/// Tidying repositories up a little at a time, as `maintenance` does.
+pub mod maintenance;
+/// Merging files and trees.
+pub mod merge;
+/// Objects (blobs, trees, commits and tags), the database and packs they
+/// are kept in, and refs.
+pub mod object;
+/// Showing long output in the pager.
+pub mod pager;
+/// Spreading work over threads.
+pub mod parallel;
+/// Matching paths against pathspecs.
+pub mod pathspec;
+/// Reporting the progress of long operations.
+pub mod progress;
+/// Comparing two ranges of commits, as `range-diff` does.
+pub mod range_diff;
+/// Other repositories, and fetching from them.
+pub mod remote;
+/// Packing objects, with deltas between them.
+pub mod repack;
+/// Finding and opening repositories, and their config.
+pub mod repo;
+/// Recording how conflicts were resolved, and resolving them again.
+pub mod rerere;
+/// Resolving revisions, and walking the history from them.
+pub mod rev;
+/// Rewriting the commits of a history.
+pub mod rewrite;
+/// A read-only view of a repository in a web browser.
+#[cfg(feature = "serve")]
+pub mod serve;
+/// Sparse checkouts.
+pub mod sparse;
+/// Comparing HEAD, the index and the working tree, as `status` does.
+pub mod status;
+/// Looking inside submodules, for what has changed in them.
+pub mod submodule;
+/// Tracing what git-rs does, and how long it takes.
+pub mod trace;
+/// Trailers at the ends of commit messages.
+pub mod trailer;
+/// Talking to other repositories over the network.
+pub mod transport;
+/// Writing files out to the working tree, and finding what has changed in
+/// it.
+pub mod worktree;
+
+/// The refs of a repository, which are kept with its objects.
+pub use object::refs;
@@ -0,0 +1,389 @@
+use crate::identity::date::{civil_from_days, days_from_civil, tz_offset, DAYS, MONTHS};
+
+/// The separator line that starts every message written by `format-patch`.
+///
+/// The date is fixed so that tools can recognise patches generated by git.
+pub const MBOX_FROM_DATE: &str = "Mon Sep 17 00:00:00 2001";
+
+/// Encoded words (and so headers) are kept to 76 columns, folding as needed.
+const MAX_ENCODED_LENGTH: usize = 76;
+
+/// Plain headers are wrapped at word boundaries to 78 columns.
+const MAX_LENGTH: usize = 78;
+
+/// An email message, as found in an mbox file.
+#[derive(Debug, Default)]
+pub struct Mail {
+  /// The headers in order, unfolded and decoded.
+  pub headers: Vec<(String, String)>,
+
+  /// Everything after the blank line that ends the headers.
+  pub body: String,
+}
+
+impl Mail {
+  /// Parses a single message, decoding RFC 2047 encoded words in headers.
+  pub fn parse(text: &str) -> Self {
+    let mut mail = Mail::default();
+    let mut lines = text.split_inclusive('\n').peekable();
+    // the mbox separator is not a header
+    if let Some(line) = lines.peek() {
+      if is_from_line(line) {
+        lines.next();
+      }
+    }
+    let mut raw: Vec<(String, String)> = Vec::new();
+    for line in lines.by_ref() {
+      let line = line.trim_end_matches(['\n', '\r']);
+      if line.is_empty() {
+        break;
+      }
+      match (
+        line.starts_with([' ', '\t']),
+        raw.last_mut(),
+        line.split_once(':'),
+      ) {
+        (true, Some((_, value)), _) => {
+          value.push('\n');
+          value.push_str(line);
+        }
+        (false, _, Some((name, value))) if !name.contains(' ') => {
+          raw.push((name.to_string(), value.trim_start().to_string()));
+        }
+        // not a header after all, so the body starts here
+        _ => {
+          mail.body.push_str(line);
+          mail.body.push('\n');
+          break;
+        }
+      }
+    }
+    mail.headers = raw
+      .into_iter()
+      .map(|(name, value)| (name, decode_header(&value)))
+      .collect();
+    mail.body.extend(lines);
+    mail
+  }
+
+  /// The value of the first header with this name (case-insensitively).
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self
+      .headers
+      .iter()
+      .find(|(key, _)| key.eq_ignore_ascii_case(name))
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+/// Splits an mbox file into its messages.
+///
+/// Messages start with a `From ` line ending in a date. Input that does not
+/// start with one is taken to be a single message.
+pub fn split_mbox(text: &str) -> Vec<String> {
+  let mut messages: Vec<String> = Vec::new();
+  for line in text.split_inclusive('\n') {
+    match messages.last_mut() {
+      Some(message) if !is_from_line(line) => message.push_str(line),
+      _ => messages.push(line.to_string()),
+    }
+  }
+  messages.retain(|message| !message.trim().is_empty());
+  messages
+}
+
+/// Checks for an mbox separator, eg. `From 1234abcd Mon Sep 17 00:00:00 2001`.
+fn is_from_line(line: &str) -> bool {
+  line.starts_with("From ")
+    && line.contains(':')
+    && line
+      .trim_end()
+      .rsplit(' ')
+      .next()
+      .is_some_and(|year| year.len() == 4 && year.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Cleans up a mail subject for use as a commit title, dropping `Re:` and
+/// bracketed prefixes like `[PATCH 1/2]`.
+pub fn clean_subject(subject: &str) -> String {
+  let mut subject = subject.replace('\n', " ");
+  loop {
+    let trimmed = subject.trim_start();
+    if trimmed.len() >= 3 && trimmed[..3].eq_ignore_ascii_case("re:") {
+      subject = trimmed[3..].to_string();
+    } else if let (true, Some(end)) = (trimmed.starts_with('['), trimmed.find(']')) {
+      subject = trimmed[end + 1..].to_string();
+    } else {
+      return trimmed.trim_end().to_string();
+    }
+  }
+}
+
+/// Splits a commit message into its title (the first paragraph, joined into
+/// one line) and the rest of the message.
+pub fn split_message(message: &str) -> (String, String) {
+  let message = message.trim_start_matches('\n');
+  let (title, body) = match message.find("\n\n") {
+    Some(end) => (&message[..end], &message[end + 2..]),
+    None => (message, ""),
+  };
+  let title: Vec<&str> = title.lines().map(|line| line.trim()).collect();
+  (title.join(" "), body.trim_matches('\n').to_string())
+}
+
+/// Writes a header line, folding it as needed.
+///
+/// Text that is not plain ASCII is written as RFC 2047 encoded words, split
+/// over as many lines as it takes. `prefix` (eg. `Subject: [PATCH] `) is
+/// written as is.
+pub fn header(prefix: &str, text: &str) -> String {
+  if needs_encoding(text) {
+    format!("{}{}", prefix, encode(text, prefix.len(), false))
+  } else {
+    format!("{}{}", prefix, wrap(text, prefix.len()))
+  }
+}
+
+/// Writes the `From:` header for an author.
+///
+/// Names that are not plain ASCII are encoded and names with special
+/// characters are quoted.
+pub fn from_header(name: &str, email: &str) -> String {
+  let prefix = "From: ";
+  let (name, max_length) = if needs_encoding(name) {
+    (encode(name, prefix.len(), true), MAX_ENCODED_LENGTH)
+  } else if name.contains(|c| "()<>@,;:\\\".[]".contains(c)) {
+    let quoted = name.replace('\\', "\\\\").replace('"', "\\\"");
+    (wrap(&format!("\"{}\"", quoted), prefix.len()), MAX_LENGTH)
+  } else {
+    (wrap(name, prefix.len()), MAX_LENGTH)
+  };
+  let mut out = format!("{}{}", prefix, name);
+  let last_line = out.rsplit('\n').next().unwrap_or("").len();
+  if max_length < last_line + email.len() + 3 {
+    out.push('\n');
+  }
+  out.push_str(&format!(" <{}>", email));
+  out
+}
+
+/// Splits a `Name <email>` address into its parts, unquoting the name.
+pub fn parse_address(address: &str) -> (String, String) {
+  let address = address.replace('\n', " ");
+  let (name, email) = match (address.rfind('<'), address.rfind('>')) {
+    (Some(start), Some(end)) if start < end => {
+      (address[..start].trim(), address[start + 1..end].trim())
+    }
+    _ => ("", address.trim()),
+  };
+  let name = match name.strip_prefix('"').and_then(|n| n.strip_suffix('"')) {
+    Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+    None => name.to_string(),
+  };
+  let name = if name.is_empty() {
+    email.split('@').next().unwrap_or("").to_string()
+  } else {
+    name
+  };
+  (name, email.to_string())
+}
+
+/// Checks whether header text must be encoded: it is not ASCII, or looks like
+/// it holds an encoded word already.
+fn needs_encoding(text: &str) -> bool {
+  !text.is_ascii() || text.contains('\n') || text.contains("=?")
+}
+
+/// Encodes text as `=?UTF-8?q?...?=` words, starting `column` characters into
+/// the line. Characters are never split across words.
+fn encode(text: &str, column: usize, address: bool) -> String {
+  let mut out = String::from("=?UTF-8?q?");
+  let mut line_len = column + "=?UTF-8?q?".len();
+  for c in text.chars() {
+    let mut bytes = [0; 4];
+    let bytes = c.encode_utf8(&mut bytes).as_bytes();
+    let special = bytes.len() > 1 || is_special(bytes[0], address);
+    let encoded: String = match special {
+      true => bytes.iter().map(|b| format!("={:02X}", b)).collect(),
+      false => c.to_string(),
+    };
+    if line_len + encoded.len() + 2 > MAX_ENCODED_LENGTH {
+      out.push_str("?=\n =?UTF-8?q?");
+      line_len = " =?UTF-8?q?".len();
+    }
+    line_len += encoded.len();
+    out.push_str(&encoded);
+  }
+  out.push_str("?=");
+  out
+}
+
+/// Checks whether a character has to be escaped inside an encoded word. Names
+/// in addresses allow fewer characters to appear as themselves.
+fn is_special(byte: u8, address: bool) -> bool {
+  if !byte.is_ascii_graphic() || byte == b'=' || byte == b'?' || byte == b'_' {
+    return true;
+  }
+  address && !(byte.is_ascii_alphanumeric() || b"!*+-/".contains(&byte))
+}
+
+/// Wraps text at word boundaries so no line is longer than 78 columns, the
+/// first line starting `column` characters in and the rest indented by one.
+fn wrap(text: &str, column: usize) -> String {
+  let mut out = String::new();
+  let mut width = column;
+  for (i, word) in text.split(' ').enumerate() {
+    let len = word.chars().count();
+    let separator = usize::from(i > 0);
+    if width + separator + len > MAX_LENGTH {
+      out.push_str("\n ");
+      width = 1 + len;
+    } else {
+      if separator == 1 {
+        out.push(' ');
+      }
+      width += separator + len;
+    }
+    out.push_str(word);
+  }
+  out
+}
+
+/// Decodes the RFC 2047 encoded words in a header and unfolds it.
+///
+/// Whitespace between two encoded words is dropped, as the standard asks.
+pub fn decode_header(value: &str) -> String {
+  let value = value
+    .replace("\r\n", "\n")
+    .replace("\n ", " ")
+    .replace("\n\t", " ");
+  let mut out: Vec<u8> = Vec::new();
+  let mut rest = value.as_str();
+  let mut pending_space = String::new();
+  let mut after_word = false;
+  while !rest.is_empty() {
+    if let Some((decoded, len)) = decode_word(rest) {
+      if !after_word {
+        out.extend(pending_space.as_bytes());
+      }
+      pending_space.clear();
+      out.extend(decoded);
+      rest = &rest[len..];
+      after_word = true;
+      continue;
+    }
+    let c = rest.chars().next().unwrap();
+    if c.is_whitespace() {
+      pending_space.push(c);
+    } else {
+      out.extend(pending_space.as_bytes());
+      pending_space.clear();
+      let mut bytes = [0; 4];
+      out.extend(c.encode_utf8(&mut bytes).as_bytes());
+      after_word = false;
+    }
+    rest = &rest[c.len_utf8()..];
+  }
+  out.extend(pending_space.as_bytes());
+  String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Decodes one `=?charset?q|b?text?=` word at the start of the text, returning
+/// the bytes and how much of the text it took up.
+fn decode_word(text: &str) -> Option<(Vec<u8>, usize)> {
+  let inner = text.strip_prefix("=?")?;
+  let (_charset, inner) = inner.split_once('?')?;
+  let (encoding, inner) = inner.split_once('?')?;
+  let end = inner.find("?=")?;
+  let encoded = &inner[..end];
+  let len = text.len() - inner.len() + end + 2;
+  let decoded = match encoding {
+    "q" | "Q" => {
+      let mut out = Vec::new();
+      let bytes = encoded.as_bytes();
+      let mut i = 0;
+      while i < bytes.len() {
+        match bytes[i] {
+          b'_' => out.push(b' '),
+          b'=' if i + 2 < bytes.len() => {
+            out.push(u8::from_str_radix(&encoded[i + 1..i + 3], 16).ok()?);
+            i += 2;
+          }
+          byte => out.push(byte),
+        }
+        i += 1;
+      }
+      out
+    }
+    "b" | "B" => base64(encoded)?,
+    _ => return None,
+  };
+  Some((decoded, len))
+}
+
+/// Decodes standard base64, ignoring padding.
+fn base64(text: &str) -> Option<Vec<u8>> {
+  const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  let mut out = Vec::new();
+  let (mut buffer, mut bits) = (0u32, 0);
+  for byte in text.bytes().filter(|b| *b != b'=') {
+    let value = ALPHABET.iter().position(|c| *c == byte)? as u32;
+    buffer = (buffer << 6) | value;
+    bits += 6;
+    if bits >= 8 {
+      bits -= 8;
+      out.push((buffer >> bits) as u8);
+      buffer &= (1 << bits) - 1;
+    }
+  }
+  Some(out)
+}
+
+/// Formats a timestamp and timezone (eg. `-0700`) as an RFC 2822 date, eg.
+/// `Tue, 14 Nov 2023 15:13:20 -0700`.
+pub fn format_date(seconds: i64, timezone: &str) -> String {
+  let local = seconds + tz_offset(timezone) * 60;
+  let days = local.div_euclid(86400);
+  let time = local.rem_euclid(86400);
+  let (year, month, day) = civil_from_days(days);
+  format!(
+    "{}, {} {} {} {:02}:{:02}:{:02} {}",
+    DAYS[(days + 4).rem_euclid(7) as usize],
+    day,
+    MONTHS[month as usize - 1],
+    year,
+    time / 3600,
+    time / 60 % 60,
+    time % 60,
+    timezone
+  )
+}
+
+/// Parses an RFC 2822 date into a timestamp and timezone, eg.
+/// `1700000000 -0700`.
+pub fn parse_date(date: &str) -> Option<(i64, String)> {
+  let date = date.split_once(',').map_or(date, |(_, rest)| rest);
+  let parts: Vec<&str> = date.split_whitespace().collect();
+  let (day, month, year, time) = match parts.as_slice() {
+    [day, month, year, time, ..] => (day, month, year, time),
+    _ => return None,
+  };
+  let day: i64 = day.parse().ok()?;
+  let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as i64 + 1;
+  let year: i64 = year.parse().ok()?;
+  let hms: Vec<i64> = time
+    .split(':')
+    .map(|n| n.parse().ok())
+    .collect::<Option<_>>()?;
+  let (hours, minutes, secs) = match hms.as_slice() {
+    [h, m] => (*h, *m, 0),
+    [h, m, s] => (*h, *m, *s),
+    _ => return None,
+  };
+  let timezone = parts.get(4).copied().unwrap_or("+0000");
+  if timezone.len() != 5 || !timezone.starts_with(['+', '-']) {
+    return None;
+  }
+  let local = days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + secs;
+  Some((local - tz_offset(timezone) * 60, timezone.to_string()))
+}
@@ -1,48 +1,171 @@
-pub mod cli;
-mod crypto;
-mod object;
-pub mod repo;
+mod cli;
 
 use self::cli::{Arguments, Command};
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
+use git_rs_core::{pager, trace};
+use std::time::Instant;
 
 use crate::cli::add::cmd_add;
+use crate::cli::am::cmd_am;
+use crate::cli::branch::cmd_branch;
 use crate::cli::cat_file::cmd_cat_file;
+use crate::cli::check_mailmap::cmd_check_mailmap;
 use crate::cli::checkout::cmd_checkout;
+use crate::cli::cherry::cmd_cherry;
+use crate::cli::clean::cmd_clean;
 use crate::cli::commit::cmd_commit;
+use crate::cli::commit_tree::cmd_commit_tree;
+use crate::cli::completions::cmd_completions;
+use crate::cli::count_objects::cmd_count_objects;
+use crate::cli::daemon::cmd_daemon;
+use crate::cli::diff_files::cmd_diff_files;
+use crate::cli::diff_index::cmd_diff_index;
+use crate::cli::diff_tree::cmd_diff_tree;
+use crate::cli::fast_export::cmd_fast_export;
+use crate::cli::fetch::cmd_fetch;
+use crate::cli::for_each_ref::cmd_for_each_ref;
+use crate::cli::format_patch::cmd_format_patch;
+use crate::cli::fsck::cmd_fsck;
 use crate::cli::hash_object::cmd_hash_object;
 use crate::cli::init::cmd_init;
+use crate::cli::interpret_trailers::cmd_interpret_trailers;
 use crate::cli::log::cmd_log;
+use crate::cli::ls_files::cmd_ls_files;
+use crate::cli::maintenance::cmd_maintenance;
 use crate::cli::merge::cmd_merge;
+use crate::cli::merge_file::cmd_merge_file;
+use crate::cli::merge_tree::cmd_merge_tree;
+use crate::cli::mktag::cmd_mktag;
+use crate::cli::mktree::cmd_mktree;
+use crate::cli::mv::cmd_mv;
+use crate::cli::name_rev::cmd_name_rev;
+use crate::cli::pack_refs::cmd_pack_refs;
+use crate::cli::patch_id::cmd_patch_id;
+use crate::cli::prune_packed::cmd_prune_packed;
+use crate::cli::push::cmd_push;
+use crate::cli::range_diff::cmd_range_diff;
+use crate::cli::read_tree::cmd_read_tree;
 use crate::cli::rebase::cmd_rebase;
+use crate::cli::remote::cmd_remote;
+use crate::cli::repack::cmd_repack;
+use crate::cli::replace::cmd_replace;
+use crate::cli::rerere::cmd_rerere;
+use crate::cli::reset::cmd_reset;
+use crate::cli::restore::cmd_restore;
+use crate::cli::rev_list::cmd_rev_list;
 use crate::cli::rev_parse::cmd_rev_parse;
 use crate::cli::rm::cmd_rm;
+#[cfg(feature = "serve")]
+use crate::cli::serve::cmd_serve;
+use crate::cli::show::cmd_show;
 use crate::cli::show_ref::cmd_show_ref;
 use crate::cli::show_tree::cmd_show_tree;
+use crate::cli::sparse_checkout::cmd_sparse_checkout;
+use crate::cli::status::cmd_status;
+use crate::cli::switch::cmd_switch;
+use crate::cli::symbolic_ref::cmd_symbolic_ref;
 use crate::cli::tag::cmd_tag;
+use crate::cli::update_index::cmd_update_index;
+use crate::cli::update_ref::cmd_update_ref;
+use crate::cli::var::cmd_var;
+use crate::cli::write_tree::cmd_write_tree;
 
 fn main() {
+  let start = Instant::now();
+  // traced as git would say it, whatever this program is called
+  let argv: Vec<String> = ["git".to_string()]
+    .into_iter()
+    .chain(std::env::args().skip(1))
+    .collect();
+  trace::event::start(&argv);
+
   // multiplex the command line args
-  let args: Arguments = Arguments::parse();
+  let matches = Arguments::command().get_matches();
+  let args = Arguments::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+  pager::exit_on_broken_pipe();
+  if let Err(err) = args.apply() {
+    println!("fatal: {}", err);
+    trace::event::exit(0);
+    return;
+  }
+  let command = matches.subcommand_name().unwrap_or_default();
+  trace::event::cmd_name(command);
+  pager::setup(command, args.paginate());
+  trace::print(
+    trace::TRACE,
+    &format!("trace: built-in: {}", trace::quote(&argv)),
+  );
   let response: Result<(), String> = match &args.command {
-    Command::Add(_) => cmd_add(),
+    Command::Add(opts) => cmd_add(opts),
+    Command::Am(opts) => cmd_am(opts),
+    Command::Branch(opts) => cmd_branch(opts),
     Command::CatFile(opts) => cmd_cat_file(opts),
+    Command::CheckMailmap(opts) => cmd_check_mailmap(opts),
     Command::Checkout(opts) => cmd_checkout(opts),
-    Command::Commit(_) => cmd_commit(),
+    Command::Cherry(opts) => cmd_cherry(opts),
+    Command::Clean(opts) => cmd_clean(opts),
+    Command::Commit(opts) => cmd_commit(opts),
+    Command::CommitTree(opts) => cmd_commit_tree(opts),
+    Command::Completions(opts) => cmd_completions(opts),
+    Command::CountObjects(opts) => cmd_count_objects(opts),
+    Command::Daemon(opts) => cmd_daemon(opts),
+    Command::DiffFiles(opts) => cmd_diff_files(opts),
+    Command::DiffIndex(opts) => cmd_diff_index(opts),
+    Command::DiffTree(opts) => cmd_diff_tree(opts),
+    Command::FastExport(opts) => cmd_fast_export(opts),
+    Command::Fetch(opts) => cmd_fetch(opts),
+    Command::ForEachRef(opts) => cmd_for_each_ref(opts),
+    Command::FormatPatch(opts) => cmd_format_patch(opts),
+    Command::Fsck(opts) => cmd_fsck(opts),
     Command::HashObject(opts) => cmd_hash_object(opts),
     Command::Init(opts) => cmd_init(opts),
+    Command::InterpretTrailers(opts) => cmd_interpret_trailers(opts),
     Command::Log(opts) => cmd_log(opts),
+    Command::LsFiles(opts) => cmd_ls_files(opts),
     Command::LsTree(opts) => cmd_show_tree(opts),
-    Command::Merge(_) => cmd_merge(),
+    Command::Maintenance(opts) => cmd_maintenance(opts),
+    Command::Merge(opts) => cmd_merge(opts),
+    Command::MergeFile(opts) => cmd_merge_file(opts),
+    Command::MergeTree(opts) => cmd_merge_tree(opts),
+    Command::Mktag(_) => cmd_mktag(),
+    Command::Mktree(opts) => cmd_mktree(opts),
+    Command::Mv(opts) => cmd_mv(opts),
+    Command::NameRev(opts) => cmd_name_rev(opts),
+    Command::PackRefs(opts) => cmd_pack_refs(opts),
+    Command::PatchId(opts) => cmd_patch_id(opts),
+    Command::PrunePacked(opts) => cmd_prune_packed(opts),
+    Command::Push(opts) => cmd_push(opts),
+    Command::RangeDiff(opts) => cmd_range_diff(opts),
+    Command::ReadTree(opts) => cmd_read_tree(opts),
     Command::Rebase(_) => cmd_rebase(),
+    Command::Remote(opts) => cmd_remote(opts),
+    Command::Repack(opts) => cmd_repack(opts),
+    Command::Replace(opts) => cmd_replace(opts),
+    Command::Rerere(opts) => cmd_rerere(opts),
+    Command::Reset(opts) => cmd_reset(opts),
+    Command::Restore(opts) => cmd_restore(opts),
+    Command::RevList(opts) => cmd_rev_list(opts),
     Command::RevParse(_) => cmd_rev_parse(),
-    Command::Rm(_) => cmd_rm(),
-    Command::ShowRef(_) => cmd_show_ref(),
+    Command::Rm(opts) => cmd_rm(opts),
+    #[cfg(feature = "serve")]
+    Command::Serve(opts) => cmd_serve(opts),
+    Command::Show(opts) => cmd_show(opts),
+    Command::ShowRef(opts) => cmd_show_ref(opts),
+    Command::SparseCheckout(opts) => cmd_sparse_checkout(opts),
+    Command::Status(opts) => cmd_status(opts),
+    Command::Switch(opts) => cmd_switch(opts),
+    Command::SymbolicRef(opts) => cmd_symbolic_ref(opts),
     Command::Tag(opts) => cmd_tag(opts),
+    Command::UpdateIndex(opts) => cmd_update_index(opts),
+    Command::UpdateRef(opts) => cmd_update_ref(opts),
+    Command::Var(opts) => cmd_var(opts),
+    Command::WriteTree(opts) => cmd_write_tree(opts),
   };
 
   // handle the response type if it errored out
   if let Some(err) = response.err() {
     println!("fatal: {}", err);
   }
+  trace::command_performance(start, &argv);
+  trace::event::exit(0);
 }
@@ -0,0 +1,393 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::cancel::Cancel;
+use crate::identity::date;
+use crate::object::{self, commit::Commit, commit_graph, refs, serializable::Unbox};
+use crate::progress::Progress;
+use crate::remote::{self, fetch, Remote};
+use crate::repack::{self, Options};
+use crate::repo::Repo;
+
+/// One of the tasks that keep a repository quick to work with, each of
+/// which tidies up a little at a time, instead of all at once as `gc` does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Task {
+  /// Fetches from every remote into `refs/prefetch/`, so the objects are
+  /// there before the user fetches.
+  Prefetch,
+
+  /// Deletes the loose objects that are in a pack, and packs those that
+  /// aren't in a pack yet.
+  LooseObjects,
+
+  /// Packs the objects of the smaller packs into one.
+  IncrementalRepack,
+
+  /// Writes a commit-graph of the commits the refs reach.
+  CommitGraph,
+
+  /// Packs the refs.
+  PackRefs,
+}
+
+impl Task {
+  /// Every task, in the order they run in when none are asked for by name.
+  pub const ALL: [Task; 5] = [
+    Task::Prefetch,
+    Task::LooseObjects,
+    Task::IncrementalRepack,
+    Task::CommitGraph,
+    Task::PackRefs,
+  ];
+
+  /// The name of the task, as `--task` and the config know it.
+  pub fn name(self) -> &'static str {
+    match self {
+      Task::Prefetch => "prefetch",
+      Task::LooseObjects => "loose-objects",
+      Task::IncrementalRepack => "incremental-repack",
+      Task::CommitGraph => "commit-graph",
+      Task::PackRefs => "pack-refs",
+    }
+  }
+
+  /// The task with a name.
+  pub fn parse(name: &str) -> Option<Task> {
+    Task::ALL.into_iter().find(|task| task.name() == name)
+  }
+
+  /// Whether the task is run when none are asked for by name, as
+  /// `maintenance.<task>.enabled` says. Every task but prefetching, which
+  /// goes out to the network, is by default.
+  pub fn enabled(self, repo: &Repo) -> bool {
+    match config(repo, self, "enabled").as_deref().map(parse_bool) {
+      Some(Some(enabled)) => enabled,
+      _ => self != Task::Prefetch,
+    }
+  }
+
+  /// Whether there is enough for the task to do to be worth running it,
+  /// as `maintenance.<task>.auto` says, the way `gc.auto` does for `gc`:
+  /// when there are at least that many commits missing from the
+  /// commit-graph, loose objects, packs or loose refs. 0 means never, and a
+  /// negative number always. Prefetching is always due.
+  pub fn due(self, repo: &Repo) -> Result<bool, String> {
+    let default = match self {
+      Task::Prefetch => -1,
+      Task::LooseObjects | Task::CommitGraph | Task::PackRefs => 100,
+      Task::IncrementalRepack => 10,
+    };
+    let limit = match config(repo, self, "auto") {
+      Some(value) => value.trim().parse::<i64>().map_err(|_| {
+        format!(
+          "bad numeric config value '{}' for 'maintenance.{}.auto'",
+          value,
+          self.name()
+        )
+      })?,
+      None => default,
+    };
+    let limit = match limit {
+      0 => return Ok(false),
+      limit if limit < 0 => return Ok(true),
+      limit => limit as usize,
+    };
+    let count = match self {
+      Task::Prefetch => return Ok(true),
+      Task::LooseObjects => repo.objects.loose().len(),
+      Task::IncrementalRepack => repo.objects.packs().len(),
+      Task::CommitGraph => missing_from_graph(repo, limit)?,
+      Task::PackRefs => repo.refs.unpacked()?,
+    };
+    Ok(count >= limit)
+  }
+
+  /// Runs the task.
+  pub fn run(self, repo: &Repo, progress: &mut dyn Progress) -> Result<(), String> {
+    // to see the packs earlier tasks wrote
+    repo.objects.reprepare_packs();
+    match self {
+      Task::Prefetch => prefetch(repo, progress),
+      Task::LooseObjects => pack_loose_objects(repo, progress),
+      Task::IncrementalRepack => incremental_repack(repo, progress),
+      Task::CommitGraph => commit_graph::write(repo).map(|_| ()),
+      Task::PackRefs => refs::pack(repo, true, true),
+    }
+  }
+}
+
+/// The tasks to run when none are asked for by name (see
+/// [`Task::enabled`]), in the order they run in.
+pub fn enabled(repo: &Repo) -> Vec<Task> {
+  Task::ALL
+    .into_iter()
+    .filter(|task| task.enabled(repo))
+    .collect()
+}
+
+/// Whether `maintenance run --auto` runs at all, as `maintenance.auto`
+/// says. It does by default.
+pub fn auto_enabled(repo: &Repo) -> bool {
+  let value = repo
+    .config
+    .as_ref()
+    .and_then(|config| config.section(Some("maintenance")))
+    .and_then(|section| {
+      section
+        .iter()
+        .rev()
+        .find(|(key, _)| key.eq_ignore_ascii_case("auto"))
+    })
+    .and_then(|(_, value)| parse_bool(value));
+  value.unwrap_or(true)
+}
+
+/// The lock that keeps two runs of maintenance from running at once, which
+/// is let go of when it is dropped.
+pub struct Lock {
+  path: PathBuf,
+}
+
+impl Drop for Lock {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.path);
+  }
+}
+
+/// Takes the lock on maintaining a repository, `objects/maintenance.lock`.
+pub fn lock(repo: &Repo) -> Result<Lock, String> {
+  let path = repo.objects.dir().join("maintenance.lock");
+  refs::create_lock(&path).map_err(|_| {
+    format!(
+      "lock file '{}' exists, skipping maintenance",
+      path.display()
+    )
+  })?;
+  Ok(Lock { path })
+}
+
+/// How old a file left behind by a process that stopped halfway must be to
+/// be deleted, as `gc.pruneExpire` says: files last changed before the
+/// time returned, two weeks ago by default, or none with `never`.
+pub fn stale_before(repo: &Repo) -> Result<Option<i64>, String> {
+  let expire = repo
+    .config
+    .as_ref()
+    .and_then(|config| config.section(Some("gc")))
+    .and_then(|section| {
+      section
+        .iter()
+        .rev()
+        .find(|(key, _)| key.eq_ignore_ascii_case("pruneExpire"))
+    })
+    .map_or("2.weeks.ago", |(_, value)| value);
+  match expire {
+    "never" => Ok(None),
+    expire => date::approxidate(expire, date::now())
+      .map(|(time, _)| Some(time))
+      .ok_or_else(|| format!("invalid gc.pruneExpire: '{}'", expire)),
+  }
+}
+
+/// Deletes the files that processes which stopped halfway left behind, and
+/// that were last changed before `before`: temporary objects and packs
+/// (`tmp_*`) in the object database, and lock files (`*.lock`) in the git
+/// directory, which would otherwise stop anything else from changing what
+/// they lock. Returns where they were.
+pub fn remove_stale_files(repo: &Repo, before: i64) -> Vec<PathBuf> {
+  let objects = repo.objects.dir();
+  let mut candidates = Vec::new();
+  let mut dirs: Vec<PathBuf> = vec![
+    repo.git_dir.clone(),
+    objects.to_path_buf(),
+    objects.join("pack"),
+    objects.join("info"),
+    repo.git_dir.join("reftable"),
+  ];
+  dirs.extend((0..=255u8).map(|i| objects.join(format!("{:02x}", i))));
+  for dir in &dirs {
+    files(dir, &mut candidates);
+  }
+  walk(&repo.git_dir.join("refs"), &mut candidates);
+  candidates.retain(|path| {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = name.starts_with("tmp_") || name == "packed-refs.new";
+    temporary || name.ends_with(".lock")
+  });
+
+  let mut removed = Vec::new();
+  for path in candidates {
+    let modified = fs::metadata(&path)
+      .and_then(|metadata| metadata.modified())
+      .ok()
+      .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+    let stale = modified.is_some_and(|modified| (modified.as_secs() as i64) < before);
+    if stale && fs::remove_file(&path).is_ok() {
+      removed.push(path);
+    }
+  }
+  removed
+}
+
+/// Adds the files in a directory to `paths`.
+fn files(dir: &Path, paths: &mut Vec<PathBuf>) {
+  let entries = fs::read_dir(dir).into_iter().flatten().flatten();
+  paths.extend(
+    entries
+      .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+      .map(|entry| entry.path()),
+  );
+}
+
+/// Adds the files under a directory to `paths`.
+fn walk(dir: &Path, paths: &mut Vec<PathBuf>) {
+  files(dir, paths);
+  for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+    if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+      walk(&entry.path(), paths);
+    }
+  }
+}
+
+/// The last value of `maintenance.<task>.<key>`.
+fn config(repo: &Repo, task: Task, key: &str) -> Option<String> {
+  let section = format!("maintenance \"{}\"", task.name());
+  repo
+    .config
+    .as_ref()?
+    .section(Some(section))?
+    .iter()
+    .rev()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_owned())
+}
+
+/// Parses a boolean as git does.
+fn parse_bool(value: &str) -> Option<bool> {
+  match value.to_ascii_lowercase().as_str() {
+    "true" | "yes" | "on" | "1" | "" => Some(true),
+    "false" | "no" | "off" | "0" => Some(false),
+    _ => None,
+  }
+}
+
+/// Counts the commits the refs reach that aren't in the commit-graph, up to
+/// `limit`. What the commits in the graph reach is in it too, so the count
+/// stops at them.
+fn missing_from_graph(repo: &Repo, limit: usize) -> Result<usize, String> {
+  let graph = commit_graph::commits(repo);
+  let mut stack: Vec<String> = refs::collect(repo, None)
+    .into_values()
+    .filter_map(|hash| object::peel(repo, &hash, Some("commit")).ok())
+    .collect();
+  let mut seen = HashSet::new();
+  let mut count = 0;
+  while let Some(hash) = stack.pop() {
+    if graph.contains(&hash) || !seen.insert(hash.clone()) {
+      continue;
+    }
+    count += 1;
+    if count >= limit {
+      break;
+    }
+    let object = object::read(repo, &hash, Some("commit"))?;
+    stack.extend(object.unbox::<Commit>()?.parents().iter().cloned());
+  }
+  Ok(count)
+}
+
+/// Fetches from each remote in turn into `refs/prefetch/`, going on to the
+/// next when one fails.
+fn prefetch(repo: &Repo, progress: &mut dyn Progress) -> Result<(), String> {
+  let options = fetch::Options {
+    prefetch: true,
+    ..Default::default()
+  };
+  let mut failed = None;
+  for name in remote::names(repo) {
+    let result = Remote::get(repo, &name)
+      .and_then(|remote| fetch::fetch(repo, &remote, &[], &options, progress));
+    if let Err(e) = result {
+      failed.get_or_insert(format!("failed to prefetch '{}': {}", name, e));
+    }
+  }
+  failed.map_or(Ok(()), Err)
+}
+
+/// Deletes the loose objects that are in a pack as well, which the last
+/// run packed, and packs those that aren't in a pack yet, reachable or not.
+/// They are left where they are until the next run, so that nothing that
+/// is reading them meanwhile finds them gone.
+fn pack_loose_objects(repo: &Repo, progress: &mut dyn Progress) -> Result<(), String> {
+  repo.objects.prune_packed(false)?;
+  let objects: Vec<_> = repo
+    .objects
+    .loose()
+    .into_iter()
+    .filter(|hash| !repo.objects.is_packed(hash))
+    .map(|hash| (hash, Default::default()))
+    .collect();
+  if objects.is_empty() {
+    return Ok(());
+  }
+  repack::write(
+    repo,
+    &objects,
+    &Options::new(repo)?,
+    progress,
+    &Cancel::new(),
+  )?;
+  Ok(())
+}
+
+/// Packs the objects of every pack but the biggest into one new pack, and
+/// deletes the packs they came from. Packs that are to be kept (with a
+/// `.keep` file), or were fetched from a promisor remote, are left alone.
+fn incremental_repack(repo: &Repo, progress: &mut dyn Progress) -> Result<(), String> {
+  let all = repo.objects.packs();
+  let mut packs: Vec<_> = all
+    .iter()
+    .filter(|pack| {
+      let path = pack.path();
+      !repack::is_kept(path) && !path.with_extension("promisor").exists()
+    })
+    .collect();
+  packs.sort_by_key(|pack| pack.size());
+  packs.pop();
+  if packs.len() < 2 {
+    return Ok(());
+  }
+  let mut seen = HashSet::new();
+  let mut objects = Vec::new();
+  for pack in &packs {
+    for hash in pack.hashes()? {
+      if seen.insert(hash.clone()) {
+        objects.push((hash, Default::default()));
+      }
+    }
+  }
+  let name = repack::write(
+    repo,
+    &objects,
+    &Options::new(repo)?,
+    progress,
+    &Cancel::new(),
+  )?;
+  let new = repo
+    .objects
+    .dir()
+    .join("pack")
+    .join(format!("pack-{}.pack", name));
+  for pack in packs.iter().filter(|pack| pack.path() != new) {
+    // the index goes first, so the pack is never listed without its data
+    let index = pack.path().with_extension("idx");
+    fs::remove_file(&index).map_err(|e| format!("{}: {}", index.display(), e))?;
+    for ending in ["pack", "rev", "bitmap", "mtimes"] {
+      let _ = fs::remove_file(pack.path().with_extension(ending));
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,195 @@
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+use crate::attr::{Attributes, State};
+use crate::repo::Repo;
+use crate::trace;
+
+use super::{merge_file_with, Favor, FileMerge, MergeOptions};
+
+/// A merge driver, named by the `merge` attribute of a path.
+///
+/// `text` merges the lines of the files (the default, which `merge.default`
+/// can change), `binary` keeps our version and reports a conflict (as
+/// `-merge` does), and `union` keeps the lines of both sides of each
+/// conflict. Any other name is a driver set up in the `merge.<name>` section
+/// of the config, whose `driver` is a command that merges the files itself,
+/// in place of our version: `%O`, `%A` and `%B` in it are replaced by
+/// temporary files holding the base, ours and theirs, `%P` by the path, `%L`
+/// by the length of the conflict markers, and `%S`, `%X` and `%Y` by the
+/// names of the base, ours and theirs. It leaves the result in `%A`, and
+/// exits with a non-zero status if there are conflicts.
+///
+/// When merge bases are merged into a virtual one, a driver's `recursive`
+/// names the driver to use instead. A driver that isn't set up merges as
+/// `text`.
+///
+/// ### Example
+/// ```text
+/// [merge "lockfile"]
+///   name = regenerate the lockfile
+///   driver = ./scripts/merge-lock %O %A %B
+///   recursive = binary
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Driver {
+  Text,
+  Binary,
+  Union,
+  Custom {
+    command: String,
+    recursive: Option<String>,
+  },
+}
+
+impl Driver {
+  /// The driver for a path, from its `merge` attribute.
+  pub fn for_path(repo: &Repo, attributes: &mut Attributes, path: &[u8]) -> Driver {
+    match attributes.get(path, "merge") {
+      State::Set => Driver::Text,
+      State::Unset => Driver::Binary,
+      State::Value(name) => Driver::named(repo, &name),
+      State::Unspecified => match config(repo, "merge", "default") {
+        Some(name) => Driver::named(repo, &name),
+        None => Driver::Text,
+      },
+    }
+  }
+
+  /// The driver with a name, built in or set up in the config.
+  pub fn named(repo: &Repo, name: &str) -> Driver {
+    match name {
+      "text" => Driver::Text,
+      "binary" => Driver::Binary,
+      "union" => Driver::Union,
+      _ => {
+        let section = format!("merge \"{}\"", name);
+        match config(repo, &section, "driver") {
+          Some(command) => Driver::Custom {
+            command,
+            recursive: config(repo, &section, "recursive"),
+          },
+          None => Driver::Text,
+        }
+      }
+    }
+  }
+
+  /// Merges the base, ours and theirs versions of the file at `path`.
+  /// `depth` is above zero for the merges of merge bases, which use the
+  /// driver's `recursive` one.
+  pub fn merge(
+    &self,
+    repo: &Repo,
+    path: &str,
+    versions: [&[u8]; 3],
+    options: &MergeOptions,
+    depth: usize,
+  ) -> Result<FileMerge, String> {
+    let [base, ours, theirs] = versions;
+    match self {
+      Driver::Text => Ok(merge_file_with(base, ours, theirs, options)),
+      Driver::Binary => Ok(FileMerge {
+        data: ours.to_vec(),
+        conflicts: 1,
+      }),
+      Driver::Union => {
+        let options = MergeOptions {
+          favor: Some(Favor::Union),
+          ..*options
+        };
+        Ok(merge_file_with(base, ours, theirs, &options))
+      }
+      Driver::Custom {
+        recursive: Some(name),
+        ..
+      } if depth > 0 => Driver::named(repo, name).merge(repo, path, versions, options, 0),
+      Driver::Custom { command, .. } => run(repo, command, path, versions, options),
+    }
+  }
+}
+
+/// Runs the command of a custom driver on temporary files holding the three
+/// versions, and reads the result back from ours.
+fn run(
+  repo: &Repo,
+  command: &str,
+  path: &str,
+  versions: [&[u8]; 3],
+  options: &MergeOptions,
+) -> Result<FileMerge, String> {
+  let mut files = Vec::new();
+  for (name, data) in ["base", "ours", "theirs"].iter().zip(versions) {
+    let file = env::temp_dir().join(format!(".merge_file_{}_{}", process::id(), name));
+    if let Err(e) = fs::write(&file, data) {
+      files.iter().for_each(|file| drop(fs::remove_file(file)));
+      return Err(format!(
+        "unable to write temporary file {} ({})",
+        file.display(),
+        e
+      ));
+    }
+    files.push(file);
+  }
+
+  let quote = |text: &str| format!("'{}'", text.replace('\'', "'\\''"));
+  let mut cmd = String::new();
+  let mut chars = command.chars();
+  while let Some(c) = chars.next() {
+    if c != '%' {
+      cmd.push(c);
+      continue;
+    }
+    match chars.next() {
+      Some('O') => cmd.push_str(&quote(&files[0].display().to_string())),
+      Some('A') => cmd.push_str(&quote(&files[1].display().to_string())),
+      Some('B') => cmd.push_str(&quote(&files[2].display().to_string())),
+      Some('P') => cmd.push_str(&quote(path)),
+      Some('L') => cmd.push_str(&options.marker_size.to_string()),
+      Some('S') => cmd.push_str(&quote(options.base_label)),
+      Some('X') => cmd.push_str(&quote(options.ours_label)),
+      Some('Y') => cmd.push_str(&quote(options.theirs_label)),
+      Some(other) => {
+        cmd.push('%');
+        cmd.push(other);
+      }
+      None => cmd.push('%'),
+    }
+  }
+
+  let mut child = Command::new("sh");
+  child.arg("-c").arg(&cmd).current_dir(&repo.work_tree);
+  let traced = trace::command("merge_driver", &child);
+  let status = child.spawn().and_then(|mut child| {
+    let pid = child.id();
+    let status = child.wait()?;
+    traced.exit(pid, &status);
+    Ok(status)
+  });
+  let data = fs::read(&files[1]);
+  files.iter().for_each(|file| drop(fs::remove_file(file)));
+  let status = status.map_err(|e| format!("cannot run merge driver '{}' ({})", cmd, e))?;
+  let data = data.map_err(|e| format!("unable to read the result of merging {} ({})", path, e))?;
+  Ok(FileMerge {
+    data,
+    conflicts: usize::from(!status.success()),
+  })
+}
+
+/// The length of the conflict markers for a path, from its
+/// `conflict-marker-size` attribute, or 7.
+pub fn marker_size(attributes: &mut Attributes, path: &[u8]) -> usize {
+  match attributes.get(path, "conflict-marker-size") {
+    State::Value(size) => size.parse().ok().filter(|size| *size > 0).unwrap_or(7),
+    _ => 7,
+  }
+}
+
+fn config(repo: &Repo, section: &str, key: &str) -> Option<String> {
+  let section = repo.config.as_ref()?.section(Some(section))?;
+  section
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_owned())
+}
@@ -0,0 +1,329 @@
+pub mod driver;
+pub mod state;
+pub mod tree;
+
+use std::ops::Range;
+
+use crate::diff::{lines, myers, Op};
+
+/// The outcome of merging three versions of a file.
+pub struct FileMerge {
+  /// The merged contents, with conflict markers around unresolved regions.
+  pub data: Vec<u8>,
+
+  /// The number of conflicting regions.
+  pub conflicts: usize,
+}
+
+/// How to resolve conflicting regions without writing conflict markers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Favor {
+  /// Take our side.
+  Ours,
+
+  /// Take their side.
+  Theirs,
+
+  /// Take both sides, ours first.
+  Union,
+}
+
+/// Options for `merge_file_with`.
+pub struct MergeOptions<'a> {
+  /// The name written after the markers of our side of a conflict.
+  pub ours_label: &'a str,
+
+  /// The name written after the marker of the base of a conflict.
+  pub base_label: &'a str,
+
+  /// The name written after the markers of their side of a conflict.
+  pub theirs_label: &'a str,
+
+  /// Resolves conflicts by picking a side rather than writing markers.
+  pub favor: Option<Favor>,
+
+  /// The length of the conflict markers.
+  pub marker_size: usize,
+
+  /// Writes the base of each conflict between the two sides (`diff3`
+  /// style), leaving conflicts as they are rather than trimming them.
+  pub diff3: bool,
+
+  /// Also joins conflicts that are far apart, as long as the lines between
+  /// them have no letters or digits.
+  pub join_non_alnum: bool,
+}
+
+impl Default for MergeOptions<'_> {
+  fn default() -> Self {
+    MergeOptions {
+      ours_label: "",
+      base_label: "",
+      theirs_label: "",
+      favor: None,
+      marker_size: 7,
+      diff3: false,
+      join_non_alnum: false,
+    }
+  }
+}
+
+/// Merges two versions of a file that both descend from `base`, with the
+/// default options.
+pub fn merge_file(
+  base: &[u8],
+  ours: &[u8],
+  theirs: &[u8],
+  ours_label: &str,
+  theirs_label: &str,
+) -> FileMerge {
+  let options = MergeOptions {
+    ours_label,
+    theirs_label,
+    ..Default::default()
+  };
+  merge_file_with(base, ours, theirs, &options)
+}
+
+/// A stretch of the three versions of a file, as lines of each.
+struct Region {
+  base: Range<usize>,
+  ours: Range<usize>,
+  theirs: Range<usize>,
+  kind: Kind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+  /// Both sides have the same lines.
+  Same,
+
+  /// Our side has the lines the result should have (changed or not).
+  Ours,
+
+  /// Their side has the lines the result should have.
+  Theirs,
+
+  /// The sides changed the base in different ways.
+  Conflict,
+}
+
+/// Merges two versions of a file that both descend from `base`.
+///
+/// Both sides are diffed against the base, and the lines they leave alone
+/// split the files into regions. A region changed on only one side (or in
+/// the same way on both) takes that change. A region changed differently on
+/// the two sides is a conflict, written out as
+///
+/// ```text
+/// <<<<<<< ours
+/// our lines
+/// =======
+/// their lines
+/// >>>>>>> theirs
+/// ```
+///
+/// with lines both sides agree on at the start and end of the region moved
+/// outside of the markers, and conflicts no more than three lines apart
+/// joined into one.
+pub fn merge_file_with(
+  base: &[u8],
+  ours: &[u8],
+  theirs: &[u8],
+  options: &MergeOptions,
+) -> FileMerge {
+  let (o, a, b) = (lines(base), lines(ours), lines(theirs));
+  let mut regions = regions(&o, &a, &b);
+  if !options.diff3 {
+    regions = trim(regions, &a, &b);
+    regions = join(regions, &a, options.join_non_alnum);
+  }
+
+  let mut out: Vec<u8> = Vec::new();
+  let mut conflicts = 0;
+  let extend = |out: &mut Vec<u8>, lines: &[&[u8]]| {
+    lines.iter().for_each(|line| out.extend_from_slice(line));
+  };
+  for region in regions {
+    let (base, ours, theirs) = (
+      &o[region.base.clone()],
+      &a[region.ours.clone()],
+      &b[region.theirs.clone()],
+    );
+    match (region.kind, options.favor) {
+      (Kind::Same | Kind::Ours, _) | (Kind::Conflict, Some(Favor::Ours)) => extend(&mut out, ours),
+      (Kind::Theirs, _) | (Kind::Conflict, Some(Favor::Theirs)) => extend(&mut out, theirs),
+      (Kind::Conflict, Some(Favor::Union)) => {
+        side(&mut out, ours);
+        extend(&mut out, theirs);
+      }
+      (Kind::Conflict, None) => {
+        conflicts += 1;
+        marker(&mut out, b'<', options.marker_size, options.ours_label);
+        side(&mut out, ours);
+        if options.diff3 {
+          marker(&mut out, b'|', options.marker_size, options.base_label);
+          side(&mut out, base);
+        }
+        marker(&mut out, b'=', options.marker_size, "");
+        side(&mut out, theirs);
+        marker(&mut out, b'>', options.marker_size, options.theirs_label);
+      }
+    }
+  }
+  FileMerge {
+    data: out,
+    conflicts,
+  }
+}
+
+/// Splits the three versions into regions at the lines neither side changed.
+fn regions(o: &[&[u8]], a: &[&[u8]], b: &[&[u8]]) -> Vec<Region> {
+  let (in_a, in_b) = (matching(o, a), matching(o, b));
+  let mut regions = Vec::new();
+  let (mut i, mut j, mut k) = (0, 0, 0);
+  loop {
+    // the lines that are unchanged on both sides
+    let start = (i, j, k);
+    while i < o.len() && in_a[i] == Some(j) && in_b[i] == Some(k) {
+      (i, j, k) = (i + 1, j + 1, k + 1);
+    }
+    if i > start.0 {
+      regions.push(Region {
+        base: start.0..i,
+        ours: start.1..j,
+        theirs: start.2..k,
+        kind: Kind::Same,
+      });
+    }
+    // then find where the sides agree with the base again
+    let (next_i, next_j, next_k) =
+      match (i..o.len()).find(|x| in_a[*x].is_some() && in_b[*x].is_some()) {
+        Some(x) => (x, in_a[x].unwrap(), in_b[x].unwrap()),
+        None => (o.len(), a.len(), b.len()),
+      };
+    if (next_i, next_j, next_k) == (i, j, k) {
+      break;
+    }
+
+    let (base_part, ours_part, theirs_part) = (&o[i..next_i], &a[j..next_j], &b[k..next_k]);
+    let kind = if ours_part == theirs_part {
+      Kind::Same
+    } else if ours_part == base_part {
+      Kind::Theirs
+    } else if theirs_part == base_part {
+      Kind::Ours
+    } else {
+      Kind::Conflict
+    };
+    regions.push(Region {
+      base: i..next_i,
+      ours: j..next_j,
+      theirs: k..next_k,
+      kind,
+    });
+    (i, j, k) = (next_i, next_j, next_k);
+  }
+  regions
+}
+
+/// Maps each line of the base to the line of the other file it is kept as,
+/// if any.
+fn matching(base: &[&[u8]], other: &[&[u8]]) -> Vec<Option<usize>> {
+  let mut matched = vec![None; base.len()];
+  for edit in myers(base, other) {
+    if edit.op == Op::Equal {
+      matched[edit.old] = Some(edit.new);
+    }
+  }
+  matched
+}
+
+/// Moves the lines the two sides of each conflict have in common at either
+/// end out of the conflict.
+fn trim(regions: Vec<Region>, a: &[&[u8]], b: &[&[u8]]) -> Vec<Region> {
+  let mut trimmed = Vec::new();
+  for region in regions {
+    if region.kind != Kind::Conflict {
+      trimmed.push(region);
+      continue;
+    }
+    let (ours, theirs) = (&a[region.ours.clone()], &b[region.theirs.clone()]);
+    let prefix = ours.iter().zip(theirs).take_while(|(x, y)| x == y).count();
+    let suffix = ours[prefix..]
+      .iter()
+      .rev()
+      .zip(theirs[prefix..].iter().rev())
+      .take_while(|(x, y)| x == y)
+      .count();
+    let (j, k) = (region.ours.start, region.theirs.start);
+    let (end_j, end_k) = (region.ours.end, region.theirs.end);
+    let same = |ours: Range<usize>, theirs: Range<usize>| Region {
+      base: region.base.start..region.base.start,
+      ours,
+      theirs,
+      kind: Kind::Same,
+    };
+    if prefix > 0 {
+      trimmed.push(same(j..j + prefix, k..k + prefix));
+    }
+    trimmed.push(Region {
+      base: region.base.clone(),
+      ours: j + prefix..end_j - suffix,
+      theirs: k + prefix..end_k - suffix,
+      kind: Kind::Conflict,
+    });
+    if suffix > 0 {
+      trimmed.push(same(end_j - suffix..end_j, end_k - suffix..end_k));
+    }
+  }
+  trimmed
+}
+
+/// Joins conflicts that are separated only by a few lines both sides agree
+/// on (or, with `non_alnum`, by lines without letters or digits), so that
+/// they can be resolved as one.
+fn join(regions: Vec<Region>, a: &[&[u8]], non_alnum: bool) -> Vec<Region> {
+  let mut joined: Vec<Region> = Vec::new();
+  for region in regions {
+    let last_conflict = joined.iter().rposition(|r| r.kind == Kind::Conflict);
+    if let (Kind::Conflict, Some(last)) = (region.kind, last_conflict) {
+      // the lines in between must all be ones both sides agree on
+      let between = &joined[last + 1..];
+      let agreed = between.iter().all(|r| r.kind == Kind::Same);
+      let gap = &a[joined[last].ours.end..region.ours.start];
+      let alnum = gap
+        .iter()
+        .any(|line| line.iter().any(|c| c.is_ascii_alphanumeric()));
+      if agreed && (gap.len() <= 3 || (non_alnum && !alnum)) {
+        joined.truncate(last + 1);
+        let conflict = &mut joined[last];
+        conflict.base.end = conflict.base.end.max(region.base.end);
+        conflict.ours.end = region.ours.end;
+        conflict.theirs.end = region.theirs.end;
+        continue;
+      }
+    }
+    joined.push(region);
+  }
+  joined
+}
+
+/// Writes a conflict marker line, such as `<<<<<<< ours`.
+fn marker(out: &mut Vec<u8>, c: u8, size: usize, label: &str) {
+  out.extend(std::iter::repeat_n(c, size));
+  if !label.is_empty() {
+    out.push(b' ');
+    out.extend_from_slice(label.as_bytes());
+  }
+  out.push(b'\n');
+}
+
+/// Writes one side of a conflict, making sure the marker after it starts on a
+/// line of its own.
+fn side(out: &mut Vec<u8>, lines: &[&[u8]]) {
+  lines.iter().for_each(|line| out.extend_from_slice(line));
+  if !out.is_empty() && !out.ends_with(b"\n") {
+    out.push(b'\n');
+  }
+}
@@ -0,0 +1,70 @@
+use std::fs;
+use std::io::ErrorKind;
+
+use crate::{object::refs::pseudo, repo::Repo};
+
+/// What a merge that stopped for conflicts leaves in the git directory, so
+/// that the commit that concludes it (or the abort that gives up on it)
+/// knows what was being merged: the commits in `MERGE_HEAD`, the message
+/// for the merge commit in `MERGE_MSG`, and in `MERGE_MODE` whether it was
+/// asked for with `--no-ff`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeState {
+  /// The commits being merged into `HEAD`.
+  pub heads: Vec<String>,
+
+  /// The message to start the merge commit's message from.
+  pub message: String,
+
+  /// Whether a merge commit was asked for even where a fast-forward would
+  /// have done.
+  pub no_ff: bool,
+}
+
+impl MergeState {
+  /// Reads the state of the merge in progress, or `None` if there is none.
+  pub fn read(repo: &Repo) -> Result<Option<Self>, String> {
+    let heads = pseudo::read_merge_heads(repo)?;
+    if heads.is_empty() {
+      return Ok(None);
+    }
+    Ok(Some(Self {
+      heads,
+      message: read_file(repo, "MERGE_MSG")?.unwrap_or_default(),
+      no_ff: read_file(repo, "MERGE_MODE")?.is_some_and(|mode| mode.trim() == "no-ff"),
+    }))
+  }
+
+  /// Records the state of a merge that stopped, for whatever comes next.
+  pub fn write(&self, repo: &Repo) -> Result<(), String> {
+    pseudo::write_merge_heads(repo, &self.heads)?;
+    write_file(repo, "MERGE_MSG", &self.message)?;
+    write_file(repo, "MERGE_MODE", if self.no_ff { "no-ff" } else { "" })
+  }
+
+  /// Forgets the merge in progress, once it is committed or given up on.
+  pub fn clear(repo: &Repo) -> Result<(), String> {
+    pseudo::remove(repo, "MERGE_HEAD")?;
+    for name in ["MERGE_MSG", "MERGE_MODE"] {
+      match fs::remove_file(repo.git_dir.join(name)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+          return Err(format!("unable to remove {} ({})", name, e))
+        }
+        _ => (),
+      }
+    }
+    Ok(())
+  }
+}
+
+fn read_file(repo: &Repo, name: &str) -> Result<Option<String>, String> {
+  match fs::read_to_string(repo.git_dir.join(name)) {
+    Ok(data) => Ok(Some(data)),
+    Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(format!("unable to read {} ({})", name, e)),
+  }
+}
+
+fn write_file(repo: &Repo, name: &str, data: &str) -> Result<(), String> {
+  fs::write(repo.git_dir.join(name), data).map_err(|e| format!("unable to write {} ({})", name, e))
+}
@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bstr::BString;
+
+use crate::{
+  attr::Attributes,
+  diff::blob_data,
+  object::{self, blob::Blob, mode::Mode, peel, tree, EMPTY_TREE},
+  repo::Repo,
+  rev::walk::merge_bases,
+};
+
+use super::driver::{self, Driver};
+use super::MergeOptions;
+
+type Files = BTreeMap<BString, (Mode, String)>;
+
+/// The outcome of merging two trees.
+pub struct TreeMerge {
+  /// The merged tree. Files with conflicting content are in it with
+  /// conflict markers.
+  pub tree: String,
+
+  /// The versions of the conflicted files, as `(path, stage, mode, hash)`,
+  /// with the base in stage 1, ours in stage 2 and theirs in stage 3.
+  pub conflicts: Vec<(BString, u16, Mode, String)>,
+
+  /// What was done to each path that needed merging, in path order.
+  pub messages: Vec<String>,
+}
+
+/// The names the two sides of a merge go by in messages and conflict
+/// markers.
+pub struct Labels<'a> {
+  pub ours: &'a str,
+  pub base: &'a str,
+  pub theirs: &'a str,
+}
+
+/// Merges two commits into a tree, without touching the index or the
+/// working tree.
+///
+/// When the commits have more than one merge base, the merge bases are first
+/// merged with each other (recursively) into a virtual one.
+pub fn merge_commits(
+  repo: &Repo,
+  ours: &str,
+  theirs: &str,
+  labels: (&str, &str),
+  allow_unrelated: bool,
+) -> Result<TreeMerge, String> {
+  let bases = merge_bases(repo, ours, theirs)?;
+  if bases.is_empty() && !allow_unrelated {
+    return Err("refusing to merge unrelated histories".to_string());
+  }
+  let base = virtual_base(repo, &bases, 1)?;
+  let labels = Labels {
+    ours: labels.0,
+    base: match bases.as_slice() {
+      [base] => base,
+      _ => "merged common ancestors",
+    },
+    theirs: labels.1,
+  };
+  let (ours, theirs) = (
+    peel(repo, ours, Some("tree"))?,
+    peel(repo, theirs, Some("tree"))?,
+  );
+  merge_trees(repo, &base, &ours, &theirs, &labels, 0)
+}
+
+/// Merges merge bases into one tree to use as the base of a merge.
+fn virtual_base(repo: &Repo, bases: &[String], depth: usize) -> Result<String, String> {
+  let (first, rest) = match bases.split_first() {
+    Some(split) => split,
+    None => return Ok(EMPTY_TREE.to_owned()),
+  };
+  let mut merged = peel(repo, first, Some("tree"))?;
+  let labels = Labels {
+    ours: "Temporary merge branch 1",
+    base: "merged common ancestors",
+    theirs: "Temporary merge branch 2",
+  };
+  for next in rest {
+    let base = virtual_base(repo, &merge_bases(repo, first, next)?, depth + 1)?;
+    let theirs = peel(repo, next, Some("tree"))?;
+    merged = merge_trees(repo, &base, &merged, &theirs, &labels, depth)?.tree;
+  }
+  Ok(merged)
+}
+
+/// Merges tree `theirs` into tree `ours`, using `base` as the common
+/// ancestor.
+///
+/// A path changed on only one side takes that change. A file changed on both
+/// sides has its contents merged by its merge driver (see [`Driver`]), and is
+/// a conflict if the driver reports one, if one side deleted it, or if the other side put a directory where
+/// it was (in which case the file is moved aside to `path~side`). Renames are
+/// not detected, so they merge as a deletion and an addition.
+///
+/// Merges of merge bases (`depth` above zero) use longer conflict markers, so
+/// that the markers they leave can be told apart from those of the outer
+/// merge.
+pub fn merge_trees(
+  repo: &Repo,
+  base: &str,
+  ours: &str,
+  theirs: &str,
+  labels: &Labels,
+  depth: usize,
+) -> Result<TreeMerge, String> {
+  let (o, a, b) = (
+    tree::flatten(repo, base)?,
+    tree::flatten(repo, ours)?,
+    tree::flatten(repo, theirs)?,
+  );
+  let mut merged = Files::new();
+  let mut conflicts = Vec::new();
+  let mut messages: BTreeMap<BString, Vec<String>> = BTreeMap::new();
+  let mut attributes = Attributes::new(repo);
+  let paths: BTreeSet<&BString> = o.keys().chain(a.keys()).chain(b.keys()).collect();
+  for path in paths {
+    let (base, ours, theirs) = (o.get(path), a.get(path), b.get(path));
+    let resolved = if ours == theirs || base == theirs {
+      ours
+    } else if base == ours {
+      theirs
+    } else {
+      let mut message = |text: String| messages.entry(path.clone()).or_default().push(text);
+      let mut stage = |sides: [Option<&(Mode, String)>; 3]| {
+        for (number, side) in (1..).zip(sides) {
+          if let Some((mode, hash)) = side {
+            conflicts.push((path.clone(), number, *mode, hash.clone()));
+          }
+        }
+      };
+      match (ours, theirs) {
+        (Some(ours_entry), Some(theirs_entry)) => {
+          let mode = match base {
+            Some((mode, _)) if *mode == ours_entry.0 => theirs_entry.0,
+            _ => ours_entry.0,
+          };
+          if ours_entry.1 == theirs_entry.1 {
+            merged.insert(path.clone(), (mode, ours_entry.1.clone()));
+            continue;
+          }
+          message(format!("Auto-merging {}", path));
+          // symlinks and submodules can't be merged, so ours is kept
+          let (hash, clean) = match (ours_entry.0, theirs_entry.0) {
+            (Mode::Symbolic | Mode::Gitlink, _) | (_, Mode::Symbolic | Mode::Gitlink) => {
+              (ours_entry.1.clone(), false)
+            }
+            _ => {
+              let options = MergeOptions {
+                ours_label: labels.ours,
+                base_label: labels.base,
+                theirs_label: labels.theirs,
+                marker_size: driver::marker_size(&mut attributes, path) + 2 * depth,
+                ..Default::default()
+              };
+              let driver = Driver::for_path(repo, &mut attributes, path);
+              if driver == Driver::Binary {
+                message(format!(
+                  "warning: Cannot merge binary files: {} ({} vs. {})",
+                  path, labels.ours, labels.theirs
+                ));
+              }
+              let result = driver.merge(
+                repo,
+                &path.to_string(),
+                [
+                  &blob_data(repo, base)?,
+                  &blob_data(repo, ours)?,
+                  &blob_data(repo, theirs)?,
+                ],
+                &options,
+                depth,
+              )?;
+              let hash = object::write(repo, &Blob::new(&result.data), false)?;
+              (hash, result.conflicts == 0)
+            }
+          };
+          merged.insert(path.clone(), (mode, hash));
+          if !clean {
+            let kind = if base.is_some() { "content" } else { "add/add" };
+            message(format!("CONFLICT ({}): Merge conflict in {}", kind, path));
+            stage([base, ours, theirs]);
+          }
+        }
+        (ours_entry, theirs_entry) => {
+          let (deleted, modified) = match ours_entry {
+            None => (labels.ours, labels.theirs),
+            Some(_) => (labels.theirs, labels.ours),
+          };
+          message(format!(
+            "CONFLICT (modify/delete): {} deleted in {} and modified in {}.  Version {} of {} left in tree.",
+            path, deleted, modified, modified, path
+          ));
+          stage([base, ours_entry, theirs_entry]);
+          merged.insert(path.clone(), ours_entry.or(theirs_entry).unwrap().clone());
+        }
+      }
+      continue;
+    };
+    if let Some(entry) = resolved {
+      merged.insert(path.clone(), entry.clone());
+    }
+  }
+
+  // a file where the other side has a directory is moved out of the way
+  let in_the_way: Vec<BString> = merged
+    .keys()
+    .filter(|path| {
+      let dir = BString::from([path.as_slice(), b"/"].concat());
+      merged
+        .range(dir.clone()..)
+        .next()
+        .is_some_and(|(other, _)| other.starts_with(&dir))
+    })
+    .cloned()
+    .collect();
+  for path in in_the_way {
+    let entry = merged.remove(&path).unwrap();
+    let (side, stage) = match a.get(&path) == Some(&entry) {
+      true => (labels.ours, 2),
+      false => (labels.theirs, 3),
+    };
+    let moved = BString::from([path.as_slice(), b"~", side.as_bytes()].concat());
+    messages.entry(path.clone()).or_default().push(format!(
+      "CONFLICT (file/directory): directory in the way of {} from {}; moving it to {} instead.",
+      path, side, moved
+    ));
+    conflicts.retain(|(conflicted, _, _, _)| *conflicted != path);
+    conflicts.push((moved.clone(), stage, entry.0, entry.1.clone()));
+    merged.insert(moved, entry);
+  }
+  conflicts.sort_by(|x, y| (&x.0, x.1).cmp(&(&y.0, y.1)));
+
+  Ok(TreeMerge {
+    tree: tree::build(repo, &merged)?,
+    conflicts,
+    messages: messages.into_values().flatten().collect(),
+  })
+}
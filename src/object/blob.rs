@@ -1,19 +1,15 @@
-use crate::repo::Repo;
-
 use super::serializable::Serializable;
 
 pub struct Blob {
   data: Vec<u8>,
   format: String,
-  repo: Repo,
 }
 
 impl Blob {
-  pub fn new(repo: Repo, data: &[u8]) -> Self {
+  pub fn new(data: &[u8]) -> Self {
     Self {
       data: data.to_vec(),
       format: String::from("blob"),
-      repo,
     }
   }
 
@@ -34,8 +30,4 @@ impl Serializable for Blob {
   fn format(&self) -> &String {
     &self.format
   }
-
-  fn repo(&self) -> &Repo {
-    &self.repo
-  }
 }
@@ -1,25 +1,80 @@
 use std::ops::Deref;
 
-use crate::repo::Repo;
+use encoding_rs::{Encoding, UTF_8};
 
-use super::{mail_map::MailMap, serializable::Serializable};
+use super::{
+  mail_map::{self, MailMap},
+  serializable::Serializable,
+  Signer,
+};
+use crate::identity::Signature;
+use crate::repo::Repo;
 
 pub struct Commit {
   format: String,
   map: MailMap,
-  repo: Repo,
 }
 
 impl Commit {
-  pub fn new(repo: Repo, data: &[u8]) -> Self {
+  pub fn new(data: &[u8]) -> Self {
     let mut new_commit: Self = Self {
       format: String::from("commit"),
       map: MailMap::new(),
-      repo,
     };
     new_commit.map.parse_bytes(data, 0);
     new_commit
   }
+
+  /// The hash of the tree this commit snapshots.
+  pub fn tree(&self) -> &str {
+    self.map.get("tree").map(|t| t.as_str()).unwrap_or("")
+  }
+
+  /// The hashes of this commit's parents, in order (empty for a root commit).
+  pub fn parents(&self) -> &[String] {
+    self.map.get_all("parent")
+  }
+
+  /// The committer timestamp in seconds since the epoch.
+  pub fn commit_time(&self) -> i64 {
+    let committer = self.map.get("committer").or_else(|| self.map.get("author"));
+    committer
+      .and_then(|line| line.rsplit(' ').nth(1))
+      .and_then(|time| time.parse::<i64>().ok())
+      .unwrap_or(0)
+  }
+
+  /// The encoding of the message and identities, named by the `encoding`
+  /// header. Commits without one are in UTF-8.
+  pub fn encoding(&self) -> Option<&str> {
+    self.map.get("encoding").map(|e| e.as_str())
+  }
+
+  /// The commit converted to UTF-8 from its encoding, for showing it, with
+  /// the `encoding` header dropped.
+  ///
+  /// Returns `None` when there is nothing to convert: the commit is already
+  /// in UTF-8, or its encoding is not one that is known, in which case it is
+  /// best shown as it is. The commit itself keeps its bytes as they are.
+  pub fn to_utf8(&self) -> Option<Commit> {
+    let encoding = Encoding::for_label(self.encoding()?.trim().as_bytes())?;
+    if encoding == UTF_8 {
+      return None;
+    }
+    let (text, _, _) = encoding.decode(self.map.to_bytes());
+    let mut data = String::with_capacity(text.len());
+    let mut lines = text.split_inclusive('\n');
+    for line in lines.by_ref() {
+      if !line.starts_with("encoding ") {
+        data.push_str(line);
+      }
+      if line == "\n" {
+        break;
+      }
+    }
+    data.extend(lines);
+    Some(Commit::new(data.as_bytes()))
+  }
 }
 
 impl Deref for Commit {
@@ -42,8 +97,137 @@ impl Serializable for Commit {
   fn format(&self) -> &String {
     &self.format
   }
+}
+
+/// Puts together a new commit, checking it is well formed before it is
+/// written, so that the headers need not be formatted by hand.
+///
+/// The tree, author and committer must be given. Extra headers (like
+/// `encoding` or `mergetag`) follow the committer, in the order they are
+/// given, and a signature made by [`sign`](CommitBuilder::sign) goes after
+/// them all in a `gpgsig` header, as git puts it.
+///
+/// ## Example
+/// ```ignore
+/// let hash = CommitBuilder::new()
+///   .tree(&tree)
+///   .parent(&head)
+///   .author(Signature::current(&repo, Role::Author)?)
+///   .committer(Signature::current(&repo, Role::Committer)?)
+///   .message("Fix the frobnicator\n")
+///   .write(&repo)?;
+/// ```
+#[derive(Default)]
+pub struct CommitBuilder {
+  tree: Option<String>,
+  parents: Vec<String>,
+  author: Option<Signature>,
+  committer: Option<Signature>,
+  headers: Vec<(String, String)>,
+  message: String,
+  signer: Option<Signer>,
+}
+
+impl CommitBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The tree the commit records.
+  pub fn tree(mut self, hash: &str) -> Self {
+    self.tree = Some(hash.to_string());
+    self
+  }
+
+  /// Adds a parent, after any already given.
+  pub fn parent(mut self, hash: &str) -> Self {
+    self.parents.push(hash.to_string());
+    self
+  }
+
+  /// Adds some parents, after any already given.
+  pub fn parents<S: AsRef<str>>(mut self, hashes: &[S]) -> Self {
+    self
+      .parents
+      .extend(hashes.iter().map(|hash| hash.as_ref().to_string()));
+    self
+  }
+
+  /// The person who wrote the change.
+  pub fn author(mut self, author: Signature) -> Self {
+    self.author = Some(author);
+    self
+  }
+
+  /// The person who recorded it.
+  pub fn committer(mut self, committer: Signature) -> Self {
+    self.committer = Some(committer);
+    self
+  }
+
+  /// Adds a header of some other kind, after any already given.
+  pub fn header(mut self, key: &str, value: &str) -> Self {
+    self.headers.push((key.to_string(), value.to_string()));
+    self
+  }
+
+  /// The message, which is kept as it is given.
+  pub fn message(mut self, message: &str) -> Self {
+    self.message = message.to_string();
+    self
+  }
+
+  /// Signs the commit with a callback, which is handed the data of the
+  /// commit as it is without the signature and returns the signature (eg.
+  /// an armored PGP signature).
+  pub fn sign(mut self, signer: impl FnOnce(&[u8]) -> Result<String, String> + 'static) -> Self {
+    self.signer = Some(Box::new(signer));
+    self
+  }
+
+  /// Makes the commit, without writing it, after checking that every hash
+  /// is a full one and every header is well formed.
+  pub fn build(self) -> Result<Commit, String> {
+    let tree = self.tree.ok_or("a commit needs a tree")?;
+    let author = self.author.ok_or("a commit needs an author")?;
+    let committer = self.committer.ok_or("a commit needs a committer")?;
+    for hash in [&tree].into_iter().chain(&self.parents) {
+      if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("not a valid object name {}", hash));
+      }
+    }
+    let mut map = MailMap::new();
+    map.insert("tree", &tree);
+    for parent in &self.parents {
+      map.insert("parent", parent);
+    }
+    map.insert("author", &author.to_string());
+    map.insert("committer", &committer.to_string());
+    for (key, value) in &self.headers {
+      if ["tree", "parent", "author", "committer"].contains(&key.as_str()) {
+        return Err(format!("the '{}' header is not an extra header", key));
+      }
+      mail_map::check_header(key)?;
+      map.insert(key, value);
+    }
+    map.insert("", &self.message);
+    if let Some(signer) = self.signer {
+      let signature = signer(&mail_map::map_to_bytes(&map.map))?;
+      map.insert("gpgsig", signature.trim_end_matches('\n'));
+    }
+    Ok(Commit::new(&mail_map::map_to_bytes(&map.map)))
+  }
 
-  fn repo(&self) -> &Repo {
-    &self.repo
+  /// Writes the commit and returns its hash, after checking that its tree
+  /// and parents are in the repository.
+  pub fn write(self, repo: &Repo) -> Result<String, String> {
+    let objects = self.tree.iter().map(|tree| (tree, "tree"));
+    let parents = self.parents.iter().map(|parent| (parent, "commit"));
+    for (hash, kind) in objects.chain(parents) {
+      if super::read(repo, hash, Some(kind)).is_err() {
+        return Err(format!("{} is not a valid '{}' object", hash, kind));
+      }
+    }
+    super::write(repo, &self.build()?, false)
   }
 }
@@ -0,0 +1,218 @@
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{commit::Commit, refs, serializable::Unbox};
+use crate::repo::Repo;
+use crate::rev::walk::RevWalk;
+
+/// The parent position of a commit with no parent (or no second one).
+const NO_PARENT: u32 = 0x7000_0000;
+
+/// Set on the second parent of an octopus merge, which is then the position
+/// in the extra edges of the rest of its parents, and on the last of those.
+const EXTRA_EDGES: u32 = 0x8000_0000;
+
+/// The highest generation number there is room for; commits further from
+/// a root than that all get this one.
+const MAX_GENERATION: u32 = 0x3fff_ffff;
+
+/// Where the commit-graph of a repository is kept.
+pub fn path(repo: &Repo) -> PathBuf {
+  repo.objects.dir().join("info").join("commit-graph")
+}
+
+/// The commits in the repository's commit-graph, or none if it has none, or
+/// one that can't be read.
+pub fn commits(repo: &Repo) -> HashSet<String> {
+  let data = match fs::read(path(repo)) {
+    Ok(data) => data,
+    Err(_) => return HashSet::new(),
+  };
+  chunk(&data, b"OIDL")
+    .map(|ids| ids.chunks_exact(20).map(hex::encode).collect())
+    .unwrap_or_default()
+}
+
+/// A chunk of a commit-graph, by its id. The table of contents lists where
+/// each starts, and the one after it where it ends.
+fn chunk<'a>(data: &'a [u8], id: &[u8; 4]) -> Option<&'a [u8]> {
+  if data.len() < 8 || data[..4] != *b"CGPH" || data[4] != 1 || data[5] != 1 {
+    return None;
+  }
+  let count = data[6] as usize;
+  let entry = |i: usize| -> Option<(&[u8], usize)> {
+    let entry = data.get(8 + i * 12..8 + (i + 1) * 12)?;
+    let offset = u64::from_be_bytes(entry[4..].try_into().ok()?);
+    Some((&entry[..4], offset as usize))
+  };
+  for i in 0..count {
+    let (chunk, start) = entry(i)?;
+    if chunk == id {
+      let (_, end) = entry(i + 1)?;
+      return data.get(start..end);
+    }
+  }
+  None
+}
+
+/// Writes a commit-graph of every commit the refs and `HEAD` reach, in
+/// place of the one there was, and returns how many commits are in it.
+///
+/// The graph has each commit's tree, parents, commit date and generation
+/// number (how far it is from the furthest root), so a walk can find them
+/// without reading the commit. Octopus merges list the parents after their
+/// first in the extra edges.
+pub fn write(repo: &Repo) -> Result<usize, String> {
+  let mut walk = RevWalk::new(repo);
+  let mut tips: Vec<String> = refs::collect(repo, None).into_values().collect();
+  tips.extend(refs::resolve(repo, "HEAD".as_ref()));
+  for tip in &tips {
+    // refs to trees and blobs have no commits to put in the graph
+    if let Ok(commit) = super::peel(repo, tip, Some("commit")) {
+      walk.push(&commit);
+    }
+  }
+  let mut hashes = walk.run()?;
+  hashes.sort();
+  hashes.dedup();
+  let positions: HashMap<&str, u32> = hashes
+    .iter()
+    .enumerate()
+    .map(|(i, hash)| (hash.as_str(), i as u32))
+    .collect();
+
+  let mut commits = Vec::with_capacity(hashes.len());
+  for hash in &hashes {
+    let object = super::read(repo, hash, Some("commit"))?;
+    let commit = object.unbox::<Commit>()?;
+    let mut parents = Vec::new();
+    for parent in commit.parents() {
+      match positions.get(parent.as_str()) {
+        Some(&position) => parents.push(position),
+        None => {
+          return Err(format!(
+            "commit-graph: missing parent {} of {}",
+            parent, hash
+          ))
+        }
+      }
+    }
+    commits.push((
+      commit.tree().to_string(),
+      parents,
+      commit.commit_time().max(0) as u64,
+    ));
+  }
+  let generations = generations(&commits);
+
+  let ids = hashes
+    .iter()
+    .map(hex::decode)
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| e.to_string())?;
+  let mut fan_out = Vec::with_capacity(256 * 4);
+  for first in 0..=255u8 {
+    let count = ids.partition_point(|id| id[0] <= first) as u32;
+    fan_out.extend(count.to_be_bytes());
+  }
+  let mut data = Vec::with_capacity(hashes.len() * 36);
+  let mut edges: Vec<u8> = Vec::new();
+  for (i, (tree, parents, time)) in commits.iter().enumerate() {
+    data.extend(hex::decode(tree).map_err(|e| e.to_string())?);
+    let first = parents.first().copied().unwrap_or(NO_PARENT);
+    let second = match parents.len() {
+      0 | 1 => NO_PARENT,
+      2 => parents[1],
+      _ => {
+        let start = (edges.len() / 4) as u32;
+        for (n, parent) in parents[1..].iter().enumerate() {
+          let last = n == parents.len() - 2;
+          let edge = if last { parent | EXTRA_EDGES } else { *parent };
+          edges.extend(edge.to_be_bytes());
+        }
+        start | EXTRA_EDGES
+      }
+    };
+    data.extend(first.to_be_bytes());
+    data.extend(second.to_be_bytes());
+    let stamp = (generations[i] as u64) << 34 | (time & 0x3_ffff_ffff);
+    data.extend(stamp.to_be_bytes());
+  }
+
+  let ids = ids.concat();
+  let mut chunks: Vec<(&[u8; 4], &[u8])> =
+    vec![(b"OIDF", &fan_out), (b"OIDL", &ids), (b"CDAT", &data)];
+  if !edges.is_empty() {
+    chunks.push((b"EDGE", &edges));
+  }
+  let mut graph = b"CGPH\x01\x01".to_vec();
+  graph.push(chunks.len() as u8);
+  graph.push(0);
+  let mut offset = (8 + (chunks.len() + 1) * 12) as u64;
+  for (id, chunk) in &chunks {
+    graph.extend(*id);
+    graph.extend(offset.to_be_bytes());
+    offset += chunk.len() as u64;
+  }
+  graph.extend([0; 4]);
+  graph.extend(offset.to_be_bytes());
+  for (_, chunk) in &chunks {
+    graph.extend(*chunk);
+  }
+  let checksum = Sha1::digest(&graph);
+  graph.extend(checksum);
+
+  replace(&path(repo), &graph)?;
+  Ok(hashes.len())
+}
+
+/// The generation number of each commit: 1 for a root, and one more than
+/// the highest of its parents' for the others.
+fn generations(commits: &[(String, Vec<u32>, u64)]) -> Vec<u32> {
+  let mut generations = vec![0; commits.len()];
+  for start in 0..commits.len() {
+    let mut stack = vec![start];
+    while let Some(&i) = stack.last() {
+      if generations[i] != 0 {
+        stack.pop();
+        continue;
+      }
+      let parents = &commits[i].1;
+      let pending: Vec<usize> = parents
+        .iter()
+        .map(|&parent| parent as usize)
+        .filter(|&parent| generations[parent] == 0)
+        .collect();
+      if pending.is_empty() {
+        let highest = parents
+          .iter()
+          .map(|&parent| generations[parent as usize])
+          .max()
+          .unwrap_or(0);
+        generations[i] = (highest + 1).min(MAX_GENERATION);
+        stack.pop();
+      } else {
+        stack.extend(pending);
+      }
+    }
+  }
+  generations
+}
+
+/// Writes a file through a lock file next to it, so that no one reads it
+/// half written, and two writers don't write it at once.
+fn replace(path: &Path, data: &[u8]) -> Result<(), String> {
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+  }
+  let lock = path.with_extension("lock");
+  refs::create_lock(&lock)?;
+  fs::write(&lock, data)
+    .and_then(|()| fs::rename(&lock, path))
+    .map_err(|e| {
+      let _ = fs::remove_file(&lock);
+      format!("unable to write {} ({})", path.display(), e)
+    })
+}
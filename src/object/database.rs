@@ -0,0 +1,582 @@
+use super::pack::{parse_header, Pack};
+use crate::crypto;
+use crate::trace::{self, event};
+use flate2::read::ZlibDecoder;
+use std::{
+  collections::{BTreeMap, HashMap},
+  env,
+  fs::{self, File},
+  io::Read,
+  os::unix::fs::MetadataExt,
+  path::{Path, PathBuf},
+  process,
+  sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, Mutex, OnceLock, RwLock,
+  },
+};
+
+/// How many bytes of objects are kept in memory when `core.objectCacheLimit`
+/// is not set.
+pub const DEFAULT_CACHE_LIMIT: usize = 32 * 1024 * 1024;
+
+/// Names the temporary files objects are written to, so that threads of the
+/// same process never share one.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// How deep alternates of alternates are followed, which also stops a cycle
+/// of them.
+const MAX_ALTERNATE_DEPTH: usize = 5;
+
+/// The object database of a repository, ie. the loose objects under
+/// `.git/objects` and the packs under `.git/objects/pack`.
+///
+/// Objects that are not found there are looked for in the alternate
+/// databases named by `GIT_ALTERNATE_OBJECT_DIRECTORIES` and then by
+/// `info/alternates`, one per line (relative to the database), whose own
+/// alternates are followed in turn. Alternates are only ever read from.
+///
+/// A database is shared by every clone of a [`Repo`](crate::repo::Repo) and
+/// can be used from several threads at once. Objects are written to a
+/// temporary file and renamed into place, so a reader never sees half of an
+/// object, and two writers of the same object simply race to put the same
+/// bytes in the same place.
+///
+/// Trees, commits and tags that have been read are kept in memory, up to a
+/// limit in bytes, so that walks which keep coming back to the same subtrees
+/// only decompress them once. The least recently used objects are dropped
+/// first. Blobs are not kept, as they are big and rarely read twice.
+pub struct Database {
+  dir: PathBuf,
+
+  /// The packs, opened the first time an object is not found loose and
+  /// looked at again when an object is not found at all.
+  packs: RwLock<Option<Arc<[Arc<Pack>]>>>,
+
+  /// The alternate databases, opened the first time an object is not found
+  /// here at all.
+  alternates: OnceLock<Vec<Database>>,
+
+  /// How many alternates away from the repository's own database this is.
+  depth: usize,
+  cache: Mutex<Cache>,
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+/// How well the object cache of a [`Database`] is doing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+  /// Reads that were answered from memory.
+  pub hits: u64,
+
+  /// Reads of objects that could be cached but had to go to disk.
+  pub misses: u64,
+
+  /// The bytes of objects in memory.
+  pub size: usize,
+}
+
+/// What a [`Database`] has on disk, for deciding when it is worth repacking.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiskStats {
+  /// The loose objects.
+  pub loose: usize,
+
+  /// The bytes of disk the loose objects take up.
+  pub loose_size: u64,
+
+  /// The loose objects that are in a pack as well, so could be deleted.
+  pub prune_packable: usize,
+
+  /// The packs, and the objects in them.
+  pub packs: usize,
+  pub in_pack: usize,
+
+  /// The bytes the packs and their indexes take up.
+  pub pack_size: u64,
+
+  /// Files that have no business being in the database, such as objects
+  /// left half written or packs without an index, each with why it is
+  /// garbage.
+  pub garbage: Vec<(PathBuf, &'static str)>,
+
+  /// The bytes the garbage takes up.
+  pub garbage_size: u64,
+}
+
+/// The files that belong to a pack, as the endings of their names.
+const PACK_FILES: [&str; 7] = [
+  ".pack",
+  ".idx",
+  ".keep",
+  ".bitmap",
+  ".promisor",
+  ".mtimes",
+  ".rev",
+];
+
+impl Database {
+  /// The database in `objects_dir`, usually `.git/objects`, caching up to
+  /// `cache_limit` bytes of objects. A limit of 0 turns the cache off.
+  pub fn new(objects_dir: &Path, cache_limit: usize) -> Self {
+    Self {
+      dir: objects_dir.to_path_buf(),
+      packs: RwLock::new(None),
+      alternates: OnceLock::new(),
+      depth: 0,
+      cache: Mutex::new(Cache::new(cache_limit)),
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+    }
+  }
+
+  /// The hits, misses and size of the object cache so far.
+  pub fn cache_stats(&self) -> CacheStats {
+    CacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+      size: self.cache.lock().unwrap().size,
+    }
+  }
+
+  /// The directory the database is in.
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// The alternate databases this one reads objects from. Ones that are
+  /// missing are skipped, as are those nested more than 5 deep.
+  pub fn alternates(&self) -> &[Database] {
+    self.alternates.get_or_init(|| {
+      if self.depth >= MAX_ALTERNATE_DEPTH {
+        return Vec::new();
+      }
+      let mut dirs = match self.depth {
+        0 => crate::env::alternate_object_dirs(),
+        _ => Vec::new(),
+      };
+      let listed = fs::read_to_string(self.dir.join("info/alternates")).unwrap_or_default();
+      dirs.extend(
+        listed
+          .lines()
+          .filter(|line| !line.is_empty() && !line.starts_with('#'))
+          .map(|line| self.dir.join(line)),
+      );
+      let own = self.dir.canonicalize().ok();
+      dirs
+        .into_iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .filter(|dir| dir.is_dir() && Some(dir) != own.as_ref())
+        .map(|dir| {
+          let mut alternate = Database::new(&dir, 0);
+          alternate.depth = self.depth + 1;
+          alternate
+        })
+        .collect()
+    })
+  }
+
+  /// Counts up what is on disk: the loose objects, the packs and anything
+  /// else that has found its way in. Alternates are left out.
+  pub fn disk_stats(&self) -> DiskStats {
+    let mut stats = DiskStats::default();
+    let garbage = |path: PathBuf, why: &'static str, stats: &mut DiskStats| {
+      stats.garbage_size += fs::metadata(&path).map_or(0, |metadata| metadata.len());
+      stats.garbage.push((path, why));
+    };
+
+    for pack in self.packs().iter() {
+      stats.packs += 1;
+      stats.in_pack += pack.count();
+      stats.pack_size += pack.size();
+    }
+
+    // the files of a pack go together, and are garbage without the pack and
+    // its index
+    let mut names: Vec<String> = fs::read_dir(self.dir.join("pack"))
+      .into_iter()
+      .flatten()
+      .flatten()
+      .map(|entry| entry.file_name().to_string_lossy().into_owned())
+      .collect();
+    names.sort();
+    for name in &names {
+      let path = self.dir.join("pack").join(name);
+      let base = match PACK_FILES
+        .iter()
+        .find_map(|ending| name.strip_suffix(ending))
+      {
+        Some(base) => base,
+        None if name.starts_with("multi-pack-index") => continue,
+        None => {
+          garbage(path, "garbage found", &mut stats);
+          continue;
+        }
+      };
+      let has = |ending: &str| {
+        names
+          .iter()
+          .any(|name| name == &format!("{}{}", base, ending))
+      };
+      match (has(".pack"), has(".idx")) {
+        (true, true) => (),
+        (true, false) => garbage(path, "no corresponding .idx", &mut stats),
+        (false, true) => garbage(path, "no corresponding .pack", &mut stats),
+        (false, false) => garbage(path, "no corresponding .idx or .pack", &mut stats),
+      }
+    }
+    for i in 0..=255u8 {
+      let prefix = format!("{:02x}", i);
+      let mut entries: Vec<_> = fs::read_dir(self.dir.join(&prefix))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .collect();
+      entries.sort_by_key(|entry| entry.file_name());
+      for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_object = name.len() == 38 && name.bytes().all(|c| c.is_ascii_hexdigit());
+        match is_object {
+          true => {
+            stats.loose += 1;
+            stats.loose_size += entry
+              .metadata()
+              .map_or(0, |metadata| metadata.blocks() * 512);
+            if self.is_packed(&format!("{}{}", prefix, name)) {
+              stats.prune_packable += 1;
+            }
+          }
+          false => garbage(entry.path(), "garbage found", &mut stats),
+        }
+      }
+    }
+
+    stats
+  }
+
+  /// The hashes of the loose objects, in order. Alternates are left out.
+  pub fn loose(&self) -> Vec<String> {
+    let mut hashes = Vec::new();
+    for i in 0..=255u8 {
+      let prefix = format!("{:02x}", i);
+      let entries = fs::read_dir(self.dir.join(&prefix)).into_iter().flatten();
+      for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.len() == 38 && name.bytes().all(|c| c.is_ascii_hexdigit()) {
+          hashes.push(format!("{}{}", prefix, name));
+        }
+      }
+    }
+    hashes.sort();
+    hashes
+  }
+
+  /// Deletes the loose objects that are in a pack too, and so are not
+  /// needed, along with the directories of them that are left empty, and
+  /// returns where they were. With `dry_run`, nothing is deleted.
+  pub fn prune_packed(&self, dry_run: bool) -> Result<Vec<PathBuf>, String> {
+    let mut pruned = Vec::new();
+    for hash in self.loose() {
+      if !self.is_packed(&hash) {
+        continue;
+      }
+      let path = self.path(&hash);
+      if !dry_run {
+        fs::remove_file(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        // the directory is only deleted once nothing is left in it
+        let _ = fs::remove_dir(self.dir.join(&hash[0..2]));
+      }
+      pruned.push(path);
+    }
+    Ok(pruned)
+  }
+
+  fn path(&self, hash: &str) -> PathBuf {
+    self.dir.join(&hash[0..2]).join(&hash[2..])
+  }
+
+  /// The packs in the database, as they were the last time they were
+  /// looked at. Packs that cannot be opened are skipped, as if they were not
+  /// there.
+  pub fn packs(&self) -> Arc<[Arc<Pack>]> {
+    if let Some(packs) = &*self.packs.read().unwrap() {
+      return packs.clone();
+    }
+    let mut packs = self.packs.write().unwrap();
+    packs.get_or_insert_with(|| self.open_packs(&[])).clone()
+  }
+
+  /// Looks at the packs on disk again, as git's `reprepare_packed_git`
+  /// does, to see the ones written since they were last looked at and let go
+  /// of the ones deleted. Returns whether anything changed.
+  ///
+  /// Reads call this when an object is not found, so it is only needed
+  /// before asking [`Database::packs`] or [`Database::is_packed`] about a
+  /// pack that has just been written.
+  pub fn reprepare_packs(&self) -> bool {
+    let mut packs = self.packs.write().unwrap();
+    let old = packs.clone().unwrap_or_default();
+    let new = self.open_packs(&old);
+    let changed = new.len() != old.len()
+      || new
+        .iter()
+        .zip(old.iter())
+        .any(|(new, old)| !Arc::ptr_eq(new, old));
+    *packs = Some(new);
+    changed
+  }
+
+  /// Opens the packs on disk, in order of their names, keeping the ones in
+  /// `open` that are still there as they are.
+  fn open_packs(&self, open: &[Arc<Pack>]) -> Arc<[Arc<Pack>]> {
+    let _region = trace::region("prepare packs");
+    let _event = event::region("packfile", "prepare_packed_git", None);
+    let mut paths: Vec<PathBuf> = fs::read_dir(self.dir.join("pack"))
+      .into_iter()
+      .flatten()
+      .flatten()
+      .map(|entry| entry.path())
+      .filter(|path| path.extension().is_some_and(|ext| ext == "idx"))
+      .collect();
+    paths.sort();
+    paths
+      .iter()
+      .filter_map(|path| {
+        let pack_path = path.with_extension("pack");
+        match open.iter().find(|pack| pack.path() == pack_path) {
+          Some(pack) => Some(pack.clone()),
+          None => Pack::open(path).ok().map(Arc::new),
+        }
+      })
+      .collect()
+  }
+
+  /// Reads an object from disk, loose or from a pack, here or in an
+  /// alternate.
+  fn read_uncached(&self, hash: &str) -> Result<Vec<u8>, String> {
+    if let Ok(file) = fs::read(self.path(hash)) {
+      return crypto::decompress(&file);
+    }
+    let base = |hash: &str| self.read(hash).map(|raw| raw.to_vec());
+    for pack in self.packs().iter() {
+      if let Some(raw) = pack.read(hash, &base)? {
+        return Ok(raw);
+      }
+    }
+    match self
+      .alternates()
+      .iter()
+      .find(|alternate| alternate.exists(hash))
+    {
+      Some(alternate) => alternate.read_uncached(hash),
+      // it may have been packed since the packs were looked at, and its
+      // loose copy deleted
+      None if self.reprepare_packs() => self.read_uncached(hash),
+      None => Err(format!("object not found {}", hash)),
+    }
+  }
+
+  /// Reads the object with the given hash, header included, decompressed.
+  pub fn read(&self, hash: &str) -> Result<Arc<[u8]>, String> {
+    if hash.len() <= 2 {
+      return Err(format!("object not found {}", hash));
+    }
+    if let Some(raw) = self.cache.lock().unwrap().get(hash) {
+      self.hits.fetch_add(1, Ordering::Relaxed);
+      return Ok(raw);
+    }
+    let raw: Arc<[u8]> = self.read_uncached(hash)?.into();
+    if !raw.starts_with(b"blob ") {
+      self.misses.fetch_add(1, Ordering::Relaxed);
+      self.cache.lock().unwrap().insert(hash, raw.clone());
+    }
+    Ok(raw)
+  }
+
+  /// The type and size of the object with the given hash, from the header
+  /// of the loose object or of its entry in a pack, without decompressing
+  /// the rest of it.
+  pub fn header(&self, hash: &str) -> Result<(&'static str, usize), String> {
+    let corrupt = || format!("object file {} is corrupt", hash);
+    if hash.len() <= 2 {
+      return Err(format!("object not found {}", hash));
+    }
+    if let Some(raw) = self.cache.lock().unwrap().get(hash) {
+      return parse_header(&raw).ok_or_else(corrupt);
+    }
+    if let Ok(file) = File::open(self.path(hash)) {
+      // the longest header there can be is `commit ` and a 20 digit size
+      let mut start = Vec::with_capacity(32);
+      ZlibDecoder::new(file)
+        .take(32)
+        .read_to_end(&mut start)
+        .map_err(|_| corrupt())?;
+      return parse_header(&start).ok_or_else(corrupt);
+    }
+    let base = |hash: &str| self.header(hash);
+    for pack in self.packs().iter() {
+      if let Some(header) = pack.header(hash, &base)? {
+        return Ok(header);
+      }
+    }
+    match self
+      .alternates()
+      .iter()
+      .find(|alternate| alternate.exists(hash))
+    {
+      Some(alternate) => alternate.header(hash),
+      None if self.reprepare_packs() => self.header(hash),
+      None => Err(format!("object not found {}", hash)),
+    }
+  }
+
+  /// Stores an object, header included, under the given hash.
+  ///
+  /// Objects never change once written, so nothing is done if the object is
+  /// already there, or in an alternate.
+  pub fn write(&self, hash: &str, data: &[u8]) -> Result<(), String> {
+    let path = self.path(hash);
+    if path.is_file()
+      || self
+        .alternates()
+        .iter()
+        .any(|alternate| alternate.exists(hash))
+    {
+      return Ok(());
+    }
+    let dir = self.dir.join(&hash[0..2]);
+    fs::create_dir_all(&dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+    let temp = dir.join(format!(
+      "tmp_obj_{}_{}",
+      process::id(),
+      TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let compressed = crypto::compress(data)?;
+    fs::write(&temp, compressed)
+      .and_then(|_| fs::rename(&temp, &path))
+      .map_err(|err| {
+        let _ = fs::remove_file(&temp);
+        format!("unable to write object {}: {}", hash, err)
+      })
+  }
+
+  /// Checks whether the object is in the database, looking at the packs
+  /// again if it is not found.
+  pub fn exists(&self, hash: &str) -> bool {
+    hash.len() > 2
+      && (self.path(hash).is_file()
+        || self.is_packed(hash)
+        || self
+          .alternates()
+          .iter()
+          .any(|alternate| alternate.exists(hash))
+        || (self.reprepare_packs() && self.is_packed(hash)))
+  }
+
+  /// Checks whether the object is in one of the database's packs, as they
+  /// were last looked at.
+  pub fn is_packed(&self, hash: &str) -> bool {
+    self.packs().iter().any(|pack| pack.contains(hash))
+  }
+
+  /// Checks whether the object is in a promisor pack, one fetched from a
+  /// remote that has promised to supply the objects it refers to on demand.
+  pub fn is_promisor(&self, hash: &str) -> bool {
+    self
+      .packs()
+      .iter()
+      .any(|pack| pack.contains(hash) && pack.path().with_extension("promisor").is_file())
+  }
+
+  /// Lists the objects whose hash begins with the given hex prefix.
+  pub fn find_by_prefix(&self, prefix: &str) -> Vec<String> {
+    let mut matches = Vec::new();
+    if prefix.len() < 2 {
+      return matches;
+    }
+    if let Ok(entries) = fs::read_dir(self.dir.join(&prefix[0..2])) {
+      for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(&prefix[2..]) && !name.starts_with("tmp_obj_") {
+          matches.push(format!("{}{}", &prefix[0..2], name));
+        }
+      }
+    }
+    for pack in self.packs().iter() {
+      pack.find_by_prefix(prefix, &mut matches);
+    }
+    for alternate in self.alternates() {
+      matches.extend(alternate.find_by_prefix(prefix));
+    }
+    matches.sort();
+    matches.dedup();
+    matches
+  }
+}
+
+impl Drop for Database {
+  /// Reports how the cache did when `GIT_TRACE_OBJECT_CACHE` is set, to help
+  /// with choosing a `core.objectCacheLimit`.
+  fn drop(&mut self) {
+    if self.depth == 0
+      && env::var_os("GIT_TRACE_OBJECT_CACHE").is_some_and(|value| !value.is_empty())
+    {
+      let stats = self.cache_stats();
+      eprintln!(
+        "object cache: {} hits, {} misses, {} bytes",
+        stats.hits, stats.misses, stats.size
+      );
+    }
+  }
+}
+
+/// The objects a [`Database`] keeps in memory, by hash.
+///
+/// Each read stamps the object with the next tick of a counter, and `order`
+/// maps stamps back to hashes, so the least recently used object is the first
+/// one in `order`.
+#[derive(Debug)]
+struct Cache {
+  limit: usize,
+  size: usize,
+  tick: u64,
+  entries: HashMap<String, (Arc<[u8]>, u64)>,
+  order: BTreeMap<u64, String>,
+}
+
+impl Cache {
+  fn new(limit: usize) -> Self {
+    Self {
+      limit,
+      size: 0,
+      tick: 0,
+      entries: HashMap::new(),
+      order: BTreeMap::new(),
+    }
+  }
+
+  fn get(&mut self, hash: &str) -> Option<Arc<[u8]>> {
+    self.tick += 1;
+    let (raw, stamp) = self.entries.get_mut(hash)?;
+    self.order.remove(stamp);
+    *stamp = self.tick;
+    self.order.insert(self.tick, hash.to_string());
+    Some(raw.clone())
+  }
+
+  fn insert(&mut self, hash: &str, raw: Arc<[u8]>) {
+    if raw.len() > self.limit || self.entries.contains_key(hash) {
+      return;
+    }
+    while self.size + raw.len() > self.limit {
+      let (_, oldest) = self.order.pop_first().unwrap();
+      let (evicted, _) = self.entries.remove(&oldest).unwrap();
+      self.size -= evicted.len();
+    }
+    self.tick += 1;
+    self.size += raw.len();
+    self.order.insert(self.tick, hash.to_string());
+    self.entries.insert(hash.to_string(), (raw, self.tick));
+  }
+}
@@ -7,17 +7,14 @@ impl Findable for [u8] {
   ///
   /// # Example
   /// ```
-  /// let my_slice = &['a', 'b', 'c'];
-  /// assert_eq!(my_slice.find('a', 0), Some(0));
-  /// assert_eq!(my_slice.find('a', 1), None);
-  /// assert_eq!(my_slice.find('z'), None);
+  /// use git_rs_core::object::findable::Findable;
+  ///
+  /// let my_slice: &[u8] = b"abc";
+  /// assert_eq!(my_slice.find(b'a', 0), Some(0));
+  /// assert_eq!(my_slice.find(b'a', 1), None);
+  /// assert_eq!(my_slice.find(b'z', 0), None);
   /// ```
   fn find(&self, ch: u8, offset: usize) -> Option<usize> {
-    for i in offset..self.len() {
-      if self.get(i).unwrap().eq(&ch) {
-        return Some(i);
-      }
-    }
-    None
+    (offset..self.len()).find(|&i| self[i] == ch)
   }
 }
@@ -8,7 +8,7 @@ use indexmap::IndexMap;
 /// item may span over multiple lines, subsequent lins start with a space which
 /// the parser must drop.
 ///
-/// See: https://www.ietf.org/rfc/rfc2822.txt
+/// See: <https://www.ietf.org/rfc/rfc2822.txt>
 ///
 /// ### Example
 /// A commit object (uncompressed, without the headers) looks like this:
@@ -46,10 +46,9 @@ use indexmap::IndexMap;
 /// committer => Thibault Polge <thibault@thb.lt> 1527025044 +0200
 /// gpgsig    => -----BEGIN PGP SIGNATURE----- ... -----END PGP SIGNATURE-----
 /// ```
-
 pub struct MailMap {
   data: Vec<u8>,
-  pub map: IndexMap<String, String>,
+  pub map: IndexMap<String, Vec<String>>,
 }
 
 impl MailMap {
@@ -61,75 +60,127 @@ impl MailMap {
   }
 
   pub fn parse_bytes(&mut self, raw: &[u8], offset: usize) {
-    // Search for the next space and newline.
-    let maybe_space = raw.find(b' ', offset);
-    let maybe_newln = raw.find(b'\n', offset);
-
-    // If newline occurs first (or there's no space at all), assume blank line.
-    match (maybe_space, maybe_newln) {
-      (_any, Some(newline)) if newline <= maybe_space.unwrap_or(newline) => {
-        assert_eq!(newline, offset);
-        extract_message(&raw[offset + 1..], &mut self.map);
-      }
-      (None, None) => (), // reached the end of the raw data
-      _ => {
-        let space = maybe_space.unwrap(); // shouldn't panic
-        let next_offset = extract_entry(&raw[offset..], space, &mut self.map);
-        self.parse_bytes(raw, next_offset);
+    let mut offset = offset;
+    loop {
+      // Search for the next space and newline.
+      let maybe_space = raw.find(b' ', offset);
+      let maybe_newln = raw.find(b'\n', offset);
+
+      // If newline occurs first (or there's no space at all), assume blank line.
+      match (maybe_space, maybe_newln) {
+        (_any, Some(newline)) if newline <= maybe_space.unwrap_or(newline) => {
+          assert_eq!(newline, offset);
+          extract_message(&raw[offset + 1..], &mut self.map);
+          break;
+        }
+        (None, None) => break, // reached the end of the raw data
+        _ => {
+          let space = maybe_space.unwrap(); // shouldn't panic
+          offset = extract_entry(raw, offset, space, &mut self.map);
+        }
       }
     }
 
-    self.data = map_to_bytes(&self.map);
+    self.data = raw.to_vec();
   }
 
   pub fn to_bytes(&self) -> &[u8] {
     self.data.as_slice()
   }
+
+  /// Returns the first value stored under `key`, if any.
+  pub fn get(&self, key: &str) -> Option<&String> {
+    self.map.get(key).and_then(|values| values.first())
+  }
+
+  /// Returns every value stored under `key` (eg. all of a commit's parents).
+  pub fn get_all(&self, key: &str) -> &[String] {
+    match self.map.get(key) {
+      Some(values) => values.as_slice(),
+      None => &[],
+    }
+  }
+
+  /// Appends a value under `key`, keeping any values already stored there.
+  pub fn insert(&mut self, key: &str, value: &str) {
+    let values = self.map.entry(key.to_owned()).or_default();
+    values.push(value.to_owned());
+  }
+
+  /// Returns the free-form message that follows the key-value pairs.
+  pub fn message(&self) -> &str {
+    self.get("").map(|m| m.as_str()).unwrap_or("")
+  }
+}
+
+impl Default for MailMap {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 /// After a blank line, the rest of the file is an optional message.
-fn extract_message(bytes: &[u8], map: &mut IndexMap<String, String>) {
+fn extract_message(bytes: &[u8], map: &mut IndexMap<String, Vec<String>>) {
   let key = String::from("");
-  let value = String::from_utf8(bytes.to_vec()).expect("invalid value");
-  map.entry(key).or_insert(value);
+  let value = String::from_utf8_lossy(bytes).into_owned();
+  map.entry(key).or_default().push(value);
 }
 
 /// Pulls out a single key, value pair from the file.
 ///
 /// The key and value are separated by a space, and the value may span multiple
 /// lines. The continuation lines must be indented by a space and the space is
-/// not part of the continuation line (ie. it must be removed).
-fn extract_entry(bytes: &[u8], space: usize, map: &mut IndexMap<String, String>) -> usize {
+/// not part of the continuation line (ie. it must be removed). Returns the
+/// offset of the first byte after the entry.
+fn extract_entry(
+  raw: &[u8],
+  offset: usize,
+  space: usize,
+  map: &mut IndexMap<String, Vec<String>>,
+) -> usize {
   // find the first `\n` that is not followed by a space character
-  let mut end = bytes.find(b'\n', 1).unwrap();
-  while bytes[end + 1] == b' ' {
-    end = bytes.find(b'\n', end + 1).unwrap() // try again
+  let mut end = raw.find(b'\n', space).unwrap_or(raw.len());
+  while end + 1 < raw.len() && raw[end + 1] == b' ' {
+    end = raw.find(b'\n', end + 1).unwrap_or(raw.len()) // try again
   }
 
-  let key = String::from_utf8(bytes[..space].to_vec()).expect("invalid key");
-  let value = String::from_utf8(bytes[space + 1..end].to_vec()).expect("invalid value");
+  let key = String::from_utf8_lossy(&raw[offset..space]).into_owned();
+  let value = String::from_utf8_lossy(&raw[space + 1..end]).into_owned();
 
-  map.entry(key).or_insert(value.replace("\n ", "\n"));
-  end
+  map.entry(key).or_default().push(value.replace("\n ", "\n"));
+  end + 1
+}
+
+/// Checks that a key can be written as a header: one word, which is not
+/// empty, with no spaces or line breaks in it.
+pub fn check_header(key: &str) -> Result<(), String> {
+  match key.is_empty() || key.contains([' ', '\n']) {
+    true => Err(format!("invalid header name '{}'", key)),
+    false => Ok(()),
+  }
 }
 
 /// Walk through the map and build up a byte vector.
-pub fn map_to_bytes(map: &IndexMap<String, String>) -> Vec<u8> {
+pub fn map_to_bytes(map: &IndexMap<String, Vec<String>>) -> Vec<u8> {
   let mut result = String::from("");
 
   // append the fields (key-value pairs)
-  for key in map.keys() {
+  for (key, values) in map.iter() {
     if !key.is_empty() {
-      let value = map.get(key).unwrap();
-      result.push_str(key);
-      result.push(' ');
-      result.push_str(&value.replace('\n', "\n "));
-      result.push('\n');
+      for value in values {
+        result.push_str(key);
+        result.push(' ');
+        result.push_str(&value.replace('\n', "\n "));
+        result.push('\n');
+      }
     }
   }
 
   // append the message (the key of the message is the empty string)
-  result.push_str(map.get("").unwrap());
+  if let Some(message) = map.get("").and_then(|values| values.first()) {
+    result.push('\n');
+    result.push_str(message);
+  }
 
   result.into_bytes()
 }
@@ -1,25 +1,50 @@
-pub(crate) mod blob;
-pub(crate) mod commit;
-pub(crate) mod findable;
-pub(crate) mod mail_map;
-pub(crate) mod mode;
-pub(crate) mod refs;
-pub(crate) mod serializable;
-pub(crate) mod tag;
-pub(crate) mod tree;
+pub mod blob;
+pub mod commit;
+pub mod commit_graph;
+pub mod database;
+pub mod findable;
+pub mod mail_map;
+pub mod mode;
+pub mod pack;
+pub mod quarantine;
+pub mod refs;
+pub mod serializable;
+pub mod tag;
+pub mod tree;
+
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::crypto;
+use crate::env;
 use crate::object::blob::Blob;
 use crate::object::commit::Commit;
 use crate::object::findable::Findable;
 use crate::object::serializable::Serializable;
+use crate::object::serializable::Unbox;
 use crate::object::tree::Tree;
-use crate::repo::{repo_file, Repo};
-use std::fs::{self, File};
-use std::io::prelude::*;
+use crate::repo::Repo;
+use crate::rev;
 
 use self::tag::Tag;
 
+/// The hash of the tree with nothing in it, which [`read`] can read even
+/// when it has never been written, as git can.
+pub const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// The hash of the blob with nothing in it.
+pub const EMPTY_BLOB: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+/// Whether [`read`] substitutes replacement objects, until a command that
+/// must see the originals turns it off.
+static REPLACE_OBJECTS: AtomicBool = AtomicBool::new(true);
+
+/// How long a chain of replacements of replacements may be.
+const MAX_REPLACE_DEPTH: usize = 5;
+
+/// Signs the data of a commit or tag as it is without the signature,
+/// returning the signature.
+pub type Signer = Box<dyn FnOnce(&[u8]) -> Result<String, String>>;
+
 /// A git object.
 ///
 /// In git, objects are a generic structure used for a lot of various things. At
@@ -44,78 +69,195 @@ use self::tag::Tag;
 /// `tag` or `tree`. This header is followed by an ASCII space (0x20), then the
 /// size of the object in bytes as an ASCII number, then null (0x00) (the null
 /// byte), then the contents of the object.
-
+///
 /// Reads object object_id from the repository repo and returns an object
 /// whose exact type depends on the object read from memory.
+///
+/// An object that has a replacement is read as the replacement instead (see
+/// [`replacement`]). The [`EMPTY_TREE`] is read even if it is not in the
+/// database, so that an unborn branch can be compared and merged as if it
+/// had it.
 pub fn read(
-  repo: Repo,
+  repo: &Repo,
   hash: &str,
   typename: Option<&str>,
 ) -> Result<Box<dyn Serializable>, String> {
-  let directories = ["objects", &hash[0..2], &hash[2..]];
-  let path = match repo_file(&repo.git_dir, &directories, false) {
-    Some(p) => p,
-    None => return Err(format!("object not found {}", hash)),
+  let hash = replacement(repo, hash)?;
+  let raw = match repo.objects.read(&hash) {
+    Err(_) if hash == EMPTY_TREE => b"tree 0\0"[..].into(),
+    raw => raw?,
   };
-  if let Ok(file) = fs::read(path) {
-    let raw = crypto::decompress(&file)?;
-
-    // Read the object type
-    let first_space: usize = raw.find(b' ', 0).unwrap();
-    let object_type: &str = &String::from_utf8(raw[0..first_space].to_vec()).unwrap();
-    match typename {
-      Some(name) if object_type != name => {
-        return Err(format!("invalid object type \"{}\"", typename.unwrap()))
-      }
-      _ => (),
+
+  // Read the object type
+  let first_space: usize = raw.find(b' ', 0).unwrap();
+  let object_type: &str = std::str::from_utf8(&raw[0..first_space]).unwrap();
+  match typename {
+    Some(name) if object_type != name => {
+      return Err(format!("invalid object type \"{}\"", typename.unwrap()))
     }
+    _ => (),
+  }
 
-    // Read and validate the object size
-    let null_byte: usize = raw.find(b'\0', 0).unwrap();
-    let object_size: usize = String::from_utf8(raw[first_space + 1..null_byte].to_vec())
-      .unwrap()
-      .parse::<usize>()
-      .unwrap();
+  // Read and validate the object size
+  let null_byte: usize = raw.find(b'\0', 0).unwrap();
+  let object_size: usize = String::from_utf8(raw[first_space + 1..null_byte].to_vec())
+    .unwrap()
+    .parse::<usize>()
+    .unwrap();
 
-    if object_size != raw.len() - null_byte - 1 {
-      return Err("size does not match size of raw data".to_string());
-    }
+  if object_size != raw.len() - null_byte - 1 {
+    return Err("size does not match size of raw data".to_string());
+  }
+
+  let payload = &raw[null_byte + 1..];
+  match object_type {
+    "blob" => Ok(Box::new(Blob::new(payload))),
+    "commit" => Ok(Box::new(Commit::new(payload))),
+    "tag" => Ok(Box::new(Tag::new(payload))),
+    "tree" => Ok(Box::new(Tree::new(payload)?)),
+    _ => Err(format!("unsupported type \"{}\"", object_type)),
+  }
+}
+
+/// The type and size of an object, read from its header alone, as
+/// [`read`] would find them: of its replacement if it has one, and for the
+/// [`EMPTY_TREE`] even if the database doesn't have it.
+pub fn header(repo: &Repo, hash: &str) -> Result<(&'static str, usize), String> {
+  let hash = replacement(repo, hash)?;
+  match repo.objects.header(&hash) {
+    Err(_) if hash == EMPTY_TREE => Ok(("tree", 0)),
+    header => header,
+  }
+}
+
+/// The object that stands in for another, if it has been replaced.
+///
+/// A ref `refs/replace/<hash>` names the object to use in place of the
+/// object with that hash, which lets history be patched up (eg. a bad commit
+/// message fixed) without rewriting everything after it. The replacement may
+/// itself be replaced.
+///
+/// Replacements are ignored when `GIT_NO_REPLACE_OBJECTS` is set or
+/// `core.useReplaceRefs` is false, and once [`ignore_replacements`] has been
+/// called. `GIT_REPLACE_REF_BASE` moves them out of `refs/replace/`.
+pub fn replacement(repo: &Repo, hash: &str) -> Result<String, String> {
+  let use_replace_refs = repo
+    .config
+    .as_ref()
+    .and_then(|config| config.section(Some("core")))
+    .and_then(|core| {
+      core
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("useReplaceRefs"))
+    })
+    .is_none_or(|(_, value)| {
+      !matches!(
+        value.to_ascii_lowercase().as_str(),
+        "false" | "no" | "off" | "0"
+      )
+    });
+  if !REPLACE_OBJECTS.load(Ordering::Relaxed)
+    || env::var("GIT_NO_REPLACE_OBJECTS").is_some()
+    || !use_replace_refs
+  {
+    return Ok(hash.to_owned());
+  }
 
-    let payload = &raw[null_byte + 1..];
-    match object_type {
-      "blob" => Ok(Box::new(Blob::new(repo, payload))),
-      "commit" => Ok(Box::new(Commit::new(repo, payload))),
-      "tag" => Ok(Box::new(Tag::new(repo, payload))),
-      "tree" => Ok(Box::new(Tree::new(repo, payload))),
-      _ => Err(format!("unsupported type \"{}\"", object_type)),
+  let base = env::replace_ref_base();
+  let mut current = hash.to_owned();
+  for _ in 0..=MAX_REPLACE_DEPTH {
+    let name = base.join(&current);
+    match repo.refs.read(&name.to_string_lossy())? {
+      Some(refs::Ref::Direct(target)) => current = target,
+      _ => return Ok(current),
     }
-  } else {
-    Err("object not found".to_string())
   }
+  Err(format!("replace depth too high for object {}", hash))
+}
+
+/// Makes [`read`] see objects as they are, ignoring replacements, from now
+/// on. Commands that check or copy objects rather than show them must do
+/// this, or they would, say, pack a replacement in place of the original.
+pub fn ignore_replacements() {
+  REPLACE_OBJECTS.store(false, Ordering::Relaxed);
 }
 
 /// Writes an object to the repository.
 ///
-/// The object is written to the repository that the object represents. If the
-/// dry_run flag is set to true, the hash will be calculated but not written
-/// to the directory.
-pub fn write(object: &dyn Serializable, dry_run: bool) -> Result<String, String> {
+/// If the dry_run flag is set to true, the hash will be calculated but not
+/// written to the directory.
+pub fn write(repo: &Repo, object: &dyn Serializable, dry_run: bool) -> Result<String, String> {
   let payload = object.serialize();
   let header = format!("{} {}\0", object.format(), payload.len());
   let data = [header.as_bytes(), payload].concat();
   let hash = crypto::sha_1(&data);
 
   if !dry_run {
-    let directories = ["objects", &hash[0..2], &hash[2..]];
-    let path = repo_file(&object.repo().git_dir, &directories, true);
-    let mut file = File::create(path.unwrap()).unwrap();
-    let compressed_data = crypto::compress(&data)?;
-    file.write_all(&compressed_data[..]).unwrap();
+    repo.objects.write(&hash, &data)?;
   }
   Ok(hash)
 }
 
-/// Finds object.
-pub fn find_object<'a>(_repo: Repo, name: &'a str, _type: Option<&str>, _follow: bool) -> &'a str {
-  name
+/// Finds an object by name.
+///
+/// The name is any revision understood by [`rev::parse`]. If a type is given
+/// and `follow` is set, tags and commits are peeled until an object of that
+/// type is reached (eg. asking for a `tree` given a tag of a commit).
+pub fn find_object(
+  repo: &Repo,
+  name: &str,
+  typename: Option<&str>,
+  follow: bool,
+) -> Result<String, String> {
+  let hash = rev::parse(repo, name)?;
+  match typename {
+    Some(name) if follow => peel(repo, &hash, Some(name)),
+    _ => Ok(hash),
+  }
+}
+
+/// Peels an object until it has the given type.
+///
+/// Tags are followed to the object they point at and commits are followed to
+/// their tree. When no type is given, tags are peeled until a non-tag object
+/// is found.
+pub fn peel(repo: &Repo, hash: &str, typename: Option<&str>) -> Result<String, String> {
+  let mut hash = hash.to_owned();
+  loop {
+    let object = read(repo, &hash, None)?;
+    let format = object.format().as_str();
+    match (format, typename) {
+      (_, Some(name)) if name == format => return Ok(hash),
+      ("tag", _) => {
+        let tag = object.unbox::<Tag>()?;
+        hash = match tag.get("object") {
+          Some(target) => target.to_owned(),
+          None => return Err(format!("malformed tag {}", hash)),
+        };
+      }
+      ("commit", Some("tree")) => hash = object.unbox::<Commit>()?.tree().to_owned(),
+      (_, None) => return Ok(hash),
+      (_, Some(name)) => return Err(format!("{} is a {}, not a {}", hash, format, name)),
+    }
+  }
+}
+
+/// The tree of the commit HEAD points at, or the [`EMPTY_TREE`] when HEAD
+/// is on a branch that has no commits yet, which is what comparing with an
+/// unborn branch compares with.
+pub fn head_tree(repo: &Repo) -> Result<String, String> {
+  match rev::parse(repo, "HEAD") {
+    Ok(head) => peel(repo, &head, Some("tree")),
+    Err(_) => Ok(EMPTY_TREE.to_owned()),
+  }
+}
+
+/// Lists the loose objects whose hash begins with the given hex prefix.
+pub fn find_by_prefix(repo: &Repo, prefix: &str) -> Vec<String> {
+  repo.objects.find_by_prefix(prefix)
+}
+
+/// Checks whether an object is in the repository's object database.
+pub fn exists(repo: &Repo, hash: &str) -> bool {
+  repo.objects.exists(hash)
 }
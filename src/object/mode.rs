@@ -1,30 +1,100 @@
 use std::convert::TryFrom;
 use std::fmt::Display;
 
-#[derive(Copy, Clone, Debug)]
+/// The kind of a tree or index entry, as the octal number git stores it as.
+///
+/// Git only writes these five modes. Anything else is rejected rather than
+/// rounded to the nearest one, save for the regular file modes old versions
+/// wrote into trees (see
+/// [`TreeEntry::from_bytes`](super::tree::TreeEntry::from_bytes)).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Mode {
-  Normal = 100644,
-  Directory = 40000,
-  Executable = 100755,
-  Symbolic = 120000,
+  /// A regular file.
+  Normal = 0o100644,
+
+  /// A directory (a subtree).
+  Directory = 0o040000,
+
+  /// A regular file with the executable bit set.
+  Executable = 0o100755,
+
+  /// A symbolic link, whose blob holds the target.
+  Symbolic = 0o120000,
+
+  /// A submodule, whose hash is a commit in another repository.
+  Gitlink = 0o160000,
+}
+
+impl Mode {
+  /// Parses a mode written in octal, as in trees, patches and `ls-tree`
+  /// output. Leading zeros are allowed, so `40000` and `040000` are both a
+  /// directory.
+  pub fn from_octal(text: impl AsRef<[u8]>) -> Result<Mode, String> {
+    let text = text.as_ref();
+    let invalid = || format!("invalid mode '{}'", String::from_utf8_lossy(text));
+    if text.is_empty() || text.len() > 7 || !text.iter().all(|c| (b'0'..=b'7').contains(c)) {
+      return Err(invalid());
+    }
+    let bits = text
+      .iter()
+      .fold(0, |bits, c| (bits << 3) | (c - b'0') as u32);
+    Mode::try_from(bits).map_err(|_| invalid())
+  }
+
+  /// The mode as git writes it in a tree: octal, without leading zeros.
+  pub fn to_octal(self) -> String {
+    format!("{:o}", self.bits())
+  }
+
+  /// The mode as the bits stored in the index.
+  pub fn bits(self) -> u32 {
+    self as u32
+  }
+
+  /// The permissions a file checked out with this mode is created with,
+  /// before the umask is applied, or `None` for entries that are not plain
+  /// files.
+  pub fn permissions(self) -> Option<u32> {
+    match self {
+      Mode::Normal => Some(0o666),
+      Mode::Executable => Some(0o777),
+      _ => None,
+    }
+  }
+
+  /// Whether the entry's object is a blob in this repository.
+  pub fn is_blob(self) -> bool {
+    matches!(self, Mode::Normal | Mode::Executable | Mode::Symbolic)
+  }
+
+  /// The type of object the entry points at.
+  pub fn object_type(self) -> &'static str {
+    match self {
+      Mode::Directory => "tree",
+      Mode::Gitlink => "commit",
+      _ => "blob",
+    }
+  }
 }
 
 impl Display for Mode {
   /// Pads the mode to six digits.
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "{:0>6}", *self as usize)
+    write!(f, "{:06o}", self.bits())
   }
 }
 
-impl TryFrom<usize> for Mode {
+impl TryFrom<u32> for Mode {
   type Error = ();
 
-  fn try_from(value: usize) -> Result<Self, Self::Error> {
+  /// Reads a mode from its bits, as stored in the index.
+  fn try_from(value: u32) -> Result<Self, Self::Error> {
     match value {
-      x if x == Mode::Normal as usize => Ok(Mode::Normal),
-      x if x == Mode::Directory as usize => Ok(Mode::Directory),
-      x if x == Mode::Executable as usize => Ok(Mode::Executable),
-      x if x == Mode::Symbolic as usize => Ok(Mode::Symbolic),
+      x if x == Mode::Normal as u32 => Ok(Mode::Normal),
+      x if x == Mode::Directory as u32 => Ok(Mode::Directory),
+      x if x == Mode::Executable as u32 => Ok(Mode::Executable),
+      x if x == Mode::Symbolic as u32 => Ok(Mode::Symbolic),
+      x if x == Mode::Gitlink as u32 => Ok(Mode::Gitlink),
       _ => Err(()),
     }
   }
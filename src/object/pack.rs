@@ -0,0 +1,525 @@
+use flate2::read::ZlibDecoder;
+use std::{
+  borrow::Cow,
+  fs::File,
+  io::{self, Read},
+  os::unix::fs::FileExt,
+  path::{Path, PathBuf},
+};
+
+use crate::trace;
+
+/// The object types as numbered in a pack.
+const COMMIT: u8 = 1;
+const TREE: u8 = 2;
+const BLOB: u8 = 3;
+const TAG: u8 = 4;
+const OFS_DELTA: u8 = 6;
+const REF_DELTA: u8 = 7;
+
+/// The type and size of an object, as its header gives them.
+pub type Header = (&'static str, usize);
+
+/// Where the tables of a version 2 index start, after its header and fan-out
+/// table.
+const INDEX_TABLES: usize = 8 + 256 * 4;
+
+/// A file that is read at arbitrary offsets, such as a pack or its index.
+///
+/// The file is mapped into memory when the `mmap` feature is on and the
+/// mapping works, so a window of it is just a slice. Otherwise each window is
+/// read with `pread`, which costs a copy but works everywhere.
+enum Data {
+  #[cfg(feature = "mmap")]
+  Mapped(memmap2::Mmap),
+  File(File, u64),
+}
+
+impl Data {
+  fn open(path: &Path) -> Result<Self, String> {
+    let file = File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    #[cfg(feature = "mmap")]
+    {
+      // SAFETY: packs and their indexes are never modified once written (a
+      // repack writes new files and deletes the old ones), so the mapping
+      // cannot change under us.
+      if let Ok(map) = unsafe { memmap2::Mmap::map(&file) } {
+        return Ok(Data::Mapped(map));
+      }
+    }
+    let len = file
+      .metadata()
+      .map_err(|err| format!("{}: {}", path.display(), err))?
+      .len();
+    Ok(Data::File(file, len))
+  }
+
+  fn len(&self) -> u64 {
+    match self {
+      #[cfg(feature = "mmap")]
+      Data::Mapped(map) => map.len() as u64,
+      Data::File(_, len) => *len,
+    }
+  }
+
+  /// Up to `len` bytes from `offset`, fewer if the file ends first.
+  fn window(&self, offset: u64, len: usize) -> Result<Cow<'_, [u8]>, String> {
+    let end = self.len().min(offset.saturating_add(len as u64));
+    if offset > end {
+      return Err(format!("offset {} is past the end of the file", offset));
+    }
+    match self {
+      #[cfg(feature = "mmap")]
+      Data::Mapped(map) => Ok(Cow::Borrowed(&map[offset as usize..end as usize])),
+      Data::File(file, _) => {
+        let mut buf = vec![0; (end - offset) as usize];
+        file
+          .read_exact_at(&mut buf, offset)
+          .map_err(|err| err.to_string())?;
+        Ok(Cow::Owned(buf))
+      }
+    }
+  }
+
+  /// Exactly `len` bytes from `offset`.
+  fn slice(&self, offset: u64, len: usize) -> Result<Cow<'_, [u8]>, String> {
+    let window = self.window(offset, len)?;
+    match window.len() == len {
+      true => Ok(window),
+      false => Err("unexpected end of file".to_string()),
+    }
+  }
+
+  fn u32_at(&self, offset: u64) -> Result<u32, String> {
+    let bytes = self.slice(offset, 4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+  }
+}
+
+/// Reads a [`Data`] from an offset onwards, for the zlib decoder.
+struct Reader<'a> {
+  data: &'a Data,
+  offset: u64,
+}
+
+impl Read for Reader<'_> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let window = self
+      .data
+      .window(self.offset, buf.len())
+      .map_err(io::Error::other)?;
+    buf[..window.len()].copy_from_slice(&window);
+    self.offset += window.len() as u64;
+    Ok(window.len())
+  }
+}
+
+/// A pack, ie. a `.pack` file of objects in `.git/objects/pack` with the
+/// `.idx` file that says where each object is in it.
+///
+/// Objects in a pack are compressed one by one, and may be stored as a delta
+/// against another object: either one earlier in the pack (`OFS_DELTA`) or
+/// any object named by its hash (`REF_DELTA`).
+pub struct Pack {
+  path: PathBuf,
+  index: Data,
+  pack: Data,
+  count: usize,
+}
+
+impl Pack {
+  /// Opens the pack whose index is at `index_path`. Only version 2 indexes
+  /// are understood.
+  pub fn open(index_path: &Path) -> Result<Self, String> {
+    let index = Data::open(index_path)?;
+    if index.slice(0, 8)?[..] != *b"\xfftOc\0\0\0\x02" {
+      return Err(format!(
+        "{}: unsupported pack index version",
+        index_path.display()
+      ));
+    }
+    let count = index.u32_at(8 + 255 * 4)? as usize;
+    if index.len() < (INDEX_TABLES + count * 28 + 40) as u64 {
+      return Err(format!("{}: pack index is truncated", index_path.display()));
+    }
+
+    let path = index_path.with_extension("pack");
+    let pack = Data::open(&path)?;
+    let header = pack.slice(0, 12)?;
+    if header[..4] != *b"PACK"
+      || u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize != count
+    {
+      return Err(format!("{}: not a pack matching its index", path.display()));
+    }
+    Ok(Self {
+      path,
+      index,
+      pack,
+      count,
+    })
+  }
+
+  /// The path of the `.pack` file.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// The number of objects in the pack.
+  pub fn count(&self) -> usize {
+    self.count
+  }
+
+  /// The bytes the pack and its index take up.
+  pub fn size(&self) -> u64 {
+    self.pack.len() + self.index.len()
+  }
+
+  /// The hashes of the objects in the pack, in order.
+  pub fn hashes(&self) -> Result<Vec<String>, String> {
+    (0..self.count)
+      .map(|i| self.hash_at(i).map(|id| hex::encode(&id[..])))
+      .collect()
+  }
+
+  fn hash_at(&self, i: usize) -> Result<Cow<'_, [u8]>, String> {
+    self.index.slice((INDEX_TABLES + i * 20) as u64, 20)
+  }
+
+  /// The range of positions in the index of the hashes that start with
+  /// `first`.
+  fn fan_out(&self, first: u8) -> Result<(usize, usize), String> {
+    let end = self.index.u32_at(8 + first as u64 * 4)? as usize;
+    let start = match first {
+      0 => 0,
+      _ => self.index.u32_at(8 + (first as u64 - 1) * 4)? as usize,
+    };
+    Ok((start, end.min(self.count)))
+  }
+
+  /// The position in the index of an object.
+  fn position(&self, id: &[u8]) -> Result<Option<usize>, String> {
+    let (mut low, mut high) = self.fan_out(id[0])?;
+    while low < high {
+      let middle = (low + high) / 2;
+      match self.hash_at(middle)?[..].cmp(id) {
+        std::cmp::Ordering::Less => low = middle + 1,
+        std::cmp::Ordering::Greater => high = middle,
+        std::cmp::Ordering::Equal => return Ok(Some(middle)),
+      }
+    }
+    Ok(None)
+  }
+
+  /// Where the object at position `i` of the index starts in the pack.
+  fn offset_at(&self, i: usize) -> Result<u64, String> {
+    let offsets = INDEX_TABLES + self.count * 24;
+    let offset = self.index.u32_at((offsets + i * 4) as u64)?;
+    if offset & 0x8000_0000 == 0 {
+      return Ok(offset as u64);
+    }
+    let large = offsets + self.count * 4 + (offset & 0x7fff_ffff) as usize * 8;
+    let bytes = self.index.slice(large as u64, 8)?;
+    Ok(u64::from_be_bytes(bytes[..].try_into().unwrap()))
+  }
+
+  /// Checks whether the object with the given hash is in the pack.
+  pub fn contains(&self, hash: &str) -> bool {
+    match hex::decode(hash) {
+      Ok(id) if id.len() == 20 => matches!(self.position(&id), Ok(Some(_))),
+      _ => false,
+    }
+  }
+
+  /// Adds the objects in the pack whose hash begins with the given hex
+  /// prefix to `matches`.
+  pub fn find_by_prefix(&self, prefix: &str, matches: &mut Vec<String>) {
+    let first = match u8::from_str_radix(&prefix[0..2], 16) {
+      Ok(first) => first,
+      Err(_) => return,
+    };
+    if let Ok((start, end)) = self.fan_out(first) {
+      for i in start..end {
+        if let Ok(id) = self.hash_at(i) {
+          let hash = hex::encode(&id[..]);
+          if hash.starts_with(prefix) {
+            matches.push(hash);
+          }
+        }
+      }
+    }
+  }
+
+  /// Reads an object from the pack, header included like a loose object.
+  ///
+  /// Returns `None` if the object is not in the pack. `base` reads the
+  /// objects that `REF_DELTA`s are against, which may be in another pack.
+  pub fn read(
+    &self,
+    hash: &str,
+    base: &dyn Fn(&str) -> Result<Vec<u8>, String>,
+  ) -> Result<Option<Vec<u8>>, String> {
+    let id = hex::decode(hash).map_err(|_| format!("invalid object name {}", hash))?;
+    let position = match self.position(&id)? {
+      Some(position) => position,
+      None => return Ok(None),
+    };
+    let (kind, payload) = self
+      .read_at(self.offset_at(position)?, base)
+      .map_err(|err| format!("{}: {}: {}", self.path.display(), hash, err))?;
+    let header = format!("{} {}\0", kind, payload.len());
+    Ok(Some([header.as_bytes(), &payload].concat()))
+  }
+
+  /// The type and size of an object in the pack, found from the headers of
+  /// its entry (and for a delta, its base's) without inflating more than
+  /// the start of a delta.
+  ///
+  /// Returns `None` if the object is not in the pack. `base` finds the type
+  /// of the objects that `REF_DELTA`s are against, which may be in another
+  /// pack.
+  pub fn header(
+    &self,
+    hash: &str,
+    base: &dyn Fn(&str) -> Result<Header, String>,
+  ) -> Result<Option<Header>, String> {
+    let id = hex::decode(hash).map_err(|_| format!("invalid object name {}", hash))?;
+    let position = match self.position(&id)? {
+      Some(position) => position,
+      None => return Ok(None),
+    };
+    self
+      .header_at(self.offset_at(position)?, base)
+      .map(Some)
+      .map_err(|err| format!("{}: {}: {}", self.path.display(), hash, err))
+  }
+
+  /// The type and size of the object at `offset` in the pack.
+  fn header_at(
+    &self,
+    offset: u64,
+    base: &dyn Fn(&str) -> Result<Header, String>,
+  ) -> Result<Header, String> {
+    let entry = self.entry_at(offset)?;
+    let kind = match &entry.base {
+      None => return Ok((kind_name(entry.kind), entry.size)),
+      Some(Base::Offset(base_offset)) => self.header_at(*base_offset, base)?.0,
+      Some(Base::Hash(hash)) => base(hash)?.0,
+    };
+    // a delta starts with the size of its base and then of the object, each
+    // in at most 10 bytes
+    let reader = Reader {
+      data: &self.pack,
+      offset: entry.data,
+    };
+    let mut start = Vec::with_capacity(20);
+    ZlibDecoder::new(reader)
+      .take(20)
+      .read_to_end(&mut start)
+      .map_err(|err| err.to_string())?;
+    let mut bytes = start.into_iter();
+    delta_size(&mut bytes)?;
+    Ok((kind, delta_size(&mut bytes)?))
+  }
+
+  /// Reads the header of the entry at `offset` in the pack.
+  fn entry_at(&self, offset: u64) -> Result<Entry, String> {
+    let header = self.pack.window(offset, 32)?;
+    let mut used = 0;
+    let mut next = || -> Result<u8, String> {
+      let byte = header.get(used).ok_or("truncated object header")?;
+      used += 1;
+      Ok(*byte)
+    };
+
+    // the type and size, with the size in 7 bit groups, least significant
+    // first after the first 4 bits
+    let mut c = next()?;
+    let kind = (c >> 4) & 7;
+    let mut size = (c & 15) as usize;
+    let mut shift = 4;
+    while c & 0x80 != 0 {
+      c = next()?;
+      size += ((c & 0x7f) as usize) << shift;
+      shift += 7;
+    }
+
+    let base = match kind {
+      OFS_DELTA => {
+        // the distance back to the base, big endian, with one added to each
+        // group but the last so that every length has its own numbers
+        let mut c = next()?;
+        let mut distance = (c & 0x7f) as u64;
+        while c & 0x80 != 0 {
+          c = next()?;
+          distance = ((distance + 1) << 7) + (c & 0x7f) as u64;
+        }
+        let base_offset = offset
+          .checked_sub(distance)
+          .ok_or("delta base is before the start of the pack")?;
+        Some(Base::Offset(base_offset))
+      }
+      REF_DELTA => {
+        let id = self.pack.slice(offset + used as u64, 20)?;
+        used += 20;
+        Some(Base::Hash(hex::encode(&id[..])))
+      }
+      COMMIT | TREE | BLOB | TAG => None,
+      _ => return Err(format!("unknown object type {}", kind)),
+    };
+    Ok(Entry {
+      kind,
+      size,
+      base,
+      data: offset + used as u64,
+    })
+  }
+
+  /// Reads the object at `offset` in the pack, applying any deltas.
+  fn read_at(
+    &self,
+    offset: u64,
+    base: &dyn Fn(&str) -> Result<Vec<u8>, String>,
+  ) -> Result<(&'static str, Vec<u8>), String> {
+    if trace::enabled(trace::PACK_ACCESS) {
+      trace::print(
+        trace::PACK_ACCESS,
+        &format!("{} {}", self.path.display(), offset),
+      );
+    }
+    let entry = self.entry_at(offset)?;
+    let data = self.inflate(entry.data, entry.size)?;
+    match entry.base {
+      None => Ok((kind_name(entry.kind), data)),
+      Some(Base::Offset(base_offset)) => {
+        let (kind, base) = self.read_at(base_offset, base)?;
+        Ok((kind, apply_delta(&base, &data)?))
+      }
+      Some(Base::Hash(hash)) => {
+        let raw = base(&hash)?;
+        let (kind, _) = parse_header(&raw).ok_or("delta base has an unknown type")?;
+        let null = raw.iter().position(|&b| b == 0).unwrap_or(0);
+        Ok((kind, apply_delta(&raw[null + 1..], &data)?))
+      }
+    }
+  }
+
+  /// Decompresses `size` bytes from the zlib stream at `offset`.
+  fn inflate(&self, offset: u64, size: usize) -> Result<Vec<u8>, String> {
+    let reader = Reader {
+      data: &self.pack,
+      offset,
+    };
+    let mut data = Vec::with_capacity(size);
+    ZlibDecoder::new(reader)
+      .take(size as u64 + 1)
+      .read_to_end(&mut data)
+      .map_err(|err| err.to_string())?;
+    match data.len() == size {
+      true => Ok(data),
+      false => Err("inflated size does not match".to_string()),
+    }
+  }
+}
+
+/// The header of an entry in a pack.
+struct Entry {
+  kind: u8,
+
+  /// The size of the object, or for a delta, of the delta.
+  size: usize,
+
+  /// What a delta is against.
+  base: Option<Base>,
+
+  /// Where the compressed data starts.
+  data: u64,
+}
+
+/// The object a delta in a pack is against: one earlier in the same pack
+/// (`OFS_DELTA`), or any object, by hash (`REF_DELTA`).
+enum Base {
+  Offset(u64),
+  Hash(String),
+}
+
+/// The name of an object type as numbered in a pack, which must be one of
+/// the four that aren't deltas.
+fn kind_name(kind: u8) -> &'static str {
+  ["commit", "tree", "blob", "tag"][kind as usize - 1]
+}
+
+/// Parses the header an object starts with, like `blob 12\0`, into its type
+/// and size. Only the start of the object is needed.
+pub(crate) fn parse_header(raw: &[u8]) -> Option<Header> {
+  let null = raw.iter().position(|&b| b == 0)?;
+  let (kind, size) = std::str::from_utf8(&raw[..null]).ok()?.split_once(' ')?;
+  let kind = match kind {
+    "commit" => "commit",
+    "tree" => "tree",
+    "blob" => "blob",
+    "tag" => "tag",
+    _ => return None,
+  };
+  Some((kind, size.parse().ok()?))
+}
+
+/// Reads one of the sizes a delta starts with, in 7 bit groups, least
+/// significant first.
+fn delta_size(bytes: &mut impl Iterator<Item = u8>) -> Result<usize, String> {
+  let (mut size, mut shift) = (0, 0);
+  loop {
+    let c = bytes.next().ok_or("truncated delta")?;
+    size |= ((c & 0x7f) as usize) << shift;
+    shift += 7;
+    if c & 0x80 == 0 {
+      return Ok(size);
+    }
+  }
+}
+
+/// Rebuilds an object from the object it is a delta against.
+///
+/// A delta starts with the sizes of the base and the result, then is a list
+/// of instructions that either copy a range of the base or insert new bytes.
+pub(crate) fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, String> {
+  let mut bytes = delta.iter().copied();
+  if delta_size(&mut bytes)? != base.len() {
+    return Err("delta base has the wrong size".to_string());
+  }
+  let expected = delta_size(&mut bytes)?;
+
+  let mut result = Vec::with_capacity(expected);
+  while let Some(op) = bytes.next() {
+    if op & 0x80 != 0 {
+      // copy: the bits of `op` say which bytes of the offset and size follow
+      let (mut offset, mut len) = (0usize, 0usize);
+      for i in 0..7 {
+        if op & (1 << i) != 0 {
+          let byte = bytes.next().ok_or("truncated delta")? as usize;
+          match i {
+            0..=3 => offset |= byte << (i * 8),
+            _ => len |= byte << ((i - 4) * 8),
+          }
+        }
+      }
+      if len == 0 {
+        len = 0x10000;
+      }
+      let copied = base
+        .get(offset..offset + len)
+        .ok_or("delta copies past the end of its base")?;
+      result.extend_from_slice(copied);
+    } else if op != 0 {
+      // insert: `op` is the number of bytes that follow
+      for _ in 0..op {
+        result.push(bytes.next().ok_or("truncated delta")?);
+      }
+    } else {
+      return Err("delta has a reserved instruction".to_string());
+    }
+  }
+  match result.len() == expected {
+    true => Ok(result),
+    false => Err("delta produced the wrong size".to_string()),
+  }
+}
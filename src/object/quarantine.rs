@@ -0,0 +1,141 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+
+use super::database::{Database, DEFAULT_CACHE_LIMIT};
+use crate::repo::Repo;
+
+/// A temporary object directory that incoming objects are written to, so
+/// that a push which turns out to be bad leaves nothing behind.
+///
+/// This is what receive-pack does with the pack it is sent: the objects go
+/// into `objects/incoming-<n>`, which reads through to the repository's own
+/// objects as an alternate. Once the connectivity check and the hooks are
+/// happy, the objects are moved into the repository proper; otherwise the
+/// whole directory is deleted, which also happens if it is dropped without
+/// being migrated.
+///
+/// Hooks run in the meantime see the quarantined objects through
+/// `GIT_OBJECT_DIRECTORY` and `GIT_ALTERNATE_OBJECT_DIRECTORIES`, and are
+/// told where it is with `GIT_QUARANTINE_PATH`.
+pub struct Quarantine {
+  dir: PathBuf,
+  objects: PathBuf,
+}
+
+impl Quarantine {
+  /// Makes a new, empty quarantine in the object database of a repository.
+  pub fn new(repo: &Repo) -> Result<Self, String> {
+    let objects = repo.objects.dir().to_path_buf();
+    let mut n = 0;
+    let dir = loop {
+      let dir = objects.join(format!("incoming-{}-{}", process::id(), n));
+      match fs::create_dir(&dir) {
+        Ok(()) => break dir,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => n += 1,
+        Err(e) => {
+          return Err(format!(
+            "unable to create temporary object directory: {}",
+            e
+          ))
+        }
+      }
+    };
+    let quarantine = Self { dir, objects };
+    // so that anything reading the quarantine sees the repository's objects
+    // too, in this process or another
+    let info = quarantine.dir.join("info");
+    fs::create_dir(&info)
+      .and_then(|_| fs::write(info.join("alternates"), "..\n"))
+      .map_err(|e| format!("unable to create temporary object directory: {}", e))?;
+    Ok(quarantine)
+  }
+
+  /// The directory the objects are written to.
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// A handle on the repository that writes its objects into the
+  /// quarantine.
+  pub fn repo(&self, repo: &Repo) -> Repo {
+    Repo {
+      objects: Arc::new(Database::new(&self.dir, DEFAULT_CACHE_LIMIT)),
+      ..repo.clone()
+    }
+  }
+
+  /// The environment to run hooks in, for them to see the quarantined
+  /// objects.
+  pub fn env(&self) -> Vec<(&'static str, OsString)> {
+    vec![
+      ("GIT_OBJECT_DIRECTORY", self.dir.clone().into()),
+      (
+        "GIT_ALTERNATE_OBJECT_DIRECTORIES",
+        self.objects.clone().into(),
+      ),
+      ("GIT_QUARANTINE_PATH", self.dir.clone().into()),
+    ]
+  }
+
+  /// Moves the objects into the repository's own object database.
+  ///
+  /// Objects the repository already has are left as they are. The files of
+  /// a pack are moved with its index last, so that the pack is never seen
+  /// without all of its parts. What is left is deleted along with the
+  /// quarantine.
+  pub fn migrate(self) -> Result<(), String> {
+    let error =
+      |path: &Path, e: std::io::Error| format!("unable to migrate {}: {}", path.display(), e);
+    let mut loose: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(&self.dir)
+      .map_err(|e| error(&self.dir, e))?
+      .flatten()
+    {
+      let name = entry.file_name().to_string_lossy().into_owned();
+      if name.len() == 2 && name.bytes().all(|c| c.is_ascii_hexdigit()) {
+        for object in fs::read_dir(entry.path())
+          .map_err(|e| error(&entry.path(), e))?
+          .flatten()
+          // leaving out objects that were never finished
+          .filter(|object| !object.file_name().to_string_lossy().starts_with("tmp_"))
+        {
+          loose.push(Path::new(&name).join(object.file_name()));
+        }
+      }
+    }
+    let mut packs: Vec<PathBuf> = fs::read_dir(self.dir.join("pack"))
+      .into_iter()
+      .flatten()
+      .flatten()
+      .map(|entry| Path::new("pack").join(entry.file_name()))
+      .collect();
+    packs.sort_by_key(|path| {
+      (
+        path.extension().is_some_and(|ext| ext == "idx"),
+        path.clone(),
+      )
+    });
+
+    for path in packs.iter().chain(&loose) {
+      let (from, to) = (self.dir.join(path), self.objects.join(path));
+      if to.exists() {
+        continue;
+      }
+      if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|e| error(parent, e))?;
+      }
+      fs::rename(&from, &to).map_err(|e| error(&from, e))?;
+    }
+    Ok(())
+  }
+}
+
+impl Drop for Quarantine {
+  /// Throws away whatever objects were not migrated.
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.dir);
+  }
+}
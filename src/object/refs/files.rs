@@ -0,0 +1,422 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::{
+  check_updates, create_lock, LogEntry, PackOptions, Ref, RefDb, Transaction, Update, NULL_HASH,
+};
+
+/// The refs of a repository as git keeps them by default: a file for each
+/// ref under the git directory (`refs/heads/master`, `HEAD`), and
+/// `packed-refs`, which holds many refs in one file. A ref with a file of
+/// its own wins over the packed one.
+///
+/// Refs are only ever written as files, until they are packed into
+/// `packed-refs`. Deleting a ref removes it from both places. A transaction locks each ref it changes with a `<name>.lock` file
+/// next to it, which becomes the ref when it is committed.
+pub struct Files {
+  git_dir: PathBuf,
+
+  /// The packed refs, as they were last read.
+  packed: Mutex<Option<Packed>>,
+}
+
+/// The refs read from `packed-refs`, with when it was changed and how big
+/// it was then, so they are only read again when it changes.
+struct Packed {
+  modified: SystemTime,
+  len: u64,
+  refs: BTreeMap<String, String>,
+}
+
+impl Files {
+  /// The refs kept in a git directory.
+  pub fn new(git_dir: &Path) -> Self {
+    Self {
+      git_dir: git_dir.to_path_buf(),
+      packed: Mutex::new(None),
+    }
+  }
+
+  /// The refs in `packed-refs`, by name. Each line there is a hash and a
+  /// name, except for the header, which starts with `#`, and the peeled
+  /// hashes of tags, which start with `^`.
+  fn packed(&self) -> Result<BTreeMap<String, String>, String> {
+    let path = self.git_dir.join("packed-refs");
+    let metadata = match fs::metadata(&path) {
+      Ok(metadata) => metadata,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+      Err(e) => return Err(format!("unable to read packed-refs ({})", e)),
+    };
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut packed = self.packed.lock().unwrap();
+    if let Some(packed) = packed.as_ref() {
+      if packed.modified == modified && packed.len == metadata.len() {
+        return Ok(packed.refs.clone());
+      }
+    }
+    let data =
+      fs::read_to_string(&path).map_err(|e| format!("unable to read packed-refs ({})", e))?;
+    let refs: BTreeMap<String, String> = data
+      .lines()
+      .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+      .filter_map(|line| line.split_once(' '))
+      .map(|(hash, name)| (name.to_owned(), hash.to_owned()))
+      .collect();
+    *packed = Some(Packed {
+      modified,
+      len: metadata.len(),
+      refs: refs.clone(),
+    });
+    Ok(refs)
+  }
+
+  /// Takes the lock on `packed-refs`, which no one else may rewrite while
+  /// it is held. As it is only ever held for a moment, another process
+  /// holding it is waited on for a while.
+  fn lock_packed(&self) -> Result<PackedLock, String> {
+    let path = self.git_dir.join("packed-refs.lock");
+    let deadline = Instant::now() + PACKED_REFS_TIMEOUT;
+    loop {
+      match create_lock(&path) {
+        Ok(_) => return Ok(PackedLock(path)),
+        Err(e) if Instant::now() >= deadline => return Err(e),
+        Err(_) => thread::sleep(Duration::from_millis(10)),
+      }
+    }
+  }
+
+  /// Replaces `packed-refs`, which must be locked, through a temporary
+  /// file, so no one reads it half written.
+  fn write_packed(&self, data: &str) -> Result<(), String> {
+    let path = self.git_dir.join("packed-refs");
+    let temp = self.git_dir.join("packed-refs.new");
+    fs::write(&temp, data)
+      .and_then(|()| fs::rename(&temp, &path))
+      .map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        format!("unable to write packed-refs ({})", e)
+      })
+  }
+
+  /// Rewrites `packed-refs`, which must be locked, without a ref (and its
+  /// peeled hash).
+  fn unpack(&self, name: &str) -> Result<(), String> {
+    let path = self.git_dir.join("packed-refs");
+    let data = match fs::read_to_string(&path) {
+      Ok(data) => data,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+      Err(e) => return Err(format!("unable to read packed-refs ({})", e)),
+    };
+    let mut kept = String::new();
+    let mut dropping = false;
+    for line in data.lines() {
+      if line.starts_with('^') && dropping {
+        continue;
+      }
+      dropping = line.split_once(' ').map(|(_, packed)| packed) == Some(name);
+      if !dropping {
+        kept.push_str(line);
+        kept.push('\n');
+      }
+    }
+    if kept.len() == data.len() {
+      return Ok(());
+    }
+    self.write_packed(&kept)
+  }
+
+  /// The refs under `refs/` that have files of their own and point
+  /// straight at objects, by name. Symbolic refs are never packed.
+  fn loose(&self) -> BTreeMap<String, String> {
+    let mut names = Vec::new();
+    self.walk(&self.git_dir.join("refs"), &mut names);
+    names
+      .into_iter()
+      .filter_map(|name| {
+        let data = fs::read_to_string(self.git_dir.join(&name)).ok()?;
+        match parse(&data) {
+          Ref::Direct(hash) => Some((name, hash)),
+          Ref::Symbolic(_) => None,
+        }
+      })
+      .collect()
+  }
+
+  /// Deletes the file of a packed ref, if it still holds what was packed,
+  /// with the directories it leaves empty below `refs/<kind>/`. The ref is
+  /// locked meanwhile, so an update isn't lost.
+  fn prune(&self, name: &str, hash: &str) {
+    let path = self.git_dir.join(name);
+    let lock = lock_path(&path);
+    if create_lock(&lock).is_err() {
+      return;
+    }
+    if fs::read_to_string(&path).is_ok_and(|data| parse(&data) == Ref::Direct(hash.to_owned())) {
+      let _ = fs::remove_file(&path);
+    }
+    let _ = fs::remove_file(&lock);
+    let top = self.git_dir.join("refs");
+    let mut dir = path.parent();
+    while let Some(parent) = dir.filter(|dir| dir.parent() != Some(&top) && *dir != top) {
+      if fs::remove_dir(parent).is_err() {
+        break;
+      }
+      dir = parent.parent();
+    }
+  }
+
+  /// Adds the names of the ref files under a directory to `names`.
+  fn walk(&self, dir: &Path, names: &mut Vec<String>) {
+    let entries = match dir.read_dir() {
+      Ok(entries) => entries,
+      Err(_) => return,
+    };
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if path.is_dir() {
+        self.walk(&path, names);
+      } else if let Ok(name) = path.strip_prefix(&self.git_dir) {
+        let name = name.to_string_lossy();
+        if !name.ends_with(".lock") {
+          names.push(name.into_owned());
+        }
+      }
+    }
+  }
+}
+
+/// How long to wait for another process to let go of `packed-refs`.
+const PACKED_REFS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The lock on `packed-refs`, which is let go of when it is dropped.
+struct PackedLock(PathBuf);
+
+impl Drop for PackedLock {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.0);
+  }
+}
+
+/// A transaction holding the locks on the refs it changes.
+struct Prepared<'a> {
+  files: &'a Files,
+  updates: Vec<Update>,
+
+  /// The lock files still held, which are removed when it is dropped.
+  locks: Vec<PathBuf>,
+}
+
+impl Transaction for Prepared<'_> {
+  fn commit(mut self: Box<Self>) -> Result<(), String> {
+    for update in std::mem::take(&mut self.updates) {
+      let path = self.files.git_dir.join(&update.name);
+      let lock = lock_path(&path);
+      match update.new.as_deref() {
+        None => (),
+        Some(NULL_HASH) => self.files.delete(&update.name)?,
+        Some(hash) => {
+          fs::write(&lock, format!("{}\n", hash))
+            .and_then(|()| fs::rename(&lock, &path))
+            .map_err(|e| format!("unable to write {} ({})", update.name, e))?;
+          self.locks.retain(|held| *held != lock);
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Drop for Prepared<'_> {
+  fn drop(&mut self) {
+    for lock in &self.locks {
+      let _ = fs::remove_file(lock);
+    }
+  }
+}
+
+/// The lock file of a ref file.
+fn lock_path(path: &Path) -> PathBuf {
+  let mut lock = path.as_os_str().to_owned();
+  lock.push(".lock");
+  PathBuf::from(lock)
+}
+
+/// What a ref file holds: `ref: ` and the name of another ref, or a hash.
+fn parse(data: &str) -> Ref {
+  match data.strip_prefix("ref: ") {
+    Some(target) => Ref::Symbolic(target.trim_end().to_owned()),
+    None => Ref::Direct(data.trim_end().to_owned()),
+  }
+}
+
+impl RefDb for Files {
+  fn read(&self, name: &str) -> Result<Option<Ref>, String> {
+    let path = self.git_dir.join(name);
+    match fs::read_to_string(&path) {
+      Ok(data) => Ok(Some(parse(&data))),
+      Err(e) if e.kind() == ErrorKind::NotFound || path.is_dir() => {
+        Ok(self.packed()?.remove(name).map(Ref::Direct))
+      }
+      Err(e) => Err(format!("unable to read {} ({})", name, e)),
+    }
+  }
+
+  fn list(&self, prefix: &str) -> Result<Vec<(String, Ref)>, String> {
+    let mut refs: BTreeMap<String, Ref> = self
+      .packed()?
+      .into_iter()
+      .filter(|(name, _)| name.starts_with(prefix))
+      .map(|(name, hash)| (name, Ref::Direct(hash)))
+      .collect();
+    // only the directory the prefix ends in needs walking
+    let dir = &prefix[..prefix.rfind('/').map_or(0, |slash| slash + 1)];
+    let mut names = Vec::new();
+    self.walk(&self.git_dir.join(dir), &mut names);
+    for name in names {
+      if name.starts_with(prefix) {
+        if let Ok(data) = fs::read_to_string(self.git_dir.join(&name)) {
+          refs.insert(name, parse(&data));
+        }
+      }
+    }
+    Ok(refs.into_iter().collect())
+  }
+
+  fn write(&self, name: &str, value: &Ref) -> Result<(), String> {
+    let path = self.git_dir.join(name);
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(|e| format!("unable to create {} ({})", name, e))?;
+    }
+    let data = match value {
+      Ref::Direct(hash) => format!("{}\n", hash),
+      Ref::Symbolic(target) => format!("ref: {}\n", target),
+    };
+    fs::write(&path, data).map_err(|e| format!("unable to write {} ({})", name, e))
+  }
+
+  fn delete(&self, name: &str) -> Result<(), String> {
+    // packed-refs stays locked until the file is gone too, so that packing
+    // the refs meanwhile can't pack the ref again
+    let _lock = self.lock_packed()?;
+    self.unpack(name)?;
+    for path in [
+      self.git_dir.join(name),
+      self.git_dir.join("logs").join(name),
+    ] {
+      match fs::remove_file(&path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+          return Err(format!("unable to delete {} ({})", name, e))
+        }
+        _ => (),
+      }
+    }
+    Ok(())
+  }
+
+  fn append_log(&self, name: &str, entry: &LogEntry) -> Result<(), String> {
+    let line = format!(
+      "{} {} {}\t{}\n",
+      entry.old, entry.new, entry.identity, entry.message
+    );
+    let path = self.git_dir.join("logs").join(name);
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent)
+        .map_err(|e| format!("unable to create the log of {} ({})", name, e))?;
+    }
+    let mut file = fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&path)
+      .map_err(|e| format!("unable to append to the log of {} ({})", name, e))?;
+    file
+      .write_all(line.as_bytes())
+      .map_err(|e| format!("unable to append to the log of {} ({})", name, e))
+  }
+
+  fn read_log(&self, name: &str) -> Result<Vec<LogEntry>, String> {
+    let path = self.git_dir.join("logs").join(name);
+    let data = match fs::read_to_string(&path) {
+      Ok(data) => data,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(format!("unable to read the log of {} ({})", name, e)),
+    };
+    let entries = data.lines().filter_map(|line| {
+      let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+      let mut fields = head.splitn(3, ' ');
+      Some(LogEntry {
+        old: fields.next()?.to_owned(),
+        new: fields.next()?.to_owned(),
+        identity: fields.next()?.to_owned(),
+        message: message.to_owned(),
+      })
+    });
+    Ok(entries.collect())
+  }
+
+  fn prepare(&self, updates: &[Update]) -> Result<Box<dyn Transaction + '_>, String> {
+    let mut prepared = Prepared {
+      files: self,
+      updates: updates.to_vec(),
+      locks: Vec::new(),
+    };
+    for update in updates {
+      let lock = lock_path(&self.git_dir.join(&update.name));
+      // a ref updated twice is locked once, and checking the updates will
+      // turn it down
+      if prepared.locks.contains(&lock) {
+        continue;
+      }
+      // a ref can't be made where a ref is in the way of its directory,
+      // which checking the updates will say
+      let parent = lock.parent().unwrap_or(&self.git_dir);
+      if fs::create_dir_all(parent).is_err() {
+        continue;
+      }
+      create_lock(&lock).map_err(|e| format!("cannot lock ref '{}': {}", update.name, e))?;
+      prepared.locks.push(lock);
+    }
+    check_updates(self, updates)?;
+    Ok(Box::new(prepared))
+  }
+
+  fn unpacked(&self) -> Result<usize, String> {
+    Ok(self.loose().len())
+  }
+
+  /// Writes the refs under `refs/` that have files of their own into
+  /// `packed-refs`, with the hashes the tags among them peel to, while it is
+  /// locked. The refs are read afresh, so none of them is lost to a change
+  /// made meanwhile.
+  fn pack(&self, options: &PackOptions) -> Result<(), String> {
+    let _lock = self.lock_packed()?;
+    let mut packed = self.packed()?;
+    let loose: BTreeMap<String, String> = self
+      .loose()
+      .into_iter()
+      .filter(|(name, _)| {
+        options.all || name.starts_with("refs/tags/") || packed.contains_key(name)
+      })
+      .collect();
+    packed.extend(loose.clone());
+    let mut data = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+    for (name, hash) in &packed {
+      data.push_str(&format!("{} {}\n", hash, name));
+      if let Some(peeled) = (options.peel)(hash) {
+        data.push_str(&format!("^{}\n", peeled));
+      }
+    }
+    self.write_packed(&data)?;
+    if !options.prune {
+      return Ok(());
+    }
+    for (name, hash) in &loose {
+      self.prune(name, hash);
+    }
+    Ok(())
+  }
+}
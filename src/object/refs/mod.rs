@@ -0,0 +1,534 @@
+pub mod files;
+pub mod pseudo;
+pub mod reftable;
+
+use crate::object;
+use crate::repo::Repo;
+use ini::Ini as ConfigParser;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+
+pub use files::Files;
+pub use reftable::Reftable;
+
+/// What a ref holds: the hash of an object, or the name of another ref it
+/// stands for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ref {
+  Direct(String),
+  Symbolic(String),
+}
+
+/// An entry in the reflog of a ref: that it moved from `old` to `new`, who
+/// moved it (`Name <email> <seconds> <timezone>`) and why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogEntry {
+  pub old: String,
+  pub new: String,
+  pub identity: String,
+  pub message: String,
+}
+
+/// The hash that stands for no object at all: in an [`Update`], a ref that
+/// must not exist yet, or that is to be deleted.
+pub const NULL_HASH: &str = "0000000000000000000000000000000000000000";
+
+/// A change to one ref, made as part of a transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Update {
+  /// The ref, named in full.
+  pub name: String,
+
+  /// The hash to point the ref at, [`NULL_HASH`] to delete it, or `None`
+  /// to leave it as it is and only check it.
+  pub new: Option<String>,
+
+  /// The hash the ref must point at for the transaction to go ahead,
+  /// [`NULL_HASH`] if it must not exist, or `None` if it doesn't matter.
+  pub old: Option<String>,
+}
+
+/// Which refs [`RefDb::pack`] packs, and how.
+pub struct PackOptions<'a> {
+  /// Whether to pack every ref, instead of only the tags and the refs
+  /// that were packed before.
+  pub all: bool,
+
+  /// Whether to delete what the refs were kept in before, once they are
+  /// packed.
+  pub prune: bool,
+
+  /// What an annotated tag peels to in the end, to keep with it; `None` for
+  /// anything else.
+  pub peel: &'a dyn Fn(&str) -> Option<String>,
+}
+
+/// A transaction that has been prepared: the refs it changes are locked,
+/// and hold what it expects. Dropping it without committing it aborts it,
+/// and lets go of the locks.
+pub trait Transaction {
+  /// Makes the changes.
+  fn commit(self: Box<Self>) -> Result<(), String>;
+}
+
+/// Where the refs of a repository are kept.
+///
+/// Everything that reads or moves refs goes through the [`Repo`]'s `refs`,
+/// so another way of keeping them (in a reftable, or in a database on a
+/// server) only has to implement this, and be put there. By default refs
+/// are kept as git keeps them, in [`Files`].
+///
+/// Refs are named in full, like `HEAD` or `refs/heads/master`, and are read
+/// and written as they are: symbolic refs are not followed.
+pub trait RefDb: Send + Sync {
+  /// Reads a ref, or `None` if there is no such ref.
+  fn read(&self, name: &str) -> Result<Option<Ref>, String>;
+
+  /// The refs whose names start with `prefix` (say `refs/tags/`), in order
+  /// of their names.
+  fn list(&self, prefix: &str) -> Result<Vec<(String, Ref)>, String>;
+
+  /// Points a ref at an object or another ref, creating it if it does not
+  /// exist.
+  fn write(&self, name: &str, value: &Ref) -> Result<(), String>;
+
+  /// Deletes a ref and its reflog. Deleting a ref that does not exist does
+  /// nothing.
+  fn delete(&self, name: &str) -> Result<(), String>;
+
+  /// Appends an entry to the reflog of a ref.
+  fn append_log(&self, name: &str, entry: &LogEntry) -> Result<(), String>;
+
+  /// The reflog of a ref, oldest first, or nothing if it has none.
+  fn read_log(&self, name: &str) -> Result<Vec<LogEntry>, String>;
+
+  /// Prepares to make changes to refs all at once, or not at all: locks
+  /// the refs, and checks them (see [`check_updates`]).
+  ///
+  /// By default, nothing is locked, and the changes are made one at a
+  /// time, which is only all at once for refs that nothing else changes
+  /// at the same time.
+  fn prepare(&self, updates: &[Update]) -> Result<Box<dyn Transaction + '_>, String> {
+    check_updates(self, updates)?;
+    Ok(Box::new(Sequential {
+      refs: self,
+      updates: updates.to_vec(),
+    }))
+  }
+
+  /// How many refs (or whatever they are kept in) [`pack`](RefDb::pack)
+  /// would pack, for deciding when it is worth it.
+  fn unpacked(&self) -> Result<usize, String> {
+    Ok(0)
+  }
+
+  /// Packs the refs, so that there are fewer places to read them from. By
+  /// default there is nothing to pack.
+  fn pack(&self, _options: &PackOptions) -> Result<(), String> {
+    Ok(())
+  }
+}
+
+/// The transaction [`RefDb::prepare`] makes by default, which makes its
+/// changes one at a time.
+struct Sequential<'a, D: RefDb + ?Sized> {
+  refs: &'a D,
+  updates: Vec<Update>,
+}
+
+impl<D: RefDb + ?Sized> Transaction for Sequential<'_, D> {
+  fn commit(self: Box<Self>) -> Result<(), String> {
+    for update in &self.updates {
+      match update.new.as_deref() {
+        None => (),
+        Some(NULL_HASH) => self.refs.delete(&update.name)?,
+        Some(hash) => self
+          .refs
+          .write(&update.name, &Ref::Direct(hash.to_owned()))?,
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Checks that changes to refs can be made together: no ref is changed
+/// twice, each holds what its update expects (following symbolic refs), and
+/// no new ref would need a directory where there is a ref, or the other way
+/// round (`refs/heads/a` and `refs/heads/a/b` can't both exist).
+///
+/// Errors are as git words them, eg. `cannot lock ref 'refs/heads/main': is
+/// at <hash> but expected <hash>`.
+pub fn check_updates<D: RefDb + ?Sized>(refs: &D, updates: &[Update]) -> Result<(), String> {
+  let mut names = HashSet::new();
+  for update in updates {
+    if !names.insert(&update.name) {
+      return Err(format!(
+        "multiple updates for ref '{}' not allowed",
+        update.name
+      ));
+    }
+  }
+  for update in updates {
+    let name = &update.name;
+    let current = peel(refs, name)?;
+    let problem = match (update.old.as_deref(), &current) {
+      (Some(NULL_HASH), Some(_)) => Some("reference already exists".to_string()),
+      (Some(old), Some(current)) if old != current => {
+        Some(format!("is at {} but expected {}", current, old))
+      }
+      (Some(old), None) if old != NULL_HASH => {
+        Some(format!("unable to resolve reference '{}'", name))
+      }
+      _ => None,
+    };
+    let creating = current.is_none() && update.new.as_deref().is_some_and(|new| new != NULL_HASH);
+    let problem = match problem {
+      Some(problem) => Some(problem),
+      None if creating => {
+        let parent = name
+          .match_indices('/')
+          .map(|(slash, _)| &name[..slash])
+          .find(|parent| matches!(refs.read(parent), Ok(Some(_))));
+        let child = refs.list(&format!("{}/", name))?.into_iter().next();
+        match (parent, child) {
+          (Some(parent), _) => Some(format!("'{}' exists; cannot create '{}'", parent, name)),
+          (None, Some((child, _))) => Some(format!("'{}' exists; cannot create '{}'", child, name)),
+          (None, None) => None,
+        }
+      }
+      None => None,
+    };
+    if let Some(problem) = problem {
+      return Err(format!("cannot lock ref '{}': {}", name, problem));
+    }
+  }
+  Ok(())
+}
+
+/// The hash a ref points at, following symbolic refs, or `None` if there is
+/// no such ref (or it is a symbolic ref to nothing).
+fn peel<D: RefDb + ?Sized>(refs: &D, name: &str) -> Result<Option<String>, String> {
+  let mut name = name.to_owned();
+  for _ in 0..=MAX_SYMREF_DEPTH {
+    match refs.read(&name)? {
+      Some(Ref::Direct(hash)) => return Ok(Some(hash)),
+      Some(Ref::Symbolic(target)) => name = target,
+      None => return Ok(None),
+    }
+  }
+  Err(format!("{} is a symbolic ref loop", name))
+}
+
+/// Takes a lock, by creating its file, which must not exist already.
+pub(crate) fn create_lock(path: &Path) -> Result<File, String> {
+  OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(path)
+    .map_err(|e| match e.kind() {
+      ErrorKind::AlreadyExists => format!(
+        "Unable to create '{}': File exists.\n\nAnother git process seems to be running in this repository.",
+        path.display()
+      ),
+      _ => format!("Unable to create '{}': {}", path.display(), e),
+    })
+}
+
+/// Opens the refs of the repository in a git directory, kept the way its
+/// `extensions.refStorage` says: `files` (the default), or `reftable`.
+pub fn open(git_dir: &Path, config: Option<&ConfigParser>) -> Result<Arc<dyn RefDb>, String> {
+  let format = config
+    .and_then(|config| config.section(Some("extensions")))
+    .and_then(|extensions| {
+      extensions
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("refStorage"))
+    })
+    .map_or("files", |(_, value)| value);
+  match format {
+    "files" => Ok(Arc::new(Files::new(git_dir))),
+    "reftable" => Ok(Arc::new(Reftable::new(git_dir))),
+    format => Err(format!(
+      "invalid value for 'extensions.refstorage': '{}'",
+      format
+    )),
+  }
+}
+
+/// How many symbolic refs may be followed to get to an object, which stops
+/// refs that point at each other going round forever.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// The full name of a ref given as a path, either relative to the git
+/// directory or under it.
+fn name_of(repo: &Repo, path: &Path) -> String {
+  let name = path.strip_prefix(&repo.git_dir).unwrap_or(path);
+  name.to_string_lossy().into_owned()
+}
+
+/// Reads a ref as it is, from the repository's refs or, for `FETCH_HEAD`
+/// and `MERGE_HEAD`, from their own files (see [`pseudo`]).
+fn read(repo: &Repo, name: &str) -> Result<Option<Ref>, String> {
+  if pseudo::SPECIAL.contains(&name) {
+    return Ok(pseudo::read_special(repo, name)?.map(Ref::Direct));
+  }
+  repo.refs.read(name)
+}
+
+/// Resolves a ref path to an object hash.
+///
+/// A ref associates a name to a particular git object. Refs can either be
+/// direct or indirect. A direct ref holds the SHA-1 hash of the object that
+/// the ref refers to. An indirect ref holds the name of another ref instead
+/// (which might, in turn, point at another indirect ref). Indirect refs must
+/// be recursively resolved.
+///
+/// The ref may be named by its path under the git directory, either
+/// relative to it (eg. `refs/heads/master`) or not.
+pub fn resolve(repo: &Repo, refr: &Path) -> Result<String, String> {
+  let mut name = name_of(repo, refr);
+  for _ in 0..=MAX_SYMREF_DEPTH {
+    match read(repo, &name)? {
+      Some(Ref::Direct(hash)) => return Ok(hash),
+      Some(Ref::Symbolic(target)) => name = target,
+      None => return Err(format!("{} is not a ref", name)),
+    }
+  }
+  Err(format!("{} is a symbolic ref loop", name_of(repo, refr)))
+}
+
+/// Collects refs and returns them as an ordered dictionary.
+///
+/// Gathers the refs under `refs/`, or under the directory given (eg.
+/// `refs/tags`), into a map between their full names and hashes. Each ref is
+/// resolved into a hash before being stored, and symbolic refs to nothing
+/// are left out.
+pub fn collect(repo: &Repo, path: Option<&Path>) -> BTreeMap<String, String> {
+  let prefix = match path {
+    Some(path) => format!("{}/", name_of(repo, path).trim_end_matches('/')),
+    None => "refs/".to_string(),
+  };
+  let mut map = BTreeMap::new();
+  for (name, value) in repo.refs.list(&prefix).unwrap_or_default() {
+    let hash = match value {
+      Ref::Direct(hash) => Some(hash),
+      Ref::Symbolic(target) => resolve(repo, Path::new(&target)).ok(),
+    };
+    if let Some(hash) = hash {
+      map.insert(name, hash);
+    }
+  }
+  map
+}
+
+/// Expands a short ref name and resolves the first matching ref to a hash.
+///
+/// Names are tried in the same order as git: the name as given (eg. `HEAD` or
+/// `refs/heads/master`), then under `refs/`, `refs/tags/`, `refs/heads/`,
+/// `refs/remotes/` and finally as the `HEAD` of a remote.
+pub fn lookup(repo: &Repo, name: &str) -> Option<String> {
+  expand(name)
+    .filter(|candidate| exists(repo, candidate))
+    .find_map(|candidate| resolve(repo, Path::new(&candidate)).ok())
+}
+
+/// The ways a short name is expanded to the name of a ref, in the order
+/// they are tried: the prefix and suffix put around it.
+const RULES: [(&str, &str); 6] = [
+  ("", ""),
+  ("refs/", ""),
+  ("refs/tags/", ""),
+  ("refs/heads/", ""),
+  ("refs/remotes/", ""),
+  ("refs/remotes/", "/HEAD"),
+];
+
+/// The full names a short ref name could stand for, in the order [`lookup`]
+/// tries them.
+pub fn expand(name: &str) -> impl Iterator<Item = String> + '_ {
+  RULES
+    .iter()
+    .map(move |(prefix, suffix)| format!("{}{}{}", prefix, name, suffix))
+}
+
+/// The shortest name that [`lookup`] would expand to a ref, eg. `master`
+/// for `refs/heads/master`, or `heads/v1` for `refs/heads/v1` when there is
+/// also a tag `v1` that `v1` would find first. A name that can't be made
+/// shorter is kept as it is.
+pub fn shorten(repo: &Repo, name: &str) -> String {
+  for (i, (prefix, suffix)) in RULES.iter().enumerate().skip(1).rev() {
+    let short = match name
+      .strip_prefix(prefix)
+      .and_then(|rest| rest.strip_suffix(suffix))
+    {
+      Some(short) if !short.is_empty() => short,
+      _ => continue,
+    };
+    let ambiguous = RULES[..i]
+      .iter()
+      .any(|(prefix, suffix)| exists(repo, &format!("{}{}{}", prefix, short, suffix)));
+    if !ambiguous {
+      return short.to_owned();
+    }
+  }
+  name.to_owned()
+}
+
+/// Whether there is a ref of this name, even one that points at nothing.
+pub fn exists(repo: &Repo, name: &str) -> bool {
+  matches!(read(repo, name), Ok(Some(_)))
+}
+
+/// Reads the target of a symbolic ref, eg. `refs/heads/master` for `HEAD`.
+///
+/// Returns `None` if the ref is missing or is a direct ref (such as a
+/// detached `HEAD`).
+pub fn read_symbolic(repo: &Repo, name: &str) -> Option<String> {
+  match read(repo, name) {
+    Ok(Some(Ref::Symbolic(target))) => Some(target),
+    _ => None,
+  }
+}
+
+/// The branch `HEAD` is on when it has no commits yet, as in a freshly
+/// initialized repository, eg. `refs/heads/master`.
+///
+/// Returns `None` if the branch has commits or `HEAD` is detached.
+pub fn unborn(repo: &Repo) -> Option<String> {
+  let branch = read_symbolic(repo, "HEAD")?;
+  match resolve(repo, Path::new(&branch)) {
+    Ok(_) => None,
+    Err(_) => Some(branch),
+  }
+}
+
+/// Where a detached `HEAD` was detached, as the last `checkout: moving
+/// from <old> to <new>` entry of its reflog has it, and the commit that was.
+///
+/// Like git, the name is kept only if it is a tag or a remote-tracking
+/// branch that still points at that commit, eg. `v1.0` or `origin/main`;
+/// anything else (a branch, `HEAD`, a hash) is shown as the commit,
+/// abbreviated to seven digits.
+///
+/// Returns `None` if `HEAD` is on a branch or its reflog doesn't say.
+pub fn detached_from(repo: &Repo) -> Option<(String, String)> {
+  if read_symbolic(repo, "HEAD").is_some() {
+    return None;
+  }
+  let log = repo.refs.read_log("HEAD").ok()?;
+  log.iter().rev().find_map(|entry| {
+    let (_, to) = entry
+      .message
+      .strip_prefix("checkout: moving from ")?
+      .rsplit_once(" to ")?;
+    let name = expand(to)
+      .find(|candidate| exists(repo, candidate))
+      .filter(|full| {
+        resolve(repo, Path::new(full))
+          .and_then(|hash| object::peel(repo, &hash, Some("commit")))
+          .is_ok_and(|commit| commit == entry.new)
+      })
+      .and_then(|full| {
+        let short = full
+          .strip_prefix("refs/tags/")
+          .or_else(|| full.strip_prefix("refs/remotes/"))?;
+        Some(short.to_owned())
+      })
+      .unwrap_or_else(|| entry.new[..7].to_owned());
+    Some((name, entry.new.clone()))
+  })
+}
+
+/// Points a ref at an object, creating it if it does not exist.
+///
+/// Symbolic refs are followed, so updating `HEAD` while on a branch moves the
+/// branch rather than detaching `HEAD`.
+pub fn update(repo: &Repo, name: &str, hash: &str) -> Result<(), String> {
+  let mut name = name.to_owned();
+  while let Some(target) = read_symbolic(repo, &name) {
+    name = target;
+  }
+  if pseudo::SPECIAL.contains(&name.as_str()) {
+    return pseudo::write_special(repo, &name, hash);
+  }
+  repo.refs.write(&name, &Ref::Direct(hash.to_owned()))
+}
+
+/// Points a symbolic ref at another ref, eg. `HEAD` at `refs/heads/master`.
+pub fn update_symbolic(repo: &Repo, name: &str, target: &str) -> Result<(), String> {
+  repo.refs.write(name, &Ref::Symbolic(target.to_owned()))
+}
+
+/// Points `HEAD` directly at a commit rather than at a branch.
+pub fn detach_head(repo: &Repo, hash: &str) -> Result<(), String> {
+  repo.refs.write("HEAD", &Ref::Direct(hash.to_owned()))
+}
+
+/// Deletes a ref, and its reflog. Symbolic refs are not followed, so it is
+/// the ref itself that goes.
+pub fn delete(repo: &Repo, name: &str) -> Result<(), String> {
+  if pseudo::SPECIAL.contains(&name) {
+    return pseudo::remove(repo, name);
+  }
+  repo.refs.delete(name)
+}
+
+/// Packs the refs of a repository (see [`RefDb::pack`]): every ref with
+/// `all`, or else only the tags and the refs packed before, and deletes
+/// their files with `prune`. Tags are packed with what they peel to.
+pub fn pack(repo: &Repo, all: bool, prune: bool) -> Result<(), String> {
+  let peel = |hash: &str| {
+    super::peel(repo, hash, None)
+      .ok()
+      .filter(|peeled| peeled != hash)
+  };
+  repo.refs.pack(&PackOptions {
+    all,
+    prune,
+    peel: &peel,
+  })
+}
+
+/// Appends an entry to the reflog of a ref, recording that it moved from
+/// `old` (`None` if it did not exist) to `new`.
+///
+/// Each line of `.git/logs/<name>` reads
+/// `<old> <new> Name <email> <seconds> <timezone>\t<message>`, where the
+/// identity is the committer making the change.
+pub fn append_log(
+  repo: &Repo,
+  name: &str,
+  old: Option<&str>,
+  new: &str,
+  identity: &str,
+  message: &str,
+) -> Result<(), String> {
+  let entry = LogEntry {
+    old: old.unwrap_or(NULL_HASH).to_owned(),
+    new: new.to_owned(),
+    identity: identity.to_owned(),
+    message: message.to_owned(),
+  };
+  repo.refs.append_log(name, &entry)
+}
+
+/// Checks that a ref name is one git allows.
+///
+/// No part of the name between slashes may be empty, begin with `.` or end
+/// with `.lock`. The name may not contain `..`, `@{`, control characters,
+/// spaces or any of `~^:?*[\`, may not end with `.` and may not be just `@`.
+pub fn check_name(name: &str) -> bool {
+  name != "@"
+    && !name.ends_with('.')
+    && !name.contains("..")
+    && !name.contains("@{")
+    && !name
+      .chars()
+      .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+    && name
+      .split('/')
+      .all(|part| !part.is_empty() && !part.starts_with('.') && !part.ends_with(".lock"))
+}
@@ -0,0 +1,137 @@
+//! The refs that merges, rebases and fetches leave beside `HEAD` while they
+//! work, for the commands that come after them.
+//!
+//! `ORIG_HEAD` (where `HEAD` was before a reset, rebase or merge moved it),
+//! `CHERRY_PICK_HEAD` and `REBASE_HEAD` are refs like any other, and are
+//! kept wherever the repository keeps its refs. `FETCH_HEAD` and
+//! `MERGE_HEAD` may hold more than one object, a line for each branch
+//! fetched or each commit being merged, so they are always files of their
+//! own in the git directory. As a revision, each names the object on its
+//! first line.
+
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+
+use crate::repo::Repo;
+
+/// The refs that are always files in the git directory, whatever keeps the
+/// others.
+pub const SPECIAL: [&str; 2] = ["FETCH_HEAD", "MERGE_HEAD"];
+
+/// A branch that `fetch` brought in, as a line of `FETCH_HEAD` records it:
+/// `<hash>\t<not-for-merge>\t<description>`, where the middle field is
+/// empty for the branches a `pull` goes on to merge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchHead {
+  pub hash: String,
+
+  /// Whether the branch is one to merge, rather than only fetched.
+  pub merge: bool,
+
+  /// Where it came from, eg. `branch 'main' of https://example.com/repo`.
+  pub description: String,
+}
+
+impl FetchHead {
+  /// Parses a line of `FETCH_HEAD`, or returns `None` if it is not one.
+  pub fn parse(line: &str) -> Option<Self> {
+    let mut fields = line.splitn(3, '\t');
+    let hash = fields.next().filter(|hash| is_hash(hash))?;
+    let merge = match fields.next()? {
+      "" => true,
+      "not-for-merge" => false,
+      _ => return None,
+    };
+    Some(Self {
+      hash: hash.to_owned(),
+      merge,
+      description: fields.next().unwrap_or_default().to_owned(),
+    })
+  }
+}
+
+impl fmt::Display for FetchHead {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let merge = if self.merge { "" } else { "not-for-merge" };
+    write!(f, "{}\t{}\t{}", self.hash, merge, self.description)
+  }
+}
+
+/// Reads one of the [`SPECIAL`] refs as a revision: the object on its first
+/// line, or `None` if there is no such file.
+pub fn read_special(repo: &Repo, name: &str) -> Result<Option<String>, String> {
+  let hash = read_file(repo, name)?.and_then(|data| {
+    let first = data.lines().next().unwrap_or_default();
+    let hash = first.split(['\t', ' ']).next().unwrap_or_default();
+    is_hash(hash).then(|| hash.to_owned())
+  });
+  Ok(hash)
+}
+
+/// The branches the last fetch brought in, in the order it wrote them.
+pub fn read_fetch_head(repo: &Repo) -> Result<Vec<FetchHead>, String> {
+  let data = read_file(repo, "FETCH_HEAD")?.unwrap_or_default();
+  data
+    .lines()
+    .map(|line| {
+      FetchHead::parse(line).ok_or_else(|| format!("malformed FETCH_HEAD line '{}'", line))
+    })
+    .collect()
+}
+
+/// Records the branches a fetch brought in, replacing what was there.
+pub fn write_fetch_head(repo: &Repo, heads: &[FetchHead]) -> Result<(), String> {
+  let data: String = heads.iter().map(|head| format!("{}\n", head)).collect();
+  write_file(repo, "FETCH_HEAD", &data)
+}
+
+/// The commits being merged into `HEAD`, or nothing if no merge is in
+/// progress.
+pub fn read_merge_heads(repo: &Repo) -> Result<Vec<String>, String> {
+  let data = read_file(repo, "MERGE_HEAD")?.unwrap_or_default();
+  data
+    .lines()
+    .map(|line| match is_hash(line) {
+      true => Ok(line.to_owned()),
+      false => Err(format!("malformed MERGE_HEAD line '{}'", line)),
+    })
+    .collect()
+}
+
+/// Records the commits being merged into `HEAD`, one to a line.
+pub fn write_merge_heads(repo: &Repo, hashes: &[String]) -> Result<(), String> {
+  let data: String = hashes.iter().map(|hash| format!("{}\n", hash)).collect();
+  write_file(repo, "MERGE_HEAD", &data)
+}
+
+/// Writes one of the [`SPECIAL`] refs as holding a single object.
+pub fn write_special(repo: &Repo, name: &str, hash: &str) -> Result<(), String> {
+  write_file(repo, name, &format!("{}\n", hash))
+}
+
+/// Removes one of the [`SPECIAL`] refs. Removing one that is not there does
+/// nothing.
+pub fn remove(repo: &Repo, name: &str) -> Result<(), String> {
+  match fs::remove_file(repo.git_dir.join(name)) {
+    Err(e) if e.kind() != ErrorKind::NotFound => Err(format!("unable to remove {} ({})", name, e)),
+    _ => Ok(()),
+  }
+}
+
+fn read_file(repo: &Repo, name: &str) -> Result<Option<String>, String> {
+  match fs::read_to_string(repo.git_dir.join(name)) {
+    Ok(data) => Ok(Some(data)),
+    Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(format!("unable to read {} ({})", name, e)),
+  }
+}
+
+fn write_file(repo: &Repo, name: &str, data: &str) -> Result<(), String> {
+  fs::write(repo.git_dir.join(name), data).map_err(|e| format!("unable to write {} ({})", name, e))
+}
+
+/// Whether a string is a full object hash.
+fn is_hash(text: &str) -> bool {
+  text.len() == 40 && text.bytes().all(|b| b.is_ascii_hexdigit())
+}
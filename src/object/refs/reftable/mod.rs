@@ -0,0 +1,382 @@
+pub mod table;
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{
+  check_updates, create_lock, LogEntry, PackOptions, Ref, RefDb, Transaction, Update, NULL_HASH,
+};
+use table::{LogRecord, RefRecord, Table};
+
+/// The refs of a repository kept in reftables, under `reftable/` in the git
+/// directory, as `extensions.refStorage = reftable` asks for.
+///
+/// `tables.list` names the tables, oldest first. Every change to the refs
+/// or their logs is a transaction, written as a new table and added to the
+/// list, which is replaced whole, so a change is made all at once or not at
+/// all. A ref is what the newest table to mention it says, and deleting one
+/// adds a record of its deletion.
+///
+/// So that the list stays short, the newest tables are merged into one
+/// whenever one is not at least twice the size of all that came after it,
+/// which keeps the number of tables to the logarithm of the number of refs.
+pub struct Reftable {
+  dir: PathBuf,
+
+  /// The tables, by name, as `tables.list` last listed them.
+  stack: Mutex<Vec<(String, Arc<Table>)>>,
+}
+
+impl Reftable {
+  /// The refs kept in reftables in a git directory.
+  pub fn new(git_dir: &Path) -> Self {
+    Self {
+      dir: git_dir.join("reftable"),
+      stack: Mutex::new(Vec::new()),
+    }
+  }
+
+  /// Sets up the directory of a new repository to keep its refs in
+  /// reftables, with no tables yet.
+  pub fn init(git_dir: &Path) -> Result<(), String> {
+    let dir = git_dir.join("reftable");
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let list = dir.join("tables.list");
+    if !list.exists() {
+      fs::write(&list, "").map_err(|e| format!("{}: {}", list.display(), e))?;
+    }
+    Ok(())
+  }
+
+  /// The tables, oldest first, as `tables.list` names them now. Tables that
+  /// were read before are not read again.
+  fn tables(&self) -> Result<Vec<(String, Arc<Table>)>, String> {
+    let list = self.dir.join("tables.list");
+    let names = fs::read_to_string(&list).map_err(|e| format!("{}: {}", list.display(), e))?;
+    let mut stack = self.stack.lock().unwrap();
+    let mut tables = Vec::new();
+    for name in names.lines().filter(|name| !name.is_empty()) {
+      let table = match stack.iter().find(|(read, _)| read == name) {
+        Some((_, table)) => table.clone(),
+        None => {
+          let path = self.dir.join(name);
+          let data = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+          Arc::new(Table::parse(data).map_err(|e| format!("{}: {}", name, e))?)
+        }
+      };
+      tables.push((name.to_owned(), table));
+    }
+    *stack = tables.clone();
+    Ok(tables)
+  }
+
+  /// Takes the lock on `tables.list`, which no one else may change while
+  /// it is held.
+  fn lock(&self) -> Result<Lock<'_>, String> {
+    let path = self.dir.join("tables.list.lock");
+    create_lock(&path)?;
+    Ok(Lock {
+      reftable: self,
+      path,
+      held: true,
+    })
+  }
+
+  /// Makes a change to the refs and their logs in one transaction.
+  fn commit(&self, refs: Vec<RefRecord>, logs: Vec<LogRecord>) -> Result<(), String> {
+    self.lock()?.commit(refs, logs)
+  }
+
+  /// Records that deletes each entry in the log of a ref, each with the key
+  /// of the entry it deletes.
+  fn delete_log(&self, name: &str) -> Result<Vec<LogRecord>, String> {
+    let mut logs = Vec::new();
+    for (_, table) in self.tables()? {
+      for record in table.logs()? {
+        if record.name == name {
+          logs.push(LogRecord {
+            entry: None,
+            ..record
+          });
+        }
+      }
+    }
+    Ok(logs)
+  }
+
+  /// Writes the table of a transaction while `tables.list` is locked,
+  /// returning the tables to list, and those no longer needed. The records
+  /// are numbered as the transaction, except for deletions of log entries,
+  /// which are numbered as the entries they delete.
+  fn commit_locked(
+    &self,
+    refs: &mut [RefRecord],
+    logs: &mut [LogRecord],
+  ) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut tables = self.tables()?;
+    let update_index = tables
+      .last()
+      .map_or(1, |(_, table)| table.max_update_index + 1);
+    for record in refs.iter_mut() {
+      record.update_index = update_index;
+    }
+    for record in logs.iter_mut().filter(|record| record.entry.is_some()) {
+      record.update_index = update_index;
+    }
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+    logs.sort_by_key(|record| (record.name.clone(), Reverse(record.update_index)));
+    let data = table::write(refs, logs, update_index, update_index)?;
+    let name = self.add(&data, update_index, update_index)?;
+    tables.push((name, Arc::new(Table::parse(data)?)));
+
+    // merge the newest tables until each is at least twice the size of all
+    // that come after it
+    let mut start = tables.len() - 1;
+    let mut newer = tables[start].1.size();
+    while start > 0 && tables[start - 1].1.size() < 2 * newer {
+      start -= 1;
+      newer += tables[start].1.size();
+    }
+    let mut old = Vec::new();
+    if start < tables.len() - 1 {
+      let merged = &tables[start..];
+      let (refs, logs) = merge(merged, start == 0)?;
+      let (min, max) = (
+        merged[0].1.min_update_index,
+        merged[merged.len() - 1].1.max_update_index,
+      );
+      let data = table::write(&refs, &logs, min, max)?;
+      let name = self.add(&data, min, max)?;
+      old = tables
+        .drain(start..)
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+      tables.push((name, Arc::new(Table::parse(data)?)));
+    }
+    Ok((tables.into_iter().map(|(name, _)| name).collect(), old))
+  }
+
+  /// Writes out a table, named for the transactions it holds and a random
+  /// number, returning its name.
+  fn add(&self, data: &[u8], min: u64, max: u64) -> Result<String, String> {
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default();
+    let random = now.subsec_nanos() ^ std::process::id().rotate_left(16);
+    let name = format!("0x{:012x}-0x{:012x}-{:08x}.ref", min, max, random);
+    let path = self.dir.join(&name);
+    fs::write(&path, data).map_err(|e| format!("unable to write {} ({})", path.display(), e))?;
+    Ok(name)
+  }
+}
+
+/// The lock on `tables.list`, which is let go of when it is dropped, unless
+/// it has become the new list.
+struct Lock<'a> {
+  reftable: &'a Reftable,
+  path: PathBuf,
+  held: bool,
+}
+
+impl Lock<'_> {
+  /// Writes a table of the changes numbered after the newest table, merges
+  /// the newest tables if they need it, and lists the result in place of
+  /// what it was made from.
+  fn commit(self, mut refs: Vec<RefRecord>, mut logs: Vec<LogRecord>) -> Result<(), String> {
+    let (names, old) = self.reftable.commit_locked(&mut refs, &mut logs)?;
+    self.list(&names, old)
+  }
+
+  /// Merges every table into one, which lists the refs as they are, and
+  /// lists it in place of them.
+  fn compact(self) -> Result<(), String> {
+    let tables = self.reftable.tables()?;
+    if tables.len() < 2 {
+      return Ok(());
+    }
+    let (refs, logs) = merge(&tables, true)?;
+    let (min, max) = (
+      tables[0].1.min_update_index,
+      tables[tables.len() - 1].1.max_update_index,
+    );
+    let data = table::write(&refs, &logs, min, max)?;
+    let name = self.reftable.add(&data, min, max)?;
+    let old = tables.into_iter().map(|(name, _)| name).collect();
+    self.list(&[name], old)
+  }
+
+  /// Makes `names` the new list of tables, and deletes the tables `old` no
+  /// longer needed.
+  fn list(mut self, names: &[String], old: Vec<String>) -> Result<(), String> {
+    let reftable = self.reftable;
+    let list = reftable.dir.join("tables.list");
+    let data: String = names.iter().map(|name| format!("{}\n", name)).collect();
+    fs::write(&self.path, data)
+      .and_then(|()| fs::rename(&self.path, &list))
+      .map_err(|e| format!("unable to write {} ({})", list.display(), e))?;
+    self.held = false;
+    for name in old {
+      let _ = fs::remove_file(reftable.dir.join(name));
+    }
+    Ok(())
+  }
+}
+
+impl Drop for Lock<'_> {
+  fn drop(&mut self) {
+    if self.held {
+      let _ = fs::remove_file(&self.path);
+    }
+  }
+}
+
+/// A transaction holding the lock on `tables.list`, with the records it
+/// will add.
+struct Prepared<'a> {
+  lock: Lock<'a>,
+  refs: Vec<RefRecord>,
+  logs: Vec<LogRecord>,
+}
+
+impl Transaction for Prepared<'_> {
+  fn commit(self: Box<Self>) -> Result<(), String> {
+    self.lock.commit(self.refs, self.logs)
+  }
+}
+
+/// Merges tables, oldest first, into the refs and logs of one. Deletions
+/// are kept, to hide the refs in the tables before, unless there are none.
+fn merge(
+  tables: &[(String, Arc<Table>)],
+  bottom: bool,
+) -> Result<(Vec<RefRecord>, Vec<LogRecord>), String> {
+  let mut refs = BTreeMap::new();
+  let mut logs = BTreeMap::new();
+  for (_, table) in tables {
+    for record in table.refs()? {
+      refs.insert(record.name.clone(), record);
+    }
+    for record in table.logs()? {
+      let key = (record.name.clone(), Reverse(record.update_index));
+      logs.insert(key, record);
+    }
+  }
+  let refs = refs.into_values();
+  let logs = logs.into_values();
+  Ok(match bottom {
+    true => (
+      refs.filter(|record| record.value.is_some()).collect(),
+      logs.filter(|record| record.entry.is_some()).collect(),
+    ),
+    false => (refs.collect(), logs.collect()),
+  })
+}
+
+impl RefDb for Reftable {
+  fn read(&self, name: &str) -> Result<Option<Ref>, String> {
+    for (_, table) in self.tables()?.iter().rev() {
+      let mut found = None;
+      table.scan_refs(name, |record| {
+        if record.name == name {
+          found = Some(record.value);
+        }
+        false
+      })?;
+      if let Some(value) = found {
+        return Ok(value);
+      }
+    }
+    Ok(None)
+  }
+
+  fn list(&self, prefix: &str) -> Result<Vec<(String, Ref)>, String> {
+    let mut refs = BTreeMap::new();
+    for (_, table) in self.tables()? {
+      table.scan_refs(prefix, |record| {
+        if !record.name.starts_with(prefix) {
+          return false;
+        }
+        match record.value {
+          Some(value) => refs.insert(record.name, value),
+          None => refs.remove(&record.name),
+        };
+        true
+      })?;
+    }
+    Ok(refs.into_iter().collect())
+  }
+
+  fn write(&self, name: &str, value: &Ref) -> Result<(), String> {
+    let record = RefRecord {
+      name: name.to_owned(),
+      update_index: 0,
+      value: Some(value.clone()),
+    };
+    self.commit(vec![record], Vec::new())
+  }
+
+  fn delete(&self, name: &str) -> Result<(), String> {
+    let deleted = RefRecord {
+      name: name.to_owned(),
+      update_index: 0,
+      value: None,
+    };
+    self.commit(vec![deleted], self.delete_log(name)?)
+  }
+
+  fn append_log(&self, name: &str, entry: &LogEntry) -> Result<(), String> {
+    let record = LogRecord {
+      name: name.to_owned(),
+      update_index: 0,
+      entry: Some(entry.clone()),
+    };
+    self.commit(Vec::new(), vec![record])
+  }
+
+  fn read_log(&self, name: &str) -> Result<Vec<LogEntry>, String> {
+    let mut entries = BTreeMap::new();
+    for (_, table) in self.tables()? {
+      for record in table.logs()? {
+        if record.name == name {
+          entries.insert(record.update_index, record.entry);
+        }
+      }
+    }
+    Ok(entries.into_values().flatten().collect())
+  }
+
+  fn prepare(&self, updates: &[Update]) -> Result<Box<dyn Transaction + '_>, String> {
+    let lock = self.lock()?;
+    check_updates(self, updates)?;
+    let mut refs = Vec::new();
+    let mut logs = Vec::new();
+    for update in updates {
+      let value = match update.new.as_deref() {
+        None => continue,
+        Some(NULL_HASH) => {
+          logs.extend(self.delete_log(&update.name)?);
+          None
+        }
+        Some(hash) => Some(Ref::Direct(hash.to_owned())),
+      };
+      refs.push(RefRecord {
+        name: update.name.clone(),
+        update_index: 0,
+        value,
+      });
+    }
+    Ok(Box::new(Prepared { lock, refs, logs }))
+  }
+
+  fn unpacked(&self) -> Result<usize, String> {
+    Ok(self.tables()?.len().saturating_sub(1))
+  }
+
+  fn pack(&self, _options: &PackOptions) -> Result<(), String> {
+    self.lock()?.compact()
+  }
+}
@@ -0,0 +1,685 @@
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc, Decompress, FlushDecompress};
+
+use crate::identity::Signature;
+use crate::object::refs::{LogEntry, Ref};
+
+/// The version of the format that is written, the one for SHA-1 names.
+const VERSION: u8 = 1;
+
+/// The size blocks of refs and of the index are padded to, and that blocks
+/// of logs are kept within before they are compressed.
+pub const BLOCK_SIZE: usize = 4096;
+
+/// How many records there are between restart points, where a whole key is
+/// written rather than what it shares with the key before it.
+const RESTART_INTERVAL: usize = 16;
+
+/// How many blocks a section may have before it is given an index, and how
+/// many blocks the top level of an index may have.
+const INDEX_THRESHOLD: usize = 3;
+
+const HASH_LEN: usize = 20;
+
+const REF_BLOCK: u8 = b'r';
+const INDEX_BLOCK: u8 = b'i';
+const OBJ_BLOCK: u8 = b'o';
+const LOG_BLOCK: u8 = b'g';
+
+/// A ref as a table holds it, or the record of its deletion (`value` is
+/// `None`), which hides the ref in the tables before it.
+#[derive(Clone, Debug)]
+pub struct RefRecord {
+  pub name: String,
+  pub update_index: u64,
+  pub value: Option<Ref>,
+}
+
+/// An entry in the reflog of a ref, or the record of its deletion.
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+  pub name: String,
+  pub update_index: u64,
+  pub entry: Option<LogEntry>,
+}
+
+impl LogRecord {
+  /// The key logs are sorted by: the name of the ref, then the newest entry
+  /// first.
+  fn key(&self) -> Vec<u8> {
+    let mut key = self.name.as_bytes().to_vec();
+    key.push(0);
+    key.extend((u64::MAX - self.update_index).to_be_bytes());
+    key
+  }
+}
+
+/// Appends a number the way reftable writes them: 7 bits to a byte, most
+/// significant first, with the top bit set on all but the last, and with
+/// one taken off each byte but the last so no number has two encodings.
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+  let mut bytes = vec![(value & 0x7f) as u8];
+  value >>= 7;
+  while value != 0 {
+    value -= 1;
+    bytes.push(0x80 | (value & 0x7f) as u8);
+    value >>= 7;
+  }
+  out.extend(bytes.iter().rev());
+}
+
+fn put_be24(out: &mut Vec<u8>, value: usize) {
+  out.extend(&(value as u32).to_be_bytes()[1..]);
+}
+
+/// Reads through the records of a block.
+struct Cursor<'a> {
+  data: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+  fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+    let bytes = self
+      .data
+      .get(self.pos..self.pos + len)
+      .ok_or("corrupt reftable: record runs past its block")?;
+    self.pos += len;
+    Ok(bytes)
+  }
+
+  fn varint(&mut self) -> Result<u64, String> {
+    let mut byte = self.bytes(1)?[0];
+    let mut value = (byte & 0x7f) as u64;
+    while byte & 0x80 != 0 {
+      byte = self.bytes(1)?[0];
+      value = ((value + 1) << 7) | (byte & 0x7f) as u64;
+    }
+    Ok(value)
+  }
+
+  fn string(&mut self) -> Result<String, String> {
+    let len = self.varint()? as usize;
+    Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+  }
+
+  fn hash(&mut self) -> Result<String, String> {
+    Ok(hex::encode(self.bytes(HASH_LEN)?))
+  }
+}
+
+fn be24(bytes: &[u8]) -> usize {
+  u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) as usize
+}
+
+fn be64(bytes: &[u8]) -> u64 {
+  u64::from_be_bytes(bytes[..8].try_into().unwrap())
+}
+
+/// A record of any of the kinds of block that are read.
+enum Record {
+  Ref(RefRecord),
+  Index(Vec<u8>, usize),
+  Log(LogRecord),
+}
+
+/// A block of a table, with its records and where the next block starts.
+struct Block {
+  kind: u8,
+  records: Vec<Record>,
+  next: usize,
+}
+
+/// A reftable: a file of refs sorted by name in blocks, with an index of
+/// the blocks to find a ref without reading them all, followed by the
+/// reflogs of those refs.
+///
+/// Each table is one or more transactions on the refs, numbered from
+/// `min_update_index` to `max_update_index`, and a repository's refs are a
+/// stack of tables, each later one winning over those before it.
+pub struct Table {
+  data: Vec<u8>,
+  header_len: usize,
+  block_size: usize,
+  pub min_update_index: u64,
+  pub max_update_index: u64,
+  ref_index: usize,
+  log_position: usize,
+
+  /// Where the footer starts, and the blocks end.
+  end: usize,
+}
+
+impl Table {
+  /// Reads a table: its header, which says how big its blocks are and which
+  /// transactions it holds, and its footer, which repeats the header and
+  /// says where each section and its index start.
+  pub fn parse(data: Vec<u8>) -> Result<Self, String> {
+    let (header_len, footer_len) = match data.get(4) {
+      Some(1) => (24, 68),
+      Some(2) => (28, 72),
+      Some(version) => return Err(format!("unsupported reftable version {}", version)),
+      None => return Err("corrupt reftable: too short".to_string()),
+    };
+    if !data.starts_with(b"REFT") || data.len() < header_len + footer_len {
+      return Err("corrupt reftable: bad header".to_string());
+    }
+    if header_len == 28 && &data[24..28] != b"sha1" {
+      return Err("unsupported reftable hash function".to_string());
+    }
+    let end = data.len() - footer_len;
+    let footer = &data[end..];
+    let mut crc = Crc::new();
+    crc.update(&footer[..footer_len - 4]);
+    if footer[..header_len] != data[..header_len]
+      || crc.sum().to_be_bytes() != footer[footer_len - 4..]
+    {
+      return Err("corrupt reftable: bad footer".to_string());
+    }
+    let positions = &footer[header_len..];
+    Ok(Self {
+      header_len,
+      block_size: be24(&data[5..8]),
+      min_update_index: be64(&data[8..16]),
+      max_update_index: be64(&data[16..24]),
+      ref_index: be64(&positions[0..8]) as usize,
+      log_position: be64(&positions[24..32]) as usize,
+      end,
+      data,
+    })
+  }
+
+  /// Reads the block at a position, or `None` if the blocks end there. The
+  /// first block starts with the header of the file, which comes before
+  /// its own.
+  fn block(&self, pos: usize) -> Result<Option<Block>, String> {
+    let skip = if pos == 0 { self.header_len } else { 0 };
+    if pos + skip + 4 > self.end {
+      return Ok(None);
+    }
+    let kind = self.data[pos + skip];
+    if ![REF_BLOCK, INDEX_BLOCK, OBJ_BLOCK, LOG_BLOCK].contains(&kind) {
+      return Ok(None);
+    }
+    let len = be24(&self.data[pos + skip + 1..]);
+    let corrupt = || "corrupt reftable: bad block".to_string();
+    let inflated;
+    let (block, next) = match kind {
+      // logs are compressed, after the header, and the block is as long as
+      // they are, but says how long they are uncompressed
+      LOG_BLOCK => {
+        let start = pos + skip + 4;
+        let mut data = vec![0; len.checked_sub(skip + 4).ok_or_else(corrupt)?];
+        let mut z = Decompress::new(true);
+        z.decompress(
+          &self.data[start..self.end],
+          &mut data,
+          FlushDecompress::Finish,
+        )
+        .map_err(|e| format!("corrupt reftable: {}", e))?;
+        inflated = [&self.data[pos..start], &data].concat();
+        (&inflated[..], start + z.total_in() as usize)
+      }
+      // other blocks are padded with zeros to the size of a block, unless
+      // the next starts straight after
+      _ => {
+        let block = self.data.get(pos..pos + len).ok_or_else(corrupt)?;
+        let padded = self.block_size > len && self.data.get(pos + len) == Some(&0);
+        let size = if padded { self.block_size } else { len };
+        (block, pos + size)
+      }
+    };
+    if block.len() < skip + 6 {
+      return Err(corrupt());
+    }
+    let restarts = u16::from_be_bytes([block[len - 2], block[len - 1]]) as usize;
+    let records_end = (len - 2)
+      .checked_sub(3 * restarts)
+      .filter(|end| *end >= skip + 4)
+      .ok_or_else(corrupt)?;
+    let records = match kind {
+      OBJ_BLOCK => Vec::new(),
+      _ => self.records(kind, &block[skip + 4..records_end])?,
+    };
+    Ok(Some(Block {
+      kind,
+      records,
+      next,
+    }))
+  }
+
+  /// Reads the records of a block. Each key is written as how much of the
+  /// key before it it starts with, and the rest of it.
+  fn records(&self, kind: u8, data: &[u8]) -> Result<Vec<Record>, String> {
+    let mut cursor = Cursor { data, pos: 0 };
+    let mut records = Vec::new();
+    let mut key: Vec<u8> = Vec::new();
+    while cursor.pos < data.len() {
+      let prefix = cursor.varint()? as usize;
+      let suffix = cursor.varint()?;
+      let value_type = (suffix & 7) as u8;
+      if prefix > key.len() {
+        return Err("corrupt reftable: bad key".to_string());
+      }
+      key.truncate(prefix);
+      key.extend(cursor.bytes((suffix >> 3) as usize)?);
+      let record = match kind {
+        REF_BLOCK => {
+          let update_index = self.min_update_index + cursor.varint()?;
+          let value = match value_type {
+            0 => None,
+            1 => Some(Ref::Direct(cursor.hash()?)),
+            2 => {
+              let value = cursor.hash()?;
+              cursor.hash()?;
+              Some(Ref::Direct(value))
+            }
+            3 => Some(Ref::Symbolic(cursor.string()?)),
+            _ => return Err("corrupt reftable: bad ref value".to_string()),
+          };
+          Record::Ref(RefRecord {
+            name: String::from_utf8_lossy(&key).into_owned(),
+            update_index,
+            value,
+          })
+        }
+        INDEX_BLOCK => Record::Index(key.clone(), cursor.varint()? as usize),
+        _ => {
+          let split = key.len().checked_sub(9).filter(|split| key[*split] == 0);
+          let split = split.ok_or("corrupt reftable: bad log key")?;
+          let entry = match value_type {
+            0 => None,
+            _ => Some(read_log_entry(&mut cursor)?),
+          };
+          Record::Log(LogRecord {
+            name: String::from_utf8_lossy(&key[..split]).into_owned(),
+            update_index: u64::MAX - be64(&key[split + 1..]),
+            entry,
+          })
+        }
+      };
+      records.push(record);
+    }
+    Ok(records)
+  }
+
+  /// The blocks of the section whose first block is at `pos`.
+  fn section(&self, mut pos: usize, kind: u8) -> Result<Vec<Block>, String> {
+    let mut blocks = Vec::new();
+    while let Some(block) = self.block(pos)? {
+      if block.kind != kind {
+        break;
+      }
+      pos = block.next;
+      blocks.push(block);
+    }
+    Ok(blocks)
+  }
+
+  /// Where the first block of refs that could hold `from` starts, going down
+  /// the index if there is one. `None` if every ref is before it.
+  fn seek(&self, from: &str) -> Result<Option<usize>, String> {
+    if self.ref_index == 0 {
+      return Ok(Some(0));
+    }
+    // an index has the last key of each block it points at, and the top
+    // level of the index may be more than one block
+    let mut level = self.section(self.ref_index, INDEX_BLOCK)?;
+    loop {
+      let found = level
+        .iter()
+        .flat_map(|block| &block.records)
+        .find_map(|record| match record {
+          Record::Index(key, pos) if key.as_slice() >= from.as_bytes() => Some(*pos),
+          _ => None,
+        });
+      let pos = match found {
+        Some(pos) => pos,
+        None => return Ok(None),
+      };
+      match self.block(pos)? {
+        Some(block) if block.kind == INDEX_BLOCK => level = vec![block],
+        _ => return Ok(Some(pos)),
+      }
+    }
+  }
+
+  /// Goes through the refs in the table in order, from the first one named
+  /// `from` or after, until `visit` returns false.
+  pub fn scan_refs(
+    &self,
+    from: &str,
+    mut visit: impl FnMut(RefRecord) -> bool,
+  ) -> Result<(), String> {
+    let mut pos = match self.seek(from)? {
+      Some(pos) => pos,
+      None => return Ok(()),
+    };
+    while let Some(block) = self.block(pos)? {
+      if block.kind != REF_BLOCK {
+        break;
+      }
+      for record in block.records {
+        if let Record::Ref(record) = record {
+          if record.name.as_str() >= from && !visit(record) {
+            return Ok(());
+          }
+        }
+      }
+      pos = block.next;
+    }
+    Ok(())
+  }
+
+  /// How big the table is, in bytes.
+  pub fn size(&self) -> usize {
+    self.data.len()
+  }
+
+  /// All of the refs in the table, in order.
+  pub fn refs(&self) -> Result<Vec<RefRecord>, String> {
+    let mut refs = Vec::new();
+    self.scan_refs("", |record| {
+      refs.push(record);
+      true
+    })?;
+    Ok(refs)
+  }
+
+  /// All of the reflog records in the table, by ref and newest first.
+  pub fn logs(&self) -> Result<Vec<LogRecord>, String> {
+    let start = match (self.log_position, self.block(0)?) {
+      (0, Some(block)) if block.kind == LOG_BLOCK => 0,
+      (0, _) => return Ok(Vec::new()),
+      (pos, _) => pos,
+    };
+    let blocks = self.section(start, LOG_BLOCK)?;
+    let records = blocks.into_iter().flat_map(|block| block.records);
+    Ok(
+      records
+        .filter_map(|record| match record {
+          Record::Log(record) => Some(record),
+          _ => None,
+        })
+        .collect(),
+    )
+  }
+}
+
+/// Reads what a reflog entry says: the old and new hashes, who made the
+/// change and when, and why. Messages are kept ending with a newline, which
+/// is taken off here.
+///
+/// The timezone is kept as git does, as the digits of `hhmm` read as a
+/// number (`-0130` is -130), rather than in minutes as the format's
+/// documentation has it.
+fn read_log_entry(cursor: &mut Cursor) -> Result<LogEntry, String> {
+  let old = cursor.hash()?;
+  let new = cursor.hash()?;
+  let name = cursor.string()?;
+  let email = cursor.string()?;
+  let time = cursor.varint()?;
+  let offset = i16::from_be_bytes(cursor.bytes(2)?.try_into().unwrap());
+  let message = cursor.string()?;
+  let sign = if offset < 0 { '-' } else { '+' };
+  Ok(LogEntry {
+    old,
+    new,
+    identity: format!(
+      "{} <{}> {} {}{:04}",
+      name,
+      email,
+      time,
+      sign,
+      offset.unsigned_abs()
+    ),
+    message: message.strip_suffix('\n').unwrap_or(&message).to_owned(),
+  })
+}
+
+/// Writes what a reflog entry says, as [`read_log_entry`] reads it.
+fn put_log_entry(out: &mut Vec<u8>, entry: &LogEntry) -> Result<(), String> {
+  for hash in [&entry.old, &entry.new] {
+    out.extend(hex::decode(hash).map_err(|_| format!("invalid object name {}", hash))?);
+  }
+  let identity = Signature::parse(&entry.identity)
+    .ok_or_else(|| format!("invalid identity '{}'", entry.identity))?;
+  let timezone = identity.timezone.as_bytes();
+  let offset = match timezone {
+    [sign @ (b'+' | b'-'), digits @ ..] if digits.len() == 4 => {
+      let digits = std::str::from_utf8(digits).unwrap_or_default();
+      let offset: i16 = digits.parse().unwrap_or(0);
+      if *sign == b'-' {
+        -offset
+      } else {
+        offset
+      }
+    }
+    _ => 0,
+  };
+  for text in [&identity.name, &identity.email] {
+    put_varint(out, text.len() as u64);
+    out.extend(text.as_bytes());
+  }
+  put_varint(out, identity.time.max(0) as u64);
+  out.extend(offset.to_be_bytes());
+  let message = format!("{}\n", entry.message.trim_end_matches('\n'));
+  put_varint(out, message.len() as u64);
+  out.extend(message.as_bytes());
+  Ok(())
+}
+
+/// Fills a block with records until it is full.
+struct BlockWriter {
+  kind: u8,
+
+  /// How much of the block is the header of the file, for the first block.
+  skip: usize,
+  data: Vec<u8>,
+  restarts: Vec<usize>,
+  last_key: Vec<u8>,
+  count: usize,
+}
+
+impl BlockWriter {
+  fn new(kind: u8, header: &[u8]) -> Self {
+    let mut data = header.to_vec();
+    data.extend([kind, 0, 0, 0]);
+    Self {
+      kind,
+      skip: header.len(),
+      data,
+      restarts: Vec::new(),
+      last_key: Vec::new(),
+      count: 0,
+    }
+  }
+
+  /// Adds a record, unless it would not fit. A record is only ever too big
+  /// for an empty block if it is too big for any, so it is added anyway.
+  fn add(&mut self, key: &[u8], value_type: u8, value: &[u8]) -> bool {
+    let restart = self.count.is_multiple_of(RESTART_INTERVAL);
+    let prefix = match restart {
+      true => 0,
+      false => self
+        .last_key
+        .iter()
+        .zip(key)
+        .take_while(|(a, b)| a == b)
+        .count(),
+    };
+    let mut record = Vec::new();
+    put_varint(&mut record, prefix as u64);
+    put_varint(
+      &mut record,
+      (((key.len() - prefix) as u64) << 3) | value_type as u64,
+    );
+    record.extend(&key[prefix..]);
+    record.extend(value);
+    let restarts = self.restarts.len() + restart as usize;
+    if self.count > 0 && self.data.len() + record.len() + 3 * restarts + 2 > BLOCK_SIZE {
+      return false;
+    }
+    if restart {
+      self.restarts.push(self.data.len());
+    }
+    self.data.extend(record);
+    self.last_key = key.to_vec();
+    self.count += 1;
+    true
+  }
+
+  /// Ends the block with where its restart points are, and says how long
+  /// it is. Logs are compressed, and other blocks padded out.
+  fn finish(mut self) -> Vec<u8> {
+    for restart in &self.restarts {
+      put_be24(&mut self.data, *restart);
+    }
+    self.data.extend((self.restarts.len() as u16).to_be_bytes());
+    let len = (self.data.len() as u32).to_be_bytes();
+    self.data[self.skip + 1..self.skip + 4].copy_from_slice(&len[1..]);
+    match self.kind {
+      LOG_BLOCK => {
+        let split = self.skip + 4;
+        let mut encoder = ZlibEncoder::new(self.data[..split].to_vec(), Compression::default());
+        // writing to a vector can't fail
+        encoder.write_all(&self.data[split..]).unwrap();
+        encoder.finish().unwrap()
+      }
+      _ => {
+        if self.data.len() < BLOCK_SIZE {
+          self.data.resize(BLOCK_SIZE, 0);
+        }
+        self.data
+      }
+    }
+  }
+}
+
+/// Lays out the blocks of a table.
+struct Writer {
+  header: Vec<u8>,
+  out: Vec<u8>,
+  block: Option<BlockWriter>,
+
+  /// The last key of each block written in the section, and where it is.
+  index: Vec<(Vec<u8>, usize)>,
+}
+
+impl Writer {
+  fn add(&mut self, kind: u8, key: &[u8], value_type: u8, value: &[u8]) {
+    let header = if self.out.is_empty() {
+      &self.header[..]
+    } else {
+      &[]
+    };
+    let block = self
+      .block
+      .get_or_insert_with(|| BlockWriter::new(kind, header));
+    if !block.add(key, value_type, value) {
+      self.flush();
+      let mut block = BlockWriter::new(kind, &[]);
+      block.add(key, value_type, value);
+      self.block = Some(block);
+    }
+  }
+
+  fn flush(&mut self) {
+    if let Some(block) = self.block.take() {
+      self.index.push((block.last_key.clone(), self.out.len()));
+      self.out.extend(block.finish());
+    }
+  }
+
+  /// Ends a section, writing an index of its blocks if it has more than a
+  /// few, and returns where the top level of the index starts (0 for
+  /// none). Each level of the index is an index of the blocks of the one
+  /// below it, until there are few enough blocks to look through.
+  fn finish_section(&mut self) -> usize {
+    self.flush();
+    let mut index_start = 0;
+    while self.index.len() > INDEX_THRESHOLD {
+      index_start = self.out.len();
+      for (key, pos) in std::mem::take(&mut self.index) {
+        let mut value = Vec::new();
+        put_varint(&mut value, pos as u64);
+        self.add(INDEX_BLOCK, &key, 0, &value);
+      }
+      self.flush();
+    }
+    self.index.clear();
+    index_start
+  }
+}
+
+/// Writes a table of refs, sorted by name, and reflog records, sorted by
+/// ref and newest first, from the transactions numbered `min` to `max`.
+pub fn write(
+  refs: &[RefRecord],
+  logs: &[LogRecord],
+  min: u64,
+  max: u64,
+) -> Result<Vec<u8>, String> {
+  let mut header = b"REFT".to_vec();
+  header.push(VERSION);
+  put_be24(&mut header, BLOCK_SIZE);
+  header.extend(min.to_be_bytes());
+  header.extend(max.to_be_bytes());
+  let mut writer = Writer {
+    header: header.clone(),
+    out: Vec::new(),
+    block: None,
+    index: Vec::new(),
+  };
+
+  for record in refs {
+    let mut value = Vec::new();
+    put_varint(&mut value, record.update_index - min);
+    let value_type = match &record.value {
+      None => 0,
+      Some(Ref::Direct(hash)) => {
+        value.extend(hex::decode(hash).map_err(|_| format!("invalid object name {}", hash))?);
+        1
+      }
+      Some(Ref::Symbolic(target)) => {
+        put_varint(&mut value, target.len() as u64);
+        value.extend(target.as_bytes());
+        3
+      }
+    };
+    writer.add(REF_BLOCK, record.name.as_bytes(), value_type, &value);
+  }
+  let ref_index = writer.finish_section();
+
+  let log_position = writer.out.len();
+  for record in logs {
+    let mut value = Vec::new();
+    let value_type = match &record.entry {
+      None => 0,
+      Some(entry) => {
+        put_log_entry(&mut value, entry)?;
+        1
+      }
+    };
+    writer.add(LOG_BLOCK, &record.key(), value_type, &value);
+  }
+  let log_index = writer.finish_section();
+
+  let mut out = writer.out;
+  if out.is_empty() {
+    out.extend(&header);
+  }
+  let mut footer = header;
+  footer.extend((ref_index as u64).to_be_bytes());
+  footer.extend(0u64.to_be_bytes());
+  footer.extend(0u64.to_be_bytes());
+  footer.extend((if logs.is_empty() { 0 } else { log_position } as u64).to_be_bytes());
+  footer.extend((log_index as u64).to_be_bytes());
+  let mut crc = Crc::new();
+  crc.update(&footer);
+  footer.extend(crc.sum().to_be_bytes());
+  out.extend(footer);
+  Ok(out)
+}
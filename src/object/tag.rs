@@ -1,9 +1,10 @@
 use std::ops::Deref;
 
-use crate::repo::Repo;
-
-use super::mail_map::MailMap;
+use super::mail_map::{self, MailMap};
 use super::serializable::Serializable;
+use super::Signer;
+use crate::identity::Signature;
+use crate::repo::Repo;
 
 /// A git tag.
 ///
@@ -23,15 +24,13 @@ use super::serializable::Serializable;
 pub struct Tag {
   format: String,
   map: MailMap,
-  repo: Repo,
 }
 
 impl Tag {
-  pub fn new(repo: Repo, data: &[u8]) -> Self {
+  pub fn new(data: &[u8]) -> Self {
     let mut new_tag: Self = Self {
       format: String::from("tag"),
       map: MailMap::new(),
-      repo,
     };
     new_tag.map.parse_bytes(data, 0);
     new_tag
@@ -58,8 +57,208 @@ impl Serializable for Tag {
   fn format(&self) -> &String {
     &self.format
   }
+}
+
+/// Puts together a new annotated tag, checking it is well formed (the way
+/// [`check`] does) before it is written, so that the headers need not be
+/// formatted by hand.
+///
+/// The object, name and tagger must be given. The type of the object is
+/// looked up when the tag is written, unless it is given. Tags have no other
+/// headers, and a signature made by [`sign`](TagBuilder::sign) goes at the
+/// end of the message, as git puts it.
+///
+/// ## Example
+/// ```ignore
+/// let hash = TagBuilder::new()
+///   .object(&head)
+///   .name("v1.0")
+///   .tagger(Signature::current(&repo, Role::Committer)?)
+///   .message("The first release\n")
+///   .write(&repo)?;
+/// ```
+#[derive(Default)]
+pub struct TagBuilder {
+  object: Option<String>,
+  kind: Option<String>,
+  name: Option<String>,
+  tagger: Option<Signature>,
+  message: String,
+  signer: Option<Signer>,
+}
+
+impl TagBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The object the tag points at.
+  pub fn object(mut self, hash: &str) -> Self {
+    self.object = Some(hash.to_string());
+    self
+  }
+
+  /// The type of the object the tag points at: `commit`, `tree`, `blob` or
+  /// `tag`.
+  pub fn kind(mut self, kind: &str) -> Self {
+    self.kind = Some(kind.to_string());
+    self
+  }
+
+  /// The name of the tag, usually the name of the ref it goes in without
+  /// `refs/tags/`.
+  pub fn name(mut self, name: &str) -> Self {
+    self.name = Some(name.to_string());
+    self
+  }
+
+  /// The person who made the tag.
+  pub fn tagger(mut self, tagger: Signature) -> Self {
+    self.tagger = Some(tagger);
+    self
+  }
 
-  fn repo(&self) -> &Repo {
-    &self.repo
+  /// The message, which is kept as it is given.
+  pub fn message(mut self, message: &str) -> Self {
+    self.message = message.to_string();
+    self
+  }
+
+  /// Signs the tag with a callback, which is handed the data of the tag as
+  /// it is without the signature and returns the signature (eg. an armored
+  /// PGP signature).
+  pub fn sign(mut self, signer: impl FnOnce(&[u8]) -> Result<String, String> + 'static) -> Self {
+    self.signer = Some(Box::new(signer));
+    self
+  }
+
+  /// Makes the tag, without writing it, after checking that it is well
+  /// formed.
+  pub fn build(self) -> Result<Tag, String> {
+    let object = self.object.ok_or("a tag needs an object")?;
+    let kind = self.kind.ok_or("a tag needs the type of its object")?;
+    let name = self.name.ok_or("a tag needs a name")?;
+    let tagger = self.tagger.ok_or("a tag needs a tagger")?;
+    if name.is_empty() || name.contains('\n') {
+      return Err(format!("invalid tag name '{}'", name));
+    }
+    let mut map = MailMap::new();
+    map.insert("object", &object);
+    map.insert("type", &kind);
+    map.insert("tag", &name);
+    map.insert("tagger", &tagger.to_string());
+    map.insert("", &self.message);
+    let mut data = mail_map::map_to_bytes(&map.map);
+    if let Some(signer) = self.signer {
+      let signature = signer(&data)?;
+      if !data.ends_with(b"\n") {
+        data.push(b'\n');
+      }
+      data.extend_from_slice(signature.as_bytes());
+      if !data.ends_with(b"\n") {
+        data.push(b'\n');
+      }
+    }
+    check(&data)?;
+    Ok(Tag::new(&data))
+  }
+
+  /// Writes the tag and returns its hash, after checking that the object it
+  /// points at is in the repository, and is of the type given.
+  pub fn write(mut self, repo: &Repo) -> Result<String, String> {
+    if let Some(hash) = &self.object {
+      let object =
+        super::read(repo, hash, None).map_err(|_| format!("{} is not a valid object", hash))?;
+      let kind = object.format();
+      match &self.kind {
+        Some(given) if given != kind => {
+          return Err(format!("object {} tagged as {} is a {}", hash, given, kind))
+        }
+        _ => self.kind = Some(kind.to_string()),
+      }
+    }
+    super::write(repo, &self.build()?, false)
+  }
+}
+
+/// Checks that the data of a tag is well formed, the way `git fsck` does, and
+/// returns the hash and type of the object it points at.
+///
+/// The headers must be `object`, `type`, `tag` and `tagger`, in that order and
+/// with nothing after them but the message. Errors are given as
+/// `<id>: <description>`, eg. `missingTaggerEntry: invalid format - expected
+/// 'tagger' line`.
+pub fn check(data: &[u8]) -> Result<(String, String), String> {
+  let end = match data.windows(2).position(|w| w == b"\n\n") {
+    Some(end) => end + 1,
+    None if data.ends_with(b"\n") => data.len(),
+    None => return Err("unterminatedHeader: unterminated header".to_string()),
+  };
+  let header = String::from_utf8_lossy(&data[..end]);
+  let mut lines = header.lines();
+  let mut field = |name: &str, id: &str| {
+    lines
+      .next()
+      .and_then(|line| line.strip_prefix(name))
+      .and_then(|line| line.strip_prefix(' '))
+      .ok_or(format!("{}: invalid format - expected '{}' line", id, name))
+  };
+
+  let object = field("object", "missingObject")?;
+  if object.len() != 40 || !object.bytes().all(|b| b.is_ascii_hexdigit()) {
+    return Err("badObjectSha1: invalid 'object' line format - bad sha1".to_string());
+  }
+  let kind = field("type", "missingTypeEntry")?;
+  if !["blob", "tree", "commit", "tag"].contains(&kind) {
+    return Err("badType: invalid 'type' value".to_string());
+  }
+  field("tag", "missingTagEntry")?;
+  let tagger = field("tagger", "missingTaggerEntry")?;
+  check_ident(tagger)
+    .map_err(|(id, problem)| format!("{}: invalid author/committer line - {}", id, problem))?;
+  if lines.next().is_some_and(|line| !line.is_empty()) {
+    return Err("extraHeaderEntry: invalid format - extra header(s) after 'tagger'".to_string());
+  }
+  Ok((object.to_owned(), kind.to_owned()))
+}
+
+/// Checks a `Name <email> seconds timezone` line, returning the id and
+/// description of the first problem found.
+fn check_ident(ident: &str) -> Result<(), (&'static str, &'static str)> {
+  if ident.starts_with('<') {
+    return Err(("missingNameBeforeEmail", "missing space before email"));
+  }
+  let start = ident.find(['<', '>']);
+  let rest = match start.map(|i| (i, ident.as_bytes()[i])) {
+    Some((_, b'>')) => return Err(("badName", "bad name")),
+    None => return Err(("missingEmail", "missing email")),
+    Some((i, _)) if !ident[..i].ends_with(' ') => {
+      return Err(("missingSpaceBeforeEmail", "missing space before email"))
+    }
+    Some((i, _)) => &ident[i + 1..],
+  };
+  let rest = match rest.find(['<', '>']) {
+    Some(i) if rest.as_bytes()[i] == b'>' => &rest[i + 1..],
+    _ => return Err(("badEmail", "bad email")),
+  };
+  let date = rest
+    .strip_prefix(' ')
+    .ok_or(("missingSpaceBeforeDate", "missing space before date"))?;
+  if date.starts_with('0') && !date.starts_with("0 ") {
+    return Err(("zeroPaddedDate", "zero-padded date"));
+  }
+  let digits = date.bytes().take_while(|b| b.is_ascii_digit()).count();
+  if digits > 0 && date[..digits].parse::<u64>().is_err() {
+    return Err(("badDateOverflow", "date causes integer overflow"));
+  }
+  let zone = match date[digits..].strip_prefix(' ') {
+    Some(zone) if digits > 0 => zone,
+    _ => return Err(("badDate", "bad date")),
+  };
+  let zone = zone.as_bytes();
+  if zone.len() != 5 || !matches!(zone[0], b'+' | b'-') || !zone[1..].iter().all(u8::is_ascii_digit)
+  {
+    return Err(("badTimezone", "bad time zone"));
   }
+  Ok(())
 }
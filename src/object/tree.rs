@@ -1,7 +1,13 @@
+use std::collections::BTreeMap;
+
+use bstr::BString;
+
 use crate::repo::Repo;
 
 use super::findable::Findable;
+use super::read;
 use super::serializable::Serializable;
+use super::serializable::Unbox;
 
 use super::mode::Mode;
 
@@ -26,24 +32,54 @@ pub struct Tree {
   bytes: Vec<u8>,
   entries: Vec<TreeEntry>,
   format: String,
-  repo: Repo,
 }
 
 impl Tree {
-  pub fn new(repo: Repo, data: &[u8]) -> Self {
+  /// Parses a tree, failing on an entry that is cut short or whose mode git
+  /// wouldn't know. The entries are put in order and written out again the
+  /// way git writes them.
+  pub fn new(data: &[u8]) -> Result<Self, String> {
+    let mut entries = Vec::new();
+    let mut offset: usize = 0;
+    while offset < data.len() {
+      let entry = TreeEntry::from_bytes(data, offset)?;
+      offset += entry.len;
+      entries.push(entry);
+    }
+    Ok(Self::from_entries(entries))
+  }
+
+  /// Makes a tree out of a list of entries, in whatever order they come.
+  pub fn from_entries(entries: Vec<TreeEntry>) -> Self {
     let mut new_tree: Self = Self {
       bytes: Vec::default(),
-      entries: Vec::default(),
+      entries,
       format: String::from("tree"),
-      repo,
     };
-    new_tree.deserialize(data);
+    new_tree.encode();
     new_tree
   }
 
   pub fn entries(&self) -> &Vec<TreeEntry> {
     &self.entries
   }
+
+  /// Sorts the entries the way git requires and regenerates the bytes of the
+  /// tree from them.
+  fn encode(&mut self) {
+    self.entries.sort_by_key(|e| sort_key(&e.path, e.mode));
+    self.bytes.clear();
+    for entry in &self.entries {
+      self
+        .bytes
+        .extend_from_slice(format!("{} ", entry.mode.to_octal()).as_bytes());
+      self.bytes.extend_from_slice(&entry.path);
+      self.bytes.push(0);
+      self
+        .bytes
+        .extend_from_slice(&hex::decode(&entry.hash).expect("tree entry hashes are checked"));
+    }
+  }
 }
 
 impl Serializable for Tree {
@@ -51,68 +87,294 @@ impl Serializable for Tree {
     &self.bytes
   }
 
+  /// Replaces the tree with the one parsed from `data`, or an empty one if
+  /// it doesn't parse (see [`Tree::new`]).
   fn deserialize(&mut self, data: &[u8]) {
-    self.bytes = data.to_vec();
-    let mut offset: usize = 0;
-    while offset < self.bytes.len() {
-      let entry: TreeEntry = TreeEntry::from_bytes(&self.bytes, offset);
-      offset += entry.len;
-      self.entries.push(entry);
-    }
+    *self = Tree::new(data).unwrap_or_else(|_| Tree::from_entries(Vec::new()));
   }
 
   fn format(&self) -> &String {
     &self.format
   }
-
-  fn repo(&self) -> &Repo {
-    &self.repo
-  }
 }
 
 /// A single tree entry.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct TreeEntry {
   pub mode: Mode,
-  pub path: String,
+
+  /// The name of the entry. Git puts no encoding on names, so this is just
+  /// the bytes that were in the tree.
+  pub path: BString,
   pub hash: String,
   pub len: usize,
 }
 
 impl TreeEntry {
+  /// Constructs a new TreeEntry, checking that the hash is a full one.
+  pub fn new(mode: Mode, path: impl Into<BString>, hash: &str) -> Result<Self, String> {
+    if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+      return Err(format!("bad hash {}", hash));
+    }
+    let path = path.into();
+    let len = format!("{} ", mode.to_octal()).len() + path.len() + 21;
+    Ok(Self {
+      mode,
+      path,
+      hash: hash.to_ascii_lowercase(),
+      len,
+    })
+  }
+
   /// Constructs a new TreeEntry from raw bytes starting at offset.
   ///
   /// An entry in the bytes is formatted as: `[mode] 0x20 [path] 0x00 [sha-1]`
-  pub fn from_bytes(raw: &[u8], offset: usize) -> Self {
+  ///
+  /// Regular files with modes git no longer writes, such as the `100664` of
+  /// old versions, are read as `100644` (or `100755` if executable by their
+  /// owner), as git reads them.
+  pub fn from_bytes(raw: &[u8], offset: usize) -> Result<Self, String> {
+    let truncated = || "malformed tree: entry is truncated".to_string();
+
     // Search for the first space after offset (a space is 0x20).
-    let maybe_space = raw.find(b' ', offset);
-    let space = match maybe_space {
+    let space = match raw.find(b' ', offset) {
       // mode should be either a 5 or 6 digit number
       Some(i) if i == offset + 5 || i == offset + 6 => i,
-      _ => panic!("Failed to create TreeEntry: inconsistent input"),
+      _ => return Err("malformed tree: bad mode".to_string()),
     };
 
     // Extract the mode as a string, convert to Mode enum
-    let mode = String::from_utf8(raw[offset..space].to_vec()).unwrap();
-    let mode = mode.parse::<usize>().unwrap();
-    let mode: Mode = Mode::try_from(mode).expect("unable to parse file mode");
+    let text = &raw[offset..space];
+    let mode = match Mode::from_octal(text) {
+      Ok(mode) => mode,
+      Err(e) => {
+        let bits = std::str::from_utf8(text)
+          .ok()
+          .and_then(|text| u32::from_str_radix(text, 8).ok());
+        match bits {
+          Some(bits) if bits & 0o170000 == 0o100000 && bits & 0o100 != 0 => Mode::Executable,
+          Some(bits) if bits & 0o170000 == 0o100000 => Mode::Normal,
+          _ => return Err(format!("malformed tree: {}", e)),
+        }
+      }
+    };
 
     // Find the null-terminator of the path
-    let maybe_null = raw.find(b'\0', space);
-    let null = match maybe_null {
-      Some(i) => i,
-      _ => panic!("Failed to create TreeEntry: inconsistent input"),
-    };
-    let path = String::from_utf8(raw[space + 1..null].to_vec()).unwrap();
+    let null = raw.find(b'\0', space).ok_or_else(truncated)?;
+    let path = BString::from(&raw[space + 1..null]);
 
     // Read out the hash and convert it to a hex string (20 bytes)
-    let hash = hex::encode(&raw[null + 1..null + 21]);
+    let hash = hex::encode(raw.get(null + 1..null + 21).ok_or_else(truncated)?);
     let len = null + 21 - offset;
-    Self {
+    Ok(Self {
       mode,
       path,
       hash,
       len,
+    })
+  }
+}
+
+/// Looks up the entry at a slash-separated path inside a tree.
+///
+/// Returns the mode and hash of the entry, or `None` if nothing exists at that
+/// path. An empty path refers to the tree itself.
+pub fn lookup(
+  repo: &Repo,
+  tree: &str,
+  path: impl AsRef<[u8]>,
+) -> Result<Option<(Mode, String)>, String> {
+  let mut current = (Mode::Directory, tree.to_owned());
+  for component in path
+    .as_ref()
+    .split(|c| *c == b'/')
+    .filter(|c| !c.is_empty())
+  {
+    if !matches!(current.0, Mode::Directory) {
+      return Ok(None);
+    }
+    let object = read(repo, &current.1, Some("tree"))?;
+    let tree = object.unbox::<Tree>()?;
+    match tree.entries().iter().find(|e| e.path == component) {
+      Some(entry) => current = (entry.mode, entry.hash.clone()),
+      None => return Ok(None),
+    }
+  }
+  Ok(Some(current))
+}
+
+/// Recursively lists every non-tree entry of a tree, keyed by full path.
+pub fn flatten(repo: &Repo, tree: &str) -> Result<BTreeMap<BString, (Mode, String)>, String> {
+  let mut entries = BTreeMap::new();
+  flatten_into(repo, tree, b"", &mut entries)?;
+  Ok(entries)
+}
+
+/// Lists the entries directly inside a tree (subtrees included, but not
+/// their contents), keyed by name.
+pub fn list(repo: &Repo, tree: &str) -> Result<BTreeMap<BString, (Mode, String)>, String> {
+  let object = read(repo, tree, Some("tree"))?;
+  Ok(
+    object
+      .unbox::<Tree>()?
+      .entries()
+      .iter()
+      .map(|entry| (entry.path.clone(), (entry.mode, entry.hash.clone())))
+      .collect(),
+  )
+}
+
+fn flatten_into(
+  repo: &Repo,
+  tree: &str,
+  prefix: &[u8],
+  entries: &mut BTreeMap<BString, (Mode, String)>,
+) -> Result<(), String> {
+  let object = read(repo, tree, Some("tree"))?;
+  for entry in object.unbox::<Tree>()?.entries() {
+    let path = BString::from([prefix, entry.path.as_slice()].concat());
+    match entry.mode {
+      Mode::Directory => flatten_into(
+        repo,
+        &entry.hash,
+        &[path.as_slice(), b"/"].concat(),
+        entries,
+      )?,
+      _ => {
+        entries.insert(path, (entry.mode, entry.hash.clone()));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Writes the trees needed to hold a set of files and returns the hash of the
+/// root tree. The files are keyed by full path, as returned by [`flatten`].
+///
+/// Entries are sorted the way git requires: by name, with the names of
+/// subtrees compared as if they ended in a `/`.
+pub fn build(repo: &Repo, files: &BTreeMap<BString, (Mode, String)>) -> Result<String, String> {
+  let entries: Vec<(&[u8], &(Mode, String))> =
+    files.iter().map(|(p, e)| (p.as_slice(), e)).collect();
+  build_level(repo, &entries)
+}
+
+fn build_level(repo: &Repo, files: &[(&[u8], &(Mode, String))]) -> Result<String, String> {
+  // group the files by their first path component
+  let mut children = TreeBuilder::new();
+  let mut i = 0;
+  while i < files.len() {
+    let (path, (mode, hash)) = files[i];
+    match path.iter().position(|c| *c == b'/') {
+      None => {
+        children.insert(path, *mode, hash)?;
+        i += 1;
+      }
+      Some(slash) => {
+        let dir = &path[..slash];
+        let prefix = [dir, b"/"].concat();
+        let mut inner = Vec::new();
+        while i < files.len() && files[i].0.starts_with(&prefix) {
+          inner.push((&files[i].0[prefix.len()..], files[i].1));
+          i += 1;
+        }
+        let hash = build_level(repo, &inner)?;
+        children.insert(dir, Mode::Directory, &hash)?;
+      }
+    }
+  }
+
+  children.write(repo)
+}
+
+/// Collects the entries of a tree that is being put together or changed.
+///
+/// Entries are kept by name, so inserting a name that is already there
+/// replaces it. The tree is sorted the way git requires when it is built.
+///
+/// ## Example
+/// ```ignore
+/// let mut builder = TreeBuilder::from_tree(&tree);
+/// builder.insert("README.md", Mode::Normal, &blob)?;
+/// builder.remove("old.txt");
+/// let hash = builder.write(&repo)?;
+/// ```
+#[derive(Default)]
+pub struct TreeBuilder {
+  entries: BTreeMap<BString, TreeEntry>,
+}
+
+impl TreeBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Starts from the entries of an existing tree.
+  pub fn from_tree(tree: &Tree) -> Self {
+    Self {
+      entries: tree
+        .entries()
+        .iter()
+        .map(|entry| (entry.path.clone(), entry.clone()))
+        .collect(),
+    }
+  }
+
+  /// Adds an entry, returning the one it replaced (if any).
+  ///
+  /// Names must be a single, non-empty path component.
+  pub fn insert(
+    &mut self,
+    name: impl Into<BString>,
+    mode: Mode,
+    hash: &str,
+  ) -> Result<Option<TreeEntry>, String> {
+    let name = name.into();
+    if name.is_empty()
+      || name.as_slice() == b"."
+      || name.as_slice() == b".."
+      || name.contains(&b'/')
+      || name.contains(&0)
+    {
+      return Err(format!("invalid tree entry name '{}'", name));
+    }
+    let entry = TreeEntry::new(mode, name.clone(), hash)?;
+    Ok(self.entries.insert(name, entry))
+  }
+
+  /// Points an existing entry at another object, keeping its mode.
+  pub fn update(&mut self, name: impl AsRef<[u8]>, hash: &str) -> Result<(), String> {
+    let name = name.as_ref();
+    match self.entries.get_mut(name) {
+      Some(entry) => {
+        *entry = TreeEntry::new(entry.mode, name, hash)?;
+        Ok(())
+      }
+      None => Err(format!("no tree entry named '{}'", BString::from(name))),
     }
   }
+
+  /// Removes an entry, returning it if it was there.
+  pub fn remove(&mut self, name: impl AsRef<[u8]>) -> Option<TreeEntry> {
+    self.entries.remove(name.as_ref())
+  }
+
+  /// Makes the tree, without writing it.
+  pub fn build(self) -> Tree {
+    Tree::from_entries(self.entries.into_values().collect())
+  }
+
+  /// Writes the tree and returns its hash.
+  pub fn write(self, repo: &Repo) -> Result<String, String> {
+    super::write(repo, &self.build(), false)
+  }
+}
+
+/// The key git sorts tree entries by: the name, plus a `/` for subtrees.
+fn sort_key(name: &[u8], mode: Mode) -> Vec<u8> {
+  let mut key = name.to_vec();
+  if mode == Mode::Directory {
+    key.push(b'/');
+  }
+  key
 }
@@ -0,0 +1,173 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::os::unix::io::AsRawFd;
+use std::panic;
+use std::path::Path;
+use std::process::{self, Child, Command, Stdio};
+use std::sync::Mutex;
+
+use crate::repo::Repo;
+use crate::trace;
+
+/// The pager the output is going to, waited for when the process exits.
+static PAGER: Mutex<Option<(Child, trace::event::Child)>> = Mutex::new(None);
+
+/// The pager to show long output in: `GIT_PAGER`, `core.pager` or `PAGER`,
+/// and otherwise `less`. An empty pager or `cat` means none.
+pub fn pager(repo: &Repo) -> Option<String> {
+  choose(Some(repo))
+}
+
+/// Picks the pager, with `core.pager` from the repository if there is one.
+fn choose(repo: Option<&Repo>) -> Option<String> {
+  let configured = repo
+    .and_then(|repo| repo.config.as_ref())
+    .and_then(|config| config.get_from(Some("core"), "pager"))
+    .map(|pager| pager.to_owned());
+  let pager = env::var("GIT_PAGER")
+    .ok()
+    .or(configured)
+    .or_else(|| env::var("PAGER").ok())
+    .unwrap_or_else(|| "less".to_string());
+  Some(pager).filter(|pager| !pager.is_empty() && pager != "cat")
+}
+
+/// Whether a command's output goes to the pager unless `pager.<command>`
+/// says otherwise.
+fn pages_by_default(command: &str) -> bool {
+  matches!(command, "log" | "range-diff" | "show")
+}
+
+/// Sends the rest of the output of a command to the pager, if it should go
+/// there: when stdout is a terminal, and either the command pages by default
+/// or is told to by `-p` (`paginate` is `Some(true)`) or `pager.<command>`.
+/// `--no-pager` (`Some(false)`) wins over both.
+///
+/// `pager.<command>` may also name a pager for just that command. The pager
+/// is run by the shell with `LESS=FRX` and `LV=-c` unless they are set, so
+/// `less` quits straight away when the output fits on the screen and lets
+/// colors through. `GIT_PAGER_IN_USE` is set, so output that is colored when
+/// it goes to a terminal still is.
+pub fn setup(command: &str, paginate: Option<bool>) {
+  if !io::stdout().is_terminal() {
+    return;
+  }
+  let repo = Repo::discover(Path::new(".")).ok();
+  let setting = repo.as_ref().and_then(|repo| config(repo, command));
+  let (wanted, program) = match (paginate, setting) {
+    (Some(paginate), _) => (paginate, None),
+    (None, Some(setting)) => match setting.to_ascii_lowercase().as_str() {
+      "true" | "yes" | "on" | "1" => (true, None),
+      "false" | "no" | "off" | "0" => (false, None),
+      _ => (true, Some(setting)),
+    },
+    (None, None) => (pages_by_default(command), None),
+  };
+  let program = program.or_else(|| choose(repo.as_ref()));
+  match program {
+    Some(program) if wanted => start(&program),
+    _ => (),
+  }
+}
+
+/// The width of the terminal, for output that fits itself to it: `COLUMNS`
+/// if it is set, or else what the terminal the output goes to says, or 80
+/// if it goes to none.
+pub fn columns() -> usize {
+  let set = env::var("COLUMNS")
+    .ok()
+    .and_then(|columns| columns.parse().ok());
+  if let Some(columns) = set.filter(|columns| *columns > 0) {
+    return columns;
+  }
+  let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+  match unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut size) } {
+    0 if size.ws_col > 0 => size.ws_col as usize,
+    _ => 80,
+  }
+}
+
+/// Starts the pager and points stdout (and stderr, if it is a terminal) at
+/// it.
+fn start(program: &str) {
+  let mut command = Command::new("sh");
+  command.arg("-c").arg(program).stdin(Stdio::piped());
+  for (name, value) in [("LESS", "FRX"), ("LV", "-c")] {
+    if env::var_os(name).is_none() {
+      command.env(name, value);
+    }
+  }
+  let traced = trace::command("pager", &command);
+  let mut child = match command.spawn() {
+    Ok(child) => child,
+    Err(_) => return,
+  };
+  let input = child.stdin.take().unwrap();
+  // the terminal can't be asked for its width once the output is piped
+  if env::var_os("COLUMNS").is_none() {
+    env::set_var("COLUMNS", columns().to_string());
+  }
+  let _ = io::stdout().flush();
+  unsafe {
+    libc::dup2(input.as_raw_fd(), 1);
+    if io::stderr().is_terminal() {
+      libc::dup2(input.as_raw_fd(), 2);
+    }
+  }
+  drop(input);
+  env::set_var("GIT_PAGER_IN_USE", "true");
+  *PAGER.lock().unwrap() = Some((child, traced));
+  unsafe {
+    libc::atexit(wait_for_pager);
+  }
+}
+
+/// Closes the output the pager is reading, so it sees the end of it, and
+/// waits for it to quit, so the shell does not prompt while it is still
+/// showing the output.
+extern "C" fn wait_for_pager() {
+  let _ = io::stdout().flush();
+  let _ = io::stderr().flush();
+  unsafe {
+    libc::close(1);
+    libc::close(2);
+  }
+  if let Ok(mut pager) = PAGER.try_lock() {
+    if let Some((mut child, traced)) = pager.take() {
+      if let Ok(status) = child.wait() {
+        traced.exit(child.id(), &status);
+      }
+    }
+  }
+}
+
+/// Exits quietly when whatever is reading the output goes away, as when
+/// the pager is quit early or the output is piped to `head`, rather than
+/// panicking about the broken pipe.
+///
+/// Rust ignores `SIGPIPE`, which is what would quietly kill git here, so a
+/// write to the closed pipe fails instead, and printing panics. Other panics
+/// are reported as usual.
+pub fn exit_on_broken_pipe() {
+  let report = panic::take_hook();
+  panic::set_hook(Box::new(move |info| {
+    let message = info
+      .payload()
+      .downcast_ref::<String>()
+      .map(String::as_str)
+      .or_else(|| info.payload().downcast_ref::<&str>().copied())
+      .unwrap_or_default();
+    if message.contains("Broken pipe") {
+      process::exit(141);
+    }
+    report(info)
+  }));
+}
+
+fn config(repo: &Repo, command: &str) -> Option<String> {
+  let section = repo.config.as_ref()?.section(Some("pager"))?;
+  section
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(command))
+    .map(|(_, value)| value.to_string())
+}
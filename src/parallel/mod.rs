@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::repo::Repo;
+
+/// The fewest files worth starting threads for, when each takes little
+/// work. Below this, the work is best done on the calling thread.
+pub const THRESHOLD: usize = 100;
+
+/// The number of threads to use for work configured by `section.key`, such
+/// as `checkout.workers`.
+///
+/// As in git, a positive number is used as is, and `0` (or `true`) means one
+/// thread per CPU. `false` means a single thread. Unset, one thread per CPU
+/// is used.
+pub fn workers(repo: &Repo, section: &str, key: &str) -> usize {
+  let value = repo
+    .config
+    .as_ref()
+    .and_then(|config| config.section(Some(section)))
+    .and_then(|section| {
+      section
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.to_ascii_lowercase())
+    });
+  match value.as_deref() {
+    Some("false" | "no" | "off") => 1,
+    Some(value) => match value.parse::<i64>() {
+      Ok(n) if n > 0 => n as usize,
+      _ => cpus(),
+    },
+    None => cpus(),
+  }
+}
+
+fn cpus() -> usize {
+  thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Runs `f` on every item, on up to `workers` threads, and returns the
+/// results in the order of the items.
+///
+/// `done` is called on the calling thread each time an item is finished,
+/// with the number finished so far and the result, so that it can drive a
+/// progress meter. Items are finished in no particular order, except with a
+/// single worker, when everything happens on the calling thread in order.
+pub fn map<T, R, F, D>(items: &[T], workers: usize, f: F, mut done: D) -> Vec<R>
+where
+  T: Sync,
+  R: Send,
+  F: Fn(&T) -> R + Sync,
+  D: FnMut(usize, &R),
+{
+  let workers = workers.min(items.len());
+  if workers <= 1 {
+    return items
+      .iter()
+      .enumerate()
+      .map(|(i, item)| {
+        let result = f(item);
+        done(i + 1, &result);
+        result
+      })
+      .collect();
+  }
+
+  let next = AtomicUsize::new(0);
+  let mut results: Vec<Option<R>> = items.iter().map(|_| None).collect();
+  thread::scope(|scope| {
+    let (sender, receiver) = mpsc::channel();
+    for _ in 0..workers {
+      let sender = sender.clone();
+      let (next, f) = (&next, &f);
+      scope.spawn(move || loop {
+        let i = next.fetch_add(1, Ordering::Relaxed);
+        if i >= items.len() || sender.send((i, f(&items[i]))).is_err() {
+          break;
+        }
+      });
+    }
+    drop(sender);
+    for (count, (i, result)) in receiver.into_iter().enumerate() {
+      done(count + 1, &result);
+      results[i] = Some(result);
+    }
+  });
+  results.into_iter().map(Option::unwrap).collect()
+}
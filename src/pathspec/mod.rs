@@ -0,0 +1,23 @@
+use bstr::BString;
+
+use crate::repo::Repo;
+
+/// Converts command-line paths into paths relative to the root of the working
+/// tree, which is the form every pathspec is matched in.
+pub fn resolve(repo: &Repo, paths: &[String]) -> Result<Vec<BString>, String> {
+  paths.iter().map(|path| repo.worktree_path(path)).collect()
+}
+
+/// Checks whether a path is matched by any of the pathspecs.
+///
+/// A pathspec matches the path itself and, when it names a directory,
+/// everything beneath it. An empty list of pathspecs matches every path.
+pub fn matches(path: impl AsRef<[u8]>, specs: &[BString]) -> bool {
+  let path = path.as_ref();
+  specs.is_empty()
+    || specs.iter().any(|spec| {
+      spec.is_empty()
+        || path == spec.as_slice()
+        || (path.starts_with(spec) && path.get(spec.len()) == Some(&b'/'))
+    })
+}
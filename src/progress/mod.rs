@@ -0,0 +1,145 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+/// Receives updates from an operation that may take a while, such as
+/// writing out the files of a branch.
+///
+/// An operation goes through one or more phases, each started with a title
+/// (`Updating files`, `Counting objects`...) and the number of units it will
+/// take, if known. Library users can implement this to drive their own
+/// progress bars; the command line uses a [`Meter`].
+pub trait Progress {
+  /// Starts a new phase of the operation.
+  fn start(&mut self, title: &str, total: Option<u64>);
+
+  /// Reports that `done` units of the current phase are finished.
+  fn update(&mut self, done: u64);
+
+  /// Ends the current phase.
+  fn finish(&mut self);
+}
+
+/// Progress that goes nowhere, for operations nobody is watching.
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+  fn start(&mut self, _title: &str, _total: Option<u64>) {}
+
+  fn update(&mut self, _done: u64) {}
+
+  fn finish(&mut self) {}
+}
+
+/// How often the meter is redrawn when the percentage has not changed.
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Draws progress on standard error the way git does, eg.
+/// `Updating files:  45% (9/20)`, ending with `, done.` once the phase is
+/// over.
+///
+/// Nothing is drawn until the phase has been going for a couple of seconds
+/// (or `GIT_PROGRESS_DELAY` seconds), so quick operations stay quiet. After
+/// that the line is redrawn whenever the percentage changes, or once a
+/// second when there is no total.
+pub struct Meter {
+  title: String,
+  total: Option<u64>,
+  done: u64,
+  delay: Duration,
+  started: Instant,
+
+  /// When the line was last drawn and the percentage it showed.
+  drawn: Option<(Instant, u64)>,
+}
+
+impl Meter {
+  pub fn new() -> Self {
+    let delay = std::env::var("GIT_PROGRESS_DELAY")
+      .ok()
+      .and_then(|delay| delay.parse().ok())
+      .unwrap_or(2);
+    Self {
+      title: String::new(),
+      total: None,
+      done: 0,
+      delay: Duration::from_secs(delay),
+      started: Instant::now(),
+      drawn: None,
+    }
+  }
+
+  /// A meter if progress should be shown, or progress that goes nowhere.
+  ///
+  /// `show` comes from `--progress` or `--no-progress`. Without either,
+  /// progress is shown when standard error is a terminal.
+  pub fn boxed(show: Option<bool>) -> Box<dyn Progress> {
+    match show.unwrap_or_else(|| io::stderr().is_terminal()) {
+      true => Box::new(Meter::new()),
+      false => Box::new(NoProgress),
+    }
+  }
+
+  fn percent(&self) -> u64 {
+    match self.total {
+      Some(0) | None => 0,
+      Some(total) => self.done * 100 / total,
+    }
+  }
+
+  fn draw(&mut self, end: &str) {
+    let line = match self.total {
+      Some(total) => format!(
+        "{}: {:3}% ({}/{}){}",
+        self.title,
+        self.percent(),
+        self.done,
+        total,
+        end
+      ),
+      None => format!("{}: {}{}", self.title, self.done, end),
+    };
+    let mut stderr = io::stderr();
+    let _ = stderr.write_all(line.as_bytes());
+    let _ = stderr.flush();
+    self.drawn = Some((Instant::now(), self.percent()));
+  }
+}
+
+impl Default for Meter {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Progress for Meter {
+  fn start(&mut self, title: &str, total: Option<u64>) {
+    self.title = title.to_string();
+    self.total = total;
+    self.done = 0;
+    self.started = Instant::now();
+    self.drawn = None;
+  }
+
+  fn update(&mut self, done: u64) {
+    self.done = done;
+    if self.started.elapsed() < self.delay {
+      return;
+    }
+    let redraw = match self.drawn {
+      None => true,
+      Some((at, percent)) => {
+        (self.total.is_some() && percent != self.percent()) || at.elapsed() >= REDRAW_INTERVAL
+      }
+    };
+    if redraw {
+      self.draw("\r");
+    }
+  }
+
+  fn finish(&mut self) {
+    // a phase that was never drawn finished quickly enough to stay quiet
+    if self.drawn.is_some() {
+      self.draw(", done.\n");
+    }
+  }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use crate::{
+  diff::{self, hunks, lines, myers, patch, Edit, Op},
+  object::{commit::Commit, peel, read, serializable::Unbox},
+  repo::Repo,
+};
+
+/// The cost of a pairing that must not be made.
+const COST_MAX: i64 = 1 << 16;
+
+/// A commit as `range-diff` compares it: its author, message and diff
+/// rendered as text, with line numbers and blob ids left out so that the same
+/// change made on top of a different base still looks the same.
+///
+/// ```text
+///  ## Metadata ##
+/// Author: A U Thor <author@example.com>
+///
+///  ## Commit message ##
+///     Fix the frobnicator
+///
+///  ## src/frob.rs ##
+/// @@ src/frob.rs: fn frob()
+///  context
+/// -old
+/// +new
+/// ```
+pub struct Patch {
+  pub hash: String,
+  pub subject: String,
+  pub text: String,
+
+  /// Where the diff starts in `text`.
+  diff_offset: usize,
+
+  /// The number of lines in the diff.
+  diff_size: usize,
+}
+
+impl Patch {
+  /// Renders a (non-merge) commit.
+  pub fn read(repo: &Repo, hash: &str) -> Result<Patch, String> {
+    let object = read(repo, hash, Some("commit"))?;
+    let commit = object.unbox::<Commit>()?;
+    let converted = commit.to_utf8();
+    let commit = converted.as_ref().unwrap_or(commit);
+    let author = commit.get("author").map(|a| a.as_str()).unwrap_or("");
+    let author = match author.rfind('>') {
+      Some(end) => &author[..=end],
+      None => author,
+    };
+
+    let mut text = format!(
+      " ## Metadata ##\nAuthor: {}\n\n ## Commit message ##\n",
+      author
+    );
+    let message = commit.message().trim_end();
+    for line in message.lines() {
+      // blank lines lose their indentation, like the output of `git log`
+      let line = format!("    {}", line);
+      text.push_str(line.trim_end());
+      text.push('\n');
+    }
+
+    let base = match commit.parents().first() {
+      Some(parent) => Some(peel(repo, parent, Some("tree"))?),
+      None => None,
+    };
+    let changes = diff::diff_trees(repo, base.as_deref(), Some(commit.tree()))?;
+    let (mut diff_offset, mut diff_size) = (0, 0);
+    for change in diff::detect_renames(repo, changes)? {
+      text.push('\n');
+      if diff_offset == 0 {
+        diff_offset = text.len();
+      }
+      let name = match (&change.old, &change.new, &change.renamed_from) {
+        (None, _, _) => format!("{} (new)", change.path),
+        (_, None, _) => format!("{} (deleted)", change.path),
+        (_, _, Some(from)) => format!("{} => {}", from, change.path),
+        _ => change.path.to_string(),
+      };
+      text.push_str(&format!(" ## {}", name));
+      if let (Some((old, _)), Some((new, _))) = (&change.old, &change.new) {
+        if old != new {
+          text.push_str(&format!(" (mode change {} => {})", old, new));
+        }
+      }
+      text.push_str(" ##\n");
+      diff_size += 1;
+
+      let patch = patch::file_patch(repo, &change)?;
+      let body = patch
+        .lines()
+        .skip_while(|line| !line.starts_with("@@ ") && !line.starts_with("Binary files "));
+      for line in body {
+        match line.strip_prefix("@@ ") {
+          // drop the line numbers, keeping the function name
+          Some(rest) => {
+            let context = rest.find("@@").map(|i| &rest[i + 2..]).unwrap_or("");
+            text.push_str("@@");
+            if !context.is_empty() {
+              text.push_str(&format!(" {}:", change.path));
+            }
+            text.push_str(context);
+          }
+          None if line.starts_with(['+', '-', ' ']) => text.push_str(line),
+          None => text.push_str(&format!(" {}", line)),
+        }
+        text.push('\n');
+        diff_size += 1;
+      }
+    }
+
+    Ok(Patch {
+      hash: hash.to_owned(),
+      subject: message.lines().next().unwrap_or("").to_owned(),
+      text,
+      diff_offset,
+      diff_size,
+    })
+  }
+
+  fn diff(&self) -> &str {
+    &self.text[self.diff_offset..]
+  }
+}
+
+/// Pairs up the commits of two ranges, returning for each commit of the
+/// first range the index of its counterpart in the second, if any.
+///
+/// Commits with identical diffs are paired first. The rest are paired so as
+/// to keep the total size of the diffs between paired commits as small as
+/// possible, where leaving a commit unpaired costs `creation_factor` percent
+/// of the size of its own diff.
+pub fn correspondences(a: &[Patch], b: &[Patch], creation_factor: i64) -> Vec<Option<usize>> {
+  let mut a2b: Vec<Option<usize>> = vec![None; a.len()];
+  let mut b2a: Vec<Option<usize>> = vec![None; b.len()];
+  let mut exact: HashMap<&str, Vec<usize>> = HashMap::new();
+  for (i, patch) in a.iter().enumerate().rev() {
+    exact.entry(patch.diff()).or_default().push(i);
+  }
+  for (j, patch) in b.iter().enumerate() {
+    if let Some(i) = exact
+      .get_mut(patch.diff())
+      .and_then(|matches| matches.pop())
+    {
+      a2b[i] = Some(j);
+      b2a[j] = Some(i);
+    }
+  }
+
+  // An n by n matrix, where the rows past `a` and the columns past `b` stand
+  // for leaving a commit of the other range unpaired.
+  let n = a.len() + b.len();
+  let mut cost = vec![vec![0; n]; n];
+  for i in 0..a.len() {
+    for j in 0..b.len() {
+      cost[i][j] = match (a2b[i], b2a[j]) {
+        (Some(paired), _) if paired == j => 0,
+        (None, None) => diff_size(a[i].diff(), b[j].diff()),
+        _ => COST_MAX,
+      };
+    }
+    let unpaired = match a2b[i] {
+      None => a[i].diff_size as i64 * creation_factor / 100,
+      Some(_) => COST_MAX,
+    };
+    cost[i][b.len()..].iter_mut().for_each(|c| *c = unpaired);
+  }
+  for j in 0..b.len() {
+    let unpaired = match b2a[j] {
+      None => b[j].diff_size as i64 * creation_factor / 100,
+      Some(_) => COST_MAX,
+    };
+    cost[a.len()..].iter_mut().for_each(|row| row[j] = unpaired);
+  }
+
+  let assigned = assignment(&cost);
+  (0..a.len())
+    .map(|i| Some(assigned[i]).filter(|j| *j < b.len()))
+    .collect()
+}
+
+/// The number of lines (hunk headers included) in a diff of two texts.
+fn diff_size(a: &str, b: &str) -> i64 {
+  let (a, b) = (lines(a.as_bytes()), lines(b.as_bytes()));
+  hunks(&myers(&a, &b), patch::CONTEXT)
+    .iter()
+    .map(|hunk| 1 + hunk.edits.len() as i64)
+    .sum()
+}
+
+/// Solves the assignment problem on a square cost matrix: picks one column
+/// for each row, every column used once, with the smallest total cost.
+///
+/// This is the Hungarian algorithm, which keeps a potential for each row and
+/// column and grows the assignment one row at a time along the cheapest
+/// augmenting path, in O(n^3) overall.
+fn assignment(cost: &[Vec<i64>]) -> Vec<usize> {
+  let n = cost.len();
+  // one-based, with row and column 0 as sentinels
+  let (mut u, mut v) = (vec![0i64; n + 1], vec![0i64; n + 1]);
+  let mut row_of = vec![0usize; n + 1];
+  let mut way = vec![0usize; n + 1];
+  for row in 1..=n {
+    row_of[0] = row;
+    let mut column = 0;
+    let mut min = vec![i64::MAX; n + 1];
+    let mut used = vec![false; n + 1];
+    loop {
+      used[column] = true;
+      let current = row_of[column];
+      let (mut delta, mut next) = (i64::MAX, 0);
+      for j in 1..=n {
+        if used[j] {
+          continue;
+        }
+        let reduced = cost[current - 1][j - 1] - u[current] - v[j];
+        if reduced < min[j] {
+          min[j] = reduced;
+          way[j] = column;
+        }
+        if min[j] < delta {
+          delta = min[j];
+          next = j;
+        }
+      }
+      for j in 0..=n {
+        if used[j] {
+          u[row_of[j]] += delta;
+          v[j] -= delta;
+        } else {
+          min[j] -= delta;
+        }
+      }
+      column = next;
+      if row_of[column] == 0 {
+        break;
+      }
+    }
+    // flip the augmenting path
+    while column != 0 {
+      let previous = way[column];
+      row_of[column] = row_of[previous];
+      column = previous;
+    }
+  }
+
+  let mut assigned = vec![0; n];
+  for j in 1..=n {
+    if row_of[j] != 0 {
+      assigned[row_of[j] - 1] = j - 1;
+    }
+  }
+  assigned
+}
+
+/// Diffs the texts of two paired commits, indenting each line of the result
+/// by four spaces.
+///
+/// Hunk headers carry no line numbers, only the section of the commit the
+/// hunk is in: the nearest ` ## Section ##` line or inner hunk header above
+/// it.
+pub fn interdiff(a: &Patch, b: &Patch) -> String {
+  let (old, new) = (lines(a.text.as_bytes()), lines(b.text.as_bytes()));
+  let edits: Vec<Edit> = myers(&old, &new);
+  let mut out = String::new();
+  for hunk in hunks(&edits, patch::CONTEXT) {
+    out.push_str("    @@");
+    if let Some(section) = section(&old[..hunk.old_start.saturating_sub(1)]) {
+      out.push(' ');
+      out.push_str(&section);
+    }
+    out.push('\n');
+    for edit in &hunk.edits {
+      let (sign, line) = match edit.op {
+        Op::Equal => (' ', old[edit.old]),
+        Op::Delete => ('-', old[edit.old]),
+        Op::Insert => ('+', new[edit.new]),
+      };
+      out.push_str("    ");
+      out.push(sign);
+      out.push_str(&String::from_utf8_lossy(line));
+      if !line.ends_with(b"\n") {
+        out.push('\n');
+      }
+    }
+  }
+  out
+}
+
+/// Finds the name of the section the last of the given lines is in.
+fn section(lines: &[&[u8]]) -> Option<String> {
+  lines.iter().rev().find_map(|line| {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end_matches('\n');
+    if let Some(name) = line
+      .strip_prefix(" ## ")
+      .and_then(|l| l.strip_suffix(" ##"))
+    {
+      return Some(name.to_owned());
+    }
+    let rest = match line.find("@@ ") {
+      Some(at) if at <= 1 => &line[at + 3..],
+      _ => return None,
+    };
+    Some(rest.to_owned())
+  })
+}
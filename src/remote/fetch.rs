@@ -0,0 +1,723 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use super::{config_all, config_bool, head, refspec::RefSpec, Remote};
+use crate::cancel::Cancel;
+use crate::connected;
+use crate::identity::{Role, Signature};
+use crate::ignore::wildmatch;
+use crate::object::{
+  self,
+  quarantine::Quarantine,
+  refs::{
+    self,
+    pseudo::{self, FetchHead},
+  },
+};
+use crate::progress::Progress;
+use crate::repo::Repo;
+use crate::rev;
+use crate::rev::walk::merge_bases;
+use crate::transport::{
+  self,
+  negotiator::{Algorithm, Negotiator},
+  RemoteRef,
+};
+
+/// What a fetch did with a ref it fetched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+  /// It was only recorded in `FETCH_HEAD`.
+  FetchHead,
+
+  /// The local ref didn't exist, and was created.
+  New,
+
+  /// The local ref was already there.
+  UpToDate,
+
+  /// The local ref was moved on to a descendant.
+  FastForward,
+
+  /// The local ref was moved to something it isn't an ancestor of, as its
+  /// refspec allowed.
+  Forced,
+
+  /// The local ref was left as it was, for the reason given:
+  /// `non-fast-forward`, or `would clobber existing tag`.
+  Rejected(&'static str),
+
+  /// The local ref was deleted, as the remote ref it was kept for is gone.
+  Pruned,
+}
+
+/// Which tags a fetch fetches, beyond those the refspecs do.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tags {
+  /// The tags that point at what is fetched (or at what the repository
+  /// already has), when refs are fetched into local refs.
+  #[default]
+  Follow,
+
+  /// Every tag (`--tags`).
+  All,
+
+  /// None (`--no-tags`).
+  None,
+}
+
+impl Tags {
+  /// Parses a value of `remote.<name>.tagOpt`: `--tags` or `--no-tags`.
+  pub fn parse(value: &str) -> Option<Tags> {
+    match value {
+      "--tags" => Some(Tags::All),
+      "--no-tags" => Some(Tags::None),
+      _ => None,
+    }
+  }
+}
+
+/// How to fetch, beyond what to.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+  /// The commits (or globs of refs) to tell the remote the repository has,
+  /// instead of everything the refs reach. Fewer tips make for a quicker
+  /// negotiation, but may bring objects the repository already had.
+  pub negotiation_tips: Vec<String>,
+
+  /// Whether to delete the refs the refspecs map refs the remote no longer
+  /// has to, and with that the local tags it no longer has, instead of
+  /// doing as the config says (`remote.<name>.prune` or `fetch.prune`, and
+  /// `remote.<name>.pruneTags` or `fetch.pruneTags`).
+  pub prune: Option<bool>,
+  pub prune_tags: Option<bool>,
+
+  /// Which tags to fetch, instead of doing as `remote.<name>.tagOpt` says.
+  pub tags: Option<Tags>,
+
+  /// Whether to fetch into `refs/prefetch/` what the refspecs would fetch
+  /// into other refs, so that a later fetch finds the objects already
+  /// there, and leave `FETCH_HEAD`, the remote's `HEAD` and tags alone.
+  pub prefetch: bool,
+
+  /// Whether to keep the packs that come in (see
+  /// [`repack::is_kept`](crate::repack::is_kept)) once the fetch is done,
+  /// not only while it runs.
+  pub keep: bool,
+
+  /// Stops the fetch part of the way through once cancelled, leaving the
+  /// refs as they were and none of the objects that were coming in.
+  pub cancel: Cancel,
+}
+
+/// The `.keep` files of the packs a fetch brought in, which keep a repack
+/// from packing their objects elsewhere and deleting them before the refs
+/// that need those objects are updated. They are deleted once the fetch is
+/// done, unless the packs are to stay kept.
+struct PackLocks {
+  files: Vec<PathBuf>,
+  message: String,
+  keep: bool,
+}
+
+impl PackLocks {
+  fn new(keep: bool) -> Self {
+    Self {
+      files: Vec::new(),
+      message: format!("fetch-pack {}", process::id()),
+      keep,
+    }
+  }
+
+  /// Takes on the locks of the packs named `names` in `repo`.
+  fn add(&mut self, repo: &Repo, names: Vec<String>) {
+    let dir = repo.objects.dir().join("pack");
+    self.files.extend(
+      names
+        .into_iter()
+        .map(|name| dir.join(format!("{}.keep", name))),
+    );
+  }
+}
+
+impl Drop for PackLocks {
+  fn drop(&mut self) {
+    if self.keep {
+      return;
+    }
+    for file in &self.files {
+      // a pack that was already kept before the fetch stays that way
+      if fs::read_to_string(file).is_ok_and(|message| message.trim_end() == self.message) {
+        let _ = fs::remove_file(file);
+      }
+    }
+  }
+}
+
+/// A ref a fetch fetched, and what became of the local ref it maps to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefUpdate {
+  /// The remote ref, named in full.
+  pub remote: String,
+
+  /// The local ref, named in full, or `None` if it was only fetched into
+  /// `FETCH_HEAD`.
+  pub local: Option<String>,
+
+  /// Where the local ref was, if it existed, and where it is now (the null
+  /// hash if it was pruned).
+  pub old: Option<String>,
+  pub new: String,
+  pub status: Status,
+}
+
+/// A remote ref to fetch, and where it goes.
+struct Mapping<'a> {
+  remote: &'a RemoteRef,
+  local: Option<String>,
+  force: bool,
+  merge: bool,
+}
+
+/// Fetches from a remote, and updates the refs the refspecs map the
+/// fetched refs to.
+///
+/// The refspecs given are fetched, or with none the remote's configured
+/// ones, or with none of those the remote's `HEAD`; negative refspecs given
+/// leave refs out of the configured ones. Each ref fetched is
+/// recorded in `FETCH_HEAD`, those to merge first: all of them if refspecs
+/// were given, or else the branch that the current branch merges from
+/// (`branch.<name>.merge`) if it is set up to merge from this remote.
+///
+/// The objects are fetched into a quarantine, and only kept if everything
+/// the fetched refs reach is then there. A local ref is only moved to a
+/// descendant of where it was, unless its refspec starts with `+`, and a
+/// tag that is already there is never moved without one. The branch
+/// checked out in the working tree is never fetched into.
+///
+/// Pruning, the refs that glob refspecs keep remote refs in are deleted
+/// when the remote no longer has those refs (unless a negative refspec
+/// leaves them out), before any ref is updated. Pruning tags fetches
+/// `refs/tags/*:refs/tags/*` as well, so that tags the remote no longer has
+/// are deleted too.
+///
+/// The remote is told of the commits the repository has, picked as
+/// `fetch.negotiationAlgorithm` says from those its refs (or the
+/// negotiation tips) reach, so it can leave out what they reach.
+///
+/// Unless told otherwise (`--tags`, `--no-tags`, or
+/// `remote.<name>.tagOpt`), tags are followed: when refs are fetched into
+/// local refs, the tags the repository doesn't have that point at what it
+/// then has are fetched too. A remote that can sends them along with the
+/// rest; the others are asked for them again.
+///
+/// A prefetch fetches what the refspecs would, tags aside, into the same
+/// refs under `refs/prefetch/`, pruning those the remote no longer has,
+/// and touches nothing else.
+///
+/// Fetching with the remote's own refspecs points its `HEAD`
+/// (`refs/remotes/<name>/HEAD`) at the branch the remote's `HEAD` is on,
+/// if it doesn't point anywhere yet.
+pub fn fetch(
+  repo: &Repo,
+  remote: &Remote,
+  refspecs: &[RefSpec],
+  options: &Options,
+  progress: &mut dyn Progress,
+) -> Result<Vec<RefUpdate>, String> {
+  let algorithm = Algorithm::from_config(repo)?;
+  let tips = negotiation_tips(repo, &options.negotiation_tips)?;
+  let prune = options.prefetch
+    || options
+      .prune
+      .or(remote.prune)
+      .or_else(|| config_bool(repo, "fetch", "prune"))
+      .unwrap_or(false);
+  let prune_tags = options
+    .prune_tags
+    .or(remote.prune_tags)
+    .or_else(|| config_bool(repo, "fetch", "pruneTags"))
+    .unwrap_or(false);
+  let given = refspecs.iter().any(|spec| !spec.negative);
+  let mut refspecs = match given {
+    true => refspecs.to_vec(),
+    false => [&remote.fetch[..], refspecs].concat(),
+  };
+  let tags = match options.prefetch {
+    true => Tags::None,
+    false => options.tags.or(remote.tag_opt).unwrap_or_default(),
+  };
+  if options.prefetch {
+    refspecs = prefetch_refspecs(&refspecs);
+    if refspecs.iter().all(|spec| spec.negative) {
+      return Ok(Vec::new());
+    }
+  }
+  if prune && prune_tags && !options.prefetch && !refspecs.is_empty() {
+    refspecs.push(RefSpec::parse("refs/tags/*:refs/tags/*")?);
+  }
+  if tags == Tags::All {
+    refspecs.push(RefSpec::parse("refs/tags/*:refs/tags/*")?);
+  }
+
+  let mut transport = transport::open(repo, &remote.url)?;
+  let advertised = transport.refs()?;
+  let mut mappings = map_refs(repo, remote, &refspecs, given, &advertised)?;
+  let follow = tags == Tags::Follow && mappings.iter().any(|m| m.local.is_some());
+
+  let head = refs::read_symbolic(repo, "HEAD");
+  for mapping in &mappings {
+    if let Some(local) = &mapping.local {
+      let checked_out = !repo.bare && head.as_ref() == Some(local);
+      if checked_out && refs::lookup(repo, local).as_ref() != Some(&mapping.remote.hash) {
+        return Err(format!(
+          "refusing to fetch into branch '{}' checked out at '{}'",
+          local,
+          repo.work_tree.display()
+        ));
+      }
+    }
+  }
+
+  let mut wants: Vec<String> = Vec::new();
+  for mapping in &mappings {
+    let hash = &mapping.remote.hash;
+    if !repo.objects.exists(hash) && !wants.contains(hash) {
+      wants.push(hash.clone());
+    }
+  }
+  // the tags that point at what is fetched are asked for with it
+  let fetched: Vec<&str> = mappings.iter().map(|m| m.remote.hash.as_str()).collect();
+  let candidates = match follow {
+    true => tags_to_follow(repo, &mappings, &advertised),
+    false => Vec::new(),
+  };
+  for tag in &candidates {
+    let target = tag.peeled.as_ref().unwrap_or(&tag.hash);
+    if fetched.contains(&target.as_str()) && !repo.objects.exists(&tag.hash) {
+      wants.push(tag.hash.clone());
+    }
+  }
+  let mut locks = PackLocks::new(options.keep);
+  if !wants.is_empty() {
+    let packs = download(
+      repo,
+      remote,
+      transport.as_mut(),
+      &wants,
+      algorithm,
+      &tips,
+      &locks.message,
+      progress,
+      &options.cancel,
+    )?;
+    locks.add(repo, packs);
+  }
+
+  // and the others that point at what the repository now has, which the
+  // remote may not have sent, are asked for again
+  let followed: Vec<&RemoteRef> = candidates
+    .into_iter()
+    .filter(|tag| {
+      repo
+        .objects
+        .exists(tag.peeled.as_ref().unwrap_or(&tag.hash))
+    })
+    .collect();
+  let missing: Vec<String> = followed
+    .iter()
+    .filter(|tag| !repo.objects.exists(&tag.hash))
+    .map(|tag| tag.hash.clone())
+    .collect();
+  if !missing.is_empty() {
+    let mut transport = transport::open(repo, &remote.url)?;
+    transport.refs()?;
+    let packs = download(
+      repo,
+      remote,
+      transport.as_mut(),
+      &missing,
+      algorithm,
+      &tips,
+      &locks.message,
+      progress,
+      &options.cancel,
+    )?;
+    locks.add(repo, packs);
+  }
+  for tag in followed {
+    mappings.push(Mapping {
+      remote: tag,
+      local: Some(tag.name.clone()),
+      force: false,
+      merge: false,
+    });
+  }
+  options.cancel.check()?;
+
+  let url = display_url(&remote.url);
+  let mut heads: Vec<FetchHead> = mappings
+    .iter()
+    .map(|mapping| FetchHead {
+      hash: mapping.remote.hash.clone(),
+      merge: mapping.merge,
+      description: describe(&mapping.remote.name, &url),
+    })
+    .collect();
+  heads.sort_by_key(|head| !head.merge);
+  if !options.prefetch {
+    pseudo::write_fetch_head(repo, &heads)?;
+  }
+
+  let identity = Signature::current(repo, Role::Committer).ok();
+  let name = remote.name.as_deref().unwrap_or(&remote.url);
+  let mut updates = Vec::new();
+  if prune {
+    for (remote, local, old) in stale_refs(repo, &refspecs, &advertised) {
+      refs::delete(repo, &local)?;
+      updates.push(RefUpdate {
+        remote,
+        local: Some(local),
+        old: Some(old),
+        new: refs::NULL_HASH.to_string(),
+        status: Status::Pruned,
+      });
+    }
+  }
+  for mapping in &mappings {
+    let new = mapping.remote.hash.clone();
+    let local = match &mapping.local {
+      Some(local) => local,
+      None => {
+        updates.push(RefUpdate {
+          remote: mapping.remote.name.clone(),
+          local: None,
+          old: None,
+          new,
+          status: Status::FetchHead,
+        });
+        continue;
+      }
+    };
+    let old = refs::resolve(repo, Path::new(local)).ok();
+    let status = match &old {
+      None => Status::New,
+      Some(old) if *old == new => Status::UpToDate,
+      Some(_) if local.starts_with("refs/tags/") && !mapping.force => {
+        Status::Rejected("would clobber existing tag")
+      }
+      Some(old) if is_ancestor(repo, old, &new)? => Status::FastForward,
+      Some(_) if mapping.force => Status::Forced,
+      Some(_) => Status::Rejected("non-fast-forward"),
+    };
+    let reason = match status {
+      Status::New => Some("storing head"),
+      Status::FastForward => Some("fast-forward"),
+      Status::Forced => Some("forced-update"),
+      _ => None,
+    };
+    if let Some(reason) = reason {
+      refs::update(repo, local, &new)?;
+      if let Some(identity) = &identity {
+        let message = format!("fetch {}: {}", name, reason);
+        refs::append_log(
+          repo,
+          local,
+          old.as_deref(),
+          &new,
+          &identity.to_string(),
+          &message,
+        )?;
+      }
+    }
+    updates.push(RefUpdate {
+      remote: mapping.remote.name.clone(),
+      local: Some(local.clone()),
+      old,
+      new,
+      status,
+    });
+  }
+  if !given && !options.prefetch {
+    head::follow(repo, remote, &advertised)?;
+  }
+  Ok(updates)
+}
+
+/// The refspecs a prefetch fetches with: those that fetch into refs,
+/// forced, into the same refs under `refs/prefetch/`. Tags are left out, as
+/// they are for the user to fetch.
+fn prefetch_refspecs(refspecs: &[RefSpec]) -> Vec<RefSpec> {
+  refspecs
+    .iter()
+    .filter_map(|spec| {
+      if spec.negative {
+        return Some(spec.clone());
+      }
+      let dst = spec.dst.as_ref()?.strip_prefix("refs/")?;
+      if spec.src.starts_with("refs/tags/") {
+        return None;
+      }
+      Some(RefSpec {
+        force: true,
+        dst: Some(format!("refs/prefetch/{}", dst)),
+        ..spec.clone()
+      })
+    })
+    .collect()
+}
+
+/// Works out which of the remote's refs the refspecs fetch, and where each
+/// goes.
+fn map_refs<'a>(
+  repo: &Repo,
+  remote: &Remote,
+  refspecs: &[RefSpec],
+  given: bool,
+  advertised: &'a [RemoteRef],
+) -> Result<Vec<Mapping<'a>>, String> {
+  if refspecs.iter().all(|spec| spec.negative) {
+    let head = advertised
+      .iter()
+      .find(|r| r.name == "HEAD")
+      .ok_or("couldn't find remote ref HEAD")?;
+    return Ok(vec![Mapping {
+      remote: head,
+      local: None,
+      force: false,
+      merge: true,
+    }]);
+  }
+
+  // the branch that the current branch merges from, if it is this remote's
+  let current = refs::read_symbolic(repo, "HEAD")
+    .and_then(|head| head.strip_prefix("refs/heads/").map(str::to_string));
+  let merge_from = current.and_then(|branch| {
+    let section = format!("branch \"{}\"", branch);
+    let from = config_all(repo, &section, "remote").pop();
+    match from.is_some() && from == remote.name {
+      true => config_all(repo, &section, "merge").pop(),
+      false => None,
+    }
+  });
+
+  let mut mappings: Vec<Mapping> = Vec::new();
+  let mut add = |remote: &'a RemoteRef, local: Option<String>, force: bool| {
+    if mappings
+      .iter()
+      .any(|m| m.remote.name == remote.name && m.local == local)
+    {
+      return;
+    }
+    let merge = given || merge_from.as_ref() == Some(&remote.name);
+    mappings.push(Mapping {
+      remote,
+      local,
+      force,
+      merge,
+    });
+  };
+  let excluded = |name: &str| {
+    refspecs
+      .iter()
+      .any(|spec| spec.negative && spec.matches(name))
+  };
+  for spec in refspecs.iter().filter(|spec| !spec.negative) {
+    if spec.is_glob() {
+      for remote in advertised.iter().filter(|r| !excluded(&r.name)) {
+        if let Some(local) = spec.map_glob(&remote.name) {
+          add(remote, local, spec.force);
+        }
+      }
+      continue;
+    }
+    let src = match spec.src.is_empty() {
+      true => "HEAD",
+      false => &spec.src,
+    };
+    let remote = refs::expand(src)
+      .find_map(|name| advertised.iter().find(|r| r.name == name))
+      .ok_or_else(|| format!("couldn't find remote ref {}", spec.src))?;
+    if excluded(&remote.name) {
+      continue;
+    }
+    let local = spec
+      .dst
+      .as_ref()
+      .map(|dst| match dst.starts_with("refs/") || dst == "HEAD" {
+        true => dst.clone(),
+        false if remote.name.starts_with("refs/tags/") => format!("refs/tags/{}", dst),
+        false => format!("refs/heads/{}", dst),
+      });
+    if let Some(local) = &local {
+      if !refs::check_name(local) {
+        return Err(format!("invalid refspec '{}'", local));
+      }
+    }
+    add(remote, local, spec.force);
+  }
+  Ok(mappings)
+}
+
+/// Fetches the objects `wants` reach into a quarantine, and keeps them if
+/// everything they reach is then there. Returns the names of the packs that
+/// came in, kept with the message `keep`. The quarantine, and with it
+/// everything fetched, is deleted if the transport fails or is cancelled.
+#[allow(clippy::too_many_arguments)]
+fn download(
+  repo: &Repo,
+  remote: &Remote,
+  transport: &mut dyn transport::Transport,
+  wants: &[String],
+  algorithm: Algorithm,
+  tips: &[String],
+  keep: &str,
+  progress: &mut dyn Progress,
+  cancel: &Cancel,
+) -> Result<Vec<String>, String> {
+  let quarantine = Quarantine::new(repo)?;
+  let mut negotiator = Negotiator::new(repo, algorithm, tips);
+  let packs = transport.fetch(
+    &quarantine.repo(repo),
+    wants,
+    &mut negotiator,
+    Some(keep),
+    progress,
+    cancel,
+  )?;
+  let complete: Vec<String> = refs::collect(repo, None).into_values().collect();
+  if !connected::missing(&quarantine.repo(repo), wants, &complete)?.is_empty() {
+    return Err(format!("{} did not send all necessary objects", remote.url));
+  }
+  quarantine.migrate()?;
+  Ok(packs)
+}
+
+/// The tags of the remote that following tags might fetch: those the
+/// repository doesn't have, that aren't fetched already.
+fn tags_to_follow<'a>(
+  repo: &Repo,
+  mappings: &[Mapping],
+  advertised: &'a [RemoteRef],
+) -> Vec<&'a RemoteRef> {
+  advertised
+    .iter()
+    .filter(|r| r.name.starts_with("refs/tags/") && refs::check_name(&r.name))
+    .filter(|r| !mappings.iter().any(|m| m.remote.name == r.name))
+    .filter(|r| !refs::exists(repo, &r.name))
+    .collect()
+}
+
+/// The local refs that glob refspecs keep remote refs in, whose remote refs
+/// the remote no longer has, each with the remote ref it was for and where
+/// it points. Symbolic refs (like `refs/remotes/origin/HEAD`) are never
+/// stale, nor are refs for remote refs that a negative refspec leaves out.
+fn stale_refs(
+  repo: &Repo,
+  refspecs: &[RefSpec],
+  advertised: &[RemoteRef],
+) -> Vec<(String, String, String)> {
+  let mut stale = Vec::new();
+  for (local, hash) in refs::collect(repo, None) {
+    if refs::read_symbolic(repo, &local).is_some() {
+      continue;
+    }
+    let remote = refspecs
+      .iter()
+      .filter(|spec| !spec.negative && spec.is_glob())
+      .find_map(|spec| spec.unmap_glob(&local));
+    let remote = match remote {
+      Some(remote) => remote,
+      None => continue,
+    };
+    let gone = !advertised.iter().any(|r| r.name == remote);
+    if gone
+      && !refspecs
+        .iter()
+        .any(|spec| spec.negative && spec.matches(&remote))
+    {
+      stale.push((remote, local, hash));
+    }
+  }
+  stale
+}
+
+/// Whether the commit `old` is an ancestor of `new`. Anything that isn't a
+/// commit is an ancestor of nothing.
+pub(super) fn is_ancestor(repo: &Repo, old: &str, new: &str) -> Result<bool, String> {
+  let (old, new) = match (
+    object::peel(repo, old, Some("commit")),
+    object::peel(repo, new, Some("commit")),
+  ) {
+    (Ok(old), Ok(new)) => (old, new),
+    _ => return Ok(false),
+  };
+  Ok(merge_bases(repo, &old, &new)?.first() == Some(&old))
+}
+
+/// The commits to start negotiating from: those `tips` name, or every ref
+/// and `HEAD` if there are none. A tip with `*`, `?` or `[` in it is a glob
+/// of refs, with `refs/` added if it doesn't start with it.
+fn negotiation_tips(repo: &Repo, tips: &[String]) -> Result<Vec<String>, String> {
+  let refs = refs::collect(repo, None);
+  if tips.is_empty() {
+    let mut all: Vec<String> = refs.into_values().collect();
+    all.extend(refs::resolve(repo, Path::new("HEAD")).ok());
+    return Ok(all);
+  }
+  let mut commits = Vec::new();
+  for tip in tips {
+    if !tip.contains(['*', '?', '[']) {
+      commits.push(rev::parse(repo, tip).map_err(|_| format!("{} is not a valid object", tip))?);
+      continue;
+    }
+    let pattern = match tip.starts_with("refs/") {
+      true => tip.clone(),
+      false => format!("refs/{}", tip),
+    };
+    let matched: Vec<String> = refs
+      .iter()
+      .filter(|(name, _)| wildmatch(pattern.as_bytes(), name.as_bytes()))
+      .map(|(_, hash)| hash.clone())
+      .collect();
+    if matched.is_empty() {
+      eprintln!(
+        "warning: ignoring --negotiation-tip={} because it does not match any refs",
+        tip
+      );
+    }
+    commits.extend(matched);
+  }
+  Ok(commits)
+}
+
+/// The URL of a remote as `FETCH_HEAD` and the fetch report show it,
+/// without trailing slashes or `.git`.
+pub fn display_url(url: &str) -> String {
+  let url = url.trim_end_matches('/');
+  let url = url.strip_suffix(".git").unwrap_or(url);
+  match transport::Url::parse(url) {
+    Ok(parsed) => parsed.to_string(),
+    Err(_) => url.to_string(),
+  }
+}
+
+/// Describes a fetched ref for `FETCH_HEAD`, eg. `branch 'main' of <url>`.
+fn describe(name: &str, url: &str) -> String {
+  let kinds = [
+    ("refs/heads/", "branch "),
+    ("refs/tags/", "tag "),
+    ("refs/remotes/", "remote-tracking branch "),
+  ];
+  if name == "HEAD" {
+    return url.to_string();
+  }
+  match kinds
+    .iter()
+    .find_map(|(prefix, kind)| name.strip_prefix(prefix).map(|short| (kind, short)))
+  {
+    Some((kind, short)) => format!("{}'{}' of {}", kind, short, url),
+    None => format!("'{}' of {}", name, url),
+  }
+}
@@ -0,0 +1,110 @@
+use super::Remote;
+use crate::object::refs;
+use crate::repo::Repo;
+use crate::transport::{self, RemoteRef};
+
+/// The branch a remote's `HEAD` is on, named in full: the one the remote
+/// says `HEAD` stands for, or else a branch at the same commit as it,
+/// `init.defaultBranch` (or `master`) first.
+///
+/// Returns `None` if the remote has no `HEAD`, or no branch is at it.
+pub fn remote_head(repo: &Repo, advertised: &[RemoteRef]) -> Option<String> {
+  let head = advertised.iter().find(|r| r.name == "HEAD")?;
+  if let Some(target) = &head.target {
+    return Some(target.clone());
+  }
+  let default = repo
+    .config
+    .as_ref()
+    .and_then(|config| config.section(Some("init")))
+    .and_then(|section| {
+      section
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("defaultBranch"))
+    })
+    .map_or("master", |(_, value)| value);
+  let default = format!("refs/heads/{}", default);
+  let branches = advertised
+    .iter()
+    .filter(|r| r.name.starts_with("refs/heads/") && r.hash == head.hash);
+  branches
+    .clone()
+    .find(|r| r.name == default)
+    .or_else(|| branches.min_by(|a, b| a.name.cmp(&b.name)))
+    .map(|r| r.name.clone())
+}
+
+/// The symbolic ref that stands for a remote's default branch, eg.
+/// `refs/remotes/origin/HEAD`.
+pub fn head_ref(name: &str) -> String {
+  format!("refs/remotes/{}/HEAD", name)
+}
+
+/// Where the remote's fetch refspecs keep a branch of the remote, eg.
+/// `refs/remotes/origin/main` for `refs/heads/main`.
+pub fn tracking_ref(remote: &Remote, branch: &str) -> Option<String> {
+  remote
+    .fetch
+    .iter()
+    .filter(|spec| !spec.negative)
+    .find_map(|spec| match spec.is_glob() {
+      true => spec.map_glob(branch).flatten(),
+      false if spec.src == branch => spec.dst.clone(),
+      false => None,
+    })
+}
+
+/// Points the remote's `HEAD` (see [`head_ref`]) at one of its
+/// remote-tracking branches, eg. `refs/remotes/origin/main` for `main`,
+/// which must exist.
+pub fn set(repo: &Repo, name: &str, branch: &str) -> Result<(), String> {
+  let target = format!("refs/remotes/{}/{}", name, branch);
+  if !refs::exists(repo, &target) {
+    return Err(format!("Not a valid ref: {}", target));
+  }
+  refs::update_symbolic(repo, &head_ref(name), &target)
+}
+
+/// Asks the remote which branch its `HEAD` is on, and points the remote's
+/// `HEAD` at the remote-tracking branch for it. Returns the branch, with
+/// the name the remote has for it (eg. `main`).
+pub fn set_auto(repo: &Repo, remote: &Remote) -> Result<String, String> {
+  let name = remote.name.as_deref().ok_or("No such remote")?;
+  let advertised = transport::open(repo, &remote.url)?.refs()?;
+  let branch = remote_head(repo, &advertised).ok_or("Cannot determine remote HEAD")?;
+  let target =
+    tracking_ref(remote, &branch).ok_or_else(|| format!("Not a valid ref: {}", branch))?;
+  if !refs::exists(repo, &target) {
+    return Err(format!("Not a valid ref: {}", target));
+  }
+  refs::update_symbolic(repo, &head_ref(name), &target)?;
+  Ok(branch.trim_start_matches("refs/heads/").to_string())
+}
+
+/// Deletes the remote's `HEAD`.
+pub fn delete(repo: &Repo, name: &str) -> Result<(), String> {
+  let head = head_ref(name);
+  match refs::read_symbolic(repo, &head) {
+    Some(_) => refs::delete(repo, &head),
+    None => Err(format!("Not a symbolic ref: {}", head)),
+  }
+}
+
+/// Points the remote's `HEAD` at the remote-tracking branch for the branch
+/// the remote's `HEAD` is on, as a fetch with the remote's own refspecs
+/// does, unless it already points somewhere.
+pub(super) fn follow(repo: &Repo, remote: &Remote, advertised: &[RemoteRef]) -> Result<(), String> {
+  let name = match &remote.name {
+    Some(name) => name,
+    None => return Ok(()),
+  };
+  let head = head_ref(name);
+  if refs::read_symbolic(repo, &head).is_some() || refs::exists(repo, &head) {
+    return Ok(());
+  }
+  let target = remote_head(repo, advertised).and_then(|branch| tracking_ref(remote, &branch));
+  match target {
+    Some(target) if refs::exists(repo, &target) => refs::update_symbolic(repo, &head, &target),
+    _ => Ok(()),
+  }
+}
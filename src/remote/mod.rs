@@ -0,0 +1,122 @@
+pub mod fetch;
+pub mod head;
+pub mod push;
+pub mod refspec;
+
+use crate::repo::Repo;
+
+use fetch::Tags;
+use refspec::RefSpec;
+
+/// Another repository that this one fetches from and pushes to, as set up
+/// in a `remote.<name>` section of the config, or just a URL.
+///
+/// ### Example
+/// ```text
+/// [remote "origin"]
+///   url = http://example.com/project.git
+///   fetch = +refs/heads/*:refs/remotes/origin/*
+/// ```
+#[derive(Clone, Debug)]
+pub struct Remote {
+  /// The name of the remote, or `None` for a URL given as it is.
+  pub name: Option<String>,
+  pub url: String,
+
+  /// The refspecs fetched when none are given.
+  pub fetch: Vec<RefSpec>,
+
+  /// The refspecs pushed when none are given.
+  pub push: Vec<RefSpec>,
+
+  /// Whether fetching deletes the refs that the refspecs map refs the
+  /// remote no longer has to (`remote.<name>.prune`), and local tags it no
+  /// longer has (`remote.<name>.pruneTags`), if the config says.
+  pub prune: Option<bool>,
+  pub prune_tags: Option<bool>,
+
+  /// Which tags fetching fetches, if `remote.<name>.tagOpt` says:
+  /// `--tags` for all of them, or `--no-tags` for none.
+  pub tag_opt: Option<Tags>,
+}
+
+impl Remote {
+  /// The remote named `name` in the config, or else the repository at the
+  /// URL `name`.
+  pub fn get(repo: &Repo, name: &str) -> Result<Remote, String> {
+    let section = format!("remote \"{}\"", name);
+    if let Some(url) = config_all(repo, &section, "url").pop() {
+      let refspecs = |key: &str| {
+        config_all(repo, &section, key)
+          .iter()
+          .map(|spec| RefSpec::parse(spec))
+          .collect::<Result<Vec<_>, _>>()
+      };
+      return Ok(Remote {
+        name: Some(name.to_string()),
+        url,
+        fetch: refspecs("fetch")?,
+        push: refspecs("push")?,
+        prune: config_bool(repo, &section, "prune"),
+        prune_tags: config_bool(repo, &section, "pruneTags"),
+        tag_opt: config_all(repo, &section, "tagOpt")
+          .pop()
+          .and_then(|value| Tags::parse(&value)),
+      });
+    }
+    match name.contains("://") {
+      true => Ok(Remote {
+        name: None,
+        url: name.to_string(),
+        fetch: Vec::new(),
+        push: Vec::new(),
+        prune: None,
+        prune_tags: None,
+        tag_opt: None,
+      }),
+      false => Err(format!("'{}' does not appear to be a git repository", name)),
+    }
+  }
+}
+
+/// The names of the remotes set up in the config, in the order they are.
+pub fn names(repo: &Repo) -> Vec<String> {
+  let config = match &repo.config {
+    Some(config) => config,
+    None => return Vec::new(),
+  };
+  let mut names: Vec<String> = config
+    .sections()
+    .flatten()
+    .filter_map(|section| section.strip_prefix("remote \"")?.strip_suffix('"'))
+    .map(str::to_string)
+    .collect();
+  names.dedup();
+  names
+}
+
+/// Every value of a key that may be given more than once, in order.
+fn config_all(repo: &Repo, section: &str, key: &str) -> Vec<String> {
+  let section = match repo.config.as_ref().and_then(|c| c.section(Some(section))) {
+    Some(section) => section,
+    None => return Vec::new(),
+  };
+  section
+    .iter()
+    .filter(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_owned())
+    .collect()
+}
+
+/// Reads a boolean setting, as git does, from its last value.
+fn config_bool(repo: &Repo, section: &str, key: &str) -> Option<bool> {
+  match config_all(repo, section, key)
+    .pop()?
+    .to_ascii_lowercase()
+    .as_str()
+  {
+    "true" | "yes" | "on" | "1" | "" => Some(true),
+    "false" | "no" | "off" | "0" => Some(false),
+    _ => None,
+  }
+}
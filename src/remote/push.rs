@@ -0,0 +1,357 @@
+use super::{config_all, config_bool, fetch::is_ancestor, head, refspec::RefSpec, Remote};
+use crate::cancel::Cancel;
+use crate::identity::{Role, Signature};
+use crate::object::refs;
+use crate::progress::Progress;
+use crate::repo::Repo;
+use crate::rev;
+use crate::transport::{
+  self,
+  send_pack::{Command, Signed},
+  RemoteRef,
+};
+
+/// What a push did with a ref.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+  /// The remote ref didn't exist, and was created.
+  New,
+
+  /// The remote ref was already there.
+  UpToDate,
+
+  /// The remote ref was moved on to a descendant.
+  FastForward,
+
+  /// The remote ref was moved to something it isn't an ancestor of, as
+  /// forcing allowed.
+  Forced,
+
+  /// The remote ref was deleted.
+  Deleted,
+
+  /// The remote ref was left as it was, for the reason given:
+  /// `non-fast-forward`, `fetch first` (the remote ref is at something the
+  /// repository doesn't have), `already exists` (for a tag), or `atomic
+  /// push failed` (another ref of an atomic push was rejected).
+  Rejected(&'static str),
+
+  /// The remote turned the update down, for the reason it gave.
+  RemoteRejected(String),
+}
+
+/// How to push, beyond what to.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+  /// Update remote refs even when that loses commits, as if every refspec
+  /// started with `+`.
+  pub force: bool,
+
+  /// Strings for the hooks of the remote, instead of those
+  /// `push.pushOption` gives.
+  pub push_options: Vec<String>,
+
+  /// Whether to sign the push, instead of doing as `push.gpgSign` says.
+  pub signed: Option<Signed>,
+
+  /// Whether to update every remote ref or none of them, instead of doing
+  /// as `push.atomic` says.
+  pub atomic: Option<bool>,
+
+  /// Stops the push while the pack is being made once cancelled, before
+  /// the remote is asked to update anything.
+  pub cancel: Cancel,
+}
+
+/// A remote ref a push updated, or meant to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefUpdate {
+  /// What was pushed: a local ref named in full, or the revision given.
+  /// `None` for a remote ref that was deleted.
+  pub local: Option<String>,
+
+  /// The remote ref, named in full.
+  pub remote: String,
+
+  /// Where the remote ref was, if it existed, and where it is now (the null
+  /// hash if it was deleted).
+  pub old: Option<String>,
+  pub new: String,
+  pub status: Status,
+}
+
+/// Pushes to a remote, and updates the remote-tracking refs for the refs
+/// that were updated.
+///
+/// The refspecs given are pushed, or with none the remote's configured
+/// ones (`remote.<name>.push`), or with none of those the current branch
+/// to the branch of the same name. A refspec with no source deletes the
+/// remote ref. A remote ref is only moved to a descendant of where it was,
+/// and a remote tag is never moved, unless the refspec starts with `+` or
+/// the push is forced; the other refs are pushed all the same.
+///
+/// The push options given, or with none those `push.pushOption` gives, are
+/// passed on to the hooks of the remote. Pushes are signed as `--signed`
+/// or `push.gpgSign` says: `true`, `false` (the default) or `if-asked`. An
+/// atomic push (`--atomic`, or `push.atomic`) sends nothing if any ref is
+/// rejected, and has the remote update every ref or none of them.
+///
+/// What the remote says it did in place of what it was asked to (with
+/// `report-status-v2`) is what the updates returned say was done.
+pub fn push(
+  repo: &Repo,
+  remote: &Remote,
+  refspecs: &[RefSpec],
+  options: &Options,
+  progress: &mut dyn Progress,
+) -> Result<Vec<RefUpdate>, String> {
+  let push_options = match options.push_options.is_empty() {
+    true => config_list(repo, "push", "pushOption"),
+    false => options.push_options.clone(),
+  };
+  let signed = match (options.signed, config_all(repo, "push", "gpgSign").pop()) {
+    (Some(signed), _) => signed,
+    (None, Some(value)) => Signed::parse(&value)
+      .ok_or_else(|| format!("bad config value '{}' for 'push.gpgsign'", value))?,
+    (None, None) => Signed::No,
+  };
+  let refspecs = match (refspecs.is_empty(), remote.push.is_empty()) {
+    (false, _) => refspecs.to_vec(),
+    (true, false) => remote.push.clone(),
+    (true, true) => {
+      let branch = refs::read_symbolic(repo, "HEAD")
+        .filter(|head| head.starts_with("refs/heads/"))
+        .ok_or("You are not currently on a branch.")?;
+      vec![RefSpec::parse(&branch)?]
+    }
+  };
+
+  let mut transport = transport::open_push(&remote.url)?;
+  let advertised = transport.refs()?;
+  let mut updates = map_refs(repo, &refspecs, &advertised, options.force)?;
+  let atomic = options
+    .atomic
+    .or_else(|| config_bool(repo, "push", "atomic"))
+    .unwrap_or(false);
+  if atomic
+    && updates
+      .iter()
+      .any(|update| matches!(update.status, Status::Rejected(_)))
+  {
+    for update in updates.iter_mut().filter(|update| changes(update)) {
+      update.status = Status::Rejected("atomic push failed");
+    }
+    return Ok(updates);
+  }
+  let commands: Vec<Command> = updates
+    .iter()
+    .filter(|update| changes(update))
+    .map(|update| Command {
+      name: update.remote.clone(),
+      old: update.old.clone().unwrap_or(refs::NULL_HASH.to_string()),
+      new: update.new.clone(),
+    })
+    .collect();
+  if commands.is_empty() {
+    return Ok(updates);
+  }
+  let report = transport.push(
+    repo,
+    &commands,
+    &push_options,
+    signed,
+    atomic,
+    progress,
+    &options.cancel,
+  )?;
+  for report in report {
+    let update = match updates.iter_mut().find(|u| u.remote == report.name) {
+      Some(update) => update,
+      None => continue,
+    };
+    if let Err(reason) = report.result {
+      update.status = Status::RemoteRejected(reason);
+      continue;
+    }
+    if let Some(refname) = report.refname {
+      update.remote = refname;
+    }
+    if let Some(old) = report.old {
+      update.old = Some(old).filter(|old| old != refs::NULL_HASH);
+    }
+    if let Some(new) = report.new {
+      update.new = new;
+    }
+    if report.forced {
+      update.status = Status::Forced;
+    }
+  }
+  update_tracking(repo, remote, &updates)?;
+  Ok(updates)
+}
+
+/// Works out which remote refs the refspecs push to, with what, and
+/// whether they may be.
+fn map_refs(
+  repo: &Repo,
+  refspecs: &[RefSpec],
+  advertised: &[RemoteRef],
+  force: bool,
+) -> Result<Vec<RefUpdate>, String> {
+  let excluded = |name: &str| {
+    refspecs
+      .iter()
+      .any(|spec| spec.negative && spec.matches(name))
+  };
+  // what to push where: the local ref or revision, its hash, and the remote
+  // ref, for each refspec
+  let mut pushes: Vec<(Option<String>, Option<String>, String, bool)> = Vec::new();
+  for spec in refspecs.iter().filter(|spec| !spec.negative) {
+    let force = force || spec.force;
+    if spec.is_glob() {
+      for (name, hash) in refs::collect(repo, None) {
+        if let Some(remote) = spec.map_glob(&name).filter(|_| !excluded(&name)) {
+          let remote = remote.unwrap_or_else(|| name.clone());
+          pushes.push((Some(name), Some(hash), remote, force));
+        }
+      }
+      continue;
+    }
+    if spec.src.is_empty() {
+      let dst = spec.dst.as_deref().unwrap_or_default();
+      pushes.push((None, None, remote_name(dst, None, advertised)?, force));
+      continue;
+    }
+    let local = refs::expand(&spec.src).find(|name| refs::exists(repo, name));
+    if local.as_deref().is_some_and(excluded) {
+      continue;
+    }
+    let hash = rev::parse(repo, &spec.src)
+      .map_err(|_| format!("src refspec {} does not match any", spec.src))?;
+    let remote = match &spec.dst {
+      Some(dst) => remote_name(dst, local.as_deref(), advertised)?,
+      None => local.clone().ok_or_else(|| not_full(&spec.src))?,
+    };
+    let local = local.unwrap_or_else(|| spec.src.clone());
+    pushes.push((Some(local), Some(hash), remote, force));
+  }
+
+  let mut updates: Vec<RefUpdate> = Vec::new();
+  for (local, hash, remote, force) in pushes {
+    if updates.iter().any(|update| update.remote == remote) {
+      continue;
+    }
+    let old = advertised
+      .iter()
+      .find(|r| r.name == remote)
+      .map(|r| r.hash.clone());
+    let status = match (&old, &hash) {
+      (None, None) => {
+        return Err(format!(
+          "unable to delete '{}': remote ref does not exist",
+          remote
+        ))
+      }
+      (Some(_), None) => Status::Deleted,
+      (None, Some(_)) => Status::New,
+      (Some(old), Some(new)) if old == new => Status::UpToDate,
+      (Some(_), Some(_)) if remote.starts_with("refs/tags/") && !force => {
+        Status::Rejected("already exists")
+      }
+      (Some(old), Some(_)) if !repo.objects.exists(old) => match force {
+        true => Status::Forced,
+        false => Status::Rejected("fetch first"),
+      },
+      (Some(old), Some(new)) if is_ancestor(repo, old, new)? => Status::FastForward,
+      _ if force => Status::Forced,
+      _ => Status::Rejected("non-fast-forward"),
+    };
+    updates.push(RefUpdate {
+      local,
+      remote,
+      old,
+      new: hash.unwrap_or(refs::NULL_HASH.to_string()),
+      status,
+    });
+  }
+  Ok(updates)
+}
+
+/// The remote ref a destination names: itself if it is named in full, the
+/// ref the remote has by that name, or else a branch or tag like the local
+/// ref pushed to it.
+fn remote_name(dst: &str, local: Option<&str>, advertised: &[RemoteRef]) -> Result<String, String> {
+  if dst.starts_with("refs/") {
+    return match refs::check_name(dst) {
+      true => Ok(dst.to_string()),
+      false => Err(format!("invalid refspec '{}'", dst)),
+    };
+  }
+  if let Some(name) = refs::expand(dst).find(|name| advertised.iter().any(|r| r.name == *name)) {
+    return Ok(name);
+  }
+  match local {
+    Some(local) if local.starts_with("refs/heads/") => Ok(format!("refs/heads/{}", dst)),
+    Some(local) if local.starts_with("refs/tags/") => Ok(format!("refs/tags/{}", dst)),
+    _ => Err(not_full(dst)),
+  }
+}
+
+fn not_full(name: &str) -> String {
+  format!(
+    "The destination you provided is not a full refname (i.e., starting with \"refs/\"): {}",
+    name
+  )
+}
+
+/// Whether an update changes the remote ref, rather than leaving it as it
+/// is.
+fn changes(update: &RefUpdate) -> bool {
+  matches!(
+    update.status,
+    Status::New | Status::FastForward | Status::Forced | Status::Deleted
+  )
+}
+
+/// Moves the remote-tracking refs that the remote's fetch refspecs keep the
+/// pushed refs in to where the push put them, so they show what the remote
+/// now has without fetching again.
+fn update_tracking(repo: &Repo, remote: &Remote, updates: &[RefUpdate]) -> Result<(), String> {
+  let identity = Signature::current(repo, Role::Committer).ok();
+  for update in updates.iter().filter(|update| changes(update)) {
+    let tracking = match head::tracking_ref(remote, &update.remote) {
+      Some(tracking) => tracking,
+      None => continue,
+    };
+    if update.status == Status::Deleted {
+      refs::delete(repo, &tracking)?;
+      continue;
+    }
+    let old = refs::lookup(repo, &tracking);
+    refs::update(repo, &tracking, &update.new)?;
+    if let Some(identity) = &identity {
+      refs::append_log(
+        repo,
+        &tracking,
+        old.as_deref(),
+        &update.new,
+        &identity.to_string(),
+        "update by push",
+      )?;
+    }
+  }
+  Ok(())
+}
+
+/// Every value of a key that may be given more than once, where an empty
+/// value clears those before it.
+fn config_list(repo: &Repo, section: &str, key: &str) -> Vec<String> {
+  let mut values: Vec<String> = Vec::new();
+  for value in config_all(repo, section, key) {
+    match value.is_empty() {
+      true => values.clear(),
+      false => values.push(value),
+    }
+  }
+  values
+}
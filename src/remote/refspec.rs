@@ -0,0 +1,95 @@
+/// Which refs of a remote to fetch, and where to keep them, eg.
+/// `+refs/heads/*:refs/remotes/origin/*`.
+///
+/// A leading `+` lets the refs be updated even when that loses commits. The
+/// source and destination may each have a `*` in them, which matches any
+/// part of a name and is replaced by it. A refspec with no destination
+/// fetches the ref without keeping it anywhere but `FETCH_HEAD`, or pushes
+/// it to the ref of the same name. One with no source fetches `HEAD`, or
+/// deletes the destination when pushing (`:refs/heads/old`).
+///
+/// A refspec starting with `^` is negative: the refs its source matches
+/// aren't fetched, whatever other refspecs say. It has no destination.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefSpec {
+  pub force: bool,
+  pub negative: bool,
+  pub src: String,
+  pub dst: Option<String>,
+}
+
+impl RefSpec {
+  pub fn parse(spec: &str) -> Result<RefSpec, String> {
+    let invalid = || format!("invalid refspec '{}'", spec);
+    if let Some(src) = spec.strip_prefix('^') {
+      if src.is_empty() || src.contains(':') || src.matches('*').count() > 1 {
+        return Err(invalid());
+      }
+      return Ok(RefSpec {
+        force: false,
+        negative: true,
+        src: src.to_string(),
+        dst: None,
+      });
+    }
+    let (force, rest) = match spec.strip_prefix('+') {
+      Some(rest) => (true, rest),
+      None => (false, spec),
+    };
+    let (src, dst) = match rest.split_once(':') {
+      Some((src, "")) => (src, None),
+      Some((src, dst)) => (src, Some(dst)),
+      None => (rest, None),
+    };
+    let globs = |part: &str| part.matches('*').count();
+    match dst {
+      Some(dst) if globs(src) != globs(dst) => return Err(invalid()),
+      None if src.is_empty() => return Err(invalid()),
+      _ if globs(src) > 1 => return Err(invalid()),
+      _ => (),
+    }
+    Ok(RefSpec {
+      force,
+      negative: false,
+      src: src.to_string(),
+      dst: dst.map(str::to_string),
+    })
+  }
+
+  /// Whether the refspec matches refs by a pattern, rather than naming one.
+  pub fn is_glob(&self) -> bool {
+    self.src.contains('*')
+  }
+
+  /// Where the remote ref `name` is kept by this glob refspec, or `None` if
+  /// the refspec doesn't match it. A match without a destination is kept
+  /// nowhere, which is `Some(None)`.
+  pub fn map_glob(&self, name: &str) -> Option<Option<String>> {
+    let matched = glob_match(&self.src, name)?;
+    Some(self.dst.as_ref().map(|dst| dst.replacen('*', matched, 1)))
+  }
+
+  /// The remote ref that this glob refspec keeps as the local ref `name`,
+  /// if its destination matches it.
+  pub fn unmap_glob(&self, name: &str) -> Option<String> {
+    let matched = glob_match(self.dst.as_ref()?, name)?;
+    Some(self.src.replacen('*', matched, 1))
+  }
+
+  /// Whether the source of the refspec names or matches the ref `name`.
+  pub fn matches(&self, name: &str) -> bool {
+    match self.is_glob() {
+      true => glob_match(&self.src, name).is_some(),
+      false => self.src == name,
+    }
+  }
+}
+
+/// What the `*` in `pattern` stands for in `name`, if `name` matches it.
+fn glob_match<'a>(pattern: &str, name: &'a str) -> Option<&'a str> {
+  let (prefix, suffix) = pattern.split_once('*')?;
+  name
+    .strip_prefix(prefix)?
+    .strip_suffix(suffix)
+    .filter(|matched| !matched.is_empty())
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+/// The length of the blocks of the base that are looked up in the target.
+/// Matches shorter than this are not worth a copy instruction.
+const BLOCK: usize = 16;
+
+/// How many places in the base are remembered for each block. Highly
+/// repetitive data would otherwise make lookups slow.
+const CANDIDATES: usize = 8;
+
+/// The longest range a single copy instruction can take.
+const MAX_COPY: usize = 0xff_ffff;
+
+/// The most bytes a single insert instruction can take.
+const MAX_INSERT: usize = 0x7f;
+
+/// Computes a delta that rebuilds `target` from `base`, in the format used
+/// by packs (and read back by `apply_delta` in `object::pack`).
+///
+/// The base is indexed in blocks of 16 bytes. The target is then scanned for
+/// those blocks, with each match grown as far as it goes both ways and
+/// turned into a copy; whatever is left over is inserted as is. Returns
+/// `None` if the delta would be bigger than `max_size`, as it is then not
+/// worth storing.
+pub fn create(base: &[u8], target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+  let mut index: HashMap<&[u8], Vec<usize>> = HashMap::new();
+  for offset in (0..base.len().saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+    let offsets = index.entry(&base[offset..offset + BLOCK]).or_default();
+    if offsets.len() < CANDIDATES {
+      offsets.push(offset);
+    }
+  }
+
+  let mut delta = Vec::new();
+  push_size(&mut delta, base.len());
+  push_size(&mut delta, target.len());
+  let mut insert: Vec<u8> = Vec::new();
+  let mut i = 0;
+  while i < target.len() {
+    let best = match target.get(i..i + BLOCK).and_then(|block| index.get(block)) {
+      Some(offsets) => offsets
+        .iter()
+        .map(|&offset| (offset, common_length(&base[offset..], &target[i..])))
+        .max_by_key(|&(offset, len)| (len, std::cmp::Reverse(offset))),
+      None => None,
+    };
+    let (mut offset, mut len) = match best {
+      Some(best) => best,
+      None => {
+        insert.push(target[i]);
+        i += 1;
+        if delta.len() + insert.len() > max_size {
+          return None;
+        }
+        continue;
+      }
+    };
+    let matched = len;
+    // bytes just before the match that are also just before it in the base
+    // are better copied than inserted
+    while offset > 0 && insert.last() == Some(&base[offset - 1]) {
+      insert.pop();
+      offset -= 1;
+      len += 1;
+    }
+    flush_insert(&mut delta, &mut insert);
+    i += matched;
+    push_copy(&mut delta, offset, len);
+    if delta.len() > max_size {
+      return None;
+    }
+  }
+  flush_insert(&mut delta, &mut insert);
+  (delta.len() <= max_size).then_some(delta)
+}
+
+/// The number of bytes at the start of `a` and `b` that are the same.
+fn common_length(a: &[u8], b: &[u8]) -> usize {
+  a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Appends a size in 7 bit groups, least significant first.
+fn push_size(delta: &mut Vec<u8>, mut size: usize) {
+  while size >= 0x80 {
+    delta.push(0x80 | (size & 0x7f) as u8);
+    size >>= 7;
+  }
+  delta.push(size as u8);
+}
+
+/// Appends instructions to insert the pending bytes.
+fn flush_insert(delta: &mut Vec<u8>, insert: &mut Vec<u8>) {
+  for chunk in insert.chunks(MAX_INSERT) {
+    delta.push(chunk.len() as u8);
+    delta.extend_from_slice(chunk);
+  }
+  insert.clear();
+}
+
+/// Appends instructions to copy `len` bytes of the base from `offset`.
+///
+/// A copy instruction has a bit for each byte of the offset and size that
+/// follows it, so that zero bytes can be left out.
+fn push_copy(delta: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+  while len > 0 {
+    let size = len.min(MAX_COPY);
+    let at = delta.len();
+    delta.push(0x80);
+    for i in 0..4 {
+      let byte = (offset >> (i * 8)) as u8;
+      if byte != 0 {
+        delta[at] |= 1 << i;
+        delta.push(byte);
+      }
+    }
+    for i in 0..3 {
+      let byte = (size >> (i * 8)) as u8;
+      if byte != 0 {
+        delta[at] |= 0x10 << i;
+        delta.push(byte);
+      }
+    }
+    offset += size;
+    len -= size;
+  }
+}
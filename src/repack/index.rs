@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::process;
+
+use flate2::{bufread::ZlibDecoder, Crc};
+use sha1::{Digest, Sha1};
+
+use super::{write_index, KINDS, OFS_DELTA};
+use crate::cancel::Cancel;
+use crate::object::pack::apply_delta;
+use crate::progress::Progress;
+use crate::repo::Repo;
+
+const REF_DELTA: u8 = 7;
+
+/// What an object in a received pack is stored against.
+enum Base {
+  None,
+  Offset(u64),
+  Hash(String),
+}
+
+/// An object as it was read from a received pack, before any delta is
+/// applied.
+struct Raw {
+  offset: u64,
+  kind: u8,
+  base: Base,
+  data: Vec<u8>,
+  crc: u32,
+}
+
+/// Stores a pack received from another repository in `.git/objects/pack`,
+/// with an index written for it, and returns its checksum.
+///
+/// Each object in the pack is inflated, and each delta applied to its base
+/// to find the object's hash. Bases must be in the pack: thin packs, whose
+/// deltas are against objects the receiver already has, are not taken.
+///
+/// With `keep`, the pack is kept (see [`super::is_kept`]) from the moment it
+/// can be seen, with a `.keep` file that says why: a repack running at the
+/// same time, which might otherwise pack its objects elsewhere and delete
+/// it before the refs that need them are updated, then leaves it alone.
+///
+/// Once `cancel` is cancelled, whatever has been written of the pack is
+/// deleted.
+pub fn write(
+  repo: &Repo,
+  data: &[u8],
+  keep: Option<&str>,
+  progress: &mut dyn Progress,
+  cancel: &Cancel,
+) -> Result<String, String> {
+  if data.len() < 32 || !data.starts_with(b"PACK") || !matches!(data[7], 2 | 3) {
+    return Err("protocol error: bad pack header".to_string());
+  }
+  let (body, checksum) = data.split_at(data.len() - 20);
+  if Sha1::digest(body)[..] != *checksum {
+    return Err("pack is corrupted (SHA1 mismatch)".to_string());
+  }
+  let count = u32::from_be_bytes([data[8], data[9], data[10], data[11]]) as usize;
+
+  progress.start("Indexing objects", Some(count as u64));
+  let mut raws: Vec<Raw> = Vec::with_capacity(count);
+  let mut offset = 12;
+  for i in 0..count {
+    cancel.check()?;
+    let (raw, next) = read_entry(body, offset)?;
+    raws.push(raw);
+    offset = next;
+    progress.update(i as u64 + 1);
+  }
+  progress.finish();
+  if offset != body.len() {
+    return Err("pack has junk at the end".to_string());
+  }
+
+  // deltas against earlier offsets resolve in one pass, but a delta may be
+  // against an object by hash that comes after it
+  let at: HashMap<u64, usize> = raws
+    .iter()
+    .enumerate()
+    .map(|(i, raw)| (raw.offset, i))
+    .collect();
+  let mut resolved: Vec<Option<(u8, Vec<u8>, String)>> = (0..count).map(|_| None).collect();
+  let mut by_hash: HashMap<String, usize> = HashMap::new();
+  let deltas = raws
+    .iter()
+    .filter(|raw| !matches!(raw.base, Base::None))
+    .count();
+  let mut done = 0;
+  progress.start("Resolving deltas", Some(deltas as u64));
+  loop {
+    let before = by_hash.len();
+    for i in 0..count {
+      if resolved[i].is_some() {
+        continue;
+      }
+      cancel.check()?;
+      let raw = &raws[i];
+      let base = match &raw.base {
+        Base::None => None,
+        Base::Offset(base) => Some(*at.get(base).ok_or("delta base offset is out of bounds")?),
+        Base::Hash(hash) => match by_hash.get(hash) {
+          Some(&base) => Some(base),
+          None => continue,
+        },
+      };
+      let (kind, payload) = match base {
+        None => (raw.kind, raw.data.clone()),
+        Some(base) => match &resolved[base] {
+          Some((kind, base, _)) => (*kind, apply_delta(base, &raw.data)?),
+          None => continue,
+        },
+      };
+      let header = format!("{} {}\0", KINDS[kind as usize - 1], payload.len());
+      let hash = hex::encode(
+        Sha1::new()
+          .chain_update(header)
+          .chain_update(&payload)
+          .finalize(),
+      );
+      by_hash.insert(hash.clone(), i);
+      resolved[i] = Some((kind, payload, hash));
+      if base.is_some() {
+        done += 1;
+        progress.update(done);
+      }
+    }
+    if by_hash.len() == count {
+      break;
+    }
+    if by_hash.len() == before {
+      return Err(format!(
+        "pack has {} unresolved deltas",
+        count - by_hash.len()
+      ));
+    }
+  }
+  progress.finish();
+
+  let dir = repo.objects.dir().join("pack");
+  fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+  let name = hex::encode(checksum);
+  let path = dir.join(format!("pack-{}.pack", name));
+  let temp = dir.join(format!("tmp_pack_{}", process::id()));
+  fs::write(&temp, data)
+    .and_then(|_| fs::rename(&temp, &path))
+    .map_err(|e| {
+      let _ = fs::remove_file(&temp);
+      format!("unable to write {}: {}", path.display(), e)
+    })?;
+  // the pack is only seen once its index is there, so kept before then
+  let keep_path = path.with_extension("keep");
+  let objects = raws
+    .iter()
+    .zip(resolved)
+    .map(|(raw, resolved)| {
+      (
+        resolved.map(|r| r.2).unwrap_or_default(),
+        raw.crc,
+        raw.offset,
+      )
+    })
+    .collect();
+  let written = match keep {
+    Some(message) => fs::write(&keep_path, format!("{}\n", message))
+      .map_err(|e| format!("unable to write {}: {}", keep_path.display(), e)),
+    None => Ok(()),
+  };
+  // and without its index it is garbage
+  written
+    .and_then(|_| cancel.check())
+    .and_then(|_| write_index(repo, checksum, objects))
+    .inspect_err(|_| {
+      let _ = fs::remove_file(&keep_path);
+      let _ = fs::remove_file(&path);
+    })?;
+  Ok(name)
+}
+
+/// Reads the object at `offset` in a pack, returning it and where the next
+/// one starts.
+fn read_entry(pack: &[u8], offset: usize) -> Result<(Raw, usize), String> {
+  let truncated = || "pack is truncated".to_string();
+  let mut used = offset;
+  let mut next = || -> Result<u8, String> {
+    let byte = pack.get(used).ok_or_else(truncated)?;
+    used += 1;
+    Ok(*byte)
+  };
+
+  // the type and size, as `object::pack` reads them
+  let mut c = next()?;
+  let kind = (c >> 4) & 7;
+  let mut size = (c & 15) as usize;
+  let mut shift = 4;
+  while c & 0x80 != 0 {
+    c = next()?;
+    size += ((c & 0x7f) as usize) << shift;
+    shift += 7;
+  }
+  let base = match kind {
+    1..=4 => Base::None,
+    OFS_DELTA => {
+      let mut c = next()?;
+      let mut distance = (c & 0x7f) as u64;
+      while c & 0x80 != 0 {
+        c = next()?;
+        distance = ((distance + 1) << 7) + (c & 0x7f) as u64;
+      }
+      Base::Offset(
+        (offset as u64)
+          .checked_sub(distance)
+          .ok_or("delta base is before the start of the pack")?,
+      )
+    }
+    REF_DELTA => {
+      let id = pack.get(used..used + 20).ok_or_else(truncated)?;
+      used += 20;
+      Base::Hash(hex::encode(id))
+    }
+    _ => return Err(format!("unknown object type {}", kind)),
+  };
+
+  let mut decoder = ZlibDecoder::new(&pack[used..]);
+  let mut data = Vec::with_capacity(size);
+  (&mut decoder)
+    .take(size as u64 + 1)
+    .read_to_end(&mut data)
+    .map_err(|e| format!("inflate returned {}", e))?;
+  if data.len() != size {
+    return Err("inflated size does not match".to_string());
+  }
+  let end = used + decoder.total_in() as usize;
+  let mut crc = Crc::new();
+  crc.update(&pack[offset..end]);
+  let raw = Raw {
+    offset: offset as u64,
+    kind,
+    base,
+    data,
+    crc: crc.sum(),
+  };
+  Ok((raw, end))
+}
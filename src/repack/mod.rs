@@ -0,0 +1,530 @@
+pub mod delta;
+pub mod index;
+
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::process;
+
+use bstr::BString;
+use flate2::Crc;
+use sha1::{Digest, Sha1};
+
+use crate::cancel::Cancel;
+use crate::crypto;
+use crate::index::Index;
+use crate::object::{self, mode::Mode, refs, serializable::Unbox, tag::Tag};
+use crate::parallel;
+use crate::progress::Progress;
+use crate::repo::{parse_size, Repo};
+use crate::rev::walk::RevWalk;
+
+/// The object types as numbered in a pack.
+const KINDS: [&str; 4] = ["commit", "tree", "blob", "tag"];
+const OFS_DELTA: u8 = 6;
+
+/// The number of objects in each run of the delta search.
+///
+/// Runs are searched on separate threads and no object is made a delta
+/// against one in another run, so the pack comes out the same however many
+/// threads there are.
+const RUN: usize = 1000;
+
+/// How hard to look for deltas.
+pub struct Options {
+  /// How many of the objects before each one to try as its delta base.
+  pub window: usize,
+
+  /// The longest chain of deltas an object may be at the end of.
+  pub depth: usize,
+
+  /// The number of threads to search on.
+  pub threads: usize,
+
+  /// The most bytes of objects to keep in each thread's window, or 0 for no
+  /// limit.
+  pub window_memory: usize,
+}
+
+impl Options {
+  /// Reads the options from `pack.window` (10 by default), `pack.depth` (50),
+  /// `pack.threads` and `pack.windowMemory` (no limit).
+  pub fn new(repo: &Repo) -> Result<Self, String> {
+    let get = |key: &str| {
+      let section = repo.config.as_ref()?.section(Some("pack"))?;
+      section
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.to_owned())
+    };
+    let number = |key: &str, default: usize| match get(key) {
+      Some(value) => parse_size(&value).ok_or(format!(
+        "bad numeric config value '{}' for 'pack.{}'",
+        value,
+        key.to_ascii_lowercase()
+      )),
+      None => Ok(default),
+    };
+    Ok(Self {
+      window: number("window", 10)?,
+      depth: number("depth", 50)?,
+      threads: parallel::workers(repo, "pack", "threads"),
+      window_memory: number("windowMemory", 0)?,
+    })
+  }
+}
+
+/// An object going into a pack.
+struct Entry {
+  hash: String,
+  kind: u8,
+  size: usize,
+  name_hash: u32,
+}
+
+/// An object as it is written to a pack: either whole or as a delta against
+/// an earlier object, compressed.
+struct Packed {
+  kind: u8,
+  base: Option<usize>,
+  size: usize,
+  data: Vec<u8>,
+
+  /// Where the object was written in the pack, and the CRC-32 of what was
+  /// written, once it has been.
+  offset: u64,
+  crc: u32,
+}
+
+/// Lists the objects reachable from the refs, `HEAD` and the index, each
+/// with the path it was first found at (empty for commits and tags).
+pub fn reachable(repo: &Repo) -> Result<Vec<(String, BString)>, String> {
+  let mut tips: Vec<String> = refs::collect(repo, None).into_values().collect();
+  if let Ok(head) = refs::resolve(repo, "HEAD".as_ref()) {
+    tips.push(head);
+  }
+
+  let mut objects = reachable_from(repo, &tips, &[])?;
+  let mut seen: HashSet<String> = objects.iter().map(|(hash, _)| hash.clone()).collect();
+  for entry in Index::read(repo)?.entries() {
+    if entry.mode != Mode::Gitlink.bits() && seen.insert(entry.hash.clone()) {
+      objects.push((entry.hash.clone(), entry.path.clone()));
+    }
+  }
+  Ok(objects)
+}
+
+/// Lists the objects reachable from `tips` but not from the commits
+/// `hidden`, each with the path it was first found at, as
+/// [`reachable`] does.
+pub fn reachable_from(
+  repo: &Repo,
+  tips: &[String],
+  hidden: &[String],
+) -> Result<Vec<(String, BString)>, String> {
+  let mut seen: HashSet<String> = HashSet::new();
+  let mut objects = Vec::new();
+  let mut walk = RevWalk::new(repo);
+  hidden.iter().for_each(|hash| walk.hide(hash));
+  let mut trees = Vec::new();
+  for tip in tips.iter().cloned() {
+    // annotated tags are packed along with what they point at
+    let mut hash = tip;
+    loop {
+      let object = object::read(repo, &hash, None)?;
+      match object.format().as_str() {
+        "tag" => {
+          if seen.insert(hash.clone()) {
+            objects.push((hash.clone(), BString::default()));
+          }
+          hash = match object.unbox::<Tag>()?.get("object") {
+            Some(target) => target.to_owned(),
+            None => return Err(format!("malformed tag {}", hash)),
+          };
+        }
+        "commit" => break walk.push(&hash),
+        "tree" => break trees.push(hash),
+        _ => {
+          if seen.insert(hash.clone()) {
+            objects.push((hash, BString::default()));
+          }
+          break;
+        }
+      }
+    }
+  }
+
+  let mut commits = walk.run()?;
+  for commit in &commits {
+    if seen.insert(commit.clone()) {
+      objects.push((commit.clone(), BString::default()));
+    }
+  }
+  // the root trees of the commits are found from the commits themselves
+  commits.extend(trees);
+  for (hash, path) in walk.objects(&commits)? {
+    if seen.insert(hash.clone()) {
+      objects.push((hash, path));
+    }
+  }
+  Ok(objects)
+}
+
+/// Writes the objects to a new pack in `.git/objects/pack` along with its
+/// index, and returns the checksum of the pack, which names it.
+///
+/// Objects are sorted so that ones likely to be alike end up next to each
+/// other: by type, then by a hash of the end of their path (so that versions
+/// of the same file, or files of the same type, are together), then biggest
+/// first. Each object is then tried as a delta against the `window` objects
+/// before it, and stored as the smallest delta found if that is less than
+/// about half its size.
+///
+/// Once `cancel` is cancelled, the pack written so far is deleted and
+/// nothing is left behind.
+pub fn write(
+  repo: &Repo,
+  objects: &[(String, BString)],
+  options: &Options,
+  progress: &mut dyn Progress,
+  cancel: &Cancel,
+) -> Result<String, String> {
+  let (entries, mut packed) = compress(repo, objects, options, progress, cancel)?;
+
+  let dir = repo.objects.dir().join("pack");
+  fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+  let temp = dir.join(format!("tmp_pack_{}", process::id()));
+  progress.start("Writing objects", Some(packed.len() as u64));
+  let checksum = File::create(&temp)
+    .and_then(|file| write_pack(&mut BufWriter::new(file), &mut packed, progress, cancel))
+    .map_err(|e| {
+      let _ = fs::remove_file(&temp);
+      match e.kind() {
+        io::ErrorKind::Interrupted => e.to_string(),
+        _ => format!("{}: {}", temp.display(), e),
+      }
+    })?;
+  progress.finish();
+  let name = hex::encode(&checksum);
+  let pack = dir.join(format!("pack-{}.pack", name));
+  fs::rename(&temp, &pack).map_err(|e| format!("{}: {}", pack.display(), e))?;
+
+  // a pack without its index is garbage
+  let objects = entries
+    .iter()
+    .zip(&packed)
+    .map(|(entry, packed)| (entry.hash.clone(), packed.crc, packed.offset))
+    .collect();
+  cancel
+    .check()
+    .and_then(|_| write_index(repo, &checksum, objects))
+    .inspect_err(|_| {
+      let _ = fs::remove_file(&pack);
+    })?;
+  Ok(name)
+}
+
+/// Writes the objects as a pack to `out`, the way [`write`] packs them, for
+/// sending to another repository.
+pub fn send(
+  repo: &Repo,
+  objects: &[(String, BString)],
+  options: &Options,
+  out: &mut dyn Write,
+  progress: &mut dyn Progress,
+  cancel: &Cancel,
+) -> Result<(), String> {
+  let (_, mut packed) = compress(repo, objects, options, progress, cancel)?;
+  progress.start("Writing objects", Some(packed.len() as u64));
+  write_pack(out, &mut packed, progress, cancel).map_err(|e| match e.kind() {
+    io::ErrorKind::Interrupted => e.to_string(),
+    _ => format!("unable to write pack ({})", e),
+  })?;
+  progress.finish();
+  Ok(())
+}
+
+/// Writes the index of the pack named by `checksum`, from the hash, CRC-32
+/// and offset of each object in it. The index goes in last, as it is what
+/// makes the pack visible.
+pub fn write_index(
+  repo: &Repo,
+  checksum: &[u8],
+  mut objects: Vec<(String, u32, u64)>,
+) -> Result<(), String> {
+  objects.sort();
+  let mut index = b"\xfftOc\0\0\0\x02".to_vec();
+  let ids = objects
+    .iter()
+    .map(|(hash, _, _)| hex::decode(hash))
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| e.to_string())?;
+  for first in 0..=255u8 {
+    let count = ids.partition_point(|id| id[0] <= first) as u32;
+    index.extend(count.to_be_bytes());
+  }
+  ids.iter().for_each(|id| index.extend(id));
+  objects
+    .iter()
+    .for_each(|(_, crc, _)| index.extend(crc.to_be_bytes()));
+  let mut large = Vec::new();
+  for &(_, _, offset) in &objects {
+    let offset = match offset {
+      offset if offset < 0x8000_0000 => offset as u32,
+      offset => {
+        large.extend(offset.to_be_bytes());
+        0x8000_0000 | (large.len() / 8 - 1) as u32
+      }
+    };
+    index.extend(offset.to_be_bytes());
+  }
+  index.extend(large);
+  index.extend(checksum);
+  let index_checksum = Sha1::digest(&index);
+  index.extend(index_checksum);
+
+  let dir = repo.objects.dir().join("pack");
+  let temp = dir.join(format!("tmp_idx_{}", process::id()));
+  let path = dir.join(format!("pack-{}.idx", hex::encode(checksum)));
+  fs::write(&temp, index)
+    .and_then(|_| fs::rename(&temp, &path))
+    .map_err(|e| {
+      let _ = fs::remove_file(&temp);
+      format!("{}: {}", path.display(), e)
+    })
+}
+
+/// Whether the pack whose `.pack` file is at `pack` is to be kept, as it
+/// has a `.keep` file next to it. A repack never deletes a kept pack, and
+/// leaves its objects out of the packs it writes unless told otherwise.
+pub fn is_kept(pack: &Path) -> bool {
+  pack.with_extension("keep").exists()
+}
+
+/// Sorts the objects to pack and finds deltas between them, returning them
+/// in the order they go in the pack along with how each is packed.
+fn compress(
+  repo: &Repo,
+  objects: &[(String, BString)],
+  options: &Options,
+  progress: &mut dyn Progress,
+  cancel: &Cancel,
+) -> Result<(Vec<Entry>, Vec<Packed>), String> {
+  let entries = parallel::map(
+    objects,
+    options.threads,
+    |(hash, path)| entry(repo, hash, path),
+    |_, _| (),
+  );
+  let mut entries = entries.into_iter().collect::<Result<Vec<_>, _>>()?;
+  entries.sort_by(|a, b| {
+    (a.kind, a.name_hash, b.size, &a.hash).cmp(&(b.kind, b.name_hash, a.size, &b.hash))
+  });
+
+  // runs end between objects that are not alike, where possible
+  let mut runs: Vec<Range<usize>> = Vec::new();
+  let mut start = 0;
+  while start < entries.len() {
+    let mut end = (start + RUN).min(entries.len());
+    while end < entries.len()
+      && (entries[end].kind, entries[end].name_hash)
+        == (entries[end - 1].kind, entries[end - 1].name_hash)
+    {
+      end += 1;
+    }
+    runs.push(start..end);
+    start = end;
+  }
+
+  progress.start("Compressing objects", Some(entries.len() as u64));
+  let mut compressed = 0;
+  let packed = parallel::map(
+    &runs,
+    options.threads,
+    |run| compress_run(repo, &entries, run.clone(), options, cancel),
+    |_, run| {
+      compressed += run.as_ref().map_or(0, |run| run.len());
+      progress.update(compressed as u64);
+    },
+  );
+  progress.finish();
+  let packed: Vec<Packed> = packed
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?
+    .into_iter()
+    .flatten()
+    .collect();
+  Ok((entries, packed))
+}
+
+/// Reads the type and size of an object to pack.
+fn entry(repo: &Repo, hash: &str, path: &[u8]) -> Result<Entry, String> {
+  let raw = repo.objects.read(hash)?;
+  let space = raw.iter().position(|&b| b == b' ').unwrap_or(0);
+  let null = raw.iter().position(|&b| b == 0).unwrap_or(0);
+  let kind = std::str::from_utf8(&raw[..space]).unwrap_or_default();
+  let kind = match KINDS.iter().position(|k| *k == kind) {
+    Some(kind) => kind as u8 + 1,
+    None => return Err(format!("unsupported type \"{}\"", kind)),
+  };
+  Ok(Entry {
+    hash: hash.to_owned(),
+    kind,
+    size: raw.len() - null - 1,
+    name_hash: name_hash(path),
+  })
+}
+
+/// Hashes a path so that paths ending the same way are close together, the
+/// way git does: later characters count for more.
+fn name_hash(path: &[u8]) -> u32 {
+  path
+    .iter()
+    .filter(|c| !c.is_ascii_whitespace())
+    .fold(0u32, |hash, &c| (hash >> 2).wrapping_add((c as u32) << 24))
+}
+
+/// Finds deltas for a run of objects and compresses them.
+fn compress_run(
+  repo: &Repo,
+  entries: &[Entry],
+  run: Range<usize>,
+  options: &Options,
+  cancel: &Cancel,
+) -> Result<Vec<Packed>, String> {
+  let mut window: VecDeque<usize> = VecDeque::new();
+  let mut payloads: VecDeque<Vec<u8>> = VecDeque::new();
+  let mut memory = 0;
+  let mut depths = vec![0; run.len()];
+  let mut packed = Vec::with_capacity(run.len());
+  for i in run.clone() {
+    cancel.check()?;
+    let entry = &entries[i];
+    let raw = repo.objects.read(&entry.hash)?;
+    let payload = raw[raw.len() - entry.size..].to_vec();
+
+    let mut best: Option<(usize, Vec<u8>)> = None;
+    let max_size = (entry.size / 2).saturating_sub(20);
+    for (&base, base_payload) in window.iter().zip(&payloads).rev() {
+      let candidate = &entries[base];
+      if candidate.kind != entry.kind
+        || depths[base - run.start] >= options.depth
+        || candidate.size < entry.size / 32
+      {
+        continue;
+      }
+      let limit = best.as_ref().map_or(max_size, |(_, delta)| delta.len() - 1);
+      if let Some(delta) = delta::create(base_payload, &payload, limit) {
+        best = Some((base, delta));
+      }
+    }
+
+    let (base, data) = match best {
+      Some((base, delta)) => {
+        depths[i - run.start] = depths[base - run.start] + 1;
+        (Some(base), delta)
+      }
+      None => (None, payload.clone()),
+    };
+    packed.push(Packed {
+      kind: entry.kind,
+      base,
+      offset: 0,
+      crc: 0,
+      size: data.len(),
+      data: crypto::compress(&data)?,
+    });
+
+    if options.window > 0 {
+      memory += payload.len();
+      window.push_back(i);
+      payloads.push_back(payload);
+    }
+    while window.len() > options.window
+      || (options.window_memory > 0 && memory > options.window_memory && window.len() > 1)
+    {
+      window.pop_front();
+      memory -= payloads.pop_front().map_or(0, |payload| payload.len());
+    }
+  }
+  Ok(packed)
+}
+
+/// Writes the objects out as a pack, recording where each one went and
+/// returning the pack's checksum. Fails as interrupted once `cancel` is
+/// cancelled.
+fn write_pack(
+  out: &mut dyn Write,
+  packed: &mut [Packed],
+  progress: &mut dyn Progress,
+  cancel: &Cancel,
+) -> io::Result<Vec<u8>> {
+  let mut hasher = Sha1::new();
+  let mut header = b"PACK\0\0\0\x02".to_vec();
+  header.extend((packed.len() as u32).to_be_bytes());
+  hasher.update(&header);
+  out.write_all(&header)?;
+
+  let mut offset = header.len() as u64;
+  for i in 0..packed.len() {
+    if let Err(e) = cancel.check() {
+      return Err(io::Error::new(io::ErrorKind::Interrupted, e));
+    }
+    let object = &packed[i];
+    let kind = match object.base {
+      Some(_) => OFS_DELTA,
+      None => object.kind,
+    };
+    let mut head = entry_header(kind, object.size);
+    if let Some(base) = object.base {
+      push_distance(&mut head, offset - packed[base].offset);
+    }
+    let mut crc = Crc::new();
+    crc.update(&head);
+    crc.update(&object.data);
+    hasher.update(&head);
+    hasher.update(&object.data);
+    out.write_all(&head)?;
+    out.write_all(&object.data)?;
+    let written = (head.len() + object.data.len()) as u64;
+    packed[i].offset = offset;
+    packed[i].crc = crc.sum();
+    offset += written;
+    progress.update(i as u64 + 1);
+  }
+  let checksum = hasher.finalize().to_vec();
+  out.write_all(&checksum)?;
+  out.flush()?;
+  Ok(checksum)
+}
+
+/// The header of an object in a pack: its type and size, with the size in 7
+/// bit groups, least significant first after the first 4 bits.
+fn entry_header(kind: u8, size: usize) -> Vec<u8> {
+  let mut header = Vec::new();
+  let mut c = kind << 4 | (size & 15) as u8;
+  let mut size = size >> 4;
+  while size > 0 {
+    header.push(c | 0x80);
+    c = (size & 0x7f) as u8;
+    size >>= 7;
+  }
+  header.push(c);
+  header
+}
+
+/// Appends how far back the base of a delta is, big endian, with one taken
+/// off each group but the last (see `object::pack` for how it is read).
+fn push_distance(header: &mut Vec<u8>, mut distance: u64) {
+  let mut bytes = vec![(distance & 0x7f) as u8];
+  distance >>= 7;
+  while distance > 0 {
+    distance -= 1;
+    bytes.push(0x80 | (distance & 0x7f) as u8);
+    distance >>= 7;
+  }
+  bytes.reverse();
+  header.extend(bytes);
+}
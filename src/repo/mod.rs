@@ -1,12 +1,21 @@
 extern crate ini;
 
+use bstr::BString;
 use ini::Ini as ConfigParser;
 use std::{
-  fs::{create_dir_all, File},
+  env,
+  ffi::OsStr,
+  fs::{self, create_dir_all, File},
   io::Write,
-  path::{PathBuf, Path},
+  os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+  path::{Component, Path, PathBuf},
+  process,
+  sync::Arc,
 };
 
+use crate::object::database::{Database, DEFAULT_CACHE_LIMIT};
+use crate::object::refs;
+
 /// A git repository.
 ///
 /// In git, a repository is made up of a `working tree` and a `git directory`.
@@ -21,10 +30,27 @@ pub struct Repo {
   /// The path to the working tree.
   pub work_tree: PathBuf,
 
+  /// Whether the repository has no working tree.
+  pub bare: bool,
+
   /// Parses config (.ini) file in `.git/config`
   pub config: Option<ConfigParser>,
+
+  /// The object database, shared by every clone of the handle.
+  pub objects: Arc<Database>,
+
+  /// Where the refs are kept, shared like the objects. This is the refs
+  /// under the git directory unless it is replaced with another
+  /// [`refs::RefDb`].
+  pub refs: Arc<dyn refs::RefDb>,
 }
 
+// A repository handle is meant to be shared between threads.
+const _: fn() = || {
+  fn assert_send_sync<T: Send + Sync>() {}
+  assert_send_sync::<Repo>();
+};
+
 impl Repo {
   /// Initializes a new git repository.
   ///
@@ -32,7 +58,7 @@ impl Repo {
   ///
   /// * `path` - The path to the working tree.
   /// * `force` - If true, the repository will be created even from an invalid
-  /// filesystem location.
+  ///   filesystem location.
   pub fn init(path: &Path, force: bool) -> Result<Repo, String> {
     // If we are not forcing creation, the path must exist.
     if !force && !path.exists() {
@@ -42,6 +68,10 @@ impl Repo {
     // Try to read in the config file inside the `.git` directory.
     let mut config: Option<ConfigParser> = None;
     let git_dir = path.join(".git");
+    let git_dir = match git_dir.is_file() {
+      true => read_gitfile(&git_dir)?,
+      false => git_dir,
+    };
     match repo_file(&git_dir, &["config"], false) {
       Some(config_file) => {
         if config_file.exists() {
@@ -61,12 +91,12 @@ impl Repo {
     }
 
     // If we are not forcing creation, the `repositoryformatversion`
-    // must be equal to 0.
+    // must be 0, or 1 for a repository that uses extensions.
     if !force {
       if let Some(ref parser) = config {
         if let Some(core) = parser.section(Some("core")) {
           if let Some(version) = core.get("repositoryformatversion") {
-            if version != "0" {
+            if version != "0" && version != "1" {
               return Err(format!(
                 "Unsupported repository format version: {}",
                 version
@@ -79,8 +109,14 @@ impl Repo {
       }
     }
     Ok(Self {
+      objects: Arc::new(Database::new(
+        &crate::env::object_dir(&git_dir),
+        DEFAULT_CACHE_LIMIT,
+      )),
+      refs: refs::open(&git_dir, config.as_ref())?,
       git_dir,
       work_tree: path.to_path_buf(),
+      bare: false,
       config,
     })
   }
@@ -93,74 +129,344 @@ impl Repo {
   ///
   /// * `path` - The path to the repository.
   pub fn new(path: &Path) -> Result<Repo, String> {
-    let repo = Repo::init(path, true)?;
+    Repo::create(path, &InitOptions::default())
+  }
+
+  /// Creates a new repository at the given path, set up as asked.
+  ///
+  /// The git directory starts out as a copy of the template directory, if
+  /// there is one: `--template`, `GIT_TEMPLATE_DIR` or `init.templateDir`.
+  /// What the template lacks is then filled in, and the core settings are
+  /// written over its config. `HEAD` points at the initial branch, which is
+  /// `init.defaultBranch` unless given and otherwise `master`.
+  ///
+  /// A separate git directory is linked to from a `.git` file in the working
+  /// tree, which reads `gitdir: <path>`.
+  pub fn create(path: &Path, options: &InitOptions) -> Result<Repo, String> {
+    if options.bare && options.separate_git_dir.is_some() {
+      return Err("--separate-git-dir incompatible with bare repository".to_string());
+    }
+    let git_dir = match (&options.separate_git_dir, options.bare) {
+      (Some(dir), _) => dir.clone(),
+      (None, true) => path.to_path_buf(),
+      (None, false) => path.join(".git"),
+    };
 
     // First, make sure the path either doesn't exist or is an empty directory.
-    if repo.work_tree.exists() {
-      let dir = repo.work_tree.display();
-      if !repo.work_tree.is_dir() {
-        return Err(format!("{} is not a directory", dir));
-      }
-      if repo.work_tree.read_dir().unwrap().count() != 0 {
-        return Err(format!("{} is not empty", dir));
+    for dir in [path, &git_dir] {
+      if dir.exists() {
+        if !dir.is_dir() {
+          return Err(format!("{} is not a directory", dir.display()));
+        }
+        if dir.read_dir().map_err(|e| e.to_string())?.count() != 0 {
+          return Err(format!("{} is not empty", dir.display()));
+        }
       }
-    } else if create_dir_all(&repo.work_tree).is_err() {
-      panic!("failed to create {}", repo.work_tree.display());
+    }
+    let branch = options
+      .initial_branch
+      .clone()
+      .or_else(|| config_parameter("init.defaultBranch"))
+      .unwrap_or_else(|| "master".to_string());
+    if !refs::check_name(&format!("refs/heads/{}", branch)) {
+      return Err(format!("invalid initial branch name: '{}'", branch));
+    }
+    let ref_format = options
+      .ref_format
+      .clone()
+      .or_else(|| env::var("GIT_DEFAULT_REF_FORMAT").ok())
+      .unwrap_or_else(|| "files".to_string());
+    let reftable = match ref_format.as_str() {
+      "files" => false,
+      "reftable" => true,
+      format => return Err(format!("unknown ref storage format '{}'", format)),
+    };
+    create_dir_all(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    create_dir_all(&git_dir).map_err(|e| format!("{}: {}", git_dir.display(), e))?;
+    let git_dir = git_dir.canonicalize().map_err(|e| e.to_string())?;
+
+    let template = options
+      .template
+      .clone()
+      .or_else(|| crate::env::var("GIT_TEMPLATE_DIR").map(PathBuf::from))
+      .or_else(|| config_parameter("init.templateDir").map(PathBuf::from));
+    if let Some(template) = template {
+      copy_template(&template, &git_dir)?;
     }
 
     // Verify that the repository has been successfully created.
-    repo_dir(&repo.git_dir, &["branches"], true);
-    repo_dir(&repo.git_dir, &["objects"], true);
-    repo_dir(&repo.git_dir, &["refs", "tags"], true);
-    repo_dir(&repo.git_dir, &["refs", "heads"], true);
+    repo_dir(&git_dir, &["branches"], true);
+    repo_dir(&git_dir, &["objects"], true);
+    let path_to = |name: &str| repo_file(&git_dir, &[name], true).unwrap();
+    if reftable {
+      // `HEAD` and `refs/` are only there so the directory is taken for a git
+      // directory, with `refs/heads` a file so that versions of git that
+      // know nothing of reftables can't mistake it for one of theirs
+      refs::Reftable::init(&git_dir)?;
+      repo_dir(&git_dir, &["refs"], true);
+      let heads = repo_file(&git_dir, &["refs", "heads"], true).unwrap();
+      Repo::write_to_file("this repository uses the reftable format\n", &heads);
+      Repo::write_to_file("ref: refs/heads/.invalid\n", &path_to("HEAD"));
+    } else {
+      repo_dir(&git_dir, &["refs", "tags"], true);
+      repo_dir(&git_dir, &["refs", "heads"], true);
+    }
 
     // Write the default `.git/description` file.
-    let data = "Unnamed repository; edit this file 'description' to name the repository.\n";
-    let path = repo_file(&repo.git_dir, &["description"], true);
-    Repo::write_to_file(data, &path.unwrap());
+    if !path_to("description").exists() {
+      let data = "Unnamed repository; edit this file 'description' to name the repository.\n";
+      Repo::write_to_file(data, &path_to("description"));
+    }
 
-    // Write the default `.git/description` file.
-    let data = "ref: refs/heads/master\n";
-    let path = repo_file(&repo.git_dir, &["HEAD"], true);
-    Repo::write_to_file(data, &path.unwrap());
+    // Write the default `.git/config` file, over any the template had.
+    let mut config = ConfigParser::load_from_file(path_to("config")).unwrap_or_default();
+    Repo::repo_default_config(&mut config, &git_dir, options.bare);
+    if reftable {
+      // extensions are only honoured from version 1 on
+      config
+        .with_section(Some("core"))
+        .set("repositoryformatversion", "1");
+      config
+        .with_section(Some("extensions"))
+        .set("refStorage", "reftable");
+    }
+    config
+      .write_to_file(path_to("config"))
+      .map_err(|e| format!("could not write config ({})", e))?;
 
-    // Write the default `.git/config` file.
-    let config = Repo::repo_default_config();
-    let path = repo_file(&repo.git_dir, &["config"], true);
-    config.write_to_file(path.unwrap()).unwrap();
+    let work_tree = match options.bare {
+      true => git_dir.clone(),
+      false => path.canonicalize().map_err(|e| e.to_string())?,
+    };
+    if options.separate_git_dir.is_some() {
+      let data = format!("gitdir: {}\n", git_dir.display());
+      fs::write(work_tree.join(".git"), data)
+        .map_err(|e| format!("could not write .git ({})", e))?;
+    }
+    let repo = Repo::open(&git_dir, &work_tree, options.bare)?;
 
+    // Point `HEAD` at the initial branch.
+    refs::update_symbolic(&repo, "HEAD", &format!("refs/heads/{}", branch))?;
     Ok(repo)
   }
 
-  /// Create a new repo object from an existing repository.
-  pub fn from_existing(path: &Path) -> Result<Repo, String> {
-    let repo = Repo::init(path, false)?;
-    Ok(repo)
+  /// Opens the repository whose git directory is `git_dir`.
+  ///
+  /// The config file must exist and have a supported
+  /// `repositoryformatversion`.
+  pub fn open(git_dir: &Path, work_tree: &Path, bare: bool) -> Result<Repo, String> {
+    let mut config = ConfigParser::load_from_file(git_dir.join("config"))
+      .map_err(|_| "Configuration file is missing.".to_string())?;
+    // the settings of this working tree alone override the shared ones
+    if worktree_config(&config) {
+      if let Ok(overrides) = ConfigParser::load_from_file(git_dir.join("config.worktree")) {
+        for (section, properties) in overrides.iter() {
+          for (key, value) in properties.iter() {
+            set_value(&mut config, section, key, value);
+          }
+        }
+      }
+    }
+    for (key, value) in crate::env::config_parameters()? {
+      let (section, name) = config_key(&key)?;
+      config.with_section(Some(section)).set(name, value);
+    }
+    let version = config
+      .section(Some("core"))
+      .and_then(|core| core.get("repositoryformatversion"));
+    if let Some(version) = version.filter(|v| *v != "0" && *v != "1") {
+      return Err(format!(
+        "Unsupported repository format version: {}",
+        version
+      ));
+    }
+    let cache_limit = match config.section(Some("core")).and_then(|core| {
+      core
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("objectCacheLimit"))
+    }) {
+      Some((_, value)) => parse_size(value).ok_or(format!(
+        "bad numeric config value '{}' for 'core.objectcachelimit'",
+        value
+      ))?,
+      None => DEFAULT_CACHE_LIMIT,
+    };
+    Ok(Self {
+      objects: Arc::new(Database::new(&crate::env::object_dir(git_dir), cache_limit)),
+      refs: refs::open(git_dir, Some(&config))?,
+      git_dir: git_dir.to_path_buf(),
+      work_tree: work_tree.to_path_buf(),
+      bare,
+      config: Some(config),
+    })
   }
 
-  /// Walk up the directory tree to find the root of the repository (`.git`).
-  pub fn find_repo(path: &Path, required: bool) -> Result<Option<Repo>, String> {
-    // Shadow the path parameter with its absolute path.
-    let path = path.canonicalize().unwrap();
+  /// Sets a value in the config file of the repository, replacing any value
+  /// the key (whose name is matched ignoring case) had before.
+  pub fn set_config(&mut self, section: &str, key: &str, value: &str) -> Result<(), String> {
+    self.write_config("config", section, key, value)
+  }
 
-    // If the path has a `.git` directory, we are done.
-    if path.join(".git").is_dir() {
-      match Repo::from_existing(&path) {
-        Ok(repo) => return Ok(Some(repo)),
-        Err(error) => return Err(error),
-      }
+  /// Sets a value for this working tree alone, in `config.worktree` when
+  /// `extensions.worktreeConfig` is on, and in the config file otherwise.
+  pub fn set_worktree_config(
+    &mut self,
+    section: &str,
+    key: &str,
+    value: &str,
+  ) -> Result<(), String> {
+    match self.config.as_ref().is_some_and(worktree_config) {
+      true => self.write_config("config.worktree", section, key, value),
+      false => self.write_config("config", section, key, value),
+    }
+  }
+
+  fn write_config(
+    &mut self,
+    file: &str,
+    section: &str,
+    key: &str,
+    value: &str,
+  ) -> Result<(), String> {
+    let path = self.git_dir.join(file);
+    let mut config = match ConfigParser::load_from_file(&path) {
+      Ok(config) => config,
+      // a working tree has no config of its own until something is set
+      Err(_) if file == "config.worktree" && !path.exists() => ConfigParser::new(),
+      Err(e) => return Err(format!("could not read {}: {}", path.display(), e)),
+    };
+    for config in [Some(&mut config), self.config.as_mut()]
+      .into_iter()
+      .flatten()
+    {
+      set_value(config, Some(section), key, value);
     }
+    config
+      .write_to_file(&path)
+      .map_err(|e| format!("could not write {}: {}", path.display(), e))
+  }
 
-    // Otherwise, we need to walk up the directory tree.
-    match path.parent() {
-      Some(parent) => Repo::find_repo(parent, required),
+  /// Finds the repository that `path` is in, the way git does.
+  ///
+  /// If `GIT_DIR` is set, it names the git directory. Otherwise the directory
+  /// tree is walked up from `path` until a directory is found that has a
+  /// `.git` directory, has a `.git` file pointing at one (`gitdir: <path>`),
+  /// or is itself a git directory (a bare repository). The walk does not go
+  /// into the directories listed in `GIT_CEILING_DIRECTORIES`.
+  ///
+  /// The working tree is `GIT_WORK_TREE` or `core.worktree` if either is
+  /// set, and otherwise the directory the git directory was found in (or the
+  /// current directory, with `GIT_DIR`). Bare repositories have none, unless
+  /// one of those is set.
+  pub fn discover(path: &Path) -> Result<Repo, String> {
+    let absolute = |path: &Path| {
+      path
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", path.display(), e))
+    };
+    let (git_dir, work_tree) = match crate::env::git_dir() {
+      Some(git_dir) => {
+        let git_dir = absolute(&git_dir)
+          .map_err(|_| format!("not a git repository: '{}'", git_dir.display()))?;
+        let git_dir = match git_dir.is_file() {
+          true => read_gitfile(&git_dir)?,
+          false => git_dir,
+        };
+        (git_dir, Some(absolute(Path::new("."))?))
+      }
       None => {
-        if required {
-          Err("Could not find a git directory".to_string())
-        } else {
-          Ok(None)
+        let start = absolute(path)?;
+        let ceilings = crate::env::ceiling_directories();
+        let mut dir = start.as_path();
+        loop {
+          if let Some(found) = Repo::git_dir_at(dir)? {
+            break found;
+          }
+          match dir.parent() {
+            Some(parent) if !ceilings.iter().any(|c| c == parent) => dir = parent,
+            _ => {
+              return Err(
+                "not a git repository (or any of the parent directories): .git".to_string(),
+              )
+            }
+          }
+        }
+      }
+    };
+
+    let mut repo = Repo::open(&git_dir, work_tree.as_deref().unwrap_or(&git_dir), false)?;
+    let core = |key: &str| {
+      let core = repo.config.as_ref()?.section(Some("core"))?;
+      core.get(key).map(str::to_owned)
+    };
+    let configured =
+      crate::env::work_tree().or_else(|| core("worktree").map(|dir| git_dir.join(dir)));
+    match configured {
+      Some(dir) => repo.work_tree = absolute(&dir)?,
+      None if work_tree.is_none() || core("bare").as_deref() == Some("true") => repo.bare = true,
+      None => (),
+    }
+    Ok(repo)
+  }
+
+  /// Looks for a git directory in `dir`, returning it along with the working
+  /// tree it belongs to (`None` when `dir` is itself a git directory).
+  fn git_dir_at(dir: &Path) -> Result<Option<(PathBuf, Option<PathBuf>)>, String> {
+    let dot_git = dir.join(".git");
+    if dot_git.is_file() {
+      return Ok(Some((read_gitfile(&dot_git)?, Some(dir.to_path_buf()))));
+    }
+    if is_git_dir(&dot_git) {
+      return Ok(Some((dot_git, Some(dir.to_path_buf()))));
+    }
+    if is_git_dir(dir) {
+      return Ok(Some((dir.to_path_buf(), None)));
+    }
+    Ok(None)
+  }
+
+  /// Fails unless the repository has a working tree.
+  pub fn require_work_tree(&self) -> Result<(), String> {
+    match self.bare {
+      true => Err("this operation must be run in a work tree".to_string()),
+      false => Ok(()),
+    }
+  }
+
+  /// The location in the working tree of a path relative to its root. The
+  /// path is taken as raw bytes, as git stores it.
+  pub fn work_tree_path(&self, path: impl AsRef<[u8]>) -> PathBuf {
+    self.work_tree.join(OsStr::from_bytes(path.as_ref()))
+  }
+
+  /// Converts a path relative to the current directory into a path relative
+  /// to the root of the working tree, with `/` as the separator.
+  ///
+  /// Fails if the path lies outside of the working tree.
+  pub fn worktree_path(&self, path: &str) -> Result<BString, String> {
+    let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+    let cwd = cwd.canonicalize().map_err(|e| e.to_string())?;
+    let mut absolute = PathBuf::new();
+    for component in cwd.join(path).components() {
+      match component {
+        Component::ParentDir => {
+          absolute.pop();
         }
+        Component::CurDir => (),
+        other => absolute.push(other),
+      }
+    }
+    match absolute.strip_prefix(&self.work_tree) {
+      Ok(relative) => {
+        let components: Vec<&[u8]> = relative.iter().map(|c| c.as_bytes()).collect();
+        Ok(components.join(&b'/').into())
       }
+      Err(_) => Err(format!(
+        "{}: '{}' is outside repository at '{}'",
+        path,
+        path,
+        self.work_tree.display()
+      )),
     }
   }
 
@@ -175,33 +481,228 @@ impl Repo {
     }
   }
 
-  /// Builds up a default configuration for a new repository.
-  fn repo_default_config() -> ConfigParser {
-    let mut conf = ConfigParser::new();
+  /// Writes the default configuration of a new repository into its config.
+  fn repo_default_config(conf: &mut ConfigParser, git_dir: &Path, bare: bool) {
     conf
       .with_section(Some("core"))
       .set("repositoryformatversion", "0") // use the initial gitdir format
-      .set("filemode", "false") // don't track file mode changes in worktree
-      .set("bare", "false"); // indicates this repo has a worktree
-    conf
+      .set("filemode", probe_filemode(git_dir).to_string()) // track the executable bit if it sticks
+      .set("bare", bare.to_string()); // whether this repo has a worktree
+    if !bare {
+      conf
+        .with_section(Some("core"))
+        .set("logallrefupdates", "true"); // keep reflogs of branches
+    }
   }
 }
 
 impl Default for Repo {
-  /// Walks up the directory tree starting in `.` to find the root of the
-  /// repository (`.git`), errors if repository not found.
+  /// Finds the repository the current directory is in (see
+  /// [`Repo::discover`]), exiting like git does if there is none.
   fn default() -> Repo {
-    if let Ok(Some(repo)) = Repo::find_repo(&PathBuf::from("."), true) {
-      repo
+    match Repo::discover(Path::new(".")) {
+      Ok(repo) => repo,
+      Err(err) => {
+        println!("fatal: {}", err);
+        process::exit(128)
+      }
+    }
+  }
+}
+
+/// How a new repository is set up by [`Repo::create`].
+#[derive(Default)]
+pub struct InitOptions {
+  /// Make the repository without a working tree, the given path being its
+  /// git directory.
+  pub bare: bool,
+
+  /// The branch `HEAD` starts out on.
+  pub initial_branch: Option<String>,
+
+  /// The directory whose files the git directory starts out with.
+  pub template: Option<PathBuf>,
+
+  /// Put the git directory here rather than in `.git`.
+  pub separate_git_dir: Option<PathBuf>,
+
+  /// How the refs are kept: `files` or `reftable`. `GIT_DEFAULT_REF_FORMAT`
+  /// says if this doesn't, and otherwise they are kept in files.
+  pub ref_format: Option<String>,
+}
+
+/// Copies the files of a template directory into a new git directory,
+/// leaving any already there alone.
+fn copy_template(template: &Path, dest: &Path) -> Result<(), String> {
+  let entries = match fs::read_dir(template) {
+    Ok(entries) => entries,
+    Err(_) => {
+      eprintln!("warning: templates not found in {}", template.display());
+      return Ok(());
+    }
+  };
+  for entry in entries {
+    let entry = entry.map_err(|e| e.to_string())?;
+    let (from, to) = (entry.path(), dest.join(entry.file_name()));
+    let error = |e: std::io::Error| {
+      format!(
+        "cannot copy '{}' to '{}': {}",
+        from.display(),
+        to.display(),
+        e
+      )
+    };
+    let file_type = entry.file_type().map_err(error)?;
+    if file_type.is_dir() {
+      create_dir_all(&to).map_err(error)?;
+      copy_template(&from, &to)?;
+    } else if to.exists() {
+      continue;
+    } else if file_type.is_symlink() {
+      std::os::unix::fs::symlink(fs::read_link(&from).map_err(error)?, &to).map_err(error)?;
     } else {
-      panic!("unable to find repository")
+      fs::copy(&from, &to).map_err(error)?;
     }
   }
+  Ok(())
+}
+
+/// Sets a value in a config, replacing any value the key (whose name is
+/// matched ignoring case) had before.
+fn set_value(config: &mut ConfigParser, section: Option<&str>, key: &str, value: &str) {
+  let name = config
+    .section(section)
+    .and_then(|properties| {
+      properties
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    })
+    .map_or(key.to_string(), |(name, _)| name.to_string());
+  config.with_section(section).set(name, value);
+}
+
+/// Whether `extensions.worktreeConfig` is on, so that each working tree has
+/// settings of its own in `config.worktree`.
+fn worktree_config(config: &ConfigParser) -> bool {
+  let value = config
+    .section(Some("extensions"))
+    .and_then(|extensions| {
+      extensions
+        .iter()
+        .rev()
+        .find(|(name, _)| name.eq_ignore_ascii_case("worktreeConfig"))
+    })
+    .map(|(_, value)| value.to_ascii_lowercase());
+  matches!(value.as_deref(), Some("true" | "yes" | "on" | "1" | ""))
+}
+
+/// Whether the filesystem keeps the executable bit, found out by flipping it
+/// on a file in the git directory.
+fn probe_filemode(git_dir: &Path) -> bool {
+  let path = git_dir.join("config");
+  let _ = fs::OpenOptions::new().create(true).append(true).open(&path);
+  let flipped = fs::metadata(&path).and_then(|metadata| {
+    let mut permissions = metadata.permissions();
+    permissions.set_mode(metadata.permissions().mode() ^ 0o100);
+    fs::set_permissions(&path, permissions.clone())?;
+    let kept = fs::metadata(&path)?.permissions().mode() == permissions.mode();
+    permissions.set_mode(metadata.permissions().mode());
+    fs::set_permissions(&path, permissions)?;
+    Ok(kept)
+  });
+  flipped.unwrap_or(false)
+}
+
+/// The value of a setting passed down in `GIT_CONFIG_PARAMETERS`, for the
+/// few read before there is a repository to read the rest from.
+fn config_parameter(key: &str) -> Option<String> {
+  crate::env::config_parameters()
+    .ok()?
+    .into_iter()
+    .rev()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value)
+}
+
+/// Adds a `key=value` setting (or just `key`, meaning `key=true`) to those
+/// passed down in `GIT_CONFIG_PARAMETERS`, which override the config file of
+/// any repository opened from then on (as `git -c` does).
+pub fn add_config_parameter(setting: &str) -> Result<(), String> {
+  let (key, value) = setting.split_once('=').unwrap_or((setting, "true"));
+  config_key(key)?;
+  let quote = |s: &str| format!("'{}'", s.replace('\'', "'\\''"));
+  let mut parameters = env::var("GIT_CONFIG_PARAMETERS").unwrap_or_default();
+  if !parameters.is_empty() {
+    parameters.push(' ');
+  }
+  parameters.push_str(&format!("{}={}", quote(key), quote(value)));
+  env::set_var("GIT_CONFIG_PARAMETERS", parameters);
+  Ok(())
+}
+
+/// Splits a config key like `branch.main.remote` into the name of its
+/// section as the config file spells it (`branch "main"`) and its name.
+/// Section and key names are not case sensitive, so they are lowercased.
+fn config_key(key: &str) -> Result<(String, String), String> {
+  let (section, name) = key
+    .rsplit_once('.')
+    .filter(|(section, _)| !section.is_empty())
+    .ok_or(format!("key does not contain a section: {}", key))?;
+  if name.is_empty() {
+    return Err(format!("key does not contain variable name: {}", key));
+  }
+  let section = match section.split_once('.') {
+    Some((section, subsection)) => format!("{} \"{}\"", section.to_lowercase(), subsection),
+    None => section.to_lowercase(),
+  };
+  Ok((section, name.to_lowercase()))
+}
+
+/// Whether a directory looks like a git directory: it has a `HEAD` and
+/// `objects` and `refs` directories.
+/// Parses a size from the config, which may end in `k`, `m` or `g`.
+pub fn parse_size(value: &str) -> Option<usize> {
+  let value = value.trim();
+  let (number, unit) = match value.char_indices().last()? {
+    (i, 'k' | 'K') => (&value[..i], 1 << 10),
+    (i, 'm' | 'M') => (&value[..i], 1 << 20),
+    (i, 'g' | 'G') => (&value[..i], 1 << 30),
+    _ => (value, 1),
+  };
+  number.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+/// Whether `dir` looks like a git directory: it has `HEAD`, `objects` and
+/// `refs`.
+pub fn is_git_dir(dir: &Path) -> bool {
+  dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+/// Reads a gitfile, a `.git` file that stands in for the git directory of
+/// a submodule or of a repository made with `--separate-git-dir`. It reads
+/// `gitdir: <path>`, where a relative path is from the directory the file is
+/// in.
+pub fn read_gitfile(path: &Path) -> Result<PathBuf, String> {
+  let data = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+  let target = data
+    .strip_prefix("gitdir: ")
+    .map(str::trim_end)
+    .filter(|target| !target.is_empty())
+    .ok_or(format!("invalid gitfile format: {}", path.display()))?;
+  let git_dir = path.parent().unwrap_or(Path::new(".")).join(target);
+  match git_dir.canonicalize() {
+    Ok(git_dir) if is_git_dir(&git_dir) => Ok(git_dir),
+    _ => Err(format!("not a git repository: {}", git_dir.display())),
+  }
 }
 
 /// Returns a new PathBuf with the given path appended to the given pathbuf.
+/// A gitfile in place of the git directory is followed.
 fn repo_path(git_dir: &Path, paths: &[&str]) -> PathBuf {
-  let mut new_path = git_dir.to_path_buf();
+  let mut new_path = match git_dir.is_file() {
+    true => read_gitfile(git_dir).unwrap_or_else(|_| git_dir.to_path_buf()),
+    false => git_dir.to_path_buf(),
+  };
   new_path.extend(paths.iter());
   new_path
 }
@@ -210,8 +711,8 @@ fn repo_path(git_dir: &Path, paths: &[&str]) -> PathBuf {
 /// it does not exist.
 ///
 /// # Examples
-/// ```
-/// repo_file(r, "refs", "remotes", "origin", "HEAD")
+/// ```text
+/// repo_file(r, &["refs", "remotes", "origin", "HEAD"], true)
 /// ```
 /// will create `.git/refs/remotes/origin` if it does not exist.
 pub fn repo_file(root: &Path, path: &[&str], mkdir: bool) -> Option<PathBuf> {
@@ -0,0 +1,349 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use bstr::{BString, ByteSlice};
+
+use crate::{
+  crypto::sha_1,
+  diff::blob_data,
+  index::{self, Index, IndexEntry},
+  merge,
+  repo::Repo,
+};
+
+/// A file's conflicts, normalized so that the same conflict is recognized no
+/// matter how it came about.
+pub struct Conflicts {
+  /// Identifies the conflict: a hash of the two sides of every hunk.
+  pub id: String,
+
+  /// The file with the labels after the conflict markers dropped and the two
+  /// sides of each hunk in sorted order.
+  pub preimage: Vec<u8>,
+
+  /// The number of conflict hunks (0 if the file has been resolved).
+  pub hunks: usize,
+}
+
+/// Checks whether rerere is turned on: by `rerere.enabled`, or if that is not
+/// set, by the presence of `.git/rr-cache`.
+pub fn enabled(repo: &Repo) -> bool {
+  match config(repo, "enabled") {
+    Some(value) => value == "true",
+    None => repo.git_dir.join("rr-cache").is_dir(),
+  }
+}
+
+/// Looks up a `rerere.*` setting, ignoring the case of its name.
+fn config(repo: &Repo, key: &str) -> Option<String> {
+  let section = repo.config.as_ref()?.section(Some("rerere"))?;
+  section
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_lowercase())
+}
+
+/// Finds the conflict hunks in a file, or returns `None` if its conflict
+/// markers do not pair up.
+///
+/// A hunk runs from a `<<<<<<<` line to a `>>>>>>>` line, with `=======`
+/// between the two sides. The base lines of a diff3-style hunk (after a
+/// `|||||||` line) are left out.
+pub fn conflicts(data: &[u8]) -> Option<Conflicts> {
+  enum State {
+    Outside,
+    Ours,
+    Base,
+    Theirs,
+  }
+  let marker = |line: &[u8], c: u8| {
+    line.len() >= 7
+      && line[..7].iter().all(|b| *b == c)
+      && matches!(line.get(7), None | Some(b' ') | Some(b'\n'))
+  };
+
+  let mut state = State::Outside;
+  let (mut ours, mut theirs): (Vec<u8>, Vec<u8>) = (Vec::new(), Vec::new());
+  let mut preimage = Vec::new();
+  let mut hashed = Vec::new();
+  let mut hunks = 0;
+  for line in data.split_inclusive(|b| *b == b'\n') {
+    match state {
+      State::Outside if marker(line, b'<') => state = State::Ours,
+      State::Outside => preimage.extend_from_slice(line),
+      State::Ours if marker(line, b'|') => state = State::Base,
+      State::Ours | State::Base if marker(line, b'=') => state = State::Theirs,
+      State::Ours | State::Base if marker(line, b'<') || marker(line, b'>') => return None,
+      State::Ours => ours.extend_from_slice(line),
+      State::Base => (),
+      State::Theirs if marker(line, b'>') => {
+        if ours > theirs {
+          std::mem::swap(&mut ours, &mut theirs);
+        }
+        for side in [&ours, &theirs] {
+          hashed.extend_from_slice(side);
+          hashed.push(0);
+        }
+        preimage.extend_from_slice(b"<<<<<<<\n");
+        preimage.append(&mut ours);
+        preimage.extend_from_slice(b"=======\n");
+        preimage.append(&mut theirs);
+        preimage.extend_from_slice(b">>>>>>>\n");
+        hunks += 1;
+        state = State::Outside;
+      }
+      State::Theirs if marker(line, b'<') || marker(line, b'=') || marker(line, b'|') => {
+        return None
+      }
+      State::Theirs => theirs.extend_from_slice(line),
+    }
+  }
+  match state {
+    State::Outside => Some(Conflicts {
+      id: sha_1(&hashed),
+      preimage,
+      hunks,
+    }),
+    _ => None,
+  }
+}
+
+/// The conflicts rerere is waiting to see resolved, as (id, path) pairs.
+///
+/// They are kept in `.git/MERGE_RR` as `<id>\t<path>` records ending in NULs.
+pub fn pending(repo: &Repo) -> Vec<(String, BString)> {
+  let data = fs::read(repo.git_dir.join("MERGE_RR")).unwrap_or_default();
+  data
+    .split_str("\0")
+    .filter_map(|record| record.split_once_str("\t"))
+    .map(|(id, path)| (id.to_str_lossy().into_owned(), path.into()))
+    .collect()
+}
+
+fn save_pending(repo: &Repo, entries: &[(String, BString)]) -> Result<(), String> {
+  let data: Vec<u8> = entries
+    .iter()
+    .flat_map(|(id, path)| [id.as_bytes(), b"\t", path, b"\0"].concat())
+    .collect();
+  fs::write(repo.git_dir.join("MERGE_RR"), data)
+    .map_err(|e| format!("could not write MERGE_RR ({})", e))
+}
+
+/// The directory a conflict's images are kept in.
+fn cache_dir(repo: &Repo, id: &str) -> PathBuf {
+  repo.git_dir.join("rr-cache").join(id)
+}
+
+fn write_image(repo: &Repo, id: &str, name: &str, data: &[u8]) -> Result<(), String> {
+  let dir = cache_dir(repo, id);
+  fs::create_dir_all(&dir).map_err(|e| format!("could not create {} ({})", dir.display(), e))?;
+  fs::write(dir.join(name), data).map_err(|e| format!("could not write {} ({})", name, e))
+}
+
+/// The paths with unmerged entries in the index.
+fn unmerged(index: &Index) -> BTreeSet<BString> {
+  index
+    .entries()
+    .iter()
+    .filter(|e| e.stage() != 0)
+    .map(|e| e.path.clone())
+    .collect()
+}
+
+/// Records and replays conflict resolutions, returning the messages to show.
+///
+/// Conflicted files that were resolved since the last run have their
+/// resolution recorded (the `postimage`). Newly conflicted files are looked
+/// up by their conflict id: if the same conflict was resolved before, that
+/// resolution is merged into the file (and staged if `rerere.autoUpdate` is
+/// set), and otherwise the conflict (the `preimage`) is recorded so that its
+/// resolution can be picked up later.
+pub fn run(repo: &Repo) -> Result<Vec<String>, String> {
+  if !enabled(repo) {
+    return Ok(Vec::new());
+  }
+  let mut messages = Vec::new();
+  let mut index = Index::read(repo)?;
+  let mut pending = pending(repo);
+  let tracked: BTreeSet<BString> = pending.iter().map(|(_, path)| path.clone()).collect();
+  let mut staged = false;
+
+  for path in unmerged(&index).difference(&tracked) {
+    let data = match fs::read(repo.work_tree_path(path)) {
+      Ok(data) => data,
+      Err(_) => continue,
+    };
+    let conflicts = match conflicts(&data) {
+      Some(conflicts) if conflicts.hunks > 0 => conflicts,
+      _ => continue,
+    };
+    let postimage = fs::read(cache_dir(repo, &conflicts.id).join("postimage"));
+    if let Ok(postimage) = postimage {
+      let preimage = fs::read(cache_dir(repo, &conflicts.id).join("preimage")).unwrap_or_default();
+      let merged = merge::merge_file(&preimage, &conflicts.preimage, &postimage, "", "");
+      if merged.conflicts == 0 {
+        fs::write(repo.work_tree_path(path), &merged.data)
+          .map_err(|e| format!("could not write '{}' ({})", path, e))?;
+        messages.push(format!("Resolved '{}' using previous resolution.", path));
+        if config(repo, "autoupdate").as_deref() == Some("true") {
+          let indexed = index.get(path).map(|entry| entry.hash.as_str());
+          let hash = index::hash_file(repo, path, indexed, true)?;
+          let metadata = fs::symlink_metadata(repo.work_tree_path(path))
+            .map_err(|e| format!("{}: {}", path, e))?;
+          index.add(IndexEntry::from_metadata(path, &hash, &metadata));
+          messages.push(format!("Staged '{}' using previous resolution.", path));
+          staged = true;
+        }
+        continue;
+      }
+    }
+    write_image(repo, &conflicts.id, "preimage", &conflicts.preimage)?;
+    messages.push(format!("Recorded preimage for '{}'", path));
+    pending.push((conflicts.id, path.clone()));
+  }
+
+  // a pending conflict whose file has no markers left has been resolved
+  let mut remaining = Vec::new();
+  for (id, path) in pending {
+    let data = match fs::read(repo.work_tree_path(&path)) {
+      Ok(data) => data,
+      Err(_) => continue,
+    };
+    match conflicts(&data) {
+      Some(conflicts) if conflicts.hunks == 0 => {
+        write_image(repo, &id, "postimage", &data)?;
+        messages.push(format!("Recorded resolution for '{}'.", path));
+      }
+      _ => remaining.push((id, path)),
+    }
+  }
+  save_pending(repo, &remaining)?;
+  if staged {
+    index.write(repo)?;
+  }
+  Ok(messages)
+}
+
+/// Forgets the conflicts of the current merge, dropping the preimages of the
+/// ones that were never resolved.
+pub fn clear(repo: &Repo) -> Result<(), String> {
+  for (id, _) in pending(repo) {
+    let dir = cache_dir(repo, &id);
+    if !dir.join("postimage").exists() {
+      fs::remove_dir_all(&dir)
+        .map_err(|e| format!("could not remove {} ({})", dir.display(), e))?;
+    }
+  }
+  match fs::remove_file(repo.git_dir.join("MERGE_RR")) {
+    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+      Err(format!("could not remove MERGE_RR ({})", e))
+    }
+    _ => Ok(()),
+  }
+}
+
+/// Drops the recorded resolution of a conflicted file, so that the next one
+/// recorded replaces it, returning the messages to show.
+///
+/// The conflict is recreated from the stages in the index, since the file
+/// itself may already have been resolved.
+pub fn forget(repo: &Repo, path: &[u8]) -> Result<Vec<String>, String> {
+  let path = path.as_bstr();
+  let index = Index::read(repo)?;
+  let mut sides: [Vec<u8>; 3] = Default::default();
+  for entry in index
+    .entries()
+    .iter()
+    .filter(|e| e.path == path && e.stage() != 0)
+  {
+    let mode = entry
+      .tree_mode()
+      .unwrap_or(crate::object::mode::Mode::Normal);
+    sides[entry.stage() as usize - 1] = blob_data(repo, Some(&(mode, entry.hash.clone())))?;
+  }
+  let merged = merge::merge_file(&sides[0], &sides[1], &sides[2], "ours", "theirs");
+  let conflicts = match conflicts(&merged.data) {
+    Some(conflicts) if conflicts.hunks > 0 => conflicts,
+    _ => return Err(format!("could not parse conflict hunks in '{}'", path)),
+  };
+
+  let mut messages = Vec::new();
+  write_image(repo, &conflicts.id, "preimage", &conflicts.preimage)?;
+  messages.push(format!("Updated preimage for '{}'", path));
+  let postimage = cache_dir(repo, &conflicts.id).join("postimage");
+  if fs::remove_file(postimage).is_ok() {
+    messages.push(format!("Forgot resolution for '{}'", path));
+  }
+  let mut pending = pending(repo);
+  pending.retain(|(_, p)| p != path);
+  pending.push((conflicts.id, path.into()));
+  save_pending(repo, &pending)?;
+  Ok(messages)
+}
+
+/// Lists the conflicted paths rerere cannot help with yet: those it is
+/// tracking and those it could not make sense of (eg. modify/delete).
+pub fn remaining(repo: &Repo) -> Result<Vec<BString>, String> {
+  let index = Index::read(repo)?;
+  let mut paths: Vec<BString> = pending(repo).into_iter().map(|(_, path)| path).collect();
+  for path in unmerged(&index) {
+    if !paths.contains(&path) {
+      paths.push(path);
+    }
+  }
+  Ok(paths)
+}
+
+/// Removes old entries from `.git/rr-cache`: unresolved conflicts after
+/// `gc.rerereUnresolved` days (15 by default) and resolved ones after
+/// `gc.rerereResolved` days (60 by default).
+pub fn gc(repo: &Repo) -> Result<(), String> {
+  let days = |key: &str, default: u64| {
+    repo
+      .config
+      .as_ref()
+      .and_then(|config| config.section(Some("gc")))
+      .and_then(|section| {
+        section
+          .iter()
+          .find(|(name, _)| name.eq_ignore_ascii_case(key))
+          .and_then(|(_, value)| value.parse().ok())
+      })
+      .unwrap_or(default)
+  };
+  let (resolved, unresolved) = (days("rerereResolved", 60), days("rerereUnresolved", 15));
+  let in_use: Vec<String> = pending(repo).into_iter().map(|(id, _)| id).collect();
+  let entries = match fs::read_dir(repo.git_dir.join("rr-cache")) {
+    Ok(entries) => entries,
+    Err(_) => return Ok(()),
+  };
+  let now = SystemTime::now();
+  for entry in entries.flatten() {
+    let id = entry.file_name().to_string_lossy().into_owned();
+    if in_use.contains(&id) {
+      continue;
+    }
+    let (image, limit) = match entry.path().join("postimage").exists() {
+      true => ("postimage", resolved),
+      false => ("preimage", unresolved),
+    };
+    let age = fs::metadata(entry.path().join(image))
+      .and_then(|metadata| metadata.modified())
+      .ok()
+      .and_then(|modified| now.duration_since(modified).ok())
+      .unwrap_or(Duration::MAX);
+    if age > Duration::from_secs(limit * 24 * 60 * 60) {
+      fs::remove_dir_all(entry.path())
+        .map_err(|e| format!("could not remove {} ({})", entry.path().display(), e))?;
+    }
+  }
+  Ok(())
+}
+
+/// The recorded conflict of a pending path, to diff the file against.
+pub fn preimage(repo: &Repo, id: &str) -> Result<Vec<u8>, String> {
+  fs::read(cache_dir(repo, id).join("preimage"))
+    .map_err(|e| format!("could not read the preimage of {} ({})", id, e))
+}
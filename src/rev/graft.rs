@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::env;
+use crate::repo::Repo;
+
+/// Parents that history is walked through in place of the ones recorded in
+/// commits.
+///
+/// Two files give them. `info/grafts` (or `GIT_GRAFT_FILE`) has a line for
+/// each grafted commit, its hash followed by the hashes of the parents it
+/// should be taken to have. `shallow` lists the commits a shallow clone is
+/// cut off at, which are taken to have no parents at all, as their parents
+/// were never fetched; it wins over the grafts.
+#[derive(Clone, Debug, Default)]
+pub struct Grafts {
+  parents: HashMap<String, Vec<String>>,
+}
+
+impl Grafts {
+  /// Reads the grafts of a repository. Lines that aren't a list of hashes
+  /// are complained about and left out.
+  pub fn read(repo: &Repo) -> Self {
+    let mut parents = HashMap::new();
+    let graft_file = env::graft_file(&repo.git_dir);
+    for line in fs::read_to_string(graft_file).unwrap_or_default().lines() {
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let hashes: Vec<String> = line.split(' ').map(str::to_owned).collect();
+      if !hashes.iter().all(|hash| is_hash(hash)) {
+        eprintln!("error: bad graft data: {}", line);
+        continue;
+      }
+      parents.insert(hashes[0].clone(), hashes[1..].to_vec());
+    }
+
+    let shallow = fs::read_to_string(repo.git_dir.join("shallow")).unwrap_or_default();
+    for hash in shallow.lines().filter(|hash| is_hash(hash)) {
+      parents.insert(hash.to_owned(), Vec::new());
+    }
+    Self { parents }
+  }
+
+  /// The parents of a commit, given the ones it records.
+  pub fn parents<'a>(&'a self, hash: &str, parents: &'a [String]) -> &'a [String] {
+    self.parents.get(hash).map_or(parents, Vec::as_slice)
+  }
+}
+
+fn is_hash(hash: &str) -> bool {
+  hash.len() == 40 && hash.bytes().all(|c| c.is_ascii_hexdigit())
+}
@@ -0,0 +1,168 @@
+/// Draws the commit graph as ASCII art next to `log` output.
+///
+/// Each line of development is given a column. A commit is drawn as a `*` in
+/// its column, a merge opens new columns for its other parents with `\`, and
+/// when two columns end up waiting on the same commit they are joined back
+/// together with `/`. Commits must be fed in topological order.
+///
+/// ### Example
+/// ```text
+/// *   merge
+/// |\
+/// | * side
+/// * | main
+/// |/
+/// * base
+/// ```
+pub struct Graph {
+  columns: Vec<String>,
+}
+
+/// The rows drawn for a single commit.
+pub struct GraphRows {
+  /// The row holding the commit's `*`.
+  pub commit: String,
+
+  /// Rows that open columns for a merge's other parents.
+  pub expand: Vec<String>,
+
+  /// The prefix for any further lines printed about the commit.
+  pub padding: String,
+
+  /// Rows that join columns waiting on the same commit, drawn after the
+  /// commit's lines.
+  pub collapse: Vec<String>,
+}
+
+impl Graph {
+  pub fn new() -> Self {
+    Self {
+      columns: Vec::new(),
+    }
+  }
+
+  /// Places the next commit in the graph and returns the rows to draw.
+  pub fn update(&mut self, hash: &str, parents: &[String]) -> GraphRows {
+    let idx = match self.columns.iter().position(|c| c == hash) {
+      Some(i) => i,
+      None => {
+        self.columns.push(hash.to_owned());
+        self.columns.len() - 1
+      }
+    };
+
+    // The commit row, with octopus merges marked by a trailing `-.` or `---.`.
+    let mut cells: Vec<(usize, char)> = (0..self.columns.len())
+      .map(|i| (2 * i, if i == idx { '*' } else { '|' }))
+      .collect();
+    if parents.len() > 2 && idx + 1 == self.columns.len() {
+      let end = 2 * idx + 2 * (parents.len() - 2);
+      cells.extend((2 * idx + 1..end).map(|position| (position, '-')));
+      cells.push((end, '.'));
+    }
+    let commit = render(&cells);
+
+    let mut expand = Vec::new();
+    let mut collapse = Vec::new();
+    let padding;
+    if parents.is_empty() {
+      // A root commit ends its line; columns to the right slide over.
+      let width = self.columns.len();
+      padding = render(
+        &(0..width)
+          .filter(|i| *i != idx)
+          .map(|i| (2 * i, '|'))
+          .collect::<Vec<_>>(),
+      );
+      self.columns.remove(idx);
+      if idx < self.columns.len() {
+        let mut cells: Vec<(usize, char)> = (0..idx).map(|i| (2 * i, '|')).collect();
+        cells.extend((idx + 1..width).map(|i| (2 * i - 1, '/')));
+        collapse.push(render(&cells));
+      }
+    } else {
+      // The first parent continues the commit's column, other parents get
+      // new columns opened to its right.
+      let mut extra: Vec<String> = Vec::new();
+      for parent in &parents[1..] {
+        if !self.columns.contains(parent) && !extra.contains(parent) {
+          extra.push(parent.clone());
+        }
+      }
+      let width = self.columns.len();
+      self.columns[idx] = parents[0].clone();
+      if !extra.is_empty() {
+        let k = extra.len();
+        let mut cells: Vec<(usize, char)> = (0..=idx).map(|i| (2 * i, '|')).collect();
+        cells.extend((1..=k).map(|j| (2 * (idx + j) - 1, '\\')));
+        cells.extend((idx + 1..width).map(|i| (2 * i + 1, '\\')));
+        expand.push(render(&cells));
+        for (j, parent) in extra.into_iter().enumerate() {
+          self.columns.insert(idx + 1 + j, parent);
+        }
+      }
+      padding = render(
+        &(0..self.columns.len())
+          .map(|i| (2 * i, '|'))
+          .collect::<Vec<_>>(),
+      );
+    }
+
+    // Join any columns that are now waiting on the same commit.
+    while let Some((i, j)) = self.duplicate() {
+      let width = self.columns.len();
+      let mut cells: Vec<(usize, char)> = (0..j).map(|c| (2 * c, '|')).collect();
+      for position in 2 * i + 1..2 * j - 1 {
+        if position % 2 == 1 {
+          cells.push((position, '_'));
+        }
+      }
+      cells.push((2 * j - 1, '/'));
+      cells.extend((j + 1..width).map(|c| (2 * c - 1, '/')));
+      collapse.push(render(&cells));
+      self.columns.remove(j);
+    }
+
+    GraphRows {
+      commit,
+      expand,
+      padding,
+      collapse,
+    }
+  }
+
+  /// The prefix for a line drawn between commits.
+  pub fn padding(&self) -> String {
+    render(
+      &(0..self.columns.len())
+        .map(|i| (2 * i, '|'))
+        .collect::<Vec<_>>(),
+    )
+  }
+
+  /// Finds the first pair of columns waiting on the same commit.
+  fn duplicate(&self) -> Option<(usize, usize)> {
+    for j in 1..self.columns.len() {
+      if let Some(i) = self.columns[..j].iter().position(|c| *c == self.columns[j]) {
+        return Some((i, j));
+      }
+    }
+    None
+  }
+}
+
+impl Default for Graph {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Draws a row of characters at the given positions, padded with spaces.
+fn render(cells: &[(usize, char)]) -> String {
+  let width = cells.iter().map(|c| c.0 + 1).max().unwrap_or(0);
+  let mut row = vec![' '; width];
+  for (position, ch) in cells {
+    row[*position] = *ch;
+  }
+  row.into_iter().collect()
+}
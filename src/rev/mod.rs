@@ -0,0 +1,170 @@
+pub mod graft;
+pub mod graph;
+pub mod name;
+pub mod walk;
+
+use bstr::BString;
+
+use crate::index::Index;
+use crate::object::{self, commit::Commit, refs, serializable::Unbox, tree};
+use crate::repo::Repo;
+
+/// Resolves a revision expression to an object hash.
+///
+/// A revision starts with a name, which may be a full or abbreviated object
+/// hash, a ref name (`HEAD`, `master`, `v1.0`, `refs/heads/topic`) or `@` as a
+/// shorthand for `HEAD`. The name may be followed by any number of suffixes:
+///
+/// * `^<n>` - the n-th parent of a commit (`^` alone means `^1`, `^0` is the
+///   commit itself)
+/// * `~<n>` - the n-th generation ancestor following only first parents
+/// * `^{<type>}` - peels tags (and commits) until an object of type is found
+/// * `^{}` - peels tags until a non-tag object is found
+///
+/// A revision followed by `:<path>` names the object at that path in its
+/// tree, and `:<n>:<path>` (or `:<path>`, for stage 0) names the blob at that
+/// path in stage n of the index. Paths are from the root of the working
+/// tree, unless they start with `./` or `../`.
+///
+/// # Example
+/// ```text
+/// HEAD~2^2     => second parent of the grandparent of HEAD
+/// v1.0^{tree}  => the tree of the commit tagged v1.0
+/// HEAD:src     => the tree of the src directory in HEAD
+/// :2:a.txt     => our side of a.txt, while it is conflicted
+/// ```
+pub fn parse(repo: &Repo, spec: &str) -> Result<String, String> {
+  if let Some(rest) = spec.strip_prefix(':') {
+    return index_path(repo, rest);
+  }
+  if let Some((rev, path)) = spec.split_once(':') {
+    return tree_path(repo, rev, path);
+  }
+
+  let split = spec.find(['^', '~']).unwrap_or(spec.len());
+  let (name, mut suffix) = spec.split_at(split);
+  let mut hash = resolve_name(repo, name)?;
+
+  while !suffix.is_empty() {
+    let op = suffix.as_bytes()[0];
+    suffix = &suffix[1..];
+
+    if op == b'^' && suffix.starts_with('{') {
+      let close = match suffix.find('}') {
+        Some(i) => i,
+        None => return Err(format!("invalid revision \"{}\"", spec)),
+      };
+      hash = match &suffix[1..close] {
+        "" => object::peel(repo, &hash, None)?,
+        typename => object::peel(repo, &hash, Some(typename))?,
+      };
+      suffix = &suffix[close + 1..];
+      continue;
+    }
+
+    // Read the (optional) number that follows the operator.
+    let digits = suffix
+      .find(|c: char| !c.is_ascii_digit())
+      .unwrap_or(suffix.len());
+    let count = match &suffix[..digits] {
+      "" => 1,
+      n => n
+        .parse::<usize>()
+        .map_err(|e| format!("{} ({})", spec, e))?,
+    };
+    suffix = &suffix[digits..];
+
+    hash = object::peel(repo, &hash, Some("commit"))?;
+    if op == b'^' {
+      if count > 0 {
+        hash = nth_parent(repo, &hash, count, spec)?;
+      }
+    } else {
+      for _ in 0..count {
+        hash = nth_parent(repo, &hash, 1, spec)?;
+      }
+    }
+  }
+
+  Ok(hash)
+}
+
+/// Finds the object at a path in the tree of a revision, for `<rev>:<path>`.
+fn tree_path(repo: &Repo, rev: &str, path: &str) -> Result<String, String> {
+  let tree = object::peel(repo, &parse(repo, rev)?, Some("tree"))?;
+  let relative = relative_path(repo, path)?;
+  match tree::lookup(repo, &tree, &relative)? {
+    Some((_, hash)) => Ok(hash),
+    None => Err(format!("path '{}' does not exist in '{}'", relative, rev)),
+  }
+}
+
+/// Finds the blob at a path in the index, for `:<n>:<path>` and `:<path>`.
+fn index_path(repo: &Repo, spec: &str) -> Result<String, String> {
+  let (stage, path) = match spec.as_bytes() {
+    [n @ b'0'..=b'3', b':', ..] => ((n - b'0') as u16, &spec[2..]),
+    _ => (0, spec),
+  };
+  let relative = relative_path(repo, path)?;
+  let index = Index::read(repo)?;
+  let entries: Vec<_> = index
+    .entries()
+    .iter()
+    .filter(|entry| entry.path == relative)
+    .collect();
+  match entries.iter().find(|entry| entry.stage() == stage) {
+    Some(entry) => Ok(entry.hash.clone()),
+    None if entries.is_empty() => Err(format!("path '{}' does not exist in the index", relative)),
+    None => Err(format!(
+      "path '{}' is in the index, but not at stage {}",
+      relative, stage
+    )),
+  }
+}
+
+/// The path after a `:`, from the root of the working tree.
+fn relative_path(repo: &Repo, path: &str) -> Result<BString, String> {
+  if path.starts_with("./") || path.starts_with("../") {
+    return repo.worktree_path(path);
+  }
+  Ok(path.trim_end_matches('/').into())
+}
+
+/// Resolves the name part of a revision (everything before any suffix).
+fn resolve_name(repo: &Repo, name: &str) -> Result<String, String> {
+  let name = if name.is_empty() || name == "@" {
+    "HEAD"
+  } else {
+    name
+  };
+
+  if let Some(hash) = refs::lookup(repo, name) {
+    return Ok(hash);
+  }
+  if name.eq_ignore_ascii_case(object::EMPTY_TREE) {
+    return Ok(object::EMPTY_TREE.to_owned());
+  }
+  if name.len() >= 4 && name.len() <= 40 && name.bytes().all(|b| b.is_ascii_hexdigit()) {
+    let mut matches = object::find_by_prefix(repo, &name.to_ascii_lowercase());
+    match matches.len() {
+      0 => (),
+      1 => return Ok(matches.remove(0)),
+      _ => return Err(format!("short object ID {} is ambiguous", name)),
+    }
+  }
+  Err(format!(
+    "ambiguous argument '{}': unknown revision or path not in the working tree",
+    name
+  ))
+}
+
+/// Returns the n-th (one-based) parent of a commit.
+fn nth_parent(repo: &Repo, hash: &str, n: usize, spec: &str) -> Result<String, String> {
+  let object = object::read(repo, hash, Some("commit"))?;
+  let commit = object.unbox::<Commit>()?;
+  let grafts = graft::Grafts::read(repo);
+  match grafts.parents(hash, commit.parents()).get(n - 1) {
+    Some(parent) => Ok(parent.to_owned()),
+    None => Err(format!("invalid revision \"{}\"", spec)),
+  }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use crate::object::{self, commit::Commit, serializable::Unbox, tag::Tag};
+use crate::repo::Repo;
+
+use super::graft::Grafts;
+
+/// How much further away a commit is taken to be when it is reached through
+/// a merge's other parents, so that names along first parents win.
+const MERGE_TRAVERSAL_WEIGHT: usize = 65535;
+
+/// The name a commit has been given, relative to a ref.
+#[derive(Clone)]
+struct Name {
+  /// The ref the name starts from, with `~<n>^<m>` for each merge it went
+  /// through to a parent other than the first.
+  tip: String,
+
+  /// The date of the tag (or of the commit a ref points at) it is named
+  /// from.
+  date: i64,
+
+  /// How many first parents are walked from the tip.
+  generation: usize,
+
+  /// How far the commit is from the ref, with merges weighted heavily.
+  distance: usize,
+
+  /// Whether it is named from a tag.
+  from_tag: bool,
+
+  /// Whether the ref points at an annotated tag of the commit, rather than
+  /// at the commit itself, so that it is `<tag>^0`.
+  deref: bool,
+}
+
+impl Name {
+  /// Whether `other` is a better name than this one: names from tags win over
+  /// others, the older tag wins between tags, and the nearer ref between
+  /// others.
+  fn is_worse_than(&self, other: &Name) -> bool {
+    if self.from_tag && other.from_tag {
+      return self.date > other.date || (self.date == other.date && self.distance > other.distance);
+    }
+    if self.from_tag != other.from_tag {
+      return other.from_tag;
+    }
+    if self.distance != other.distance {
+      return self.distance > other.distance;
+    }
+    self.date > other.date
+  }
+
+  /// The name as it is shown: `<tip>`, `<tip>^0` for a tag's commit, or
+  /// `<tip>~<n>`.
+  fn render(&self) -> String {
+    match (self.generation, self.deref) {
+      (0, true) => format!("{}^0", self.tip),
+      (0, false) => self.tip.clone(),
+      (n, _) => format!("{}~{}", self.tip, n),
+    }
+  }
+}
+
+/// Names commits after the refs they can be reached from, as `name-rev`
+/// does: `tags/v1.2~3` is the third first-parent ancestor of the commit
+/// `v1.2` tags, and `master~2^2` the second parent of `master~2`.
+///
+/// Each ref added is walked back through its history, giving every commit
+/// it reaches a name unless it already has a better one. Commits dated
+/// before the cutoff are not walked into, so naming a recent commit doesn't
+/// have to read the whole history.
+///
+/// # Example
+/// ```text
+/// let mut names = Names::new(&repo, Some(cutoff));
+/// names.add("tags/v1.2", &tag, true)?;
+/// names.add("master", &master, false)?;
+/// names.name(&commit)  // => Some("tags/v1.2~3")
+/// ```
+pub struct Names {
+  repo: Repo,
+  grafts: Grafts,
+  cutoff: i64,
+  names: HashMap<String, Name>,
+  commits: HashMap<String, (Vec<String>, i64)>,
+}
+
+impl Names {
+  /// Names nothing yet; commits dated before `cutoff`, if given, are never
+  /// named.
+  pub fn new(repo: &Repo, cutoff: Option<i64>) -> Self {
+    Self {
+      repo: repo.clone(),
+      grafts: Grafts::read(repo),
+      cutoff: cutoff.unwrap_or(i64::MIN),
+      names: HashMap::new(),
+      commits: HashMap::new(),
+    }
+  }
+
+  /// Names the commits reachable from a ref, which points at `hash`, after
+  /// `tip` where they have no better name. `from_tag` says whether the ref
+  /// is a tag. Refs that don't lead to a commit name nothing.
+  pub fn add(&mut self, tip: &str, hash: &str, from_tag: bool) -> Result<(), String> {
+    let mut hash = hash.to_owned();
+    let mut date = None;
+    let mut deref = false;
+    loop {
+      let object = object::read(&self.repo, &hash, None)?;
+      match object.format().as_str() {
+        "commit" => break,
+        "tag" => {
+          let tag = object.unbox::<Tag>()?;
+          if date.is_none() {
+            date = tag.get("tagger").map(|tagger| time_of(tagger));
+          }
+          deref = true;
+          hash = match tag.get("object") {
+            Some(target) => target.to_owned(),
+            None => return Ok(()),
+          };
+        }
+        _ => return Ok(()),
+      }
+    }
+    let date = match date {
+      Some(date) => date,
+      None => self.commit(&hash)?.1,
+    };
+
+    let name = Name {
+      tip: tip.to_owned(),
+      date,
+      generation: 0,
+      distance: 0,
+      from_tag,
+      deref,
+    };
+    let mut stack = vec![(hash, name)];
+    while let Some((hash, name)) = stack.pop() {
+      let (parents, time) = self.commit(&hash)?.clone();
+      if time < self.cutoff {
+        continue;
+      }
+      if let Some(old) = self.names.get(&hash) {
+        if !old.is_worse_than(&name) {
+          continue;
+        }
+      }
+      for (i, parent) in parents.iter().enumerate().rev() {
+        let parent_name = match i {
+          0 => Name {
+            generation: name.generation + 1,
+            distance: name.distance + 1,
+            ..name.clone()
+          },
+          _ => Name {
+            tip: match name.generation {
+              0 => format!("{}^{}", name.tip, i + 1),
+              n => format!("{}~{}^{}", name.tip, n, i + 1),
+            },
+            generation: 0,
+            distance: name.distance + MERGE_TRAVERSAL_WEIGHT,
+            deref: false,
+            ..name.clone()
+          },
+        };
+        stack.push((parent.clone(), parent_name));
+      }
+      self.names.insert(hash, name);
+    }
+    Ok(())
+  }
+
+  /// The name of a commit, or `None` if no ref reaches it.
+  pub fn name(&self, hash: &str) -> Option<String> {
+    self.names.get(hash).map(Name::render)
+  }
+
+  /// Every commit that has been named, with its name, in order of their
+  /// hashes.
+  pub fn all(&self) -> Vec<(String, String)> {
+    let mut names: Vec<(String, String)> = self
+      .names
+      .iter()
+      .map(|(hash, name)| (hash.clone(), name.render()))
+      .collect();
+    names.sort();
+    names
+  }
+
+  /// Reads (or fetches from the cache) the parents and time of a commit,
+  /// through any grafts.
+  fn commit(&mut self, hash: &str) -> Result<&(Vec<String>, i64), String> {
+    if !self.commits.contains_key(hash) {
+      let object = object::read(&self.repo, hash, Some("commit"))?;
+      let commit = object.unbox::<Commit>()?;
+      let parents = self.grafts.parents(hash, commit.parents()).to_vec();
+      self
+        .commits
+        .insert(hash.to_owned(), (parents, commit.commit_time()));
+    }
+    Ok(&self.commits[hash])
+  }
+}
+
+/// The time in an identity line, `Name <email> <seconds> <timezone>`.
+fn time_of(identity: &str) -> i64 {
+  identity
+    .rsplit(' ')
+    .nth(1)
+    .and_then(|time| time.parse::<i64>().ok())
+    .unwrap_or(0)
+}
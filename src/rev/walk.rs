@@ -0,0 +1,627 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use bstr::BString;
+
+use crate::cancel::Cancel;
+use crate::diff::{self, pickaxe::Pickaxe};
+use crate::object;
+use crate::object::tree::{self, Tree};
+use crate::object::{commit::Commit, mode::Mode, serializable::Unbox};
+use crate::repo::Repo;
+
+use super::graft::Grafts;
+
+/// The order in which a [`RevWalk`] emits commits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sort {
+  /// Newest commit first (by committer date), like `git log`.
+  Date,
+
+  /// Never show a parent before all of its children, keeping lines of
+  /// history together (`--topo-order`).
+  Topo,
+}
+
+/// The parts of a commit the walker needs, cached so each commit object is
+/// only read and parsed once.
+struct Node {
+  parents: Vec<String>,
+  time: i64,
+}
+
+/// Walks the commit graph.
+///
+/// A walk starts at one or more tips and visits every commit reachable from
+/// them, except for commits that are also reachable from a hidden commit. This
+/// is the machinery behind `rev-list` and `log`.
+///
+/// # Example
+/// ```text
+/// let mut walk = RevWalk::new(&repo);
+/// walk.push_spec("main..topic")?;  // commits on topic but not main
+/// let commits = walk.run()?;
+/// ```
+pub struct RevWalk {
+  repo: Repo,
+  tips: Vec<String>,
+  hidden: Vec<String>,
+  sort: Sort,
+  max_count: Option<usize>,
+  since: Option<i64>,
+  until: Option<i64>,
+  paths: Vec<BString>,
+  follow: bool,
+  pickaxe: Option<Pickaxe>,
+  cancel: Cancel,
+  grafts: Grafts,
+  nodes: HashMap<String, Node>,
+  edges: HashMap<String, Vec<String>>,
+  shown: HashSet<String>,
+}
+
+impl RevWalk {
+  pub fn new(repo: &Repo) -> Self {
+    Self {
+      repo: repo.clone(),
+      tips: Vec::new(),
+      hidden: Vec::new(),
+      sort: Sort::Date,
+      max_count: None,
+      since: None,
+      until: None,
+      paths: Vec::new(),
+      follow: false,
+      pickaxe: None,
+      cancel: Cancel::new(),
+      grafts: Grafts::read(repo),
+      nodes: HashMap::new(),
+      edges: HashMap::new(),
+      shown: HashSet::new(),
+    }
+  }
+
+  /// Starts the walk at the given commit.
+  pub fn push(&mut self, hash: &str) {
+    self.tips.push(hash.to_owned());
+  }
+
+  /// Excludes the given commit and all of its ancestors from the walk.
+  pub fn hide(&mut self, hash: &str) {
+    self.hidden.push(hash.to_owned());
+  }
+
+  /// Adds a revision argument the way `rev-list` understands it.
+  ///
+  /// Supports plain revisions (`HEAD`), exclusions (`^main`), ranges
+  /// (`main..topic`, meaning `^main topic`) and symmetric differences
+  /// (`main...topic`, commits reachable from either but not both).
+  pub fn push_spec(&mut self, spec: &str) -> Result<(), String> {
+    if let Some((left, right)) = spec.split_once("...") {
+      let left = self.resolve(left)?;
+      let right = self.resolve(right)?;
+      for base in merge_bases(&self.repo, &left, &right)? {
+        self.hide(&base);
+      }
+      self.push(&left);
+      self.push(&right);
+    } else if let Some((left, right)) = spec.split_once("..") {
+      let left = self.resolve(left)?;
+      let right = self.resolve(right)?;
+      self.hide(&left);
+      self.push(&right);
+    } else if let Some(name) = spec.strip_prefix('^') {
+      let hash = self.resolve(name)?;
+      self.hide(&hash);
+    } else {
+      let hash = self.resolve(spec)?;
+      self.push(&hash);
+    }
+    Ok(())
+  }
+
+  /// Sets the order in which commits are returned.
+  pub fn sort(&mut self, sort: Sort) {
+    self.sort = sort;
+  }
+
+  /// Stops the walk after the given number of shown commits.
+  pub fn max_count(&mut self, max_count: Option<usize>) {
+    self.max_count = max_count;
+  }
+
+  /// Only shows commits made at or after the given time (`--since`). The walk
+  /// goes no further back than the first older commit on each line of
+  /// history.
+  pub fn since(&mut self, since: Option<i64>) {
+    self.since = since;
+  }
+
+  /// Only shows commits made at or before the given time (`--until`).
+  pub fn until(&mut self, until: Option<i64>) {
+    self.until = until;
+  }
+
+  /// Limits the walk to commits that change the given paths.
+  ///
+  /// Paths are relative to the root of the working tree; a directory matches
+  /// everything beneath it.
+  pub fn paths(&mut self, paths: &[BString]) {
+    self.paths = paths.to_vec();
+  }
+
+  /// Follows a single path across renames (`--follow`).
+  pub fn follow(&mut self, follow: bool) {
+    self.follow = follow;
+  }
+
+  /// Only shows commits whose changes match the pickaxe (`-S` or `-G`).
+  pub fn pickaxe(&mut self, pickaxe: Option<Pickaxe>) {
+    self.pickaxe = pickaxe;
+  }
+
+  /// Stops the walk with an error once the token is cancelled.
+  pub fn cancel(&mut self, cancel: &Cancel) {
+    self.cancel = cancel.clone();
+  }
+
+  /// Runs the walk and returns the hashes of the selected commits in order.
+  pub fn run(&mut self) -> Result<Vec<String>, String> {
+    let hidden_tips = self.hidden.clone();
+    let hidden = self.reachable(&hidden_tips)?;
+    let tips = self.tips.clone();
+    let mut commits = match self.sort {
+      Sort::Date => {
+        let visited = self.date_order(&tips, &hidden, self.max_count)?;
+        visited.into_iter().filter(|v| v.1).map(|v| v.0).collect()
+      }
+      Sort::Topo => self.topo_order(&tips, &hidden)?,
+    };
+    if let Some(max) = self.max_count {
+      commits.truncate(max);
+    }
+    self.shown = commits.iter().cloned().collect();
+    Ok(commits)
+  }
+
+  /// Returns the parents of a commit returned by [`RevWalk::run`], rewritten
+  /// to skip over commits that were not shown.
+  ///
+  /// Parents that were never reached (because they were hidden or the walk
+  /// stopped early) are left out, so the result only names shown commits.
+  pub fn parents(&self, hash: &str) -> Vec<String> {
+    let mut parents: Vec<String> = Vec::new();
+    let mut seen: HashSet<&String> = HashSet::new();
+    let mut stack: Vec<&String> = match self.edges.get(hash) {
+      Some(edges) => edges.iter().rev().collect(),
+      None => return parents,
+    };
+    while let Some(parent) = stack.pop() {
+      if !seen.insert(parent) {
+        continue;
+      }
+      if self.shown.contains(parent) {
+        if !parents.contains(parent) {
+          parents.push(parent.clone());
+        }
+      } else if let Some(edges) = self.edges.get(parent) {
+        stack.extend(edges.iter().rev());
+      }
+    }
+    parents
+  }
+
+  /// Lists the trees and blobs reachable from the given commits, along with
+  /// their paths, skipping objects reachable from the hidden commits.
+  ///
+  /// The root tree of each commit is listed with an empty path. Each object is
+  /// listed once, the first time it is encountered.
+  pub fn objects(&self, commits: &[String]) -> Result<Vec<(String, BString)>, String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut ignored = Vec::new();
+    for hidden in &self.hidden {
+      let tree = object::peel(&self.repo, hidden, Some("tree"))?;
+      walk_tree(&self.repo, &tree, b"", &mut seen, &mut ignored)?;
+    }
+
+    let mut objects = Vec::new();
+    for commit in commits {
+      self.cancel.check()?;
+      let tree = object::peel(&self.repo, commit, Some("tree"))?;
+      walk_tree(&self.repo, &tree, b"", &mut seen, &mut objects)?;
+    }
+    Ok(objects)
+  }
+
+  /// Resolves a revision to a commit hash.
+  fn resolve(&self, spec: &str) -> Result<String, String> {
+    let spec = if spec.is_empty() { "HEAD" } else { spec };
+    object::find_object(&self.repo, spec, Some("commit"), true)
+  }
+
+  /// Reads (or fetches from the cache) the parents and time of a commit.
+  ///
+  /// The parents are the grafted ones if the commit has been grafted, so a
+  /// shallow clone's history ends at the commits it was cut off at rather than
+  /// at parents that were never fetched.
+  fn node(&mut self, hash: &str) -> Result<&Node, String> {
+    if !self.nodes.contains_key(hash) {
+      self.cancel.check()?;
+      let object = object::read(&self.repo, hash, Some("commit"))?;
+      let commit = object.unbox::<Commit>()?;
+      let node = Node {
+        parents: self.grafts.parents(hash, commit.parents()).to_vec(),
+        time: commit.commit_time(),
+      };
+      self.nodes.insert(hash.to_owned(), node);
+    }
+    Ok(self.nodes.get(hash).unwrap())
+  }
+
+  /// Returns every commit reachable from the given tips (tips included).
+  fn reachable(&mut self, tips: &[String]) -> Result<HashSet<String>, String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut stack: Vec<String> = tips.to_vec();
+    while let Some(hash) = stack.pop() {
+      if seen.insert(hash.clone()) {
+        stack.extend(self.node(&hash)?.parents.iter().cloned());
+      }
+    }
+    Ok(seen)
+  }
+
+  /// Visits commits newest first, using a priority queue keyed on date.
+  ///
+  /// Returns every visited commit along with whether it should be shown (a
+  /// commit is hidden by history simplification when it doesn't touch any of
+  /// the paths being followed). The limit applies to the shown commits.
+  fn date_order(
+    &mut self,
+    tips: &[String],
+    hidden: &HashSet<String>,
+    limit: Option<usize>,
+  ) -> Result<Vec<(String, bool)>, String> {
+    let mut queue: BinaryHeap<(i64, Reverse<usize>, String)> = BinaryHeap::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut sequence = 0;
+    for tip in tips {
+      if !hidden.contains(tip) && seen.insert(tip.clone()) {
+        let time = self.node(tip)?.time;
+        queue.push((time, Reverse(sequence), tip.clone()));
+        sequence += 1;
+      }
+    }
+
+    let mut result = Vec::new();
+    let mut shown = 0;
+    while let Some((time, _, hash)) = queue.pop() {
+      if limit.is_some_and(|max| shown >= max) {
+        break;
+      }
+      let (show, parents) = self.simplify(&hash)?;
+      let show = show && self.until.is_none_or(|until| time <= until);
+      let too_old = self.since.is_some_and(|since| time < since);
+      let show = show && !too_old && self.pickaxe_matches(&hash)?;
+      for parent in parents.iter().filter(|_| !too_old) {
+        if !hidden.contains(parent) && seen.insert(parent.clone()) {
+          let time = self.node(parent)?.time;
+          queue.push((time, Reverse(sequence), parent.clone()));
+          sequence += 1;
+        }
+      }
+      self.edges.insert(hash.clone(), parents);
+      if show {
+        shown += 1;
+      }
+      result.push((hash, show));
+    }
+    Ok(result)
+  }
+
+  /// Visits commits so that children always come before their parents.
+  fn topo_order(
+    &mut self,
+    tips: &[String],
+    hidden: &HashSet<String>,
+  ) -> Result<Vec<String>, String> {
+    // Count how many visited children each visited commit has.
+    let visited = self.date_order(tips, hidden, None)?;
+    let shown: HashSet<&String> = visited.iter().filter(|v| v.1).map(|v| &v.0).collect();
+    let mut children: HashMap<&String, usize> = visited.iter().map(|v| (&v.0, 0)).collect();
+    for (hash, _) in &visited {
+      for parent in &self.edges[hash] {
+        if let Some(count) = children.get_mut(parent) {
+          *count += 1;
+        }
+      }
+    }
+
+    // Emit commits once all of their children have been emitted. A stack
+    // keeps following the same line of history for as long as possible.
+    let mut stack: Vec<&String> = visited
+      .iter()
+      .map(|v| &v.0)
+      .filter(|hash| children.get(hash) == Some(&0))
+      .rev()
+      .collect();
+    let mut result = Vec::new();
+    while let Some(hash) = stack.pop() {
+      for parent in &self.edges[hash] {
+        if let Some(count) = children.get_mut(parent) {
+          *count -= 1;
+          if *count == 0 {
+            stack.push(parent);
+          }
+        }
+      }
+      if shown.contains(hash) {
+        result.push(hash.clone());
+      }
+    }
+    Ok(result)
+  }
+
+  /// Decides whether a commit is shown and which parents the walk continues
+  /// through.
+  ///
+  /// Without paths every commit is shown and every parent is followed. With
+  /// paths, a commit that is TREESAME to one of its parents (the paths are
+  /// identical in both) is not shown and only that parent is followed, which
+  /// prunes side branches that never touched the paths. A root commit is shown
+  /// only if it introduces one of the paths.
+  fn simplify(&mut self, hash: &str) -> Result<(bool, Vec<String>), String> {
+    let parents = self.node(hash)?.parents.clone();
+    if self.paths.is_empty() {
+      return Ok((true, parents));
+    }
+
+    let tree = object::peel(&self.repo, hash, Some("tree"))?;
+    if parents.is_empty() {
+      for path in &self.paths {
+        if tree::lookup(&self.repo, &tree, path)?.is_some() {
+          return Ok((true, parents));
+        }
+      }
+      return Ok((false, parents));
+    }
+
+    for parent in &parents {
+      let parent_tree = object::peel(&self.repo, parent, Some("tree"))?;
+      if self.treesame(&tree, &parent_tree)? {
+        return Ok((false, vec![parent.clone()]));
+      }
+    }
+
+    if self.follow {
+      let parent_tree = object::peel(&self.repo, &parents[0], Some("tree"))?;
+      if let Some(source) = self.find_rename(&tree, &parent_tree)? {
+        self.paths = vec![source];
+      }
+    }
+    Ok((true, parents))
+  }
+
+  /// Checks a commit against the pickaxe, if there is one.
+  ///
+  /// Like git, merges are never matched since they don't have a single diff
+  /// to search through, and root commits are compared to the empty tree.
+  fn pickaxe_matches(&mut self, hash: &str) -> Result<bool, String> {
+    if self.pickaxe.is_none() {
+      return Ok(true);
+    }
+    let parents = self.node(hash)?.parents.clone();
+    if parents.len() > 1 {
+      return Ok(false);
+    }
+    let parent_tree = match parents.first() {
+      Some(parent) => Some(object::peel(&self.repo, parent, Some("tree"))?),
+      None => None,
+    };
+    let tree = object::peel(&self.repo, hash, Some("tree"))?;
+    let pickaxe = self.pickaxe.as_ref().unwrap();
+    pickaxe.matches(&self.repo, parent_tree.as_deref(), Some(&tree), &self.paths)
+  }
+
+  /// Checks whether every followed path is identical in both trees.
+  fn treesame(&self, tree: &str, parent_tree: &str) -> Result<bool, String> {
+    for path in &self.paths {
+      let ours = tree::lookup(&self.repo, tree, path)?;
+      let theirs = tree::lookup(&self.repo, parent_tree, path)?;
+      if ours.map(|entry| entry.1) != theirs.map(|entry| entry.1) {
+        return Ok(false);
+      }
+    }
+    Ok(true)
+  }
+
+  /// Finds the name the followed file had in the parent tree, if the commit
+  /// created it by renaming another file.
+  fn find_rename(&self, tree: &str, parent_tree: &str) -> Result<Option<BString>, String> {
+    let path = &self.paths[0];
+    if tree::lookup(&self.repo, parent_tree, path)?.is_some() {
+      return Ok(None);
+    }
+    let changes = diff::diff_trees(&self.repo, Some(parent_tree), Some(tree))?;
+    let changes = diff::detect_renames(&self.repo, changes)?;
+    Ok(
+      changes
+        .into_iter()
+        .find(|change| change.path == *path)
+        .and_then(|change| change.renamed_from),
+    )
+  }
+}
+
+/// Recursively lists the objects in a tree that have not been seen before.
+fn walk_tree(
+  repo: &Repo,
+  hash: &str,
+  path: &[u8],
+  seen: &mut HashSet<String>,
+  objects: &mut Vec<(String, BString)>,
+) -> Result<(), String> {
+  if !seen.insert(hash.to_owned()) {
+    return Ok(());
+  }
+  objects.push((hash.to_owned(), path.into()));
+
+  let object = object::read(repo, hash, Some("tree"))?;
+  let tree = object.unbox::<Tree>()?;
+  for entry in tree.entries() {
+    let entry_path = match path {
+      b"" => entry.path.clone(),
+      _ => BString::from([path, b"/", &entry.path].concat()),
+    };
+    match entry.mode {
+      Mode::Directory => walk_tree(repo, &entry.hash, &entry_path, seen, objects)?,
+      // submodule commits live in another repository
+      Mode::Gitlink => (),
+      _ => {
+        if seen.insert(entry.hash.clone()) {
+          objects.push((entry.hash.clone(), entry_path));
+        }
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Finds the best common ancestors of two commits.
+///
+/// A common ancestor is "best" if it is not an ancestor of any other common
+/// ancestor. Criss-cross histories can have more than one merge base.
+pub fn merge_bases(repo: &Repo, one: &str, two: &str) -> Result<Vec<String>, String> {
+  let mut walk = RevWalk::new(repo);
+  let left = walk.reachable(&[one.to_owned()])?;
+  let right = walk.reachable(&[two.to_owned()])?;
+  let common: HashSet<&String> = left.intersection(&right).collect();
+
+  // Every common ancestor with a child that is also a common ancestor is an
+  // ancestor of another common ancestor, so it can't be a best one.
+  let mut has_child: HashSet<&String> = HashSet::new();
+  for hash in &common {
+    for parent in &walk.node(hash)?.parents {
+      if let Some(parent) = common.get(parent) {
+        has_child.insert(parent);
+      }
+    }
+  }
+
+  let mut bases: Vec<String> = common
+    .into_iter()
+    .filter(|hash| !has_child.contains(hash))
+    .cloned()
+    .collect();
+  bases.sort();
+  Ok(bases)
+}
+
+/// How many commits [`graph_ahead_behind`] looks at after it could stop, as
+/// git does, to make up for commits with skewed dates.
+const SLOP: usize = 5;
+
+/// Counts the commits reachable from `one` but not `two` (ahead) and from
+/// `two` but not `one` (behind).
+///
+/// Rather than listing everything reachable from both sides, the walk goes
+/// newest first, marking each commit with the sides it is reachable from,
+/// and stops as soon as every commit left in the queue is reachable from
+/// both: all of their ancestors are too, so none of them can be counted.
+/// Only the commits since the merge bases are read.
+///
+/// Like git without a commit-graph, the walk trusts commit dates to put
+/// children before their parents. It looks at a few more commits before it
+/// stops to allow for some clock skew, but heavily skewed dates can still
+/// make the counts approximate.
+pub fn graph_ahead_behind(repo: &Repo, one: &str, two: &str) -> Result<(usize, usize), String> {
+  const ONE: u8 = 1;
+  const TWO: u8 = 2;
+  let mut walk = RevWalk::new(repo);
+  let mut flags: HashMap<String, u8> = HashMap::new();
+  let mut queue: BinaryHeap<(i64, String)> = BinaryHeap::new();
+  for (tip, flag) in [(one, ONE), (two, TWO)] {
+    *flags.entry(tip.to_owned()).or_insert(0) |= flag;
+    queue.push((walk.node(tip)?.time, tip.to_owned()));
+  }
+
+  // a commit is queued again whenever it is reached from a new side, so
+  // that its parents are marked with that side too. The walk goes on for a
+  // few commits after the queue is all common, in case one was dated
+  // before its parent.
+  let mut slop = SLOP;
+  let mut last = i64::MAX;
+  while let Some((time, hash)) = queue.pop() {
+    let flag = flags[&hash];
+    if flag != ONE | TWO {
+      last = time;
+    }
+    for parent in walk.node(&hash)?.parents.clone() {
+      let old = flags.get(&parent).copied().unwrap_or(0);
+      if old | flag != old {
+        flags.insert(parent.clone(), old | flag);
+        queue.push((walk.node(&parent)?.time, parent));
+      }
+    }
+    slop = match queue.peek() {
+      None => break,
+      Some((next, _)) if *next >= last => SLOP,
+      Some(_) if queue.iter().any(|(_, hash)| flags[hash] != ONE | TWO) => SLOP,
+      Some(_) => slop - 1,
+    };
+    if slop == 0 {
+      break;
+    }
+  }
+
+  let count = |side: u8| flags.values().filter(|&&flag| flag == side).count();
+  Ok((count(ONE), count(TWO)))
+}
+
+/// How much older than a commit another commit may be dated and still be
+/// taken to be able to reach it, to allow for some clock skew: a day, as
+/// git gives `name-rev`.
+pub const CUTOFF_SLOP: i64 = 24 * 60 * 60;
+
+/// Checks, for each of the tips, whether the commit is reachable from it
+/// (a commit reaches itself), as `branch --contains` asks.
+///
+/// What is learnt about a commit while checking one tip is kept for the
+/// rest, so tips that share most of their history (as branches do) are
+/// cheap after the first. The walk doesn't go into commits dated more than
+/// [`CUTOFF_SLOP`] before the commit, which can't have it as an ancestor
+/// unless their dates are skewed.
+pub fn contains(repo: &Repo, commit: &str, tips: &[String]) -> Result<Vec<bool>, String> {
+  let mut walk = RevWalk::new(repo);
+  let cutoff = walk.node(commit)?.time - CUTOFF_SLOP;
+  let mut known: HashMap<String, bool> = HashMap::new();
+  known.insert(commit.to_owned(), true);
+
+  let mut result = Vec::new();
+  for tip in tips {
+    // each commit is pushed once to have its parents looked at, and again
+    // once they have all been decided
+    let mut stack: Vec<(String, bool)> = vec![(tip.clone(), false)];
+    while let Some((hash, expanded)) = stack.pop() {
+      if known.contains_key(&hash) {
+        continue;
+      }
+      let node = walk.node(&hash)?;
+      if node.time < cutoff {
+        known.insert(hash, false);
+      } else if expanded {
+        let found = node.parents.iter().any(|parent| known[parent]);
+        known.insert(hash, found);
+      } else {
+        let parents = node.parents.clone();
+        stack.push((hash, true));
+        for parent in parents {
+          if !known.contains_key(&parent) {
+            stack.push((parent, false));
+          }
+        }
+      }
+    }
+    result.push(known[tip]);
+  }
+  Ok(result)
+}
@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use bstr::BString;
+
+use crate::identity::Signature;
+use crate::object::{
+  self,
+  commit::{Commit, CommitBuilder},
+  mode::Mode,
+  refs,
+  serializable::Unbox,
+  tree,
+};
+use crate::repo::Repo;
+use crate::rev::walk::{RevWalk, Sort};
+
+/// A commit as it is about to be rewritten, for a [`rewrite`] callback to
+/// change.
+///
+/// Everything starts out as it is in the original commit, except for the
+/// parents, which already name the rewritten commits. A commit in another
+/// encoding is converted to UTF-8, and is written in UTF-8 if it changes.
+pub struct Edit {
+  /// The hash of the original commit.
+  pub original: String,
+
+  /// The files of the commit's tree, keyed by full path, as returned by
+  /// [`tree::flatten`]. Removing one takes it out of the commit.
+  pub files: BTreeMap<BString, (Mode, String)>,
+
+  pub parents: Vec<String>,
+  pub author: Signature,
+  pub committer: Signature,
+  pub message: String,
+
+  /// Leaves the commit out altogether. Its children take its parents in its
+  /// place.
+  pub skip: bool,
+}
+
+/// Rewrites the history of some refs (given by their full names, eg.
+/// `refs/heads/master`), oldest commit first, and points the
+/// refs at the rewritten commits. This is how a secret or a large file is
+/// purged from every commit it was ever in, or an address is fixed
+/// throughout.
+///
+/// `callback` is called once for every commit reachable from the refs, after
+/// it has been called for all of its parents, and changes the commit as it
+/// likes. A commit that comes out the same as it went in keeps its hash, as
+/// does everything before it. Signatures (`gpgsig`) are dropped from the
+/// commits that change, as they no longer hold; other headers are kept.
+///
+/// Refs that point at something other than a commit, like an annotated tag,
+/// are left as they are. A ref whose commits are all skipped is deleted. The
+/// index and working tree are not touched, even if a rewritten branch is
+/// checked out.
+///
+/// Returns the hash each original commit was rewritten to. Skipped commits
+/// are left out of it.
+///
+/// # Example
+/// ```ignore
+/// // purge passwords.txt from master
+/// let map = rewrite(&repo, &["refs/heads/master".to_string()], |edit| {
+///   edit.files.remove(b"passwords.txt".as_slice());
+///   Ok(())
+/// })?;
+/// ```
+pub fn rewrite<F>(
+  repo: &Repo,
+  names: &[String],
+  mut callback: F,
+) -> Result<HashMap<String, String>, String>
+where
+  F: FnMut(&mut Edit) -> Result<(), String>,
+{
+  let mut tips: Vec<(&String, String)> = Vec::new();
+  let mut walk = RevWalk::new(repo);
+  walk.sort(Sort::Topo);
+  for name in names {
+    let hash =
+      refs::resolve(repo, Path::new(name)).map_err(|_| format!("not a valid ref: {}", name))?;
+    if object::read(repo, &hash, None)?.format() == "commit" {
+      walk.push(&hash);
+      tips.push((name, hash));
+    }
+  }
+  let mut commits = walk.run()?;
+  commits.reverse();
+
+  // what each original commit became: one commit, or the parents of a
+  // skipped one, which may be none
+  let mut rewritten: HashMap<String, Vec<String>> = HashMap::new();
+  let mut map = HashMap::new();
+  for hash in commits {
+    let object = object::read(repo, &hash, Some("commit"))?;
+    let original = object.unbox::<Commit>()?;
+    let converted = original.to_utf8();
+    let commit = converted.as_ref().unwrap_or(original);
+    let signature = |role: &str| {
+      commit
+        .get(role)
+        .and_then(|line| Signature::parse(line))
+        .ok_or(format!("commit {} has a malformed {} line", hash, role))
+    };
+    // parents that were not walked, like those a shallow clone is cut off
+    // at, are kept as they are
+    let mut parents: Vec<String> = Vec::new();
+    for parent in commit.parents() {
+      let new = rewritten
+        .get(parent)
+        .cloned()
+        .unwrap_or(vec![parent.clone()]);
+      for parent in new {
+        if !parents.contains(&parent) {
+          parents.push(parent);
+        }
+      }
+    }
+    let files = tree::flatten(repo, commit.tree())?;
+    let mut edit = Edit {
+      original: hash.clone(),
+      files: files.clone(),
+      parents,
+      author: signature("author")?,
+      committer: signature("committer")?,
+      message: commit.message().to_owned(),
+      skip: false,
+    };
+    callback(&mut edit)?;
+
+    if edit.skip {
+      rewritten.insert(hash, edit.parents);
+      continue;
+    }
+    let tree = if edit.files == files {
+      commit.tree().to_owned()
+    } else {
+      tree::build(repo, &edit.files)?
+    };
+    let new = write(repo, commit, &tree, &edit)?;
+    rewritten.insert(hash.clone(), vec![new.clone()]);
+    map.insert(hash, new);
+  }
+
+  for (name, hash) in tips {
+    match rewritten[&hash].first() {
+      Some(new) if *new == hash => (),
+      Some(new) => refs::update(repo, name, new)?,
+      None => refs::delete(repo, name)?,
+    }
+  }
+  Ok(map)
+}
+
+/// Writes the rewritten commit, unless nothing about it changed, and returns
+/// its hash.
+fn write(repo: &Repo, commit: &Commit, tree: &str, edit: &Edit) -> Result<String, String> {
+  let (author, committer) = (edit.author.to_string(), edit.committer.to_string());
+  let unchanged = tree == commit.tree()
+    && edit.parents == commit.parents()
+    && commit.get("author") == Some(&author)
+    && commit.get("committer") == Some(&committer)
+    && edit.message == commit.message();
+  if unchanged {
+    return Ok(edit.original.clone());
+  }
+
+  let mut builder = CommitBuilder::new()
+    .tree(tree)
+    .parents(&edit.parents)
+    .author(edit.author.clone())
+    .committer(edit.committer.clone());
+  for (key, values) in &commit.map {
+    if matches!(
+      key.as_str(),
+      "tree" | "parent" | "author" | "committer" | "gpgsig" | ""
+    ) {
+      continue;
+    }
+    for value in values {
+      builder = builder.header(key, value);
+    }
+  }
+  // the tree and parents were just written, so need not be looked for
+  let commit = builder.message(&edit.message).build()?;
+  object::write(repo, &commit, false)
+}
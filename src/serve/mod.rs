@@ -0,0 +1,533 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::thread;
+
+use bstr::ByteSlice;
+
+use crate::diff::{self, blob_data, patch};
+use crate::identity::{date, Signature};
+use crate::mail;
+use crate::object::{self, commit::Commit, mode::Mode, refs, serializable::Unbox, tree};
+use crate::repo::Repo;
+use crate::rev::{self, walk::RevWalk};
+
+/// The most commits listed on one page of a log.
+const PAGE: usize = 50;
+
+/// The most bytes of a request's headers that are read.
+const MAX_HEADERS: usize = 16 * 1024;
+
+/// A read-only view of a repository over HTTP, for browsing it from another
+/// machine without setting up anything else.
+///
+/// The pages are plain HTML:
+///
+/// - `/` lists the branches, tags and latest commits.
+/// - `/log/<rev>` lists the commits reachable from a revision, a page at a
+///   time.
+/// - `/commit/<rev>` shows a commit with its stat and diff.
+/// - `/tree/<rev>/<path>` lists a directory at a commit.
+/// - `/blob/<rev>/<path>` shows a file, which `/raw/<rev>/<path>` serves as
+///   it is.
+///
+/// Links always name commits by hash, but a revision in a typed URL may be
+/// anything `rev-parse` takes, including branch names with slashes in them.
+pub struct Server {
+  repo: Repo,
+  listener: TcpListener,
+}
+
+/// What is sent back for a request.
+pub struct Response {
+  pub status: u16,
+  pub content_type: &'static str,
+  pub body: Vec<u8>,
+}
+
+impl Server {
+  /// Starts listening on `addr` (eg. `0.0.0.0:1234`).
+  pub fn bind(repo: &Repo, addr: &str) -> Result<Server, String> {
+    let listener =
+      TcpListener::bind(addr).map_err(|e| format!("unable to listen on {} ({})", addr, e))?;
+    Ok(Server {
+      repo: repo.clone(),
+      listener,
+    })
+  }
+
+  /// The address being listened on, with the port picked if `0` was asked
+  /// for.
+  pub fn local_addr(&self) -> Result<SocketAddr, String> {
+    self
+      .listener
+      .local_addr()
+      .map_err(|e| format!("unable to get the address listened on ({})", e))
+  }
+
+  /// Answers requests until the process is stopped, each on a thread of its
+  /// own. A connection that fails is dropped without stopping the others.
+  pub fn run(&self) -> Result<(), String> {
+    for stream in self.listener.incoming() {
+      let stream = match stream {
+        Ok(stream) => stream,
+        Err(_) => continue,
+      };
+      let repo = self.repo.clone();
+      thread::spawn(move || {
+        let _ = handle(&repo, stream);
+      });
+    }
+    Ok(())
+  }
+}
+
+/// Reads a request from a connection and writes the response to it.
+fn handle(repo: &Repo, mut stream: TcpStream) -> std::io::Result<()> {
+  let mut reader = BufReader::new(stream.try_clone()?);
+  let mut request_line = String::new();
+  reader.read_line(&mut request_line)?;
+  // the headers don't matter, but are read so the client sees them taken
+  let mut read = request_line.len();
+  loop {
+    let mut header = String::new();
+    let len = reader.read_line(&mut header)?;
+    read += len;
+    if len == 0 || header.trim_end().is_empty() || read > MAX_HEADERS {
+      break;
+    }
+  }
+
+  let mut parts = request_line.split_whitespace();
+  let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+  let response = respond(repo, method, target);
+  let reason = match response.status {
+    200 => "OK",
+    400 => "Bad Request",
+    404 => "Not Found",
+    405 => "Method Not Allowed",
+    _ => "Internal Server Error",
+  };
+  write!(
+    stream,
+    "HTTP/1.0 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+    response.status,
+    reason,
+    response.content_type,
+    response.body.len()
+  )?;
+  if method != "HEAD" {
+    stream.write_all(&response.body)?;
+  }
+  stream.flush()
+}
+
+/// Answers a request for `target` (the path and query of the URL). Only
+/// `GET` and `HEAD` are allowed, as nothing can be changed.
+pub fn respond(repo: &Repo, method: &str, target: &str) -> Response {
+  if method != "GET" && method != "HEAD" {
+    return error(405, "Only GET and HEAD requests are supported.");
+  }
+  let path = target.split(['?', '#']).next().unwrap_or("");
+  let path = match percent_decode(path) {
+    Some(path) => path,
+    None => return error(400, "The URL is not valid."),
+  };
+  let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+  let result = match segments.split_first() {
+    None => summary(repo),
+    Some((&"log", rest)) => with_rev(repo, rest, |repo, hash, _| log(repo, hash)),
+    Some((&"commit", rest)) => with_rev(repo, rest, |repo, hash, _| commit(repo, hash)),
+    Some((&"tree", rest)) => with_rev(repo, rest, tree),
+    Some((&"blob", rest)) => with_rev(repo, rest, |repo, hash, path| blob(repo, hash, path, false)),
+    Some((&"raw", rest)) => with_rev(repo, rest, |repo, hash, path| blob(repo, hash, path, true)),
+    Some(_) => Ok(None),
+  };
+  match result {
+    Ok(Some(response)) => response,
+    Ok(None) => error(404, "Nothing was found at this URL."),
+    Err(e) => error(500, &e),
+  }
+}
+
+/// Finds the commit named at the start of `segments`, trying more and more
+/// of them as the revision (as branch names may have slashes), and calls
+/// `f` with it and the path in the rest. No HEAD means HEAD.
+fn with_rev(
+  repo: &Repo,
+  segments: &[&str],
+  f: impl Fn(&Repo, &str, &str) -> Result<Option<Response>, String>,
+) -> Result<Option<Response>, String> {
+  if segments.is_empty() {
+    return match rev::parse(repo, "HEAD") {
+      Ok(hash) => f(repo, &hash, ""),
+      Err(_) => Ok(None),
+    };
+  }
+  for split in 1..=segments.len() {
+    let name = segments[..split].join("/");
+    if let Ok(hash) = object::peel(repo, &name, Some("commit")).or_else(|_| {
+      rev::parse(repo, &name).and_then(|hash| object::peel(repo, &hash, Some("commit")))
+    }) {
+      return f(repo, &hash, &segments[split..].join("/"));
+    }
+  }
+  Ok(None)
+}
+
+/// The front page: the branches and tags, and the latest commits on HEAD.
+fn summary(repo: &Repo) -> Result<Option<Response>, String> {
+  let mut body = String::new();
+  for (title, prefix) in [("Branches", "refs/heads"), ("Tags", "refs/tags")] {
+    let refs = refs::collect(repo, Some(Path::new(prefix)));
+    if refs.is_empty() {
+      continue;
+    }
+    body.push_str(&format!("<h2>{}</h2>\n<table>\n", title));
+    for (name, hash) in refs {
+      let hash = object::peel(repo, &hash, Some("commit")).unwrap_or(hash);
+      body.push_str(&format!(
+        "<tr><td><a href=\"/log/{}\">{}</a></td><td><a href=\"/tree/{}\">tree</a></td></tr>\n",
+        encode(&hash),
+        escape(&refs::shorten(repo, &name)),
+        encode(&hash)
+      ));
+    }
+    body.push_str("</table>\n");
+  }
+  if let Ok(head) = rev::parse(repo, "HEAD") {
+    body.push_str("<h2>Latest commits</h2>\n");
+    body.push_str(&commit_list(repo, &head, 10)?.0);
+  }
+  Ok(Some(page(repo, "Summary", &body)))
+}
+
+/// A page of the commits reachable from `hash`.
+fn log(repo: &Repo, hash: &str) -> Result<Option<Response>, String> {
+  let (mut body, next) = commit_list(repo, hash, PAGE)?;
+  if let Some(next) = next {
+    body.push_str(&format!(
+      "<p><a href=\"/log/{}\">Older commits</a></p>\n",
+      next
+    ));
+  }
+  Ok(Some(page(repo, &format!("Log of {}", &hash[..7]), &body)))
+}
+
+/// A table of up to `count` commits from `hash`, with the commit that would
+/// come next, if there are more.
+fn commit_list(repo: &Repo, hash: &str, count: usize) -> Result<(String, Option<String>), String> {
+  let mut walk = RevWalk::new(repo);
+  walk.push(hash);
+  walk.max_count(Some(count + 1));
+  let mut commits = walk.run()?;
+  let next = match commits.len() > count {
+    true => commits.pop(),
+    false => None,
+  };
+  let mut out = String::from("<table>\n");
+  for hash in &commits {
+    let object = object::read(repo, hash, Some("commit"))?;
+    let commit = object.unbox::<Commit>()?;
+    let (subject, _) = mail::split_message(commit.message());
+    let author = commit.get("author").and_then(|line| Signature::parse(line));
+    let (name, when) = match &author {
+      Some(author) => (
+        author.name.clone(),
+        date::format(author.time, &author.timezone, "short").unwrap_or_default(),
+      ),
+      None => (String::new(), String::new()),
+    };
+    out.push_str(&format!(
+      "<tr><td>{}</td><td><a href=\"/commit/{}\">{}</a></td><td>{}</td><td><code>{}</code></td></tr>\n",
+      when,
+      hash,
+      escape(&subject),
+      escape(&name),
+      &hash[..7]
+    ));
+  }
+  out.push_str("</table>\n");
+  Ok((out, next))
+}
+
+/// A commit: who made it and when, its message, and the diff it makes to its
+/// first parent.
+fn commit(repo: &Repo, hash: &str) -> Result<Option<Response>, String> {
+  let object = object::read(repo, hash, Some("commit"))?;
+  let commit = object.unbox::<Commit>()?;
+  let mut body = String::from("<table>\n");
+  body.push_str(&format!(
+    "<tr><th>commit</th><td><code>{}</code></td></tr>\n",
+    hash
+  ));
+  for role in ["author", "committer"] {
+    if let Some(person) = commit.get(role).and_then(|line| Signature::parse(line)) {
+      body.push_str(&format!(
+        "<tr><th>{}</th><td>{}<br>{}</td></tr>\n",
+        role,
+        escape(&person.person()),
+        escape(&person.date())
+      ));
+    }
+  }
+  for parent in commit.parents() {
+    body.push_str(&format!(
+      "<tr><th>parent</th><td><a href=\"/commit/{}\"><code>{}</code></a></td></tr>\n",
+      parent, parent
+    ));
+  }
+  body.push_str(&format!(
+    "<tr><th>tree</th><td><a href=\"/tree/{}\"><code>{}</code></a></td></tr>\n</table>\n",
+    hash,
+    commit.tree()
+  ));
+  body.push_str(&format!("<pre>{}</pre>\n", escape(commit.message())));
+
+  // merges have no single diff to show
+  if commit.parents().len() <= 1 {
+    let parent_tree = match commit.parents().first() {
+      Some(parent) => Some(object::peel(repo, parent, Some("tree"))?),
+      None => None,
+    };
+    let changes = diff::detect_renames(
+      repo,
+      diff::diff_trees(repo, parent_tree.as_deref(), Some(commit.tree()))?,
+    )?;
+    body.push_str(&format!(
+      "<pre>{}</pre>\n<pre>",
+      escape(&patch::stat(repo, &changes, 80)?)
+    ));
+    for change in &changes {
+      for line in patch::file_patch(repo, change)?.lines() {
+        let class = match line.as_bytes().first() {
+          _ if line.starts_with("diff --git ") => "file",
+          _ if line.starts_with("+++ ") || line.starts_with("--- ") => "meta",
+          Some(b'+') => "add",
+          Some(b'-') => "del",
+          Some(b'@') => "hunk",
+          Some(b' ') | Some(b'\\') => "",
+          _ => "meta",
+        };
+        body.push_str(&format!(
+          "<span class=\"{}\">{}</span>\n",
+          class,
+          escape(line)
+        ));
+      }
+    }
+    body.push_str("</pre>\n");
+  }
+  let (subject, _) = mail::split_message(commit.message());
+  Ok(Some(page(repo, &subject, &body)))
+}
+
+/// A directory at a commit, with links to what is in it.
+fn tree(repo: &Repo, hash: &str, path: &str) -> Result<Option<Response>, String> {
+  let root = object::peel(repo, hash, Some("tree"))?;
+  let tree = match tree::lookup(repo, &root, path)? {
+    Some((Mode::Directory, tree)) => tree,
+    _ => return Ok(None),
+  };
+  let mut body = breadcrumbs(hash, path);
+  body.push_str("<table>\n");
+  if !path.is_empty() {
+    let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+    body.push_str(&format!(
+      "<tr><td></td><td><a href=\"/tree/{}/{}\">..</a></td></tr>\n",
+      hash,
+      encode(parent)
+    ));
+  }
+  let join = |name: &str| match path {
+    "" => name.to_string(),
+    _ => format!("{}/{}", path, name),
+  };
+  for (name, (mode, entry)) in tree::list(repo, &tree)? {
+    let name = name.to_str_lossy();
+    let link = match mode {
+      Mode::Directory => format!(
+        "<a href=\"/tree/{}/{}\">{}/</a>",
+        hash,
+        encode(&join(&name)),
+        escape(&name)
+      ),
+      // a submodule's commits are in another repository
+      Mode::Gitlink => format!("{} @ <code>{}</code>", escape(&name), &entry[..7]),
+      _ => format!(
+        "<a href=\"/blob/{}/{}\">{}</a>",
+        hash,
+        encode(&join(&name)),
+        escape(&name)
+      ),
+    };
+    body.push_str(&format!(
+      "<tr><td><code>{}</code></td><td>{}</td></tr>\n",
+      mode, link
+    ));
+  }
+  body.push_str("</table>\n");
+  let title = match path {
+    "" => "/".to_string(),
+    _ => path.to_string(),
+  };
+  Ok(Some(page(repo, &title, &body)))
+}
+
+/// A file at a commit, shown in a page or, with `raw`, served as it is.
+fn blob(repo: &Repo, hash: &str, path: &str, raw: bool) -> Result<Option<Response>, String> {
+  let root = object::peel(repo, hash, Some("tree"))?;
+  let entry = match tree::lookup(repo, &root, path)? {
+    Some(entry) if !matches!(entry.0, Mode::Directory | Mode::Gitlink) => entry,
+    _ => return Ok(None),
+  };
+  let data = blob_data(repo, Some(&entry))?;
+  let binary = patch::is_binary(&data);
+  if raw {
+    return Ok(Some(Response {
+      status: 200,
+      content_type: match binary {
+        true => "application/octet-stream",
+        false => "text/plain; charset=utf-8",
+      },
+      body: data,
+    }));
+  }
+  let mut body = breadcrumbs(hash, path);
+  body.push_str(&format!(
+    "<p><a href=\"/raw/{}/{}\">raw</a></p>\n",
+    hash,
+    encode(path)
+  ));
+  match binary {
+    true => body.push_str(&format!("<p>Binary file, {} bytes.</p>\n", data.len())),
+    false => body.push_str(&format!("<pre>{}</pre>\n", escape(&data.to_str_lossy()))),
+  }
+  Ok(Some(page(repo, path, &body)))
+}
+
+/// Links to each directory above a path, from the root of the commit.
+fn breadcrumbs(hash: &str, path: &str) -> String {
+  let mut out = format!(
+    "<p><a href=\"/commit/{}\"><code>{}</code></a>: <a href=\"/tree/{}\">/</a>",
+    hash,
+    &hash[..7],
+    hash
+  );
+  let mut so_far = String::new();
+  let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+  for (i, part) in parts.iter().enumerate() {
+    if !so_far.is_empty() {
+      so_far.push('/');
+      out.push('/');
+    }
+    so_far.push_str(part);
+    match i + 1 == parts.len() {
+      true => out.push_str(&escape(part)),
+      false => out.push_str(&format!(
+        "<a href=\"/tree/{}/{}\">{}</a>",
+        hash,
+        encode(&so_far),
+        escape(part)
+      )),
+    }
+  }
+  out.push_str("</p>\n");
+  out
+}
+
+/// Wraps the body of a page in the HTML around it, headed by the name of
+/// the repository.
+fn page(repo: &Repo, title: &str, body: &str) -> Response {
+  let dir = match repo.bare {
+    true => &repo.git_dir,
+    false => &repo.work_tree,
+  };
+  let name = dir
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_else(|| dir.display().to_string());
+  let html = format!(
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{} - {}</title>\n\
+     <style>{}</style>\n</head>\n<body>\n\
+     <nav><a href=\"/\"><b>{}</b></a> <a href=\"/log\">log</a> <a href=\"/tree\">tree</a></nav>\n\
+     <h1>{}</h1>\n{}</body>\n</html>\n",
+    escape(title),
+    escape(&name),
+    STYLE,
+    escape(&name),
+    escape(title),
+    body
+  );
+  Response {
+    status: 200,
+    content_type: "text/html; charset=utf-8",
+    body: html.into_bytes(),
+  }
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:1em 2em}\
+  td,th{padding:0 1em 0 0;text-align:left;vertical-align:top}\
+  pre{background:#f6f8fa;padding:.5em;overflow:auto}\
+  .add{color:#22863a}.del{color:#b31d28}.hunk{color:#6f42c1}\
+  .file{font-weight:bold}.meta{color:#586069}";
+
+/// A plain text error page.
+fn error(status: u16, message: &str) -> Response {
+  Response {
+    status,
+    content_type: "text/plain; charset=utf-8",
+    body: format!("{}\n", message).into_bytes(),
+  }
+}
+
+/// Escapes text for HTML.
+fn escape(text: &str) -> String {
+  let mut out = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => out.push_str("&amp;"),
+      '<' => out.push_str("&lt;"),
+      '>' => out.push_str("&gt;"),
+      '"' => out.push_str("&quot;"),
+      c => out.push(c),
+    }
+  }
+  out
+}
+
+/// Escapes a path for a URL, leaving its slashes as they are.
+fn encode(path: &str) -> String {
+  let mut out = String::new();
+  for b in path.bytes() {
+    match b {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+        out.push(b as char)
+      }
+      b => out.push_str(&format!("%{:02X}", b)),
+    }
+  }
+  out
+}
+
+/// Undoes the `%XX` escapes in a URL path, or returns `None` if they aren't
+/// valid or don't make UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+  let bytes = path.as_bytes();
+  let mut out = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'%' => {
+        let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+        out.push(u8::from_str_radix(hex, 16).ok()?);
+        i += 3;
+      }
+      b => {
+        out.push(b);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8(out).ok()
+}
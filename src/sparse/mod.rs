@@ -0,0 +1,241 @@
+use std::collections::BTreeSet;
+use std::fs;
+
+use bstr::{BString, ByteSlice};
+
+use crate::index::{self, Index, IndexEntry, SKIP_WORKTREE};
+use crate::object::mode::Mode;
+use crate::repo::Repo;
+use crate::worktree;
+
+/// The directories of a cone mode sparse checkout, which limit the tracked
+/// files that are in the working tree.
+///
+/// The files at the top of the working tree are always there. Everything
+/// inside a chosen directory is there too, as are the files directly inside
+/// the directories leading to one. Everything else is left out, marked
+/// skip-worktree in the index.
+///
+/// The directories are kept in `.git/info/sparse-checkout` as patterns, one
+/// per line, in the shape git expects of cone mode: the top-level files, each
+/// leading directory with its subdirectories left out, and each chosen
+/// directory.
+///
+/// ### Example
+/// ```text
+/// /*
+/// !/*/
+/// /src/
+/// !/src/*/
+/// /src/lib/
+/// ```
+pub struct Sparse {
+  /// The chosen directories, everything inside which is included.
+  recursive: BTreeSet<BString>,
+
+  /// The directories leading to the chosen ones, whose files are included.
+  parents: BTreeSet<BString>,
+}
+
+impl Sparse {
+  /// Makes a sparse checkout of a set of directories, given relative to the
+  /// top of the working tree.
+  pub fn new<'a>(dirs: impl IntoIterator<Item = &'a [u8]>) -> Self {
+    let mut recursive = BTreeSet::new();
+    for dir in dirs {
+      let dir = dir
+        .trim_start_with(|c| c == '/')
+        .trim_end_with(|c| c == '/');
+      if !dir.is_empty() {
+        recursive.insert(BString::from(dir));
+      }
+    }
+    // a directory inside a chosen one adds nothing
+    let nested: Vec<BString> = recursive
+      .iter()
+      .filter(|dir| ancestors(dir).any(|parent| recursive.contains(parent)))
+      .cloned()
+      .collect();
+    for dir in nested {
+      recursive.remove(&dir);
+    }
+    let parents = recursive
+      .iter()
+      .flat_map(|dir| ancestors(dir).map(BString::from))
+      .collect();
+    Self { recursive, parents }
+  }
+
+  /// Reads the sparse checkout of a repository, if `core.sparseCheckout` is
+  /// on. Patterns that are not in the shape of cone mode are not
+  /// understood, and the sparse checkout is ignored with a warning.
+  pub fn read(repo: &Repo) -> Option<Self> {
+    if !config_bool(repo, "sparseCheckout") {
+      return None;
+    }
+    let data = fs::read(repo.git_dir.join("info/sparse-checkout")).unwrap_or_default();
+    let sparse = Self::parse(&data);
+    if sparse.is_none() {
+      eprintln!("warning: unrecognized pattern in sparse-checkout file");
+      eprintln!("warning: disabling cone pattern matching");
+    }
+    sparse
+  }
+
+  /// Parses the patterns of a cone mode sparse checkout.
+  fn parse(data: &[u8]) -> Option<Self> {
+    let mut positive = BTreeSet::new();
+    let mut parents = BTreeSet::new();
+    let mut lines = data
+      .lines()
+      .map(|line| line.trim())
+      .filter(|line| !line.is_empty() && !line.starts_with(b"#"));
+    if lines.next()? != b"/*" || lines.next()? != b"!/*/" {
+      return None;
+    }
+    for line in lines {
+      let (negated, pattern) = match line.strip_prefix(b"!") {
+        Some(pattern) => (true, pattern),
+        None => (false, line),
+      };
+      let dir = pattern.strip_prefix(b"/")?;
+      match negated {
+        true => parents.insert(unescape(dir.strip_suffix(b"/*/")?)),
+        false => positive.insert(unescape(dir.strip_suffix(b"/")?)),
+      };
+    }
+    if !parents.is_subset(&positive) {
+      return None;
+    }
+    let recursive = positive.difference(&parents).cloned().collect();
+    Some(Self { recursive, parents })
+  }
+
+  /// The patterns to write to `.git/info/sparse-checkout`.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut data = b"/*\n!/*/\n".to_vec();
+    for dir in &self.parents {
+      data.extend(format!("/{0}/\n!/{0}/*/\n", escape(dir)).as_bytes());
+    }
+    for dir in &self.recursive {
+      data.extend(format!("/{}/\n", escape(dir)).as_bytes());
+    }
+    data
+  }
+
+  /// The chosen directories, sorted.
+  pub fn dirs(&self) -> impl Iterator<Item = &BString> {
+    self.recursive.iter()
+  }
+
+  /// Whether a tracked file belongs in the working tree.
+  pub fn includes(&self, path: impl AsRef<[u8]>) -> bool {
+    let path = path.as_ref();
+    let dir = match path.rfind_byte(b'/') {
+      Some(slash) => &path[..slash],
+      None => return true,
+    };
+    self.parents.contains(dir)
+      || self.recursive.contains(dir)
+      || ancestors(dir).any(|parent| self.recursive.contains(parent))
+  }
+
+  /// Brings the working tree in line with the sparse checkout: files that
+  /// are left out are deleted and marked skip-worktree, and files that are
+  /// included but missing are written out.
+  ///
+  /// Files that would be deleted but have local changes are kept, with a
+  /// warning, as are conflicted files.
+  pub fn apply(&self, repo: &Repo, index: &mut Index) -> Result<(), String> {
+    let mut kept = Vec::new();
+    let entries: Vec<_> = index
+      .entries()
+      .iter()
+      .filter(|entry| entry.stage() == 0)
+      .cloned()
+      .collect();
+    for mut entry in entries {
+      let skipped = entry.extended_flags & SKIP_WORKTREE != 0;
+      match self.includes(&entry.path) {
+        true if skipped => {
+          let present = fs::symlink_metadata(repo.work_tree_path(&entry.path)).is_ok();
+          if !present {
+            let mode = entry.tree_mode().unwrap_or(Mode::Normal);
+            let written = worktree::checkout_file(repo, &entry.path, mode, &entry.hash)?;
+            entry = IndexEntry {
+              flags: entry.flags,
+              extended_flags: entry.extended_flags,
+              ..written
+            };
+          }
+          entry.extended_flags &= !SKIP_WORKTREE;
+          index.add(entry);
+        }
+        false if !skipped => {
+          let present = fs::symlink_metadata(repo.work_tree_path(&entry.path)).is_ok();
+          if present && index::is_modified(repo, &entry)? {
+            kept.push(entry.path);
+            continue;
+          }
+          worktree::remove_file(repo, &entry.path)?;
+          entry.extended_flags |= SKIP_WORKTREE;
+          index.add(entry);
+        }
+        _ => (),
+      }
+    }
+    if !kept.is_empty() {
+      eprintln!(
+        "warning: The following paths are not up to date and were left despite sparse patterns:"
+      );
+      for path in kept {
+        eprintln!("{}", path);
+      }
+    }
+    Ok(())
+  }
+}
+
+/// The directories a path is inside, deepest first.
+fn ancestors(path: &[u8]) -> impl Iterator<Item = &[u8]> {
+  path.rfind_iter(b"/").map(move |slash| &path[..slash])
+}
+
+/// Escapes the characters that are special in patterns.
+fn escape(dir: &[u8]) -> String {
+  let mut escaped = String::new();
+  for c in dir.to_str_lossy().chars() {
+    if matches!(c, '*' | '?' | '[' | '\\') {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+fn unescape(pattern: &[u8]) -> BString {
+  let mut dir = Vec::with_capacity(pattern.len());
+  let mut bytes = pattern.iter();
+  while let Some(&c) = bytes.next() {
+    match c {
+      b'\\' => dir.extend(bytes.next()),
+      _ => dir.push(c),
+    }
+  }
+  dir.into()
+}
+
+/// Reads a boolean `core` setting, which is off unless set.
+fn config_bool(repo: &Repo, key: &str) -> bool {
+  let value = repo
+    .config
+    .as_ref()
+    .and_then(|config| config.section(Some("core")))
+    .and_then(|core| {
+      core
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.to_ascii_lowercase())
+    });
+  matches!(value.as_deref(), Some("true" | "yes" | "on" | "1" | ""))
+}
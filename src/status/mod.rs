@@ -0,0 +1,657 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use bstr::{BString, ByteSlice, ByteVec};
+
+use crate::{
+  branch::{self, Upstream},
+  color::Colors,
+  diff::{self, blob_data, TreeChange},
+  index::{self, Index},
+  object::{self, mode::Mode, refs, tree},
+  repo::Repo,
+  rev, submodule,
+  trace::event,
+  worktree,
+};
+
+type Files = BTreeMap<BString, (Mode, String)>;
+
+/// The hash `status` shows for a missing side.
+const NULL_HASH: &str = "0000000000000000000000000000000000000000";
+
+/// What is about to be committed and what is being left out.
+pub struct Status {
+  /// The branch HEAD points at, or `None` when it is detached.
+  pub branch: Option<String>,
+  pub head: Option<String>,
+  pub initial: bool,
+
+  /// When HEAD is detached, the name it was detached at and the commit
+  /// that was (see [`refs::detached_from`]).
+  pub detached_from: Option<(String, String)>,
+
+  /// The branch the current one tracks, if it has one.
+  pub upstream: Option<Upstream>,
+
+  /// Whether a merge is in progress (`MERGE_HEAD` exists).
+  pub merging: bool,
+  pub staged: Vec<TreeChange>,
+
+  /// The files that differ from the index, with `M` for modified, `T` for a
+  /// change of type (eg. a symlink replaced by a file) and `D` for deleted.
+  pub unstaged: Vec<(char, BString)>,
+
+  /// The submodules whose working trees differ from the commits the index
+  /// records for them, which are also among the `unstaged` paths.
+  pub submodules: BTreeMap<BString, submodule::State>,
+  pub unmerged: Vec<Unmerged>,
+  pub untracked: Vec<BString>,
+
+  /// The files in the tree of the base commit and in the index.
+  base_files: Files,
+  index_files: Files,
+}
+
+/// A path with conflicts in the index.
+pub struct Unmerged {
+  pub path: BString,
+
+  /// The mode and hash of the base, ours and theirs (stages 1 to 3).
+  pub stages: [Option<(Mode, String)>; 3],
+}
+
+impl Unmerged {
+  /// Which stages are there, as bits: 1 for the base, 2 for ours and 4 for
+  /// theirs.
+  fn mask(&self) -> usize {
+    (0..3)
+      .filter(|i| self.stages[*i].is_some())
+      .map(|i| 1 << i)
+      .sum()
+  }
+
+  /// The two letter code the short formats show, eg. `UU`.
+  pub fn code(&self) -> &'static str {
+    ["", "DD", "AU", "UD", "UA", "DU", "AA", "UU"][self.mask()]
+  }
+
+  /// How the long format describes the conflict, eg. `both modified:`.
+  fn label(&self) -> &'static str {
+    [
+      "",
+      "both deleted:",
+      "added by us:",
+      "deleted by them:",
+      "added by them:",
+      "deleted by us:",
+      "both added:",
+      "both modified:",
+    ][self.mask()]
+  }
+}
+
+impl Status {
+  /// Compares the index to the tree of `base` (the commit the next one goes
+  /// on top of, usually HEAD) and the working tree to the index.
+  ///
+  /// Along the way, the stat data of files that were touched but not changed
+  /// is refreshed, and the untracked cache (if the index has one) updated.
+  /// The caller may write the index back to save the work next time.
+  pub fn collect(repo: &Repo, index: &mut Index, base: Option<&str>) -> Result<Status, String> {
+    index.refresh(repo)?;
+    let base_tree = match base {
+      Some(commit) => object::peel(repo, commit, Some("tree"))?,
+      None => object::EMPTY_TREE.to_owned(),
+    };
+    let base_files = tree::flatten(repo, &base_tree)?;
+
+    let mut unmerged: Vec<Unmerged> = Vec::new();
+    for entry in index.entries().iter().filter(|e| e.stage() != 0) {
+      if unmerged.last().map(|u| &u.path) != Some(&entry.path) {
+        unmerged.push(Unmerged {
+          path: entry.path.clone(),
+          stages: Default::default(),
+        });
+      }
+      if let Some(mode) = entry.tree_mode() {
+        unmerged.last_mut().unwrap().stages[entry.stage() as usize - 1] =
+          Some((mode, entry.hash.clone()));
+      }
+    }
+    // an unmerged path is not a staged deletion
+    let index_files = index.files();
+    let changes = diff::compare(&base_files, &index_files)
+      .into_iter()
+      .filter(|change| !unmerged.iter().any(|u| u.path == change.path))
+      .collect();
+
+    let branch = refs::read_symbolic(repo, "HEAD")
+      .map(|name| name.strip_prefix("refs/heads/").unwrap_or(&name).to_owned());
+    let head = rev::parse(repo, "HEAD").ok();
+    let staged = diff::detect_renames(repo, changes)?;
+    let mut unstaged = worktree::unstaged_changes(repo, index)?;
+    let mut submodules = BTreeMap::new();
+    for entry in index.entries() {
+      if entry.stage() != 0 || entry.tree_mode() != Some(Mode::Gitlink) {
+        continue;
+      }
+      let state = submodule::state(repo, &entry.path, &entry.hash)?;
+      if state.is_changed() {
+        if !unstaged.iter().any(|(_, path)| *path == entry.path) {
+          unstaged.push(('M', entry.path.clone()));
+        }
+        submodules.insert(entry.path.clone(), state);
+      }
+    }
+    unstaged.sort_by(|a, b| a.1.cmp(&b.1));
+    let untracked = {
+      let _event = event::region("status", "untracked", None);
+      worktree::untracked_files(repo, index)?
+    };
+    event::data("status", "count/changed", staged.len() + unstaged.len());
+    event::data("status", "count/untracked", untracked.len());
+    Ok(Status {
+      upstream: match (&branch, &head) {
+        (Some(branch), Some(head)) => branch::upstream(repo, branch, head)?,
+        _ => None,
+      },
+      detached_from: refs::detached_from(repo),
+      branch,
+      head,
+      initial: base.is_none(),
+      merging: repo.git_dir.join("MERGE_HEAD").exists(),
+      staged,
+      unstaged,
+      submodules,
+      unmerged,
+      untracked,
+      base_files,
+      index_files,
+    })
+  }
+
+  /// The `On branch` or `HEAD detached at` line.
+  pub fn position(&self) -> String {
+    match (&self.branch, &self.head) {
+      (Some(branch), _) => format!("On branch {}", branch),
+      (None, Some(_)) => {
+        let (how, name) = self.detached();
+        format!("HEAD detached {} {}", how, name)
+      }
+      (None, None) => "Not currently on any branch.".to_string(),
+    }
+  }
+
+  /// Where a detached HEAD was detached, and whether it is still `at` it or
+  /// has moved on `from` it with new commits, eg. `("from", "v1.0")`.
+  fn detached(&self) -> (&'static str, String) {
+    let head = self.head.as_deref().unwrap_or_default();
+    match &self.detached_from {
+      Some((name, hash)) if hash != head => ("from", name.clone()),
+      Some((name, _)) => ("at", name.clone()),
+      None => ("at", head[..7.min(head.len())].to_owned()),
+    }
+  }
+
+  /// The staged changes, labelled and padded the way `git status` does.
+  pub fn staged_lines(&self) -> Vec<String> {
+    self
+      .staged
+      .iter()
+      .map(|change| {
+        let (label, path) = match (&change.old, &change.new, &change.renamed_from) {
+          (_, _, Some(from)) => (
+            "renamed:",
+            format!("{} -> {}", quote(from), quote(&change.path)),
+          ),
+          (None, _, _) => ("new file:", quote(&change.path)),
+          (_, None, _) => ("deleted:", quote(&change.path)),
+          (Some((old, _)), Some((new, _)), _) if is_link(*old) != is_link(*new) => {
+            ("typechange:", quote(&change.path))
+          }
+          _ => ("modified:", quote(&change.path)),
+        };
+        format!("{:<12}{}", label, path)
+      })
+      .collect()
+  }
+
+  pub fn unstaged_lines(&self) -> Vec<String> {
+    self
+      .unstaged
+      .iter()
+      .map(|(kind, path)| {
+        let label = match kind {
+          'D' => "deleted:",
+          'T' => "typechange:",
+          _ => "modified:",
+        };
+        match self.submodules.get(path) {
+          Some(state) => format!("{:<12}{} ({})", label, quote(path), state.describe()),
+          None => format!("{:<12}{}", label, quote(path)),
+        }
+      })
+      .collect()
+  }
+
+  /// What `git status` says by default, with the paths in the colors of
+  /// their kind of change.
+  pub fn long(&self, colors: &Colors) -> String {
+    let mut out = match (&self.branch, &self.head) {
+      (None, Some(_)) => {
+        let (how, name) = self.detached();
+        let label = format!("HEAD detached {} ", how);
+        format!("{}{}\n", colors.paint("status.nobranch", &label), name)
+      }
+      _ => format!("{}\n", self.position()),
+    };
+    if let Some(upstream) = &self.upstream {
+      out.push_str(&upstream.long());
+      out.push('\n');
+    }
+    if self.initial {
+      out.push_str("\nNo commits yet\n\n");
+    }
+    if self.merging {
+      out.push_str(match self.unmerged.is_empty() {
+        true => "All conflicts fixed but you are still merging.\n  (use \"git commit\" to conclude merge)\n\n",
+        false => "You have unmerged paths.\n  (fix conflicts and run \"git commit\")\n  (use \"git merge --abort\" to abort the merge)\n\n",
+      });
+    }
+    if !self.staged.is_empty() {
+      out.push_str("Changes to be committed:\n");
+      if !self.merging {
+        out.push_str(match self.initial {
+          true => "  (use \"git rm --cached <file>...\" to unstage)\n",
+          false => "  (use \"git restore --staged <file>...\" to unstage)\n",
+        });
+      }
+      for line in self.staged_lines() {
+        out.push_str(&format!("\t{}\n", colors.paint("status.added", &line)));
+      }
+      out.push('\n');
+    }
+    if !self.unmerged.is_empty() {
+      let masks: Vec<usize> = self.unmerged.iter().map(|u| u.mask()).collect();
+      let deletion = masks.iter().any(|mask| [3, 5].contains(mask));
+      let both_deleted = masks.contains(&1);
+      let hint = match (both_deleted, deletion, masks.iter().all(|mask| *mask == 1)) {
+        (false, false, _) => "git add <file>...\" to",
+        (true, false, true) => "git rm <file>...\" to",
+        _ => "git add/rm <file>...\" as appropriate to",
+      };
+      out.push_str(&format!(
+        "Unmerged paths:\n  (use \"{} mark resolution)\n",
+        hint
+      ));
+      for unmerged in &self.unmerged {
+        let line = format!("{:<17}{}", unmerged.label(), quote(&unmerged.path));
+        out.push_str(&format!("\t{}\n", colors.paint("status.unmerged", &line)));
+      }
+      out.push('\n');
+    }
+    if !self.unstaged.is_empty() {
+      let add = match self.unstaged.iter().any(|(kind, _)| *kind == 'D') {
+        true => "git add/rm <file>...",
+        false => "git add <file>...",
+      };
+      out.push_str(&format!(
+        "Changes not staged for commit:\n  (use \"{}\" to update what will be committed)\n  \
+         (use \"git restore <file>...\" to discard changes in working directory)\n",
+        add
+      ));
+      if self
+        .submodules
+        .values()
+        .any(|state| state.modified || state.untracked)
+      {
+        out.push_str("  (commit or discard the untracked or modified content in submodules)\n");
+      }
+      for line in self.unstaged_lines() {
+        out.push_str(&format!("\t{}\n", colors.paint("status.changed", &line)));
+      }
+      out.push('\n');
+    }
+    if !self.untracked.is_empty() {
+      out.push_str(
+        "Untracked files:\n  (use \"git add <file>...\" to include in what will be committed)\n",
+      );
+      for path in &self.untracked {
+        out.push_str(&format!(
+          "\t{}\n",
+          colors.paint("status.untracked", &quote(path))
+        ));
+      }
+      out.push('\n');
+    }
+    if !self.staged.is_empty() {
+      return out;
+    }
+    let changed = !self.unstaged.is_empty() || !self.unmerged.is_empty();
+    out.push_str(match (changed, self.untracked.is_empty(), self.initial) {
+      (true, _, _) => "no changes added to commit (use \"git add\" and/or \"git commit -a\")\n",
+      (false, false, _) => {
+        "nothing added to commit but untracked files present (use \"git add\" to track)\n"
+      }
+      (false, true, true) => "nothing to commit (create/copy files and use \"git add\" to track)\n",
+      (false, true, false) => "nothing to commit, working tree clean\n",
+    });
+    out
+  }
+
+  /// The `--porcelain` (version 1) format: a two letter code for each
+  /// changed path, the first letter for the index and the second for the
+  /// working tree, eg. `MM file`.
+  ///
+  /// With `z`, lines end in NUL, paths are not quoted and a renamed path is
+  /// followed by the path it was renamed from, instead of `from -> to`.
+  pub fn porcelain_v1(&self, branch: bool, z: bool) -> BString {
+    let eol = if z { '\0' } else { '\n' };
+    let mut out = BString::default();
+    if branch {
+      out.push_str("## ");
+      match (&self.branch, self.initial) {
+        (Some(name), true) => out.push_str(format!("No commits yet on {}", name)),
+        (Some(name), false) => out.push_str(name),
+        (None, _) => out.push_str("HEAD (no branch)"),
+      }
+      if let Some(upstream) = &self.upstream {
+        out.push_str(format!("...{}", upstream.name));
+        match upstream.short().as_str() {
+          "" => (),
+          short => out.push_str(format!(" [{}]", short)),
+        }
+      }
+      out.push_char(eol);
+    }
+
+    let mut lines: Vec<(&BString, BString)> = Vec::new();
+    for entry in self.entries() {
+      let code = format!("{}{}", entry.staged, entry.unstaged).replace('.', " ");
+      let mut line = BString::from(format!("{} ", code));
+      match (entry.renamed_from, z) {
+        (Some(from), true) => {
+          line.push_str(entry.path);
+          line.push_byte(b'\0');
+          line.push_str(from);
+        }
+        (Some(from), false) => line.push_str(format!(
+          "{} -> {}",
+          quote_short(from),
+          quote_short(entry.path)
+        )),
+        (None, _) => line.push_str(quote_short_if(entry.path, !z)),
+      }
+      lines.push((entry.path, line));
+    }
+    for unmerged in &self.unmerged {
+      let mut line = BString::from(format!("{} ", unmerged.code()));
+      line.push_str(quote_short_if(&unmerged.path, !z));
+      lines.push((&unmerged.path, line));
+    }
+    lines.sort_by(|a, b| a.0.cmp(b.0));
+    for path in &self.untracked {
+      let mut line = BString::from("?? ");
+      line.push_str(quote_short_if(path, !z));
+      lines.push((path, line));
+    }
+    for (_, line) in lines {
+      out.push_str(line);
+      out.push_char(eol);
+    }
+    out
+  }
+
+  /// The `--porcelain=v2` format, which adds the modes and hashes of each
+  /// changed path in HEAD and the index, how similar renamed files are, and
+  /// the stages of unmerged paths. With `branch`, it starts with `# branch.*`
+  /// header lines about HEAD and its upstream.
+  ///
+  /// The submodule field is `N...` for other files, and for submodules `S`
+  /// followed by `C` if the submodule has new commits, `M` if it has
+  /// modified content and `U` if it has untracked content (each `.` if not).
+  pub fn porcelain_v2(&self, repo: &Repo, branch: bool, z: bool) -> Result<BString, String> {
+    let eol = if z { '\0' } else { '\n' };
+    let mut out = BString::default();
+    if branch {
+      let oid = if self.initial {
+        "(initial)"
+      } else {
+        self.head.as_deref().unwrap_or("(initial)")
+      };
+      out.push_str(format!("# branch.oid {}{}", oid, eol));
+      out.push_str(format!(
+        "# branch.head {}{}",
+        self.branch.as_deref().unwrap_or("(detached)"),
+        eol
+      ));
+      if let Some(upstream) = &self.upstream {
+        out.push_str(format!("# branch.upstream {}{}", upstream.name, eol));
+        if let Some((ahead, behind)) = upstream.ahead_behind {
+          out.push_str(format!("# branch.ab +{} -{}{}", ahead, behind, eol));
+        }
+      }
+    }
+
+    for entry in self.entries() {
+      let worktree_mode = match (&entry.index, entry.unstaged) {
+        (None, _) | (_, 'D') => None,
+        (Some((mode, _)), '.') => Some(*mode),
+        (Some(_), _) => fs::symlink_metadata(repo.work_tree_path(entry.path))
+          .ok()
+          .map(|metadata| match metadata.is_dir() {
+            // a submodule's checkout is a directory
+            true => Mode::Gitlink,
+            false => index::file_mode(&metadata),
+          }),
+      };
+      let modes = [entry.head, entry.index].map(|side| side.map(|(mode, _)| *mode));
+      let fields = format!(
+        "{}{} {} {} {} {} {} {}",
+        entry.staged,
+        entry.unstaged,
+        submodule_state(
+          &[modes[0], modes[1], worktree_mode],
+          self.submodules.get(entry.path),
+        ),
+        format_mode(modes[0]),
+        format_mode(modes[1]),
+        format_mode(worktree_mode),
+        entry.head.map_or(NULL_HASH, |(_, hash)| hash),
+        entry.index.map_or(NULL_HASH, |(_, hash)| hash),
+      );
+      match entry.renamed_from {
+        Some(from) => {
+          let score = (100.0
+            * diff::similarity(
+              &blob_data(repo, entry.head)?,
+              &blob_data(repo, entry.index)?,
+            )) as usize;
+          let separator = if z { '\0' } else { '\t' };
+          out.push_str(format!("2 {} R{} ", fields, score));
+          out.push_str(quote_if(entry.path, !z));
+          out.push_char(separator);
+          out.push_str(quote_if(from, !z));
+        }
+        None => {
+          out.push_str(format!("1 {} ", fields));
+          out.push_str(quote_if(entry.path, !z));
+        }
+      }
+      out.push_char(eol);
+    }
+
+    for unmerged in &self.unmerged {
+      let worktree_mode = fs::symlink_metadata(repo.work_tree_path(&unmerged.path))
+        .ok()
+        .map(|metadata| index::file_mode(&metadata));
+      let modes = unmerged
+        .stages
+        .clone()
+        .map(|stage| stage.map(|(mode, _)| mode));
+      let hashes = unmerged
+        .stages
+        .iter()
+        .map(|stage| stage.as_ref().map_or(NULL_HASH, |(_, hash)| hash))
+        .collect::<Vec<&str>>();
+      out.push_str(format!(
+        "u {} {} {} {} {} {} {} ",
+        unmerged.code(),
+        submodule_state(&[modes[0], modes[1], modes[2], worktree_mode], None),
+        format_mode(modes[0]),
+        format_mode(modes[1]),
+        format_mode(modes[2]),
+        format_mode(worktree_mode),
+        hashes.join(" "),
+      ));
+      out.push_str(quote_if(&unmerged.path, !z));
+      out.push_char(eol);
+    }
+    for path in &self.untracked {
+      out.push_str("? ");
+      out.push_str(quote_if(path, !z));
+      out.push_char(eol);
+    }
+    Ok(out)
+  }
+
+  /// The tracked paths with changes (other than conflicts), in path order,
+  /// with what changed in the index and in the working tree.
+  pub fn entries(&self) -> Vec<Entry<'_>> {
+    let mut entries: BTreeMap<&BString, Entry> = BTreeMap::new();
+    for change in &self.staged {
+      let code = match (&change.old, &change.new, &change.renamed_from) {
+        (_, _, Some(_)) => 'R',
+        (None, _, _) => 'A',
+        (_, None, _) => 'D',
+        (Some((old, _)), Some((new, _)), _) if is_link(*old) != is_link(*new) => 'T',
+        _ => 'M',
+      };
+      entries.insert(
+        &change.path,
+        Entry {
+          path: &change.path,
+          staged: code,
+          unstaged: '.',
+          head: change.old.as_ref(),
+          index: change.new.as_ref(),
+          renamed_from: change.renamed_from.as_ref(),
+        },
+      );
+    }
+    for (kind, path) in &self.unstaged {
+      entries
+        .entry(path)
+        .or_insert(Entry {
+          path,
+          staged: '.',
+          unstaged: '.',
+          head: self.base_files.get(path),
+          index: self.index_files.get(path),
+          renamed_from: None,
+        })
+        .unstaged = *kind;
+    }
+    entries.into_values().collect()
+  }
+}
+
+/// A changed path, as the short formats show it.
+pub struct Entry<'a> {
+  pub path: &'a BString,
+
+  /// How the index differs from HEAD (`M`, `T`, `A`, `D` or `R`), or `.`.
+  pub staged: char,
+
+  /// How the working tree differs from the index (`M`, `T` or `D`), or `.`.
+  pub unstaged: char,
+
+  /// The modes and hashes in HEAD (under the old name, for a rename) and in
+  /// the index.
+  pub head: Option<&'a (Mode, String)>,
+  pub index: Option<&'a (Mode, String)>,
+  pub renamed_from: Option<&'a BString>,
+}
+
+/// A mode as `status` shows it, with `000000` for a missing side.
+fn format_mode(mode: Option<Mode>) -> String {
+  mode.map_or("000000".to_string(), |mode| mode.to_string())
+}
+
+/// The submodule state field: `N...` unless any side is a submodule, and
+/// otherwise `S` and what has changed in it.
+fn submodule_state(modes: &[Option<Mode>], state: Option<&submodule::State>) -> String {
+  if !modes.contains(&Some(Mode::Gitlink)) {
+    return "N...".to_string();
+  }
+  let state = state.copied().unwrap_or_default();
+  let flag = |set: bool, c: char| if set { c } else { '.' };
+  format!(
+    "S{}{}{}",
+    flag(state.new_commits, 'C'),
+    flag(state.modified, 'M'),
+    flag(state.untracked, 'U')
+  )
+}
+
+/// Quotes a path the way git does when `core.quotePath` is on: in double
+/// quotes with C-style escapes, if it has control characters, quotes,
+/// backslashes or bytes outside of ASCII.
+pub fn quote(path: &[u8]) -> String {
+  quote_with(path, false)
+}
+
+/// Quotes a path for the short formats, which also quote paths with spaces
+/// in them.
+fn quote_short(path: &[u8]) -> String {
+  quote_with(path, true)
+}
+
+fn quote_with(path: &[u8], space: bool) -> String {
+  let special = |b: u8| !(0x20..0x7f).contains(&b) || b == b'"' || b == b'\\';
+  if space && path.contains(&b' ') && !path.iter().copied().any(special) {
+    return format!("\"{}\"", path.to_str_lossy());
+  }
+  if !path.iter().copied().any(special) {
+    return path.to_str_lossy().into_owned();
+  }
+  let mut quoted = String::from("\"");
+  for b in path.iter().copied() {
+    match b {
+      b'"' => quoted.push_str("\\\""),
+      b'\\' => quoted.push_str("\\\\"),
+      0x07 => quoted.push_str("\\a"),
+      0x08 => quoted.push_str("\\b"),
+      b'\t' => quoted.push_str("\\t"),
+      b'\n' => quoted.push_str("\\n"),
+      0x0b => quoted.push_str("\\v"),
+      0x0c => quoted.push_str("\\f"),
+      b'\r' => quoted.push_str("\\r"),
+      b if special(b) => quoted.push_str(&format!("\\{:03o}", b)),
+      b => quoted.push(b as char),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+/// Quotes a path if `enabled`, and otherwise leaves its bytes as they are
+/// (for `-z`, where paths are never quoted).
+fn quote_if(path: &[u8], enabled: bool) -> BString {
+  match enabled {
+    true => quote(path).into(),
+    false => path.into(),
+  }
+}
+
+fn quote_short_if(path: &[u8], enabled: bool) -> BString {
+  match enabled {
+    true => quote_short(path).into(),
+    false => path.into(),
+  }
+}
+
+fn is_link(mode: Mode) -> bool {
+  mode == Mode::Symbolic
+}
@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+use crate::diff::raw::NULL_HASH;
+use crate::index::Index;
+use crate::mail;
+use crate::object::{self, commit::Commit, serializable::Unbox};
+use crate::repo::{read_gitfile, Repo};
+use crate::rev::{
+  self,
+  walk::{merge_bases, RevWalk},
+};
+use crate::status::Status;
+
+/// How the working tree of a submodule differs from the commit the
+/// superproject records for it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct State {
+  /// The submodule's HEAD is at another commit.
+  pub new_commits: bool,
+
+  /// Its tracked files have changes, staged or not.
+  pub modified: bool,
+
+  /// It has untracked files.
+  pub untracked: bool,
+}
+
+impl State {
+  pub fn is_changed(&self) -> bool {
+    self.new_commits || self.modified || self.untracked
+  }
+
+  /// What has changed, the way `status` lists it, eg. `new commits,
+  /// untracked content`.
+  pub fn describe(&self) -> String {
+    let parts = [
+      (self.new_commits, "new commits"),
+      (self.modified, "modified content"),
+      (self.untracked, "untracked content"),
+    ];
+    parts
+      .iter()
+      .filter(|(changed, _)| *changed)
+      .map(|(_, part)| *part)
+      .collect::<Vec<_>>()
+      .join(", ")
+  }
+}
+
+/// Opens the repository of the submodule at `path`: the one checked out
+/// there (whose `.git` is usually a gitfile pointing into the superproject's
+/// `.git/modules`), or else the one kept in `.git/modules`. Returns `None`
+/// if the submodule was never cloned.
+pub fn open(repo: &Repo, path: &[u8]) -> Option<Repo> {
+  let dir = repo.work_tree_path(path);
+  let dot_git = dir.join(".git");
+  let git_dir = match dot_git.is_file() {
+    true => read_gitfile(&dot_git).ok()?,
+    false if dot_git.is_dir() => dot_git,
+    false => repo.git_dir.join("modules").join(OsStr::from_bytes(path)),
+  };
+  Repo::open(&git_dir, &dir, false).ok()
+}
+
+/// How the submodule checked out at `path` differs from `commit`. One that
+/// isn't checked out hasn't changed.
+pub fn state(repo: &Repo, path: &[u8], commit: &str) -> Result<State, String> {
+  // without a checkout, the repository in `.git/modules` has no working tree
+  // to look at
+  if !repo.work_tree_path(path).join(".git").exists() {
+    return Ok(State::default());
+  }
+  let sub = match open(repo, path) {
+    Some(sub) => sub,
+    None => return Ok(State::default()),
+  };
+  let head = rev::parse(&sub, "HEAD").ok();
+  let mut index = Index::read(&sub)?;
+  let status = Status::collect(&sub, &mut index, head.as_deref())?;
+  Ok(State {
+    new_commits: head.as_deref() != Some(commit),
+    modified: !status.staged.is_empty()
+      || !status.unstaged.is_empty()
+      || !status.unmerged.is_empty(),
+    untracked: !status.untracked.is_empty(),
+  })
+}
+
+/// Describes a submodule moving from `old` to `new` (either of which is
+/// `None` when the submodule is added or removed), as `--submodule=log`
+/// does.
+///
+/// A header names the submodule and both commits, joined by `..` when one
+/// is an ancestor of the other and `...` otherwise, and followed by
+/// `(rewind)` if the submodule went back. Below it the commits in between
+/// are listed by subject, `>` marking those only `new` has and `<` those
+/// only `old` has. When the commits can't be looked at, the header says
+/// why instead.
+pub fn log(
+  repo: &Repo,
+  path: &str,
+  old: Option<&str>,
+  new: Option<&str>,
+) -> Result<String, String> {
+  let mut message = match (old, new) {
+    (None, _) => Some("(new submodule)"),
+    (_, None) => Some("(submodule deleted)"),
+    _ => None,
+  };
+  let sub = open(repo, path.as_bytes());
+  let commit = |hash: Option<&str>| -> Option<String> {
+    let sub = sub.as_ref()?;
+    object::peel(sub, hash?, Some("commit")).ok()
+  };
+  let (left, right) = (commit(old), commit(new));
+  if sub.is_none() || (old.is_some() && left.is_none()) || (new.is_some() && right.is_none()) {
+    message = message.or(Some("(commits not present)"));
+  }
+  let bases = match (&sub, &left, &right) {
+    (Some(sub), Some(left), Some(right)) => merge_bases(sub, left, right)?,
+    _ => Vec::new(),
+  };
+  let fast_forward = left.is_some() && bases.first() == left.as_ref();
+  let rewind = right.is_some() && bases.first() == right.as_ref();
+
+  let abbrev = |hash: Option<&str>| hash.unwrap_or(NULL_HASH)[..7].to_string();
+  let mut out = format!(
+    "Submodule {} {}{}{}",
+    path,
+    abbrev(old),
+    if fast_forward || rewind { ".." } else { "..." },
+    abbrev(new)
+  );
+  match message {
+    Some(message) => out.push_str(&format!(" {}\n", message)),
+    None if rewind => out.push_str(" (rewind):\n"),
+    None => out.push_str(":\n"),
+  }
+  let (sub, left, right) = match (&sub, &left, &right) {
+    (Some(sub), Some(left), Some(right)) if message.is_none() => (sub, left, right),
+    _ => return Ok(out),
+  };
+
+  let mut walk = RevWalk::new(sub);
+  walk.push(left);
+  walk.push(right);
+  for base in &bases {
+    walk.hide(base);
+  }
+  let mut only_new = RevWalk::new(sub);
+  only_new.push(right);
+  only_new.hide(left);
+  let only_new: HashSet<String> = only_new.run()?.into_iter().collect();
+  for hash in walk.run()? {
+    let object = object::read(sub, &hash, Some("commit"))?;
+    let (subject, _) = mail::split_message(object.unbox::<Commit>()?.message());
+    let side = if only_new.contains(&hash) { '>' } else { '<' };
+    out.push_str(&format!("  {} {}\n", side, subject));
+  }
+  Ok(out)
+}
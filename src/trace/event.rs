@@ -0,0 +1,358 @@
+use std::env;
+use std::fmt::Display;
+use std::os::unix::process::ExitStatusExt;
+use std::panic::Location;
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+
+use super::{sink, write, Sink};
+
+/// Structured performance events, one JSON object per line, like git's
+/// trace2 event target.
+pub const EVENT: &str = "GIT_TRACE2_EVENT";
+
+/// How deep in regions events are still said, 2 unless it says otherwise.
+const EVENT_NESTING: &str = "GIT_TRACE2_EVENT_NESTING";
+
+/// The session of the process that ran this one, which this one's session
+/// is named under, and which the processes this one runs are told of.
+const PARENT_SID: &str = "GIT_TRACE2_PARENT_SID";
+
+/// The version of the event format, which is git's.
+const FORMAT_VERSION: &str = "3";
+
+/// When the process started, which `t_abs` is counted from.
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// The name of the session, set when it starts.
+static SID: OnceLock<String> = OnceLock::new();
+
+/// When each of the regions the command is in started, innermost last.
+static REGIONS: Mutex<Vec<Instant>> = Mutex::new(Vec::new());
+
+/// How many child processes have been started, which numbers them.
+static CHILDREN: AtomicUsize = AtomicUsize::new(0);
+
+/// A value of an event, as JSON.
+fn string(value: &str) -> String {
+  Value::from(value).to_string()
+}
+
+/// Some seconds, to the microsecond, the way git says them.
+fn seconds(since: Instant) -> String {
+  format!("{:.6}", since.elapsed().as_secs_f64())
+}
+
+fn t_abs() -> String {
+  seconds(*START.get_or_init(Instant::now))
+}
+
+/// Says an event, if events are traced: its name, the session, the thread,
+/// the time and where in the source it was said from, then its own fields,
+/// which are already JSON.
+///
+/// Where events go is set as for the other kinds of trace output, except
+/// that a directory gets a file for each session, named after it.
+fn emit(event: &str, location: &Location, fields: &[(&str, String)]) {
+  let sink = match sink(EVENT) {
+    Some(Sink::File(path)) if path.is_dir() => Sink::File(path.join(sid())),
+    Some(sink) => sink,
+    None => return,
+  };
+  let file = location.file();
+  let file = file.strip_prefix("src/").unwrap_or(file);
+  let common = [
+    ("event", string(event)),
+    ("sid", string(sid())),
+    ("thread", string("main")),
+    ("time", string(&utc())),
+    ("file", string(file)),
+    ("line", location.line().to_string()),
+  ];
+  let fields: Vec<String> = common
+    .iter()
+    .chain(fields)
+    .map(|(key, value)| format!("{}:{}", string(key), value))
+    .collect();
+  write(&sink, &format!("{{{}}}\n", fields.join(",")));
+}
+
+/// The name of the session, like `20240102T030405.123456Z-H1a2b3c4d-P00001234`:
+/// when it started, a hash of the host name and the process ID, under the
+/// session of the process that ran this one, if there was one.
+fn sid() -> &'static str {
+  SID.get_or_init(|| {
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default();
+    let tm = gmtime(now.as_secs());
+    let mut host = [0u8; 256];
+    unsafe {
+      libc::gethostname(host.as_mut_ptr() as *mut libc::c_char, host.len());
+    }
+    let host = &host[..host.iter().position(|&b| b == 0).unwrap_or(host.len())];
+    let own = format!(
+      "{:04}{:02}{:02}T{:02}{:02}{:02}.{:06}Z-H{}-P{:08x}",
+      tm.tm_year + 1900,
+      tm.tm_mon + 1,
+      tm.tm_mday,
+      tm.tm_hour,
+      tm.tm_min,
+      tm.tm_sec,
+      now.subsec_micros(),
+      hex::encode(&Sha1::digest(host)[..4]),
+      std::process::id()
+    );
+    match env::var(PARENT_SID) {
+      Ok(parent) if !parent.is_empty() => format!("{}/{}", parent, own),
+      _ => own,
+    }
+  })
+}
+
+/// The time, in UTC to the microsecond, like `2024-01-02T03:04:05.123456Z`.
+fn utc() -> String {
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default();
+  let tm = gmtime(now.as_secs());
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+    tm.tm_year + 1900,
+    tm.tm_mon + 1,
+    tm.tm_mday,
+    tm.tm_hour,
+    tm.tm_min,
+    tm.tm_sec,
+    now.subsec_micros()
+  )
+}
+
+fn gmtime(seconds: u64) -> libc::tm {
+  let seconds = seconds as libc::time_t;
+  let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+  unsafe {
+    libc::gmtime_r(&seconds, &mut tm);
+  }
+  tm
+}
+
+/// Whether events this deep in regions are said.
+fn shown(nesting: usize) -> bool {
+  let max = env::var(EVENT_NESTING)
+    .ok()
+    .and_then(|max| max.parse().ok())
+    .unwrap_or(2);
+  nesting <= max
+}
+
+/// Starts the session of a command: says which version of the events and
+/// of git-rs these are, then the command line. The processes the command
+/// runs are told of the session, so theirs are named under it.
+#[track_caller]
+pub fn start(argv: &[String]) {
+  START.get_or_init(Instant::now);
+  if !super::enabled(EVENT) {
+    return;
+  }
+  let location = Location::caller();
+  emit(
+    "version",
+    location,
+    &[
+      ("evt", string(FORMAT_VERSION)),
+      ("exe", string(env!("CARGO_PKG_VERSION"))),
+    ],
+  );
+  let argv: Vec<Value> = argv.iter().map(|arg| Value::from(arg.as_str())).collect();
+  emit(
+    "start",
+    location,
+    &[("t_abs", t_abs()), ("argv", Value::from(argv).to_string())],
+  );
+  env::set_var(PARENT_SID, sid());
+}
+
+/// Says which command is being run.
+#[track_caller]
+pub fn cmd_name(name: &str) {
+  if !super::enabled(EVENT) {
+    return;
+  }
+  emit(
+    "cmd_name",
+    Location::caller(),
+    &[("name", string(name)), ("hierarchy", string(name))],
+  );
+}
+
+/// Ends the session, saying how long it took and the code the process
+/// exits with.
+#[track_caller]
+pub fn exit(code: i32) {
+  if !super::enabled(EVENT) {
+    return;
+  }
+  for event in ["exit", "atexit"] {
+    emit(
+      event,
+      Location::caller(),
+      &[("t_abs", t_abs()), ("code", code.to_string())],
+    );
+  }
+}
+
+/// A part of a command that is timed, from when it is started to when it is
+/// dropped, with `region_enter` and `region_leave` events.
+///
+/// # Example
+/// ```text
+/// let _region = event::region("index", "do_read_index", Some(".git/index"));
+/// // ...which says:
+/// // {"event":"region_enter",...,"nesting":1,"category":"index","label":"do_read_index","msg":".git/index"}
+/// // {"event":"region_leave",...,"t_rel":0.000071,"nesting":1,"category":"index","label":"do_read_index","msg":".git/index"}
+/// ```
+pub struct Region {
+  fields: Vec<(&'static str, String)>,
+  location: &'static Location<'static>,
+}
+
+/// Starts a region, if events are traced. `category` is the part of git-rs
+/// it is in, `label` what it does, and `msg` anything else about it.
+#[track_caller]
+pub fn region(category: &str, label: &str, msg: Option<&str>) -> Option<Region> {
+  if !super::enabled(EVENT) {
+    return None;
+  }
+  let mut regions = REGIONS.lock().unwrap();
+  regions.push(Instant::now());
+  let mut fields = vec![
+    ("nesting", regions.len().to_string()),
+    ("category", string(category)),
+    ("label", string(label)),
+  ];
+  if let Some(msg) = msg {
+    fields.push(("msg", string(msg)));
+  }
+  if shown(regions.len()) {
+    emit("region_enter", Location::caller(), &fields);
+  }
+  Some(Region {
+    fields,
+    location: Location::caller(),
+  })
+}
+
+impl Drop for Region {
+  fn drop(&mut self) {
+    let mut regions = REGIONS.lock().unwrap();
+    let nesting = regions.len();
+    let start = regions.pop().unwrap_or_else(Instant::now);
+    drop(regions);
+    if shown(nesting) {
+      let fields: Vec<(&str, String)> = [("t_rel", seconds(start))]
+        .into_iter()
+        .chain(self.fields.iter().cloned())
+        .collect();
+      emit("region_leave", self.location, &fields);
+    }
+  }
+}
+
+/// Says a value worked out along the way, like how many entries the index
+/// has. `t_rel` is the time since the innermost region started, which the
+/// value is nested in.
+#[track_caller]
+pub fn data(category: &str, key: &str, value: impl Display) {
+  if !super::enabled(EVENT) {
+    return;
+  }
+  let regions = REGIONS.lock().unwrap();
+  let nesting = regions.len() + 1;
+  let t_rel = match regions.last() {
+    Some(start) => seconds(*start),
+    None => t_abs(),
+  };
+  drop(regions);
+  if shown(nesting) {
+    emit(
+      "data",
+      Location::caller(),
+      &[
+        ("t_abs", t_abs()),
+        ("t_rel", t_rel),
+        ("nesting", nesting.to_string()),
+        ("category", string(category)),
+        ("key", string(key)),
+        ("value", string(&value.to_string())),
+      ],
+    );
+  }
+}
+
+/// A child process that has been started, whose exit is said when it is
+/// known.
+#[derive(Debug)]
+pub struct Child {
+  id: usize,
+  start: Instant,
+}
+
+/// Says a child process is about to be started. `class` says what it is
+/// for, like `editor` or `pager`, or is `?`.
+#[track_caller]
+pub fn child_start(class: &str, command: &Command) -> Child {
+  let child = Child {
+    id: CHILDREN.fetch_add(1, Ordering::SeqCst),
+    start: Instant::now(),
+  };
+  if super::enabled(EVENT) {
+    let argv: Vec<Value> = [command.get_program()]
+      .into_iter()
+      .chain(command.get_args())
+      .map(|arg| Value::from(arg.to_string_lossy()))
+      .collect();
+    emit(
+      "child_start",
+      Location::caller(),
+      &[
+        ("child_id", child.id.to_string()),
+        ("child_class", string(class)),
+        ("use_shell", "false".to_string()),
+        ("argv", Value::from(argv).to_string()),
+      ],
+    );
+  }
+  child
+}
+
+impl Child {
+  /// Says how the child process with this process ID exited. One killed by
+  /// a signal exits with 128 and the number of the signal, as the shell
+  /// would say.
+  #[track_caller]
+  pub fn exit(self, pid: u32, status: &ExitStatus) {
+    if !super::enabled(EVENT) {
+      return;
+    }
+    let code = status
+      .code()
+      .or_else(|| status.signal().map(|signal| 128 + signal))
+      .unwrap_or(-1);
+    emit(
+      "child_exit",
+      Location::caller(),
+      &[
+        ("child_id", self.id.to_string()),
+        ("pid", pid.to_string()),
+        ("code", code.to_string()),
+        ("t_rel", seconds(self.start)),
+      ],
+    );
+  }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::panic::Location;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub mod event;
+
+/// What commands are run and what runs them.
+pub const TRACE: &str = "GIT_TRACE";
+
+/// The pkt-lines exchanged with other processes.
+pub const PACKET: &str = "GIT_TRACE_PACKET";
+
+/// How long commands, and the slow parts of them, take.
+pub const PERFORMANCE: &str = "GIT_TRACE_PERFORMANCE";
+
+/// Which pack each object is read from, and where in it.
+pub const PACK_ACCESS: &str = "GIT_TRACE_PACK_ACCESS";
+
+/// Where a kind of trace output goes.
+#[derive(Clone, Debug)]
+enum Sink {
+  Fd(i32),
+  File(PathBuf),
+}
+
+/// The sink of each kind of trace output, worked out the first time it is
+/// asked for.
+static SINKS: Mutex<Option<HashMap<&'static str, Option<Sink>>>> = Mutex::new(None);
+
+/// How deep in performance regions the command is, which indents what is
+/// said about them.
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Where a kind of trace output goes, as its variable says: `1`, `2` or
+/// `true` for stderr, a number from 3 to 9 for that file descriptor, or an
+/// absolute path for a file that is appended to. Anything else turns it
+/// off, with a warning.
+fn sink(key: &'static str) -> Option<Sink> {
+  let mut sinks = SINKS.lock().unwrap();
+  let sinks = sinks.get_or_insert_with(HashMap::new);
+  sinks
+    .entry(key)
+    .or_insert_with(|| {
+      let value = env::var(key).unwrap_or_default();
+      match value.to_ascii_lowercase().as_str() {
+        "" | "0" | "false" => None,
+        "1" | "2" | "true" => Some(Sink::Fd(2)),
+        fd @ ("3" | "4" | "5" | "6" | "7" | "8" | "9") => Some(Sink::Fd(fd.parse().unwrap())),
+        _ if value.starts_with('/') => Some(Sink::File(PathBuf::from(value))),
+        _ => {
+          eprintln!(
+            "warning: unknown trace value for '{}': {}\n         \
+             If you want to trace into a file, then please set {}\n         \
+             to an absolute pathname (starting with /)",
+            key, value, key
+          );
+          None
+        }
+      }
+    })
+    .clone()
+}
+
+/// Whether a kind of trace output is turned on, for when working out what
+/// to say is costly.
+pub fn enabled(key: &'static str) -> bool {
+  sink(key).is_some()
+}
+
+/// Says something in a kind of trace output, if it is turned on. Each line
+/// starts with the time and where in the source it was said from, like
+/// `12:34:56.123456 index/mod.rs:151`, padded so the messages line up.
+#[track_caller]
+pub fn print(key: &'static str, message: &str) {
+  print_at(key, Location::caller(), message);
+}
+
+fn print_at(key: &'static str, location: &Location, message: &str) {
+  let sink = match sink(key) {
+    Some(sink) => sink,
+    None => return,
+  };
+  let file = location.file();
+  let file = file.strip_prefix("src/").unwrap_or(file);
+  let mut line = format!("{} {}:{} ", timestamp(), file, location.line());
+  if line.len() < 40 {
+    line.push_str(&" ".repeat(40 - line.len()));
+  }
+  line.push_str(message);
+  line.push('\n');
+  write(&sink, &line);
+}
+
+/// Writes some trace output where it goes. Tracing is no reason to fail, so
+/// errors writing it are ignored.
+fn write(sink: &Sink, line: &str) {
+  match sink {
+    Sink::Fd(2) => {
+      let _ = io::stderr().write_all(line.as_bytes());
+    }
+    Sink::Fd(fd) => unsafe {
+      libc::write(*fd, line.as_ptr() as *const libc::c_void, line.len());
+    },
+    Sink::File(path) => {
+      let file = OpenOptions::new().create(true).append(true).open(path);
+      let _ = file.and_then(|mut file| file.write_all(line.as_bytes()));
+    }
+  }
+}
+
+/// The local time of day, to the microsecond.
+fn timestamp() -> String {
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default();
+  let seconds = now.as_secs() as libc::time_t;
+  let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+  unsafe {
+    libc::localtime_r(&seconds, &mut tm);
+  }
+  format!(
+    "{:02}:{:02}:{:02}.{:06}",
+    tm.tm_hour,
+    tm.tm_min,
+    tm.tm_sec,
+    now.subsec_micros()
+  )
+}
+
+/// Quotes the arguments of a command the way a shell would take them, where
+/// they need it.
+pub fn quote<S: AsRef<str>>(args: &[S]) -> String {
+  let plain = |c: char| c.is_ascii_alphanumeric() || "+,-./:=@_^%".contains(c);
+  let quoted: Vec<String> = args
+    .iter()
+    .map(|arg| {
+      let arg = arg.as_ref();
+      match !arg.is_empty() && arg.chars().all(plain) {
+        true => arg.to_string(),
+        false => format!("'{}'", arg.replace('\'', "'\\''")),
+      }
+    })
+    .collect();
+  quoted.join(" ")
+}
+
+/// Says which command is about to be run, as `trace: run_command: ...`, and
+/// in a `child_start` event, which is ended by telling what is returned how
+/// the command exited. `class` says what the command is for, as in
+/// [`event::child_start`].
+#[track_caller]
+pub fn command(class: &str, command: &Command) -> event::Child {
+  let child = event::child_start(class, command);
+  if !enabled(TRACE) {
+    return child;
+  }
+  let args: Vec<String> = [command.get_program()]
+    .into_iter()
+    .chain(command.get_args())
+    .map(|arg| arg.to_string_lossy().into_owned())
+    .collect();
+  print(TRACE, &format!("trace: run_command: {}", quote(&args)));
+  child
+}
+
+/// Says what a pkt-line held, as it was written (`write`) or read, with
+/// `None` for a flush packet. Newlines are left out, and bytes that can't
+/// be printed are shown in octal.
+#[track_caller]
+pub fn packet(write: bool, data: Option<&[u8]>) {
+  if !enabled(PACKET) {
+    return;
+  }
+  let mut text = String::new();
+  for byte in data.unwrap_or(b"0000") {
+    match byte {
+      b'\n' => (),
+      0x20..=0x7e => text.push(*byte as char),
+      _ => text.push_str(&format!("\\{:o}", byte)),
+    }
+  }
+  let direction = if write { '>' } else { '<' };
+  print(
+    PACKET,
+    &format!("packet: {:>12}{} {}", "git", direction, text),
+  );
+}
+
+/// A slow part of a command, whose time is said in the performance trace
+/// when it is dropped, indented by how many regions it is in.
+///
+/// # Example
+/// ```text
+/// let _region = trace::region("read cache .git/index");
+/// // ...which, when done, says:
+/// // 12:34:56.123456 index/mod.rs:151        performance: 0.000035914 s:  read cache .git/index
+/// ```
+pub struct Region {
+  start: Instant,
+  name: String,
+  location: &'static Location<'static>,
+}
+
+/// Starts a region, if performance is being traced.
+#[track_caller]
+pub fn region(name: &str) -> Option<Region> {
+  if !enabled(PERFORMANCE) {
+    return None;
+  }
+  DEPTH.fetch_add(1, Ordering::SeqCst);
+  Some(Region {
+    start: Instant::now(),
+    name: name.to_string(),
+    location: Location::caller(),
+  })
+}
+
+impl Drop for Region {
+  fn drop(&mut self) {
+    let depth = DEPTH.fetch_sub(1, Ordering::SeqCst);
+    let message = format!(
+      "performance: {:.9} s: {}{}",
+      self.start.elapsed().as_secs_f64(),
+      " ".repeat(depth),
+      self.name
+    );
+    print_at(PERFORMANCE, self.location, &message);
+  }
+}
+
+/// Says how long the whole command took, given when it started.
+#[track_caller]
+pub fn command_performance(start: Instant, args: &[String]) {
+  let message = format!(
+    "performance: {:.9} s: git command: {}",
+    start.elapsed().as_secs_f64(),
+    quote(args)
+  );
+  print(PERFORMANCE, &message);
+}
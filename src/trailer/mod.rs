@@ -0,0 +1,459 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Lines that git adds itself. A paragraph with one of these in it is a
+/// trailer block as long as a quarter of its lines are trailers.
+const GIT_GENERATED_PREFIXES: [&str; 2] = ["Signed-off-by: ", "(cherry picked from commit "];
+
+/// The separator between a trailer's token and its value.
+const SEPARATORS: &str = ":";
+
+/// Trailers given on the command line may also be written `token=value`.
+const ARG_SEPARATORS: &str = "=:";
+
+/// A `token: value` line at the end of a commit message.
+///
+/// ## Example
+/// ```text
+/// Signed-off-by: A U Thor <author@example.com>
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trailer {
+  pub token: String,
+
+  /// The value, which keeps the newlines and indentation of any
+  /// continuation lines.
+  pub value: String,
+}
+
+impl Trailer {
+  pub fn new(token: &str, value: &str) -> Self {
+    Self {
+      token: token.trim().to_string(),
+      value: value.trim().to_string(),
+    }
+  }
+
+  /// Parses a trailer given on the command line, as `token=value`,
+  /// `token: value` or just `token` (with an empty value).
+  pub fn parse_arg(text: &str) -> Result<Self, String> {
+    match find_separator(text, ARG_SEPARATORS) {
+      Some(0) => Err(format!("empty trailer token in trailer '{}'", text)),
+      Some(i) => Ok(Trailer::new(&text[..i], &text[i + 1..])),
+      None => Ok(Trailer::new(text, "")),
+    }
+  }
+
+  /// The value on one line, with each continuation line joined to the one
+  /// before it by a space.
+  pub fn unfolded_value(&self) -> String {
+    self
+      .value
+      .split_whitespace()
+      .collect::<Vec<&str>>()
+      .join(" ")
+  }
+
+  /// Whether two trailers have the same token, ignoring case.
+  ///
+  /// Like git, only the length of the shorter token is compared, so `Sig`
+  /// matches `Signed-off-by`.
+  fn same_token(&self, other: &Trailer) -> bool {
+    let length = self.token.len().min(other.token.len());
+    self.token.as_bytes()[..length].eq_ignore_ascii_case(&other.token.as_bytes()[..length])
+  }
+
+  /// Whether two trailers have the same token and value, ignoring case.
+  fn same(&self, other: &Trailer) -> bool {
+    self.same_token(other) && self.value.eq_ignore_ascii_case(&other.value)
+  }
+}
+
+impl Display for Trailer {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}: {}", self.token, self.value)
+  }
+}
+
+/// A line of a trailer block. Blocks made mostly of trailers may have other
+/// lines in them, which are kept as they are.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Line {
+  Trailer(Trailer),
+  Other(String),
+}
+
+/// Where a new trailer goes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Where {
+  /// After the last trailer, or after the last one with the same token if
+  /// it is added because of it.
+  #[default]
+  End,
+
+  /// Before the first trailer.
+  Start,
+
+  /// Right after the last trailer with the same token.
+  After,
+
+  /// Right before the first trailer with the same token.
+  Before,
+}
+
+/// What to do when a trailer with the same token is already there.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IfExists {
+  /// Add it, unless the trailer it would go next to is the same.
+  #[default]
+  AddIfDifferentNeighbor,
+
+  /// Add it, unless the same trailer is already there.
+  AddIfDifferent,
+
+  /// Add it anyway.
+  Add,
+
+  /// Replace the trailer with the same token.
+  Replace,
+
+  /// Leave the trailers as they are.
+  DoNothing,
+}
+
+/// What to do when there is no trailer with the same token.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IfMissing {
+  #[default]
+  Add,
+  DoNothing,
+}
+
+/// How [`Message::add`] adds a trailer. The default is git's: at the end,
+/// unless the last trailer is the same one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AddOptions {
+  pub position: Where,
+  pub if_exists: IfExists,
+  pub if_missing: IfMissing,
+}
+
+/// A commit message split around its trailer block.
+///
+/// The trailer block is the last paragraph of the message, not counting
+/// trailing comments and blank lines, if every line of it is a trailer (or
+/// continues the one before it), or if it has a line git generated in it and
+/// at least a quarter of its lines are trailers. The first paragraph is the
+/// title, and is never a trailer block.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Message {
+  /// Everything before the trailer block.
+  pub head: String,
+
+  /// The lines of the trailer block. Comments in it are left out.
+  pub lines: Vec<Line>,
+
+  /// Everything after the trailer block: trailing blank lines and comments,
+  /// and a patch after a `---` line.
+  pub tail: String,
+}
+
+impl Message {
+  /// Splits a message into the text before its trailer block, the block and
+  /// the text after it.
+  ///
+  /// With `divider`, a line starting with `---` ends the message, as it does
+  /// in a patch sent by email. Commit messages are read without it.
+  pub fn parse(text: &str, divider: bool) -> Self {
+    let end = end_of_message(text, divider);
+    let start = start_of_trailers(&text[..end]);
+
+    let mut lines: Vec<Line> = Vec::new();
+    let mut continued = false;
+    for line in text[start..end].split_inclusive('\n') {
+      match lines.last_mut() {
+        // a continuation line belongs to the trailer right before it
+        Some(Line::Trailer(trailer)) if continued && line.starts_with(char::is_whitespace) => {
+          trailer.value.push('\n');
+          trailer.value.push_str(line);
+          trailer.value.truncate(trailer.value.trim_end().len());
+          continue;
+        }
+        _ => (),
+      }
+      continued = false;
+      if line.starts_with('#') {
+        continue;
+      }
+      lines.push(match find_separator(line, SEPARATORS) {
+        Some(i) if i > 0 => {
+          continued = true;
+          Line::Trailer(Trailer::new(&line[..i], &line[i + 1..]))
+        }
+        _ => Line::Other(line.trim_end_matches('\n').to_string()),
+      });
+    }
+    Self {
+      head: text[..start].to_string(),
+      lines,
+      tail: text[end..].to_string(),
+    }
+  }
+
+  /// The trailers in the trailer block, in order.
+  pub fn trailers(&self) -> impl Iterator<Item = &Trailer> {
+    self.lines.iter().filter_map(|line| match line {
+      Line::Trailer(trailer) => Some(trailer),
+      Line::Other(_) => None,
+    })
+  }
+
+  /// Adds a trailer to the block, or replaces the one with the same token,
+  /// as the options say.
+  pub fn add(&mut self, trailer: Trailer, options: &AddOptions) {
+    // trailers that go at or after the end look for a match from the end
+    let backwards = matches!(options.position, Where::End | Where::After);
+    let indices: Vec<usize> = match backwards {
+      true => (0..self.lines.len()).rev().collect(),
+      false => (0..self.lines.len()).collect(),
+    };
+    let found = indices.iter().copied().find(
+      |&i| matches!(&self.lines[i], Line::Trailer(existing) if existing.same_token(&trailer)),
+    );
+    let found = match found {
+      Some(found) => found,
+      None => {
+        if options.if_missing == IfMissing::Add {
+          match backwards {
+            true => self.lines.push(Line::Trailer(trailer)),
+            false => self.lines.insert(0, Line::Trailer(trailer)),
+          }
+        }
+        return;
+      }
+    };
+
+    // the new trailer goes next to the one found, or at the start or end
+    let neighbor = match options.position {
+      Where::After | Where::Before => found,
+      Where::End | Where::Start => indices[0],
+    };
+    let at = if backwards { neighbor + 1 } else { neighbor };
+    let same = |line: &Line| matches!(line, Line::Trailer(existing) if existing.same(&trailer));
+    let add = match options.if_exists {
+      IfExists::AddIfDifferentNeighbor => !same(&self.lines[neighbor]),
+      IfExists::AddIfDifferent => !self.lines.iter().any(same),
+      IfExists::Add | IfExists::Replace => true,
+      IfExists::DoNothing => false,
+    };
+    if add {
+      self.lines.insert(at, Line::Trailer(trailer));
+      if options.if_exists == IfExists::Replace {
+        self
+          .lines
+          .remove(if at <= found { found + 1 } else { found });
+      }
+    }
+  }
+}
+
+impl Display for Message {
+  /// Writes the message back out, with a blank line between the body and
+  /// the trailer block.
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.head)?;
+    if !ends_with_blank_line(&self.head) {
+      writeln!(f)?;
+    }
+    for line in &self.lines {
+      match line {
+        Line::Trailer(trailer) => writeln!(f, "{}", trailer)?,
+        Line::Other(text) => writeln!(f, "{}", text)?,
+      }
+    }
+    write!(f, "{}", self.tail)
+  }
+}
+
+/// Adds a `Signed-off-by` trailer for a person to a commit message, unless
+/// the message already ends with the same one.
+pub fn sign_off(message: &str, person: &str) -> String {
+  let trailer = Trailer::new("Signed-off-by", person);
+  let mut message = message.to_string();
+  if !message.is_empty() && !message.ends_with('\n') {
+    message.push('\n');
+  }
+  if message == format!("{}\n", trailer) {
+    return message;
+  }
+  let mut message = Message::parse(&message, false);
+  message.add(trailer, &AddOptions::default());
+  message.to_string()
+}
+
+/// Where the token of a trailer line ends: at the first separator, after a
+/// token of letters, digits and dashes and optionally some whitespace.
+fn find_separator(line: &str, separators: &str) -> Option<usize> {
+  let mut whitespace = false;
+  for (i, c) in line.char_indices() {
+    if separators.contains(c) {
+      return Some(i);
+    }
+    if !whitespace && (c.is_ascii_alphanumeric() || c == '-') {
+      continue;
+    }
+    if i > 0 && (c == ' ' || c == '\t') {
+      whitespace = true;
+      continue;
+    }
+    break;
+  }
+  None
+}
+
+fn is_blank_line(line: &str) -> bool {
+  line.trim().is_empty()
+}
+
+fn ends_with_blank_line(text: &str) -> bool {
+  text
+    .split_inclusive('\n')
+    .next_back()
+    .is_some_and(is_blank_line)
+}
+
+/// The offsets of the start of each line of the text.
+fn line_starts(text: &str) -> Vec<usize> {
+  text
+    .split_inclusive('\n')
+    .scan(0, |offset, line| {
+      let start = *offset;
+      *offset += line.len();
+      Some(start)
+    })
+    .collect()
+}
+
+/// Where the message proper ends: before a patch (with `divider`), and
+/// before the blank lines and comments at the end.
+fn end_of_message(text: &str, divider: bool) -> usize {
+  let mut end = text.len();
+  if divider {
+    if let Some(start) = line_starts(text).into_iter().find(|&start| {
+      text[start..]
+        .strip_prefix("---")
+        .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+    }) {
+      end = start;
+    }
+  }
+
+  // the start of the run of comments and blank lines at the end, if any
+  let mut trailing = None;
+  for start in line_starts(&text[..end]) {
+    let line = &text[start..end];
+    if line.starts_with(['#', '\n']) {
+      trailing.get_or_insert(start);
+    } else {
+      trailing = None;
+    }
+  }
+  trailing.unwrap_or(end)
+}
+
+/// Where the trailer block starts, or the end of the text if there is none.
+fn start_of_trailers(text: &str) -> usize {
+  let starts = line_starts(text);
+  let title_end = starts
+    .iter()
+    .copied()
+    .find(|&start| !text[start..].starts_with('#') && is_blank_line(line_at(text, start)))
+    .unwrap_or(text.len());
+
+  let (mut trailers, mut others, mut continuations) = (0, 0, 0);
+  let mut recognized = false;
+  let mut only_spaces = true;
+  for &start in starts.iter().rev().take_while(|&&start| start >= title_end) {
+    let line = line_at(text, start);
+    if line.starts_with('#') {
+      others += continuations;
+      continuations = 0;
+    } else if is_blank_line(line) {
+      if only_spaces {
+        continue;
+      }
+      others += continuations;
+      return match (recognized && trailers * 3 >= others) || (trailers > 0 && others == 0) {
+        true => start + line.len(),
+        false => text.len(),
+      };
+    } else {
+      only_spaces = false;
+      if GIT_GENERATED_PREFIXES.iter().any(|p| line.starts_with(p)) {
+        trailers += 1;
+        continuations = 0;
+        recognized = true;
+      } else if find_separator(line, SEPARATORS).is_some_and(|i| i > 0)
+        && !line.starts_with(char::is_whitespace)
+      {
+        trailers += 1;
+        continuations = 0;
+      } else if line.starts_with(char::is_whitespace) {
+        continuations += 1;
+      } else {
+        others += 1 + continuations;
+        continuations = 0;
+      }
+    }
+  }
+  text.len()
+}
+
+/// The line starting at an offset, with its newline.
+fn line_at(text: &str, start: usize) -> &str {
+  match text[start..].find('\n') {
+    Some(i) => &text[start..=start + i],
+    None => &text[start..],
+  }
+}
+
+impl FromStr for Where {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    match text.to_ascii_lowercase().as_str() {
+      "end" => Ok(Where::End),
+      "start" => Ok(Where::Start),
+      "after" => Ok(Where::After),
+      "before" => Ok(Where::Before),
+      _ => Err(format!("unknown value '{}' for key 'where'", text)),
+    }
+  }
+}
+
+impl FromStr for IfExists {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    match text.to_ascii_lowercase().as_str() {
+      "addifdifferentneighbor" => Ok(IfExists::AddIfDifferentNeighbor),
+      "addifdifferent" => Ok(IfExists::AddIfDifferent),
+      "add" => Ok(IfExists::Add),
+      "replace" => Ok(IfExists::Replace),
+      "donothing" => Ok(IfExists::DoNothing),
+      _ => Err(format!("unknown value '{}' for key 'ifexists'", text)),
+    }
+  }
+}
+
+impl FromStr for IfMissing {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    match text.to_ascii_lowercase().as_str() {
+      "add" => Ok(IfMissing::Add),
+      "donothing" => Ok(IfMissing::DoNothing),
+      _ => Err(format!("unknown value '{}' for key 'ifmissing'", text)),
+    }
+  }
+}
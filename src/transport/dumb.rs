@@ -0,0 +1,311 @@
+use std::collections::HashSet;
+use std::fs;
+
+use sha1::{Digest, Sha1};
+
+use super::{http::Client, negotiator::Negotiator, RemoteRef, Transport, Url};
+use crate::cancel::Cancel;
+use crate::crypto;
+use crate::object::{self, commit::Commit, mode::Mode, serializable::Unbox, tag::Tag, tree::Tree};
+use crate::progress::Progress;
+use crate::repo::Repo;
+
+/// The dumb HTTP protocol, for servers that serve the files of a repository
+/// as they are, and nothing more.
+///
+/// The refs are listed in `info/refs`, one `<hash>\t<name>` to a line, each
+/// annotated tag followed by what it peels to as `<name>^{}`; `HEAD` is
+/// read on its own. Objects are fetched by walking from the wanted commits:
+/// each object that is missing is asked for loose, from
+/// `objects/<xx>/<rest of hash>`, and if the server doesn't have it loose
+/// then from the packs listed in `objects/info/packs`. The index of each
+/// pack is fetched to find the one with the object in it, and that pack is
+/// fetched whole, bringing the rest of its objects along. The walk stops at
+/// objects the repository already had, which are taken to have all they
+/// reach.
+pub struct Dumb {
+  client: Client,
+  url: Url,
+  refs: Vec<RemoteRef>,
+
+  /// The packs the server has that haven't been fetched, once they have
+  /// been listed.
+  packs: Option<Vec<RemotePack>>,
+}
+
+/// A pack on the server.
+struct RemotePack {
+  name: String,
+
+  /// Its index, and the objects the index lists, once it has been fetched.
+  index: Option<(Vec<u8>, HashSet<String>)>,
+}
+
+impl Dumb {
+  /// A connection to the repository at `url`, whose `info/refs` says
+  /// `info_refs`.
+  pub fn new(mut client: Client, url: Url, info_refs: &[u8]) -> Result<Dumb, String> {
+    let mut refs: Vec<RemoteRef> = Vec::new();
+    for line in String::from_utf8_lossy(info_refs).lines() {
+      let (hash, name) = line
+        .split_once('\t')
+        .filter(|(hash, _)| is_hash(hash))
+        .ok_or_else(|| format!("{}: bad info/refs line: {}", url, line))?;
+      match name.strip_suffix("^{}") {
+        Some(tag) => {
+          if let Some(last) = refs.last_mut().filter(|last| last.name == tag) {
+            last.peeled = Some(hash.to_string());
+          }
+        }
+        None => refs.push(RemoteRef {
+          name: name.to_string(),
+          hash: hash.to_string(),
+          peeled: None,
+          target: None,
+        }),
+      }
+    }
+
+    // HEAD isn't in info/refs, and a server need not have it
+    let head = client.get(&url.join("HEAD"))?;
+    if head.status == 200 {
+      let head = String::from_utf8_lossy(&head.body).trim().to_string();
+      let (hash, target) = match head.strip_prefix("ref: ") {
+        Some(target) => (
+          refs
+            .iter()
+            .find(|r| r.name == target)
+            .map(|r| r.hash.clone()),
+          Some(target.to_string()),
+        ),
+        None if is_hash(&head) => (Some(head), None),
+        None => (None, None),
+      };
+      if let Some(hash) = hash {
+        refs.insert(
+          0,
+          RemoteRef {
+            name: "HEAD".to_string(),
+            hash,
+            peeled: None,
+            target,
+          },
+        );
+      }
+    }
+    Ok(Dumb {
+      client,
+      url,
+      refs,
+      packs: None,
+    })
+  }
+
+  /// Fetches an object loose, returning whether the server had it so.
+  fn fetch_loose(&mut self, repo: &Repo, hash: &str) -> Result<bool, String> {
+    let url = self
+      .url
+      .join(&format!("objects/{}/{}", &hash[..2], &hash[2..]));
+    let response = self.client.get(&url)?;
+    match response.status {
+      200 => (),
+      404 | 410 => return Ok(false),
+      status => {
+        return Err(format!(
+          "unable to fetch object {}: the server returned error {}",
+          hash, status
+        ))
+      }
+    }
+    let raw =
+      crypto::decompress(&response.body).map_err(|_| format!("object file {} is corrupt", hash))?;
+    if hex::encode(Sha1::digest(&raw)) != hash {
+      return Err(format!("object file {} is corrupt", hash));
+    }
+    repo.objects.write(hash, &raw)?;
+    Ok(true)
+  }
+
+  /// Fetches the pack with an object in it, kept with the message `keep` if
+  /// there is one, returning its name and the objects it brought, or `None`
+  /// if no pack has it.
+  fn fetch_pack(
+    &mut self,
+    repo: &Repo,
+    hash: &str,
+    keep: Option<&str>,
+  ) -> Result<Option<(String, HashSet<String>)>, String> {
+    if self.packs.is_none() {
+      let response = self.client.get(&self.url.join("objects/info/packs"))?;
+      let list = match response.status {
+        200 => String::from_utf8_lossy(&response.body).into_owned(),
+        _ => String::new(),
+      };
+      self.packs = Some(
+        list
+          .lines()
+          .filter_map(|line| line.strip_prefix("P "))
+          .filter_map(|name| name.trim().strip_suffix(".pack"))
+          .filter(|name| name.starts_with("pack-"))
+          .map(|name| RemotePack {
+            name: name.to_string(),
+            index: None,
+          })
+          .collect(),
+      );
+    }
+
+    let count = self.packs.as_ref().map_or(0, Vec::len);
+    for i in 0..count {
+      let name = self.packs.as_ref().unwrap()[i].name.clone();
+      if self.packs.as_ref().unwrap()[i].index.is_none() {
+        let index = self.fetch_file(&format!("objects/pack/{}.idx", name))?;
+        let objects = index_objects(&index)
+          .ok_or_else(|| format!("{}: pack index {}.idx is corrupt", self.url, name))?;
+        self.packs.as_mut().unwrap()[i].index = Some((index, objects));
+      }
+      if !self.packs.as_ref().unwrap()[i]
+        .index
+        .as_ref()
+        .is_some_and(|(_, objects)| objects.contains(hash))
+      {
+        continue;
+      }
+
+      let pack = self.fetch_file(&format!("objects/pack/{}.pack", name))?;
+      let (index, objects) = self.packs.as_mut().unwrap().remove(i).index.unwrap();
+      // both end with the pack's checksum, which names it
+      let checksum = pack.len().checked_sub(20).map(|at| &pack[at..]);
+      if checksum.map(hex::encode).as_deref() != name.strip_prefix("pack-")
+        || index.len() < 40
+        || checksum != Some(&index[index.len() - 40..index.len() - 20])
+      {
+        return Err(format!("{}: pack {} is corrupt", self.url, name));
+      }
+      // the index goes in last, as a pack is only seen through its index
+      let dir = repo.objects.dir().join("pack");
+      fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+      let keep = keep.map(|message| format!("{}\n", message).into_bytes());
+      let files = [
+        ("pack", Some(&pack)),
+        ("keep", keep.as_ref()),
+        ("idx", Some(&index)),
+      ];
+      for (ending, data) in files
+        .into_iter()
+        .filter_map(|(ending, data)| Some((ending, data?)))
+      {
+        let path = dir.join(format!("{}.{}", name, ending));
+        let temp = dir.join(format!("tmp_{}.{}", name, ending));
+        fs::write(&temp, data)
+          .and_then(|_| fs::rename(&temp, &path))
+          .map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            format!("unable to write {}: {}", path.display(), e)
+          })?;
+      }
+      return Ok(Some((name, objects)));
+    }
+    Ok(None)
+  }
+
+  /// Fetches a file of the repository, which must be there.
+  fn fetch_file(&mut self, path: &str) -> Result<Vec<u8>, String> {
+    let response = self.client.get(&self.url.join(path))?;
+    match response.status {
+      200 => Ok(response.body),
+      status => Err(format!(
+        "unable to fetch {}: the server returned error {}",
+        self.url.join(path),
+        status
+      )),
+    }
+  }
+}
+
+impl Transport for Dumb {
+  fn refs(&mut self) -> Result<Vec<RemoteRef>, String> {
+    Ok(self.refs.clone())
+  }
+
+  fn fetch(
+    &mut self,
+    repo: &Repo,
+    wants: &[String],
+    _: &mut Negotiator,
+    keep: Option<&str>,
+    progress: &mut dyn Progress,
+    cancel: &Cancel,
+  ) -> Result<Vec<String>, String> {
+    // the objects fetched so far, which unlike the ones the repository
+    // already had must be walked from
+    let mut fetched: HashSet<String> = HashSet::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut packs = Vec::new();
+    let mut stack: Vec<String> = wants.iter().rev().cloned().collect();
+    progress.start("Fetching objects", None);
+
+    while let Some(hash) = stack.pop() {
+      cancel.check()?;
+      if !seen.insert(hash.clone()) {
+        continue;
+      }
+      if repo.objects.exists(&hash) {
+        if !fetched.contains(&hash) {
+          continue;
+        }
+      } else if self.fetch_loose(repo, &hash)? {
+        fetched.insert(hash.clone());
+      } else {
+        match self.fetch_pack(repo, &hash, keep)? {
+          Some((name, objects)) => {
+            packs.push(name);
+            fetched.extend(objects);
+          }
+          None => return Err(format!("Unable to find {} under {}", hash, self.url)),
+        }
+      }
+      progress.update(seen.len() as u64);
+
+      let object = object::read(repo, &hash, None)?;
+      match object.format().as_str() {
+        "commit" => {
+          let commit = object.unbox::<Commit>()?;
+          stack.extend(commit.parents().iter().rev().cloned());
+          stack.push(commit.tree().to_string());
+        }
+        "tree" => {
+          let tree = object.unbox::<Tree>()?;
+          for entry in tree.entries().iter().rev() {
+            // submodule commits live in another repository
+            if entry.mode != Mode::Gitlink {
+              stack.push(entry.hash.clone());
+            }
+          }
+        }
+        "tag" => {
+          let tag = object.unbox::<Tag>()?;
+          stack.extend(tag.get("object").cloned());
+        }
+        _ => (),
+      }
+    }
+    progress.finish();
+    Ok(packs)
+  }
+}
+
+/// The objects listed in a version 2 pack index, or `None` if it isn't one.
+fn index_objects(index: &[u8]) -> Option<HashSet<String>> {
+  const TABLES: usize = 8 + 256 * 4;
+  if !index.starts_with(b"\xfftOc\0\0\0\x02") || index.len() < TABLES {
+    return None;
+  }
+  let count = u32::from_be_bytes(index[TABLES - 4..TABLES].try_into().ok()?) as usize;
+  let ids = index.get(TABLES..TABLES + count * 20)?;
+  Some(ids.chunks(20).map(hex::encode).collect())
+}
+
+fn is_hash(text: &str) -> bool {
+  text.len() == 40 && text.bytes().all(|c| c.is_ascii_hexdigit())
+}
@@ -0,0 +1,155 @@
+use std::io::{self, Read, Write};
+
+use super::{negotiator::Negotiator, pkt_line, RemoteRef};
+use crate::cancel::Cancel;
+use crate::progress::Progress;
+use crate::repack;
+use crate::repo::Repo;
+
+/// How many commits the client tells the server it has before waiting to
+/// hear whether any were in common.
+const HAVES_PER_BATCH: usize = 32;
+
+/// Reads the refs a server sends at the start of the pack protocol, and
+/// the capabilities it sends with the first of them.
+pub fn read_refs(input: &mut impl Read) -> Result<(Vec<RemoteRef>, Vec<String>), String> {
+  let mut refs: Vec<RemoteRef> = Vec::new();
+  let mut capabilities: Vec<String> = Vec::new();
+  while let Some(line) = read_line(input)? {
+    let (line, offered) = match line.split_once('\0') {
+      Some((line, offered)) => (line, Some(offered)),
+      None => (line.as_str(), None),
+    };
+    if let Some(offered) = offered {
+      capabilities = offered.split(' ').map(str::to_string).collect();
+    }
+    let (hash, name) = line
+      .split_once(' ')
+      .filter(|(hash, _)| hash.len() == 40)
+      .ok_or_else(|| format!("protocol error: unexpected '{}'", line))?;
+    match name.strip_suffix("^{}") {
+      Some("capabilities") => (),
+      Some(tag) => {
+        if let Some(last) = refs.last_mut().filter(|last| last.name == tag) {
+          last.peeled = Some(hash.to_string());
+        }
+      }
+      None => refs.push(RemoteRef {
+        name: name.to_string(),
+        hash: hash.to_string(),
+        peeled: None,
+        target: None,
+      }),
+    }
+  }
+  for capability in &capabilities {
+    if let Some((name, target)) = capability
+      .strip_prefix("symref=")
+      .and_then(|symref| symref.split_once(':'))
+    {
+      if let Some(symref) = refs.iter_mut().find(|r| r.name == name) {
+        symref.target = Some(target.to_string());
+      }
+    }
+  }
+  Ok((refs, capabilities))
+}
+
+/// Fetches the objects `wants` reach from a server that has sent its refs
+/// and `capabilities`, into a new pack in `repo`.
+///
+/// The commits the negotiator picks are sent in batches until the server
+/// has one of them too; the server then leaves out of the pack what that
+/// commit reaches. A server that can (`include-tag`) adds the annotated
+/// tags that point into the pack.
+///
+/// Returns the checksum of the pack, which is kept with the message `keep`
+/// if there is one. Once `cancel` is cancelled, the fetch stops and nothing
+/// of the pack is left behind.
+#[allow(clippy::too_many_arguments)]
+pub fn fetch(
+  repo: &Repo,
+  input: &mut impl Read,
+  output: &mut impl Write,
+  capabilities: &[String],
+  wants: &[String],
+  negotiator: &mut Negotiator,
+  keep: Option<&str>,
+  progress: &mut dyn Progress,
+  cancel: &Cancel,
+) -> Result<String, String> {
+  let error = |e: io::Error| format!("unable to talk to the remote ({})", e);
+  let offered = |name: &str| capabilities.iter().any(|c| c == name);
+  let band = match (offered("side-band-64k"), offered("side-band")) {
+    (true, _) => "side-band-64k",
+    (_, true) => "side-band",
+    _ => return Err("the remote does not support side-band".to_string()),
+  };
+  let mut asked: Vec<&str> = vec![band];
+  asked.extend(
+    ["ofs-delta", "no-progress", "include-tag"]
+      .into_iter()
+      .filter(|name| offered(name)),
+  );
+  let agent = format!("agent=git/git-rs-{}", env!("CARGO_PKG_VERSION"));
+  asked.push(&agent);
+  for (i, want) in wants.iter().enumerate() {
+    let line = match i {
+      0 => format!("want {} {}", want, asked.join(" ")),
+      _ => format!("want {}", want),
+    };
+    pkt_line::write_line(output, &line).map_err(error)?;
+  }
+  pkt_line::write(output, None).map_err(error)?;
+
+  loop {
+    cancel.check()?;
+    let batch: Vec<String> = negotiator.by_ref().take(HAVES_PER_BATCH).collect();
+    if batch.is_empty() {
+      break;
+    }
+    for have in batch {
+      pkt_line::write_line(output, &format!("have {}", have)).map_err(error)?;
+    }
+    pkt_line::write(output, None).map_err(error)?;
+    output.flush().map_err(error)?;
+    match read_line(input)?.as_deref() {
+      Some("NAK") => (),
+      Some(ack) if ack.starts_with("ACK ") => break,
+      _ => return Err("protocol error: expected ACK/NAK".to_string()),
+    }
+  }
+  pkt_line::write_line(output, "done").map_err(error)?;
+  output.flush().map_err(error)?;
+
+  // the pack comes on band 1, after the rest of the answers to the haves:
+  // an ACK for each the server has too, or a NAK if it had none
+  let mut pack = Vec::new();
+  while let Some(packet) = pkt_line::read(input).map_err(error)? {
+    cancel.check()?;
+    match packet.split_first() {
+      Some((1, data)) => pack.extend_from_slice(data),
+      Some((2, message)) => eprint!("remote: {}", String::from_utf8_lossy(message)),
+      Some((3, message)) => {
+        let message = String::from_utf8_lossy(message);
+        return Err(format!("remote error: {}", message.trim_end()));
+      }
+      _ if pack.is_empty() && (packet.starts_with(b"ACK ") || packet == b"NAK\n") => (),
+      _ => return Err("protocol error: bad band".to_string()),
+    }
+  }
+  repack::index::write(repo, &pack, keep, progress, cancel)
+}
+
+/// Reads a pkt-line of text, turning an `ERR` line into the error it
+/// reports.
+fn read_line(input: &mut impl Read) -> Result<Option<String>, String> {
+  let line = pkt_line::read_line(input).map_err(|e| match e.kind() {
+    io::ErrorKind::UnexpectedEof => "the remote end hung up unexpectedly".to_string(),
+    _ => format!("unable to talk to the remote ({})", e),
+  })?;
+  match line.as_deref().and_then(|line| line.strip_prefix("ERR ")) {
+    Some(message) => Err(format!("remote error: {}", message)),
+    None => Ok(line),
+  }
+}
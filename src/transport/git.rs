@@ -0,0 +1,119 @@
+use std::io::{BufReader, BufWriter, Write};
+use std::net::TcpStream;
+
+use super::send_pack::{self, Command, Report, Signed};
+use super::{fetch_pack, negotiator::Negotiator, pkt_line, RemoteRef, Transport, Url};
+use crate::cancel::Cancel;
+use crate::progress::Progress;
+use crate::repo::Repo;
+use crate::trace;
+
+/// The port `git://` URLs are served on unless they say otherwise.
+pub const DEFAULT_PORT: u16 = 9418;
+
+/// The services a client asks for: fetching, and pushing.
+pub const UPLOAD_PACK: &str = "git-upload-pack";
+pub const RECEIVE_PACK: &str = "git-receive-pack";
+
+/// The `git://` protocol, spoken by `daemon`: the pack protocol over a
+/// plain TCP connection, without authentication or encryption.
+///
+/// The client opens with a request naming the service and the path of the
+/// repository, eg. `git-upload-pack /project.git\0host=example.com\0`, and
+/// the server answers with its refs, as `upload-pack` does. Pushing asks
+/// for `git-receive-pack` instead.
+pub struct Git {
+  url: Url,
+  reader: BufReader<TcpStream>,
+  writer: BufWriter<TcpStream>,
+  refs: Vec<RemoteRef>,
+  capabilities: Vec<String>,
+}
+
+impl Git {
+  /// Connects to the daemon serving `url` for a service, and reads its
+  /// refs.
+  pub fn connect(url: &Url, service: &str) -> Result<Git, String> {
+    let address = url.address(DEFAULT_PORT);
+    trace::print(trace::TRACE, &format!("git: connecting to {}", address));
+    let stream = TcpStream::connect(&address)
+      .map_err(|e| format!("unable to connect to {}: {}", url.host, e))?;
+    let clone = stream
+      .try_clone()
+      .map_err(|e| format!("unable to connect to {}: {}", url.host, e))?;
+    let mut writer = BufWriter::new(stream);
+    let host = match url.port {
+      Some(port) => format!("{}:{}", url.host, port),
+      None => url.host.clone(),
+    };
+    let request = format!("{} {}\0host={}\0", service, url.path, host);
+    pkt_line::write(&mut writer, Some(request.as_bytes()))
+      .and_then(|_| writer.flush())
+      .map_err(|e| format!("unable to talk to {} ({})", url.host, e))?;
+    let mut reader = BufReader::new(clone);
+    let (refs, capabilities) = fetch_pack::read_refs(&mut reader)?;
+    Ok(Git {
+      url: url.clone(),
+      reader,
+      writer,
+      refs,
+      capabilities,
+    })
+  }
+}
+
+impl Transport for Git {
+  fn refs(&mut self) -> Result<Vec<RemoteRef>, String> {
+    Ok(self.refs.clone())
+  }
+
+  fn fetch(
+    &mut self,
+    repo: &Repo,
+    wants: &[String],
+    negotiator: &mut Negotiator,
+    keep: Option<&str>,
+    progress: &mut dyn Progress,
+    cancel: &Cancel,
+  ) -> Result<Vec<String>, String> {
+    let name = fetch_pack::fetch(
+      repo,
+      &mut self.reader,
+      &mut self.writer,
+      &self.capabilities,
+      wants,
+      negotiator,
+      keep,
+      progress,
+      cancel,
+    )?;
+    Ok(vec![format!("pack-{}", name)])
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn push(
+    &mut self,
+    repo: &Repo,
+    commands: &[Command],
+    push_options: &[String],
+    signed: Signed,
+    atomic: bool,
+    progress: &mut dyn Progress,
+    cancel: &Cancel,
+  ) -> Result<Report, String> {
+    send_pack::push(
+      repo,
+      &mut self.reader,
+      &mut self.writer,
+      &self.url,
+      &self.capabilities,
+      &self.refs,
+      commands,
+      push_options,
+      signed,
+      atomic,
+      progress,
+      cancel,
+    )
+  }
+}
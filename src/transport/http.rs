@@ -0,0 +1,542 @@
+#[cfg(feature = "https")]
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use super::{dumb::Dumb, Transport, Url};
+use crate::credential::{Credential, Credentials};
+use crate::env;
+use crate::repo::Repo;
+use crate::trace;
+
+/// The most redirects followed for one request.
+const MAX_REDIRECTS: usize = 5;
+
+/// The content type a smart server answers the first request with.
+const SMART_ADVERTISEMENT: &str = "application/x-git-upload-pack-advertisement";
+
+/// What a server sent back.
+pub struct Response {
+  pub status: u16,
+
+  /// The headers, with their names in lower case.
+  pub headers: Vec<(String, String)>,
+  pub body: Vec<u8>,
+
+  /// Where the response came from, after any redirects.
+  pub url: Url,
+}
+
+impl Response {
+  /// The value of a header, whose name is given in lower case.
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self
+      .headers
+      .iter()
+      .find(|(key, _)| key == name)
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+/// Connects to the repository at an `http://` or `https://` URL.
+///
+/// The first request is for the refs as a smart server advertises them,
+/// `info/refs?service=git-upload-pack`. The smart protocol isn't spoken yet,
+/// so when a server answers as a smart one the refs are asked for again the
+/// dumb way, as plain `info/refs`, which a server that serves the files of
+/// the repository as they are (once `update-server-info` has written them)
+/// answers. A dumb server answers the first request that way already, as
+/// it takes no notice of the query.
+pub fn connect(repo: &Repo, url: &Url) -> Result<Box<dyn Transport>, String> {
+  let mut client = Client::new(repo, url);
+  let response = client.get(&url.join("info/refs?service=git-upload-pack"))?;
+  check(&response, url)?;
+  let base = base(&response, "info/refs?service=git-upload-pack").unwrap_or_else(|| url.clone());
+  let response = match response.header("content-type") {
+    Some(SMART_ADVERTISEMENT) => {
+      let response = client.get(&base.join("info/refs"))?;
+      check(&response, url)?;
+      if response.header("content-type") == Some(SMART_ADVERTISEMENT) {
+        return Err(format!(
+          "{} only speaks the smart HTTP protocol, which is not supported",
+          url
+        ));
+      }
+      response
+    }
+    _ => response,
+  };
+  Ok(Box::new(Dumb::new(client, base, &response.body)?))
+}
+
+/// Checks that a request for something of the repository at `url` worked.
+fn check(response: &Response, url: &Url) -> Result<(), String> {
+  match response.status {
+    200 => Ok(()),
+    404 => Err(format!("repository '{}' not found", url)),
+    status => Err(format!(
+      "unable to access '{}': The requested URL returned error: {}",
+      url, status
+    )),
+  }
+}
+
+/// The URL of the repository, if the request for `file` in it was
+/// redirected, so the rest of the files are asked for where they are.
+fn base(response: &Response, file: &str) -> Option<Url> {
+  let path = response.url.path.strip_suffix(file)?;
+  Some(Url {
+    path: path.trim_end_matches('/').to_string(),
+    ..response.url.clone()
+  })
+}
+
+/// Sends requests to the server a repository is on, with the credentials
+/// it asks for.
+///
+/// A request is sent without any, unless the URL has both a username and
+/// a password in it or a credential has already worked for the server.
+/// If the server answers `401 Unauthorized`, the username and password are
+/// looked for with the credential helpers, and the request is sent once
+/// more with them (as HTTP Basic authentication). A credential that works
+/// is kept, and sent with each request after that; one that is turned down
+/// is an error.
+pub struct Client {
+  /// The repository, for saying which one couldn't be got at.
+  url: Url,
+  settings: Settings,
+  credentials: Credentials,
+}
+
+impl Client {
+  pub fn new(repo: &Repo, url: &Url) -> Client {
+    Client {
+      url: url.clone(),
+      settings: Settings::new(repo),
+      credentials: Credentials::new(repo),
+    }
+  }
+
+  /// Sends a `GET` request, following redirects.
+  pub fn get(&mut self, url: &Url) -> Result<Response, String> {
+    let mut url = url.clone();
+    for _ in 0..=MAX_REDIRECTS {
+      let response = self.send(&url, "GET")?;
+      let location = match (response.status, response.header("location")) {
+        (301 | 302 | 303 | 307 | 308, Some(location)) => location,
+        _ => return Ok(response),
+      };
+      url = match location.starts_with('/') {
+        true => Url {
+          path: location.to_string(),
+          ..url
+        },
+        false => Url::parse(location)?,
+      };
+    }
+    Err(format!("too many redirects for '{}'", url))
+  }
+
+  /// Sends a request, and again with a credential if the server asks for
+  /// one.
+  fn send(&mut self, url: &Url, method: &str) -> Result<Response, String> {
+    let mut credential = Credential::for_url(url);
+    if !credential.is_complete() {
+      credential = self.credentials.cached(&credential).unwrap_or(credential);
+    }
+    let sent = credential.is_complete();
+    let settings = &self.settings;
+    let mut response = request(settings, url, method, Some(&credential).filter(|_| sent))?;
+    if response.status == 401 && !sent {
+      self.credentials.fill(&mut credential)?;
+      response = request(settings, url, method, Some(&credential))?;
+    }
+    match response.status {
+      401 => {
+        self.credentials.reject(&credential);
+        Err(format!("Authentication failed for '{}'", self.url))
+      }
+      _ => {
+        self.credentials.approve(&credential);
+        Ok(response)
+      }
+    }
+  }
+}
+
+/// How requests are sent, as the `http` section of the config and the
+/// environment say.
+///
+/// ### Example
+/// ```text
+/// [http]
+///   proxy = http://proxy.example.com:3128
+///   sslCAInfo = ~/certs/example-ca.pem
+///   extraHeader = X-Team: tools
+/// ```
+struct Settings {
+  /// `http.proxy`, which wins over the environment's proxies. Set but
+  /// empty, it says to use no proxy.
+  proxy: Option<String>,
+
+  /// The hosts that are reached without a proxy (`NO_PROXY`), with their
+  /// subdomains, or `*` for every host.
+  no_proxy: Vec<String>,
+
+  /// The file of certificates of the authorities that servers are trusted
+  /// on, instead of the system's (`GIT_SSL_CAINFO` or `http.sslCAInfo`).
+  #[cfg_attr(not(feature = "https"), allow(dead_code))]
+  ca_info: Option<PathBuf>,
+
+  /// Headers sent with every request (`http.extraHeader`). An empty one
+  /// clears those given before it.
+  extra_headers: Vec<String>,
+}
+
+impl Settings {
+  fn new(repo: &Repo) -> Settings {
+    let section = repo
+      .config
+      .as_ref()
+      .and_then(|config| config.section(Some("http")));
+    let values = |key: &str| -> Vec<String> {
+      section
+        .iter()
+        .flat_map(|section| section.iter())
+        .filter(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.to_string())
+        .collect()
+    };
+    let no_proxy = env_var(&["no_proxy", "NO_PROXY"]).unwrap_or_default();
+    let ca_info = env_var(&["GIT_SSL_CAINFO"])
+      .or_else(|| values("sslCAInfo").pop())
+      .map(|path| match path.strip_prefix("~/") {
+        Some(rest) => Path::new(&std::env::var("HOME").unwrap_or_default()).join(rest),
+        None => PathBuf::from(path),
+      });
+    let mut extra_headers: Vec<String> = Vec::new();
+    for header in values("extraHeader") {
+      match header.is_empty() {
+        true => extra_headers.clear(),
+        false => extra_headers.push(header),
+      }
+    }
+    Settings {
+      proxy: values("proxy").pop(),
+      no_proxy: no_proxy
+        .split(',')
+        .map(|host| host.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect(),
+      ca_info,
+      extra_headers,
+    }
+  }
+
+  /// The proxy to reach the server at `url` through, if any: `http.proxy`,
+  /// or else `https_proxy` or `http_proxy` (whichever is for the URL's
+  /// scheme) or `all_proxy`, unless `NO_PROXY` names the host. Only HTTP
+  /// proxies are supported; one given without a scheme is taken to be one,
+  /// on port 1080 if it says no other.
+  fn proxy(&self, url: &Url) -> Result<Option<Url>, String> {
+    let proxy = self.proxy.clone().or_else(|| match url.scheme.as_str() {
+      "https" => env_var(&["https_proxy", "HTTPS_PROXY", "all_proxy", "ALL_PROXY"]),
+      _ => env_var(&["http_proxy", "all_proxy", "ALL_PROXY"]),
+    });
+    let proxy = match proxy.filter(|proxy| !proxy.is_empty()) {
+      Some(proxy) => proxy,
+      None => return Ok(None),
+    };
+    let host = url.host.to_ascii_lowercase();
+    if self
+      .no_proxy
+      .iter()
+      .any(|skipped| skipped == "*" || host == *skipped || host.ends_with(&format!(".{}", skipped)))
+    {
+      return Ok(None);
+    }
+    let proxy = match proxy.contains("://") {
+      true => Url::parse(&proxy)?,
+      false => Url::parse(&format!("http://{}", proxy))?,
+    };
+    match proxy.scheme.as_str() {
+      "http" => Ok(Some(proxy)),
+      scheme => Err(format!(
+        "unable to access '{}': {} proxies are not supported",
+        url, scheme
+      )),
+    }
+  }
+}
+
+/// The first of the environment variables `names` that is set.
+fn env_var(names: &[&str]) -> Option<String> {
+  names
+    .iter()
+    .find_map(|name| env::var(name))
+    .and_then(|value| value.into_string().ok())
+}
+
+/// A connection to a server, in the clear or over TLS.
+trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+/// Opens a connection to the server at `url`, through `proxy` if there is
+/// one, and over TLS for `https`. An `https` URL is reached through a tunnel the
+/// proxy is asked to open (with `CONNECT`), so the proxy never sees what
+/// goes through it.
+fn open(settings: &Settings, url: &Url, proxy: Option<&Url>) -> Result<Box<dyn Stream>, String> {
+  let address = match proxy {
+    Some(proxy) => proxy.address(1080),
+    None => url.address(if url.scheme == "https" { 443 } else { 80 }),
+  };
+  let error = |e: io::Error| format!("unable to access '{}': {}", url, e);
+  let mut stream = TcpStream::connect(&address).map_err(error)?;
+  if url.scheme == "http" {
+    return Ok(Box::new(stream));
+  }
+
+  if let Some(proxy) = proxy {
+    let target = url.address(443);
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", target, target);
+    if let Some(authorization) = basic(&Credential::for_url(proxy)) {
+      request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    request.push_str("\r\n");
+    stream
+      .write_all(request.as_bytes())
+      .and_then(|_| stream.flush())
+      .map_err(error)?;
+    // nothing comes after the proxy's answer until the TLS handshake starts,
+    // so no more is read than the answer
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(error)?;
+    let status = line
+      .split_whitespace()
+      .nth(1)
+      .unwrap_or_default()
+      .to_string();
+    while !line.trim_end().is_empty() {
+      line.clear();
+      if reader.read_line(&mut line).map_err(error)? == 0 {
+        break;
+      }
+    }
+    if status != "200" {
+      return Err(format!(
+        "unable to access '{}': CONNECT tunnel failed, response {}",
+        url, status
+      ));
+    }
+    stream = reader.into_inner();
+  }
+  tls(settings, url, stream)
+}
+
+/// Starts TLS on a connection to the server at `url`, checking that its
+/// certificate is for the host and signed by an authority that is trusted.
+#[cfg(feature = "https")]
+fn tls(settings: &Settings, url: &Url, stream: TcpStream) -> Result<Box<dyn Stream>, String> {
+  use native_tls::{Certificate, TlsConnector};
+
+  let error = |e: &dyn std::fmt::Display| format!("unable to access '{}': {}", url, e);
+  let mut builder = TlsConnector::builder();
+  if let Some(path) = &settings.ca_info {
+    let pem = fs::read_to_string(path).map_err(|e| {
+      error(&format!(
+        "error setting certificate file: {} ({})",
+        path.display(),
+        e
+      ))
+    })?;
+    builder.disable_built_in_roots(true);
+    let mut rest = pem.as_str();
+    while let Some(start) = rest.find("-----BEGIN CERTIFICATE-----") {
+      let end = rest[start..]
+        .find("-----END CERTIFICATE-----")
+        .map_or(rest.len(), |end| {
+          start + end + "-----END CERTIFICATE-----".len()
+        });
+      let certificate = Certificate::from_pem(&rest.as_bytes()[start..end]).map_err(|e| {
+        error(&format!(
+          "error setting certificate file: {} ({})",
+          path.display(),
+          e
+        ))
+      })?;
+      builder.add_root_certificate(certificate);
+      rest = &rest[end..];
+    }
+  }
+  let connector = builder.build().map_err(|e| error(&e))?;
+  // an IPv6 address is checked against the certificate without brackets
+  let host = url.host.trim_start_matches('[').trim_end_matches(']');
+  let stream = connector.connect(host, stream).map_err(|e| error(&e))?;
+  Ok(Box::new(stream))
+}
+
+#[cfg(not(feature = "https"))]
+fn tls(_: &Settings, url: &Url, _: TcpStream) -> Result<Box<dyn Stream>, String> {
+  Err(format!(
+    "unable to access '{}': https is not supported (git-rs was built without the https feature)",
+    url
+  ))
+}
+
+/// The `Authorization` header for a credential, if it is complete.
+fn basic(credential: &Credential) -> Option<String> {
+  let username = credential.username.as_ref()?;
+  let password = credential.password.as_ref()?;
+  Some(format!(
+    "Basic {}",
+    base64(format!("{}:{}", username, password).as_bytes())
+  ))
+}
+
+/// Sends a request and reads the response, over a connection of its own.
+fn request(
+  settings: &Settings,
+  url: &Url,
+  method: &str,
+  credential: Option<&Credential>,
+) -> Result<Response, String> {
+  let error = |e: io::Error| format!("unable to access '{}': {}", url, e);
+  if url.scheme != "http" && url.scheme != "https" {
+    return Err(format!(
+      "unable to access '{}': {} is not supported",
+      url, url.scheme
+    ));
+  }
+  let proxy = settings.proxy(url)?;
+  match &proxy {
+    Some(proxy) => trace::print(
+      trace::TRACE,
+      &format!("http: {} {} through {}", method, url, proxy),
+    ),
+    None => trace::print(trace::TRACE, &format!("http: {} {}", method, url)),
+  }
+  let mut stream = open(settings, url, proxy.as_ref())?;
+
+  let mut headers = String::new();
+  if let Some(authorization) = credential.and_then(basic) {
+    headers.push_str(&format!("Authorization: {}\r\n", authorization));
+  }
+  // a proxy that isn't tunnelling is asked for the whole URL
+  let target = match &proxy {
+    Some(proxy) if url.scheme == "http" => {
+      if let Some(authorization) = basic(&Credential::for_url(proxy)) {
+        headers.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+      }
+      format!("http://{}{}", url.host_and_port(), url.path)
+    }
+    _ => url.path.clone(),
+  };
+  for header in &settings.extra_headers {
+    headers.push_str(&format!("{}\r\n", header));
+  }
+  write!(
+    stream,
+    "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: git/git-rs-{}\r\nAccept: */*\r\n\
+     {}Pragma: no-cache\r\nConnection: close\r\n\r\n",
+    method,
+    target,
+    url.host_and_port(),
+    env!("CARGO_PKG_VERSION"),
+    headers
+  )
+  .and_then(|_| stream.flush())
+  .map_err(error)?;
+
+  let mut reader = BufReader::new(stream);
+  let mut line = String::new();
+  reader.read_line(&mut line).map_err(error)?;
+  let status = line
+    .split_whitespace()
+    .nth(1)
+    .and_then(|status| status.parse().ok())
+    .ok_or_else(|| format!("unable to access '{}': bad response from server", url))?;
+  let mut headers = Vec::new();
+  loop {
+    line.clear();
+    reader.read_line(&mut line).map_err(error)?;
+    let header = line.trim_end();
+    if header.is_empty() {
+      break;
+    }
+    if let Some((name, value)) = header.split_once(':') {
+      headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+  }
+  let mut response = Response {
+    status,
+    headers,
+    body: Vec::new(),
+    url: url.clone(),
+  };
+
+  let chunked = response
+    .header("transfer-encoding")
+    .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+  let length = response
+    .header("content-length")
+    .and_then(|length| length.parse::<u64>().ok());
+  match (chunked, length) {
+    (true, _) => read_chunked(&mut reader, &mut response.body),
+    (false, Some(length)) => (&mut reader)
+      .take(length)
+      .read_to_end(&mut response.body)
+      .map(|_| ()),
+    (false, None) => reader.read_to_end(&mut response.body).map(|_| ()),
+  }
+  .map_err(error)?;
+  Ok(response)
+}
+
+/// Reads a body sent in chunks, each preceded by its length in hex.
+fn read_chunked(reader: &mut impl BufRead, body: &mut Vec<u8>) -> io::Result<()> {
+  let mut line = String::new();
+  loop {
+    line.clear();
+    reader.read_line(&mut line)?;
+    let size = line.trim_end().split(';').next().unwrap_or("");
+    let size = usize::from_str_radix(size, 16)
+      .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad chunk size"))?;
+    if size == 0 {
+      break;
+    }
+    let start = body.len();
+    body.resize(start + size, 0);
+    reader.read_exact(&mut body[start..])?;
+    line.clear();
+    reader.read_line(&mut line)?;
+  }
+  // what trails the last chunk, up to a blank line
+  loop {
+    line.clear();
+    if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+      break;
+    }
+  }
+  Ok(())
+}
+
+/// Encodes bytes as standard base64, with padding.
+fn base64(data: &[u8]) -> String {
+  const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+  for chunk in data.chunks(3) {
+    let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+      bits | (byte as u32) << (16 - 8 * i)
+    });
+    for i in 0..4 {
+      match i <= chunk.len() {
+        true => text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char),
+        false => text.push('='),
+      }
+    }
+  }
+  text
+}
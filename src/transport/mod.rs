@@ -0,0 +1,225 @@
+pub mod dumb;
+pub mod fetch_pack;
+pub mod git;
+pub mod http;
+pub mod negotiator;
+pub mod pkt_line;
+pub mod receive_pack;
+pub mod send_pack;
+pub mod upload_pack;
+
+use std::fmt::{self, Display};
+
+use crate::cancel::Cancel;
+use crate::progress::Progress;
+use crate::repo::Repo;
+
+use negotiator::Negotiator;
+use send_pack::{Command, Report, Signed};
+
+/// A ref as a remote advertises it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteRef {
+  /// The ref, named in full (`HEAD`, `refs/heads/master`...).
+  pub name: String,
+
+  /// The object it points at.
+  pub hash: String,
+
+  /// For an annotated tag, what it peels to.
+  pub peeled: Option<String>,
+
+  /// For a symbolic ref (usually `HEAD`), the ref it stands for.
+  pub target: Option<String>,
+}
+
+/// A way of talking to another repository: listing its refs, fetching the
+/// objects they reach, and pushing to it.
+///
+/// Each kind of URL has its own: [`dumb::Dumb`] for HTTP servers that only
+/// serve the files of a repository as they are, and [`git::Git`] for
+/// `git://` URLs.
+pub trait Transport {
+  /// The refs of the remote, with `HEAD` first if it has one.
+  fn refs(&mut self) -> Result<Vec<RemoteRef>, String>;
+
+  /// Fetches the objects the remote has that `wants` reach and `repo` does
+  /// not, writing them to `repo`. Transports that can tell the remote what
+  /// `repo` has do so with the commits `negotiator` picks.
+  ///
+  /// Returns the names of the packs that came in (eg. `pack-<checksum>`),
+  /// which with `keep` are kept with that message in their `.keep` files
+  /// (see [`repack::index::write`](crate::repack::index::write)). Once
+  /// `cancel` is cancelled, the fetch stops, leaving no half-written pack.
+  fn fetch(
+    &mut self,
+    repo: &Repo,
+    wants: &[String],
+    negotiator: &mut Negotiator,
+    keep: Option<&str>,
+    progress: &mut dyn Progress,
+    cancel: &Cancel,
+  ) -> Result<Vec<String>, String>;
+
+  /// Asks the remote to update its refs as `commands` say (all of them or
+  /// none, if `atomic`), sending it the objects they need from `repo`, and
+  /// returns what it said of each.
+  /// Only transports opened with [`open_push`] can push.
+  #[allow(clippy::too_many_arguments)]
+  fn push(
+    &mut self,
+    _repo: &Repo,
+    _commands: &[Command],
+    _push_options: &[String],
+    _signed: Signed,
+    _atomic: bool,
+    _progress: &mut dyn Progress,
+    _cancel: &Cancel,
+  ) -> Result<Report, String> {
+    Err("the remote can't be pushed to this way".to_string())
+  }
+}
+
+/// Opens a transport to the repository at `url`, for `repo` to fetch into.
+pub fn open(repo: &Repo, url: &str) -> Result<Box<dyn Transport>, String> {
+  let url = Url::parse(url)?;
+  match url.scheme.as_str() {
+    "http" | "https" => http::connect(repo, &url),
+    "git" => Ok(Box::new(git::Git::connect(&url, git::UPLOAD_PACK)?)),
+    scheme => Err(format!("Unable to find remote helper for '{}'", scheme)),
+  }
+}
+
+/// Opens a transport to the repository at `url`, to push to it.
+/// Only `git://` URLs can be pushed to, as a dumb HTTP server has no way
+/// of taking what is pushed.
+pub fn open_push(url: &str) -> Result<Box<dyn Transport>, String> {
+  let url = Url::parse(url)?;
+  match url.scheme.as_str() {
+    "git" => Ok(Box::new(git::Git::connect(&url, git::RECEIVE_PACK)?)),
+    "http" | "https" => Err(format!("pushing over HTTP is not supported: {}", url)),
+    scheme => Err(format!("Unable to find remote helper for '{}'", scheme)),
+  }
+}
+
+/// A URL as git takes them, eg. `https://user@example.com:8080/repo.git`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Url {
+  pub scheme: String,
+  pub user: Option<String>,
+  pub password: Option<String>,
+  pub host: String,
+
+  /// The port, if the URL gives one.
+  pub port: Option<u16>,
+
+  /// The path, starting with `/`, with any query left on it.
+  pub path: String,
+}
+
+impl Url {
+  /// Parses a URL. Percent escapes are undone in the user and password, but
+  /// left as they are in the path.
+  pub fn parse(url: &str) -> Result<Url, String> {
+    let invalid = || format!("'{}' is not a valid URL", url);
+    let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+    let (authority, path) = match rest.find('/') {
+      Some(slash) => (&rest[..slash], &rest[slash..]),
+      None => (rest, "/"),
+    };
+    let (userinfo, host_port) = match authority.rsplit_once('@') {
+      Some((userinfo, host_port)) => (Some(userinfo), host_port),
+      None => (None, authority),
+    };
+    let (user, password) = match userinfo.map(|userinfo| userinfo.split_once(':')) {
+      None => (None, None),
+      Some(None) => (userinfo.map(decode), None),
+      Some(Some((user, password))) => (Some(decode(user)), Some(decode(password))),
+    };
+    // IPv6 addresses are bracketed, as they have colons of their own
+    let (host, port) = match host_port.rfind(':') {
+      Some(colon) if !host_port[colon..].contains(']') => (
+        &host_port[..colon],
+        Some(host_port[colon + 1..].parse().map_err(|_| invalid())?),
+      ),
+      _ => (host_port, None),
+    };
+    if scheme.is_empty() || host.is_empty() {
+      return Err(invalid());
+    }
+    Ok(Url {
+      scheme: scheme.to_ascii_lowercase(),
+      user,
+      password,
+      host: host.to_string(),
+      port,
+      path: path.to_string(),
+    })
+  }
+
+  /// The URL of a file under this one, taken as a directory.
+  pub fn join(&self, path: &str) -> Url {
+    Url {
+      path: format!("{}/{}", self.path.trim_end_matches('/'), path),
+      ..self.clone()
+    }
+  }
+
+  /// The host, with the port if the URL gives one, as a `Host` header
+  /// names it.
+  pub fn host_and_port(&self) -> String {
+    match self.port {
+      Some(port) => format!("{}:{}", self.host, port),
+      None => self.host.clone(),
+    }
+  }
+
+  /// The host and port to connect to, with `default` as the port if the URL
+  /// doesn't give one.
+  pub fn address(&self, default: u16) -> String {
+    format!("{}:{}", self.host, self.port.unwrap_or(default))
+  }
+}
+
+impl Display for Url {
+  /// The URL without its password, fit to be shown.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}://", self.scheme)?;
+    if let Some(user) = &self.user {
+      write!(f, "{}@", user)?;
+    }
+    write!(f, "{}", self.host)?;
+    if let Some(port) = self.port {
+      write!(f, ":{}", port)?;
+    }
+    write!(f, "{}", self.path)
+  }
+}
+
+/// Undoes the `%XX` escapes in part of a URL, leaving any that aren't valid
+/// as they are.
+fn decode(text: &str) -> String {
+  let bytes = text.as_bytes();
+  let mut out = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let escaped = match bytes[i] {
+      b'%' => bytes
+        .get(i + 1..i + 3)
+        .and_then(|hex| std::str::from_utf8(hex).ok())
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+      _ => None,
+    };
+    match escaped {
+      Some(byte) => {
+        out.push(byte);
+        i += 3;
+      }
+      None => {
+        out.push(bytes[i]);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&out).into_owned()
+}
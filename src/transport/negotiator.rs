@@ -0,0 +1,179 @@
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::object::{self, commit::Commit, serializable::Unbox};
+use crate::repo::Repo;
+
+/// The most commits the client tells the server it has.
+const MAX_HAVES: usize = 256;
+
+/// The longest the skipping algorithm goes between the commits it sends.
+const MAX_SKIP: u32 = 10000;
+
+/// How the commits to tell a server about are picked, as
+/// `fetch.negotiationAlgorithm` says.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+  /// Every commit, newest first (`consecutive`).
+  Consecutive,
+
+  /// Commits further and further apart going back along each line of
+  /// history (`skipping`), so that a long history is covered with few of
+  /// them. The server may then find an older commit in common than it
+  /// could have, and send more than it needed to.
+  Skipping,
+
+  /// None at all (`noop`): the server sends everything.
+  Noop,
+}
+
+impl Algorithm {
+  /// The algorithm the config of `repo` asks for. `default` is
+  /// `consecutive`, unless `feature.experimental` is set, when it is
+  /// `skipping`.
+  pub fn from_config(repo: &Repo) -> Result<Algorithm, String> {
+    let config = |section: &str, key: &str| {
+      repo
+        .config
+        .as_ref()
+        .and_then(|config| config.section(Some(section)))
+        .and_then(|section| {
+          section
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.to_string())
+        })
+    };
+    let experimental = matches!(
+      config("feature", "experimental").as_deref(),
+      Some("true" | "yes" | "on" | "1")
+    );
+    match config("fetch", "negotiationAlgorithm").as_deref() {
+      None | Some("default") if experimental => Ok(Algorithm::Skipping),
+      None | Some("default" | "consecutive") => Ok(Algorithm::Consecutive),
+      Some("skipping") => Ok(Algorithm::Skipping),
+      Some("noop") => Ok(Algorithm::Noop),
+      Some(other) => Err(format!("unknown fetch negotiation algorithm '{}'", other)),
+    }
+  }
+}
+
+/// A commit waiting to be looked at.
+struct Entry {
+  parents: Vec<String>,
+
+  /// How many commits are skipped after the last one sent on its line of
+  /// history, and how many of those are left before one is sent again.
+  skip: u32,
+  left: u32,
+  popped: bool,
+}
+
+/// Picks the commits a client tells the server it has, walking back from
+/// the tips newest first, for the server to find the newest it has too.
+///
+/// Skipping, each tip is sent, and then along each line of history 1, 2,
+/// 4, 7, 11... commits are skipped between those sent, up to 10000 at a
+/// time. The last commit of a line is always sent. Either way, no more than
+/// 256 commits are sent in all.
+pub struct Negotiator {
+  repo: Repo,
+  algorithm: Algorithm,
+  entries: HashMap<String, Entry>,
+  queue: BinaryHeap<(i64, String)>,
+  sent: usize,
+}
+
+impl Negotiator {
+  /// Starts at the commits the `tips` point at. Tips that aren't commits,
+  /// or aren't there, are passed over.
+  pub fn new(repo: &Repo, algorithm: Algorithm, tips: &[String]) -> Negotiator {
+    let mut negotiator = Negotiator {
+      repo: repo.clone(),
+      algorithm,
+      entries: HashMap::new(),
+      queue: BinaryHeap::new(),
+      sent: 0,
+    };
+    if algorithm != Algorithm::Noop {
+      for tip in tips {
+        if let Ok(commit) = object::peel(repo, tip, Some("commit")) {
+          if !negotiator.entries.contains_key(&commit) {
+            negotiator.push(&commit, 0, 0);
+          }
+        }
+      }
+    }
+    negotiator
+  }
+
+  /// Queues a commit, unless it isn't there (as the parents of the
+  /// commits of a shallow repository aren't).
+  fn push(&mut self, hash: &str, skip: u32, left: u32) {
+    let object = match object::read(&self.repo, hash, Some("commit")) {
+      Ok(object) => object,
+      Err(_) => return,
+    };
+    let commit = match object.unbox::<Commit>() {
+      Ok(commit) => commit,
+      Err(_) => return,
+    };
+    self.entries.insert(
+      hash.to_string(),
+      Entry {
+        parents: commit.parents().to_vec(),
+        skip,
+        left,
+        popped: false,
+      },
+    );
+    self.queue.push((commit.commit_time(), hash.to_string()));
+  }
+}
+
+impl Iterator for Negotiator {
+  type Item = String;
+
+  /// The next commit to tell the server about.
+  fn next(&mut self) -> Option<String> {
+    while self.sent < MAX_HAVES {
+      let (_, hash) = self.queue.pop()?;
+      let entry = self.entries.get_mut(&hash).unwrap();
+      entry.popped = true;
+      let (skip, left) = (entry.skip, entry.left);
+      let parents = entry.parents.clone();
+      let mut send = left == 0 || self.algorithm == Algorithm::Consecutive;
+
+      // along a line of history, each gap is half again as long as the
+      // last, and a parent reached more than one way takes the longest
+      let (skip, left) = match left {
+        0 => {
+          let skip = (skip * 3 / 2 + 1).min(MAX_SKIP);
+          (skip, skip)
+        }
+        _ => (skip, left - 1),
+      };
+      let mut pushed = false;
+      for parent in parents {
+        match self.entries.get_mut(&parent) {
+          None => {
+            self.push(&parent, skip, left);
+            pushed = true;
+          }
+          Some(entry) if !entry.popped && entry.skip < skip => {
+            entry.skip = skip;
+            entry.left = left;
+          }
+          Some(_) => (),
+        }
+      }
+      // the oldest commit of a line is sent, skipped or not, so the server
+      // hears of the end of it
+      send |= !pushed;
+      if send {
+        self.sent += 1;
+        return Some(hash);
+      }
+    }
+    None
+  }
+}
@@ -0,0 +1,57 @@
+use std::io::{self, Read, Write};
+
+use crate::trace;
+
+/// The most data a pkt-line holds.
+pub const MAX_DATA: usize = 65516;
+
+/// Writes a pkt-line: its length, including the four hex digits of the
+/// length itself, then its data. A flush packet is just `0000`.
+pub fn write(out: &mut impl Write, data: Option<&[u8]>) -> io::Result<()> {
+  trace::packet(true, data);
+  match data {
+    Some(data) => {
+      out.write_all(format!("{:04x}", data.len() + 4).as_bytes())?;
+      out.write_all(data)
+    }
+    None => out.write_all(b"0000"),
+  }
+}
+
+/// Writes a line of text as a pkt-line, with a newline after it.
+pub fn write_line(out: &mut impl Write, line: &str) -> io::Result<()> {
+  write(out, Some(format!("{}\n", line).as_bytes()))
+}
+
+/// Reads a pkt-line, which is `None` for a flush packet.
+pub fn read(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+  let bad = || io::Error::new(io::ErrorKind::InvalidData, "bad packet length");
+  let mut len = [0; 4];
+  input.read_exact(&mut len)?;
+  let len = std::str::from_utf8(&len)
+    .ok()
+    .and_then(|len| usize::from_str_radix(len, 16).ok())
+    .ok_or_else(bad)?;
+  match len {
+    0 => {
+      trace::packet(false, None);
+      Ok(None)
+    }
+    1..=3 => Err(bad()),
+    _ => {
+      let mut data = vec![0; len - 4];
+      input.read_exact(&mut data)?;
+      trace::packet(false, Some(&data));
+      Ok(Some(data))
+    }
+  }
+}
+
+/// Reads a pkt-line of text, without the newline it ends with, or `None`
+/// for a flush packet.
+pub fn read_line(input: &mut impl Read) -> io::Result<Option<String>> {
+  Ok(read(input)?.map(|data| {
+    let data = data.strip_suffix(b"\n").unwrap_or(&data);
+    String::from_utf8_lossy(data).into_owned()
+  }))
+}
@@ -0,0 +1,627 @@
+use std::ffi::OsString;
+use std::io::{self, BufRead, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{self, Stdio};
+
+use flate2::bufread::ZlibDecoder;
+
+use super::pkt_line;
+use crate::cancel::Cancel;
+use crate::connected;
+use crate::crypto;
+use crate::gpg;
+use crate::identity::date;
+use crate::object::{self, blob::Blob, quarantine::Quarantine, refs};
+use crate::progress::NoProgress;
+use crate::repack;
+use crate::repo::Repo;
+use crate::trace;
+
+/// What the server side of a push can always do, as it says in the first
+/// line of its refs.
+const CAPABILITIES: &str =
+  "report-status report-status-v2 delete-refs side-band-64k ofs-delta atomic";
+
+/// An update to a ref that a client asks for, as `<old> <new> <ref>`. The
+/// null hash is an old value for a ref to be created, and a new value for
+/// one to be deleted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Command {
+  old: String,
+  new: String,
+  name: String,
+}
+
+impl Command {
+  fn parse(line: &str) -> Result<Command, String> {
+    let mut words = line.splitn(3, ' ');
+    match (words.next(), words.next(), words.next()) {
+      (Some(old), Some(new), Some(name)) if old.len() == 40 && new.len() == 40 => Ok(Command {
+        old: old.to_string(),
+        new: new.to_string(),
+        name: name.to_string(),
+      }),
+      _ => Err(format!(
+        "protocol error: expected old/new/ref, got '{}'",
+        line
+      )),
+    }
+  }
+}
+
+/// A push certificate, which `push --signed` sends in place of the
+/// commands: what is being pushed, by whom, where to and with which nonce
+/// and push options, signed by the pusher.
+///
+/// ### Example
+/// ```text
+/// certificate version 0.1
+/// pusher A U Thor <author@example.com> 1700000000 +0000
+/// pushee git://example.com/project.git
+/// nonce 1700000000-5d41402abc4b2a76b9719d911017c592ae7a5b1e
+///
+/// 0000000000000000000000000000000000000000 8d1f3c... refs/heads/main
+/// -----BEGIN PGP SIGNATURE-----
+/// ...
+/// ```
+struct Certificate {
+  text: String,
+  payload: Vec<u8>,
+  signature: Vec<u8>,
+  nonce: Option<String>,
+  commands: Vec<Command>,
+}
+
+impl Certificate {
+  fn parse(text: String) -> Result<Certificate, String> {
+    let bad = || "protocol error: malformed push certificate".to_string();
+    let (payload, signature) = gpg::split_tag(text.as_bytes()).ok_or_else(bad)?;
+    let body = String::from_utf8_lossy(&payload).into_owned();
+    let (header, commands) = body.split_once("\n\n").ok_or_else(bad)?;
+    let mut lines = header.lines();
+    if lines.next() != Some("certificate version 0.1") {
+      return Err(bad());
+    }
+    let nonce = lines
+      .find_map(|line| line.strip_prefix("nonce "))
+      .map(str::to_string);
+    let commands = commands
+      .lines()
+      .map(Command::parse)
+      .collect::<Result<_, _>>()?;
+    Ok(Certificate {
+      text,
+      payload,
+      signature,
+      nonce,
+      commands,
+    })
+  }
+}
+
+/// Serves a push into `repo` from a client that speaks the pack protocol
+/// (version 0) over `input` and `output`, as `receive-pack` does.
+///
+/// The refs are sent first, the first with the capabilities. The client
+/// then sends the updates it wants made, the push options if it asked to
+/// send some, and a pack of the objects the new values need, which goes
+/// into a quarantine until the new values are found to have everything
+/// they reach. The `pre-receive` hook may then turn the whole push down;
+/// if it doesn't, each ref is updated if it still holds the old value the
+/// client expected, and the `post-receive` hook is told of those that
+/// were. Each hook reads `<old> <new> <ref>` lines, and anything they print
+/// goes to the client on band 2 of the side band. Last, the client is told
+/// how each update went, if it asked (`report-status`, or
+/// `report-status-v2`).
+///
+/// An atomic push (`atomic`) updates every ref in one transaction, or none
+/// of them: if one can't be updated, the others are turned down too, with
+/// `atomic transaction failed`.
+///
+/// Push options are only taken if `receive.advertisePushOptions` is set;
+/// the hooks find them in `GIT_PUSH_OPTION_COUNT` and `GIT_PUSH_OPTION_<n>`.
+/// Signed pushes are only taken if `receive.certNonceSeed` is set: the
+/// nonce handed out is the time with an HMAC of it made with the seed, so
+/// that the server can tell its own nonces. The certificate a signed push
+/// sends is kept as a blob, and its signature and nonce checked, for the
+/// hooks to decide what to make of them (see [`certificate_env`]).
+pub fn serve(repo: &Repo, input: &mut impl BufRead, output: &mut impl Write) -> Result<(), String> {
+  let error = |e: io::Error| format!("receive-pack: {}", e);
+  let mut capabilities = CAPABILITIES.to_string();
+  if config_bool(repo, "advertisePushOptions") {
+    capabilities.push_str(" push-options");
+  }
+  let seed = config(repo, "certNonceSeed");
+  let stamp = date::now();
+  let nonce = seed
+    .as_ref()
+    .map(|seed| nonce(repo, seed, stamp))
+    .inspect(|nonce| capabilities.push_str(&format!(" push-cert={}", nonce)));
+  capabilities.push_str(&format!(" agent=git/git-rs-{}", env!("CARGO_PKG_VERSION")));
+  advertise(repo, &capabilities, output).map_err(error)?;
+
+  let mut commands: Vec<Command> = Vec::new();
+  let mut asked: Vec<String> = Vec::new();
+  let mut certificate: Option<Certificate> = None;
+  let mut first = true;
+  loop {
+    let line = match pkt_line::read_line(input) {
+      Ok(Some(line)) => line,
+      Ok(None) => break,
+      // a client that only wanted the refs may hang up without a flush
+      Err(_) if first => return Ok(()),
+      Err(e) => return Err(error(e)),
+    };
+    let line = match line.split_once('\0') {
+      Some((line, offered)) if first => {
+        asked = offered.split(' ').map(str::to_string).collect();
+        line.to_string()
+      }
+      _ => line,
+    };
+    first = false;
+    match line.as_str() {
+      "push-cert" => {
+        let mut text = String::new();
+        loop {
+          match pkt_line::read_line(input).map_err(error)? {
+            Some(line) if line == "push-cert-end" => break,
+            Some(line) => text.push_str(&format!("{}\n", line)),
+            None => return Err("protocol error: push certificate not ended".to_string()),
+          }
+        }
+        let parsed = Certificate::parse(text)?;
+        commands.extend(parsed.commands.iter().cloned());
+        certificate = Some(parsed);
+      }
+      line => commands.push(Command::parse(line)?),
+    }
+  }
+  if commands.is_empty() {
+    return Ok(());
+  }
+  let has = |capability: &str| asked.iter().any(|c| c == capability);
+  let push_options = match has("push-options") {
+    true => read_push_options(input).map_err(error)?,
+    false => Vec::new(),
+  };
+  let band = has("side-band-64k");
+
+  let quarantine = Quarantine::new(repo)?;
+  let incoming = quarantine.repo(repo);
+  let unpacked = match commands.iter().any(|c| c.new != refs::NULL_HASH) {
+    true => read_pack(input)
+      .map_err(|e| format!("unpack-objects abnormal exit ({})", e))
+      .and_then(|pack| {
+        repack::index::write(&incoming, &pack, None, &mut NoProgress, &Cancel::new()).map(|_| ())
+      }),
+    false => Ok(()),
+  };
+
+  let complete: Vec<String> = refs::collect(repo, None).into_values().collect();
+  let mut results: Vec<Result<(), String>> = commands
+    .iter()
+    .map(|command| {
+      if unpacked.is_err() {
+        return Err("unpacker error".to_string());
+      }
+      if !command.name.starts_with("refs/") || !refs::check_name(&command.name) {
+        return Err("funny refname".to_string());
+      }
+      if command.new == refs::NULL_HASH {
+        return Ok(());
+      }
+      match connected::missing(&incoming, std::slice::from_ref(&command.new), &complete) {
+        Ok(missing) if missing.is_empty() => Ok(()),
+        _ => Err("missing necessary objects".to_string()),
+      }
+    })
+    .collect();
+  let atomic = has("atomic");
+  if atomic {
+    fail_all(&mut results);
+  }
+
+  let mut env: Vec<(String, OsString)> = Vec::new();
+  if has("push-options") {
+    env.push((
+      "GIT_PUSH_OPTION_COUNT".to_string(),
+      push_options.len().to_string().into(),
+    ));
+    for (i, option) in push_options.iter().enumerate() {
+      env.push((format!("GIT_PUSH_OPTION_{}", i), option.into()));
+    }
+  }
+  if let Some(certificate) = &certificate {
+    env.extend(certificate_env(
+      repo,
+      certificate,
+      seed.as_deref(),
+      nonce.as_deref(),
+    )?);
+  }
+
+  let accepted = |results: &[Result<(), String>]| -> String {
+    commands
+      .iter()
+      .zip(results)
+      .filter(|(_, result)| result.is_ok())
+      .map(|(c, _)| format!("{} {} {}\n", c.old, c.new, c.name))
+      .collect()
+  };
+  let stdin = accepted(&results);
+  if !stdin.is_empty() {
+    let mut hook_env = env.clone();
+    hook_env.extend(
+      quarantine
+        .env()
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value)),
+    );
+    if let Some((ok, said)) = run_hook(repo, "pre-receive", &stdin, &hook_env) {
+      remote_message(output, band, &said).map_err(error)?;
+      if !ok {
+        for result in results.iter_mut().filter(|result| result.is_ok()) {
+          *result = Err("pre-receive hook declined".to_string());
+        }
+      }
+    }
+  }
+  if results.iter().any(Result::is_ok) {
+    quarantine.migrate()?;
+  }
+
+  let update = |command: &Command| refs::Update {
+    name: command.name.clone(),
+    new: Some(command.new.clone()),
+    old: Some(command.old.clone()),
+  };
+  match atomic {
+    true => {
+      let updates: Vec<refs::Update> = commands
+        .iter()
+        .zip(&results)
+        .filter(|(_, result)| result.is_ok())
+        .map(|(command, _)| update(command))
+        .collect();
+      if !updates.is_empty() {
+        let updated = repo
+          .refs
+          .prepare(&updates)
+          .and_then(|transaction| transaction.commit());
+        if let Err(reason) = updated {
+          for result in results.iter_mut() {
+            *result = Err(reason.clone());
+          }
+        }
+      }
+    }
+    false => {
+      for (command, result) in commands.iter().zip(results.iter_mut()) {
+        if result.is_ok() {
+          *result = repo
+            .refs
+            .prepare(&[update(command)])
+            .and_then(|transaction| transaction.commit());
+        }
+      }
+    }
+  }
+  let stdin = accepted(&results);
+  if !stdin.is_empty() {
+    if let Some((_, said)) = run_hook(repo, "post-receive", &stdin, &env) {
+      remote_message(output, band, &said).map_err(error)?;
+    }
+  }
+
+  if has("report-status") || has("report-status-v2") {
+    let mut report = Vec::new();
+    let unpack = match &unpacked {
+      Ok(()) => "unpack ok".to_string(),
+      Err(message) => format!("unpack {}", message),
+    };
+    pkt_line::write_line(&mut report, &unpack).map_err(error)?;
+    for (command, result) in commands.iter().zip(&results) {
+      let line = match result {
+        Ok(()) => format!("ok {}", command.name),
+        Err(reason) => format!("ng {} {}", command.name, reason),
+      };
+      pkt_line::write_line(&mut report, &line).map_err(error)?;
+    }
+    pkt_line::write(&mut report, None).map_err(error)?;
+    match band {
+      true => send_band(output, 1, &report).map_err(error)?,
+      false => output.write_all(&report).map_err(error)?,
+    }
+  }
+  if band {
+    pkt_line::write(output, None).map_err(error)?;
+  }
+  output.flush().map_err(error)?;
+  unpacked
+}
+
+/// Turns down every update of an atomic push if one of them is, saying why
+/// for those that weren't.
+fn fail_all(results: &mut [Result<(), String>]) {
+  if results.iter().all(Result::is_ok) {
+    return;
+  }
+  for result in results.iter_mut().filter(|result| result.is_ok()) {
+    *result = Err("atomic transaction failed".to_string());
+  }
+}
+
+/// Sends the refs of `repo` with the capabilities. A repository without
+/// refs still says what it can do.
+fn advertise(repo: &Repo, capabilities: &str, output: &mut impl Write) -> io::Result<()> {
+  let mut lines: Vec<(String, String)> = refs::collect(repo, None)
+    .into_iter()
+    .map(|(name, hash)| (hash, name))
+    .collect();
+  if lines.is_empty() {
+    lines.push((refs::NULL_HASH.to_string(), "capabilities^{}".to_string()));
+  }
+  for (i, (hash, name)) in lines.iter().enumerate() {
+    let line = match i {
+      0 => format!("{} {}\0{}\n", hash, name, capabilities),
+      _ => format!("{} {}\n", hash, name),
+    };
+    pkt_line::write(output, Some(line.as_bytes()))?;
+  }
+  pkt_line::write(output, None)?;
+  output.flush()
+}
+
+fn read_push_options(input: &mut impl Read) -> io::Result<Vec<String>> {
+  let mut options = Vec::new();
+  while let Some(option) = pkt_line::read_line(input)? {
+    options.push(option);
+  }
+  Ok(options)
+}
+
+/// Reads a pack sent down the connection as it is. Nothing but the pack
+/// itself says where it ends: its header gives the number of objects, each
+/// object is compressed, and its checksum follows the last of them.
+fn read_pack(input: &mut impl BufRead) -> io::Result<Vec<u8>> {
+  let mut tee = Tee {
+    input,
+    data: Vec::new(),
+  };
+  let mut header = [0; 12];
+  tee.read_exact(&mut header)?;
+  if &header[..4] != b"PACK" {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      "bad pack header",
+    ));
+  }
+  let count = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+  let mut byte = [0];
+  for _ in 0..count {
+    // the type and size, with a byte more for each that has its top bit
+    // set, and then what a delta is against
+    tee.read_exact(&mut byte)?;
+    let kind = (byte[0] >> 4) & 7;
+    while byte[0] & 0x80 != 0 {
+      tee.read_exact(&mut byte)?;
+    }
+    match kind {
+      6 => loop {
+        tee.read_exact(&mut byte)?;
+        if byte[0] & 0x80 == 0 {
+          break;
+        }
+      },
+      7 => tee.read_exact(&mut [0; 20])?,
+      _ => (),
+    }
+    io::copy(&mut ZlibDecoder::new(&mut tee), &mut io::sink())?;
+  }
+  tee.read_exact(&mut [0; 20])?;
+  Ok(tee.data)
+}
+
+/// A reader that keeps a copy of everything read through it, and reads no
+/// further ahead than it is asked to.
+struct Tee<'a, R> {
+  input: &'a mut R,
+  data: Vec<u8>,
+}
+
+impl<R: BufRead> Read for Tee<'_, R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let available = self.fill_buf()?;
+    let len = available.len().min(buf.len());
+    buf[..len].copy_from_slice(&available[..len]);
+    self.consume(len);
+    Ok(len)
+  }
+}
+
+impl<R: BufRead> BufRead for Tee<'_, R> {
+  fn fill_buf(&mut self) -> io::Result<&[u8]> {
+    self.input.fill_buf()
+  }
+
+  fn consume(&mut self, amt: usize) {
+    // what is consumed has always just been filled
+    if let Ok(buf) = self.input.fill_buf() {
+      self.data.extend_from_slice(&buf[..amt]);
+    }
+    self.input.consume(amt);
+  }
+}
+
+/// A nonce for a signed push: the time it was handed out, and an HMAC of
+/// that and the repository made with the seed.
+fn nonce(repo: &Repo, seed: &str, stamp: i64) -> String {
+  let text = format!("{}:{}", repo.git_dir.display(), stamp);
+  format!(
+    "{}-{}",
+    stamp,
+    crypto::hmac_sha_1(seed.as_bytes(), text.as_bytes())
+  )
+}
+
+/// Keeps a push certificate, checks it, and returns what was found out, as
+/// the environment the hooks run in:
+///
+/// - `GIT_PUSH_CERT`: the blob the certificate is kept in;
+/// - `GIT_PUSH_CERT_SIGNER` and `GIT_PUSH_CERT_KEY`: who signed it, and
+///   with which key;
+/// - `GIT_PUSH_CERT_STATUS`: how the signature checked out, as the letter
+///   `%G?` shows for it (`G` for a good signature);
+/// - `GIT_PUSH_CERT_NONCE`: the nonce in the certificate;
+/// - `GIT_PUSH_CERT_NONCE_STATUS`: `OK` if it is the one handed out,
+///   `SLOP` if it is another this server handed out less than
+///   `receive.certNonceSlop` seconds before or after (which is then in
+///   `GIT_PUSH_CERT_NONCE_SLOP`), `BAD` if it is not, `MISSING` if there
+///   is none, or `UNSOLICITED` if there is one but none was handed out.
+fn certificate_env(
+  repo: &Repo,
+  certificate: &Certificate,
+  seed: Option<&str>,
+  sent: Option<&str>,
+) -> Result<Vec<(String, OsString)>, String> {
+  let blob = object::write(repo, &Blob::new(certificate.text.as_bytes()), false)?;
+  let verification = gpg::verify(repo, &certificate.payload, &certificate.signature)?;
+  let mut env: Vec<(String, OsString)> = vec![
+    ("GIT_PUSH_CERT".to_string(), blob.into()),
+    (
+      "GIT_PUSH_CERT_SIGNER".to_string(),
+      verification.signer.unwrap_or_default().into(),
+    ),
+    (
+      "GIT_PUSH_CERT_KEY".to_string(),
+      verification.key.unwrap_or_default().into(),
+    ),
+    (
+      "GIT_PUSH_CERT_STATUS".to_string(),
+      verification.status.letter().to_string().into(),
+    ),
+  ];
+  let received = certificate.nonce.as_deref();
+  if let Some(received) = received {
+    env.push(("GIT_PUSH_CERT_NONCE".to_string(), received.into()));
+  }
+  let slop: i64 = config(repo, "certNonceSlop")
+    .and_then(|slop| slop.parse().ok())
+    .unwrap_or(0);
+  let status = match (sent, received, seed) {
+    (None, Some(_), _) | (_, Some(_), None) => "UNSOLICITED".to_string(),
+    (_, None, _) => "MISSING".to_string(),
+    (Some(sent), Some(received), _) if sent == received => "OK".to_string(),
+    (Some(sent), Some(received), Some(seed)) => {
+      let stamp = |nonce: &str| {
+        nonce
+          .split_once('-')
+          .and_then(|(s, _)| s.parse::<i64>().ok())
+      };
+      match (stamp(sent), stamp(received)) {
+        (Some(ours), Some(theirs))
+          if nonce(repo, seed, theirs) == received && (ours - theirs).abs() <= slop =>
+        {
+          env.push((
+            "GIT_PUSH_CERT_NONCE_SLOP".to_string(),
+            (theirs - ours).to_string().into(),
+          ));
+          "SLOP".to_string()
+        }
+        _ => "BAD".to_string(),
+      }
+    }
+  };
+  env.push(("GIT_PUSH_CERT_NONCE_STATUS".to_string(), status.into()));
+  Ok(env)
+}
+
+/// Runs a hook, if the repository has it (as an executable in
+/// `core.hooksPath`, or else in `hooks` in the git directory), with `stdin`
+/// as its input. Returns whether it succeeded, with what it printed on its
+/// standard output and error.
+fn run_hook(
+  repo: &Repo,
+  name: &str,
+  stdin: &str,
+  env: &[(String, OsString)],
+) -> Option<(bool, Vec<u8>)> {
+  let dir = repo
+    .config
+    .as_ref()
+    .and_then(|config| config.get_from(Some("core"), "hooksPath"))
+    .map_or_else(
+      || repo.git_dir.join("hooks"),
+      |path| repo.git_dir.join(path),
+    );
+  let path: PathBuf = dir.join(name);
+  let executable = path
+    .metadata()
+    .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0);
+  if !executable {
+    return None;
+  }
+  let mut command = process::Command::new(&path);
+  command
+    .current_dir(&repo.git_dir)
+    .env("GIT_DIR", ".")
+    .envs(env.iter().map(|(key, value)| (key, value)))
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+  let traced = trace::command("hook", &command);
+  let output = command.spawn().and_then(|mut child| {
+    // a hook that doesn't read what it's told is no error
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    let pid = child.id();
+    let output = child.wait_with_output()?;
+    traced.exit(pid, &output.status);
+    Ok(output)
+  });
+  match output {
+    Ok(output) => Some((
+      output.status.success(),
+      [output.stdout, output.stderr].concat(),
+    )),
+    Err(_) => Some((false, Vec::new())),
+  }
+}
+
+/// Passes on what a hook said to the client, on band 2 of the side band,
+/// or else to the server's standard error.
+fn remote_message(output: &mut impl Write, band: bool, message: &[u8]) -> io::Result<()> {
+  match band {
+    true => send_band(output, 2, message),
+    false => {
+      eprint!("{}", String::from_utf8_lossy(message));
+      Ok(())
+    }
+  }
+}
+
+/// Sends data on a band of the side band, in as many packets as it takes.
+fn send_band(output: &mut impl Write, band: u8, data: &[u8]) -> io::Result<()> {
+  for chunk in data.chunks(pkt_line::MAX_DATA - 1) {
+    pkt_line::write(output, Some(&[&[band], chunk].concat()))?;
+  }
+  output.flush()
+}
+
+fn config(repo: &Repo, key: &str) -> Option<String> {
+  let section = repo.config.as_ref()?.section(Some("receive"))?;
+  section
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case(key))
+    .map(|(_, value)| value.to_string())
+}
+
+fn config_bool(repo: &Repo, key: &str) -> bool {
+  matches!(
+    config(repo, key)
+      .map(|value| value.to_ascii_lowercase())
+      .as_deref(),
+    Some("true" | "yes" | "on" | "1" | "")
+  )
+}
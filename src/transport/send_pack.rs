@@ -0,0 +1,311 @@
+use std::io::{self, Read, Write};
+
+use super::{pkt_line, RemoteRef, Url};
+use crate::cancel::Cancel;
+use crate::gpg;
+use crate::identity::{Role, Signature};
+use crate::object::{self, refs};
+use crate::progress::Progress;
+use crate::repack::{self, Options};
+use crate::repo::Repo;
+
+/// An update to a ref of the remote: from the hash it is expected to be at
+/// to the new one. The null hash is the old value of a ref to be created,
+/// and the new value of one to be deleted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+  /// The remote ref, named in full.
+  pub name: String,
+  pub old: String,
+  pub new: String,
+}
+
+/// Whether to sign a push, as `push --signed` (or `push.gpgSign`) says.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Signed {
+  #[default]
+  No,
+
+  /// Always, which fails if the remote doesn't take signed pushes.
+  Yes,
+
+  /// Only if the remote takes signed pushes (`if-asked`).
+  IfAsked,
+}
+
+impl Signed {
+  /// Parses a value of `--signed` or `push.gpgSign`: a boolean, or
+  /// `if-asked`.
+  pub fn parse(value: &str) -> Option<Signed> {
+    match value.to_ascii_lowercase().as_str() {
+      "true" | "yes" | "on" | "1" | "" => Some(Signed::Yes),
+      "false" | "no" | "off" | "0" => Some(Signed::No),
+      "if-asked" => Some(Signed::IfAsked),
+      _ => None,
+    }
+  }
+}
+
+/// What the remote said of a ref it was asked to update.
+///
+/// A remote that reports with `report-status-v2` may say that it did
+/// something other than what it was asked to, eg. that a hook updated
+/// another ref in place of the one pushed to; what it says it did is in
+/// the options of the report.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefReport {
+  /// The ref the remote was asked to update, named in full.
+  pub name: String,
+
+  /// Nothing if it updated the ref, or why it didn't.
+  pub result: Result<(), String>,
+
+  /// The ref it updated in its place (`option refname`).
+  pub refname: Option<String>,
+
+  /// Where the ref it updated was (`option old-oid`), and where it is now
+  /// (`option new-oid`).
+  pub old: Option<String>,
+  pub new: Option<String>,
+
+  /// Whether the update lost commits (`option forced-update`).
+  pub forced: bool,
+}
+
+impl RefReport {
+  fn new(name: &str, result: Result<(), String>) -> RefReport {
+    RefReport {
+      name: name.to_string(),
+      result,
+      refname: None,
+      old: None,
+      new: None,
+      forced: false,
+    }
+  }
+}
+
+/// What the remote said of each ref it was asked to update.
+pub type Report = Vec<RefReport>;
+
+/// Pushes to a server that has sent its refs and `capabilities`: asks it to
+/// make the updates `commands` say, and sends it a pack of the objects they
+/// need that the refs it `advertised` don't reach.
+///
+/// `push_options` are passed on to the hooks of the remote, which must
+/// take them (`push-options`). A signed push sends a push certificate in
+/// place of the commands, signed with the signing key (see
+/// [`gpg::sign`]), for the remote to check and keep; the remote must hand
+/// out a nonce for it (`push-cert=<nonce>`), unless it is only signed if
+/// asked to be. An atomic push has the remote update every ref or none of
+/// them, which it must be able to do (`atomic`).
+#[allow(clippy::too_many_arguments)]
+pub fn push(
+  repo: &Repo,
+  input: &mut impl Read,
+  output: &mut impl Write,
+  url: &Url,
+  capabilities: &[String],
+  advertised: &[RemoteRef],
+  commands: &[Command],
+  push_options: &[String],
+  signed: Signed,
+  atomic: bool,
+  progress: &mut dyn Progress,
+  cancel: &Cancel,
+) -> Result<Report, String> {
+  let error = |e: io::Error| format!("unable to talk to the remote ({})", e);
+  let offered = |name: &str| capabilities.iter().any(|c| c == name);
+  if !push_options.is_empty() && !offered("push-options") {
+    return Err("the receiving end does not support push options".to_string());
+  }
+  if atomic && !offered("atomic") {
+    return Err("the receiving end does not support --atomic push".to_string());
+  }
+  let nonce = capabilities
+    .iter()
+    .find_map(|capability| capability.strip_prefix("push-cert="));
+  let nonce = match (signed, nonce) {
+    (Signed::Yes, None) => {
+      return Err("the receiving end does not support --signed push".to_string())
+    }
+    (Signed::No, _) | (Signed::IfAsked, None) => None,
+    (_, nonce) => nonce,
+  };
+
+  let mut asked: Vec<&str> = match offered("report-status-v2") {
+    true => vec!["report-status-v2"],
+    false => vec!["report-status"],
+  };
+  asked.extend(
+    ["side-band-64k", "ofs-delta"]
+      .into_iter()
+      .filter(|name| offered(name)),
+  );
+  if atomic {
+    asked.push("atomic");
+  }
+  if !push_options.is_empty() {
+    asked.push("push-options");
+  }
+  let agent = format!("agent=git/git-rs-{}", env!("CARGO_PKG_VERSION"));
+  asked.push(&agent);
+  let band = offered("side-band-64k");
+
+  match nonce {
+    Some(nonce) => {
+      let certificate = certificate(repo, url, nonce, commands, push_options)?;
+      let first = format!("push-cert\0{}\n", asked.join(" "));
+      pkt_line::write(output, Some(first.as_bytes())).map_err(error)?;
+      for line in certificate.lines() {
+        pkt_line::write_line(output, line).map_err(error)?;
+      }
+      pkt_line::write_line(output, "push-cert-end").map_err(error)?;
+    }
+    None => {
+      for (i, command) in commands.iter().enumerate() {
+        let line = format!("{} {} {}", command.old, command.new, command.name);
+        let line = match i {
+          0 => format!("{}\0{}\n", line, asked.join(" ")),
+          _ => format!("{}\n", line),
+        };
+        pkt_line::write(output, Some(line.as_bytes())).map_err(error)?;
+      }
+    }
+  }
+  pkt_line::write(output, None).map_err(error)?;
+  if !push_options.is_empty() {
+    for option in push_options {
+      pkt_line::write_line(output, option).map_err(error)?;
+    }
+    pkt_line::write(output, None).map_err(error)?;
+  }
+
+  // the objects the new values reach that the remote's refs don't, unless
+  // there are only refs to delete
+  let tips: Vec<String> = commands
+    .iter()
+    .filter(|command| command.new != refs::NULL_HASH)
+    .map(|command| command.new.clone())
+    .collect();
+  if !tips.is_empty() {
+    let hidden: Vec<String> = advertised
+      .iter()
+      .filter(|r| repo.objects.exists(&r.hash))
+      .filter_map(|r| object::peel(repo, &r.hash, Some("commit")).ok())
+      .collect();
+    let objects = repack::reachable_from(repo, &tips, &hidden)?;
+    repack::send(
+      repo,
+      &objects,
+      &Options::new(repo)?,
+      output,
+      progress,
+      cancel,
+    )?;
+  }
+  output.flush().map_err(error)?;
+
+  if !band {
+    return read_report(input, commands);
+  }
+  // the report comes on band 1, with what the hooks say on band 2
+  let mut report = Vec::new();
+  while let Some(packet) = pkt_line::read(input).map_err(error)? {
+    match packet.split_first() {
+      Some((1, data)) => report.extend_from_slice(data),
+      Some((2, message)) => {
+        for line in String::from_utf8_lossy(message).lines() {
+          eprintln!("remote: {}", line);
+        }
+      }
+      Some((3, message)) => {
+        let message = String::from_utf8_lossy(message);
+        return Err(format!("remote error: {}", message.trim_end()));
+      }
+      _ => return Err("protocol error: bad band".to_string()),
+    }
+  }
+  read_report(&mut &report[..], commands)
+}
+
+/// Reads the report of how the updates went: `unpack ok` (or the error
+/// unpacking the pack), then `ok <ref>` or `ng <ref> <reason>` for each,
+/// where an `ok` may be followed by `option <key> [<value>]` lines that say
+/// what was done in its place.
+fn read_report(input: &mut impl Read, commands: &[Command]) -> Result<Report, String> {
+  let error = |_| "protocol error: bad report".to_string();
+  match pkt_line::read_line(input).map_err(error)?.as_deref() {
+    Some("unpack ok") => (),
+    Some(line) => match line.strip_prefix("unpack ") {
+      Some(message) => return Err(format!("remote unpack failed: {}", message)),
+      None => return Err(format!("protocol error: unexpected '{}'", line)),
+    },
+    None => return Err("protocol error: bad report".to_string()),
+  }
+  let mut report: Report = Vec::new();
+  while let Some(line) = pkt_line::read_line(input).map_err(error)? {
+    let (name, result) = match line.split_once(' ') {
+      Some(("ok", name)) => (name, Ok(())),
+      Some(("ng", rest)) => match rest.split_once(' ') {
+        Some((name, reason)) => (name, Err(reason.to_string())),
+        None => (rest, Err("failed".to_string())),
+      },
+      Some(("option", option)) => {
+        let last = report
+          .last_mut()
+          .filter(|last| last.result.is_ok())
+          .ok_or_else(|| format!("protocol error: unexpected '{}'", line))?;
+        match option.split_once(' ').unwrap_or((option, "")) {
+          ("refname", name) => last.refname = Some(name.to_string()),
+          ("old-oid", hash) => last.old = Some(hash.to_string()),
+          ("new-oid", hash) => last.new = Some(hash.to_string()),
+          ("forced-update", _) => last.forced = true,
+          // options this doesn't know of are no matter
+          _ => (),
+        }
+        continue;
+      }
+      _ => return Err(format!("protocol error: unexpected '{}'", line)),
+    };
+    report.push(RefReport::new(name, result));
+  }
+  // a ref the remote said nothing of wasn't updated
+  for command in commands {
+    if !report.iter().any(|r| r.name == command.name) {
+      report.push(RefReport::new(
+        &command.name,
+        Err("remote did not report status".into()),
+      ));
+    }
+  }
+  Ok(report)
+}
+
+/// The signed push certificate for the commands.
+fn certificate(
+  repo: &Repo,
+  url: &Url,
+  nonce: &str,
+  commands: &[Command],
+  push_options: &[String],
+) -> Result<String, String> {
+  let key = gpg::signing_key(repo)?;
+  let now = Signature::current(repo, Role::Committer)?;
+  let mut text = format!(
+    "certificate version 0.1\npusher {} {} {}\npushee {}\nnonce {}\n",
+    key, now.time, now.timezone, url, nonce
+  );
+  for option in push_options {
+    text.push_str(&format!("push-option {}\n", option));
+  }
+  text.push('\n');
+  for command in commands {
+    text.push_str(&format!(
+      "{} {} {}\n",
+      command.old, command.new, command.name
+    ));
+  }
+  let signature = gpg::sign(repo, text.as_bytes())?;
+  Ok(text + &signature)
+}
@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use bstr::BString;
+
+use super::pkt_line;
+use crate::cancel::Cancel;
+use crate::object::{self, refs, serializable::Unbox, tag::Tag};
+use crate::progress::NoProgress;
+use crate::repack::{self, Options};
+use crate::repo::Repo;
+
+/// What the server side of a fetch can do, as it says in the first line of
+/// its refs.
+const CAPABILITIES: &str = "side-band side-band-64k ofs-delta no-progress include-tag";
+
+/// The most data that goes in a side-band packet, leaving a byte for the
+/// band: for `side-band-64k`, and for plain `side-band`.
+const BAND_DATA: usize = pkt_line::MAX_DATA - 1;
+const SMALL_BAND_DATA: usize = 999;
+
+/// Serves a fetch from `repo` to a client that speaks the pack protocol
+/// (version 0) over `input` and `output`, as `upload-pack` does.
+///
+/// The refs are sent first, `HEAD` leading with the capabilities, each
+/// annotated tag followed by what it peels to as `<name>^{}`. The client
+/// then says which of those it wants, and lists the commits it has in
+/// batches. The first that the server has too is acknowledged with `ACK`,
+/// and until then each batch is answered with `NAK`, as is `done`.
+/// Once the client is `done`, the objects the wanted refs reach but the
+/// common commits don't are sent as a pack, on band 1 of the side band if
+/// the client asked for one. A client that asked for `include-tag` gets the
+/// annotated tags that point into the pack in it too, so it can follow
+/// them without fetching again.
+pub fn serve(repo: &Repo, input: &mut impl Read, output: &mut impl Write) -> Result<(), String> {
+  let error = |e: std::io::Error| format!("upload-pack: {}", e);
+  let advertised = advertise(repo, output).map_err(error)?;
+
+  let mut wants: Vec<String> = Vec::new();
+  let mut capabilities: Vec<String> = Vec::new();
+  loop {
+    let line = match pkt_line::read_line(input) {
+      Ok(Some(line)) => line,
+      Ok(None) => break,
+      // a client that only wanted the refs may hang up without a flush
+      Err(_) if wants.is_empty() => return Ok(()),
+      Err(e) => return Err(error(e)),
+    };
+    let mut words = line.split(' ');
+    let hash = match (words.next(), words.next()) {
+      (Some("want"), Some(hash)) => hash.to_string(),
+      _ => return Err(format!("protocol error: expected want, got '{}'", line)),
+    };
+    if !advertised.contains(&hash) {
+      let message = format!("upload-pack: not our ref {}", hash);
+      let _ = pkt_line::write_line(output, &format!("ERR {}", message));
+      return Err(message);
+    }
+    if wants.is_empty() {
+      capabilities.extend(words.map(str::to_string));
+    }
+    wants.push(hash);
+  }
+  if wants.is_empty() {
+    return Ok(());
+  }
+
+  let mut common: Vec<String> = Vec::new();
+  loop {
+    match pkt_line::read_line(input).map_err(error)?.as_deref() {
+      None => {
+        if common.is_empty() {
+          pkt_line::write_line(output, "NAK").map_err(error)?;
+        }
+        output.flush().map_err(error)?;
+      }
+      Some("done") => {
+        if common.is_empty() {
+          pkt_line::write_line(output, "NAK").map_err(error)?;
+        }
+        break;
+      }
+      Some(line) => {
+        let have = line
+          .strip_prefix("have ")
+          .ok_or_else(|| format!("protocol error: expected have, got '{}'", line))?;
+        let commit = match repo.objects.exists(have) {
+          true => object::peel(repo, have, Some("commit")).ok(),
+          false => None,
+        };
+        if let Some(commit) = commit {
+          if common.is_empty() {
+            pkt_line::write_line(output, &format!("ACK {}", have)).map_err(error)?;
+          }
+          common.push(commit);
+        }
+      }
+    }
+  }
+  output.flush().map_err(error)?;
+
+  let has = |capability: &str| capabilities.iter().any(|c| c == capability);
+  let band = match (has("side-band-64k"), has("side-band")) {
+    (true, _) => Some(BAND_DATA),
+    (_, true) => Some(SMALL_BAND_DATA),
+    _ => None,
+  };
+  let mut pack = Vec::new();
+  let packed = repack::reachable_from(repo, &wants, &common).and_then(|mut objects| {
+    if has("include-tag") {
+      include_tags(repo, &mut objects);
+    }
+    repack::send(
+      repo,
+      &objects,
+      &Options::new(repo)?,
+      &mut pack,
+      &mut NoProgress,
+      &Cancel::new(),
+    )
+  });
+  match (band, packed) {
+    (None, Ok(())) => output.write_all(&pack).map_err(error)?,
+    (Some(size), Ok(())) => {
+      for chunk in pack.chunks(size) {
+        pkt_line::write(output, Some(&[&[1], chunk].concat())).map_err(error)?;
+      }
+      pkt_line::write(output, None).map_err(error)?;
+    }
+    (band, Err(message)) => {
+      if band.is_some() {
+        let _ = pkt_line::write(output, Some(format!("\x03{}\n", message).as_bytes()));
+      }
+      return Err(message);
+    }
+  }
+  output.flush().map_err(error)
+}
+
+/// Adds the annotated tags that point at objects being sent, or at tags
+/// that do, to them.
+fn include_tags(repo: &Repo, objects: &mut Vec<(String, BString)>) {
+  let mut sent: HashSet<String> = objects.iter().map(|(hash, _)| hash.clone()).collect();
+  let tags: Vec<(String, String)> = refs::collect(repo, Some(Path::new("refs/tags")))
+    .into_values()
+    .filter_map(|hash| {
+      let tag = object::read(repo, &hash, Some("tag")).ok()?;
+      let target = tag.unbox::<Tag>().ok()?.get("object")?.to_owned();
+      Some((hash, target))
+    })
+    .collect();
+  loop {
+    let mut added = false;
+    for (hash, target) in &tags {
+      if sent.contains(target) && sent.insert(hash.clone()) {
+        objects.push((hash.clone(), BString::default()));
+        added = true;
+      }
+    }
+    if !added {
+      break;
+    }
+  }
+}
+
+/// Sends the refs of `repo`, and returns the objects they point at.
+fn advertise(repo: &Repo, output: &mut impl Write) -> std::io::Result<HashSet<String>> {
+  let mut lines: Vec<(String, String)> = Vec::new();
+  let mut capabilities = CAPABILITIES.to_string();
+  if let Ok(head) = refs::resolve(repo, Path::new("HEAD")) {
+    lines.push((head, "HEAD".to_string()));
+    if let Some(target) = refs::read_symbolic(repo, "HEAD") {
+      capabilities.push_str(&format!(" symref=HEAD:{}", target));
+    }
+  }
+  capabilities.push_str(&format!(" agent=git/git-rs-{}", env!("CARGO_PKG_VERSION")));
+  for (name, hash) in refs::collect(repo, None) {
+    let peeled = object::peel(repo, &hash, None).ok().filter(|p| *p != hash);
+    lines.push((hash, name.clone()));
+    if let Some(peeled) = peeled {
+      lines.push((peeled, format!("{}^{{}}", name)));
+    }
+  }
+
+  // a repository without refs still says what it can do
+  if lines.is_empty() {
+    lines.push(("0".repeat(40), "capabilities^{}".to_string()));
+  }
+  for (i, (hash, name)) in lines.iter().enumerate() {
+    let line = match i {
+      0 => format!("{} {}\0{}\n", hash, name, capabilities),
+      _ => format!("{} {}\n", hash, name),
+    };
+    pkt_line::write(output, Some(line.as_bytes()))?;
+  }
+  pkt_line::write(output, None)?;
+  output.flush()?;
+  Ok(lines.into_iter().map(|(hash, _)| hash).collect())
+}
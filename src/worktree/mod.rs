@@ -0,0 +1,575 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, OpenOptionsExt};
+use std::path::PathBuf;
+
+use bstr::{BString, ByteSlice};
+
+use crate::cancel::Cancel;
+use crate::convert;
+use crate::diff::blob_data;
+use crate::ignore::Ignore;
+use crate::index::untracked::{self as untracked_cache, Dir};
+use crate::index::{self, Index, IndexEntry, SKIP_WORKTREE};
+use crate::object::mode::Mode;
+use crate::parallel;
+use crate::progress::Progress;
+use crate::repo::Repo;
+use crate::sparse::Sparse;
+
+/// Writes a blob out to a path in the working tree and returns the index
+/// entry describing the freshly written file.
+///
+/// Missing parent directories are created, and anything already at the path
+/// is replaced. The contents are converted for the working tree, by the
+/// file's filter driver and line ending settings.
+pub fn checkout_file(
+  repo: &Repo,
+  path: impl AsRef<[u8]>,
+  mode: Mode,
+  hash: &str,
+) -> Result<IndexEntry, String> {
+  let path = path.as_ref();
+  repo.require_work_tree()?;
+  let dest = repo.work_tree_path(path);
+  let error = |e: std::io::Error| format!("{}: {}", path.as_bstr(), e);
+  if let Some(parent) = dest.parent() {
+    // a file may be in the way of a directory we need
+    if parent.is_file() {
+      fs::remove_file(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+    fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+  }
+  match fs::symlink_metadata(&dest) {
+    // a submodule that is already there is left alone
+    Ok(metadata) if mode == Mode::Gitlink && metadata.is_dir() => (),
+    Ok(metadata) => {
+      let removed = if metadata.is_dir() {
+        fs::remove_dir_all(&dest)
+      } else {
+        fs::remove_file(&dest)
+      };
+      removed.map_err(error)?;
+    }
+    Err(_) => (),
+  }
+
+  match mode {
+    Mode::Symbolic => {
+      let data = blob_data(repo, Some(&(mode, hash.to_owned())))?;
+      symlink(OsStr::from_bytes(&data), &dest).map_err(error)?;
+    }
+    // submodules are not cloned, just given an empty directory
+    Mode::Gitlink if dest.is_dir() => (),
+    Mode::Gitlink => fs::create_dir(&dest).map_err(error)?,
+    _ => {
+      let data = blob_data(repo, Some(&(mode, hash.to_owned())))?;
+      let data = convert::to_worktree(repo, path, data)?;
+      let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode.permissions().unwrap_or(0o666))
+        .open(&dest)
+        .map_err(error)?;
+      file.write_all(&data).map_err(error)?;
+    }
+  }
+
+  let metadata = fs::symlink_metadata(&dest).map_err(error)?;
+  let mut entry = IndexEntry::new(path, mode, hash);
+  entry.refresh(&metadata);
+  Ok(entry)
+}
+
+/// Deletes a file from the working tree, along with any directories that are
+/// left empty by its removal.
+pub fn remove_file(repo: &Repo, path: impl AsRef<[u8]>) -> Result<(), String> {
+  let path = path.as_ref();
+  repo.require_work_tree()?;
+  let dest = repo.work_tree_path(path);
+  match fs::symlink_metadata(&dest) {
+    // the directory of a submodule only goes if it is empty
+    Ok(metadata) if metadata.is_dir() => {
+      let _ = fs::remove_dir(&dest);
+    }
+    Ok(_) => fs::remove_file(&dest).map_err(|e| format!("{}: {}", path.as_bstr(), e))?,
+    Err(_) => (),
+  }
+  let mut dir = dest.parent();
+  while let Some(parent) = dir {
+    if parent == repo.work_tree || fs::remove_dir(parent).is_err() {
+      break;
+    }
+    dir = parent.parent();
+  }
+  Ok(())
+}
+
+/// Moves the index and working tree from one set of files to another, as when
+/// switching branches or resetting hard.
+///
+/// Files missing from `target` are deleted and files that differ are written
+/// out. Files that are the same in both keep their index entries (and so their
+/// stat data), which saves having to re-hash them later. Local modifications to
+/// those files are left alone unless `force` is set, in which case they are
+/// overwritten too. Each file deleted or written counts towards the
+/// `Updating files` progress.
+///
+/// Files are deleted one at a time, but written on `checkout.workers`
+/// threads when there are many of them. The directories they go in are all
+/// made first, in order, so that the threads never race to make one.
+///
+/// In a sparse checkout, files left out of it are only put in the index,
+/// marked skip-worktree, and deleted from the working tree if they were
+/// there.
+///
+/// If `cancel` is cancelled part of the way through, the files already
+/// deleted or written are put back the way the index has them before the
+/// error is returned, so the working tree is left as it was.
+pub fn switch_files(
+  repo: &Repo,
+  index: &Index,
+  target: &BTreeMap<BString, (Mode, String)>,
+  force: bool,
+  progress: &mut dyn Progress,
+  cancel: &Cancel,
+) -> Result<Index, String> {
+  repo.require_work_tree()?;
+  let mut removed: Vec<&BString> = index
+    .entries()
+    .iter()
+    .map(|entry| &entry.path)
+    .filter(|path| !target.contains_key(*path))
+    .collect();
+  // the stages of a conflict share a path
+  removed.dedup();
+  let mut result = Index::new();
+  let mut updated = Vec::new();
+  let sparse = Sparse::read(repo);
+  for (path, (mode, hash)) in target {
+    let current = index.get(path);
+    let same =
+      current.is_some_and(|entry| entry.hash == *hash && entry.mode == index::mode_bits(*mode));
+    let skipped = current.is_some_and(|entry| entry.extended_flags & SKIP_WORKTREE != 0);
+
+    // a file left out of a sparse checkout is only in the index, unless it
+    // has local changes to keep
+    if sparse.as_ref().is_some_and(|sparse| !sparse.includes(path)) {
+      let keep = match current {
+        Some(entry) if !skipped && !force && same => index::is_modified(repo, entry)?,
+        _ => false,
+      };
+      if keep {
+        result.add(current.unwrap().clone());
+        continue;
+      }
+      if !skipped && fs::symlink_metadata(repo.work_tree_path(path)).is_ok() {
+        removed.push(path);
+      }
+      let mut entry = IndexEntry::new(path, *mode, hash);
+      entry.extended_flags = SKIP_WORKTREE;
+      result.add(entry);
+      continue;
+    }
+
+    let unchanged = match current {
+      Some(entry) => same && !skipped && !(force && index::is_modified(repo, entry)?),
+      None => false,
+    };
+    match unchanged {
+      true => result.add(current.unwrap().clone()),
+      false => updated.push((path, mode, hash)),
+    }
+  }
+
+  progress.start(
+    "Updating files",
+    Some((removed.len() + updated.len()) as u64),
+  );
+  let mut done: Vec<&BString> = Vec::new();
+  for path in removed {
+    if let Err(e) = cancel.check() {
+      return Err(restore_files(repo, index, &done).err().unwrap_or(e));
+    }
+    remove_file(repo, path)?;
+    done.push(path);
+    progress.update(done.len() as u64);
+  }
+  let workers = match updated.len() >= parallel::THRESHOLD {
+    true => parallel::workers(repo, "checkout", "workers"),
+    false => 1,
+  };
+  if workers > 1 {
+    make_parents(repo, updated.iter().map(|(path, ..)| *path))?;
+  }
+  let removed = done.len();
+  let written = parallel::map(
+    &updated,
+    workers,
+    |(path, mode, hash)| match cancel.is_cancelled() {
+      true => Ok(None),
+      false => checkout_file(repo, path, **mode, hash).map(Some),
+    },
+    |count, _| progress.update((removed + count) as u64),
+  );
+  let mut failed = None;
+  for ((path, ..), entry) in updated.iter().zip(written) {
+    match entry {
+      Ok(Some(entry)) => {
+        result.add(entry);
+        done.push(path);
+      }
+      Ok(None) => (),
+      Err(e) => failed = failed.or(Some(e)),
+    }
+  }
+  if let Err(e) = cancel.check() {
+    return Err(restore_files(repo, index, &done).err().unwrap_or(e));
+  }
+  if let Some(e) = failed {
+    return Err(e);
+  }
+  progress.finish();
+  Ok(result)
+}
+
+/// Makes the directories the given files go in, replacing any file that is
+/// in the way of one.
+fn make_parents<'a>(repo: &Repo, paths: impl Iterator<Item = &'a BString>) -> Result<(), String> {
+  let mut made: BTreeSet<PathBuf> = BTreeSet::new();
+  for path in paths {
+    let dest = repo.work_tree_path(path);
+    let parent = match dest.parent() {
+      Some(parent) if !made.contains(parent) => parent,
+      _ => continue,
+    };
+    if parent.is_file() {
+      fs::remove_file(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    }
+    fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+    made.insert(parent.to_path_buf());
+  }
+  Ok(())
+}
+
+/// Puts files back the way the index has them, deleting the ones it does not
+/// track.
+fn restore_files(repo: &Repo, index: &Index, paths: &[&BString]) -> Result<(), String> {
+  for path in paths {
+    match index.get(path) {
+      Some(entry) => {
+        let mode = entry.tree_mode().unwrap_or(Mode::Normal);
+        checkout_file(repo, path, mode, &entry.hash)?;
+      }
+      None => remove_file(repo, path)?,
+    }
+  }
+  Ok(())
+}
+
+/// Lists the tracked files whose working tree copy differs from the index,
+/// paired with `M` for a modified file, `T` for one whose type changed (such
+/// as a symlink replaced by a regular file) or `D` for a deleted one.
+/// Entries a filesystem watcher vouches for are not looked at.
+pub fn unstaged_changes(repo: &Repo, index: &Index) -> Result<Vec<(char, BString)>, String> {
+  repo.require_work_tree()?;
+  let mut changes = Vec::new();
+  for entry in index.entries().iter().filter(|e| e.stage() == 0) {
+    if entry.assumed_unchanged() || entry.fsmonitor_valid {
+      continue;
+    }
+    let metadata = match fs::symlink_metadata(repo.work_tree_path(&entry.path)) {
+      Ok(metadata) => metadata,
+      Err(_) => {
+        changes.push(('D', entry.path.clone()));
+        continue;
+      }
+    };
+    if index::is_modified(repo, entry)? {
+      let was_link = entry.tree_mode() == Some(Mode::Symbolic);
+      let kind = match was_link != metadata.file_type().is_symlink() {
+        true => 'T',
+        false => 'M',
+      };
+      changes.push((kind, entry.path.clone()));
+    }
+  }
+  Ok(changes)
+}
+
+/// A file or directory in the working tree that is not in the index.
+pub struct Untracked {
+  /// The path, with a trailing `/` for a directory.
+  pub path: BString,
+  pub is_dir: bool,
+  pub ignored: bool,
+}
+
+/// Lists the untracked files in the working tree, sorted by path.
+///
+/// An untracked directory is listed as a single entry. When `split` is set
+/// that only happens if its contents are either all ignored or all not
+/// ignored; a directory mixing the two is broken down into its contents
+/// instead so that callers can tell them apart. Ignored directories are never
+/// descended into.
+pub fn untracked(
+  repo: &Repo,
+  index: &Index,
+  ignore: &mut Ignore,
+  split: bool,
+) -> Result<Vec<Untracked>, String> {
+  repo.require_work_tree()?;
+  let mut result = Vec::new();
+  walk_untracked(repo, index, ignore, b"", split, &mut result)?;
+  Ok(result)
+}
+
+fn walk_untracked(
+  repo: &Repo,
+  index: &Index,
+  ignore: &mut Ignore,
+  dir: &[u8],
+  split: bool,
+  result: &mut Vec<Untracked>,
+) -> Result<(), String> {
+  let full_path = repo.work_tree_path(dir);
+  let entries = fs::read_dir(&full_path).map_err(|e| format!("{}: {}", full_path.display(), e))?;
+  let mut names: Vec<(BString, bool)> = entries
+    .flatten()
+    .map(|e| {
+      let is_dir = e.file_type().is_ok_and(|t| t.is_dir());
+      (BString::from(e.file_name().as_bytes()), is_dir)
+    })
+    .filter(|(name, _)| name != ".git")
+    .collect();
+  names.sort();
+
+  for (name, is_dir) in names {
+    let path = if dir.is_empty() {
+      name
+    } else {
+      BString::from([dir, b"/", &name].concat())
+    };
+    let ignored = ignore.matches(&path, is_dir);
+    if !is_dir {
+      if !index.contains(&path) {
+        result.push(Untracked {
+          path,
+          is_dir,
+          ignored,
+        });
+      }
+      continue;
+    }
+
+    let prefix = BString::from([path.as_slice(), b"/"].concat());
+    let tracked = index.contains_dir(&path);
+    if ignored && !tracked {
+      result.push(Untracked {
+        path: prefix,
+        is_dir,
+        ignored,
+      });
+      continue;
+    }
+
+    ignore.load_dir(&path);
+    let mut inner = Vec::new();
+    walk_untracked(repo, index, ignore, &path, split, &mut inner)?;
+    let uniform = !split || inner.iter().all(|u| !u.ignored) || inner.iter().all(|u| u.ignored);
+    if !tracked && uniform {
+      let ignored = !inner.is_empty() && inner.iter().all(|u| u.ignored);
+      result.push(Untracked {
+        path: prefix,
+        is_dir,
+        ignored,
+      });
+    } else {
+      result.extend(inner);
+    }
+  }
+  Ok(())
+}
+
+/// Lists the untracked files that `status` shows, sorted by path: those not
+/// ignored, with an untracked directory shown as a single entry (with a
+/// trailing `/`) if there is anything in it to show.
+///
+/// When the index has an untracked cache, the directories that have not
+/// changed since the cache was last updated are not read again, and the
+/// cache is brought up to date with the ones that have. How much work that
+/// saved is printed when `GIT_TRACE_UNTRACKED_STATS` is set.
+pub fn untracked_files(repo: &Repo, index: &mut Index) -> Result<Vec<BString>, String> {
+  repo.require_work_tree()?;
+  let mut ignore = Ignore::new(repo);
+  let mut result = Vec::new();
+  let mut cache = index.untracked.take();
+  let mut stats = CacheStats {
+    fsmonitor: cache.as_ref().is_some_and(|cache| cache.fsmonitor),
+    ..Default::default()
+  };
+  let mut reset = false;
+  let root = cache.as_mut().map(|cache| {
+    reset = cache.validate(repo);
+    cache.root()
+  });
+  let walked = walk_status(repo, index, &mut ignore, b"", root, &mut result, &mut stats);
+  if cache.is_some() && env::var_os("GIT_TRACE_UNTRACKED_STATS").is_some_and(|v| !v.is_empty()) {
+    eprintln!(
+      "node creation: {}\ngitignore invalidation: {}\ndirectory invalidation: {}\nopendir: {}",
+      stats.created, stats.gitignore_invalidated, stats.invalidated, stats.opendir
+    );
+  }
+  // a directory read again may have been read because it was racy, and
+  // writing the index is what settles that
+  if cache.is_some() && (reset || stats.opendir > 0 || stats.gitignore_invalidated > 0) {
+    index.mark_changed();
+  }
+  index.untracked = cache;
+  walked?;
+  Ok(result)
+}
+
+/// What happened to the directories of the untracked cache during a walk.
+#[derive(Default)]
+struct CacheStats {
+  /// Whether a filesystem watcher has invalidated the directories that
+  /// changed, so that those still valid need no look.
+  fsmonitor: bool,
+
+  /// Directories new to the cache.
+  created: usize,
+
+  /// Directories dropped with everything below them, as their `.gitignore`
+  /// changed.
+  gitignore_invalidated: usize,
+
+  /// Directories whose contents changed.
+  invalidated: usize,
+
+  /// Directories that had to be read.
+  opendir: usize,
+}
+
+fn walk_status(
+  repo: &Repo,
+  index: &Index,
+  ignore: &mut Ignore,
+  dir: &[u8],
+  mut cached: Option<&mut Dir>,
+  result: &mut Vec<BString>,
+  stats: &mut CacheStats,
+) -> Result<(), String> {
+  let full_path = repo.work_tree_path(dir);
+  let error = |e: std::io::Error| format!("{}: {}", full_path.display(), e);
+  let trusted = stats.fsmonitor && cached.as_ref().is_some_and(|cached| cached.valid);
+  let metadata = fs::metadata(&full_path).map_err(error)?;
+  let exclude = cached
+    .as_ref()
+    .filter(|_| !trusted)
+    .and_then(|_| untracked_cache::exclude_hash(repo, dir));
+
+  // an unchanged directory has the same untracked files and subdirectories
+  // as last time, and the tracked files are in the index
+  let mut names: Vec<(BString, bool)> = match &mut cached {
+    Some(cached) if trusted || cached.is_valid(&metadata, &exclude, index.timestamp()) => cached
+      .dirs
+      .iter()
+      .map(|dir| (dir.name.clone(), true))
+      .chain(
+        cached
+          .untracked
+          .iter()
+          .filter(|name| !name.ends_with(b"/"))
+          .map(|name| (name.clone(), false)),
+      )
+      .collect(),
+    cached => {
+      if let Some(cached) = cached {
+        // the rules of a changed `.gitignore` apply to everything below it
+        if cached.exclude != exclude && cached.stat != Default::default() {
+          stats.gitignore_invalidated += 1;
+          cached.dirs.clear();
+        } else if cached.valid {
+          stats.invalidated += 1;
+        }
+        cached.stat = untracked_cache::StatData::from_metadata(&metadata);
+        cached.exclude = exclude;
+      }
+      stats.opendir += 1;
+      fs::read_dir(&full_path)
+        .map_err(error)?
+        .flatten()
+        .map(|e| {
+          let is_dir = e.file_type().is_ok_and(|t| t.is_dir());
+          (BString::from(e.file_name().as_bytes()), is_dir)
+        })
+        .filter(|(name, _)| name != ".git")
+        .collect()
+    }
+  };
+  names.sort();
+
+  let mut untracked = Vec::new();
+  let mut dirs = Vec::new();
+  for (name, is_dir) in names {
+    let path = match dir.is_empty() {
+      true => name.clone(),
+      false => BString::from([dir, b"/", &name].concat()),
+    };
+    // a submodule is tracked as a whole
+    if index.contains(&path) {
+      continue;
+    }
+    if !is_dir {
+      if !ignore.matches(&path, false) {
+        result.push(path);
+        untracked.push(name);
+      }
+      continue;
+    }
+
+    let tracked = index.contains_dir(&path);
+    if ignore.matches(&path, true) && !tracked {
+      continue;
+    }
+    ignore.load_dir(&path);
+    let mut child = cached.as_mut().map(|cached| {
+      cached.take_dir(&name).unwrap_or_else(|| {
+        stats.created += 1;
+        Dir::new(&name)
+      })
+    });
+    let mut inner = Vec::new();
+    walk_status(
+      repo,
+      index,
+      ignore,
+      &path,
+      child.as_mut(),
+      &mut inner,
+      stats,
+    )?;
+    if tracked {
+      result.extend(inner);
+    } else if !inner.is_empty() {
+      result.push(BString::from([path.as_slice(), b"/"].concat()));
+      untracked.push(BString::from([name.as_slice(), b"/"].concat()));
+    }
+    if let Some(mut child) = child {
+      child.check_only = !tracked;
+      dirs.push(child);
+    }
+  }
+
+  if let Some(cached) = cached {
+    cached.untracked = untracked;
+    cached.dirs = dirs;
+    cached.valid = true;
+  }
+  Ok(())
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{git_rs, init_repo, write_commit_with_tree, write_ref};
+use std::fs;
+
+#[test]
+fn test_add() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  fs::create_dir_all(path.join("d/e"))?;
+  fs::write(path.join(".gitignore"), "*.log\nbuild/\n")?;
+  fs::write(path.join("a"), "a\n")?;
+  fs::write(path.join("d/b"), "b\n")?;
+  fs::write(path.join("d/e/c"), "c\n")?;
+  fs::write(path.join("debug.log"), "log\n")?;
+
+  // nothing is added without a pathspec, -A or -u
+  Command::cargo_bin("git-rs")?
+    .current_dir(path)
+    .arg("add")
+    .assert()
+    .success()
+    .stderr("Nothing specified, nothing added.\nhint: Maybe you wanted to say 'git add .'?\n");
+  assert_eq!(git_rs(path, &["ls-files"])?, "");
+
+  // a directory adds the files inside, leaving out the ignored ones
+  assert_eq!(
+    git_rs(path, &["add", "-v", "d"])?,
+    "add 'd/b'\nadd 'd/e/c'\n"
+  );
+  assert_eq!(git_rs(path, &["add", "."])?, "");
+  assert_eq!(git_rs(path, &["ls-files"])?, ".gitignore\na\nd/b\nd/e/c\n");
+  Command::cargo_bin("git-rs")?
+    .current_dir(path)
+    .args(["add", "nowhere"])
+    .assert()
+    .stdout("fatal: pathspec 'nowhere' did not match any files\n");
+
+  // naming an ignored file is an error unless forced
+  Command::cargo_bin("git-rs")?
+    .current_dir(path)
+    .args(["add", "debug.log"])
+    .assert()
+    .code(1)
+    .stderr(
+      "The following paths are ignored by one of your .gitignore files:\n\
+       debug.log\n\
+       hint: Use -f if you really want to add them.\n",
+    );
+  git_rs(path, &["add", "-f", "debug.log"])?;
+  assert!(git_rs(path, &["ls-files"])?.contains("debug.log\n"));
+
+  // -u only updates and removes what is tracked, -A adds the rest too
+  let hash = git_rs(path, &["write-tree"])?;
+  fs::write(path.join("a"), "changed\n")?;
+  fs::remove_file(path.join("d/b"))?;
+  fs::write(path.join("new"), "new\n")?;
+  assert_eq!(
+    git_rs(path, &["add", "-u", "-n"])?,
+    "add 'a'\nremove 'd/b'\n"
+  );
+  assert_eq!(git_rs(path, &["write-tree"])?, hash);
+  git_rs(path, &["add", "-u"])?;
+  assert_eq!(
+    git_rs(path, &["status", "--porcelain"])?.lines().last(),
+    Some("?? new")
+  );
+  assert_eq!(git_rs(path, &["add", "-A", "-v"])?, "add 'new'\n");
+  assert_eq!(
+    git_rs(path, &["ls-files"])?,
+    ".gitignore\na\nd/e/c\ndebug.log\nnew\n"
+  );
+  assert_eq!(git_rs(path, &["diff-files"])?, "");
+
+  // lots of files are hashed on worker threads, in the same order
+  fs::create_dir(path.join("many"))?;
+  for i in 0..150 {
+    fs::write(path.join(format!("many/{:03}", i)), format!("{}\n", i))?;
+  }
+  git_rs(path, &["-c", "index.threads=4", "add", "many"])?;
+  let listed = git_rs(path, &["ls-files", "-s", "many"])?;
+  assert_eq!(listed.lines().count(), 150);
+  assert_eq!(git_rs(path, &["diff-files"])?, "");
+  assert_eq!(git_rs(path, &["status", "--porcelain", "many"])?, "");
+  Ok(())
+}
+
+#[test]
+fn test_add_sparse() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  for dir in ["in", "out"] {
+    fs::create_dir(path.join(dir))?;
+    fs::write(path.join(dir).join("f"), "f\n")?;
+  }
+  git_rs(path, &["add", "in", "out"])?;
+  let tree = git_rs(path, &["write-tree"])?;
+  let commit = write_commit_with_tree(path, tree.trim(), &[], 1000, "first")?;
+  write_ref(path, "refs/heads/master", &commit)?;
+  git_rs(path, &["sparse-checkout", "set", "in"])?;
+  assert!(!path.join("out/f").exists());
+
+  // new files outside of the sparse checkout are left out
+  fs::create_dir(path.join("out"))?;
+  fs::write(path.join("out/g"), "g\n")?;
+  fs::write(path.join("in/g"), "g\n")?;
+  Command::cargo_bin("git-rs")?
+    .current_dir(path)
+    .args(["add", "-A"])
+    .assert()
+    .code(1)
+    .stderr(
+      "The following paths and/or pathspecs matched paths that exist\n\
+       outside of your sparse-checkout definition, so will not be\n\
+       updated in the index:\n\
+       out/g\n\
+       hint: If you intend to update such entries, try one of the following:\n\
+       hint: * Use the --sparse option.\n\
+       hint: * Disable or modify the sparsity rules.\n",
+    );
+  assert_eq!(git_rs(path, &["ls-files"])?, "in/f\nin/g\nout/f\n");
+
+  // unless asked for
+  git_rs(path, &["add", "--sparse", "out/g"])?;
+  assert_eq!(git_rs(path, &["ls-files"])?, "in/f\nin/g\nout/f\nout/g\n");
+  Ok(())
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref};
+use std::{fs, io::Write};
+
+#[test]
+fn test_branch_verbose() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  hash_object(&canonical_path, "tree", b"")?;
+
+  // base <- one <- two on master, base <- side on topic
+  let base = write_commit(&canonical_path, &[], 1000, "base")?;
+  let one = write_commit(&canonical_path, &[&base], 2000, "one")?;
+  let two = write_commit(&canonical_path, &[&one], 3000, "two")?;
+  let side = write_commit(&canonical_path, &[&base], 4000, "side")?;
+  write_ref(&canonical_path, "refs/heads/master", &two)?;
+  git_rs(&canonical_path, &["branch", "topic", &side])?;
+  let output = git_rs(&canonical_path, &["branch"])?;
+  assert_eq!(output, "* master\n  topic\n");
+
+  // topic tracks master, which is two commits ahead and one behind it
+  fs::OpenOptions::new()
+    .append(true)
+    .open(canonical_path.join(".git/config"))?
+    .write_all(b"[branch \"topic\"]\n\tremote = .\n\tmerge = refs/heads/master\n")?;
+  let output = git_rs(&canonical_path, &["branch", "-v"])?;
+  assert_eq!(
+    output,
+    format!(
+      "* master {} two\n  topic  {} [ahead 1, behind 2] side\n",
+      &two[..7],
+      &side[..7]
+    )
+  );
+  let output = git_rs(&canonical_path, &["branch", "-vv"])?;
+  assert_eq!(
+    output,
+    format!(
+      "* master {} two\n  topic  {} [master: ahead 1, behind 2] side\n",
+      &two[..7],
+      &side[..7]
+    )
+  );
+
+  let output = git_rs(&canonical_path, &["branch", "topic"])?;
+  assert_eq!(output, "fatal: a branch named 'topic' already exists\n");
+  Ok(())
+}
+
+#[test]
+fn test_branch_json() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  hash_object(&canonical_path, "tree", b"")?;
+  let base = write_commit(&canonical_path, &[], 1000, "base")?;
+  let side = write_commit(&canonical_path, &[&base], 2000, "side")?;
+  write_ref(&canonical_path, "refs/heads/master", &base)?;
+  git_rs(&canonical_path, &["branch", "topic", &side])?;
+  fs::OpenOptions::new()
+    .append(true)
+    .open(canonical_path.join(".git/config"))?
+    .write_all(b"[branch \"topic\"]\n\tremote = .\n\tmerge = refs/heads/master\n")?;
+
+  let output = git_rs(&canonical_path, &["branch", "--json"])?;
+  let branches: serde_json::Value = serde_json::from_str(&output)?;
+  assert_eq!(
+    branches,
+    serde_json::json!([
+      {"name": "master", "hash": base, "current": true},
+      {
+        "name": "topic",
+        "hash": side,
+        "current": false,
+        "upstream": {"name": "master", "ahead": 1, "behind": 0},
+      },
+    ])
+  );
+
+  // a detached HEAD has no name
+  fs::write(canonical_path.join(".git/HEAD"), format!("{}\n", side))?;
+  let output = git_rs(&canonical_path, &["branch", "--json", "--contains", &side])?;
+  let branches: serde_json::Value = serde_json::from_str(&output)?;
+  assert_eq!(
+    branches,
+    serde_json::json!([
+      {"name": null, "hash": side, "current": true},
+      {
+        "name": "topic",
+        "hash": side,
+        "current": false,
+        "upstream": {"name": "master", "ahead": 1, "behind": 0},
+      },
+    ])
+  );
+  Ok(())
+}
+
+#[test]
+fn test_branch_color() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  write_ref(path, "refs/heads/master", &base)?;
+  write_ref(path, "refs/heads/topic", &base)?;
+
+  // not colored when piped, unless asked for
+  assert_eq!(git_rs(path, &["branch"])?, "* master\n  topic\n");
+  assert_eq!(
+    git_rs(path, &["branch", "--color"])?,
+    "* \x1b[32mmaster\x1b[m\n  topic\x1b[m\n"
+  );
+  assert_eq!(
+    git_rs(path, &["-c", "color.ui=always", "branch", "--no-color"])?,
+    "* master\n  topic\n"
+  );
+
+  // the colors of the slots can be changed
+  assert_eq!(
+    git_rs(
+      path,
+      &[
+        "-c",
+        "color.branch=always",
+        "-c",
+        "color.branch.current=bold #ff0000 blue",
+        "-c",
+        "color.branch.local=208 ul",
+        "branch",
+      ]
+    )?,
+    "* \x1b[1;38;2;255;0;0;44mmaster\x1b[m\n  \x1b[4;38;5;208mtopic\x1b[m\n"
+  );
+  assert_eq!(
+    git_rs(
+      path,
+      &["-c", "color.branch.current=red blue green", "branch"]
+    )?,
+    "fatal: invalid color value: red blue green\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,227 @@
+mod common;
+
+use common::{
+  daemon, git_rs, hash_object, init_repo, write_commit, write_commit_with_tree, write_ref,
+  write_tree,
+};
+use git_rs_core::cancel::Cancel;
+use git_rs_core::index::Index;
+use git_rs_core::object::tree;
+use git_rs_core::progress::{NoProgress, Progress};
+use git_rs_core::remote::{fetch, push, refspec::RefSpec, Remote};
+use git_rs_core::repack::{self, Options};
+use git_rs_core::{repo::Repo, rev::walk::RevWalk, worktree};
+use std::path::Path;
+use std::{fs, thread};
+
+#[test]
+fn test_cancel_walk() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  let repo = Repo::discover(path)?;
+
+  // a clone of the token cancels the walk from another thread
+  let cancel = Cancel::new();
+  let mut walk = RevWalk::new(&repo);
+  walk.push(&next);
+  walk.cancel(&cancel);
+  let token = cancel.clone();
+  thread::spawn(move || token.cancel()).join().unwrap();
+  assert!(cancel.is_cancelled());
+  assert_eq!(walk.run().err().as_deref(), Some("operation cancelled"));
+
+  let mut walk = RevWalk::new(&repo);
+  walk.push(&next);
+  walk.cancel(&Cancel::new());
+  assert_eq!(walk.run()?, [next, base]);
+  Ok(())
+}
+
+/// Progress that cancels the operation once it has done some of its work.
+struct CancelAfter {
+  cancel: Cancel,
+  after: u64,
+}
+
+impl Progress for CancelAfter {
+  fn start(&mut self, _title: &str, _total: Option<u64>) {}
+
+  fn update(&mut self, done: u64) {
+    if done >= self.after {
+      self.cancel.cancel();
+    }
+  }
+
+  fn finish(&mut self) {}
+}
+
+#[test]
+fn test_cancel_switch_files() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  for name in ["a", "b", "c", "d"] {
+    fs::write(path.join(name), format!("{}\n", name))?;
+  }
+  git_rs(path, &["update-index", "--add", "a", "b", "c", "d"])?;
+  let old = git_rs(path, &["write-tree"])?;
+  fs::write(path.join("a"), "changed\n")?;
+  fs::write(path.join("e"), "new\n")?;
+  git_rs(path, &["update-index", "--force-remove", "b"])?;
+  git_rs(path, &["update-index", "--add", "a", "e"])?;
+  let new = git_rs(path, &["write-tree"])?;
+  git_rs(path, &["read-tree", old.trim()])?;
+  git_rs(path, &["restore", "."])?;
+  fs::remove_file(path.join("e"))?;
+  let before = fs::read(path.join(".git/index"))?;
+
+  // cancelled once b is deleted and a written, with e still to go
+  let repo = Repo::discover(path)?;
+  let index = Index::read(&repo)?;
+  let target = tree::flatten(&repo, new.trim())?;
+  let cancel = Cancel::new();
+  let mut progress = CancelAfter {
+    cancel: cancel.clone(),
+    after: 2,
+  };
+  let result = worktree::switch_files(&repo, &index, &target, false, &mut progress, &cancel);
+  assert_eq!(result.err().as_deref(), Some("operation cancelled"));
+
+  // which puts back what was done
+  for name in ["a", "b", "c", "d"] {
+    assert_eq!(fs::read_to_string(path.join(name))?, format!("{}\n", name));
+  }
+  assert!(!path.join("e").exists());
+  assert_eq!(fs::read(path.join(".git/index"))?, before);
+  assert_eq!(git_rs(path, &["diff-files"])?, "");
+  assert_eq!(git_rs(path, &["write-tree"])?, old);
+  Ok(())
+}
+
+/// The files in the pack directory of a repository, and the directories
+/// objects are quarantined in.
+fn leftovers(path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+  let mut names = Vec::new();
+  for dir in [".git/objects", ".git/objects/pack"] {
+    for entry in fs::read_dir(path.join(dir)).into_iter().flatten() {
+      let name = entry?.file_name().to_string_lossy().into_owned();
+      if dir.ends_with("pack") || name.starts_with("incoming-") {
+        names.push(name);
+      }
+    }
+  }
+  Ok(names)
+}
+
+#[test]
+fn test_cancel_repack() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let blobs: Vec<String> = (0..20)
+    .map(|i| hash_object(path, "blob", format!("{}\n", i).as_bytes()))
+    .collect::<Result<_, _>>()?;
+  let names: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+  let entries: Vec<(&str, &str)> = names
+    .iter()
+    .zip(&blobs)
+    .map(|(name, blob)| (name.as_str(), blob.as_str()))
+    .collect();
+  let tree = write_tree(path, &entries)?;
+  let commit = write_commit_with_tree(path, &tree, &[], 1000, "first")?;
+  write_ref(path, "refs/heads/master", &commit)?;
+  let repo = Repo::discover(path)?;
+  let objects = repack::reachable(&repo)?;
+  let options = Options::new(&repo)?;
+
+  // once compressed, part of the way through writing the pack, which is
+  // deleted
+  let cancel = Cancel::new();
+  let mut progress = CancelAfter {
+    cancel: cancel.clone(),
+    after: 5,
+  };
+  let result = repack::write(&repo, &objects, &options, &mut progress, &cancel);
+  assert_eq!(result.err().as_deref(), Some("operation cancelled"));
+  assert_eq!(leftovers(path)?, Vec::<String>::new());
+
+  // and indexing a pack that came in
+  let mut pack = Vec::new();
+  repack::send(
+    &repo,
+    &objects,
+    &options,
+    &mut pack,
+    &mut NoProgress,
+    &Cancel::new(),
+  )?;
+  let (_other_dir, other) = init_repo()?;
+  let other_repo = Repo::discover(&other)?;
+  let cancel = Cancel::new();
+  let mut progress = CancelAfter {
+    cancel: cancel.clone(),
+    after: 5,
+  };
+  let result = repack::index::write(&other_repo, &pack, None, &mut progress, &cancel);
+  assert_eq!(result.err().as_deref(), Some("operation cancelled"));
+  assert_eq!(leftovers(&other)?, Vec::<String>::new());
+  let name = repack::index::write(&other_repo, &pack, None, &mut NoProgress, &Cancel::new())?;
+  assert_eq!(leftovers(&other)?.len(), 2, "pack-{}", name);
+  Ok(())
+}
+
+#[test]
+fn test_cancel_fetch_and_push() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  write_tree(&upstream, &[])?;
+  let base = write_commit(&upstream, &[], 1000, "base")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+  let (_daemon, url) = daemon(&[
+    "--base-path",
+    root.to_str().unwrap(),
+    "--export-all",
+    "--enable=receive-pack",
+  ])?;
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!(
+      "{}[remote \"origin\"]\n\turl = {}{}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n",
+      config, url, name
+    ),
+  )?;
+  let repo = Repo::discover(path)?;
+  let remote = Remote::get(&repo, "origin")?;
+  let cancel = Cancel::new();
+  cancel.cancel();
+
+  // a cancelled fetch updates no refs and leaves nothing of what came in
+  let options = fetch::Options {
+    cancel: cancel.clone(),
+    ..Default::default()
+  };
+  let result = fetch::fetch(&repo, &remote, &[], &options, &mut NoProgress);
+  assert_eq!(result.err().as_deref(), Some("operation cancelled"));
+  assert!(!path.join(".git/refs/remotes/origin/master").exists());
+  assert_eq!(leftovers(path)?, Vec::<String>::new());
+  assert!(!repo.objects.exists(&base));
+
+  // and a cancelled push sends no pack, so the remote takes nothing
+  write_tree(path, &[])?;
+  let next = write_commit(path, &[], 2000, "next")?;
+  write_ref(path, "refs/heads/master", &next)?;
+  let options = push::Options {
+    cancel,
+    ..Default::default()
+  };
+  let refspecs = [RefSpec::parse("master:refs/heads/next")?];
+  let result = push::push(&repo, &remote, &refspecs, &options, &mut NoProgress);
+  assert_eq!(result.err().as_deref(), Some("operation cancelled"));
+  assert!(!upstream.join(".git/refs/heads/next").exists());
+  assert!(!Repo::discover(&upstream)?.objects.exists(&next));
+  Ok(())
+}
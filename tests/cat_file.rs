@@ -1,10 +1,19 @@
+mod common;
+
 use assert_cmd::prelude::*;
+use common::{
+  blob_hash, git_rs, hash_object, init_repo, write_commit, write_commit_with_tree, write_ref,
+  write_tree, EMPTY_TREE,
+};
+use flate2::{write::ZlibEncoder, Compression};
 use hex_literal::hex;
 use predicates::prelude::*;
+use sha1::{Digest, Sha1};
 use std::{
   fs::{self, File},
   io::Write,
   process::Command,
+  thread,
 };
 use tempdir::TempDir;
 
@@ -19,6 +28,285 @@ fn test_cat_file() -> Result<(), Box<dyn std::error::Error>> {
   Ok(())
 }
 
+#[test]
+fn test_cat_file_paths() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let one = hash_object(path, "blob", b"one\n")?;
+  let two = hash_object(path, "blob", b"two\n")?;
+  let inner = write_tree(path, &[("b.txt", &two)])?;
+  let mut payload = b"100644 a.txt\0".to_vec();
+  payload.extend(hex::decode(&one)?);
+  payload.extend(b"40000 dir\0");
+  payload.extend(hex::decode(&inner)?);
+  let tree = hash_object(path, "tree", &payload)?;
+  let commit = write_commit_with_tree(path, &tree, &[], 1000, "base")?;
+  write_ref(path, "refs/heads/master", &commit)?;
+
+  // paths in the tree of a revision
+  assert_eq!(git_rs(path, &["cat-file", "-p", "HEAD:a.txt"])?, "one\n");
+  assert_eq!(
+    git_rs(path, &["cat-file", "blob", "master:dir/b.txt"])?,
+    "two\n"
+  );
+  assert_eq!(
+    git_rs(path, &["cat-file", "-p", "HEAD:dir"])?,
+    format!("100644 blob {}\tb.txt\n", two)
+  );
+  assert_eq!(git_rs(path, &["cat-file", "-t", "HEAD:dir/"])?, "tree\n");
+  assert_eq!(git_rs(path, &["cat-file", "-s", "HEAD:a.txt"])?, "4\n");
+  // the size of a tree is that of its entries
+  assert_eq!(
+    git_rs(path, &["cat-file", "-s", "HEAD^{tree}"])?,
+    format!("{}\n", payload.len())
+  );
+  assert_eq!(
+    git_rs(path, &["show", "HEAD:missing"])?,
+    "fatal: path 'missing' does not exist in 'HEAD'\n"
+  );
+  let mut exists = Command::cargo_bin("git-rs")?;
+  exists
+    .current_dir(path)
+    .args(["cat-file", "-e", "HEAD:missing"]);
+  exists.assert().code(1).stdout("");
+
+  // paths in the stages of the index
+  let mut update = assert_cmd::Command::cargo_bin("git-rs")?;
+  update
+    .current_dir(path)
+    .args(["update-index", "--index-info"])
+    .write_stdin(format!(
+      "100644 {} 0\tclean.txt\n100644 {} 2\tboth.txt\n100644 {} 3\tboth.txt\n",
+      one, one, two
+    ))
+    .assert()
+    .success();
+  assert_eq!(git_rs(path, &["cat-file", "-p", ":clean.txt"])?, "one\n");
+  assert_eq!(git_rs(path, &["cat-file", "-p", ":0:clean.txt"])?, "one\n");
+  assert_eq!(git_rs(path, &["cat-file", "-p", ":2:both.txt"])?, "one\n");
+  assert_eq!(git_rs(path, &["cat-file", "-p", ":3:both.txt"])?, "two\n");
+  assert_eq!(
+    git_rs(path, &["cat-file", "-p", ":1:both.txt"])?,
+    "fatal: Not a valid object name :1:both.txt\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_cat_file_empty_tree() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+
+  // the empty tree can be read without ever having been written
+  assert_eq!(git_rs(path, &["cat-file", "-t", EMPTY_TREE])?, "tree\n");
+  assert_eq!(git_rs(path, &["cat-file", "-s", EMPTY_TREE])?, "0\n");
+  assert_eq!(git_rs(path, &["ls-tree", EMPTY_TREE])?, "");
+  assert!(!path.join(".git/objects/4b").exists());
+
+  // so commits of it can be shown and merged, with it as their base
+  let x = hash_object(path, "blob", b"x\n")?;
+  let tree = write_tree(path, &[("x", &x)])?;
+  let empty = write_commit(path, &[], 1000, "empty")?;
+  let other = write_commit_with_tree(path, &tree, &[], 2000, "other")?;
+  write_ref(path, "refs/heads/master", &empty)?;
+  assert_eq!(git_rs(path, &["show", "-s", "HEAD^{tree}"])?, "");
+  assert_eq!(
+    git_rs(
+      path,
+      &["merge-tree", "--allow-unrelated-histories", &empty, &other]
+    )?,
+    format!("{}\n", tree)
+  );
+  Ok(())
+}
+
+#[test]
+fn test_cat_file_concurrent() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+
+  // every thread writes the same object and reads it back while the others
+  // are still writing it, so a half written object would be noticed
+  let threads: Vec<_> = (0..8)
+    .map(|i| {
+      let repo = canonical_path.clone();
+      thread::spawn(move || {
+        let name = format!("file{}", i);
+        fs::write(repo.join(&name), "shared\n").unwrap();
+        for _ in 0..5 {
+          let hash = git_rs(&repo, &["hash-object", &name, "blob", "--write"]).unwrap();
+          let output = git_rs(&repo, &["cat-file", "blob", hash.trim()]).unwrap();
+          assert_eq!(output, "shared\n");
+        }
+      })
+    })
+    .collect();
+  for thread in threads {
+    thread.join().unwrap();
+  }
+
+  // nothing is left behind but the object itself
+  let dir = canonical_path.join(".git/objects/8a");
+  let names: Vec<_> = fs::read_dir(dir)?
+    .map(|entry| entry.unwrap().file_name())
+    .collect();
+  assert_eq!(names, ["205e8dc3e7c7914d69c3e900f2e944d77bb100"]);
+  Ok(())
+}
+
+#[test]
+fn test_cat_file_packed() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+
+  // a pack holding a blob, a delta against it by offset and another by hash
+  let base = b"hello world\n".repeat(4);
+  let ofs_result = [&base[..], b"and more\n"].concat();
+  let ref_result = [b"and less\n", &base[..12]].concat();
+  let ofs_delta = [
+    &[
+      base.len() as u8,
+      ofs_result.len() as u8,
+      0x90,
+      base.len() as u8,
+      9,
+    ][..],
+    b"and more\n",
+  ]
+  .concat();
+  let ref_delta = [
+    &[base.len() as u8, ref_result.len() as u8, 9][..],
+    b"and less\n",
+    &[0x90, 12],
+  ]
+  .concat();
+  let base_hash = blob_hash(&base);
+  let mut pack = b"PACK\0\0\0\x02\0\0\0\x03".to_vec();
+  let base_offset = pack.len();
+  pack.extend(pack_entry(3, base.len(), &[], &base)?);
+  let ofs_offset = pack.len();
+  let distance = [(ofs_offset - base_offset) as u8];
+  pack.extend(pack_entry(6, ofs_delta.len(), &distance, &ofs_delta)?);
+  let ref_offset = pack.len();
+  let base_id = hex::decode(&base_hash)?;
+  pack.extend(pack_entry(7, ref_delta.len(), &base_id, &ref_delta)?);
+  let checksum = Sha1::digest(&pack).to_vec();
+  pack.extend(&checksum);
+
+  let mut objects = [
+    (hex::decode(&base_hash)?, base_offset),
+    (hex::decode(blob_hash(&ofs_result))?, ofs_offset),
+    (hex::decode(blob_hash(&ref_result))?, ref_offset),
+  ];
+  objects.sort();
+  let mut index = b"\xfftOc\0\0\0\x02".to_vec();
+  for first in 0..=255u8 {
+    let count = objects.iter().filter(|(id, _)| id[0] <= first).count() as u32;
+    index.extend(count.to_be_bytes());
+  }
+  objects.iter().for_each(|(id, _)| index.extend(id));
+  objects.iter().for_each(|_| index.extend([0; 4]));
+  objects
+    .iter()
+    .for_each(|(_, offset)| index.extend((*offset as u32).to_be_bytes()));
+  index.extend(&checksum);
+  let index_checksum = Sha1::digest(&index).to_vec();
+  index.extend(index_checksum);
+
+  let dir = canonical_path.join(".git/objects/pack");
+  fs::create_dir_all(&dir)?;
+  fs::write(dir.join("pack-test.pack"), pack)?;
+  fs::write(dir.join("pack-test.idx"), index)?;
+
+  for data in [&base, &ofs_result, &ref_result] {
+    let hash = blob_hash(data);
+    let output = git_rs(&canonical_path, &["cat-file", "blob", &hash])?;
+    assert_eq!(output.as_bytes(), &data[..]);
+  }
+  Ok(())
+}
+
+/// An entry of a pack whose size fits in one byte after the type, followed
+/// by its delta base (if any) and its compressed data.
+fn pack_entry(
+  kind: u8,
+  size: usize,
+  base: &[u8],
+  data: &[u8],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+  let mut entry = vec![0x80 | kind << 4 | (size & 15) as u8, (size >> 4) as u8];
+  entry.extend(base);
+  let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(data)?;
+  entry.extend(encoder.finish()?);
+  Ok(entry)
+}
+
+#[test]
+fn test_cat_file_batch_check() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  // versions of a file that pack as deltas against each other
+  let mut data = String::new();
+  let mut blobs = Vec::new();
+  let mut parents: Vec<String> = Vec::new();
+  for version in 0..4u64 {
+    for line in 0..40 {
+      data.push_str(&format!("line {} of version {}\n", line, version));
+    }
+    let blob = hash_object(path, "blob", data.as_bytes())?;
+    let tree = write_tree(path, &[("data.txt", &blob)])?;
+    let parents_refs: Vec<&str> = parents.iter().map(String::as_str).collect();
+    let commit = write_commit_with_tree(path, &tree, &parents_refs, 1000 + version, "v")?;
+    parents = vec![commit];
+    blobs.push((blob, data.len(), tree));
+  }
+  write_ref(path, "refs/heads/master", &parents[0])?;
+  git_rs(path, &["repack", "-a", "-d", "-q", "--threads=1"])?;
+  for entry in fs::read_dir(path.join(".git/objects"))? {
+    let entry = entry?;
+    if entry.file_name().len() == 2 {
+      fs::remove_dir_all(entry.path())?;
+    }
+  }
+  let extra = hash_object(path, "blob", b"loose\n")?;
+
+  let mut input = String::new();
+  let mut expected = String::new();
+  for (blob, size, tree) in &blobs {
+    input.push_str(&format!("{}\n{}\n", blob, tree));
+    expected.push_str(&format!("{} blob {}\n{} tree 36\n", blob, size, tree));
+  }
+  input.push_str(&format!(
+    "HEAD:data.txt\n{}\nnope\n{}\n",
+    extra,
+    "0".repeat(40)
+  ));
+  expected.push_str(&format!(
+    "{} blob {}\n{} blob 6\nnope missing\n{} missing\n",
+    blobs[3].0,
+    blobs[3].1,
+    extra,
+    "0".repeat(40)
+  ));
+  let output = assert_cmd::Command::cargo_bin("git-rs")?
+    .current_dir(path)
+    .args(["cat-file", "--batch-check"])
+    .write_stdin(input)
+    .output()?;
+  assert_eq!(String::from_utf8(output.stdout)?, expected);
+
+  // -t and -s only read the header too, delta or not
+  for (blob, size, _) in &blobs {
+    assert_eq!(git_rs(path, &["cat-file", "-t", blob])?, "blob\n");
+    assert_eq!(
+      git_rs(path, &["cat-file", "-s", blob])?,
+      format!("{}\n", size)
+    );
+  }
+  assert_eq!(git_rs(path, &["cat-file", "-s", &extra])?, "6\n");
+  assert_eq!(git_rs(path, &["cat-file", "-t", "HEAD"])?, "commit\n");
+  Ok(())
+}
+
 fn cat_file_template(
   obj: &str,
   hash: &str,
@@ -44,8 +332,8 @@ fn cat_file_template(
 
   // Add the object file
   let dir_path = &canonical_path.join(".git").join("objects").join(&hash[..2]);
-  fs::create_dir(&dir_path)?;
-  let mut f = File::create(&dir_path.join(&hash[2..]))?;
+  fs::create_dir(dir_path)?;
+  let mut f = File::create(dir_path.join(&hash[2..]))?;
   f.write_all(compressed_data)?;
   f.flush()?;
 
@@ -0,0 +1,60 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{git_rs, init_repo};
+use std::fs;
+
+#[test]
+fn test_check_mailmap() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  fs::write(
+    path.join(".mailmap"),
+    concat!(
+      "# comment\n",
+      "Proper Name <commit@example.com>\n",
+      "<proper@example.com> <other@example.com>\n",
+      "Both Name <both@example.com> <b@example.com>\n",
+      "Specific <spec@example.com> Old Name <s@example.com>\n",
+      "Default <def@example.com> <s@example.com>\n",
+    ),
+  )?;
+  let contacts = [
+    ("A <commit@example.com>", "Proper Name <commit@example.com>"),
+    ("A <COMMIT@example.com>", "Proper Name <COMMIT@example.com>"),
+    ("B <other@example.com>", "B <proper@example.com>"),
+    ("C <b@example.com>", "Both Name <both@example.com>"),
+    ("old name <s@example.com>", "Specific <spec@example.com>"),
+    ("Else <s@example.com>", "Default <def@example.com>"),
+    ("<commit@example.com>", "Proper Name <commit@example.com>"),
+    ("Nobody <n@example.com>", "Nobody <n@example.com>"),
+    ("<n@example.com>", "<n@example.com>"),
+  ];
+  for (contact, expected) in contacts {
+    assert_eq!(
+      git_rs(path, &["check-mailmap", contact])?,
+      format!("{}\n", expected)
+    );
+  }
+
+  // mailmap.file comes after .mailmap, so wins over it
+  fs::write(
+    path.join("more"),
+    "Later <later@example.com> <commit@example.com>\n",
+  )?;
+  let mut cmd = Command::cargo_bin("git-rs")?;
+  cmd
+    .current_dir(path)
+    .args(["-c", "mailmap.file=more", "check-mailmap", "--stdin"])
+    .args(["<b@example.com>"])
+    .write_stdin("X <commit@example.com>\nY <other@example.com>\n")
+    .assert()
+    .success()
+    .stdout("Both Name <both@example.com>\nLater <later@example.com>\nY <proper@example.com>\n");
+
+  assert_eq!(
+    git_rs(path, &["check-mailmap", "nobody"])?,
+    "fatal: unable to parse contact: nobody\n"
+  );
+  Ok(())
+}
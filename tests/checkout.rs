@@ -0,0 +1,42 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit_with_tree};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+#[test]
+fn test_checkout_modes() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let script = hash_object(path, "blob", b"#!/bin/sh\necho hi\n")?;
+  let text = hash_object(path, "blob", b"text\n")?;
+  let target = hash_object(path, "blob", b"run.sh")?;
+  let mut payload = Vec::new();
+  for (mode, name, hash) in [
+    ("120000", "link", &target),
+    ("100755", "run.sh", &script),
+    ("100644", "text.txt", &text),
+  ] {
+    payload.extend(format!("{} {}\0", mode, name).as_bytes());
+    payload.extend(hex::decode(hash)?);
+  }
+  let tree = hash_object(path, "tree", &payload)?;
+  let commit = write_commit_with_tree(path, &tree, &[], 1000, "modes")?;
+
+  let out = path.join("out");
+  git_rs(path, &["checkout", &commit, out.to_str().unwrap()])?;
+  let mode = |name: &str| -> Result<u32, std::io::Error> {
+    Ok(fs::symlink_metadata(out.join(name))?.permissions().mode())
+  };
+  assert_eq!(mode("run.sh")? & 0o111, 0o111);
+  assert_eq!(mode("text.txt")? & 0o111, 0);
+  assert!(fs::symlink_metadata(out.join("link"))?
+    .file_type()
+    .is_symlink());
+  assert_eq!(fs::read_link(out.join("link"))?.to_str(), Some("run.sh"));
+  assert_eq!(
+    fs::read_to_string(out.join("link"))?,
+    "#!/bin/sh\necho hi\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit_with_tree, write_ref, write_tree};
+
+#[test]
+fn test_cherry() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let blob = |data: &str| hash_object(path, "blob", data.as_bytes());
+  let (base, two) = (blob("one\ntwo\nthree\n")?, blob("one\n2\nthree\n")?);
+  let (g, h) = (blob("g\n")?, blob("h\n")?);
+
+  let tree = write_tree(path, &[("f", &base)])?;
+  let root = write_commit_with_tree(path, &tree, &[], 1000, "base")?;
+
+  // upstream adds g, then takes the change to two
+  let tree = write_tree(path, &[("f", &base), ("g", &g)])?;
+  let upstream = write_commit_with_tree(path, &tree, &[&root], 2000, "add g")?;
+  let tree = write_tree(path, &[("f", &two), ("g", &g)])?;
+  let upstream = write_commit_with_tree(path, &tree, &[&upstream], 3000, "two")?;
+  write_ref(path, "refs/heads/upstream", &upstream)?;
+
+  let tree = write_tree(path, &[("f", &two)])?;
+  let picked = write_commit_with_tree(path, &tree, &[&root], 2000, "change two")?;
+  let tree = write_tree(path, &[("f", &two), ("h", &h)])?;
+  let new = write_commit_with_tree(path, &tree, &[&picked], 4000, "add h")?;
+  write_ref(path, "refs/heads/master", &new)?;
+
+  assert_eq!(
+    git_rs(path, &["cherry", "upstream"])?,
+    format!("- {}\n+ {}\n", picked, new)
+  );
+  assert_eq!(
+    git_rs(path, &["cherry", "-v", "upstream", "master", &picked])?,
+    format!("+ {} add h\n", new)
+  );
+  assert_eq!(
+    git_rs(path, &["cherry"])?,
+    "fatal: Could not find a tracked remote branch, please specify <upstream> manually.\n"
+  );
+  Ok(())
+}
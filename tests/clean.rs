@@ -0,0 +1,42 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit_with_tree, write_ref, write_tree};
+use std::fs;
+
+#[test]
+fn test_clean() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let blob = hash_object(&canonical_path, "blob", b"*.log\n!keep.log\n")?;
+  let tree = write_tree(&canonical_path, &[(".gitignore", &blob)])?;
+  let commit = write_commit_with_tree(&canonical_path, &tree, &[], 1000, "first")?;
+  write_ref(&canonical_path, "refs/heads/master", &commit)?;
+  git_rs(&canonical_path, &["reset", "--hard", "--force"])?;
+
+  for file in ["notes.txt", "debug.log", "keep.log", "build/out.txt"] {
+    let path = canonical_path.join(file);
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, "")?;
+  }
+
+  let output = git_rs(&canonical_path, &["clean"])?;
+  assert!(output.contains("refusing to clean"));
+
+  let output = git_rs(&canonical_path, &["clean", "-n", "-d"])?;
+  assert_eq!(
+    output,
+    "Would remove build/\nWould remove keep.log\nWould remove notes.txt\n"
+  );
+  let output = git_rs(&canonical_path, &["clean", "-n", "-X"])?;
+  assert_eq!(output, "Would remove debug.log\n");
+
+  let output = git_rs(&canonical_path, &["clean", "-f"])?;
+  assert_eq!(output, "Removing keep.log\nRemoving notes.txt\n");
+  assert!(canonical_path.join("build/out.txt").exists());
+  assert!(canonical_path.join("debug.log").exists());
+
+  git_rs(&canonical_path, &["clean", "-f", "-d", "-x"])?;
+  assert!(!canonical_path.join("build").exists());
+  assert!(!canonical_path.join("debug.log").exists());
+  assert!(canonical_path.join(".gitignore").exists());
+  Ok(())
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use common::{git_rs, git_rs_as, git_rs_as_command, init_repo};
+use std::{
+  fs,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+#[test]
+fn test_commit() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let output = git_rs_as(&canonical_path, &["commit", "-m", "empty"])?;
+  assert_eq!(
+    output,
+    "On branch master\n\nNo commits yet\n\n\
+     nothing to commit (create/copy files and use \"git add\" to track)\n"
+  );
+
+  fs::write(canonical_path.join("a"), "a\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "a"])?;
+  let output = git_rs_as(&canonical_path, &["commit", "-m", "initial"])?;
+  assert_eq!(
+    output,
+    "[master (root-commit) 31f47a9] initial\n \
+     Author: A U Thor <author@example.com>\n \
+     1 file changed, 1 insertion(+)\n \
+     create mode 100644 a\n"
+  );
+
+  // an editor that leaves only comments aborts the commit
+  let output = git_rs_as(&canonical_path, &["commit", "--allow-empty"])?;
+  assert_eq!(output, "Aborting commit due to empty commit message.\n");
+
+  let output = git_rs_as(&canonical_path, &["commit", "-m", "again"])?;
+  assert_eq!(
+    output,
+    "On branch master\nnothing to commit, working tree clean\n"
+  );
+
+  // --date sets the author date, which may be relative
+  let args = ["commit", "--allow-empty", "-m", "dated"];
+  let output = git_rs_as(
+    &canonical_path,
+    &[&args[..], &["--date", "2005-04-07T22:13:13+02:00"]].concat(),
+  )?;
+  assert!(output.contains("\n Date: Thu Apr 7 22:13:13 2005 +0200\n"));
+  git_rs_as(
+    &canonical_path,
+    &[&args[..], &["--date", "3.weeks.ago"]].concat(),
+  )?;
+  let head = fs::read_to_string(canonical_path.join(".git/refs/heads/master"))?;
+  let commit = git_rs(&canonical_path, &["cat-file", "commit", head.trim()])?;
+  let author = commit.lines().find(|l| l.starts_with("author ")).unwrap();
+  let time: u64 = author.rsplit(' ').nth(1).unwrap().parse()?;
+  let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+  assert!((now - 21 * 86400 - 60..=now - 21 * 86400).contains(&time));
+  Ok(())
+}
+
+#[test]
+fn test_commit_amend() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  fs::write(canonical_path.join("a"), "a\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "a"])?;
+  git_rs_as(&canonical_path, &["commit", "-m", "initial"])?;
+  fs::write(canonical_path.join("b"), "b\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "b"])?;
+  fs::write(canonical_path.join("u"), "u\n")?;
+
+  // the editor sees the old message and a summary of the amended commit
+  let output = git_rs_as_command(&canonical_path, &["commit", "--amend"])?
+    .env("GIT_EDITOR", "cat")
+    .output()?;
+  let output = String::from_utf8(output.stdout)?;
+  assert_eq!(
+    output,
+    "initial\n\n\
+     # Please enter the commit message for your changes. Lines starting\n\
+     # with '#' will be ignored, and an empty message aborts the commit.\n\
+     #\n\
+     # Author:    A U Thor <author@example.com>\n\
+     # Date:      Tue Nov 14 22:13:20 2023 +0000\n\
+     #\n\
+     # On branch master\n\
+     #\n\
+     # Initial commit\n\
+     #\n\
+     # Changes to be committed:\n\
+     #\tnew file:   a\n\
+     #\tnew file:   b\n\
+     #\n\
+     # Untracked files:\n\
+     #\tu\n\
+     #\n\
+     [master (root-commit) 6fe4e26] initial\n \
+     Author: A U Thor <author@example.com>\n \
+     Date: Tue Nov 14 22:13:20 2023 +0000\n \
+     2 files changed, 2 insertions(+)\n \
+     create mode 100644 a\n \
+     create mode 100644 b\n"
+  );
+
+  let output = git_rs_as_command(&canonical_path, &["commit", "--amend", "-q"])?
+    .env("GIT_EDITOR", "sed -i 's/^initial$/amended\\n\\n\\nbody  /'")
+    .output()?;
+  assert_eq!(output.stdout, b"");
+  let head = fs::read_to_string(canonical_path.join(".git/refs/heads/master"))?;
+  let commit = git_rs(&canonical_path, &["cat-file", "commit", head.trim()])?;
+  assert!(commit.ends_with("\n\namended\n\nbody\n"), "{}", commit);
+
+  let reflog = fs::read_to_string(canonical_path.join(".git/logs/refs/heads/master"))?;
+  let reasons: Vec<&str> = reflog
+    .lines()
+    .filter_map(|line| line.split_once('\t'))
+    .map(|(_, reason)| reason)
+    .collect();
+  assert_eq!(
+    reasons,
+    [
+      "commit (initial): initial",
+      "commit (amend): initial",
+      "commit (amend): amended"
+    ]
+  );
+  assert_eq!(
+    fs::read_to_string(canonical_path.join(".git/logs/HEAD"))?,
+    reflog
+  );
+  Ok(())
+}
+
+#[test]
+fn test_commit_signoff() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let message = || -> Result<String, Box<dyn std::error::Error>> {
+    let head = fs::read_to_string(canonical_path.join(".git/refs/heads/master"))?;
+    let commit = git_rs(&canonical_path, &["cat-file", "commit", head.trim()])?;
+    Ok(commit.split_once("\n\n").unwrap().1.to_string())
+  };
+  let args = ["commit", "--allow-empty", "-q", "-s"];
+  git_rs_as(&canonical_path, &[&args[..], &["-m", "initial"]].concat())?;
+  assert_eq!(
+    message()?,
+    "initial\n\nSigned-off-by: C O Mitter <committer@example.com>\n"
+  );
+
+  // the same sign-off is not added twice in a row
+  git_rs_as(
+    &canonical_path,
+    &[&args[..], &["--amend", "--no-edit"]].concat(),
+  )?;
+  assert_eq!(
+    message()?,
+    "initial\n\nSigned-off-by: C O Mitter <committer@example.com>\n"
+  );
+
+  // but joins an existing trailer block
+  let text =
+    "fix\n\nSigned-off-by: C O Mitter <committer@example.com>\nReviewed-by: R <r@example.com>";
+  git_rs_as(
+    &canonical_path,
+    &[&args[..], &["--amend", "-m", text]].concat(),
+  )?;
+  assert_eq!(
+    message()?,
+    format!(
+      "{}\nSigned-off-by: C O Mitter <committer@example.com>\n",
+      text
+    )
+  );
+  Ok(())
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{git_rs, hash_object, init_repo, EMPTY_TREE};
+use std::{fs, process::Command};
+
+#[test]
+fn test_write_tree_and_commit_tree() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  fs::create_dir(canonical_path.join("dir"))?;
+  fs::write(canonical_path.join("a.txt"), "hello\n")?;
+  fs::write(canonical_path.join("dir/b.txt"), "b\n")?;
+  git_rs(
+    &canonical_path,
+    &["update-index", "--add", "a.txt", "dir/b.txt"],
+  )?;
+
+  let tree = git_rs(&canonical_path, &["write-tree"])?;
+  assert_eq!(tree, "ba5c5c6cec9f85c0481046e49254bcd90f650666\n");
+  let subtree = git_rs(&canonical_path, &["write-tree", "--prefix", "dir/"])?;
+  assert_eq!(subtree, "f8f7aefc2900a3d737cea9eee45729fd55761e1a\n");
+
+  let mut commit_cmd = Command::cargo_bin("git-rs")?;
+  commit_cmd
+    .current_dir(&canonical_path)
+    .args(["commit-tree", tree.trim(), "-m", "msg"])
+    .env("GIT_AUTHOR_NAME", "A U Thor")
+    .env("GIT_AUTHOR_EMAIL", "author@example.com")
+    .env("GIT_AUTHOR_DATE", "@1000 +0000")
+    .env("GIT_COMMITTER_NAME", "C O Mitter")
+    .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+    .env("GIT_COMMITTER_DATE", "@1000 +0000");
+  commit_cmd
+    .assert()
+    .success()
+    .stdout("a6d811a68322c534589173f51e72098bae5d0351\n");
+
+  // dates may also be given in ISO 8601 or RFC 2822 format
+  hash_object(&canonical_path, "tree", b"")?;
+  let mut commit_cmd = Command::cargo_bin("git-rs")?;
+  commit_cmd
+    .current_dir(&canonical_path)
+    .args(["commit-tree", EMPTY_TREE, "-m", "msg"])
+    .env("GIT_AUTHOR_NAME", "A U Thor")
+    .env("GIT_AUTHOR_EMAIL", "author@example.com")
+    .env("GIT_AUTHOR_DATE", "2005-04-07T22:13:13+02:00")
+    .env("GIT_COMMITTER_NAME", "C O Mitter")
+    .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+    .env("GIT_COMMITTER_DATE", "Thu, 07 Apr 2005 22:13:13 +0200");
+  commit_cmd
+    .assert()
+    .success()
+    .stdout("8e4496baec17d1113f7224bfafdbb87ef534fdb5\n");
+
+  // objects the index refers to must exist
+  git_rs(
+    &canonical_path,
+    &[
+      "update-index",
+      "--add",
+      "--cacheinfo",
+      "100644,1111111111111111111111111111111111111111,missing.txt",
+    ],
+  )?;
+  let output = git_rs(&canonical_path, &["write-tree"])?;
+  assert!(output.contains("invalid object 100644 1111111111111111111111111111111111111111"));
+  let output = git_rs(&canonical_path, &["write-tree", "--missing-ok"])?;
+  assert_eq!(output.len(), 41);
+  Ok(())
+}
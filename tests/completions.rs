@@ -0,0 +1,42 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref};
+use std::fs;
+
+#[test]
+fn test_completions() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  write_ref(path, "refs/heads/master", &base)?;
+  write_ref(path, "refs/heads/topic", &base)?;
+  write_ref(path, "refs/tags/v1.0", &base)?;
+  fs::create_dir_all(path.join(".git/refs/remotes/origin"))?;
+  write_ref(path, "refs/remotes/origin/master", &base)?;
+
+  // the scripts offer every command and its options...
+  let bash = git_rs(path, &["completions", "bash"])?;
+  assert!(bash.contains("cat-file)"));
+  assert!(bash.contains("--porcelain"));
+  assert!(bash.ends_with("complete -F _git-rs_refs -o bashdefault -o default git-rs\n"));
+  assert!(!bash.contains("complete -F _git-rs "));
+  let zsh = git_rs(path, &["completions", "zsh"])?;
+  assert!(zsh.starts_with("#compdef git-rs\n"));
+  assert!(zsh.contains("_git-rs_generated \"$@\""));
+  let fish = git_rs(path, &["completions", "fish"])?;
+  assert!(fish.contains("-a \"(git-rs completions --refs 2>/dev/null)\""));
+  let powershell = git_rs(path, &["completions", "powershell"])?;
+  assert!(powershell.contains("completions --refs"));
+
+  // ...and ask for the refs of the repository being completed in
+  assert_eq!(
+    git_rs(path, &["completions", "--refs"])?,
+    "HEAD\nmaster\ntopic\nv1.0\norigin/master\n"
+  );
+  assert_eq!(
+    git_rs(path.parent().unwrap(), &["completions", "--refs"])?,
+    ""
+  );
+  Ok(())
+}
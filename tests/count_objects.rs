@@ -0,0 +1,37 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit_with_tree, write_ref, write_tree};
+use std::fs;
+
+#[test]
+fn test_count_objects() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  assert_eq!(
+    git_rs(path, &["count-objects"])?,
+    "0 objects, 0 kilobytes\n"
+  );
+
+  let blob = hash_object(path, "blob", b"hello\n")?;
+  let tree = write_tree(path, &[("hello.txt", &blob)])?;
+  let commit = write_commit_with_tree(path, &tree, &[], 1000, "hello")?;
+  write_ref(path, "refs/heads/master", &commit)?;
+  let summary = git_rs(path, &["count-objects"])?;
+  assert!(summary.starts_with("3 objects, "), "{}", summary);
+
+  // packing leaves the loose objects behind, to be pruned
+  git_rs(path, &["repack", "-q"])?;
+  hash_object(path, "blob", b"loose\n")?;
+  let objects = path.join(".git/objects");
+  fs::write(objects.join("pack/stray.keep"), "")?;
+  fs::write(objects.join(&blob[..2]).join("tmp_obj_1"), "half")?;
+  let verbose = git_rs(path, &["count-objects", "-v"])?;
+  let lines: Vec<&str> = verbose.lines().collect();
+  assert_eq!(lines[0], "count: 4");
+  assert_eq!(&lines[2..4], ["in-pack: 3", "packs: 1"]);
+  assert_eq!(
+    &lines[5..],
+    ["prune-packable: 3", "garbage: 2", "size-garbage: 0"]
+  );
+  Ok(())
+}
@@ -0,0 +1,218 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{
+  daemon, git_rs, git_rs_err, hash_object, init_repo, write_commit_with_tree, write_ref, write_tree,
+};
+use std::{fs, path::Path, process::Command};
+
+/// The number of objects in the packs of a repository.
+fn in_pack(repo: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+  let stats = git_rs(repo, &["count-objects", "-v"])?;
+  let count = stats
+    .lines()
+    .find_map(|line| line.strip_prefix("in-pack: "))
+    .unwrap();
+  Ok(count.parse()?)
+}
+
+#[test]
+fn test_daemon_fetch() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let one = hash_object(&upstream, "blob", b"one\n")?;
+  let tree = write_tree(&upstream, &[("a.txt", &one)])?;
+  let base = write_commit_with_tree(&upstream, &tree, &[], 1000, "base")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  let tag = hash_object(
+    &upstream,
+    "tag",
+    format!(
+      "object {}\ntype commit\ntag v1\ntagger T <t@example.com> 1000 +0000\n\nv1\n",
+      base
+    )
+    .as_bytes(),
+  )?;
+  write_ref(&upstream, "refs/tags/v1", &tag)?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+
+  // only repositories that say they may be are served, without --export-all
+  let (_daemon, url) = daemon(&["--base-path", root.to_str().unwrap()])?;
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  assert_eq!(
+    git_rs(path, &["fetch", &format!("{}{}", url, name)])?,
+    format!(
+      "fatal: remote error: access denied or repository not exported: /{}\n",
+      name
+    )
+  );
+  fs::write(upstream.join(".git/git-daemon-export-ok"), "")?;
+  let refspecs = [
+    "+refs/heads/*:refs/remotes/origin/*",
+    "refs/tags/*:refs/tags/*",
+  ];
+  let url = format!("{}{}", url, name);
+  let (code, report) = git_rs_err(path, &[&["fetch", &url][..], &refspecs].concat())?;
+  assert_eq!(
+    (code, report),
+    (
+      0,
+      format!(
+        "From {}\n * [new branch]      master     -> origin/master\n * [new tag]         v1         -> v1\n",
+        url
+      )
+    )
+  );
+  assert_eq!(
+    fs::read_to_string(path.join(".git/refs/remotes/origin/master"))?,
+    format!("{}\n", base)
+  );
+  assert_eq!(git_rs(path, &["cat-file", "-t", &tag])?, "tag\n");
+  assert_eq!(git_rs(path, &["cat-file", "-p", &one])?, "one\n");
+  assert_eq!(in_pack(path)?, 4);
+
+  // only what is new is sent the next time
+  let two = hash_object(&upstream, "blob", b"two\n")?;
+  let tree_two = write_tree(&upstream, &[("a.txt", &one), ("b.txt", &two)])?;
+  let next = write_commit_with_tree(&upstream, &tree_two, &[&base], 2000, "next")?;
+  write_ref(&upstream, "refs/heads/master", &next)?;
+  let (code, report) = git_rs_err(path, &[&["fetch", &url][..], &refspecs].concat())?;
+  assert_eq!(code, 0);
+  assert!(report.ends_with(&format!(
+    "   {}..{}  master     -> origin/master\n",
+    &base[..7],
+    &next[..7]
+  )));
+  assert_eq!(in_pack(path)?, 7);
+  assert_eq!(git_rs(path, &["cat-file", "-p", &two])?, "two\n");
+
+  // repositories outside the directories given are never served
+  let (_other, other_url) = daemon(&["--export-all", path.to_str().unwrap()])?;
+  let other_url = other_url.trim_end_matches('/');
+  assert!(git_rs(
+    path,
+    &["fetch", &format!("{}{}", other_url, upstream.display())]
+  )?
+  .contains("access denied or repository not exported"));
+  let (code, report) = git_rs_err(
+    &upstream,
+    &[
+      "fetch",
+      &format!("{}{}", other_url, path.display()),
+      "origin/master",
+    ],
+  )?;
+  assert_eq!(code, 0);
+  assert!(report.ends_with(" origin/master -> FETCH_HEAD\n"));
+  Ok(())
+}
+
+/// Runs `git-rs fetch`, and returns the commits it told the remote it had.
+fn haves(repo: &Path, args: &[&str]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+  let trace = repo.join(".git/packet-trace");
+  let _ = fs::remove_file(&trace);
+  Command::cargo_bin("git-rs")?
+    .current_dir(repo)
+    .arg("fetch")
+    .args(args)
+    .env("GIT_TRACE_PACKET", &trace)
+    .output()?;
+  Ok(
+    fs::read_to_string(&trace)
+      .unwrap_or_default()
+      .lines()
+      .filter_map(|line| {
+        line
+          .split_once("git> have ")
+          .map(|(_, have)| have.to_string())
+      })
+      .collect(),
+  )
+}
+
+#[test]
+fn test_daemon_negotiation() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let one = hash_object(&upstream, "blob", b"one\n")?;
+  let tree = write_tree(&upstream, &[("a.txt", &one)])?;
+  // 40 commits, newest first
+  let mut history: Vec<String> = Vec::new();
+  for i in 0..40 {
+    let parents: Vec<&str> = history.first().map(String::as_str).into_iter().collect();
+    let commit = write_commit_with_tree(&upstream, &tree, &parents, 1000 + i, "commit")?;
+    history.insert(0, commit);
+  }
+  write_ref(&upstream, "refs/heads/master", &history[0])?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+  let (_daemon, url) = daemon(&["--export-all", "--base-path", root.to_str().unwrap()])?;
+  let url = format!("{}{}", url, name);
+
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  assert_eq!(
+    haves(path, &[&url, "master:refs/remotes/origin/master"])?,
+    Vec::<String>::new()
+  );
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  // each fetch is of a new commit, on top of what the repository has
+  let mut time = 2000;
+  let mut advance = || -> Result<(), Box<dyn std::error::Error>> {
+    time += 1;
+    let next = write_commit_with_tree(&upstream, &tree, &[&history[0]], time, "next")?;
+    write_ref(&upstream, "refs/heads/master", &next)?;
+    Ok(())
+  };
+
+  // consecutive: newest first, a batch at a time until one is in common
+  advance()?;
+  assert_eq!(haves(path, &[&url, "master"])?, history[..32]);
+
+  // skipping: further and further apart, and the oldest
+  fs::write(
+    path.join(".git/config"),
+    format!("{}[fetch]\n\tnegotiationAlgorithm = skipping\n", config),
+  )?;
+  advance()?;
+  let skipped: Vec<String> = [0, 2, 5, 10, 18, 30, 39]
+    .iter()
+    .map(|&i| history[i].clone())
+    .collect();
+  assert_eq!(haves(path, &[&url, "master"])?, skipped);
+
+  // noop: nothing at all
+  fs::write(
+    path.join(".git/config"),
+    format!("{}[fetch]\n\tnegotiationAlgorithm = noop\n", config),
+  )?;
+  advance()?;
+  assert_eq!(haves(path, &[&url, "master"])?, Vec::<String>::new());
+
+  // only what the tips given reach
+  fs::write(path.join(".git/config"), &config)?;
+  let other = hash_object(path, "blob", b"other\n")?;
+  let other_tree = write_tree(path, &[("b.txt", &other)])?;
+  let side = write_commit_with_tree(path, &other_tree, &[], 3000, "side")?;
+  write_ref(path, "refs/heads/side", &side)?;
+  advance()?;
+  assert_eq!(
+    haves(path, &[&url, "master", "--negotiation-tip", "side"])?,
+    vec![side.clone()]
+  );
+  advance()?;
+  assert_eq!(
+    haves(path, &[&url, "master", "--negotiation-tip=heads/*"])?,
+    [side]
+  );
+
+  fs::write(
+    path.join(".git/config"),
+    format!("{}[fetch]\n\tnegotiationAlgorithm = bogus\n", config),
+  )?;
+  assert_eq!(
+    git_rs(path, &["fetch", &url, "master"])?,
+    "fatal: unknown fetch negotiation algorithm 'bogus'\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use common::{git_rs, init_repo};
+use std::fs;
+
+#[test]
+fn test_raw_diff_plumbing() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  fs::create_dir(canonical_path.join("d"))?;
+  fs::write(canonical_path.join("a"), "a\n")?;
+  fs::write(canonical_path.join("c"), "c\n")?;
+  fs::write(canonical_path.join("d/b"), "b\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "a", "c", "d/b"])?;
+  let old = git_rs(&canonical_path, &["write-tree"])?;
+
+  fs::write(canonical_path.join("a"), "a2\n")?;
+  fs::remove_file(canonical_path.join("c"))?;
+  fs::write(canonical_path.join("new"), "n\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "new"])?;
+
+  let output = git_rs(&canonical_path, &["diff-files"])?;
+  assert_eq!(
+    output,
+    ":100644 100644 78981922613b2afb6025042ff6bd878ac1994e85 0000000000000000000000000000000000000000 M\ta\n\
+     :100644 000000 f2ad6c76f0115a6ba5b00456a849810e7ec0af20 0000000000000000000000000000000000000000 D\tc\n"
+  );
+  let output = git_rs(
+    &canonical_path,
+    &["diff-index", "--name-status", old.trim()],
+  )?;
+  assert_eq!(output, "M\ta\nD\tc\nA\tnew\n");
+  let output = git_rs(&canonical_path, &["diff-index", "--cached", old.trim()])?;
+  assert_eq!(
+    output,
+    ":000000 100644 0000000000000000000000000000000000000000 8ba3a16384aacc37d01564b28401755ce8053f51 A\tnew\n"
+  );
+
+  git_rs(&canonical_path, &["update-index", "--remove", "a", "c"])?;
+  let new = git_rs(&canonical_path, &["write-tree"])?;
+  let output = git_rs(&canonical_path, &["diff-tree", old.trim(), new.trim()])?;
+  assert_eq!(
+    output,
+    ":100644 100644 78981922613b2afb6025042ff6bd878ac1994e85 c1827f07e114c20547dc6a7296588870a4b5b62c M\ta\n\
+     :100644 000000 f2ad6c76f0115a6ba5b00456a849810e7ec0af20 0000000000000000000000000000000000000000 D\tc\n\
+     :000000 100644 0000000000000000000000000000000000000000 8ba3a16384aacc37d01564b28401755ce8053f51 A\tnew\n"
+  );
+  let output = git_rs(
+    &canonical_path,
+    &[
+      "diff-tree",
+      "-r",
+      "-z",
+      "--name-only",
+      old.trim(),
+      new.trim(),
+    ],
+  )?;
+  assert_eq!(output, "a\0c\0new\0");
+  Ok(())
+}
+
+#[test]
+fn test_diff_tree_stats() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  fs::create_dir(path.join("d"))?;
+  fs::write(path.join("a"), "one\ntwo\n")?;
+  fs::write(path.join("d/b"), "b\n")?;
+  git_rs(path, &["update-index", "--add", "a", "d/b"])?;
+  let old = git_rs(path, &["write-tree"])?;
+  fs::write(path.join("a"), "one\n2\n3\n")?;
+  fs::write(path.join("d/b"), "\0b\n")?;
+  git_rs(path, &["update-index", "a", "d/b"])?;
+  let new = git_rs(path, &["write-tree"])?;
+  let diff_tree = |args: &[&str]| {
+    let args = [&["diff-tree"], args, &[old.trim(), new.trim()]].concat();
+    git_rs(path, &args)
+  };
+
+  // the summaries look into subtrees even without -r
+  assert_eq!(diff_tree(&["--numstat"])?, "2\t1\ta\n-\t-\td/b\n");
+  assert_eq!(diff_tree(&["--numstat", "-z"])?, "2\t1\ta\0-\t-\td/b\0");
+  assert_eq!(
+    diff_tree(&["--shortstat"])?,
+    " 2 files changed, 2 insertions(+), 1 deletion(-)\n"
+  );
+  assert_eq!(
+    diff_tree(&["--stat=40"])?,
+    " a   |   3 ++-\n d/b | Bin 2 -> 3 bytes\n 2 files changed, 2 insertions(+), 1 deletion(-)\n"
+  );
+  let stat: serde_json::Value = serde_json::from_str(&diff_tree(&["--stat", "--json"])?)?;
+  assert_eq!(
+    stat,
+    serde_json::json!([
+      {"path": "a", "added": 2, "deleted": 1},
+      {"path": "d/b", "added": null, "deleted": null},
+    ])
+  );
+  Ok(())
+}
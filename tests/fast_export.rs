@@ -0,0 +1,67 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit_with_tree, write_ref, write_tree};
+use std::fs;
+
+#[test]
+fn test_fast_export() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let one = hash_object(&canonical_path, "blob", b"one\n")?;
+  let two = hash_object(&canonical_path, "blob", b"two\n")?;
+  let tree = write_tree(&canonical_path, &[("a", &one), ("b c", &one)])?;
+  let first = write_commit_with_tree(&canonical_path, &tree, &[], 1000, "first")?;
+  let tree = write_tree(&canonical_path, &[("a", &two)])?;
+  let second = write_commit_with_tree(&canonical_path, &tree, &[&first], 2000, "second")?;
+  write_ref(&canonical_path, "refs/heads/master", &second)?;
+  write_ref(&canonical_path, "refs/heads/old", &first)?;
+
+  let output = git_rs(&canonical_path, &["fast-export", "--all"])?;
+  assert_eq!(
+    output,
+    "blob\nmark :1\ndata 4\none\n\n\
+     reset refs/heads/old\n\
+     commit refs/heads/old\n\
+     mark :2\n\
+     author A U Thor <author@example.com> 1000 +0000\n\
+     committer C O Mitter <committer@example.com> 1000 +0000\n\
+     data 6\nfirst\n\
+     M 100644 :1 a\n\
+     M 100644 :1 \"b c\"\n\
+     \n\
+     blob\nmark :3\ndata 4\ntwo\n\n\
+     commit refs/heads/master\n\
+     mark :4\n\
+     author A U Thor <author@example.com> 2000 +0000\n\
+     committer C O Mitter <committer@example.com> 2000 +0000\n\
+     data 7\nsecond\n\
+     from :2\n\
+     M 100644 :3 a\n\
+     D \"b c\"\n\n"
+  );
+
+  // symbolic refs are left out, and only the branch they point at is reset
+  fs::create_dir_all(canonical_path.join(".git/refs/remotes/origin"))?;
+  write_ref(&canonical_path, "refs/remotes/origin/master", &second)?;
+  fs::write(
+    canonical_path.join(".git/refs/remotes/origin/HEAD"),
+    "ref: refs/remotes/origin/master\n",
+  )?;
+  let output = git_rs(&canonical_path, &["fast-export", "--all"])?;
+  assert!(!output.contains("origin/HEAD"), "{}", output);
+  assert!(output.ends_with("reset refs/remotes/origin/master\nfrom :4\n\n"));
+
+  // a range leaves out the excluded commits, so the first exported commit
+  // lists its whole tree
+  let output = git_rs(&canonical_path, &["fast-export", "old..master"])?;
+  assert_eq!(
+    output,
+    "blob\nmark :1\ndata 4\ntwo\n\n\
+     commit refs/heads/master\n\
+     mark :2\n\
+     author A U Thor <author@example.com> 2000 +0000\n\
+     committer C O Mitter <committer@example.com> 2000 +0000\n\
+     data 7\nsecond\n\
+     M 100644 :1 a\n\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,627 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{
+  daemon, git_rs, git_rs_err, hash_object, init_repo, serve_files, serve_files_over_tls,
+  serve_files_with_auth, write_commit_with_tree, write_ref, write_tree, TLS_CERTIFICATE,
+};
+use std::{fs, path::Path, process::Command};
+
+/// Writes the lists of refs and packs a dumb server serves, as
+/// `update-server-info` would.
+fn update_server_info(
+  repo: &Path,
+  refs: &[(&str, &str)],
+) -> Result<(), Box<dyn std::error::Error>> {
+  let git_dir = repo.join(".git");
+  fs::create_dir_all(git_dir.join("info"))?;
+  let info: String = refs
+    .iter()
+    .map(|(name, hash)| format!("{}\t{}\n", hash, name))
+    .collect();
+  fs::write(git_dir.join("info/refs"), info)?;
+  let mut packs = String::new();
+  for entry in fs::read_dir(git_dir.join("objects/pack"))? {
+    let name = entry?.file_name().to_string_lossy().into_owned();
+    if name.ends_with(".pack") {
+      packs.push_str(&format!("P {}\n", name));
+    }
+  }
+  fs::create_dir_all(git_dir.join("objects/info"))?;
+  fs::write(git_dir.join("objects/info/packs"), packs + "\n")?;
+  Ok(())
+}
+
+#[test]
+fn test_fetch_dumb_http() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let one = hash_object(&upstream, "blob", b"one\n")?;
+  let tree = write_tree(&upstream, &[("a.txt", &one)])?;
+  let base = write_commit_with_tree(&upstream, &tree, &[], 1000, "base")?;
+  let topic = write_commit_with_tree(&upstream, &tree, &[&base], 2000, "topic")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  write_ref(&upstream, "refs/heads/topic", &topic)?;
+  // some objects packed, the rest loose
+  git_rs(&upstream, &["repack", "-a", "-d", "-q"])?;
+  let two = hash_object(&upstream, "blob", b"two\n")?;
+  let tree_two = write_tree(&upstream, &[("a.txt", &one), ("b.txt", &two)])?;
+  let master = write_commit_with_tree(&upstream, &tree_two, &[&base], 3000, "two")?;
+  write_ref(&upstream, "refs/heads/master", &master)?;
+  write_ref(&upstream, "refs/tags/v1", &base)?;
+  update_server_info(
+    &upstream,
+    &[
+      ("refs/heads/master", &master),
+      ("refs/heads/topic", &topic),
+      ("refs/tags/v1", &base),
+    ],
+  )?;
+  let url = format!("http://{}/.git", serve_files(&upstream)?);
+
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  fs::write(
+    path.join(".git/config"),
+    fs::read_to_string(path.join(".git/config"))?
+      + &format!(
+        "[remote \"origin\"]\n\turl = {}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+         \ttagOpt = --no-tags\n",
+        url
+      ),
+  )?;
+
+  // the configured refspecs, with the objects from both the pack and loose
+  let (code, report) = git_rs_err(path, &["fetch"])?;
+  assert_eq!(
+    (code, report),
+    (
+      0,
+      format!(
+        "From {}\n * [new branch]      master     -> origin/master\n * [new branch]      topic      -> origin/topic\n",
+        url.trim_end_matches(".git")
+      )
+    )
+  );
+  let remote_ref =
+    |name: &str| fs::read_to_string(path.join(".git/refs/remotes/origin").join(name));
+  assert_eq!(remote_ref("master")?, format!("{}\n", master));
+  assert_eq!(remote_ref("topic")?, format!("{}\n", topic));
+  assert_eq!(git_rs(path, &["cat-file", "-p", &two])?, "two\n");
+  assert_eq!(git_rs(path, &["cat-file", "-t", &topic])?, "commit\n");
+  assert_eq!(
+    fs::read_to_string(path.join(".git/FETCH_HEAD"))?,
+    format!(
+      "{}\tnot-for-merge\tbranch 'master' of {}\n{}\tnot-for-merge\tbranch 'topic' of {}\n",
+      master,
+      url.trim_end_matches(".git"),
+      topic,
+      url.trim_end_matches(".git")
+    )
+  );
+  // fetching again changes nothing
+  assert_eq!(git_rs_err(path, &["fetch"])?, (0, String::new()));
+
+  // master moves on, and topic is rewritten
+  let three = write_commit_with_tree(&upstream, &tree, &[&master], 4000, "three")?;
+  let rewritten = write_commit_with_tree(&upstream, &tree_two, &[&base], 5000, "topic again")?;
+  update_server_info(
+    &upstream,
+    &[
+      ("refs/heads/master", &three),
+      ("refs/heads/topic", &rewritten),
+      ("refs/tags/v1", &base),
+    ],
+  )?;
+  // without a `+` the rewrite is refused
+  let (code, report) = git_rs_err(
+    path,
+    &["fetch", "origin", "topic:refs/remotes/origin/topic"],
+  )?;
+  assert_eq!(code, 1);
+  assert!(report.ends_with(" ! [rejected]        topic      -> origin/topic  (non-fast-forward)\n"));
+  assert_eq!(remote_ref("topic")?, format!("{}\n", topic));
+  let (_, report) = git_rs_err(path, &["fetch", "-v"])?;
+  assert_eq!(
+    report.lines().skip(1).collect::<Vec<_>>(),
+    [
+      format!(
+        "   {}..{}  master     -> origin/master",
+        &master[..7],
+        &three[..7]
+      ),
+      format!(
+        " + {}...{} topic      -> origin/topic  (forced update)",
+        &topic[..7],
+        &rewritten[..7]
+      ),
+    ]
+  );
+
+  // refs fetched by name go to FETCH_HEAD, to be merged
+  let (_, report) = git_rs_err(path, &["fetch", &url, "tag", "v1", "master"])?;
+  assert!(report.ends_with(
+    " * [new tag]         v1         -> v1\n * branch            master     -> FETCH_HEAD\n"
+  ));
+  assert_eq!(
+    fs::read_to_string(path.join(".git/refs/tags/v1"))?,
+    format!("{}\n", base)
+  );
+  assert_eq!(
+    fs::read_to_string(path.join(".git/FETCH_HEAD"))?
+      .lines()
+      .nth(1),
+    Some(
+      format!(
+        "{}\t\tbranch 'master' of {}",
+        three,
+        url.trim_end_matches(".git")
+      )
+      .as_str()
+    )
+  );
+
+  assert!(git_rs(path, &["fetch", "nowhere"])?
+    .contains("'nowhere' does not appear to be a git repository"));
+  assert!(
+    git_rs(path, &["fetch", "origin", "missing"])?.contains("couldn't find remote ref missing")
+  );
+  Ok(())
+}
+
+#[test]
+fn test_fetch_http_auth() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let one = hash_object(&upstream, "blob", b"one\n")?;
+  let tree = write_tree(&upstream, &[("a.txt", &one)])?;
+  let base = write_commit_with_tree(&upstream, &tree, &[], 1000, "base")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  git_rs(&upstream, &["repack", "-a", "-d", "-q"])?;
+  update_server_info(&upstream, &[("refs/heads/master", &base)])?;
+  // "user:secret"
+  let addr = serve_files_with_auth(&upstream, Some("Basic dXNlcjpzZWNyZXQ="))?;
+  let url = format!("http://{}/.git", addr);
+
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  assert_eq!(
+    git_rs(path, &["fetch", &url])?,
+    format!(
+      "fatal: could not read Username for 'http://{}': terminal prompts disabled\n",
+      addr
+    )
+  );
+  assert_eq!(
+    git_rs(
+      path,
+      &["fetch", &format!("http://user:wrong@{}/.git", addr)]
+    )?,
+    format!(
+      "fatal: Authentication failed for 'http://user@{}/.git'\n",
+      addr
+    )
+  );
+  let (code, _) = git_rs_err(
+    path,
+    &[
+      "fetch",
+      &format!("http://user:secret@{}/.git", addr),
+      "master",
+    ],
+  )?;
+  assert_eq!(code, 0);
+  assert_eq!(git_rs(path, &["cat-file", "-p", &one])?, "one\n");
+
+  // a helper is asked once, and told the credential worked
+  let helper = path.join("helper.sh");
+  let log = path.join("helper.log");
+  let password = path.join("password");
+  fs::write(
+    &helper,
+    format!(
+      "echo \"$1\" >>{}\ntest \"$1\" = get && printf 'username=user\\npassword=%s\\n' \"$(cat {})\"\n",
+      log.display(),
+      password.display()
+    ),
+  )?;
+  fs::write(password.as_path(), "secret")?;
+  fs::write(
+    path.join(".git/config"),
+    fs::read_to_string(path.join(".git/config"))?
+      + &format!("[credential]\n\thelper = !sh {}\n", helper.display()),
+  )?;
+  let commit = write_commit_with_tree(&upstream, &tree, &[&base], 2000, "next")?;
+  update_server_info(&upstream, &[("refs/heads/master", &commit)])?;
+  let (code, _) = git_rs_err(path, &["fetch", &url, "master"])?;
+  assert_eq!(code, 0);
+  assert_eq!(git_rs(path, &["cat-file", "-t", &commit])?, "commit\n");
+  assert_eq!(fs::read_to_string(&log)?, "get\nstore\n");
+
+  // and told to forget one that is turned down
+  fs::write(&log, "")?;
+  fs::write(password.as_path(), "stale")?;
+  assert_eq!(
+    git_rs(path, &["fetch", &url, "master"])?,
+    format!("fatal: Authentication failed for '{}'\n", url)
+  );
+  assert_eq!(fs::read_to_string(&log)?, "get\nerase\n");
+  Ok(())
+}
+
+/// Runs `git-rs fetch` with the proxy environment variables given (and no
+/// others), returning its standard output.
+fn fetch_with_env(
+  repo: &Path,
+  args: &[&str],
+  vars: &[(&str, &str)],
+) -> Result<String, Box<dyn std::error::Error>> {
+  let mut command = Command::cargo_bin("git-rs")?;
+  for var in [
+    "http_proxy",
+    "https_proxy",
+    "HTTPS_PROXY",
+    "all_proxy",
+    "ALL_PROXY",
+  ] {
+    command.env_remove(var);
+  }
+  for var in ["no_proxy", "NO_PROXY", "GIT_SSL_CAINFO"] {
+    command.env_remove(var);
+  }
+  let output = command
+    .current_dir(repo)
+    .arg("fetch")
+    .args(args)
+    .envs(vars.iter().copied())
+    .output()?;
+  Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_fetch_http_settings() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let one = hash_object(&upstream, "blob", b"one\n")?;
+  let tree = write_tree(&upstream, &[("a.txt", &one)])?;
+  let base = write_commit_with_tree(&upstream, &tree, &[], 1000, "base")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  git_rs(&upstream, &["repack", "-a", "-d", "-q"])?;
+  update_server_info(&upstream, &[("refs/heads/master", &base)])?;
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  let config = fs::read_to_string(path.join(".git/config"))?;
+
+  // a host that can't be reached but through the proxy, which serves the
+  // files whatever host they are asked for on
+  let proxy = serve_files(&upstream)?;
+  let url = "http://git-rs.invalid/.git";
+  assert_eq!(
+    fetch_with_env(path, &[url, "master"], &[("http_proxy", &proxy)])?,
+    ""
+  );
+  assert_eq!(git_rs(path, &["cat-file", "-t", &base])?, "commit\n");
+  let vars = [("http_proxy", proxy.as_str()), ("NO_PROXY", ".invalid")];
+  assert!(fetch_with_env(path, &[url, "master"], &vars)?
+    .starts_with("fatal: unable to access 'http://git-rs.invalid/.git/info/refs"));
+  // http.proxy wins over the environment, and NO_PROXY still counts
+  fs::write(
+    path.join(".git/config"),
+    format!("{}[http]\n\tproxy = {}\n", config, proxy),
+  )?;
+  let vars = [("http_proxy", "127.0.0.1:1")];
+  assert_eq!(fetch_with_env(path, &[url, "master"], &vars)?, "");
+  let vars = [("NO_PROXY", "*")];
+  assert!(fetch_with_env(path, &[url, "master"], &vars)?.starts_with("fatal: unable to access"));
+
+  // headers for every request, which may carry what the server asks for
+  fs::write(path.join(".git/config"), &config)?;
+  let addr = serve_files_with_auth(&upstream, Some("Basic dXNlcjpzZWNyZXQ="))?;
+  let url = format!("http://{}/.git", addr);
+  assert_eq!(
+    fetch_with_env(path, &[&url, "master"], &[])?,
+    format!(
+      "fatal: could not read Username for 'http://{}': terminal prompts disabled\n",
+      addr
+    )
+  );
+  fs::write(
+    path.join(".git/config"),
+    format!(
+      "{}[http]\n\textraHeader = X-Ignored: yes\n\textraHeader =\n\
+       \textraHeader = Authorization: Basic dXNlcjpzZWNyZXQ=\n",
+      config
+    ),
+  )?;
+  assert_eq!(fetch_with_env(path, &[&url, "master"], &[])?, "");
+  Ok(())
+}
+
+#[cfg(feature = "https")]
+#[test]
+fn test_fetch_https() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let one = hash_object(&upstream, "blob", b"one\n")?;
+  let tree = write_tree(&upstream, &[("a.txt", &one)])?;
+  let base = write_commit_with_tree(&upstream, &tree, &[], 1000, "base")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  git_rs(&upstream, &["repack", "-a", "-d", "-q"])?;
+  update_server_info(&upstream, &[("refs/heads/master", &base)])?;
+  let url = format!("https://{}/.git", serve_files_over_tls(&upstream)?);
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+
+  // the server's certificate is signed by no authority the system trusts
+  assert!(fetch_with_env(path, &[&url, "master"], &[])?
+    .starts_with(&format!("fatal: unable to access '{}/info/refs", url)));
+  let ca = path.join("ca.pem");
+  fs::write(&ca, TLS_CERTIFICATE)?;
+  fs::write(
+    path.join(".git/config"),
+    fs::read_to_string(path.join(".git/config"))?
+      + &format!("[http]\n\tsslCAInfo = {}\n", ca.display()),
+  )?;
+  assert_eq!(fetch_with_env(path, &[&url, "master"], &[])?, "");
+  assert_eq!(git_rs(path, &["cat-file", "-p", &one])?, "one\n");
+  assert_eq!(
+    fetch_with_env(path, &[&url, "master"], &[("GIT_SSL_CAINFO", "/nonexistent.pem")])?,
+    format!(
+      "fatal: unable to access '{}/info/refs?service=git-upload-pack': error setting certificate file: /nonexistent.pem (No such file or directory (os error 2))\n",
+      url
+    )
+  );
+  Ok(())
+}
+
+#[test]
+fn test_fetch_prune() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let one = hash_object(&upstream, "blob", b"one\n")?;
+  let tree = write_tree(&upstream, &[("a.txt", &one)])?;
+  let base = write_commit_with_tree(&upstream, &tree, &[], 1000, "base")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  git_rs(&upstream, &["repack", "-a", "-d", "-q"])?;
+  let all = [
+    ("refs/heads/master", base.as_str()),
+    ("refs/heads/topic", &base),
+    ("refs/heads/tmp-1", &base),
+    ("refs/tags/v1", &base),
+  ];
+  update_server_info(&upstream, &all)?;
+  let url = format!("http://{}/.git", serve_files(&upstream)?);
+
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  let config = fs::read_to_string(path.join(".git/config"))?
+    + &format!(
+      "[remote \"origin\"]\n\turl = {}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+       \tfetch = ^refs/heads/tmp-*\n",
+      url
+    );
+  fs::write(path.join(".git/config"), &config)?;
+  let (code, report) = git_rs_err(path, &["fetch"])?;
+  assert_eq!(code, 0);
+  assert!(!report.contains("tmp-1"));
+  assert!(!path.join(".git/refs/remotes/origin/tmp-1").exists());
+  assert!(report.ends_with(" * [new tag]         v1         -> v1\n"));
+
+  // refs the negative refspec leaves out, symbolic refs and refs for
+  // branches still there stay; only origin/topic is gone upstream
+  write_ref(path, "refs/remotes/origin/tmp-old", &base)?;
+  fs::write(
+    path.join(".git/refs/remotes/origin/HEAD"),
+    "ref: refs/remotes/origin/master\n",
+  )?;
+  write_ref(path, "refs/tags/v0", &base)?;
+  update_server_info(&upstream, &[all[0], all[2], all[3]])?;
+  assert_eq!(git_rs_err(path, &["fetch"])?, (0, String::new()));
+  assert!(path.join(".git/refs/remotes/origin/topic").exists());
+  let (code, report) = git_rs_err(path, &["fetch", "--prune"])?;
+  assert_eq!(
+    (code, report),
+    (
+      0,
+      format!(
+        "From {}\n - [deleted]         (none)     -> origin/topic\n",
+        url.trim_end_matches(".git")
+      )
+    )
+  );
+  assert!(!path.join(".git/refs/remotes/origin/topic").exists());
+  assert!(path.join(".git/refs/remotes/origin/tmp-old").exists());
+  assert!(path.join(".git/refs/remotes/origin/HEAD").exists());
+
+  // pruning tags fetches them all, and deletes those upstream doesn't have
+  let (_, report) = git_rs_err(path, &["fetch", "-p", "-P"])?;
+  assert_eq!(
+    report.lines().skip(1).collect::<Vec<_>>(),
+    [" - [deleted]         (none)     -> v0"]
+  );
+  assert!(!path.join(".git/refs/tags/v0").exists());
+
+  // as the config says, unless told otherwise
+  write_ref(path, "refs/remotes/origin/gone", &base)?;
+  fs::write(
+    path.join(".git/config"),
+    format!("{}[fetch]\n\tprune = true\n", config),
+  )?;
+  assert_eq!(
+    git_rs_err(path, &["fetch", "--no-prune"])?,
+    (0, String::new())
+  );
+  assert!(path.join(".git/refs/remotes/origin/gone").exists());
+  let (_, report) = git_rs_err(path, &["fetch"])?;
+  assert!(report.ends_with(" - [deleted]         (none)     -> origin/gone\n"));
+
+  assert_eq!(
+    git_rs(path, &["fetch", "origin", "^refs/heads/a:refs/heads/b"])?,
+    "fatal: invalid refspec '^refs/heads/a:refs/heads/b'\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_fetch_follows_tags() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let tree = write_tree(&upstream, &[])?;
+  let first = write_commit_with_tree(&upstream, &tree, &[], 1000, "first")?;
+  let second = write_commit_with_tree(&upstream, &tree, &[&first], 2000, "second")?;
+  write_ref(&upstream, "refs/heads/master", &second)?;
+  git_rs(&upstream, &["repack", "-a", "-d", "-q"])?;
+  // the other branch and the tags stay loose, for a dumb server to have to
+  // be asked for them
+  let other = write_commit_with_tree(&upstream, &tree, &[], 3000, "other")?;
+  write_ref(&upstream, "refs/heads/other", &other)?;
+  let tag = |name: &str, target: &str| {
+    let payload = format!(
+      "object {}\ntype commit\ntag {}\ntagger T A Gger <tagger@example.com> 4000 +0000\n\n{}\n",
+      target, name, name
+    );
+    hash_object(&upstream, "tag", payload.as_bytes())
+  };
+  let v1 = tag("v1", &first)?;
+  let v2 = tag("v2", &other)?;
+  write_ref(&upstream, "refs/tags/v1", &v1)?;
+  write_ref(&upstream, "refs/tags/v2", &v2)?;
+  write_ref(&upstream, "refs/tags/light", &second)?;
+  let all = [
+    ("refs/heads/master", second.as_str()),
+    ("refs/heads/other", &other),
+    ("refs/tags/light", &second),
+    ("refs/tags/v1", &v1),
+    ("refs/tags/v1^{}", &first),
+    ("refs/tags/v2", &v2),
+    ("refs/tags/v2^{}", &other),
+  ];
+  update_server_info(&upstream, &all)?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+  let (_daemon, git_url) = daemon(&["--base-path", root.to_str().unwrap(), "--export-all"])?;
+  let git_url = format!("{}{}", git_url, name);
+  let http_url = format!("http://{}/.git", serve_files(&upstream)?);
+
+  for url in [&git_url, &http_url] {
+    let (_dir, path) = init_repo()?;
+    let path = &path;
+    let config = fs::read_to_string(path.join(".git/config"))?
+      + &format!(
+        "[remote \"origin\"]\n\turl = {}\n\tfetch = +refs/heads/master:refs/remotes/origin/master\n",
+        url
+      );
+    fs::write(path.join(".git/config"), &config)?;
+
+    // the tags that point into what is fetched come with it
+    let (code, report) = git_rs_err(path, &["fetch"])?;
+    assert_eq!(
+      (code, report.lines().skip(1).collect::<Vec<_>>()),
+      (
+        0,
+        vec![
+          " * [new branch]      master     -> origin/master",
+          " * [new tag]         light      -> light",
+          " * [new tag]         v1         -> v1",
+        ]
+      )
+    );
+    assert_eq!(
+      fs::read_to_string(path.join(".git/refs/tags/v1"))?,
+      format!("{}\n", v1)
+    );
+    assert_eq!(git_rs(path, &["cat-file", "-t", &v1])?, "tag\n");
+    assert!(!path.join(".git/refs/tags/v2").exists());
+    assert!(fs::read_to_string(path.join(".git/FETCH_HEAD"))?
+      .contains(&format!("{}\tnot-for-merge\ttag 'v1' of", v1)));
+
+    // only into local refs
+    fs::remove_file(path.join(".git/refs/tags/v1"))?;
+    assert_eq!(git_rs_err(path, &["fetch", "origin", "master"])?.0, 0);
+    assert!(!path.join(".git/refs/tags/v1").exists());
+
+    // unless told not to, as the config may
+    assert_eq!(
+      git_rs_err(path, &["fetch", "--no-tags"])?,
+      (0, String::new())
+    );
+    assert!(!path.join(".git/refs/tags/v1").exists());
+    fs::write(
+      path.join(".git/config"),
+      format!("{}\ttagOpt = --no-tags\n", config),
+    )?;
+    assert_eq!(git_rs_err(path, &["fetch"])?, (0, String::new()));
+
+    // and all of them with --tags
+    let (code, report) = git_rs_err(path, &["fetch", "--tags"])?;
+    assert_eq!(
+      (code, report.lines().skip(1).collect::<Vec<_>>()),
+      (
+        0,
+        vec![
+          " * [new tag]         v1         -> v1",
+          " * [new tag]         v2         -> v2",
+        ]
+      )
+    );
+    assert_eq!(git_rs(path, &["cat-file", "-t", &other])?, "commit\n");
+  }
+  Ok(())
+}
+
+#[test]
+fn test_fetch_keep() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let one = hash_object(&upstream, "blob", b"one\n")?;
+  let tree = write_tree(&upstream, &[("a.txt", &one)])?;
+  let base = write_commit_with_tree(&upstream, &tree, &[], 1000, "base")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+  let (_daemon, url) = daemon(&["--base-path", root.to_str().unwrap(), "--export-all"])?;
+
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  fs::write(
+    path.join(".git/config"),
+    fs::read_to_string(path.join(".git/config"))?
+      + &format!(
+        "[remote \"origin\"]\n\turl = {}{}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n",
+        url, name
+      ),
+  )?;
+  let pack_dir = path.join(".git/objects/pack");
+  let files = |ending: &str| -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(&pack_dir)
+      .into_iter()
+      .flatten()
+      .flatten()
+      .map(|entry| entry.file_name().to_string_lossy().into_owned())
+      .filter(|name| name.ends_with(ending))
+      .collect();
+    names.sort();
+    names
+  };
+
+  // the pack is only kept while the fetch runs
+  assert_eq!(git_rs_err(path, &["fetch", "-q"])?.0, 0);
+  assert_eq!(files(".pack").len(), 1);
+  assert!(files(".keep").is_empty());
+
+  // unless asked to keep it
+  let two = hash_object(&upstream, "blob", b"two\n")?;
+  let tree = write_tree(&upstream, &[("a.txt", &two)])?;
+  let next = write_commit_with_tree(&upstream, &tree, &[&base], 2000, "next")?;
+  write_ref(&upstream, "refs/heads/master", &next)?;
+  assert_eq!(git_rs_err(path, &["fetch", "-q", "--keep"])?.0, 0);
+  assert_eq!(files(".pack").len(), 2);
+  let kept = files(".keep");
+  assert_eq!(kept.len(), 1);
+  let message = fs::read_to_string(pack_dir.join(&kept[0]))?;
+  assert!(message.starts_with("fetch-pack "), "{}", message);
+
+  // which a repack then leaves alone
+  git_rs(path, &["repack", "-a", "-d", "-q"])?;
+  let packs = files(".pack");
+  assert_eq!(packs.len(), 2);
+  assert!(packs.contains(&kept[0].replace(".keep", ".pack")));
+  assert_eq!(
+    git_rs(path, &["rev-list", "--count", "origin/master"])?,
+    "2\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref};
+use std::fs::OpenOptions;
+use std::io::Write;
+
+#[test]
+fn test_for_each_ref() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let one = write_commit(path, &[], 1000000000, "one")?;
+  let two = write_commit(path, &[&one], 1100000000, "two\n\nthe body")?;
+  let three = write_commit(path, &[&two], 1200000000, "three")?;
+  let tag = format!(
+    "object {}\ntype commit\ntag v1.10\ntagger T Agger <tagger@example.com> 1300000000 -0700\n\nrelease\n",
+    two
+  );
+  let tag = hash_object(path, "tag", tag.as_bytes())?;
+  write_ref(path, "refs/heads/master", &three)?;
+  write_ref(path, "refs/heads/topic", &two)?;
+  write_ref(path, "refs/tags/v1.9", &one)?;
+  write_ref(path, "refs/tags/v1.10", &tag)?;
+  write_ref(path, "refs/tags/v1.2", &three)?;
+  let mut config = OpenOptions::new()
+    .append(true)
+    .open(path.join(".git/config"))?;
+  writeln!(
+    config,
+    "[branch \"topic\"]\nremote = .\nmerge = refs/heads/master"
+  )?;
+
+  assert_eq!(
+    git_rs(path, &["for-each-ref", "refs/heads"])?,
+    format!(
+      "{} commit\trefs/heads/master\n{} commit\trefs/heads/topic\n",
+      three, two
+    )
+  );
+  let format = "--format=%(HEAD)%(refname:short) %(objectname:short) %(upstream:short) \
+                %(upstream:track) %(upstream:trackshort)";
+  assert_eq!(
+    git_rs(path, &["for-each-ref", format, "refs/heads/"])?,
+    format!(
+      "*master {}   \n topic {} master [behind 1] <\n",
+      &three[..7],
+      &two[..7]
+    )
+  );
+
+  // release tooling: the newest tags first, by date or by version
+  let format = "--format=%(refname:short) %(objecttype) %(creatordate:short) %(*subject)";
+  assert_eq!(
+    git_rs(
+      path,
+      &["for-each-ref", "--sort=-creatordate", format, "refs/tags"]
+    )?,
+    "v1.10 tag 2011-03-13 two\nv1.2 commit 2008-01-10 \nv1.9 commit 2001-09-09 \n"
+  );
+  let format = "--format=%(refname:lstrip=2)";
+  assert_eq!(
+    git_rs(
+      path,
+      &["for-each-ref", "--sort=-v:refname", format, "refs/tags/v*"]
+    )?,
+    "v1.10\nv1.9\nv1.2\n"
+  );
+  assert_eq!(
+    git_rs(
+      path,
+      &[
+        "for-each-ref",
+        "--sort=refname",
+        "--count=2",
+        format,
+        "refs/tags"
+      ]
+    )?,
+    "v1.10\nv1.2\n"
+  );
+
+  // --points-at sees through tags, --merged only takes what a commit reaches
+  assert_eq!(
+    git_rs(path, &["for-each-ref", "--points-at", &two, format])?,
+    "topic\nv1.10\n"
+  );
+  assert_eq!(
+    git_rs(path, &["for-each-ref", "--merged", &two, format])?,
+    "topic\nv1.10\nv1.9\n"
+  );
+  assert_eq!(
+    git_rs(path, &["for-each-ref", "--no-merged", &two, format])?,
+    "master\nv1.2\n"
+  );
+
+  let format = "--format=%(subject)|%(body)|%(authorname) %(authoremail) %(taggerdate:iso)%%%41";
+  assert_eq!(
+    git_rs(
+      path,
+      &[
+        "for-each-ref",
+        format,
+        "refs/heads/topic",
+        "refs/tags/v1.10"
+      ]
+    )?,
+    "two|the body\n|A U Thor <author@example.com> %A\n\
+     release||  2011-03-13 00:06:40 -0700%A\n"
+  );
+  assert_eq!(
+    git_rs(path, &["for-each-ref", "--format=%(bogus)"])?,
+    "fatal: unknown field name: bogus\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use common::{blob_hash, git_rs, git_rs_as, init_repo, write_ref};
+use std::{fs, path::Path};
+
+/// Commits the given files on top of `parent` and points the branch at the
+/// new commit, returning its hash.
+fn commit(
+  repo: &Path,
+  parent: Option<&str>,
+  files: &[(&str, &str)],
+  message: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+  for (path, contents) in files {
+    fs::write(repo.join(path), contents)?;
+    git_rs(repo, &["update-index", "--add", path])?;
+  }
+  let tree = git_rs(repo, &["write-tree"])?;
+  let mut args = vec!["commit-tree", tree.trim(), "-m", message];
+  if let Some(parent) = parent {
+    args.extend(["-p", parent]);
+  }
+  let hash = git_rs_as(repo, &args)?.trim().to_owned();
+  write_ref(repo, "refs/heads/master", &hash)?;
+  Ok(hash)
+}
+
+fn head(repo: &Path) -> Result<String, Box<dyn std::error::Error>> {
+  Ok(
+    fs::read_to_string(repo.join(".git/refs/heads/master"))?
+      .trim()
+      .to_owned(),
+  )
+}
+
+#[test]
+fn test_format_patch_and_am() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let base = commit(
+    &canonical_path,
+    None,
+    &[("f", "one\ntwo\nthree\n")],
+    "initial",
+  )?;
+  let change = commit(
+    &canonical_path,
+    Some(&base),
+    &[("f", "one\n2\nthree\n"), ("g", "new\n")],
+    "change two, add g\n\nWith a body.",
+  )?;
+
+  let output = git_rs(&canonical_path, &["format-patch", "-o", "out", "-1"])?;
+  assert_eq!(output, "out/0001-change-two-add-g.patch\n");
+  let patch = fs::read_to_string(canonical_path.join("out/0001-change-two-add-g.patch"))?;
+  let expected = format!(
+    "From {} Mon Sep 17 00:00:00 2001\n\
+     From: A U Thor <author@example.com>\n\
+     Date: Tue, 14 Nov 2023 22:13:20 +0000\n\
+     Subject: [PATCH] change two, add g\n\
+     \n\
+     With a body.\n\
+     ---\n \
+     f | 2 +-\n \
+     g | 1 +\n \
+     2 files changed, 2 insertions(+), 1 deletion(-)\n \
+     create mode 100644 g\n\
+     \n\
+     diff --git a/f b/f\n\
+     index 4cb29ea..f04eb26 100644\n\
+     --- a/f\n\
+     +++ b/f\n\
+     @@ -1,3 +1,3 @@\n \
+     one\n\
+     -two\n\
+     +2\n \
+     three\n\
+     diff --git a/g b/g\n\
+     new file mode 100644\n\
+     index 0000000..3e75765\n\
+     --- /dev/null\n\
+     +++ b/g\n\
+     @@ -0,0 +1 @@\n\
+     +new\n\
+     -- \n",
+    change
+  );
+  assert!(patch.starts_with(&expected), "{}", patch);
+
+  // applying the patch on top of its parent recreates the same commit
+  git_rs(&canonical_path, &["reset", "--hard", &base])?;
+  let output = git_rs_as(&canonical_path, &["am", "out/0001-change-two-add-g.patch"])?;
+  assert_eq!(output, "Applying: change two, add g\n");
+  assert_eq!(head(&canonical_path)?, change);
+  assert_eq!(fs::read_to_string(canonical_path.join("g"))?, "new\n");
+  assert!(!canonical_path.join(".git/rebase-apply").exists());
+  Ok(())
+}
+
+#[test]
+fn test_am_three_way() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let base = commit(
+    &canonical_path,
+    None,
+    &[("f", "one\ntwo\nthree\n")],
+    "initial",
+  )?;
+  commit(
+    &canonical_path,
+    Some(&base),
+    &[("f", "one\n2\nthree\n")],
+    "change two",
+  )?;
+  git_rs(&canonical_path, &["format-patch", "-1"])?;
+  git_rs(&canonical_path, &["reset", "--hard", &base])?;
+  let ours = commit(
+    &canonical_path,
+    Some(&base),
+    &[("f", "one\nzwei\nthree\n")],
+    "local two",
+  )?;
+
+  let output = git_rs_as(&canonical_path, &["am", "0001-change-two.patch"])?;
+  assert!(output.contains("error: patch failed: f:1\nerror: f: patch does not apply\n"));
+  assert!(output.contains("Patch failed at 0001 change two\n"));
+  let output = git_rs(&canonical_path, &["am", "0001-change-two.patch"])?;
+  assert!(output.contains("still exists but mbox given"));
+  git_rs(&canonical_path, &["am", "--abort"])?;
+  assert_eq!(head(&canonical_path)?, ours);
+
+  let output = git_rs_as(&canonical_path, &["am", "-3", "0001-change-two.patch"])?;
+  assert!(output.contains("Auto-merging f\nCONFLICT (content): Merge conflict in f\n"));
+  assert_eq!(
+    fs::read_to_string(canonical_path.join("f"))?,
+    "one\n<<<<<<< HEAD\nzwei\n=======\n2\n>>>>>>> change two\nthree\n"
+  );
+  let output = git_rs(&canonical_path, &["am", "--continue"])?;
+  assert!(output.starts_with("You still have unmerged paths in your index."));
+
+  fs::write(canonical_path.join("f"), "one\nzwei 2\nthree\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "f"])?;
+  git_rs_as(&canonical_path, &["am", "--continue"])?;
+  let log = git_rs(&canonical_path, &["rev-list", "HEAD"])?;
+  assert_eq!(log.lines().count(), 3);
+  assert_eq!(log.lines().nth(1), Some(ours.as_str()));
+  assert!(!canonical_path.join(".git/rebase-apply").exists());
+  Ok(())
+}
+
+#[test]
+fn test_format_patch_and_am_binary() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let data: String = (0..500).map(|n| format!("{}\0", n)).collect();
+  let changed = data.replace("250\0", "two hundred and fifty\0");
+  let base = commit(path, None, &[("data.bin", &data)], "initial")?;
+  let change = commit(
+    path,
+    Some(&base),
+    &[("data.bin", &changed), ("new.bin", "\0new\0")],
+    "change data",
+  )?;
+
+  // binary files are sent as base85 hunks, with full hashes to check them by
+  git_rs(path, &["format-patch", "-1"])?;
+  let patch = fs::read_to_string(path.join("0001-change-data.patch"))?;
+  assert!(patch.contains(" data.bin | Bin 1890 -> 1908 bytes\n new.bin  | Bin 0 -> 5 bytes\n"));
+  assert!(patch.contains(&format!(
+    "new file mode 100644\nindex {}..{}\n",
+    "0".repeat(40),
+    blob_hash(b"\0new\0")
+  )));
+  assert!(patch.contains("GIT binary patch\ndelta "));
+  assert!(patch.contains("GIT binary patch\nliteral 5\n"));
+  let output = git_rs(path, &["format-patch", "--no-binary", "--stdout", "-1"])?;
+  assert!(output.contains("Binary files a/data.bin and b/data.bin differ\n"));
+
+  // and applied from the hunks, recreating the same commit
+  git_rs(path, &["reset", "--hard", &base])?;
+  let output = git_rs_as(path, &["am", "0001-change-data.patch"])?;
+  assert_eq!(output, "Applying: change data\n");
+  assert_eq!(head(path)?, change);
+  assert_eq!(fs::read_to_string(path.join("data.bin"))?, changed);
+  assert_eq!(fs::read_to_string(path.join("new.bin"))?, "\0new\0");
+
+  // a file that has changed since can't take the delta
+  git_rs(path, &["reset", "--hard", &base])?;
+  commit(path, Some(&base), &[("data.bin", "\0other\0")], "other")?;
+  let output = git_rs_as(path, &["am", "0001-change-data.patch"])?;
+  assert!(output.contains(
+    "which does not match the current contents.\nerror: data.bin: patch does not apply\n"
+  ));
+  Ok(())
+}
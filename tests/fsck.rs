@@ -0,0 +1,44 @@
+mod common;
+
+use common::{
+  git_rs, hash_object, init_repo, write_commit, write_commit_with_tree, write_ref, write_tree,
+};
+use std::fs;
+
+#[test]
+fn test_fsck() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let first = write_commit(path, &[], 1000, "first")?;
+  let blob = hash_object(path, "blob", b"hello\n")?;
+  let tree = write_tree(path, &[("hello.txt", &blob)])?;
+  let second = write_commit_with_tree(path, &tree, &[&first], 1001, "second")?;
+  write_ref(path, "refs/heads/master", &second)?;
+  assert_eq!(git_rs(path, &["fsck"])?, "");
+
+  let remove =
+    |hash: &str| fs::remove_file(path.join(".git/objects").join(&hash[..2]).join(&hash[2..]));
+  remove(&blob)?;
+  assert_eq!(
+    git_rs(path, &["fsck", "--connectivity-only"])?,
+    format!(
+      "broken link from    tree {}\n              to    blob {}\nmissing blob {}\n",
+      tree, blob, blob
+    )
+  );
+  hash_object(path, "blob", b"hello\n")?;
+
+  // a shallow history needs nothing before where it was cut off
+  remove(&first)?;
+  assert_eq!(
+    git_rs(path, &["fsck"])?,
+    format!(
+      "broken link from  commit {}\n              to  commit {}\nmissing commit {}\n",
+      second, first, first
+    )
+  );
+  fs::write(path.join(".git/shallow"), format!("{}\n", second))?;
+  assert_eq!(git_rs(path, &["fsck"])?, "");
+  Ok(())
+}
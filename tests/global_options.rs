@@ -0,0 +1,71 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{git_rs, hash_object, init_repo, EMPTY_TREE};
+use std::{fs, process::Command};
+
+#[test]
+fn test_global_options() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  fs::create_dir_all(path.join("a/b"))?;
+  let outside = path.parent().unwrap();
+
+  // -C paths build on each other
+  assert_eq!(
+    git_rs(
+      outside,
+      &[
+        "-C",
+        path.to_str().unwrap(),
+        "-C",
+        "a",
+        "-C",
+        "b",
+        "write-tree"
+      ]
+    )?,
+    format!("{}\n", EMPTY_TREE)
+  );
+  assert!(git_rs(path, &["-C", "missing", "write-tree"])?
+    .starts_with("fatal: cannot change to 'missing': "));
+
+  // --git-dir names the repository outright
+  let git_dir = path.join(".git");
+  assert_eq!(
+    git_rs(
+      outside,
+      &["--git-dir", git_dir.to_str().unwrap(), "write-tree"]
+    )?,
+    format!("{}\n", EMPTY_TREE)
+  );
+  assert_eq!(
+    git_rs(path, &["--git-dir", "missing", "write-tree"])?,
+    "fatal: not a git repository: 'missing'\n"
+  );
+
+  // -c settings take the place of the config file
+  let mut commit_cmd = Command::cargo_bin("git-rs")?;
+  commit_cmd
+    .current_dir(path)
+    .args([
+      "-c",
+      "User.Name=Some One",
+      "-c",
+      "user.email=one@example.com",
+    ])
+    .args(["commit-tree", EMPTY_TREE, "-m", "msg"])
+    .env("HOME", path)
+    .env("GIT_AUTHOR_DATE", "@1000 +0000")
+    .env("GIT_COMMITTER_DATE", "@1000 +0000");
+  commit_cmd
+    .assert()
+    .success()
+    .stdout("1aa2dcb3704d1cb787b96ee28405cf15f5fe1695\n");
+  assert_eq!(
+    git_rs(path, &["-c", "name", "write-tree"])?,
+    "fatal: key does not contain a section: name\n"
+  );
+  Ok(())
+}
@@ -60,7 +60,7 @@ fn hash_object_template(
   }
 
   // Add the file to be hash-object'ed
-  let mut f = File::create(&canonical_path.join(filename))?;
+  let mut f = File::create(canonical_path.join(filename))?;
   f.write_all(plaintext_data.as_bytes())?;
   f.flush()?;
 
@@ -88,3 +88,31 @@ fn hash_object_template(
 
   Ok(())
 }
+
+#[test]
+fn test_hash_object_tree_is_canonical() -> Result<(), Box<dyn std::error::Error>> {
+  let temp_dir = TempDir::new("gitrs")?;
+  let empty_blob = hex::decode("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391")?;
+  let empty_tree = hex::decode("4b825dc642cb6eb9a060e54bf8d69288fbee4904")?;
+
+  // out of order, and with the zero-padded directory mode old versions wrote
+  let mut payload = Vec::new();
+  for (mode, name, hash) in [
+    ("100644", "b", &empty_blob),
+    ("040000", "a", &empty_tree),
+    ("100644", "a-", &empty_blob),
+  ] {
+    payload.extend_from_slice(format!("{} {}\0", mode, name).as_bytes());
+    payload.extend_from_slice(hash);
+  }
+  let file_path = temp_dir.path().join("tree");
+  File::create(&file_path)?.write_all(&payload)?;
+
+  // the directory sorts as "a/", after "a-"
+  let mut cmd = Command::cargo_bin("git-rs")?;
+  cmd.arg("hash-object").arg(&file_path).arg("tree");
+  cmd.assert().success().stdout(predicate::str::contains(
+    "e1f6a29ea86620215be94c0d909c86e9c2084bde",
+  ));
+  Ok(())
+}
@@ -56,8 +56,9 @@ fn init_template(path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
     &git_dir.join("config"),
     "[core]\n\
     repositoryformatversion=0\n\
-    filemode=false\n\
-    bare=false\n"
+    filemode=true\n\
+    bare=false\n\
+    logallrefupdates=true\n"
   ));
   assert!(verify_file_matches(
     &git_dir.join("description"),
@@ -91,3 +92,76 @@ fn verify_file_matches(path: &Path, expected: &str) -> bool {
   }
   true
 }
+
+#[test]
+fn test_init_options() -> Result<(), Box<dyn std::error::Error>> {
+  let temp_dir = TempDir::new("gitrs")?;
+  let canonical_path = temp_dir.path().canonicalize().unwrap();
+  let git_rs = |args: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::cargo_bin("git-rs")?
+      .current_dir(&canonical_path)
+      .args(args)
+      .output()?;
+    Ok(String::from_utf8(output.stdout)?)
+  };
+
+  // a bare repository is its own git directory
+  let output = git_rs(&["init", "--bare", "-b", "main", "bare.git"])?;
+  let bare = canonical_path.join("bare.git");
+  assert_eq!(
+    output,
+    format!("Initialized empty Git repository in {}/\n", bare.display())
+  );
+  assert!(verify_file_matches(
+    &bare.join("HEAD"),
+    "ref: refs/heads/main\n"
+  ));
+  assert!(verify_file_matches(
+    &bare.join("config"),
+    "[core]\nrepositoryformatversion=0\nfilemode=true\nbare=true\n"
+  ));
+
+  // the git directory starts out as a copy of the template
+  let template = canonical_path.join("template");
+  fs::create_dir_all(template.join("hooks"))?;
+  fs::write(template.join("hooks/pre-commit"), "#!/bin/sh\n")?;
+  fs::write(template.join("description"), "templated\n")?;
+  fs::write(template.join("config"), "[user]\nname=T\n")?;
+  git_rs(&["init", "--template", "template", "templated"])?;
+  let git_dir = canonical_path.join("templated/.git");
+  assert!(verify_file_matches(
+    &git_dir.join("hooks/pre-commit"),
+    "#!/bin/sh\n"
+  ));
+  assert!(verify_file_matches(
+    &git_dir.join("description"),
+    "templated\n"
+  ));
+  assert!(verify_file_matches(
+    &git_dir.join("config"),
+    "[user]\nname=T\n\n[core]\nrepositoryformatversion=0\nfilemode=true\nbare=false\nlogallrefupdates=true\n"
+  ));
+
+  // a separate git directory is linked to from the working tree
+  git_rs(&["init", "--separate-git-dir", "separate.git", "linked"])?;
+  assert!(verify_file_matches(
+    &canonical_path.join("linked/.git"),
+    &format!(
+      "gitdir: {}\n",
+      canonical_path.join("separate.git").display()
+    )
+  ));
+  assert!(canonical_path.join("separate.git/refs/heads").is_dir());
+  fs::write(canonical_path.join("linked/a.txt"), "a\n")?;
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(canonical_path.join("linked"))
+    .args(["status", "--porcelain"])
+    .output()?;
+  assert_eq!(String::from_utf8(output.stdout)?, "?? a.txt\n");
+
+  let output = git_rs(&["init", "-b", "bad..name", "bad"])?;
+  assert_eq!(output, "fatal: invalid initial branch name: 'bad..name'\n");
+  let output = git_rs(&["init", "--object-format=sha256", "sha256"])?;
+  assert_eq!(output, "fatal: the sha256 object format is not supported\n");
+  Ok(())
+}
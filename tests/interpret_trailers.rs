@@ -0,0 +1,151 @@
+use assert_cmd::Command;
+use std::fs;
+use tempdir::TempDir;
+
+/// Runs `git-rs interpret-trailers` on a message given on standard input.
+fn interpret(message: &str, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+  let output = Command::cargo_bin("git-rs")?
+    .arg("interpret-trailers")
+    .args(args)
+    .write_stdin(message)
+    .output()?;
+  Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_interpret_trailers() -> Result<(), Box<dyn std::error::Error>> {
+  // a message without trailers gets a new trailer block
+  assert_eq!(
+    interpret("subject\n", &["--trailer", "Acked-by=A"])?,
+    "subject\n\nAcked-by: A\n"
+  );
+  // the title is never a trailer block
+  assert_eq!(
+    interpret("k: v\n", &["--trailer", "a: b"])?,
+    "k: v\n\na: b\n"
+  );
+
+  // existing trailers are normalized, and continuation lines kept
+  let message = "subject\n\nbody\n\nKey:value\nOther : v2\n  cont\n";
+  assert_eq!(
+    interpret(message, &["--trailer", "key=value"])?,
+    "subject\n\nbody\n\nKey: value\nOther: v2\n  cont\nkey: value\n"
+  );
+  assert_eq!(
+    interpret(message, &["--parse"])?,
+    "Key: value\nOther: v2 cont\n"
+  );
+
+  // by default a trailer is not added next to the same one
+  assert_eq!(
+    interpret("subject\n\nKey: value\n", &["--trailer", "key=value"])?,
+    "subject\n\nKey: value\n"
+  );
+
+  // a paragraph with a sign-off only needs a quarter of its lines to be
+  // trailers
+  let message = "subject\n\nSigned-off-by: x\nnot a trailer\nmore\nnope\n";
+  assert_eq!(
+    interpret(message, &["--trailer", "a=b"])?,
+    format!("{}a: b\n", message)
+  );
+  let message = "subject\n\nSigned-off-by: x\nnot a trailer\nmore\nnope\nno\n";
+  assert_eq!(
+    interpret(message, &["--trailer", "a=b"])?,
+    format!("{}\na: b\n", message)
+  );
+
+  // trailers go before comments and patches
+  assert_eq!(
+    interpret(
+      "subject\n\nk: v\n\n# comment\n---\npatch\n",
+      &["--trailer", "a=b"]
+    )?,
+    "subject\n\nk: v\na: b\n\n# comment\n---\npatch\n"
+  );
+  assert_eq!(
+    interpret("subject\n\nk: v\n---\npatch\n", &["--trailer", "a=b"])?,
+    "subject\n\nk: v\na: b\n---\npatch\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_interpret_trailers_placement() -> Result<(), Box<dyn std::error::Error>> {
+  let message = "subject\n\nA: 1\nB: 2\nA: 3\n";
+  assert_eq!(
+    interpret(
+      message,
+      &[
+        "--where",
+        "before",
+        "--if-exists",
+        "replace",
+        "--trailer",
+        "a: 4"
+      ]
+    )?,
+    "subject\n\na: 4\nB: 2\nA: 3\n"
+  );
+  assert_eq!(
+    interpret(message, &["--where", "start", "--trailer", "c: 5"])?,
+    "subject\n\nc: 5\nA: 1\nB: 2\nA: 3\n"
+  );
+  assert_eq!(
+    interpret(
+      message,
+      &[
+        "--if-exists",
+        "addIfDifferent",
+        "--trailer",
+        "a: 1",
+        "--trailer",
+        "b: 3"
+      ]
+    )?,
+    "subject\n\nA: 1\nB: 2\nA: 3\nb: 3\n"
+  );
+  assert_eq!(
+    interpret(
+      message,
+      &[
+        "--if-exists",
+        "doNothing",
+        "--if-missing",
+        "doNothing",
+        "--trailer",
+        "a: 5",
+        "--trailer",
+        "c: 5"
+      ]
+    )?,
+    message
+  );
+  assert_eq!(
+    interpret(message, &["--trailer", "=x"])?,
+    "fatal: empty trailer token in trailer '=x'\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_interpret_trailers_in_place() -> Result<(), Box<dyn std::error::Error>> {
+  let temp_dir = TempDir::new("gitrs")?;
+  let path = temp_dir.path().join("message");
+  fs::write(&path, "subject\n\nA: 1\nB:\n")?;
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(temp_dir.path())
+    .args(["interpret-trailers", "--in-place", "--trim-empty"])
+    .args([
+      "--trailer",
+      "Signed-off-by: C O Mitter <committer@example.com>",
+      "message",
+    ])
+    .output()?;
+  assert_eq!(String::from_utf8(output.stdout)?, "");
+  assert_eq!(
+    fs::read_to_string(&path)?,
+    "subject\n\nA: 1\nSigned-off-by: C O Mitter <committer@example.com>\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,346 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref, EMPTY_TREE};
+use git_rs_core::identity::Signature;
+use git_rs_core::object::{
+  self,
+  commit::{Commit, CommitBuilder},
+  mode::Mode,
+  serializable::Unbox,
+  tag::TagBuilder,
+  tree::{Tree, TreeBuilder},
+};
+use git_rs_core::refs::{
+  pseudo::{self, FetchHead},
+  LogEntry, Ref, RefDb,
+};
+use git_rs_core::{gpg, refs, repo::Repo, rev};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_library() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  write_ref(path, "refs/heads/master", &next)?;
+
+  // what the binary does, another program can do through the library
+  let repo = Repo::discover(path)?;
+  assert_eq!(rev::parse(&repo, "HEAD~")?, base);
+  let object = object::read(&repo, &next, Some("commit"))?;
+  let commit = object.unbox::<Commit>()?;
+  assert_eq!(commit.tree(), EMPTY_TREE);
+  assert_eq!(commit.parents(), [base]);
+  assert_eq!(refs::collect(&repo, None)["refs/heads/master"], next);
+  Ok(())
+}
+
+/// Refs kept in memory, as a server might keep them in a database.
+#[derive(Default)]
+struct Memory(Mutex<BTreeMap<String, Ref>>);
+
+impl RefDb for Memory {
+  fn read(&self, name: &str) -> Result<Option<Ref>, String> {
+    Ok(self.0.lock().unwrap().get(name).cloned())
+  }
+
+  fn list(&self, prefix: &str) -> Result<Vec<(String, Ref)>, String> {
+    let refs = self.0.lock().unwrap();
+    let listed = refs.iter().filter(|(name, _)| name.starts_with(prefix));
+    Ok(
+      listed
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect(),
+    )
+  }
+
+  fn write(&self, name: &str, value: &Ref) -> Result<(), String> {
+    self
+      .0
+      .lock()
+      .unwrap()
+      .insert(name.to_owned(), value.clone());
+    Ok(())
+  }
+
+  fn delete(&self, name: &str) -> Result<(), String> {
+    self.0.lock().unwrap().remove(name);
+    Ok(())
+  }
+
+  fn append_log(&self, _: &str, _: &LogEntry) -> Result<(), String> {
+    Ok(())
+  }
+
+  fn read_log(&self, _: &str) -> Result<Vec<LogEntry>, String> {
+    Ok(Vec::new())
+  }
+}
+
+#[test]
+fn test_ref_db() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  write_ref(path, "refs/heads/master", &next)?;
+
+  // by default, refs are read from packed-refs too, where a loose one wins
+  let packed = format!(
+    "# pack-refs with: peeled fully-peeled sorted \n\
+     {base} refs/heads/master\n\
+     {base} refs/heads/packed\n\
+     {base} refs/tags/v1\n\
+     ^{base}\n",
+    base = base
+  );
+  fs::write(path.join(".git/packed-refs"), packed)?;
+  assert_eq!(
+    git_rs(path, &["branch"])?,
+    "* master\n  packed\n".to_string()
+  );
+  let repo = Repo::discover(path)?;
+  assert_eq!(rev::parse(&repo, "packed")?, base);
+  assert_eq!(rev::parse(&repo, "master")?, next);
+  refs::delete(&repo, "refs/heads/packed")?;
+  assert!(!refs::exists(&repo, "refs/heads/packed"));
+  assert_eq!(
+    fs::read_to_string(path.join(".git/packed-refs"))?
+      .lines()
+      .count(),
+    4
+  );
+  assert_eq!(refs::collect(&repo, None).len(), 2);
+
+  // another way of keeping refs is plugged in without changing the callers
+  let mut repo = Repo::discover(path)?;
+  let memory = Memory::default();
+  memory.write("HEAD", &Ref::Symbolic("refs/heads/main".to_owned()))?;
+  memory.write("refs/heads/main", &Ref::Direct(base.clone()))?;
+  repo.refs = Arc::new(memory);
+  assert_eq!(rev::parse(&repo, "HEAD")?, base);
+  assert!(rev::parse(&repo, "master").is_err());
+  refs::update(&repo, "HEAD", &next)?;
+  assert_eq!(
+    refs::read_symbolic(&repo, "HEAD").unwrap(),
+    "refs/heads/main"
+  );
+  assert_eq!(
+    refs::collect(&repo, None),
+    BTreeMap::from([("refs/heads/main".to_string(), next.clone())])
+  );
+  assert!(!path.join(".git/refs/heads/main").exists());
+  Ok(())
+}
+
+#[test]
+fn test_commit_builder() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let repo = Repo::discover(path)?;
+  let author = Signature::parse("A U Thor <author@example.com> 1000 +0000").unwrap();
+  let committer = Signature::parse("C O Mitter <committer@example.com> 1000 +0000").unwrap();
+  let builder = || {
+    CommitBuilder::new()
+      .tree(EMPTY_TREE)
+      .parent(&base)
+      .author(author.clone())
+      .committer(committer.clone())
+  };
+
+  // the same commit as one written by hand
+  let hash = builder().message("next\n").write(&repo)?;
+  assert_eq!(hash, write_commit(path, &[&base], 1000, "next")?);
+  let hash = builder()
+    .header("encoding", "ISO-8859-1")
+    .message("next\n")
+    .write(&repo)?;
+  assert!(git_rs(path, &["cat-file", "commit", &hash])?.contains(
+    "committer C O Mitter <committer@example.com> 1000 +0000\nencoding ISO-8859-1\n\nnext\n"
+  ));
+
+  // what is missing or malformed is not written
+  let error = |builder: CommitBuilder| builder.write(&repo).err().unwrap();
+  assert_eq!(
+    error(CommitBuilder::new().tree(EMPTY_TREE)),
+    "a commit needs an author"
+  );
+  assert_eq!(
+    error(builder().parent("1234")),
+    "1234 is not a valid 'commit' object"
+  );
+  assert_eq!(
+    error(builder().parent(EMPTY_TREE)),
+    format!("{} is not a valid 'commit' object", EMPTY_TREE)
+  );
+  assert_eq!(
+    error(builder().header("parent", &base)),
+    "the 'parent' header is not an extra header"
+  );
+  assert_eq!(
+    error(builder().header("two words", "")),
+    "invalid header name 'two words'"
+  );
+
+  // the signature is made over the rest of the commit, and goes in gpgsig
+  let signature = "-----BEGIN PGP SIGNATURE-----\n\nc2lnbmVk\n-----END PGP SIGNATURE-----\n";
+  let signed = Rc::new(RefCell::new(Vec::new()));
+  let payload = signed.clone();
+  let hash = builder()
+    .message("signed\n")
+    .sign(move |data| {
+      payload.borrow_mut().extend_from_slice(data);
+      Ok(signature.to_string())
+    })
+    .write(&repo)?;
+  let data = object::read(&repo, &hash, None)?.serialize().to_vec();
+  let (payload, found) = gpg::split_commit(&data).unwrap();
+  assert_eq!(payload, *signed.borrow());
+  assert_eq!(found, signature.as_bytes());
+  Ok(())
+}
+
+#[test]
+fn test_tag_builder() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let repo = Repo::discover(path)?;
+  let tagger = Signature::parse("C O Mitter <committer@example.com> 1000 +0000").unwrap();
+  let builder = || {
+    TagBuilder::new()
+      .object(&base)
+      .name("v1.0")
+      .tagger(tagger.clone())
+  };
+
+  // the type of the object is looked up, unless it is given
+  let hash = builder().message("the first\n").write(&repo)?;
+  assert_eq!(
+    git_rs(path, &["cat-file", "tag", &hash])?,
+    format!(
+      "object {}\ntype commit\ntag v1.0\ntagger C O Mitter <committer@example.com> 1000 +0000\n\nthe first\n",
+      base
+    )
+  );
+  assert_eq!(
+    builder().kind("tree").write(&repo).err().unwrap(),
+    format!("object {} tagged as tree is a commit", base)
+  );
+  assert_eq!(
+    builder().build().err().unwrap(),
+    "a tag needs the type of its object"
+  );
+  assert_eq!(
+    builder().kind("commit").name("").build().err().unwrap(),
+    "invalid tag name ''"
+  );
+
+  // the signature goes at the end of the message
+  let signature = "-----BEGIN PGP SIGNATURE-----\n\nc2lnbmVk\n-----END PGP SIGNATURE-----\n";
+  let hash = builder()
+    .message("signed")
+    .sign(move |_| Ok(signature.to_string()))
+    .write(&repo)?;
+  let data = object::read(&repo, &hash, None)?.serialize().to_vec();
+  let (payload, found) = gpg::split_tag(&data).unwrap();
+  assert!(payload.ends_with(b"\n\nsigned\n"));
+  assert_eq!(found, signature.as_bytes());
+  Ok(())
+}
+
+#[test]
+fn test_tree_builder() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let one = hash_object(path, "blob", b"one\n")?;
+  let two = hash_object(path, "blob", b"two\n")?;
+  let repo = Repo::discover(path)?;
+  let mut builder = TreeBuilder::new();
+  builder.insert("run", Mode::Executable, &one)?;
+  builder.insert("a", Mode::Normal, &one)?;
+  builder.insert("b", Mode::Normal, &one)?;
+  let base = builder.write(&repo)?;
+
+  // an update keeps the mode, and only what is there can be updated
+  let object = object::read(&repo, &base, Some("tree"))?;
+  let mut builder = TreeBuilder::from_tree(object.unbox::<Tree>()?);
+  builder.update("run", &two)?;
+  assert_eq!(
+    builder.update("c", &two).err().unwrap(),
+    "no tree entry named 'c'"
+  );
+  assert_eq!(
+    builder.remove("b").map(|entry| entry.hash),
+    Some(one.clone())
+  );
+  assert!(builder.remove("b").is_none());
+  let hash = builder.write(&repo)?;
+  assert_eq!(
+    git_rs(path, &["ls-tree", &hash])?,
+    format!("100644 blob {}\ta\n100755 blob {}\trun\n", one, two)
+  );
+  Ok(())
+}
+
+#[test]
+fn test_pseudo_refs() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  let repo = Repo::discover(path)?;
+
+  // FETCH_HEAD names the first branch it records
+  let heads = [
+    FetchHead {
+      hash: next.clone(),
+      merge: true,
+      description: "branch 'main' of https://example.com/repo".to_string(),
+    },
+    FetchHead {
+      hash: base.clone(),
+      merge: false,
+      description: "branch 'old' of https://example.com/repo".to_string(),
+    },
+  ];
+  pseudo::write_fetch_head(&repo, &heads)?;
+  assert_eq!(
+    fs::read_to_string(path.join(".git/FETCH_HEAD"))?,
+    format!(
+      "{}\t\tbranch 'main' of https://example.com/repo\n\
+       {}\tnot-for-merge\tbranch 'old' of https://example.com/repo\n",
+      next, base
+    )
+  );
+  assert_eq!(pseudo::read_fetch_head(&repo)?, heads);
+  assert_eq!(rev::parse(&repo, "FETCH_HEAD")?, next);
+  assert_eq!(rev::parse(&repo, "FETCH_HEAD~")?, base);
+
+  // MERGE_HEAD holds a commit for each branch being merged
+  pseudo::write_merge_heads(&repo, &[base.clone(), next.clone()])?;
+  assert_eq!(
+    pseudo::read_merge_heads(&repo)?,
+    [base.clone(), next.clone()]
+  );
+  assert_eq!(git_rs(path, &["cat-file", "-t", "MERGE_HEAD"])?, "commit\n");
+  refs::delete(&repo, "MERGE_HEAD")?;
+  assert!(!path.join(".git/MERGE_HEAD").exists());
+  assert!(pseudo::read_merge_heads(&repo)?.is_empty());
+
+  // ORIG_HEAD is a ref like any other
+  refs::update(&repo, "ORIG_HEAD", &base)?;
+  assert_eq!(rev::parse(&repo, "ORIG_HEAD")?, base);
+  Ok(())
+}
@@ -0,0 +1,353 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{
+  git_rs, hash_object, init_repo, write_commit, write_commit_with_tree, write_ref, write_tree,
+  EMPTY_TREE,
+};
+use predicates::prelude::*;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::{
+  fs,
+  path::Path,
+  process::{Command, Stdio},
+};
+
+#[test]
+fn test_log_graph() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+
+  // base <- main <- merge, base <- side <- merge
+  let base = write_commit(&canonical_path, &[], 1000, "base")?;
+  let main = write_commit(&canonical_path, &[&base], 2000, "main")?;
+  let side = write_commit(&canonical_path, &[&base], 3000, "side")?;
+  let merge = write_commit(&canonical_path, &[&main, &side], 4000, "merge")?;
+  write_ref(&canonical_path, "refs/heads/master", &merge)?;
+
+  let mut log_cmd = Command::cargo_bin("git-rs")?;
+  log_cmd
+    .current_dir(&canonical_path)
+    .arg("log")
+    .arg("--graph");
+  let output = log_cmd.assert().success().get_output().stdout.clone();
+  // keep the graph drawn to the left of each line, where it isn't just `|`
+  let graph_rows: Vec<String> = String::from_utf8(output)?
+    .lines()
+    .map(|line| {
+      let end = line.find(char::is_alphanumeric).unwrap_or(line.len());
+      line[..end].trim_end().to_owned()
+    })
+    .filter(|row| row.contains(['*', '\\', '/']))
+    .collect();
+  assert_eq!(graph_rows, vec!["*", "|\\", "| *", "* |", "|/", "*"]);
+
+  // without --graph the commits are listed newest first
+  let mut log_cmd = Command::cargo_bin("git-rs")?;
+  log_cmd.current_dir(&canonical_path).arg("log");
+  log_cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(format!(
+      "commit {}\nMerge: {} {}",
+      merge,
+      &main[..7],
+      &side[..7]
+    )));
+  Ok(())
+}
+
+#[test]
+fn test_log_pickaxe() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+
+  // "needle" is added, then its line is edited, then removed
+  let blob1 = hash_object(&canonical_path, "blob", b"hay\n")?;
+  let blob2 = hash_object(&canonical_path, "blob", b"hay\nneedle\n")?;
+  let blob3 = hash_object(&canonical_path, "blob", b"hay\nneedle here\n")?;
+  let mut parents: Vec<String> = Vec::new();
+  let mut commits: Vec<String> = Vec::new();
+  for (i, blob) in [&blob1, &blob2, &blob3, &blob1].iter().enumerate() {
+    let tree = write_tree(&canonical_path, &[("file.txt", blob)])?;
+    let parent: Vec<&str> = parents.iter().map(|p| p.as_str()).collect();
+    let commit = write_commit_with_tree(&canonical_path, &tree, &parent, 1000 * i as u64, "c")?;
+    parents = vec![commit.clone()];
+    commits.push(commit);
+  }
+  write_ref(&canonical_path, "refs/heads/master", &commits[3])?;
+
+  // -S only finds the commits that change the number of occurrences
+  let output = log_hashes(&canonical_path, &["-S", "needle"])?;
+  assert_eq!(output, vec![commits[3].clone(), commits[1].clone()]);
+
+  // -G finds every commit whose diff touches a matching line
+  let output = log_hashes(&canonical_path, &["-G", "^nee"])?;
+  assert_eq!(
+    output,
+    vec![commits[3].clone(), commits[2].clone(), commits[1].clone()]
+  );
+  Ok(())
+}
+
+/// Runs `git-rs log` and returns the hashes of the listed commits.
+fn log_hashes(repo: &Path, args: &[&str]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+  let mut log_cmd = Command::cargo_bin("git-rs")?;
+  log_cmd.current_dir(repo).arg("log").args(args);
+  let output = log_cmd.assert().success().get_output().stdout.clone();
+  Ok(
+    String::from_utf8(output)?
+      .lines()
+      .filter_map(|line| line.strip_prefix("commit "))
+      .map(|hash| hash.to_owned())
+      .collect(),
+  )
+}
+
+#[test]
+fn test_log_follow() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+
+  // old.txt is added and changed, something else changes, then it is moved
+  // to new.txt and changed again
+  let one = hash_object(path, "blob", b"one\ntwo\nthree\n")?;
+  let two = hash_object(path, "blob", b"one\ntwo\nthree\nfour\n")?;
+  let three = hash_object(path, "blob", b"one\ntwo\nthree\nfour\nfive\n")?;
+  let other = hash_object(path, "blob", b"other\n")?;
+  let trees = [
+    write_tree(path, &[("old.txt", &one)])?,
+    write_tree(path, &[("old.txt", &two)])?,
+    write_tree(path, &[("old.txt", &two), ("other.txt", &other)])?,
+    write_tree(path, &[("new.txt", &two), ("other.txt", &other)])?,
+    write_tree(path, &[("new.txt", &three), ("other.txt", &other)])?,
+  ];
+  let mut commits: Vec<String> = Vec::new();
+  for (i, tree) in trees.iter().enumerate() {
+    let parent: Vec<&str> = commits.last().map(|p| p.as_str()).into_iter().collect();
+    let commit = write_commit_with_tree(path, tree, &parent, 1000 * (i as u64 + 1), "c")?;
+    commits.push(commit);
+  }
+  write_ref(path, "refs/heads/master", &commits[4])?;
+
+  // without --follow, the history of new.txt starts at the move
+  let output = log_hashes(path, &["--", "new.txt"])?;
+  assert_eq!(output, vec![commits[4].clone(), commits[3].clone()]);
+
+  // with it, the commits that changed old.txt before it was moved follow
+  let output = log_hashes(path, &["--follow", "--", "new.txt"])?;
+  assert_eq!(
+    output,
+    vec![
+      commits[4].clone(),
+      commits[3].clone(),
+      commits[1].clone(),
+      commits[0].clone()
+    ]
+  );
+  Ok(())
+}
+
+#[test]
+fn test_log_json() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let base = write_commit(&canonical_path, &[], 1000, "base")?;
+  let next = write_commit(&canonical_path, &[&base], 2000, "next\n\nbody")?;
+  write_ref(&canonical_path, "refs/heads/master", &next)?;
+
+  let output = git_rs(&canonical_path, &["log", "--json"])?;
+  let log: serde_json::Value = serde_json::from_str(&output)?;
+  assert_eq!(
+    log,
+    serde_json::json!([
+      {
+        "hash": next,
+        "parents": [base],
+        "author": {"name": "A U Thor", "email": "author@example.com", "time": 2000, "timezone": "+0000"},
+        "committer": {"name": "C O Mitter", "email": "committer@example.com", "time": 2000, "timezone": "+0000"},
+        "message": "next\n\nbody\n",
+      },
+      {
+        "hash": base,
+        "parents": [],
+        "author": {"name": "A U Thor", "email": "author@example.com", "time": 1000, "timezone": "+0000"},
+        "committer": {"name": "C O Mitter", "email": "committer@example.com", "time": 1000, "timezone": "+0000"},
+        "message": "base\n",
+      },
+    ])
+  );
+  Ok(())
+}
+
+#[test]
+fn test_log_show_signature() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let unsigned = format!(
+    "tree {}\nparent {}\nauthor A U Thor <author@example.com> 2000 +0000\ncommitter C O Mitter <committer@example.com> 2000 +0000\n",
+    EMPTY_TREE, base
+  );
+  let signature = "-----BEGIN PGP SIGNATURE-----\n\nc2lnbmVk\n-----END PGP SIGNATURE-----\n";
+  let signed = format!(
+    "{}gpgsig {}\n\nsigned\n",
+    unsigned,
+    signature.trim_end().replace('\n', "\n ")
+  );
+  let next = hash_object(path, "commit", signed.as_bytes())?;
+  write_ref(path, "refs/heads/master", &next)?;
+
+  // a stand-in for gpg, which keeps what it was asked to check
+  let gpg = path.join(".git/gpg");
+  fs::write(
+    &gpg,
+    concat!(
+      "#!/bin/sh\n",
+      "cat >\"$(dirname \"$0\")/payload\"\n",
+      "cp \"$4\" \"$(dirname \"$0\")/signature\"\n",
+      "echo '[GNUPG:] GOODSIG 6D2BEA3D6D7AB2A2 Tester <t@x>'\n",
+      "echo '[GNUPG:] VALIDSIG 357AF2CB 2026-10-16 1792154207 0 4 0 22 8 00 EE0F1B6E'\n",
+      "echo '[GNUPG:] TRUST_ULTIMATE 0 pgp'\n",
+      "echo 'gpg: Good signature from \"Tester <t@x>\" [ultimate]' >&2\n",
+    ),
+  )?;
+  fs::set_permissions(&gpg, fs::Permissions::from_mode(0o755))?;
+  let program = format!("gpg.program={}", gpg.display());
+
+  let output = git_rs(path, &["-c", &program, "log", "--show-signature"])?;
+  assert_eq!(
+    output,
+    format!(
+      "commit {}\ngpg: Good signature from \"Tester <t@x>\" [ultimate]\nAuthor: A U Thor <author@example.com>\n\n    signed\n\ncommit {}\nAuthor: A U Thor <author@example.com>\n\n    base\n",
+      next, base
+    )
+  );
+  assert_eq!(
+    fs::read_to_string(path.join(".git/payload"))?,
+    format!("{}\nsigned\n", unsigned)
+  );
+  assert_eq!(fs::read_to_string(path.join(".git/signature"))?, signature);
+
+  let output = git_rs(path, &["-c", &program, "log", "--show-signature", "--json"])?;
+  let log: serde_json::Value = serde_json::from_str(&output)?;
+  assert_eq!(
+    log[0]["signature"],
+    serde_json::json!({
+      "status": "good",
+      "signer": "Tester <t@x>",
+      "key": "6D2BEA3D6D7AB2A2",
+      "fingerprint": "357AF2CB",
+      "primary_fingerprint": "EE0F1B6E",
+      "trust": "ultimate",
+    })
+  );
+  assert_eq!(log[1]["signature"]["status"], "missing");
+
+  // a signature that can't be checked at all
+  let output = git_rs(
+    path,
+    &[
+      "-c",
+      "gpg.program=false",
+      "log",
+      "--show-signature",
+      "--json",
+    ],
+  )?;
+  let log: serde_json::Value = serde_json::from_str(&output)?;
+  assert_eq!(log[0]["signature"]["status"], "unverified");
+  Ok(())
+}
+
+#[test]
+fn test_log_encoding() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let raw = [
+    format!("tree {}\n", EMPTY_TREE).as_bytes(),
+    b"author Ren\xe9 <rene@example.com> 1000 +0000\n",
+    b"committer Ren\xe9 <rene@example.com> 1000 +0000\n",
+    b"encoding ISO-8859-1\n\ncaf\xe9 au lait\n",
+  ]
+  .concat();
+  let hash = hash_object(path, "commit", &raw)?;
+  write_ref(path, "refs/heads/master", &hash)?;
+  let run = |args: &[&str]| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("git-rs")?;
+    Ok(cmd.current_dir(path).args(args).output()?.stdout)
+  };
+
+  // shown in UTF-8, unless asked for in another encoding...
+  assert_eq!(
+    String::from_utf8(run(&["log"])?)?,
+    format!(
+      "commit {}\nAuthor: René <rene@example.com>\n\n    café au lait\n",
+      hash
+    )
+  );
+  assert_eq!(
+    run(&["-c", "i18n.logOutputEncoding=ISO-8859-1", "log"])?,
+    [
+      format!("commit {}\n", hash).as_bytes(),
+      b"Author: Ren\xe9 <rene@example.com>\n\n    caf\xe9 au lait\n",
+    ]
+    .concat()
+  );
+  let log: serde_json::Value = serde_json::from_str(&git_rs(path, &["log", "--json"])?)?;
+  assert_eq!(log[0]["message"], "café au lait\n");
+  assert_eq!(log[0]["author"]["name"], "René");
+
+  // ...while the commit itself keeps its bytes
+  assert_eq!(run(&["cat-file", "commit", &hash])?, raw);
+  Ok(())
+}
+
+#[test]
+fn test_log_broken_pipe() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let message = "x".repeat(300_000);
+  let hash = write_commit(path, &[], 1000, &message)?;
+  write_ref(path, "refs/heads/master", &hash)?;
+
+  // like `log | head -c 6`: the reader goes away long before the end
+  let mut cmd = Command::cargo_bin("git-rs")?;
+  let mut child = cmd
+    .current_dir(path)
+    .arg("log")
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()?;
+  let mut start = [0; 6];
+  child.stdout.take().unwrap().read_exact(&mut start)?;
+  assert_eq!(&start, b"commit");
+  let output = child.wait_with_output()?;
+  assert_eq!(output.status.code(), Some(141));
+  assert_eq!(String::from_utf8(output.stderr)?, "");
+
+  // the pager is left out when the output is not going to a terminal
+  assert_eq!(
+    git_rs(path, &["-c", "core.pager=false", "-p", "log"])?.len(),
+    git_rs(path, &["--no-pager", "log"])?.len()
+  );
+  Ok(())
+}
+
+#[test]
+fn test_log_unborn() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  assert_eq!(
+    git_rs(path, &["log"])?,
+    "fatal: your current branch 'master' does not have any commits yet\n"
+  );
+
+  // what is staged is compared with the empty tree
+  std::fs::write(path.join("a.txt"), "a\n")?;
+  git_rs(path, &["update-index", "--add", "a.txt"])?;
+  assert_eq!(
+    git_rs(path, &["diff-index", "--cached", "--name-status", "HEAD"])?,
+    "A\ta.txt\n"
+  );
+  assert_eq!(git_rs(path, &["status", "--porcelain"])?, "A  a.txt\n");
+  Ok(())
+}
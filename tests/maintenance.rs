@@ -0,0 +1,250 @@
+mod common;
+
+use common::{daemon, git_rs, git_rs_err, init_repo, write_commit, write_ref, write_tree};
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// The names of the packs in a repository.
+fn packs(path: &Path) -> Vec<String> {
+  let mut names: Vec<String> = fs::read_dir(path.join(".git/objects/pack"))
+    .into_iter()
+    .flatten()
+    .flatten()
+    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+    .filter(|name| name.ends_with(".pack"))
+    .collect();
+  names.sort();
+  names
+}
+
+#[test]
+fn test_maintenance_run() -> Result<(), Box<dyn std::error::Error>> {
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  write_tree(path, &[])?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  let tip = write_commit(path, &[&next], 3000, "tip")?;
+  write_ref(path, "refs/heads/master", &tip)?;
+  write_ref(path, "refs/tags/v1", &base)?;
+
+  assert_eq!(
+    git_rs(path, &["maintenance", "run", "--task=nope"])?,
+    "fatal: 'nope' is not a valid task\n"
+  );
+  assert_eq!(
+    git_rs(
+      path,
+      &["maintenance", "run", "--task=pack-refs", "--task=pack-refs"]
+    )?,
+    "fatal: task 'pack-refs' cannot be selected multiple times\n"
+  );
+
+  // with too little to do, nothing is done
+  let graph = path.join(".git/objects/info/commit-graph");
+  assert_eq!(git_rs(path, &["maintenance", "run", "--auto", "-q"])?, "");
+  assert!(!graph.exists());
+  assert!(packs(path).is_empty());
+  assert!(path.join(".git/refs/heads/master").exists());
+  let auto = ["-c", "maintenance.commit-graph.auto=3"];
+  let args = [&auto[..], &["maintenance", "run", "--auto", "-q"]].concat();
+  git_rs(path, &args)?;
+  assert!(graph.exists());
+  let data = fs::read(&graph)?;
+  assert_eq!(&data[..8], b"CGPH\x01\x01\x03\x00");
+  assert_eq!(data.len(), 8 + 4 * 12 + 256 * 4 + 3 * 20 + 3 * 36 + 20);
+
+  // the graph has every commit the refs reach, so isn't written again
+  let newer = write_commit(path, &[&tip], 4000, "newer")?;
+  write_ref(path, "refs/heads/master", &newer)?;
+  fs::remove_file(&graph)?;
+  git_rs(path, &["maintenance", "run", "--task=commit-graph"])?;
+  fs::write(&graph, b"stale")?;
+  let auto = ["-c", "maintenance.commit-graph.auto=5"];
+  let args = [&auto[..], &["maintenance", "run", "--auto", "-q"]].concat();
+  git_rs(path, &args)?;
+  assert_eq!(fs::read(&graph)?, b"stale");
+  let auto = ["-c", "maintenance.commit-graph.auto=4"];
+  let args = [&auto[..], &["maintenance", "run", "--auto", "-q"]].concat();
+  git_rs(path, &args)?;
+  assert_eq!(
+    fs::read(&graph)?.len(),
+    8 + 4 * 12 + 256 * 4 + 4 * 20 + 4 * 36 + 20
+  );
+
+  // each run of loose-objects packs what isn't packed yet
+  let run = ["maintenance", "run", "-q", "--task=loose-objects"];
+  git_rs(path, &run)?;
+  assert_eq!(packs(path).len(), 1);
+  assert_eq!(
+    git_rs(path, &["count-objects"])?,
+    "5 objects, 20 kilobytes\n"
+  );
+  git_rs(path, &run)?;
+  assert_eq!(packs(path).len(), 1);
+  assert_eq!(
+    git_rs(path, &["count-objects"])?,
+    "0 objects, 0 kilobytes\n"
+  );
+  for time in [5000, 6000] {
+    let tip = git_rs(path, &["rev-list", "-n", "1", "master"])?;
+    let commit = write_commit(path, &[tip.trim()], time, "more")?;
+    write_ref(path, "refs/heads/master", &commit)?;
+    git_rs(path, &run)?;
+  }
+  assert_eq!(packs(path).len(), 3);
+
+  // and incremental-repack packs all but the biggest pack into one
+  let biggest = packs(path)
+    .into_iter()
+    .max_by_key(|name| {
+      fs::metadata(path.join(".git/objects/pack").join(name))
+        .unwrap()
+        .len()
+    })
+    .unwrap();
+  let auto = ["-c", "maintenance.incremental-repack.auto=4"];
+  let args = [&auto[..], &["maintenance", "run", "--auto", "-q"]].concat();
+  git_rs(path, &args)?;
+  assert_eq!(packs(path).len(), 3);
+  git_rs(
+    path,
+    &["maintenance", "run", "-q", "--task=incremental-repack"],
+  )?;
+  let left = packs(path);
+  assert_eq!(left.len(), 2);
+  assert!(left.contains(&biggest));
+  for entry in fs::read_dir(path.join(".git/objects"))? {
+    let entry = entry?;
+    if entry.file_name().len() == 2 {
+      fs::remove_dir_all(entry.path())?;
+    }
+  }
+  assert_eq!(git_rs(path, &["rev-list", "--count", "master"])?, "6\n");
+
+  // pack-refs packs the refs, leaving the directories they were in
+  let off = [
+    "-c",
+    "maintenance.auto=false",
+    "-c",
+    "maintenance.pack-refs.auto=-1",
+  ];
+  let args = [&off[..], &["maintenance", "run", "--auto", "-q"]].concat();
+  git_rs(path, &args)?;
+  assert!(path.join(".git/refs/heads/master").exists());
+  git_rs(path, &["maintenance", "run", "-q"])?;
+  assert!(!path.join(".git/refs/heads/master").exists());
+  assert!(path.join(".git/refs/heads").is_dir());
+  let packed = fs::read_to_string(path.join(".git/packed-refs"))?;
+  assert!(packed.contains(&format!("{} refs/tags/v1\n", base)));
+  assert_eq!(
+    git_rs(path, &["rev-list", "-n", "1", "v1"])?,
+    format!("{}\n", base)
+  );
+
+  // only one run at a time
+  fs::write(path.join(".git/objects/maintenance.lock"), "")?;
+  assert_eq!(git_rs(path, &["maintenance", "run", "--auto"])?, "");
+  assert!(git_rs(path, &["maintenance", "run"])?.contains("skipping maintenance"));
+  Ok(())
+}
+
+#[test]
+fn test_maintenance_removes_stale_files() -> Result<(), Box<dyn std::error::Error>> {
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  let git_dir = path.join(".git");
+  fs::create_dir_all(git_dir.join("objects/pack"))?;
+  fs::create_dir_all(git_dir.join("refs/heads/topic"))?;
+  let old = SystemTime::now() - Duration::from_secs(15 * 24 * 60 * 60);
+  let stale = [
+    "objects/pack/tmp_pack_123",
+    "refs/heads/topic/x.lock",
+    "index.lock",
+  ];
+  for name in stale {
+    File::create(git_dir.join(name))?.set_modified(old)?;
+  }
+  fs::write(git_dir.join("config.lock"), "")?;
+
+  // files left behind are only deleted once they are old enough
+  let never = ["-c", "gc.pruneExpire=never"];
+  let args = [&never[..], &["maintenance", "run", "--task=commit-graph"]].concat();
+  git_rs(path, &args)?;
+  assert!(stale.iter().all(|name| git_dir.join(name).exists()));
+  git_rs(path, &["maintenance", "run", "--task=commit-graph"])?;
+  assert!(stale.iter().all(|name| !git_dir.join(name).exists()));
+  assert!(git_dir.join("config.lock").exists());
+  let now = ["-c", "gc.pruneExpire=now"];
+  File::create(git_dir.join("config.lock"))?.set_modified(old)?;
+  let args = [&now[..], &["maintenance", "run", "--task=commit-graph"]].concat();
+  git_rs(path, &args)?;
+  assert!(!git_dir.join("config.lock").exists());
+  Ok(())
+}
+
+#[test]
+fn test_maintenance_prefetch() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  write_tree(&upstream, &[])?;
+  let base = write_commit(&upstream, &[], 1000, "base")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  write_ref(&upstream, "refs/tags/v1", &base)?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+  let (_daemon, url) = daemon(&["--base-path", root.to_str().unwrap(), "--export-all"])?;
+
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!(
+      "{}[remote \"origin\"]\n\turl = {}{}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n",
+      config, url, name
+    ),
+  )?;
+
+  // prefetching is off unless asked for
+  git_rs(path, &["maintenance", "run", "-q"])?;
+  assert!(!path.join(".git/refs/prefetch").exists());
+
+  // and fetches into refs/prefetch/, leaving everything else alone
+  let args = [
+    "-c",
+    "maintenance.prefetch.enabled=true",
+    "-c",
+    "maintenance.pack-refs.enabled=false",
+    "maintenance",
+    "run",
+    "-q",
+  ];
+  git_rs(path, &args)?;
+  assert_eq!(
+    fs::read_to_string(path.join(".git/refs/prefetch/remotes/origin/master"))?,
+    format!("{}\n", base)
+  );
+  assert!(!path.join(".git/refs/remotes/origin/master").exists());
+  assert!(!path.join(".git/refs/tags/v1").exists());
+  assert!(!path.join(".git/FETCH_HEAD").exists());
+  assert_eq!(git_rs(path, &["cat-file", "-t", &base])?, "commit\n");
+
+  // a remote that can't be fetched from fails the task
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!(
+      "{}[remote \"gone\"]\n\turl = {}nowhere\n\tfetch = +refs/heads/*:refs/remotes/gone/*\n",
+      config, url
+    ),
+  )?;
+  let (code, stderr) = git_rs_err(path, &["maintenance", "run", "--task=prefetch", "-q"])?;
+  assert_eq!(code, 1);
+  assert!(
+    stderr.contains("error: task 'prefetch' failed\n"),
+    "{}",
+    stderr
+  );
+  Ok(())
+}
@@ -0,0 +1,211 @@
+mod common;
+
+use common::{
+  blob_hash, git_rs, git_rs_as, hash_object, init_repo, write_commit_with_tree, write_ref,
+  write_tree,
+};
+use std::{fs, path::Path};
+
+/// Writes a base commit with `a.txt` and `b.txt`, `master` changing `a.txt`
+/// one way and `topic` changing it (and `b.txt`) another. Returns the base,
+/// master and topic.
+fn write_history(path: &Path, topic_a: &[u8]) -> Result<[String; 3], Box<dyn std::error::Error>> {
+  let one = hash_object(path, "blob", b"one\n")?;
+  let ours = hash_object(path, "blob", b"ours\n")?;
+  let theirs = hash_object(path, "blob", topic_a)?;
+  let two = hash_object(path, "blob", b"two\n")?;
+  let base_tree = write_tree(path, &[("a.txt", &one), ("b.txt", &one)])?;
+  let master_tree = write_tree(path, &[("a.txt", &ours), ("b.txt", &one)])?;
+  let topic_tree = write_tree(path, &[("a.txt", &theirs), ("b.txt", &two)])?;
+  let base = write_commit_with_tree(path, &base_tree, &[], 1000, "base")?;
+  let master = write_commit_with_tree(path, &master_tree, &[&base], 2000, "ours")?;
+  let topic = write_commit_with_tree(path, &topic_tree, &[&base], 3000, "theirs")?;
+  write_ref(path, "refs/heads/master", &master)?;
+  write_ref(path, "refs/heads/topic", &topic)?;
+  git_rs(path, &["reset", "--hard", "--force"])?;
+  Ok([base, master, topic])
+}
+
+#[test]
+fn test_merge() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let [base, master, topic] = write_history(path, b"one\n")?;
+
+  // a branch that is behind is fast-forwarded
+  write_ref(path, "refs/heads/old", &base)?;
+  git_rs(path, &["switch", "old"])?;
+  assert_eq!(
+    git_rs_as(path, &["merge", "master"])?,
+    format!(
+      "Updating {}..{}\nFast-forward\n a.txt | 2 +-\n 1 file changed, 1 insertion(+), 1 deletion(-)\n",
+      &base[..7],
+      &master[..7]
+    )
+  );
+  assert_eq!(
+    git_rs(path, &["rev-list", "-n1", "old"])?,
+    format!("{}\n", master)
+  );
+  assert_eq!(
+    git_rs_as(path, &["merge", "master"])?,
+    "Already up to date.\n"
+  );
+  git_rs(path, &["switch", "master"])?;
+
+  // changes on both sides are merged into a commit with both as parents
+  assert_eq!(
+    git_rs_as(path, &["merge", "topic"])?,
+    "Merge made by the 'ort' strategy.\n b.txt | 2 +-\n 1 file changed, 1 insertion(+), 1 deletion(-)\n"
+  );
+  assert_eq!(fs::read_to_string(path.join("a.txt"))?, "ours\n");
+  assert_eq!(fs::read_to_string(path.join("b.txt"))?, "two\n");
+  let merge = fs::read_to_string(path.join(".git/refs/heads/master"))?;
+  let commit = git_rs(path, &["cat-file", "commit", merge.trim()])?;
+  assert!(commit.contains(&format!("parent {}\nparent {}\n", master, topic)));
+  assert!(commit.ends_with("\n\nMerge branch 'topic'\n"));
+  assert_eq!(
+    fs::read_to_string(path.join(".git/ORIG_HEAD"))?,
+    format!("{}\n", master)
+  );
+  Ok(())
+}
+
+#[test]
+fn test_merge_conflict() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let [_, master, topic] = write_history(path, b"theirs\n")?;
+
+  // a conflict stops the merge, leaving what it was doing behind
+  assert_eq!(
+    git_rs_as(path, &["merge", "topic"])?,
+    "Auto-merging a.txt\nCONFLICT (content): Merge conflict in a.txt\n\
+     Automatic merge failed; fix conflicts and then commit the result.\n"
+  );
+  assert_eq!(
+    fs::read_to_string(path.join(".git/MERGE_HEAD"))?,
+    format!("{}\n", topic)
+  );
+  assert_eq!(
+    fs::read_to_string(path.join(".git/MERGE_MSG"))?,
+    "Merge branch 'topic'\n\n# Conflicts:\n#\ta.txt\n"
+  );
+  assert_eq!(fs::read_to_string(path.join(".git/MERGE_MODE"))?, "");
+  assert_eq!(
+    fs::read_to_string(path.join("a.txt"))?,
+    "<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> topic\n"
+  );
+  let status = git_rs(path, &["status"])?;
+  assert!(status.contains("You have unmerged paths."));
+  assert!(status.contains("both modified:   a.txt"));
+  assert!(git_rs_as(path, &["merge", "topic"])?
+    .contains("You have not concluded your merge (MERGE_HEAD exists)."));
+
+  // --abort puts things back
+  git_rs(path, &["merge", "--abort"])?;
+  assert!(!path.join(".git/MERGE_HEAD").exists());
+  assert!(!path.join(".git/MERGE_MSG").exists());
+  assert_eq!(fs::read_to_string(path.join("a.txt"))?, "ours\n");
+  assert_eq!(fs::read_to_string(path.join("b.txt"))?, "one\n");
+  assert_eq!(git_rs(path, &["status", "--porcelain"])?, "");
+  assert!(git_rs(path, &["merge", "--abort"])?.contains("There is no merge to abort"));
+
+  // --continue commits the resolution with both parents
+  git_rs_as(path, &["merge", "topic"])?;
+  fs::write(path.join("a.txt"), "both\n")?;
+  git_rs(path, &["update-index", "a.txt"])?;
+  let output = git_rs_as(path, &["merge", "--continue"])?;
+  assert!(output.starts_with("[master "));
+  assert!(output.contains("] Merge branch 'topic'\n"));
+  assert!(!path.join(".git/MERGE_HEAD").exists());
+  let merge = fs::read_to_string(path.join(".git/refs/heads/master"))?;
+  let commit = git_rs(path, &["cat-file", "commit", merge.trim()])?;
+  assert!(commit.contains(&format!("parent {}\nparent {}\n", master, topic)));
+  assert!(commit.ends_with("\n\nMerge branch 'topic'\n"));
+  assert!(fs::read_to_string(path.join(".git/logs/HEAD"))?
+    .ends_with("\tcommit (merge): Merge branch 'topic'\n"));
+  Ok(())
+}
+
+#[test]
+fn test_merge_stages() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  write_history(path, b"theirs\n")?;
+  git_rs_as(path, &["merge", "topic"])?;
+
+  // the base, ours and theirs are kept in the index at stages 1 to 3
+  let one = blob_hash(b"one\n");
+  let ours = blob_hash(b"ours\n");
+  let theirs = blob_hash(b"theirs\n");
+  let two = blob_hash(b"two\n");
+  assert_eq!(
+    git_rs(path, &["ls-files", "-u"])?,
+    format!(
+      "100644 {} 1\ta.txt\n100644 {} 2\ta.txt\n100644 {} 3\ta.txt\n",
+      one, ours, theirs
+    )
+  );
+  assert_eq!(
+    git_rs(path, &["ls-files", "--stage", "b.txt"])?,
+    format!("100644 {} 0\tb.txt\n", two)
+  );
+  assert_eq!(git_rs(path, &["ls-files"])?, "a.txt\nb.txt\n");
+
+  // either side can be checked out, leaving the path unmerged
+  git_rs(path, &["checkout", "--theirs", "a.txt"])?;
+  assert_eq!(fs::read_to_string(path.join("a.txt"))?, "theirs\n");
+  git_rs(path, &["checkout", "--ours", "a.txt"])?;
+  assert_eq!(fs::read_to_string(path.join("a.txt"))?, "ours\n");
+  assert_eq!(git_rs(path, &["ls-files", "-u"])?.lines().count(), 3);
+
+  // or the conflict made again
+  git_rs(path, &["checkout", "-m", "a.txt"])?;
+  assert_eq!(
+    fs::read_to_string(path.join("a.txt"))?,
+    "<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n"
+  );
+
+  // once resolved, there are no sides left to take
+  git_rs(path, &["update-index", "a.txt"])?;
+  assert_eq!(git_rs(path, &["ls-files", "-u"])?, "");
+  git_rs(path, &["checkout", "--theirs", "a.txt"])?;
+  assert!(fs::read_to_string(path.join("a.txt"))?.starts_with("<<<<<<< ours\n"));
+  Ok(())
+}
+
+#[test]
+fn test_merge_driver() -> Result<(), Box<dyn std::error::Error>> {
+  let cases = [
+    // a custom driver merges the file itself
+    ("merge=pick", "cp %B %A", "theirs\n", false),
+    // and a conflict is reported when it fails
+    ("merge=pick", "echo %P %L >%A; false", "a.txt 7\n", true),
+    // files that aren't to be merged keep our version
+    ("-merge", "", "ours\n", true),
+    // or both sides' lines, with the union driver
+    ("merge=union", "", "ours\ntheirs\n", false),
+  ];
+  for (attribute, driver, merged, conflict) in cases {
+    let (_temp_dir, canonical_path) = init_repo()?;
+    let path = &canonical_path;
+    let config = fs::read_to_string(path.join(".git/config"))?;
+    fs::write(
+      path.join(".git/config"),
+      format!("{}[merge \"pick\"]\n\tdriver = {}\n", config, driver),
+    )?;
+    fs::write(
+      path.join(".gitattributes"),
+      format!("a.txt {}\n", attribute),
+    )?;
+    write_history(path, b"theirs\n")?;
+    let output = git_rs_as(path, &["merge", "topic"])?;
+    assert_eq!(fs::read_to_string(path.join("a.txt"))?, merged);
+    assert_eq!(
+      output.contains("CONFLICT (content): Merge conflict in a.txt\n"),
+      conflict
+    );
+  }
+  Ok(())
+}
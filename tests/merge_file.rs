@@ -0,0 +1,64 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::init_repo;
+use std::{fs, path::Path, process::Command};
+
+/// Runs `git-rs merge-file`, returning its output and exit code.
+fn merge_file(repo: &Path, args: &[&str]) -> Result<(String, i32), Box<dyn std::error::Error>> {
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(repo)
+    .arg("merge-file")
+    .args(args)
+    .output()?;
+  Ok((
+    String::from_utf8(output.stdout)?,
+    output.status.code().unwrap_or(-1),
+  ))
+}
+
+#[test]
+fn test_merge_file() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  fs::write(path.join("base"), "a\nb\nc\nd\ne\nf\ng\n")?;
+  fs::write(path.join("ours"), "a\nB\nc\nd\ne\nf\nG\n")?;
+  fs::write(path.join("theirs"), "a\nb2\nc\nd\ne\nf\ng2\n")?;
+
+  // conflicts more than three lines apart stay apart
+  let (output, code) = merge_file(path, &["-p", "ours", "base", "theirs"])?;
+  assert_eq!(
+    output,
+    "a\n<<<<<<< ours\nB\n=======\nb2\n>>>>>>> theirs\nc\nd\ne\nf\n\
+     <<<<<<< ours\nG\n=======\ng2\n>>>>>>> theirs\n"
+  );
+  assert_eq!(code, 2);
+
+  let args = [
+    "-p",
+    "--diff3",
+    "-L",
+    "x",
+    "-L",
+    "y",
+    "-L",
+    "z",
+    "--marker-size",
+    "3",
+  ];
+  let (output, _) = merge_file(path, &[&args[..], &["ours", "base", "theirs"]].concat())?;
+  assert!(output.starts_with("a\n<<< x\nB\n||| y\nb\n===\nb2\n>>> z\n"));
+
+  let (output, code) = merge_file(path, &["-p", "--union", "ours", "base", "theirs"])?;
+  assert_eq!(output, "a\nB\nb2\nc\nd\ne\nf\nG\ng2\n");
+  assert_eq!(code, 0);
+
+  // without -p the result goes to the current file
+  let (output, code) = merge_file(path, &["--theirs", "ours", "base", "theirs"])?;
+  assert_eq!((output.as_str(), code), ("", 0));
+  assert_eq!(
+    fs::read_to_string(path.join("ours"))?,
+    "a\nb2\nc\nd\ne\nf\ng2\n"
+  );
+  Ok(())
+}
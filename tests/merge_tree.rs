@@ -0,0 +1,73 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{git_rs, hash_object, init_repo, write_commit_with_tree, write_ref, write_tree};
+use std::process::Command;
+
+#[test]
+fn test_merge_tree() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let blob = |data: &str| hash_object(path, "blob", data.as_bytes());
+  let (f, x) = (blob("a\nb\nc\nd\ne\n")?, blob("x\n")?);
+  let tree = write_tree(path, &[("del", &x), ("f", &f)])?;
+  let base = write_commit_with_tree(path, &tree, &[], 1000, "base")?;
+
+  // master changes both ends of f and deletes del
+  let master_f = blob("A\nb\nc\nd\ne2\n")?;
+  let tree = write_tree(path, &[("f", &master_f)])?;
+  let master = write_commit_with_tree(path, &tree, &[&base], 2000, "master")?;
+  write_ref(path, "refs/heads/master", &master)?;
+
+  // side changes the end of f the other way and changes del
+  let (side_f, y) = (blob("a\nb\nc\nd\nE\n")?, blob("y\n")?);
+  let tree = write_tree(path, &[("del", &y), ("f", &side_f)])?;
+  let side = write_commit_with_tree(path, &tree, &[&base], 3000, "side")?;
+  write_ref(path, "refs/heads/side", &side)?;
+
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(path)
+    .args(["merge-tree", "--write-tree", "master", "side"])
+    .output()?;
+  assert_eq!(output.status.code(), Some(1));
+  let output = String::from_utf8(output.stdout)?;
+  let tree = output.lines().next().unwrap();
+  assert_eq!(
+    output,
+    format!(
+      "{}\n\
+       100644 {} 1\tdel\n\
+       100644 {} 3\tdel\n\
+       100644 {} 1\tf\n\
+       100644 {} 2\tf\n\
+       100644 {} 3\tf\n\
+       \n\
+       CONFLICT (modify/delete): del deleted in master and modified in side.  \
+       Version side of del left in tree.\n\
+       Auto-merging f\n\
+       CONFLICT (content): Merge conflict in f\n",
+      tree, x, y, f, master_f, side_f
+    )
+  );
+
+  // the tree has the conflict markers, and nothing else was touched
+  let merged = blob("A\nb\nc\nd\n<<<<<<< master\ne2\n=======\nE\n>>>>>>> side\n")?;
+  assert_eq!(
+    git_rs(path, &["ls-tree", tree])?,
+    format!("100644 blob {}\tdel\n100644 blob {}\tf\n", y, merged)
+  );
+  assert!(!path.join(".git/index").exists());
+
+  let output = git_rs(
+    path,
+    &[
+      "merge-tree",
+      "--name-only",
+      "--no-messages",
+      "master",
+      "side",
+    ],
+  )?;
+  assert_eq!(output, format!("{}\ndel\nf\n", tree));
+  Ok(())
+}
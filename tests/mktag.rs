@@ -0,0 +1,61 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{git_rs, init_repo};
+use std::path::Path;
+
+/// Runs `git-rs mktag` on the given input.
+fn mktag(repo: &Path, input: &str) -> Result<String, Box<dyn std::error::Error>> {
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(repo)
+    .arg("mktag")
+    .write_stdin(input)
+    .output()?;
+  Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_mktag() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let tree = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(path)
+    .arg("mktree")
+    .write_stdin("")
+    .output()?;
+  assert_eq!(String::from_utf8(output.stdout)?.trim(), tree);
+
+  let tag = |kind: &str, tagger: &str| {
+    format!(
+      "object {}\ntype {}\ntag empty\ntagger {}\n\nnothing here\n",
+      tree, kind, tagger
+    )
+  };
+  let tagger = "A U Thor <author@example.com> 1700000000 -0700";
+  let hash = mktag(path, &tag("tree", tagger))?;
+  assert_eq!(hash, "c98cbe57a40e1470325b67f70f32fb94e89f81de\n");
+  assert_eq!(
+    git_rs(path, &["cat-file", "tag", hash.trim()])?,
+    tag("tree", tagger)
+  );
+
+  assert_eq!(
+    mktag(path, &tag("commit", tagger))?,
+    format!(
+      "fatal: object '{}' tagged as 'commit', but is a 'tree' type\n",
+      tree
+    )
+  );
+  assert_eq!(
+    mktag(path, &tag("tree", "A U Thor <author@example.com> 1700000000 0700"))?,
+    "error: tag input does not pass fsck: badTimezone: invalid author/committer line - bad time zone\n\
+     fatal: tag on stdin did not pass our strict fsck check\n"
+  );
+  assert_eq!(
+    mktag(path, &format!("object {}\ntype tree\ntag empty\n\n", tree))?,
+    "error: tag input does not pass fsck: missingTaggerEntry: invalid format - expected 'tagger' line\n\
+     fatal: tag on stdin did not pass our strict fsck check\n"
+  );
+  Ok(())
+}
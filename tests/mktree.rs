@@ -0,0 +1,100 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{git_rs, hash_object, init_repo};
+use std::path::Path;
+
+/// Runs `git-rs mktree` on the given input.
+fn mktree(repo: &Path, input: &str, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(repo)
+    .arg("mktree")
+    .args(args)
+    .write_stdin(input)
+    .output()?;
+  Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_mktree() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let blob = hash_object(path, "blob", b"x\n")?;
+
+  // entries are sorted, whatever order they come in
+  let input = format!("100644 blob {}\tb\n100755 blob {}\ta\n", blob, blob);
+  assert_eq!(
+    mktree(path, &input, &[])?,
+    "2e94612ff7ae183132becea10bf21b456cb5dfcf\n"
+  );
+  assert_eq!(
+    mktree(path, "", &[])?,
+    "4b825dc642cb6eb9a060e54bf8d69288fbee4904\n"
+  );
+
+  let input = format!("100644 blob {}\ta/b\n", blob);
+  assert_eq!(
+    mktree(path, &input, &[])?,
+    "fatal: path a/b contains slash\n"
+  );
+  let input = format!("100645 blob {}\ta\n", blob);
+  assert_eq!(
+    mktree(path, &input, &[])?,
+    format!("fatal: input format error: 100645 blob {}\ta\n", blob)
+  );
+  let input = format!("040000 tree {}\ta\n", blob);
+  assert_eq!(
+    mktree(path, &input, &[])?,
+    format!(
+      "fatal: entry 'a' object {} is a blob but specified type was (tree)\n",
+      blob
+    )
+  );
+  let input = format!("100644 blob {}\ta\n100644 blob {}\ta\n", blob, blob);
+  assert_eq!(mktree(path, &input, &[])?, "fatal: duplicate entry 'a'\n");
+
+  let missing = "1111111111111111111111111111111111111111";
+  let input = format!("100644 blob {}\ta\n", missing);
+  assert_eq!(
+    mktree(path, &input, &[])?,
+    format!("fatal: entry 'a' object {} is unavailable\n", missing)
+  );
+  assert_eq!(mktree(path, &input, &["--missing"])?.len(), 41);
+
+  let input = format!("100644 blob {}\ta\n\n100644 blob {}\tb\n", blob, blob);
+  assert_eq!(mktree(path, &input, &["--batch"])?.lines().count(), 2);
+  Ok(())
+}
+
+#[test]
+fn test_mktree_gitlink() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+
+  // the commit of a submodule is never looked for
+  let commit = "1234567890123456789012345678901234567890";
+  let input = format!("160000 commit {}\tsub\n", commit);
+  let tree = "fdd0c7ca09656df4e2400d1eff404338cffd7aec";
+  assert_eq!(mktree(path, &input, &[])?, format!("{}\n", tree));
+  assert_eq!(git_rs(path, &["ls-tree", tree])?, input);
+  assert_eq!(
+    git_rs(path, &["ls-tree", "--json", tree])?,
+    format!(
+      "[{{\"mode\":\"160000\",\"type\":\"commit\",\"hash\":\"{}\",\"path\":\"sub\"}}]\n",
+      commit
+    )
+  );
+  let input = format!("160000 blob {}\tsub\n", commit);
+  assert_eq!(
+    mktree(path, &input, &[])?,
+    "fatal: entry 'sub' object type (blob) doesn't match mode type (commit)\n"
+  );
+
+  // checking it out leaves an empty directory, which counts as unchanged
+  git_rs(path, &["read-tree", tree])?;
+  git_rs(path, &["restore", "."])?;
+  assert!(path.join("sub").is_dir());
+  assert_eq!(git_rs(path, &["diff-files"])?, "");
+  assert_eq!(git_rs(path, &["write-tree"])?, format!("{}\n", tree));
+  Ok(())
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref};
+use std::path::Path;
+
+/// Writes the history the tests name:
+///
+/// ```text
+/// base <- one <- two <- merge   master
+///    ^      ^           /
+///    |      v1         /
+///    +---- side ------+         topic
+/// ```
+///
+/// Returns base, one, two, side and merge.
+fn write_history(path: &Path) -> Result<[String; 5], Box<dyn std::error::Error>> {
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let one = write_commit(path, &[&base], 2000, "one")?;
+  let two = write_commit(path, &[&one], 3000, "two")?;
+  let side = write_commit(path, &[&base], 2500, "side")?;
+  let merge = write_commit(path, &[&two, &side], 4000, "merge")?;
+  let tag = format!(
+    "object {}\ntype commit\ntag v1\ntagger T Agger <tagger@example.com> 2100 +0000\n\nv1\n",
+    one
+  );
+  let tag = hash_object(path, "tag", tag.as_bytes())?;
+  write_ref(path, "refs/heads/master", &merge)?;
+  write_ref(path, "refs/heads/topic", &side)?;
+  write_ref(path, "refs/tags/v1", &tag)?;
+  Ok([base, one, two, side, merge])
+}
+
+#[test]
+fn test_name_rev() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let [base, one, two, side, merge] = write_history(path)?;
+
+  // tags win, even from further away, and then the nearest ref
+  let output = git_rs(path, &["name-rev", &base, &one, &two, &side, &merge])?;
+  assert_eq!(
+    output,
+    format!(
+      "{} tags/v1~1\n{} tags/v1^0\n{} master~1\n{} topic\n{} master\n",
+      base, one, two, side, merge
+    )
+  );
+  assert_eq!(
+    git_rs(path, &["name-rev", "--name-only", "--exclude=topic", &side])?,
+    "master^2\n"
+  );
+  assert_eq!(
+    git_rs(path, &["name-rev", "--name-only", "--refs=mast*", &base])?,
+    "master~3\n"
+  );
+  assert_eq!(
+    git_rs(path, &["name-rev", "--tags", "--name-only", &base])?,
+    "v1~1\n"
+  );
+  assert_eq!(
+    git_rs(path, &["name-rev", "--tags", "--name-only", &two])?,
+    "undefined\n"
+  );
+  assert_eq!(
+    git_rs(path, &["name-rev", "--tags", "--always", &two])?,
+    format!("{} {}\n", two, &two[..7])
+  );
+  assert_eq!(
+    git_rs(path, &["name-rev", "--tags", "--no-undefined", &two])?,
+    format!("fatal: cannot describe '{}'\n", two)
+  );
+
+  // every named commit, in order of hashes
+  let mut all = [
+    format!("{} tags/v1~1", base),
+    format!("{} tags/v1^0", one),
+    format!("{} master~1", two),
+    format!("{} topic", side),
+    format!("{} master", merge),
+  ];
+  all.sort();
+  assert_eq!(
+    git_rs(path, &["name-rev", "--all"])?,
+    format!("{}\n", all.join("\n"))
+  );
+  Ok(())
+}
+
+#[test]
+fn test_contains() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let [base, one, two, side, _] = write_history(path)?;
+  write_ref(path, "refs/heads/old", &one)?;
+
+  assert_eq!(
+    git_rs(path, &["branch", "--contains", &base])?,
+    "* master\n  old\n  topic\n"
+  );
+  assert_eq!(
+    git_rs(path, &["branch", "--contains", &one])?,
+    "* master\n  old\n"
+  );
+  assert_eq!(
+    git_rs(path, &["branch", "--contains", &side])?,
+    "* master\n  topic\n"
+  );
+  assert_eq!(git_rs(path, &["branch", "--contains"])?, "* master\n");
+
+  assert_eq!(git_rs(path, &["tag", "--contains", &base])?, "v1\n");
+  assert_eq!(git_rs(path, &["tag", "--contains", &one])?, "v1\n");
+  assert_eq!(git_rs(path, &["tag", "--contains", &two])?, "");
+  assert_eq!(git_rs(path, &["tag"])?, "v1\n");
+  Ok(())
+}
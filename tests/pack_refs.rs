@@ -0,0 +1,96 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref};
+use std::fs;
+use tempdir::TempDir;
+
+#[test]
+fn test_pack_refs() -> Result<(), Box<dyn std::error::Error>> {
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  let payload = format!(
+    "object {}\ntype commit\ntag v1\ntagger T A Gger <tagger@example.com> 3000 +0000\n\nv1\n",
+    base
+  );
+  let v1 = hash_object(path, "tag", payload.as_bytes())?;
+  write_ref(path, "refs/heads/master", &next)?;
+  fs::create_dir_all(path.join(".git/refs/heads/feature"))?;
+  write_ref(path, "refs/heads/feature/x", &base)?;
+  write_ref(path, "refs/tags/v1", &v1)?;
+  write_ref(path, "refs/tags/light", &next)?;
+  let git_dir = path.join(".git");
+
+  // by default only the tags are packed, with what they peel to
+  assert_eq!(git_rs(path, &["pack-refs"])?, "");
+  assert_eq!(
+    fs::read_to_string(git_dir.join("packed-refs"))?,
+    format!(
+      "# pack-refs with: peeled fully-peeled sorted \n\
+       {next} refs/tags/light\n\
+       {v1} refs/tags/v1\n\
+       ^{base}\n",
+      next = next,
+      v1 = v1,
+      base = base
+    )
+  );
+  assert!(!git_dir.join("refs/tags/v1").exists());
+  assert!(git_dir.join("refs/tags").is_dir());
+  assert!(git_dir.join("refs/heads/master").exists());
+  assert_eq!(
+    git_rs(path, &["rev-list", "-n", "1", "v1"])?,
+    format!("{}\n", base)
+  );
+
+  // --all packs the branches too, and --no-prune keeps their files
+  assert_eq!(git_rs(path, &["pack-refs", "--all", "--no-prune"])?, "");
+  let packed = fs::read_to_string(git_dir.join("packed-refs"))?;
+  assert!(packed.contains(&format!("{} refs/heads/master\n", next)));
+  assert!(packed.contains(&format!("{} refs/heads/feature/x\n", base)));
+  assert!(git_dir.join("refs/heads/master").exists());
+
+  // a ref changed since it was packed is packed again
+  write_ref(path, "refs/heads/feature/x", &next)?;
+  assert_eq!(git_rs(path, &["pack-refs", "--prune"])?, "");
+  let packed = fs::read_to_string(git_dir.join("packed-refs"))?;
+  assert!(packed.contains(&format!("{} refs/heads/feature/x\n", next)));
+  assert!(!git_dir.join("refs/heads/feature").exists());
+  assert!(!git_dir.join("refs/heads/master").exists());
+  assert!(git_dir.join("refs/heads").is_dir());
+  assert_eq!(
+    git_rs(path, &["branch"])?,
+    "  feature/x\n* master\n".to_string()
+  );
+
+  // packed-refs is only rewritten by one at a time
+  fs::write(git_dir.join("packed-refs.lock"), "")?;
+  write_ref(path, "refs/tags/later", &base)?;
+  let out = git_rs(path, &["pack-refs"])?;
+  assert!(out.contains("packed-refs.lock': File exists."), "{}", out);
+  assert!(git_dir.join("refs/tags/later").exists());
+  Ok(())
+}
+
+#[test]
+fn test_pack_refs_reftable() -> Result<(), Box<dyn std::error::Error>> {
+  let temp_dir = TempDir::new("gitrs")?;
+  let path = &temp_dir.path().canonicalize()?;
+  git_rs(path, &["init", "--ref-format=reftable"])?;
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  git_rs(path, &["reset", "--soft", &base])?;
+  git_rs(path, &["branch", "topic"])?;
+  git_rs(path, &["tag", "v1"])?;
+
+  // the tables are compacted into one
+  let list = path.join(".git/reftable/tables.list");
+  assert_eq!(git_rs(path, &["pack-refs", "--all"])?, "");
+  assert_eq!(fs::read_to_string(&list)?.lines().count(), 1);
+  assert_eq!(fs::read_dir(path.join(".git/reftable"))?.count(), 2);
+  assert_eq!(git_rs(path, &["branch"])?, "* master\n  topic\n");
+  assert_eq!(git_rs(path, &["tag"])?, "v1\n");
+  Ok(())
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use assert_cmd::Command;
+use common::init_repo;
+
+const PATCH: &str = "From 1111111111111111111111111111111111111111 Mon Sep 17 00:00:00 2001
+Subject: [PATCH] two
+
+---
+ f | 2 +-
+
+diff --git a/f b/f
+index 5626abf..f719efd 100644
+--- a/f
++++ b/f
+@@ -1,3 +1,3 @@
+ one
+-two
++2
+ three
+diff --git a/g b/g
+new file mode 100644
+index 0000000..587be6b
+--- /dev/null
++++ b/g
+@@ -0,0 +1 @@
++x
+";
+
+/// Runs `git-rs patch-id` on the given patch.
+fn patch_id(patch: &str, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(&canonical_path)
+    .arg("patch-id")
+    .args(args)
+    .write_stdin(patch)
+    .output()?;
+  Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_patch_id() -> Result<(), Box<dyn std::error::Error>> {
+  let commit = "1111111111111111111111111111111111111111";
+  assert_eq!(
+    patch_id(PATCH, &[])?,
+    format!("d5221a52cacdf12122778363edbb1fe288664ce4 {}\n", commit)
+  );
+  assert_eq!(
+    patch_id(PATCH, &["--stable"])?,
+    format!("2d48ecf2860fb12fa443685034f4756f0a3e2a18 {}\n", commit)
+  );
+
+  // whitespace does not count
+  assert_eq!(
+    patch_id(&PATCH.replace("+2\n", "+ 2\n"), &[])?,
+    format!("d5221a52cacdf12122778363edbb1fe288664ce4 {}\n", commit)
+  );
+  Ok(())
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{git_rs, hash_object, init_repo};
+use std::{ffi::OsStr, fs, os::unix::ffi::OsStrExt};
+
+#[test]
+fn test_non_utf8_paths() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let blob = hash_object(path, "blob", b"x\n")?;
+
+  // "café" in latin-1 is not valid UTF-8
+  let name = b"caf\xe9";
+  let mut payload = b"100644 ".to_vec();
+  payload.extend_from_slice(name);
+  payload.push(0);
+  payload.extend_from_slice(&hex::decode(&blob)?);
+  let tree = hash_object(path, "tree", &payload)?;
+  assert_eq!(tree, "16004dd6e52de069a8455fdf2d3c33aa640d5b47");
+
+  // the name survives a trip through the index
+  git_rs(path, &["read-tree", &tree])?;
+  assert_eq!(git_rs(path, &["write-tree"])?, format!("{}\n", tree));
+
+  // and into the working tree
+  let file = path.join(OsStr::from_bytes(name));
+  git_rs(path, &["restore", "."])?;
+  assert_eq!(fs::read(&file)?, b"x\n");
+  assert_eq!(git_rs(path, &["diff-files"])?, "");
+  fs::write(&file, "y\n")?;
+  assert!(git_rs(path, &["diff-files"])?.ends_with(" M\t\"caf\\351\"\n"));
+
+  // quoted the way git does, unless -z leaves the bytes as they are
+  let output = |args: &[&str]| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(
+      Command::cargo_bin("git-rs")?
+        .current_dir(path)
+        .args(args)
+        .output()?
+        .stdout,
+    )
+  };
+  let empty = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+  assert_eq!(
+    output(&["diff-tree", "--name-status", empty, &tree])?,
+    b"A\t\"caf\\351\"\n"
+  );
+  assert_eq!(
+    output(&["diff-tree", "--name-status", "-z", empty, &tree])?,
+    b"A\0caf\xe9\0"
+  );
+  assert_eq!(
+    output(&["diff-index", "--name-only", "-z", empty])?,
+    b"caf\xe9\0"
+  );
+  assert_eq!(output(&["ls-files", "-z"])?, b"caf\xe9\0");
+  assert_eq!(output(&["diff-files", "--name-only", "-z"])?, b"caf\xe9\0");
+  assert_eq!(output(&["status", "--porcelain"])?, b"AM \"caf\\351\"\n");
+  assert_eq!(output(&["status", "-z"])?, b"AM caf\xe9\0");
+  assert!(output(&["status", "--porcelain=v2", "-z"])?.ends_with(b" caf\xe9\0"));
+  Ok(())
+}
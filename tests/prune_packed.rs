@@ -0,0 +1,45 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref};
+
+#[test]
+fn test_prune_packed() -> Result<(), Box<dyn std::error::Error>> {
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  write_ref(path, "refs/heads/master", &next)?;
+  git_rs(path, &["repack", "-q"])?;
+  let loose = hash_object(path, "blob", b"not packed\n")?;
+
+  // nothing is deleted on a dry run
+  let mut expected: Vec<String> = [&base, &next]
+    .iter()
+    .map(|hash| format!("rm -f .git/objects/{}/{}\n", &hash[..2], &hash[2..]))
+    .collect();
+  expected.push("rm -f .git/objects/4b/825dc642cb6eb9a060e54bf8d69288fbee4904\n".to_string());
+  expected.sort();
+  let mut output: Vec<String> = git_rs(path, &["prune-packed", "-n"])?
+    .split_inclusive('\n')
+    .map(str::to_string)
+    .collect();
+  output.sort();
+  assert_eq!(output, expected);
+  assert_eq!(
+    git_rs(path, &["count-objects"])?,
+    "4 objects, 16 kilobytes\n"
+  );
+
+  // the objects in a pack are deleted, with the directories they leave empty
+  assert_eq!(git_rs(path, &["prune-packed", "-q"])?, "");
+  assert_eq!(
+    git_rs(path, &["count-objects"])?,
+    "1 objects, 4 kilobytes\n"
+  );
+  assert!(!path.join(".git/objects").join(&next[..2]).exists());
+  assert!(path.join(".git/objects").join(&loose[..2]).exists());
+  assert_eq!(git_rs(path, &["rev-list", "--count", "master"])?, "2\n");
+  assert_eq!(git_rs(path, &["prune-packed", "-n"])?, "");
+  Ok(())
+}
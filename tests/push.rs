@@ -0,0 +1,382 @@
+mod common;
+
+use common::{daemon, git_rs, git_rs_err, init_repo, write_commit, write_ref, write_tree};
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+fn write_script(path: &Path, script: &str) -> Result<(), Box<dyn std::error::Error>> {
+  fs::write(path, script)?;
+  fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+  Ok(())
+}
+
+#[test]
+fn test_push_options_and_signed_push() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+  let (_daemon, url) = daemon(&[
+    "--base-path",
+    root.to_str().unwrap(),
+    "--export-all",
+    "--enable=receive-pack",
+  ])?;
+  let url = format!("{}{}", url, name);
+
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  write_tree(path, &[])?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  write_ref(path, "refs/heads/master", &base)?;
+
+  // a stand-in for gpg, which signs anything and keeps what it signed, and
+  // finds good only what it signed
+  let gpg = path.join(".git/gpg");
+  write_script(
+    &gpg,
+    concat!(
+      "#!/bin/sh\n",
+      "case \"$1\" in\n",
+      "--status-fd=2)\n",
+      "  cat >\"$(dirname \"$0\")/payload\"\n",
+      "  echo '[GNUPG:] SIG_CREATED D 1 8 00 1792154207 EE0F1B6E' >&2\n",
+      "  printf -- '-----BEGIN PGP SIGNATURE-----\\n\\nc2lnbmVk\\n-----END PGP SIGNATURE-----\\n' ;;\n",
+      "*)\n",
+      "  grep -q c2lnbmVk \"$4\" || exit 1\n",
+      "  echo '[GNUPG:] GOODSIG 6D2BEA3D6D7AB2A2 Tester <t@x>'\n",
+      "  echo '[GNUPG:] TRUST_ULTIMATE 0 pgp' ;;\n",
+      "esac\n",
+    ),
+  )?;
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!(
+      "{}[remote \"origin\"]\n\turl = {}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+       [user]\n\tname = C O Mitter\n\temail = committer@example.com\n\
+       [gpg]\n\tprogram = {}\n",
+      config,
+      url,
+      gpg.display()
+    ),
+  )?;
+  let hooks = upstream.join(".git/hooks");
+  fs::create_dir_all(&hooks)?;
+  write_script(
+    &hooks.join("pre-receive"),
+    "#!/bin/sh\n[ \"$GIT_PUSH_OPTION_0\" = reject ] && echo 'not today' && exit 1\nexit 0\n",
+  )?;
+  write_script(
+    &hooks.join("post-receive"),
+    concat!(
+      "#!/bin/sh\n",
+      "cat\n",
+      "echo \"options: $GIT_PUSH_OPTION_COUNT $GIT_PUSH_OPTION_0 $GIT_PUSH_OPTION_1\"\n",
+      "echo \"cert: $GIT_PUSH_CERT_STATUS $GIT_PUSH_CERT_NONCE_STATUS $GIT_PUSH_CERT_SIGNER\"\n",
+      "echo \"$GIT_PUSH_CERT\" >cert\n",
+    ),
+  )?;
+
+  // push options only go to a remote that takes them
+  assert_eq!(
+    git_rs(path, &["push", "-o", "ci.skip", "origin", "master"])?,
+    "fatal: the receiving end does not support push options\n"
+  );
+  let upstream_config = fs::read_to_string(upstream.join(".git/config"))?
+    + &format!("[gpg]\n\tprogram = {}\n", gpg.display())
+    + "[receive]\n\tadvertisePushOptions = true\n";
+  fs::write(upstream.join(".git/config"), &upstream_config)?;
+  assert_eq!(
+    git_rs_err(
+      path,
+      &[
+        "push",
+        "-o",
+        "ci.skip",
+        "-o",
+        "reason=x y",
+        "origin",
+        "master"
+      ]
+    )?,
+    (
+      0,
+      format!(
+        "remote: {} {} refs/heads/master\nremote: options: 2 ci.skip reason=x y\n\
+         remote: cert:   \nTo {}\n * [new branch]      master -> master\n",
+        "0".repeat(40),
+        base,
+        url
+      )
+    )
+  );
+  assert_eq!(
+    fs::read_to_string(upstream.join(".git/refs/heads/master"))?,
+    format!("{}\n", base)
+  );
+  assert_eq!(
+    fs::read_to_string(path.join(".git/refs/remotes/origin/master"))?,
+    format!("{}\n", base)
+  );
+
+  // a hook may turn the whole push down
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  write_ref(path, "refs/heads/master", &next)?;
+  assert_eq!(
+    git_rs_err(path, &["push", "-o", "reject", "origin", "master"])?,
+    (
+      1,
+      format!(
+        "remote: not today\nTo {}\n ! [remote rejected] master -> master (pre-receive hook declined)\n\
+         error: failed to push some refs to '{}'\n",
+        url, url
+      )
+    )
+  );
+
+  // signing needs a nonce from the remote, unless only if asked
+  assert_eq!(
+    git_rs(path, &["push", "--signed", "origin", "master"])?,
+    "fatal: the receiving end does not support --signed push\n"
+  );
+  let (code, report) = git_rs_err(path, &["push", "--signed=if-asked", "origin", "master"])?;
+  assert_eq!(code, 0);
+  assert!(report.contains("remote: cert:   \n"));
+  assert!(report.ends_with(&format!(
+    "   {}..{}  master -> master\n",
+    &base[..7],
+    &next[..7]
+  )));
+
+  fs::write(
+    upstream.join(".git/config"),
+    upstream_config + "\tcertNonceSeed = sekrit\n",
+  )?;
+  let signed = write_commit(path, &[&next], 3000, "signed")?;
+  write_ref(path, "refs/heads/master", &signed)?;
+  let (code, report) = git_rs_err(path, &["push", "--signed", "origin", "master"])?;
+  assert_eq!(code, 0);
+  assert!(report.contains("remote: cert: G OK Tester <t@x>\n"));
+  let payload = fs::read_to_string(path.join(".git/payload"))?;
+  assert!(
+    payload.starts_with("certificate version 0.1\npusher C O Mitter <committer@example.com> ")
+  );
+  assert!(payload.contains(&format!("\npushee {}\nnonce ", url)));
+  assert!(payload.ends_with(&format!("\n\n{} {} refs/heads/master\n", next, signed)));
+  // the remote keeps the certificate
+  let cert = fs::read_to_string(upstream.join(".git/cert"))?;
+  assert_eq!(
+    git_rs(&upstream, &["cat-file", "-p", cert.trim()])?,
+    format!(
+      "{}-----BEGIN PGP SIGNATURE-----\n\nc2lnbmVk\n-----END PGP SIGNATURE-----\n",
+      payload
+    )
+  );
+  Ok(())
+}
+
+#[test]
+fn test_push_refspecs() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+
+  // pushing is off unless enabled
+  let (_daemon, url) = daemon(&["--base-path", root.to_str().unwrap(), "--export-all"])?;
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  write_tree(path, &[])?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  write_ref(path, "refs/heads/master", &base)?;
+  assert_eq!(
+    git_rs(path, &["push", &format!("{}{}", url, name), "master"])?,
+    format!("fatal: remote error: service not enabled: '/{}'\n", name)
+  );
+
+  let (_daemon, url) = daemon(&[
+    "--base-path",
+    root.to_str().unwrap(),
+    "--export-all",
+    "--enable=receive-pack",
+  ])?;
+  let url = format!("{}{}", url, name);
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!(
+      "{}[remote \"origin\"]\n\turl = {}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n",
+      config, url
+    ),
+  )?;
+  let (code, report) = git_rs_err(path, &["push", "origin", "master", "master:topic"])?;
+  assert_eq!(
+    (code, report),
+    (
+      0,
+      format!(
+        "To {}\n * [new branch]      master -> master\n * [new branch]      master -> topic\n",
+        url
+      )
+    )
+  );
+  assert_eq!(
+    git_rs_err(path, &["push"])?,
+    (0, "Everything up-to-date\n".to_string())
+  );
+
+  // a remote ref isn't moved back without forcing
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  let side = write_commit(path, &[&base], 3000, "side")?;
+  write_ref(path, "refs/heads/master", &next)?;
+  write_ref(path, "refs/heads/side", &side)?;
+  assert_eq!(git_rs_err(path, &["push"])?.0, 0);
+  assert_eq!(
+    git_rs_err(path, &["push", "origin", "side:master"])?,
+    (
+      1,
+      format!(
+        "To {}\n ! [rejected]        side -> master (non-fast-forward)\n\
+         error: failed to push some refs to '{}'\n",
+        url, url
+      )
+    )
+  );
+  let (code, report) = git_rs_err(path, &["push", "origin", "+side:master", ":topic"])?;
+  assert_eq!(
+    (code, report),
+    (
+      0,
+      format!(
+        "To {}\n + {}...{} side -> master (forced update)\n - [deleted]         topic\n",
+        url,
+        &next[..7],
+        &side[..7]
+      )
+    )
+  );
+  assert_eq!(
+    fs::read_to_string(upstream.join(".git/refs/heads/master"))?,
+    format!("{}\n", side)
+  );
+  assert!(!upstream.join(".git/refs/heads/topic").exists());
+  assert!(!path.join(".git/refs/remotes/origin/topic").exists());
+  assert_eq!(
+    git_rs(path, &["push", "origin", ":nothing"])?,
+    "fatal: The destination you provided is not a full refname (i.e., starting with \"refs/\"): nothing\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_push_atomic() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+  let (_daemon, url) = daemon(&[
+    "--base-path",
+    root.to_str().unwrap(),
+    "--export-all",
+    "--enable=receive-pack",
+  ])?;
+  let url = format!("{}{}", url, name);
+
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  write_tree(path, &[])?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let topic = write_commit(path, &[&base], 1500, "topic")?;
+  write_ref(path, "refs/heads/master", &base)?;
+  write_ref(path, "refs/heads/topic", &topic)?;
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!(
+      "{}[remote \"origin\"]\n\turl = {}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n",
+      config, url
+    ),
+  )?;
+  assert_eq!(
+    git_rs_err(path, &["push", "origin", "master", "topic"])?.0,
+    0
+  );
+
+  // a rejected ref keeps the others from being pushed
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  let side = write_commit(path, &[&base], 3000, "side")?;
+  write_ref(path, "refs/heads/master", &next)?;
+  write_ref(path, "refs/heads/side", &side)?;
+  assert_eq!(
+    git_rs_err(
+      path,
+      &["push", "--atomic", "origin", "master", "side:topic"]
+    )?,
+    (
+      1,
+      format!(
+        "To {}\n ! [rejected]        master -> master (atomic push failed)\n \
+         ! [rejected]        side -> topic (non-fast-forward)\n\
+         error: failed to push some refs to '{}'\n",
+        url, url
+      )
+    )
+  );
+  assert_eq!(
+    fs::read_to_string(upstream.join(".git/refs/heads/master"))?,
+    format!("{}\n", base)
+  );
+
+  // and so does a ref the remote can't update, which a hook moves here
+  let hooks = upstream.join(".git/hooks");
+  fs::create_dir_all(&hooks)?;
+  let move_topic = |to: &str| {
+    write_script(
+      &hooks.join("pre-receive"),
+      &format!("#!/bin/sh\necho {} >refs/heads/topic\n", to),
+    )
+  };
+  move_topic(&next)?;
+  let (code, report) = git_rs_err(
+    path,
+    &[
+      "-c",
+      "push.atomic=true",
+      "push",
+      "origin",
+      "master",
+      "+side:topic",
+    ],
+  )?;
+  assert_eq!(code, 1);
+  assert!(report.contains(" ! [remote rejected] master -> master ("));
+  assert!(report.contains(" ! [remote rejected] side -> topic ("));
+  assert_eq!(
+    fs::read_to_string(upstream.join(".git/refs/heads/master"))?,
+    format!("{}\n", base)
+  );
+
+  // unless the push isn't atomic
+  move_topic(&base)?;
+  let (code, report) = git_rs_err(
+    path,
+    &[
+      "-c",
+      "push.atomic=true",
+      "push",
+      "--no-atomic",
+      "origin",
+      "master",
+      "+side:topic",
+    ],
+  )?;
+  assert_eq!(code, 1);
+  assert!(report.contains(&format!(
+    "   {}..{}  master -> master\n",
+    &base[..7],
+    &next[..7]
+  )));
+  assert!(report.contains(" ! [remote rejected] side -> topic ("));
+  assert_eq!(
+    fs::read_to_string(upstream.join(".git/refs/heads/master"))?,
+    format!("{}\n", next)
+  );
+  Ok(())
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit_with_tree, write_tree};
+
+#[test]
+fn test_range_diff() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let blob = |data: &str| hash_object(path, "blob", data.as_bytes());
+  let base = blob("one\ntwo\nthree\nfour\nfive\nsix\nseven\n")?;
+  let two = blob("one\n2\nthree\nfour\nfive\nsix\nseven\n")?;
+  let six = blob("one\n2\nthree\nfour\nfive\n6\nseven\n")?;
+  let (g, h) = (blob("new\n")?, blob("h\n")?);
+
+  let tree = write_tree(path, &[("f", &base)])?;
+  let root = write_commit_with_tree(path, &tree, &[], 1000, "base")?;
+
+  // the old series changes two, adds g and changes six
+  let tree = write_tree(path, &[("f", &two)])?;
+  let old_two = write_commit_with_tree(path, &tree, &[&root], 2000, "change two")?;
+  let tree = write_tree(path, &[("f", &two), ("g", &g)])?;
+  let old_g = write_commit_with_tree(path, &tree, &[&old_two], 3000, "add g")?;
+  let tree = write_tree(path, &[("f", &six), ("g", &g)])?;
+  let old = write_commit_with_tree(path, &tree, &[&old_g], 4000, "change six")?;
+
+  // the new one drops g, rewords the change to six and adds h
+  let tree = write_tree(path, &[("f", &two)])?;
+  let new_two = write_commit_with_tree(path, &tree, &[&root], 2000, "change two")?;
+  let tree = write_tree(path, &[("f", &six)])?;
+  let new_six = write_commit_with_tree(path, &tree, &[&new_two], 5000, "change 6")?;
+  let tree = write_tree(path, &[("f", &six), ("h", &h)])?;
+  let new = write_commit_with_tree(path, &tree, &[&new_six], 6000, "add h")?;
+
+  let output = git_rs(path, &["range-diff", &root, &old, &new])?;
+  assert_eq!(
+    output,
+    format!(
+      "1:  {} = 1:  {} change two\n\
+       2:  {} < -:  ------- add g\n\
+       3:  {} ! 2:  {} change six\n    \
+       @@ Metadata\n     \
+       Author: A U Thor <author@example.com>\n     \
+       \n      \
+       ## Commit message ##\n    \
+       -    change six\n    \
+       +    change 6\n     \
+       \n      \
+       ## f ##\n     \
+       @@ f: one\n\
+       -:  ------- > 3:  {} add h\n",
+      &old_two[..7],
+      &new_two[..7],
+      &old_g[..7],
+      &old[..7],
+      &new_six[..7],
+      &new[..7]
+    )
+  );
+  Ok(())
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::{git_rs, init_repo};
+use std::fs;
+
+#[test]
+fn test_read_tree() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let stage = |files: &[(&str, &str)]| -> Result<String, Box<dyn std::error::Error>> {
+    git_rs(&canonical_path, &["read-tree", "--empty"])?;
+    for (path, contents) in files {
+      fs::write(canonical_path.join(path), contents)?;
+      git_rs(&canonical_path, &["update-index", "--add", path])?;
+    }
+    Ok(git_rs(&canonical_path, &["write-tree"])?.trim().to_string())
+  };
+  let base = stage(&[("a", "a\n"), ("b", "b\n")])?;
+  let ours = stage(&[("a", "ours\n"), ("b", "b\n")])?;
+  let theirs = stage(&[("a", "theirs\n"), ("b", "theirs\n"), ("c", "c\n")])?;
+  let merged = stage(&[("a", "ours\n"), ("b", "theirs\n"), ("c", "c\n")])?;
+
+  // one side changing a path wins, both changing it is a conflict
+  git_rs(&canonical_path, &["read-tree", &ours])?;
+  let output = git_rs(&canonical_path, &["read-tree", "-m", &base, &ours, &theirs])?;
+  assert_eq!(output, "");
+  let output = git_rs(&canonical_path, &["write-tree"])?;
+  assert!(output.contains("unmerged"));
+
+  // a clean three-way merge
+  let theirs_b = stage(&[("a", "a\n"), ("b", "theirs\n"), ("c", "c\n")])?;
+  git_rs(&canonical_path, &["read-tree", &ours])?;
+  git_rs(
+    &canonical_path,
+    &["read-tree", "-m", &base, &ours, &theirs_b],
+  )?;
+  assert_eq!(git_rs(&canonical_path, &["write-tree"])?.trim(), merged);
+
+  // two-way merge moves the index and working tree along
+  git_rs(&canonical_path, &["read-tree", "--reset", "-u", &base])?;
+  git_rs(&canonical_path, &["read-tree", "-m", "-u", &base, &theirs])?;
+  assert_eq!(git_rs(&canonical_path, &["write-tree"])?.trim(), theirs);
+  assert_eq!(fs::read_to_string(canonical_path.join("b"))?, "theirs\n");
+
+  // but refuses to throw away local changes
+  fs::write(canonical_path.join("b"), "local\n")?;
+  let output = git_rs(&canonical_path, &["read-tree", "-m", "-u", &theirs, &base])?;
+  assert!(output.contains("Entry 'b' not uptodate. Cannot merge."));
+
+  // subtree reads
+  git_rs(&canonical_path, &["read-tree", &base])?;
+  git_rs(&canonical_path, &["read-tree", "--prefix=sub/", &ours])?;
+  let output = git_rs(&canonical_path, &["write-tree", "--prefix=sub/"])?;
+  assert_eq!(output.trim(), ours);
+  let output = git_rs(&canonical_path, &["read-tree", "--prefix=sub/", &ours])?;
+  assert!(output.contains("subdirectory 'sub' already exists."));
+  Ok(())
+}
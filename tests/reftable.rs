@@ -0,0 +1,239 @@
+mod common;
+
+use common::{git_rs, hash_object, write_commit};
+use git_rs_core::refs::reftable::table::{self, LogRecord, RefRecord, Table};
+use git_rs_core::refs::{self, LogEntry, Ref};
+use git_rs_core::repo::Repo;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tempdir::TempDir;
+
+/// A stack of two tables laid out the way git writes them. The first holds
+/// transactions 1 to 4, as `git pack-refs` leaves them: `HEAD` pointing at
+/// main, main and topic at a commit, an annotated tag (with its peeled
+/// value) and the logs of the commit and the branch. The second deletes
+/// topic and, with tombstones, its log.
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/reftable");
+const COMMIT: &str = "d8a6b4d2f0e7c8b91a3e5f7d6c4b2a0918273645";
+const TAG: &str = "7f3e2d1c0b9a8f7e6d5c4b3a2918070605040302";
+
+#[test]
+fn test_reftable() -> Result<(), Box<dyn std::error::Error>> {
+  let temp_dir = TempDir::new("gitrs")?;
+  let path = &temp_dir.path().canonicalize()?;
+  git_rs(path, &["init", "--ref-format=reftable"])?;
+
+  // older versions of git see a git directory, but no refs they can read
+  let git_dir = path.join(".git");
+  assert_eq!(
+    fs::read_to_string(git_dir.join("HEAD"))?,
+    "ref: refs/heads/.invalid\n"
+  );
+  assert!(git_dir.join("refs/heads").is_file());
+  let config = fs::read_to_string(git_dir.join("config"))?;
+  assert!(config.contains("repositoryformatversion=1"));
+  assert!(config.contains("refStorage=reftable"));
+
+  hash_object(path, "tree", b"")?;
+  let base = write_commit(path, &[], 1000, "base")?;
+  let next = write_commit(path, &[&base], 2000, "next")?;
+  git_rs(path, &["reset", "--soft", &base])?;
+  git_rs(path, &["branch", "topic"])?;
+  git_rs(path, &["tag", "v1"])?;
+  git_rs(path, &["reset", "--soft", &next])?;
+  assert_eq!(git_rs(path, &["branch"])?, "* master\n  topic\n");
+  assert_eq!(git_rs(path, &["tag"])?, "v1\n");
+  assert!(!git_dir.join("refs/tags").exists());
+
+  // every change is a table, and the newest are merged so there are few
+  let list = fs::read_to_string(git_dir.join("reftable/tables.list"))?;
+  assert!(list.lines().count() <= 2);
+  for name in list.lines() {
+    assert!(name.starts_with("0x") && name.ends_with(".ref"));
+  }
+  let repo = Repo::discover(path)?;
+  assert_eq!(
+    refs::read_symbolic(&repo, "HEAD").as_deref(),
+    Some("refs/heads/master")
+  );
+  assert_eq!(refs::lookup(&repo, "master"), Some(next.clone()));
+  assert_eq!(refs::lookup(&repo, "topic"), Some(base.clone()));
+  assert_eq!(refs::lookup(&repo, "v1"), Some(base.clone()));
+
+  // deleting a ref hides it, and its log with it
+  let entry = LogEntry {
+    old: base.clone(),
+    new: next.clone(),
+    identity: "C O Mitter <committer@example.com> 2000 -0130".to_string(),
+    message: "moved".to_string(),
+  };
+  repo.refs.append_log("refs/heads/topic", &entry)?;
+  assert_eq!(repo.refs.read_log("refs/heads/topic")?, [entry]);
+  refs::delete(&repo, "refs/heads/topic")?;
+  assert_eq!(refs::lookup(&repo, "topic"), None);
+  assert!(repo.refs.read_log("refs/heads/topic")?.is_empty());
+  assert_eq!(
+    refs::collect(&repo, None).into_keys().collect::<Vec<_>>(),
+    ["refs/heads/master", "refs/tags/v1"]
+  );
+  Ok(())
+}
+
+#[test]
+fn test_reftable_index() -> Result<(), Box<dyn std::error::Error>> {
+  // enough refs, with long enough names, for an index of more than one level
+  let hash = |i: usize| format!("{:040x}", i + 1);
+  let name = |i: usize| format!("refs/tags/v{:06}-{}", i, "x".repeat(200));
+  let refs: Vec<RefRecord> = (0..20000)
+    .map(|i| RefRecord {
+      name: name(i),
+      update_index: 1 + i as u64 % 2,
+      value: Some(Ref::Direct(hash(i))),
+    })
+    .chain([RefRecord {
+      name: "refs/tags/z".to_string(),
+      update_index: 2,
+      value: None,
+    }])
+    .collect();
+  let logs = [LogRecord {
+    name: name(0),
+    update_index: 2,
+    entry: Some(LogEntry {
+      old: "0".repeat(40),
+      new: hash(0),
+      identity: "A U Thor <author@example.com> 1234567890 +0200".to_string(),
+      message: "created".to_string(),
+    }),
+  }];
+  let data = table::write(&refs, &logs, 1, 2)?;
+  let table = Table::parse(data)?;
+  assert_eq!((table.min_update_index, table.max_update_index), (1, 2));
+
+  for i in [0, 1, 4095, 10000, 19999] {
+    let mut found = None;
+    table.scan_refs(&name(i), |record| {
+      found = Some(record);
+      false
+    })?;
+    let found = found.unwrap();
+    assert_eq!(found.name, name(i));
+    assert_eq!(found.update_index, 1 + i as u64 % 2);
+    assert_eq!(found.value, Some(Ref::Direct(hash(i))));
+  }
+  let mut after = Vec::new();
+  table.scan_refs(&name(19998), |record| {
+    after.push(record);
+    true
+  })?;
+  assert_eq!(after.len(), 3);
+  assert_eq!(after[2].value, None);
+  assert_eq!(table.refs()?.len(), refs.len());
+  let read = table.logs()?;
+  assert_eq!(read.len(), 1);
+  assert_eq!(read[0].entry, logs[0].entry);
+
+  // a table with nothing in it is just a header and a footer
+  let empty = table::write(&[], &[], 3, 3)?;
+  assert_eq!(empty.len(), 24 + 68);
+  assert!(Table::parse(empty)?.refs()?.is_empty());
+  assert!(Table::parse(vec![0; 92]).is_err());
+  Ok(())
+}
+
+#[test]
+fn test_reftable_fixture() -> Result<(), Box<dyn std::error::Error>> {
+  let fixture = Path::new(FIXTURE);
+  let names: Vec<String> = fs::read_to_string(fixture.join("tables.list"))?
+    .lines()
+    .map(|name| name.to_string())
+    .collect();
+  let entry = |message: &str| LogEntry {
+    old: "0".repeat(40),
+    new: COMMIT.to_string(),
+    identity: "C O Mitter <committer@example.com> 1112911993 -0700".to_string(),
+    message: message.to_string(),
+  };
+
+  // the peeled value of the tag is skipped, and log messages lose their
+  // newline
+  let older = Table::parse(fs::read(fixture.join(&names[0]))?)?;
+  assert_eq!((older.min_update_index, older.max_update_index), (1, 4));
+  let refs: Vec<(String, u64, Option<Ref>)> = older
+    .refs()?
+    .into_iter()
+    .map(|record| (record.name, record.update_index, record.value))
+    .collect();
+  assert_eq!(
+    refs,
+    [
+      ("HEAD", 1, Ref::Symbolic("refs/heads/main".to_string())),
+      ("refs/heads/main", 2, Ref::Direct(COMMIT.to_string())),
+      ("refs/heads/topic", 3, Ref::Direct(COMMIT.to_string())),
+      ("refs/tags/v1", 4, Ref::Direct(TAG.to_string())),
+    ]
+    .map(|(name, update_index, value)| (name.to_string(), update_index, Some(value)))
+  );
+  let logs: Vec<(String, u64, Option<LogEntry>)> = older
+    .logs()?
+    .into_iter()
+    .map(|record| (record.name, record.update_index, record.entry))
+    .collect();
+  assert_eq!(
+    logs,
+    [
+      ("HEAD", 2, entry("commit (initial): first")),
+      ("refs/heads/main", 2, entry("commit (initial): first")),
+      ("refs/heads/topic", 3, entry("branch: Created from HEAD")),
+    ]
+    .map(|(name, update_index, entry)| (name.to_string(), update_index, Some(entry)))
+  );
+
+  // a deletion, and a tombstone keyed as the log entry it deletes
+  let newer = Table::parse(fs::read(fixture.join(&names[1]))?)?;
+  assert_eq!((newer.min_update_index, newer.max_update_index), (5, 5));
+  let deleted = &newer.refs()?[0];
+  assert_eq!(
+    (deleted.name.as_str(), deleted.update_index),
+    ("refs/heads/topic", 5)
+  );
+  assert!(deleted.value.is_none());
+  let tombstone = &newer.logs()?[0];
+  assert_eq!(
+    (tombstone.name.as_str(), tombstone.update_index),
+    ("refs/heads/topic", 3)
+  );
+  assert!(tombstone.entry.is_none());
+
+  // a repository keeping its refs in them sees topic gone
+  let temp_dir = TempDir::new("gitrs")?;
+  let path = &temp_dir.path().canonicalize()?;
+  git_rs(path, &["init", "--ref-format=reftable"])?;
+  let dir = path.join(".git/reftable");
+  fs::remove_dir_all(&dir)?;
+  fs::create_dir(&dir)?;
+  for name in names.iter().map(String::as_str).chain(["tables.list"]) {
+    fs::copy(fixture.join(name), dir.join(name))?;
+  }
+  let repo = Repo::discover(path)?;
+  assert_eq!(
+    refs::read_symbolic(&repo, "HEAD").as_deref(),
+    Some("refs/heads/main")
+  );
+  assert_eq!(repo.refs.read("refs/heads/topic")?, None);
+  assert_eq!(
+    refs::collect(&repo, None),
+    BTreeMap::from([
+      ("refs/heads/main".to_string(), COMMIT.to_string()),
+      ("refs/tags/v1".to_string(), TAG.to_string()),
+    ])
+  );
+  assert_eq!(
+    repo.refs.read_log("HEAD")?,
+    [entry("commit (initial): first")]
+  );
+  assert!(repo.refs.read_log("refs/heads/topic")?.is_empty());
+  assert_eq!(git_rs(path, &["branch"])?, "* main\n");
+  Ok(())
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use common::{daemon, git_rs, init_repo, write_commit, write_ref, write_tree};
+use std::fs;
+
+#[test]
+fn test_remote_set_head() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  write_tree(&upstream, &[])?;
+  let base = write_commit(&upstream, &[], 1000, "base")?;
+  let topic = write_commit(&upstream, &[&base], 2000, "topic")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  write_ref(&upstream, "refs/heads/topic", &topic)?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+  let (_daemon, url) = daemon(&["--base-path", root.to_str().unwrap(), "--export-all"])?;
+
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!(
+      "{}[remote \"origin\"]\n\turl = {}{}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n",
+      config, url, name
+    ),
+  )?;
+  let head = path.join(".git/refs/remotes/origin/HEAD");
+
+  // fetching sets the default branch of the remote, if it isn't set yet
+  git_rs(path, &["fetch", "-q"])?;
+  assert_eq!(
+    fs::read_to_string(&head)?,
+    "ref: refs/remotes/origin/master\n"
+  );
+  assert_eq!(
+    git_rs(path, &["rev-list", "-n", "1", "origin"])?,
+    format!("{}\n", base)
+  );
+
+  assert_eq!(
+    git_rs(path, &["remote", "set-head", "origin", "topic"])?,
+    ""
+  );
+  assert_eq!(
+    fs::read_to_string(&head)?,
+    "ref: refs/remotes/origin/topic\n"
+  );
+  assert_eq!(
+    git_rs(path, &["remote", "set-head", "origin", "nope"])?,
+    "fatal: Not a valid ref: refs/remotes/origin/nope\n"
+  );
+
+  fs::write(upstream.join(".git/HEAD"), "ref: refs/heads/master\n")?;
+  assert_eq!(
+    git_rs(path, &["remote", "set-head", "origin", "--auto"])?,
+    "origin/HEAD set to master\n"
+  );
+  assert_eq!(
+    fs::read_to_string(&head)?,
+    "ref: refs/remotes/origin/master\n"
+  );
+
+  // fetching leaves a default branch that is set alone
+  fs::write(upstream.join(".git/HEAD"), "ref: refs/heads/topic\n")?;
+  git_rs(path, &["fetch", "-q"])?;
+  assert_eq!(
+    fs::read_to_string(&head)?,
+    "ref: refs/remotes/origin/master\n"
+  );
+  assert_eq!(git_rs(path, &["remote", "set-head", "origin", "-d"])?, "");
+  assert!(!head.exists());
+  assert_eq!(
+    git_rs(path, &["remote", "set-head", "origin", "-d"])?,
+    "fatal: Not a symbolic ref: refs/remotes/origin/HEAD\n"
+  );
+  git_rs(path, &["fetch", "-q"])?;
+  assert_eq!(
+    fs::read_to_string(&head)?,
+    "ref: refs/remotes/origin/topic\n"
+  );
+  assert_eq!(
+    git_rs(path, &["remote", "set-head", "nowhere", "--auto"])?,
+    "fatal: 'nowhere' does not appear to be a git repository\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_switch_guesses_remote_branch() -> Result<(), Box<dyn std::error::Error>> {
+  let (_upstream_dir, upstream) = init_repo()?;
+  write_tree(&upstream, &[])?;
+  let base = write_commit(&upstream, &[], 1000, "base")?;
+  let topic = write_commit(&upstream, &[&base], 2000, "topic")?;
+  write_ref(&upstream, "refs/heads/master", &base)?;
+  write_ref(&upstream, "refs/heads/topic", &topic)?;
+  let root = upstream.parent().unwrap();
+  let name = upstream.file_name().unwrap().to_str().unwrap();
+  let (_daemon, url) = daemon(&["--base-path", root.to_str().unwrap(), "--export-all"])?;
+
+  let (_dir, path) = init_repo()?;
+  let path = &path;
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!(
+      "{}[remote \"origin\"]\n\turl = {}{}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n",
+      config, url, name
+    ),
+  )?;
+  git_rs(path, &["fetch", "-q"])?;
+  write_ref(path, "refs/heads/master", &base)?;
+  git_rs(path, &["reset", "--hard", "--force"])?;
+
+  // a branch only a remote has is made from it, tracking it
+  assert_eq!(
+    git_rs(path, &["switch", "--no-guess", "topic"])?,
+    "fatal: invalid reference: topic\n"
+  );
+  assert_eq!(
+    git_rs(path, &["switch", "topic"])?,
+    "branch 'topic' set up to track 'origin/topic'.\nSwitched to a new branch 'topic'\n"
+  );
+  assert_eq!(
+    fs::read_to_string(path.join(".git/refs/heads/topic"))?,
+    format!("{}\n", topic)
+  );
+  assert!(git_rs(path, &["status"])?.contains("Your branch is up to date with 'origin/topic'."));
+
+  // with more than one remote to make it from, the default remote is
+  git_rs(path, &["switch", "master"])?;
+  fs::remove_file(path.join(".git/refs/heads/topic"))?;
+  fs::write(
+    path.join(".git/config"),
+    fs::read_to_string(path.join(".git/config"))?
+      + &format!(
+        "[remote \"other\"]\n\turl = {}{}\n\tfetch = +refs/heads/*:refs/remotes/other/*\n",
+        url, name
+      ),
+  )?;
+  git_rs(path, &["fetch", "-q", "other"])?;
+  assert_eq!(
+    git_rs(path, &["switch", "topic"])?,
+    "fatal: invalid reference: topic\n"
+  );
+  assert_eq!(
+    git_rs(
+      path,
+      &["-c", "checkout.defaultRemote=other", "switch", "topic"]
+    )?,
+    "branch 'topic' set up to track 'other/topic'.\nSwitched to a new branch 'topic'\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit_with_tree, write_ref, write_tree};
+use git_rs_core::cancel::Cancel;
+use git_rs_core::progress::NoProgress;
+use git_rs_core::repack::{self, Options};
+use git_rs_core::{object, repo::Repo};
+use std::fs;
+
+#[test]
+fn test_repack() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let output = git_rs(&canonical_path, &["repack", "-a", "-d"])?;
+  assert_eq!(output, "Nothing new to pack.\n");
+
+  // five versions of a file of random looking lines, each one adding a line,
+  // which compress badly on their own but well as deltas
+  let mut seed: u64 = 1;
+  let mut lines = Vec::new();
+  let mut blobs = Vec::new();
+  let mut parents: Vec<String> = Vec::new();
+  for version in 0..5u64 {
+    let count = if version == 0 { 64 } else { 1 };
+    for _ in 0..count {
+      seed = seed
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407);
+      lines.push(format!("{:016x}{:016x}\n", seed, seed.rotate_left(29)));
+    }
+    let blob = hash_object(&canonical_path, "blob", lines.concat().as_bytes())?;
+    let tree = write_tree(&canonical_path, &[("data.txt", &blob)])?;
+    let parents_refs: Vec<&str> = parents.iter().map(String::as_str).collect();
+    let commit =
+      write_commit_with_tree(&canonical_path, &tree, &parents_refs, 1000 + version, "v")?;
+    parents = vec![commit];
+    blobs.push((blob, lines.concat()));
+  }
+  write_ref(&canonical_path, "refs/heads/master", &parents[0])?;
+
+  let args = ["repack", "-a", "-d", "-q", "--threads=1"];
+  assert_eq!(git_rs(&canonical_path, &args)?, "");
+  let pack_dir = canonical_path.join(".git/objects/pack");
+  let mut names: Vec<String> = fs::read_dir(&pack_dir)?
+    .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+    .collect();
+  names.sort();
+  assert_eq!(names.len(), 2);
+  assert!(names[0].starts_with("pack-") && names[0].ends_with(".idx"));
+  let pack = fs::metadata(pack_dir.join(&names[1]))?.len();
+  assert!(pack < 6000, "pack is {} bytes", pack);
+
+  // the loose objects are deleted, so they are read from the pack alone
+  for entry in fs::read_dir(canonical_path.join(".git/objects"))? {
+    assert_ne!(entry?.file_name().len(), 2);
+  }
+  let output = git_rs(&canonical_path, &["count-objects"])?;
+  assert!(output.starts_with("0 objects"), "{}", output);
+  for (blob, data) in &blobs {
+    assert_eq!(&git_rs(&canonical_path, &["cat-file", "blob", blob])?, data);
+  }
+  let log = git_rs(&canonical_path, &["rev-list", "--count", "master"])?;
+  assert_eq!(log, "5\n");
+
+  // the same pack comes out on more threads, and replaces itself
+  let args = ["repack", "-a", "-d", "-q", "--threads=4"];
+  git_rs(&canonical_path, &args)?;
+  let mut again: Vec<String> = fs::read_dir(&pack_dir)?
+    .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+    .collect();
+  again.sort();
+  assert_eq!(again, names);
+
+  // everything is packed already
+  let output = git_rs(&canonical_path, &["repack"])?;
+  assert_eq!(output, "Nothing new to pack.\n");
+  Ok(())
+}
+
+#[test]
+fn test_repack_seen_by_open_handle() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let blob = hash_object(path, "blob", b"data\n")?;
+  let tree = write_tree(path, &[("a", &blob)])?;
+  let commit = write_commit_with_tree(path, &tree, &[], 1000, "first")?;
+  write_ref(path, "refs/heads/master", &commit)?;
+
+  // a handle that has listed its packs before any were written
+  let repo = Repo::discover(path)?;
+  assert!(repo.objects.packs().is_empty());
+  let objects = repack::reachable(&repo)?;
+  let options = Options::new(&repo)?;
+  repack::write(&repo, &objects, &options, &mut NoProgress, &Cancel::new())?;
+  assert!(!repo.objects.is_packed(&blob));
+
+  // still finds the objects once their loose copies are gone
+  git_rs(path, &["prune-packed"])?;
+  assert!(!path.join(".git/objects").join(&blob[..2]).exists());
+  assert_eq!(object::read(&repo, &commit, None)?.format(), "commit");
+  assert_eq!(repo.objects.header(&blob)?, ("blob", 5));
+  assert!(repo.objects.exists(&tree));
+  assert_eq!(repo.objects.packs().len(), 1);
+  assert!(repo.objects.is_packed(&blob));
+
+  // and lets go of deleted packs
+  for entry in fs::read_dir(path.join(".git/objects/pack"))? {
+    fs::remove_file(entry?.path())?;
+  }
+  assert!(repo.objects.reprepare_packs());
+  assert!(repo.objects.packs().is_empty());
+  assert!(!repo.objects.reprepare_packs());
+  Ok(())
+}
+
+/// The number of objects in a pack, from its header.
+fn pack_count(pack: &std::path::Path) -> Result<u32, Box<dyn std::error::Error>> {
+  let data = fs::read(pack)?;
+  Ok(u32::from_be_bytes(data[8..12].try_into()?))
+}
+
+#[test]
+fn test_repack_kept_packs() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, path) = init_repo()?;
+  let path = &path;
+  let pack_dir = path.join(".git/objects/pack");
+  let packs = || -> Result<Vec<std::path::PathBuf>, std::io::Error> {
+    let mut packs: Vec<_> = fs::read_dir(&pack_dir)?
+      .map(|entry| entry.unwrap().path())
+      .filter(|path| path.extension().is_some_and(|ext| ext == "pack"))
+      .collect();
+    packs.sort();
+    Ok(packs)
+  };
+
+  let one = hash_object(path, "blob", b"one\n")?;
+  let tree = write_tree(path, &[("a.txt", &one)])?;
+  let base = write_commit_with_tree(path, &tree, &[], 1000, "base")?;
+  write_ref(path, "refs/heads/master", &base)?;
+  git_rs(path, &["repack", "-a", "-d", "-q"])?;
+  let kept = packs()?.remove(0);
+  fs::write(kept.with_extension("keep"), "")?;
+
+  // a kept pack is neither deleted nor packed again
+  let two = hash_object(path, "blob", b"two\n")?;
+  let tree = write_tree(path, &[("a.txt", &two)])?;
+  let next = write_commit_with_tree(path, &tree, &[&base], 2000, "next")?;
+  write_ref(path, "refs/heads/master", &next)?;
+  git_rs(path, &["repack", "-a", "-d", "-q"])?;
+  let after = packs()?;
+  assert_eq!(after.len(), 2);
+  assert!(after.contains(&kept));
+  let new = after.iter().find(|pack| **pack != kept).unwrap();
+  assert_eq!(pack_count(new)?, 3);
+
+  // unless its objects are asked for too
+  git_rs(path, &["repack", "-a", "-d", "-q", "--pack-kept-objects"])?;
+  let after = packs()?;
+  assert_eq!(after.len(), 2);
+  assert!(after.contains(&kept));
+  let new = after.iter().find(|pack| **pack != kept).unwrap();
+  assert_eq!(pack_count(new)?, 6);
+  git_rs(
+    path,
+    &[
+      "-c",
+      "repack.packKeptObjects=false",
+      "repack",
+      "-a",
+      "-d",
+      "-q",
+    ],
+  )?;
+  let new = packs()?.into_iter().find(|pack| *pack != kept).unwrap();
+  assert_eq!(pack_count(&new)?, 3);
+  Ok(())
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref, EMPTY_TREE};
+
+#[test]
+fn test_replace() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let first = write_commit(path, &[], 1000, "first")?;
+  let second = write_commit(path, &[&first], 1001, "second")?;
+  let third = write_commit(path, &[&second], 1002, "third")?;
+  let fixed = write_commit(path, &[], 1001, "second, fixed")?;
+  write_ref(path, "refs/heads/master", &third)?;
+
+  // history is read through the replacement
+  assert_eq!(git_rs(path, &["replace", &second, &fixed])?, "");
+  assert_eq!(
+    git_rs(path, &["rev-list", "master"])?,
+    format!("{}\n{}\n", third, second)
+  );
+  assert_eq!(
+    git_rs(path, &["--no-replace-objects", "rev-list", "master"])?,
+    format!("{}\n{}\n{}\n", third, second, first)
+  );
+  assert_eq!(
+    git_rs(
+      path,
+      &[
+        "-c",
+        "core.useReplaceRefs=false",
+        "rev-list",
+        "--count",
+        "master"
+      ]
+    )?,
+    "3\n"
+  );
+  assert_eq!(git_rs(path, &["fsck"])?, "");
+
+  assert_eq!(git_rs(path, &["replace"])?, format!("{}\n", second));
+  assert_eq!(
+    git_rs(path, &["replace", "-l", "--format=long", &second[..4]])?,
+    ""
+  );
+  assert_eq!(
+    git_rs(
+      path,
+      &[
+        "replace",
+        "-l",
+        "--format=long",
+        &format!("{}*", &second[..4])
+      ]
+    )?,
+    format!("{} (commit) -> {} (commit)\n", second, fixed)
+  );
+
+  // a replacement has to be of the same type, and is not overwritten
+  assert_eq!(
+    git_rs(path, &["replace", &second, EMPTY_TREE])?,
+    format!(
+      "fatal: Objects must be of the same type.\n'{}' points to a replaced object of type 'commit'\nwhile '{}' points to a replacement object of type 'tree'.\n",
+      second, EMPTY_TREE
+    )
+  );
+  assert_eq!(
+    git_rs(path, &["replace", &second, &first])?,
+    format!(
+      "fatal: replace ref 'refs/replace/{}' already exists\n",
+      second
+    )
+  );
+  assert_eq!(git_rs(path, &["replace", "-f", &second, &first])?, "");
+  assert_eq!(git_rs(path, &["rev-list", "--count", "master"])?, "2\n");
+
+  assert_eq!(
+    git_rs(path, &["replace", "-d", &second])?,
+    format!("Deleted replace ref '{}'\n", second)
+  );
+  assert_eq!(git_rs(path, &["replace"])?, "");
+  assert_eq!(git_rs(path, &["rev-list", "--count", "master"])?, "3\n");
+  Ok(())
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{git_rs, init_repo, EMPTY_TREE};
+use std::{fs, path::Path, process::Command};
+
+/// Runs `git-rs` in `dir` with the given environment variables set.
+fn git_rs_env(
+  dir: &Path,
+  env: &[(&str, &Path)],
+  args: &[&str],
+) -> Result<String, Box<dyn std::error::Error>> {
+  let mut cmd = Command::cargo_bin("git-rs")?;
+  cmd.current_dir(dir).args(args);
+  for (key, value) in env {
+    cmd.env(key, value);
+  }
+  Ok(String::from_utf8(cmd.output()?.stdout)?)
+}
+
+#[test]
+fn test_discover() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let repo = path.join("repo");
+  fs::create_dir(&repo)?;
+  git_rs(&repo, &["init"])?;
+  fs::create_dir_all(repo.join("a/b"))?;
+  fs::write(repo.join("f"), "hi\n")?;
+  git_rs(&repo, &["update-index", "--add", "f"])?;
+  let tree = git_rs(&repo, &["write-tree"])?;
+  assert_ne!(tree.trim(), EMPTY_TREE);
+
+  // the repository is found from any of its subdirectories
+  assert_eq!(git_rs(&repo.join("a/b"), &["write-tree"])?, tree);
+
+  // but not past a ceiling directory
+  let ceiling = [("GIT_CEILING_DIRECTORIES", repo.as_path())];
+  assert_eq!(
+    git_rs_env(&repo.join("a/b"), &ceiling, &["write-tree"])?,
+    "fatal: not a git repository (or any of the parent directories): .git\n"
+  );
+  let ceiling = [("GIT_CEILING_DIRECTORIES", path.as_path())];
+  assert_eq!(
+    git_rs_env(&repo.join("a/b"), &ceiling, &["write-tree"])?,
+    tree
+  );
+
+  // GIT_DIR names the git directory outright
+  let git_dir = repo.join(".git");
+  assert_eq!(
+    git_rs_env(path, &[("GIT_DIR", &git_dir)], &["write-tree"])?,
+    tree
+  );
+
+  // a .git file points at the git directory
+  let linked = path.join("linked");
+  fs::create_dir(&linked)?;
+  fs::write(linked.join(".git"), "gitdir: ../repo/.git\n")?;
+  assert_eq!(git_rs(&linked, &["write-tree"])?, tree);
+
+  // a git directory on its own is a bare repository
+  assert_eq!(git_rs(&git_dir, &["write-tree"])?, tree);
+  assert_eq!(
+    git_rs(&git_dir, &["clean", "-n"])?,
+    "fatal: this operation must be run in a work tree\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_gitfile() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+
+  // a submodule's git directory lives inside that of its superproject
+  let modules = path.join(".git/modules");
+  git_rs(
+    path,
+    &["init", "--separate-git-dir", ".git/modules/sub", "sub"],
+  )?;
+  let sub = path.join("sub");
+  fs::write(sub.join(".git"), "gitdir: ../.git/modules/sub\n")?;
+  fs::write(sub.join("f"), "hi\n")?;
+  git_rs(&sub, &["update-index", "--add", "f"])?;
+  assert!(modules.join("sub/index").is_file());
+  assert!(!path.join(".git/index").exists());
+  let tree = git_rs(&sub, &["write-tree"])?;
+  assert_ne!(tree.trim(), EMPTY_TREE);
+  fs::write(sub.join("g"), "hi\n")?;
+  assert_eq!(git_rs(&sub, &["status", "--porcelain"])?, "A  f\n?? g\n");
+
+  // GIT_DIR may name a .git file too
+  assert_eq!(
+    git_rs_env(path, &[("GIT_DIR", &sub.join(".git"))], &["write-tree"])?,
+    tree
+  );
+
+  // a .git file must point at a git directory
+  fs::write(sub.join(".git"), "../.git/modules/sub\n")?;
+  assert_eq!(
+    git_rs(&sub, &["write-tree"])?,
+    format!(
+      "fatal: invalid gitfile format: {}\n",
+      sub.join(".git").display()
+    )
+  );
+  fs::write(sub.join(".git"), "gitdir: ../missing\n")?;
+  assert_eq!(
+    git_rs(&sub, &["write-tree"])?,
+    format!(
+      "fatal: not a git repository: {}\n",
+      sub.join("../missing").display()
+    )
+  );
+  Ok(())
+}
+
+#[test]
+fn test_environment() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  fs::write(path.join("f"), "hi\n")?;
+
+  // the index may be kept elsewhere
+  let index = path.join(".git/other-index");
+  let env = [("GIT_INDEX_FILE", index.as_path())];
+  git_rs_env(path, &env, &["update-index", "--add", "f"])?;
+  assert!(index.is_file());
+  assert!(!path.join(".git/index").exists());
+  let tree = git_rs_env(path, &env, &["write-tree"])?;
+  assert_eq!(tree, "df55a7dce59d040dc7819c1e241082965a80ebd9\n");
+  assert_eq!(git_rs(path, &["write-tree"])?, format!("{}\n", EMPTY_TREE));
+
+  // and so may the objects
+  let objects = path.join("objects");
+  fs::create_dir(&objects)?;
+  let env = [("GIT_OBJECT_DIRECTORY", objects.as_path())];
+  fs::write(path.join("g"), "elsewhere\n")?;
+  let blob = git_rs_env(path, &env, &["hash-object", "-w", "g"])?;
+  let blob = blob.trim();
+  assert!(objects.join(&blob[..2]).join(&blob[2..]).is_file());
+  assert_eq!(
+    git_rs_env(path, &env, &["cat-file", "blob", blob])?,
+    "elsewhere\n"
+  );
+  assert_ne!(git_rs(path, &["cat-file", "blob", blob])?, "elsewhere\n");
+
+  // which can be borrowed from as alternates
+  let env = [("GIT_ALTERNATE_OBJECT_DIRECTORIES", objects.as_path())];
+  assert_eq!(
+    git_rs_env(path, &env, &["cat-file", "blob", blob])?,
+    "elsewhere\n"
+  );
+  fs::create_dir_all(path.join(".git/objects/info"))?;
+  fs::write(path.join(".git/objects/info/alternates"), "../../objects\n")?;
+  assert_eq!(git_rs(path, &["cat-file", "blob", blob])?, "elsewhere\n");
+  git_rs(path, &["hash-object", "-w", "g"])?;
+  assert!(!path.join(".git/objects").join(&blob[..2]).exists());
+
+  // settings may be passed down as numbered pairs
+  let count = [
+    ("GIT_CONFIG_COUNT", Path::new("2")),
+    ("GIT_CONFIG_KEY_0", Path::new("user.name")),
+    ("GIT_CONFIG_VALUE_0", Path::new("A U Thor")),
+    ("GIT_CONFIG_KEY_1", Path::new("user.email")),
+    ("GIT_CONFIG_VALUE_1", Path::new("author@example.com")),
+    ("GIT_AUTHOR_DATE", Path::new("@1000 +0000")),
+    ("GIT_COMMITTER_DATE", Path::new("@1000 +0000")),
+  ];
+  let commit = git_rs_env(path, &count, &["commit-tree", EMPTY_TREE, "-m", "msg"])?;
+  let text = git_rs(path, &["cat-file", "commit", commit.trim()])?;
+  assert!(text.contains("\nauthor A U Thor <author@example.com> 1000 +0000\n"));
+  assert_eq!(
+    git_rs_env(path, &count[..1], &["write-tree"])?,
+    "fatal: missing config key GIT_CONFIG_KEY_0\n"
+  );
+  assert_eq!(
+    git_rs_env(
+      path,
+      &[("GIT_CONFIG_COUNT", Path::new("x"))],
+      &["write-tree"]
+    )?,
+    "fatal: bogus count in GIT_CONFIG_COUNT\n"
+  );
+  Ok(())
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::{git_rs, git_rs_as, init_repo, write_ref};
+use std::{fs, path::Path};
+
+/// Commits a new version of `f` on top of `parent` and points the branch at
+/// the new commit, returning its hash.
+fn commit(
+  repo: &Path,
+  parent: Option<&str>,
+  contents: &str,
+  message: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+  fs::write(repo.join("f"), contents)?;
+  git_rs(repo, &["update-index", "--add", "f"])?;
+  let tree = git_rs(repo, &["write-tree"])?;
+  let mut args = vec!["commit-tree", tree.trim(), "-m", message];
+  if let Some(parent) = parent {
+    args.extend(["-p", parent]);
+  }
+  let hash = git_rs_as(repo, &args)?.trim().to_owned();
+  write_ref(repo, "refs/heads/master", &hash)?;
+  Ok(hash)
+}
+
+#[test]
+fn test_rerere() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  fs::create_dir(canonical_path.join(".git/rr-cache"))?;
+  let base = commit(&canonical_path, None, "one\ntwo\nthree\n", "base")?;
+  commit(&canonical_path, Some(&base), "one\n2\nthree\n", "two")?;
+  git_rs(&canonical_path, &["format-patch", "-1"])?;
+  git_rs(&canonical_path, &["reset", "--hard", &base])?;
+  let ours = commit(&canonical_path, Some(&base), "one\nzwei\nthree\n", "zwei")?;
+
+  // the conflict is recorded under a hash of its sorted sides
+  let output = git_rs_as(&canonical_path, &["am", "-3", "0001-two.patch"])?;
+  assert!(output.contains("Merge conflict in f\nRecorded preimage for 'f'\n"));
+  let id = "fc15b3d4b97f1db05d7a95ed1ac13239504844d2";
+  assert_eq!(
+    fs::read_to_string(
+      canonical_path
+        .join(".git/rr-cache")
+        .join(id)
+        .join("preimage")
+    )?,
+    "one\n<<<<<<<\n2\n=======\nzwei\n>>>>>>>\nthree\n"
+  );
+  assert_eq!(
+    fs::read_to_string(canonical_path.join(".git/MERGE_RR"))?,
+    format!("{}\tf\0", id)
+  );
+  assert_eq!(git_rs(&canonical_path, &["rerere", "status"])?, "f\n");
+
+  // resolving and continuing records the resolution
+  fs::write(canonical_path.join("f"), "one\nzwei 2\nthree\n")?;
+  let output = git_rs(&canonical_path, &["rerere", "diff"])?;
+  assert_eq!(
+    output,
+    "--- a/f\n+++ b/f\n@@ -1,7 +1,3 @@\n one\n-<<<<<<<\n-2\n-=======\n-zwei\n->>>>>>>\n+zwei 2\n three\n"
+  );
+  git_rs(&canonical_path, &["update-index", "--add", "f"])?;
+  let output = git_rs_as(&canonical_path, &["am", "--continue"])?;
+  assert_eq!(output, "Recorded resolution for 'f'.\n");
+  assert_eq!(
+    fs::read_to_string(
+      canonical_path
+        .join(".git/rr-cache")
+        .join(id)
+        .join("postimage")
+    )?,
+    "one\nzwei 2\nthree\n"
+  );
+
+  // the same conflict is resolved the same way the next time
+  git_rs(&canonical_path, &["reset", "--hard", &ours])?;
+  let output = git_rs_as(&canonical_path, &["am", "-3", "0001-two.patch"])?;
+  assert!(output.contains("Merge conflict in f\nResolved 'f' using previous resolution.\n"));
+  assert_eq!(
+    fs::read_to_string(canonical_path.join("f"))?,
+    "one\nzwei 2\nthree\n"
+  );
+  assert_eq!(git_rs(&canonical_path, &["rerere", "remaining"])?, "f\n");
+
+  let output = git_rs(&canonical_path, &["rerere", "forget", "f"])?;
+  assert_eq!(
+    output,
+    "Updated preimage for 'f'\nForgot resolution for 'f'\n"
+  );
+  git_rs(&canonical_path, &["am", "--abort"])?;
+  assert!(!canonical_path.join(".git/MERGE_RR").exists());
+  assert!(!canonical_path.join(".git/rr-cache").join(id).exists());
+  Ok(())
+}
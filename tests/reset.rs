@@ -0,0 +1,112 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{hash_object, init_repo, write_commit_with_tree, write_ref, write_tree};
+use predicates::prelude::*;
+use std::{fs, process::Command};
+
+#[test]
+fn test_reset() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let blob1 = hash_object(&canonical_path, "blob", b"one\n")?;
+  let blob2 = hash_object(&canonical_path, "blob", b"two\n")?;
+  let tree1 = write_tree(&canonical_path, &[("file.txt", &blob1)])?;
+  let tree2 = write_tree(&canonical_path, &[("file.txt", &blob2)])?;
+  let first = write_commit_with_tree(&canonical_path, &tree1, &[], 1000, "first")?;
+  let second = write_commit_with_tree(&canonical_path, &tree2, &[&first], 2000, "second")?;
+  write_ref(&canonical_path, "refs/heads/master", &second)?;
+  let head = || fs::read_to_string(canonical_path.join(".git/refs/heads/master")).unwrap();
+
+  // nothing is checked out yet, so a hard reset would lose the staged deletion
+  let mut reset_cmd = Command::cargo_bin("git-rs")?;
+  reset_cmd
+    .current_dir(&canonical_path)
+    .arg("reset")
+    .arg("--hard");
+  reset_cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("would be lost:\n\tfile.txt"));
+  assert!(!canonical_path.join("file.txt").exists());
+
+  let mut reset_cmd = Command::cargo_bin("git-rs")?;
+  reset_cmd
+    .current_dir(&canonical_path)
+    .args(["reset", "--hard", "--force"]);
+  reset_cmd
+    .assert()
+    .success()
+    .stdout(format!("HEAD is now at {}\n", &second[..7]));
+  assert_eq!(
+    fs::read_to_string(canonical_path.join("file.txt"))?,
+    "two\n"
+  );
+
+  // a clean working tree can be reset without forcing
+  let mut reset_cmd = Command::cargo_bin("git-rs")?;
+  reset_cmd
+    .current_dir(&canonical_path)
+    .args(["reset", "--hard", "HEAD~1"]);
+  reset_cmd.assert().success();
+  assert_eq!(
+    fs::read_to_string(canonical_path.join("file.txt"))?,
+    "one\n"
+  );
+  assert_eq!(head(), format!("{}\n", first));
+  assert_eq!(
+    fs::read_to_string(canonical_path.join(".git/ORIG_HEAD"))?,
+    format!("{}\n", second)
+  );
+
+  // a soft reset only moves the branch
+  let mut reset_cmd = Command::cargo_bin("git-rs")?;
+  reset_cmd
+    .current_dir(&canonical_path)
+    .args(["reset", "--soft", &second]);
+  reset_cmd.assert().success().stdout("");
+  assert_eq!(head(), format!("{}\n", second));
+  assert_eq!(
+    fs::read_to_string(canonical_path.join("file.txt"))?,
+    "one\n"
+  );
+
+  // a mixed reset updates the index, leaving the working tree modified
+  let mut reset_cmd = Command::cargo_bin("git-rs")?;
+  reset_cmd.current_dir(&canonical_path).arg("reset");
+  reset_cmd
+    .assert()
+    .success()
+    .stdout("Unstaged changes after reset:\nM\tfile.txt\n");
+  Ok(())
+}
+
+#[test]
+fn test_reset_index_locked() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let blob = hash_object(&canonical_path, "blob", b"one\n")?;
+  let tree = write_tree(&canonical_path, &[("file.txt", &blob)])?;
+  let commit = write_commit_with_tree(&canonical_path, &tree, &[], 1000, "first")?;
+  write_ref(&canonical_path, "refs/heads/master", &commit)?;
+
+  // another process holding the lock is left alone, and the index unwritten
+  let lock = canonical_path.join(".git/index.lock");
+  fs::write(&lock, "")?;
+  let mut reset_cmd = Command::cargo_bin("git-rs")?;
+  reset_cmd.current_dir(&canonical_path).arg("reset");
+  reset_cmd.assert().stdout(format!(
+    "fatal: Unable to create '{}': File exists.\n\n\
+     Another git process seems to be running in this repository.\n",
+    lock.display()
+  ));
+  assert!(lock.exists());
+  assert!(!canonical_path.join(".git/index").exists());
+
+  // once it is gone, the index is written through the lock
+  fs::remove_file(&lock)?;
+  let mut reset_cmd = Command::cargo_bin("git-rs")?;
+  reset_cmd.current_dir(&canonical_path).arg("reset");
+  reset_cmd.assert().success();
+  assert!(!lock.exists());
+  assert!(canonical_path.join(".git/index").exists());
+  Ok(())
+}
@@ -0,0 +1,195 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{
+  hash_object, init_repo, write_commit, write_commit_with_tree, write_ref, write_tree, EMPTY_TREE,
+};
+use predicates::prelude::*;
+use std::{fs, path::Path, process::Command};
+
+#[test]
+fn test_rev_list() -> Result<(), Box<dyn std::error::Error>> {
+  // Create a new temporary directory with a repository in it
+  let (_temp_dir, canonical_path) = init_repo()?;
+
+  // Build the history: one <- two <- three (master), two <- side (topic)
+  hash_object(&canonical_path, "tree", b"")?;
+  let one = write_commit(&canonical_path, &[], 1000, "one")?;
+  let two = write_commit(&canonical_path, &[&one], 2000, "two")?;
+  let three = write_commit(&canonical_path, &[&two], 3000, "three")?;
+  let side = write_commit(&canonical_path, &[&two], 4000, "side")?;
+  write_ref(&canonical_path, "refs/heads/master", &three)?;
+  write_ref(&canonical_path, "refs/heads/topic", &side)?;
+  write_ref(&canonical_path, "refs/tags/one", &one)?;
+
+  let expected = format!("{}\n{}\n{}\n", three, two, one);
+  rev_list_template(&canonical_path, &["HEAD"], &expected)?;
+  rev_list_template(&canonical_path, &["--count", "master"], "3\n")?;
+  rev_list_template(
+    &canonical_path,
+    &["-n", "1", "master"],
+    &format!("{}\n", three),
+  )?;
+  rev_list_template(&canonical_path, &["topic..master"], &format!("{}\n", three))?;
+  rev_list_template(
+    &canonical_path,
+    &["master", "^topic"],
+    &format!("{}\n", three),
+  )?;
+  rev_list_template(
+    &canonical_path,
+    &["master...topic"],
+    &format!("{}\n{}\n", side, three),
+  )?;
+  rev_list_template(
+    &canonical_path,
+    &["--objects", "one"],
+    &format!("{}\n{} \n", one, EMPTY_TREE),
+  )?;
+  rev_list_template(
+    &canonical_path,
+    &["--since", "@2000", "master"],
+    &format!("{}\n{}\n", three, two),
+  )?;
+  rev_list_template(
+    &canonical_path,
+    &[
+      "--after=@1500",
+      "--before=1970-01-01 00:41:40 +0000",
+      "master",
+    ],
+    &format!("{}\n", two),
+  )?;
+  rev_list_template(
+    &canonical_path,
+    &["--objects", "HEAD~1..HEAD"],
+    &format!("{}\n", three),
+  )?;
+  Ok(())
+}
+
+#[test]
+fn test_rev_list_paths() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+
+  // a.txt is added, then b.txt is added, then a.txt changes
+  let a1 = hash_object(&canonical_path, "blob", b"one\n")?;
+  let a2 = hash_object(&canonical_path, "blob", b"two\n")?;
+  let b1 = hash_object(&canonical_path, "blob", b"three\n")?;
+  let tree1 = write_tree(&canonical_path, &[("a.txt", &a1)])?;
+  let tree2 = write_tree(&canonical_path, &[("a.txt", &a1), ("b.txt", &b1)])?;
+  let tree3 = write_tree(&canonical_path, &[("a.txt", &a2), ("b.txt", &b1)])?;
+  let one = write_commit_with_tree(&canonical_path, &tree1, &[], 1000, "one")?;
+  let two = write_commit_with_tree(&canonical_path, &tree2, &[&one], 2000, "two")?;
+  let three = write_commit_with_tree(&canonical_path, &tree3, &[&two], 3000, "three")?;
+  write_ref(&canonical_path, "refs/heads/master", &three)?;
+
+  rev_list_template(
+    &canonical_path,
+    &["HEAD", "--", "a.txt"],
+    &format!("{}\n{}\n", three, one),
+  )?;
+  rev_list_template(
+    &canonical_path,
+    &["HEAD", "--", "b.txt"],
+    &format!("{}\n", two),
+  )?;
+  rev_list_template(&canonical_path, &["HEAD", "--", "c.txt"], "")?;
+  Ok(())
+}
+
+#[test]
+fn test_rev_list_object_cache() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  hash_object(&canonical_path, "tree", b"")?;
+  let one = write_commit(&canonical_path, &[], 1000, "one")?;
+  let two = write_commit(&canonical_path, &[&one], 2000, "two")?;
+  let three = write_commit(&canonical_path, &[&two], 3000, "three")?;
+  write_ref(&canonical_path, "refs/heads/master", &three)?;
+
+  // the commits are read again when their objects are listed, and they all
+  // share a tree, so the cache is used...
+  let run = |args: &[&str]| -> Result<(String, String), Box<dyn std::error::Error>> {
+    let output = Command::cargo_bin("git-rs")?
+      .current_dir(&canonical_path)
+      .args(args)
+      .env("GIT_TRACE_OBJECT_CACHE", "1")
+      .output()?;
+    Ok((
+      String::from_utf8(output.stdout)?,
+      String::from_utf8(output.stderr)?,
+    ))
+  };
+  let (cached, trace) = run(&["rev-list", "--objects", "master"])?;
+  assert_eq!(
+    cached,
+    format!("{}\n{}\n{}\n{} \n", three, two, one, EMPTY_TREE)
+  );
+  let hits: u64 = trace
+    .strip_prefix("object cache: ")
+    .and_then(|rest| rest.split(' ').next())
+    .ok_or("no trace")?
+    .parse()?;
+  assert!(hits > 0);
+
+  // ...unless it is turned off, which changes nothing but the trace
+  let args = [
+    "-c",
+    "core.objectCacheLimit=0",
+    "rev-list",
+    "--objects",
+    "master",
+  ];
+  let (uncached, trace) = run(&args)?;
+  assert_eq!(uncached, cached);
+  assert!(trace.starts_with("object cache: 0 hits, "));
+  assert!(trace.ends_with(" misses, 0 bytes\n"));
+  Ok(())
+}
+
+#[test]
+fn test_rev_list_grafts() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let one = write_commit(path, &[], 1000, "one")?;
+  let two = write_commit(path, &[&one], 2000, "two")?;
+  let three = write_commit(path, &[&two], 3000, "three")?;
+  let four = write_commit(path, &[&three], 4000, "four")?;
+  write_ref(path, "refs/heads/master", &four)?;
+
+  // a graft skips over a commit...
+  fs::create_dir_all(path.join(".git/info"))?;
+  let grafts = path.join(".git/info/grafts");
+  fs::write(&grafts, format!("# skip two\n{} {}\n", three, one))?;
+  rev_list_template(
+    path,
+    &["master"],
+    &format!("{}\n{}\n{}\n", four, three, one),
+  )?;
+
+  // ...and a shallow clone ends where it was cut off, even where the
+  // parent that was never fetched has been grafted on
+  fs::write(path.join(".git/shallow"), format!("{}\n", three))?;
+  let remove =
+    |hash: &str| fs::remove_file(path.join(".git/objects").join(&hash[..2]).join(&hash[2..]));
+  remove(&one)?;
+  remove(&two)?;
+  rev_list_template(path, &["master"], &format!("{}\n{}\n", four, three))?;
+  rev_list_template(path, &["--count", "master~1"], "1\n")?;
+  Ok(())
+}
+
+fn rev_list_template(
+  repo: &Path,
+  args: &[&str],
+  expected: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+  let mut rev_list_cmd = Command::cargo_bin("git-rs")?;
+  rev_list_cmd.current_dir(repo).arg("rev-list").args(args);
+  rev_list_cmd
+    .assert()
+    .success()
+    .stdout(predicate::eq(expected));
+  Ok(())
+}
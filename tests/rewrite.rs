@@ -0,0 +1,74 @@
+mod common;
+
+use bstr::BString;
+use common::{hash_object, init_repo, write_commit_with_tree, write_ref, write_tree};
+use git_rs_core::object::{self, commit::Commit, serializable::Unbox, tree};
+use git_rs_core::{refs, repo::Repo, rewrite::rewrite};
+
+#[test]
+fn test_rewrite() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let one = hash_object(path, "blob", b"one\n")?;
+  let two = hash_object(path, "blob", b"two\n")?;
+  let secret = hash_object(path, "blob", b"hunter2\n")?;
+
+  // master: base (with the secret) <- drop <- tip, and two branches of
+  // their own, one that is dropped and one that needs no change
+  let tree = write_tree(path, &[("a", &one), ("secret", &secret)])?;
+  let base = write_commit_with_tree(path, &tree, &[], 1000, "base")?;
+  let tree = write_tree(path, &[("a", &two), ("secret", &secret)])?;
+  let dropped = write_commit_with_tree(path, &tree, &[&base], 2000, "drop this")?;
+  let tree = write_tree(path, &[("a", &two), ("b", &one), ("secret", &secret)])?;
+  let tip = write_commit_with_tree(path, &tree, &[&dropped], 3000, "tip")?;
+  let side = write_commit_with_tree(path, &tree, &[], 4000, "drop side")?;
+  let clean = write_commit_with_tree(path, &write_tree(path, &[("a", &one)])?, &[], 5000, "clean")?;
+  write_ref(path, "refs/heads/master", &tip)?;
+  write_ref(path, "refs/heads/side", &side)?;
+  write_ref(path, "refs/heads/clean", &clean)?;
+
+  let repo = Repo::discover(path)?;
+  let names = ["master", "side", "clean"].map(|name| format!("refs/heads/{}", name));
+  let map = rewrite(&repo, &names, |edit| {
+    edit.files.remove(&BString::from("secret"));
+    edit.skip = edit.message.starts_with("drop");
+    Ok(())
+  })?;
+
+  // the dropped commits are left out of the map, and what is unchanged
+  // keeps its hash
+  let mut originals: Vec<&String> = map.keys().collect();
+  originals.sort();
+  let mut expected = vec![&base, &tip, &clean];
+  expected.sort();
+  assert_eq!(originals, expected);
+  assert_eq!(map[&clean], clean);
+  assert_ne!(map[&base], base);
+
+  // the secret is gone from every commit, and the child of a dropped
+  // commit takes its parent
+  let read = |hash: &str| -> Result<(Vec<String>, Vec<BString>, String), String> {
+    let object = object::read(&repo, hash, Some("commit"))?;
+    let commit = object.unbox::<Commit>()?;
+    let files = tree::flatten(&repo, commit.tree())?.into_keys().collect();
+    Ok((
+      commit.parents().to_vec(),
+      files,
+      commit.message().to_owned(),
+    ))
+  };
+  let (parents, files, message) = read(&map[&tip])?;
+  assert_eq!(parents, [map[&base].clone()]);
+  assert_eq!(files, ["a", "b"]);
+  assert_eq!(message, "tip\n");
+  let (parents, files, _) = read(&map[&base])?;
+  assert!(parents.is_empty());
+  assert_eq!(files, ["a"]);
+
+  // the refs follow, and a ref with nothing left is deleted
+  let refs = refs::collect(&repo, None);
+  assert_eq!(refs["refs/heads/master"], map[&tip]);
+  assert_eq!(refs["refs/heads/clean"], clean);
+  assert!(!refs.contains_key("refs/heads/side"));
+  Ok(())
+}
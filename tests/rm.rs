@@ -0,0 +1,61 @@
+mod common;
+
+use common::{git_rs, hash_object, init_repo, write_commit_with_tree, write_ref, write_tree};
+use std::fs;
+
+#[test]
+fn test_rm() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let blob = hash_object(&canonical_path, "blob", b"content\n")?;
+  let tree = write_tree(&canonical_path, &[("a.txt", &blob), ("b.txt", &blob)])?;
+  let commit = write_commit_with_tree(&canonical_path, &tree, &[], 1000, "first")?;
+  write_ref(&canonical_path, "refs/heads/master", &commit)?;
+  git_rs(&canonical_path, &["reset", "--hard", "--force"])?;
+
+  // local modifications are protected unless forced
+  fs::write(canonical_path.join("a.txt"), "changed\n")?;
+  let output = git_rs(&canonical_path, &["rm", "a.txt"])?;
+  assert!(output.contains("have local modifications:\n    a.txt"));
+  assert!(canonical_path.join("a.txt").exists());
+
+  // --cached keeps the file in the working tree
+  let output = git_rs(&canonical_path, &["rm", "--cached", "a.txt"])?;
+  assert_eq!(output, "rm 'a.txt'\n");
+  assert!(canonical_path.join("a.txt").exists());
+
+  let output = git_rs(&canonical_path, &["rm", "b.txt"])?;
+  assert_eq!(output, "rm 'b.txt'\n");
+  assert!(!canonical_path.join("b.txt").exists());
+
+  // neither file is tracked any more
+  let output = git_rs(&canonical_path, &["rm", "a.txt"])?;
+  assert!(output.contains("pathspec 'a.txt' did not match any files"));
+  Ok(())
+}
+
+#[test]
+fn test_mv() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let blob = hash_object(&canonical_path, "blob", b"content\n")?;
+  let tree = write_tree(&canonical_path, &[("a.txt", &blob), ("b.txt", &blob)])?;
+  let commit = write_commit_with_tree(&canonical_path, &tree, &[], 1000, "first")?;
+  write_ref(&canonical_path, "refs/heads/master", &commit)?;
+  git_rs(&canonical_path, &["reset", "--hard", "--force"])?;
+
+  git_rs(&canonical_path, &["mv", "a.txt", "c.txt"])?;
+  assert!(!canonical_path.join("a.txt").exists());
+  assert_eq!(
+    fs::read_to_string(canonical_path.join("c.txt"))?,
+    "content\n"
+  );
+
+  // the index follows the file, so it can be removed under its new name
+  let output = git_rs(&canonical_path, &["rm", "--cached", "-f", "c.txt"])?;
+  assert_eq!(output, "rm 'c.txt'\n");
+
+  let output = git_rs(&canonical_path, &["mv", "b.txt", "c.txt"])?;
+  assert!(output.contains("destination exists, source=b.txt, destination=c.txt"));
+  let output = git_rs(&canonical_path, &["mv", "missing.txt", "d.txt"])?;
+  assert!(output.contains("bad source, source=missing.txt, destination=d.txt"));
+  Ok(())
+}
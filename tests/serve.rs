@@ -0,0 +1,131 @@
+#![cfg(feature = "serve")]
+
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{hash_object, init_repo, write_commit_with_tree, write_ref, write_tree};
+use std::{
+  io::{BufRead, BufReader, Read, Write},
+  net::TcpStream,
+  process::{Child, Command, Stdio},
+};
+
+/// A running `serve`, stopped when dropped (even by a failed assertion).
+struct Server(Child);
+
+impl Drop for Server {
+  fn drop(&mut self) {
+    let _ = self.0.kill();
+    let _ = self.0.wait();
+  }
+}
+
+/// Sends a request and returns the status line and body of the response.
+fn get(
+  addr: &str,
+  method: &str,
+  path: &str,
+) -> Result<(String, String), Box<dyn std::error::Error>> {
+  let mut stream = TcpStream::connect(addr)?;
+  write!(
+    stream,
+    "{} {} HTTP/1.0\r\nHost: {}\r\n\r\n",
+    method, path, addr
+  )?;
+  let mut response = String::new();
+  stream.read_to_string(&mut response)?;
+  let (head, body) = response.split_once("\r\n\r\n").unwrap();
+  Ok((head.lines().next().unwrap().to_string(), body.to_string()))
+}
+
+#[test]
+fn test_serve() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let one = hash_object(path, "blob", b"one\n")?;
+  let two = hash_object(path, "blob", b"one\n<two>\n")?;
+  let sub = write_tree(path, &[("b.txt", &one)])?;
+  let before = write_tree(path, &[("a.txt", &one)])?;
+  let mut entries = b"100644 a.txt\0".to_vec();
+  entries.extend(hex::decode(&two)?);
+  entries.extend(b"40000 dir\0");
+  entries.extend(hex::decode(&sub)?);
+  let after = hash_object(path, "tree", &entries)?;
+  let base = write_commit_with_tree(path, &before, &[], 1000, "base")?;
+  let head = write_commit_with_tree(path, &after, &[&base], 2000, "add two & dir")?;
+  write_ref(path, "refs/heads/master", &head)?;
+  std::fs::create_dir_all(path.join(".git/refs/heads/topic"))?;
+  write_ref(path, "refs/heads/topic/old", &base)?;
+
+  // port 0 picks a free port, which is printed once the server is listening
+  let mut server = Server(
+    Command::cargo_bin("git-rs")?
+      .current_dir(path)
+      .args(["serve", "--local", "--port", "0"])
+      .stdout(Stdio::piped())
+      .spawn()?,
+  );
+  let mut line = String::new();
+  BufReader::new(server.0.stdout.take().unwrap()).read_line(&mut line)?;
+  let addr = line
+    .trim_end()
+    .rsplit_once("http://")
+    .unwrap()
+    .1
+    .trim_end_matches('/')
+    .to_string();
+
+  // the summary lists the branches and latest commits
+  let (status, body) = get(&addr, "GET", "/")?;
+  assert_eq!(status, "HTTP/1.0 200 OK");
+  assert!(body.contains(&format!("<a href=\"/log/{}\">master</a>", head)));
+  assert!(body.contains(&format!("<a href=\"/log/{}\">topic/old</a>", base)));
+  assert!(body.contains(&format!(
+    "<a href=\"/commit/{}\">add two &amp; dir</a>",
+    head
+  )));
+
+  // a commit, with its diff escaped
+  let (status, body) = get(&addr, "GET", &format!("/commit/{}", head))?;
+  assert_eq!(status, "HTTP/1.0 200 OK");
+  assert!(body.contains(&format!(
+    "<a href=\"/commit/{}\"><code>{}</code></a>",
+    base, base
+  )));
+  assert!(body.contains("<span class=\"add\">+&lt;two&gt;</span>"));
+  assert!(body.contains("<span class=\"file\">diff --git a/dir/b.txt b/dir/b.txt</span>"));
+
+  // branch names with slashes resolve, and logs list the commits
+  let (_, body) = get(&addr, "GET", "/log/topic/old")?;
+  assert!(body.contains(&format!("<a href=\"/commit/{}\">base</a>", base)));
+  assert!(!body.contains("add two"));
+
+  // trees and files
+  let (_, body) = get(&addr, "GET", &format!("/tree/{}", head))?;
+  assert!(body.contains(&format!("<a href=\"/blob/{}/a.txt\">a.txt</a>", head)));
+  assert!(body.contains(&format!("<a href=\"/tree/{}/dir\">dir/</a>", head)));
+  let (_, body) = get(&addr, "GET", "/blob/master/a.txt")?;
+  assert!(body.contains("<pre>one\n&lt;two&gt;\n</pre>"));
+  assert_eq!(get(&addr, "GET", "/raw/master/dir/b.txt")?.1, "one\n");
+  let (status, body) = get(&addr, "HEAD", "/raw/master/dir/b.txt")?;
+  assert_eq!((status.as_str(), body.as_str()), ("HTTP/1.0 200 OK", ""));
+
+  // anything else is not found, and nothing can be changed
+  assert_eq!(
+    get(&addr, "GET", "/blob/master/c.txt")?.0,
+    "HTTP/1.0 404 Not Found"
+  );
+  assert_eq!(
+    get(&addr, "GET", "/commit/nope")?.0,
+    "HTTP/1.0 404 Not Found"
+  );
+  assert_eq!(
+    get(&addr, "GET", "/tree/master/a.txt")?.0,
+    "HTTP/1.0 404 Not Found"
+  );
+  assert_eq!(
+    get(&addr, "POST", "/")?.0,
+    "HTTP/1.0 405 Method Not Allowed"
+  );
+  Ok(())
+}
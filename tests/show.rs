@@ -0,0 +1,282 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{
+  blob_hash, git_rs, hash_object, init_repo, write_commit_with_tree, write_ref, write_tree,
+  EMPTY_TREE,
+};
+use git_rs_core::repo::Repo;
+use sha1::{Digest, Sha1};
+use std::{fs, path::Path};
+
+#[test]
+fn test_show() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let one = hash_object(path, "blob", b"one\n")?;
+  let two = hash_object(path, "blob", b"one\ntwo\n")?;
+  let before = write_tree(path, &[("a.txt", &one)])?;
+  let after = write_tree(path, &[("a.txt", &two)])?;
+  let base = write_commit_with_tree(path, &before, &[], 1000, "base")?;
+  let head = write_commit_with_tree(path, &after, &[&base], 2000, "add two")?;
+  write_ref(path, "refs/heads/master", &head)?;
+
+  // a commit, with the diff it makes to its parent
+  assert_eq!(
+    git_rs(path, &["show"])?,
+    format!(
+      "commit {}\nAuthor: A U Thor <author@example.com>\nDate:   Thu Jan 1 00:33:20 1970 +0000\n\n    add two\n\ndiff --git a/a.txt b/a.txt\nindex {}..{} 100644\n--- a/a.txt\n+++ b/a.txt\n@@ -1 +1,2 @@\n one\n+two\n",
+      head,
+      &one[..7],
+      &two[..7]
+    )
+  );
+  assert_eq!(
+    git_rs(path, &["show", "-s", "HEAD~"])?,
+    format!(
+      "commit {}\nAuthor: A U Thor <author@example.com>\nDate:   Thu Jan 1 00:16:40 1970 +0000\n\n    base\n",
+      base
+    )
+  );
+
+  // a tag, followed by what it tags
+  let tag = format!(
+    "object {}\ntype tree\ntag v1\ntagger T Agger <tagger@example.com> 3000 +0100\n\nthe tree\n",
+    after
+  );
+  let tag = hash_object(path, "tag", tag.as_bytes())?;
+  write_ref(path, "refs/tags/v1", &tag)?;
+  assert_eq!(
+    git_rs(path, &["show", "v1"])?,
+    format!(
+      "tag v1\nTagger: T Agger <tagger@example.com>\nDate:   Thu Jan 1 01:50:00 1970 +0100\n\nthe tree\n\n100644 blob {}\ta.txt\n",
+      two
+    )
+  );
+
+  // trees are listed, and blobs shown as they are
+  assert_eq!(
+    git_rs(path, &["show", "HEAD^{tree}", &one])?,
+    format!("100644 blob {}\ta.txt\n\none\n", two)
+  );
+  assert_eq!(git_rs(path, &["show", EMPTY_TREE])?, "");
+  Ok(())
+}
+
+#[test]
+fn test_show_diff_driver() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!(
+      "{}[diff \"ini\"]\n\txfuncname = \"^\\\\[(.*)\\\\]$\"\n\tcommand = echo EXT\n",
+      config
+    ),
+  )?;
+  fs::write(path.join(".gitattributes"), "*.ini diff=ini\n*.dat -diff\n")?;
+  let old = "[one]\na\nb\nc\nd\n[two]\ne\nf\ng\nh\ni\n";
+  let new = "[one]\na\nb\nc\nd\n[two]\ne\nf\ng\nh\nI\n";
+  let before = write_tree(
+    path,
+    &[
+      ("a.dat", &hash_object(path, "blob", b"one\n")?),
+      ("a.ini", &hash_object(path, "blob", old.as_bytes())?),
+    ],
+  )?;
+  let after = write_tree(
+    path,
+    &[
+      ("a.dat", &hash_object(path, "blob", b"two\n")?),
+      ("a.ini", &hash_object(path, "blob", new.as_bytes())?),
+    ],
+  )?;
+  let base = write_commit_with_tree(path, &before, &[], 1000, "base")?;
+  let head = write_commit_with_tree(path, &after, &[&base], 2000, "change")?;
+  write_ref(path, "refs/heads/master", &head)?;
+
+  // hunks are headed by what the driver's pattern picks, and files that
+  // aren't to be diffed are binary
+  let show = git_rs(path, &["show"])?;
+  assert!(show.contains("Binary files a/a.dat and b/a.dat differ\n"));
+  assert!(show.contains("@@ -8,4 +8,4 @@ two\n f\n g\n h\n-i\n+I\n"));
+
+  // the driver's command shows the diff when asked to
+  let show = git_rs(path, &["show", "--ext-diff"])?;
+  assert!(show.contains("Binary files a/a.dat and b/a.dat differ\n"));
+  let line = show.lines().find(|line| line.starts_with("EXT ")).unwrap();
+  let args: Vec<&str> = line.split(' ').collect();
+  assert_eq!(args.len(), 8);
+  assert_eq!(args[1], "a.ini");
+  assert_eq!(
+    &args[3..5],
+    [blob_hash(old.as_bytes()), "100644".to_string()]
+  );
+  assert_eq!(
+    &args[6..8],
+    [blob_hash(new.as_bytes()), "100644".to_string()]
+  );
+  assert!(!Path::new(args[2]).exists());
+  Ok(())
+}
+
+#[test]
+fn test_show_word_diff() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let old = hash_object(path, "blob", b"ctx\na b c\nfoo bar\nend\n")?;
+  let new = hash_object(path, "blob", b"ctx\na c\nfoo  baz qux\nend\n")?;
+  let before = write_tree(path, &[("a.txt", &old), ("b.chr", &old)])?;
+  let after = write_tree(path, &[("a.txt", &new), ("b.chr", &new)])?;
+  let base = write_commit_with_tree(path, &before, &[], 1000, "base")?;
+  let head = write_commit_with_tree(path, &after, &[&base], 2000, "change")?;
+  write_ref(path, "refs/heads/master", &head)?;
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!("{}[diff \"chars\"]\n\twordRegex = .\n", config),
+  )?;
+  fs::write(path.join(".gitattributes"), "*.chr diff=chars\n")?;
+  let hunks = |args: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let show = git_rs(path, args)?;
+    Ok(
+      show
+        .split("@@ -1,4 +1,4 @@\n")
+        .skip(1)
+        .map(|hunk| hunk.split("diff --git").next().unwrap().to_string())
+        .collect(),
+    )
+  };
+
+  // the words that changed are marked, and what is between them is taken
+  // from the new side
+  assert_eq!(
+    hunks(&["show", "--word-diff"])?,
+    [
+      "ctx\na[-b-] c\nfoo  [-bar-]{+baz qux+}\nend\n",
+      "ctx\na [-b -]c\nfoo {+ +}ba[-r-]{+z qux+}\nend\n"
+    ]
+  );
+  assert_eq!(
+    hunks(&["show", "--word-diff=porcelain"])?[0],
+    " ctx\n~\n a\n-b\n  c\n~\n foo  \n-bar\n+baz qux\n~\n end\n~\n"
+  );
+  // a regex given on the command line wins over the driver's
+  assert_eq!(
+    hunks(&["show", "--word-diff-regex=[a-z]+"])?[1],
+    "ctx\na[-b-] c\nfoo  [-bar-]{+baz qux+}\nend\n"
+  );
+  assert_eq!(
+    hunks(&["show", "--word-diff=color"])?[0],
+    "ctx\na\x1b[31mb\x1b[m c\nfoo  \x1b[31mbar\x1b[m\x1b[32mbaz qux\x1b[m\nend\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_show_submodule() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let sub = path.join("sub");
+  fs::create_dir(&sub)?;
+  git_rs(&sub, &["init"])?;
+  let first = write_commit_with_tree(&sub, EMPTY_TREE, &[], 1000, "one")?;
+  let second = write_commit_with_tree(&sub, EMPTY_TREE, &[&first], 2000, "two")?;
+  let third = write_commit_with_tree(&sub, EMPTY_TREE, &[&second], 3000, "three")?;
+  write_ref(&sub, "refs/heads/master", &third)?;
+
+  let mut commits = Vec::new();
+  for (n, hash) in [&first, &third].iter().enumerate() {
+    let cacheinfo = format!("160000,{},sub", hash);
+    git_rs(path, &["update-index", "--add", "--cacheinfo", &cacheinfo])?;
+    let tree = git_rs(path, &["write-tree"])?;
+    let parents: Vec<&str> = commits.iter().map(String::as_str).collect();
+    commits.push(write_commit_with_tree(
+      path,
+      tree.trim(),
+      &parents,
+      1000 + n as u64,
+      "sub",
+    )?);
+  }
+
+  // by default, a submodule's diff is the commits it moves between
+  let show = git_rs(path, &["show", &commits[1]])?;
+  assert!(show.contains(&format!(
+    "-Subproject commit {}\n+Subproject commit {}\n",
+    first, third
+  )));
+
+  // or with the log, the commits in between
+  let show = git_rs(path, &["show", "--submodule", &commits[1]])?;
+  assert!(show.ends_with(&format!(
+    "\nSubmodule sub {}..{}:\n  > three\n  > two\n",
+    &first[..7],
+    &third[..7]
+  )));
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!("{}[diff]\n\tsubmodule = log\n", config),
+  )?;
+  let show = git_rs(path, &["show", &commits[0]])?;
+  assert!(show.ends_with(&format!(
+    "\nSubmodule sub 0000000...{} (new submodule)\n",
+    &first[..7]
+  )));
+
+  // which can't be listed without the submodule's repository
+  fs::rename(&sub, path.join("elsewhere"))?;
+  let show = git_rs(path, &["show", &commits[1]])?;
+  assert!(show.ends_with(&format!(
+    "\nSubmodule sub {}...{} (commits not present)\n",
+    &first[..7],
+    &third[..7]
+  )));
+  Ok(())
+}
+
+/// Writes a tree of entries as they are given, bad modes and all, which
+/// `hash-object` would tidy up.
+fn write_raw_tree(repo: &Path, entries: &[&[u8]]) -> Result<String, Box<dyn std::error::Error>> {
+  let payload = entries.concat();
+  let data = [format!("tree {}\0", payload.len()).as_bytes(), &payload].concat();
+  let hash = hex::encode(Sha1::digest(&data));
+  Repo::discover(repo)?.objects.write(&hash, &data)?;
+  Ok(hash)
+}
+
+#[test]
+fn test_show_bad_trees() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let blob = hash_object(path, "blob", b"a\n")?;
+  let id = hex::decode(&blob)?;
+
+  // the group-writable mode old versions of git wrote reads as 100644
+  let tree = write_raw_tree(path, &[b"100664 a\0", &id])?;
+  let commit = write_commit_with_tree(path, &tree, &[], 1000, "legacy")?;
+  write_ref(path, "refs/heads/master", &commit)?;
+  assert_eq!(
+    git_rs(path, &["ls-tree", &tree])?,
+    format!("100644 blob {}\ta\n", blob)
+  );
+  assert!(git_rs(path, &["show", "HEAD"])?.contains("+++ b/a\n@@ -0,0 +1 @@\n+a\n"));
+
+  // an unknown mode and a cut short hash are errors, not crashes
+  let unknown = write_raw_tree(path, &[b"170000 a\0", &id])?;
+  let truncated = write_raw_tree(path, &[b"100644 a\0", &id[..10]])?;
+  for (tree, error) in [
+    (&unknown, "malformed tree: invalid mode '170000'"),
+    (&truncated, "malformed tree: entry is truncated"),
+  ] {
+    Command::cargo_bin("git-rs")?
+      .current_dir(path)
+      .args(["ls-tree", tree])
+      .assert()
+      .stdout(format!("fatal: {}\n", error));
+  }
+  Ok(())
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref};
+use std::path::Path;
+
+/// Runs `git-rs show-ref` and returns what it printed and its exit code.
+fn show_ref(repo: &Path, args: &[&str]) -> Result<(String, i32), Box<dyn std::error::Error>> {
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(repo)
+    .arg("show-ref")
+    .args(args)
+    .output()?;
+  Ok((
+    String::from_utf8(output.stdout)?,
+    output.status.code().unwrap_or(-1),
+  ))
+}
+
+#[test]
+fn test_show_ref() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let commit = write_commit(path, &[], 1000, "base")?;
+  let tag = format!(
+    "object {}\ntype commit\ntag v1\ntagger T Agger <tagger@example.com> 2000 +0000\n\nv1\n",
+    commit
+  );
+  let tag = hash_object(path, "tag", tag.as_bytes())?;
+  write_ref(path, "refs/heads/master", &commit)?;
+  write_ref(path, "refs/tags/v1", &tag)?;
+
+  assert_eq!(
+    git_rs(path, &["show-ref"])?,
+    format!("{} refs/heads/master\n{} refs/tags/v1\n", commit, tag)
+  );
+  assert_eq!(
+    show_ref(path, &["--tags", "-d", "--abbrev"])?,
+    (
+      format!(
+        "{} refs/tags/v1\n{} refs/tags/v1^{{}}\n",
+        &tag[..7],
+        &commit[..7]
+      ),
+      0
+    )
+  );
+  assert_eq!(
+    show_ref(path, &["--head", "--hash=8", "master"])?,
+    (format!("{}\n{}\n", &commit[..8], &commit[..8]), 0)
+  );
+
+  // the exit code says whether a ref exists
+  assert_eq!(show_ref(path, &["-q", "master"])?, (String::new(), 0));
+  assert_eq!(show_ref(path, &["aster"])?, (String::new(), 1));
+  assert_eq!(show_ref(path, &["--heads", "v1"])?, (String::new(), 1));
+  assert_eq!(
+    show_ref(path, &["--verify", "refs/heads/master"])?,
+    (format!("{} refs/heads/master\n", commit), 0)
+  );
+  assert_eq!(
+    show_ref(path, &["--verify", "master"])?,
+    ("fatal: 'master' - not a valid ref\n".to_string(), 128)
+  );
+  assert_eq!(
+    show_ref(path, &["--verify", "-q", "refs/heads/topic"])?,
+    (String::new(), 1)
+  );
+  Ok(())
+}
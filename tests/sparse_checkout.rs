@@ -0,0 +1,100 @@
+mod common;
+
+use common::{git_rs, init_repo, write_commit_with_tree, write_ref};
+use std::{fs, path::Path};
+
+/// The files in the working tree, sorted.
+fn files(dir: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+  let mut files = Vec::new();
+  for path in ["top", "a/x", "a/b/y", "e/v"] {
+    if dir.join(path).is_file() {
+      files.push(path.to_string());
+    }
+  }
+  Ok(files)
+}
+
+#[test]
+fn test_sparse_checkout() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  fs::create_dir_all(canonical_path.join("a/b"))?;
+  fs::create_dir_all(canonical_path.join("e"))?;
+  for path in ["top", "a/x", "a/b/y", "e/v"] {
+    fs::write(canonical_path.join(path), format!("{}\n", path))?;
+  }
+  git_rs(
+    &canonical_path,
+    &["update-index", "--add", "top", "a/x", "a/b/y", "e/v"],
+  )?;
+  let tree = git_rs(&canonical_path, &["write-tree"])?;
+  let first = write_commit_with_tree(&canonical_path, tree.trim(), &[], 1000, "first")?;
+  write_ref(&canonical_path, "refs/heads/master", &first)?;
+
+  // the top-level files stay, as do the files leading to the directory
+  git_rs(&canonical_path, &["sparse-checkout", "set", "a/b/"])?;
+  assert_eq!(files(&canonical_path)?, ["top", "a/x", "a/b/y"]);
+  assert_eq!(
+    fs::read_to_string(canonical_path.join(".git/info/sparse-checkout"))?,
+    "/*\n!/*/\n/a/\n!/a/*/\n/a/b/\n"
+  );
+  assert_eq!(
+    git_rs(&canonical_path, &["sparse-checkout", "list"])?,
+    "a/b\n"
+  );
+  assert_eq!(git_rs(&canonical_path, &["status", "--porcelain"])?, "");
+
+  // files left out are only updated in the index
+  fs::write(canonical_path.join("a/b/y"), "changed\n")?;
+  fs::create_dir_all(canonical_path.join("e"))?;
+  fs::write(canonical_path.join("e/v"), "changed\n")?;
+  git_rs(&canonical_path, &["update-index", "a/b/y", "e/v"])?;
+  let tree = git_rs(&canonical_path, &["write-tree"])?;
+  let second = write_commit_with_tree(&canonical_path, tree.trim(), &[&first], 2000, "second")?;
+  write_ref(&canonical_path, "refs/heads/master", &second)?;
+  fs::remove_dir_all(canonical_path.join("e"))?;
+  git_rs(&canonical_path, &["reset", "--hard", &first])?;
+  assert_eq!(files(&canonical_path)?, ["top", "a/x", "a/b/y"]);
+  git_rs(&canonical_path, &["reset", "--hard", &second])?;
+  assert_eq!(files(&canonical_path)?, ["top", "a/x", "a/b/y"]);
+  assert_eq!(git_rs(&canonical_path, &["status", "--porcelain"])?, "");
+
+  // and written out once they are in the sparse checkout
+  git_rs(&canonical_path, &["sparse-checkout", "set", "e"])?;
+  assert_eq!(files(&canonical_path)?, ["top", "e/v"]);
+  assert_eq!(fs::read_to_string(canonical_path.join("e/v"))?, "changed\n");
+  assert_eq!(git_rs(&canonical_path, &["status", "--porcelain"])?, "");
+  Ok(())
+}
+
+#[test]
+fn test_sparse_checkout_worktree_config() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let config = fs::read_to_string(path.join(".git/config"))?;
+  fs::write(
+    path.join(".git/config"),
+    format!("{}[extensions]\n\tworktreeConfig = true\n", config),
+  )?;
+  fs::create_dir_all(path.join(".git/info"))?;
+  fs::write(path.join(".git/info/sparse-checkout"), "/*\n!/*/\n/a/\n")?;
+
+  // the sparse checkout is only on where config.worktree says so
+  fs::write(
+    path.join(".git/config.worktree"),
+    "[core]\n\tsparseCheckout = true\n",
+  )?;
+  assert_eq!(git_rs(path, &["sparse-checkout", "list"])?, "a\n");
+  fs::write(
+    path.join(".git/config.worktree"),
+    "[core]\n\tsparseCheckout = false\n",
+  )?;
+  assert_eq!(git_rs(path, &["sparse-checkout", "list"])?, "");
+
+  // which is where turning it on goes
+  git_rs(path, &["sparse-checkout", "set", "e"])?;
+  assert_eq!(git_rs(path, &["sparse-checkout", "list"])?, "e\n");
+  let worktree = fs::read_to_string(path.join(".git/config.worktree"))?;
+  assert!(worktree.contains("sparseCheckout=true"));
+  assert!(!fs::read_to_string(path.join(".git/config"))?.contains("sparseCheckout"));
+  Ok(())
+}
@@ -0,0 +1,372 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{
+  git_rs, hash_object, init_repo, write_commit, write_commit_with_tree, write_ref, write_tree,
+};
+use std::{fs, io::Write, thread, time::Duration};
+
+#[test]
+fn test_status_porcelain() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let output = git_rs(&canonical_path, &["status", "--porcelain=v2", "--branch"])?;
+  assert_eq!(output, "# branch.oid (initial)\n# branch.head master\n");
+  let output = git_rs(&canonical_path, &["status", "--porcelain", "-b"])?;
+  assert_eq!(output, "## No commits yet on master\n");
+
+  // commit a and b, then change b, stage c and leave "d e" untracked
+  fs::write(canonical_path.join("a"), "a\n")?;
+  fs::write(canonical_path.join("b"), "b\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "a", "b"])?;
+  let tree = git_rs(&canonical_path, &["write-tree"])?;
+  let commit = write_commit_with_tree(&canonical_path, tree.trim(), &[], 1000, "initial")?;
+  write_ref(&canonical_path, "refs/heads/master", &commit)?;
+  fs::write(canonical_path.join("b"), "changed\n")?;
+  fs::write(canonical_path.join("c"), "c\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "c"])?;
+  fs::write(canonical_path.join("d e"), "d\n")?;
+
+  let b = hash_object(&canonical_path, "blob", b"b\n")?;
+  let c = hash_object(&canonical_path, "blob", b"c\n")?;
+  let output = git_rs(&canonical_path, &["status", "--porcelain=v2", "--branch"])?;
+  assert_eq!(
+    output,
+    format!(
+      "# branch.oid {}\n# branch.head master\n\
+       1 .M N... 100644 100644 100644 {} {} b\n\
+       1 A. N... 000000 100644 100644 {} {} c\n\
+       ? d e\n",
+      commit,
+      b,
+      b,
+      "0".repeat(40),
+      c
+    )
+  );
+
+  // version 1 quotes paths with spaces, unless the entries end in NUL
+  let output = git_rs(&canonical_path, &["status", "--porcelain"])?;
+  assert_eq!(output, " M b\nA  c\n?? \"d e\"\n");
+  let output = git_rs(&canonical_path, &["status", "-z"])?;
+  assert_eq!(output, " M b\0A  c\0?? d e\0");
+  Ok(())
+}
+
+#[test]
+fn test_status_upstream() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  hash_object(&canonical_path, "tree", b"")?;
+  let base = write_commit(&canonical_path, &[], 1000, "base")?;
+  let local = write_commit(&canonical_path, &[&base], 2000, "local")?;
+  let remote = write_commit(&canonical_path, &[&base], 3000, "remote")?;
+  write_ref(&canonical_path, "refs/heads/master", &local)?;
+  fs::create_dir_all(canonical_path.join(".git/refs/remotes/origin"))?;
+  write_ref(&canonical_path, "refs/remotes/origin/master", &remote)?;
+  fs::OpenOptions::new()
+    .append(true)
+    .open(canonical_path.join(".git/config"))?
+    .write_all(
+      b"[remote \"origin\"]\n\
+        \turl = /nowhere\n\
+        \tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+        [branch \"master\"]\n\
+        \tremote = origin\n\
+        \tmerge = refs/heads/master\n",
+    )?;
+
+  let output = git_rs(&canonical_path, &["status", "--porcelain=v2", "-b"])?;
+  assert_eq!(
+    output,
+    format!(
+      "# branch.oid {}\n# branch.head master\n\
+       # branch.upstream origin/master\n# branch.ab +1 -1\n",
+      local
+    )
+  );
+  let output = git_rs(&canonical_path, &["status", "--porcelain", "-b"])?;
+  assert_eq!(output, "## master...origin/master [ahead 1, behind 1]\n");
+  let output = git_rs(&canonical_path, &["status"])?;
+  assert!(output.starts_with(
+    "On branch master\nYour branch and 'origin/master' have diverged,\n\
+     and have 1 and 1 different commits each, respectively.\n"
+  ));
+
+  // an upstream that no longer exists is gone
+  fs::remove_file(canonical_path.join(".git/refs/remotes/origin/master"))?;
+  let output = git_rs(&canonical_path, &["status", "--porcelain", "-b"])?;
+  assert_eq!(output, "## master...origin/master [gone]\n");
+  Ok(())
+}
+
+#[test]
+fn test_status_json() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let output = git_rs(&canonical_path, &["status", "--json"])?;
+  let status: serde_json::Value = serde_json::from_str(&output)?;
+  assert_eq!(
+    status,
+    serde_json::json!({"branch": "master", "head": null, "entries": []})
+  );
+
+  // commit a and b, then change b, rename a to c and leave d untracked
+  fs::write(canonical_path.join("a"), "a\n")?;
+  fs::write(canonical_path.join("b"), "b\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "a", "b"])?;
+  let tree = git_rs(&canonical_path, &["write-tree"])?;
+  let commit = write_commit_with_tree(&canonical_path, tree.trim(), &[], 1000, "initial")?;
+  write_ref(&canonical_path, "refs/heads/master", &commit)?;
+  fs::write(canonical_path.join("b"), "changed\n")?;
+  fs::rename(canonical_path.join("a"), canonical_path.join("c"))?;
+  git_rs(&canonical_path, &["update-index", "--remove", "a"])?;
+  git_rs(&canonical_path, &["update-index", "--add", "c"])?;
+  fs::write(canonical_path.join("d"), "d\n")?;
+
+  let output = git_rs(&canonical_path, &["status", "--json"])?;
+  let status: serde_json::Value = serde_json::from_str(&output)?;
+  assert_eq!(
+    status,
+    serde_json::json!({
+      "branch": "master",
+      "head": commit,
+      "entries": [
+        {"path": "b", "code": ".M"},
+        {"path": "c", "code": "R.", "renamed_from": "a"},
+        {"path": "d", "code": "??"},
+      ],
+    })
+  );
+  Ok(())
+}
+
+#[test]
+fn test_status_untracked_cache() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  for dir in ["a", "c", "d/e", "empty"] {
+    fs::create_dir_all(canonical_path.join(dir))?;
+  }
+  fs::write(canonical_path.join("a/t"), "t\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "a/t"])?;
+  fs::write(canonical_path.join("a/u"), "u\n")?;
+  fs::write(canonical_path.join("c/z.log"), "z\n")?;
+  fs::write(canonical_path.join("d/e/f"), "f\n")?;
+  fs::write(canonical_path.join(".gitignore"), "*.log\n")?;
+  git_rs(&canonical_path, &["update-index", "--untracked-cache"])?;
+
+  // the directories are each read once to fill the cache, and again once
+  // the index is written after them, in case they changed in between
+  let status = || -> Result<(String, String), Box<dyn std::error::Error>> {
+    let output = Command::cargo_bin("git-rs")?
+      .current_dir(&canonical_path)
+      .args(["status", "--porcelain"])
+      .env("GIT_TRACE_UNTRACKED_STATS", "1")
+      .output()?;
+    let stderr = String::from_utf8(output.stderr)?;
+    let opendir = stderr.lines().last().unwrap_or_default().to_owned();
+    Ok((String::from_utf8(output.stdout)?, opendir))
+  };
+  let expected = "A  a/t\n?? .gitignore\n?? a/u\n?? d/\n";
+  let (output, opendir) = status()?;
+  assert_eq!(output, expected);
+  assert_eq!(opendir, "opendir: 6");
+  thread::sleep(Duration::from_millis(50));
+  status()?;
+  thread::sleep(Duration::from_millis(50));
+  assert_eq!(status()?, (expected.to_string(), "opendir: 0".to_string()));
+
+  // only the directory that changed is read
+  fs::write(canonical_path.join("a/new"), "new\n")?;
+  let (output, opendir) = status()?;
+  assert_eq!(output, "A  a/t\n?? .gitignore\n?? a/new\n?? a/u\n?? d/\n");
+  assert_eq!(opendir, "opendir: 1");
+
+  // changes to the index and the ignore rules are seen too
+  git_rs(&canonical_path, &["update-index", "--add", "a/u"])?;
+  fs::write(canonical_path.join("a/.gitignore"), "new\n")?;
+  fs::write(canonical_path.join("c/z"), "z\n")?;
+  let (output, _) = status()?;
+  assert_eq!(
+    output,
+    "A  a/t\nA  a/u\n?? .gitignore\n?? a/.gitignore\n?? c/\n?? d/\n"
+  );
+
+  git_rs(&canonical_path, &["update-index", "--no-untracked-cache"])?;
+  let (output, opendir) = status()?;
+  assert_eq!(
+    output,
+    "A  a/t\nA  a/u\n?? .gitignore\n?? a/.gitignore\n?? c/\n?? d/\n"
+  );
+  assert_eq!(opendir, "");
+  Ok(())
+}
+
+#[test]
+fn test_status_fsmonitor() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  fs::write(canonical_path.join("a"), "a\n")?;
+  fs::write(canonical_path.join("b"), "b\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "a", "b"])?;
+
+  // the watcher reports whatever is in .git/changed
+  let hook = canonical_path.join(".git/watcher");
+  fs::write(&hook, "printf 'token\\0'\ncat .git/changed 2>/dev/null\n")?;
+  let config = fs::read_to_string(canonical_path.join(".git/config"))?;
+  fs::write(
+    canonical_path.join(".git/config"),
+    config.replacen("[core]\n", "[core]\n\tfsmonitor = sh .git/watcher\n", 1),
+  )?;
+
+  // nothing is trusted the first time, after which the unchanged files are
+  let output = git_rs(&canonical_path, &["status", "--porcelain"])?;
+  assert_eq!(output, "A  a\nA  b\n");
+
+  // so a change the watcher does not report is not seen
+  fs::write(canonical_path.join("a"), "changed\n")?;
+  fs::write(canonical_path.join("b"), "changed\n")?;
+  fs::write(canonical_path.join(".git/changed"), "a\0")?;
+  let output = git_rs(&canonical_path, &["status", "--porcelain"])?;
+  assert_eq!(output, "AM a\nA  b\n");
+
+  fs::write(canonical_path.join(".git/changed"), "/\0")?;
+  let output = git_rs(&canonical_path, &["status", "--porcelain"])?;
+  assert_eq!(output, "AM a\nAM b\n");
+  Ok(())
+}
+
+#[test]
+fn test_status_lfs() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let oid = "429f3467c4c4e8362adacbf3e0bf9d5e1210cb876293612ed0af8bafe0e67541";
+  let pointer = format!(
+    "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 11\n",
+    oid
+  );
+  let hash = hash_object(&canonical_path, "blob", pointer.as_bytes())?;
+  fs::write(
+    canonical_path.join(".gitattributes"),
+    "*.bin filter=lfs -text\n",
+  )?;
+  git_rs(
+    &canonical_path,
+    &[
+      "update-index",
+      "--add",
+      "--cacheinfo",
+      &format!("100644,{},large.bin", hash),
+      ".gitattributes",
+    ],
+  )?;
+
+  // a pointer whose contents have not been fetched is checked out as it is
+  git_rs(&canonical_path, &["restore", "large.bin"])?;
+  assert_eq!(
+    fs::read_to_string(canonical_path.join("large.bin"))?,
+    pointer
+  );
+  assert_eq!(
+    git_rs(&canonical_path, &["status", "--porcelain"])?,
+    "A  .gitattributes\nA  large.bin\n"
+  );
+
+  // once they are there it is replaced by them, which match the pointer
+  let object = canonical_path.join(".git/lfs/objects/42/9f").join(oid);
+  fs::create_dir_all(object.parent().unwrap())?;
+  fs::write(&object, "large file\n")?;
+  fs::remove_file(canonical_path.join("large.bin"))?;
+  git_rs(&canonical_path, &["restore", "large.bin"])?;
+  assert_eq!(
+    fs::read_to_string(canonical_path.join("large.bin"))?,
+    "large file\n"
+  );
+  thread::sleep(Duration::from_millis(10));
+  fs::write(canonical_path.join("large.bin"), "large file\n")?;
+  assert_eq!(
+    git_rs(&canonical_path, &["status", "--porcelain"])?,
+    "A  .gitattributes\nA  large.bin\n"
+  );
+
+  // other contents are a change
+  fs::write(canonical_path.join("large.bin"), "changed\n")?;
+  assert_eq!(
+    git_rs(&canonical_path, &["status", "--porcelain"])?,
+    "A  .gitattributes\nAM large.bin\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_status_color() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  fs::write(path.join("a"), "a\n")?;
+  git_rs(path, &["update-index", "--add", "a"])?;
+  fs::write(path.join("a"), "changed\n")?;
+  fs::write(path.join("b"), "b\n")?;
+
+  let output = git_rs(path, &["-c", "color.status=always", "status"])?;
+  assert!(output.contains("\t\x1b[32mnew file:   a\x1b[m\n"));
+  assert!(output.contains("\t\x1b[31mmodified:   a\x1b[m\n"));
+  assert!(output.contains("\t\x1b[31mb\x1b[m\n"));
+
+  // a slot with no color is left alone; --color=never wins over the config
+  let output = git_rs(
+    path,
+    &[
+      "-c",
+      "color.ui=always",
+      "-c",
+      "color.status.untracked=normal",
+      "-c",
+      "color.status.updated=yellow",
+      "status",
+    ],
+  )?;
+  assert!(output.contains("\t\x1b[33mnew file:   a\x1b[m\n"));
+  assert!(output.contains("\tb\n"));
+  let output = git_rs(path, &["-c", "color.ui=always", "status", "--color=never"])?;
+  assert!(!output.contains('\x1b'));
+  Ok(())
+}
+
+#[test]
+fn test_status_submodule() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let sub = path.join("sub");
+  fs::create_dir(&sub)?;
+  git_rs(&sub, &["init"])?;
+  let one = hash_object(&sub, "blob", b"one\n")?;
+  let tree = write_tree(&sub, &[("f", &one)])?;
+  let first = write_commit_with_tree(&sub, &tree, &[], 1000, "one")?;
+  let second = write_commit_with_tree(&sub, &tree, &[&first], 2000, "two")?;
+  write_ref(&sub, "refs/heads/master", &first)?;
+  git_rs(&sub, &["reset", "--hard", "--force"])?;
+
+  let cacheinfo = format!("160000,{},sub", first);
+  git_rs(path, &["update-index", "--add", "--cacheinfo", &cacheinfo])?;
+  let tree = git_rs(path, &["write-tree"])?;
+  let commit = write_commit_with_tree(path, tree.trim(), &[], 1000, "add sub")?;
+  write_ref(path, "refs/heads/master", &commit)?;
+  assert_eq!(git_rs(path, &["status", "--porcelain"])?, "");
+
+  // the files in a submodule are looked at
+  fs::write(sub.join("f"), "changed\n")?;
+  fs::write(sub.join("new"), "new\n")?;
+  assert_eq!(git_rs(path, &["status", "--porcelain"])?, " M sub\n");
+  assert_eq!(
+    git_rs(path, &["status", "--porcelain=v2"])?,
+    format!("1 .M S.MU 160000 160000 160000 {} {} sub\n", first, first)
+  );
+  let output = git_rs(path, &["status"])?;
+  assert!(output.contains(
+    "  (commit or discard the untracked or modified content in submodules)\n\
+     \tmodified:   sub (modified content, untracked content)\n"
+  ));
+
+  // as is the commit it is at
+  fs::remove_file(sub.join("new"))?;
+  write_ref(&sub, "refs/heads/master", &second)?;
+  git_rs(&sub, &["reset", "--hard", "--force"])?;
+  assert!(git_rs(path, &["status"])?.contains("\tmodified:   sub (new commits)\n"));
+  assert!(git_rs(path, &["status", "--porcelain=v2"])?.starts_with("1 .M SC.. "));
+  Ok(())
+}
@@ -0,0 +1,278 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{
+  blob_hash, git_rs, git_rs_as, git_rs_as_command, hash_object, init_repo, write_commit_with_tree,
+  write_ref, write_tree,
+};
+use std::{fs, process::Command};
+
+#[test]
+fn test_switch() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let one = hash_object(&canonical_path, "blob", b"one\n")?;
+  let two = hash_object(&canonical_path, "blob", b"two\n")?;
+  let tree1 = write_tree(&canonical_path, &[("a.txt", &one), ("b.txt", &one)])?;
+  let tree2 = write_tree(&canonical_path, &[("a.txt", &two), ("b.txt", &one)])?;
+  let first = write_commit_with_tree(&canonical_path, &tree1, &[], 1000, "first")?;
+  let second = write_commit_with_tree(&canonical_path, &tree2, &[&first], 2000, "second")?;
+  write_ref(&canonical_path, "refs/heads/master", &second)?;
+  write_ref(&canonical_path, "refs/heads/old", &first)?;
+  git_rs(&canonical_path, &["reset", "--hard", "--force"])?;
+
+  // a change to a file that differs between the branches blocks the switch
+  fs::write(canonical_path.join("a.txt"), "local\n")?;
+  let output = git_rs(&canonical_path, &["switch", "old"])?;
+  assert!(output.contains("would be overwritten by checkout:\n\ta.txt"));
+  assert_eq!(
+    fs::read_to_string(canonical_path.join(".git/HEAD"))?,
+    "ref: refs/heads/master\n"
+  );
+
+  // a change to a file that is the same on both is carried over
+  git_rs(&canonical_path, &["restore", "a.txt"])?;
+  fs::write(canonical_path.join("b.txt"), "local\n")?;
+  let output = git_rs(&canonical_path, &["switch", "old"])?;
+  assert_eq!(output, "Switched to branch 'old'\n");
+  assert_eq!(fs::read_to_string(canonical_path.join("a.txt"))?, "one\n");
+  assert_eq!(fs::read_to_string(canonical_path.join("b.txt"))?, "local\n");
+
+  // -c creates the branch at the start point
+  let output = git_rs(&canonical_path, &["switch", "-f", "-c", "topic", "master"])?;
+  assert_eq!(output, "Switched to a new branch 'topic'\n");
+  assert_eq!(
+    fs::read_to_string(canonical_path.join(".git/HEAD"))?,
+    "ref: refs/heads/topic\n"
+  );
+  assert_eq!(
+    fs::read_to_string(canonical_path.join(".git/refs/heads/topic"))?,
+    format!("{}\n", second)
+  );
+  assert_eq!(fs::read_to_string(canonical_path.join("b.txt"))?, "one\n");
+  Ok(())
+}
+
+#[test]
+fn test_switch_progress() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let one = hash_object(&canonical_path, "blob", b"one\n")?;
+  let two = hash_object(&canonical_path, "blob", b"two\n")?;
+  let tree1 = write_tree(&canonical_path, &[("a.txt", &one), ("b.txt", &one)])?;
+  let tree2 = write_tree(&canonical_path, &[("a.txt", &two)])?;
+  let first = write_commit_with_tree(&canonical_path, &tree1, &[], 1000, "first")?;
+  let second = write_commit_with_tree(&canonical_path, &tree2, &[&first], 2000, "second")?;
+  write_ref(&canonical_path, "refs/heads/master", &first)?;
+  write_ref(&canonical_path, "refs/heads/next", &second)?;
+  git_rs(&canonical_path, &["reset", "--hard", "--force"])?;
+
+  // a.txt is written and b.txt deleted, drawn as they happen
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(&canonical_path)
+    .args(["switch", "--progress", "next"])
+    .env("GIT_PROGRESS_DELAY", "0")
+    .output()?;
+  assert_eq!(
+    String::from_utf8(output.stderr)?,
+    "Updating files:  50% (1/2)\rUpdating files: 100% (2/2)\r\
+     Updating files: 100% (2/2), done.\n"
+  );
+
+  // without --progress, stderr is not a terminal and nothing is drawn
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(&canonical_path)
+    .args(["switch", "master"])
+    .env("GIT_PROGRESS_DELAY", "0")
+    .output()?;
+  assert!(output.stderr.is_empty());
+  Ok(())
+}
+
+#[test]
+fn test_switch_workers() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+
+  // 150 files at the top level on master, and 150 spread over ten
+  // directories on next
+  let mut flat = Vec::new();
+  let mut nested = Vec::new();
+  for i in 0..150 {
+    flat.push((
+      format!("f{:03}", i),
+      hash_object(&canonical_path, "blob", format!("{}\n", i).as_bytes())?,
+    ));
+  }
+  for dir in 0..10 {
+    let mut files = Vec::new();
+    for i in 0..15 {
+      let data = format!("{} {}\n", dir, i);
+      files.push((
+        format!("g{:02}", i),
+        hash_object(&canonical_path, "blob", data.as_bytes())?,
+      ));
+    }
+    let files: Vec<(&str, &str)> = files
+      .iter()
+      .map(|(n, h)| (n.as_str(), h.as_str()))
+      .collect();
+    nested.push((format!("d{}", dir), write_tree(&canonical_path, &files)?));
+  }
+  let flat: Vec<(&str, &str)> = flat.iter().map(|(n, h)| (n.as_str(), h.as_str())).collect();
+  let tree1 = write_tree(&canonical_path, &flat)?;
+  let mut payload = Vec::new();
+  for (name, hash) in &nested {
+    payload.extend(format!("40000 {}\0", name).as_bytes());
+    payload.extend(hex::decode(hash)?);
+  }
+  let tree2 = hash_object(&canonical_path, "tree", &payload)?;
+  let first = write_commit_with_tree(&canonical_path, &tree1, &[], 1000, "first")?;
+  let second = write_commit_with_tree(&canonical_path, &tree2, &[&first], 2000, "second")?;
+  write_ref(&canonical_path, "refs/heads/master", &first)?;
+  write_ref(&canonical_path, "refs/heads/next", &second)?;
+  git_rs(&canonical_path, &["reset", "--hard", "--force"])?;
+
+  // the files are written on several threads, into directories made first
+  let args = ["-c", "checkout.workers=4", "switch", "next"];
+  assert_eq!(
+    git_rs(&canonical_path, &args)?,
+    "Switched to branch 'next'\n"
+  );
+  assert!(!canonical_path.join("f000").exists());
+  for dir in 0..10 {
+    for i in 0..15 {
+      let path = canonical_path.join(format!("d{}/g{:02}", dir, i));
+      assert_eq!(fs::read_to_string(path)?, format!("{} {}\n", dir, i));
+    }
+  }
+  assert_eq!(
+    git_rs(&canonical_path, &["write-tree"])?,
+    format!("{}\n", tree2)
+  );
+  assert_eq!(git_rs(&canonical_path, &["status", "--porcelain"])?, "");
+
+  let args = ["-c", "checkout.workers=4", "switch", "master"];
+  git_rs(&canonical_path, &args)?;
+  assert!(!canonical_path.join("d0").exists());
+  assert_eq!(fs::read_to_string(canonical_path.join("f149"))?, "149\n");
+  assert_eq!(
+    blob_hash(&fs::read(canonical_path.join("f042"))?),
+    flat[42].1
+  );
+  assert_eq!(
+    git_rs(&canonical_path, &["write-tree"])?,
+    format!("{}\n", tree1)
+  );
+  Ok(())
+}
+
+#[test]
+fn test_restore() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let one = hash_object(&canonical_path, "blob", b"one\n")?;
+  let two = hash_object(&canonical_path, "blob", b"two\n")?;
+  let tree1 = write_tree(&canonical_path, &[("a.txt", &one)])?;
+  let tree2 = write_tree(&canonical_path, &[("a.txt", &two)])?;
+  let first = write_commit_with_tree(&canonical_path, &tree1, &[], 1000, "first")?;
+  let second = write_commit_with_tree(&canonical_path, &tree2, &[&first], 2000, "second")?;
+  write_ref(&canonical_path, "refs/heads/master", &second)?;
+  git_rs(&canonical_path, &["reset", "--hard", "--force"])?;
+
+  // the working tree is restored from the index by default
+  fs::write(canonical_path.join("a.txt"), "local\n")?;
+  git_rs(&canonical_path, &["restore", "a.txt"])?;
+  assert_eq!(fs::read_to_string(canonical_path.join("a.txt"))?, "two\n");
+
+  // --source with --staged updates both the index and the working tree
+  git_rs(
+    &canonical_path,
+    &["restore", "-s", &first, "-S", "-W", "a.txt"],
+  )?;
+  assert_eq!(fs::read_to_string(canonical_path.join("a.txt"))?, "one\n");
+  let output = git_rs(&canonical_path, &["reset"])?;
+  assert_eq!(output, "Unstaged changes after reset:\nM\ta.txt\n");
+
+  let output = git_rs(&canonical_path, &["restore", "missing.txt"])?;
+  assert!(output.contains("pathspec 'missing.txt' did not match any file(s) known to git"));
+  Ok(())
+}
+
+#[test]
+fn test_switch_detach() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  let one = hash_object(path, "blob", b"one\n")?;
+  let two = hash_object(path, "blob", b"two\n")?;
+  let first = write_commit_with_tree(
+    path,
+    &write_tree(path, &[("a.txt", &one)])?,
+    &[],
+    1000,
+    "first",
+  )?;
+  let second = write_commit_with_tree(
+    path,
+    &write_tree(path, &[("a.txt", &two)])?,
+    &[&first],
+    2000,
+    "second",
+  )?;
+  write_ref(path, "refs/heads/master", &second)?;
+  write_ref(path, "refs/tags/v1", &first)?;
+  git_rs(path, &["reset", "--hard", "--force"])?;
+
+  // a tag is switched to by its commit, and the move logged
+  let output = git_rs_as_command(path, &["switch", "--detach", "v1"])?.output()?;
+  assert_eq!(
+    String::from_utf8(output.stdout)?,
+    format!("HEAD is now at {} first\n", &first[..7])
+  );
+  assert_eq!(
+    fs::read_to_string(path.join(".git/HEAD"))?,
+    format!("{}\n", first)
+  );
+  assert!(fs::read_to_string(path.join(".git/logs/HEAD"))?
+    .ends_with("\tcheckout: moving from master to v1\n"));
+  assert!(git_rs(path, &["status"])?.starts_with("HEAD detached at v1\n"));
+  assert_eq!(
+    git_rs(path, &["branch"])?,
+    "* (HEAD detached at v1)\n  master\n"
+  );
+
+  // commits move the detached HEAD on
+  fs::write(path.join("a.txt"), "three\n")?;
+  git_rs(path, &["update-index", "a.txt"])?;
+  let output = git_rs_as(path, &["commit", "-m", "work"])?;
+  let work = fs::read_to_string(path.join(".git/HEAD"))?
+    .trim()
+    .to_string();
+  assert!(output.starts_with(&format!("[detached HEAD {}] work\n", &work[..7])));
+  assert!(fs::read_to_string(path.join(".git/logs/HEAD"))?.ends_with("\tcommit: work\n"));
+  assert!(git_rs(path, &["status"])?.starts_with("HEAD detached from v1\n"));
+  assert_eq!(
+    git_rs(path, &["branch"])?,
+    "* (HEAD detached from v1)\n  master\n"
+  );
+
+  // and leaving them behind is warned about
+  let output = git_rs_as_command(path, &["switch", "master"])?.output()?;
+  assert_eq!(
+    String::from_utf8(output.stdout)?,
+    "Switched to branch 'master'\n"
+  );
+  assert_eq!(
+    String::from_utf8(output.stderr)?,
+    format!(
+      "Warning: you are leaving 1 commit behind, not connected to\n\
+       any of your branches:\n\n  {} work\n\n\
+       If you want to keep it by creating a new branch, this may be a good time\n\
+       to do so with:\n\n git branch <new-branch-name> {}\n\n",
+      &work[..7],
+      &work[..7]
+    )
+  );
+
+  // a name that is not a tag or remote-tracking branch is shown as the commit
+  git_rs_as(path, &["switch", "--detach", "HEAD"])?;
+  let label = format!("HEAD detached at {}\n", &second[..7]);
+  assert!(git_rs(path, &["status"])?.starts_with(&label));
+  Ok(())
+}
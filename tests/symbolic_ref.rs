@@ -0,0 +1,89 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref};
+use std::fs;
+use std::path::Path;
+
+/// Runs `git-rs symbolic-ref` and returns what it printed and its exit code.
+fn symbolic_ref(repo: &Path, args: &[&str]) -> Result<(String, i32), Box<dyn std::error::Error>> {
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(repo)
+    .arg("symbolic-ref")
+    .args(args)
+    .env("GIT_COMMITTER_NAME", "C O Mitter")
+    .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+    .output()?;
+  Ok((
+    String::from_utf8(output.stdout)?,
+    output.status.code().unwrap_or(-1),
+  ))
+}
+
+#[test]
+fn test_symbolic_ref() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let commit = write_commit(path, &[], 1000, "base")?;
+  write_ref(path, "refs/heads/master", &commit)?;
+  write_ref(path, "refs/heads/topic", &commit)?;
+
+  assert_eq!(
+    git_rs(path, &["symbolic-ref", "HEAD"])?,
+    "refs/heads/master\n"
+  );
+  assert_eq!(
+    git_rs(path, &["symbolic-ref", "--short", "HEAD"])?,
+    "master\n"
+  );
+
+  // symbolic refs to symbolic refs are followed, unless asked not to
+  git_rs(
+    path,
+    &["symbolic-ref", "refs/heads/alias", "refs/heads/topic"],
+  )?;
+  symbolic_ref(path, &["-m", "to alias", "HEAD", "refs/heads/alias"])?;
+  assert_eq!(
+    git_rs(path, &["symbolic-ref", "HEAD"])?,
+    "refs/heads/topic\n"
+  );
+  assert_eq!(
+    git_rs(path, &["symbolic-ref", "--no-recurse", "HEAD"])?,
+    "refs/heads/alias\n"
+  );
+  let log = fs::read_to_string(path.join(".git/logs/HEAD"))?;
+  assert!(log.starts_with(&format!("{} {} ", commit, commit)));
+  assert!(log.contains(" C O Mitter <committer@example.com> "));
+  assert!(log.ends_with("\tto alias\n"));
+
+  assert_eq!(
+    symbolic_ref(path, &["refs/heads/master"])?,
+    (
+      "fatal: ref refs/heads/master is not a symbolic ref\n".to_string(),
+      128
+    )
+  );
+  assert_eq!(
+    symbolic_ref(path, &["-q", "refs/heads/master"])?,
+    (String::new(), 1)
+  );
+  assert_eq!(
+    symbolic_ref(path, &["HEAD", "master"])?,
+    (
+      "fatal: Refusing to point HEAD outside of refs/\n".to_string(),
+      128
+    )
+  );
+  assert_eq!(
+    symbolic_ref(path, &["-d", "HEAD"])?,
+    ("fatal: deleting 'HEAD' is not allowed\n".to_string(), 128)
+  );
+  assert_eq!(
+    symbolic_ref(path, &["-d", "refs/heads/alias"])?,
+    (String::new(), 0)
+  );
+  assert!(!path.join(".git/refs/heads/alias").exists());
+  assert!(path.join(".git/refs/heads/topic").exists());
+  Ok(())
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{git_rs, init_repo};
+use serde_json::{json, Value};
+use std::fs;
+use std::process::Command;
+use tempdir::TempDir;
+
+#[test]
+fn test_trace() -> Result<(), Box<dyn std::error::Error>> {
+  let (temp_dir, canonical_path) = init_repo()?;
+  fs::write(canonical_path.join("a.txt"), "a\n")?;
+  let traced =
+    |env: &[(&str, &str)], args: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+      let mut cmd = Command::cargo_bin("git-rs")?;
+      cmd.current_dir(&canonical_path);
+      for name in ["GIT_TRACE", "GIT_TRACE_PACKET", "GIT_TRACE_PERFORMANCE"] {
+        cmd.env_remove(name);
+      }
+      let output = cmd.envs(env.iter().copied()).args(args).output()?;
+      assert!(output.status.success());
+      Ok(String::from_utf8(output.stderr)?)
+    };
+
+  // each line says when and where it was said from, lined up
+  let trace = traced(&[("GIT_TRACE", "1")], &["update-index", "--add", "a.txt"])?;
+  let line = trace.lines().next().unwrap();
+  assert!(line.ends_with("trace: built-in: git update-index --add a.txt"));
+  assert_eq!(&line[2..3], ":");
+  assert_eq!(&line[8..9], ".");
+  assert!(line[16..39].starts_with("main.rs:"));
+  assert_eq!(traced(&[("GIT_TRACE", "0")], &["write-tree"])?, "");
+
+  // a path traces into that file instead
+  let file = temp_dir.path().join("trace.log");
+  let trace = traced(&[("GIT_TRACE", file.to_str().unwrap())], &["write-tree"])?;
+  assert_eq!(trace, "");
+  assert!(fs::read_to_string(&file)?.contains("trace: built-in: git write-tree\n"));
+
+  // performance says how long reading the index and the command took
+  let trace = traced(&[("GIT_TRACE_PERFORMANCE", "2")], &["write-tree"])?;
+  assert!(trace.contains(" s:  read cache "));
+  assert!(trace.contains(" s: git command: git write-tree\n"));
+
+  // anything else is warned about and ignored
+  let trace = traced(&[("GIT_TRACE", "yes please")], &["write-tree"])?;
+  assert!(trace.starts_with("warning: unknown trace value for 'GIT_TRACE': yes please\n"));
+  assert!(!trace.contains("built-in"));
+  Ok(())
+}
+
+#[test]
+fn test_trace2_event() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  fs::write(canonical_path.join("a.txt"), "a\n")?;
+  git_rs(&canonical_path, &["update-index", "--add", "a.txt"])?;
+  fs::write(canonical_path.join("b.txt"), "b\n")?;
+  // out of the working tree, where it would be untracked
+  let trace_dir = TempDir::new("trace")?;
+  let target = trace_dir.path().join("events");
+  let events =
+    |env: &[(&str, &str)], args: &[&str]| -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+      let _ = fs::remove_file(&target);
+      let mut cmd = Command::cargo_bin("git-rs")?;
+      cmd
+        .current_dir(&canonical_path)
+        .env_remove("GIT_TRACE2_PARENT_SID")
+        .env_remove("GIT_TRACE2_EVENT_NESTING")
+        .env("GIT_TRACE2_EVENT", &target)
+        .env("GIT_AUTHOR_NAME", "A U Thor")
+        .env("GIT_AUTHOR_EMAIL", "author@example.com")
+        .env("GIT_COMMITTER_NAME", "C O Mitter")
+        .env("GIT_COMMITTER_EMAIL", "committer@example.com");
+      cmd.envs(env.iter().copied()).args(args).output()?;
+      let events = fs::read_to_string(&target)?;
+      let events: Result<Vec<Value>, _> = events.lines().map(serde_json::from_str).collect();
+      Ok(events?)
+    };
+  let named = |events: &[Value], name: &str| -> Vec<Value> {
+    events
+      .iter()
+      .filter(|event| event["event"] == name)
+      .cloned()
+      .collect()
+  };
+
+  // a session starts with the version and command line, and ends with the
+  // exit code, all under one session ID
+  let status = events(&[], &["status"])?;
+  let names: Vec<&str> = status
+    .iter()
+    .map(|e| e["event"].as_str().unwrap())
+    .collect();
+  assert_eq!(names[..3], ["version", "start", "cmd_name"]);
+  assert_eq!(names[names.len() - 2..], ["exit", "atexit"]);
+  assert_eq!(status[0]["evt"], "3");
+  assert_eq!(status[1]["argv"], json!(["git", "status"]));
+  assert_eq!(status[2]["name"], "status");
+  assert_eq!(status.last().unwrap()["code"], 0);
+  assert!(status.iter().all(|e| e["sid"] == status[0]["sid"]));
+  assert!(status
+    .iter()
+    .all(|e| e["time"].as_str().unwrap().ends_with('Z')));
+
+  // regions are timed, and what is worked out in them nested in them
+  let enter = named(&status, "region_enter");
+  let leave = named(&status, "region_leave");
+  assert_eq!(enter[0]["label"], "do_read_index");
+  assert_eq!(enter[0]["nesting"], 1);
+  assert_eq!(leave[0]["label"], "do_read_index");
+  assert!(leave[0]["t_rel"].as_f64().unwrap() >= 0.0);
+  let data = named(&status, "data");
+  let cache_nr = data.iter().find(|e| e["key"] == "read/cache_nr").unwrap();
+  assert_eq!(cache_nr["nesting"], 2);
+  assert_eq!(cache_nr["value"], "1");
+  let untracked = data.iter().find(|e| e["key"] == "count/untracked").unwrap();
+  assert_eq!(untracked["category"], "status");
+  assert_eq!(untracked["value"], "1");
+
+  // regions deeper than GIT_TRACE2_EVENT_NESTING are left out
+  let shallow = events(&[("GIT_TRACE2_EVENT_NESTING", "0")], &["status"])?;
+  assert!(named(&shallow, "region_enter").is_empty());
+  assert!(named(&shallow, "data").is_empty());
+
+  // children are numbered and timed, and their sessions named under this one
+  let sid = trace_dir.path().join("sid");
+  let editor = format!("echo \"$GIT_TRACE2_PARENT_SID\" > {}; true", sid.display());
+  let commit = events(
+    &[("GIT_EDITOR", &editor), ("GIT_TRACE2_PARENT_SID", "parent")],
+    &["commit", "--allow-empty"],
+  )?;
+  let own = commit[0]["sid"].as_str().unwrap();
+  assert!(own.starts_with("parent/"));
+  assert_eq!(fs::read_to_string(&sid)?, format!("{}\n", own));
+  let start = &named(&commit, "child_start")[0];
+  assert_eq!(start["child_id"], 0);
+  assert_eq!(start["child_class"], "editor");
+  assert_eq!(start["argv"][0], "sh");
+  let exit = &named(&commit, "child_exit")[0];
+  assert_eq!(exit["child_id"], 0);
+  assert_eq!(exit["code"], 0);
+
+  // a directory gets a file for each session
+  let directory = trace_dir.path().join("sessions");
+  fs::create_dir(&directory)?;
+  let mut cmd = Command::cargo_bin("git-rs")?;
+  cmd
+    .current_dir(&canonical_path)
+    .env_remove("GIT_TRACE2_PARENT_SID")
+    .env("GIT_TRACE2_EVENT", &directory)
+    .arg("write-tree")
+    .output()?;
+  let sessions: Vec<_> = fs::read_dir(&directory)?.collect::<Result<_, _>>()?;
+  assert_eq!(sessions.len(), 1);
+  let name = sessions[0].file_name().into_string().unwrap();
+  assert!(fs::read_to_string(sessions[0].path())?.contains(&format!("\"sid\":\"{}\"", name)));
+  Ok(())
+}
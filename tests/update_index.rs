@@ -0,0 +1,357 @@
+mod common;
+
+use common::{blob_hash, git_rs, init_repo, write_tree};
+use sha1::{Digest, Sha1};
+use std::fs;
+
+#[test]
+fn test_update_index() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  fs::write(canonical_path.join("a.txt"), "a\n")?;
+
+  // new files need --add
+  let output = git_rs(&canonical_path, &["update-index", "a.txt"])?;
+  assert!(output.contains("a.txt: cannot add to the index - missing --add option?"));
+  let output = git_rs(
+    &canonical_path,
+    &["update-index", "--add", "--verbose", "a.txt"],
+  )?;
+  assert_eq!(output, "add 'a.txt'\n");
+
+  // entries can be inserted without a file in the working tree, but new
+  // ones need --add too
+  let cacheinfo = "100644,e69de29bb2d1d6434b8b29ae775ad8c2e48c5391,dir/empty.txt";
+  let output = git_rs(&canonical_path, &["update-index", "--cacheinfo", cacheinfo])?;
+  assert_eq!(
+    output,
+    "fatal: dir/empty.txt: cannot add to the index - missing --add option?\n\
+     git update-index: --cacheinfo cannot add dir/empty.txt\n"
+  );
+  let output = git_rs(
+    &canonical_path,
+    &["update-index", "--add", "--cacheinfo", cacheinfo],
+  )?;
+  assert_eq!(output, "");
+  let output = git_rs(&canonical_path, &["update-index", "--cacheinfo", cacheinfo])?;
+  assert_eq!(output, "");
+  let output = git_rs(&canonical_path, &["rm", "-n", "-r", "--cached", "."])?;
+  assert_eq!(output, "rm 'a.txt'\nrm 'dir/empty.txt'\n");
+
+  // a skip-worktree file is never considered modified
+  fs::write(canonical_path.join("a.txt"), "changed\n")?;
+  git_rs(
+    &canonical_path,
+    &["update-index", "--skip-worktree", "a.txt"],
+  )?;
+  let output = git_rs(&canonical_path, &["rm", "-n", "--cached", "a.txt"])?;
+  assert_eq!(output, "rm 'a.txt'\n");
+
+  // missing files are only dropped with --remove
+  fs::remove_file(canonical_path.join("a.txt"))?;
+  git_rs(
+    &canonical_path,
+    &["update-index", "--no-skip-worktree", "a.txt"],
+  )?;
+  let output = git_rs(&canonical_path, &["update-index", "a.txt"])?;
+  assert!(output.contains("a.txt: does not exist and --remove not passed"));
+  git_rs(&canonical_path, &["update-index", "--remove", "a.txt"])?;
+  let output = git_rs(&canonical_path, &["rm", "-n", "-r", "--cached", "."])?;
+  assert_eq!(output, "rm 'dir/empty.txt'\n");
+  Ok(())
+}
+
+#[test]
+fn test_update_index_threads() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let names: Vec<String> = (0..150).map(|i| format!("f{:03}", i)).collect();
+  for (i, name) in names.iter().enumerate() {
+    fs::write(canonical_path.join(name), format!("{}\n", i))?;
+  }
+
+  // enough files to be hashed on several threads, which adds them all the
+  // same as one thread would
+  let mut args = vec!["-c", "index.threads=4", "update-index", "--add"];
+  args.extend(names.iter().map(String::as_str));
+  git_rs(&canonical_path, &args)?;
+  let hashes: Vec<String> = (0..150)
+    .map(|i| blob_hash(format!("{}\n", i).as_bytes()))
+    .collect();
+  let entries: Vec<(&str, &str)> = names
+    .iter()
+    .zip(&hashes)
+    .map(|(name, hash)| (name.as_str(), hash.as_str()))
+    .collect();
+  let tree = write_tree(&canonical_path, &entries)?;
+  assert_eq!(
+    git_rs(&canonical_path, &["write-tree"])?,
+    format!("{}\n", tree)
+  );
+  for hash in &hashes {
+    let object = canonical_path
+      .join(".git/objects")
+      .join(&hash[..2])
+      .join(&hash[2..]);
+    assert!(object.is_file());
+  }
+  Ok(())
+}
+
+#[test]
+fn test_update_index_extensions() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  fs::create_dir_all(canonical_path.join("a/b"))?;
+  fs::create_dir_all(canonical_path.join("c"))?;
+  for (path, contents) in [
+    ("a/b/x", "x\n"),
+    ("a/y", "y\n"),
+    ("c/z", "z\n"),
+    ("top", "top\n"),
+  ] {
+    fs::write(canonical_path.join(path), contents)?;
+  }
+  git_rs(
+    &canonical_path,
+    &["update-index", "--add", "a/b/x", "a/y", "c/z", "top"],
+  )?;
+
+  // writing the trees leaves them in the cache tree extension
+  let output = git_rs(&canonical_path, &["write-tree"])?;
+  assert_eq!(output, "404af917cc015a992dad838584439fa3c7295525\n");
+  let index = fs::read(canonical_path.join(".git/index"))?;
+  assert!(index.windows(4).any(|window| window == b"TREE"));
+
+  // an extension we know nothing about is kept as it is
+  let mut index = index[..index.len() - 20].to_vec();
+  index.extend_from_slice(b"ZZZZ\0\0\0\x03abc");
+  let checksum = Sha1::digest(&index);
+  index.extend_from_slice(&checksum);
+  fs::write(canonical_path.join(".git/index"), &index)?;
+
+  // only the directories leading to a change are written again
+  fs::write(canonical_path.join("c/z"), "changed\n")?;
+  git_rs(&canonical_path, &["update-index", "c/z"])?;
+  let output = git_rs(&canonical_path, &["write-tree"])?;
+  assert_eq!(output, "fd9316ee6864a3238699ba421ad57c9874d3b231\n");
+  let output = git_rs(&canonical_path, &["write-tree", "--prefix=a/"])?;
+  assert_eq!(output, "d8546f32bfb40c525a7d33dcacbda5f6b54134cb\n");
+  let index = fs::read(canonical_path.join(".git/index"))?;
+  assert!(index
+    .windows(11)
+    .any(|window| window == b"ZZZZ\0\0\0\x03abc"));
+  Ok(())
+}
+
+#[test]
+fn test_update_index_split() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let names: Vec<String> = (0..10).map(|i| format!("f{}", i)).collect();
+  for name in &names {
+    fs::write(canonical_path.join(name), format!("{}\n", name))?;
+  }
+  let mut args = vec!["update-index", "--add", "--split-index"];
+  args.extend(names.iter().map(String::as_str));
+  git_rs(&canonical_path, &args)?;
+  let shared_indexes = || -> Result<Vec<String>, std::io::Error> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(canonical_path.join(".git"))? {
+      let name = entry?.file_name().to_string_lossy().into_owned();
+      if name.starts_with("sharedindex.") {
+        names.push(name);
+      }
+    }
+    Ok(names)
+  };
+  let shared = shared_indexes()?;
+  assert_eq!(shared.len(), 1);
+
+  // a small change goes in the split index alone
+  fs::write(canonical_path.join("f3"), "changed\n")?;
+  fs::remove_file(canonical_path.join("f5"))?;
+  git_rs(&canonical_path, &["update-index", "--remove", "f3", "f5"])?;
+  assert_eq!(shared_indexes()?, shared);
+  let index = fs::read(canonical_path.join(".git/index"))?;
+  assert!(index.windows(4).any(|window| window == b"link"));
+
+  let hashes: Vec<String> = names
+    .iter()
+    .map(|name| match name.as_str() {
+      "f3" => blob_hash(b"changed\n"),
+      _ => blob_hash(format!("{}\n", name).as_bytes()),
+    })
+    .collect();
+  let entries: Vec<(&str, &str)> = names
+    .iter()
+    .zip(&hashes)
+    .filter(|(name, _)| *name != "f5")
+    .map(|(name, hash)| (name.as_str(), hash.as_str()))
+    .collect();
+  let tree = format!("{}\n", write_tree(&canonical_path, &entries)?);
+  assert_eq!(git_rs(&canonical_path, &["write-tree"])?, tree);
+
+  // while a big one writes a new shared index
+  for name in &names[..4] {
+    fs::write(canonical_path.join(name), "again\n")?;
+  }
+  git_rs(&canonical_path, &["update-index", "f0", "f1", "f2", "f3"])?;
+  assert_eq!(shared_indexes()?.len(), 2);
+
+  git_rs(&canonical_path, &["update-index", "--no-split-index"])?;
+  let index = fs::read(canonical_path.join(".git/index"))?;
+  assert!(!index.windows(4).any(|window| window == b"link"));
+  Ok(())
+}
+
+#[test]
+fn test_update_index_sparse() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  fs::create_dir_all(canonical_path.join("a"))?;
+  fs::create_dir_all(canonical_path.join("b"))?;
+  for path in ["a/x", "a/y", "b/z"] {
+    fs::write(canonical_path.join(path), format!("{}\n", path))?;
+  }
+  git_rs(
+    &canonical_path,
+    &["update-index", "--add", "a/x", "a/y", "b/z"],
+  )?;
+  git_rs(
+    &canonical_path,
+    &["update-index", "--skip-worktree", "a/x", "a/y"],
+  )?;
+  fs::remove_dir_all(canonical_path.join("a"))?;
+  let config = fs::read_to_string(canonical_path.join(".git/config"))?;
+  fs::write(
+    canonical_path.join(".git/config"),
+    config.replacen(
+      "[core]\n",
+      "[core]\n\tsparseCheckout = true\n\tsparseCheckoutCone = true\n",
+      1,
+    ) + "[index]\n\tsparse = true\n",
+  )?;
+
+  // a directory of skip-worktree entries is written as one entry, once the
+  // index knows its tree
+  let tree = git_rs(&canonical_path, &["write-tree"])?;
+  let index = fs::read(canonical_path.join(".git/index"))?;
+  assert!(index.windows(4).any(|window| window == b"sdir"));
+  assert!(index.windows(3).any(|window| window == b"a/\0"));
+  assert!(!index.windows(3).any(|window| window == b"a/x"));
+
+  // and expanded again when read
+  let output = git_rs(&canonical_path, &["status", "--porcelain"])?;
+  assert_eq!(output, "A  a/x\nA  a/y\nA  b/z\n");
+  fs::write(canonical_path.join("b/z"), "changed\n")?;
+  git_rs(&canonical_path, &["update-index", "b/z"])?;
+  assert_ne!(git_rs(&canonical_path, &["write-tree"])?, tree);
+  let index = fs::read(canonical_path.join(".git/index"))?;
+  assert!(index.windows(3).any(|window| window == b"a/\0"));
+  Ok(())
+}
+
+#[test]
+fn test_update_index_eol() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let config_path = canonical_path.join(".git/config");
+  let config = fs::read_to_string(&config_path)?;
+  fs::write(
+    &config_path,
+    config.replacen("[core]\n", "[core]\n\tautocrlf = true\n", 1),
+  )?;
+  fs::write(
+    canonical_path.join(".gitattributes"),
+    "*.sh eol=lf\n*.dat -text\n",
+  )?;
+  fs::write(canonical_path.join("a.txt"), "a\r\nb\r\n")?;
+  fs::write(canonical_path.join("b.sh"), "a\r\nb\r\n")?;
+  fs::write(canonical_path.join("c.dat"), "a\r\nb\r\n")?;
+  fs::write(canonical_path.join("d.bin"), "a\0\r\n")?;
+
+  // text is stored with LF line endings, unless it is binary
+  let paths = ["a.txt", "b.sh", "c.dat", "d.bin"];
+  git_rs(
+    &canonical_path,
+    &[&["update-index", "--add"][..], &paths].concat(),
+  )?;
+  let hashes: Vec<String> = ["a\nb\n", "a\nb\n", "a\r\nb\r\n", "a\0\r\n"]
+    .iter()
+    .map(|data| blob_hash(data.as_bytes()))
+    .collect();
+  let entries: Vec<(&str, &str)> = paths
+    .iter()
+    .copied()
+    .zip(hashes.iter().map(|h| h.as_str()))
+    .collect();
+  let tree = format!("{}\n", write_tree(&canonical_path, &entries)?);
+  assert_eq!(git_rs(&canonical_path, &["write-tree"])?, tree);
+  assert_eq!(
+    git_rs(&canonical_path, &["hash-object", "a.txt"])?,
+    format!("{}\n", blob_hash(b"a\nb\n"))
+  );
+  assert_eq!(
+    git_rs(&canonical_path, &["hash-object", "--no-filters", "a.txt"])?,
+    format!("{}\n", blob_hash(b"a\r\nb\r\n"))
+  );
+
+  // and gets the line endings it asks for when written out
+  for path in paths {
+    fs::remove_file(canonical_path.join(path))?;
+  }
+  git_rs(&canonical_path, &["restore", "."])?;
+  assert_eq!(fs::read(canonical_path.join("a.txt"))?, b"a\r\nb\r\n");
+  assert_eq!(fs::read(canonical_path.join("b.sh"))?, b"a\nb\n");
+  assert_eq!(fs::read(canonical_path.join("c.dat"))?, b"a\r\nb\r\n");
+  assert_eq!(fs::read(canonical_path.join("d.bin"))?, b"a\0\r\n");
+  assert_eq!(
+    git_rs(&canonical_path, &["status", "--porcelain"])?,
+    "A  a.txt\nA  b.sh\nA  c.dat\nA  d.bin\n?? .gitattributes\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn test_update_index_filter() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let config_path = canonical_path.join(".git/config");
+  let mut config = fs::read_to_string(&config_path)?;
+  config.push_str("[filter \"up\"]\n\tclean = tr a-z A-Z\n\tsmudge = tr A-Z a-z\n");
+  config.push_str("[filter \"name\"]\n\tclean = echo %f\n");
+  config.push_str("[filter \"broken\"]\n\tclean = false\n\trequired = true\n");
+  fs::write(&config_path, config)?;
+  fs::write(
+    canonical_path.join(".gitattributes"),
+    "*.up filter=up\n*.name filter=name\n*.broken filter=broken\n",
+  )?;
+  fs::write(canonical_path.join("a.up"), "hello\n")?;
+  fs::write(canonical_path.join("it's.name"), "hello\n")?;
+  fs::write(canonical_path.join("c.broken"), "hello\n")?;
+
+  // the clean command turns the file into what is stored
+  git_rs(
+    &canonical_path,
+    &["update-index", "--add", "a.up", "it's.name"],
+  )?;
+  let tree = write_tree(
+    &canonical_path,
+    &[
+      ("a.up", &blob_hash(b"HELLO\n")),
+      ("it's.name", &blob_hash(b"it's.name\n")),
+    ],
+  )?;
+  assert_eq!(
+    git_rs(&canonical_path, &["write-tree"])?,
+    format!("{}\n", tree)
+  );
+
+  // and the smudge command turns it back
+  fs::remove_file(canonical_path.join("a.up"))?;
+  git_rs(&canonical_path, &["restore", "a.up"])?;
+  assert_eq!(fs::read_to_string(canonical_path.join("a.up"))?, "hello\n");
+
+  // a required filter that fails stops the file being added
+  let output = git_rs(&canonical_path, &["update-index", "--add", "c.broken"])?;
+  assert!(output.contains("c.broken: clean filter 'broken' failed"));
+  assert_eq!(
+    git_rs(&canonical_path, &["write-tree"])?,
+    format!("{}\n", tree)
+  );
+  Ok(())
+}
@@ -0,0 +1,196 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{git_rs, hash_object, init_repo, write_commit, write_ref};
+use std::fs;
+use std::path::Path;
+use tempdir::TempDir;
+
+/// Runs `git-rs update-ref` on the given input and returns what it printed
+/// and its exit code.
+fn update_ref(
+  repo: &Path,
+  args: &[&str],
+  input: &str,
+) -> Result<(String, i32), Box<dyn std::error::Error>> {
+  let output = Command::cargo_bin("git-rs")?
+    .current_dir(repo)
+    .arg("update-ref")
+    .args(args)
+    .env("GIT_COMMITTER_NAME", "C O Mitter")
+    .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+    .write_stdin(input)
+    .output()?;
+  Ok((
+    String::from_utf8(output.stdout)?,
+    output.status.code().unwrap_or(-1),
+  ))
+}
+
+#[test]
+fn test_update_ref() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let path = &canonical_path;
+  hash_object(path, "tree", b"")?;
+  let one = write_commit(path, &[], 1000, "one")?;
+  let two = write_commit(path, &[&one], 2000, "two")?;
+  write_ref(path, "refs/heads/master", &two)?;
+  let show_refs = || git_rs(path, &["for-each-ref", "--format=%(refname) %(objectname)"]);
+
+  // HEAD is followed to the branch it is on, and both get a reflog entry
+  assert_eq!(
+    update_ref(path, &["-m", "back", "HEAD", &one, &two], "")?,
+    (String::new(), 0)
+  );
+  assert_eq!(
+    fs::read_to_string(path.join(".git/refs/heads/master"))?,
+    format!("{}\n", one)
+  );
+  for log in ["HEAD", "refs/heads/master"] {
+    let log = fs::read_to_string(path.join(".git/logs").join(log))?;
+    assert!(log.starts_with(&format!("{} {} ", two, one)));
+    assert!(log.ends_with("\tback\n"));
+  }
+  assert_eq!(
+    update_ref(path, &["refs/heads/master", &two, &two], "")?,
+    (
+      format!(
+        "fatal: update_ref failed for ref 'refs/heads/master': cannot lock ref \
+         'refs/heads/master': is at {} but expected {}\n",
+        one, two
+      ),
+      128
+    )
+  );
+  assert_eq!(
+    update_ref(path, &["refs/heads/master", "nope"], "")?,
+    ("fatal: nope: not a valid SHA1\n".to_string(), 128)
+  );
+
+  // all of a transaction happens, or none of it
+  let input = format!(
+    "start\ncreate refs/tags/v1 {}\nupdate refs/heads/topic {} {}\nprepare\ncommit\n",
+    one,
+    two,
+    "0".repeat(40)
+  );
+  assert_eq!(
+    update_ref(path, &["--stdin"], &input)?,
+    ("start: ok\nprepare: ok\ncommit: ok\n".to_string(), 0)
+  );
+  let refs = show_refs()?;
+  assert_eq!(
+    refs,
+    format!(
+      "refs/heads/master {}\nrefs/heads/topic {}\nrefs/tags/v1 {}\n",
+      one, two, one
+    )
+  );
+  let input = format!(
+    "delete refs/heads/topic\ncreate refs/tags/v1 {}\nverify refs/heads/master {}\n",
+    two, one
+  );
+  assert_eq!(
+    update_ref(path, &["--stdin"], &input)?,
+    (
+      "fatal: cannot lock ref 'refs/tags/v1': reference already exists\n".to_string(),
+      128
+    )
+  );
+  assert_eq!(show_refs()?, refs);
+  assert!(!path.join(".git/refs/heads/topic.lock").exists());
+
+  // a ref somebody else holds the lock on can't be changed
+  fs::write(path.join(".git/refs/heads/topic.lock"), "")?;
+  let (output, code) = update_ref(path, &["--stdin"], "delete refs/heads/topic\n")?;
+  assert!(output.starts_with("fatal: cannot lock ref 'refs/heads/topic': Unable to create"));
+  assert_eq!(code, 128);
+  fs::remove_file(path.join(".git/refs/heads/topic.lock"))?;
+
+  let input = "start\nprepare\nabort\ncommit\n";
+  assert_eq!(
+    update_ref(path, &["--stdin"], input)?,
+    (
+      "start: ok\nprepare: ok\nabort: ok\nfatal: transaction is closed\n".to_string(),
+      128
+    )
+  );
+  assert_eq!(
+    update_ref(
+      path,
+      &["--stdin"],
+      "start\nprepare\ndelete refs/heads/topic\n"
+    )?,
+    (
+      "start: ok\nprepare: ok\nfatal: prepared transactions can only be closed\n".to_string(),
+      128
+    )
+  );
+  assert_eq!(
+    update_ref(path, &["--stdin"], "frobnicate refs/heads/topic\n")?,
+    (
+      "fatal: unknown command: frobnicate refs/heads/topic\n".to_string(),
+      128
+    )
+  );
+  assert_eq!(show_refs()?, refs);
+
+  // with -z, an empty old value is one that is not given
+  let input = format!(
+    "update refs/heads/topic\0{}\0\0option no-deref\0update HEAD\0{}\0{}\0",
+    one, two, one
+  );
+  assert_eq!(
+    update_ref(path, &["--stdin", "-z"], &input)?,
+    (String::new(), 0)
+  );
+  assert_eq!(
+    fs::read_to_string(path.join(".git/HEAD"))?,
+    format!("{}\n", two)
+  );
+  assert_eq!(
+    update_ref(path, &["-d", "refs/heads/topic", &two], "")?,
+    (
+      format!(
+        "error: cannot lock ref 'refs/heads/topic': is at {} but expected {}\n",
+        one, two
+      ),
+      1
+    )
+  );
+  assert_eq!(
+    update_ref(path, &["-d", "refs/heads/topic", &one], "")?,
+    (String::new(), 0)
+  );
+  assert!(!path.join(".git/refs/heads/topic").exists());
+  Ok(())
+}
+
+#[test]
+fn test_update_ref_reftable() -> Result<(), Box<dyn std::error::Error>> {
+  let temp_dir = TempDir::new("gitrs")?;
+  let path = &temp_dir.path().canonicalize()?;
+  git_rs(path, &["init", "--ref-format=reftable"])?;
+  hash_object(path, "tree", b"")?;
+  let one = write_commit(path, &[], 1000, "one")?;
+
+  let input = format!(
+    "create refs/heads/main {}\ncreate refs/tags/v1 {}\n",
+    one, one
+  );
+  assert_eq!(update_ref(path, &["--stdin"], &input)?, (String::new(), 0));
+  let input = format!("delete refs/tags/v1\ncreate refs/heads/main {}\n", one);
+  assert_eq!(
+    update_ref(path, &["--stdin"], &input)?,
+    (
+      "fatal: cannot lock ref 'refs/heads/main': reference already exists\n".to_string(),
+      128
+    )
+  );
+  assert_eq!(
+    git_rs(path, &["show-ref"])?,
+    format!("{} refs/heads/main\n{} refs/tags/v1\n", one, one)
+  );
+  assert!(!path.join(".git/reftable/tables.list.lock").exists());
+  Ok(())
+}
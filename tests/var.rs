@@ -0,0 +1,69 @@
+mod common;
+
+use assert_cmd::prelude::*;
+use common::init_repo;
+use std::process::Command;
+
+#[test]
+fn test_var() -> Result<(), Box<dyn std::error::Error>> {
+  let (_temp_dir, canonical_path) = init_repo()?;
+  let var = |env: &[(&str, &str)], args: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("git-rs")?;
+    cmd
+      .current_dir(&canonical_path)
+      .env("GIT_CONFIG_GLOBAL", "/dev/null");
+    for name in [
+      "GIT_EDITOR",
+      "VISUAL",
+      "EDITOR",
+      "GIT_PAGER",
+      "PAGER",
+      "TERM",
+    ] {
+      cmd.env_remove(name);
+    }
+    cmd.envs(env.iter().copied()).arg("var").args(args);
+    Ok(String::from_utf8(cmd.output()?.stdout)?)
+  };
+
+  let identity = [
+    ("GIT_AUTHOR_NAME", "A U Thor"),
+    ("GIT_AUTHOR_EMAIL", "author@example.com"),
+    ("GIT_AUTHOR_DATE", "@1000 +0100"),
+  ];
+  assert_eq!(
+    var(&identity, &["GIT_AUTHOR_IDENT"])?,
+    "A U Thor <author@example.com> 1000 +0100\n"
+  );
+
+  // the editor is the first one set, but VISUAL is no good on a dumb terminal
+  let terminal = ("TERM", "xterm");
+  assert_eq!(var(&[terminal], &["GIT_EDITOR"])?, "vi\n");
+  assert_eq!(var(&[terminal, ("EDITOR", "ed")], &["GIT_EDITOR"])?, "ed\n");
+  let visual = [("VISUAL", "vim"), ("EDITOR", "ed")];
+  assert_eq!(
+    var(&[&visual[..], &[terminal]].concat(), &["GIT_EDITOR"])?,
+    "vim\n"
+  );
+  assert_eq!(var(&visual, &["GIT_EDITOR"])?, "ed\n");
+  assert_eq!(
+    var(&[], &["GIT_EDITOR"])?,
+    "fatal: Terminal is dumb, but EDITOR unset\n"
+  );
+  assert_eq!(
+    var(&[("GIT_EDITOR", "nano"), ("EDITOR", "ed")], &["GIT_EDITOR"])?,
+    "nano\n"
+  );
+
+  assert_eq!(var(&[], &["GIT_PAGER"])?, "less\n");
+  assert_eq!(var(&[("PAGER", "more")], &["GIT_PAGER"])?, "more\n");
+  assert_eq!(var(&[("GIT_PAGER", "")], &["GIT_PAGER"])?, "cat\n");
+  assert_eq!(var(&[], &["GIT_DEFAULT_BRANCH"])?, "master\n");
+
+  let listed = var(&identity, &["-l"])?;
+  assert!(listed.contains("core.bare=false\n"));
+  assert!(listed.contains("GIT_AUTHOR_IDENT=A U Thor <author@example.com> 1000 +0100\n"));
+  assert!(listed.ends_with("GIT_PAGER=less\nGIT_DEFAULT_BRANCH=master\n"));
+  assert_eq!(var(&[], &["NOT_A_VARIABLE"])?, "");
+  Ok(())
+}